@@ -0,0 +1,180 @@
+//! Command line tool to manage the nodes of a cluster through their admin port, like Cassandra's
+//! `nodetool`, without reading their log files.
+
+use std::{
+    env,
+    net::{Ipv4Addr, SocketAddr},
+    process,
+    time::Duration,
+};
+
+use driver::admin::{send_admin_command_to, AdminAnswer, ADMIN_PORT};
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+
+/// Time each line of the answer to a command is waited for.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time each line of the answer is waited for by the commands that run across the cluster or wait
+/// for the node to finish its work.
+const LONG_READ_TIMEOUT: Duration = Duration::from_secs(600);
+/// Commands that take longer than `READ_TIMEOUT` to answer.
+const LONG_COMMANDS: [&str; 6] = [
+    "DRAIN",
+    "DECOMMISSION",
+    "REPAIR",
+    "FLUSH",
+    "COMPACT",
+    "SNAPSHOT",
+];
+
+fn print_usage() {
+    println!("Usage: admin [--host <ip>] [--port <port>] <command> [arguments]");
+    println!("  --host <ip>                  Node to manage (default {DEFAULT_HOST})");
+    println!("  --port <port>                Admin port of the node (default {ADMIN_PORT})");
+    println!("Commands:");
+    println!(
+        "  status                       Nodes of the cluster, whether they are up and their state"
+    );
+    println!("  describering <keyspace>      Ranges of tokens of the ring and their replicas");
+    println!("  flush <keyspace[.table]>     Writes the memtables to disk in every node");
+    println!("  compact <keyspace[.table]>   Compacts the SSTables in every node");
+    println!("  snapshot <keyspace> <tag>    Backs up the tables of the keyspace in every node");
+    println!(
+        "  repair <keyspace[.table]>    Reads every row from all of its replicas, repairing them"
+    );
+    println!("  drain [seconds]              Stops taking queries so the node can be restarted");
+    println!("  decommission [seconds]       Hands the data of the node over and takes it out of the ring");
+    println!("Any other command of the admin protocol (e.g. `metrics`) is sent as is.");
+}
+
+/// The node a command is sent to, and the command.
+#[derive(Debug, PartialEq)]
+struct Invocation {
+    addr: SocketAddr,
+    command: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Invocation, String> {
+    let mut host: Ipv4Addr = DEFAULT_HOST
+        .parse()
+        .map_err(|_| format!("invalid default host {}", DEFAULT_HOST))?;
+    let mut port = ADMIN_PORT;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1);
+        match args[i].as_str() {
+            "--host" => {
+                host = value
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--host needs an IPv4 address")?
+            }
+            "--port" => {
+                port = value
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--port needs a port number")?
+            }
+            _ => break,
+        }
+        i += 2;
+    }
+
+    if i >= args.len() {
+        return Err("missing command".to_string());
+    }
+
+    Ok(Invocation {
+        addr: SocketAddr::new(host.into(), port),
+        command: args[i..].join(" "),
+    })
+}
+
+// Returns how long each line of the answer to `command` is waited for
+fn read_timeout(command: &str) -> Duration {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    if LONG_COMMANDS
+        .iter()
+        .any(|long| name.eq_ignore_ascii_case(long))
+    {
+        LONG_READ_TIMEOUT
+    } else {
+        READ_TIMEOUT
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print_usage();
+        return;
+    }
+
+    let invocation = match parse_args(&args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("Invalid argument: {}", e);
+            print_usage();
+            process::exit(2);
+        }
+    };
+
+    let timeout = read_timeout(&invocation.command);
+    match send_admin_command_to(invocation.addr, &invocation.command, timeout) {
+        Ok(AdminAnswer::Output(lines)) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Ok(AdminAnswer::Error(reason)) => {
+            eprintln!("Error: {}", reason);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not reach the node at {}: {:?}", invocation.addr, e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let invocation = parse_args(&to_args("status")).unwrap();
+        assert_eq!(
+            invocation.addr,
+            SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), ADMIN_PORT)
+        );
+        assert_eq!(invocation.command, "status");
+
+        let invocation =
+            parse_args(&to_args("--host 127.0.0.3 --port 9000 repair sky.flights")).unwrap();
+        assert_eq!(
+            invocation.addr,
+            SocketAddr::new(Ipv4Addr::new(127, 0, 0, 3).into(), 9000)
+        );
+        assert_eq!(invocation.command, "repair sky.flights");
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(&to_args("--host 127.0.0.3")).is_err());
+        assert!(parse_args(&to_args("--host node3 status")).is_err());
+        assert!(parse_args(&to_args("--port")).is_err());
+    }
+
+    #[test]
+    fn test_long_commands_wait_longer() {
+        assert_eq!(read_timeout("drain 30"), LONG_READ_TIMEOUT);
+        assert_eq!(read_timeout("REPAIR sky"), LONG_READ_TIMEOUT);
+        assert_eq!(read_timeout("snapshot sky nightly"), LONG_READ_TIMEOUT);
+        assert_eq!(read_timeout("status"), READ_TIMEOUT);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use crate::ClientError;
+
+/// Port the nodes take admin commands on, unless their config sets another one.
+pub const ADMIN_PORT: u16 = 0x4144;
+
+/// Time the answer to a command is waited for by default.
+const ADMIN_READ_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Sends a single command to the admin port of the node at `ip` and returns its answer.
+///
+/// The node answers each command with its output lines followed by a status line, `OK` on
+/// success or `ERROR <reason>` otherwise, in which case `ClientError::ServerError` is returned.
+/// On success the output lines are returned joined by newlines. Commands that stop the node
+/// (like `KILL`) close the connection without answering, which is returned as an empty string.
+pub fn send_admin_command(ip: Ipv4Addr, command: &str) -> Result<String, ClientError> {
+    send_admin_command_with_timeout(ip, command, ADMIN_READ_TIMEOUT)
+}
+
+/// Sends a command as `send_admin_command` does, waiting up to `timeout` for each line of its
+/// answer, for commands that take longer to run (like `DRAIN`).
+pub fn send_admin_command_with_timeout(
+    ip: Ipv4Addr,
+    command: &str,
+    timeout: Duration,
+) -> Result<String, ClientError> {
+    let addr = SocketAddr::new(IpAddr::V4(ip), ADMIN_PORT);
+    match send_admin_command_to(addr, command, timeout)? {
+        AdminAnswer::Output(output) => Ok(output.join("\n")),
+        AdminAnswer::Error(_) => Err(ClientError::ServerError),
+    }
+}
+
+/// The answer of a node to an admin command.
+#[derive(Debug, PartialEq)]
+pub enum AdminAnswer {
+    /// The command succeeded, with these output lines.
+    Output(Vec<String>),
+    /// The command failed, for this reason.
+    Error(String),
+}
+
+/// Sends a command to the admin port at `addr`, for nodes whose admin port is not the default one,
+/// and returns its output lines or the reason it failed, waiting up to `timeout` for each line.
+pub fn send_admin_command_to(
+    addr: SocketAddr,
+    command: &str,
+    timeout: Duration,
+) -> Result<AdminAnswer, ClientError> {
+    let mut stream = TcpStream::connect(addr).map_err(|_| ClientError::ConnectionError)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|_| ClientError::TimeoutError)?;
+
+    stream
+        .write_all(format!("{}\n", command.trim()).as_bytes())
+        .map_err(|_| ClientError::IOError)?;
+    stream.flush().map_err(|_| ClientError::IOError)?;
+
+    let mut output = Vec::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(|_| ClientError::IOError)?;
+        let status = line.trim();
+        if status == "OK" {
+            break;
+        }
+        if let Some(reason) = status.strip_prefix("ERROR") {
+            return Ok(AdminAnswer::Error(reason.trim().to_string()));
+        }
+        output.push(line);
+    }
+
+    Ok(AdminAnswer::Output(output))
+}
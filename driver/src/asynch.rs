@@ -0,0 +1,403 @@
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use native_protocol::{
+    checksum::Checksum,
+    frame::{Frame, FrameOptions},
+    framing::{self, HEADER_LENGTH},
+    messages::{
+        auth::AuthResponse,
+        batch::{Batch, BatchType},
+        error::Error,
+        execute::Execute,
+        prepare::Prepare,
+        query::{Consistency, Query, QueryParams},
+        result::result_,
+        startup::Startup,
+    },
+    types::Bytes,
+};
+use rustls::ClientConfig;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::{Mutex, Semaphore},
+    time::timeout,
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+use crate::{
+    auth_token, bundle::SecureConnectBundle, is_schema_change, tls::configure_client, ClientError,
+    PreparedStatement, QueryResult, NATIVE_PORT, SCHEMA_CHANGE_TIMEOUT,
+};
+
+/// Connections a client opens to its node by default, at most.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 8;
+
+/// Time a connection or a request can take, as the read and write timeouts of the blocking
+/// client.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// An asynchronous version of [`crate::CassandraClient`], to be used from a tokio runtime (with
+/// its time and IO drivers enabled).
+///
+/// Nodes answer the requests of a connection one at a time, so the client keeps a pool of up to
+/// `max_connections` TLS connections to its node and runs each request on a free one, opening
+/// it (and sending its `STARTUP`) the first time it is needed. Clones of the client share the
+/// pool, so a task can clone it and issue queries concurrently with the others: when all the
+/// connections are in use, requests wait for one of them to be free.
+///
+/// A connection that fails is closed, and another one is opened by the next request that needs
+/// it.
+#[derive(Clone)]
+pub struct CassandraClient {
+    node: SocketAddr,
+    connector: TlsConnector,
+    checksum: Option<Checksum>,
+    /// User and password the connections log in with, instead of the default superuser.
+    credentials: Option<(String, String)>,
+    pool: Arc<Pool>,
+}
+
+struct Pool {
+    idle: Mutex<Vec<Connection>>,
+    /// One permit for each connection that can be in use.
+    permits: Semaphore,
+    max_connections: usize,
+}
+
+impl Pool {
+    fn new(max_connections: usize, idle: Vec<Connection>) -> Self {
+        Self {
+            idle: Mutex::new(idle),
+            permits: Semaphore::new(max_connections),
+            max_connections,
+        }
+    }
+}
+
+/// A connection to the node, with the options of the frames negotiated in its `STARTUP`.
+struct Connection {
+    stream: TlsStream<TcpStream>,
+    frame_options: FrameOptions,
+}
+
+impl CassandraClient {
+    /// Creates a client of the node at `ip` (or at `NODE_ADDR`, if set), as
+    /// [`crate::CassandraClient::connect`] does, and opens its first connection.
+    pub async fn connect(ip: Ipv4Addr) -> Result<Self, ClientError> {
+        Self::connect_with_config(ip, configure_client()).await
+    }
+
+    pub async fn connect_with_config(
+        ip: Ipv4Addr,
+        config: ClientConfig,
+    ) -> Result<Self, ClientError> {
+        let addr = if let Ok(var) = env::var("NODE_ADDR") {
+            var.parse().map_err(|_| ClientError::AddrError)?
+        } else {
+            SocketAddr::new(IpAddr::V4(ip), NATIVE_PORT)
+        };
+
+        Self::connect_to(addr, config, None).await
+    }
+
+    /// Creates a client of the first contact point of the secure connect bundle at `path` that
+    /// accepts it, as [`crate::CassandraClient::from_bundle`] does.
+    pub async fn from_bundle(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let bundle = SecureConnectBundle::load(path)?;
+        let config = bundle.client_config()?;
+
+        let mut last_error = ClientError::ConnectionError;
+        for &addr in bundle.contact_points() {
+            match Self::connect_to(addr, config.clone(), None).await {
+                Ok(client) => return Ok(client),
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Creates a client of the node at `addr`, ignoring `NODE_ADDR`, whose connections
+    /// checksum their frames with `checksum` (see
+    /// [`crate::CassandraClient::startup_with_checksum`]). Its first connection is opened right
+    /// away, so a node that can not be reached fails here.
+    pub async fn connect_to(
+        addr: SocketAddr,
+        config: ClientConfig,
+        checksum: Option<Checksum>,
+    ) -> Result<Self, ClientError> {
+        let mut client = Self {
+            node: addr,
+            connector: TlsConnector::from(Arc::new(config)),
+            checksum,
+            credentials: None,
+            pool: Arc::new(Pool::new(DEFAULT_MAX_CONNECTIONS, vec![])),
+        };
+        let connection = client.open().await?;
+        client.pool = Arc::new(Pool::new(DEFAULT_MAX_CONNECTIONS, vec![connection]));
+        Ok(client)
+    }
+
+    /// Sets how many connections the client opens to its node, at most. Meant to be called
+    /// before cloning the client: the clones made before keep the previous pool.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        let idle = match Arc::try_unwrap(self.pool) {
+            Ok(pool) => pool.idle.into_inner(),
+            Err(_) => vec![],
+        };
+        self.pool = Arc::new(Pool::new(max_connections.max(1), idle));
+        self
+    }
+
+    /// Logs the connections in as `user` with `password`, as
+    /// [`crate::CassandraClient::with_credentials`] does, instead of as the default superuser.
+    /// Meant to be called before cloning the client: the connections open so far are closed, and
+    /// a new one is opened right away, so credentials the node rejects fail here.
+    pub async fn with_credentials(
+        mut self,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, ClientError> {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        let connection = self.open().await?;
+        let max_connections = self.pool.max_connections;
+        self.pool = Arc::new(Pool::new(max_connections, vec![connection]));
+        Ok(self)
+    }
+
+    /// Returns the address of the node the client is connected to.
+    pub fn node(&self) -> SocketAddr {
+        self.node
+    }
+
+    /// Executes a query.
+    pub async fn execute(
+        &self,
+        query: &str,
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        let consistency =
+            Consistency::from_string(consistency_str).map_err(|_| ClientError::ConsistencyError)?;
+        let params = QueryParams::new(consistency, vec![]);
+        let frame = Frame::Query(Query::new(query.to_string(), params));
+        query_result(self.request(&frame).await?)
+    }
+
+    /// Executes `queries` in a single BATCH request, as
+    /// [`crate::CassandraClient::execute_batch`] does.
+    pub async fn execute_batch(
+        &self,
+        queries: &[String],
+        batch_type: BatchType,
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        let consistency =
+            Consistency::from_string(consistency_str).map_err(|_| ClientError::ConsistencyError)?;
+        let batch = Batch::new(batch_type, queries.to_vec(), consistency);
+        query_result(self.request(&Frame::Batch(batch)).await?)
+    }
+
+    /// Prepares `query` in the node. Statements are prepared for the node, so they can be
+    /// executed on any connection of the client.
+    pub async fn prepare(&self, query: &str) -> Result<PreparedStatement, ClientError> {
+        let prepare = Frame::Prepare(Prepare::new(query.to_string()));
+        match self.request(&prepare).await? {
+            Frame::Result(result_::Result::Prepared(prepared)) => Ok(PreparedStatement {
+                id: prepared.get_id().to_vec(),
+                query: query.to_string(),
+                bind_markers: prepared.get_metadata().columns_count as usize,
+            }),
+            Frame::Error(_) => Err(ClientError::ServerError),
+            _ => Err(ClientError::InvalidFrame),
+        }
+    }
+
+    /// Executes a prepared statement, binding `values` to its markers in order, as
+    /// [`crate::CassandraClient::execute_prepared`] does (preparing it again if the node no
+    /// longer knows it).
+    pub async fn execute_prepared(
+        &self,
+        statement: &PreparedStatement,
+        values: &[&str],
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        let consistency =
+            Consistency::from_string(consistency_str).map_err(|_| ClientError::ConsistencyError)?;
+        let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+
+        let execute = Execute::new(statement.id.clone(), values.clone(), consistency.clone());
+        let mut result = self.request(&Frame::Execute(execute)).await?;
+        if let Frame::Error(Error::Unprepared(_)) = result {
+            let statement = self.prepare(&statement.query).await?;
+            let execute = Execute::new(statement.id, values, consistency);
+            result = self.request(&Frame::Execute(execute)).await?;
+        }
+        query_result(result)
+    }
+
+    // Sends a request on a free connection, opening one if there is none, and returns its
+    // answer. The connection is kept for the next requests unless it failed.
+    async fn request(&self, frame: &Frame) -> Result<Frame, ClientError> {
+        let _permit = self
+            .pool
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| ClientError::ConnectionError)?;
+
+        let idle = self.pool.idle.lock().await.pop();
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => self.open().await?,
+        };
+
+        let wait = match frame {
+            Frame::Query(query) if is_schema_change(query.get_query()) => SCHEMA_CHANGE_TIMEOUT,
+            _ => REQUEST_TIMEOUT,
+        };
+        let answer = timeout(wait, connection.request(frame))
+            .await
+            .map_err(|_| ClientError::TimeoutError)??;
+        self.pool.idle.lock().await.push(connection);
+        Ok(answer)
+    }
+
+    // Opens a connection to the node and initializes it, authenticating the client.
+    async fn open(&self) -> Result<Connection, ClientError> {
+        let stream = timeout(REQUEST_TIMEOUT, TcpStream::connect(self.node))
+            .await
+            .map_err(|_| ClientError::TimeoutError)?
+            .map_err(|_| ClientError::ConnectionError)?;
+        let server_name = rustls::pki_types::ServerName::try_from("databaseserver")
+            .map_err(|_| ClientError::ServerError)?;
+        let stream = timeout(REQUEST_TIMEOUT, self.connector.connect(server_name, stream))
+            .await
+            .map_err(|_| ClientError::TimeoutError)?
+            .map_err(|_| ClientError::ConnectionError)?;
+
+        let mut connection = Connection {
+            stream,
+            frame_options: FrameOptions::default(),
+        };
+        let startup = match self.checksum {
+            Some(checksum) => Startup::default().with_checksum(checksum),
+            None => Startup::default(),
+        };
+        let token = auth_token(self.credentials.as_ref());
+        timeout(REQUEST_TIMEOUT, connection.startup(startup, token))
+            .await
+            .map_err(|_| ClientError::TimeoutError)??;
+        Ok(connection)
+    }
+}
+
+impl Connection {
+    async fn startup(&mut self, startup: Startup, token: Vec<u8>) -> Result<(), ClientError> {
+        let frame_options = startup
+            .frame_options()
+            .map_err(|_| ClientError::SerializationError)?;
+
+        let response = self.request(&Frame::Startup(startup)).await?;
+        // The node accepted the options, so the frames after its answer use them
+        if !matches!(response, Frame::Error(_)) {
+            self.frame_options = frame_options;
+        }
+
+        match response {
+            Frame::Authenticate(_) => {
+                let auth_response = Frame::AuthResponse(AuthResponse::new(Bytes::Vec(token)));
+                match self.request(&auth_response).await? {
+                    Frame::AuthSuccess(_) => Ok(()),
+                    _ => Err(ClientError::InvalidFrame),
+                }
+            }
+            Frame::Ready => Ok(()),
+            _ => Err(ClientError::InvalidFrame),
+        }
+    }
+
+    async fn request(&mut self, frame: &Frame) -> Result<Frame, ClientError> {
+        let bytes = frame
+            .to_bytes_with_options(&self.frame_options, None)
+            .map_err(|_| ClientError::SerializationError)?;
+        self.stream
+            .write_all(&bytes)
+            .await
+            .map_err(|_| ClientError::IOError)?;
+        self.stream
+            .flush()
+            .await
+            .map_err(|_| ClientError::IOError)?;
+
+        let answer = read_frame(&mut self.stream).await?;
+        Frame::from_bytes_with_options(&answer, &self.frame_options)
+            .map(|(frame, _)| frame)
+            .map_err(|_| ClientError::DeserializationError)
+    }
+}
+
+/// Reads the bytes of a whole frame from `stream`: its header, and then as many bytes of body
+/// as the header says.
+///
+/// # Errors
+/// - `ClientError::IOError` if the stream fails or ends before the frame does.
+/// - `ClientError::InvalidFrame` if the body is longer than `framing::MAX_BODY_LENGTH`.
+async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>, ClientError> {
+    let mut header = [0u8; HEADER_LENGTH];
+    stream
+        .read_exact(&mut header)
+        .await
+        .map_err(|_| ClientError::IOError)?;
+    let length = framing::body_length(&header).map_err(|_| ClientError::InvalidFrame)?;
+
+    let mut bytes = header.to_vec();
+    bytes.resize(HEADER_LENGTH + length, 0);
+    stream
+        .read_exact(&mut bytes[HEADER_LENGTH..])
+        .await
+        .map_err(|_| ClientError::IOError)?;
+    Ok(bytes)
+}
+
+fn query_result(frame: Frame) -> Result<QueryResult, ClientError> {
+    match frame {
+        Frame::Result(res) => Ok(QueryResult::Result(res)),
+        Frame::Error(err) => Ok(QueryResult::Error(err)),
+        _ => Err(ClientError::InvalidFrame),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_protocol::Serializable;
+
+    #[test]
+    fn frames_are_read_one_at_a_time() {
+        let query = Frame::Query(Query::new(
+            "SELECT * FROM flights".to_string(),
+            QueryParams::new(Consistency::One, vec![]),
+        ));
+        let mut bytes = Frame::Ready.to_bytes().unwrap();
+        bytes.extend(query.to_bytes().unwrap());
+        let mut stream = bytes.as_slice();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let ready = read_frame(&mut stream).await.unwrap();
+            assert!(matches!(Frame::from_bytes(&ready).unwrap(), Frame::Ready));
+
+            let read = read_frame(&mut stream).await.unwrap();
+            assert_eq!(read, query.to_bytes().unwrap());
+            assert!(read_frame(&mut stream).await.is_err());
+        });
+    }
+}
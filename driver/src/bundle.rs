@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+};
+
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::{ClientConfig, RootCertStore};
+
+use crate::{tls::install_crypto_provider, ClientError, NATIVE_PORT};
+
+/// Files of a bundle: the CA the certificates of the nodes are signed with, the certificate and
+/// key of the client (both optional, for nodes that ask the clients for one) and the config.
+const CA_FILE: &str = "ca.crt";
+const CERT_FILE: &str = "cert";
+const KEY_FILE: &str = "key";
+const CONFIG_FILE: &str = "config";
+
+/// Everything a client needs to connect to a cluster, read from a single zip file (or a directory
+/// with the same files), so the GUI and the simulator can be pointed to a different environment by
+/// changing one path.
+///
+/// The `config` file has one `key = value` per line (`#` starts a comment):
+///
+/// - `contact_points`: the nodes to connect to, in order, as `ip` or `ip:port`, separated by
+///   commas.
+/// - `port`: the port of the contact points that do not have one (by default, the native port).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecureConnectBundle {
+    contact_points: Vec<SocketAddr>,
+    ca_cert: Vec<u8>,
+    client_identity: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SecureConnectBundle {
+    /// Loads the bundle at `path`, which is either a zip file or a directory. Files inside
+    /// directories of the zip are found by their name, as bundles are usually zipped with one.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            Self::read_dir(path)?
+        } else {
+            Self::read_zip(path)?
+        };
+        Self::from_files(files)
+    }
+
+    /// Builds a bundle from its files, by name.
+    pub fn from_files(mut files: HashMap<String, Vec<u8>>) -> Result<Self, ClientError> {
+        let ca_cert = files.remove(CA_FILE).ok_or(ClientError::InvalidBundle)?;
+        let client_identity = match (files.remove(CERT_FILE), files.remove(KEY_FILE)) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            // A certificate is useless without its key, and the other way around
+            _ => return Err(ClientError::InvalidBundle),
+        };
+        let config = files
+            .remove(CONFIG_FILE)
+            .ok_or(ClientError::InvalidBundle)?;
+        let config = String::from_utf8(config).map_err(|_| ClientError::InvalidBundle)?;
+
+        Ok(Self {
+            contact_points: Self::parse_contact_points(&config)?,
+            ca_cert,
+            client_identity,
+        })
+    }
+
+    /// Returns the nodes to connect to, in the order they should be tried.
+    pub fn contact_points(&self) -> &[SocketAddr] {
+        &self.contact_points
+    }
+
+    /// Whether the bundle has a certificate for the client to authenticate with.
+    pub fn has_client_certificate(&self) -> bool {
+        self.client_identity.is_some()
+    }
+
+    /// Returns the TLS configuration of the connections to the cluster, trusting only the CA of
+    /// the bundle and presenting its client certificate, if it has one.
+    pub fn client_config(&self) -> Result<ClientConfig, ClientError> {
+        let mut root_store = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(&self.ca_cert) {
+            let cert = cert.map_err(|_| ClientError::InvalidBundle)?;
+            root_store
+                .add(cert)
+                .map_err(|_| ClientError::InvalidBundle)?;
+        }
+
+        install_crypto_provider();
+
+        let builder = ClientConfig::builder().with_root_certificates(root_store);
+        match &self.client_identity {
+            Some((cert, key)) => {
+                let certs = CertificateDer::pem_slice_iter(cert)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| ClientError::InvalidBundle)?;
+                let key =
+                    PrivateKeyDer::from_pem_slice(key).map_err(|_| ClientError::InvalidBundle)?;
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|_| ClientError::InvalidBundle)
+            }
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+
+    fn read_dir(path: &Path) -> Result<HashMap<String, Vec<u8>>, ClientError> {
+        let mut files = HashMap::new();
+        for name in [CA_FILE, CERT_FILE, KEY_FILE, CONFIG_FILE] {
+            let file = path.join(name);
+            if file.is_file() {
+                let contents = fs::read(file).map_err(|_| ClientError::IOError)?;
+                files.insert(name.to_string(), contents);
+            }
+        }
+        Ok(files)
+    }
+
+    fn read_zip(path: &Path) -> Result<HashMap<String, Vec<u8>>, ClientError> {
+        let file = File::open(path).map_err(|_| ClientError::IOError)?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|_| ClientError::InvalidBundle)?;
+
+        let mut files = HashMap::new();
+        for i in 0..archive.len() {
+            let mut entry = archive
+                .by_index(i)
+                .map_err(|_| ClientError::InvalidBundle)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let Some(name) = Path::new(entry.name())
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            let mut contents = Vec::new();
+            entry
+                .read_to_end(&mut contents)
+                .map_err(|_| ClientError::InvalidBundle)?;
+            files.insert(name, contents);
+        }
+        Ok(files)
+    }
+
+    fn parse_contact_points(config: &str) -> Result<Vec<SocketAddr>, ClientError> {
+        let mut contact_points = None;
+        let mut port = NATIVE_PORT;
+        for line in config.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or(ClientError::InvalidBundle)?;
+            match key.trim() {
+                "contact_points" => contact_points = Some(value.trim().to_string()),
+                "port" => {
+                    port = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| ClientError::InvalidBundle)?
+                }
+                _ => return Err(ClientError::InvalidBundle),
+            }
+        }
+
+        let contact_points = contact_points
+            .ok_or(ClientError::InvalidBundle)?
+            .split(',')
+            .map(str::trim)
+            .filter(|point| !point.is_empty())
+            .map(|point| match point.parse::<SocketAddr>() {
+                Ok(addr) => Ok(addr),
+                Err(_) => point
+                    .parse::<IpAddr>()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .map_err(|_| ClientError::AddrError),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if contact_points.is_empty() {
+            return Err(ClientError::InvalidBundle);
+        }
+        Ok(contact_points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(config: &str) -> HashMap<String, Vec<u8>> {
+        HashMap::from([
+            (CA_FILE.to_string(), b"ca".to_vec()),
+            (CONFIG_FILE.to_string(), config.as_bytes().to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_bundle_contact_points_and_files() {
+        let bundle = SecureConnectBundle::from_files(files(
+            "# staging\ncontact_points = 127.0.0.1, 127.0.0.2:9042\nport = 4000\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            bundle.contact_points(),
+            [
+                "127.0.0.1:4000".parse().unwrap(),
+                "127.0.0.2:9042".parse().unwrap()
+            ]
+        );
+        assert!(!bundle.has_client_certificate());
+
+        let bundle = SecureConnectBundle::from_files(files("contact_points = 127.0.0.1")).unwrap();
+        assert_eq!(
+            bundle.contact_points(),
+            [SocketAddr::new("127.0.0.1".parse().unwrap(), NATIVE_PORT)]
+        );
+
+        let mut with_identity = files("contact_points = 127.0.0.1");
+        with_identity.insert(CERT_FILE.to_string(), b"cert".to_vec());
+        assert!(matches!(
+            SecureConnectBundle::from_files(with_identity.clone()),
+            Err(ClientError::InvalidBundle)
+        ));
+        with_identity.insert(KEY_FILE.to_string(), b"key".to_vec());
+        assert!(SecureConnectBundle::from_files(with_identity)
+            .unwrap()
+            .has_client_certificate());
+
+        for config in ["", "contact_points = ", "contact_point = 127.0.0.1"] {
+            assert!(matches!(
+                SecureConnectBundle::from_files(files(config)),
+                Err(ClientError::InvalidBundle)
+            ));
+        }
+        assert!(matches!(
+            SecureConnectBundle::from_files(files("contact_points = node1")),
+            Err(ClientError::AddrError)
+        ));
+        let mut without_ca = files("contact_points = 127.0.0.1");
+        without_ca.remove(CA_FILE);
+        assert!(SecureConnectBundle::from_files(without_ca).is_err());
+    }
+}
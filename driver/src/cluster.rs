@@ -0,0 +1,629 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use native_protocol::{
+    checksum::Checksum,
+    messages::{error::Error, startup::Startup},
+};
+use rustls::ClientConfig;
+
+use crate::{
+    bundle::SecureConnectBundle, tls::configure_client, CassandraClient, ClientError, QueryResult,
+};
+
+/// Time a node that could not be connected to is tried after the others.
+const NODE_DOWN_TIME: Duration = Duration::from_secs(10);
+
+/// How many connections a session keeps to each node, and how it takes care of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolConfig {
+    /// Connections open to each node, at most. Requests wait for one of them to be free when
+    /// all of them are in use.
+    pub max_connections_per_node: usize,
+    /// How often the idle connections are checked with an `OPTIONS` request, so the broken
+    /// ones are replaced before a query needs them and nodes with an idle timeout do not
+    /// close them. `None` never checks them.
+    pub heartbeat_interval: Option<Duration>,
+    /// Time a request waits for a free connection to a node before trying the next one.
+    pub acquire_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_node: 4,
+            heartbeat_interval: Some(Duration::from_secs(30)),
+            acquire_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The nodes of a cluster and how to connect to them. The [`Session`]s opened with
+/// [`Cluster::connect`] keep a pool of TLS connections to each of them.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    contact_points: Vec<SocketAddr>,
+    fallback_points: Vec<SocketAddr>,
+    tls_config: ClientConfig,
+    pool_config: PoolConfig,
+    checksum: Option<Checksum>,
+    credentials: Option<(String, String)>,
+}
+
+impl Cluster {
+    /// A cluster reached through `contact_points`, with the TLS configuration of
+    /// [`CassandraClient::connect`] and the default [`PoolConfig`].
+    pub fn new(contact_points: Vec<SocketAddr>) -> Self {
+        Self {
+            contact_points,
+            fallback_points: Vec::new(),
+            tls_config: configure_client(),
+            pool_config: PoolConfig::default(),
+            checksum: None,
+            credentials: None,
+        }
+    }
+
+    /// A cluster reached through the contact points of the secure connect bundle at `path`,
+    /// with its TLS configuration (see [`SecureConnectBundle`]).
+    pub fn from_bundle(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let bundle = SecureConnectBundle::load(path)?;
+        Ok(Self::new(bundle.contact_points().to_vec()).with_tls_config(bundle.client_config()?))
+    }
+
+    pub fn with_tls_config(mut self, tls_config: ClientConfig) -> Self {
+        self.tls_config = tls_config;
+        self
+    }
+
+    pub fn with_pool_config(mut self, pool_config: PoolConfig) -> Self {
+        self.pool_config = pool_config;
+        self
+    }
+
+    /// Checksums the frames of every connection, as
+    /// [`CassandraClient::startup_with_checksum`] does.
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// Nodes used only while none of the contact points can be connected to, as a standby
+    /// cluster (or datacenter) for an active one. Queries go back to the contact points as soon
+    /// as one of them accepts connections again.
+    pub fn with_fallback_contact_points(mut self, fallback_points: Vec<SocketAddr>) -> Self {
+        self.fallback_points = fallback_points;
+        self
+    }
+
+    /// Logs every connection in as `user` with `password`, as
+    /// [`CassandraClient::with_credentials`] does.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn contact_points(&self) -> &[SocketAddr] {
+        &self.contact_points
+    }
+
+    pub fn fallback_contact_points(&self) -> &[SocketAddr] {
+        &self.fallback_points
+    }
+
+    /// Opens a session to the cluster, connecting to the first contact point that accepts it,
+    /// or else to the first fallback one. Fails with the error of the last node tried if none
+    /// of them does.
+    pub fn connect(&self) -> Result<Session, ClientError> {
+        if self.contact_points.is_empty() && self.fallback_points.is_empty() {
+            return Err(ClientError::AddrError);
+        }
+        let shared = Arc::new(Shared {
+            cluster: self.clone(),
+            pools: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+            next_node: AtomicUsize::new(0),
+            coordinator: Mutex::new(None),
+        });
+
+        let mut last_error = ClientError::ConnectionError;
+        let mut connected = false;
+        for addr in shared.nodes_in_order() {
+            match shared.acquire(addr) {
+                Ok(client) => {
+                    shared.release(addr, client);
+                    shared.set_coordinator(addr);
+                    connected = true;
+                    break;
+                }
+                Err(err) => last_error = err,
+            }
+        }
+        if !connected {
+            return Err(last_error);
+        }
+
+        if let Some(interval) = self.pool_config.heartbeat_interval {
+            spawn_heartbeat(&shared, interval);
+        }
+        Ok(Session { shared })
+    }
+
+    fn startup_message(&self) -> Startup {
+        match self.checksum {
+            Some(checksum) => Startup::default().with_checksum(checksum),
+            None => Startup::default(),
+        }
+    }
+}
+
+/// Queries to a cluster, sent through a pool of connections to its nodes. It can be shared by
+/// several threads, each query taking a connection of the pool while it runs.
+///
+/// The nodes are used in turns. When a connection breaks in the middle of a query, the query
+/// is sent again to the next node only if it is idempotent, since the node may have run it
+/// before the connection broke. Queries answered as overloaded (by a draining node) were not
+/// run, so they are always sent to the next node.
+pub struct Session {
+    shared: Arc<Shared>,
+}
+
+impl Session {
+    /// Executes a query, retrying it on another node if it is a `SELECT`.
+    pub fn execute(&self, query: &str, consistency: &str) -> Result<QueryResult, ClientError> {
+        self.execute_with_retries(query, consistency, is_idempotent(query))
+    }
+
+    /// Executes a query that can be run more than once with the same effect (for example, an
+    /// `INSERT` of fixed values without `IF NOT EXISTS`), retrying it on another node if the
+    /// connection breaks.
+    pub fn execute_idempotent(
+        &self,
+        query: &str,
+        consistency: &str,
+    ) -> Result<QueryResult, ClientError> {
+        self.execute_with_retries(query, consistency, true)
+    }
+
+    /// Returns the connections open to each node, both idle and in use.
+    pub fn open_connections(&self) -> HashMap<SocketAddr, usize> {
+        self.shared
+            .lock_pools()
+            .map(|pools| {
+                pools
+                    .iter()
+                    .map(|(addr, pool)| (*addr, pool.open))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the node that answered the last query, or that the session connected to if no
+    /// query was answered yet.
+    pub fn coordinator(&self) -> Option<SocketAddr> {
+        self.shared.coordinator.lock().ok().and_then(|addr| *addr)
+    }
+
+    /// Whether queries are going to a fallback contact point, because none of the contact
+    /// points could be connected to.
+    pub fn is_on_fallback(&self) -> bool {
+        self.coordinator()
+            .is_some_and(|addr| self.shared.cluster.fallback_points.contains(&addr))
+    }
+
+    fn execute_with_retries(
+        &self,
+        query: &str,
+        consistency: &str,
+        idempotent: bool,
+    ) -> Result<QueryResult, ClientError> {
+        let mut last = Err(ClientError::ConnectionError);
+        for addr in self.shared.nodes_in_order() {
+            let mut client = match self.shared.acquire(addr) {
+                Ok(client) => client,
+                Err(err) => {
+                    last = Err(err);
+                    continue;
+                }
+            };
+
+            match client.execute(query, consistency) {
+                Ok(result @ QueryResult::Error(Error::Overloaded(_))) => {
+                    self.shared.release(addr, client);
+                    last = Ok(result);
+                }
+                Ok(result) => {
+                    self.shared.release(addr, client);
+                    self.shared.set_coordinator(addr);
+                    return Ok(result);
+                }
+                Err(err) if breaks_connection(&err) => {
+                    self.shared.discard(addr);
+                    if !idempotent {
+                        return Err(err);
+                    }
+                    last = Err(err);
+                }
+                Err(err) => {
+                    self.shared.release(addr, client);
+                    return Err(err);
+                }
+            }
+        }
+        last
+    }
+}
+
+/// Whether a query can be sent again without changing its effect, as far as its text tells.
+fn is_idempotent(query: &str) -> bool {
+    query
+        .trim_start()
+        .get(..6)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("SELECT"))
+}
+
+/// Whether the connection a request failed with can not be used anymore.
+fn breaks_connection(err: &ClientError) -> bool {
+    matches!(
+        err,
+        ClientError::IOError
+            | ClientError::ConnectionError
+            | ClientError::TimeoutError
+            | ClientError::InvalidFrame
+            | ClientError::DeserializationError
+    )
+}
+
+type Pools = HashMap<SocketAddr, NodePool<CassandraClient>>;
+
+/// What the session and its heartbeat thread share.
+struct Shared {
+    cluster: Cluster,
+    pools: Mutex<Pools>,
+    /// Notified when a connection is released or discarded, for requests waiting for one.
+    released: Condvar,
+    next_node: AtomicUsize,
+    /// The node that answered the last query.
+    coordinator: Mutex<Option<SocketAddr>>,
+}
+
+impl Shared {
+    fn lock_pools(&self) -> Result<MutexGuard<'_, Pools>, ClientError> {
+        self.pools.lock().map_err(|_| ClientError::ConnectionError)
+    }
+
+    /// Returns the contact points in the order a request tries them: starting from the next
+    /// one in turn, then the fallback ones, with the ones that could not be connected to lately
+    /// at the end.
+    fn nodes_in_order(&self) -> Vec<SocketAddr> {
+        let start = self.next_node.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let pools = self.lock_pools();
+        order_with_fallback(
+            &self.cluster.contact_points,
+            &self.cluster.fallback_points,
+            start,
+            |addr| {
+                pools
+                    .as_ref()
+                    .is_ok_and(|pools| pools.get(addr).is_some_and(|pool| pool.is_down(now)))
+            },
+        )
+    }
+
+    fn set_coordinator(&self, addr: SocketAddr) {
+        if let Ok(mut coordinator) = self.coordinator.lock() {
+            *coordinator = Some(addr);
+        }
+    }
+
+    /// Takes an idle connection to `addr`, opens a new one if the pool is not full, or waits
+    /// for one to be released.
+    fn acquire(&self, addr: SocketAddr) -> Result<CassandraClient, ClientError> {
+        let config = self.cluster.pool_config;
+        let deadline = Instant::now() + config.acquire_timeout;
+        let mut pools = self.lock_pools()?;
+        loop {
+            match pools
+                .entry(addr)
+                .or_default()
+                .checkout(config.max_connections_per_node)
+            {
+                Checkout::Idle(client) => return Ok(client),
+                Checkout::Open => {
+                    drop(pools);
+                    return self.open(addr);
+                }
+                Checkout::Busy => {
+                    let left = deadline
+                        .checked_duration_since(Instant::now())
+                        .filter(|left| !left.is_zero())
+                        .ok_or(ClientError::TimeoutError)?;
+                    pools = self
+                        .released
+                        .wait_timeout(pools, left)
+                        .map_err(|_| ClientError::ConnectionError)?
+                        .0;
+                }
+            }
+        }
+    }
+
+    // Opens the connection counted by a checkout, uncounting it if it fails.
+    fn open(&self, addr: SocketAddr) -> Result<CassandraClient, ClientError> {
+        let opened =
+            CassandraClient::connect_to(addr, self.cluster.tls_config.clone()).and_then(|client| {
+                let mut client = match &self.cluster.credentials {
+                    Some((user, password)) => client.with_credentials(user, password),
+                    None => client,
+                };
+                client.startup_with(self.cluster.startup_message())?;
+                Ok(client)
+            });
+
+        let mut pools = self.lock_pools()?;
+        let pool = pools.entry(addr).or_default();
+        match opened {
+            Ok(_) => pool.down_since = None,
+            Err(_) => {
+                pool.discard();
+                pool.down_since = Some(Instant::now());
+                self.released.notify_one();
+            }
+        }
+        opened
+    }
+
+    fn release(&self, addr: SocketAddr, client: CassandraClient) {
+        if let Ok(mut pools) = self.lock_pools() {
+            pools
+                .entry(addr)
+                .or_default()
+                .checkin(client, Instant::now());
+            self.released.notify_one();
+        }
+    }
+
+    fn discard(&self, addr: SocketAddr) {
+        if let Ok(mut pools) = self.lock_pools() {
+            pools.entry(addr).or_default().discard();
+            self.released.notify_one();
+        }
+    }
+
+    /// Sends an `OPTIONS` request through the connections idle for at least `interval`,
+    /// discarding the ones that do not answer.
+    fn check_idle_connections(&self, interval: Duration) {
+        let now = Instant::now();
+        let stale: Vec<(SocketAddr, CassandraClient)> = match self.lock_pools() {
+            Ok(mut pools) => pools
+                .iter_mut()
+                .flat_map(|(addr, pool)| {
+                    let addr = *addr;
+                    pool.take_stale(now, interval)
+                        .into_iter()
+                        .map(move |client| (addr, client))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+
+        for (addr, mut client) in stale {
+            match client.heartbeat() {
+                Ok(()) => self.release(addr, client),
+                Err(_) => self.discard(addr),
+            }
+        }
+    }
+}
+
+/// Checks the idle connections of the session every `interval`, until the session is dropped.
+fn spawn_heartbeat(shared: &Arc<Shared>, interval: Duration) {
+    let shared = Arc::downgrade(shared);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        shared.check_idle_connections(interval);
+    });
+}
+
+/// Returns `nodes` starting from the one at `start` (modulo their amount), with the ones that
+/// are down moved to the end.
+fn order_nodes(
+    nodes: &[SocketAddr],
+    start: usize,
+    is_down: impl Fn(&SocketAddr) -> bool,
+) -> Vec<SocketAddr> {
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+    let start = start % nodes.len();
+    let mut ordered: Vec<SocketAddr> = nodes[start..]
+        .iter()
+        .chain(&nodes[..start])
+        .copied()
+        .collect();
+    ordered.sort_by_key(|addr| is_down(addr));
+    ordered
+}
+
+/// Returns the `nodes` and then the `fallback` ones, each starting from the one at `start`,
+/// with the ones that are down moved to the end. A fallback node is only tried before a
+/// contact point when the contact point is down.
+fn order_with_fallback(
+    nodes: &[SocketAddr],
+    fallback: &[SocketAddr],
+    start: usize,
+    is_down: impl Fn(&SocketAddr) -> bool,
+) -> Vec<SocketAddr> {
+    let mut ordered = order_nodes(nodes, start, |_| false);
+    ordered.extend(order_nodes(fallback, start, |_| false));
+    ordered.sort_by_key(|addr| is_down(addr));
+    ordered
+}
+
+/// The connections to a node: the idle ones, with the time they were released, and how many
+/// are open in total (idle or in use).
+#[derive(Debug)]
+struct NodePool<C> {
+    idle: Vec<(C, Instant)>,
+    open: usize,
+    /// When a connection to the node last failed to open, if it did after the last one that
+    /// opened.
+    down_since: Option<Instant>,
+}
+
+impl<C> Default for NodePool<C> {
+    fn default() -> Self {
+        Self {
+            idle: Vec::new(),
+            open: 0,
+            down_since: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Checkout<C> {
+    /// An idle connection, now in use.
+    Idle(C),
+    /// No connection is idle, but one more can be opened. It is already counted as open.
+    Open,
+    /// Every connection is in use and no more can be opened.
+    Busy,
+}
+
+impl<C> NodePool<C> {
+    fn checkout(&mut self, max_connections: usize) -> Checkout<C> {
+        // The most recently used connection is the most likely to still work
+        if let Some((connection, _)) = self.idle.pop() {
+            return Checkout::Idle(connection);
+        }
+        if self.open < max_connections {
+            self.open += 1;
+            return Checkout::Open;
+        }
+        Checkout::Busy
+    }
+
+    fn checkin(&mut self, connection: C, now: Instant) {
+        self.idle.push((connection, now));
+    }
+
+    /// Uncounts a connection in use that broke or failed to open.
+    fn discard(&mut self) {
+        self.open = self.open.saturating_sub(1);
+    }
+
+    /// Takes the connections idle for at least `interval`. They are still counted as open,
+    /// so they must be checked in or discarded.
+    fn take_stale(&mut self, now: Instant, interval: Duration) -> Vec<C> {
+        let (stale, fresh): (Vec<_>, Vec<_>) = self
+            .idle
+            .drain(..)
+            .partition(|(_, since)| now.duration_since(*since) >= interval);
+        self.idle = fresh;
+        stale
+            .into_iter()
+            .map(|(connection, _)| connection)
+            .collect()
+    }
+
+    fn is_down(&self, now: Instant) -> bool {
+        self.down_since
+            .is_some_and(|since| now.duration_since(since) < NODE_DOWN_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_pool_checkout_and_heartbeat() {
+        let now = Instant::now();
+        let mut pool = NodePool::default();
+
+        assert_eq!(pool.checkout(2), Checkout::Open);
+        assert_eq!(pool.checkout(2), Checkout::Open);
+        assert_eq!(pool.checkout(2), Checkout::Busy);
+
+        pool.checkin(1, now);
+        pool.checkin(2, now + Duration::from_secs(20));
+        assert_eq!(pool.open, 2);
+
+        // Only the connections idle for the whole interval are checked
+        assert_eq!(
+            pool.take_stale(now + Duration::from_secs(30), Duration::from_secs(30)),
+            vec![1]
+        );
+        assert_eq!(pool.checkout(2), Checkout::Idle(2));
+        // The stale connection broke, so another one can be opened
+        pool.discard();
+        assert_eq!(pool.checkout(2), Checkout::Open);
+        assert_eq!(pool.checkout(2), Checkout::Busy);
+
+        assert!(!pool.is_down(now));
+        pool.down_since = Some(now);
+        assert!(pool.is_down(now + Duration::from_secs(1)));
+        assert!(!pool.is_down(now + NODE_DOWN_TIME));
+    }
+
+    #[test]
+    fn test_nodes_are_used_in_turns_and_down_ones_last() {
+        let nodes: Vec<SocketAddr> = ["127.0.0.1:1", "127.0.0.2:1", "127.0.0.3:1"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+
+        assert_eq!(order_nodes(&nodes, 0, |_| false), nodes);
+        assert_eq!(
+            order_nodes(&nodes, 4, |_| false),
+            vec![nodes[1], nodes[2], nodes[0]]
+        );
+        assert_eq!(
+            order_nodes(&nodes, 1, |addr| *addr == nodes[1]),
+            vec![nodes[2], nodes[0], nodes[1]]
+        );
+        assert!(order_nodes(&[], 3, |_| false).is_empty());
+
+        assert!(is_idempotent("  select * FROM flights"));
+        assert!(!is_idempotent("INSERT INTO flights (id) VALUES (1)"));
+        assert!(!is_idempotent("SEL"));
+    }
+
+    #[test]
+    fn test_fallback_nodes_are_used_when_contact_points_are_down() {
+        let parse = |addrs: &[&str]| -> Vec<SocketAddr> {
+            addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+        };
+        let nodes = parse(&["127.0.0.1:1", "127.0.0.2:1"]);
+        let fallback = parse(&["127.0.0.3:1", "127.0.0.4:1"]);
+
+        assert_eq!(
+            order_with_fallback(&nodes, &fallback, 1, |_| false),
+            vec![nodes[1], nodes[0], fallback[1], fallback[0]]
+        );
+        // One contact point is still up, so it goes before the fallback nodes
+        assert_eq!(
+            order_with_fallback(&nodes, &fallback, 0, |addr| *addr == nodes[0]),
+            vec![nodes[1], fallback[0], fallback[1], nodes[0]]
+        );
+        assert_eq!(
+            order_with_fallback(&nodes, &fallback, 0, |addr| nodes.contains(addr)),
+            vec![fallback[0], fallback[1], nodes[0], nodes[1]]
+        );
+        assert_eq!(order_with_fallback(&[], &fallback, 0, |_| false), fallback);
+    }
+}
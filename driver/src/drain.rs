@@ -0,0 +1,118 @@
+use std::{fmt, net::Ipv4Addr, str::FromStr, time::Duration};
+
+use crate::{
+    admin::{send_admin_command, send_admin_command_with_timeout},
+    ClientError,
+};
+
+/// Extra time the answer to `DRAIN` is waited for, besides the time the node waits for its
+/// open queries.
+const DRAIN_ANSWER_MARGIN: Duration = Duration::from_secs(5);
+
+/// What a node did to drain: the client queries it was coordinating that finished before the
+/// timeout, the ones still open when it gave up waiting, and the memtables it flushed.
+///
+/// Reports are sent over the admin port as a single line:
+/// `<finished queries> <abandoned queries> <flushed memtables>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DrainReport {
+    pub finished_queries: usize,
+    pub abandoned_queries: usize,
+    pub flushed_memtables: usize,
+}
+
+impl DrainReport {
+    /// Whether every open query finished before the node stopped waiting.
+    pub fn is_clean(&self) -> bool {
+        self.abandoned_queries == 0
+    }
+}
+
+impl fmt::Display for DrainReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.finished_queries, self.abandoned_queries, self.flushed_memtables
+        )
+    }
+}
+
+impl FromStr for DrainReport {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let mut next = || -> Result<usize, ClientError> {
+            tokens
+                .next()
+                .ok_or(ClientError::DeserializationError)?
+                .parse()
+                .map_err(|_| ClientError::DeserializationError)
+        };
+
+        Ok(DrainReport {
+            finished_queries: next()?,
+            abandoned_queries: next()?,
+            flushed_memtables: next()?,
+        })
+    }
+}
+
+/// Drains the node at `ip` before it is restarted: it stops taking client queries, waits up to
+/// `timeout` for the ones it is coordinating, flushes its memtables and announces it is leaving.
+/// Returns once the node is drained, so a rolling restart can go on with the node.
+pub fn drain_node(ip: Ipv4Addr, timeout: Duration) -> Result<DrainReport, ClientError> {
+    let response = send_admin_command_with_timeout(
+        ip,
+        &format!("DRAIN {}", timeout.as_secs()),
+        timeout + DRAIN_ANSWER_MARGIN,
+    )?;
+    DrainReport::from_str(response.trim())
+}
+
+/// Decommissions the node at `ip`: it drains, streams its rows to the nodes that take its ranges,
+/// waits up to `timeout` for the drain and for the rows to be acknowledged, and leaves the ring.
+/// Returns the amount of rows streamed once the node left, shortly before it stops.
+pub fn decommission_node(ip: Ipv4Addr, timeout: Duration) -> Result<usize, ClientError> {
+    let response = send_admin_command_with_timeout(
+        ip,
+        &format!("DECOMMISSION {}", timeout.as_secs()),
+        timeout + DRAIN_ANSWER_MARGIN,
+    )?;
+    response
+        .trim()
+        .parse()
+        .map_err(|_| ClientError::DeserializationError)
+}
+
+/// Removes the dead node `removed` from the cluster through the node at `ip`. The removal is
+/// gossiped to every node, which takes the removed node out of its ring and never takes it back.
+pub fn remove_node(ip: Ipv4Addr, removed: Ipv4Addr) -> Result<(), ClientError> {
+    send_admin_command(ip, &format!("REMOVENODE {}", removed))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_report_round_trip() {
+        let report = DrainReport::from_str("12 1 3").unwrap();
+        assert_eq!(
+            report,
+            DrainReport {
+                finished_queries: 12,
+                abandoned_queries: 1,
+                flushed_memtables: 3,
+            }
+        );
+        assert_eq!(report.to_string(), "12 1 3");
+        assert!(!report.is_clean());
+        assert!(DrainReport::default().is_clean());
+
+        assert!(DrainReport::from_str("12 1").is_err());
+        assert!(DrainReport::from_str("12 -1 3").is_err());
+    }
+}
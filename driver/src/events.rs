@@ -0,0 +1,203 @@
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+use crate::{admin::send_admin_command, ClientError};
+
+/// A high level event that happened in a node, as seen by that node.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeEvent {
+    /// A node joined the ring.
+    NodeJoined(Ipv4Addr),
+    /// A node was detected as dead and left the ring.
+    NodeDied(Ipv4Addr),
+    /// A node changed its position in the ring.
+    NodeMoved(Ipv4Addr),
+    /// A node restarted with empty storage and its data is being streamed to it again.
+    NodeRestarted(Ipv4Addr),
+    /// A node was decommissioned and left the ring, after streaming its data to the others.
+    NodeLeft(Ipv4Addr),
+    /// A dead node was removed from the cluster by an operator and left the ring.
+    NodeRemoved(Ipv4Addr),
+    /// The node started moving its data after a change in the ring.
+    RedistributionStarted,
+    /// The node finished moving its data.
+    RedistributionFinished,
+    /// The node could not move its data.
+    RedistributionFailed,
+    /// The schema of the cluster changed (a keyspace or table was created, altered or dropped).
+    SchemaChanged,
+    /// The node was drained: it takes no more client queries and can be stopped.
+    Drained,
+    /// The node streamed its data to the rest of the ring and is leaving it.
+    Decommissioned,
+}
+
+impl fmt::Display for NodeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeEvent::NodeJoined(ip) => write!(f, "NODE_JOINED {}", ip),
+            NodeEvent::NodeDied(ip) => write!(f, "NODE_DIED {}", ip),
+            NodeEvent::NodeMoved(ip) => write!(f, "NODE_MOVED {}", ip),
+            NodeEvent::NodeRestarted(ip) => write!(f, "NODE_RESTARTED {}", ip),
+            NodeEvent::NodeLeft(ip) => write!(f, "NODE_LEFT {}", ip),
+            NodeEvent::NodeRemoved(ip) => write!(f, "NODE_REMOVED {}", ip),
+            NodeEvent::RedistributionStarted => write!(f, "REDISTRIBUTION_STARTED"),
+            NodeEvent::RedistributionFinished => write!(f, "REDISTRIBUTION_FINISHED"),
+            NodeEvent::RedistributionFailed => write!(f, "REDISTRIBUTION_FAILED"),
+            NodeEvent::SchemaChanged => write!(f, "SCHEMA_CHANGED"),
+            NodeEvent::Drained => write!(f, "DRAINED"),
+            NodeEvent::Decommissioned => write!(f, "DECOMMISSIONED"),
+        }
+    }
+}
+
+/// An event recorded by a node, with the position it was given in the node's event log.
+///
+/// Records are sent over the admin port as one line each: `<id> <timestamp> <event>`, where
+/// the timestamp is in seconds since the epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    pub id: u64,
+    pub timestamp: i64,
+    pub event: NodeEvent,
+}
+
+impl fmt::Display for EventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.id, self.timestamp, self.event)
+    }
+}
+
+impl FromStr for EventRecord {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let mut next = || tokens.next().ok_or(ClientError::DeserializationError);
+
+        let id = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let timestamp = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+
+        let event = match next()? {
+            "NODE_JOINED" => NodeEvent::NodeJoined(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "NODE_DIED" => NodeEvent::NodeDied(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "NODE_MOVED" => NodeEvent::NodeMoved(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "NODE_RESTARTED" => NodeEvent::NodeRestarted(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "NODE_LEFT" => NodeEvent::NodeLeft(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "NODE_REMOVED" => NodeEvent::NodeRemoved(
+                next()?
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            "REDISTRIBUTION_STARTED" => NodeEvent::RedistributionStarted,
+            "REDISTRIBUTION_FINISHED" => NodeEvent::RedistributionFinished,
+            "REDISTRIBUTION_FAILED" => NodeEvent::RedistributionFailed,
+            "SCHEMA_CHANGED" => NodeEvent::SchemaChanged,
+            "DRAINED" => NodeEvent::Drained,
+            "DECOMMISSIONED" => NodeEvent::Decommissioned,
+            _ => return Err(ClientError::DeserializationError),
+        };
+
+        Ok(EventRecord {
+            id,
+            timestamp,
+            event,
+        })
+    }
+}
+
+/// Returns the events recorded by the node at `ip` after the event with id `since`, oldest first.
+///
+/// Pass `0` to get every event the node still remembers, and the id of the last event received
+/// on the following calls to poll only for new ones.
+pub fn poll_events(ip: Ipv4Addr, since: u64) -> Result<Vec<EventRecord>, ClientError> {
+    let response = send_admin_command(ip, &format!("EVENTS {}", since))?;
+    response
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(EventRecord::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_record_round_trip() {
+        let records = vec![
+            EventRecord {
+                id: 1,
+                timestamp: 1733000000,
+                event: NodeEvent::NodeJoined(Ipv4Addr::new(127, 0, 0, 2)),
+            },
+            EventRecord {
+                id: 2,
+                timestamp: 1733000001,
+                event: NodeEvent::NodeDied(Ipv4Addr::new(127, 0, 0, 3)),
+            },
+            EventRecord {
+                id: 3,
+                timestamp: 1733000002,
+                event: NodeEvent::RedistributionFinished,
+            },
+            EventRecord {
+                id: 4,
+                timestamp: 1733000003,
+                event: NodeEvent::Drained,
+            },
+            EventRecord {
+                id: 5,
+                timestamp: 1733000004,
+                event: NodeEvent::NodeLeft(Ipv4Addr::new(127, 0, 0, 4)),
+            },
+            EventRecord {
+                id: 6,
+                timestamp: 1733000005,
+                event: NodeEvent::Decommissioned,
+            },
+            EventRecord {
+                id: 7,
+                timestamp: 1733000006,
+                event: NodeEvent::NodeRemoved(Ipv4Addr::new(127, 0, 0, 5)),
+            },
+        ];
+
+        for record in records {
+            let parsed = EventRecord::from_str(&record.to_string()).unwrap();
+            assert_eq!(parsed, record);
+        }
+    }
+
+    #[test]
+    fn test_invalid_event_records() {
+        assert!(EventRecord::from_str("").is_err());
+        assert!(EventRecord::from_str("1 1733000000").is_err());
+        assert!(EventRecord::from_str("1 1733000000 NODE_JOINED").is_err());
+        assert!(EventRecord::from_str("1 1733000000 NODE_REBOOTED").is_err());
+        assert!(EventRecord::from_str("x 1733000000 SCHEMA_CHANGED").is_err());
+    }
+}
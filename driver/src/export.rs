@@ -0,0 +1,136 @@
+use std::{fmt, net::Ipv4Addr, ops::Range, str::FromStr};
+
+use crate::{admin::send_admin_command, ring::TokenRange, ClientError};
+
+/// Format of the files a node exports the rows of a table to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// One line per row, with the names of the columns in the first line.
+    Csv,
+    /// Apache Parquet, with one typed column per column of the table.
+    Parquet,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "CSV"),
+            ExportFormat::Parquet => write!(f, "PARQUET"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ClientError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_uppercase().as_str() {
+            "CSV" => Ok(ExportFormat::Csv),
+            "PARQUET" => Ok(ExportFormat::Parquet),
+            _ => Err(ClientError::DeserializationError),
+        }
+    }
+}
+
+/// What a node wrote when it exported a table: the rows and the size of the file.
+///
+/// Reports are sent over the admin port as a single line: `<rows> <bytes>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportReport {
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+impl fmt::Display for ExportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.rows, self.bytes)
+    }
+}
+
+impl FromStr for ExportReport {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let mut next = || -> Result<u64, ClientError> {
+            tokens
+                .next()
+                .ok_or(ClientError::DeserializationError)?
+                .parse()
+                .map_err(|_| ClientError::DeserializationError)
+        };
+
+        Ok(ExportReport {
+            rows: next()?,
+            bytes: next()?,
+        })
+    }
+}
+
+/// Asks the node at `ip` to write the rows it stores of `keyspace.table`, as an owner or a
+/// replica, to the file at `path` of the node. With `tokens`, only the partitions in that range
+/// of the ring are written.
+///
+/// The rows are read from the storage of the node, without going through a coordinator, so
+/// exporting each range returned by `ring::token_splits` from its owner writes the whole table
+/// once, spread over the nodes of the ring.
+pub fn export_table(
+    ip: Ipv4Addr,
+    keyspace: &str,
+    table: &str,
+    format: ExportFormat,
+    path: &str,
+    tokens: Option<Range<u64>>,
+) -> Result<ExportReport, ClientError> {
+    let mut command = format!("EXPORT TABLE {}.{} {} {}", keyspace, table, format, path);
+    if let Some(tokens) = tokens {
+        command.push_str(&format!(" {} {}", tokens.start, tokens.end));
+    }
+    let response = send_admin_command(ip, &command)?;
+    ExportReport::from_str(response.trim())
+}
+
+/// Exports the rows of `keyspace.table` in `range` from the owner of the range, to the file at
+/// `path` of that node.
+pub fn export_range(
+    range: &TokenRange,
+    keyspace: &str,
+    table: &str,
+    format: ExportFormat,
+    path: &str,
+) -> Result<ExportReport, ClientError> {
+    export_table(
+        range.owner,
+        keyspace,
+        table,
+        format,
+        path,
+        Some(range.start..range.end),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_format_and_report_round_trip() {
+        assert_eq!(ExportFormat::from_str("csv").unwrap(), ExportFormat::Csv);
+        assert_eq!(
+            ExportFormat::from_str(&ExportFormat::Parquet.to_string()).unwrap(),
+            ExportFormat::Parquet
+        );
+        assert!(ExportFormat::from_str("json").is_err());
+
+        let report = ExportReport::from_str("120 4096").unwrap();
+        assert_eq!(
+            report,
+            ExportReport {
+                rows: 120,
+                bytes: 4096
+            }
+        );
+        assert_eq!(report.to_string(), "120 4096");
+        assert!(ExportReport::from_str("120").is_err());
+    }
+}
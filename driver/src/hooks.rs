@@ -0,0 +1,175 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Mutex, time::Duration};
+
+use native_protocol::messages::error::Error;
+
+use crate::ClientError;
+
+/// A request about to be sent by a [`CassandraClient`](crate::CassandraClient).
+#[derive(Debug, Clone, Copy)]
+pub struct RequestStart<'a> {
+    /// Address of the node the request is sent to.
+    pub node: SocketAddr,
+    pub query: &'a str,
+    pub consistency: &'a str,
+}
+
+/// How a request ended.
+#[derive(Debug, Clone, Copy)]
+pub enum RequestOutcome<'a> {
+    /// The node answered with a result.
+    Success,
+    /// The node answered with an error.
+    ServerError(&'a Error),
+    /// The request could not be sent or its answer could not be read.
+    ClientError(&'a ClientError),
+}
+
+/// A request that ended, with the time it took.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestEnd<'a> {
+    /// Address of the node the request was sent to.
+    pub node: SocketAddr,
+    pub query: &'a str,
+    pub consistency: &'a str,
+    pub latency: Duration,
+    pub outcome: RequestOutcome<'a>,
+}
+
+impl RequestEnd<'_> {
+    /// Whether the request failed, either in the node or in the client.
+    pub fn is_error(&self) -> bool {
+        !matches!(self.outcome, RequestOutcome::Success)
+    }
+}
+
+/// Observer of the requests of a client, to log them or export metrics without changing the
+/// driver. Both methods do nothing by default.
+///
+/// Hooks are called on the thread executing the request, so they should return quickly.
+pub trait RequestHook: Send + Sync {
+    /// Called right before the request is sent.
+    fn on_request_start(&self, _request: &RequestStart) {}
+
+    /// Called once the request ended, successfully or not.
+    fn on_request_end(&self, _request: &RequestEnd) {}
+}
+
+/// Counters of the requests sent to a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NodeMetrics {
+    pub requests: u64,
+    pub errors: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl NodeMetrics {
+    /// Returns the mean latency of the requests, or zero if there were none.
+    pub fn mean_latency(&self) -> Duration {
+        if self.requests == 0 {
+            return Duration::ZERO;
+        }
+        self.total_latency / self.requests as u32
+    }
+}
+
+/// A hook that keeps the count, errors and latency of the requests sent to each node.
+///
+/// It can be shared between clients (wrapped in an `Arc`) to get the metrics of a whole
+/// application.
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    nodes: Mutex<HashMap<SocketAddr, NodeMetrics>>,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metrics of every node a request was sent to.
+    pub fn per_node(&self) -> HashMap<SocketAddr, NodeMetrics> {
+        match self.nodes.lock() {
+            Ok(nodes) => nodes.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Returns the metrics of all the nodes added up.
+    pub fn total(&self) -> NodeMetrics {
+        self.per_node()
+            .values()
+            .fold(NodeMetrics::default(), |total, node| NodeMetrics {
+                requests: total.requests + node.requests,
+                errors: total.errors + node.errors,
+                total_latency: total.total_latency + node.total_latency,
+                max_latency: total.max_latency.max(node.max_latency),
+            })
+    }
+}
+
+impl RequestHook for RequestMetrics {
+    fn on_request_end(&self, request: &RequestEnd) {
+        let mut nodes = match self.nodes.lock() {
+            Ok(nodes) => nodes,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let metrics = nodes.entry(request.node).or_default();
+        metrics.requests += 1;
+        if request.is_error() {
+            metrics.errors += 1;
+        }
+        metrics.total_latency += request.latency;
+        metrics.max_latency = metrics.max_latency.max(request.latency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn end(node: SocketAddr, latency_ms: u64, outcome: RequestOutcome) -> RequestEnd {
+        RequestEnd {
+            node,
+            query: "SELECT * FROM flights",
+            consistency: "quorum",
+            latency: Duration::from_millis(latency_ms),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_request_metrics() {
+        let node_a = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 17989);
+        let node_b = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)), 17989);
+        let metrics = RequestMetrics::new();
+
+        metrics.on_request_end(&end(node_a, 10, RequestOutcome::Success));
+        metrics.on_request_end(&end(node_a, 30, RequestOutcome::Success));
+        metrics.on_request_end(&end(
+            node_b,
+            5,
+            RequestOutcome::ClientError(&ClientError::TimeoutError),
+        ));
+
+        let per_node = metrics.per_node();
+        assert_eq!(per_node[&node_a].requests, 2);
+        assert_eq!(per_node[&node_a].errors, 0);
+        assert_eq!(per_node[&node_a].mean_latency(), Duration::from_millis(20));
+        assert_eq!(per_node[&node_a].max_latency, Duration::from_millis(30));
+        assert_eq!(per_node[&node_b].errors, 1);
+
+        let total = metrics.total();
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.errors, 1);
+        assert_eq!(total.total_latency, Duration::from_millis(45));
+        assert_eq!(total.max_latency, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_mean_latency_without_requests() {
+        assert_eq!(NodeMetrics::default().mean_latency(), Duration::ZERO);
+    }
+}
@@ -1,32 +1,99 @@
 use std::{
+    collections::HashMap,
     env,
-    io::{Read, Write},
+    io::Write,
     net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream},
+    path::Path,
     sync::Arc,
+    time::{Duration, Instant},
 };
+pub mod admin;
+pub mod asynch;
+pub mod bundle;
+pub mod cluster;
+pub mod drain;
+pub mod events;
+pub mod export;
+pub mod hooks;
+pub mod maintenance;
+pub mod ring;
+pub mod sampling;
 pub mod server;
+pub mod timings;
 mod tls;
 
+use bundle::SecureConnectBundle;
+use hooks::{RequestEnd, RequestHook, RequestOutcome, RequestStart};
 use native_protocol::{
     self,
-    frame::Frame,
+    checksum::Checksum,
+    frame::{Frame, FrameOptions},
+    framing,
     messages::{
         self,
         auth::AuthResponse,
+        batch::{Batch, BatchType},
+        error::Error,
+        execute::Execute,
+        prepare::Prepare,
         query::{Consistency, Query, QueryParams},
+        result::result_,
+        startup::Startup,
     },
-    types::Bytes,
+    types::{Bytes, CustomPayload},
     Serializable,
 };
+use ring::TokenRange;
 use rustls::{ClientConfig, ClientConnection, StreamOwned};
+use timings::QueryTimings;
 use tls::configure_client;
 
+/// Time the answer to a query is waited for.
+const READ_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Rows of a result the node is asked to send in each frame. The pages of a result are read until
+/// its last one, so the caller gets it whole.
+const PAGE_SIZE: i32 = 5000;
+
+/// Time the answer to a schema change is waited for. The node answers it once the live nodes
+/// agree on the new schema, which it waits up to 10 seconds for.
+pub const SCHEMA_CHANGE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Returns whether `query` changes the schema (`CREATE`, `ALTER` or `DROP`), whose answer is waited
+/// for up to `SCHEMA_CHANGE_TIMEOUT`.
+pub(crate) fn is_schema_change(query: &str) -> bool {
+    let statement = query.split_whitespace().next().unwrap_or_default();
+    ["CREATE", "ALTER", "DROP"]
+        .iter()
+        .any(|keyword| statement.eq_ignore_ascii_case(keyword))
+}
+
+/// Returns the token of the `AUTH_RESPONSE` of a connection: SASL `PLAIN` credentials, or the
+/// password of the default superuser.
+pub(crate) fn auth_token(credentials: Option<&(String, String)>) -> Vec<u8> {
+    match credentials {
+        Some((user, password)) => format!("\0{}\0{}", user, password).into_bytes(),
+        None => "admin".as_bytes().to_vec(),
+    }
+}
+
 pub struct CassandraClient {
     stream: StreamOwned<ClientConnection, TcpStream>,
     config: ClientConfig,
+    node: SocketAddr,
+    hooks: Vec<Arc<dyn RequestHook>>,
+    /// Connections opened by `execute_on` to other nodes, kept to be reused.
+    node_connections: HashMap<Ipv4Addr, CassandraClient>,
+    /// Options of the frames negotiated in the `STARTUP` of the connection.
+    frame_options: FrameOptions,
+    /// Options of the frames `startup` asks the node for.
+    startup_options: FrameOptions,
+    /// User and password the client logs in with, instead of the default superuser.
+    credentials: Option<(String, String)>,
 }
 
-const NATIVE_PORT: u16 = 0x4645;
+/// Port the nodes take the connections of their clients on.
+pub const NATIVE_PORT: u16 = 0x4645;
 
 #[derive(Debug)]
 pub enum ClientError {
@@ -39,6 +106,7 @@ pub enum ClientError {
     IOError,
     SerializationError,
     DeserializationError,
+    InvalidBundle,
 }
 
 #[derive(Debug)]
@@ -47,6 +115,27 @@ pub enum QueryResult {
     Error(messages::error::Error),
 }
 
+/// A query prepared in a node, to be executed with [`CassandraClient::execute_prepared`] by
+/// binding values to its `?` markers instead of sending (and parsing) the whole query again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedStatement {
+    id: Vec<u8>,
+    query: String,
+    bind_markers: usize,
+}
+
+impl PreparedStatement {
+    /// Returns the query that was prepared.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Returns the amount of values the query must be executed with.
+    pub fn bind_markers(&self) -> usize {
+        self.bind_markers
+    }
+}
+
 impl CassandraClient {
     /// Creates a connection with the node at `ip`.
     pub fn connect(ip: Ipv4Addr) -> Result<Self, ClientError> {
@@ -66,7 +155,7 @@ impl CassandraClient {
         };
 
         let sock = TcpStream::connect(addr).map_err(|_| ClientError::ConnectionError)?;
-        sock.set_read_timeout(Some(std::time::Duration::from_secs(3)))
+        sock.set_read_timeout(Some(READ_TIMEOUT))
             .map_err(|_| ClientError::TimeoutError)?;
         sock.set_write_timeout(Some(std::time::Duration::from_secs(3)))
             .map_err(|_| ClientError::TimeoutError)?;
@@ -75,10 +164,63 @@ impl CassandraClient {
         Ok(Self {
             stream: tls,
             config: config,
+            node: addr,
+            hooks: Vec::new(),
+            node_connections: HashMap::new(),
+            frame_options: FrameOptions::default(),
+            startup_options: FrameOptions::default(),
+            credentials: None,
         })
     }
 
+    /// Creates a connection with the node at `ip` as `connect` does, whose `startup` asks the
+    /// node to compress (with LZ4 or Snappy) and checksum the frames that follow it as in
+    /// `options`. The frames are sent as they are if the node does not support the options.
+    ///
+    /// Compression pays off with large results or batches over slow links, and costs CPU in
+    /// both ends otherwise.
+    pub fn connect_with_options(ip: Ipv4Addr, options: FrameOptions) -> Result<Self, ClientError> {
+        let mut client = Self::connect(ip)?;
+        client.startup_options = options;
+        Ok(client)
+    }
+
     pub fn connect_with_config(ip: Ipv4Addr, config: ClientConfig) -> Result<Self, ClientError> {
+        let addr = if let Ok(var) = env::var("NODE_ADDR") {
+            var.parse().map_err(|_| ClientError::AddrError)?
+        } else {
+            SocketAddr::new(IpAddr::V4(ip), NATIVE_PORT)
+        };
+
+        Self::connect_to(addr, config)
+    }
+
+    /// Creates a connection with the first contact point of the secure connect bundle at `path`
+    /// (a zip file or a directory, see [`SecureConnectBundle`]) that accepts it, using the CA and
+    /// client certificate of the bundle. Fails with the error of the last contact point if none
+    /// of them does.
+    pub fn from_bundle(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let bundle = SecureConnectBundle::load(path)?;
+        let config = bundle.client_config()?;
+
+        let mut last_error = ClientError::ConnectionError;
+        for &addr in bundle.contact_points() {
+            match Self::connect_to(addr, config.clone()) {
+                Ok(client) => return Ok(client),
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Creates a connection with the node listening for clients at `addr`, as `connect` does but
+    /// on any port and ignoring `NODE_ADDR`.
+    pub fn connect_to_addr(addr: SocketAddr) -> Result<Self, ClientError> {
+        Self::connect_to(addr, configure_client())
+    }
+
+    /// Creates a connection with the node at `addr`, ignoring `NODE_ADDR`.
+    fn connect_to(addr: SocketAddr, config: ClientConfig) -> Result<Self, ClientError> {
         let config_arc = Arc::new(config.clone());
         // Configurar TLS sin verificación de certificados
         let server_name = rustls::pki_types::ServerName::try_from("databaseserver")
@@ -86,14 +228,8 @@ impl CassandraClient {
         let conn = ClientConnection::new(config_arc, server_name)
             .map_err(|_| ClientError::ConnectionError)?;
 
-        let addr = if let Ok(var) = env::var("NODE_ADDR") {
-            var.parse().map_err(|_| ClientError::AddrError)?
-        } else {
-            SocketAddr::new(IpAddr::V4(ip), NATIVE_PORT)
-        };
-
         let sock = TcpStream::connect(addr).map_err(|_| ClientError::ConnectionError)?;
-        sock.set_read_timeout(Some(std::time::Duration::from_secs(3)))
+        sock.set_read_timeout(Some(READ_TIMEOUT))
             .map_err(|_| ClientError::TimeoutError)?;
         sock.set_write_timeout(Some(std::time::Duration::from_secs(3)))
             .map_err(|_| ClientError::TimeoutError)?;
@@ -102,22 +238,259 @@ impl CassandraClient {
         Ok(Self {
             stream: tls,
             config: config,
+            node: addr,
+            hooks: Vec::new(),
+            node_connections: HashMap::new(),
+            frame_options: FrameOptions::default(),
+            startup_options: FrameOptions::default(),
+            credentials: None,
         })
     }
 
+    /// Logs in as `user` with `password` in the `startup` of the connection, instead of as the
+    /// default superuser (`admin`, with password `admin`).
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
     pub fn config(&self) -> ClientConfig {
         self.config.clone()
     }
 
-    /// Execute a query.
+    /// Returns the address of the node the client is connected to.
+    pub fn node(&self) -> SocketAddr {
+        self.node
+    }
+
+    /// Adds a hook called before and after every query executed by this client.
+    pub fn add_hook(&mut self, hook: Arc<dyn RequestHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Execute a query, calling the hooks of the client before and after it.
     pub fn execute(
         &mut self,
         query: &str,
         consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        self.with_hooks(query, consistency_str, |client| {
+            client.execute_query(query, consistency_str)
+        })
+    }
+
+    /// Executes a query as `execute` does, asking the node for where it spent the time of the
+    /// query. The timings are `None` if the node did not send them (for example, because the
+    /// query failed).
+    pub fn execute_with_timings(
+        &mut self,
+        query: &str,
+        consistency_str: &str,
+    ) -> Result<(QueryResult, Option<QueryTimings>), ClientError> {
+        let mut timings = None;
+        let result = self.with_hooks(query, consistency_str, |client| {
+            let consistency = Consistency::from_string(consistency_str)
+                .map_err(|_| ClientError::ConsistencyError)?;
+            let params = QueryParams::new(consistency, vec![]).with_timings();
+            let frame = Frame::Query(Query::new(query.to_string(), params));
+
+            let (result, payload) = client.send_frame_with_payload(&frame)?;
+            timings = payload
+                .map(|payload| QueryTimings::from_payload(&payload))
+                .transpose()?;
+            match result {
+                Frame::Result(res) => Ok(QueryResult::Result(res)),
+                Frame::Error(err) => Ok(QueryResult::Error(err)),
+                _ => Err(ClientError::InvalidFrame),
+            }
+        })?;
+
+        Ok((result, timings))
+    }
+
+    /// Executes `queries` (`INSERT`, `UPDATE` and `DELETE` statements) in a single BATCH request,
+    /// so the node coordinating it sends every replica its part in one message. The hooks of the
+    /// client are called once, with the batch as a `BEGIN BATCH ... APPLY BATCH` statement.
+    pub fn execute_batch(
+        &mut self,
+        queries: &[String],
+        batch_type: BatchType,
+        consistency_str: &str,
     ) -> Result<QueryResult, ClientError> {
         let consistency =
             Consistency::from_string(consistency_str).map_err(|_| ClientError::ConsistencyError)?;
-        let result = self.send_query(query, consistency)?;
+        let batch = Batch::new(batch_type, queries.to_vec(), consistency);
+
+        self.with_hooks(&batch.to_cql(), consistency_str, |client| {
+            match client.send_frame(&Frame::Batch(batch))? {
+                Frame::Result(res) => Ok(QueryResult::Result(res)),
+                Frame::Error(err) => Ok(QueryResult::Error(err)),
+                _ => Err(ClientError::InvalidFrame),
+            }
+        })
+    }
+
+    /// Prepares `query` in the node the client is connected to. Its `?` markers are bound to
+    /// the values given to `execute_prepared` on each execution.
+    pub fn prepare(&mut self, query: &str) -> Result<PreparedStatement, ClientError> {
+        let prepare = Frame::Prepare(Prepare::new(query.to_string()));
+        match self.send_frame(&prepare)? {
+            Frame::Result(result_::Result::Prepared(prepared)) => Ok(PreparedStatement {
+                id: prepared.get_id().to_vec(),
+                query: query.to_string(),
+                bind_markers: prepared.get_metadata().columns_count as usize,
+            }),
+            Frame::Error(_) => Err(ClientError::ServerError),
+            _ => Err(ClientError::InvalidFrame),
+        }
+    }
+
+    /// Executes a prepared statement, binding `values` to its markers in order. Each value is
+    /// given as text without quotes (for example `EZE` or `42`), and the node binds it as the
+    /// type of the column of its marker, rejecting values that are not valid for it.
+    ///
+    /// If the node no longer knows the statement (for example, because it restarted), it is
+    /// prepared again and executed once more. The hooks of the client are called as in
+    /// `execute`, with the prepared query.
+    pub fn execute_prepared(
+        &mut self,
+        statement: &PreparedStatement,
+        values: &[&str],
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        let query = statement.query.clone();
+        self.with_hooks(&query, consistency_str, |client| {
+            let consistency = Consistency::from_string(consistency_str)
+                .map_err(|_| ClientError::ConsistencyError)?;
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+
+            let execute = Execute::new(statement.id.clone(), values.clone(), consistency.clone());
+            let mut result = client.send_frame(&Frame::Execute(execute))?;
+            if let Frame::Error(Error::Unprepared(_)) = result {
+                let statement = client.prepare(&statement.query)?;
+                let execute = Execute::new(statement.id, values, consistency);
+                result = client.send_frame(&Frame::Execute(execute))?;
+            }
+
+            match result {
+                Frame::Result(res) => Ok(QueryResult::Result(res)),
+                Frame::Error(err) => Ok(QueryResult::Error(err)),
+                _ => Err(ClientError::InvalidFrame),
+            }
+        })
+    }
+
+    // Runs a request, calling the hooks of the client before and after it.
+    fn with_hooks(
+        &mut self,
+        query: &str,
+        consistency_str: &str,
+        request: impl FnOnce(&mut Self) -> Result<QueryResult, ClientError>,
+    ) -> Result<QueryResult, ClientError> {
+        let start = RequestStart {
+            node: self.node,
+            query,
+            consistency: consistency_str,
+        };
+        for hook in &self.hooks {
+            hook.on_request_start(&start);
+        }
+
+        let started_at = Instant::now();
+        let result = request(self);
+
+        if !self.hooks.is_empty() {
+            let outcome = match &result {
+                Ok(QueryResult::Result(_)) => RequestOutcome::Success,
+                Ok(QueryResult::Error(err)) => RequestOutcome::ServerError(err),
+                Err(err) => RequestOutcome::ClientError(err),
+            };
+            let end = RequestEnd {
+                node: self.node,
+                query,
+                consistency: consistency_str,
+                latency: started_at.elapsed(),
+                outcome,
+            };
+            for hook in &self.hooks {
+                hook.on_request_end(&end);
+            }
+        }
+
+        result
+    }
+
+    /// Executes a query on the node at `ip`, instead of the node the client is connected to.
+    ///
+    /// Meant for administration and diagnostics, where the node coordinating the query matters
+    /// (for example, comparing what each replica answers with consistency `one`). The
+    /// first query to a node opens a connection to it with the configuration of this client,
+    /// which is kept for the following ones. The hooks of the client are called as in `execute`.
+    pub fn execute_on(
+        &mut self,
+        ip: Ipv4Addr,
+        query: &str,
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        if self.node.ip() == IpAddr::V4(ip) {
+            return self.execute(query, consistency_str);
+        }
+
+        if !self.node_connections.contains_key(&ip) {
+            let addr = SocketAddr::new(IpAddr::V4(ip), NATIVE_PORT);
+            let mut client = Self::connect_to(addr, self.config.clone())?;
+            client.credentials = self.credentials.clone();
+            client.startup_with(self.startup_message())?;
+            self.node_connections.insert(ip, client);
+        }
+
+        let hooks = self.hooks.clone();
+        let client = self
+            .node_connections
+            .get_mut(&ip)
+            .ok_or(ClientError::ConnectionError)?;
+        client.hooks = hooks;
+
+        let result = client.execute(query, consistency_str);
+        // A connection that failed is opened again on the next query
+        if result.is_err() {
+            self.node_connections.remove(&ip);
+        }
+        result
+    }
+
+    /// Splits the ring into about `n` ranges of tokens of roughly the same size, as known by the
+    /// node the client is connected to, for full table scans run in parallel (see
+    /// `ring::token_splits`).
+    pub fn token_splits(&self, n: usize) -> Result<Vec<TokenRange>, ClientError> {
+        match self.node.ip() {
+            IpAddr::V4(ip) => ring::token_splits(ip, n),
+            IpAddr::V6(_) => Err(ClientError::AddrError),
+        }
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), ClientError> {
+        self.stream
+            .sock
+            .set_read_timeout(Some(timeout))
+            .map_err(|_| ClientError::TimeoutError)
+    }
+
+    fn execute_query(
+        &mut self,
+        query: &str,
+        consistency_str: &str,
+    ) -> Result<QueryResult, ClientError> {
+        let consistency =
+            Consistency::from_string(consistency_str).map_err(|_| ClientError::ConsistencyError)?;
+        let result = if is_schema_change(query) {
+            self.set_read_timeout(SCHEMA_CHANGE_TIMEOUT)?;
+            let result = self.send_query(query, consistency);
+            self.set_read_timeout(READ_TIMEOUT)?;
+            result?
+        } else {
+            self.send_query(query, consistency)?
+        };
         match result {
             Frame::Result(res) => Ok(QueryResult::Result(res)),
             Frame::Error(err) => Ok(QueryResult::Error(err)),
@@ -125,8 +498,42 @@ impl CassandraClient {
         }
     }
 
+    /// Initializes the connection, with the options of `connect_with_options` if it was opened
+    /// with them, and authenticates the client.
     pub fn startup(&mut self) -> Result<(), ClientError> {
-        let startup = Frame::Startup;
+        self.startup_with(Startup::with_options(&self.startup_options))
+    }
+
+    /// Initializes the connection as `startup` does, asking the node to checksum the body of
+    /// every frame that follows with `checksum`, and checksumming the ones the client sends.
+    /// A frame whose checksum does not match fails with a `DeserializationError`.
+    ///
+    /// Meant for connections without TLS (for example, in local development) over links that
+    /// may corrupt long results; TLS connections already detect corrupted records.
+    pub fn startup_with_checksum(&mut self, checksum: Checksum) -> Result<(), ClientError> {
+        self.startup_with(Startup::default().with_checksum(checksum))
+    }
+
+    /// Checks that the connection still works by asking the node for the options it supports,
+    /// which it answers without running anything.
+    pub fn heartbeat(&mut self) -> Result<(), ClientError> {
+        match self.send_frame(&Frame::Options)? {
+            Frame::Supported(_) => Ok(()),
+            _ => Err(ClientError::InvalidFrame),
+        }
+    }
+
+    // Returns the `STARTUP` that negotiates the options of the frames of this connection, for
+    // the connections opened to other nodes.
+    fn startup_message(&self) -> Startup {
+        Startup::with_options(&self.frame_options)
+    }
+
+    fn startup_with(&mut self, startup: Startup) -> Result<(), ClientError> {
+        let frame_options = startup
+            .frame_options()
+            .map_err(|_| ClientError::SerializationError)?;
+        let startup = Frame::Startup(startup);
 
         self.stream
             .write_all(
@@ -136,37 +543,21 @@ impl CassandraClient {
             )
             .map_err(|_| ClientError::IOError)?;
 
-        let mut result = [0u8; 2048];
-        let _ = self
-            .stream
-            .read(&mut result)
-            .map_err(|_| ClientError::IOError)?;
+        let result = framing::read_frame(&mut self.stream).map_err(|_| ClientError::IOError)?;
 
         let response = Frame::from_bytes(&result).map_err(|_| ClientError::DeserializationError)?;
+        // The node accepted the options, so the frames after its answer use them
+        if !matches!(response, Frame::Error(_)) {
+            self.frame_options = frame_options;
+        }
 
         match response {
             Frame::Authenticate(_) => {
-                let auth_response = Frame::AuthResponse(AuthResponse::new(Bytes::Vec(
-                    "admin".to_string().as_bytes().to_vec(),
-                )));
-
-                self.stream
-                    .write_all(
-                        &auth_response
-                            .to_bytes()
-                            .map_err(|_| ClientError::SerializationError)?,
-                    )
-                    .map_err(|_| ClientError::IOError)?;
-
-                let mut result = [0u8; 2048];
-
-                let _ = self
-                    .stream
-                    .read(&mut result)
-                    .map_err(|_| ClientError::IOError)?;
+                let auth_response = Frame::AuthResponse(AuthResponse::new(Bytes::Vec(auth_token(
+                    self.credentials.as_ref(),
+                ))));
 
-                let response =
-                    Frame::from_bytes(&result).map_err(|_| ClientError::DeserializationError)?;
+                let (response, _) = self.send_frame_with_payload(&auth_response)?;
 
                 match response {
                     Frame::AuthSuccess(_) => return Ok(()),
@@ -183,28 +574,56 @@ impl CassandraClient {
         cql_query: &str,
         consistency: Consistency,
     ) -> Result<Frame, ClientError> {
-        let params = QueryParams::new(consistency, vec![]);
-        let query = Query::new(cql_query.to_string(), params);
-        let query = Frame::Query(query);
+        let params = QueryParams::new(consistency, vec![]).with_page_size(PAGE_SIZE);
+        let mut answer = self.send_frame(&Frame::Query(Query::new(
+            cql_query.to_string(),
+            params.clone(),
+        )))?;
 
+        // Asks for the pages after the first one until the node sends the last
+        while let Frame::Result(result_::Result::Rows(rows)) = &mut answer {
+            let Some(paging_state) = rows.metadata.paging_state.take() else {
+                break;
+            };
+            let params = params.clone().with_paging_state(paging_state);
+            match self.send_frame(&Frame::Query(Query::new(cql_query.to_string(), params)))? {
+                Frame::Result(result_::Result::Rows(page)) => {
+                    rows.rows_count += page.rows_count;
+                    rows.rows_content.extend(page.rows_content);
+                    rows.metadata.flags.has_more_pages = page.metadata.flags.has_more_pages;
+                    rows.metadata.paging_state = page.metadata.paging_state;
+                }
+                other => return Ok(other),
+            }
+        }
+        Ok(answer)
+    }
+
+    // Sends a request to the node and returns its answer.
+    fn send_frame(&mut self, frame: &Frame) -> Result<Frame, ClientError> {
+        self.send_frame_with_payload(frame).map(|(frame, _)| frame)
+    }
+
+    // Sends a request to the node and returns its answer, with the custom payload it came with.
+    fn send_frame_with_payload(
+        &mut self,
+        frame: &Frame,
+    ) -> Result<(Frame, Option<CustomPayload>), ClientError> {
         // Escribir la consulta en el stream
         self.stream
             .write_all(
-                query
-                    .to_bytes()
+                frame
+                    .to_bytes_with_options(&self.frame_options, None)
                     .map_err(|_| ClientError::SerializationError)?
                     .as_slice(),
             )
             .map_err(|_| ClientError::IOError)?;
 
-        let mut result = [0u8; 850000];
-
-        self.stream
-            .read(&mut result)
-            .map_err(|_| ClientError::IOError)?;
+        // The answer may take several reads, as long results do
+        let result = framing::read_frame(&mut self.stream).map_err(|_| ClientError::IOError)?;
 
         // Decodificar la respuesta
-        let result = Frame::from_bytes(&result).map_err(|_| ClientError::DeserializationError)?;
-        Ok(result)
+        Frame::from_bytes_with_options(&result, &self.frame_options)
+            .map_err(|_| ClientError::DeserializationError)
     }
 }
@@ -0,0 +1,166 @@
+use std::{fmt, net::Ipv4Addr, str::FromStr, time::Duration};
+
+use crate::{admin::send_admin_command_with_timeout, ClientError};
+
+/// Extra time the answer to `FLUSH`, `COMPACT` and `SNAPSHOT` is waited for, besides the time the
+/// node waits for the other nodes.
+const MAINTENANCE_ANSWER_MARGIN: Duration = Duration::from_secs(5);
+
+/// Time the node that gets a `FLUSH`, `COMPACT` or `SNAPSHOT` waits for the other nodes of the ring.
+pub const MAINTENANCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a node of the ring did when asked to flush, compact or take a snapshot of the tables of a
+/// keyspace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaintenanceStatus {
+    /// The node flushed the given amount of memtables, compacted the given amount of SSTables, or
+    /// put the given amount of files in the snapshot.
+    Done(usize),
+    /// The node failed, for the given reason.
+    Failed(String),
+    /// The node could not be reached, or did not answer in time.
+    Unreachable,
+}
+
+impl From<Result<u32, String>> for MaintenanceStatus {
+    fn from(outcome: Result<u32, String>) -> Self {
+        match outcome {
+            Ok(amount) => MaintenanceStatus::Done(amount as usize),
+            Err(reason) => MaintenanceStatus::Failed(reason),
+        }
+    }
+}
+
+/// The progress of a `FLUSH`, `COMPACT` or `SNAPSHOT` in a node of the ring.
+///
+/// The node that gets the command answers with one line per node of the ring, as
+/// `<ip> DONE <amount>`, `<ip> FAILED <reason>` or `<ip> UNREACHABLE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeProgress {
+    pub node: Ipv4Addr,
+    pub status: MaintenanceStatus,
+}
+
+impl NodeProgress {
+    pub fn is_done(&self) -> bool {
+        matches!(self.status, MaintenanceStatus::Done(_))
+    }
+}
+
+impl fmt::Display for NodeProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            MaintenanceStatus::Done(amount) => write!(f, "{} DONE {}", self.node, amount),
+            MaintenanceStatus::Failed(reason) => write!(f, "{} FAILED {}", self.node, reason),
+            MaintenanceStatus::Unreachable => write!(f, "{} UNREACHABLE", self.node),
+        }
+    }
+}
+
+impl FromStr for NodeProgress {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.trim().splitn(3, ' ');
+        let node = tokens
+            .next()
+            .and_then(|ip| ip.parse().ok())
+            .ok_or(ClientError::DeserializationError)?;
+
+        let status = match (tokens.next(), tokens.next()) {
+            (Some("DONE"), Some(amount)) => MaintenanceStatus::Done(
+                amount
+                    .parse()
+                    .map_err(|_| ClientError::DeserializationError)?,
+            ),
+            (Some("FAILED"), reason) => {
+                MaintenanceStatus::Failed(reason.unwrap_or_default().to_string())
+            }
+            (Some("UNREACHABLE"), None) => MaintenanceStatus::Unreachable,
+            _ => return Err(ClientError::DeserializationError),
+        };
+
+        Ok(NodeProgress { node, status })
+    }
+}
+
+/// Asks the node at `ip` to flush the memtables of `keyspace` (or only of `table`) in every node
+/// of the ring, and returns what each node did.
+pub fn flush(
+    ip: Ipv4Addr,
+    keyspace: &str,
+    table: Option<&str>,
+) -> Result<Vec<NodeProgress>, ClientError> {
+    run("FLUSH", ip, keyspace, table)
+}
+
+/// Asks the node at `ip` to compact the SSTables of `keyspace` (or only of `table`) into their
+/// data files in every node of the ring, and returns what each node did.
+pub fn compact(
+    ip: Ipv4Addr,
+    keyspace: &str,
+    table: Option<&str>,
+) -> Result<Vec<NodeProgress>, ClientError> {
+    run("COMPACT", ip, keyspace, table)
+}
+
+/// Asks the node at `ip` to take a snapshot named `tag` of `keyspace` in every node of the ring,
+/// and returns how many files each node put in it. A node brings the snapshot back when it is
+/// started with `restore_snapshot: <tag>` in its config.
+pub fn snapshot(ip: Ipv4Addr, keyspace: &str, tag: &str) -> Result<Vec<NodeProgress>, ClientError> {
+    send(ip, &format!("SNAPSHOT {} {}", keyspace, tag))
+}
+
+fn run(
+    command: &str,
+    ip: Ipv4Addr,
+    keyspace: &str,
+    table: Option<&str>,
+) -> Result<Vec<NodeProgress>, ClientError> {
+    let target = match table {
+        Some(table) => format!("{}.{}", keyspace, table),
+        None => keyspace.to_string(),
+    };
+    send(ip, &format!("{} {}", command, target))
+}
+
+fn send(ip: Ipv4Addr, command: &str) -> Result<Vec<NodeProgress>, ClientError> {
+    let response = send_admin_command_with_timeout(
+        ip,
+        command,
+        MAINTENANCE_TIMEOUT + MAINTENANCE_ANSWER_MARGIN,
+    )?;
+
+    response
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(NodeProgress::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_progress_round_trip() {
+        let node = Ipv4Addr::new(127, 0, 0, 2);
+        for status in [
+            MaintenanceStatus::Done(3),
+            MaintenanceStatus::Failed("Keyspace error".to_string()),
+            MaintenanceStatus::Unreachable,
+        ] {
+            let progress = NodeProgress {
+                node,
+                status: status.clone(),
+            };
+            assert_eq!(
+                NodeProgress::from_str(&progress.to_string()).unwrap(),
+                progress
+            );
+        }
+
+        assert!(NodeProgress::from_str("127.0.0.2 DONE many").is_err());
+        assert!(NodeProgress::from_str("node DONE 1").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::{fmt, net::Ipv4Addr, ops::Range, str::FromStr};
+
+use crate::{admin::send_admin_command, ClientError};
+
+/// A range of tokens of the ring and the node that owns it.
+///
+/// Ranges are sent over the admin port as one line each: `<start> <end> <owner>`, where `start`
+/// is the first token of the range and `end` the one after its last token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenRange {
+    pub start: u64,
+    pub end: u64,
+    pub owner: Ipv4Addr,
+}
+
+impl TokenRange {
+    pub fn new(tokens: Range<u64>, owner: Ipv4Addr) -> Self {
+        TokenRange {
+            start: tokens.start,
+            end: tokens.end,
+            owner,
+        }
+    }
+
+    /// Returns whether `token` is in the range.
+    pub fn contains(&self, token: u64) -> bool {
+        (self.start..self.end).contains(&token)
+    }
+}
+
+impl fmt::Display for TokenRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.start, self.end, self.owner)
+    }
+}
+
+impl FromStr for TokenRange {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let mut next = || tokens.next().ok_or(ClientError::DeserializationError);
+
+        let start = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let end = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let owner = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+
+        Ok(TokenRange { start, end, owner })
+    }
+}
+
+/// Asks the node at `ip` to split the ring into about `n` ranges of roughly the same size,
+/// sorted by token.
+///
+/// Each range is owned by a single node, so a full scan of a table can be run in parallel with
+/// one query per range, each one sent to the owner of its range.
+pub fn token_splits(ip: Ipv4Addr, n: usize) -> Result<Vec<TokenRange>, ClientError> {
+    let response = send_admin_command(ip, &format!("SPLITS {}", n))?;
+    response
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(TokenRange::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_range_round_trip() {
+        let range = TokenRange::new(101..4294967296, Ipv4Addr::new(127, 0, 0, 2));
+
+        let parsed = TokenRange::from_str(&range.to_string()).unwrap();
+        assert_eq!(parsed, range);
+        assert!(parsed.contains(101));
+        assert!(!parsed.contains(100));
+
+        assert!(TokenRange::from_str("101 201").is_err());
+        assert!(TokenRange::from_str("101 201 not_an_ip").is_err());
+    }
+}
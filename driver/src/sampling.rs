@@ -0,0 +1,154 @@
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+use crate::{admin::send_admin_command, ClientError};
+
+/// What a node read from a random subset of the partitions it owns of a table, to estimate
+/// the size of the table without reading all of it.
+///
+/// Samples are sent over the admin port as a single line:
+/// `<fraction> <partitions> <rows> <bytes>`, where `fraction` is the fraction of the partitions
+/// that was read, between 0 and 1.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableSample {
+    pub fraction: f64,
+    /// Partitions read.
+    pub partitions: u64,
+    /// Rows of the partitions read.
+    pub rows: u64,
+    /// Size of the rows read, as stored.
+    pub bytes: u64,
+}
+
+impl TableSample {
+    /// Returns the estimated amount of partitions of the table.
+    pub fn estimated_partitions(&self) -> u64 {
+        self.estimate(self.partitions)
+    }
+
+    /// Returns the estimated amount of rows of the table.
+    pub fn estimated_rows(&self) -> u64 {
+        self.estimate(self.rows)
+    }
+
+    /// Returns the estimated size of the table, in bytes.
+    pub fn estimated_bytes(&self) -> u64 {
+        self.estimate(self.bytes)
+    }
+
+    /// Returns the mean amount of rows of a partition, or zero if no partition was read.
+    pub fn mean_partition_rows(&self) -> f64 {
+        if self.partitions == 0 {
+            return 0.0;
+        }
+        self.rows as f64 / self.partitions as f64
+    }
+
+    /// Adds the sample of another node, taken with the same fraction. Nodes only sample the
+    /// partitions they own, so the samples of every node of the ring add up to a sample of the
+    /// whole table.
+    pub fn merge(&mut self, other: &TableSample) {
+        self.fraction = other.fraction;
+        self.partitions += other.partitions;
+        self.rows += other.rows;
+        self.bytes += other.bytes;
+    }
+
+    fn estimate(&self, sampled: u64) -> u64 {
+        if self.fraction <= 0.0 {
+            return 0;
+        }
+        (sampled as f64 / self.fraction).round() as u64
+    }
+}
+
+impl fmt::Display for TableSample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.fraction, self.partitions, self.rows, self.bytes
+        )
+    }
+}
+
+impl FromStr for TableSample {
+    type Err = ClientError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let mut next = || tokens.next().ok_or(ClientError::DeserializationError);
+
+        let fraction = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let partitions = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let rows = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+        let bytes = next()?
+            .parse()
+            .map_err(|_| ClientError::DeserializationError)?;
+
+        Ok(TableSample {
+            fraction,
+            partitions,
+            rows,
+            bytes,
+        })
+    }
+}
+
+/// Asks the node at `ip` to read about `percent`% of the partitions it owns of
+/// `keyspace.table`, picked at random.
+pub fn sample_table(
+    ip: Ipv4Addr,
+    keyspace: &str,
+    table: &str,
+    percent: f64,
+) -> Result<TableSample, ClientError> {
+    let response = send_admin_command(ip, &format!("SAMPLE {}.{} {}", keyspace, table, percent))?;
+    TableSample::from_str(response.trim())
+}
+
+/// Samples `keyspace.table` in each of `nodes` and adds up their samples, for approximate
+/// statistics of the whole table (for example, for summary panels). Every node of the ring
+/// must be given for the estimates to cover the whole table.
+pub fn sample_cluster(
+    nodes: &[Ipv4Addr],
+    keyspace: &str,
+    table: &str,
+    percent: f64,
+) -> Result<TableSample, ClientError> {
+    let mut sample = TableSample {
+        fraction: percent / 100.0,
+        ..TableSample::default()
+    };
+    for ip in nodes {
+        sample.merge(&sample_table(*ip, keyspace, table, percent)?);
+    }
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_sample_round_trip_and_estimates() {
+        let mut sample = TableSample::from_str("0.1 4 12 600").unwrap();
+        assert_eq!(sample.to_string(), "0.1 4 12 600");
+        assert_eq!(sample.estimated_partitions(), 40);
+        assert_eq!(sample.estimated_rows(), 120);
+        assert_eq!(sample.estimated_bytes(), 6000);
+        assert_eq!(sample.mean_partition_rows(), 3.0);
+
+        sample.merge(&TableSample::from_str("0.1 1 3 150").unwrap());
+        assert_eq!(sample.estimated_partitions(), 50);
+        assert_eq!(sample.estimated_rows(), 150);
+
+        assert_eq!(TableSample::default().estimated_rows(), 0);
+        assert!(TableSample::from_str("0.1 4 12").is_err());
+    }
+}
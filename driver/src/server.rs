@@ -1,4 +1,8 @@
-use native_protocol::{frame::Frame, messages::query::Query, types::Bytes, Serializable};
+use native_protocol::{
+    frame::{Frame, FrameOptions},
+    messages::{batch::Batch, execute::Execute, prepare::Prepare, query::Query, startup::Startup},
+    types::Bytes,
+};
 
 #[derive(Debug)]
 pub enum RequestError {
@@ -8,16 +12,28 @@ pub enum RequestError {
 
 #[derive(Debug)]
 pub enum Request {
-    Startup,
+    Startup(Startup),
+    Options,
     Query(Query),
+    Prepare(Prepare),
+    Execute(Execute),
+    Batch(Batch),
     AuthResponse(String),
 }
 
-pub fn handle_client_request(bytes: &[u8]) -> Result<Request, RequestError> {
-    let frame = Frame::from_bytes(bytes).map_err(|_| RequestError::InvalidConversion)?;
+/// Converts the bytes of a frame sent by a client to a request, verifying the checksum of its
+/// body and decompressing it with the `options` negotiated in the `STARTUP` of the
+/// connection, if any.
+pub fn handle_client_request(
+    bytes: &[u8],
+    options: &FrameOptions,
+) -> Result<Request, RequestError> {
+    let (frame, _) = Frame::from_bytes_with_options(bytes, options)
+        .map_err(|_| RequestError::InvalidConversion)?;
 
     match frame {
-        Frame::Startup => Ok(Request::Startup),
+        Frame::Startup(startup) => Ok(Request::Startup(startup)),
+        Frame::Options => Ok(Request::Options),
         Frame::AuthResponse(auth_response) => {
             let r = if let Bytes::Vec(vec) = auth_response.token {
                 String::from_utf8(vec).map_err(|_| RequestError::InvalidConversion)?
@@ -28,6 +44,9 @@ pub fn handle_client_request(bytes: &[u8]) -> Result<Request, RequestError> {
             Ok(Request::AuthResponse(r))
         }
         Frame::Query(query) => Ok(Request::Query(query)),
+        Frame::Prepare(prepare) => Ok(Request::Prepare(prepare)),
+        Frame::Execute(execute) => Ok(Request::Execute(execute)),
+        Frame::Batch(batch) => Ok(Request::Batch(batch)),
         _ => Err(RequestError::InvalidFrame),
     }
 }
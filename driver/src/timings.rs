@@ -0,0 +1,115 @@
+use std::{fmt, time::Duration};
+
+use native_protocol::types::CustomPayload;
+
+use crate::ClientError;
+
+/// Keys of the timings in the custom payload of a result, each holding a number of microseconds
+/// as an 8 bytes big endian integer.
+const PARSE_KEY: &str = "timing.parse_us";
+const ROUTE_KEY: &str = "timing.route_us";
+const REPLICA_WAIT_KEY: &str = "timing.replica_wait_us";
+const MERGE_KEY: &str = "timing.merge_us";
+const REPAIR_KEY: &str = "timing.repair_us";
+
+/// Where the node coordinating a query spent its time, sent in the custom payload of the result
+/// when the query is executed with [`CassandraClient::execute_with_timings`](crate::CassandraClient::execute_with_timings).
+///
+/// - `parse`: parsing the CQL statement.
+/// - `route`: finding the keyspace, table and replicas of the query and sending it to them.
+/// - `replica_wait`: waiting for the replicas to answer.
+/// - `merge`: building the result from the answers of the replicas.
+/// - `repair`: the read repair of the answers, which picks the newest version of each row and
+///   finds the replicas that answered with an old one (reads only). The newest rows are written
+///   to those replicas after the result is sent, so it is not part of the timings.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryTimings {
+    pub parse: Duration,
+    pub route: Duration,
+    pub replica_wait: Duration,
+    pub merge: Duration,
+    pub repair: Duration,
+}
+
+impl QueryTimings {
+    /// Returns the time of the query spent in the coordinator.
+    pub fn total(&self) -> Duration {
+        self.parse + self.route + self.replica_wait + self.merge + self.repair
+    }
+
+    pub fn to_payload(&self) -> CustomPayload {
+        self.entries()
+            .into_iter()
+            .map(|(key, duration)| {
+                let micros = i64::try_from(duration.as_micros()).unwrap_or(i64::MAX);
+                (key.to_string(), micros.to_be_bytes().to_vec())
+            })
+            .collect()
+    }
+
+    /// Reads the timings of a custom payload. Fails if any of them is missing.
+    pub fn from_payload(payload: &CustomPayload) -> Result<Self, ClientError> {
+        let read = |key: &str| -> Result<Duration, ClientError> {
+            let bytes: [u8; 8] = payload
+                .get(key)
+                .and_then(|value| value.as_slice().try_into().ok())
+                .ok_or(ClientError::DeserializationError)?;
+            let micros = u64::try_from(i64::from_be_bytes(bytes))
+                .map_err(|_| ClientError::DeserializationError)?;
+            Ok(Duration::from_micros(micros))
+        };
+
+        Ok(QueryTimings {
+            parse: read(PARSE_KEY)?,
+            route: read(ROUTE_KEY)?,
+            replica_wait: read(REPLICA_WAIT_KEY)?,
+            merge: read(MERGE_KEY)?,
+            repair: read(REPAIR_KEY)?,
+        })
+    }
+
+    fn entries(&self) -> [(&'static str, Duration); 5] {
+        [
+            (PARSE_KEY, self.parse),
+            (ROUTE_KEY, self.route),
+            (REPLICA_WAIT_KEY, self.replica_wait),
+            (MERGE_KEY, self.merge),
+            (REPAIR_KEY, self.repair),
+        ]
+    }
+}
+
+impl fmt::Display for QueryTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "parse {:?}, route {:?}, replica wait {:?}, merge {:?}, repair {:?}",
+            self.parse, self.route, self.replica_wait, self.merge, self.repair
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings_to_payload_and_back() {
+        let timings = QueryTimings {
+            parse: Duration::from_micros(40),
+            route: Duration::from_micros(120),
+            replica_wait: Duration::from_millis(3),
+            merge: Duration::from_micros(15),
+            repair: Duration::ZERO,
+        };
+
+        let payload = timings.to_payload();
+        assert_eq!(payload.len(), 5);
+        assert_eq!(QueryTimings::from_payload(&payload).unwrap(), timings);
+        assert_eq!(timings.total(), Duration::from_micros(3175));
+
+        let mut incomplete = payload;
+        incomplete.remove(MERGE_KEY);
+        assert!(QueryTimings::from_payload(&incomplete).is_err());
+    }
+}
@@ -23,6 +23,16 @@ pub fn configure_client() -> ClientConfig {
 
     let root_store = load_root_cert(&cert_path);
 
+    install_crypto_provider();
+
+    ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth()
+}
+
+/// Installs the crypto provider used by the TLS connections of the process, if it was not
+/// installed yet.
+pub(crate) fn install_crypto_provider() {
     // Configurar el proveedor criptográfico
     match rustls::crypto::aws_lc_rs::default_provider().install_default() {
         Ok(_) => {}
@@ -30,8 +40,4 @@ pub fn configure_client() -> ClientConfig {
             eprintln!("Failed to install CryptoProvider: {:?}", err);
         }
     }
-
-    ClientConfig::builder()
-        .with_root_certificates(root_store)
-        .with_no_client_auth()
 }
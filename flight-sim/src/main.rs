@@ -3,8 +3,10 @@ mod types;
 use crate::types::airport::Airport;
 use crate::types::flight::Flight;
 use chrono::{NaiveDateTime, Utc};
+use driver::admin::send_admin_command;
 use std::{
     io::{self, Write},
+    net::Ipv4Addr,
     sync::{Arc, Mutex},
 };
 use threadpool::ThreadPool;
@@ -79,6 +81,73 @@ fn set_time_rate(sim: &mut Simulation) -> Result<(), SimError> {
     Ok(())
 }
 
+fn parse_node_ip(arg: Option<&&str>) -> Result<Ipv4Addr, SimError> {
+    arg.ok_or(SimError::InvalidInput)?
+        .parse()
+        .map_err(|_| SimError::InvalidInput)
+}
+
+fn kill_node(args: &[&str]) -> Result<(), SimError> {
+    let node = parse_node_ip(args.get(1))?;
+    send_admin_command(node, "KILL").map_err(|_| SimError::ClientError)?;
+    println!("Node {} killed", node);
+    Ok(())
+}
+
+/// Splits the link between two nodes by telling each one to drop the messages of the other.
+fn partition(args: &[&str]) -> Result<(), SimError> {
+    let node = parse_node_ip(args.get(1))?;
+    let peer = parse_node_ip(args.get(2))?;
+
+    send_admin_command(node, &format!("PARTITION {}", peer)).map_err(|_| SimError::ClientError)?;
+    send_admin_command(peer, &format!("PARTITION {}", node)).map_err(|_| SimError::ClientError)?;
+
+    println!("Nodes {} and {} partitioned", node, peer);
+    Ok(())
+}
+
+fn heal(args: &[&str]) -> Result<(), SimError> {
+    let node = parse_node_ip(args.get(1))?;
+    send_admin_command(node, "HEAL").map_err(|_| SimError::ClientError)?;
+    println!("Node {} healed", node);
+    Ok(())
+}
+
+fn recent_logs(args: &[&str]) -> Result<(), SimError> {
+    let node = parse_node_ip(args.get(1))?;
+    let command = match args.get(2) {
+        Some(amount) => {
+            let amount: usize = amount.parse().map_err(|_| SimError::InvalidInput)?;
+            format!("LOGS {}", amount)
+        }
+        None => "LOGS".to_string(),
+    };
+
+    let logs = send_admin_command(node, &command).map_err(|_| SimError::ClientError)?;
+    println!("{}", logs);
+    Ok(())
+}
+
+fn fault_report(sim: &Simulation) -> Result<(), SimError> {
+    let write_stats = sim.write_stats()?;
+    if write_stats.is_empty() {
+        println!("No writes issued yet.");
+        return Ok(());
+    }
+
+    println!(
+        "\n{:<15} {:<10} {:<10} {:<10}",
+        "Consistency", "Writes", "Retried", "Failed"
+    );
+    for (consistency, stats) in write_stats {
+        println!(
+            "{:<15} {:<10} {:<10} {:<10}",
+            consistency, stats.attempts, stats.retries, stats.failures
+        );
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), SimError> {
     let ip = "127.0.0.1".parse().expect("Invalid IP format");
 
@@ -152,6 +221,36 @@ fn main() -> Result<(), SimError> {
                 println!("Simulation resumed");
             }
 
+            "kill-node" => {
+                if let Err(e) = kill_node(&args) {
+                    println!("{}", e);
+                }
+            }
+
+            "partition" => {
+                if let Err(e) = partition(&args) {
+                    println!("{}", e);
+                }
+            }
+
+            "heal" => {
+                if let Err(e) = heal(&args) {
+                    println!("{}", e);
+                }
+            }
+
+            "recent-logs" => {
+                if let Err(e) = recent_logs(&args) {
+                    println!("{}", e);
+                }
+            }
+
+            "fault-report" => {
+                if let Err(e) = fault_report(&sim) {
+                    println!("{}", e);
+                }
+            }
+
             "-h" | "help" => print_help(),
 
             "exit" => break,
@@ -193,6 +292,16 @@ fn print_help() {
     println!("    Resumes the simulation.");
     println!("  test-data");
     println!("    Adds four airports and four flights to the simulation.");
+    println!("  kill-node <node_ip>");
+    println!("    Stops the given node, as if it had crashed.");
+    println!("  partition <node_ip> <peer_ip>");
+    println!("    Cuts the internode link between the two given nodes.");
+    println!("  heal <node_ip>");
+    println!("    Removes every partition set on the given node.");
+    println!("  recent-logs <node_ip> [amount]");
+    println!("    Shows the last log records written by the given node.");
+    println!("  fault-report");
+    println!("    Shows how many writes were retried or failed at each consistency level.");
     println!("  exit");
     println!("    Closes this application.");
 }
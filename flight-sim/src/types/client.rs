@@ -1,5 +1,6 @@
 use chrono::{DateTime, NaiveDateTime, NaiveTime};
 use driver::{CassandraClient, ClientError, QueryResult};
+use native_protocol::messages::batch::BatchType;
 use native_protocol::messages::result::rows::ColumnValue;
 use native_protocol::messages::result::{result_, rows};
 use std::collections::{BTreeMap, HashMap};
@@ -17,6 +18,18 @@ use crate::types::flight_status::FlightStatus;
 pub struct Client {
     cassandra_client: CassandraClient,
     ip: Ipv4Addr,
+    write_stats: BTreeMap<String, WriteStats>,
+}
+
+/// Counters of the writes issued at a given consistency level.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WriteStats {
+    /// Writes issued by the simulator.
+    pub attempts: u64,
+    /// Writes that failed at least once and were sent again.
+    pub retries: u64,
+    /// Writes that failed even after being retried.
+    pub failures: u64,
 }
 
 impl Client {
@@ -29,6 +42,7 @@ impl Client {
         let mut client = Self {
             cassandra_client,
             ip,
+            write_stats: BTreeMap::new(),
         };
         client.setup_keyspace_and_tables()?;
 
@@ -46,6 +60,63 @@ impl Client {
         Ok(())
     }
 
+    /// Returns the write counters of this client, keyed by consistency level.
+    pub fn write_stats(&self) -> BTreeMap<String, WriteStats> {
+        self.write_stats.clone()
+    }
+
+    /// Executes a write, retrying it once on a fresh connection if it fails.
+    ///
+    /// Every attempt, retry and final failure is recorded in the write counters under the
+    /// given consistency level.
+    fn execute_write(&mut self, query: &str, consistency: &str) -> Result<(), ClientError> {
+        self.execute_writes(&[query.to_string()], consistency)
+    }
+
+    /// Executes the given writes, in a single BATCH request if there are more than one, with
+    /// the same retries and counters as `execute_write`.
+    fn execute_writes(&mut self, queries: &[String], consistency: &str) -> Result<(), ClientError> {
+        self.write_stats
+            .entry(consistency.to_string())
+            .or_default()
+            .attempts += 1;
+
+        if self.try_write(queries, consistency).is_ok() {
+            return Ok(());
+        }
+
+        self.write_stats
+            .entry(consistency.to_string())
+            .or_default()
+            .retries += 1;
+
+        let result = self
+            .recreate_client()
+            .and_then(|_| self.try_write(queries, consistency));
+
+        if result.is_err() {
+            self.write_stats
+                .entry(consistency.to_string())
+                .or_default()
+                .failures += 1;
+        }
+
+        result
+    }
+
+    fn try_write(&mut self, queries: &[String], consistency: &str) -> Result<(), ClientError> {
+        let result = match queries {
+            [query] => self.cassandra_client.execute(query, consistency)?,
+            _ => self
+                .cassandra_client
+                .execute_batch(queries, BatchType::Unlogged, consistency)?,
+        };
+        match result {
+            QueryResult::Result(_) => Ok(()),
+            QueryResult::Error(_) => Err(ClientError::ServerError),
+        }
+    }
+
     /// Sets up the keyspace and required tables in Cassandra
     fn setup_keyspace_and_tables(&mut self) -> Result<(), ClientError> {
         let create_keyspace_query = r#"
@@ -113,10 +184,7 @@ impl Client {
             airport.iata_code, airport.country, airport.name, airport.latitude, airport.longitude
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&insert_airport_query, "quorum")
-        {
+        if let Err(e) = self.execute_write(&insert_airport_query, "quorum") {
             eprintln!("Failed to add the airport. Error: {:?}", e);
             return Ok(());
         }
@@ -161,26 +229,17 @@ impl Client {
             flight.destination.iata_code
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&insert_departure_query, "quorum")
-        {
+        if let Err(e) = self.execute_write(&insert_departure_query, "quorum") {
             eprintln!("Failed to add the flight. Error: {:?}", e);
             return Ok(());
         }
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&insert_arrival_query, "quorum")
-        {
+        if let Err(e) = self.execute_write(&insert_arrival_query, "quorum") {
             eprintln!("Failed to add the flight (arrival). Error: {:?}", e);
             return Ok(());
         }
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&insert_flight_info_query, "one")
-        {
+        if let Err(e) = self.execute_write(&insert_flight_info_query, "one") {
             eprintln!("Failed to add the flight info. Error: {:?}", e);
             return Ok(());
         }
@@ -191,6 +250,8 @@ impl Client {
     }
 
     /// Updates flight details in the Cassandra database.
+    ///
+    /// The position of both rows of the flight and its info are written in a single batch.
     pub fn update_flight(&mut self, flight: &Flight) -> Result<(), ClientError> {
         let update_query_status_departure = format!(
             "UPDATE sky.flights SET lat = {}, lon = {}, angle = {} WHERE airport = '{}' AND direction = '{}' AND departure_time = {} AND arrival_time = {} AND number = {};",
@@ -204,15 +265,6 @@ impl Client {
             flight.flight_number
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&update_query_status_departure, "one")
-        {
-            eprintln!("Failed to update the flight (departure). Error: {:?}", e);
-            self.recreate_client()?;
-            return Ok(());
-        }
-
         let update_query_status_arrival = format!(
             "UPDATE sky.flights SET lat = {}, lon = {}, angle = {} WHERE airport = '{}' AND direction = '{}' AND departure_time = {} AND arrival_time = {} AND number = {};",
             flight.latitude,
@@ -225,31 +277,28 @@ impl Client {
             flight.flight_number
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&update_query_status_arrival, "one")
-        {
-            eprintln!("Failed to update the flight (arrival). Error: {:?}", e);
-            return Ok(());
-        }
-
         let update_query_flight_info = format!(
             "UPDATE sky.flight_info SET fuel = {}, speed = {}, height = {} WHERE number = '{}';",
             flight.fuel_level, flight.average_speed, flight.altitude, flight.flight_number
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&update_query_flight_info, "one")
-        {
-            eprintln!("Failed to update the flight info. Error: {:?}", e);
-            return Ok(());
+        if let Err(e) = self.execute_writes(
+            &[
+                update_query_status_departure,
+                update_query_status_arrival,
+                update_query_flight_info,
+            ],
+            "one",
+        ) {
+            eprintln!("Failed to update the flight. Error: {:?}", e);
         }
 
         Ok(())
     }
 
     /// Updates flight status and some details in the Cassandra database.
+    ///
+    /// Both rows of the flight are written in a single batch.
     pub fn update_flight_status(&mut self, flight: &Flight) -> Result<(), ClientError> {
         let update_query_status_departure = format!(
             "UPDATE sky.flights SET status = '{}', lat = {}, lon = {}, WHERE airport = '{}' AND direction = '{}' AND departure_time = {} AND arrival_time = {} AND number = {};",
@@ -263,18 +312,6 @@ impl Client {
             flight.flight_number
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&update_query_status_departure, "quorum")
-        {
-            eprintln!(
-                "Failed to update the flight status (departure). Error: {:?}",
-                e
-            );
-            self.recreate_client()?;
-            return Ok(());
-        }
-
         let update_query_status_arrival = format!(
             "UPDATE sky.flights SET status = '{}', lat = {}, lon = {}, WHERE airport = '{}' AND direction = '{}' AND departure_time = {} AND arrival_time = {} AND number = {};",
             flight.status.as_str(),
@@ -287,15 +324,11 @@ impl Client {
             flight.flight_number
         );
 
-        if let Err(e) = self
-            .cassandra_client
-            .execute(&update_query_status_arrival, "quorum")
-        {
-            eprintln!(
-                "Failed to update the flight status (arrival). Error: {:?}",
-                e
-            );
-            return Ok(());
+        if let Err(e) = self.execute_writes(
+            &[update_query_status_departure, update_query_status_arrival],
+            "quorum",
+        ) {
+            eprintln!("Failed to update the flight status. Error: {:?}", e);
         }
 
         Ok(())
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::sync::{mpsc, Arc, Mutex, RwLock, RwLockReadGuard};
 use std::time::Duration;
@@ -6,7 +6,7 @@ use std::{io, thread};
 use threadpool::ThreadPool;
 
 use super::airport::Airport;
-use super::client::Client;
+use super::client::{Client, WriteStats};
 use super::flight::Flight;
 
 use super::flight_status::FlightStatus;
@@ -285,6 +285,12 @@ impl Simulation {
             .map_err(|_| SimError::AirportNotFound("Could not read airports".to_string()))
     }
 
+    /// Returns the write counters of the database client, keyed by consistency level.
+    pub fn write_stats(&self) -> Result<BTreeMap<String, WriteStats>, SimError> {
+        let db = self.db.lock().map_err(|_| SimError::ClientError)?;
+        Ok(db.write_stats())
+    }
+
     pub fn pause_simulation(&mut self) {
         self.timer.pause();
     }
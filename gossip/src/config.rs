@@ -0,0 +1,85 @@
+//! Settings of the gossip rounds of an endpoint, which trade how fast the cluster converges for the
+//! messages it sends.
+
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+
+/// Endpoints a `Syn` is sent to on every round by default.
+pub const DEFAULT_FANOUT: usize = 3;
+/// Time between two gossip rounds by default.
+pub const DEFAULT_ROUND_INTERVAL: Duration = Duration::from_millis(1000);
+/// Time a removed endpoint is remembered by default, 3 days as in Cassandra.
+pub const DEFAULT_REMOVED_EXPIRY: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// How often an endpoint gossips and with how many endpoints.
+///
+/// ### Fields
+/// - `fanout`: Endpoints the `Syn` of each round is sent to. Larger clusters converge in fewer
+///   rounds with a larger fanout, at the cost of more messages.
+/// - `round_interval`: Time between two rounds.
+/// - `jitter`: Most time added at random to each interval, so the rounds of the endpoints started
+///   together do not line up.
+/// - `seed_probability`: Probability of also sending the `Syn` of a round to a seed when none was
+///   picked, so endpoints that only know part of the cluster still reach the rest through the seeds.
+/// - `startup_delay`: Time an endpoint waits before its first round.
+/// - `removed_expiry`: Time an endpoint removed from the cluster is remembered as removed, so
+///   endpoints that were down while it was removed learn it before it is forgotten.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GossipConfig {
+    pub fanout: usize,
+    pub round_interval: Duration,
+    pub jitter: Duration,
+    pub seed_probability: f64,
+    pub startup_delay: Duration,
+    pub removed_expiry: Duration,
+}
+
+impl Default for GossipConfig {
+    /// Rounds every second to 3 endpoints, without jitter, seed bias nor startup delay, and
+    /// removed endpoints remembered for 3 days.
+    fn default() -> Self {
+        Self {
+            fanout: DEFAULT_FANOUT,
+            round_interval: DEFAULT_ROUND_INTERVAL,
+            jitter: Duration::ZERO,
+            seed_probability: 0.0,
+            startup_delay: Duration::ZERO,
+            removed_expiry: DEFAULT_REMOVED_EXPIRY,
+        }
+    }
+}
+
+impl GossipConfig {
+    /// Returns the time to wait before the next round: the interval plus a random part of the
+    /// jitter.
+    pub fn next_round_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.round_interval;
+        }
+        self.round_interval + thread_rng().gen_range(Duration::ZERO..=self.jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_delay_stays_within_the_jitter() {
+        assert_eq!(
+            GossipConfig::default().next_round_delay(),
+            DEFAULT_ROUND_INTERVAL
+        );
+
+        let config = GossipConfig {
+            round_interval: Duration::from_millis(500),
+            jitter: Duration::from_millis(100),
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let delay = config.next_round_delay();
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(600));
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Primitives of the binary encoding of the gossip messages.
+//!
+//! Integers are written as variable length integers (LEB128): 7 bits per byte, least significant
+//! group first, with the high bit set on every byte but the last one. Small numbers like versions,
+//! lengths and counts take a single byte. Strings are written as their length followed by their
+//! UTF-8 bytes.
+//!
+//! Every read is checked against the bytes left in the cursor, so malformed or truncated input
+//! fails with a [`MessageError`] instead of panicking or allocating more than the message holds.
+
+use std::io::{Cursor, Read};
+
+use crate::messages::MessageError;
+
+/// Longest LEB128 encoding of a `u128`.
+const MAX_VARINT_LEN: usize = 19;
+
+/// Writes `value` as a variable length integer.
+pub fn write_varint(bytes: &mut Vec<u8>, value: u128) {
+    let mut value = value;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Reads a variable length integer written by [`write_varint`].
+pub fn read_varint(cursor: &mut Cursor<&[u8]>) -> Result<u128, MessageError> {
+    let mut value = 0u128;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = read_byte(cursor)?;
+        let bits = (byte & 0x7f) as u128;
+        if i == MAX_VARINT_LEN - 1 && bits > 0x03 {
+            return Err(MessageError::InvalidValue(
+                "Variable length integer overflows".to_string(),
+            ));
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(MessageError::InvalidValue(
+        "Variable length integer is too long".to_string(),
+    ))
+}
+
+/// Reads a variable length integer that must fit in a `u32`.
+pub fn read_varint_u32(cursor: &mut Cursor<&[u8]>) -> Result<u32, MessageError> {
+    u32::try_from(read_varint(cursor)?)
+        .map_err(|_| MessageError::ConversionError("Value does not fit in a u32".to_string()))
+}
+
+/// Reads a variable length integer that must fit in a `u64`.
+pub fn read_varint_u64(cursor: &mut Cursor<&[u8]>) -> Result<u64, MessageError> {
+    u64::try_from(read_varint(cursor)?)
+        .map_err(|_| MessageError::ConversionError("Value does not fit in a u64".to_string()))
+}
+
+/// Reads a single byte.
+pub fn read_byte(cursor: &mut Cursor<&[u8]>) -> Result<u8, MessageError> {
+    let mut byte = [0u8; 1];
+    cursor
+        .read_exact(&mut byte)
+        .map_err(|_| MessageError::CursorError)?;
+    Ok(byte[0])
+}
+
+/// Reads the number of elements of a list. Every element takes at least one byte, so a count
+/// greater than the bytes left is rejected before reading the elements.
+pub fn read_count(cursor: &mut Cursor<&[u8]>) -> Result<usize, MessageError> {
+    let count = read_varint(cursor)?;
+    if count > remaining(cursor) as u128 {
+        return Err(MessageError::InvalidLength(format!(
+            "Count of {} elements is greater than the bytes left",
+            count
+        )));
+    }
+    Ok(count as usize)
+}
+
+/// Writes `value` as its length followed by its UTF-8 bytes.
+pub fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    write_varint(bytes, value.len() as u128);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+/// Reads bytes written as their length followed by the bytes themselves. A length greater than
+/// the bytes left is rejected before allocating for it.
+pub fn read_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, MessageError> {
+    let len = read_count(cursor)?;
+    let mut bytes = vec![0u8; len];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| MessageError::CursorError)?;
+    Ok(bytes)
+}
+
+/// Reads a string written by [`write_string`].
+pub fn read_string(cursor: &mut Cursor<&[u8]>) -> Result<String, MessageError> {
+    String::from_utf8(read_bytes(cursor)?)
+        .map_err(|_| MessageError::ConversionError("String is not valid UTF-8".to_string()))
+}
+
+/// Returns the number of bytes left to read in the cursor.
+fn remaining(cursor: &Cursor<&[u8]>) -> usize {
+    cursor
+        .get_ref()
+        .len()
+        .saturating_sub(cursor.position() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            300,
+            u32::MAX as u128,
+            u64::MAX as u128,
+            u128::MAX,
+        ] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+
+            let mut cursor = Cursor::new(bytes.as_slice());
+            assert_eq!(read_varint(&mut cursor).unwrap(), value);
+            assert_eq!(cursor.position() as usize, bytes.len());
+        }
+    }
+
+    #[test]
+    fn small_varints_take_one_byte() {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 127);
+        assert_eq!(bytes, vec![0x7f]);
+
+        bytes.clear();
+        write_varint(&mut bytes, 128);
+        assert_eq!(bytes, vec![0x80, 0x01]);
+    }
+
+    #[test]
+    fn invalid_varints() {
+        let truncated = [0x80u8, 0x80];
+        assert!(read_varint(&mut Cursor::new(&truncated[..])).is_err());
+
+        let too_long = [0xffu8; MAX_VARINT_LEN + 1];
+        assert!(read_varint(&mut Cursor::new(&too_long[..])).is_err());
+
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, u32::MAX as u128 + 1);
+        assert!(read_varint_u32(&mut Cursor::new(bytes.as_slice())).is_err());
+    }
+
+    #[test]
+    fn string_longer_than_the_message_is_rejected() {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, u64::MAX as u128);
+        bytes.extend_from_slice(b"abc");
+
+        let result = read_string(&mut Cursor::new(bytes.as_slice()));
+        assert!(matches!(result, Err(MessageError::InvalidLength(_))));
+
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 4);
+        bytes.extend_from_slice(b"abc");
+        let result = read_bytes(&mut Cursor::new(bytes.as_slice()));
+        assert!(matches!(result, Err(MessageError::InvalidLength(_))));
+    }
+}
@@ -0,0 +1,195 @@
+//! Phi Accrual failure detector, which decides when an endpoint is dead from the times its
+//! heartbeats arrive instead of from a single message that could not be sent.
+//!
+//! For every endpoint it keeps the intervals between the last heartbeats received and computes
+//! `phi`, a measure of how unlikely it is to go that long without a new heartbeat: with `phi = 1`
+//! the odds of being wrong when suspecting the endpoint are about 10%, with `phi = 2` about 1%,
+//! and so on. Heartbeat intervals are assumed to follow an exponential distribution, as in
+//! Cassandra, so `phi` is the time since the last heartbeat divided by the mean interval, times
+//! `log10(e)`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::Ipv4Addr,
+    sync::Arc,
+};
+
+use chrono::Utc;
+
+/// `phi` above which an endpoint is considered dead, the default of Cassandra.
+pub const DEFAULT_PHI_THRESHOLD: f64 = 8.0;
+
+/// Number of latest intervals between heartbeats kept for each endpoint.
+const ARRIVAL_WINDOW_SIZE: usize = 100;
+/// Interval assumed between heartbeats of an endpoint until a second one arrives.
+const INITIAL_INTERVAL_MILLIS: f64 = 2000.0;
+
+/// Returns the current time in milliseconds. The detector can be given another clock to run on
+/// simulated time.
+pub type Clock = Arc<dyn Fn() -> u128 + Send + Sync>;
+
+/// The clock of the system, in milliseconds since the epoch.
+pub fn system_clock() -> Clock {
+    Arc::new(|| Utc::now().timestamp_millis() as u128)
+}
+
+/// Times at which the latest heartbeats of an endpoint arrived.
+#[derive(Debug, Clone, Default)]
+struct ArrivalWindow {
+    last_arrival: Option<u128>,
+    intervals: VecDeque<u128>,
+}
+
+impl ArrivalWindow {
+    fn add(&mut self, now: u128) {
+        if let Some(last_arrival) = self.last_arrival {
+            if self.intervals.len() >= ARRIVAL_WINDOW_SIZE {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(now.saturating_sub(last_arrival));
+        }
+        self.last_arrival = Some(now);
+    }
+
+    fn mean_interval(&self) -> f64 {
+        if self.intervals.is_empty() {
+            return INITIAL_INTERVAL_MILLIS;
+        }
+        let total: u128 = self.intervals.iter().sum();
+        (total as f64 / self.intervals.len() as f64).max(1.0)
+    }
+
+    fn phi(&self, now: u128) -> f64 {
+        match self.last_arrival {
+            Some(last_arrival) => {
+                let elapsed = now.saturating_sub(last_arrival) as f64;
+                elapsed / self.mean_interval() * std::f64::consts::LOG10_E
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// Tracks the heartbeats received from every endpoint and suspects the ones that stopped
+/// sending them for much longer than usual.
+#[derive(Clone)]
+pub struct FailureDetector {
+    windows: HashMap<Ipv4Addr, ArrivalWindow>,
+    phi_threshold: f64,
+    clock: Clock,
+}
+
+impl Default for FailureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FailureDetector {
+    /// Creates a detector with the default threshold, running on the clock of the system.
+    pub fn new() -> Self {
+        Self {
+            windows: HashMap::new(),
+            phi_threshold: DEFAULT_PHI_THRESHOLD,
+            clock: system_clock(),
+        }
+    }
+
+    /// Sets the `phi` above which endpoints are considered dead. Lower thresholds detect
+    /// failures sooner, at the cost of more false positives.
+    pub fn with_phi_threshold(mut self, phi_threshold: f64) -> Self {
+        self.phi_threshold = phi_threshold;
+        self
+    }
+
+    /// Sets the clock the arrival times are taken from.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Records that a new heartbeat of the endpoint with the given ip arrived now.
+    pub fn report(&mut self, ip: Ipv4Addr) {
+        let now = (self.clock)();
+        self.windows.entry(ip).or_default().add(now);
+    }
+
+    /// Forgets the heartbeats of the endpoint with the given ip, for example because it
+    /// restarted and its previous intervals say nothing about the new one.
+    pub fn remove(&mut self, ip: Ipv4Addr) {
+        self.windows.remove(&ip);
+    }
+
+    /// Returns the current `phi` of the endpoint with the given ip, or 0 if none of its
+    /// heartbeats arrived yet.
+    pub fn phi(&self, ip: Ipv4Addr) -> f64 {
+        self.windows
+            .get(&ip)
+            .map_or(0.0, |window| window.phi((self.clock)()))
+    }
+
+    /// Whether the endpoint with the given ip is not suspected, that is, its `phi` is not above
+    /// the threshold. Endpoints whose heartbeats never arrived are not suspected.
+    pub fn is_alive(&self, ip: Ipv4Addr) -> bool {
+        self.phi(ip) <= self.phi_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    fn manual_clock() -> (Arc<AtomicU64>, Clock) {
+        let time = Arc::new(AtomicU64::new(0));
+        let clock_time = Arc::clone(&time);
+        let clock: Clock = Arc::new(move || clock_time.load(Ordering::SeqCst) as u128);
+        (time, clock)
+    }
+
+    #[test]
+    fn phi_grows_with_the_time_since_the_last_heartbeat() {
+        let ip = Ipv4Addr::new(127, 0, 0, 2);
+        let (time, clock) = manual_clock();
+        let mut detector = FailureDetector::new().with_clock(clock);
+
+        for _ in 0..10 {
+            detector.report(ip);
+            time.fetch_add(1000, Ordering::SeqCst);
+        }
+        // A second after the last heartbeat, as usual
+        assert!((detector.phi(ip) - std::f64::consts::LOG10_E).abs() < 1e-9);
+        assert!(detector.is_alive(ip));
+
+        // Eight times phi goes above the threshold after about 18 intervals
+        time.fetch_add(17_000, Ordering::SeqCst);
+        assert!(detector.is_alive(ip));
+        time.fetch_add(2_000, Ordering::SeqCst);
+        assert!(!detector.is_alive(ip));
+
+        detector.report(ip);
+        assert_eq!(detector.phi(ip), 0.0);
+        assert!(detector.is_alive(ip));
+    }
+
+    #[test]
+    fn endpoints_without_heartbeats_are_not_suspected() {
+        let ip = Ipv4Addr::new(127, 0, 0, 2);
+        let (time, clock) = manual_clock();
+        let mut detector = FailureDetector::new()
+            .with_clock(clock)
+            .with_phi_threshold(1.0);
+
+        time.fetch_add(1_000_000, Ordering::SeqCst);
+        assert!(detector.is_alive(ip));
+
+        // A single heartbeat is measured against the initial interval
+        detector.report(ip);
+        time.fetch_add(INITIAL_INTERVAL_MILLIS as u64 * 3, Ordering::SeqCst);
+        assert!(!detector.is_alive(ip));
+
+        detector.remove(ip);
+        assert!(detector.is_alive(ip));
+    }
+}
@@ -1,33 +1,144 @@
-//! This module contains the implementation of the gossip protocol.
-//! TODO: complete
+//! Implementation of the gossip protocol used by the nodes to share their state.
+//!
+//! Every endpoint keeps an [`EndpointState`] for each endpoint it knows about: a heartbeat,
+//! whose generation is the time the endpoint started and whose version grows every round, and
+//! an application state, the data the endpoint gossips about itself. Once per round a
+//! [`Gossiper`] sends a `Syn` with the digests of its states to a few random endpoints, which
+//! answer with an `Ack` holding the states they have newer and the digests they need, and the
+//! exchange ends with an `Ack2` carrying those.
+//!
+//! Large parts of a state, like the keyspaces of the schema, are gossiped as a name and a digest
+//! only. A gossiper that does not know a part with that digest asks the peer for it with a `Pull`,
+//! answered by a `Push`, so the messages of every round stay small while the schema is unchanged.
+//!
+//! The crate can be embedded by any application that needs membership tracking:
+//! - The application state is anything implementing [`GossipState`]. The database nodes use
+//!   [`ApplicationState`], and the methods that deal with its status, token and schema are only
+//!   available on `Gossiper<ApplicationState>`.
+//! - Messages are sent through a [`Transport`], so the application chooses how they travel, and
+//!   the incoming ones are given back to [`Gossiper::handle_message`].
+//! - [`Gossiper::subscribe`] returns a channel of [`MembershipEvent`]s, sent whenever an
+//!   endpoint joins, dies, comes back or restarts.
+//! - [`Gossiper::with_config`] sets the [`GossipConfig`] of the rounds: how many endpoints each
+//!   one gossips with, how often they run and how much they lean towards the seeds.
+//! - [`Gossiper::with_state_file`] keeps the states of the endpoints in a file, so a restarted
+//!   endpoint remembers the cluster and takes a generation greater than the one it had.
+//! - [`Gossiper::remove_endpoint`] removes a dead endpoint for good: it is gossiped as removed,
+//!   so stale states of it are ignored, until every endpoint forgets it.
+//! - Endpoints are marked dead by a Phi Accrual [`FailureDetector`], which suspects the ones
+//!   whose heartbeats stopped arriving for much longer than usual, instead of on the first
+//!   message that could not be sent to them.
+//!
+//! The [`simulation`] module runs whole clusters of gossipers in memory, to test how the protocol
+//! converges.
+//!
+//! ```
+//! use gossip::{membership::MembershipEvent, messages::GossipMessage, transport::Transport};
+//! use gossip::{GossipError, Gossiper};
+//! use std::{cell::RefCell, net::Ipv4Addr};
+//!
+//! // A transport that keeps the messages so they can be delivered by hand.
+//! struct Outbox(RefCell<Vec<(Ipv4Addr, GossipMessage)>>);
+//!
+//! impl Transport for Outbox {
+//!     fn send(&self, to: Ipv4Addr, message: GossipMessage) -> Result<(), GossipError> {
+//!         self.0.borrow_mut().push((to, message));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let (a, b) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+//! let mut gossiper_a: Gossiper = Gossiper::new().with_endpoint_state(a).with_seeds(vec![b]);
+//! let mut gossiper_b: Gossiper = Gossiper::new().with_endpoint_state(b);
+//! let events = gossiper_b.subscribe();
+//!
+//! let outbox = Outbox(RefCell::new(Vec::new()));
+//! gossiper_a.gossip_round(a, &outbox).unwrap();
+//! let next_message = || outbox.0.borrow_mut().pop();
+//! while let Some((to, message)) = next_message() {
+//!     let gossiper = if to == a { &mut gossiper_a } else { &mut gossiper_b };
+//!     gossiper.handle_message(to, &message, &outbox).unwrap();
+//! }
+//!
+//! assert_eq!(events.try_recv(), Ok(MembershipEvent::Joined(a)));
+//! ```
 
 use chrono::{self, Utc};
 
-use messages::{Ack, Ack2, Digest, GossipMessage, Syn};
+use config::GossipConfig;
+use failure_detector::FailureDetector;
+use membership::MembershipEvent;
+use messages::{Ack, Ack2, Digest, GossipMessage, Payload, Pull, Push, Syn};
 use query_creator::clauses::{
-    keyspace::create_keyspace_cql::CreateKeyspace, table::create_table_cql::CreateTable,
+    keyspace::{alter_keyspace_cql::AlterKeyspace, create_keyspace_cql::CreateKeyspace},
+    table::create_table_cql::CreateTable,
+};
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    thread_rng, Rng,
 };
-use rand::{seq::IteratorRandom, thread_rng};
 use std::{
     collections::{BTreeMap, HashMap},
     fmt,
     net::Ipv4Addr,
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
 };
 use structures::{
-    application_state::{KeyspaceSchema, NodeStatus, Schema, TableSchema},
+    application_state::{
+        ApplicationState, KeyspaceSchema, NodeStatus, Schema, SchemaAgreement, TableSchema,
+    },
     endpoint_state::EndpointState,
+    gossip_state::GossipState,
     heartbeat_state::HeartbeatState,
 };
+use transport::Transport;
+pub mod config;
+pub mod encoding;
+pub mod failure_detector;
+pub mod membership;
 pub mod messages;
+pub mod simulation;
+mod state_file;
 pub mod structures;
+pub mod transport;
 
 /// Struct to represent the gossiper node.
 ///
 /// ### Fields
 /// - `endpoints_state`: HashMap containing the state of all the endpoints that the gossiper knows about.
+/// - `subscribers`: Channels notified of the changes in the membership of the cluster.
+/// - `failure_detector`: Tracks the arrival of the heartbeats of the other endpoints to decide
+///   when they are dead.
+/// - `state_file`: File where the states of the endpoints are saved, if they are.
+/// - `seeds`: Endpoints given as seeds, which the rounds are biased towards.
+/// - `config`: How many endpoints each round gossips with and how often rounds run.
 #[derive(Clone)]
-pub struct Gossiper {
-    pub endpoints_state: HashMap<Ipv4Addr, EndpointState>,
+pub struct Gossiper<S: GossipState = ApplicationState> {
+    pub endpoints_state: HashMap<Ipv4Addr, EndpointState<S>>,
+    subscribers: Vec<Sender<MembershipEvent>>,
+    failure_detector: FailureDetector,
+    state_file: Option<PathBuf>,
+    seeds: Vec<Ipv4Addr>,
+    config: GossipConfig,
+}
+
+/// The states of the endpoints of a gossiper, encoded to be written to its state file.
+///
+/// ### Fields
+/// - `path`: The state file of the gossiper.
+/// - `bytes`: The states, as they are written to the file.
+#[derive(Debug)]
+pub struct SavedState {
+    path: PathBuf,
+    bytes: Vec<u8>,
+}
+
+impl SavedState {
+    /// Writes the states to the state file of the gossiper they were taken from.
+    pub fn write(&self) -> Result<(), GossipError> {
+        state_file::write_bytes(&self.path, &self.bytes).map_err(|_| GossipError::StateFileError)
+    }
 }
 
 #[derive(Debug)]
@@ -38,6 +149,10 @@ pub enum GossipError {
     NoSuchKeyspace,
     KeyspaceAlreadyExists,
     TableAlreadyExists,
+    NoSuchTable,
+    SendError,
+    StateFileError,
+    EndpointIsAlive,
 }
 
 impl fmt::Display for GossipError {
@@ -49,22 +164,31 @@ impl fmt::Display for GossipError {
             GossipError::NoSuchKeyspace => "The given keyspace does not exist",
             GossipError::KeyspaceAlreadyExists => "The given keyspace already exists",
             GossipError::TableAlreadyExists => "The given table already exists",
+            GossipError::NoSuchTable => "The given table does not exist",
+            GossipError::SendError => "The message could not be sent to the given endpoint",
+            GossipError::StateFileError => "The states of the endpoints could not be saved",
+            GossipError::EndpointIsAlive => "The given endpoint is alive and can not be removed",
         };
         write!(f, "{}", description)
     }
 }
 
-impl Default for Gossiper {
+impl<S: GossipState> Default for Gossiper<S> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Gossiper {
+impl<S: GossipState> Gossiper<S> {
     /// Create a new Gossiper instance with an empty state.
     pub fn new() -> Self {
         Self {
             endpoints_state: HashMap::new(),
+            subscribers: Vec::new(),
+            failure_detector: FailureDetector::new(),
+            state_file: None,
+            seeds: Vec::new(),
+            config: GossipConfig::default(),
         }
     }
 
@@ -80,217 +204,249 @@ impl Gossiper {
     }
 
     /// Set the application state of the endpoint with the given ip.
+    ///
+    /// The generation of its heartbeat is the time the endpoint started, so a restarted node
+    /// (whose versions start over) is still seen as newer than its previous incarnation. If a
+    /// generation of the endpoint was saved (see [`Gossiper::with_state_file`]), the new one is
+    /// also greater than it, even if the clock went back since.
     pub fn with_endpoint_state(mut self, ip: Ipv4Addr) -> Self {
-        self.endpoints_state.insert(ip, EndpointState::default());
+        let saved_generation = self
+            .endpoints_state
+            .get(&ip)
+            .map(|state| state.heartbeat_state.generation);
+        self.endpoints_state.insert(
+            ip,
+            EndpointState::new(
+                S::default(),
+                HeartbeatState::new(new_generation(saved_generation), 0),
+            ),
+        );
+        self
+    }
+
+    /// Reloads the states of the endpoints saved to the given file by a previous run, and saves
+    /// them there from now on with [`Gossiper::save_state`]. A file that is missing or can not be
+    /// read is ignored, and the gossiper starts without the saved states.
+    ///
+    /// The endpoints already set with `with_endpoint_state` keep their new state, but their
+    /// generation is made greater than the saved one, as in Cassandra: peers that still gossip the
+    /// state of the previous run, with its higher versions, can not override the new one.
+    ///
+    /// The other endpoints are reported to the failure detector as if their heartbeats had just
+    /// arrived, so the ones that do not gossip anymore are suspected and marked as dead.
+    pub fn with_state_file(mut self, path: PathBuf) -> Self {
+        let saved = state_file::read::<S>(&path).unwrap_or_default();
+        for (ip, saved_state) in saved {
+            match self.endpoints_state.get_mut(&ip) {
+                Some(state) => {
+                    let saved_generation = saved_state.heartbeat_state.generation;
+                    state.heartbeat_state.generation = state
+                        .heartbeat_state
+                        .generation
+                        .max(new_generation(Some(saved_generation)));
+                }
+                None => {
+                    self.failure_detector.report(ip);
+                    self.endpoints_state.insert(ip, saved_state);
+                }
+            }
+        }
+        self.state_file = Some(path);
         self
     }
 
-    /// Inserts the given ip with a default state into the gossiper.
+    /// Saves the states of the endpoints to the file given to `with_state_file`, if any.
+    pub fn save_state(&self) -> Result<(), GossipError> {
+        self.state_to_save().map_or(Ok(()), |state| state.write())
+    }
+
+    /// Returns the states of the endpoints to save to the file given to `with_state_file`, if any,
+    /// so the file can be written without holding the gossiper.
+    pub fn state_to_save(&self) -> Option<SavedState> {
+        Some(SavedState {
+            path: self.state_file.clone()?,
+            bytes: state_file::encode(&self.endpoints_state),
+        })
+    }
+
+    /// Inserts the given ips with a default state into the gossiper. Seeds whose state is already
+    /// known, like the endpoint itself or the ones reloaded from the state file, keep it.
     pub fn with_seeds(mut self, seeds_ip: Vec<Ipv4Addr>) -> Self {
         for ip in seeds_ip {
-            self.endpoints_state.insert(ip, EndpointState::default());
+            self.endpoints_state.entry(ip).or_default();
+            self.seeds.push(ip);
         }
         self
     }
 
-    /// Changes the status of the application state of the endpoint with the given ip.
-    pub fn change_status(&mut self, ip: Ipv4Addr, status: NodeStatus) -> Result<(), GossipError> {
-        let app_state = &mut self
-            .endpoints_state
-            .get_mut(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state;
+    /// Sets how many endpoints each round gossips with and how often rounds run.
+    pub fn with_config(mut self, config: GossipConfig) -> Self {
+        self.config = config;
+        self
+    }
 
-        app_state.status = status;
-        app_state.version += 1;
+    /// Returns how many endpoints each round gossips with and how often rounds run.
+    pub fn config(&self) -> &GossipConfig {
+        &self.config
+    }
 
-        Ok(())
+    /// Replaces the failure detector, for example to change its threshold or its clock.
+    pub fn with_failure_detector(mut self, failure_detector: FailureDetector) -> Self {
+        self.failure_detector = failure_detector;
+        self
     }
 
-    /// Returns a copy of the application state of the endpoint with the given ip.
-    pub fn get_status(&self, ip: Ipv4Addr) -> Result<NodeStatus, GossipError> {
-        let app_state = self
-            .endpoints_state
-            .get(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state
-            .status;
+    /// Returns a channel that receives every change in the membership of the cluster seen by
+    /// this gossiper from now on.
+    ///
+    /// Subscribers whose receiver was dropped are forgotten on the next event.
+    pub fn subscribe(&mut self) -> Receiver<MembershipEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
 
-        Ok(app_state)
+    /// Returns the ips of the endpoints that are currently alive.
+    pub fn live_endpoints(&self) -> Vec<Ipv4Addr> {
+        self.endpoints_state
+            .iter()
+            .filter(|(_, state)| state.application_state.is_alive())
+            .map(|(ip, _)| *ip)
+            .collect()
     }
 
-    /// Returns the schema with the largest timestamp from the known application states.
-    pub fn get_most_updated_schema(&self) -> Option<Schema> {
-        let mut most_updated_schema = None;
-        let mut most_updated_timestamp = 0;
+    /// Marks the endpoint with the given ip as dead.
+    pub fn kill(&mut self, ip: Ipv4Addr) -> Result<(), GossipError> {
+        self.update_application_state(ip, |app_state| app_state.mark_dead())
+    }
 
-        for state in self.endpoints_state.values() {
-            if state.application_state.schema.timestamp > most_updated_timestamp {
-                most_updated_schema = Some(&state.application_state.schema);
-                most_updated_timestamp = state.application_state.schema.timestamp;
-            }
+    /// Removes the dead endpoint with the given ip from the cluster, as Cassandra's `removenode`.
+    ///
+    /// Its state is marked as removed until the removed expiry of the config passes, and gets a
+    /// newer heartbeat, so the removal is gossiped to the other endpoints. Until then the states of
+    /// the endpoint gossiped by peers that did not learn the removal, or by the endpoint itself if
+    /// it comes back, are ignored. Once it expires, the endpoint is forgotten.
+    ///
+    /// # Errors
+    /// - `GossipError::NoEndpointStateForIp` if the endpoint is not known.
+    /// - `GossipError::EndpointIsAlive` if the endpoint is alive, which includes the endpoint
+    ///   running the gossiper.
+    pub fn remove_endpoint(&mut self, ip: Ipv4Addr) -> Result<(), GossipError> {
+        if !self.endpoints_state.contains_key(&ip) {
+            return Err(GossipError::NoEndpointStateForIp);
+        }
+        if self.is_alive(ip) {
+            return Err(GossipError::EndpointIsAlive);
         }
 
-        most_updated_schema.cloned()
-    }
-
-    /// Removes the keyspace from the application state of the endpoint with the given ip.
-    pub fn remove_keyspace(&mut self, ip: Ipv4Addr, keyspace: &str) -> Result<(), GossipError> {
-        // Find the app state of the given ip
-        let app_state = &mut self
+        let removed_until = now_millis() + self.config.removed_expiry.as_millis() as u64;
+        let state = self
             .endpoints_state
             .get_mut(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state;
-
-        // TODO: make it an app state or schema method which also alters the timestamp
-        app_state.schema.keyspaces.remove(keyspace);
-
-        app_state.version += 1;
-        app_state.schema.timestamp = Utc::now().timestamp_millis();
+            .ok_or(GossipError::NoEndpointStateForIp)?;
+        state.application_state.mark_removed(removed_until);
+        state.heartbeat_state.inc_version();
 
+        self.failure_detector.remove(ip);
+        self.seeds.retain(|seed| *seed != ip);
+        self.notify(MembershipEvent::Removed(ip));
         Ok(())
     }
 
-    /// Adds the keyspace to the application state of the endpoint with the given ip.
-    pub fn add_keyspace(
-        &mut self,
-        ip: Ipv4Addr,
-        keyspace: CreateKeyspace,
-    ) -> Result<(), GossipError> {
-        // Find the app state of the given ip
-        let app_state = &mut self
+    /// Forgets the removed endpoints whose removal expired.
+    fn forget_removed_endpoints(&mut self) {
+        let now = now_millis();
+        let expired: Vec<Ipv4Addr> = self
             .endpoints_state
-            .get_mut(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state;
+            .iter()
+            .filter(|(_, state)| {
+                state
+                    .application_state
+                    .removed_until()
+                    .is_some_and(|removed_until| removed_until <= now)
+            })
+            .map(|(ip, _)| *ip)
+            .collect();
 
-        // Add the keyspace to the schema
-        if !app_state
-            .schema
-            .keyspaces
-            .keys()
-            .any(|k| *k == keyspace.get_name())
-        {
-            app_state.schema.keyspaces.insert(
-                keyspace.get_name(),
-                KeyspaceSchema {
-                    inner: keyspace,
-                    tables: Vec::new(),
-                },
-            );
-        } else {
-            return Err(GossipError::KeyspaceAlreadyExists);
+        for ip in expired {
+            self.endpoints_state.remove(&ip);
+            self.failure_detector.remove(ip);
         }
+    }
 
-        app_state.version += 1;
-        app_state.schema.timestamp = Utc::now().timestamp_millis();
-
-        Ok(())
+    /// Returns whether the endpoint with the given ip is alive: its gossiped state is alive and
+    /// the failure detector does not suspect it.
+    pub fn is_alive(&self, ip: Ipv4Addr) -> bool {
+        self.endpoints_state
+            .get(&ip)
+            .is_some_and(|state| state.application_state.is_alive())
+            && self.failure_detector.is_alive(ip)
     }
 
-    /// Add the table to the keyspace of the application state of the endpoint with the given ip.
-    pub fn add_table(
-        &mut self,
-        ip: Ipv4Addr,
-        table: CreateTable,
-        kesyapce_name: &str,
-    ) -> Result<(), GossipError> {
-        // Find the app state of the given ip
-        let app_state = &mut self
+    /// Marks the endpoint with the given ip as dead if the failure detector suspects it, that is,
+    /// if its heartbeats stopped arriving for much longer than usual.
+    ///
+    /// Returns whether the endpoint was marked as dead by this call.
+    pub fn suspect(&mut self, ip: Ipv4Addr) -> Result<bool, GossipError> {
+        let state = self
             .endpoints_state
-            .get_mut(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state;
-
-        // Test if the keyspace of the table exists
-        let keyspace_exists = app_state
-            .schema
-            .keyspaces
-            .keys()
-            .any(|k| *k == kesyapce_name);
-
-        if keyspace_exists {
-            let keyspace = app_state
-                .schema
-                .keyspaces
-                .get_mut(kesyapce_name)
-                .ok_or(GossipError::NoSuchKeyspace)?;
-
-            // Check if the table already exists
-            for t in keyspace.tables.iter() {
-                if t.inner.get_name() == table.get_name() {
-                    return Err(GossipError::TableAlreadyExists);
-                }
-            }
-
-            let table_schema = TableSchema::new(table);
-
-            keyspace.tables.push(table_schema);
-        } else {
-            return Err(GossipError::NoSuchKeyspace);
+            .get(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?;
+        if !state.application_state.is_alive() || self.failure_detector.is_alive(ip) {
+            return Ok(false);
         }
 
-        app_state.version += 1;
-        app_state.schema.timestamp = Utc::now().timestamp_millis();
-
-        Ok(())
+        self.kill(ip)?;
+        Ok(true)
     }
 
-    /// Removes the table from the keyspace of the application state of the endpoint with the given ip.
-    pub fn remove_table(
-        &mut self,
-        ip: Ipv4Addr,
-        keyspace: &str,
-        table: &str,
-    ) -> Result<(), GossipError> {
-        // Find the app state of the given ip
-        let app_state = &mut self
+    /// Picks as many random ips of live endpoints as the fanout of the config, excluding the
+    /// given ip. If none of them is a seed, a random seed is also picked with the seed probability
+    /// of the config, even if it is not known to be alive.
+    pub fn pick_ips(&self, exclude: Ipv4Addr) -> Vec<&Ipv4Addr> {
+        let mut rng = thread_rng();
+        let mut ips: Vec<&Ipv4Addr> = self
             .endpoints_state
-            .get_mut(&ip)
-            .ok_or(GossipError::NoEndpointStateForIp)?
-            .application_state;
-
-        // Find the given keyspace in the schema
-        let k = app_state
-            .schema
-            .keyspaces
-            .iter_mut()
-            .find(|(keyspace_name, _)| *keyspace_name == keyspace);
-
-        // If the keyspace exists, remove the table from it
-        if let Some((_, k_schema)) = k {
-            k_schema.tables.retain(|t| t.inner.get_name() != table);
-            app_state.version += 1;
-            app_state.schema.timestamp = Utc::now().timestamp_millis();
+            .iter()
+            .filter(|(&ip, state)| ip != exclude && state.application_state.is_alive())
+            .map(|(ip, _)| ip)
+            .choose_multiple(&mut rng, self.config.fanout);
 
-            Ok(())
-        } else {
-            Err(GossipError::NoSuchKeyspace)
+        let other_seeds: Vec<&Ipv4Addr> = self.seeds.iter().filter(|&&ip| ip != exclude).collect();
+        if !ips.iter().any(|ip| other_seeds.contains(ip))
+            && rng.gen_bool(self.config.seed_probability.clamp(0.0, 1.0))
+        {
+            ips.extend(other_seeds.choose(&mut rng));
         }
+        ips
     }
 
-    /// Marks the endpoint with the given ip as dead.
-    pub fn kill(&mut self, ip: Ipv4Addr) -> Result<(), GossipError> {
-        self.change_status(ip, NodeStatus::Dead)
-    }
-
-    /// Picks 3 random ips from the gossiper state, excluding the given ip.
-    pub fn pick_ips(&self, exclude: Ipv4Addr) -> Vec<&Ipv4Addr> {
-        let mut rng = thread_rng();
-        let ips: Vec<&Ipv4Addr> = self
+    /// Picks the ip of a random dead endpoint, excluding the given ip, with the probability of the
+    /// dead endpoints over the live ones plus one, as in Cassandra: the fewer endpoints are alive,
+    /// the more often the dead ones are contacted, so they are seen alive again once they can be
+    /// reached.
+    ///
+    /// Removed endpoints are never picked.
+    pub fn pick_unreachable_ip(&self, exclude: Ipv4Addr) -> Option<Ipv4Addr> {
+        let (dead, live): (Vec<Ipv4Addr>, Vec<Ipv4Addr>) = self
             .endpoints_state
             .iter()
             .filter(|(&ip, state)| {
-                ip != exclude && state.application_state.status != NodeStatus::Dead
+                ip != exclude && state.application_state.removed_until().is_none()
             })
             .map(|(ip, _)| ip)
-            .choose_multiple(&mut rng, 3);
-        ips
+            .partition(|ip| !self.endpoints_state[*ip].application_state.is_alive());
+
+        let mut rng = thread_rng();
+        let probability = dead.len() as f64 / (live.len() + 1) as f64;
+        if dead.is_empty() || !rng.gen_bool(probability.min(1.0)) {
+            return None;
+        }
+        dead.choose(&mut rng).copied()
     }
 
     /// Creates a Syn message with the digests of the endpoints in the gossiper state.
-    pub fn create_syn(&self, from: Ipv4Addr) -> GossipMessage {
+    pub fn create_syn(&self, from: Ipv4Addr) -> GossipMessage<S> {
         let digests: Vec<Digest> = self
             .endpoints_state
             .iter()
@@ -301,12 +457,125 @@ impl Gossiper {
 
         GossipMessage {
             from,
-            payload: messages::Payload::Syn(syn),
+            payload: Payload::Syn(syn),
+        }
+    }
+
+    /// Returns the parts of the known states that were gossiped by digest only and have not been
+    /// pulled yet, without repetitions.
+    pub fn missing_parts(&self) -> Vec<(String, u64)> {
+        let mut parts: Vec<(String, u64)> = self
+            .endpoints_state
+            .values()
+            .flat_map(|state| state.application_state.missing_parts())
+            .collect();
+        parts.sort();
+        parts.dedup();
+        parts
+    }
+
+    /// Runs a round of gossip for the endpoint with the given ip: beats its heartbeat, forgets the
+    /// endpoints whose removal expired, marks as dead the endpoints suspected by the failure
+    /// detector and sends a Syn to the picked endpoints through the transport.
+    ///
+    /// An endpoint that can not be reached is only suspected: a single failed send does not
+    /// mark it as dead, as long as its heartbeats keep arriving through other endpoints.
+    ///
+    /// Besides the live endpoints, the Syn is sent now and then to a dead endpoint (see
+    /// [`Gossiper::pick_unreachable_ip`]), and to a random seed on the rounds where no live
+    /// endpoint is picked. Otherwise two parts of a cluster that saw each other as dead during a
+    /// network partition would never gossip again once it heals.
+    ///
+    /// If some known states still miss parts, they are also pulled from the picked endpoints.
+    pub fn gossip_round<T: Transport<S>>(
+        &mut self,
+        from: Ipv4Addr,
+        transport: &T,
+    ) -> Result<(), GossipError> {
+        self.heartbeat(from)?;
+        self.forget_removed_endpoints();
+
+        for ip in self.live_endpoints() {
+            if ip != from {
+                self.suspect(ip)?;
+            }
+        }
+
+        let syn = self.create_syn(from);
+        let mut ips: Vec<Ipv4Addr> = self.pick_ips(from).into_iter().copied().collect();
+        if ips.is_empty() {
+            let other_seeds: Vec<&Ipv4Addr> = self.seeds.iter().filter(|&&ip| ip != from).collect();
+            ips.extend(other_seeds.choose(&mut thread_rng()).copied());
+        }
+        if let Some(ip) = self.pick_unreachable_ip(from) {
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
+        }
+        let missing_parts = self.missing_parts();
+
+        for ip in ips {
+            if transport.send(ip, syn.clone()).is_err() {
+                self.suspect(ip)?;
+                continue;
+            }
+
+            if !missing_parts.is_empty() {
+                let pull =
+                    GossipMessage::new(from, Payload::Pull(Pull::new(missing_parts.clone())));
+                if transport.send(ip, pull).is_err() {
+                    self.suspect(ip)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a message received by the endpoint with the given ip, sending the answer (if the
+    /// message expects one) through the transport. If the sender can not be reached it is
+    /// suspected, and marked as dead if the failure detector agrees.
+    ///
+    /// When an `Ack` or `Ack2` leaves some states with missing parts, they are pulled from the
+    /// sender, which holds the states it just gossiped.
+    pub fn handle_message<T: Transport<S>>(
+        &mut self,
+        me: Ipv4Addr,
+        message: &GossipMessage<S>,
+        transport: &T,
+    ) -> Result<(), GossipError> {
+        let mut answers = Vec::new();
+
+        match &message.payload {
+            Payload::Syn(syn) => answers.push(Payload::Ack(self.handle_syn(syn))),
+            Payload::Ack(ack) => answers.push(Payload::Ack2(self.handle_ack(ack))),
+            Payload::Ack2(ack2) => self.handle_ack2(ack2),
+            Payload::Pull(pull) => answers.push(Payload::Push(self.handle_pull(pull))),
+            Payload::Push(push) => self.handle_push(push),
+        };
+
+        if matches!(message.payload, Payload::Ack(_) | Payload::Ack2(_)) {
+            let missing_parts = self.missing_parts();
+            if !missing_parts.is_empty() {
+                answers.push(Payload::Pull(Pull::new(missing_parts)));
+            }
+        }
+
+        for answer in answers {
+            if transport
+                .send(message.from, GossipMessage::new(me, answer))
+                .is_err()
+            {
+                self.suspect(message.from)?;
+                break;
+            }
         }
+
+        Ok(())
     }
 
     /// Handles a Syn message and returns the corresponding Ack message.
-    pub fn handle_syn(&self, syn: &Syn) -> Ack {
+    pub fn handle_syn(&self, syn: &Syn) -> Ack<S> {
         let mut stale_digests = Vec::new();
         let mut updated_info = BTreeMap::new();
 
@@ -328,6 +597,9 @@ impl Gossiper {
                         // Si el de él está desactualizado, le mando la info para que lo actualice
                         updated_info.insert(my_digest, my_state.application_state.clone());
                     }
+                    // A removed endpoint is not taken back, whatever its newer state says
+                    std::cmp::Ordering::Greater
+                        if my_state.application_state.removed_until().is_some() => {}
                     std::cmp::Ordering::Greater => {
                         // Si el mío está desactualizado, le mando mi digest
                         stale_digests.push(my_digest);
@@ -351,7 +623,7 @@ impl Gossiper {
     }
 
     /// Handles an Ack message and returns the corresponding Ack2 message.
-    pub fn handle_ack(&mut self, ack: &Ack) -> Ack2 {
+    pub fn handle_ack(&mut self, ack: &Ack<S>) -> Ack2<S> {
         let mut updated_info = BTreeMap::new();
 
         for digest in &ack.stale_digests {
@@ -393,7 +665,7 @@ impl Gossiper {
             //assert!(digest.get_heartbeat_state() > my_state.heartbeat_state);
 
             // la actualizo
-            self.endpoints_state.insert(
+            self.update_endpoint_state(
                 digest.address,
                 EndpointState::new(
                     info.clone(),
@@ -406,38 +678,524 @@ impl Gossiper {
     }
 
     /// Handles an Ack2 message and updates the local state.
-    pub fn handle_ack2(&mut self, ack2: &Ack2) {
+    pub fn handle_ack2(&mut self, ack2: &Ack2<S>) {
         for (digest, info) in &ack2.updated_info {
-            if let Some(_my_state) = self.endpoints_state.get(&digest.address) {
-                // El ACK2 debe contener info más actualizada que la mía
-                //assert!(digest.get_heartbeat_state() > my_state.heartbeat_state);
-
-                self.endpoints_state.insert(
-                    digest.address,
-                    EndpointState::new(
-                        info.clone(),
-                        HeartbeatState::new(digest.generation, digest.version),
-                    ),
-                );
-            } else {
-                self.endpoints_state.insert(
-                    digest.address,
-                    EndpointState::new(
-                        info.clone(),
-                        HeartbeatState::new(digest.generation, digest.version),
-                    ),
-                );
-            }
+            // El ACK2 debe contener info más actualizada que la mía
+            self.update_endpoint_state(
+                digest.address,
+                EndpointState::new(
+                    info.clone(),
+                    HeartbeatState::new(digest.generation, digest.version),
+                ),
+            );
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use messages::Payload;
-    use std::str::FromStr;
-    use structures::application_state::ApplicationState;
+    /// Handles a Pull message and returns the Push with the parts asked for that are known.
+    pub fn handle_pull(&self, pull: &Pull) -> Push {
+        let parts = pull
+            .parts
+            .iter()
+            .filter_map(|(name, digest)| {
+                self.endpoints_state
+                    .values()
+                    .find_map(|state| state.application_state.part_bytes(name, *digest))
+            })
+            .collect();
+
+        Push::new(parts)
+    }
+
+    /// Handles a Push message, adding its parts to the states that were missing them. Parts that
+    /// can not be read are ignored, they are pulled again on the next round.
+    pub fn handle_push(&mut self, push: &Push) {
+        for state in self.endpoints_state.values_mut() {
+            if state.application_state.missing_parts().is_empty() {
+                continue;
+            }
+            for part in &push.parts {
+                let _ = state.application_state.add_part(part);
+            }
+        }
+    }
+
+    /// Replaces the state of the endpoint with the given ip, notifying the subscribers if the
+    /// membership of the cluster changed.
+    ///
+    /// The parts of the new state that were gossiped by digest only are taken from the known
+    /// states when possible, starting with the previous state of the same endpoint.
+    ///
+    /// A newer heartbeat is reported to the failure detector as an arrival. A new generation
+    /// means the endpoint restarted, so the arrivals of its previous incarnation are forgotten.
+    ///
+    /// States of a removed endpoint are ignored until its removal expires, unless they are also
+    /// removed, and so are removed states that already expired, so a forgotten endpoint is not
+    /// learnt again from a peer that still holds it.
+    fn update_endpoint_state(&mut self, ip: Ipv4Addr, mut state: EndpointState<S>) {
+        let removed_until = state.application_state.removed_until();
+        let is_removed = self
+            .endpoints_state
+            .get(&ip)
+            .and_then(|known| known.application_state.removed_until())
+            .is_some();
+        if (is_removed && removed_until.is_none())
+            || removed_until.is_some_and(|removed_until| removed_until <= now_millis())
+        {
+            return;
+        }
+        if removed_until.is_some() {
+            self.failure_detector.remove(ip);
+            self.seeds.retain(|seed| *seed != ip);
+        }
+
+        let previous = self.endpoints_state.get(&ip).into_iter();
+        for known in previous.chain(self.endpoints_state.values()) {
+            if state.application_state.missing_parts().is_empty() {
+                break;
+            }
+            state
+                .application_state
+                .complete_from(&known.application_state);
+        }
+
+        let previous_heartbeat = self.endpoints_state.get(&ip).map(|s| s.heartbeat_state);
+        if previous_heartbeat.map(|h| h.generation) != Some(state.heartbeat_state.generation) {
+            self.failure_detector.remove(ip);
+        }
+        if previous_heartbeat.is_none_or(|h| h < state.heartbeat_state) && removed_until.is_none() {
+            self.failure_detector.report(ip);
+        }
+
+        let event = Self::membership_event(ip, self.endpoints_state.get(&ip), &state);
+        self.endpoints_state.insert(ip, state);
+
+        if let Some(event) = event {
+            self.notify(event);
+        }
+    }
+
+    /// Applies `update` to the application state of the endpoint with the given ip, notifying
+    /// the subscribers if the endpoint died or came back.
+    fn update_application_state<F: FnOnce(&mut S)>(
+        &mut self,
+        ip: Ipv4Addr,
+        update: F,
+    ) -> Result<(), GossipError> {
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        let was_alive = app_state.is_alive();
+        update(app_state);
+
+        match (was_alive, app_state.is_alive()) {
+            (true, false) => self.notify(MembershipEvent::Dead(ip)),
+            (false, true) => self.notify(MembershipEvent::Alive(ip)),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the change in membership caused by replacing the `previous` state of an endpoint
+    /// with the `current` one, if any.
+    ///
+    /// Seeds start with an empty state (generation 0), so an endpoint is considered joined the
+    /// first time its real state is received.
+    fn membership_event(
+        ip: Ipv4Addr,
+        previous: Option<&EndpointState<S>>,
+        current: &EndpointState<S>,
+    ) -> Option<MembershipEvent> {
+        let was_removed = previous.is_some_and(|p| p.application_state.removed_until().is_some());
+        if current.application_state.removed_until().is_some() {
+            return (!was_removed).then_some(MembershipEvent::Removed(ip));
+        }
+
+        let previous = match previous {
+            Some(previous) if previous.heartbeat_state.generation != 0 => previous,
+            _ if current.heartbeat_state.generation != 0 => {
+                return Some(MembershipEvent::Joined(ip))
+            }
+            _ => return None,
+        };
+
+        if previous.heartbeat_state.generation != current.heartbeat_state.generation {
+            return Some(MembershipEvent::Restarted(ip));
+        }
+
+        match (
+            previous.application_state.is_alive(),
+            current.application_state.is_alive(),
+        ) {
+            (true, false) => Some(MembershipEvent::Dead(ip)),
+            (false, true) => Some(MembershipEvent::Alive(ip)),
+            _ => None,
+        }
+    }
+
+    /// Sends the event to every subscriber, forgetting the ones that are gone.
+    fn notify(&mut self, event: MembershipEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
+
+/// Returns the generation of an endpoint that starts now: the current time, or the saved
+/// generation plus one if the clock is behind it.
+fn new_generation(saved_generation: Option<u128>) -> u128 {
+    let now = now_millis() as u128;
+    saved_generation.map_or(now, |saved| now.max(saved + 1))
+}
+
+/// Returns the current time in milliseconds since the epoch.
+fn now_millis() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+/// Methods for the application state gossiped by the database nodes.
+impl Gossiper<ApplicationState> {
+    /// Changes the status of the application state of the endpoint with the given ip.
+    pub fn change_status(&mut self, ip: Ipv4Addr, status: NodeStatus) -> Result<(), GossipError> {
+        self.update_application_state(ip, |app_state| {
+            app_state.status = status;
+            app_state.version += 1;
+        })
+    }
+
+    /// Sets the position in the ring of the endpoint with the given ip.
+    pub fn set_token(&mut self, ip: Ipv4Addr, token: u64) -> Result<(), GossipError> {
+        self.endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state
+            .set_token(token);
+
+        Ok(())
+    }
+
+    /// Returns the position in the ring of the endpoint with the given ip, or `None` if it
+    /// keeps the default one.
+    pub fn get_token(&self, ip: Ipv4Addr) -> Result<Option<u64>, GossipError> {
+        let token = self
+            .endpoints_state
+            .get(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state
+            .token;
+
+        Ok(token)
+    }
+
+    /// Sets the datacenter and rack of the endpoint with the given ip.
+    pub fn set_location(
+        &mut self,
+        ip: Ipv4Addr,
+        datacenter: &str,
+        rack: &str,
+    ) -> Result<(), GossipError> {
+        self.update_application_state(ip, |app_state| app_state.set_location(datacenter, rack))
+    }
+
+    /// Sets whether the endpoint with the given ip started without any data of its own.
+    pub fn set_empty_data(&mut self, ip: Ipv4Addr, empty_data: bool) -> Result<(), GossipError> {
+        self.update_application_state(ip, |app_state| app_state.set_empty_data(empty_data))
+    }
+
+    /// Returns the datacenter and rack of the endpoint with the given ip.
+    pub fn get_location(&self, ip: Ipv4Addr) -> Result<(String, String), GossipError> {
+        let app_state = &self
+            .endpoints_state
+            .get(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        Ok((app_state.datacenter.clone(), app_state.rack.clone()))
+    }
+
+    /// Returns a copy of the application state of the endpoint with the given ip.
+    pub fn get_status(&self, ip: Ipv4Addr) -> Result<NodeStatus, GossipError> {
+        let app_state = self
+            .endpoints_state
+            .get(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state
+            .status;
+
+        Ok(app_state)
+    }
+
+    /// Returns the schema with the largest timestamp from the known application states. Schemas
+    /// whose keyspaces have not all been pulled yet are left out.
+    ///
+    /// Schemas changed at the same time on different endpoints are told apart by their version, so
+    /// every endpoint takes the same one of them. Whether the endpoints ended up with the same
+    /// schema is told by [`Gossiper::schema_agreement`].
+    pub fn get_most_updated_schema(&self) -> Option<Schema> {
+        self.endpoints_state
+            .values()
+            .map(|state| &state.application_state.schema)
+            .filter(|schema| schema.is_complete() && schema.timestamp > 0)
+            .max_by_key(|schema| (schema.timestamp, schema.version()))
+            .cloned()
+    }
+
+    /// Returns whether the live endpoints gossip the same version of the schema as the endpoint
+    /// with the given ip, and the ones that do not.
+    ///
+    /// Endpoints converge to the most updated schema after a few rounds, so a disagreement that
+    /// lasts means that some endpoints can not take it, as they can not pull its keyspaces.
+    pub fn schema_agreement(&self, ip: Ipv4Addr) -> Result<SchemaAgreement, GossipError> {
+        let version = self
+            .endpoints_state
+            .get(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state
+            .schema_version();
+
+        let mut disagreeing: Vec<(Ipv4Addr, u64)> = self
+            .endpoints_state
+            .iter()
+            .filter(|(other, _)| **other != ip && self.is_alive(**other))
+            .map(|(other, state)| (*other, state.application_state.schema_version()))
+            .filter(|(_, other_version)| *other_version != version)
+            .collect();
+        disagreeing.sort();
+
+        Ok(SchemaAgreement {
+            version,
+            disagreeing,
+        })
+    }
+
+    /// Removes the keyspace from the application state of the endpoint with the given ip.
+    pub fn remove_keyspace(&mut self, ip: Ipv4Addr, keyspace: &str) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        // TODO: make it an app state or schema method which also alters the timestamp
+        app_state.schema.keyspaces.remove(keyspace);
+
+        app_state.version += 1;
+        app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+        Ok(())
+    }
+
+    /// Changes the replication options of a keyspace in the application state of the endpoint with
+    /// the given ip.
+    ///
+    /// If the options are the same the schema is left untouched, so no new version is gossiped.
+    pub fn alter_keyspace(
+        &mut self,
+        ip: Ipv4Addr,
+        alter_keyspace: AlterKeyspace,
+    ) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        let keyspace = app_state
+            .schema
+            .keyspaces
+            .get_mut(&alter_keyspace.get_name())
+            .ok_or(GossipError::NoSuchKeyspace)?;
+
+        if keyspace.get_replication_class() == alter_keyspace.get_replication_class()
+            && keyspace.get_replication_factor() == alter_keyspace.get_replication_factor()
+            && keyspace.get_datacenters() == alter_keyspace.get_datacenters()
+        {
+            return Ok(());
+        }
+
+        keyspace.update_replication_class(alter_keyspace.get_replication_class());
+        keyspace.update_replication_factor(alter_keyspace.get_replication_factor());
+        keyspace.update_datacenters(alter_keyspace.get_datacenters());
+
+        app_state.version += 1;
+        app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+        Ok(())
+    }
+
+    /// Adds the keyspace to the application state of the endpoint with the given ip.
+    ///
+    /// If the keyspace already exists, the schema is left untouched and it is an error unless the
+    /// keyspace was created with `IF NOT EXISTS`.
+    pub fn add_keyspace(
+        &mut self,
+        ip: Ipv4Addr,
+        keyspace: CreateKeyspace,
+    ) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        // Add the keyspace to the schema
+        if !app_state
+            .schema
+            .keyspaces
+            .keys()
+            .any(|k| *k == keyspace.get_name())
+        {
+            app_state.schema.keyspaces.insert(
+                keyspace.get_name(),
+                KeyspaceSchema {
+                    inner: keyspace,
+                    tables: Vec::new(),
+                },
+            );
+        } else if keyspace.if_not_exists_clause {
+            return Ok(());
+        } else {
+            return Err(GossipError::KeyspaceAlreadyExists);
+        }
+
+        app_state.version += 1;
+        app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+        Ok(())
+    }
+
+    /// Add the table to the keyspace of the application state of the endpoint with the given ip.
+    ///
+    /// If the table already exists, the schema is left untouched and it is an error unless the
+    /// table was created with `IF NOT EXISTS`.
+    pub fn add_table(
+        &mut self,
+        ip: Ipv4Addr,
+        table: CreateTable,
+        kesyapce_name: &str,
+    ) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        // Test if the keyspace of the table exists
+        let keyspace_exists = app_state
+            .schema
+            .keyspaces
+            .keys()
+            .any(|k| *k == kesyapce_name);
+
+        if keyspace_exists {
+            let keyspace = app_state
+                .schema
+                .keyspaces
+                .get_mut(kesyapce_name)
+                .ok_or(GossipError::NoSuchKeyspace)?;
+
+            // Check if the table already exists
+            for t in keyspace.tables.iter() {
+                if t.inner.get_name() == table.get_name() {
+                    if table.get_if_not_exists_clause() {
+                        return Ok(());
+                    }
+                    return Err(GossipError::TableAlreadyExists);
+                }
+            }
+
+            let table_schema = TableSchema::new(table);
+
+            keyspace.tables.push(table_schema);
+        } else {
+            return Err(GossipError::NoSuchKeyspace);
+        }
+
+        app_state.version += 1;
+        app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+        Ok(())
+    }
+
+    /// Removes the table from the keyspace of the application state of the endpoint with the given ip.
+    pub fn remove_table(
+        &mut self,
+        ip: Ipv4Addr,
+        keyspace: &str,
+        table: &str,
+    ) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        // Find the given keyspace in the schema
+        let k = app_state
+            .schema
+            .keyspaces
+            .iter_mut()
+            .find(|(keyspace_name, _)| *keyspace_name == keyspace);
+
+        // If the keyspace exists, remove the table from it
+        if let Some((_, k_schema)) = k {
+            k_schema.tables.retain(|t| t.inner.get_name() != table);
+            app_state.version += 1;
+            app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+            Ok(())
+        } else {
+            Err(GossipError::NoSuchKeyspace)
+        }
+    }
+
+    /// Replaces a table of the keyspace of the application state of the endpoint with the given ip
+    /// with its altered version, the table with the same name.
+    pub fn alter_table(
+        &mut self,
+        ip: Ipv4Addr,
+        keyspace: &str,
+        table: CreateTable,
+    ) -> Result<(), GossipError> {
+        // Find the app state of the given ip
+        let app_state = &mut self
+            .endpoints_state
+            .get_mut(&ip)
+            .ok_or(GossipError::NoEndpointStateForIp)?
+            .application_state;
+
+        let keyspace = app_state
+            .schema
+            .keyspaces
+            .get_mut(keyspace)
+            .ok_or(GossipError::NoSuchKeyspace)?;
+        let old_table = keyspace
+            .tables
+            .iter_mut()
+            .find(|t| t.inner.get_name() == table.get_name())
+            .ok_or(GossipError::NoSuchTable)?;
+        *old_table = TableSchema::new(table);
+
+        app_state.version += 1;
+        app_state.schema.timestamp = Utc::now().timestamp_millis();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use messages::Payload;
+    use std::str::FromStr;
+    use structures::application_state::ApplicationState;
 
     #[test]
     fn incoming_syn_same_generation_lower_version() {
@@ -457,6 +1215,7 @@ mod tests {
 
         let gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack = gossiper.handle_syn(&syn);
@@ -497,6 +1256,7 @@ mod tests {
 
         let gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack = gossiper.handle_syn(&syn);
@@ -537,6 +1297,7 @@ mod tests {
 
         let gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack = gossiper.handle_syn(&syn);
@@ -551,6 +1312,23 @@ mod tests {
         assert!(ack.updated_info.is_empty());
     }
 
+    #[test]
+    fn restarted_endpoint_has_a_newer_generation() {
+        // a node that restarts starts its versions over, but its
+        // generation must still be greater than the previous one
+        let ip = Ipv4Addr::from_str("127.0.0.2").unwrap();
+
+        let first: Gossiper = Gossiper::new().with_endpoint_state(ip);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let restarted: Gossiper = Gossiper::new().with_endpoint_state(ip);
+
+        let first_heartbeat = first.endpoints_state.get(&ip).unwrap().heartbeat_state;
+        let restarted_heartbeat = restarted.endpoints_state.get(&ip).unwrap().heartbeat_state;
+
+        assert_ne!(first_heartbeat.generation, 0);
+        assert!(restarted_heartbeat > first_heartbeat);
+    }
+
     #[test]
     fn incoming_syn_higher_version_same_generation() {
         // if the incoming digest version is higher, the return ack
@@ -569,6 +1347,7 @@ mod tests {
 
         let gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack = gossiper.handle_syn(&syn);
@@ -601,6 +1380,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -638,6 +1418,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -675,6 +1456,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -720,6 +1502,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -759,6 +1542,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -807,6 +1591,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack2 = gossiper.handle_ack(&ack);
@@ -877,6 +1662,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         gossiper.handle_ack2(&ack2);
@@ -918,6 +1704,7 @@ mod tests {
 
         let gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let ack = gossiper.handle_syn(&syn);
@@ -939,6 +1726,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: local_state.clone(),
+            ..Default::default()
         };
 
         let _ = gossiper.handle_ack2(&ack);
@@ -1033,6 +1821,7 @@ mod tests {
 
         let mut gossiper_server = Gossiper {
             endpoints_state: server_state.clone(),
+            ..Default::default()
         };
 
         // server handles syn and sends ack to client
@@ -1057,6 +1846,7 @@ mod tests {
 
         let mut gossiper_client = Gossiper {
             endpoints_state: client_state.clone(),
+            ..Default::default()
         };
 
         // client handles ack, updates its state and sends ack2 to server
@@ -1095,7 +1885,7 @@ mod tests {
             ],
         };
 
-        let gossip_msg = GossipMessage {
+        let gossip_msg: GossipMessage = GossipMessage {
             from: Ipv4Addr::new(127, 0, 0, 1),
             payload: Payload::Syn(syn),
         };
@@ -1119,6 +1909,7 @@ mod tests {
                     HeartbeatState::default(),
                 ),
             )]),
+            ..Default::default()
         };
 
         gossiper.change_status(ip, NodeStatus::Normal).unwrap();
@@ -1149,6 +1940,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: HashMap::new(),
+            ..Default::default()
         };
 
         let result = gossiper.change_status(ip, NodeStatus::Normal);
@@ -1168,6 +1960,7 @@ mod tests {
                     HeartbeatState::new(7, 2),
                 ),
             )]),
+            ..Default::default()
         };
 
         gossiper.remove_keyspace(ip, "keyspace").unwrap();
@@ -1198,6 +1991,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: HashMap::new(),
+            ..Default::default()
         };
 
         let result = gossiper.remove_keyspace(ip, "keyspace");
@@ -1217,6 +2011,7 @@ mod tests {
                     HeartbeatState::new(7, 2),
                 ),
             )]),
+            ..Default::default()
         };
 
         gossiper
@@ -1265,6 +2060,7 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: HashMap::new(),
+            ..Default::default()
         };
 
         let result = gossiper.add_keyspace(ip, CreateKeyspace::default());
@@ -1306,6 +2102,7 @@ mod tests {
                     HeartbeatState::new(7, 2),
                 ),
             )]),
+            ..Default::default()
         };
 
         gossiper.remove_table(ip, "keyspace", "table1").unwrap();
@@ -1339,15 +2136,298 @@ mod tests {
 
         let mut gossiper = Gossiper {
             endpoints_state: HashMap::new(),
+            ..Default::default()
+        };
+
+        let result = gossiper.remove_table(ip, "keyspace", "table1");
+
+        assert!(matches!(result, Err(GossipError::NoEndpointStateForIp)));
+    }
+
+    #[test]
+    fn remove_table_non_existent_keyspace() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::from([(
+                ip,
+                EndpointState::new(
+                    ApplicationState::new(NodeStatus::Bootstrap, 2, Schema::default()),
+                    HeartbeatState::new(7, 2),
+                ),
+            )]),
+            ..Default::default()
+        };
+
+        let result = gossiper.remove_table(ip, "keyspace", "table1");
+
+        assert!(matches!(result, Err(GossipError::NoSuchKeyspace)));
+    }
+
+    #[test]
+    fn add_table() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::from([(
+                ip,
+                EndpointState::new(
+                    ApplicationState::new(
+                        NodeStatus::Bootstrap,
+                        2,
+                        Schema {
+                            keyspaces: HashMap::from([(
+                                "keyspace".to_string(),
+                                KeyspaceSchema {
+                                    inner: CreateKeyspace {
+                                        name: "keyspace".to_string(),
+                                        ..Default::default()
+                                    },
+                                    tables: Vec::new(),
+                                },
+                            )]),
+                            timestamp: 0,
+                            missing_keyspaces: HashMap::new(),
+                        },
+                    ),
+                    HeartbeatState::new(7, 2),
+                ),
+            )]),
+            ..Default::default()
+        };
+
+        gossiper
+            .add_table(
+                ip,
+                CreateTable {
+                    name: "table".to_string(),
+                    keyspace_used_name: "keyspace".to_string(),
+                    if_not_exists_clause: false,
+                    columns: Vec::new(),
+                    clustering_columns_in_order: Vec::new(),
+                    options: Default::default(),
+                },
+                "keyspace",
+            )
+            .unwrap();
+
+        assert_eq!(
+            gossiper
+                .endpoints_state
+                .get(&ip)
+                .unwrap()
+                .application_state
+                .schema,
+            Schema {
+                keyspaces: HashMap::from([(
+                    "keyspace".to_string(),
+                    KeyspaceSchema {
+                        inner: CreateKeyspace {
+                            name: "keyspace".to_string(),
+                            ..Default::default()
+                        },
+                        tables: vec![TableSchema {
+                            inner: CreateTable {
+                                name: "table".to_string(),
+                                keyspace_used_name: "keyspace".to_string(),
+                                if_not_exists_clause: false,
+                                columns: Vec::new(),
+                                clustering_columns_in_order: Vec::new(),
+                                options: Default::default(),
+                            },
+                        }],
+                    }
+                )]),
+                // copy timestamp from insertion
+                timestamp: gossiper
+                    .endpoints_state
+                    .get(&ip)
+                    .unwrap()
+                    .application_state
+                    .schema
+                    .timestamp,
+                missing_keyspaces: HashMap::new(),
+            }
+        );
+
+        assert_eq!(
+            gossiper
+                .endpoints_state
+                .get(&ip)
+                .unwrap()
+                .application_state
+                .version,
+            3
+        );
+    }
+
+    #[test]
+    fn add_table_non_existent_ip() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::new(),
+            ..Default::default()
+        };
+
+        let result = gossiper.add_table(
+            ip,
+            CreateTable {
+                name: "table".to_string(),
+                keyspace_used_name: "keyspace".to_string(),
+                if_not_exists_clause: false,
+                columns: Vec::new(),
+                clustering_columns_in_order: Vec::new(),
+                options: Default::default(),
+            },
+            "keyspace",
+        );
+
+        assert!(matches!(result, Err(GossipError::NoEndpointStateForIp)));
+    }
+
+    #[test]
+    fn add_table_non_existent_keyspace() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::from([(
+                ip,
+                EndpointState::new(
+                    ApplicationState::new(NodeStatus::Bootstrap, 2, Schema::default()),
+                    HeartbeatState::new(7, 2),
+                ),
+            )]),
+            ..Default::default()
+        };
+
+        let result = gossiper.add_table(
+            ip,
+            CreateTable {
+                name: "table".to_string(),
+                keyspace_used_name: "keyspace".to_string(),
+                if_not_exists_clause: false,
+                columns: Vec::new(),
+                clustering_columns_in_order: Vec::new(),
+                options: Default::default(),
+            },
+            "keyspace",
+        );
+
+        assert!(matches!(result, Err(GossipError::NoSuchKeyspace)));
+    }
+
+    #[test]
+    fn alter_table() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+        let table = CreateTable::deserialize(
+            "CREATE TABLE keyspace.table (id INT, name TEXT, PRIMARY KEY (id))",
+        )
+        .unwrap();
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::from([(
+                ip,
+                EndpointState::new(
+                    ApplicationState::new(
+                        NodeStatus::Normal,
+                        2,
+                        Schema {
+                            keyspaces: HashMap::from([(
+                                "keyspace".to_string(),
+                                KeyspaceSchema {
+                                    inner: CreateKeyspace {
+                                        name: "keyspace".to_string(),
+                                        ..Default::default()
+                                    },
+                                    tables: vec![TableSchema::new(table.clone())],
+                                },
+                            )]),
+                            timestamp: 0,
+                            missing_keyspaces: HashMap::new(),
+                        },
+                    ),
+                    HeartbeatState::new(7, 2),
+                ),
+            )]),
+            ..Default::default()
+        };
+
+        let mut altered = table.clone();
+        altered.rename_column("name", "full_name").unwrap();
+        gossiper
+            .alter_table(ip, "keyspace", altered.clone())
+            .unwrap();
+
+        let app_state = &gossiper.endpoints_state.get(&ip).unwrap().application_state;
+        assert_eq!(
+            app_state.schema.keyspaces["keyspace"].tables,
+            vec![TableSchema::new(altered)]
+        );
+        assert_eq!(app_state.version, 3);
+        assert!(app_state.schema.timestamp > 0);
+
+        let mut missing = table;
+        missing.name = "other".to_string();
+        assert!(matches!(
+            gossiper.alter_table(ip, "keyspace", missing.clone()),
+            Err(GossipError::NoSuchTable)
+        ));
+        assert!(matches!(
+            gossiper.alter_table(ip, "other", missing),
+            Err(GossipError::NoSuchKeyspace)
+        ));
+    }
+
+    #[test]
+    fn alter_keyspace() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+
+        let mut gossiper = Gossiper {
+            endpoints_state: HashMap::from([(
+                ip,
+                EndpointState::new(
+                    ApplicationState::new(NodeStatus::Bootstrap, 2, Schema::default()),
+                    HeartbeatState::new(7, 2),
+                ),
+            )]),
+            ..Default::default()
         };
 
-        let result = gossiper.remove_table(ip, "keyspace", "table1");
+        let alter_keyspace = AlterKeyspace::deserialize(
+            "ALTER KEYSPACE keyspace WITH REPLICATION = {'class': 'SimpleStrategy', 'replication_factor': 3};",
+        )
+        .unwrap();
+
+        let result = gossiper.alter_keyspace(ip, alter_keyspace.clone());
+        assert!(matches!(result, Err(GossipError::NoSuchKeyspace)));
+
+        gossiper
+            .add_keyspace(
+                ip,
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    replication_class: "SimpleStrategy".to_string(),
+                    replication_factor: 1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        gossiper.alter_keyspace(ip, alter_keyspace.clone()).unwrap();
+
+        let app_state = &gossiper.endpoints_state.get(&ip).unwrap().application_state;
+        let keyspace = app_state.schema.keyspaces.get("keyspace").unwrap();
+        assert_eq!(keyspace.get_replication_factor(), 3);
+        assert_eq!(app_state.version, 4);
 
-        assert!(matches!(result, Err(GossipError::NoEndpointStateForIp)));
+        // Altering to the same options does not create a new version
+        gossiper.alter_keyspace(ip, alter_keyspace).unwrap();
+        let app_state = &gossiper.endpoints_state.get(&ip).unwrap().application_state;
+        assert_eq!(app_state.version, 4);
     }
 
     #[test]
-    fn remove_table_non_existent_keyspace() {
+    fn add_existing_keyspace() {
         let ip = Ipv4Addr::new(127, 0, 0, 1);
 
         let mut gossiper = Gossiper {
@@ -1358,154 +2438,560 @@ mod tests {
                     HeartbeatState::new(7, 2),
                 ),
             )]),
+            ..Default::default()
         };
 
-        let result = gossiper.remove_table(ip, "keyspace", "table1");
+        let keyspace = CreateKeyspace {
+            name: "keyspace".to_string(),
+            ..Default::default()
+        };
+        gossiper.add_keyspace(ip, keyspace.clone()).unwrap();
 
-        assert!(matches!(result, Err(GossipError::NoSuchKeyspace)));
+        let result = gossiper.add_keyspace(ip, keyspace.clone());
+        assert!(matches!(result, Err(GossipError::KeyspaceAlreadyExists)));
+
+        // With IF NOT EXISTS nothing changes, not even the version
+        let result = gossiper.add_keyspace(
+            ip,
+            CreateKeyspace {
+                if_not_exists_clause: true,
+                replication_factor: 5,
+                ..keyspace.clone()
+            },
+        );
+        assert!(result.is_ok());
+
+        let app_state = &gossiper.endpoints_state.get(&ip).unwrap().application_state;
+        assert_eq!(app_state.version, 3);
+        assert_eq!(
+            app_state.schema.keyspaces.get("keyspace").unwrap().inner,
+            keyspace
+        );
     }
 
     #[test]
-    fn add_table() {
+    fn add_existing_table() {
         let ip = Ipv4Addr::new(127, 0, 0, 1);
 
         let mut gossiper = Gossiper {
             endpoints_state: HashMap::from([(
                 ip,
                 EndpointState::new(
-                    ApplicationState::new(
-                        NodeStatus::Bootstrap,
-                        2,
-                        Schema {
-                            keyspaces: HashMap::from([(
-                                "keyspace".to_string(),
-                                KeyspaceSchema {
-                                    inner: CreateKeyspace {
-                                        name: "keyspace".to_string(),
-                                        ..Default::default()
-                                    },
-                                    tables: Vec::new(),
-                                },
-                            )]),
-                            timestamp: 0,
-                        },
-                    ),
+                    ApplicationState::new(NodeStatus::Bootstrap, 2, Schema::default()),
                     HeartbeatState::new(7, 2),
                 ),
             )]),
+            ..Default::default()
         };
 
         gossiper
-            .add_table(
+            .add_keyspace(
                 ip,
-                CreateTable {
-                    name: "table".to_string(),
-                    keyspace_used_name: "keyspace".to_string(),
-                    if_not_exists_clause: false,
-                    columns: Vec::new(),
-                    clustering_columns_in_order: Vec::new(),
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    ..Default::default()
                 },
-                "keyspace",
             )
             .unwrap();
 
+        let table = CreateTable {
+            name: "table".to_string(),
+            keyspace_used_name: "keyspace".to_string(),
+            if_not_exists_clause: false,
+            columns: Vec::new(),
+            clustering_columns_in_order: Vec::new(),
+            options: Default::default(),
+        };
+        gossiper.add_table(ip, table.clone(), "keyspace").unwrap();
+
+        let result = gossiper.add_table(ip, table.clone(), "keyspace");
+        assert!(matches!(result, Err(GossipError::TableAlreadyExists)));
+
+        let result = gossiper.add_table(
+            ip,
+            CreateTable {
+                if_not_exists_clause: true,
+                ..table
+            },
+            "keyspace",
+        );
+        assert!(result.is_ok());
+
+        let app_state = &gossiper.endpoints_state.get(&ip).unwrap().application_state;
+        assert_eq!(app_state.version, 4);
         assert_eq!(
-            gossiper
-                .endpoints_state
-                .get(&ip)
+            app_state
+                .schema
+                .keyspaces
+                .get("keyspace")
                 .unwrap()
-                .application_state
-                .schema,
-            Schema {
-                keyspaces: HashMap::from([(
-                    "keyspace".to_string(),
-                    KeyspaceSchema {
-                        inner: CreateKeyspace {
-                            name: "keyspace".to_string(),
-                            ..Default::default()
-                        },
-                        tables: vec![TableSchema {
-                            inner: CreateTable {
-                                name: "table".to_string(),
-                                keyspace_used_name: "keyspace".to_string(),
-                                if_not_exists_clause: false,
-                                columns: Vec::new(),
-                                clustering_columns_in_order: Vec::new(),
-                            },
-                        }],
-                    }
-                )]),
-                // copy timestamp from insertion
-                timestamp: gossiper
-                    .endpoints_state
-                    .get(&ip)
-                    .unwrap()
-                    .application_state
-                    .schema
-                    .timestamp
-            }
+                .tables
+                .len(),
+            1
         );
+    }
+
+    #[test]
+    fn subscribers_are_notified_of_membership_changes() {
+        let ip = Ipv4Addr::new(127, 0, 0, 2);
+        let mut gossiper: Gossiper = Gossiper::new().with_seeds(vec![ip]);
+        let events = gossiper.subscribe();
+
+        let state = |generation| {
+            Ack2::new(BTreeMap::from([(
+                Digest::new(ip, generation, 1),
+                ApplicationState::new(NodeStatus::Normal, 1, Schema::default()),
+            )]))
+        };
+
+        gossiper.handle_ack2(&state(5));
+        gossiper.kill(ip).unwrap();
+        gossiper.change_status(ip, NodeStatus::Normal).unwrap();
+        gossiper.handle_ack2(&state(9));
 
         assert_eq!(
-            gossiper
-                .endpoints_state
-                .get(&ip)
-                .unwrap()
-                .application_state
-                .version,
-            3
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                MembershipEvent::Joined(ip),
+                MembershipEvent::Dead(ip),
+                MembershipEvent::Alive(ip),
+                MembershipEvent::Restarted(ip),
+            ]
         );
     }
 
+    struct RecordingTransport {
+        unreachable: Ipv4Addr,
+        sent: std::cell::RefCell<Vec<(Ipv4Addr, GossipMessage)>>,
+    }
+
+    impl Transport for RecordingTransport {
+        fn send(&self, to: Ipv4Addr, message: GossipMessage) -> Result<(), GossipError> {
+            if to == self.unreachable {
+                return Err(GossipError::SendError);
+            }
+            self.sent.borrow_mut().push((to, message));
+            Ok(())
+        }
+    }
+
     #[test]
-    fn add_table_non_existent_ip() {
-        let ip = Ipv4Addr::new(127, 0, 0, 1);
+    fn gossip_round_kills_unreachable_endpoints_once_suspected() {
+        use std::sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        };
 
-        let mut gossiper = Gossiper {
-            endpoints_state: HashMap::new(),
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let alive = Ipv4Addr::new(127, 0, 0, 2);
+        let unreachable = Ipv4Addr::new(127, 0, 0, 3);
+
+        let time = Arc::new(AtomicU64::new(0));
+        let clock_time = Arc::clone(&time);
+        let failure_detector = FailureDetector::new()
+            .with_clock(Arc::new(move || clock_time.load(Ordering::SeqCst) as u128));
+
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(me)
+            .with_seeds(vec![alive, unreachable])
+            .with_failure_detector(failure_detector);
+        let transport = RecordingTransport {
+            unreachable,
+            sent: std::cell::RefCell::new(Vec::new()),
+        };
+        let beat = |gossiper: &mut Gossiper, ip: Ipv4Addr, version: u32| {
+            let state =
+                EndpointState::new(ApplicationState::default(), HeartbeatState::new(1, version));
+            gossiper.update_endpoint_state(ip, state);
         };
 
-        let result = gossiper.add_table(
-            ip,
-            CreateTable {
-                name: "table".to_string(),
-                keyspace_used_name: "keyspace".to_string(),
-                if_not_exists_clause: false,
-                columns: Vec::new(),
-                clustering_columns_in_order: Vec::new(),
-            },
-            "keyspace",
+        // A single failed send does not kill the endpoint
+        beat(&mut gossiper, alive, 1);
+        beat(&mut gossiper, unreachable, 1);
+        gossiper.gossip_round(me, &transport).unwrap();
+
+        {
+            let sent = transport.sent.borrow();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].0, alive);
+            assert!(matches!(sent[0].1.payload, Payload::Syn(_)));
+        }
+        assert!(gossiper.is_alive(unreachable));
+        assert_eq!(gossiper.live_endpoints().len(), 3);
+
+        // Only the heartbeats of the reachable endpoint keep arriving
+        for version in 2..45 {
+            time.fetch_add(1000, Ordering::SeqCst);
+            beat(&mut gossiper, alive, version);
+        }
+        assert!(!gossiper.is_alive(unreachable));
+        gossiper.gossip_round(me, &transport).unwrap();
+
+        assert_eq!(gossiper.get_status(unreachable).unwrap(), NodeStatus::Dead);
+        assert!(gossiper.is_alive(alive));
+        assert_eq!(gossiper.live_endpoints().len(), 2);
+        assert!(!gossiper.suspect(alive).unwrap());
+    }
+
+    #[test]
+    fn shut_down_endpoints_are_never_killed() {
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let stopped = Ipv4Addr::new(127, 0, 0, 2);
+
+        let mut gossiper: Gossiper = Gossiper::new().with_endpoint_state(me);
+        let state = EndpointState::new(
+            ApplicationState::new(NodeStatus::Shutdown, 3, Schema::default()),
+            HeartbeatState::new(1, 1),
         );
+        gossiper.update_endpoint_state(stopped, state);
 
-        assert!(matches!(result, Err(GossipError::NoEndpointStateForIp)));
+        // Its heartbeats stopped, but it keeps its ranges until it restarts
+        assert!(!gossiper.is_alive(stopped));
+        assert!(!gossiper.suspect(stopped).unwrap());
+        assert_eq!(gossiper.get_status(stopped).unwrap(), NodeStatus::Shutdown);
+        assert_eq!(gossiper.live_endpoints(), vec![me]);
     }
 
     #[test]
-    fn add_table_non_existent_keyspace() {
-        let ip = Ipv4Addr::new(127, 0, 0, 1);
+    fn handle_message_answers_through_transport() {
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let peer = Ipv4Addr::new(127, 0, 0, 2);
+
+        let mut gossiper: Gossiper = Gossiper::new().with_endpoint_state(me);
+        let transport = RecordingTransport {
+            unreachable: Ipv4Addr::UNSPECIFIED,
+            sent: std::cell::RefCell::new(Vec::new()),
+        };
 
-        let mut gossiper = Gossiper {
-            endpoints_state: HashMap::from([(
+        let syn = GossipMessage::new(peer, Payload::Syn(Syn::new(vec![Digest::new(me, 0, 0)])));
+        gossiper.handle_message(me, &syn, &transport).unwrap();
+
+        let sent = transport.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, peer);
+        assert_eq!(sent[0].1.from, me);
+        match &sent[0].1.payload {
+            Payload::Ack(ack) => assert!(ack.updated_info.keys().any(|d| d.address == me)),
+            _ => panic!("a Syn must be answered with an Ack"),
+        }
+    }
+
+    /// Delivers the messages sent through the transport, and the answers to them, after writing
+    /// them to bytes and reading them back as they would travel between nodes.
+    fn deliver_over_the_wire(
+        transport: &RecordingTransport,
+        gossipers: &mut HashMap<Ipv4Addr, Gossiper>,
+    ) {
+        let next_message = || transport.sent.borrow_mut().pop();
+        while let Some((to, message)) = next_message() {
+            let message = GossipMessage::from_bytes(&message.as_bytes()).unwrap();
+            let gossiper = gossipers.get_mut(&to).unwrap();
+            gossiper.handle_message(to, &message, transport).unwrap();
+        }
+    }
+
+    #[test]
+    fn missing_keyspaces_are_pulled_from_the_sender() {
+        let a = Ipv4Addr::new(127, 0, 0, 1);
+        let b = Ipv4Addr::new(127, 0, 0, 2);
+
+        let mut gossiper_a: Gossiper = Gossiper::new().with_endpoint_state(a).with_seeds(vec![b]);
+        gossiper_a
+            .add_keyspace(
+                a,
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let gossiper_b: Gossiper = Gossiper::new().with_endpoint_state(b);
+
+        let mut gossipers = HashMap::from([(a, gossiper_a), (b, gossiper_b)]);
+        let transport = RecordingTransport {
+            unreachable: Ipv4Addr::UNSPECIFIED,
+            sent: std::cell::RefCell::new(Vec::new()),
+        };
+
+        gossipers
+            .get_mut(&a)
+            .unwrap()
+            .gossip_round(a, &transport)
+            .unwrap();
+        deliver_over_the_wire(&transport, &mut gossipers);
+
+        let schema_of_a = gossipers[&a].endpoints_state[&a]
+            .application_state
+            .schema
+            .clone();
+        let gossiper_b = &gossipers[&b];
+        assert!(gossiper_b.missing_parts().is_empty());
+        assert_eq!(
+            gossiper_b.endpoints_state[&a].application_state.schema,
+            schema_of_a
+        );
+        assert_eq!(gossiper_b.get_most_updated_schema(), Some(schema_of_a));
+    }
+
+    #[test]
+    fn known_keyspaces_are_not_gossiped_again() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+        let mut gossiper: Gossiper = Gossiper::new().with_endpoint_state(ip);
+        gossiper
+            .add_keyspace(
                 ip,
-                EndpointState::new(
-                    ApplicationState::new(NodeStatus::Bootstrap, 2, Schema::default()),
-                    HeartbeatState::new(7, 2),
-                ),
-            )]),
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        for i in 0..50 {
+            gossiper
+                .add_table(
+                    ip,
+                    CreateTable {
+                        name: format!("table{}", i),
+                        keyspace_used_name: "keyspace".to_string(),
+                        ..Default::default()
+                    },
+                    "keyspace",
+                )
+                .unwrap();
+        }
+
+        let state = gossiper.endpoints_state[&ip].clone();
+        let bytes = state.application_state.as_bytes();
+        assert!(bytes.len() < 64);
+
+        // A newer heartbeat of the same state is completed from the one already known
+        let mut received =
+            ApplicationState::from_bytes(&mut std::io::Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(received.missing_parts().len(), 1);
+        received.complete_from(&state.application_state);
+        assert_eq!(received, state.application_state);
+
+        let pull = Pull::new(received.missing_parts());
+        assert!(pull.parts.is_empty());
+        let push = gossiper.handle_pull(&Pull::new(vec![(
+            "keyspace".to_string(),
+            state.application_state.schema.keyspaces["keyspace"].digest(),
+        )]));
+        assert_eq!(push.parts.len(), 1);
+        assert!(gossiper
+            .handle_pull(&Pull::new(vec![("keyspace".to_string(), 0)]))
+            .parts
+            .is_empty());
+    }
+
+    #[test]
+    fn schema_agreement_reports_the_disagreeing_endpoints() {
+        let [a, b, c] = [1, 2, 3].map(|i| Ipv4Addr::new(127, 0, 0, i));
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(a)
+            .with_endpoint_state(b)
+            .with_endpoint_state(c);
+        for (ip, name) in [(a, "keyspace"), (b, "other"), (c, "keyspace")] {
+            gossiper
+                .add_keyspace(
+                    ip,
+                    CreateKeyspace {
+                        name: name.to_string(),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+        }
+
+        let agreement = gossiper.schema_agreement(a).unwrap();
+        let version_of_b = gossiper.endpoints_state[&b]
+            .application_state
+            .schema_version();
+        assert!(!agreement.is_reached());
+        assert_eq!(agreement.disagreeing, [(b, version_of_b)]);
+        assert_eq!(
+            agreement.version,
+            gossiper.endpoints_state[&c]
+                .application_state
+                .schema_version()
+        );
+
+        // Schemas changed at the same time are told apart by their version
+        for ip in [a, b, c] {
+            let state = gossiper.endpoints_state.get_mut(&ip).unwrap();
+            state.application_state.schema.timestamp = 100;
+        }
+        let most_updated = gossiper.get_most_updated_schema().unwrap();
+        assert_eq!(most_updated.version(), version_of_b.max(agreement.version));
+
+        gossiper.kill(b).unwrap();
+        assert!(gossiper.schema_agreement(a).unwrap().is_reached());
+        assert!(gossiper.schema_agreement(Ipv4Addr::UNSPECIFIED).is_err());
+    }
+
+    #[test]
+    fn saved_states_are_reloaded_with_a_newer_generation() {
+        let path = std::env::temp_dir().join(format!("gossip_state_test_{}", std::process::id()));
+        let (a, b) = (Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(127, 0, 0, 2));
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(a)
+            .with_endpoint_state(b)
+            .with_state_file(path.clone());
+        gossiper
+            .add_keyspace(
+                b,
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        // The clock of the endpoint goes back after it saves its state
+        let saved_generation = Utc::now().timestamp_millis() as u128 + 60_000;
+        let state_of_a = gossiper.endpoints_state.get_mut(&a).unwrap();
+        state_of_a.heartbeat_state = HeartbeatState::new(saved_generation, 40);
+        gossiper.save_state().unwrap();
+
+        for restarted in [
+            Gossiper::<ApplicationState>::new()
+                .with_endpoint_state(a)
+                .with_state_file(path.clone()),
+            Gossiper::new()
+                .with_state_file(path.clone())
+                .with_endpoint_state(a),
+        ] {
+            let heartbeat = restarted.endpoints_state[&a].heartbeat_state;
+            assert!(heartbeat > HeartbeatState::new(saved_generation, 40));
+            assert_eq!(heartbeat.version, 0);
+            assert_eq!(restarted.endpoints_state[&b], gossiper.endpoints_state[&b]);
+            assert!(restarted.missing_parts().is_empty());
+        }
+
+        std::fs::write(&path, [0xff]).unwrap();
+        let restarted: Gossiper = Gossiper::new()
+            .with_endpoint_state(a)
+            .with_state_file(path.clone());
+        assert_eq!(restarted.endpoints_state.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pick_ips_follows_the_fanout_and_the_seed_bias() {
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let seed = Ipv4Addr::new(127, 0, 0, 2);
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(me)
+            .with_seeds(vec![me, seed]);
+        for i in 3..10 {
+            gossiper = gossiper.with_endpoint_state(Ipv4Addr::new(127, 0, 0, i));
+        }
+
+        let gossiper = gossiper.with_config(GossipConfig {
+            fanout: 2,
+            ..Default::default()
+        });
+        assert_eq!(gossiper.config().fanout, 2);
+        for _ in 0..20 {
+            let ips = gossiper.pick_ips(me);
+            assert_eq!(ips.len(), 2);
+            assert!(!ips.contains(&&me));
+        }
+
+        let gossiper = gossiper.with_config(GossipConfig {
+            fanout: 2,
+            seed_probability: 1.0,
+            ..Default::default()
+        });
+        for _ in 0..20 {
+            let ips = gossiper.pick_ips(me);
+            assert!(ips.contains(&&seed));
+            assert!(ips.len() == 2 || ips.len() == 3);
+        }
+    }
+
+    #[test]
+    fn removed_endpoint_is_not_taken_back_by_stale_gossip() {
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let removed = Ipv4Addr::new(127, 0, 0, 2);
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(me)
+            .with_seeds(vec![removed]);
+        let events = gossiper.subscribe();
+
+        let state = |generation, version| {
+            Ack2::new(BTreeMap::from([(
+                Digest::new(removed, generation, version),
+                ApplicationState::new(NodeStatus::Normal, 1, Schema::default()),
+            )]))
         };
 
-        let result = gossiper.add_table(
-            ip,
-            CreateTable {
-                name: "table".to_string(),
-                keyspace_used_name: "keyspace".to_string(),
-                if_not_exists_clause: false,
-                columns: Vec::new(),
-                clustering_columns_in_order: Vec::new(),
-            },
-            "keyspace",
+        gossiper.handle_ack2(&state(5, 3));
+        assert!(matches!(
+            gossiper.remove_endpoint(removed),
+            Err(GossipError::EndpointIsAlive)
+        ));
+        assert!(matches!(
+            gossiper.remove_endpoint(me),
+            Err(GossipError::EndpointIsAlive)
+        ));
+
+        gossiper.kill(removed).unwrap();
+        gossiper.remove_endpoint(removed).unwrap();
+        assert_eq!(gossiper.get_status(removed).unwrap(), NodeStatus::Removed);
+        assert!(gossiper.pick_ips(me).is_empty());
+        assert_eq!(gossiper.pick_unreachable_ip(me), None);
+
+        // Neither the stale state nor the one of a new generation bring it back
+        gossiper.handle_ack2(&state(5, 3));
+        gossiper.handle_ack2(&state(9, 1));
+        assert_eq!(gossiper.get_status(removed).unwrap(), NodeStatus::Removed);
+        let ack = gossiper.handle_syn(&Syn::new(vec![Digest::new(removed, 9, 1)]));
+        assert!(ack.stale_digests.is_empty() && ack.updated_info.is_empty());
+
+        // Peers with the stale state get the removal
+        let ack = gossiper.handle_syn(&Syn::new(vec![Digest::new(removed, 5, 3)]));
+        let (digest, info) = ack.updated_info.into_iter().next().unwrap();
+        assert_eq!(digest, Digest::new(removed, 5, 4));
+        assert_eq!(info.status, NodeStatus::Removed);
+
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                MembershipEvent::Joined(removed),
+                MembershipEvent::Dead(removed),
+                MembershipEvent::Removed(removed),
+            ]
         );
+    }
 
-        assert!(matches!(result, Err(GossipError::NoSuchKeyspace)));
+    #[test]
+    fn removed_endpoint_is_forgotten_once_expired() {
+        let me = Ipv4Addr::new(127, 0, 0, 1);
+        let removed = Ipv4Addr::new(127, 0, 0, 2);
+        let mut gossiper: Gossiper = Gossiper::new()
+            .with_endpoint_state(me)
+            .with_seeds(vec![removed])
+            .with_config(GossipConfig {
+                removed_expiry: std::time::Duration::ZERO,
+                ..Default::default()
+            });
+        let transport = RecordingTransport {
+            unreachable: Ipv4Addr::UNSPECIFIED,
+            sent: std::cell::RefCell::new(Vec::new()),
+        };
+
+        gossiper.kill(removed).unwrap();
+        gossiper.remove_endpoint(removed).unwrap();
+        let removed_state = gossiper.endpoints_state[&removed].clone();
+        gossiper.gossip_round(me, &transport).unwrap();
+        assert!(!gossiper.endpoints_state.contains_key(&removed));
+        assert!(transport.sent.borrow().is_empty());
+
+        // A peer that still holds the expired removal does not make it known again
+        gossiper.handle_ack2(&Ack2::new(BTreeMap::from([(
+            Digest::from_heartbeat_state(removed, &removed_state.heartbeat_state),
+            removed_state.application_state,
+        )])));
+        assert!(!gossiper.endpoints_state.contains_key(&removed));
     }
 }
@@ -0,0 +1,32 @@
+use std::net::Ipv4Addr;
+
+/// A change in the membership of the cluster, as seen by a gossiper.
+///
+/// Subscribers get these events through the channel returned by
+/// [`Gossiper::subscribe`](crate::Gossiper::subscribe).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipEvent {
+    /// The gossiper learned the state of an endpoint for the first time.
+    Joined(Ipv4Addr),
+    /// An endpoint that was dead is alive again.
+    Alive(Ipv4Addr),
+    /// An endpoint was marked as dead.
+    Dead(Ipv4Addr),
+    /// An endpoint started again with a new generation.
+    Restarted(Ipv4Addr),
+    /// An endpoint was removed from the cluster, and is forgotten once its removal expires.
+    Removed(Ipv4Addr),
+}
+
+impl MembershipEvent {
+    /// Returns the ip of the endpoint the event is about.
+    pub fn ip(&self) -> Ipv4Addr {
+        match self {
+            MembershipEvent::Joined(ip)
+            | MembershipEvent::Alive(ip)
+            | MembershipEvent::Dead(ip)
+            | MembershipEvent::Restarted(ip)
+            | MembershipEvent::Removed(ip) => *ip,
+        }
+    }
+}
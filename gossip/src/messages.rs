@@ -4,7 +4,12 @@ use std::{
     net::Ipv4Addr,
 };
 
-use crate::structures::{application_state::ApplicationState, heartbeat_state::HeartbeatState};
+use crate::encoding::{
+    read_byte, read_count, read_string, read_varint, read_varint_u32, write_string, write_varint,
+};
+use crate::structures::{
+    application_state::ApplicationState, gossip_state::GossipState, heartbeat_state::HeartbeatState,
+};
 
 #[derive(Debug)]
 /// Errors that can occur when creating a message.
@@ -18,6 +23,9 @@ pub enum MessageError {
     CursorError,
 }
 
+/// Version of the binary encoding of the gossip messages, sent as the first byte of every message.
+pub const GOSSIP_PROTOCOL_VERSION: u8 = 1;
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Copy)]
 /// A `Digest` used to identify a node in the cluster.
 ///
@@ -31,13 +39,15 @@ pub struct Digest {
     pub version: u32,
 }
 
+/// Digests are ordered by generation and version. Digests of different endpoints with the same
+/// heartbeat (e.g. nodes started in the same millisecond) are told apart by their address, so they
+/// are not merged when used as keys of a map.
 impl Ord for Digest {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.generation == other.generation {
-            self.version.cmp(&other.version)
-        } else {
-            self.generation.cmp(&other.generation)
-        }
+        self.generation
+            .cmp(&other.generation)
+            .then(self.version.cmp(&other.version))
+            .then(self.address.cmp(&other.address))
     }
 }
 
@@ -104,27 +114,20 @@ impl Digest {
     /// +----+----+----+----+
     /// |    ip address     |
     /// +----+----+----+----+
-    /// |     generation    |
-    /// +----+----+----+----+
-    /// |     generation    |
-    /// +----+----+----+----+
-    /// |     generation    |
-    /// +----+----+----+----+
-    /// |     generation    |
+    /// | generation (var)  |
+    /// |        ...        |
     /// +----+----+----+----+
-    /// |      version      |
+    /// |  version (var)    |
+    /// |        ...        |
     /// +----+----+----+----+
     /// ```
-    /// Convert the `Digest` message to a byte slice.
+    /// Convert the `Digest` message to a byte slice. The generation and the version are variable
+    /// length integers (see [`crate::encoding`]).
     pub fn as_bytes(&self) -> Vec<u8> {
-        let ip_bytes = self.address.octets();
-        let gen_bytes = self.generation.to_be_bytes();
-        let ver_bytes = self.version.to_be_bytes();
-
         let mut bytes = Vec::new();
-        bytes.extend_from_slice(&ip_bytes);
-        bytes.extend_from_slice(&gen_bytes);
-        bytes.extend_from_slice(&ver_bytes);
+        bytes.extend_from_slice(&self.address.octets());
+        write_varint(&mut bytes, self.generation);
+        write_varint(&mut bytes, self.version as u128);
 
         bytes
     }
@@ -138,22 +141,8 @@ impl Digest {
             .map_err(|_| MessageError::CursorError)?;
 
         let address = Ipv4Addr::from(address_bytes);
-
-        let mut generation_bytes = [0u8; 16];
-
-        cursor
-            .read_exact(&mut generation_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let generation = u128::from_be_bytes(generation_bytes);
-
-        let mut version_bytes = [0u8; 4];
-
-        cursor
-            .read_exact(&mut version_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let version = u32::from_be_bytes(version_bytes);
+        let generation = read_varint(cursor)?;
+        let version = read_varint_u32(cursor)?;
 
         Ok(Digest {
             address,
@@ -169,14 +158,14 @@ impl Digest {
 /// ### Fields
 /// - `from`: The IP address of the sender.
 /// - `payload`: The payload of the message.
-pub struct GossipMessage {
+pub struct GossipMessage<S: GossipState = ApplicationState> {
     pub from: Ipv4Addr,
-    pub payload: Payload,
+    pub payload: Payload<S>,
 }
 
-impl GossipMessage {
+impl<S: GossipState> GossipMessage<S> {
     /// Create a new `GossipMessage`.
-    pub fn new(from: Ipv4Addr, payload: Payload) -> Self {
+    pub fn new(from: Ipv4Addr, payload: Payload<S>) -> Self {
         GossipMessage { from, payload }
     }
 }
@@ -187,10 +176,14 @@ impl GossipMessage {
 /// - `Syn`: A `Syn` message.
 /// - `Ack`: An `Ack` message.
 /// - `Ack2`: An `Ack2` message.
+/// - `Pull`: A `Pull` message.
+/// - `Push`: A `Push` message.
 pub enum PayloadType {
     Syn = 0x00,
     Ack = 0x01,
     Ack2 = 0x02,
+    Pull = 0x03,
+    Push = 0x04,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -198,27 +191,32 @@ pub enum PayloadType {
 /// - `Syn`: A `Syn` message.
 /// - `Ack`: An `Ack` message.
 /// - `Ack2`: An `Ack2` message.
-pub enum Payload {
+/// - `Pull`: A `Pull` message.
+/// - `Push`: A `Push` message.
+pub enum Payload<S: GossipState = ApplicationState> {
     Syn(Syn),
-    Ack(Ack),
-    Ack2(Ack2),
+    Ack(Ack<S>),
+    Ack2(Ack2<S>),
+    Pull(Pull),
+    Push(Push),
 }
 
-impl GossipMessage {
+impl<S: GossipState> GossipMessage<S> {
     /// ```md
     /// 0    8    16   24   32
     /// +----+----+----+----+
-    /// |         ip        |
+    /// |ver |      ip
     /// +----+----+----+----+
-    /// |type|   payload    |
+    /// |    |type| payload |
     /// +----+----+----+----+
     /// |      payload      |
     /// |        ...        |
     /// +----+----+----+----+
     /// ```
-    /// Convert the `GossipMessage` to a byte array.
+    /// Convert the `GossipMessage` to a byte array. The first byte is the version of the encoding
+    /// ([`GOSSIP_PROTOCOL_VERSION`]), so nodes can tell messages they do not understand.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = vec![GOSSIP_PROTOCOL_VERSION];
 
         bytes.extend_from_slice(&self.from.to_bits().to_be_bytes());
 
@@ -226,14 +224,18 @@ impl GossipMessage {
             Payload::Syn(_) => PayloadType::Syn as u8,
             Payload::Ack(_) => PayloadType::Ack as u8,
             Payload::Ack2(_) => PayloadType::Ack2 as u8,
+            Payload::Pull(_) => PayloadType::Pull as u8,
+            Payload::Push(_) => PayloadType::Push as u8,
         };
 
-        bytes.extend_from_slice(&payload_type.to_be_bytes());
+        bytes.push(payload_type);
 
         let payload_bytes = match &self.payload {
             Payload::Syn(syn) => syn.as_bytes(),
             Payload::Ack(ack) => ack.as_bytes(),
             Payload::Ack2(ack2) => ack2.as_bytes(),
+            Payload::Pull(pull) => pull.as_bytes(),
+            Payload::Push(push) => push.as_bytes(),
         };
 
         bytes.extend_from_slice(&payload_bytes);
@@ -242,36 +244,48 @@ impl GossipMessage {
     }
 
     /// Create a `GossipMessage` from a byte slice.
+    ///
+    /// Fails, without panicking, if the message was written with another version of the encoding
+    /// or if it is malformed.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
         let mut cursor = Cursor::new(bytes);
 
+        let version = read_byte(&mut cursor)?;
+        if version != GOSSIP_PROTOCOL_VERSION {
+            return Err(MessageError::InvalidValue(format!(
+                "Unsupported gossip protocol version: {}",
+                version
+            )));
+        }
+
         let mut bytes_ip = [0u8; 4];
         cursor
             .read_exact(&mut bytes_ip)
             .map_err(|_| MessageError::CursorError)?;
-        let mut bytes_type = [0u8; 1];
-        cursor
-            .read_exact(&mut bytes_type)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let mut bytes_payload = Vec::new();
-        cursor
-            .read_to_end(&mut bytes_payload)
-            .map_err(|_| MessageError::CursorError)?;
-
         let ip = Ipv4Addr::from_bits(u32::from_be_bytes(bytes_ip));
 
-        let payload_type = match u8::from_be_bytes(bytes_type) {
+        let payload_type = match read_byte(&mut cursor)? {
             0x00 => PayloadType::Syn,
             0x01 => PayloadType::Ack,
             0x02 => PayloadType::Ack2,
-            _ => panic!(),
+            0x03 => PayloadType::Pull,
+            0x04 => PayloadType::Push,
+            other => {
+                return Err(MessageError::InvalidValue(format!(
+                    "Invalid payload type: {}",
+                    other
+                )))
+            }
         };
 
+        let bytes_payload = &bytes[cursor.position() as usize..];
+
         let payload = match payload_type {
-            PayloadType::Syn => Payload::Syn(Syn::from_bytes(&bytes_payload)?),
-            PayloadType::Ack => Payload::Ack(Ack::from_bytes(&bytes_payload)?),
-            PayloadType::Ack2 => Payload::Ack2(Ack2::from_bytes(&bytes_payload)?),
+            PayloadType::Syn => Payload::Syn(Syn::from_bytes(bytes_payload)?),
+            PayloadType::Ack => Payload::Ack(Ack::from_bytes(bytes_payload)?),
+            PayloadType::Ack2 => Payload::Ack2(Ack2::from_bytes(bytes_payload)?),
+            PayloadType::Pull => Payload::Pull(Pull::from_bytes(bytes_payload)?),
+            PayloadType::Push => Payload::Push(Push::from_bytes(bytes_payload)?),
         };
 
         Ok(Self { from: ip, payload })
@@ -294,19 +308,19 @@ impl Syn {
     }
 
     /// ```md
-    /// 0    8    16   24
-    /// +----+----+----+
-    /// |    digest    |
-    /// |      ...     |
-    /// +----+----+----+
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// | digest count (var)|
+    /// +----+----+----+----+
+    /// |      digest       |
+    /// |        ...        |
+    /// +----+----+----+----+
     /// ```
     /// Convert the `Syn` message to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let digest_len = self.digests.len() as u32;
-
-        bytes.extend_from_slice(&digest_len.to_be_bytes());
+        write_varint(&mut bytes, self.digests.len() as u128);
 
         for digest in &self.digests {
             bytes.extend_from_slice(&digest.as_bytes());
@@ -319,57 +333,34 @@ impl Syn {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
         let mut cursor = Cursor::new(bytes);
 
-        let mut digest_len_bytes = [0u8; 4];
-
-        cursor
-            .read_exact(&mut digest_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let digest_len = u32::from_be_bytes(digest_len_bytes);
+        let digest_len = read_count(&mut cursor)?;
 
         let mut digests = Vec::new();
 
         for _ in 0..digest_len {
-            let digest = Digest::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
-
-            digests.push(digest);
+            digests.push(Digest::from_bytes(&mut cursor)?);
         }
 
         Ok(Syn { digests })
     }
 }
 
-/// The type of information in an `Ack` message.
-/// - `Digest`: Only a digest.
-/// - `DigestAndInfo`: Digest with info.
-enum InfoType {
-    /// Only a digest, e.g.
-    /// `127.0.0.1:100:15`
-    Digest = 0x00,
-    /// Digest with info: e.g.
-    /// `127.0.0.2:100:15 LOAD:55`
-    DigestAndInfo = 0x01,
-}
-
 #[derive(Debug, PartialEq, Clone)]
 /// An `Ack` message used to acknowledge a `Syn` message.
 ///
 /// ### Fields
 /// - `stale_digests`: Local outdated digests which application state need to be updated in the `Ack2`.
 /// - `updated_info`: Local updated digests with application state which where outdated in the `Syn`.
-pub struct Ack {
+pub struct Ack<S: GossipState = ApplicationState> {
     /// Local outdated digests which application state need to be updated in the ACK2.
     pub stale_digests: Vec<Digest>,
     /// Local updated digests with application state which where outdated in the SYN.
-    pub updated_info: BTreeMap<Digest, ApplicationState>,
+    pub updated_info: BTreeMap<Digest, S>,
 }
 
-impl Ack {
+impl<S: GossipState> Ack<S> {
     /// Create a new `Ack` message.
-    pub fn new(
-        stale_digests: Vec<Digest>,
-        updated_info: BTreeMap<Digest, ApplicationState>,
-    ) -> Self {
+    pub fn new(stale_digests: Vec<Digest>, updated_info: BTreeMap<Digest, S>) -> Self {
         Ack {
             stale_digests,
             updated_info,
@@ -379,56 +370,33 @@ impl Ack {
     /// ```md
     /// 0    8    16   24   32
     /// +----+----+----+----+
-    /// |        0x00       |
+    /// | stale count (var) |
     /// +----+----+----+----+
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +       digest      +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
+    /// | info count (var)  |
     /// +----+----+----+----+
-    /// |        0x01       |
+    /// |   stale digest    |
+    /// |        ...        |
     /// +----+----+----+----+
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +       digest      +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
+    /// |   info digest     |
+    /// |        ...        |
     /// +----+----+----+----+
     /// | application state |
+    /// |        ...        |
     /// +----+----+----+----+
     /// ```
-    /// Convert the `Ack` message to a byte array.
+    /// Convert the `Ack` message to a byte array. The stale digests go first, followed by the
+    /// updated digests, each one with its application state.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let stale_len = self.stale_digests.len() as u32;
-
-        bytes.extend_from_slice(&stale_len.to_be_bytes());
-
-        let info_len = self.updated_info.len() as u32;
-
-        bytes.extend_from_slice(&info_len.to_be_bytes());
+        write_varint(&mut bytes, self.stale_digests.len() as u128);
+        write_varint(&mut bytes, self.updated_info.len() as u128);
 
         for digest in &self.stale_digests {
-            bytes.extend_from_slice(&(InfoType::Digest as u32).to_be_bytes());
             bytes.extend_from_slice(&digest.as_bytes());
         }
 
         for (digest, info) in &self.updated_info {
-            bytes.extend_from_slice(&(InfoType::DigestAndInfo as u32).to_be_bytes());
             bytes.extend_from_slice(&digest.as_bytes());
             bytes.extend_from_slice(&info.as_bytes());
         }
@@ -444,60 +412,16 @@ impl Ack {
 
         let mut cursor = Cursor::new(bytes);
 
-        let mut stale_len_bytes = [0u8; 4];
-
-        cursor
-            .read_exact(&mut stale_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let stale_len = u32::from_be_bytes(stale_len_bytes);
-
-        let mut info_len_bytes = [0u8; 4];
-
-        cursor
-            .read_exact(&mut info_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let info_len = u32::from_be_bytes(info_len_bytes);
+        let stale_len = read_count(&mut cursor)?;
+        let info_len = read_count(&mut cursor)?;
 
         for _ in 0..stale_len {
-            let mut info_type_bytes = [0u8; 4];
-            cursor
-                .read_exact(&mut info_type_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-
-            let info_type = u32::from_be_bytes(info_type_bytes);
-
-            if info_type != InfoType::Digest as u32 {
-                return Err(MessageError::InvalidValue(format!(
-                    "Invalid InfoType value: {}",
-                    info_type
-                )));
-            }
-
-            let digest = Digest::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
-
-            stale_digests.push(digest);
+            stale_digests.push(Digest::from_bytes(&mut cursor)?);
         }
 
         for _ in 0..info_len {
-            let mut info_type_bytes = [0u8; 4];
-            cursor
-                .read_exact(&mut info_type_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-
-            let info_type = u32::from_be_bytes(info_type_bytes);
-
-            if info_type != InfoType::DigestAndInfo as u32 {
-                return Err(MessageError::InvalidValue(format!(
-                    "Invalid InfoType value: {}",
-                    info_type
-                )));
-            }
-
-            let digest = Digest::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
-            let info =
-                ApplicationState::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
+            let digest = Digest::from_bytes(&mut cursor)?;
+            let info = S::from_bytes(&mut cursor)?;
 
             updated_info.insert(digest, info);
         }
@@ -513,43 +437,33 @@ impl Ack {
 /// An `Ack2` message used to acknowledge an `Ack` message.
 /// ### Fields
 /// - `updated_info`: Local updated digests with application state which were outdated in the `Syn`.
-pub struct Ack2 {
-    pub updated_info: BTreeMap<Digest, ApplicationState>,
+pub struct Ack2<S: GossipState = ApplicationState> {
+    pub updated_info: BTreeMap<Digest, S>,
 }
 
-impl Ack2 {
+impl<S: GossipState> Ack2<S> {
     /// Create a new `Ack2` message.
-    pub fn new(updated_info: BTreeMap<Digest, ApplicationState>) -> Self {
+    pub fn new(updated_info: BTreeMap<Digest, S>) -> Self {
         Ack2 { updated_info }
     }
 
     /// ```md
     /// 0    8    16   24   32
     /// +----+----+----+----+
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +       digest      +
-    /// |                   |
-    /// +                   +
-    /// |                   |
-    /// +                   +
-    /// |                   |
+    /// | info count (var)  |
     /// +----+----+----+----+
-    /// |                   |
-    /// + application state +
-    /// |                   |
+    /// |      digest       |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// | application state |
+    /// |        ...        |
     /// +----+----+----+----+
     /// ```
     /// Convert the `Ack2` message to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let info_len = self.updated_info.len() as u32;
-
-        bytes.extend_from_slice(&info_len.to_be_bytes());
+        write_varint(&mut bytes, self.updated_info.len() as u128);
 
         for (digest, info) in &self.updated_info {
             bytes.extend_from_slice(&digest.as_bytes());
@@ -563,19 +477,13 @@ impl Ack2 {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
         let mut cursor = Cursor::new(bytes);
 
-        let mut info_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut info_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let digest_len = u32::from_be_bytes(info_len_bytes);
+        let info_len = read_count(&mut cursor)?;
 
         let mut updated_info = BTreeMap::new();
 
-        for _ in 0..digest_len {
-            let digest = Digest::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
-            let app_state =
-                ApplicationState::from_bytes(&mut cursor).map_err(|_| MessageError::CursorError)?;
+        for _ in 0..info_len {
+            let digest = Digest::from_bytes(&mut cursor)?;
+            let app_state = S::from_bytes(&mut cursor)?;
 
             updated_info.insert(digest, app_state);
         }
@@ -584,6 +492,135 @@ impl Ack2 {
     }
 }
 
+#[derive(PartialEq, Debug, Clone)]
+/// A `Pull` message, sent to ask a peer for the parts of the application states that were
+/// gossiped by digest only (see [`GossipState::missing_parts`]).
+///
+/// ### Fields
+/// - `parts`: The name and digest of each part asked for.
+pub struct Pull {
+    pub parts: Vec<(String, u64)>,
+}
+
+impl Pull {
+    /// Create a new `Pull` message.
+    pub fn new(parts: Vec<(String, u64)>) -> Self {
+        Pull { parts }
+    }
+
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// | part count (var)  |
+    /// +----+----+----+----+
+    /// |     part name     |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |                   |
+    /// +    part digest    +
+    /// |                   |
+    /// +----+----+----+----+
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
+    /// Convert the `Pull` message to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        write_varint(&mut bytes, self.parts.len() as u128);
+
+        for (name, digest) in &self.parts {
+            write_string(&mut bytes, name);
+            bytes.extend_from_slice(&digest.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Create a `Pull` message from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let parts_len = read_count(&mut cursor)?;
+
+        let mut parts = Vec::new();
+
+        for _ in 0..parts_len {
+            let name = read_string(&mut cursor)?;
+            let mut digest_bytes = [0u8; 8];
+            cursor
+                .read_exact(&mut digest_bytes)
+                .map_err(|_| MessageError::CursorError)?;
+            parts.push((name, u64::from_be_bytes(digest_bytes)));
+        }
+
+        Ok(Pull { parts })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+/// A `Push` message, the answer to a `Pull` with the parts the peer had.
+///
+/// ### Fields
+/// - `parts`: The bytes of each part, as written by [`GossipState::part_bytes`].
+pub struct Push {
+    pub parts: Vec<Vec<u8>>,
+}
+
+impl Push {
+    /// Create a new `Push` message.
+    pub fn new(parts: Vec<Vec<u8>>) -> Self {
+        Push { parts }
+    }
+
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// | part count (var)  |
+    /// +----+----+----+----+
+    /// | part length (var) |
+    /// +----+----+----+----+
+    /// |       part        |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
+    /// Convert the `Push` message to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        write_varint(&mut bytes, self.parts.len() as u128);
+
+        for part in &self.parts {
+            write_varint(&mut bytes, part.len() as u128);
+            bytes.extend_from_slice(part);
+        }
+
+        bytes
+    }
+
+    /// Create a `Push` message from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let parts_len = read_count(&mut cursor)?;
+
+        let mut parts = Vec::new();
+
+        for _ in 0..parts_len {
+            let part_len = read_count(&mut cursor)?;
+            let mut part = vec![0u8; part_len];
+            cursor
+                .read_exact(&mut part)
+                .map_err(|_| MessageError::CursorError)?;
+            parts.push(part);
+        }
+
+        Ok(Push { parts })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -601,6 +638,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn digests_with_the_same_heartbeat_are_not_merged() {
+        let digest = |ip: &str| Digest {
+            address: Ipv4Addr::from_str(ip).unwrap(),
+            generation: 1,
+            version: 2,
+        };
+
+        let mut updated_info = BTreeMap::new();
+        updated_info.insert(digest("127.0.0.1"), "first");
+        updated_info.insert(digest("127.0.0.2"), "second");
+
+        assert_eq!(updated_info.len(), 2);
+        assert_eq!(updated_info[&digest("127.0.0.2")], "second");
+    }
+
     #[test]
     fn digest_as_bytes_ok() {
         let digest = Digest {
@@ -614,8 +667,8 @@ mod tests {
         let mut bytes = Vec::new();
 
         bytes.extend_from_slice(digest.address.octets().as_ref());
-        bytes.extend_from_slice(&digest.generation.to_be_bytes());
-        bytes.extend_from_slice(&digest.version.to_be_bytes());
+        write_varint(&mut bytes, digest.generation);
+        write_varint(&mut bytes, digest.version as u128);
 
         assert_eq!(digest_bytes, bytes)
     }
@@ -646,11 +699,7 @@ mod tests {
 
         let syn_bytes = syn.as_bytes();
 
-        let mut bytes = Vec::new();
-
-        let digest_len = 3u32.to_be_bytes();
-
-        bytes.extend_from_slice(&digest_len);
+        let mut bytes = vec![3u8];
 
         for digest in vec![node1, node2, node3] {
             bytes.extend_from_slice(&digest.as_bytes());
@@ -684,8 +733,11 @@ mod tests {
             version: 0xffffffff,
             schema: Schema {
                 timestamp: 0,
+                missing_keyspaces: HashMap::new(),
                 keyspaces: HashMap::new(),
             },
+            token: None,
+            ..Default::default()
         };
 
         let mut updated_info = BTreeMap::new();
@@ -698,21 +750,13 @@ mod tests {
 
         let ack_bytes = ack.as_bytes();
 
-        let mut bytes = Vec::new();
-
-        let stale_len = 2u32.to_be_bytes();
-        bytes.extend_from_slice(&stale_len);
-
-        let info_len = 1u32.to_be_bytes();
-        bytes.extend_from_slice(&info_len);
+        let mut bytes = vec![2u8, 1u8];
 
         for digest in ack.stale_digests {
-            bytes.extend_from_slice(&(InfoType::Digest as u32).to_be_bytes());
             bytes.extend_from_slice(&digest.as_bytes());
         }
 
         for (digest, info) in ack.updated_info {
-            bytes.extend_from_slice(&(InfoType::DigestAndInfo as u32).to_be_bytes());
             bytes.extend_from_slice(&digest.as_bytes());
             bytes.extend_from_slice(&info.as_bytes());
         }
@@ -733,6 +777,7 @@ mod tests {
             version: 0x1,
             schema: Schema {
                 timestamp: 10,
+                missing_keyspaces: HashMap::new(),
                 keyspaces: HashMap::from([(
                     "keyspace".to_string(),
                     KeyspaceSchema::new(
@@ -741,6 +786,7 @@ mod tests {
                             if_not_exists_clause: false,
                             replication_class: String::new(),
                             replication_factor: 1,
+                            datacenters: Default::default(),
                         },
                         vec![TableSchema::new(CreateTable {
                             name: "table1".to_string(),
@@ -756,10 +802,13 @@ mod tests {
                                 clustering_order: String::new(),
                             }],
                             clustering_columns_in_order: vec![],
+                            options: Default::default(),
                         })],
                     ),
                 )]),
             },
+            token: None,
+            ..Default::default()
         };
 
         let node2 = Digest {
@@ -773,6 +822,7 @@ mod tests {
             version: 0x1,
             schema: Schema {
                 timestamp: 10,
+                missing_keyspaces: HashMap::new(),
                 keyspaces: HashMap::from([(
                     "keyspace".to_string(),
                     KeyspaceSchema::new(
@@ -781,6 +831,7 @@ mod tests {
                             if_not_exists_clause: false,
                             replication_class: String::new(),
                             replication_factor: 1,
+                            datacenters: Default::default(),
                         },
                         vec![TableSchema::new(CreateTable {
                             name: "table1".to_string(),
@@ -796,10 +847,13 @@ mod tests {
                                 clustering_order: String::new(),
                             }],
                             clustering_columns_in_order: vec![],
+                            options: Default::default(),
                         })],
                     ),
                 )]),
             },
+            token: None,
+            ..Default::default()
         };
 
         let mut updated_info = BTreeMap::new();
@@ -885,6 +939,8 @@ mod tests {
             status: NodeStatus::Normal,
             version: 0x1,
             schema: Schema::default(),
+            token: None,
+            ..Default::default()
         };
 
         let mut updated_info = BTreeMap::new();
@@ -914,6 +970,8 @@ mod tests {
             status: NodeStatus::Normal,
             version: 1,
             schema: Schema::default(),
+            token: None,
+            ..Default::default()
         };
 
         let node2 = Digest {
@@ -926,6 +984,8 @@ mod tests {
             status: NodeStatus::Normal,
             version: 2,
             schema: Schema::default(),
+            token: None,
+            ..Default::default()
         };
 
         let mut updated_info = BTreeMap::new();
@@ -940,4 +1000,146 @@ mod tests {
 
         assert_eq!(ack2, expected_ack2);
     }
+
+    fn gossip_message_with_schema() -> GossipMessage {
+        let state = ApplicationState {
+            status: NodeStatus::Normal,
+            version: 3,
+            schema: Schema {
+                timestamp: 1733000000000,
+                missing_keyspaces: HashMap::new(),
+                keyspaces: HashMap::from([(
+                    "keyspace".to_string(),
+                    KeyspaceSchema::new(
+                        CreateKeyspace {
+                            name: "keyspace".to_string(),
+                            if_not_exists_clause: false,
+                            replication_class: "SimpleStrategy".to_string(),
+                            replication_factor: 3,
+                            datacenters: Default::default(),
+                        },
+                        vec![TableSchema::new(CreateTable {
+                            name: "table1".to_string(),
+                            keyspace_used_name: "keyspace".to_string(),
+                            if_not_exists_clause: false,
+                            columns: vec![Column {
+                                name: "column1".to_string(),
+                                data_type: DataType::Int,
+                                is_primary_key: true,
+                                allows_null: false,
+                                is_clustering_column: false,
+                                is_partition_key: true,
+                                clustering_order: String::new(),
+                            }],
+                            clustering_columns_in_order: vec![],
+                            options: Default::default(),
+                        })],
+                    ),
+                )]),
+            },
+            token: Some(42),
+            ..Default::default()
+        };
+
+        GossipMessage::new(
+            Ipv4Addr::new(127, 0, 0, 1),
+            Payload::Ack(Ack::new(
+                vec![Digest::new(Ipv4Addr::new(127, 0, 0, 2), 1733000000, 7)],
+                BTreeMap::from([(
+                    Digest::new(Ipv4Addr::new(127, 0, 0, 3), 1733000001, 9),
+                    state,
+                )]),
+            )),
+        )
+    }
+
+    #[test]
+    fn gossip_message_round_trip() {
+        let message = gossip_message_with_schema();
+
+        let mut parsed: GossipMessage = GossipMessage::from_bytes(&message.as_bytes()).unwrap();
+
+        // The keyspaces travel by digest only, and are completed from the schema already known
+        let (Payload::Ack(parsed_ack), Payload::Ack(ack)) = (&mut parsed.payload, &message.payload)
+        else {
+            panic!("the payload should be an Ack");
+        };
+        for (digest, state) in parsed_ack.updated_info.iter_mut() {
+            assert!(state.schema.keyspaces.is_empty());
+            assert_eq!(state.schema.missing_keyspaces.len(), 1);
+            state.complete_from(&ack.updated_info[digest]);
+        }
+
+        assert_eq!(parsed, message);
+    }
+
+    #[test]
+    fn pull_and_push_round_trip() {
+        let pull = GossipMessage::<ApplicationState>::new(
+            Ipv4Addr::new(127, 0, 0, 1),
+            Payload::Pull(Pull::new(vec![
+                ("keyspace".to_string(), 0xdeadbeef),
+                ("other".to_string(), u64::MAX),
+            ])),
+        );
+        let push = GossipMessage::<ApplicationState>::new(
+            Ipv4Addr::new(127, 0, 0, 2),
+            Payload::Push(Push::new(vec![vec![1, 2, 3], vec![]])),
+        );
+
+        assert_eq!(GossipMessage::from_bytes(&pull.as_bytes()).unwrap(), pull);
+        assert_eq!(GossipMessage::from_bytes(&push.as_bytes()).unwrap(), push);
+    }
+
+    #[test]
+    fn digest_with_small_values_is_compact() {
+        let digest = Digest::new(Ipv4Addr::new(127, 0, 0, 1), 1733000000, 12);
+
+        // 4 bytes of ip, 5 of generation and 1 of version
+        assert_eq!(digest.as_bytes().len(), 10);
+    }
+
+    #[test]
+    fn gossip_message_with_other_version_is_rejected() {
+        let mut bytes = gossip_message_with_schema().as_bytes();
+        bytes[0] = GOSSIP_PROTOCOL_VERSION + 1;
+
+        let result = GossipMessage::<ApplicationState>::from_bytes(&bytes);
+
+        assert!(matches!(result, Err(MessageError::InvalidValue(_))));
+    }
+
+    #[test]
+    fn gossip_message_with_invalid_payload_type_is_rejected() {
+        let mut bytes = gossip_message_with_schema().as_bytes();
+        bytes[5] = 0x07;
+
+        assert!(GossipMessage::<ApplicationState>::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_gossip_messages_are_rejected() {
+        let bytes = gossip_message_with_schema().as_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(
+                GossipMessage::<ApplicationState>::from_bytes(&bytes[..len]).is_err(),
+                "a message truncated to {} bytes was accepted",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_gossip_messages_do_not_panic() {
+        let bytes = gossip_message_with_schema().as_bytes();
+
+        for i in 0..bytes.len() {
+            for value in [0x00, 0x01, 0x7f, 0x80, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] = value;
+                let _ = GossipMessage::<ApplicationState>::from_bytes(&corrupted);
+            }
+        }
+    }
 }
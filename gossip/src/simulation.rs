@@ -0,0 +1,423 @@
+//! In-memory simulation of a cluster of gossipers, to test how fast the protocol converges.
+//!
+//! The simulator runs synchronous rounds: on every round each running gossiper sends its `Syn`
+//! and the whole exchange it starts is delivered before the next gossiper does the same. Messages
+//! can be lost with a configurable probability, and gossipers can be stopped to simulate a node
+//! going down.
+//!
+//! The failure detectors of the gossipers run on a simulated clock that advances
+//! `ROUND_MILLIS` on every round, so a stopped gossiper is suspected after the same number of
+//! rounds no matter how fast they run.
+//!
+//! ```
+//! use gossip::simulation::ClusterSimulator;
+//!
+//! let mut cluster: ClusterSimulator = ClusterSimulator::new(5).with_message_loss(0.2);
+//! let rounds = cluster.assert_converges_within(50);
+//! assert!(rounds <= 50);
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet, VecDeque},
+    net::Ipv4Addr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    failure_detector::FailureDetector,
+    messages::GossipMessage,
+    structures::{application_state::ApplicationState, gossip_state::GossipState},
+    transport::Transport,
+    GossipError, Gossiper,
+};
+
+/// Seed used by default for the random number generator that decides which messages are lost.
+const DEFAULT_SEED: u64 = 0x5EED;
+/// Simulated time between two rounds, in milliseconds.
+const ROUND_MILLIS: u64 = 1000;
+
+/// A cluster of gossipers that talk to each other through in-memory queues.
+///
+/// The gossipers get the ips `10.0.0.1`, `10.0.0.2`, ... and all of them start knowing only the
+/// first one, which acts as the seed of the cluster.
+pub struct ClusterSimulator<S: GossipState = ApplicationState> {
+    gossipers: BTreeMap<Ipv4Addr, Gossiper<S>>,
+    stopped: HashSet<Ipv4Addr>,
+    message_loss: f64,
+    rng: StdRng,
+    rounds: usize,
+    time: Arc<AtomicU64>,
+}
+
+/// Transport that queues the messages so the simulator can deliver them. Sending to a stopped
+/// gossiper fails, as a connection to a dead node would.
+struct QueueTransport<'a, S: GossipState> {
+    queue: RefCell<VecDeque<(Ipv4Addr, GossipMessage<S>)>>,
+    stopped: &'a HashSet<Ipv4Addr>,
+}
+
+impl<S: GossipState> Transport<S> for QueueTransport<'_, S> {
+    fn send(&self, to: Ipv4Addr, message: GossipMessage<S>) -> Result<(), GossipError> {
+        if self.stopped.contains(&to) {
+            return Err(GossipError::SendError);
+        }
+        self.queue.borrow_mut().push_back((to, message));
+        Ok(())
+    }
+}
+
+impl<S: GossipState> ClusterSimulator<S> {
+    /// Creates a cluster of `size` gossipers with no message loss.
+    pub fn new(size: usize) -> Self {
+        let ips: Vec<Ipv4Addr> = (1..=size as u32)
+            .map(|i| Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 0, 0, 0)) + i))
+            .collect();
+        let seeds: Vec<Ipv4Addr> = ips.iter().take(1).copied().collect();
+        let time = Arc::new(AtomicU64::new(0));
+
+        let gossipers = ips
+            .iter()
+            .map(|&ip| {
+                let seeds = seeds.iter().filter(|&&seed| seed != ip).copied().collect();
+                let clock_time = Arc::clone(&time);
+                let failure_detector = FailureDetector::new()
+                    .with_clock(Arc::new(move || clock_time.load(Ordering::SeqCst) as u128));
+                (
+                    ip,
+                    Gossiper::new()
+                        .with_endpoint_state(ip)
+                        .with_seeds(seeds)
+                        .with_failure_detector(failure_detector),
+                )
+            })
+            .collect();
+
+        ClusterSimulator {
+            gossipers,
+            stopped: HashSet::new(),
+            message_loss: 0.0,
+            rng: StdRng::seed_from_u64(DEFAULT_SEED),
+            rounds: 0,
+            time,
+        }
+    }
+
+    /// Sets the probability (between 0 and 1) of each message being lost.
+    pub fn with_message_loss(mut self, message_loss: f64) -> Self {
+        self.message_loss = message_loss.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the seed of the random number generator that decides which messages are lost, so a
+    /// run can be repeated.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Returns the ips of all the gossipers of the cluster, running or not.
+    pub fn ips(&self) -> Vec<Ipv4Addr> {
+        self.gossipers.keys().copied().collect()
+    }
+
+    /// Returns the gossiper with the given ip.
+    pub fn gossiper(&self, ip: Ipv4Addr) -> Option<&Gossiper<S>> {
+        self.gossipers.get(&ip)
+    }
+
+    /// Returns the gossiper with the given ip, to change its state between rounds.
+    pub fn gossiper_mut(&mut self, ip: Ipv4Addr) -> Option<&mut Gossiper<S>> {
+        self.gossipers.get_mut(&ip)
+    }
+
+    /// Stops the gossiper with the given ip: it does not gossip anymore and messages sent to it
+    /// fail.
+    pub fn stop(&mut self, ip: Ipv4Addr) {
+        self.stopped.insert(ip);
+    }
+
+    /// Starts again a stopped gossiper, with the state it had when it was stopped. Having missed
+    /// the heartbeats of the rounds it was stopped, it suspects every other gossiper on its first
+    /// round, as they suspected it.
+    pub fn resume(&mut self, ip: Ipv4Addr) {
+        self.stopped.remove(&ip);
+    }
+
+    /// Returns the number of rounds run so far.
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Runs a round of gossip on every running gossiper.
+    pub fn run_round(&mut self) {
+        let ips: Vec<Ipv4Addr> = self
+            .ips()
+            .into_iter()
+            .filter(|ip| !self.stopped.contains(ip))
+            .collect();
+
+        for ip in ips {
+            let transport = QueueTransport {
+                queue: RefCell::new(VecDeque::new()),
+                stopped: &self.stopped,
+            };
+
+            if let Some(gossiper) = self.gossipers.get_mut(&ip) {
+                let _ = gossiper.gossip_round(ip, &transport);
+            }
+
+            let next_message = || transport.queue.borrow_mut().pop_front();
+            while let Some((to, message)) = next_message() {
+                if self.rng.gen_bool(self.message_loss) {
+                    continue;
+                }
+                if let Some(gossiper) = self.gossipers.get_mut(&to) {
+                    let _ = gossiper.handle_message(to, &message, &transport);
+                }
+            }
+        }
+
+        self.rounds += 1;
+        self.time.fetch_add(ROUND_MILLIS, Ordering::SeqCst);
+    }
+
+    /// Whether every running gossiper knows every endpoint and agrees with the others on its
+    /// generation and application state. Heartbeat versions are not compared, since they keep
+    /// growing every round.
+    pub fn is_converged(&self) -> bool {
+        let mut views = self
+            .gossipers
+            .iter()
+            .filter(|(ip, _)| !self.stopped.contains(ip))
+            .map(|(_, gossiper)| gossiper);
+
+        let first = match views.next() {
+            Some(first) => first,
+            None => return true,
+        };
+
+        if first.endpoints_state.len() != self.gossipers.len() {
+            return false;
+        }
+
+        views.all(|view| {
+            view.endpoints_state.len() == first.endpoints_state.len()
+                && first.endpoints_state.iter().all(|(ip, state)| {
+                    view.endpoints_state.get(ip).is_some_and(|other| {
+                        other.heartbeat_state.generation == state.heartbeat_state.generation
+                            && other.application_state == state.application_state
+                    })
+                })
+        })
+    }
+
+    /// Runs rounds until `condition` holds for the cluster, returning the number of rounds it
+    /// took, or `None` if it still does not hold after `max_rounds`.
+    pub fn run_until<F: Fn(&Self) -> bool>(
+        &mut self,
+        max_rounds: usize,
+        condition: F,
+    ) -> Option<usize> {
+        let start = self.rounds;
+        while !condition(self) {
+            if self.rounds - start >= max_rounds {
+                return None;
+            }
+            self.run_round();
+        }
+        Some(self.rounds - start)
+    }
+
+    /// Runs rounds until the cluster converges, returning the number of rounds it took, or
+    /// `None` if it did not converge after `max_rounds`.
+    pub fn run_until_converged(&mut self, max_rounds: usize) -> Option<usize> {
+        self.run_until(max_rounds, Self::is_converged)
+    }
+
+    /// Runs rounds until the cluster converges and returns the number of rounds it took.
+    ///
+    /// # Panics
+    /// If the cluster does not converge after `max_rounds`.
+    pub fn assert_converges_within(&mut self, max_rounds: usize) -> usize {
+        match self.run_until_converged(max_rounds) {
+            Some(rounds) => rounds,
+            None => panic!(
+                "the cluster of {} gossipers did not converge after {} rounds with a message loss of {}",
+                self.gossipers.len(),
+                max_rounds,
+                self.message_loss
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::application_state::NodeStatus;
+    use query_creator::clauses::keyspace::create_keyspace_cql::CreateKeyspace;
+
+    #[test]
+    fn cluster_converges_without_loss() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(10);
+
+        assert!(!cluster.is_converged());
+        let rounds = cluster.assert_converges_within(30);
+
+        assert!(rounds > 0);
+        for ip in cluster.ips() {
+            assert_eq!(cluster.gossiper(ip).unwrap().live_endpoints().len(), 10);
+        }
+    }
+
+    #[test]
+    fn cluster_converges_with_message_loss() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(10)
+            .with_message_loss(0.3)
+            .with_seed(7);
+
+        let rounds = cluster.assert_converges_within(60);
+
+        assert!(rounds > 0);
+        assert_eq!(cluster.rounds(), rounds);
+    }
+
+    #[test]
+    fn cluster_does_not_converge_when_every_message_is_lost() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(3).with_message_loss(1.0);
+
+        assert_eq!(cluster.run_until_converged(20), None);
+        assert_eq!(cluster.rounds(), 20);
+    }
+
+    #[test]
+    fn stopped_gossiper_is_seen_as_dead() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(5);
+        cluster.assert_converges_within(30);
+
+        let stopped = cluster.ips()[3];
+        cluster.stop(stopped);
+
+        let all_see_it_dead = |cluster: &ClusterSimulator| {
+            cluster
+                .ips()
+                .into_iter()
+                .filter(|&ip| ip != stopped)
+                .all(|ip| {
+                    let gossiper = cluster.gossiper(ip).unwrap();
+                    gossiper.get_status(stopped).unwrap() == NodeStatus::Dead
+                })
+        };
+
+        assert!(cluster.run_until(50, all_see_it_dead).is_some());
+        assert!(cluster.is_converged());
+    }
+
+    #[test]
+    fn resumed_gossiper_is_seen_alive_again() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(5);
+        cluster.assert_converges_within(30);
+
+        let stopped = cluster.ips()[3];
+        cluster.stop(stopped);
+        cluster.run_until(50, |cluster| {
+            let gossiper = cluster.gossiper(cluster.ips()[0]).unwrap();
+            gossiper.get_status(stopped).unwrap() == NodeStatus::Dead
+        });
+
+        // The stopped gossiper comes back seeing every other one as dead, and they see it dead
+        cluster.resume(stopped);
+        let all_alive = |cluster: &ClusterSimulator| {
+            cluster
+                .ips()
+                .into_iter()
+                .all(|ip| cluster.gossiper(ip).unwrap().live_endpoints().len() == 5)
+        };
+        assert!(cluster.run_until(30, all_alive).is_some());
+        assert!(cluster.run_until_converged(30).is_some());
+    }
+
+    #[test]
+    fn removal_is_gossiped_to_every_gossiper() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(5);
+        cluster.assert_converges_within(30);
+
+        let [first, removed] = [cluster.ips()[0], cluster.ips()[3]];
+        cluster.stop(removed);
+        cluster.run_until(50, |cluster| {
+            let gossiper = cluster.gossiper(first).unwrap();
+            gossiper.get_status(removed).unwrap() == NodeStatus::Dead
+        });
+
+        cluster
+            .gossiper_mut(first)
+            .unwrap()
+            .remove_endpoint(removed)
+            .unwrap();
+        let all_see_it_removed = |cluster: &ClusterSimulator| {
+            cluster
+                .ips()
+                .into_iter()
+                .filter(|&ip| ip != removed)
+                .all(|ip| {
+                    let gossiper = cluster.gossiper(ip).unwrap();
+                    gossiper.get_status(removed).unwrap() == NodeStatus::Removed
+                })
+        };
+        assert!(cluster.run_until(30, all_see_it_removed).is_some());
+        assert!(cluster.run_until_converged(30).is_some());
+    }
+
+    #[test]
+    fn status_changes_are_gossiped() {
+        let mut cluster: ClusterSimulator = ClusterSimulator::new(4);
+        cluster.assert_converges_within(30);
+
+        let ip = cluster.ips()[2];
+        cluster
+            .gossiper_mut(ip)
+            .unwrap()
+            .change_status(ip, NodeStatus::Normal)
+            .unwrap();
+        cluster.assert_converges_within(30);
+
+        for other in cluster.ips() {
+            let gossiper = cluster.gossiper(other).unwrap();
+            assert_eq!(gossiper.get_status(ip).unwrap(), NodeStatus::Normal);
+        }
+    }
+
+    #[test]
+    fn schema_changes_are_pulled_by_every_gossiper() {
+        let mut cluster: ClusterSimulator =
+            ClusterSimulator::new(6).with_message_loss(0.2).with_seed(3);
+        cluster.assert_converges_within(40);
+
+        let ip = cluster.ips()[4];
+        cluster
+            .gossiper_mut(ip)
+            .unwrap()
+            .add_keyspace(
+                ip,
+                CreateKeyspace {
+                    name: "keyspace".to_string(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        cluster.assert_converges_within(40);
+
+        for other in cluster.ips() {
+            let gossiper = cluster.gossiper(other).unwrap();
+            assert!(gossiper.missing_parts().is_empty());
+            let schema = gossiper.get_most_updated_schema().unwrap();
+            assert!(schema.keyspaces.contains_key("keyspace"));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! File where a gossiper keeps the states of the endpoints it knows, so a restarted endpoint
+//! remembers the cluster and its own generation.
+//!
+//! The file holds the same encoding as the gossip messages (see [`crate::encoding`]): the number
+//! of endpoints, and for each one the digest of its heartbeat, its application state and the
+//! parts of the state that are gossiped by digest only, so a reloaded state does not have to be
+//! pulled again:
+//!
+//! ```md
+//! +----+----+----+----+
+//! | endpoints (var)   |
+//! +----+----+----+----+
+//! |      digest       |
+//! |        ...        |
+//! +----+----+----+----+
+//! | application state |
+//! |        ...        |
+//! +----+----+----+----+
+//! |   parts (var)     |
+//! +----+----+----+----+
+//! | part length (var) |
+//! +----+----+----+----+
+//! |       part        |
+//! |        ...        |
+//! +----+----+----+----+
+//! |        ...        |
+//! +----+----+----+----+
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Cursor},
+    net::Ipv4Addr,
+    path::Path,
+};
+
+use crate::{
+    encoding::{read_bytes, read_count, write_varint},
+    messages::{Digest, MessageError},
+    structures::{endpoint_state::EndpointState, gossip_state::GossipState},
+};
+
+/// Returns the states of the endpoints as they are written to the file.
+pub(crate) fn encode<S: GossipState>(
+    endpoints_state: &HashMap<Ipv4Addr, EndpointState<S>>,
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_varint(&mut bytes, endpoints_state.len() as u128);
+
+    for (ip, state) in endpoints_state {
+        let digest = Digest::from_heartbeat_state(*ip, &state.heartbeat_state);
+        bytes.extend_from_slice(&digest.as_bytes());
+        bytes.extend_from_slice(&state.application_state.as_bytes());
+
+        let parts: Vec<Vec<u8>> = state
+            .application_state
+            .parts()
+            .iter()
+            .filter_map(|(name, digest)| state.application_state.part_bytes(name, *digest))
+            .collect();
+        write_varint(&mut bytes, parts.len() as u128);
+        for part in parts {
+            write_varint(&mut bytes, part.len() as u128);
+            bytes.extend_from_slice(&part);
+        }
+    }
+    bytes
+}
+
+/// Writes states encoded by [`encode`] to the file. They are written under a temporary name first,
+/// so a crash never leaves half a file behind.
+pub(crate) fn write_bytes(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, bytes)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Reads the states of the endpoints written to the file by [`write`].
+pub(crate) fn read<S: GossipState>(
+    path: &Path,
+) -> Result<HashMap<Ipv4Addr, EndpointState<S>>, MessageError> {
+    let bytes = fs::read(path).map_err(|_| MessageError::CursorError)?;
+    let mut cursor = Cursor::new(bytes.as_slice());
+    let mut endpoints_state = HashMap::new();
+
+    for _ in 0..read_count(&mut cursor)? {
+        let digest = Digest::from_bytes(&mut cursor)?;
+        let mut application_state = S::from_bytes(&mut cursor)?;
+
+        for _ in 0..read_count(&mut cursor)? {
+            application_state.add_part(&read_bytes(&mut cursor)?)?;
+        }
+
+        endpoints_state.insert(
+            digest.address,
+            EndpointState::new(application_state, digest.get_heartbeat_state()),
+        );
+    }
+
+    Ok(endpoints_state)
+}
@@ -1,15 +1,37 @@
+use super::gossip_state::GossipState;
+use crate::encoding::{
+    read_byte, read_count, read_string, read_varint_u32, read_varint_u64, write_string,
+    write_varint,
+};
 use crate::messages::MessageError;
 use query_creator::clauses::{
     keyspace::create_keyspace_cql::CreateKeyspace,
     table::create_table_cql::CreateTable,
-    types::{column::Column, datatype::DataType},
+    types::{
+        column::Column,
+        datatype::DataType,
+        table_options::{TableOptions, DEFAULT_READ_REPAIR_CHANCE},
+    },
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Display},
     io::{Cursor, Read},
+    net::Ipv4Addr,
 };
 
+/// Offset basis of the 64 bit FNV-1a hash used for the keyspace digests and schema versions.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Prime of the 64 bit FNV-1a hash used for the keyspace digests and schema versions.
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Datacenter of the nodes that are not given one, as in Cassandra.
+pub const DEFAULT_DATACENTER: &str = "datacenter1";
+
+/// Rack of the nodes that are not given one, as in Cassandra.
+pub const DEFAULT_RACK: &str = "rack1";
+
 pub trait CursorSerializable {
     fn to_bytes(&self) -> Vec<u8>;
 
@@ -125,36 +147,72 @@ impl TableSchema {
     pub fn get_clustering_column_in_order(&self) -> Vec<String> {
         self.inner.get_clustering_column_in_order()
     }
+
+    /// Gets the clustering columns, in the order of the primary key, with the order
+    /// (`ASC` or `DESC`) their rows are sorted in.
+    pub fn get_clustering_key_columns(&self) -> Vec<Column> {
+        let columns = self.get_columns();
+        self.get_clustering_column_in_order()
+            .iter()
+            .filter_map(|name| columns.iter().find(|column| column.name == *name).cloned())
+            .collect()
+    }
+
+    /// Gets the options of the table, like its default TTL and comment.
+    pub fn get_options(&self) -> &TableOptions {
+        self.inner.get_options()
+    }
+
+    /// Describes the table as `DESCRIBE TABLE` does: the `CREATE TABLE` statement that creates it
+    /// again, with its keyspace, clustering order and options.
+    pub fn describe(&self) -> String {
+        let mut inner = self.inner.clone();
+        inner.if_not_exists_clause = false;
+        format!("{};", inner.serialize())
+    }
 }
 
+/// Flags of a `Column`, packed in a single byte.
+const COLUMN_IS_PRIMARY_KEY: u8 = 0b0001;
+const COLUMN_ALLOWS_NULL: u8 = 0b0010;
+const COLUMN_IS_CLUSTERING_COLUMN: u8 = 0b0100;
+const COLUMN_IS_PARTITION_KEY: u8 = 0b1000;
+
 impl CursorSerializable for Column {
+    /// ```md
+    /// +----+----+----+----+
+    /// |       name        |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |type|flgs|
+    /// +----+----+----+----+
+    /// | clustering_order  |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let name_len_bytes = (self.name.len() as u32).to_be_bytes();
-        bytes.extend_from_slice(&name_len_bytes);
-        let name_bytes = self.name.as_bytes();
-        bytes.extend_from_slice(name_bytes);
+        write_string(&mut bytes, &self.name);
 
-        let data_type_byte = self.data_type as u8;
-        bytes.push(data_type_byte);
+        bytes.push(self.data_type as u8);
 
-        let is_primary_key = self.is_primary_key as u8;
-        bytes.push(is_primary_key);
-
-        let allows_null = self.allows_null as u8;
-        bytes.push(allows_null);
-
-        let is_clustering_column = self.is_clustering_column as u8;
-        bytes.push(is_clustering_column);
-
-        let is_partition_key = self.is_partition_key as u8;
-        bytes.push(is_partition_key);
+        let mut flags = 0;
+        if self.is_primary_key {
+            flags |= COLUMN_IS_PRIMARY_KEY;
+        }
+        if self.allows_null {
+            flags |= COLUMN_ALLOWS_NULL;
+        }
+        if self.is_clustering_column {
+            flags |= COLUMN_IS_CLUSTERING_COLUMN;
+        }
+        if self.is_partition_key {
+            flags |= COLUMN_IS_PARTITION_KEY;
+        }
+        bytes.push(flags);
 
-        let clustering_order_len = self.clustering_order.len() as u32;
-        bytes.extend_from_slice(&clustering_order_len.to_be_bytes());
-        let clustering_order_bytes = self.clustering_order.as_bytes();
-        bytes.extend_from_slice(clustering_order_bytes);
+        write_string(&mut bytes, &self.clustering_order);
 
         bytes
     }
@@ -163,24 +221,10 @@ impl CursorSerializable for Column {
     where
         Self: Sized,
     {
-        let mut name_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut name_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name_len = u32::from_be_bytes(name_len_bytes);
-
-        let mut name_bytes = vec![0u8; name_len as usize];
-        cursor
-            .read_exact(&mut name_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name = String::from_utf8(name_bytes).map_err(|_| MessageError::CursorError)?;
-
-        let mut data_type_byte = [0u8; 1];
-        cursor
-            .read_exact(&mut data_type_byte)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let data_type = match data_type_byte[0] {
+        let name = read_string(cursor)?;
+
+        let data_type_byte = read_byte(cursor)?;
+        let data_type = match data_type_byte {
             0 => DataType::Int,
             1 => DataType::String,
             2 => DataType::Boolean,
@@ -188,100 +232,79 @@ impl CursorSerializable for Column {
             4 => DataType::Double,
             5 => DataType::Timestamp,
             6 => DataType::Uuid,
+            7 => DataType::Counter,
             _ => {
                 return Err(MessageError::InvalidValue(format!(
                     "Invalid DataType value: {}",
-                    data_type_byte[0]
+                    data_type_byte
                 )))
             }
         };
 
-        let mut is_primary_key_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut is_primary_key_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let is_primary_key = is_primary_key_bytes[0] == 1;
-
-        let mut allows_null_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut allows_null_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let allows_null = allows_null_bytes[0] == 1;
-
-        let mut is_clustering_column_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut is_clustering_column_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let is_clustering_column = is_clustering_column_bytes[0] == 1;
-
-        let mut is_partition_key_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut is_partition_key_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let is_partition_key = is_partition_key_bytes[0] == 1;
-
-        let mut clustering_order_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut clustering_order_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let clustering_order_len = u32::from_be_bytes(clustering_order_len_bytes);
-
-        let mut clustering_order_bytes = vec![0u8; clustering_order_len as usize];
-        cursor
-            .read_exact(&mut clustering_order_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let clustering_order =
-            String::from_utf8(clustering_order_bytes).map_err(|_| MessageError::CursorError)?;
+        let flags = read_byte(cursor)?;
+        let clustering_order = read_string(cursor)?;
 
         Ok(Column {
             name,
             data_type,
-            is_clustering_column,
-            is_partition_key,
-            is_primary_key,
-            allows_null,
+            is_clustering_column: flags & COLUMN_IS_CLUSTERING_COLUMN != 0,
+            is_partition_key: flags & COLUMN_IS_PARTITION_KEY != 0,
+            is_primary_key: flags & COLUMN_IS_PRIMARY_KEY != 0,
+            allows_null: flags & COLUMN_ALLOWS_NULL != 0,
             clustering_order,
         })
     }
 }
 
 impl CursorSerializable for CreateTable {
+    /// ```md
+    /// +----+----+----+----+
+    /// |       name        |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |     keyspace      |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |ine |  column count (var)
+    /// +----+----+----+----+
+    /// |      columns      |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// | clustering count  |
+    /// +----+----+----+----+
+    /// | clustering columns|
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |  default TTL (var)|
+    /// +----+----+----+----+
+    /// |      comment      |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let name_len_bytes = (self.name.len() as u32).to_be_bytes();
-        let name_bytes = self.name.as_bytes();
-        bytes.extend_from_slice(&name_len_bytes);
-        bytes.extend_from_slice(name_bytes);
+        write_string(&mut bytes, &self.name);
+        write_string(&mut bytes, &self.keyspace_used_name);
 
-        let keyspace_len_bytes = (self.keyspace_used_name.len() as u32).to_be_bytes();
-        let keyspace_bytes = self.keyspace_used_name.as_bytes();
-        bytes.extend_from_slice(&keyspace_len_bytes);
-        bytes.extend_from_slice(keyspace_bytes);
-
-        let if_not_exists = self.if_not_exists_clause as u8;
-        bytes.push(if_not_exists);
-
-        let columns_len = self.columns.len() as u32;
-        bytes.extend_from_slice(&columns_len.to_be_bytes());
-        let mut columns_bytes = Vec::new();
+        bytes.push(self.if_not_exists_clause as u8);
 
+        write_varint(&mut bytes, self.columns.len() as u128);
         for column in &self.columns {
-            columns_bytes.extend_from_slice(&column.to_bytes());
+            bytes.extend_from_slice(&column.to_bytes());
         }
 
-        bytes.extend_from_slice(&columns_bytes);
-
-        let clustering_columns_len = self.clustering_columns_in_order.len() as u32;
-        bytes.extend_from_slice(&clustering_columns_len.to_be_bytes());
-        let mut clustering_columns_bytes = Vec::new();
-
+        write_varint(&mut bytes, self.clustering_columns_in_order.len() as u128);
         for column in &self.clustering_columns_in_order {
-            clustering_columns_bytes.extend_from_slice(&(column.len() as u32).to_be_bytes());
-            clustering_columns_bytes.extend_from_slice(column.as_bytes());
+            write_string(&mut bytes, column);
         }
 
-        bytes.extend_from_slice(&clustering_columns_bytes);
+        write_varint(&mut bytes, self.options.default_time_to_live as u128);
+        write_string(&mut bytes, &self.options.comment);
+        write_varint(
+            &mut bytes,
+            self.options.read_repair_chance.to_bits() as u128,
+        );
 
         bytes
     }
@@ -290,83 +313,43 @@ impl CursorSerializable for CreateTable {
     where
         Self: Sized,
     {
-        let mut name_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut name_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name_len = u32::from_be_bytes(name_len_bytes);
-
-        let mut name_bytes = vec![0u8; name_len as usize];
-        cursor
-            .read_exact(&mut name_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name = String::from_utf8(name_bytes).map_err(|_| MessageError::CursorError)?;
-
-        let mut keyspace_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut keyspace_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-
-        let keyspace_len = u32::from_be_bytes(keyspace_len_bytes);
-
-        let mut keyspace_bytes = vec![0u8; keyspace_len as usize];
-        cursor
-            .read_exact(&mut keyspace_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let keyspace = String::from_utf8(keyspace_bytes).map_err(|_| MessageError::CursorError)?;
-
-        let mut if_not_exists_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut if_not_exists_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let if_not_exists = if_not_exists_bytes[0] == 1;
-
-        let mut columns_len_bytes = [0u8; 4];
-
-        cursor
-            .read_exact(&mut columns_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let columns_len = u32::from_be_bytes(columns_len_bytes);
+        let name = read_string(cursor)?;
+        let keyspace = read_string(cursor)?;
+        let if_not_exists = read_byte(cursor)? == 1;
 
+        let columns_len = read_count(cursor)?;
         let mut columns = Vec::new();
-
         for _ in 0..columns_len {
-            let column = Column::from_bytes(cursor).map_err(|_| MessageError::CursorError)?;
-            columns.push(column);
+            columns.push(Column::from_bytes(cursor)?);
         }
 
-        let mut clustering_columns_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut clustering_columns_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let clustering_columns_len = u32::from_be_bytes(clustering_columns_len_bytes);
-
+        let clustering_columns_len = read_count(cursor)?;
         let mut clustering_columns = Vec::new();
-
         for _ in 0..clustering_columns_len {
-            let mut column_len_bytes = [0u8; 4];
-            cursor
-                .read_exact(&mut column_len_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-            let column_len = u32::from_be_bytes(column_len_bytes);
-
-            let mut column_bytes = vec![0u8; column_len as usize];
-
-            cursor
-                .read_exact(&mut column_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-
-            let column = String::from_utf8(column_bytes).map_err(|_| MessageError::CursorError)?;
-
-            clustering_columns.push(column);
+            clustering_columns.push(read_string(cursor)?);
         }
 
+        let default_time_to_live = read_varint_u64(cursor)?;
+        let comment = read_string(cursor)?;
+        // Schemas gossiped before tables had a read repair chance end right after the comment
+        let read_repair_chance = if cursor.position() as usize >= cursor.get_ref().len() {
+            DEFAULT_READ_REPAIR_CHANCE
+        } else {
+            f64::from_bits(read_varint_u64(cursor)?)
+        };
+        let options = TableOptions {
+            default_time_to_live,
+            comment,
+            read_repair_chance,
+        };
+
         Ok(CreateTable {
             name,
             keyspace_used_name: keyspace,
             if_not_exists_clause: if_not_exists,
             columns,
             clustering_columns_in_order: clustering_columns,
+            options,
         })
     }
 }
@@ -383,7 +366,7 @@ impl CursorSerializable for TableSchema {
     }
 
     fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
-        let inner = CreateTable::from_bytes(cursor).map_err(|_| MessageError::CursorError)?;
+        let inner = CreateTable::from_bytes(cursor)?;
 
         Ok(TableSchema { inner })
     }
@@ -395,6 +378,8 @@ pub struct Schema {
     // no puedo usar Keyspace porque sino tengo una
     // dependencia circular entre node y gossip
     pub keyspaces: HashMap<String, KeyspaceSchema>,
+    /// Keyspaces received by name and digest only, which still have to be pulled from a peer.
+    pub missing_keyspaces: HashMap<String, u64>,
 }
 
 impl Schema {
@@ -402,84 +387,216 @@ impl Schema {
         Schema {
             timestamp: 0,
             keyspaces: HashMap::new(),
+            missing_keyspaces: HashMap::new(),
         }
     }
 
+    /// Whether every keyspace of the schema is known, and not only its digest.
+    pub fn is_complete(&self) -> bool {
+        self.missing_keyspaces.is_empty()
+    }
+
+    /// Returns the version of the schema: a digest of the names and digests of its keyspaces, the
+    /// ones still missing included, so two nodes gossip the same version if and only if they hold
+    /// the same keyspaces. Unlike the timestamp, it tells apart schemas changed at the same time on
+    /// different nodes.
+    pub fn version(&self) -> u64 {
+        let mut digests: Vec<(&String, u64)> = self
+            .keyspaces
+            .iter()
+            .map(|(name, keyspace)| (name, keyspace.digest()))
+            .chain(
+                self.missing_keyspaces
+                    .iter()
+                    .map(|(name, digest)| (name, *digest)),
+            )
+            .collect();
+        digests.sort();
+
+        digests
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, (keyspace_name, digest)| {
+                let hash = fnv1a(hash, keyspace_name.as_bytes());
+                fnv1a(hash, &digest.to_be_bytes())
+            })
+    }
+
+    /// ```md
+    /// +----+----+----+----+
+    /// |  timestamp (var)  |
+    /// +----+----+----+----+
+    /// |  keyspace count   |
+    /// +----+----+----+----+
+    /// |   keyspace name   |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |  keyspace schema  |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
+    /// Convert the `Schema` to a byte vector.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let timestamp_bytes = self.timestamp.to_be_bytes();
-        bytes.extend_from_slice(&timestamp_bytes);
-
-        let keyspaces_len = self.keyspaces.len() as u32;
-        bytes.extend_from_slice(&keyspaces_len.to_be_bytes());
+        write_varint(&mut bytes, self.timestamp as u64 as u128);
 
+        write_varint(&mut bytes, self.keyspaces.len() as u128);
         for (keyspace_name, keyspace_schema) in &self.keyspaces {
-            let keyspace_name_len_bytes = (keyspace_name.len() as u32).to_be_bytes();
-            let keyspace_name_bytes = keyspace_name.as_bytes();
-            bytes.extend_from_slice(&keyspace_name_len_bytes);
-            bytes.extend_from_slice(keyspace_name_bytes);
-
-            let keyspace_schema_bytes = keyspace_schema.to_bytes();
-            bytes.extend_from_slice(&keyspace_schema_bytes);
+            write_string(&mut bytes, keyspace_name);
+            bytes.extend_from_slice(&keyspace_schema.to_bytes());
         }
 
         bytes
     }
 
+    /// Create a `Schema` from bytes.
     pub fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
-        let mut timestamp_bytes = [0u8; 8];
-        cursor
-            .read_exact(&mut timestamp_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let timestamp = i64::from_be_bytes(timestamp_bytes);
-
-        let mut keyspaces_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut keyspaces_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let keyspaces_len = u32::from_be_bytes(keyspaces_len_bytes);
+        let timestamp = read_varint_u64(cursor)? as i64;
 
+        let keyspaces_len = read_count(cursor)?;
         let mut keyspaces = HashMap::new();
-
         for _ in 0..keyspaces_len {
-            let mut keyspace_name_len_bytes = [0u8; 4];
-            cursor
-                .read_exact(&mut keyspace_name_len_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-            let keyspace_name_len = u32::from_be_bytes(keyspace_name_len_bytes);
+            let keyspace_name = read_string(cursor)?;
+            let keyspace_schema = KeyspaceSchema::from_bytes(cursor)?;
+            keyspaces.insert(keyspace_name, keyspace_schema);
+        }
 
-            let mut keyspace_name_bytes = vec![0u8; keyspace_name_len as usize];
-            cursor
-                .read_exact(&mut keyspace_name_bytes)
-                .map_err(|_| MessageError::CursorError)?;
-            let keyspace_name =
-                String::from_utf8(keyspace_name_bytes).map_err(|_| MessageError::CursorError)?;
+        Ok(Schema {
+            keyspaces,
+            timestamp,
+            missing_keyspaces: HashMap::new(),
+        })
+    }
 
-            let keyspace_schema =
-                KeyspaceSchema::from_bytes(cursor).map_err(|_| MessageError::CursorError)?;
+    /// ```md
+    /// +----+----+----+----+
+    /// |  timestamp (var)  |
+    /// +----+----+----+----+
+    /// |  keyspace count   |
+    /// +----+----+----+----+
+    /// |   keyspace name   |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |                   |
+    /// +  keyspace digest  +
+    /// |                   |
+    /// +----+----+----+----+
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
+    /// Convert the `Schema` to the bytes gossiped in the application state: each keyspace is
+    /// written as its name and digest, so the size does not grow with the tables. Peers pull the
+    /// keyspaces they do not have.
+    pub fn to_digest_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
 
-            keyspaces.insert(keyspace_name, keyspace_schema);
+        write_varint(&mut bytes, self.timestamp as u64 as u128);
+
+        let digests: Vec<(&String, u64)> = self
+            .keyspaces
+            .iter()
+            .map(|(name, keyspace)| (name, keyspace.digest()))
+            .chain(
+                self.missing_keyspaces
+                    .iter()
+                    .map(|(name, digest)| (name, *digest)),
+            )
+            .collect();
+
+        write_varint(&mut bytes, digests.len() as u128);
+        for (keyspace_name, digest) in digests {
+            write_string(&mut bytes, keyspace_name);
+            bytes.extend_from_slice(&digest.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Create a `Schema` from the bytes written by `to_digest_bytes`. Every keyspace is missing
+    /// until it is taken from a known schema or pulled from a peer.
+    pub fn from_digest_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
+        let timestamp = read_varint_u64(cursor)? as i64;
+
+        let keyspaces_len = read_count(cursor)?;
+        let mut missing_keyspaces = HashMap::new();
+        for _ in 0..keyspaces_len {
+            let keyspace_name = read_string(cursor)?;
+            let mut digest_bytes = [0u8; 8];
+            cursor
+                .read_exact(&mut digest_bytes)
+                .map_err(|_| MessageError::CursorError)?;
+            missing_keyspaces.insert(keyspace_name, u64::from_be_bytes(digest_bytes));
         }
 
         Ok(Schema {
-            keyspaces,
             timestamp,
+            keyspaces: HashMap::new(),
+            missing_keyspaces,
         })
     }
+
+    /// Takes the missing keyspaces from `known`, if it has them with the same digest.
+    pub fn complete_from(&mut self, known: &Schema) {
+        self.missing_keyspaces.retain(|keyspace_name, digest| {
+            match known.keyspaces.get(keyspace_name) {
+                Some(keyspace) if keyspace.digest() == *digest => {
+                    self.keyspaces
+                        .insert(keyspace_name.clone(), keyspace.clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
 }
 
-#[derive(Clone, PartialEq, Debug, Default)]
+/// Whether the live endpoints of the cluster hold the same schema, as seen by one of them.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SchemaAgreement {
+    /// Version of the schema of the endpoint that checked the agreement.
+    pub version: u64,
+    /// Live endpoints that gossip another version of the schema, with their version.
+    pub disagreeing: Vec<(Ipv4Addr, u64)>,
+}
+
+impl SchemaAgreement {
+    /// Whether every live endpoint gossips the same version of the schema.
+    pub fn is_reached(&self) -> bool {
+        self.disagreeing.is_empty()
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 /// Represents the application state of the endpoint in the cluster at a given point in time.
 ///
 /// ### Fields
 /// - `status`: The status of the node.
 /// - `version`: The version of the ApplicationState.
 /// - `schema`: The schema of the cluster.
+/// - `token`: The position of the node in the ring, if it was moved from the hash of its ip.
+/// - `datacenter`: The datacenter of the node, for `NetworkTopologyStrategy` keyspaces.
+/// - `rack`: The rack of the node within its datacenter.
+/// - `removed_until`: The time, in milliseconds since the epoch, until which a removed node is
+///   remembered as such. It is gossiped with the status, so every node forgets it at the same time.
+/// - `empty_data`: Whether the node started without any data of its own, so the other nodes
+///   stream its ranges to it again when they see it restart.
 pub struct ApplicationState {
     pub status: NodeStatus,
     pub version: u32,
     pub schema: Schema,
+    pub token: Option<u64>,
+    pub datacenter: String,
+    pub rack: String,
+    pub removed_until: Option<u64>,
+    pub empty_data: bool,
+}
+
+impl Default for ApplicationState {
+    fn default() -> Self {
+        ApplicationState::new(NodeStatus::default(), 0, Schema::default())
+    }
 }
 
 /// Represents the schema of the keyspace.
@@ -538,6 +655,17 @@ impl KeyspaceSchema {
         self.tables.clone()
     }
 
+    /// Describes the keyspace as `DESCRIBE KEYSPACE` does: the `CREATE KEYSPACE` statement that
+    /// creates it again, followed by the `CREATE TABLE` statements of its tables, one per line.
+    pub fn describe(&self) -> Vec<String> {
+        let mut inner = self.inner.clone();
+        inner.if_not_exists_clause = false;
+
+        let mut statements = vec![inner.serialize()];
+        statements.extend(self.tables.iter().map(|table| table.describe()));
+        statements
+    }
+
     /// Gets the replication class of the keyspace.
     ///
     /// # Returns
@@ -572,6 +700,24 @@ impl KeyspaceSchema {
         self.inner.update_replication_factor(replication_factor)
     }
 
+    /// Gets the replication factor of each datacenter, which is empty unless the keyspace uses
+    /// `NetworkTopologyStrategy`.
+    ///
+    /// # Returns
+    /// Returns the replication factors by datacenter name.
+    pub fn get_datacenters(&self) -> BTreeMap<String, u32> {
+        self.inner.get_datacenters()
+    }
+
+    /// Updates the replication factor of each datacenter.
+    ///
+    /// # Arguments
+    ///
+    /// * `datacenters` - The new replication factors, by datacenter name.
+    pub fn update_datacenters(&mut self, datacenters: BTreeMap<String, u32>) {
+        self.inner.update_datacenters(datacenters)
+    }
+
     /// Adds a new table to the keyspace.
     ///
     /// # Arguments
@@ -633,23 +779,41 @@ impl KeyspaceSchema {
 }
 
 impl CursorSerializable for CreateKeyspace {
+    /// ```md
+    /// +----+----+----+----+
+    /// |       name        |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |ine | replication_class
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// | replication_factor|
+    /// |       (var)       |
+    /// +----+----+----+----+
+    /// | datacenter count  |
+    /// +----+----+----+----+
+    /// |  datacenter name  |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// | datacenter factor |
+    /// |       (var)       |
+    /// +----+----+----+----+
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let name_len = self.name.len() as u32;
-        let name_bytes = self.name.as_bytes();
-        bytes.extend_from_slice(&name_len.to_be_bytes());
-        bytes.extend_from_slice(name_bytes);
-
-        let if_not_exists = self.if_not_exists_clause as u8;
-        bytes.push(if_not_exists);
-
-        let replication_class_len = self.replication_class.len() as u32;
-        let replication_class_bytes = self.replication_class.as_bytes();
-        bytes.extend_from_slice(&replication_class_len.to_be_bytes());
-        bytes.extend_from_slice(replication_class_bytes);
+        write_string(&mut bytes, &self.name);
+        bytes.push(self.if_not_exists_clause as u8);
+        write_string(&mut bytes, &self.replication_class);
+        write_varint(&mut bytes, self.replication_factor as u128);
 
-        bytes.extend_from_slice(&self.replication_factor.to_be_bytes());
+        write_varint(&mut bytes, self.datacenters.len() as u128);
+        for (datacenter, factor) in &self.datacenters {
+            write_string(&mut bytes, datacenter);
+            write_varint(&mut bytes, *factor as u128);
+        }
 
         bytes
     }
@@ -658,48 +822,24 @@ impl CursorSerializable for CreateKeyspace {
     where
         Self: Sized,
     {
-        let mut name_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut name_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name_len = u32::from_be_bytes(name_len_bytes);
-
-        let mut name_bytes = vec![0u8; name_len as usize];
-        cursor
-            .read_exact(&mut name_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let name = String::from_utf8(name_bytes).map_err(|_| MessageError::CursorError)?;
-
-        let mut if_not_exists_bytes = [0u8; 1];
-        cursor
-            .read_exact(&mut if_not_exists_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let if_not_exists = if_not_exists_bytes[0] == 1;
-
-        let mut replication_class_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut replication_class_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let replication_class_len = u32::from_be_bytes(replication_class_len_bytes);
-
-        let mut replication_class_bytes = vec![0u8; replication_class_len as usize];
-        cursor
-            .read_exact(&mut replication_class_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let replication_class =
-            String::from_utf8(replication_class_bytes).map_err(|_| MessageError::CursorError)?;
-
-        let mut replication_factor_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut replication_factor_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let replication_factor = u32::from_be_bytes(replication_factor_bytes);
+        let name = read_string(cursor)?;
+        let if_not_exists = read_byte(cursor)? == 1;
+        let replication_class = read_string(cursor)?;
+        let replication_factor = read_varint_u32(cursor)?;
+
+        let datacenters_len = read_count(cursor)?;
+        let mut datacenters = BTreeMap::new();
+        for _ in 0..datacenters_len {
+            let datacenter = read_string(cursor)?;
+            datacenters.insert(datacenter, read_varint_u32(cursor)?);
+        }
 
         Ok(CreateKeyspace {
             name,
             if_not_exists_clause: if_not_exists,
             replication_class,
             replication_factor,
+            datacenters,
         })
     }
 }
@@ -717,6 +857,8 @@ impl KeyspaceSchema {
     /// |      keyspace     |
     /// |        ...        |
     /// +----+----+----+----+
+    /// | table count (var) |
+    /// +----+----+----+----+
     /// |      tables       |
     /// |        ...        |
     /// +----+----+----+----+
@@ -725,38 +867,32 @@ impl KeyspaceSchema {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        let keyspace_bytes = self.inner.to_bytes();
-        bytes.extend_from_slice(&keyspace_bytes);
-
-        let tables_len = self.tables.len() as u32;
-        bytes.extend_from_slice(&tables_len.to_be_bytes());
-
-        let mut tables_bytes = vec![];
+        bytes.extend_from_slice(&self.inner.to_bytes());
 
+        write_varint(&mut bytes, self.tables.len() as u128);
         for table in &self.tables {
-            tables_bytes.extend_from_slice(&table.to_bytes());
+            bytes.extend_from_slice(&table.to_bytes());
         }
 
-        bytes.extend_from_slice(&tables_bytes);
-
         bytes
     }
 
+    /// Returns a digest of the keyspace and its tables, which changes whenever any of them does.
+    ///
+    /// It is the 64 bit FNV-1a hash of the bytes of the keyspace, so every node computes the same
+    /// digest for the same keyspace.
+    pub fn digest(&self) -> u64 {
+        fnv1a(FNV_OFFSET_BASIS, &self.to_bytes())
+    }
+
     /// Create a `KeyspaceSchema` from bytes.
     pub fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
-        let keyspace = CreateKeyspace::from_bytes(cursor).map_err(|_| MessageError::CursorError)?;
-
-        let mut tables_len_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut tables_len_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let tables_len = u32::from_be_bytes(tables_len_bytes);
+        let keyspace = CreateKeyspace::from_bytes(cursor)?;
 
+        let tables_len = read_count(cursor)?;
         let mut tables = Vec::new();
-
         for _ in 0..tables_len {
-            let table = TableSchema::from_bytes(cursor).map_err(|_| MessageError::CursorError)?;
-            tables.push(table);
+            tables.push(TableSchema::from_bytes(cursor)?);
         }
 
         Ok(KeyspaceSchema {
@@ -766,6 +902,13 @@ impl KeyspaceSchema {
     }
 }
 
+/// Continues a 64 bit FNV-1a hash with the given bytes.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
 impl ApplicationState {
     /// Create a new `ApplicationState` message.
     pub fn new(status: NodeStatus, version: u32, schema: Schema) -> Self {
@@ -773,55 +916,133 @@ impl ApplicationState {
             status,
             version,
             schema,
+            token: None,
+            datacenter: DEFAULT_DATACENTER.to_string(),
+            rack: DEFAULT_RACK.to_string(),
+            removed_until: None,
+            empty_data: false,
         }
     }
 
+    /// Returns the version of the schema of the node (see [`Schema::version`]).
+    pub fn schema_version(&self) -> u64 {
+        self.schema.version()
+    }
+
     pub fn set_schema(&mut self, schema: Schema) {
         self.schema = schema;
         self.version += 1;
     }
 
+    pub fn set_token(&mut self, token: u64) {
+        self.token = Some(token);
+        self.version += 1;
+    }
+
+    /// Sets the datacenter and rack of the node.
+    pub fn set_location(&mut self, datacenter: &str, rack: &str) {
+        self.datacenter = datacenter.to_string();
+        self.rack = rack.to_string();
+        self.version += 1;
+    }
+
+    /// Sets whether the node started without any data of its own.
+    pub fn set_empty_data(&mut self, empty_data: bool) {
+        self.empty_data = empty_data;
+        self.version += 1;
+    }
+
     /// ```md
     /// 0    8    16   24   32
     /// +----+----+----+----+
-    /// |  status |   0x00  |
+    /// |stat|  version (var)
+    /// +----+----+----+----+
+    /// |has token|
+    /// +----+----+----+----+
+    /// |   token (only if  |
+    /// |   has token = 1)  |
+    /// +----+----+----+----+
+    /// |    datacenter     |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |       rack        |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |has removed until|
+    /// +----+----+----+----+
+    /// |  removed until    |
+    /// |  (only if has     |
+    /// |  removed until=1) |
     /// +----+----+----+----+
-    /// |      version      |
+    /// |empty data|
     /// +----+----+----+----+
-    /// |       schema      |
+    /// |   schema digest   |
     /// |        ...        |
     /// +----+----+----+----+
     /// ```
-    /// Convert the `ApplicationState` message to a byte slice.
+    /// Convert the `ApplicationState` message to a byte slice. The schema goes by digest only
+    /// (see [`Schema::to_digest_bytes`]).
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = vec![self.status as u8];
+
+        write_varint(&mut bytes, self.version as u128);
 
-        let status_bytes = (self.status as u16).to_be_bytes();
-        let version_bytes = self.version.to_be_bytes();
+        match self.token {
+            Some(token) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&token.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
 
-        bytes.extend_from_slice(&status_bytes);
-        bytes.extend_from_slice(&version_bytes);
+        write_string(&mut bytes, &self.datacenter);
+        write_string(&mut bytes, &self.rack);
 
-        let schemas_bytes = self.schema.to_bytes();
+        match self.removed_until {
+            Some(removed_until) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&removed_until.to_be_bytes());
+            }
+            None => bytes.push(0),
+        }
 
-        bytes.extend_from_slice(&schemas_bytes);
+        bytes.push(self.empty_data as u8);
+
+        bytes.extend_from_slice(&self.schema.to_digest_bytes());
 
         bytes
     }
 
-    /// Create an `ApplicationState` message from a byte slice.
+    /// Create an `ApplicationState` message from a byte slice. The keyspaces of its schema are
+    /// missing until they are completed from a known schema or pulled.
     pub fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
-        let mut status_bytes = [0u8; 2];
-        cursor
-            .read_exact(&mut status_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let status_value = u16::from_be_bytes(status_bytes);
-
-        let mut version_bytes = [0u8; 4];
-        cursor
-            .read_exact(&mut version_bytes)
-            .map_err(|_| MessageError::CursorError)?;
-        let version = u32::from_be_bytes(version_bytes);
+        let status_value = read_byte(cursor)?;
+        let version = read_varint_u32(cursor)?;
+
+        let token = if read_byte(cursor)? == 1 {
+            let mut token_bytes = [0u8; 8];
+            cursor
+                .read_exact(&mut token_bytes)
+                .map_err(|_| MessageError::CursorError)?;
+            Some(u64::from_be_bytes(token_bytes))
+        } else {
+            None
+        };
+
+        let datacenter = read_string(cursor)?;
+        let rack = read_string(cursor)?;
+
+        let removed_until = if read_byte(cursor)? == 1 {
+            let mut removed_until_bytes = [0u8; 8];
+            cursor
+                .read_exact(&mut removed_until_bytes)
+                .map_err(|_| MessageError::CursorError)?;
+            Some(u64::from_be_bytes(removed_until_bytes))
+        } else {
+            None
+        };
+
+        let empty_data = read_byte(cursor)? == 1;
 
         let status = match status_value {
             0 => NodeStatus::Bootstrap,
@@ -829,6 +1050,9 @@ impl ApplicationState {
             2 => NodeStatus::Leaving,
             3 => NodeStatus::Removing,
             4 => NodeStatus::Dead,
+            5 => NodeStatus::Left,
+            6 => NodeStatus::Removed,
+            7 => NodeStatus::Shutdown,
             _ => {
                 return Err(MessageError::InvalidValue(format!(
                     "Invalid NodeStatus value: {}",
@@ -837,16 +1061,97 @@ impl ApplicationState {
             }
         };
 
-        let schema = Schema::from_bytes(cursor)?;
+        let schema = Schema::from_digest_bytes(cursor)?;
 
         Ok(ApplicationState {
             status,
             version,
             schema,
+            token,
+            datacenter,
+            rack,
+            removed_until,
+            empty_data,
         })
     }
 }
 
+impl GossipState for ApplicationState {
+    fn as_bytes(&self) -> Vec<u8> {
+        ApplicationState::as_bytes(self)
+    }
+
+    fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError> {
+        ApplicationState::from_bytes(cursor)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.status.is_alive()
+    }
+
+    fn mark_dead(&mut self) {
+        self.status = NodeStatus::Dead;
+        self.version += 1;
+    }
+
+    fn mark_removed(&mut self, removed_until: u64) {
+        self.status = NodeStatus::Removed;
+        self.removed_until = Some(removed_until);
+        self.version += 1;
+    }
+
+    fn removed_until(&self) -> Option<u64> {
+        self.removed_until
+    }
+
+    fn missing_parts(&self) -> Vec<(String, u64)> {
+        self.schema
+            .missing_keyspaces
+            .iter()
+            .map(|(name, digest)| (name.clone(), *digest))
+            .collect()
+    }
+
+    fn parts(&self) -> Vec<(String, u64)> {
+        self.schema
+            .keyspaces
+            .iter()
+            .map(|(name, keyspace)| (name.clone(), keyspace.digest()))
+            .chain(self.missing_parts())
+            .collect()
+    }
+
+    fn complete_from(&mut self, known: &Self) {
+        self.schema.complete_from(&known.schema);
+    }
+
+    /// A part is a keyspace of the schema: its name followed by the keyspace itself.
+    fn part_bytes(&self, name: &str, digest: u64) -> Option<Vec<u8>> {
+        let keyspace = self.schema.keyspaces.get(name)?;
+        if keyspace.digest() != digest {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, name);
+        bytes.extend_from_slice(&keyspace.to_bytes());
+        Some(bytes)
+    }
+
+    fn add_part(&mut self, bytes: &[u8]) -> Result<(), MessageError> {
+        let mut cursor = Cursor::new(bytes);
+        let name = read_string(&mut cursor)?;
+        let keyspace = KeyspaceSchema::from_bytes(&mut cursor)?;
+
+        if self.schema.missing_keyspaces.get(&name) == Some(&keyspace.digest()) {
+            self.schema.missing_keyspaces.remove(&name);
+            self.schema.keyspaces.insert(name, keyspace);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
 /// Represents the status of the node in the cluster.
 /// - `Bootstrap`: The node is bootstrapping.
@@ -854,6 +1159,9 @@ impl ApplicationState {
 /// - `Leaving`: The node is leaving the cluster.
 /// - `Removing`: The node is being removed from the cluster.
 /// - `Dead`: The node is dead.
+/// - `Left`: The node left the cluster.
+/// - `Removed`: The node was removed from the cluster by an operator.
+/// - `Shutdown`: The node was shut down, and keeps its ranges until it restarts.
 pub enum NodeStatus {
     #[default]
     /// The node is in the process of joining the cluster.
@@ -866,6 +1174,15 @@ pub enum NodeStatus {
     Removing = 0x3,
     /// The node is dead. Rip.
     Dead = 0x4,
+    /// The node was decommissioned: its data was streamed to the nodes that took its ranges, so it
+    /// is taken out of the ring without redistributing anything.
+    Left = 0x5,
+    /// The node was removed with `removenode` after it died: it is taken out of the ring, and its
+    /// state is kept only so stale gossip can not bring it back, until it is forgotten.
+    Removed = 0x6,
+    /// The node was drained to be stopped, as in a rolling restart: it takes no queries, but it is
+    /// expected back, so it keeps its ranges of the ring and is never marked as dead meanwhile.
+    Shutdown = 0x7,
 }
 
 impl NodeStatus {
@@ -889,8 +1206,20 @@ impl NodeStatus {
         matches!(self, NodeStatus::Removing)
     }
 
+    pub fn is_left(&self) -> bool {
+        matches!(self, NodeStatus::Left)
+    }
+
+    pub fn is_removed(&self) -> bool {
+        matches!(self, NodeStatus::Removed)
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        matches!(self, NodeStatus::Shutdown)
+    }
+
     pub fn is_alive(&self) -> bool {
-        !self.is_dead()
+        !self.is_dead() && !self.is_removed() && !self.is_shutdown()
     }
 }
 
@@ -901,12 +1230,18 @@ mod tests {
     use query_creator::clauses::{
         keyspace::create_keyspace_cql::CreateKeyspace,
         table::create_table_cql::CreateTable,
-        types::{column::Column, datatype::DataType},
+        types::{
+            column::Column,
+            datatype::DataType,
+            table_options::{TableOptions, DEFAULT_READ_REPAIR_CHANCE},
+        },
     };
 
+    use crate::encoding::write_varint;
     use crate::structures::application_state::{
         ApplicationState, CursorSerializable, KeyspaceSchema, NodeStatus, Schema, TableSchema,
     };
+    use crate::structures::gossip_state::GossipState;
 
     #[test]
     fn app_state_to_from_bytes() {
@@ -920,6 +1255,115 @@ mod tests {
 
         assert_eq!(app_state.status, NodeStatus::Bootstrap);
         assert_eq!(app_state.version, 1);
+        assert_eq!(app_state.token, None);
+    }
+
+    #[test]
+    fn removed_app_state_to_from_bytes() {
+        let mut app_state = ApplicationState::new(NodeStatus::Dead, 3, Schema::new());
+        app_state.mark_removed(1_700_000_000_000);
+
+        let bytes = app_state.as_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let app_state = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(app_state.status, NodeStatus::Removed);
+        assert_eq!(app_state.version, 4);
+        assert_eq!(app_state.removed_until, Some(1_700_000_000_000));
+        assert!(!app_state.status.is_alive());
+    }
+
+    #[test]
+    fn shutdown_app_state_to_from_bytes() {
+        let app_state = ApplicationState::new(NodeStatus::Shutdown, 2, Schema::new());
+
+        let bytes = app_state.as_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let app_state = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(app_state.status, NodeStatus::Shutdown);
+        assert!(!app_state.status.is_alive());
+    }
+
+    #[test]
+    fn app_state_with_token_to_from_bytes() {
+        let mut app_state = ApplicationState::new(NodeStatus::Normal, 1, Schema::new());
+        app_state.set_token(0xdeadbeef);
+
+        let bytes = app_state.as_bytes();
+
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+
+        let parsed = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(parsed, app_state);
+        assert_eq!(parsed.token, Some(0xdeadbeef));
+        assert_eq!(parsed.version, 2);
+    }
+
+    #[test]
+    fn app_state_of_a_node_that_left_to_from_bytes() {
+        let app_state = ApplicationState::new(NodeStatus::Left, 3, Schema::new());
+
+        let bytes = app_state.as_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let parsed = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(parsed, app_state);
+        assert!(parsed.status.is_left() && parsed.status.is_alive());
+    }
+
+    #[test]
+    fn app_state_with_location_to_from_bytes() {
+        let mut app_state = ApplicationState::new(NodeStatus::Normal, 1, Schema::new());
+        assert_eq!(
+            (app_state.datacenter.as_str(), app_state.rack.as_str()),
+            ("datacenter1", "rack1")
+        );
+        app_state.set_location("us_east", "rack2");
+
+        let bytes = app_state.as_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let parsed = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(parsed, app_state);
+        assert_eq!(parsed.datacenter, "us_east");
+        assert_eq!(parsed.rack, "rack2");
+    }
+
+    #[test]
+    fn app_state_with_empty_data_to_from_bytes() {
+        let mut app_state = ApplicationState::new(NodeStatus::Bootstrap, 1, Schema::new());
+        assert!(!app_state.empty_data);
+        app_state.set_empty_data(true);
+
+        let bytes = app_state.as_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let parsed = ApplicationState::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(parsed, app_state);
+        assert!(parsed.empty_data);
+        assert_eq!(parsed.version, 2);
+    }
+
+    #[test]
+    fn network_topology_keyspace_to_from_bytes() {
+        let keyspace = KeyspaceSchema::new(
+            CreateKeyspace::deserialize(
+                "CREATE KEYSPACE sky WITH replication = {'class': 'NetworkTopologyStrategy', \
+                'us_east': 3, 'eu_west': 2}",
+            )
+            .unwrap(),
+            vec![],
+        );
+
+        let bytes = keyspace.to_bytes();
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let parsed = KeyspaceSchema::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(parsed, keyspace);
+        assert_eq!(parsed.get_datacenters().get("us_east"), Some(&3));
+        assert_eq!(parsed.get_replication_factor(), 5);
     }
 
     #[test]
@@ -943,6 +1387,46 @@ mod tests {
         assert_eq!(expected_column, column);
     }
 
+    #[test]
+    fn describe_table_with_options() {
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE IF NOT EXISTS sky.flights (id INT PRIMARY KEY, origin TEXT) \
+                WITH default_time_to_live = 60 AND comment = 'flights of the day'",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(table.get_options().default_time_to_live, 60);
+        assert_eq!(
+            table.describe(),
+            "CREATE TABLE sky.flights (id INT PRIMARY KEY, origin TEXT) \
+            WITH default_time_to_live = 60 AND comment = 'flights of the day';"
+        );
+    }
+
+    #[test]
+    fn describe_keyspace_with_its_tables() {
+        let keyspace = KeyspaceSchema::new(
+            CreateKeyspace::deserialize(
+                "CREATE KEYSPACE IF NOT EXISTS sky WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2}",
+            )
+            .unwrap(),
+            vec![TableSchema::new(
+                CreateTable::deserialize("CREATE TABLE sky.flights (id INT PRIMARY KEY, origin TEXT)")
+                    .unwrap(),
+            )],
+        );
+
+        assert_eq!(
+            keyspace.describe(),
+            vec![
+                "CREATE KEYSPACE sky WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2};",
+                "CREATE TABLE sky.flights (id INT PRIMARY KEY, origin TEXT);",
+            ]
+        );
+    }
+
     #[test]
     fn create_table_to_from_bytes() {
         let expected_table = CreateTable {
@@ -959,6 +1443,11 @@ mod tests {
                 clustering_order: "asc".to_string(),
             }],
             clustering_columns_in_order: vec![],
+            options: TableOptions {
+                default_time_to_live: 60,
+                comment: "flights of the day".to_string(),
+                read_repair_chance: 0.1,
+            },
         };
 
         let bytes = expected_table.to_bytes();
@@ -968,6 +1457,37 @@ mod tests {
         let table = CreateTable::from_bytes(&mut cursor).unwrap();
 
         assert_eq!(table, expected_table);
+        assert_eq!(table.options, expected_table.options);
+    }
+
+    #[test]
+    fn create_table_without_read_repair_chance_from_bytes() {
+        let table = CreateTable {
+            name: "table".to_string(),
+            keyspace_used_name: "keyspace".to_string(),
+            if_not_exists_clause: false,
+            columns: vec![],
+            clustering_columns_in_order: vec![],
+            options: TableOptions {
+                default_time_to_live: 60,
+                comment: "flights of the day".to_string(),
+                read_repair_chance: 0.1,
+            },
+        };
+        let mut bytes = table.to_bytes();
+        // Drops the read repair chance, the last varint
+        let mut chance = Vec::new();
+        write_varint(&mut chance, 0.1f64.to_bits() as u128);
+        bytes.truncate(bytes.len() - chance.len());
+
+        let mut cursor = std::io::Cursor::new(bytes.as_slice());
+        let old_table = CreateTable::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(old_table.options.comment, "flights of the day");
+        assert_eq!(
+            old_table.options.read_repair_chance,
+            DEFAULT_READ_REPAIR_CHANCE
+        );
     }
 
     #[test]
@@ -987,6 +1507,7 @@ mod tests {
                     clustering_order: "asc".to_string(),
                 }],
                 clustering_columns_in_order: vec![],
+                options: Default::default(),
             },
         };
 
@@ -1018,6 +1539,7 @@ mod tests {
                         clustering_order: "asc".to_string(),
                     }],
                     clustering_columns_in_order: vec![],
+                    options: Default::default(),
                 },
             }],
         };
@@ -1035,6 +1557,7 @@ mod tests {
     fn schema_to_from_bytes() {
         let expected_schema = Schema {
             timestamp: 100,
+            missing_keyspaces: HashMap::new(),
             keyspaces: HashMap::from([(
                 "keyspace".to_string(),
                 KeyspaceSchema {
@@ -1047,6 +1570,7 @@ mod tests {
                                 if_not_exists_clause: false,
                                 columns: vec![],
                                 clustering_columns_in_order: vec![],
+                                options: Default::default(),
                             },
                         },
                         TableSchema {
@@ -1056,6 +1580,7 @@ mod tests {
                                 if_not_exists_clause: false,
                                 columns: vec![],
                                 clustering_columns_in_order: vec![],
+                                options: Default::default(),
                             },
                         },
                     ],
@@ -1071,4 +1596,45 @@ mod tests {
 
         assert_eq!(expected_schema, schema);
     }
+
+    #[test]
+    fn schema_version_depends_on_the_keyspaces_only() {
+        let mut schema = Schema {
+            timestamp: 100,
+            missing_keyspaces: HashMap::new(),
+            keyspaces: HashMap::from([(
+                "keyspace".to_string(),
+                KeyspaceSchema {
+                    inner: CreateKeyspace::default(),
+                    tables: vec![],
+                },
+            )]),
+        };
+        let version = schema.version();
+
+        // A peer that has not pulled the keyspace yet gossips the same version
+        let bytes = schema.to_digest_bytes();
+        let gossiped = Schema::from_digest_bytes(&mut std::io::Cursor::new(bytes.as_slice()));
+        assert_eq!(gossiped.unwrap().version(), version);
+
+        schema.timestamp = 200;
+        assert_eq!(schema.version(), version);
+
+        let table = TableSchema::new(CreateTable {
+            name: "table".to_string(),
+            keyspace_used_name: "keyspace".to_string(),
+            if_not_exists_clause: false,
+            columns: vec![],
+            clustering_columns_in_order: vec![],
+            options: Default::default(),
+        });
+        schema
+            .keyspaces
+            .get_mut("keyspace")
+            .unwrap()
+            .add_table(table)
+            .unwrap();
+        assert_ne!(schema.version(), version);
+        assert_ne!(Schema::new().version(), version);
+    }
 }
@@ -1,4 +1,6 @@
-use super::{application_state::ApplicationState, heartbeat_state::HeartbeatState};
+use super::{
+    application_state::ApplicationState, gossip_state::GossipState, heartbeat_state::HeartbeatState,
+};
 
 #[derive(Debug, Clone, PartialEq, Default)]
 /// Represents the state of the endpoint in the cluster at a given point in time.
@@ -6,14 +8,14 @@ use super::{application_state::ApplicationState, heartbeat_state::HeartbeatState
 /// ### Fields
 /// - `heartbeat_state`: The heartbeat state of the endpoint.
 /// - `application_state`: The application state of the endpoint.
-pub struct EndpointState {
+pub struct EndpointState<S: GossipState = ApplicationState> {
     pub heartbeat_state: HeartbeatState,
-    pub application_state: ApplicationState,
+    pub application_state: S,
 }
 
-impl EndpointState {
+impl<S: GossipState> EndpointState<S> {
     /// Creates a new `EndpointState` with the given `application_state` and `heartbeat_state`.
-    pub fn new(application_state: ApplicationState, heartbeat_state: HeartbeatState) -> Self {
+    pub fn new(application_state: S, heartbeat_state: HeartbeatState) -> Self {
         Self {
             application_state,
             heartbeat_state,
@@ -0,0 +1,57 @@
+use std::{fmt, io::Cursor};
+
+use crate::messages::MessageError;
+
+/// The information an endpoint gossips about itself, next to its heartbeat.
+///
+/// The gossiper only needs to serialize it, compare it and know if the endpoint is alive, so any
+/// type implementing this trait can be used as the payload of the protocol. The database nodes
+/// use [`ApplicationState`](super::application_state::ApplicationState), which carries the status
+/// of the node, its token and the schema of the cluster.
+pub trait GossipState: Clone + Default + PartialEq + fmt::Debug {
+    /// Convert the state to the bytes sent in `Ack` and `Ack2` messages.
+    fn as_bytes(&self) -> Vec<u8>;
+
+    /// Read a state written by `as_bytes` from the cursor.
+    fn from_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Self, MessageError>;
+
+    /// Whether the endpoint is alive. Dead endpoints are not picked to gossip with.
+    fn is_alive(&self) -> bool;
+
+    /// Mark the endpoint as dead, bumping the version of the state so the change is gossiped.
+    fn mark_dead(&mut self);
+
+    /// Mark the endpoint as removed from the cluster until the given time, in milliseconds since
+    /// the epoch, bumping the version of the state so the change is gossiped. A removed endpoint
+    /// is not alive.
+    fn mark_removed(&mut self, removed_until: u64);
+
+    /// The time, in milliseconds since the epoch, until which the endpoint is remembered as
+    /// removed, if it was removed.
+    fn removed_until(&self) -> Option<u64>;
+
+    /// Parts of the state that arrived as a name and a digest of their content only, and still
+    /// have to be pulled from a peer. States that are always gossiped whole have none.
+    fn missing_parts(&self) -> Vec<(String, u64)> {
+        Vec::new()
+    }
+
+    /// Parts of the state that are gossiped as a name and a digest of their content only, whether
+    /// they are missing or not. They are saved with the state, so a reloaded one is complete.
+    fn parts(&self) -> Vec<(String, u64)> {
+        Vec::new()
+    }
+
+    /// Takes the missing parts of the state from `known`, a state the gossiper already holds.
+    fn complete_from(&mut self, _known: &Self) {}
+
+    /// Returns the bytes of the part with the given name and digest, to answer a pull.
+    fn part_bytes(&self, _name: &str, _digest: u64) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Adds a part written by `part_bytes`, if the state was missing it.
+    fn add_part(&mut self, _bytes: &[u8]) -> Result<(), MessageError> {
+        Ok(())
+    }
+}
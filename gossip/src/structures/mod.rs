@@ -1,3 +1,4 @@
 pub mod application_state;
 pub mod endpoint_state;
+pub mod gossip_state;
 pub mod heartbeat_state;
@@ -0,0 +1,20 @@
+use std::net::Ipv4Addr;
+
+use crate::{
+    messages::GossipMessage,
+    structures::{application_state::ApplicationState, gossip_state::GossipState},
+    GossipError,
+};
+
+/// The way a gossiper sends its messages to other endpoints.
+///
+/// The gossiper never opens connections by itself: the embedding application decides how
+/// messages travel (the database nodes wrap them in internode messages, a simulation can just
+/// push them into a queue) and gives the incoming ones to
+/// [`Gossiper::handle_message`](crate::Gossiper::handle_message).
+pub trait Transport<S: GossipState = ApplicationState> {
+    /// Sends the message to the endpoint with the given ip.
+    ///
+    /// An error means the endpoint could not be reached, and makes the gossiper mark it as dead.
+    fn send(&self, to: Ipv4Addr, message: GossipMessage<S>) -> Result<(), GossipError>;
+}
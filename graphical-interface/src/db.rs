@@ -1,11 +1,19 @@
-use std::{net::Ipv4Addr, str::FromStr};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
 
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use driver::{self, CassandraClient, QueryResult};
+use driver::{
+    self,
+    cluster::{Cluster, Session},
+    events::{self, EventRecord},
+    QueryResult, NATIVE_PORT,
+};
 use native_protocol::messages::result::{result_, rows};
 use walkers::Position;
 
-use crate::types::{Airport, Flight, FlightInfo, FlightStatus};
+use crate::types::{Airport, Coordinator, Flight, FlightInfo, FlightStatus};
 
 #[derive(Debug, Clone)]
 pub struct DBError;
@@ -39,14 +47,21 @@ pub trait Provider {
     fn add_flight(&mut self, flight: Flight) -> Result<(), DBError>;
 
     fn update_state(&mut self, flight: Flight, direction: &str) -> Result<(), DBError>;
+
+    fn get_node_events(&mut self, since: u64) -> Result<Vec<EventRecord>, DBError>;
+
+    fn get_coordinator(&mut self) -> Result<Coordinator, DBError>;
 }
 
 /// A structure representing the database connection for managing flight and airport data.
 ///
 /// The `Db` struct is responsible for connecting to a Cassandra database and
 /// executing queries required by the graphical interface of the flight simulator.
+///
+/// It works in active-standby mode: the queries go to the primary nodes, and only go to the
+/// fallback nodes while none of the primary ones can be reached.
 pub struct Db {
-    driver: CassandraClient,
+    session: Session,
 }
 
 impl Default for Db {
@@ -58,13 +73,28 @@ impl Default for Db {
 impl Db {
     /// Creates a new instance of the `Db` struct, establishing a connection to the database.
     pub fn new() -> Self {
-        let mut driver = CassandraClient::connect(Ipv4Addr::from_str(IP).unwrap()).unwrap();
-        driver.startup().unwrap();
-        Self { driver: driver }
+        Self::with_nodes(vec![Ipv4Addr::from_str(IP).unwrap()], Vec::new())
+    }
+
+    /// Creates a new instance of the `Db` struct that sends its queries to the `primary` nodes,
+    /// and to the `fallback` nodes while none of the primary ones can be reached.
+    pub fn with_nodes(primary: Vec<Ipv4Addr>, fallback: Vec<Ipv4Addr>) -> Self {
+        let addrs = |ips: Vec<Ipv4Addr>| {
+            ips.into_iter()
+                .map(|ip| SocketAddr::new(IpAddr::V4(ip), NATIVE_PORT))
+                .collect()
+        };
+        let session = Cluster::new(addrs(primary))
+            .with_fallback_contact_points(addrs(fallback))
+            .connect()
+            .unwrap();
+        Self { session }
     }
 
     fn execute_query(&mut self, query: &str, consistency: &str) -> Result<QueryResult, DBError> {
-        self.driver.execute(query, consistency).map_err(|_| DBError)
+        self.session
+            .execute(query, consistency)
+            .map_err(|_| DBError)
     }
 }
 
@@ -591,4 +621,22 @@ impl Provider for Db {
     fn get_airports(&mut self) -> Result<Vec<Airport>, DBError> {
         self.get_airports_by_country("ARG")
     }
+
+    /// Get the events recorded by the node the queries are currently sent to after the event with id
+    /// `since`, to show them in the status bar.
+    fn get_node_events(&mut self, since: u64) -> Result<Vec<EventRecord>, DBError> {
+        let coordinator = self.get_coordinator()?;
+        events::poll_events(coordinator.ip, since).map_err(|_| DBError)
+    }
+
+    /// Get the node that answered the last query, and whether it is a fallback one.
+    fn get_coordinator(&mut self) -> Result<Coordinator, DBError> {
+        match self.session.coordinator() {
+            Some(SocketAddr::V4(addr)) => Ok(Coordinator {
+                ip: *addr.ip(),
+                is_fallback: self.session.is_on_fallback(),
+            }),
+            _ => Err(DBError),
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::net::Ipv4Addr;
+
 use db::Db;
 
 pub mod db;
@@ -10,9 +12,22 @@ mod windows;
 use map::MyApp;
 
 pub fn run() -> Result<(), eframe::Error> {
+    run_with_db(Db::new)
+}
+
+/// Runs the interface in active-standby mode: its queries go to the `primary` nodes, and to the
+/// `fallback` nodes while none of the primary ones can be reached.
+pub fn run_with_nodes(
+    primary: Vec<Ipv4Addr>,
+    fallback: Vec<Ipv4Addr>,
+) -> Result<(), eframe::Error> {
+    run_with_db(move || Db::with_nodes(primary, fallback))
+}
+
+fn run_with_db(db: impl FnOnce() -> Db + 'static) -> Result<(), eframe::Error> {
     eframe::run_native(
         "Flight Tracker",
         Default::default(),
-        Box::new(|cc| Ok(Box::new(MyApp::new(cc.egui_ctx.clone(), Db::new())))),
+        Box::new(|cc| Ok(Box::new(MyApp::new(cc.egui_ctx.clone(), db())))),
     )
 }
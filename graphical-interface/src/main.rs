@@ -1,5 +1,27 @@
-use graphical_interface::run;
+use std::{env, net::Ipv4Addr};
 
+use graphical_interface::{run, run_with_nodes};
+
+/// Usage: `graphical-interface [<primary_ips> [<fallback_ips>]]`, where each list of nodes is
+/// separated by commas (for example `127.0.0.1,127.0.0.2`).
 pub fn main() {
-    run().unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        run().unwrap();
+        return;
+    }
+
+    let primary = parse_ips(&args[0]);
+    let fallback = args.get(1).map(|ips| parse_ips(ips)).unwrap_or_default();
+    run_with_nodes(primary, fallback).unwrap();
+}
+
+fn parse_ips(ips: &str) -> Vec<Ipv4Addr> {
+    ips.split(',')
+        .map(|ip| {
+            ip.trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid node IP: {}", ip))
+        })
+        .collect()
 }
@@ -11,7 +11,7 @@ use walkers::{HttpOptions, HttpTiles, Map, MapMemory, Position, Tiles};
 use crate::{
     db::{Db, Provider},
     plugins,
-    state::{SelectionState, ViewState},
+    state::{SelectionState, StatusState, ViewState},
     types::{CountryTracker, _MapBounds},
     widgets::{WidgetAddFlight, WidgetAirport, WidgetFlight},
     windows,
@@ -30,6 +30,7 @@ pub struct MyApp {
     map_memory: MapMemory,
     selection_state: Rc<RefCell<SelectionState>>,
     view_state: ViewState,
+    status_state: StatusState,
     airport_widget: Option<WidgetAirport>,
     flight_widget: Option<WidgetFlight>,
     add_flight_widget: Option<WidgetAddFlight>,
@@ -54,6 +55,7 @@ impl MyApp {
             map_memory: initial_map_memory,
             selection_state: Rc::new(RefCell::new(SelectionState::new())),
             view_state: ViewState::new(vec![], db.get_airports().unwrap_or_default()),
+            status_state: StatusState::new(),
             airport_widget: None,
             flight_widget: None,
             add_flight_widget: None,
@@ -71,6 +73,7 @@ impl eframe::App for MyApp {
             // let map_bounds = calculate_map_bounds(&self.map_memory);
             // self.country_tracker.update_visible_countries(&map_bounds);
             self.view_state.update_airports(&mut self.db);
+            self.status_state.update_events(&mut self.db);
 
             if let Some(selected_airport) = &self.selection_state.borrow().airport {
                 self.view_state
@@ -86,6 +89,9 @@ impl eframe::App for MyApp {
             ..Default::default()
         };
 
+        // The status bar goes first so the map takes the remaining space
+        windows::status_bar(ctx, &self.status_state);
+
         egui::CentralPanel::default()
             .frame(rimless)
             .show(ctx, |ui| {
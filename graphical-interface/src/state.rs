@@ -1,8 +1,15 @@
+use std::collections::VecDeque;
+
+use driver::events::EventRecord;
+
 use crate::{
     db::Provider,
-    types::{Airport, Flight},
+    types::{Airport, Coordinator, Flight},
 };
 
+/// Number of node events kept for the status bar.
+const STATUS_EVENTS: usize = 5;
+
 /// Tracks the state for the selection of flights and airports.
 pub struct SelectionState {
     pub flight: Option<Flight>,
@@ -69,3 +76,49 @@ impl ViewState {
         }
     }
 }
+
+/// Tracks the last events of the node shown in the status bar.
+pub struct StatusState {
+    pub events: VecDeque<EventRecord>,
+    pub connected: bool,
+    /// The node the queries are sent to, which may be a fallback one.
+    pub coordinator: Option<Coordinator>,
+    last_event_id: u64,
+}
+
+impl StatusState {
+    pub fn new() -> Self {
+        Self {
+            events: VecDeque::with_capacity(STATUS_EVENTS),
+            connected: false,
+            coordinator: None,
+            last_event_id: 0,
+        }
+    }
+
+    /// Asks the node only for the events that happened since the last update.
+    ///
+    /// The events are numbered by each node, so they are asked from the start when the
+    /// coordinator changes.
+    pub fn update_events<P: Provider>(&mut self, db: &mut P) {
+        let coordinator = db.get_coordinator().ok();
+        if coordinator.map(|c| c.ip) != self.coordinator.map(|c| c.ip) {
+            self.last_event_id = 0;
+        }
+        self.coordinator = coordinator;
+
+        match db.get_node_events(self.last_event_id) {
+            Ok(new_events) => {
+                self.connected = true;
+                for event in new_events {
+                    self.last_event_id = event.id;
+                    if self.events.len() >= STATUS_EVENTS {
+                        self.events.pop_front();
+                    }
+                    self.events.push_back(event);
+                }
+            }
+            Err(_) => self.connected = false,
+        }
+    }
+}
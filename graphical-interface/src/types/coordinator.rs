@@ -0,0 +1,9 @@
+use std::net::Ipv4Addr;
+
+/// The node the queries of the graphical interface are currently sent to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinator {
+    pub ip: Ipv4Addr,
+    /// Whether it is a fallback node, used because no primary node can be reached.
+    pub is_fallback: bool,
+}
@@ -15,3 +15,6 @@ pub use map_bounds::_MapBounds;
 
 mod country_tracker;
 pub use country_tracker::CountryTracker;
+
+mod coordinator;
+pub use coordinator::Coordinator;
@@ -1,6 +1,10 @@
-use egui::{Align2, RichText, Ui, Window};
+use chrono::DateTime;
+use driver::events::NodeEvent;
+use egui::{Align2, Color32, Context, RichText, TopBottomPanel, Ui, Window};
 use walkers::MapMemory;
 
+use crate::state::StatusState;
+
 /// Simple GUI to zoom in and out.
 pub fn zoom(ui: &Ui, map_memory: &mut MapMemory) {
     Window::new("Map")
@@ -20,3 +24,52 @@ pub fn zoom(ui: &Ui, map_memory: &mut MapMemory) {
             });
         });
 }
+
+/// Bar at the bottom of the window showing the connection to the node and its last events.
+pub fn status_bar(ctx: &Context, status_state: &StatusState) {
+    TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if status_state.connected {
+                ui.label(RichText::new("● Connected").color(Color32::GREEN));
+            } else {
+                ui.label(RichText::new("● Disconnected").color(Color32::RED));
+            }
+
+            if let Some(coordinator) = &status_state.coordinator {
+                ui.separator();
+                if coordinator.is_fallback {
+                    ui.label(
+                        RichText::new(format!("Coordinator {} (fallback)", coordinator.ip))
+                            .color(Color32::YELLOW),
+                    );
+                } else {
+                    ui.label(format!("Coordinator {}", coordinator.ip));
+                }
+            }
+
+            for record in status_state.events.iter().rev() {
+                ui.separator();
+                let time = DateTime::from_timestamp(record.timestamp, 0)
+                    .map(|time| time.format("%H:%M:%S").to_string())
+                    .unwrap_or_default();
+                ui.label(format!("{} {}", time, describe_event(&record.event)));
+            }
+        });
+    });
+}
+
+fn describe_event(event: &NodeEvent) -> String {
+    match event {
+        NodeEvent::NodeJoined(ip) => format!("Node {} joined", ip),
+        NodeEvent::NodeDied(ip) => format!("Node {} died", ip),
+        NodeEvent::NodeMoved(ip) => format!("Node {} moved", ip),
+        NodeEvent::NodeRestarted(ip) => format!("Node {} restarted", ip),
+        NodeEvent::NodeLeft(ip) => format!("Node {} left", ip),
+        NodeEvent::RedistributionStarted => "Redistribution started".to_string(),
+        NodeEvent::RedistributionFinished => "Redistribution finished".to_string(),
+        NodeEvent::RedistributionFailed => "Redistribution failed".to_string(),
+        NodeEvent::SchemaChanged => "Schema changed".to_string(),
+        NodeEvent::Drained => "Node drained".to_string(),
+        NodeEvent::Decommissioned => "Node decommissioned".to_string(),
+    }
+}
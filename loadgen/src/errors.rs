@@ -0,0 +1,30 @@
+use std::fmt;
+
+use driver::ClientError;
+
+/// Errors that can stop a load generator run.
+#[derive(Debug)]
+pub enum LoadgenError {
+    /// A command line argument is missing or invalid.
+    InvalidArgument(String),
+    /// The driver failed to connect to or talk with the node.
+    ClientError(ClientError),
+    /// A worker thread panicked.
+    ThreadError,
+}
+
+impl fmt::Display for LoadgenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadgenError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            LoadgenError::ClientError(e) => write!(f, "Client error: {:?}", e),
+            LoadgenError::ThreadError => write!(f, "A worker thread panicked"),
+        }
+    }
+}
+
+impl From<ClientError> for LoadgenError {
+    fn from(error: ClientError) -> Self {
+        LoadgenError::ClientError(error)
+    }
+}
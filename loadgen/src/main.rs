@@ -0,0 +1,315 @@
+mod errors;
+mod stats;
+mod workload;
+
+use std::{
+    env,
+    net::Ipv4Addr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use driver::{CassandraClient, QueryResult};
+use errors::LoadgenError;
+use rand::{distributions::Alphanumeric, Rng};
+use stats::{OperationStats, RunStats};
+use workload::{KeyDistribution, KeyGenerator, Workload};
+
+const DEFAULT_NODE: &str = "127.0.0.1";
+const KEYSPACE: &str = "loadgen";
+const TABLE: &str = "usertable";
+
+fn print_usage() {
+    println!("Usage: loadgen [options]");
+    println!("  --node <ip>                  Node to connect to (default {DEFAULT_NODE})");
+    println!("  --profile <a|b|c>            YCSB core profile: a (50% reads), b (95% reads), c (read only)");
+    println!("  --read-ratio <0..1>          Fraction of operations that are reads");
+    println!("  --distribution <uniform|zipfian>");
+    println!("  --records <n>                Rows inserted before the run");
+    println!("  --operations <n>             Operations issued during the run");
+    println!("  --payload-size <bytes>       Size of the written values");
+    println!("  --threads <n>                Concurrent clients");
+    println!("  --consistency <level>        Consistency level of every query");
+}
+
+fn parse_value<T: std::str::FromStr>(
+    flag: &str,
+    value: Option<&String>,
+) -> Result<T, LoadgenError> {
+    value
+        .ok_or_else(|| LoadgenError::InvalidArgument(format!("missing value for {}", flag)))?
+        .parse()
+        .map_err(|_| LoadgenError::InvalidArgument(format!("invalid value for {}", flag)))
+}
+
+fn parse_args(args: &[String]) -> Result<(Ipv4Addr, Workload), LoadgenError> {
+    let mut node = DEFAULT_NODE.parse().map_err(|_| {
+        LoadgenError::InvalidArgument(format!("invalid default node {}", DEFAULT_NODE))
+    })?;
+
+    // The profile is applied first so that explicit flags can override it
+    let mut workload = match args.iter().position(|arg| arg == "--profile") {
+        Some(i) => Workload::from_profile(&parse_value::<String>("--profile", args.get(i + 1))?)?,
+        None => Workload::default(),
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        let value = args.get(i + 1);
+        match flag {
+            "--node" => node = parse_value(flag, value)?,
+            "--profile" => {}
+            "--read-ratio" => workload.read_ratio = parse_value(flag, value)?,
+            "--distribution" => {
+                workload.distribution =
+                    KeyDistribution::from_str(&parse_value::<String>(flag, value)?)?
+            }
+            "--records" => workload.record_count = parse_value(flag, value)?,
+            "--operations" => workload.operation_count = parse_value(flag, value)?,
+            "--payload-size" => workload.payload_size = parse_value(flag, value)?,
+            "--threads" => workload.threads = parse_value(flag, value)?,
+            "--consistency" => workload.consistency = parse_value(flag, value)?,
+            _ => {
+                return Err(LoadgenError::InvalidArgument(format!(
+                    "unknown flag {}",
+                    flag
+                )))
+            }
+        }
+        i += 2;
+    }
+
+    if !(0.0..=1.0).contains(&workload.read_ratio) {
+        return Err(LoadgenError::InvalidArgument(
+            "--read-ratio must be between 0 and 1".to_string(),
+        ));
+    }
+    if workload.threads == 0 || workload.record_count == 0 {
+        return Err(LoadgenError::InvalidArgument(
+            "--threads and --records must be greater than 0".to_string(),
+        ));
+    }
+
+    Ok((node, workload))
+}
+
+fn connect(node: Ipv4Addr) -> Result<CassandraClient, LoadgenError> {
+    let mut client = CassandraClient::connect(node)?;
+    client.startup()?;
+    Ok(client)
+}
+
+fn setup_schema(node: Ipv4Addr, workload: &Workload) -> Result<(), LoadgenError> {
+    let mut client = connect(node)?;
+
+    let create_keyspace = format!(
+        "CREATE KEYSPACE {KEYSPACE} WITH REPLICATION = {{'class': 'SimpleStrategy', 'replication_factor': 3}};"
+    );
+    let create_table =
+        format!("CREATE TABLE {KEYSPACE}.{TABLE} (key TEXT, field0 TEXT, PRIMARY KEY (key))");
+
+    // The schema may already exist from a previous run, so errors are not fatal here
+    for query in [create_keyspace, create_table] {
+        if let Ok(QueryResult::Error(e)) = client.execute(&query, &workload.consistency) {
+            eprintln!("Schema setup: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn random_payload<R: Rng>(rng: &mut R, size: usize) -> String {
+    rng.sample_iter(&Alphanumeric)
+        .take(size)
+        .map(char::from)
+        .collect()
+}
+
+fn insert_query(key: u64, payload: &str) -> String {
+    format!("INSERT INTO {KEYSPACE}.{TABLE} (key, field0) VALUES ('user{key}', '{payload}');")
+}
+
+fn read_query(key: u64) -> String {
+    format!("SELECT field0 FROM {KEYSPACE}.{TABLE} WHERE key = 'user{key}'")
+}
+
+/// Executes a query and records its latency, or an error if it failed.
+fn timed_execute(
+    client: &mut CassandraClient,
+    query: &str,
+    consistency: &str,
+    stats: &mut OperationStats,
+) {
+    let start = Instant::now();
+    match client.execute(query, consistency) {
+        Ok(QueryResult::Result(_)) => stats.record(start.elapsed()),
+        _ => stats.record_error(),
+    }
+}
+
+/// Runs `job` on `threads` worker threads, each one with its own connection, and merges
+/// their stats.
+fn run_phase<F>(
+    node: Ipv4Addr,
+    threads: usize,
+    job: F,
+) -> Result<(RunStats, Duration), LoadgenError>
+where
+    F: Fn(usize, &mut CassandraClient) -> RunStats + Send + Sync + Clone + 'static,
+{
+    let start = Instant::now();
+    let mut handles = Vec::new();
+
+    for thread_id in 0..threads {
+        let mut client = connect(node)?;
+        let job = job.clone();
+        handles.push(thread::spawn(move || job(thread_id, &mut client)));
+    }
+
+    let mut stats = RunStats::default();
+    for handle in handles {
+        stats.merge(handle.join().map_err(|_| LoadgenError::ThreadError)?);
+    }
+
+    Ok((stats, start.elapsed()))
+}
+
+fn load(node: Ipv4Addr, workload: &Workload) -> Result<(), LoadgenError> {
+    let threads = workload.threads;
+    let records = workload.record_count;
+    let payload_size = workload.payload_size;
+    let consistency = workload.consistency.clone();
+
+    let (stats, elapsed) = run_phase(node, threads, move |thread_id, client| {
+        let mut rng = rand::thread_rng();
+        let mut stats = RunStats::default();
+
+        for key in (thread_id as u64..records).step_by(threads) {
+            let payload = random_payload(&mut rng, payload_size);
+            timed_execute(
+                client,
+                &insert_query(key, &payload),
+                &consistency,
+                &mut stats.writes,
+            );
+        }
+        stats
+    })?;
+
+    stats.print_summary("LOAD", elapsed);
+    Ok(())
+}
+
+fn run(node: Ipv4Addr, workload: &Workload) -> Result<(), LoadgenError> {
+    let threads = workload.threads;
+    let workload_clone = workload.clone();
+
+    let (stats, elapsed) = run_phase(node, threads, move |thread_id, client| {
+        let workload = &workload_clone;
+        let mut rng = rand::thread_rng();
+        let generator = KeyGenerator::new(workload.distribution, workload.record_count);
+        let mut stats = RunStats::default();
+
+        // The operations are split evenly, the first threads take the remainder
+        let operations = workload.operation_count / threads as u64
+            + u64::from((thread_id as u64) < workload.operation_count % threads as u64);
+
+        for _ in 0..operations {
+            let key = generator.next_key(&mut rng);
+            if rng.gen_bool(workload.read_ratio) {
+                timed_execute(
+                    client,
+                    &read_query(key),
+                    &workload.consistency,
+                    &mut stats.reads,
+                );
+            } else {
+                let payload = random_payload(&mut rng, workload.payload_size);
+                timed_execute(
+                    client,
+                    &insert_query(key, &payload),
+                    &workload.consistency,
+                    &mut stats.writes,
+                );
+            }
+        }
+        stats
+    })?;
+
+    stats.print_summary("RUN", elapsed);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print_usage();
+        return;
+    }
+
+    let (node, workload) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            print_usage();
+            return;
+        }
+    };
+
+    println!(
+        "Running against {}: {:.0}% reads, {} keys, {} records, {} operations, {} bytes payload, {} threads, CL {}",
+        node,
+        workload.read_ratio * 100.0,
+        workload.distribution,
+        workload.record_count,
+        workload.operation_count,
+        workload.payload_size,
+        workload.threads,
+        workload.consistency
+    );
+
+    let result = setup_schema(node, &workload)
+        .and_then(|_| load(node, &workload))
+        .and_then(|_| run(node, &workload));
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let (node, workload) = parse_args(&[]).unwrap();
+        assert_eq!(node, Ipv4Addr::new(127, 0, 0, 1));
+        assert_eq!(workload, Workload::default());
+    }
+
+    #[test]
+    fn test_flags_override_profile() {
+        let args = to_args("--threads 8 --profile b --distribution uniform --node 127.0.0.3");
+        let (node, workload) = parse_args(&args).unwrap();
+
+        assert_eq!(node, Ipv4Addr::new(127, 0, 0, 3));
+        assert_eq!(workload.read_ratio, 0.95);
+        assert_eq!(workload.distribution, KeyDistribution::Uniform);
+        assert_eq!(workload.threads, 8);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(&to_args("--read-ratio 2")).is_err());
+        assert!(parse_args(&to_args("--threads 0")).is_err());
+        assert!(parse_args(&to_args("--records")).is_err());
+        assert!(parse_args(&to_args("--unknown 1")).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+/// Latencies and errors recorded for one kind of operation.
+#[derive(Debug, Default, Clone)]
+pub struct OperationStats {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+impl OperationStats {
+    pub fn record(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    /// Adds the samples of `other` to these stats.
+    pub fn merge(&mut self, other: OperationStats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32
+    }
+
+    /// Returns the latency below which `percentile` percent of the operations fall.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+
+        let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    pub fn max(&self) -> Duration {
+        self.latencies.iter().max().copied().unwrap_or_default()
+    }
+}
+
+/// Stats of a whole run, split by operation.
+#[derive(Debug, Default, Clone)]
+pub struct RunStats {
+    pub reads: OperationStats,
+    pub writes: OperationStats,
+}
+
+impl RunStats {
+    pub fn merge(&mut self, other: RunStats) {
+        self.reads.merge(other.reads);
+        self.writes.merge(other.writes);
+    }
+
+    /// Prints the throughput and latency summary of the run.
+    pub fn print_summary(&self, phase: &str, elapsed: Duration) {
+        let total = self.reads.count() + self.writes.count();
+        let seconds = elapsed.as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            total as f64 / seconds
+        } else {
+            0.0
+        };
+
+        println!("[{}] Run time: {:.2} s", phase, seconds);
+        println!("[{}] Operations: {}", phase, total);
+        println!("[{}] Throughput: {:.2} ops/s", phase, throughput);
+        println!(
+            "\n{:<8} {:<8} {:<8} {:<12} {:<12} {:<12} {:<12} {:<12}",
+            "Op", "Count", "Errors", "Mean(us)", "p50(us)", "p95(us)", "p99(us)", "Max(us)"
+        );
+        for (name, stats) in [("READ", &self.reads), ("WRITE", &self.writes)] {
+            if stats.count() == 0 && stats.errors() == 0 {
+                continue;
+            }
+            println!(
+                "{:<8} {:<8} {:<8} {:<12} {:<12} {:<12} {:<12} {:<12}",
+                name,
+                stats.count(),
+                stats.errors(),
+                stats.mean().as_micros(),
+                stats.percentile(50.0).as_micros(),
+                stats.percentile(95.0).as_micros(),
+                stats.percentile(99.0).as_micros(),
+                stats.max().as_micros()
+            );
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_from_millis(millis: &[u64]) -> OperationStats {
+        let mut stats = OperationStats::default();
+        for m in millis {
+            stats.record(Duration::from_millis(*m));
+        }
+        stats
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let stats = stats_from_millis(&(1..=100).collect::<Vec<u64>>());
+
+        assert_eq!(stats.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(stats.percentile(95.0), Duration::from_millis(95));
+        assert_eq!(stats.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(stats.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(stats.max(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_mean() {
+        let stats = stats_from_millis(&[10, 20, 30]);
+        assert_eq!(stats.mean(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_empty_stats() {
+        let stats = OperationStats::default();
+        assert_eq!(stats.mean(), Duration::ZERO);
+        assert_eq!(stats.percentile(99.0), Duration::ZERO);
+        assert_eq!(stats.max(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut stats = stats_from_millis(&[1, 2]);
+        let mut other = stats_from_millis(&[3]);
+        other.record_error();
+
+        stats.merge(other);
+
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.errors(), 1);
+    }
+}
@@ -0,0 +1,208 @@
+use rand::Rng;
+use std::fmt;
+
+use crate::errors::LoadgenError;
+
+/// The zipfian constant used by YCSB.
+const ZIPFIAN_CONSTANT: f64 = 0.99;
+
+/// How keys are picked for each operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    /// Every key is equally likely to be picked.
+    Uniform,
+    /// A few keys are picked much more often than the rest.
+    Zipfian,
+}
+
+impl KeyDistribution {
+    pub fn from_str(s: &str) -> Result<Self, LoadgenError> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(KeyDistribution::Uniform),
+            "zipfian" => Ok(KeyDistribution::Zipfian),
+            _ => Err(LoadgenError::InvalidArgument(format!(
+                "unknown key distribution '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDistribution::Uniform => write!(f, "uniform"),
+            KeyDistribution::Zipfian => write!(f, "zipfian"),
+        }
+    }
+}
+
+/// Parameters of a benchmark run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    /// Fraction of the operations that are reads, between 0 and 1. The rest are writes.
+    pub read_ratio: f64,
+    /// How keys are picked for each operation.
+    pub distribution: KeyDistribution,
+    /// Number of rows inserted before the run starts.
+    pub record_count: u64,
+    /// Total number of operations issued during the run, shared among all threads.
+    pub operation_count: u64,
+    /// Size in bytes of the value written by each insert.
+    pub payload_size: usize,
+    /// Number of concurrent clients.
+    pub threads: usize,
+    /// Consistency level used for every query.
+    pub consistency: String,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            read_ratio: 0.5,
+            distribution: KeyDistribution::Zipfian,
+            record_count: 1000,
+            operation_count: 10000,
+            payload_size: 100,
+            threads: 4,
+            consistency: "quorum".to_string(),
+        }
+    }
+}
+
+impl Workload {
+    /// Creates a workload from one of the YCSB core profiles.
+    ///
+    /// - `a`: update heavy, 50% reads and 50% writes.
+    /// - `b`: read mostly, 95% reads and 5% writes.
+    /// - `c`: read only.
+    ///
+    /// All of them pick keys with a zipfian distribution.
+    pub fn from_profile(profile: &str) -> Result<Self, LoadgenError> {
+        let read_ratio = match profile.to_lowercase().as_str() {
+            "a" => 0.5,
+            "b" => 0.95,
+            "c" => 1.0,
+            _ => {
+                return Err(LoadgenError::InvalidArgument(format!(
+                    "unknown profile '{}'",
+                    profile
+                )))
+            }
+        };
+
+        Ok(Workload {
+            read_ratio,
+            distribution: KeyDistribution::Zipfian,
+            ..Default::default()
+        })
+    }
+}
+
+/// Picks keys in `[0, item_count)` following a `KeyDistribution`.
+///
+/// The zipfian generator follows the algorithm from "Quickly Generating Billion-Record Synthetic
+/// Databases" (Gray et al.), the same one used by YCSB.
+pub struct KeyGenerator {
+    distribution: KeyDistribution,
+    item_count: u64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+    theta: f64,
+}
+
+impl KeyGenerator {
+    pub fn new(distribution: KeyDistribution, item_count: u64) -> Self {
+        let item_count = item_count.max(1);
+        let theta = ZIPFIAN_CONSTANT;
+        let zeta2 = zeta(2, theta);
+        let zetan = zeta(item_count, theta);
+
+        KeyGenerator {
+            distribution,
+            item_count,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / item_count as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+            theta,
+        }
+    }
+
+    /// Returns the next key.
+    pub fn next_key<R: Rng>(&self, rng: &mut R) -> u64 {
+        match self.distribution {
+            KeyDistribution::Uniform => rng.gen_range(0..self.item_count),
+            KeyDistribution::Zipfian => {
+                let u: f64 = rng.gen();
+                let uz = u * self.zetan;
+
+                if uz < 1.0 {
+                    return 0;
+                }
+                if uz < 1.0 + 0.5f64.powf(self.theta) {
+                    return 1.min(self.item_count - 1);
+                }
+
+                let key = (self.item_count as f64
+                    * (self.eta * u - self.eta + 1.0).powf(self.alpha))
+                    as u64;
+                key.min(self.item_count - 1)
+            }
+        }
+    }
+}
+
+fn zeta(n: u64, theta: f64) -> f64 {
+    (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        assert_eq!(Workload::from_profile("a").unwrap().read_ratio, 0.5);
+        assert_eq!(Workload::from_profile("B").unwrap().read_ratio, 0.95);
+        assert_eq!(Workload::from_profile("c").unwrap().read_ratio, 1.0);
+        assert!(Workload::from_profile("z").is_err());
+    }
+
+    #[test]
+    fn test_distribution_from_str() {
+        assert_eq!(
+            KeyDistribution::from_str("uniform").unwrap(),
+            KeyDistribution::Uniform
+        );
+        assert_eq!(
+            KeyDistribution::from_str("Zipfian").unwrap(),
+            KeyDistribution::Zipfian
+        );
+        assert!(KeyDistribution::from_str("latest").is_err());
+    }
+
+    #[test]
+    fn test_keys_are_in_range() {
+        let mut rng = rand::thread_rng();
+        for distribution in [KeyDistribution::Uniform, KeyDistribution::Zipfian] {
+            let generator = KeyGenerator::new(distribution, 50);
+            for _ in 0..1000 {
+                assert!(generator.next_key(&mut rng) < 50);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zipfian_favours_first_keys() {
+        let mut rng = rand::thread_rng();
+        let generator = KeyGenerator::new(KeyDistribution::Zipfian, 1000);
+
+        let hits = (0..10000)
+            .filter(|_| generator.next_key(&mut rng) < 10)
+            .count();
+
+        // With a uniform distribution only 1% of the keys would fall in the first ten
+        assert!(hits > 1000);
+    }
+}
@@ -1,7 +1,14 @@
-use chrono::Utc;
+use chrono::{SecondsFormat, Utc};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
 use std::fs::OpenOptions;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Number of log records kept in memory by default.
+const RECENT_LOGS_CAPACITY: usize = 500;
 
 #[derive(Debug, Clone)]
 enum LogLevel {
@@ -10,6 +17,105 @@ enum LogLevel {
     Error,
 }
 
+impl LogLevel {
+    fn level(&self) -> Level {
+        match self {
+            LogLevel::Info(_) => Level::Info,
+            LogLevel::Warn => Level::Warn,
+            LogLevel::Error => Level::Error,
+        }
+    }
+}
+
+/// How important a log record is. Loggers drop the records below their minimum level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// Returns the name of the level, as written in the records.
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = LoggerError;
+
+    /// Parses the name of a level, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "info" => Ok(Level::Info),
+            "warn" | "warning" => Ok(Level::Warn),
+            "error" => Ok(Level::Error),
+            _ => Err(LoggerError::InvalidSetting(format!(
+                "unknown log level {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How log records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// A line of text per record, colored in the console.
+    #[default]
+    Text,
+    /// A JSON object per line, with the fields of the record, for log shippers (Logstash,
+    /// Filebeat) to index without parsing the text.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = LoggerError;
+
+    /// Parses `text` or `json`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LoggerError::InvalidSetting(format!(
+                "unknown log format {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// The part of a node a log record comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    /// The gossip rounds and the changes of the ring they find.
+    Gossip,
+    /// The connections of the clients and their queries.
+    Native,
+    /// The messages exchanged with the other nodes, with the rows redistributed to them.
+    Internode,
+    /// The tables on disk: the commit log, flushes and compactions.
+    Storage,
+}
+
+impl Component {
+    /// Returns the tag of the component, as written in the records.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Component::Gossip => "GOSSIP",
+            Component::Native => "NATIVE",
+            Component::Internode => "INTERNODE",
+            Component::Storage => "STORAGE",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Color {
     Red,
@@ -38,6 +144,15 @@ impl Color {
 #[derive(Debug, Clone)]
 pub struct Logger {
     log_file: PathBuf,
+    // Address of the node, written in the JSON records
+    node: String,
+    correlation_id: Option<String>,
+    component: Option<Component>,
+    format: LogFormat,
+    min_level: Level,
+    // Last records written, shared among all the clones of this logger
+    recent_logs: Arc<Mutex<VecDeque<String>>>,
+    recent_logs_capacity: usize,
 }
 
 impl Logger {
@@ -70,20 +185,140 @@ impl Logger {
             .open(&log_file)
             .map_err(LoggerError::from)?;
 
-        Ok(Logger { log_file })
+        Ok(Logger {
+            log_file,
+            node: ip.to_string(),
+            correlation_id: None,
+            component: None,
+            format: LogFormat::Text,
+            min_level: Level::Info,
+            recent_logs: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_LOGS_CAPACITY))),
+            recent_logs_capacity: RECENT_LOGS_CAPACITY,
+        })
+    }
+
+    /// Sets how many log records are kept in memory.
+    ///
+    /// # Parameters
+    /// - `capacity`: The maximum number of records kept. Older records are dropped first.
+    pub fn with_recent_logs_capacity(mut self, capacity: usize) -> Self {
+        self.recent_logs_capacity = capacity;
+        self
+    }
+
+    /// Sets how the records of this logger and the clones made after it are written.
+    ///
+    /// # Parameters
+    /// - `format`: Text lines, as by default, or a JSON object per line.
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the least important level of the records written by this logger and the clones made
+    /// after it. Records below it are dropped, without being kept in memory either.
+    ///
+    /// # Parameters
+    /// - `level`: The minimum level. Every record is written by default.
+    pub fn with_min_level(mut self, level: Level) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    /// Returns the last `n` log records written by this logger or any of its clones,
+    /// from oldest to newest.
+    ///
+    /// # Parameters
+    /// - `n`: The maximum number of records to return.
+    pub fn recent_logs(&self, n: usize) -> Result<Vec<String>, LoggerError> {
+        let recent_logs = self
+            .recent_logs
+            .lock()
+            .map_err(|_| LoggerError::LockError)?;
+        let skip = recent_logs.len().saturating_sub(n);
+        Ok(recent_logs.iter().skip(skip).cloned().collect())
+    }
+
+    /// Returns a copy of this logger that tags every message with `correlation_id`.
+    ///
+    /// The copy writes to the same file, so the lines of a single query can be grepped
+    /// out of the log of every node it went through.
+    ///
+    /// # Parameters
+    /// - `correlation_id`: The identifier of the query being logged.
+    pub fn with_correlation_id(&self, correlation_id: &str) -> Self {
+        Logger {
+            correlation_id: Some(correlation_id.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the correlation ID this logger tags its messages with, if any.
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+
+    /// Returns a copy of this logger that tags every record with the component of the node it
+    /// comes from, keeping its correlation ID.
+    ///
+    /// JSON records carry the tag as a field of their own, so they can be filtered by component.
+    /// Text lines are written as they were, as their messages already start with what logged them.
+    ///
+    /// # Parameters
+    /// - `component`: The component the records of the copy come from.
+    pub fn with_component(&self, component: Component) -> Self {
+        Logger {
+            component: Some(component),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the component this logger tags its records with, if any.
+    pub fn component(&self) -> Option<Component> {
+        self.component
+    }
+
+    // Formats a record as a JSON object, without the line break
+    fn json_record(&self, level: Level, message: &str) -> String {
+        let mut record = format!(
+            "{{\"timestamp\":{},\"level\":\"{}\",\"node\":{}",
+            json_string(&Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+            level.name(),
+            json_string(&self.node),
+        );
+        if let Some(component) = self.component {
+            let _ = write!(record, ",\"component\":\"{}\"", component.tag());
+        }
+        if let Some(id) = &self.correlation_id {
+            let _ = write!(record, ",\"correlation_id\":{}", json_string(id));
+        }
+        let _ = write!(record, ",\"message\":{}}}", json_string(message));
+        record
     }
 
     // Generic method for writing log messages
     fn log(&self, level: LogLevel, message: &str, to_console: bool) -> Result<(), LoggerError> {
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        let log_message = match &level {
-            LogLevel::Info(_) => format!("[INFO] [{}]: {}\n", timestamp, message),
-            LogLevel::Warn => format!("[WARN] [{}]: {}\n", timestamp, message),
-            LogLevel::Error => format!("[ERROR] [{}]: {}\n", timestamp, message),
+        if level.level() < self.min_level {
+            return Ok(());
+        }
+
+        let log_message = match self.format {
+            LogFormat::Text => {
+                let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                let message = match &self.correlation_id {
+                    Some(id) => format!("[{}] {}", id, message),
+                    None => message.to_string(),
+                };
+                format!("[{}] [{}]: {}\n", level.level().name(), timestamp, message)
+            }
+            LogFormat::Json => format!("{}\n", self.json_record(level.level(), message)),
         };
 
-        // If logging to console, apply colors
-        if to_console {
+        // If logging to console, apply colors, unless the records are JSON
+        if to_console && self.format == LogFormat::Json {
+            print!("{}", log_message);
+            io::stdout().flush().map_err(LoggerError::from)?;
+        } else if to_console {
             let colored_message = match &level {
                 LogLevel::Info(color) => format!("{}{}\x1b[0m", color.to_ansi_code(), log_message),
                 LogLevel::Warn => format!("\x1b[93m{}\x1b[0m", log_message), // Bright Yellow
@@ -93,6 +328,20 @@ impl Logger {
             io::stdout().flush().map_err(LoggerError::from)?;
         }
 
+        // Keep the record in memory, dropping the oldest one if the buffer is full
+        {
+            let mut recent_logs = self
+                .recent_logs
+                .lock()
+                .map_err(|_| LoggerError::LockError)?;
+            if recent_logs.len() >= self.recent_logs_capacity {
+                recent_logs.pop_front();
+            }
+            if self.recent_logs_capacity > 0 {
+                recent_logs.push_back(log_message.trim_end().to_string());
+            }
+        }
+
         // Open the file, write the log message, and close the file
         let mut file = OpenOptions::new()
             .create(true)
@@ -135,10 +384,33 @@ impl Logger {
     }
 }
 
+// Quotes a string as a JSON string, escaping what JSON does not allow in one
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[derive(Debug)]
 pub enum LoggerError {
     IoError(std::io::Error),
     InvalidPath(String), // Nueva variante para manejar rutas inválidas
+    LockError,
+    InvalidSetting(String),
 }
 
 impl std::fmt::Display for LoggerError {
@@ -146,6 +418,8 @@ impl std::fmt::Display for LoggerError {
         match self {
             LoggerError::IoError(e) => write!(f, "I/O Error: {}", e),
             LoggerError::InvalidPath(msg) => write!(f, "Invalid Path: {}", msg),
+            LoggerError::LockError => write!(f, "Failed to acquire lock"),
+            LoggerError::InvalidSetting(msg) => write!(f, "Invalid Setting: {}", msg),
         }
     }
 }
@@ -155,6 +429,8 @@ impl std::error::Error for LoggerError {
         match self {
             LoggerError::IoError(e) => Some(e),
             LoggerError::InvalidPath(_) => None, // Las rutas inválidas no tienen una fuente de error adicional
+            LoggerError::LockError => None,
+            LoggerError::InvalidSetting(_) => None,
         }
     }
 }
@@ -195,6 +471,141 @@ mod tests {
         fs::remove_dir_all(log_dir).expect("Failed to remove test directory");
     }
 
+    #[test]
+    fn test_logging_with_correlation_id() {
+        let log_dir = Path::new("/tmp/test_logs_correlation");
+        fs::create_dir_all(log_dir).expect("Failed to create test directory");
+
+        let ip = "127.0.0.2";
+        let logger = Logger::new(log_dir, ip).expect("Failed to create logger");
+        let query_logger = logger.with_correlation_id("a1b2c3d4");
+
+        assert_eq!(logger.correlation_id(), None);
+        assert_eq!(query_logger.correlation_id(), Some("a1b2c3d4"));
+
+        logger
+            .info("Untagged message.", Color::Green, false)
+            .expect("Failed to log message");
+        query_logger
+            .warn("Tagged message.", false)
+            .expect("Failed to log message");
+
+        let log_file_path = log_dir.join(format!("node_{}.log", ip));
+        let log_contents = fs::read_to_string(&log_file_path).expect("Failed to read log file");
+
+        assert!(log_contents.contains("[a1b2c3d4] Tagged message."));
+        assert!(!log_contents.contains("[a1b2c3d4] Untagged message."));
+
+        // Limpieza
+        fs::remove_dir_all(log_dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_recent_logs_ring_buffer() {
+        let log_dir = Path::new("/tmp/test_logs_recent");
+        fs::create_dir_all(log_dir).expect("Failed to create test directory");
+
+        let logger = Logger::new(log_dir, "127.0.0.3")
+            .expect("Failed to create logger")
+            .with_recent_logs_capacity(3);
+        let query_logger = logger.with_correlation_id("a1b2c3d4");
+
+        for i in 0..4 {
+            logger
+                .info(&format!("Message {}", i), Color::White, false)
+                .expect("Failed to log message");
+        }
+        query_logger
+            .error("Tagged message", false)
+            .expect("Failed to log message");
+
+        // Clones share the buffer and only the last three records are kept
+        let recent_logs = logger.recent_logs(10).expect("Failed to read recent logs");
+        assert_eq!(recent_logs.len(), 3);
+        assert!(recent_logs[0].ends_with("Message 2"));
+        assert!(recent_logs[1].ends_with("Message 3"));
+        assert!(recent_logs[2].ends_with("[a1b2c3d4] Tagged message"));
+
+        let last = query_logger
+            .recent_logs(1)
+            .expect("Failed to read recent logs");
+        assert_eq!(last.len(), 1);
+        assert!(last[0].starts_with("[ERROR]"));
+
+        // Limpieza
+        fs::remove_dir_all(log_dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_json_records_with_component() {
+        let log_dir = Path::new("/tmp/test_logs_json");
+        fs::create_dir_all(log_dir).expect("Failed to create test directory");
+
+        let logger = Logger::new(log_dir, "127.0.0.4")
+            .expect("Failed to create logger")
+            .with_format(LogFormat::Json);
+        let gossip_logger = logger.with_component(Component::Gossip);
+        let query_logger = gossip_logger.with_correlation_id("a1b2c3d4");
+        assert_eq!(query_logger.component(), Some(Component::Gossip));
+
+        gossip_logger
+            .info("New Gossip Round", Color::White, false)
+            .expect("Failed to log message");
+        query_logger
+            .error("Said \"no\"\n", false)
+            .expect("Failed to log message");
+
+        let log_contents = fs::read_to_string(log_dir.join("node_127.0.0.4.log"))
+            .expect("Failed to read log file");
+        let lines: Vec<&str> = log_contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"timestamp\":\""));
+        assert!(lines[0].ends_with(
+            "\"level\":\"INFO\",\"node\":\"127.0.0.4\",\"component\":\"GOSSIP\",\"message\":\"New Gossip Round\"}"
+        ));
+        assert!(lines[1].ends_with(
+            "\"level\":\"ERROR\",\"node\":\"127.0.0.4\",\"component\":\"GOSSIP\",\"correlation_id\":\"a1b2c3d4\",\"message\":\"Said \\\"no\\\"\\n\"}"
+        ));
+
+        // Limpieza
+        fs::remove_dir_all(log_dir).expect("Failed to remove test directory");
+    }
+
+    #[test]
+    fn test_records_below_the_min_level_are_dropped() {
+        let log_dir = Path::new("/tmp/test_logs_level");
+        fs::create_dir_all(log_dir).expect("Failed to create test directory");
+
+        let logger = Logger::new(log_dir, "127.0.0.5")
+            .expect("Failed to create logger")
+            .with_min_level("warn".parse().expect("Failed to parse level"));
+        let query_logger = logger.with_correlation_id("a1b2c3d4");
+
+        logger
+            .info("Dropped", Color::White, false)
+            .expect("Failed to log message");
+        query_logger
+            .warn("Kept", false)
+            .expect("Failed to log message");
+        logger
+            .error("Kept too", false)
+            .expect("Failed to log message");
+
+        let recent_logs = logger.recent_logs(10).expect("Failed to read recent logs");
+        assert_eq!(recent_logs.len(), 2);
+        assert!(recent_logs[0].starts_with("[WARN]"));
+        assert!(recent_logs[1].starts_with("[ERROR]"));
+        let log_contents = fs::read_to_string(log_dir.join("node_127.0.0.5.log"))
+            .expect("Failed to read log file");
+        assert!(!log_contents.contains("Dropped"));
+
+        assert!("debug".parse::<Level>().is_err());
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+
+        // Limpieza
+        fs::remove_dir_all(log_dir).expect("Failed to remove test directory");
+    }
+
     #[test]
     fn test_invalid_path() {
         let invalid_path = Path::new("/invalid/path");
@@ -0,0 +1,141 @@
+use crate::errors::NativeError;
+
+/// Name of the CRC32 algorithm in the `CHECKSUM` option of a `STARTUP` message.
+pub const CRC32: &str = "crc32";
+
+/// Bytes the checksum adds at the end of the body of a frame.
+pub const CHECKSUM_LENGTH: usize = 4;
+
+/// Polynomial of the CRC32 used by zlib and Ethernet, in reversed bit order.
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Algorithms that can checksum the body of the frames of a connection, once negotiated in its
+/// `STARTUP` message, to detect frames corrupted on the way when TLS (which already does) is
+/// not used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Checksum {
+    /// The body is followed by its CRC32 as an [int].
+    Crc32,
+}
+
+impl Checksum {
+    /// Returns the algorithm named `name` (case-insensitive), if it is supported.
+    pub fn from_name(name: &str) -> Result<Self, NativeError> {
+        match name.to_lowercase().as_str() {
+            CRC32 => Ok(Checksum::Crc32),
+            _ => Err(NativeError::InvalidVariant),
+        }
+    }
+
+    /// Returns the name of the algorithm, as sent in the `CHECKSUM` option.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Checksum::Crc32 => CRC32,
+        }
+    }
+
+    /// Appends the checksum of the body of a frame to it.
+    pub fn append(&self, body: &mut Vec<u8>) {
+        match self {
+            Checksum::Crc32 => {
+                let checksum = crc32(body);
+                body.extend_from_slice(&checksum.to_be_bytes());
+            }
+        }
+    }
+
+    /// Removes the checksum appended by `append` from the body of a frame, failing if it does
+    /// not match the body.
+    pub fn verify(&self, mut body: Vec<u8>) -> Result<Vec<u8>, NativeError> {
+        match self {
+            Checksum::Crc32 => {
+                let start = body
+                    .len()
+                    .checked_sub(CHECKSUM_LENGTH)
+                    .ok_or(NativeError::ChecksumError)?;
+                let expected: [u8; CHECKSUM_LENGTH] = body[start..]
+                    .try_into()
+                    .map_err(|_| NativeError::ChecksumError)?;
+                body.truncate(start);
+                if crc32(&body) != u32::from_be_bytes(expected) {
+                    return Err(NativeError::ChecksumError);
+                }
+                Ok(body)
+            }
+        }
+    }
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the CRC32 of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// Returns the CRC32 of some bytes followed by `bytes`, given the CRC32 `crc` of the first ones
+/// (`0` for none), so the CRC32 of a file can be computed a chunk at a time.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_of_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+    }
+
+    #[test]
+    fn crc32_of_chunks_is_the_crc32_of_their_bytes() {
+        let bytes = b"The quick brown fox jumps over the lazy dog";
+        let crc = bytes.chunks(5).fold(0, crc32_update);
+        assert_eq!(crc, crc32(bytes));
+    }
+
+    #[test]
+    fn crc32_detects_corrupted_bodies() {
+        let mut body = b"SELECT * FROM flights".to_vec();
+        Checksum::Crc32.append(&mut body);
+        assert_eq!(body.len(), 21 + CHECKSUM_LENGTH);
+        assert_eq!(
+            Checksum::Crc32.verify(body.clone()).unwrap(),
+            b"SELECT * FROM flights"
+        );
+
+        body[3] ^= 0x10;
+        assert!(Checksum::Crc32.verify(body).is_err());
+        assert!(Checksum::Crc32.verify(vec![1, 2]).is_err());
+        assert_eq!(Checksum::from_name("CRC32").unwrap(), Checksum::Crc32);
+        assert!(Checksum::from_name("md5").is_err());
+    }
+}
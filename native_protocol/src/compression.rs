@@ -0,0 +1,530 @@
+use std::io::{Cursor, Read};
+
+use crate::{errors::NativeError, framing::MAX_BODY_LENGTH, types::Int};
+
+/// Name of the LZ4 algorithm in the `COMPRESSION` option of a `STARTUP` message.
+pub const LZ4: &str = "lz4";
+/// Name of the Snappy algorithm in the `COMPRESSION` option of a `STARTUP` message.
+pub const SNAPPY: &str = "snappy";
+
+/// Bytes at the end of a block that are always literals.
+const LAST_LITERALS: usize = 5;
+/// A match can not start in the last `MF_LIMIT` bytes of a block.
+const MF_LIMIT: usize = 12;
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_LOG: u32 = 12;
+/// Longest match a Snappy copy with a 2 bytes offset can hold.
+const SNAPPY_MAX_COPY: usize = 64;
+/// Longest literal a Snappy tag holds without extra length bytes.
+const SNAPPY_TAG_LITERAL: usize = 60;
+
+/// Algorithms that can compress the body of the frames of a connection, once negotiated
+/// in its `STARTUP` message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    /// The body is the length of the uncompressed body as an [int], followed by an LZ4 block.
+    Lz4,
+    /// The body is a raw Snappy block, which starts with the length of the uncompressed body
+    /// as a varint.
+    Snappy,
+}
+
+impl Compression {
+    /// Returns the algorithm named `name` (case-insensitive), if it is supported.
+    pub fn from_name(name: &str) -> Result<Self, NativeError> {
+        match name.to_lowercase().as_str() {
+            LZ4 => Ok(Compression::Lz4),
+            SNAPPY => Ok(Compression::Snappy),
+            _ => Err(NativeError::InvalidVariant),
+        }
+    }
+
+    /// Returns the name of the algorithm, as sent in the `COMPRESSION` option.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Lz4 => LZ4,
+            Compression::Snappy => SNAPPY,
+        }
+    }
+
+    /// Compresses the body of a frame.
+    pub fn compress(&self, body: &[u8]) -> Result<Vec<u8>, NativeError> {
+        match self {
+            Compression::Lz4 => {
+                let length =
+                    Int::try_from(body.len()).map_err(|_| NativeError::CompressionError)?;
+                let mut bytes = length.to_be_bytes().to_vec();
+                bytes.extend(lz4_compress(body));
+                Ok(bytes)
+            }
+            Compression::Snappy => {
+                u32::try_from(body.len()).map_err(|_| NativeError::CompressionError)?;
+                Ok(snappy_compress(body))
+            }
+        }
+    }
+
+    /// Decompresses the body of a frame compressed with `compress`.
+    ///
+    /// # Errors
+    /// - `NativeError::CompressionError` if the body is corrupted, or would decompress to more
+    ///   than `MAX_BODY_LENGTH` bytes, which is rejected before allocating for it.
+    pub fn decompress(&self, body: &[u8]) -> Result<Vec<u8>, NativeError> {
+        match self {
+            Compression::Lz4 => {
+                let mut cursor = Cursor::new(body);
+                let mut length_bytes = [0u8; 4];
+                cursor
+                    .read_exact(&mut length_bytes)
+                    .map_err(|_| NativeError::CursorError)?;
+                let length = usize::try_from(Int::from_be_bytes(length_bytes))
+                    .map_err(|_| NativeError::CompressionError)?;
+                if length > MAX_BODY_LENGTH {
+                    return Err(NativeError::CompressionError);
+                }
+
+                let decompressed = lz4_decompress(&body[4..], length)?;
+                if decompressed.len() != length {
+                    return Err(NativeError::CompressionError);
+                }
+                Ok(decompressed)
+            }
+            Compression::Snappy => snappy_decompress(body),
+        }
+    }
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_sequence(input: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes([
+        input[position],
+        input[position + 1],
+        input[position + 2],
+        input[position + 3],
+    ])
+}
+
+/// Writes a length that does not fit in the 4 bits of a token, as a run of 255 bytes
+/// followed by the rest.
+fn write_extra_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        output.push(255);
+        length -= 255;
+    }
+    output.push(length as u8);
+}
+
+fn write_sequence(
+    output: &mut Vec<u8>,
+    literals: &[u8],
+    offset_and_length: Option<(usize, usize)>,
+) {
+    let literal_token = literals.len().min(15) as u8;
+    let match_token = offset_and_length.map_or(0, |(_, length)| (length - MIN_MATCH).min(15) as u8);
+    output.push(literal_token << 4 | match_token);
+    if literals.len() >= 15 {
+        write_extra_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+
+    if let Some((offset, length)) = offset_and_length {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if length - MIN_MATCH >= 15 {
+            write_extra_length(output, length - MIN_MATCH - 15);
+        }
+    }
+}
+
+/// Compresses `input` into an LZ4 block, matching every sequence of 4 bytes against the last
+/// one with the same hash.
+fn lz4_compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut position = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        let match_end_limit = input.len() - LAST_LITERALS;
+
+        while position < match_limit {
+            let sequence = read_sequence(input, position);
+            let slot = hash(sequence);
+            let candidate = table[slot];
+            table[slot] = position;
+
+            if candidate == usize::MAX
+                || position - candidate > MAX_OFFSET
+                || read_sequence(input, candidate) != sequence
+            {
+                position += 1;
+                continue;
+            }
+
+            let mut length = MIN_MATCH;
+            while position + length < match_end_limit
+                && input[candidate + length] == input[position + length]
+            {
+                length += 1;
+            }
+
+            write_sequence(
+                &mut output,
+                &input[anchor..position],
+                Some((position - candidate, length)),
+            );
+            position += length;
+            anchor = position;
+        }
+    }
+
+    write_sequence(&mut output, &input[anchor..], None);
+    output
+}
+
+fn read_byte(cursor: &mut Cursor<&[u8]>) -> Result<u8, NativeError> {
+    let mut byte = [0u8];
+    cursor
+        .read_exact(&mut byte)
+        .map_err(|_| NativeError::CursorError)?;
+    Ok(byte[0])
+}
+
+fn read_length(cursor: &mut Cursor<&[u8]>, token_length: usize) -> Result<usize, NativeError> {
+    let mut length = token_length;
+    if token_length == 15 {
+        loop {
+            let byte = read_byte(cursor)?;
+            length += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
+
+/// Decompresses an LZ4 block, which must not expand to more than `max_length` bytes.
+fn lz4_decompress(input: &[u8], max_length: usize) -> Result<Vec<u8>, NativeError> {
+    let mut output: Vec<u8> = Vec::with_capacity(max_length);
+    let mut cursor = Cursor::new(input);
+
+    loop {
+        let token = read_byte(&mut cursor)?;
+
+        let literals = read_length(&mut cursor, (token >> 4) as usize)?;
+        if output.len() + literals > max_length {
+            return Err(NativeError::CompressionError);
+        }
+        let start = output.len();
+        output.resize(start + literals, 0);
+        cursor
+            .read_exact(&mut output[start..])
+            .map_err(|_| NativeError::CursorError)?;
+
+        // The last sequence only has literals
+        if cursor.position() as usize == input.len() {
+            return Ok(output);
+        }
+
+        let mut offset_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut offset_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        let offset = u16::from_le_bytes(offset_bytes) as usize;
+        if offset == 0 || offset > output.len() {
+            return Err(NativeError::CompressionError);
+        }
+
+        let length = read_length(&mut cursor, (token & 0x0F) as usize)? + MIN_MATCH;
+        if output.len() + length > max_length {
+            return Err(NativeError::CompressionError);
+        }
+        // The match may overlap the bytes it writes, so they are copied one by one
+        let from = output.len() - offset;
+        for i in 0..length {
+            output.push(output[from + i]);
+        }
+    }
+}
+
+/// Writes `value` as a varint: 7 bits per byte, the least significant first, with the high
+/// bit set in every byte but the last.
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(cursor: &mut Cursor<&[u8]>) -> Result<usize, NativeError> {
+    let mut value = 0usize;
+    // The length of a Snappy block is a 32 bits integer, which takes up to 5 bytes
+    for shift in (0..35).step_by(7) {
+        let byte = read_byte(cursor)?;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(NativeError::CompressionError)
+}
+
+fn write_snappy_literal(output: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let length = literal.len() - 1;
+    if length < SNAPPY_TAG_LITERAL {
+        output.push((length as u8) << 2);
+    } else {
+        // The tags 60 to 63 say the length follows in 1 to 4 bytes
+        let length_bytes = length.to_le_bytes();
+        let used = (usize::BITS - length.leading_zeros()).div_ceil(8) as usize;
+        output.push(((SNAPPY_TAG_LITERAL + used - 1) as u8) << 2);
+        output.extend_from_slice(&length_bytes[..used]);
+    }
+    output.extend_from_slice(literal);
+}
+
+/// Writes a match as copies with a 2 bytes offset, which hold up to `SNAPPY_MAX_COPY` bytes each.
+fn write_snappy_copy(output: &mut Vec<u8>, offset: usize, mut length: usize) {
+    while length > 0 {
+        let copied = length.min(SNAPPY_MAX_COPY);
+        output.push(((copied - 1) as u8) << 2 | 0b10);
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        length -= copied;
+    }
+}
+
+/// Compresses `input` into a raw Snappy block, matching every sequence of 4 bytes against the
+/// last one with the same hash, as `lz4_compress` does.
+fn snappy_compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    write_varint(&mut output, input.len());
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut position = 0;
+
+    while position + MIN_MATCH <= input.len() {
+        let sequence = read_sequence(input, position);
+        let slot = hash(sequence);
+        let candidate = table[slot];
+        table[slot] = position;
+
+        if candidate == usize::MAX
+            || position - candidate > MAX_OFFSET
+            || read_sequence(input, candidate) != sequence
+        {
+            position += 1;
+            continue;
+        }
+
+        let mut length = MIN_MATCH;
+        while position + length < input.len()
+            && input[candidate + length] == input[position + length]
+        {
+            length += 1;
+        }
+
+        write_snappy_literal(&mut output, &input[anchor..position]);
+        write_snappy_copy(&mut output, position - candidate, length);
+        position += length;
+        anchor = position;
+    }
+
+    write_snappy_literal(&mut output, &input[anchor..]);
+    output
+}
+
+/// Decompresses a raw Snappy block, which must expand to the length it starts with.
+fn snappy_decompress(input: &[u8]) -> Result<Vec<u8>, NativeError> {
+    let mut cursor = Cursor::new(input);
+    let length = read_varint(&mut cursor)?;
+    if length > MAX_BODY_LENGTH {
+        return Err(NativeError::CompressionError);
+    }
+    let mut output: Vec<u8> = Vec::new();
+
+    while (cursor.position() as usize) < input.len() {
+        let tag = read_byte(&mut cursor)?;
+        let (offset, copied) = match tag & 0b11 {
+            0b00 => {
+                let mut literal = (tag >> 2) as usize;
+                if literal >= SNAPPY_TAG_LITERAL {
+                    let mut length_bytes = [0u8; 8];
+                    cursor
+                        .read_exact(&mut length_bytes[..literal - SNAPPY_TAG_LITERAL + 1])
+                        .map_err(|_| NativeError::CursorError)?;
+                    literal = usize::from_le_bytes(length_bytes);
+                }
+                let literal = literal + 1;
+                // The literal is in the block, so it can not be longer than the bytes left
+                let left = input.len() - cursor.position() as usize;
+                if output.len() + literal > length || literal > left {
+                    return Err(NativeError::CompressionError);
+                }
+                let start = output.len();
+                output.resize(start + literal, 0);
+                cursor
+                    .read_exact(&mut output[start..])
+                    .map_err(|_| NativeError::CursorError)?;
+                continue;
+            }
+            // Copies of 4 to 11 bytes with an offset of 11 bits, 3 of them in the tag
+            0b01 => {
+                let offset = ((tag >> 5) as usize) << 8 | read_byte(&mut cursor)? as usize;
+                (offset, ((tag >> 2) & 0b111) as usize + 4)
+            }
+            0b10 => {
+                let mut offset_bytes = [0u8; 2];
+                cursor
+                    .read_exact(&mut offset_bytes)
+                    .map_err(|_| NativeError::CursorError)?;
+                (
+                    u16::from_le_bytes(offset_bytes) as usize,
+                    (tag >> 2) as usize + 1,
+                )
+            }
+            _ => {
+                let mut offset_bytes = [0u8; 4];
+                cursor
+                    .read_exact(&mut offset_bytes)
+                    .map_err(|_| NativeError::CursorError)?;
+                (
+                    u32::from_le_bytes(offset_bytes) as usize,
+                    (tag >> 2) as usize + 1,
+                )
+            }
+        };
+
+        if offset == 0 || offset > output.len() || output.len() + copied > length {
+            return Err(NativeError::CompressionError);
+        }
+        // The copy may overlap the bytes it writes, so they are copied one by one
+        let from = output.len() - offset;
+        for i in 0..copied {
+            output.push(output[from + i]);
+        }
+    }
+
+    if output.len() != length {
+        return Err(NativeError::CompressionError);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lz4_compresses_repeated_bodies() {
+        let body = "SELECT * FROM flights WHERE airport = 'EZE';".repeat(50);
+
+        let compressed = Compression::Lz4.compress(body.as_bytes()).unwrap();
+        assert!(compressed.len() < body.len() / 4);
+
+        let decompressed = Compression::Lz4.decompress(&compressed).unwrap();
+        assert_eq!(decompressed, body.as_bytes());
+    }
+
+    #[test]
+    fn lz4_round_trips_short_and_incompressible_bodies() {
+        let mut seed = 0x2545_f491u32;
+        let incompressible: Vec<u8> = (0..1000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        let long_run = vec![0u8; 70000];
+
+        for body in [&b""[..], &b"abc"[..], &incompressible[..], &long_run[..]] {
+            let compressed = Compression::Lz4.compress(body).unwrap();
+            assert_eq!(Compression::Lz4.decompress(&compressed).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn lz4_rejects_corrupted_bodies() {
+        let mut compressed = Compression::Lz4.compress(&[1u8; 100]).unwrap();
+        // Claim a longer uncompressed body than the block holds
+        compressed[3] = 200;
+
+        assert!(Compression::Lz4.decompress(&compressed).is_err());
+        assert!(Compression::Lz4.decompress(&[0, 0]).is_err());
+
+        // An uncompressed body longer than a frame can be is rejected without allocating it
+        let mut compressed = Compression::Lz4.compress(&[1u8; 100]).unwrap();
+        compressed[..4].copy_from_slice(&Int::MAX.to_be_bytes());
+        assert!(Compression::Lz4.decompress(&compressed).is_err());
+    }
+
+    #[test]
+    fn snappy_round_trips_bodies() {
+        let repeated = "SELECT * FROM flights WHERE airport = 'EZE';".repeat(50);
+        let compressed = Compression::Snappy.compress(repeated.as_bytes()).unwrap();
+        assert!(compressed.len() < repeated.len() / 4);
+
+        let literal: Vec<u8> = (0..=255u8).cycle().take(70000).collect();
+        let long_run = vec![0u8; 70000];
+        for body in [
+            &b""[..],
+            &b"abc"[..],
+            repeated.as_bytes(),
+            &literal[..],
+            &long_run[..],
+        ] {
+            let compressed = Compression::Snappy.compress(body).unwrap();
+            assert_eq!(Compression::Snappy.decompress(&compressed).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn snappy_decompresses_every_kind_of_copy() {
+        // "abcd" as a literal, then copies of it with 1, 2 and 4 bytes offsets
+        let mut block = vec![16, 3 << 2];
+        block.extend_from_slice(b"abcd");
+        block.extend_from_slice(&[0b01, 4]);
+        block.extend_from_slice(&[3 << 2 | 0b10, 8, 0]);
+        block.extend_from_slice(&[3 << 2 | 0b11, 12, 0, 0, 0]);
+
+        let decompressed = Compression::Snappy.decompress(&block).unwrap();
+        assert_eq!(decompressed, b"abcd".repeat(4));
+
+        // A copy from before the start of the body, and a body shorter than its length
+        assert!(Compression::Snappy.decompress(&[4, 0b01, 1]).is_err());
+        assert!(Compression::Snappy
+            .decompress(&[5, 3 << 2, b'a', b'b', b'c', b'd'])
+            .is_err());
+
+        // A body longer than a frame can be, and a literal longer than the block, are rejected
+        // without allocating them
+        let mut block = vec![];
+        write_varint(&mut block, MAX_BODY_LENGTH + 1);
+        block.extend_from_slice(&[0, b'a']);
+        assert!(Compression::Snappy.decompress(&block).is_err());
+        let mut block = vec![];
+        write_varint(&mut block, MAX_BODY_LENGTH);
+        block.extend_from_slice(&[63 << 2, 0xff, 0xff, 0xff, 0x0f, b'a']);
+        assert!(Compression::Snappy.decompress(&block).is_err());
+    }
+
+    #[test]
+    fn compression_from_name() {
+        assert_eq!(Compression::from_name("LZ4").unwrap(), Compression::Lz4);
+        assert_eq!(
+            Compression::from_name("snappy").unwrap(),
+            Compression::Snappy
+        );
+        assert!(Compression::from_name("zstd").is_err());
+    }
+}
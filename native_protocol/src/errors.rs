@@ -9,6 +9,9 @@ pub enum NativeError {
     CursorError,
     InvalidCode,
     InvalidVariant,
+    CompressionError,
+    ChecksumError,
+    FrameTooLong,
 }
 
 impl fmt::Display for NativeError {
@@ -21,6 +24,9 @@ impl fmt::Display for NativeError {
             NativeError::CursorError => "Cursor error encountered",
             NativeError::InvalidCode => "Invalid code encountered",
             NativeError::InvalidVariant => "Invalid variant provided",
+            NativeError::CompressionError => "Compressed body is invalid",
+            NativeError::ChecksumError => "Checksum of the body does not match",
+            NativeError::FrameTooLong => "Frame is longer than the longest allowed",
         };
         write!(f, "{}", description)
     }
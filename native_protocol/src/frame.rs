@@ -4,26 +4,43 @@ use std::{
 };
 
 use crate::{
+    checksum::Checksum,
+    compression::Compression,
     errors::NativeError,
     header::{Flags, FrameHeader, Opcode, Version},
     messages::{
         auth::{AuthChallenge, AuthResponse, AuthSuccess, Authenticate},
+        batch::Batch,
         error::Error,
+        execute::Execute,
+        prepare::Prepare,
         query::Query,
         result::result_::Result,
+        startup::Startup,
+        supported::Supported,
     },
-    types::{Int, Short},
+    types::{custom_payload_from_bytes, custom_payload_to_bytes, CustomPayload, Int, Short},
     ByteSerializable, Serializable,
 };
 
 #[derive(Debug)]
 pub enum Frame {
     /// Initialize the connection.
-    Startup,
+    Startup(Startup),
     /// Indicates that the server is ready to process queries.
     Ready,
+    /// Asks the server for the options of `STARTUP` it supports.
+    Options,
+    /// The options of `STARTUP` the server supports, answering an `OPTIONS`.
+    Supported(Supported),
     /// Performs a CQL query.
     Query(Query),
+    /// Prepares a query for later execution.
+    Prepare(Prepare),
+    /// Executes a prepared query.
+    Execute(Execute),
+    /// Executes several queries in a single request.
+    Batch(Batch),
     /// The result to a query.
     Result(Result),
     /// Indicates an error processing a request.
@@ -38,7 +55,31 @@ pub enum Frame {
     AuthChallenge(AuthChallenge),
 }
 
-impl Serializable for Frame {
+/// What a client and a node agreed on in the `STARTUP` of a connection, for the frames that
+/// follow it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameOptions {
+    /// Algorithm to compress the bodies with.
+    pub compression: Option<Compression>,
+    /// Algorithm to checksum the bodies with, after compressing them.
+    pub checksum: Option<Checksum>,
+}
+
+impl FrameOptions {
+    /// Options of a connection whose frames are only compressed, if `compression` is given.
+    pub fn compressed(compression: Option<&Compression>) -> Self {
+        Self {
+            compression: compression.copied(),
+            checksum: None,
+        }
+    }
+}
+
+impl Frame {
+    /// Converts the frame to bytes, compressing its body with `compression` unless it is a
+    /// `STARTUP`, which is always sent uncompressed since it is the one that negotiates the
+    /// compression. The compression flag of the header tells whether the body is compressed.
+    ///
     /// 0         8        16        24        32         40
     /// +---------+---------+---------+---------+---------+
     /// | version |  flags  |      stream       | opcode  |
@@ -50,12 +91,43 @@ impl Serializable for Frame {
     /// .                                                 .
     /// .                                                 .
     /// +-------------------------------------------------+
-    fn to_bytes(&self) -> std::result::Result<Vec<u8>, NativeError> {
+    pub fn to_compressed_bytes(
+        &self,
+        compression: Option<&Compression>,
+    ) -> std::result::Result<Vec<u8>, NativeError> {
+        self.to_compressed_bytes_with_payload(compression, None)
+    }
+
+    /// Converts the frame to bytes as `to_compressed_bytes` does, starting its body with
+    /// `payload` if given (with the custom payload flag set in the header).
+    pub fn to_compressed_bytes_with_payload(
+        &self,
+        compression: Option<&Compression>,
+        payload: Option<&CustomPayload>,
+    ) -> std::result::Result<Vec<u8>, NativeError> {
+        self.to_bytes_with_options(&FrameOptions::compressed(compression), payload)
+    }
+
+    /// Converts the frame to bytes as `to_compressed_bytes_with_payload` does, also ending its
+    /// body with the checksum of the `options`, if any, over the (compressed) body. `STARTUP`
+    /// frames are neither compressed nor checksummed, since they negotiate the options.
+    pub fn to_bytes_with_options(
+        &self,
+        options: &FrameOptions,
+        payload: Option<&CustomPayload>,
+    ) -> std::result::Result<Vec<u8>, NativeError> {
         let mut bytes = Vec::new();
 
         let version = match self {
-            Frame::Startup | Frame::Query(_) | Frame::AuthResponse(_) => Version::RequestV3,
+            Frame::Startup(_)
+            | Frame::Options
+            | Frame::Query(_)
+            | Frame::Prepare(_)
+            | Frame::Execute(_)
+            | Frame::Batch(_)
+            | Frame::AuthResponse(_) => Version::RequestV3,
             Frame::Ready
+            | Frame::Supported(_)
             | Frame::Result(_)
             | Frame::Error(_)
             | Frame::AuthChallenge(_)
@@ -64,9 +136,14 @@ impl Serializable for Frame {
         };
 
         let opcode = match self {
-            Frame::Startup => Opcode::Startup,
+            Frame::Startup(_) => Opcode::Startup,
             Frame::Ready => Opcode::Ready,
+            Frame::Options => Opcode::Options,
+            Frame::Supported(_) => Opcode::Supported,
             Frame::Query(_) => Opcode::Query,
+            Frame::Prepare(_) => Opcode::Prepare,
+            Frame::Execute(_) => Opcode::Execute,
+            Frame::Batch(_) => Opcode::Batch,
             Frame::Result(_) => Opcode::Result,
             Frame::Error(_) => Opcode::Error,
             Frame::AuthChallenge(_) => Opcode::AuthChallenge,
@@ -75,15 +152,26 @@ impl Serializable for Frame {
             Frame::AuthResponse(_) => Opcode::AuthResponse,
         };
 
+        let (compression, checksum) = match self {
+            Frame::Startup(_) => (None, None),
+            _ => (options.compression.as_ref(), options.checksum.as_ref()),
+        };
+
         let flags = Flags {
-            compression: false,
+            compression: compression.is_some(),
             tracing: false,
+            custom_payload: payload.is_some(),
+            checksum: checksum.is_some(),
         };
 
-        let body_bytes = match self {
-            Frame::Startup => vec![0x00, 0x00], // View 4.1.1., the startup body is a [string map] of options, but we do not use them. The [string map] requires 2 bytes for the length nonetheless, therefore, the 0x0000.
-            Frame::Ready => Vec::new(),
+        let message_bytes = match self {
+            Frame::Startup(startup) => startup.to_bytes()?,
+            Frame::Ready | Frame::Options => Vec::new(),
+            Frame::Supported(supported) => supported.to_bytes()?,
             Frame::Query(query) => query.to_bytes()?,
+            Frame::Prepare(prepare) => prepare.to_bytes()?,
+            Frame::Execute(execute) => execute.to_bytes()?,
+            Frame::Batch(batch) => batch.to_bytes()?,
             Frame::Result(result) => result.to_bytes()?,
             Frame::Error(error) => error.to_bytes()?,
             Frame::AuthChallenge(auth_challenge) => auth_challenge.to_bytes()?,
@@ -92,6 +180,20 @@ impl Serializable for Frame {
             Frame::AuthResponse(auth_response) => auth_response.to_bytes()?,
         };
 
+        let mut body_bytes = match payload {
+            Some(payload) => custom_payload_to_bytes(payload)?,
+            None => vec![],
+        };
+        body_bytes.extend(message_bytes);
+
+        let mut body_bytes = match compression {
+            Some(compression) => compression.compress(&body_bytes)?,
+            None => body_bytes,
+        };
+        if let Some(checksum) = checksum {
+            checksum.append(&mut body_bytes);
+        }
+
         let length =
             u32::try_from(body_bytes.len()).map_err(|_| NativeError::SerializationError)?;
 
@@ -105,7 +207,33 @@ impl Serializable for Frame {
         Ok(bytes)
     }
 
-    fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, NativeError> {
+    /// Converts bytes to a frame, decompressing its body with `compression` if the header
+    /// says it is compressed. A compressed body fails to convert if no compression was
+    /// negotiated.
+    pub fn from_compressed_bytes(
+        bytes: &[u8],
+        compression: Option<&Compression>,
+    ) -> std::result::Result<Self, NativeError> {
+        Self::from_compressed_bytes_with_payload(bytes, compression).map(|(frame, _)| frame)
+    }
+
+    /// Converts bytes to a frame as `from_compressed_bytes` does, also returning the custom
+    /// payload at the start of its body, if the header says there is one.
+    pub fn from_compressed_bytes_with_payload(
+        bytes: &[u8],
+        compression: Option<&Compression>,
+    ) -> std::result::Result<(Self, Option<CustomPayload>), NativeError> {
+        Self::from_bytes_with_options(bytes, &FrameOptions::compressed(compression))
+    }
+
+    /// Converts bytes to a frame as `from_compressed_bytes_with_payload` does, verifying and
+    /// removing the checksum at the end of its body first. Once a checksum was negotiated,
+    /// frames without one (other than `STARTUP`) fail to convert, and so do frames with one
+    /// if it was not.
+    pub fn from_bytes_with_options(
+        bytes: &[u8],
+        options: &FrameOptions,
+    ) -> std::result::Result<(Self, Option<CustomPayload>), NativeError> {
         let mut cursor = Cursor::new(bytes);
 
         // Read version (1 byte)
@@ -120,7 +248,7 @@ impl Serializable for Frame {
         cursor
             .read_exact(&mut flags_bytes)
             .map_err(|_| NativeError::CursorError)?;
-        let _ = Flags::from_byte(flags_bytes[0])?;
+        let flags = Flags::from_byte(flags_bytes[0])?;
 
         // Read stream (2 bytes)
         let mut stream_bytes = [0u8; 2];
@@ -154,10 +282,41 @@ impl Serializable for Frame {
             .read_exact(&mut body)
             .map_err(|_| NativeError::CursorError)?;
 
+        match (flags.checksum, &options.checksum) {
+            (true, Some(checksum)) => body = checksum.verify(body)?,
+            (false, Some(_)) if !matches!(opcode, Opcode::Startup) => {
+                return Err(NativeError::ChecksumError)
+            }
+            (true, None) => return Err(NativeError::ChecksumError),
+            _ => {}
+        }
+
+        if flags.compression {
+            let compression = options
+                .compression
+                .as_ref()
+                .ok_or(NativeError::CompressionError)?;
+            body = compression.decompress(&body)?;
+        }
+
+        let payload = if flags.custom_payload {
+            let mut body_cursor = Cursor::new(body.as_slice());
+            let payload = custom_payload_from_bytes(&mut body_cursor)?;
+            body = body[body_cursor.position() as usize..].to_vec();
+            Some(payload)
+        } else {
+            None
+        };
+
         let frame = match opcode {
-            Opcode::Startup => Self::Startup,
+            Opcode::Startup => Self::Startup(Startup::from_bytes(&body)?),
             Opcode::Ready => Self::Ready,
+            Opcode::Options => Self::Options,
+            Opcode::Supported => Self::Supported(Supported::from_bytes(&body)?),
             Opcode::Query => Self::Query(Query::from_bytes(&body)?),
+            Opcode::Prepare => Self::Prepare(Prepare::from_bytes(&body)?),
+            Opcode::Execute => Self::Execute(Execute::from_bytes(&body)?),
+            Opcode::Batch => Self::Batch(Batch::from_bytes(&body)?),
             Opcode::Error => Self::Error(Error::from_bytes(&body)?),
             Opcode::Result => Self::Result(Result::from_bytes(&body)?),
             Opcode::AuthChallenge => Self::AuthChallenge(AuthChallenge::from_bytes(&body)?),
@@ -167,7 +326,19 @@ impl Serializable for Frame {
             _ => return Err(NativeError::InvalidVariant),
         };
 
-        Ok(frame)
+        Ok((frame, payload))
+    }
+}
+
+impl Serializable for Frame {
+    /// Converts the frame to bytes, with its body uncompressed.
+    fn to_bytes(&self) -> std::result::Result<Vec<u8>, NativeError> {
+        self.to_compressed_bytes(None)
+    }
+
+    /// Converts bytes to a frame whose body is not compressed.
+    fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, NativeError> {
+        Self::from_compressed_bytes(bytes, None)
     }
 }
 
@@ -178,6 +349,7 @@ mod tests {
 
     use crate::{
         messages::{
+            batch::BatchType,
             query::{Consistency, QueryParams},
             result::rows::{ColumnType, ColumnValue, Rows},
         },
@@ -188,7 +360,7 @@ mod tests {
 
     #[test]
     fn test_frame_to_bytes_startup() {
-        let frame = Frame::Startup;
+        let frame = Frame::Startup(Startup::new(BTreeMap::new()));
         let bytes = frame.to_bytes().unwrap();
 
         let expected_bytes = vec![
@@ -251,10 +423,143 @@ mod tests {
 
     #[test]
     fn bytes_to_frame_startup() {
-        let bytes = Frame::Startup.to_bytes().unwrap();
+        let bytes = Frame::Startup(Startup::default()).to_bytes().unwrap();
         let frame = Frame::from_bytes(&bytes).unwrap();
 
-        assert!(matches!(frame, Frame::Startup))
+        assert!(matches!(frame, Frame::Startup(startup) if startup == Startup::default()))
+    }
+
+    #[test]
+    fn prepare_and_execute_frames_to_from_bytes() {
+        let query = "SELECT * FROM flights WHERE id = ?".to_string();
+        let bytes = Frame::Prepare(Prepare::new(query.clone()))
+            .to_bytes()
+            .unwrap();
+        assert_eq!(bytes[4], 0x09);
+        assert!(
+            matches!(Frame::from_bytes(&bytes), Ok(Frame::Prepare(prepare)) if prepare.query == query)
+        );
+
+        let execute = || Execute::new(vec![1, 2], vec!["3".to_string()], Consistency::One);
+        let bytes = Frame::Execute(execute()).to_bytes().unwrap();
+        assert_eq!(bytes[4], 0x0A);
+        assert!(
+            matches!(Frame::from_bytes(&bytes), Ok(Frame::Execute(received)) if received == execute())
+        );
+
+        let batch = || {
+            Batch::new(
+                BatchType::Unlogged,
+                vec!["INSERT INTO t (a) VALUES (1)".to_string()],
+                Consistency::One,
+            )
+        };
+        let bytes = Frame::Batch(batch()).to_bytes().unwrap();
+        assert_eq!(bytes[4], 0x0D);
+        assert!(
+            matches!(Frame::from_bytes(&bytes), Ok(Frame::Batch(received)) if received == batch())
+        );
+    }
+
+    #[test]
+    fn compressed_frames_to_from_bytes() {
+        let query_string = "SELECT * FROM flights WHERE airport = 'EZE'".repeat(10);
+        let query = || {
+            Query::new(
+                query_string.clone(),
+                QueryParams::new(Consistency::One, vec![]),
+            )
+        };
+        let plain_length = Frame::Query(query()).to_bytes().unwrap().len();
+
+        let bytes = Frame::Query(query())
+            .to_compressed_bytes(Some(&Compression::Lz4))
+            .unwrap();
+        // The compression flag is set and the body shrinks
+        assert_eq!(bytes[1], 0x01);
+        assert!(bytes.len() < plain_length);
+
+        assert!(Frame::from_bytes(&bytes).is_err());
+        let frame = Frame::from_compressed_bytes(&bytes, Some(&Compression::Lz4)).unwrap();
+        assert!(matches!(frame, Frame::Query(query) if query.query == query_string));
+
+        // STARTUP negotiates the compression, so it is never compressed
+        let startup = Frame::Startup(Startup::with_compression(Compression::Lz4))
+            .to_compressed_bytes(Some(&Compression::Lz4))
+            .unwrap();
+        assert_eq!(startup[1], 0x00);
+        assert!(matches!(Frame::from_bytes(&startup), Ok(Frame::Startup(_))));
+    }
+
+    #[test]
+    fn checksummed_frames_to_from_bytes() {
+        let options = FrameOptions {
+            compression: Some(Compression::Lz4),
+            checksum: Some(Checksum::Crc32),
+        };
+        let query_string = "SELECT * FROM flights WHERE airport = 'EZE'".repeat(10);
+        let query = Query::new(
+            query_string.clone(),
+            QueryParams::new(Consistency::One, vec![]),
+        );
+
+        let mut bytes = Frame::Query(query)
+            .to_bytes_with_options(&options, None)
+            .unwrap();
+        // The compression and checksum flags are set
+        assert_eq!(bytes[1], 0x09);
+        let (frame, _) = Frame::from_bytes_with_options(&bytes, &options).unwrap();
+        assert!(matches!(frame, Frame::Query(query) if query.query == query_string));
+
+        // Frames with a checksum that was not negotiated, or without the negotiated one, fail
+        assert!(Frame::from_compressed_bytes(&bytes, Some(&Compression::Lz4)).is_err());
+        let plain = Frame::Ready
+            .to_compressed_bytes(Some(&Compression::Lz4))
+            .unwrap();
+        assert!(Frame::from_bytes_with_options(&plain, &options).is_err());
+
+        // A corrupted body is detected
+        let last = bytes.len() - 10;
+        bytes[last] ^= 0x01;
+        assert!(matches!(
+            Frame::from_bytes_with_options(&bytes, &options),
+            Err(NativeError::ChecksumError)
+        ));
+
+        // STARTUP negotiates the checksum, so it never has one
+        let startup = Frame::Startup(Startup::default())
+            .to_bytes_with_options(&options, None)
+            .unwrap();
+        assert_eq!(startup[1], 0x00);
+        assert!(matches!(
+            Frame::from_bytes_with_options(&startup, &options),
+            Ok((Frame::Startup(_), None))
+        ));
+    }
+
+    #[test]
+    fn test_frame_with_custom_payload() {
+        let payload = CustomPayload::from([("parse_us".to_string(), vec![0x01, 0x02])]);
+
+        let bytes = Frame::Result(Result::Void)
+            .to_compressed_bytes_with_payload(Some(&Compression::Lz4), Some(&payload))
+            .unwrap();
+        // The compression and custom payload flags are set
+        assert_eq!(bytes[1], 0x05);
+
+        let (frame, read_payload) =
+            Frame::from_compressed_bytes_with_payload(&bytes, Some(&Compression::Lz4)).unwrap();
+        assert!(matches!(frame, Frame::Result(Result::Void)));
+        assert_eq!(read_payload, Some(payload));
+
+        // Readers that do not care about the payload still get the frame
+        let bytes = Frame::Result(Result::Void)
+            .to_compressed_bytes_with_payload(None, Some(&CustomPayload::new()))
+            .unwrap();
+        assert!(matches!(
+            Frame::from_bytes(&bytes),
+            Ok(Frame::Result(Result::Void))
+        ));
     }
 
     #[test]
@@ -265,6 +570,23 @@ mod tests {
         assert!(matches!(frame, Frame::Ready))
     }
 
+    #[test]
+    fn options_and_supported_frames_to_from_bytes() {
+        let bytes = Frame::Options.to_bytes().unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x03, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert!(matches!(Frame::from_bytes(&bytes), Ok(Frame::Options)));
+
+        let bytes = Frame::Supported(Supported::default()).to_bytes().unwrap();
+        assert_eq!(bytes[0], 0x83);
+        assert_eq!(bytes[4], 0x06);
+        assert!(
+            matches!(Frame::from_bytes(&bytes), Ok(Frame::Supported(supported)) if supported == Supported::default())
+        );
+    }
+
     #[test]
     fn bytes_to_frame_query() {
         let query_string = "SELECT * FROM table WHERE id = 1".to_string();
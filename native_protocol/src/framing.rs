@@ -0,0 +1,167 @@
+//! Splitting the bytes of a connection into frames.
+//!
+//! A connection is a stream of bytes: a single read may get only part of a frame (a large result,
+//! or any frame over a slow network) or more than one. Every frame starts with a header whose last
+//! 4 bytes are the length of its body, so the header is read first and then exactly as many bytes
+//! as it says.
+
+use std::io::{self, Read};
+
+use crate::errors::NativeError;
+
+/// Bytes of the header of a frame: version, flags, stream, opcode and length of the body.
+pub const HEADER_LENGTH: usize = 9;
+
+/// Longest body a frame can have, as in Cassandra. Longer ones are rejected before they are read,
+/// so a corrupted length can not make the reader wait for (or allocate) gigabytes.
+pub const MAX_BODY_LENGTH: usize = 256 * 1024 * 1024;
+
+/// Returns the length of the body of a frame, read from its header.
+///
+/// # Errors
+/// - `NativeError::FrameTooLong` if it is longer than `MAX_BODY_LENGTH`.
+pub fn body_length(header: &[u8; HEADER_LENGTH]) -> Result<usize, NativeError> {
+    let length = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if length > MAX_BODY_LENGTH {
+        return Err(NativeError::FrameTooLong);
+    }
+    Ok(length)
+}
+
+/// Reads the bytes of a whole frame from `reader`: its header, and then as many bytes of body as
+/// the header says, however many reads they take.
+///
+/// # Errors
+/// - The error of the reader if it fails or ends before the frame does.
+/// - `io::ErrorKind::InvalidData` if the body is longer than `MAX_BODY_LENGTH`.
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; HEADER_LENGTH];
+    reader.read_exact(&mut header)?;
+    let length = body_length(&header)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let mut bytes = header.to_vec();
+    bytes.resize(HEADER_LENGTH + length, 0);
+    reader.read_exact(&mut bytes[HEADER_LENGTH..])?;
+    Ok(bytes)
+}
+
+/// Bytes read from a connection that may not make whole frames yet, for readers that can not block
+/// until a frame is complete (for example, because their reads time out to do something else).
+#[derive(Debug, Default)]
+pub struct FrameBuffer {
+    pending: Vec<u8>,
+}
+
+impl FrameBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the bytes of a read.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    /// Takes the bytes of the first frame of the buffer, if all of them were read.
+    ///
+    /// # Errors
+    /// - `NativeError::FrameTooLong` if the body of the frame is longer than `MAX_BODY_LENGTH`.
+    ///   The bytes after its header can not be split into frames anymore.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, NativeError> {
+        let Some(header) = self.pending.first_chunk::<HEADER_LENGTH>() else {
+            return Ok(None);
+        };
+        let length = HEADER_LENGTH + body_length(header)?;
+        if self.pending.len() < length {
+            return Ok(None);
+        }
+        Ok(Some(self.pending.drain(..length).collect()))
+    }
+
+    /// Returns the amount of bytes read that are waiting for the rest of their frame.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        frame::Frame,
+        messages::query::{Consistency, Query, QueryParams},
+        Serializable,
+    };
+
+    use super::*;
+
+    fn query(text: &str) -> Vec<u8> {
+        let params = QueryParams::new(Consistency::One, vec![]);
+        Frame::Query(Query::new(text.to_string(), params))
+            .to_bytes()
+            .unwrap()
+    }
+
+    #[test]
+    fn frames_are_split_however_they_are_read() {
+        let long = format!(
+            "SELECT * FROM flights WHERE origin = '{}'",
+            "EZE".repeat(1000)
+        );
+        let mut bytes = query("SELECT * FROM flights");
+        bytes.extend(query(&long));
+
+        let mut buffer = FrameBuffer::new();
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(7) {
+            buffer.extend(chunk);
+            while let Some(frame) = buffer.next_frame().unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(frames, vec![query("SELECT * FROM flights"), query(&long)]);
+        assert_eq!(buffer.pending_len(), 0);
+    }
+
+    #[test]
+    fn frames_are_read_whole_from_a_reader() {
+        let mut bytes = query("SELECT * FROM flights");
+        let first = bytes.len();
+        bytes.extend(query("SELECT * FROM airports"));
+
+        // A reader that returns a few bytes on every read, as a slow network does
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let read = self.0.len().min(buf.len()).min(3);
+                buf[..read].copy_from_slice(&self.0[..read]);
+                self.0 = &self.0[read..];
+                Ok(read)
+            }
+        }
+
+        let mut reader = Trickle(&bytes);
+        assert_eq!(read_frame(&mut reader).unwrap(), &bytes[..first]);
+        assert_eq!(read_frame(&mut reader).unwrap(), &bytes[first..]);
+        assert!(read_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    fn frames_too_long_are_rejected() {
+        let mut header = [0u8; HEADER_LENGTH];
+        header[5..].copy_from_slice(&(MAX_BODY_LENGTH as u32 + 1).to_be_bytes());
+
+        let mut buffer = FrameBuffer::new();
+        buffer.extend(&header);
+        assert!(matches!(
+            buffer.next_frame(),
+            Err(NativeError::FrameTooLong)
+        ));
+        assert_eq!(
+            read_frame(&mut header.as_slice()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
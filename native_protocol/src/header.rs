@@ -146,6 +146,8 @@ impl ByteSerializable for Version {
 enum FlagCodes {
     Compression = 0x01,
     Tracing = 0x02,
+    CustomPayload = 0x04,
+    Checksum = 0x08,
 }
 
 #[derive(Debug)]
@@ -154,6 +156,10 @@ pub struct Flags {
     pub compression: bool,
     /// Tracing flag.
     pub tracing: bool,
+    /// Whether the body starts with a custom payload, before the message.
+    pub custom_payload: bool,
+    /// Whether the body ends with its checksum.
+    pub checksum: bool,
 }
 
 impl ByteSerializable for Flags {
@@ -168,16 +174,28 @@ impl ByteSerializable for Flags {
             flags |= FlagCodes::Tracing as u8;
         };
 
+        if self.custom_payload {
+            flags |= FlagCodes::CustomPayload as u8;
+        };
+
+        if self.checksum {
+            flags |= FlagCodes::Checksum as u8;
+        };
+
         Ok(flags)
     }
 
     fn from_byte(flags: u8) -> Result<Self, NativeError> {
         let compression = flags & FlagCodes::Compression as u8 != 0;
         let tracing = flags & FlagCodes::Tracing as u8 != 0;
+        let custom_payload = flags & FlagCodes::CustomPayload as u8 != 0;
+        let checksum = flags & FlagCodes::Checksum as u8 != 0;
 
         Ok(Self {
             compression,
             tracing,
+            custom_payload,
+            checksum,
         })
     }
 }
@@ -191,6 +209,8 @@ mod tests {
         let flags = Flags {
             compression: false,
             tracing: false,
+            custom_payload: false,
+            checksum: false,
         };
 
         let flags = flags.to_byte().unwrap();
@@ -203,23 +223,29 @@ mod tests {
         let flags = Flags {
             compression: true,
             tracing: true,
+            custom_payload: true,
+            checksum: true,
         };
 
         let flags = flags.to_byte().unwrap();
 
-        assert_eq!(flags, 0x03)
+        assert_eq!(flags, 0x0F)
     }
 
     #[test]
     fn byte_to_flags_all_true() {
-        let flags = 0x03;
+        let flags = 0x0F;
 
         let Flags {
             compression,
             tracing,
+            custom_payload,
+            checksum,
         } = Flags::from_byte(flags).unwrap();
 
         assert!(compression);
         assert!(tracing);
+        assert!(custom_payload);
+        assert!(checksum);
     }
 }
@@ -1,7 +1,10 @@
 use errors::NativeError;
 
+pub mod checksum;
+pub mod compression;
 pub mod errors;
 pub mod frame;
+pub mod framing;
 pub mod header;
 pub mod messages;
 pub mod types;
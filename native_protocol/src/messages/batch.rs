@@ -0,0 +1,226 @@
+use std::io::Read;
+
+use crate::{errors::NativeError, types::read_bytes, Serializable};
+
+use super::query::{Consistency, QueryParams};
+
+/// Kind of a query inside a BATCH: a CQL string. Prepared statements are not supported in
+/// batches.
+const QUERY_KIND: u8 = 0;
+
+/// Whether a batch is `LOGGED` or `UNLOGGED`. The nodes keep no batch log, so both are applied the
+/// same way, and a timed out batch is always reported as an `UNLOGGED_BATCH` write.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BatchType {
+    Logged = 0,
+    Unlogged = 1,
+}
+
+/// Executes several `INSERT`, `UPDATE` and `DELETE` queries in a single request.
+#[derive(PartialEq, Debug)]
+pub struct Batch {
+    pub batch_type: BatchType,
+    pub queries: Vec<String>,
+    pub params: QueryParams,
+}
+
+impl Batch {
+    pub fn new(batch_type: BatchType, queries: Vec<String>, consistency: Consistency) -> Self {
+        Batch {
+            batch_type,
+            queries,
+            params: QueryParams::new(consistency, vec![]),
+        }
+    }
+
+    pub fn get_queries(&self) -> &[String] {
+        &self.queries
+    }
+
+    pub fn get_consistency(&self) -> &str {
+        self.params.consistency.to_string()
+    }
+
+    /// Asks for the timings of the query in the custom payload of its result.
+    pub fn with_timings(mut self) -> Self {
+        self.params = self.params.with_timings();
+        self
+    }
+
+    pub fn wants_timings(&self) -> bool {
+        self.params.wants_timings()
+    }
+
+    /// Returns the batch as a single `BEGIN BATCH ... APPLY BATCH` CQL statement.
+    pub fn to_cql(&self) -> String {
+        let queries: Vec<String> = self
+            .queries
+            .iter()
+            .map(|query| format!("{}; ", query.trim().trim_end_matches(';')))
+            .collect();
+
+        let batch_type = match self.batch_type {
+            BatchType::Logged => "",
+            BatchType::Unlogged => "UNLOGGED ",
+        };
+
+        format!("BEGIN {}BATCH {}APPLY BATCH", batch_type, queries.concat())
+    }
+}
+
+impl Serializable for Batch {
+    /// ```md
+    /// 0         8        16        24        32
+    /// +---------+---------+---------+---------+
+    /// |  type   | queries count (2) |  kind   |
+    /// +---------+---------+---------+---------+
+    /// |        query length (4 bytes)         |
+    /// +---------+---------+---------+---------+
+    /// |              query bytes              |
+    /// +                                       +
+    /// |                 ...                   |
+    /// +---------+---------+---------+---------+
+    /// | values count (2)  |  ... next query   |
+    /// +---------+---------+---------+---------+
+    /// |  consistency (2)  | flag (1)|
+    /// +---------+---------+---------+
+    /// ```
+    ///
+    /// Every query is a CQL string (kind `0`) without bound values.
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = vec![self.batch_type as u8];
+
+        let count =
+            u16::try_from(self.queries.len()).map_err(|_| NativeError::SerializationError)?;
+        bytes.extend_from_slice(&count.to_be_bytes());
+
+        for query in &self.queries {
+            bytes.push(QUERY_KIND);
+            bytes.extend_from_slice(&(query.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(query.as_bytes());
+            bytes.extend_from_slice(&0u16.to_be_bytes());
+        }
+
+        let consistency_code = self.params.consistency.to_code()?;
+        bytes.extend_from_slice(&(consistency_code as u16).to_be_bytes());
+
+        let flags_byte = self.params.flags_to_byte()?;
+        bytes.push(flags_byte);
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, NativeError> {
+        let mut cursor = std::io::Cursor::new(bytes);
+
+        let mut type_byte = [0u8; 1];
+        cursor
+            .read_exact(&mut type_byte)
+            .map_err(|_| NativeError::CursorError)?;
+        let batch_type = match type_byte[0] {
+            0 => BatchType::Logged,
+            1 => BatchType::Unlogged,
+            _ => return Err(NativeError::InvalidCode),
+        };
+
+        let mut count_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut count_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+
+        let mut queries = Vec::new();
+        for _ in 0..u16::from_be_bytes(count_bytes) {
+            let mut kind = [0u8; 1];
+            cursor
+                .read_exact(&mut kind)
+                .map_err(|_| NativeError::CursorError)?;
+            if kind[0] != QUERY_KIND {
+                return Err(NativeError::InvalidVariant);
+            }
+
+            let mut query_len_bytes = [0u8; 4];
+            cursor
+                .read_exact(&mut query_len_bytes)
+                .map_err(|_| NativeError::CursorError)?;
+            let query_bytes =
+                read_bytes(&mut cursor, u32::from_be_bytes(query_len_bytes) as usize)?;
+            queries.push(
+                String::from_utf8(query_bytes).map_err(|_| NativeError::DeserializationError)?,
+            );
+
+            let mut values_count_bytes = [0u8; 2];
+            cursor
+                .read_exact(&mut values_count_bytes)
+                .map_err(|_| NativeError::CursorError)?;
+            if u16::from_be_bytes(values_count_bytes) != 0 {
+                return Err(NativeError::DeserializationError);
+            }
+        }
+
+        let mut consistency_code_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut consistency_code_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        let consistency = Consistency::from_code(u16::from_be_bytes(consistency_code_bytes))?;
+
+        let mut flags_byte = [0u8; 1];
+        cursor
+            .read_exact(&mut flags_byte)
+            .map_err(|_| NativeError::CursorError)?;
+        let flags = QueryParams::byte_to_flags(flags_byte[0])?;
+
+        Ok(Batch {
+            batch_type,
+            queries,
+            params: QueryParams::new(consistency, flags),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_to_bytes_and_back() {
+        let batch = Batch::new(
+            BatchType::Unlogged,
+            vec![
+                "INSERT INTO t (a) VALUES (1)".to_string(),
+                "UPDATE t SET b = 2 WHERE a = 1;".to_string(),
+            ],
+            Consistency::Quorum,
+        );
+
+        let bytes = batch.to_bytes().unwrap();
+        assert_eq!(bytes[0], BatchType::Unlogged as u8);
+        assert_eq!(&bytes[1..3], &[0x00, 0x02]);
+
+        assert_eq!(Batch::from_bytes(&bytes).unwrap(), batch);
+    }
+
+    #[test]
+    fn test_batch_to_cql() {
+        let batch = Batch::new(
+            BatchType::Logged,
+            vec![
+                "INSERT INTO t (a) VALUES (1)".to_string(),
+                "UPDATE t SET b = 2 WHERE a = 1;".to_string(),
+            ],
+            Consistency::One,
+        );
+
+        assert_eq!(
+            batch.to_cql(),
+            "BEGIN BATCH INSERT INTO t (a) VALUES (1); UPDATE t SET b = 2 WHERE a = 1; APPLY BATCH"
+        );
+    }
+
+    #[test]
+    fn test_batch_from_invalid_bytes() {
+        // Unknown batch type
+        assert!(Batch::from_bytes(&[0x05, 0x00, 0x00, 0x00, 0x01, 0x00]).is_err());
+        // Missing the consistency
+        assert!(Batch::from_bytes(&[0x00, 0x00, 0x00]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use std::io::Read;
 
+use crate::messages::query::Consistency;
+use crate::types::{CassandraString, FromCursorDeserializable, Int};
 use crate::{errors::NativeError, Serializable};
 
 #[derive(Debug, Copy, Clone)]
@@ -50,8 +52,96 @@ impl ErrorCode {
     }
 }
 
+/// The kind of write that timed out, as told to the client in a `WriteTimeout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteType {
+    /// A write to a single partition, other than a counter.
+    Simple,
+    /// A logged batch.
+    Batch,
+    /// An unlogged batch.
+    UnloggedBatch,
+    /// A write to a counter.
+    Counter,
+    /// The write of a logged batch to the batch log.
+    BatchLog,
+    /// A lightweight transaction.
+    Cas,
+}
+
+impl WriteType {
+    /// Returns the name of the write type, as sent in the frame.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WriteType::Simple => "SIMPLE",
+            WriteType::Batch => "BATCH",
+            WriteType::UnloggedBatch => "UNLOGGED_BATCH",
+            WriteType::Counter => "COUNTER",
+            WriteType::BatchLog => "BATCH_LOG",
+            WriteType::Cas => "CAS",
+        }
+    }
+
+    /// Returns the write type with the given name.
+    pub fn from_name(name: &str) -> Result<Self, NativeError> {
+        let write_type = match name {
+            "SIMPLE" => WriteType::Simple,
+            "BATCH" => WriteType::Batch,
+            "UNLOGGED_BATCH" => WriteType::UnloggedBatch,
+            "COUNTER" => WriteType::Counter,
+            "BATCH_LOG" => WriteType::BatchLog,
+            "CAS" => WriteType::Cas,
+            _ => return Err(NativeError::InvalidVariant),
+        };
+        Ok(write_type)
+    }
+}
+
+/// How far a write that timed out got.
+///
+/// ### Fields
+/// - `consistency`: The consistency level of the write.
+/// - `received`: The replicas that acknowledged the write.
+/// - `block_for`: The acknowledgements the consistency level required.
+/// - `write_type`: The kind of write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteTimeout {
+    pub consistency: Consistency,
+    pub received: Int,
+    pub block_for: Int,
+    pub write_type: WriteType,
+}
+
+impl WriteTimeout {
+    /// ```md
+    /// +---------+---------+---------+---------+---------+
+    /// | cl (2)  | received (4)      | blockfor (4)      | writeType ([string])
+    /// +---------+---------+---------+---------+---------+
+    /// ```
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = (self.consistency.to_code()? as u16).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.received.to_be_bytes());
+        bytes.extend_from_slice(&self.block_for.to_be_bytes());
+        bytes.extend(self.write_type.name().to_string().to_string_bytes()?);
+        Ok(bytes)
+    }
+
+    fn from_bytes(cursor: &mut std::io::Cursor<&[u8]>) -> Result<Self, NativeError> {
+        let mut consistency_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut consistency_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        Ok(Self {
+            consistency: Consistency::from_code(u16::from_be_bytes(consistency_bytes))?,
+            received: Int::deserialize(cursor)?,
+            block_for: Int::deserialize(cursor)?,
+            write_type: WriteType::from_name(&String::from_string_bytes(cursor)?)?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub struct WriteTimeout;
+pub struct ReadTimeout;
 #[derive(Debug, PartialEq)]
 pub struct UnavailableException;
 
@@ -59,8 +149,10 @@ pub struct UnavailableException;
 pub enum Error {
     /// Something unexpected happened. This indicates a server-side bug.
     ServerError(String),
-    /// Timeout exception during a write request.
+    /// Timeout exception during a write request, with how far the write got.
     WriteTimeout(String, WriteTimeout),
+    /// Timeout exception during a read request.
+    ReadTimeout(String, ReadTimeout),
     /// Some client message triggered a protocol violation (for instance
     /// a QUERY message is sent before a STARTUP one has been sent).
     ProtocolError(String),
@@ -73,6 +165,13 @@ pub enum Error {
     /// The request was a read request but the coordinator node is
     /// bootstrapping.
     IsBootstrapping(String),
+    /// An EXECUTE was sent with an id the server does not know, so the query must be
+    /// prepared again.
+    Unprepared(String),
+    /// The logged user does not have the permission to run the query.
+    Unauthorized(String),
+    /// The query is syntactically correct but invalid.
+    Invalid(String),
 }
 
 impl Serializable for Error {
@@ -84,8 +183,13 @@ impl Serializable for Error {
                 bytes.extend_from_slice(&ErrorCode::ServerError.to_u32().to_be_bytes());
                 bytes.extend_from_slice(message.as_bytes());
             }
-            Error::WriteTimeout(message, _) => {
+            Error::WriteTimeout(message, timeout) => {
                 bytes.extend_from_slice(&ErrorCode::WriteTimeout.to_u32().to_be_bytes());
+                bytes.extend(timeout.to_bytes()?);
+                bytes.extend_from_slice(message.as_bytes());
+            }
+            Error::ReadTimeout(message, _) => {
+                bytes.extend_from_slice(&ErrorCode::ReadTimeout.to_u32().to_be_bytes());
                 bytes.extend_from_slice(message.as_bytes());
             }
             Error::ProtocolError(message) => {
@@ -104,6 +208,18 @@ impl Serializable for Error {
                 bytes.extend_from_slice(&ErrorCode::IsBootstrapping.to_u32().to_be_bytes());
                 bytes.extend_from_slice(message.as_bytes());
             }
+            Error::Unprepared(message) => {
+                bytes.extend_from_slice(&ErrorCode::Unprepared.to_u32().to_be_bytes());
+                bytes.extend_from_slice(message.as_bytes());
+            }
+            Error::Unauthorized(message) => {
+                bytes.extend_from_slice(&ErrorCode::Unauthorized.to_u32().to_be_bytes());
+                bytes.extend_from_slice(message.as_bytes());
+            }
+            Error::Invalid(message) => {
+                bytes.extend_from_slice(&ErrorCode::Invalid.to_u32().to_be_bytes());
+                bytes.extend_from_slice(message.as_bytes());
+            }
         }
 
         Ok(bytes)
@@ -117,6 +233,11 @@ impl Serializable for Error {
             .map_err(|_| NativeError::CursorError)?;
 
         let code = ErrorCode::from_u32(u32::from_be_bytes(code_bytes))?;
+        // The details of a write timeout go before its message
+        let write_timeout = match code {
+            ErrorCode::WriteTimeout => Some(WriteTimeout::from_bytes(&mut cursor)?),
+            _ => None,
+        };
 
         let mut message_bytes = Vec::new();
 
@@ -129,13 +250,20 @@ impl Serializable for Error {
 
         let error = match code {
             ErrorCode::ServerError => Error::ServerError(message),
-            ErrorCode::WriteTimeout => Error::WriteTimeout(message, WriteTimeout),
+            ErrorCode::WriteTimeout => Error::WriteTimeout(
+                message,
+                write_timeout.ok_or(NativeError::DeserializationError)?,
+            ),
+            ErrorCode::ReadTimeout => Error::ReadTimeout(message, ReadTimeout),
             ErrorCode::ProtocolError => Error::ProtocolError(message),
             ErrorCode::Overloaded => Error::Overloaded(message),
             ErrorCode::UnavailableException => {
                 Error::UnavailableException(message, UnavailableException)
             }
             ErrorCode::IsBootstrapping => Error::IsBootstrapping(message),
+            ErrorCode::Unprepared => Error::Unprepared(message),
+            ErrorCode::Unauthorized => Error::Unauthorized(message),
+            ErrorCode::Invalid => Error::Invalid(message),
             _ => return Err(NativeError::InvalidVariant),
         };
 
@@ -193,4 +321,54 @@ mod tests {
 
         assert_eq!(error, Error::ProtocolError("Protocol error".to_string()));
     }
+
+    #[test]
+    fn test_timeouts_to_from_bytes() {
+        let read_timeout = Error::ReadTimeout("Read timed out".to_string(), ReadTimeout);
+        let bytes = read_timeout.to_bytes().unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x12, 0x00]);
+        assert_eq!(Error::from_bytes(&bytes).unwrap(), read_timeout);
+
+        let write_timeout = Error::WriteTimeout(
+            "Write timed out".to_string(),
+            WriteTimeout {
+                consistency: Consistency::Quorum,
+                received: 1,
+                block_for: 2,
+                write_type: WriteType::UnloggedBatch,
+            },
+        );
+        let bytes = write_timeout.to_bytes().unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x11, 0x00]);
+        assert_eq!(
+            bytes[4..14],
+            [0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02]
+        );
+        assert_eq!(bytes[14..16], [0x00, 0x0E]);
+        assert_eq!(Error::from_bytes(&bytes).unwrap(), write_timeout);
+    }
+
+    #[test]
+    fn test_unprepared_to_from_bytes() {
+        let unprepared = Error::Unprepared("Unknown prepared id".to_string());
+        let bytes = unprepared.to_bytes().unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x25, 0x00]);
+        assert_eq!(Error::from_bytes(&bytes).unwrap(), unprepared);
+    }
+
+    #[test]
+    fn test_unauthorized_to_from_bytes() {
+        let unauthorized = Error::Unauthorized("No MODIFY permission on sky.flights".to_string());
+        let bytes = unauthorized.to_bytes().unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x21, 0x00]);
+        assert_eq!(Error::from_bytes(&bytes).unwrap(), unauthorized);
+    }
+
+    #[test]
+    fn test_invalid_to_from_bytes() {
+        let invalid = Error::Invalid("Replication factor 3 is above the 2 live nodes".to_string());
+        let bytes = invalid.to_bytes().unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x22, 0x00]);
+        assert_eq!(Error::from_bytes(&bytes).unwrap(), invalid);
+    }
 }
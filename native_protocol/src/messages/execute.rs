@@ -0,0 +1,197 @@
+use std::io::Read;
+
+use crate::{errors::NativeError, types::read_bytes, Serializable};
+
+use super::query::{Consistency, Flag, QueryParams};
+
+/// Executes a query prepared with PREPARE, binding `values` to its `?` markers in order.
+/// Each value is the text of the value of its marker, without quotes (for example `EZE` or `42`),
+/// which the server binds as the type of the column of the marker.
+#[derive(PartialEq, Debug)]
+pub struct Execute {
+    /// The id returned by the server in the result of the PREPARE.
+    pub id: Vec<u8>,
+    pub values: Vec<String>,
+    pub params: QueryParams,
+}
+
+impl Execute {
+    pub fn new(id: Vec<u8>, values: Vec<String>, consistency: Consistency) -> Self {
+        let flags = if values.is_empty() {
+            vec![]
+        } else {
+            vec![Flag::Values]
+        };
+
+        Execute {
+            id,
+            values,
+            params: QueryParams::new(consistency, flags),
+        }
+    }
+
+    pub fn get_id(&self) -> &[u8] {
+        &self.id
+    }
+
+    pub fn get_values(&self) -> &[String] {
+        &self.values
+    }
+
+    pub fn get_consistency(&self) -> &str {
+        self.params.consistency.to_string()
+    }
+
+    /// Asks for the timings of the query in the custom payload of its result.
+    pub fn with_timings(mut self) -> Self {
+        self.params = self.params.with_timings();
+        self
+    }
+
+    pub fn wants_timings(&self) -> bool {
+        self.params.wants_timings()
+    }
+}
+
+impl Serializable for Execute {
+    /// ```md
+    /// 0         8        16        24        32
+    /// +---------+---------+---------+---------+
+    /// |  id length (2)    |     id bytes      |
+    /// +---------+---------+                   +
+    /// |                 ...                   |
+    /// +---------+---------+---------+---------+
+    /// |  consistency (2)  | flag (1)|         |
+    /// +---------+---------+---------+---------+
+    /// |  values count (2) |                   |
+    /// +---------+---------+---------+---------+
+    /// |        value length (4 bytes)         |
+    /// +---------+---------+---------+---------+
+    /// |              value bytes              |
+    /// +                                       +
+    /// |                 ...                   |
+    /// +---------+---------+---------+---------+
+    /// ```
+    ///
+    /// The values are only present if the `Values` flag is set.
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = Vec::new();
+
+        let id_len = u16::try_from(self.id.len()).map_err(|_| NativeError::SerializationError)?;
+        bytes.extend_from_slice(&id_len.to_be_bytes());
+        bytes.extend_from_slice(&self.id);
+
+        let consistency_code = self.params.consistency.to_code()?;
+        bytes.extend_from_slice(&(consistency_code as u16).to_be_bytes());
+
+        let flags_byte = self.params.flags_to_byte()?;
+        bytes.push(flags_byte);
+
+        if self.params.flags.contains(&Flag::Values) {
+            let count =
+                u16::try_from(self.values.len()).map_err(|_| NativeError::SerializationError)?;
+            bytes.extend_from_slice(&count.to_be_bytes());
+            for value in &self.values {
+                bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, NativeError> {
+        let mut cursor = std::io::Cursor::new(bytes);
+
+        let mut id_len_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut id_len_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        let mut id = vec![0u8; u16::from_be_bytes(id_len_bytes) as usize];
+        cursor
+            .read_exact(&mut id)
+            .map_err(|_| NativeError::CursorError)?;
+
+        let mut consistency_code_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut consistency_code_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        let consistency = Consistency::from_code(u16::from_be_bytes(consistency_code_bytes))?;
+
+        let mut flags_byte = [0u8; 1];
+        cursor
+            .read_exact(&mut flags_byte)
+            .map_err(|_| NativeError::CursorError)?;
+        let flags = QueryParams::byte_to_flags(flags_byte[0])?;
+
+        let mut values = Vec::new();
+        if flags.contains(&Flag::Values) {
+            let mut count_bytes = [0u8; 2];
+            cursor
+                .read_exact(&mut count_bytes)
+                .map_err(|_| NativeError::CursorError)?;
+            for _ in 0..u16::from_be_bytes(count_bytes) {
+                let mut value_len_bytes = [0u8; 4];
+                cursor
+                    .read_exact(&mut value_len_bytes)
+                    .map_err(|_| NativeError::CursorError)?;
+                let value_bytes =
+                    read_bytes(&mut cursor, u32::from_be_bytes(value_len_bytes) as usize)?;
+                values.push(
+                    String::from_utf8(value_bytes)
+                        .map_err(|_| NativeError::DeserializationError)?,
+                );
+            }
+        }
+
+        Ok(Execute {
+            id,
+            values,
+            params: QueryParams::new(consistency, flags),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute_to_bytes() {
+        let execute = Execute::new(
+            vec![0xAB, 0xCD],
+            vec!["EZE".to_string(), "7".to_string()],
+            Consistency::Quorum,
+        );
+
+        let expected_bytes: Vec<u8> = vec![
+            // Id
+            0x00, 0x02, 0xAB, 0xCD, //
+            // Consistency and flags (Values)
+            0x00, 0x04, 0x01, //
+            // Two values
+            0x00, 0x02, //
+            0x00, 0x00, 0x00, 0x03, b'E', b'Z', b'E', //
+            0x00, 0x00, 0x00, 0x01, b'7',
+        ];
+
+        assert_eq!(execute.to_bytes().unwrap(), expected_bytes);
+    }
+
+    #[test]
+    fn test_execute_from_bytes() {
+        let execute = Execute::new(
+            vec![1, 2, 3, 4],
+            vec!["'AEP'".to_string()],
+            Consistency::One,
+        );
+        let bytes = execute.to_bytes().unwrap();
+        assert_eq!(Execute::from_bytes(&bytes).unwrap(), execute);
+
+        // Without values, the count is left out
+        let execute = Execute::new(vec![9], vec![], Consistency::All);
+        let bytes = execute.to_bytes().unwrap();
+        assert_eq!(bytes, vec![0x00, 0x01, 0x09, 0x00, 0x05, 0x00]);
+        assert_eq!(Execute::from_bytes(&bytes).unwrap(), execute);
+    }
+}
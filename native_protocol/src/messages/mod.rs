@@ -1,4 +1,9 @@
 pub mod auth;
+pub mod batch;
 pub mod error;
+pub mod execute;
+pub mod prepare;
 pub mod query;
 pub mod result;
+pub mod startup;
+pub mod supported;
@@ -0,0 +1,77 @@
+use std::io::Read;
+
+use crate::{errors::NativeError, types::read_bytes, Serializable};
+
+/// Prepares a query for later execution (through EXECUTE). The query may contain `?` bind
+/// markers, whose values are given on each execution.
+#[derive(PartialEq, Debug)]
+pub struct Prepare {
+    pub query: String,
+}
+
+impl Prepare {
+    pub fn new(query: String) -> Self {
+        Prepare { query }
+    }
+
+    pub fn get_query(&self) -> &str {
+        &self.query
+    }
+}
+
+impl Serializable for Prepare {
+    // this is a [long string]
+    /// ```md
+    /// 0         8        16        24        32
+    /// +---------+---------+---------+---------+
+    /// |        query length (4 bytes)         |
+    /// +---------+---------+---------+---------+
+    /// |              query bytes              |
+    /// +                                       +
+    /// |                 ...                   |
+    /// +---------+---------+---------+---------+
+    /// ```
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = Vec::new();
+
+        let query_len = self.query.len() as u32;
+        bytes.extend_from_slice(&query_len.to_be_bytes());
+        bytes.extend_from_slice(self.query.as_bytes());
+
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, NativeError> {
+        let mut cursor = std::io::Cursor::new(bytes);
+
+        let mut query_len_bytes = [0u8; 4];
+        cursor
+            .read_exact(&mut query_len_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+        let query_len = u32::from_be_bytes(query_len_bytes) as usize;
+
+        let query_bytes = read_bytes(&mut cursor, query_len)?;
+        let query =
+            String::from_utf8(query_bytes).map_err(|_| NativeError::DeserializationError)?;
+
+        Ok(Prepare { query })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_to_from_bytes() {
+        let prepare = Prepare::new("SELECT * FROM flights WHERE id = ?".to_string());
+
+        let bytes = prepare.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], &[0x00, 0x00, 0x00, 0x22]);
+        assert_eq!(&bytes[4..], prepare.query.as_bytes());
+
+        assert_eq!(Prepare::from_bytes(&bytes).unwrap(), prepare);
+        assert!(Prepare::from_bytes(&bytes[..10]).is_err());
+        assert!(Prepare::from_bytes(&[0xff, 0xff, 0xff, 0xff, b'S']).is_err());
+    }
+}
@@ -1,8 +1,12 @@
 use std::io::Read;
 
-use crate::{errors::NativeError, Serializable};
+use crate::{
+    errors::NativeError,
+    types::{read_bytes, Bytes},
+    Serializable,
+};
 
-enum ConsistencyCode {
+pub(crate) enum ConsistencyCode {
     Any = 0x0000,
     One = 0x0001,
     Two = 0x0002,
@@ -67,7 +71,7 @@ impl Consistency {
         }
     }
 
-    fn to_code(&self) -> Result<ConsistencyCode, NativeError> {
+    pub(crate) fn to_code(&self) -> Result<ConsistencyCode, NativeError> {
         let consistency_code = match self {
             Consistency::Any => ConsistencyCode::Any,
             Consistency::One => ConsistencyCode::One,
@@ -85,7 +89,7 @@ impl Consistency {
         Ok(consistency_code)
     }
 
-    fn from_code(consistency_code: u16) -> Result<Self, NativeError> {
+    pub(crate) fn from_code(consistency_code: u16) -> Result<Self, NativeError> {
         let consistency = match consistency_code {
             0x0000 => Consistency::Any,
             0x0001 => Consistency::One,
@@ -113,6 +117,7 @@ enum FlagCode {
     WithSerialConsistency = 0x10,
     WithDefaultTimestamp = 0x20,
     WithNamesForValues = 0x40,
+    WithTimings = 0x80,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -144,22 +149,77 @@ pub enum Flag {
     /// is ignored otherwise. If present, the values from the 0x01 flag will
     /// be preceded by a name.
     WithNamesForValues,
+    /// If set, the result is sent with a custom payload holding where the
+    /// coordinator spent the time of the query (parse, route, replica wait,
+    /// merge and repair). Not part of the protocol, it uses the unused 0x80 bit.
+    WithTimings,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct QueryParams {
     /// Is the consistency level for the operation.
-    consistency: Consistency,
+    pub(crate) consistency: Consistency,
     /// Is a byte whose bits define the options for this query.
-    flags: Vec<Flag>, // TODO: should be struct with possible values
+    pub(crate) flags: Vec<Flag>, // TODO: should be struct with possible values
+    /// The rows the client wants in each page of the result, sent with `Flag::PageSize`.
+    pub(crate) page_size: Option<i32>,
+    /// Where the page the client wants starts, as the previous page of the result said, sent
+    /// with `Flag::WithPagingState`.
+    pub(crate) paging_state: Option<Vec<u8>>,
 }
 
 impl QueryParams {
     pub fn new(consistency: Consistency, flags: Vec<Flag>) -> Self {
-        QueryParams { consistency, flags }
+        QueryParams {
+            consistency,
+            flags,
+            page_size: None,
+            paging_state: None,
+        }
+    }
+
+    /// Asks for the result in pages of `page_size` rows.
+    pub fn with_page_size(mut self, page_size: i32) -> Self {
+        if !self.flags.contains(&Flag::PageSize) {
+            self.flags.push(Flag::PageSize);
+        }
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Asks for the page of the result that starts at `paging_state`, as returned with the
+    /// previous page.
+    pub fn with_paging_state(mut self, paging_state: Vec<u8>) -> Self {
+        if !self.flags.contains(&Flag::WithPagingState) {
+            self.flags.push(Flag::WithPagingState);
+        }
+        self.paging_state = Some(paging_state);
+        self
+    }
+
+    /// Returns the rows the client wants in each page of the result, if it wants it paged.
+    pub fn page_size(&self) -> Option<i32> {
+        self.page_size.filter(|page_size| *page_size > 0)
+    }
+
+    /// Returns where the page the client wants starts, if it is not the first one.
+    pub fn paging_state(&self) -> Option<&[u8]> {
+        self.paging_state.as_deref()
     }
 
-    fn flags_to_byte(&self) -> Result<u8, NativeError> {
+    /// Asks for the timings of the query in the custom payload of its result.
+    pub fn with_timings(mut self) -> Self {
+        if !self.wants_timings() {
+            self.flags.push(Flag::WithTimings);
+        }
+        self
+    }
+
+    pub fn wants_timings(&self) -> bool {
+        self.flags.contains(&Flag::WithTimings)
+    }
+
+    pub(crate) fn flags_to_byte(&self) -> Result<u8, NativeError> {
         let mut flags_byte: u8 = 0;
 
         for flag in &self.flags {
@@ -171,13 +231,14 @@ impl QueryParams {
                 Flag::WithSerialConsistency => FlagCode::WithSerialConsistency as u8,
                 Flag::WithDefaultTimestamp => FlagCode::WithDefaultTimestamp as u8,
                 Flag::WithNamesForValues => FlagCode::WithNamesForValues as u8,
+                Flag::WithTimings => FlagCode::WithTimings as u8,
             }
         }
 
         Ok(flags_byte)
     }
 
-    fn byte_to_flags(flags_byte: u8) -> Result<Vec<Flag>, NativeError> {
+    pub(crate) fn byte_to_flags(flags_byte: u8) -> Result<Vec<Flag>, NativeError> {
         let mut flags = Vec::new();
 
         if flags_byte & FlagCode::Values as u8 != 0 {
@@ -201,6 +262,9 @@ impl QueryParams {
         if flags_byte & FlagCode::WithNamesForValues as u8 != 0 {
             flags.push(Flag::WithNamesForValues);
         }
+        if flags_byte & FlagCode::WithTimings as u8 != 0 {
+            flags.push(Flag::WithTimings);
+        }
 
         Ok(flags)
     }
@@ -224,6 +288,14 @@ impl Query {
     pub fn get_consistency(&self) -> &str {
         self.params.consistency.to_string()
     }
+
+    pub fn wants_timings(&self) -> bool {
+        self.params.wants_timings()
+    }
+
+    pub fn params(&self) -> &QueryParams {
+        &self.params
+    }
 }
 
 impl Serializable for Query {
@@ -263,7 +335,13 @@ impl Serializable for Query {
         let flags_byte = self.params.flags_to_byte()?;
         bytes.push(flags_byte);
 
-        // TODO: Add optional parameters based on flags.
+        // TODO: Add the rest of the optional parameters based on flags.
+        if let Some(page_size) = self.params.page_size {
+            bytes.extend_from_slice(&page_size.to_be_bytes());
+        }
+        if let Some(paging_state) = &self.params.paging_state {
+            bytes.extend_from_slice(&Bytes::Vec(paging_state.clone()).to_bytes()?);
+        }
 
         Ok(bytes)
     }
@@ -281,10 +359,7 @@ impl Serializable for Query {
         let query_len = u32::from_be_bytes(query_len_bytes) as usize;
 
         // Read the query string (UTF-8)
-        let mut query_bytes = vec![0u8; query_len];
-        cursor
-            .read_exact(&mut query_bytes)
-            .map_err(|_| NativeError::CursorError)?;
+        let query_bytes = read_bytes(&mut cursor, query_len)?;
         let query =
             String::from_utf8(query_bytes).map_err(|_| NativeError::DeserializationError)?;
 
@@ -307,9 +382,23 @@ impl Serializable for Query {
 
         // Convert the flags byte to a vector of `Flag`
         let flags = QueryParams::byte_to_flags(flags_byte)?;
-
-        // Create the `QueryParams` and the `Query` struct
-        let params = QueryParams { consistency, flags };
+        let mut params = QueryParams::new(consistency, flags);
+
+        // Values are not sent with queries, so the paging parameters come right after the flags
+        if !params.flags.contains(&Flag::Values) {
+            if params.flags.contains(&Flag::PageSize) {
+                let mut page_size_bytes = [0u8; 4];
+                cursor
+                    .read_exact(&mut page_size_bytes)
+                    .map_err(|_| NativeError::CursorError)?;
+                params.page_size = Some(i32::from_be_bytes(page_size_bytes));
+            }
+            if params.flags.contains(&Flag::WithPagingState) {
+                if let Bytes::Vec(paging_state) = Bytes::from_bytes(&mut cursor)? {
+                    params.paging_state = Some(paging_state);
+                }
+            }
+        }
 
         Ok(Query { query, params })
     }
@@ -322,10 +411,7 @@ mod tests {
     #[test]
     fn query_to_bytes_ok() {
         let query = "SELECT * FROM users WHERE id = 2".to_string();
-        let params = QueryParams {
-            consistency: Consistency::Quorum,
-            flags: vec![Flag::Values, Flag::PageSize],
-        };
+        let params = QueryParams::new(Consistency::Quorum, vec![Flag::Values, Flag::PageSize]);
 
         let query_message = Query {
             query: query.to_string(),
@@ -353,10 +439,7 @@ mod tests {
     #[test]
     fn test_to_bytes() {
         let query = "SELECT * FROM users WHERE id = 2".to_string();
-        let params = QueryParams {
-            consistency: Consistency::Quorum,
-            flags: vec![Flag::Values, Flag::PageSize],
-        };
+        let params = QueryParams::new(Consistency::Quorum, vec![Flag::Values, Flag::PageSize]);
 
         let query_len = query.len();
 
@@ -395,10 +478,7 @@ mod tests {
     #[test]
     fn test_from_bytes() {
         let original_query = "SELECT * FROM users WHERE id = ?".to_string();
-        let params = QueryParams {
-            consistency: Consistency::Quorum,
-            flags: vec![Flag::Values, Flag::PageSize],
-        };
+        let params = QueryParams::new(Consistency::Quorum, vec![Flag::Values, Flag::PageSize]);
 
         let expected_query = Query {
             query: original_query,
@@ -414,4 +494,38 @@ mod tests {
         // Check that the original and deserialized queries are the same
         assert_eq!(expected_query, deserialized_query);
     }
+
+    #[test]
+    fn test_query_with_paging_to_from_bytes() {
+        let params = QueryParams::new(Consistency::One, vec![])
+            .with_page_size(100)
+            .with_paging_state(vec![0, 1]);
+        let query = Query::new("SELECT * FROM users".to_string(), params);
+
+        let bytes = query.to_bytes().unwrap();
+        // The page size and then the paging state follow the flags
+        assert_eq!(
+            &bytes[bytes.len() - 10..],
+            &[0, 0, 0, 100, 0, 0, 0, 2, 0, 1]
+        );
+
+        let deserialized = Query::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized.params().page_size(), Some(100));
+        assert_eq!(
+            deserialized.params().paging_state(),
+            Some([0, 1].as_slice())
+        );
+        assert_eq!(deserialized, query);
+    }
+
+    #[test]
+    fn test_query_with_timings() {
+        let params = QueryParams::new(Consistency::One, vec![]).with_timings();
+        let query = Query::new("SELECT * FROM users".to_string(), params);
+        assert!(query.wants_timings());
+
+        let bytes = query.to_bytes().unwrap();
+        assert_eq!(bytes[bytes.len() - 1], FlagCode::WithTimings as u8);
+        assert!(Query::from_bytes(&bytes).unwrap().wants_timings());
+    }
 }
@@ -2,12 +2,12 @@ use std::io::Read;
 
 use crate::{
     errors::NativeError,
-    types::{CassandraString, OptionBytes},
+    types::{Bytes, CassandraString, OptionBytes},
 };
 
 use super::rows::ColumnType;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ColumnSpec {
     pub keyspace: Option<String>,
     pub table_name: Option<String>,
@@ -67,7 +67,7 @@ impl ColumnSpec {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TableSpec {
     pub keyspace: String,
     pub table_name: String,
@@ -79,7 +79,7 @@ enum MetadataFlagsCode {
     NoMetadata = 0x0004,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct MetadataFlags {
     pub global_table_spec: bool,
     pub has_more_pages: bool,
@@ -116,10 +116,13 @@ impl MetadataFlags {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Metadata {
     pub flags: MetadataFlags,
     pub columns_count: u32,
+    /// Where the next page of the result starts, sent back by the client to get it. Only
+    /// present if `flags.has_more_pages` is set.
+    pub paging_state: Option<Vec<u8>>,
     pub global_table_spec: Option<TableSpec>,
     pub col_spec_i: Vec<ColumnSpec>,
 }
@@ -146,6 +149,7 @@ impl Metadata {
         Self {
             flags,
             columns_count,
+            paging_state: None,
             global_table_spec: None,
             col_spec_i,
         }
@@ -158,6 +162,11 @@ impl Metadata {
 
         bytes.extend_from_slice(&self.columns_count.to_be_bytes());
 
+        if self.flags.has_more_pages {
+            let paging_state = Bytes::Vec(self.paging_state.clone().unwrap_or_default());
+            bytes.extend_from_slice(&paging_state.to_bytes()?);
+        }
+
         if let Some(table_spec) = &self.global_table_spec {
             bytes.extend_from_slice(table_spec.keyspace.to_string_bytes()?.as_slice());
             bytes.extend_from_slice(table_spec.table_name.to_string_bytes()?.as_slice());
@@ -182,6 +191,14 @@ impl Metadata {
             .map_err(|_| NativeError::CursorError)?;
         let columns_count = u32::from_be_bytes(columns_count_bytes);
 
+        let paging_state = match flags.has_more_pages {
+            true => match Bytes::from_bytes(cursor)? {
+                Bytes::Vec(paging_state) => Some(paging_state),
+                Bytes::None => None,
+            },
+            false => None,
+        };
+
         let keyspace = String::from_string_bytes(cursor)?;
         let table_name = String::from_string_bytes(cursor)?;
 
@@ -203,6 +220,7 @@ impl Metadata {
         Ok(Metadata {
             flags,
             columns_count,
+            paging_state,
             global_table_spec,
             col_spec_i,
         })
@@ -353,6 +371,7 @@ mod tests {
                 no_metadata: false,
             },
             columns_count: 1,
+            paging_state: None,
             global_table_spec: Some(TableSpec {
                 keyspace: "test_keyspace".to_string(),
                 table_name: "test_table".to_string(),
@@ -389,6 +408,7 @@ mod tests {
                 no_metadata: false,
             },
             columns_count: 1,
+            paging_state: None,
             global_table_spec: Some(TableSpec {
                 keyspace: "test_keyspace".to_string(),
                 table_name: "test_table".to_string(),
@@ -417,6 +437,7 @@ mod tests {
                 no_metadata: false,
             },
             columns_count: 1,
+            paging_state: None,
             global_table_spec: None,
             col_spec_i: vec![ColumnSpec {
                 keyspace: None,
@@ -448,6 +469,7 @@ mod tests {
                 no_metadata: false,
             },
             columns_count: 1,
+            paging_state: None,
             global_table_spec: None,
             col_spec_i: vec![ColumnSpec {
                 keyspace: None,
@@ -464,4 +486,20 @@ mod tests {
 
         assert_eq!(expected_metadata, metadata);
     }
+
+    #[test]
+    fn test_metadata_with_more_pages_to_from_bytes() {
+        let mut expected_metadata = Metadata::new(1, vec![("id".to_string(), ColumnType::Int)]);
+        expected_metadata.flags.has_more_pages = true;
+        expected_metadata.paging_state = Some(vec![0, 0, 0, 7]);
+
+        let bytes = expected_metadata.to_bytes().unwrap();
+        // The paging state follows the count of the columns
+        assert_eq!(&bytes[8..16], &[0, 0, 0, 4, 0, 0, 0, 7]);
+
+        let mut cursor = Cursor::new(bytes.as_slice());
+        let metadata = Metadata::from_bytes(&mut cursor).unwrap();
+
+        assert_eq!(expected_metadata, metadata);
+    }
 }
@@ -12,6 +12,28 @@ pub struct Prepared {
     result_metadata: Metadata,
 }
 
+impl Prepared {
+    /// Creates the result of a PREPARE, with the `metadata` of its bind markers and the
+    /// `result_metadata` of the rows it returns.
+    pub fn new(id: Vec<u8>, metadata: Metadata, result_metadata: Metadata) -> Self {
+        Prepared {
+            id,
+            metadata,
+            result_metadata,
+        }
+    }
+
+    /// Returns the id to execute the prepared query with.
+    pub fn get_id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Returns the metadata of the bind markers of the prepared query.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
 impl Serializable for Prepared {
     fn to_bytes(&self) -> std::result::Result<Vec<u8>, NativeError> {
         let mut bytes = Vec::new();
@@ -73,6 +95,7 @@ mod tests {
                 no_metadata: false,
             },
             columns_count: 1,
+            paging_state: None,
             global_table_spec: Some(TableSpec {
                 keyspace: "test_keyspace".to_string(),
                 table_name: "test_table".to_string(),
@@ -34,7 +34,7 @@ enum ColumnTypeCode {
     Tuple = 0x0031,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ColumnType {
     Custom(String),
     Ascii,
@@ -759,6 +759,7 @@ mod tests {
                     no_metadata: false,
                 },
                 columns_count: 1,
+                paging_state: None,
                 global_table_spec: Some(TableSpec {
                     keyspace: "test_keyspace".to_string(),
                     table_name: "test_table".to_string(),
@@ -805,6 +806,7 @@ mod tests {
                     no_metadata: false,
                 },
                 columns_count: 1,
+                paging_state: None,
                 global_table_spec: Some(TableSpec {
                     keyspace: "test_keyspace".to_string(),
                     table_name: "test_table".to_string(),
@@ -840,6 +842,7 @@ mod tests {
                     no_metadata: false,
                 },
                 columns_count: 1,
+                paging_state: None,
                 global_table_spec: None,
                 col_spec_i: vec![ColumnSpec {
                     keyspace: None,
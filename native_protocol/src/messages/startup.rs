@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+};
+
+use crate::{
+    checksum::Checksum, compression::Compression, errors::NativeError, frame::FrameOptions,
+    messages::error::Error, types::CassandraString, Serializable,
+};
+
+/// Option with the version of CQL the client wants to use. Mandatory.
+pub const CQL_VERSION: &str = "CQL_VERSION";
+/// Option with the algorithm to compress the frames of the connection with. Optional.
+pub const COMPRESSION: &str = "COMPRESSION";
+/// Option with the algorithm to checksum the frames of the connection with. Optional.
+pub const CHECKSUM: &str = "CHECKSUM";
+
+/// The version of CQL sent by default, and the major version the server supports.
+pub const SUPPORTED_CQL_VERSION: &str = "3.0.0";
+
+/// Initializes the connection. The server answers with a `READY` message or, if
+/// authentication is required, with an `AUTHENTICATE` message.
+///
+/// ### Fields
+///
+/// - `options` - The [string map] of options of the connection. The server supports
+///   `CQL_VERSION`, `COMPRESSION` and `CHECKSUM`; any other option is ignored.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Startup {
+    pub options: BTreeMap<String, String>,
+}
+
+impl Default for Startup {
+    /// A `STARTUP` with the supported `CQL_VERSION` and no compression.
+    fn default() -> Self {
+        Self {
+            options: BTreeMap::from([(CQL_VERSION.to_string(), SUPPORTED_CQL_VERSION.to_string())]),
+        }
+    }
+}
+
+impl Startup {
+    pub fn new(options: BTreeMap<String, String>) -> Self {
+        Self { options }
+    }
+
+    /// Returns a `STARTUP` with the default options that asks to compress the connection
+    /// with `compression`.
+    pub fn with_compression(compression: Compression) -> Self {
+        let mut startup = Self::default();
+        startup
+            .options
+            .insert(COMPRESSION.to_string(), compression.name().to_string());
+        startup
+    }
+
+    /// Returns a `STARTUP` with the default options that asks for the compression and checksum
+    /// of `options`, if any.
+    pub fn with_options(options: &FrameOptions) -> Self {
+        let startup = match options.compression {
+            Some(compression) => Self::with_compression(compression),
+            None => Self::default(),
+        };
+        match options.checksum {
+            Some(checksum) => startup.with_checksum(checksum),
+            None => startup,
+        }
+    }
+
+    /// Asks to also checksum the frames of the connection with `checksum`.
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.options
+            .insert(CHECKSUM.to_string(), checksum.name().to_string());
+        self
+    }
+
+    /// Returns the options of the frames the `STARTUP` asks for, which the server accepts
+    /// by answering it without an error.
+    pub fn frame_options(&self) -> Result<FrameOptions, Error> {
+        let compression = self
+            .options
+            .get(COMPRESSION)
+            .map(|name| {
+                Compression::from_name(name).map_err(|_| {
+                    Error::ProtocolError(format!("Unsupported {} {}", COMPRESSION, name))
+                })
+            })
+            .transpose()?;
+        let checksum = self
+            .options
+            .get(CHECKSUM)
+            .map(|name| {
+                Checksum::from_name(name)
+                    .map_err(|_| Error::ProtocolError(format!("Unsupported {} {}", CHECKSUM, name)))
+            })
+            .transpose()?;
+        Ok(FrameOptions {
+            compression,
+            checksum,
+        })
+    }
+
+    /// Checks the options against what the server supports and returns the compression
+    /// and checksum to use for the frames that follow in the connection, if they were asked
+    /// for.
+    ///
+    /// Fails with a `ProtocolError` if `CQL_VERSION` is missing or its major version is not
+    /// the supported one, or if `COMPRESSION` or `CHECKSUM` name an unknown algorithm.
+    pub fn negotiate(&self) -> Result<FrameOptions, Error> {
+        let version = self.options.get(CQL_VERSION).ok_or_else(|| {
+            Error::ProtocolError(format!("{} is mandatory in STARTUP", CQL_VERSION))
+        })?;
+        if !is_supported_cql_version(version) {
+            return Err(Error::ProtocolError(format!(
+                "Unsupported {} {}, the supported version is {}",
+                CQL_VERSION, version, SUPPORTED_CQL_VERSION
+            )));
+        }
+
+        self.frame_options()
+    }
+}
+
+/// A version is supported if it has the form `major[.minor[.patch]]` and the same major
+/// version as `SUPPORTED_CQL_VERSION`.
+fn is_supported_cql_version(version: &str) -> bool {
+    let parts: Vec<&str> = version.split('.').collect();
+    let supported_major = SUPPORTED_CQL_VERSION.split('.').next();
+
+    parts.len() <= 3
+        && parts.iter().all(|part| part.parse::<u32>().is_ok())
+        && parts.first().copied() == supported_major
+}
+
+impl Serializable for Startup {
+    /// Converts the `Startup` message to bytes, as a [string map]: a [short] with the number
+    /// of pairs, followed by each key and value as a [string].
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = Vec::new();
+        let pairs =
+            u16::try_from(self.options.len()).map_err(|_| NativeError::SerializationError)?;
+        bytes.extend_from_slice(&pairs.to_be_bytes());
+        for (key, value) in &self.options {
+            bytes.extend_from_slice(&key.to_string_bytes()?);
+            bytes.extend_from_slice(&value.to_string_bytes()?);
+        }
+        Ok(bytes)
+    }
+
+    /// Converts bytes to a `Startup` message.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, NativeError>
+    where
+        Self: Sized,
+    {
+        let mut cursor = Cursor::new(bytes);
+        let mut pairs_bytes = [0u8; 2];
+        cursor
+            .read_exact(&mut pairs_bytes)
+            .map_err(|_| NativeError::CursorError)?;
+
+        let mut options = BTreeMap::new();
+        for _ in 0..u16::from_be_bytes(pairs_bytes) {
+            let key = String::from_string_bytes(&mut cursor)?;
+            let value = String::from_string_bytes(&mut cursor)?;
+            options.insert(key, value);
+        }
+        Ok(Startup { options })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_to_from_bytes() {
+        let startup = Startup::with_compression(Compression::Lz4);
+
+        let bytes = startup.to_bytes().unwrap();
+        let new_startup = Startup::from_bytes(&bytes).unwrap();
+
+        assert_eq!(new_startup, startup);
+        assert_eq!(new_startup.options[COMPRESSION], "lz4");
+    }
+
+    #[test]
+    fn negotiate_the_supported_options() {
+        assert_eq!(Startup::default().negotiate(), Ok(FrameOptions::default()));
+        assert_eq!(
+            Startup::with_compression(Compression::Lz4).negotiate(),
+            Ok(FrameOptions::compressed(Some(&Compression::Lz4)))
+        );
+        assert_eq!(
+            Startup::default()
+                .with_checksum(Checksum::Crc32)
+                .negotiate(),
+            Ok(FrameOptions {
+                compression: None,
+                checksum: Some(Checksum::Crc32),
+            })
+        );
+        let options = FrameOptions {
+            compression: Some(Compression::Snappy),
+            checksum: Some(Checksum::Crc32),
+        };
+        assert_eq!(Startup::with_options(&options).negotiate(), Ok(options));
+
+        let startup = Startup::new(BTreeMap::from([
+            (CQL_VERSION.to_string(), "3.4".to_string()),
+            ("DRIVER_NAME".to_string(), "rustic".to_string()),
+        ]));
+        assert_eq!(startup.negotiate(), Ok(FrameOptions::default()));
+    }
+
+    #[test]
+    fn negotiate_rejects_unsupported_options() {
+        let missing_version = Startup::new(BTreeMap::new());
+        let old_version = Startup::new(BTreeMap::from([(
+            CQL_VERSION.to_string(),
+            "2.0.0".to_string(),
+        )]));
+        let mut unknown_compression = Startup::default();
+        unknown_compression
+            .options
+            .insert(COMPRESSION.to_string(), "zstd".to_string());
+        let mut unknown_checksum = Startup::default();
+        unknown_checksum
+            .options
+            .insert(CHECKSUM.to_string(), "md5".to_string());
+
+        for startup in [
+            missing_version,
+            old_version,
+            unknown_compression,
+            unknown_checksum,
+        ] {
+            assert!(matches!(startup.negotiate(), Err(Error::ProtocolError(_))));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+};
+
+use crate::{
+    checksum::CRC32,
+    compression::{LZ4, SNAPPY},
+    errors::NativeError,
+    messages::startup::{CHECKSUM, COMPRESSION, CQL_VERSION, SUPPORTED_CQL_VERSION},
+    types::CassandraString,
+    Serializable,
+};
+
+/// The answer to an `OPTIONS` request: the values the server supports for each option of a
+/// `STARTUP` message. Since it is cheap to answer, drivers also send `OPTIONS` to check that
+/// an idle connection still works.
+///
+/// ### Fields
+///
+/// - `options` - The [string multimap] of supported values, by option.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Supported {
+    pub options: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Supported {
+    /// The options supported by the nodes.
+    fn default() -> Self {
+        Self {
+            options: BTreeMap::from([
+                (
+                    CQL_VERSION.to_string(),
+                    vec![SUPPORTED_CQL_VERSION.to_string()],
+                ),
+                (
+                    COMPRESSION.to_string(),
+                    vec![LZ4.to_string(), SNAPPY.to_string()],
+                ),
+                (CHECKSUM.to_string(), vec![CRC32.to_string()]),
+            ]),
+        }
+    }
+}
+
+fn read_short(cursor: &mut Cursor<&[u8]>) -> Result<u16, NativeError> {
+    let mut bytes = [0u8; 2];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| NativeError::CursorError)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn short_bytes(length: usize) -> Result<[u8; 2], NativeError> {
+    u16::try_from(length)
+        .map(u16::to_be_bytes)
+        .map_err(|_| NativeError::SerializationError)
+}
+
+impl Serializable for Supported {
+    /// Converts the `Supported` message to bytes, as a [string multimap]: a [short] with the
+    /// number of pairs, followed by each key as a [string] and its values as a [string list]
+    /// (a [short] with the number of values, followed by each one as a [string]).
+    fn to_bytes(&self) -> Result<Vec<u8>, NativeError> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&short_bytes(self.options.len())?);
+        for (key, values) in &self.options {
+            bytes.extend_from_slice(&key.to_string_bytes()?);
+            bytes.extend_from_slice(&short_bytes(values.len())?);
+            for value in values {
+                bytes.extend_from_slice(&value.to_string_bytes()?);
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Converts bytes to a `Supported` message.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, NativeError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let mut options = BTreeMap::new();
+        for _ in 0..read_short(&mut cursor)? {
+            let key = String::from_string_bytes(&mut cursor)?;
+            let values = (0..read_short(&mut cursor)?)
+                .map(|_| String::from_string_bytes(&mut cursor))
+                .collect::<Result<Vec<_>, _>>()?;
+            options.insert(key, values);
+        }
+        Ok(Supported { options })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_to_from_bytes() {
+        let supported = Supported::default();
+
+        let bytes = supported.to_bytes().unwrap();
+        let new_supported = Supported::from_bytes(&bytes).unwrap();
+
+        assert_eq!(new_supported, supported);
+        assert_eq!(new_supported.options[COMPRESSION], vec!["lz4", "snappy"]);
+        assert!(Supported::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
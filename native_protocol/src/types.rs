@@ -1,11 +1,69 @@
-use std::io::{Cursor, Read};
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+};
 
 use crate::errors::NativeError;
 
+/// Reads the next `len` bytes of a frame. A length past the end of the frame is rejected before
+/// allocating for it, as it may come from a corrupted or hostile length prefix.
+pub fn read_bytes(cursor: &mut Cursor<&[u8]>, len: usize) -> Result<Vec<u8>, NativeError> {
+    let remaining = (cursor.get_ref().len() as u64).saturating_sub(cursor.position());
+    if len as u64 > remaining {
+        return Err(NativeError::CursorError);
+    }
+    let mut bytes = vec![0u8; len];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| NativeError::CursorError)?;
+    Ok(bytes)
+}
+
 /// A 2 bytes unsigned integer.
 pub type Short = u16;
 /// A 4 bytes signed integer.
 pub type Int = i32;
+/// A [bytes map]: extra values sent in a frame besides its message, such as the custom payload
+/// of a result.
+pub type CustomPayload = BTreeMap<String, Vec<u8>>;
+
+/// ```md
+/// +---------+---------+---------+---------+---------+
+/// |   count (2)       | key ([string]) | value ([bytes]) | ...
+/// +---------+---------+---------+---------+---------+
+/// ```
+pub fn custom_payload_to_bytes(
+    payload: &CustomPayload,
+) -> std::result::Result<Vec<u8>, NativeError> {
+    let count = Short::try_from(payload.len()).map_err(|_| NativeError::SerializationError)?;
+    let mut bytes = count.to_be_bytes().to_vec();
+
+    for (key, value) in payload {
+        bytes.extend(key.to_string_bytes()?);
+        bytes.extend(Bytes::Vec(value.clone()).to_bytes()?);
+    }
+    Ok(bytes)
+}
+
+pub fn custom_payload_from_bytes(
+    cursor: &mut Cursor<&[u8]>,
+) -> std::result::Result<CustomPayload, NativeError> {
+    let mut count_bytes = [0u8; 2];
+    cursor
+        .read_exact(&mut count_bytes)
+        .map_err(|_| NativeError::CursorError)?;
+
+    let mut payload = CustomPayload::new();
+    for _ in 0..Short::from_be_bytes(count_bytes) {
+        let key = String::from_string_bytes(cursor)?;
+        let value = match Bytes::from_bytes(cursor)? {
+            Bytes::Vec(value) => value,
+            Bytes::None => vec![],
+        };
+        payload.insert(key, value);
+    }
+    Ok(payload)
+}
 
 pub trait FromCursorDeserializable {
     fn deserialize(cursor: &mut Cursor<&[u8]>) -> Result<Self, NativeError>
@@ -143,12 +201,7 @@ impl Bytes {
             return Ok(Self::None);
         }
 
-        let mut bytes_bytes = vec![0u8; bytes_len as usize];
-        cursor
-            .read_exact(&mut bytes_bytes)
-            .map_err(|_| NativeError::CursorError)?;
-
-        Ok(Self::Vec(bytes_bytes))
+        Ok(Self::Vec(read_bytes(cursor, bytes_len as usize)?))
     }
 }
 
@@ -197,6 +250,29 @@ mod tests {
         assert_eq!(result, Bytes::Vec(vec![0x01, 0x02, 0x03, 0x00]));
     }
 
+    #[test]
+    fn test_from_bytes_longer_than_the_frame() {
+        let input = [0x7f, 0xff, 0xff, 0xff, 0x01, 0x02].as_slice();
+
+        let mut cursor = std::io::Cursor::new(input);
+
+        assert!(Bytes::from_bytes(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn custom_payload_from_to_bytes() {
+        let payload = CustomPayload::from([
+            ("parse_us".to_string(), 12i64.to_be_bytes().to_vec()),
+            ("merge_us".to_string(), vec![]),
+        ]);
+
+        let bytes = custom_payload_to_bytes(&payload).unwrap();
+        assert_eq!(&bytes[..2], &[0x00, 0x02]);
+
+        let mut cursor = Cursor::new(bytes.as_slice());
+        assert_eq!(custom_payload_from_bytes(&mut cursor).unwrap(), payload);
+    }
+
     #[test]
     fn string_from_to_bytes() {
         let string = "test_column".to_string();
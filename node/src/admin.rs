@@ -0,0 +1,545 @@
+//! Administrative commands accepted by a node on its admin port.
+//!
+//! The admin port speaks a tiny line based text protocol: the client sends one command per
+//! line and the node answers with zero or more lines of output followed by a status line,
+//! either `OK` or `ERROR <reason>`. It is meant for operators and test tooling (e.g. the flight
+//! simulator) that need to inspect a running node or inject faults into the cluster.
+
+use std::net::Ipv4Addr;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use driver::export::ExportFormat;
+
+use crate::errors::NodeError;
+use crate::internode_protocol::maintenance::MaintenanceOperation;
+use crate::metrics::Operation;
+
+/// Number of log records returned by `LOGS` when no amount is given.
+const DEFAULT_RECENT_LOGS: usize = 50;
+
+/// Time `DRAIN` waits for the open queries when no amount of seconds is given.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time `DECOMMISSION` waits for the open queries and then for the rows it streams when no
+/// amount of seconds is given.
+const DEFAULT_DECOMMISSION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The table, format, file and range of tokens of an `EXPORT TABLE` command.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TableExport {
+    pub keyspace: String,
+    pub table: String,
+    pub format: ExportFormat,
+    pub path: PathBuf,
+    pub tokens: Option<Range<u64>>,
+}
+
+/// A command sent to the admin port of a node.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AdminCommand {
+    /// Stops the node immediately, as if it had crashed: `Node::start` fails without waiting for its
+    /// threads, so the process exits.
+    Kill,
+    /// Drops every internode message exchanged with the given peer.
+    Partition(Ipv4Addr),
+    /// Removes every partition previously set on the node.
+    Heal,
+    /// Returns the last log records written by the node.
+    RecentLogs(usize),
+    /// Returns the events recorded by the node after the event with the given id.
+    Events(u64),
+    /// Moves the node to the given token of the ring. Tokens are 32 bit hashes.
+    Move(u64),
+    /// Sets the SLO on the p99 latency of reads or writes in a keyspace.
+    Slo(String, Operation, Duration),
+    /// Returns the latency metrics of the queries coordinated by the node, the droppable data of
+    /// the tables it stores, the hottest partitions of the tables it wrote to and the read repairs
+    /// of the tables it read.
+    Metrics,
+    /// Returns the ring split into about the given amount of ranges of tokens.
+    Splits(usize),
+    /// Returns the CQL script that creates the given keyspace, or every keyspace, and its tables.
+    ExportSchema(Option<String>),
+    /// Writes the rows of a table stored by the node to a file of the node, in the given format,
+    /// only for the partitions in the given range of tokens if there is one.
+    ExportTable(TableExport),
+    /// Runs the schema statements of the CQL script at the given path of the node, in order, leaving
+    /// the keyspaces and tables that already exist as they are.
+    ImportSchema(PathBuf),
+    /// Reads about the given percentage of the partitions of a table owned by the node, picked at
+    /// random, and returns what it read, for approximate statistics of the table.
+    Sample(String, String, f64),
+    /// Stops taking client queries, waits up to the given time for the open ones, flushes the
+    /// storage and announces the node is leaving, so it can be restarted.
+    Drain(Duration),
+    /// Drains the node, streams its rows to the nodes that take its ranges and waits up to the
+    /// given time for them to be acknowledged, and then takes the node out of the ring and stops it.
+    Decommission(Duration),
+    /// Removes the given dead node from the cluster for good: it is gossiped as removed, so every
+    /// node takes it out of the ring and streams its ranges to the nodes that take them.
+    RemoveNode(Ipv4Addr),
+    /// Runs a flush or a compaction of the given keyspace, or only of one of its tables, in every
+    /// node of the ring, and returns what each node did.
+    Maintenance(MaintenanceOperation, String, Option<String>),
+    /// Takes a snapshot of the given keyspace with the given tag in every node of the ring, and
+    /// returns how many files each node put in it.
+    Snapshot(String, String),
+    /// Returns the nodes of the cluster as this node knows them by gossip: whether they are up,
+    /// their state, their location and their tokens.
+    Status,
+    /// Returns the ranges of tokens of the ring and the nodes that store each of them in the
+    /// given keyspace.
+    DescribeRing(String),
+    /// Reads every row of the given keyspace, or only of one of its tables, from all of its
+    /// replicas, so the stale versions some of them keep are repaired.
+    Repair(String, Option<String>),
+}
+
+// Parses the `keyspace[.table]` target of the commands that run on a keyspace or one of its
+// tables
+fn keyspace_target(target: Option<&str>) -> Result<(String, Option<String>), NodeError> {
+    let target = target.ok_or(NodeError::OtherError)?;
+    let (keyspace, table) = match target.split_once('.') {
+        Some((keyspace, table)) => (keyspace, Some(table.to_string())),
+        None => (target, None),
+    };
+    if keyspace.is_empty() || table.as_ref().is_some_and(|table| table.is_empty()) {
+        return Err(NodeError::OtherError);
+    }
+    Ok((keyspace.to_string(), table))
+}
+
+impl FromStr for AdminCommand {
+    type Err = NodeError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+        let command = tokens.next().ok_or(NodeError::OtherError)?;
+
+        let admin_command = match command.to_uppercase().as_str() {
+            "KILL" => AdminCommand::Kill,
+            "PARTITION" => {
+                let peer = tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?;
+                AdminCommand::Partition(peer)
+            }
+            "HEAL" => AdminCommand::Heal,
+            "LOGS" => match tokens.next() {
+                Some(amount) => {
+                    AdminCommand::RecentLogs(amount.parse().map_err(|_| NodeError::OtherError)?)
+                }
+                None => AdminCommand::RecentLogs(DEFAULT_RECENT_LOGS),
+            },
+            "EVENTS" => match tokens.next() {
+                Some(since) => {
+                    AdminCommand::Events(since.parse().map_err(|_| NodeError::OtherError)?)
+                }
+                None => AdminCommand::Events(0),
+            },
+            "MOVE" => {
+                let token: u32 = tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?;
+                AdminCommand::Move(token as u64)
+            }
+            "SLO" => {
+                let keyspace = tokens.next().ok_or(NodeError::OtherError)?;
+                let operation = tokens.next().ok_or(NodeError::OtherError)?.parse()?;
+                let millis = tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?;
+                AdminCommand::Slo(
+                    keyspace.to_string(),
+                    operation,
+                    Duration::from_millis(millis),
+                )
+            }
+            "METRICS" => AdminCommand::Metrics,
+            "SPLITS" => AdminCommand::Splits(
+                tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?,
+            ),
+            "EXPORT" => match tokens.next().map(|token| token.to_uppercase()).as_deref() {
+                Some("SCHEMA") => {
+                    AdminCommand::ExportSchema(tokens.next().map(|keyspace| keyspace.to_string()))
+                }
+                Some("TABLE") => {
+                    let (keyspace, table) = tokens
+                        .next()
+                        .and_then(|name| name.split_once('.'))
+                        .ok_or(NodeError::OtherError)?;
+                    let format = tokens
+                        .next()
+                        .ok_or(NodeError::OtherError)?
+                        .parse()
+                        .map_err(|_| NodeError::OtherError)?;
+                    let path = PathBuf::from(tokens.next().ok_or(NodeError::OtherError)?);
+                    let tokens = match (tokens.next(), tokens.next()) {
+                        (Some(start), Some(end)) => Some(
+                            start.parse().map_err(|_| NodeError::OtherError)?
+                                ..end.parse().map_err(|_| NodeError::OtherError)?,
+                        ),
+                        (None, None) => None,
+                        _ => return Err(NodeError::OtherError),
+                    };
+                    AdminCommand::ExportTable(TableExport {
+                        keyspace: keyspace.to_string(),
+                        table: table.to_string(),
+                        format,
+                        path,
+                        tokens,
+                    })
+                }
+                _ => return Err(NodeError::OtherError),
+            },
+            "IMPORT" => {
+                if !tokens
+                    .next()
+                    .is_some_and(|token| token.eq_ignore_ascii_case("SCHEMA"))
+                {
+                    return Err(NodeError::OtherError);
+                }
+                AdminCommand::ImportSchema(PathBuf::from(
+                    tokens.next().ok_or(NodeError::OtherError)?,
+                ))
+            }
+            "SAMPLE" => {
+                let (keyspace, table) = tokens
+                    .next()
+                    .and_then(|name| name.split_once('.'))
+                    .ok_or(NodeError::OtherError)?;
+                let percent: f64 = tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .trim_end_matches('%')
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?;
+                if !(percent > 0.0 && percent <= 100.0) {
+                    return Err(NodeError::OtherError);
+                }
+                AdminCommand::Sample(keyspace.to_string(), table.to_string(), percent)
+            }
+            "DRAIN" => match tokens.next() {
+                Some(secs) => AdminCommand::Drain(Duration::from_secs(
+                    secs.parse().map_err(|_| NodeError::OtherError)?,
+                )),
+                None => AdminCommand::Drain(DEFAULT_DRAIN_TIMEOUT),
+            },
+            "DECOMMISSION" => match tokens.next() {
+                Some(secs) => AdminCommand::Decommission(Duration::from_secs(
+                    secs.parse().map_err(|_| NodeError::OtherError)?,
+                )),
+                None => AdminCommand::Decommission(DEFAULT_DECOMMISSION_TIMEOUT),
+            },
+            "REMOVENODE" => {
+                let removed = tokens
+                    .next()
+                    .ok_or(NodeError::OtherError)?
+                    .parse()
+                    .map_err(|_| NodeError::OtherError)?;
+                AdminCommand::RemoveNode(removed)
+            }
+            "FLUSH" | "COMPACT" => {
+                let operation = if command.eq_ignore_ascii_case("FLUSH") {
+                    MaintenanceOperation::Flush
+                } else {
+                    MaintenanceOperation::Compact
+                };
+                let (keyspace, table) = keyspace_target(tokens.next())?;
+                AdminCommand::Maintenance(operation, keyspace, table)
+            }
+            "SNAPSHOT" => {
+                let keyspace = tokens.next().ok_or(NodeError::OtherError)?;
+                let tag = tokens.next().ok_or(NodeError::OtherError)?;
+                AdminCommand::Snapshot(keyspace.to_string(), tag.to_string())
+            }
+            "STATUS" => AdminCommand::Status,
+            "DESCRIBERING" => {
+                AdminCommand::DescribeRing(tokens.next().ok_or(NodeError::OtherError)?.to_string())
+            }
+            "REPAIR" => {
+                let (keyspace, table) = keyspace_target(tokens.next())?;
+                AdminCommand::Repair(keyspace, table)
+            }
+            _ => return Err(NodeError::OtherError),
+        };
+
+        if tokens.next().is_some() {
+            return Err(NodeError::OtherError);
+        }
+
+        Ok(admin_command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kill() {
+        assert_eq!(AdminCommand::from_str("KILL").unwrap(), AdminCommand::Kill);
+        assert_eq!(
+            AdminCommand::from_str("kill\n").unwrap(),
+            AdminCommand::Kill
+        );
+    }
+
+    #[test]
+    fn test_parse_partition() {
+        let command = AdminCommand::from_str("PARTITION 127.0.0.2").unwrap();
+        assert_eq!(
+            command,
+            AdminCommand::Partition(Ipv4Addr::new(127, 0, 0, 2))
+        );
+    }
+
+    #[test]
+    fn test_parse_heal() {
+        assert_eq!(AdminCommand::from_str("HEAL").unwrap(), AdminCommand::Heal);
+    }
+
+    #[test]
+    fn test_parse_recent_logs() {
+        assert_eq!(
+            AdminCommand::from_str("LOGS 10").unwrap(),
+            AdminCommand::RecentLogs(10)
+        );
+        assert_eq!(
+            AdminCommand::from_str("logs").unwrap(),
+            AdminCommand::RecentLogs(DEFAULT_RECENT_LOGS)
+        );
+        assert!(AdminCommand::from_str("LOGS many").is_err());
+    }
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(
+            AdminCommand::from_str("EVENTS 7").unwrap(),
+            AdminCommand::Events(7)
+        );
+        assert_eq!(
+            AdminCommand::from_str("events").unwrap(),
+            AdminCommand::Events(0)
+        );
+        assert!(AdminCommand::from_str("EVENTS -1").is_err());
+    }
+
+    #[test]
+    fn test_parse_move() {
+        assert_eq!(
+            AdminCommand::from_str("MOVE 1073741824").unwrap(),
+            AdminCommand::Move(1073741824)
+        );
+        assert!(AdminCommand::from_str("MOVE").is_err());
+        assert!(AdminCommand::from_str("MOVE 4294967296").is_err());
+    }
+
+    #[test]
+    fn test_parse_slo_and_metrics() {
+        assert_eq!(
+            AdminCommand::from_str("SLO sky write 50").unwrap(),
+            AdminCommand::Slo(
+                "sky".to_string(),
+                Operation::Write,
+                Duration::from_millis(50)
+            )
+        );
+        assert!(AdminCommand::from_str("SLO sky scan 50").is_err());
+        assert!(AdminCommand::from_str("SLO sky read").is_err());
+        assert_eq!(
+            AdminCommand::from_str("metrics").unwrap(),
+            AdminCommand::Metrics
+        );
+    }
+
+    #[test]
+    fn test_parse_splits() {
+        assert_eq!(
+            AdminCommand::from_str("SPLITS 16").unwrap(),
+            AdminCommand::Splits(16)
+        );
+        assert!(AdminCommand::from_str("SPLITS").is_err());
+        assert!(AdminCommand::from_str("SPLITS -2").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_and_import_schema() {
+        assert_eq!(
+            AdminCommand::from_str("EXPORT SCHEMA").unwrap(),
+            AdminCommand::ExportSchema(None)
+        );
+        assert_eq!(
+            AdminCommand::from_str("export schema sky").unwrap(),
+            AdminCommand::ExportSchema(Some("sky".to_string()))
+        );
+        assert_eq!(
+            AdminCommand::from_str("IMPORT SCHEMA /tmp/flights.cql").unwrap(),
+            AdminCommand::ImportSchema(PathBuf::from("/tmp/flights.cql"))
+        );
+        assert!(AdminCommand::from_str("EXPORT sky").is_err());
+        assert!(AdminCommand::from_str("IMPORT SCHEMA").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_table() {
+        assert_eq!(
+            AdminCommand::from_str("EXPORT TABLE sky.flights parquet /tmp/flights.parquet")
+                .unwrap(),
+            AdminCommand::ExportTable(TableExport {
+                keyspace: "sky".to_string(),
+                table: "flights".to_string(),
+                format: ExportFormat::Parquet,
+                path: PathBuf::from("/tmp/flights.parquet"),
+                tokens: None,
+            })
+        );
+        assert_eq!(
+            AdminCommand::from_str("export table sky.flights CSV /tmp/flights.csv 0 1024").unwrap(),
+            AdminCommand::ExportTable(TableExport {
+                keyspace: "sky".to_string(),
+                table: "flights".to_string(),
+                format: ExportFormat::Csv,
+                path: PathBuf::from("/tmp/flights.csv"),
+                tokens: Some(0..1024),
+            })
+        );
+        assert!(AdminCommand::from_str("EXPORT TABLE sky.flights JSON /tmp/flights").is_err());
+        assert!(AdminCommand::from_str("EXPORT TABLE flights CSV /tmp/flights.csv").is_err());
+        assert!(AdminCommand::from_str("EXPORT TABLE sky.flights CSV /tmp/f.csv 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_commands() {
+        assert!(AdminCommand::from_str("").is_err());
+        assert!(AdminCommand::from_str("PARTITION").is_err());
+        assert!(AdminCommand::from_str("PARTITION not_an_ip").is_err());
+        assert!(AdminCommand::from_str("KILL now").is_err());
+        assert!(AdminCommand::from_str("REBOOT").is_err());
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        assert_eq!(
+            AdminCommand::from_str("SAMPLE sky.flights 1%").unwrap(),
+            AdminCommand::Sample("sky".to_string(), "flights".to_string(), 1.0)
+        );
+        assert_eq!(
+            AdminCommand::from_str("sample sky.flights 0.5").unwrap(),
+            AdminCommand::Sample("sky".to_string(), "flights".to_string(), 0.5)
+        );
+        assert!(AdminCommand::from_str("SAMPLE flights 1").is_err());
+        assert!(AdminCommand::from_str("SAMPLE sky.flights 0").is_err());
+        assert!(AdminCommand::from_str("SAMPLE sky.flights 101").is_err());
+    }
+
+    #[test]
+    fn test_parse_drain() {
+        assert_eq!(
+            AdminCommand::from_str("DRAIN 10").unwrap(),
+            AdminCommand::Drain(Duration::from_secs(10))
+        );
+        assert_eq!(
+            AdminCommand::from_str("drain").unwrap(),
+            AdminCommand::Drain(DEFAULT_DRAIN_TIMEOUT)
+        );
+        assert!(AdminCommand::from_str("DRAIN soon").is_err());
+    }
+
+    #[test]
+    fn test_parse_decommission() {
+        assert_eq!(
+            AdminCommand::from_str("DECOMMISSION 60").unwrap(),
+            AdminCommand::Decommission(Duration::from_secs(60))
+        );
+        assert_eq!(
+            AdminCommand::from_str("decommission").unwrap(),
+            AdminCommand::Decommission(DEFAULT_DECOMMISSION_TIMEOUT)
+        );
+        assert!(AdminCommand::from_str("DECOMMISSION 60 now").is_err());
+    }
+
+    #[test]
+    fn test_parse_removenode() {
+        assert_eq!(
+            AdminCommand::from_str("REMOVENODE 127.0.0.4").unwrap(),
+            AdminCommand::RemoveNode(Ipv4Addr::new(127, 0, 0, 4))
+        );
+        assert_eq!(
+            AdminCommand::from_str("removenode 127.0.0.4").unwrap(),
+            AdminCommand::RemoveNode(Ipv4Addr::new(127, 0, 0, 4))
+        );
+        assert!(AdminCommand::from_str("REMOVENODE").is_err());
+        assert!(AdminCommand::from_str("REMOVENODE node4").is_err());
+    }
+
+    #[test]
+    fn test_parse_flush_and_compact() {
+        assert_eq!(
+            AdminCommand::from_str("FLUSH sky").unwrap(),
+            AdminCommand::Maintenance(MaintenanceOperation::Flush, "sky".to_string(), None)
+        );
+        assert_eq!(
+            AdminCommand::from_str("compact sky.flights").unwrap(),
+            AdminCommand::Maintenance(
+                MaintenanceOperation::Compact,
+                "sky".to_string(),
+                Some("flights".to_string())
+            )
+        );
+        assert!(AdminCommand::from_str("FLUSH").is_err());
+        assert!(AdminCommand::from_str("COMPACT sky.").is_err());
+        assert!(AdminCommand::from_str("FLUSH sky flights").is_err());
+    }
+
+    #[test]
+    fn test_parse_snapshot() {
+        assert_eq!(
+            AdminCommand::from_str("SNAPSHOT sky nightly").unwrap(),
+            AdminCommand::Snapshot("sky".to_string(), "nightly".to_string())
+        );
+        assert!(AdminCommand::from_str("snapshot sky").is_err());
+        assert!(AdminCommand::from_str("SNAPSHOT sky nightly weekly").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_and_describering() {
+        assert_eq!(
+            AdminCommand::from_str("status").unwrap(),
+            AdminCommand::Status
+        );
+        assert_eq!(
+            AdminCommand::from_str("DESCRIBERING sky").unwrap(),
+            AdminCommand::DescribeRing("sky".to_string())
+        );
+        assert!(AdminCommand::from_str("STATUS sky").is_err());
+        assert!(AdminCommand::from_str("DESCRIBERING").is_err());
+    }
+
+    #[test]
+    fn test_parse_repair() {
+        assert_eq!(
+            AdminCommand::from_str("REPAIR sky").unwrap(),
+            AdminCommand::Repair("sky".to_string(), None)
+        );
+        assert_eq!(
+            AdminCommand::from_str("repair sky.flights").unwrap(),
+            AdminCommand::Repair("sky".to_string(), Some("flights".to_string()))
+        );
+        assert!(AdminCommand::from_str("REPAIR").is_err());
+        assert!(AdminCommand::from_str("REPAIR .flights").is_err());
+    }
+}
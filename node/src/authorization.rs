@@ -0,0 +1,421 @@
+//! Authorization of the statements run by the clients of a node.
+//!
+//! Before a statement is executed, the node asks its `Authorizer` whether the logged user may run
+//! it, passing the user, the fingerprint of the statement, the keyspace and table it acts on and
+//! the permission it needs. Nodes allow every statement by default (`AllowAll`); a
+//! `TableAuthorizer` checks them against a table of rules, and any other policy (for example, one
+//! asking an external service) can be plugged in with `Node::with_authorizer`.
+
+use std::{fmt, str::FromStr};
+
+use query_creator::{GetTableName, GetUsedKeyspace, Query};
+
+use crate::errors::NodeError;
+
+/// The permission a statement needs, as in Cassandra.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// `SELECT` queries.
+    Select,
+    /// `INSERT`, `UPDATE`, `DELETE` and `BATCH` queries.
+    Modify,
+    /// `CREATE KEYSPACE` and `CREATE TABLE`.
+    Create,
+    /// `ALTER KEYSPACE` and `ALTER TABLE`.
+    Alter,
+    /// `DROP KEYSPACE` and `DROP TABLE`.
+    Drop,
+    /// `USE`.
+    Describe,
+    /// `CREATE USER`, `ALTER USER`, `DROP USER`, `GRANT` and `REVOKE`.
+    Authorize,
+}
+
+impl Permission {
+    /// Returns the permission needed to run `query`.
+    pub fn of(query: &Query) -> Permission {
+        match query {
+            Query::Select(_) => Permission::Select,
+            Query::Insert(_) | Query::Update(_) | Query::Delete(_) | Query::Batch(_) => {
+                Permission::Modify
+            }
+            Query::CreateTable(_) | Query::CreateKeyspace(_) => Permission::Create,
+            Query::AlterTable(_) | Query::AlterKeyspace(_) => Permission::Alter,
+            Query::DropTable(_) | Query::DropKeyspace(_) => Permission::Drop,
+            Query::Use(_) => Permission::Describe,
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => Permission::Authorize,
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Permission::Select => "SELECT",
+            Permission::Modify => "MODIFY",
+            Permission::Create => "CREATE",
+            Permission::Alter => "ALTER",
+            Permission::Drop => "DROP",
+            Permission::Describe => "DESCRIBE",
+            Permission::Authorize => "AUTHORIZE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Permission {
+    type Err = NodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "SELECT" => Ok(Permission::Select),
+            "MODIFY" => Ok(Permission::Modify),
+            "CREATE" => Ok(Permission::Create),
+            "ALTER" => Ok(Permission::Alter),
+            "DROP" => Ok(Permission::Drop),
+            "DESCRIBE" => Ok(Permission::Describe),
+            "AUTHORIZE" => Ok(Permission::Authorize),
+            _ => Err(NodeError::OtherError),
+        }
+    }
+}
+
+/// What an `Authorizer` is asked about a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationRequest {
+    /// The user the client logged in as.
+    pub user: String,
+    /// The statement with its literals replaced by `?` (see `fingerprint`).
+    pub fingerprint: String,
+    /// The keyspace the statement acts on, if any. It is `None` for the statements on users, which
+    /// act on every keyspace.
+    pub keyspace: Option<String>,
+    /// The table the statement acts on, if any.
+    pub table: Option<String>,
+    pub permission: Permission,
+}
+
+impl AuthorizationRequest {
+    /// Returns the requests to authorize before running `query` (one per statement of a batch),
+    /// using `keyspace` for the statements that do not name one.
+    pub fn of(user: &str, query: &Query, keyspace: Option<String>) -> Vec<AuthorizationRequest> {
+        let statements = match query {
+            Query::Batch(batch) => batch.statements.iter().collect(),
+            query => vec![query],
+        };
+
+        statements
+            .into_iter()
+            .map(|statement| {
+                let keyspace = match statement {
+                    Query::CreateKeyspace(create) => Some(create.get_name()),
+                    Query::DropKeyspace(drop) => Some(drop.get_name()),
+                    Query::AlterKeyspace(alter) => Some(alter.get_name()),
+                    Query::Use(use_cql) => Some(use_cql.get_name()),
+                    // Users are not scoped to a keyspace, so changing them needs the permission on
+                    // every keyspace, whatever the keyspace of the client or of the grant
+                    Query::CreateUser(_)
+                    | Query::AlterUser(_)
+                    | Query::DropUser(_)
+                    | Query::Grant(_)
+                    | Query::Revoke(_) => None,
+                    _ => statement.get_used_keyspace().or(keyspace.clone()),
+                };
+                AuthorizationRequest {
+                    user: user.to_string(),
+                    fingerprint: fingerprint(&statement_cql(statement)),
+                    keyspace,
+                    table: statement.get_table_name(),
+                    permission: Permission::of(statement),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Decides whether a user may run a statement. Called by the node before executing every
+/// statement of its clients, so it must be cheap or cache its answers.
+pub trait Authorizer: Send + Sync {
+    /// Returns `Ok(())` if the statement may run, or the reason it may not, which is sent to the
+    /// client in an `Unauthorized` error.
+    fn authorize(&self, request: &AuthorizationRequest) -> Result<(), String>;
+}
+
+/// Allows every statement. Used by default.
+#[derive(Debug, Clone, Default)]
+pub struct AllowAll;
+
+impl Authorizer for AllowAll {
+    fn authorize(&self, _request: &AuthorizationRequest) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A rule of a `TableAuthorizer`. `None` matches any user, keyspace, table or permission.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorizationRule {
+    pub allow: bool,
+    pub user: Option<String>,
+    pub keyspace: Option<String>,
+    pub table: Option<String>,
+    pub permissions: Option<Vec<Permission>>,
+}
+
+impl AuthorizationRule {
+    fn matches(&self, request: &AuthorizationRequest) -> bool {
+        fn matches(rule: &Option<String>, value: &Option<String>) -> bool {
+            rule.is_none() || rule == value
+        }
+
+        self.user.as_ref().is_none_or(|user| *user == request.user)
+            && matches(&self.keyspace, &request.keyspace)
+            && matches(&self.table, &request.table)
+            && self
+                .permissions
+                .as_ref()
+                .is_none_or(|permissions| permissions.contains(&request.permission))
+    }
+}
+
+/// Checks the statements against a table of rules: the first rule matching a statement decides
+/// whether it may run, and statements matching no rule are denied.
+///
+/// The rules are written one per line, as `<allow|deny> <user> <keyspace>.<table> <permissions>`,
+/// where `*` matches anything and the permissions are separated by commas (or `ALL`). Empty lines
+/// and lines starting with `#` are skipped. For example:
+///
+/// ```text
+/// allow admin *.* ALL
+/// deny * sky.* DROP
+/// allow * sky.* SELECT,MODIFY
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableAuthorizer {
+    rules: Vec<AuthorizationRule>,
+}
+
+impl TableAuthorizer {
+    pub fn new(rules: Vec<AuthorizationRule>) -> Self {
+        TableAuthorizer { rules }
+    }
+
+    pub fn rules(&self) -> &[AuthorizationRule] {
+        &self.rules
+    }
+}
+
+impl Authorizer for TableAuthorizer {
+    fn authorize(&self, request: &AuthorizationRequest) -> Result<(), String> {
+        match self.rules.iter().find(|rule| rule.matches(request)) {
+            Some(rule) if rule.allow => Ok(()),
+            _ => Err(format!(
+                "User {} has no {} permission on {}.{}",
+                request.user,
+                request.permission,
+                request.keyspace.as_deref().unwrap_or("*"),
+                request.table.as_deref().unwrap_or("*")
+            )),
+        }
+    }
+}
+
+impl FromStr for TableAuthorizer {
+    type Err = NodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn any(value: &str) -> Option<String> {
+            (value != "*").then(|| value.to_string())
+        }
+
+        let rules = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let invalid = || NodeError::ScriptError(format!("invalid rule: {}", line));
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                let [action, user, resource, permissions] = parts[..] else {
+                    return Err(invalid());
+                };
+
+                let allow = match action.to_lowercase().as_str() {
+                    "allow" => true,
+                    "deny" => false,
+                    _ => return Err(invalid()),
+                };
+                let (keyspace, table) = resource.split_once('.').ok_or_else(invalid)?;
+                let permissions = match permissions.to_uppercase().as_str() {
+                    "ALL" | "*" => None,
+                    permissions => Some(
+                        permissions
+                            .split(',')
+                            .map(|permission| permission.parse().map_err(|_| invalid()))
+                            .collect::<Result<Vec<Permission>, NodeError>>()?,
+                    ),
+                };
+
+                Ok(AuthorizationRule {
+                    allow,
+                    user: any(user),
+                    keyspace: any(keyspace),
+                    table: any(table),
+                    permissions,
+                })
+            })
+            .collect::<Result<Vec<AuthorizationRule>, NodeError>>()?;
+
+        Ok(TableAuthorizer { rules })
+    }
+}
+
+/// Returns `query` with its string and number literals replaced by `?` and its whitespace
+/// collapsed, so every execution of the same statement with different values has the same
+/// fingerprint.
+pub fn fingerprint(query: &str) -> String {
+    let mut fingerprint = String::with_capacity(query.len());
+    let mut chars = query.trim().trim_end_matches(';').chars().peekable();
+    // Whether the last character written ends a word, so a digit after it is part of a name
+    let mut in_word = false;
+
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // '' inside a string is an escaped quote
+            while let Some(c) = chars.next() {
+                if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                    break;
+                }
+            }
+            fingerprint.push('?');
+            in_word = false;
+        } else if (c.is_ascii_digit() || c == '-') && !in_word {
+            if c == '-' && !chars.peek().is_some_and(|next| next.is_ascii_digit()) {
+                fingerprint.push(c);
+                continue;
+            }
+            while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+            fingerprint.push('?');
+        } else if c.is_whitespace() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            fingerprint.push(' ');
+            in_word = false;
+        } else {
+            fingerprint.push(c);
+            in_word = c.is_alphanumeric() || c == '_';
+        }
+    }
+
+    fingerprint
+}
+
+// The CQL of a statement, to take its fingerprint.
+fn statement_cql(query: &Query) -> String {
+    match query {
+        Query::Select(select) => select.serialize(),
+        Query::Insert(insert) => insert.serialize(),
+        Query::Update(update) => update.serialize(),
+        Query::Delete(delete) => delete.serialize(),
+        Query::CreateTable(create) => create.serialize(),
+        Query::DropTable(drop) => drop.serialize(),
+        Query::AlterTable(alter) => alter.serialize(),
+        Query::CreateKeyspace(create) => create.serialize(),
+        Query::DropKeyspace(drop) => drop.serialize(),
+        Query::AlterKeyspace(alter) => alter.serialize(),
+        Query::Use(use_cql) => use_cql.serialize(),
+        Query::Batch(batch) => batch.serialize(),
+        Query::CreateUser(create) => create.serialize(),
+        Query::AlterUser(alter) => alter.serialize(),
+        Query::DropUser(drop) => drop.serialize(),
+        Query::Grant(grant) => grant.serialize(),
+        Query::Revoke(revoke) => revoke.serialize(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::QueryCreator;
+
+    fn query(cql: &str) -> Query {
+        QueryCreator::new().handle_query(cql.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(
+            fingerprint("SELECT *  FROM sky.flights WHERE number = 'AR 1''2' AND lat > -3.5;"),
+            "SELECT * FROM sky.flights WHERE number = ? AND lat > ?"
+        );
+        assert_eq!(
+            fingerprint("INSERT INTO t2 (a1, b) VALUES (10, 'x')"),
+            "INSERT INTO t2 (a1, b) VALUES (?, ?)"
+        );
+    }
+
+    #[test]
+    fn test_requests_of_a_batch() {
+        let batch = query(
+            "BEGIN BATCH INSERT INTO flights (number) VALUES ('AR1'); \
+             DELETE FROM flight_info WHERE number = 'AR1'; APPLY BATCH",
+        );
+
+        let requests = AuthorizationRequest::of("admin", &batch, Some("sky".to_string()));
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].keyspace, Some("sky".to_string()));
+        assert_eq!(requests[0].table, Some("flights".to_string()));
+        assert_eq!(requests[1].table, Some("flight_info".to_string()));
+        assert!(requests.iter().all(|r| r.permission == Permission::Modify));
+    }
+
+    #[test]
+    fn test_table_authorizer() {
+        let authorizer: TableAuthorizer = "
+            # The admin can do anything
+            allow admin *.* ALL
+            deny * sky.* DROP
+            allow * sky.flights SELECT,MODIFY
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(authorizer.rules().len(), 3);
+
+        let request = |user: &str, cql: &str| {
+            AuthorizationRequest::of(user, &query(cql), Some("sky".to_string())).remove(0)
+        };
+
+        assert!(authorizer
+            .authorize(&request("admin", "DROP TABLE flights"))
+            .is_ok());
+        assert!(authorizer
+            .authorize(&request("pilot", "DROP TABLE flights"))
+            .is_err());
+        assert!(authorizer
+            .authorize(&request(
+                "pilot",
+                "UPDATE flights SET lat = 1 WHERE number = 'AR1'"
+            ))
+            .is_ok());
+        // No rule matches
+        assert!(authorizer
+            .authorize(&request(
+                "pilot",
+                "SELECT * FROM airports WHERE iata = 'EZE'"
+            ))
+            .is_err());
+        assert!(AllowAll
+            .authorize(&request("pilot", "DROP TABLE flights"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!("allow admin".parse::<TableAuthorizer>().is_err());
+        assert!("permit * *.* ALL".parse::<TableAuthorizer>().is_err());
+        assert!("allow * sky ALL".parse::<TableAuthorizer>().is_err());
+        assert!("allow * *.* WRITE".parse::<TableAuthorizer>().is_err());
+    }
+}
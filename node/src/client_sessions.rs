@@ -0,0 +1,142 @@
+//! Limits on how long the connections of the clients stay open, and the cleanup of their sessions.
+//!
+//! A client that never closes its connection (a GUI that leaked it, or one that crashed behind a
+//! NAT that never resets it) would keep its thread and its session (keyspace and user) in the node
+//! forever. Connections idle for longer than `idle_timeout`, or open for longer than
+//! `max_lifetime`, are closed by the node between two requests, never in the middle of one.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Node;
+
+/// How long the connections of the clients may stay open. `None` leaves them open for as long as
+/// the client wants, which is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ClientConnectionLimits {
+    /// Time a connection may go without sending a request.
+    pub idle_timeout: Option<Duration>,
+    /// Time a connection may stay open since it was accepted.
+    pub max_lifetime: Option<Duration>,
+}
+
+/// Why the node closed the connection of a client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CloseReason {
+    Idle(Duration),
+    MaxLifetime(Duration),
+    /// The node is shutting down, which closes every connection between two requests too.
+    ShuttingDown,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseReason::Idle(timeout) => {
+                write!(f, "idle for more than {}s", timeout.as_secs_f64())
+            }
+            CloseReason::MaxLifetime(lifetime) => {
+                write!(f, "open for more than {}s", lifetime.as_secs_f64())
+            }
+            CloseReason::ShuttingDown => write!(f, "the node is shutting down"),
+        }
+    }
+}
+
+impl ClientConnectionLimits {
+    /// Returns how long a connection accepted at `opened`, whose last request was received at
+    /// `last_request`, may wait for its next request (`None` if it may wait forever), or why it
+    /// must be closed at `now`.
+    pub(crate) fn time_left(
+        &self,
+        opened: Instant,
+        last_request: Instant,
+        now: Instant,
+    ) -> Result<Option<Duration>, CloseReason> {
+        let idle = self.idle_timeout.map(|timeout| {
+            (last_request + timeout)
+                .checked_duration_since(now)
+                .filter(|left| !left.is_zero())
+                .ok_or(CloseReason::Idle(timeout))
+        });
+        let lifetime = self.max_lifetime.map(|lifetime| {
+            (opened + lifetime)
+                .checked_duration_since(now)
+                .filter(|left| !left.is_zero())
+                .ok_or(CloseReason::MaxLifetime(lifetime))
+        });
+
+        let idle = idle.transpose()?;
+        let lifetime = lifetime.transpose()?;
+        Ok(match (idle, lifetime) {
+            (Some(idle), Some(lifetime)) => Some(idle.min(lifetime)),
+            (left, None) | (None, left) => left,
+        })
+    }
+}
+
+/// The session of a connected client. When it is dropped, because the connection was closed by
+/// either side or failed, the node forgets the keyspace and the user of the client.
+pub(crate) struct ClientSession {
+    node: Arc<Mutex<Node>>,
+    client_id: i32,
+}
+
+impl ClientSession {
+    pub(crate) fn new(node: Arc<Mutex<Node>>, client_id: i32) -> Self {
+        Self { node, client_id }
+    }
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        if let Ok(mut node) = self.node.lock() {
+            node.clients_keyspace.remove(&self.client_id);
+            node.clients_user.remove(&self.client_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_left_until_the_connection_is_closed() {
+        let opened = Instant::now();
+        let limits = ClientConnectionLimits {
+            idle_timeout: Some(Duration::from_secs(10)),
+            max_lifetime: Some(Duration::from_secs(60)),
+        };
+
+        assert_eq!(
+            limits.time_left(opened, opened, opened + Duration::from_secs(4)),
+            Ok(Some(Duration::from_secs(6)))
+        );
+        // A request resets the idle timeout, but not the lifetime
+        let last_request = opened + Duration::from_secs(55);
+        assert_eq!(
+            limits.time_left(opened, last_request, last_request),
+            Ok(Some(Duration::from_secs(5)))
+        );
+        assert_eq!(
+            limits.time_left(opened, opened, opened + Duration::from_secs(10)),
+            Err(CloseReason::Idle(Duration::from_secs(10)))
+        );
+        assert_eq!(
+            limits.time_left(opened, last_request, opened + Duration::from_secs(61)),
+            Err(CloseReason::MaxLifetime(Duration::from_secs(60)))
+        );
+
+        // Without limits connections stay open
+        assert_eq!(
+            ClientConnectionLimits::default().time_left(
+                opened,
+                opened,
+                opened + Duration::from_secs(3600)
+            ),
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! Configuration of a node, read from a `node.yaml` file.
+//!
+//! The file holds one `key: value` setting per line, and lines starting with `#` are comments.
+//! Every setting is optional and takes its default value when it is missing:
+//!
+//! ```yaml
+//! # Ports the node listens on. Every node of a cluster must use the same internode port.
+//! client_port: 17989
+//! internode_port: 21837
+//! admin_port: 16708
+//! # Milliseconds between two gossip rounds, plus a random part of the jitter
+//! gossip_interval: 1000
+//! gossip_jitter: 0
+//! # Nodes each gossip round is sent to, and probability of also sending it to a seed
+//! gossip_fanout: 3
+//! gossip_seed_probability: 0
+//! # Milliseconds the node waits before its first gossip round
+//! gossip_startup_delay: 0
+//! # Name of the cluster, shown in system.local
+//! cluster_name: Rustic Airlines
+//! # Folder with the cert.crt and cert.key files of the TLS connections of the clients
+//! certs_path: ../certs
+//! # Folder where the node stores its data, unless the launcher is given one
+//! storage_path: /var/lib/rustic-airlines
+//! # Certificate and key of this node, and the CA that signs the ones of every node, to encrypt
+//! # the internode connections with mutual TLS. Either the three are set, or none of them.
+//! # They are not in the repository: make internode, in certs, generates them.
+//! internode_cert: ../certs/internode/node.crt
+//! internode_key: ../certs/internode/node.key
+//! internode_ca: ../certs/internode/ca.crt
+//! # Algorithm the node compresses the messages it sends to the other nodes with: lz4, snappy or
+//! # none
+//! internode_compression: lz4
+//! # How the log records are written (text or json) and the least important level written
+//! # (info, warn or error)
+//! log_format: json
+//! log_level: warn
+//! ```
+//!
+//! Running several clusters on one host only takes a file per cluster with other ports.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use gossip::config::GossipConfig;
+use logger::{Level, LogFormat};
+use native_protocol::compression::Compression;
+
+use crate::NodeError;
+
+/// Port the node takes the connections of its clients on by default.
+pub const DEFAULT_CLIENT_PORT: u16 = 0x4645; // Hexadecimal of "FE" (FERRUM) = 17989
+/// Port the nodes of the cluster talk to each other on by default.
+pub const DEFAULT_INTERNODE_PORT: u16 = 0x554D; // Hexadecimal of "UM" (FERRUM) = 21837
+/// Port the node takes admin commands on by default.
+pub const DEFAULT_ADMIN_PORT: u16 = 0x4144; // Hexadecimal of "AD" (ADMIN) = 16708
+/// Name of the cluster of the node by default, as in Cassandra.
+pub const DEFAULT_CLUSTER_NAME: &str = "Test Cluster";
+
+/// Where a node listens, how often it gossips and where it finds its files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeConfig {
+    /// Port of the native protocol, where the clients connect.
+    pub client_port: u16,
+    /// Port the node listens on for the other nodes, and connects to them on.
+    pub internode_port: u16,
+    /// Port of the admin commands.
+    pub admin_port: u16,
+    /// How often the node gossips and with how many nodes.
+    pub gossip: GossipConfig,
+    /// Name of the cluster the node belongs to, as clients see it in `system.local`.
+    pub cluster_name: String,
+    /// Folder with the `cert.crt` and `cert.key` files of the TLS connections of the clients.
+    pub certs_path: PathBuf,
+    /// Folder where the node stores its data, used when the launcher is not given one.
+    pub storage_path: Option<PathBuf>,
+    /// Files of the mutual TLS of the internode connections, which are plaintext without them.
+    pub internode_tls: Option<InternodeTlsFiles>,
+    /// Algorithm the messages sent to the other nodes are compressed with, or `None` to send them
+    /// uncompressed.
+    pub internode_compression: Option<Compression>,
+    /// How the node writes its log records: text lines, or a JSON object per line for log shippers.
+    pub log_format: LogFormat,
+    /// Least important level of the log records the node writes.
+    pub log_level: Level,
+    /// Tag of the snapshot whose keyspaces the node brings back when it starts, with the mutations
+    /// logged after it still in the commit log, or `None` to start from the commit log only.
+    pub restore_snapshot: Option<String>,
+}
+
+/// Files a node encrypts its internode connections with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternodeTlsFiles {
+    /// PEM certificate the node presents to the other nodes, signed by `ca`.
+    pub cert: PathBuf,
+    /// PEM private key of `cert`.
+    pub key: PathBuf,
+    /// PEM certificate of the CA that signs the certificates of every node of the cluster.
+    pub ca: PathBuf,
+}
+
+impl Default for NodeConfig {
+    /// The certificates are looked for in the `certs` folder of the workspace.
+    fn default() -> Self {
+        let project_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+        let certs_path = Path::new(&project_dir)
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("certs");
+
+        Self {
+            client_port: DEFAULT_CLIENT_PORT,
+            internode_port: DEFAULT_INTERNODE_PORT,
+            admin_port: DEFAULT_ADMIN_PORT,
+            gossip: GossipConfig::default(),
+            cluster_name: DEFAULT_CLUSTER_NAME.to_string(),
+            certs_path,
+            storage_path: None,
+            internode_tls: None,
+            internode_compression: None,
+            log_format: LogFormat::Text,
+            log_level: Level::Info,
+            restore_snapshot: None,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Reads the configuration of a node from a file.
+    ///
+    /// # Errors
+    /// - `NodeError::ConfigError` if the file can not be read or has an invalid setting.
+    pub fn load(path: &Path) -> Result<NodeConfig, NodeError> {
+        fs::read_to_string(path)
+            .map_err(|_| NodeError::ConfigError(format!("can not read {}", path.display())))?
+            .parse()
+    }
+}
+
+impl FromStr for NodeConfig {
+    type Err = NodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = NodeConfig::default();
+        let (mut internode_cert, mut internode_key, mut internode_ca) = (None, None, None);
+        let lines = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        for line in lines {
+            let invalid = || NodeError::ConfigError(format!("invalid setting: {}", line));
+            let (key, value) = line.split_once(':').ok_or_else(invalid)?;
+            let value = value.trim().trim_matches('"');
+            match key.trim() {
+                "client_port" => config.client_port = value.parse().map_err(|_| invalid())?,
+                "internode_port" => config.internode_port = value.parse().map_err(|_| invalid())?,
+                "admin_port" => config.admin_port = value.parse().map_err(|_| invalid())?,
+                "gossip_interval" => {
+                    let millis = value.parse().map_err(|_| invalid())?;
+                    config.gossip.round_interval = Duration::from_millis(millis);
+                }
+                "gossip_jitter" => {
+                    let millis = value.parse().map_err(|_| invalid())?;
+                    config.gossip.jitter = Duration::from_millis(millis);
+                }
+                "gossip_fanout" => {
+                    config.gossip.fanout = match value.parse() {
+                        Ok(fanout) if fanout > 0 => fanout,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "gossip_seed_probability" => {
+                    config.gossip.seed_probability = match value.parse() {
+                        Ok(probability) if (0.0..=1.0).contains(&probability) => probability,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "gossip_startup_delay" => {
+                    let millis = value.parse().map_err(|_| invalid())?;
+                    config.gossip.startup_delay = Duration::from_millis(millis);
+                }
+                // The name is a value of the rows of `system.local`, which are separated by commas
+                "cluster_name" if !value.is_empty() && !value.contains(',') => {
+                    config.cluster_name = value.to_string()
+                }
+                "cluster_name" => return Err(invalid()),
+                "certs_path" => config.certs_path = PathBuf::from(value),
+                "storage_path" => config.storage_path = Some(PathBuf::from(value)),
+                "internode_cert" => internode_cert = Some(PathBuf::from(value)),
+                "internode_key" => internode_key = Some(PathBuf::from(value)),
+                "internode_ca" => internode_ca = Some(PathBuf::from(value)),
+                "internode_compression" if value == "none" => config.internode_compression = None,
+                "internode_compression" => {
+                    config.internode_compression =
+                        Some(Compression::from_name(value).map_err(|_| invalid())?)
+                }
+                "log_format" => config.log_format = value.parse().map_err(|_| invalid())?,
+                "log_level" => config.log_level = value.parse().map_err(|_| invalid())?,
+                "restore_snapshot" if !value.is_empty() => {
+                    config.restore_snapshot = Some(value.to_string())
+                }
+                "restore_snapshot" => return Err(invalid()),
+                _ => {
+                    return Err(NodeError::ConfigError(format!(
+                        "unknown setting: {}",
+                        key.trim()
+                    )))
+                }
+            }
+        }
+
+        config.internode_tls = match (internode_cert, internode_key, internode_ca) {
+            (Some(cert), Some(key), Some(ca)) => Some(InternodeTlsFiles { cert, key, ca }),
+            (None, None, None) => None,
+            _ => {
+                return Err(NodeError::ConfigError(
+                    "internode TLS needs internode_cert, internode_key and internode_ca"
+                        .to_string(),
+                ))
+            }
+        };
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_str() {
+        let config: NodeConfig = "# Second cluster of the host\n\
+            client_port: 18989\n\
+            internode_port: 22837\n\
+            \n\
+            gossip_interval: 500\n\
+            cluster_name: Rustic Airlines\n\
+            storage_path: \"/tmp/cluster b\"\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(config.client_port, 18989);
+        assert_eq!(config.internode_port, 22837);
+        assert_eq!(config.admin_port, DEFAULT_ADMIN_PORT);
+        assert_eq!(config.gossip.round_interval, Duration::from_millis(500));
+        assert_eq!(config.gossip.fanout, GossipConfig::default().fanout);
+        assert_eq!(config.cluster_name, "Rustic Airlines");
+        assert_eq!(config.storage_path, Some(PathBuf::from("/tmp/cluster b")));
+        assert_eq!(config.certs_path, NodeConfig::default().certs_path);
+        assert_eq!(config.internode_tls, None);
+        assert_eq!(config.internode_compression, None);
+    }
+
+    #[test]
+    fn test_config_internode_compression() {
+        let config: NodeConfig = "internode_compression: LZ4".parse().unwrap();
+        assert_eq!(config.internode_compression, Some(Compression::Lz4));
+        let config: NodeConfig = "internode_compression: none".parse().unwrap();
+        assert_eq!(config.internode_compression, None);
+        let config: NodeConfig = "internode_compression: snappy".parse().unwrap();
+        assert_eq!(config.internode_compression, Some(Compression::Snappy));
+        assert!("internode_compression: zstd".parse::<NodeConfig>().is_err());
+    }
+
+    #[test]
+    fn test_config_internode_tls() {
+        let config: NodeConfig = "internode_cert: node.crt\n\
+            internode_key: node.key\n\
+            internode_ca: ca.crt\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.internode_tls,
+            Some(InternodeTlsFiles {
+                cert: PathBuf::from("node.crt"),
+                key: PathBuf::from("node.key"),
+                ca: PathBuf::from("ca.crt"),
+            })
+        );
+
+        assert!("internode_cert: node.crt\ninternode_key: node.key"
+            .parse::<NodeConfig>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_config_gossip() {
+        let config: NodeConfig = "gossip_fanout: 5
+            gossip_jitter: 200
+            gossip_seed_probability: 0.25
+            gossip_startup_delay: 3000
+"
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config.gossip,
+            GossipConfig {
+                fanout: 5,
+                jitter: Duration::from_millis(200),
+                seed_probability: 0.25,
+                startup_delay: Duration::from_secs(3),
+                ..Default::default()
+            }
+        );
+
+        assert!("gossip_fanout: 0".parse::<NodeConfig>().is_err());
+        assert!("gossip_seed_probability: 1.5"
+            .parse::<NodeConfig>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_config_logging() {
+        let config: NodeConfig = "log_format: json\nlog_level: WARN".parse().unwrap();
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_level, Level::Warn);
+        assert_eq!(NodeConfig::default().log_format, LogFormat::Text);
+        assert!("log_format: xml".parse::<NodeConfig>().is_err());
+        assert!("log_level: debug".parse::<NodeConfig>().is_err());
+    }
+
+    #[test]
+    fn test_config_restore_snapshot() {
+        let config: NodeConfig = "restore_snapshot: nightly".parse().unwrap();
+        assert_eq!(config.restore_snapshot, Some("nightly".to_string()));
+        assert_eq!(NodeConfig::default().restore_snapshot, None);
+        assert!("restore_snapshot:".parse::<NodeConfig>().is_err());
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_settings() {
+        assert!("client_port: 70000".parse::<NodeConfig>().is_err());
+        assert!("gossip_interval 500".parse::<NodeConfig>().is_err());
+        assert!("seed: 127.0.0.1".parse::<NodeConfig>().is_err());
+        assert!("cluster_name: a,b".parse::<NodeConfig>().is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! The connections a node opened to the other nodes, shared by everything that sends them
+//! messages: the queries and their answers, gossip and the background traffic.
+//!
+//! Each peer has a lock of its own, held while a connection to it is opened, so a single
+//! connection is opened to a peer at a time and a peer that is slow to connect never holds up
+//! the messages to the others. Peers are spread over shards, so looking one up only locks the
+//! shard it is in instead of every connection of the node.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::errors::NodeError;
+use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::internode_protocol::startup::ConnectionStartup;
+use crate::internode_tls::{self, InternodeStream};
+use crate::peer_connection::{PeerConnection, INTERNODE_WRITE_TIMEOUT};
+use crate::utils::internode_compression;
+
+/// Shards the peers of a node are spread over.
+const SHARDS: usize = 16;
+/// Time a connection to another node can take to open.
+const INTERNODE_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The connection to a peer, if one is open, behind the lock of the peer.
+type PeerSlot = Arc<Mutex<Option<PeerConnection>>>;
+type Shard = Mutex<HashMap<SocketAddrV4, PeerSlot>>;
+
+/// The connections a node opened to the other nodes, by their internode address. Clones share
+/// the same connections.
+///
+/// ### Fields
+/// - `shards`: The peers of the node, each in the shard its address hashes to.
+#[derive(Debug, Clone)]
+pub struct ConnectionManager {
+    shards: Arc<Vec<Shard>>,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionManager {
+    /// Creates a manager without connections, which opens them as messages are sent.
+    pub fn new() -> Self {
+        Self {
+            shards: Arc::new((0..SHARDS).map(|_| Mutex::default()).collect()),
+        }
+    }
+
+    // Returns the lock of the connection to `peer`, creating it the first time the peer gets
+    // a message. Only the shard of the peer is locked, and only while it is looked up.
+    fn slot(&self, peer: SocketAddrV4) -> Result<PeerSlot, NodeError> {
+        let shard = (u32::from(*peer.ip()) as usize ^ peer.port() as usize) % SHARDS;
+        let mut peers = self.shards[shard].lock()?;
+        Ok(Arc::clone(peers.entry(peer).or_default()))
+    }
+
+    /// Sends a message to the internode `port` of a peer, through the connection to it.
+    ///
+    /// # Behavior
+    /// - Reuses the connection to the peer if there is one and it did not break, queueing the
+    ///   message in it, so the caller never waits for a slow peer.
+    /// - Otherwise opens a new one within `INTERNODE_CONNECT_TIMEOUT`, wrapped in TLS if the
+    ///   sending node (`message.from`) registered its internode TLS settings. If the sending node
+    ///   compresses its messages, the connection starts with a `ConnectionStartup` naming the
+    ///   algorithm, so the peer decompresses them.
+    ///
+    /// # Errors
+    /// - `NodeError::IoError` if the connection can not be opened.
+    /// - `NodeError::Unavailable` if the peer is overloaded: the queue of its connection is full.
+    /// - `NodeError::InternodeError` if the connection broke while the message was queued.
+    ///
+    /// # Notes
+    /// - A message queued is not yet written: if the connection breaks before, the message is
+    ///   lost, as the ones the peer did not read when it went down are. The queries sent to it
+    ///   time out.
+    pub fn send(
+        &self,
+        peer: Ipv4Addr,
+        port: u16,
+        message: InternodeMessage,
+    ) -> Result<(), NodeError> {
+        let peer_addr = SocketAddrV4::new(peer, port);
+        let compression = internode_compression(&message.from)?;
+        let message_bytes = message.to_compressed_bytes(compression.as_ref());
+
+        let slot = self.slot(peer_addr)?;
+        let mut connection = slot.lock()?;
+        if let Some(open) = connection.as_ref().filter(|open| !open.is_closed()) {
+            return open.send(message_bytes);
+        }
+
+        let stream = TcpStream::connect_timeout(&peer_addr.into(), INTERNODE_CONNECT_TIMEOUT)
+            .map_err(|e| {
+                eprintln!("Error al intentar conectar con {:?}: {:?}", peer_addr, e);
+                NodeError::IoError(e)
+            })?;
+        // A peer that stops reading breaks the connection, instead of blocking its writer for good
+        stream.set_write_timeout(Some(INTERNODE_WRITE_TIMEOUT))?;
+
+        // The node encrypts its connections if it registered the TLS settings of its config
+        let stream = match internode_tls::registered(&message.from)? {
+            Some(tls) => tls.connect(stream)?,
+            None => InternodeStream::Plain(stream),
+        };
+
+        // The compression of the connection is negotiated by its first message
+        let mut bytes = Vec::new();
+        if compression.is_some() {
+            let startup = InternodeMessage::new(
+                message.from,
+                InternodeMessageContent::Startup(ConnectionStartup::new(compression.as_ref())),
+            );
+            bytes.extend(startup.to_compressed_bytes(None));
+        }
+        bytes.extend(message_bytes);
+
+        *connection = Some(PeerConnection::start(peer, stream, bytes));
+        Ok(())
+    }
+
+    /// Forgets the connections that broke, and the peers that could not be connected to, and
+    /// returns how many were forgotten. Their next message opens a new connection.
+    ///
+    /// # Notes
+    /// - The writer of a connection finds out that it broke when a write fails, or when its peer
+    ///   closed it while it had nothing to write (see `PeerConnection::start`).
+    /// - The peers a connection is being opened to are kept.
+    pub fn check_health(&self) -> Result<usize, NodeError> {
+        let mut forgotten = 0;
+        for shard in self.shards.iter() {
+            let mut peers = shard.lock()?;
+            let before = peers.len();
+            peers.retain(|_, slot| match slot.try_lock() {
+                Ok(connection) => connection.as_ref().is_some_and(|open| !open.is_closed()),
+                Err(_) => true,
+            });
+            forgotten += before - peers.len();
+        }
+        Ok(forgotten)
+    }
+
+    /// Returns how many connections are open, the ones that broke but were not forgotten by
+    /// `check_health` yet included.
+    pub fn open_connections(&self) -> Result<usize, NodeError> {
+        let mut open = 0;
+        for shard in self.shards.iter() {
+            for slot in shard.lock()?.values() {
+                open += usize::from(slot.lock()?.is_some());
+            }
+        }
+        Ok(open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::internode_protocol::decoder::InternodeDecoder;
+    use crate::internode_protocol::streaming::StreamResult;
+    use crate::peer_connection::CONNECTION_HEALTH_CHECK_INTERVAL;
+
+    fn result(id: u32) -> InternodeMessage {
+        InternodeMessage::new(
+            Ipv4Addr::new(127, 0, 0, 2),
+            InternodeMessageContent::StreamResult(StreamResult { id, outcome: Ok(3) }),
+        )
+    }
+
+    // Accepts a connection of the manager and returns the messages read from it once the
+    // manager closes it
+    fn read_connection(listener: &TcpListener) -> thread::JoinHandle<Vec<InternodeMessage>> {
+        let listener = listener.try_clone().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut bytes = Vec::new();
+            stream.read_to_end(&mut bytes).ok();
+            InternodeDecoder::new().decode(&bytes).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_messages_to_a_peer_reuse_its_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let reader = read_connection(&listener);
+
+        let connections = ConnectionManager::new();
+        for id in 0..3 {
+            connections
+                .clone()
+                .send(Ipv4Addr::LOCALHOST, port, result(id))
+                .unwrap();
+        }
+        assert_eq!(connections.open_connections().unwrap(), 1);
+
+        drop(connections);
+        assert_eq!(
+            reader.join().unwrap(),
+            vec![result(0), result(1), result(2)]
+        );
+    }
+
+    #[test]
+    fn test_broken_connections_are_forgotten_and_opened_again() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = ConnectionManager::new();
+
+        // The peer closes the first connection right away, so the writer finds it broken once
+        // it writes to it again, or checks it while idle
+        let closer = listener.try_clone().unwrap();
+        let closed = thread::spawn(move || drop(closer.accept().unwrap()));
+        connections
+            .send(Ipv4Addr::LOCALHOST, port, result(0))
+            .unwrap();
+        closed.join().unwrap();
+        connections
+            .send(Ipv4Addr::LOCALHOST, port, result(0))
+            .unwrap();
+
+        let deadline = Instant::now() + 2 * CONNECTION_HEALTH_CHECK_INTERVAL;
+        while connections.check_health().unwrap() == 0 {
+            assert!(Instant::now() < deadline, "the broken connection was kept");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(connections.open_connections().unwrap(), 0);
+
+        let reader = read_connection(&listener);
+        connections
+            .send(Ipv4Addr::LOCALHOST, port, result(1))
+            .unwrap();
+        drop(connections);
+        assert_eq!(reader.join().unwrap(), vec![result(1)]);
+    }
+
+    #[test]
+    fn test_unreachable_peers_are_an_error() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let connections = ConnectionManager::new();
+        assert!(connections
+            .send(Ipv4Addr::LOCALHOST, port, result(0))
+            .is_err());
+        assert_eq!(connections.check_health().unwrap(), 1);
+    }
+}
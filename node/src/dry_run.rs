@@ -0,0 +1,438 @@
+//! Dry runs of schema statements, written as the statement prefixed with `DRY RUN`.
+//!
+//! A dry run validates a `CREATE`, `ALTER` or `DROP` of a keyspace or table against the schema of
+//! the coordinator (name collisions, columns and primary key) and answers with the changes the
+//! statement would make, one per row, without applying them or sending anything to the other
+//! nodes. A statement that would change nothing, like a `CREATE TABLE IF NOT EXISTS` of a table
+//! that exists, is answered with no rows.
+//!
+//! ```sql
+//! DRY RUN ALTER TABLE sky.flights ADD gate TEXT;
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
+use native_protocol::frame::Frame;
+use native_protocol::messages::result::result_;
+use native_protocol::messages::result::rows::{ColumnType, ColumnValue, Rows};
+use query_creator::clauses::table::create_table_cql::CreateTable;
+use query_creator::clauses::types::alter_table_op::AlterTableOperation;
+use query_creator::Query;
+
+use crate::errors::NodeError;
+
+/// Prefix of the statements that are only validated, case insensitive.
+pub const DRY_RUN_PREFIX: &str = "DRY RUN";
+
+/// A change a schema statement would make.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedChange {
+    /// The keyspace (`sky`) or table (`sky.flights`) that would change.
+    pub target: String,
+    pub change: String,
+}
+
+impl PlannedChange {
+    fn new(target: &str, change: impl Into<String>) -> Self {
+        Self {
+            target: target.to_string(),
+            change: change.into(),
+        }
+    }
+}
+
+/// Returns the statement of a query prefixed with `DRY RUN`, or `None` if it is not a dry run.
+pub fn strip_dry_run(query: &str) -> Option<&str> {
+    let query = query.trim_start();
+    let prefix = query.get(..DRY_RUN_PREFIX.len())?;
+    let statement = &query[DRY_RUN_PREFIX.len()..];
+    (prefix.eq_ignore_ascii_case(DRY_RUN_PREFIX) && statement.starts_with(char::is_whitespace))
+        .then(|| statement.trim_start())
+}
+
+/// Validates a schema statement against the keyspaces of the schema, returning the changes it
+/// would make.
+///
+/// # Arguments
+/// - `query`: The statement, which must create, alter or drop a keyspace or table.
+/// - `keyspaces`: The keyspaces of the schema, by name.
+/// - `client_keyspace`: The keyspace in use by the client, for tables given without one.
+///
+/// # Errors
+/// - `NodeError::Invalid` with the reason if the statement is not a schema statement, or would
+///   fail when run.
+pub fn plan(
+    query: &Query,
+    keyspaces: &HashMap<String, KeyspaceSchema>,
+    client_keyspace: Option<&str>,
+) -> Result<Vec<PlannedChange>, NodeError> {
+    match query {
+        Query::CreateKeyspace(create_keyspace) => {
+            let name = create_keyspace.get_name();
+            if keyspaces.contains_key(&name) {
+                return match create_keyspace.if_not_exists_clause {
+                    true => Ok(Vec::new()),
+                    false => Err(invalid(format!("keyspace {} already exists", name))),
+                };
+            }
+            let mut create_keyspace = create_keyspace.clone();
+            create_keyspace.if_not_exists_clause = false;
+            Ok(vec![PlannedChange::new(&name, create_keyspace.serialize())])
+        }
+        Query::AlterKeyspace(alter_keyspace) => {
+            let name = alter_keyspace.get_name();
+            let keyspace = existing_keyspace(keyspaces, &name)?;
+            let mut changes = Vec::new();
+            let class = (
+                keyspace.get_replication_class(),
+                alter_keyspace.get_replication_class(),
+            );
+            if class.0 != class.1 {
+                changes.push(PlannedChange::new(
+                    &name,
+                    format!("replication class {} -> {}", class.0, class.1),
+                ));
+            }
+            let factor = (
+                keyspace.get_replication_factor(),
+                alter_keyspace.get_replication_factor(),
+            );
+            if factor.0 != factor.1 {
+                changes.push(PlannedChange::new(
+                    &name,
+                    format!("replication factor {} -> {}", factor.0, factor.1),
+                ));
+            }
+            let datacenters = (keyspace.get_datacenters(), alter_keyspace.get_datacenters());
+            if datacenters.0 != datacenters.1 {
+                changes.push(PlannedChange::new(
+                    &name,
+                    format!(
+                        "datacenters {} -> {}",
+                        describe_datacenters(&datacenters.0),
+                        describe_datacenters(&datacenters.1)
+                    ),
+                ));
+            }
+            Ok(changes)
+        }
+        Query::DropKeyspace(drop_keyspace) => {
+            let name = drop_keyspace.get_name();
+            let Some(keyspace) = keyspaces.get(&name) else {
+                return match drop_keyspace.get_if_exists_clause() {
+                    true => Ok(Vec::new()),
+                    false => Err(invalid(format!("keyspace {} does not exist", name))),
+                };
+            };
+            let mut changes = vec![PlannedChange::new(&name, "drop keyspace")];
+            changes.extend(keyspace.get_tables().iter().map(|table| {
+                PlannedChange::new(
+                    &format!("{}.{}", name, table.get_name()),
+                    "drop table and its rows",
+                )
+            }));
+            Ok(changes)
+        }
+        Query::CreateTable(create_table) => {
+            let keyspace = table_keyspace(
+                keyspaces,
+                &create_table.get_used_keyspace(),
+                client_keyspace,
+            )?;
+            let target = format!("{}.{}", keyspace.get_name(), create_table.get_name());
+            if keyspace.get_table(&create_table.get_name()).is_ok() {
+                return match create_table.get_if_not_exists_clause() {
+                    true => Ok(Vec::new()),
+                    false => Err(invalid(format!("table {} already exists", target))),
+                };
+            }
+            check_primary_key(create_table, &target)?;
+
+            let mut create_table = create_table.clone();
+            create_table.keyspace_used_name = keyspace.get_name();
+            Ok(vec![PlannedChange::new(
+                &target,
+                TableSchema::new(create_table).describe(),
+            )])
+        }
+        Query::DropTable(drop_table) => {
+            let keyspace =
+                table_keyspace(keyspaces, &drop_table.get_used_keyspace(), client_keyspace)?;
+            let target = format!("{}.{}", keyspace.get_name(), drop_table.get_table_name());
+            if keyspace.get_table(&drop_table.get_table_name()).is_err() {
+                return match drop_table.get_if_exists_clause() {
+                    true => Ok(Vec::new()),
+                    false => Err(invalid(format!("table {} does not exist", target))),
+                };
+            }
+            Ok(vec![PlannedChange::new(&target, "drop table and its rows")])
+        }
+        Query::AlterTable(alter_table) => {
+            let keyspace =
+                table_keyspace(keyspaces, &alter_table.get_used_keyspace(), client_keyspace)?;
+            let target = format!("{}.{}", keyspace.get_name(), alter_table.get_table_name());
+            let mut table = keyspace
+                .get_table(&alter_table.get_table_name())
+                .map_err(|_| invalid(format!("table {} does not exist", target)))?
+                .inner;
+
+            // The operations are applied in order to a copy, as the statement applies them
+            let mut changes = Vec::new();
+            for operation in alter_table.get_operations() {
+                let change = match operation {
+                    AlterTableOperation::AddColumn(column) => {
+                        table.add_column(column.clone()).map_err(|_| {
+                            invalid(format!("column {} already exists", column.name))
+                        })?;
+                        format!(
+                            "add column {} {}",
+                            column.name,
+                            column.data_type.to_string()
+                        )
+                    }
+                    AlterTableOperation::DropColumn(name) => {
+                        table.remove_column(&name).map_err(|_| {
+                            invalid(format!(
+                                "column {} does not exist or is part of the primary key",
+                                name
+                            ))
+                        })?;
+                        format!("drop column {} and its values", name)
+                    }
+                    AlterTableOperation::ModifyColumn(name, _, _) => {
+                        return Err(invalid(format!(
+                            "the type of column {} can not be changed",
+                            name
+                        )))
+                    }
+                    AlterTableOperation::RenameColumn(old_name, new_name) => {
+                        let is_regular = table.get_columns().iter().any(|column| {
+                            column.name == old_name
+                                && !column.is_partition_key
+                                && !column.is_clustering_column
+                        });
+                        if is_regular {
+                            return Err(invalid(format!(
+                                "column {} is not part of the primary key, only those can be renamed",
+                                old_name
+                            )));
+                        }
+                        table.rename_column(&old_name, &new_name).map_err(|_| {
+                            invalid(format!(
+                                "column {} does not exist or {} already exists",
+                                old_name, new_name
+                            ))
+                        })?;
+                        format!("rename column {} to {}", old_name, new_name)
+                    }
+                    AlterTableOperation::SetOptions(options) => options
+                        .iter()
+                        .map(|option| format!("set {}", option))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                };
+                changes.push(PlannedChange::new(&target, change));
+            }
+            check_counters(&table, &target)?;
+            Ok(changes)
+        }
+        _ => Err(invalid(format!(
+            "{} only applies to CREATE, ALTER and DROP of keyspaces and tables",
+            DRY_RUN_PREFIX
+        ))),
+    }
+}
+
+/// Builds the rows the client gets for the changes of a dry run, with a `target` and a `change`
+/// column.
+pub fn to_frame(changes: Vec<PlannedChange>) -> Frame {
+    let columns = vec![
+        ("target".to_string(), ColumnType::Ascii),
+        ("change".to_string(), ColumnType::Ascii),
+    ];
+    let rows = changes
+        .into_iter()
+        .map(|planned| {
+            BTreeMap::from([
+                ("target".to_string(), ColumnValue::Ascii(planned.target)),
+                ("change".to_string(), ColumnValue::Ascii(planned.change)),
+            ])
+        })
+        .collect();
+    Frame::Result(result_::Result::Rows(Rows::new(columns, rows)))
+}
+
+fn invalid(reason: String) -> NodeError {
+    NodeError::Invalid(reason)
+}
+
+fn existing_keyspace<'a>(
+    keyspaces: &'a HashMap<String, KeyspaceSchema>,
+    name: &str,
+) -> Result<&'a KeyspaceSchema, NodeError> {
+    keyspaces
+        .get(name)
+        .ok_or_else(|| invalid(format!("keyspace {} does not exist", name)))
+}
+
+// The keyspace a table statement refers to: the one before the table name, or else the one in
+// use by the client.
+fn table_keyspace<'a>(
+    keyspaces: &'a HashMap<String, KeyspaceSchema>,
+    used_keyspace: &str,
+    client_keyspace: Option<&str>,
+) -> Result<&'a KeyspaceSchema, NodeError> {
+    let name = match used_keyspace {
+        "" => client_keyspace.ok_or_else(|| invalid("no keyspace given or in use".to_string()))?,
+        name => name,
+    };
+    existing_keyspace(keyspaces, name)
+}
+
+// The columns are checked here because the parser takes any name in the primary key.
+fn check_primary_key(create_table: &CreateTable, target: &str) -> Result<(), NodeError> {
+    let mut names = HashSet::new();
+    if let Some(column) = create_table
+        .columns
+        .iter()
+        .find(|column| !names.insert(&column.name))
+    {
+        return Err(invalid(format!(
+            "column {} of table {} is declared twice",
+            column.name, target
+        )));
+    }
+    if !create_table
+        .columns
+        .iter()
+        .any(|column| column.is_partition_key)
+    {
+        return Err(invalid(format!(
+            "the primary key of table {} has no partition key column",
+            target
+        )));
+    }
+    if let Some(name) = create_table
+        .clustering_columns_in_order
+        .iter()
+        .find(|name| !names.contains(name))
+    {
+        return Err(invalid(format!(
+            "clustering column {} is not a column of table {}",
+            name, target
+        )));
+    }
+    check_counters(create_table, target)
+}
+
+fn check_counters(table: &CreateTable, target: &str) -> Result<(), NodeError> {
+    if !table.has_valid_counters() {
+        return Err(invalid(format!(
+            "table {} mixes counters with other columns or has counters in its primary key",
+            target
+        )));
+    }
+    Ok(())
+}
+
+fn describe_datacenters(datacenters: &BTreeMap<String, u32>) -> String {
+    let datacenters: Vec<String> = datacenters
+        .iter()
+        .map(|(datacenter, factor)| format!("{}: {}", datacenter, factor))
+        .collect();
+    format!("{{{}}}", datacenters.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::keyspace::create_keyspace_cql::CreateKeyspace;
+    use query_creator::QueryCreator;
+
+    fn keyspaces() -> HashMap<String, KeyspaceSchema> {
+        let keyspace = CreateKeyspace::deserialize(
+            "CREATE KEYSPACE sky WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 2}",
+        )
+        .unwrap();
+        let flights = CreateTable::deserialize(
+            "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+            PRIMARY KEY (airport, number))",
+        )
+        .unwrap();
+        HashMap::from([(
+            "sky".to_string(),
+            KeyspaceSchema::new(keyspace, vec![TableSchema::new(flights)]),
+        )])
+    }
+
+    fn plan_of(statement: &str) -> Result<Vec<String>, NodeError> {
+        let query = QueryCreator::new()
+            .handle_query(strip_dry_run(statement).unwrap().to_string())
+            .map_err(NodeError::CQLError)?;
+        Ok(plan(&query, &keyspaces(), Some("sky"))?
+            .into_iter()
+            .map(|planned| format!("{}: {}", planned.target, planned.change))
+            .collect())
+    }
+
+    #[test]
+    fn test_strip_dry_run() {
+        assert_eq!(
+            strip_dry_run("  dry run DROP TABLE sky.flights"),
+            Some("DROP TABLE sky.flights")
+        );
+        assert_eq!(strip_dry_run("DROP TABLE sky.flights"), None);
+        assert_eq!(strip_dry_run("DRY RUNNING"), None);
+    }
+
+    #[test]
+    fn test_plan_of_table_statements() {
+        assert_eq!(
+            plan_of("DRY RUN ALTER TABLE flights ADD gate TEXT").unwrap(),
+            vec!["sky.flights: add column gate TEXT"]
+        );
+        assert_eq!(
+            plan_of("DRY RUN DROP TABLE IF EXISTS sky.gates").unwrap(),
+            Vec::<String>::new()
+        );
+        assert!(matches!(
+            plan_of("DRY RUN CREATE TABLE sky.flights (number INT PRIMARY KEY)"),
+            Err(NodeError::Invalid(reason)) if reason == "table sky.flights already exists"
+        ));
+        assert!(matches!(
+            plan_of("DRY RUN CREATE TABLE sky.gates (gate TEXT, PRIMARY KEY (name))"),
+            Err(NodeError::Invalid(reason))
+                if reason == "the primary key of table sky.gates has no partition key column"
+        ));
+        assert!(matches!(
+            plan_of("DRY RUN ALTER TABLE sky.flights DROP airport"),
+            Err(NodeError::Invalid(_))
+        ));
+        assert!(matches!(
+            plan_of("DRY RUN ALTER TABLE sky.flights ADD departures COUNTER"),
+            Err(NodeError::Invalid(reason))
+                if reason == "table sky.flights mixes counters with other columns or has counters in its primary key"
+        ));
+    }
+
+    #[test]
+    fn test_plan_of_keyspace_statements() {
+        assert_eq!(
+            plan_of(
+                "DRY RUN ALTER KEYSPACE sky WITH replication = \
+                {'class': 'SimpleStrategy', 'replication_factor': 3}"
+            )
+            .unwrap(),
+            vec!["sky: replication factor 2 -> 3"]
+        );
+        assert_eq!(
+            plan_of("DRY RUN DROP KEYSPACE sky").unwrap(),
+            vec!["sky: drop keyspace", "sky.flights: drop table and its rows"]
+        );
+        assert!(matches!(
+            plan_of("DRY RUN SELECT * FROM sky.flights"),
+            Err(NodeError::Invalid(_))
+        ));
+    }
+}
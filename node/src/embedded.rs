@@ -0,0 +1,292 @@
+//! A single node run inside the process of an application, without the launcher binary.
+//!
+//! An [`EmbeddedNode`] is a ring of one node (it is its own seed) that listens on loopback ports
+//! the operating system picks and stores its data in a folder of its own, so an application,
+//! a test or a doctest can use the database as it would use SQLite while prototyping:
+//!
+//! ```no_run
+//! use node::EmbeddedNode;
+//!
+//! let node = EmbeddedNode::start().unwrap();
+//! let mut client = node.client().unwrap();
+//! client
+//!     .execute(
+//!         "CREATE KEYSPACE demo WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}",
+//!         "all",
+//!     )
+//!     .unwrap();
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use driver::CassandraClient;
+use uuid::Uuid;
+
+use crate::config::NodeConfig;
+use crate::{ConnectionManager, Node, NodeError};
+
+/// Time the node is given to take client connections after it starts.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time between two checks of whether the node takes client connections.
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Time the queries of the clients are given to finish when the node is dropped.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Folder backed by memory (a tmpfs) on most Linux systems.
+const MEMORY_DIR: &str = "/dev/shm";
+
+/// Where an [`EmbeddedNode`] stores its data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum EmbeddedStorage {
+    /// A new folder in the temporary folder of the system, removed when the node is dropped.
+    #[default]
+    TempDir,
+    /// A new folder in `/dev/shm`, a filesystem kept in memory, removed when the node is
+    /// dropped. Where `/dev/shm` does not exist, the temporary folder of the system is used.
+    Memory,
+    /// The given folder, created if missing and kept when the node is dropped, so the data
+    /// outlives the process.
+    Dir(PathBuf),
+}
+
+impl EmbeddedStorage {
+    // Returns the folder of the data of a node, and whether it is removed with the node.
+    fn folder(&self) -> (PathBuf, bool) {
+        let name = format!("rustic-airlines-{}", Uuid::new_v4());
+        match self {
+            EmbeddedStorage::TempDir => (std::env::temp_dir().join(name), true),
+            EmbeddedStorage::Memory if Path::new(MEMORY_DIR).is_dir() => {
+                (Path::new(MEMORY_DIR).join(name), true)
+            }
+            EmbeddedStorage::Memory => (std::env::temp_dir().join(name), true),
+            EmbeddedStorage::Dir(path) => (path.clone(), false),
+        }
+    }
+}
+
+/// Settings of an [`EmbeddedNode`] before it starts.
+#[derive(Debug, Clone)]
+pub struct EmbeddedNodeBuilder {
+    ip: Ipv4Addr,
+    storage: EmbeddedStorage,
+    config: Option<NodeConfig>,
+}
+
+impl Default for EmbeddedNodeBuilder {
+    fn default() -> Self {
+        Self {
+            ip: Ipv4Addr::LOCALHOST,
+            storage: EmbeddedStorage::default(),
+            config: None,
+        }
+    }
+}
+
+impl EmbeddedNodeBuilder {
+    /// Sets the IP the node listens on, `127.0.0.1` by default.
+    pub fn with_ip(mut self, ip: Ipv4Addr) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    /// Sets where the node stores its data, a temporary folder by default.
+    pub fn with_storage(mut self, storage: EmbeddedStorage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Sets the ports, gossip interval and certificates of the node. Without it, the node
+    /// listens on free ports the operating system picks and uses the certificates of the
+    /// workspace.
+    pub fn with_config(mut self, config: NodeConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Starts the node in background threads, and returns once it takes client connections.
+    ///
+    /// # Returns
+    /// The running node, or the error that kept it from starting.
+    pub fn start(self) -> Result<EmbeddedNode, NodeError> {
+        let config = match self.config {
+            Some(config) => config,
+            None => free_ports_config(self.ip)?,
+        };
+        let (path, removable) = self.storage.folder();
+        fs::create_dir_all(&path)?;
+        let temp_path = removable.then(|| path.clone());
+
+        let client_addr = SocketAddr::V4(SocketAddrV4::new(self.ip, config.client_port));
+        let node = Node::new(self.ip, vec![self.ip], path.clone(), config)?;
+        let node = Arc::new(Mutex::new(node));
+        let connections = ConnectionManager::new();
+        let logger = node.lock()?.get_logger().clone();
+        let started = Arc::clone(&node);
+        let thread = thread::spawn(move || {
+            if let Err(err) = Node::start(started, connections) {
+                logger
+                    .error(&format!("EMBEDDED NODE STOPPED: {}", err), false)
+                    .ok();
+            }
+        });
+
+        let embedded = EmbeddedNode {
+            client_addr,
+            path,
+            temp_path,
+            node,
+            thread: Some(thread),
+        };
+        embedded.wait_until_ready()?;
+        Ok(embedded)
+    }
+}
+
+/// A node running in background threads of the current process.
+///
+/// Dropping the `EmbeddedNode` shuts the node down (see [`Node::shutdown`]), waiting for the
+/// queries of its clients, and then removes its data when it was stored in a temporary folder.
+pub struct EmbeddedNode {
+    client_addr: SocketAddr,
+    path: PathBuf,
+    temp_path: Option<PathBuf>,
+    node: Arc<Mutex<Node>>,
+    /// The thread running `Node::start`, which returns once the node is shut down.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl fmt::Debug for EmbeddedNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedNode")
+            .field("client_addr", &self.client_addr)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EmbeddedNode {
+    /// Starts a node on `127.0.0.1` with free ports and its data in a temporary folder.
+    pub fn start() -> Result<Self, NodeError> {
+        Self::builder().start()
+    }
+
+    /// Returns the settings of a node to start, to change its IP, storage or ports.
+    pub fn builder() -> EmbeddedNodeBuilder {
+        EmbeddedNodeBuilder::default()
+    }
+
+    /// Returns a client connected to the node, already started up.
+    pub fn client(&self) -> Result<CassandraClient, NodeError> {
+        let mut client = CassandraClient::connect_to_addr(self.client_addr)
+            .map_err(|_| NodeError::ClientError)?;
+        client.startup().map_err(|_| NodeError::ClientError)?;
+        Ok(client)
+    }
+
+    /// Returns the address the node takes client connections on.
+    pub fn client_addr(&self) -> SocketAddr {
+        self.client_addr
+    }
+
+    /// Returns the folder the node stores its data in.
+    pub fn storage_path(&self) -> &Path {
+        &self.path
+    }
+
+    // Waits until the node answers the startup of a client, or the startup timeout runs out.
+    fn wait_until_ready(&self) -> Result<(), NodeError> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            if self.client().is_ok() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(NodeError::ClientError);
+            }
+            thread::sleep(STARTUP_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for EmbeddedNode {
+    fn drop(&mut self) {
+        // The files are only removed once no thread of the node writes them
+        if Node::shutdown(&self.node, SHUTDOWN_TIMEOUT).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                thread.join().ok();
+            }
+        }
+        if let Some(path) = &self.temp_path {
+            fs::remove_dir_all(path).ok();
+        }
+    }
+}
+
+// Returns the default configuration of a node with client, internode and admin ports that are
+// free on `ip`.
+fn free_ports_config(ip: Ipv4Addr) -> Result<NodeConfig, NodeError> {
+    // The listeners are kept until the three ports are picked, so that they differ
+    let client = TcpListener::bind((ip, 0))?;
+    let internode = TcpListener::bind((ip, 0))?;
+    let admin = TcpListener::bind((ip, 0))?;
+    let certs_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("certs");
+    Ok(NodeConfig {
+        client_port: client.local_addr()?.port(),
+        internode_port: internode.local_addr()?.port(),
+        admin_port: admin.local_addr()?.port(),
+        certs_path,
+        ..NodeConfig::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use driver::QueryResult;
+
+    #[test]
+    fn test_embedded_node_runs_queries_and_removes_its_folder() {
+        let node = EmbeddedNode::builder()
+            .with_storage(EmbeddedStorage::Memory)
+            .start()
+            .unwrap();
+        let path = node.storage_path().to_path_buf();
+        let mut client = node.client().unwrap();
+
+        client
+            .execute(
+                "CREATE KEYSPACE embedded WITH replication = {'class': 'SimpleStrategy', 'replication_factor': 1}",
+                "all",
+            )
+            .unwrap();
+        client
+            .execute(
+                "CREATE TABLE embedded.flights (id INT, origin TEXT, PRIMARY KEY (id))",
+                "all",
+            )
+            .unwrap();
+        client
+            .execute(
+                "INSERT INTO embedded.flights (id, origin) VALUES (1, 'EZE')",
+                "one",
+            )
+            .unwrap();
+        let result = client
+            .execute("SELECT origin FROM embedded.flights WHERE id = 1", "one")
+            .unwrap();
+        assert!(matches!(result, QueryResult::Result(_)));
+        assert!(path.is_dir());
+
+        drop(client);
+        drop(node);
+        assert!(!path.exists());
+    }
+}
@@ -8,6 +8,7 @@ use super::storage_engine::errors::StorageEngineError;
 use gossip::structures::application_state::SchemaError;
 use logger::LoggerError;
 use native_protocol::errors::NativeError;
+use native_protocol::messages::error::WriteTimeout;
 use partitioner::errors::PartitionerError;
 use query_creator::errors::CQLError; // Importar LoggerError
 
@@ -46,6 +47,33 @@ pub enum NodeError {
     GossipError,
     /// Error related to schema updating.
     SchemaError(SchemaError),
+    /// The node received a query for a partition it does not own (or replicate) anymore.
+    NotOwner,
+    /// A statement of a CQL script run by the node can not be run, or failed.
+    ScriptError(String),
+    /// The authorizer of the node denied a statement of a client, for the given reason.
+    Unauthorized(String),
+    /// The configuration file of the node can not be read, for the given reason.
+    ConfigError(String),
+    /// A statement of a client is valid CQL but can not be run, for the given reason.
+    Invalid(String),
+    /// A write did not reach enough replicas in time, for the given reason, with how many did.
+    WriteTimeout(String, WriteTimeout),
+    /// The node could not hand its data over to the rest of the ring, for the given reason.
+    DecommissionError(String),
+    /// The users of the cluster can not be read or written, for the given reason.
+    AuthError(String),
+    /// The coordinator of a query stopped waiting for it before the node could execute it.
+    DeadlineExceeded,
+    /// Not enough replicas of a query are alive to achieve its consistency level, for the given reason.
+    Unavailable(String),
+    /// The node is already doing as much work as it may at once, for the given reason.
+    Overloaded(String),
+    /// The rows of a table could not be read from all of its replicas to repair them, for the
+    /// given reason.
+    RepairError(String),
+    /// The node was killed by an admin command, as if it had crashed.
+    Killed,
 }
 
 impl Display for NodeError {
@@ -68,6 +96,19 @@ impl Display for NodeError {
             NodeError::LoggerError(e) => write!(f, "Logger Error: {}", e),
             NodeError::GossipError => write!(f, "Gossip Error"),
             NodeError::SchemaError(e) => write!(f, "Schema Error: {}", e),
+            NodeError::NotOwner => write!(f, "Not the owner of the partition"),
+            NodeError::ScriptError(e) => write!(f, "Script Error: {}", e),
+            NodeError::Unauthorized(e) => write!(f, "Unauthorized: {}", e),
+            NodeError::ConfigError(e) => write!(f, "Config Error: {}", e),
+            NodeError::Invalid(e) => write!(f, "Invalid: {}", e),
+            NodeError::WriteTimeout(e, _) => write!(f, "Write Timeout: {}", e),
+            NodeError::DecommissionError(e) => write!(f, "Decommission Error: {}", e),
+            NodeError::AuthError(e) => write!(f, "Auth Error: {}", e),
+            NodeError::DeadlineExceeded => write!(f, "The deadline of the query passed"),
+            NodeError::Unavailable(e) => write!(f, "Unavailable: {}", e),
+            NodeError::Overloaded(e) => write!(f, "Overloaded: {}", e),
+            NodeError::RepairError(e) => write!(f, "Repair Error: {}", e),
+            NodeError::Killed => write!(f, "The node was killed"),
         }
     }
 }
@@ -0,0 +1,107 @@
+//! Log of the high level events of a node (ring membership changes, data redistribution and
+//! schema changes), polled by clients through the `EVENTS` admin command.
+
+use std::collections::VecDeque;
+
+use chrono::Utc;
+use driver::events::{EventRecord, NodeEvent};
+
+/// Number of events kept by default. Older events are dropped first.
+const EVENT_LOG_CAPACITY: usize = 200;
+
+/// A bounded list of the last events of a node, each one with an increasing id so clients can
+/// ask only for the events they have not seen yet.
+///
+/// The ids start from the time the log was created, in microseconds since the epoch, so the
+/// events of a restarted node follow the ones of its previous run: a client that keeps the id of
+/// the last event it saw does not skip the new ones.
+pub struct EventLog {
+    events: VecDeque<EventRecord>,
+    last_id: u64,
+    capacity: usize,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog {
+            events: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+            last_id: Utc::now().timestamp_micros() as u64,
+            capacity: EVENT_LOG_CAPACITY,
+        }
+    }
+
+    /// Records `event` with the current time, dropping the oldest event if the log is full.
+    pub fn record(&mut self, event: NodeEvent) {
+        self.last_id += 1;
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(EventRecord {
+            id: self.last_id,
+            timestamp: Utc::now().timestamp(),
+            event,
+        });
+    }
+
+    /// Returns the events recorded after the event with id `since`, oldest first.
+    pub fn since(&self, since: u64) -> Vec<EventRecord> {
+        self.events
+            .iter()
+            .filter(|record| record.id > since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_events_since() {
+        let mut log = EventLog::new();
+        log.record(NodeEvent::NodeJoined(Ipv4Addr::new(127, 0, 0, 2)));
+        log.record(NodeEvent::RedistributionStarted);
+        log.record(NodeEvent::RedistributionFinished);
+
+        let events = log.since(0);
+        assert_eq!(events.len(), 3);
+        let first = events[0].id;
+
+        let new_events = log.since(first);
+        assert_eq!(new_events.len(), 2);
+        assert_eq!(new_events[0].id, first + 1);
+        assert_eq!(new_events[0].event, NodeEvent::RedistributionStarted);
+        assert!(log.since(first + 2).is_empty());
+    }
+
+    #[test]
+    fn test_ids_go_on_after_a_restart() {
+        let mut log = EventLog::new();
+        log.record(NodeEvent::SchemaChanged);
+        let last_seen = log.since(0)[0].id;
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let mut restarted = EventLog::new();
+        restarted.record(NodeEvent::NodeJoined(Ipv4Addr::new(127, 0, 0, 2)));
+
+        assert_eq!(restarted.since(last_seen).len(), 1);
+    }
+
+    #[test]
+    fn test_oldest_events_are_dropped() {
+        let mut log = EventLog {
+            capacity: 2,
+            ..EventLog::new()
+        };
+        log.record(NodeEvent::SchemaChanged);
+        log.record(NodeEvent::RedistributionStarted);
+        log.record(NodeEvent::RedistributionFinished);
+
+        let events = log.since(0);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, NodeEvent::RedistributionStarted);
+        assert_eq!(events[1].id, events[0].id + 1);
+    }
+}
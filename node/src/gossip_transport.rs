@@ -0,0 +1,58 @@
+//! Transport used by the gossiper of a node: gossip messages travel inside internode messages,
+//! over the same connections used for queries.
+
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+
+use gossip::messages::GossipMessage;
+use gossip::transport::Transport;
+use gossip::GossipError;
+
+use crate::connection_manager::ConnectionManager;
+use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::utils::connect_and_send_message;
+
+/// Sends the gossip messages of the node with ip `from` to the internode port of its peers.
+pub struct InternodeGossipTransport {
+    from: Ipv4Addr,
+    port: u16,
+    connections: ConnectionManager,
+    blocked_peers: HashSet<Ipv4Addr>,
+}
+
+impl InternodeGossipTransport {
+    /// Creates a transport for the node with ip `from`, which sends to the internode `port` of its
+    /// peers. Messages to `blocked_peers` (the ones on the other side of a simulated partition) are
+    /// dropped.
+    pub fn new(
+        from: Ipv4Addr,
+        port: u16,
+        connections: ConnectionManager,
+        blocked_peers: HashSet<Ipv4Addr>,
+    ) -> Self {
+        InternodeGossipTransport {
+            from,
+            port,
+            connections,
+            blocked_peers,
+        }
+    }
+}
+
+impl Transport for InternodeGossipTransport {
+    fn send(&self, to: Ipv4Addr, message: GossipMessage) -> Result<(), GossipError> {
+        // A partitioned peer is unreachable, but it is not marked as dead so the ring does not
+        // change until the partition is healed
+        if self.blocked_peers.contains(&to) {
+            return Ok(());
+        }
+
+        connect_and_send_message(
+            to,
+            self.port,
+            self.connections.clone(),
+            InternodeMessage::new(self.from, InternodeMessageContent::Gossip(message)),
+        )
+        .map_err(|_| GossipError::SendError)
+    }
+}
@@ -0,0 +1,186 @@
+//! Hinted handoff: writes that could not reach one of their replicas are kept on disk, as hints
+//! for that replica, and sent to it again once gossip reports it back to `Normal`.
+//!
+//! The hints of each replica are stored in their own file, `<ip>.hints`, as a sequence of
+//! serialized `InternodeQuery`, each one preceded by its length (4 bytes). A hint expires once
+//! `ttl` passed since its write was received by the coordinator, as a replica that was down for
+//! longer is expected to be repaired by other means.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    net::Ipv4Addr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use chrono::Utc;
+
+use crate::{
+    errors::NodeError,
+    internode_protocol::{query::InternodeQuery, InternodeSerializable},
+};
+
+/// Time a hint is kept by default (3 hours).
+pub const DEFAULT_HINT_TTL: Duration = Duration::from_secs(3 * 60 * 60);
+
+/// The hints a node keeps for the replicas it could not reach.
+#[derive(Debug, Clone)]
+pub struct HintStore {
+    folder: PathBuf,
+    ttl: Duration,
+}
+
+impl HintStore {
+    /// Creates a store that keeps its hints in `folder`, which is created when the first hint is
+    /// stored.
+    pub fn new(folder: PathBuf) -> Self {
+        HintStore {
+            folder,
+            ttl: DEFAULT_HINT_TTL,
+        }
+    }
+
+    /// Sets the time a hint is kept before it is dropped.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Stores `query` as a hint for `target`. The hint does not belong to any open query, so the
+    /// replica accepts it even if the ring changed, and its response is ignored.
+    pub fn store(&self, target: Ipv4Addr, query: &InternodeQuery) -> Result<(), NodeError> {
+        fs::create_dir_all(&self.folder)?;
+
+        let hint = InternodeQuery {
+            open_query_id: 0,
+            client_id: 0,
+            // The hint is replayed long after the client got its answer
+            time_left: None,
+            ..query.clone()
+        }
+        .as_bytes();
+
+        let mut record = (hint.len() as u32).to_be_bytes().to_vec();
+        record.extend_from_slice(&hint);
+
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.hints_path(target))?
+            .write_all(&record)?;
+        Ok(())
+    }
+
+    /// Returns the replicas there are hints for.
+    pub fn targets(&self) -> Vec<Ipv4Addr> {
+        let Ok(entries) = fs::read_dir(&self.folder) else {
+            return vec![];
+        };
+
+        entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_suffix(".hints")?.parse().ok()
+            })
+            .collect()
+    }
+
+    /// Removes the hints of `target` and returns the ones that did not expire, oldest first.
+    ///
+    /// The file is renamed before it is read, so the hints stored meanwhile go to a new file
+    /// instead of being removed with the ones taken.
+    pub fn take(&self, target: Ipv4Addr) -> Result<Vec<InternodeQuery>, NodeError> {
+        let path = self.hints_path(target);
+        let replaying = path.with_extension("replaying");
+        match fs::rename(&path, &replaying) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        }
+        let bytes = fs::read(&replaying)?;
+        fs::remove_file(&replaying)?;
+
+        let oldest = Utc::now().timestamp() - self.ttl.as_secs() as i64;
+        Ok(Self::read_hints(&bytes)
+            .into_iter()
+            .filter(|hint| hint.timestamp >= oldest)
+            .collect())
+    }
+
+    // Reads the hints of a file, stopping at the first one that can not be read (such as the last
+    // one of a file being written when the node crashed).
+    fn read_hints(bytes: &[u8]) -> Vec<InternodeQuery> {
+        let mut hints = Vec::new();
+        let mut rest = bytes;
+
+        while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+            let len = u32::from_be_bytes(*len) as usize;
+            if tail.len() < len {
+                break;
+            }
+            let Ok(hint) = InternodeQuery::from_bytes(&tail[..len]) else {
+                break;
+            };
+            hints.push(hint);
+            rest = &tail[len..];
+        }
+        hints
+    }
+
+    fn hints_path(&self, target: Ipv4Addr) -> PathBuf {
+        self.folder.join(format!("{}.hints", target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn write(query_string: &str, timestamp: i64) -> InternodeQuery {
+        InternodeQuery {
+            query_string: query_string.to_string(),
+            open_query_id: 7,
+            client_id: 3,
+            replication: true,
+            keyspace_name: "sky".to_string(),
+            timestamp,
+            correlation_id: String::new(),
+            statement: None,
+            time_left: None,
+            digest: false,
+        }
+    }
+
+    #[test]
+    fn test_hints_are_stored_and_taken() {
+        let folder = PathBuf::from(format!("/tmp/hints_test_{}", Uuid::new_v4()));
+        let hints = HintStore::new(folder.clone()).with_ttl(Duration::from_secs(60));
+        let target = Ipv4Addr::new(127, 0, 0, 2);
+        let now = Utc::now().timestamp();
+
+        assert!(hints.targets().is_empty());
+
+        let first = write("INSERT INTO sky.flights (id) VALUES (1)", now);
+        let expired = write("INSERT INTO sky.flights (id) VALUES (2)", now - 120);
+        let last = write("INSERT INTO sky.flights (id) VALUES (3)", now);
+        for query in [&first, &expired, &last] {
+            hints.store(target, query).unwrap();
+        }
+        assert_eq!(hints.targets(), vec![target]);
+
+        let taken = hints.take(target).unwrap();
+        let queries: Vec<&str> = taken.iter().map(|q| q.query_string.as_str()).collect();
+        assert_eq!(queries, vec![&first.query_string, &last.query_string]);
+        // Replayed hints do not belong to the query that created them
+        assert!(taken
+            .iter()
+            .all(|q| q.open_query_id == 0 && q.client_id == 0));
+
+        assert!(hints.targets().is_empty());
+        assert!(hints.take(target).unwrap().is_empty());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
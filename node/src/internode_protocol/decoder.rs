@@ -0,0 +1,155 @@
+//! Incremental decoder of the messages read from an internode connection.
+//!
+//! TCP (and TLS on top of it) delivers a stream of bytes, not messages: a single read may hold
+//! several messages written back to back, and a long message may arrive over several reads. Every
+//! message starts with a header holding the length of its content (see `message`), so the decoder
+//! keeps the bytes of the message that did not fully arrive yet and returns each message once all
+//! of its bytes are read, whatever the reads they came in.
+
+use native_protocol::compression::Compression;
+
+use super::message::{InternodeMessage, InternodeMessageContent, InternodeMessageError};
+
+/// Decodes the messages of one internode connection from the bytes read from it, in order.
+///
+/// ### Fields
+/// - `pending`: Bytes read that do not make a whole message yet.
+/// - `compression`: The algorithm the messages of the connection are compressed with, set by
+///   its `Startup` message.
+#[derive(Debug, Default)]
+pub struct InternodeDecoder {
+    pending: Vec<u8>,
+    compression: Option<Compression>,
+}
+
+impl InternodeDecoder {
+    /// Creates the decoder of a new connection, whose messages are not compressed until its
+    /// `Startup` says otherwise.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the bytes of a read of the connection and returns the messages they complete, in
+    /// the order they were written.
+    ///
+    /// `Startup` messages are not returned: they set the compression of the messages after them,
+    /// which may have arrived in the same read.
+    ///
+    /// # Errors
+    /// - `InternodeMessageError` if a `Startup` asks for a compression this node does not
+    ///   support, in which case the connection can not be read anymore.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Vec<InternodeMessage>, InternodeMessageError> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut decoded = Vec::new();
+        loop {
+            let (messages, consumed) =
+                InternodeMessage::split_from_bytes(&self.pending, self.compression.as_ref());
+            self.pending.drain(..consumed);
+
+            // A startup is the last message split, the ones after it are split again with the
+            // compression it negotiates
+            let mut negotiated = false;
+            for message in messages {
+                match &message.content {
+                    InternodeMessageContent::Startup(startup) => {
+                        self.compression = startup.compression()?;
+                        negotiated = true;
+                    }
+                    _ => decoded.push(message),
+                }
+            }
+            if !negotiated {
+                return Ok(decoded);
+            }
+        }
+    }
+
+    /// Returns the amount of bytes read that are waiting for the rest of their message.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::internode_protocol::{
+        startup::ConnectionStartup,
+        streaming::{StreamChunk, StreamResult},
+        InternodeSerializable,
+    };
+
+    fn result(id: u32) -> InternodeMessage {
+        InternodeMessage::new(
+            Ipv4Addr::new(127, 0, 0, 2),
+            InternodeMessageContent::StreamResult(StreamResult { id, outcome: Ok(3) }),
+        )
+    }
+
+    #[test]
+    fn test_messages_split_across_reads_are_decoded_once_complete() {
+        let mut bytes = result(1).as_bytes();
+        bytes.extend(result(2).as_bytes());
+
+        let mut decoder = InternodeDecoder::new();
+        let mut decoded = Vec::new();
+        for byte in &bytes {
+            decoded.extend(decoder.decode(&[*byte]).unwrap());
+            if decoded.len() == 1 {
+                assert!(decoder.pending_len() < result(2).as_bytes().len());
+            }
+        }
+
+        assert_eq!(decoded, vec![result(1), result(2)]);
+        assert_eq!(decoder.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_messages_after_the_startup_are_decompressed() {
+        let from = Ipv4Addr::new(127, 0, 0, 2);
+        let startup = InternodeMessage::new(
+            from,
+            InternodeMessageContent::Startup(ConnectionStartup::new(Some(&Compression::Lz4))),
+        );
+        let chunk = InternodeMessage::new(
+            from,
+            InternodeMessageContent::StreamChunk(StreamChunk::new(
+                7,
+                0,
+                "sky",
+                "flights",
+                false,
+                vec!["1,EZE,AEP;1700000000".to_string(); 100],
+            )),
+        );
+
+        // The startup and the first half of the compressed chunk arrive in the same read
+        let mut bytes = startup.as_bytes();
+        bytes.extend(chunk.to_compressed_bytes(Some(&Compression::Lz4)));
+        bytes.extend(result(1).to_compressed_bytes(Some(&Compression::Lz4)));
+        let half = startup.as_bytes().len() + 40;
+
+        let mut decoder = InternodeDecoder::new();
+        assert_eq!(decoder.decode(&bytes[..half]).unwrap(), vec![]);
+        assert_eq!(
+            decoder.decode(&bytes[half..]).unwrap(),
+            vec![chunk, result(1)]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_compression_is_an_error() {
+        let startup = InternodeMessage::new(
+            Ipv4Addr::new(127, 0, 0, 2),
+            InternodeMessageContent::Startup(ConnectionStartup {
+                compression: Some("zstd".to_string()),
+            }),
+        );
+
+        let mut decoder = InternodeDecoder::new();
+        assert!(decoder.decode(&startup.as_bytes()).is_err());
+    }
+}
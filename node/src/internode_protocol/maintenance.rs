@@ -0,0 +1,200 @@
+//! Maintenance operations a node asks the other nodes of the ring to run on their storage, such
+//! as flushing the memtables, compacting the SSTables or taking a snapshot of a keyspace, and their
+//! results.
+
+use super::{
+    message::InternodeMessageError, read_string, read_u32, write_string, InternodeSerializable,
+};
+use std::io::{Cursor, Read};
+
+/// An operation on the storage of the tables of a keyspace.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MaintenanceOperation {
+    /// Writes the memtables of the tables to new SSTables.
+    Flush = 0x00,
+    /// Writes the live rows of the SSTables and memtables of the tables to their data files.
+    Compact = 0x01,
+    /// Takes a snapshot of the tables, to back them up.
+    Snapshot = 0x02,
+}
+
+impl MaintenanceOperation {
+    fn from_byte(byte: u8) -> Result<Self, InternodeMessageError> {
+        match byte {
+            0x00 => Ok(MaintenanceOperation::Flush),
+            0x01 => Ok(MaintenanceOperation::Compact),
+            0x02 => Ok(MaintenanceOperation::Snapshot),
+            _ => Err(InternodeMessageError),
+        }
+    }
+}
+
+/// Asks a node to run a maintenance operation on a keyspace, or on one of its tables.
+///
+/// ### Fields
+/// - `id`: Identifies the request in the node that sent it, which gets it back in the result.
+/// - `operation`: The operation to run.
+/// - `keyspace`: The keyspace whose tables the operation runs on.
+/// - `table`: The only table to run the operation on, or `None` for every table of the keyspace.
+/// - `tag`: The name of the snapshot taken by a `Snapshot`, `None` for the other operations.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MaintenanceRequest {
+    pub id: u32,
+    pub operation: MaintenanceOperation,
+    pub keyspace: String,
+    pub table: Option<String>,
+    pub tag: Option<String>,
+}
+
+/// What a node did when running a maintenance request.
+///
+/// ### Fields
+/// - `id`: The id of the request.
+/// - `outcome`: The amount of memtables flushed or SSTables compacted, or why the node failed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MaintenanceResult {
+    pub id: u32,
+    pub outcome: Result<u32, String>,
+}
+
+impl InternodeSerializable for MaintenanceRequest {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// | op |  keyspace_len  |
+    /// +----+----+----+----+
+    /// |     keyspace      |
+    /// +----+----+----+----+
+    /// |     table_len     |
+    /// +----+----+----+----+
+    /// |       table       |
+    /// +----+----+----+----+
+    /// |      tag_len      |
+    /// +----+----+----+----+
+    /// |        tag        |
+    /// +----+----+----+----+
+    /// ```
+    /// A request for every table of the keyspace has an empty table, and one without a tag an empty tag.
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.push(self.operation as u8);
+        write_string(&mut bytes, &self.keyspace);
+        write_string(&mut bytes, self.table.as_deref().unwrap_or_default());
+        write_string(&mut bytes, self.tag.as_deref().unwrap_or_default());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let mut operation = [0u8; 1];
+        cursor
+            .read_exact(&mut operation)
+            .map_err(|_| InternodeMessageError)?;
+        let operation = MaintenanceOperation::from_byte(operation[0])?;
+        let keyspace = read_string(&mut cursor)?;
+        let table = Some(read_string(&mut cursor)?).filter(|table| !table.is_empty());
+        let tag = Some(read_string(&mut cursor)?).filter(|tag| !tag.is_empty());
+
+        Ok(MaintenanceRequest {
+            id,
+            operation,
+            keyspace,
+            table,
+            tag,
+        })
+    }
+}
+
+impl InternodeSerializable for MaintenanceResult {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// | ok |    amount or   |
+    /// +----+----+----+----+
+    /// |reason_len + reason|
+    /// +----+----+----+----+
+    /// ```
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        match &self.outcome {
+            Ok(amount) => {
+                bytes.push(0x00);
+                bytes.extend(&amount.to_be_bytes());
+            }
+            Err(reason) => {
+                bytes.push(0x01);
+                write_string(&mut bytes, reason);
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let mut status = [0u8; 1];
+        cursor
+            .read_exact(&mut status)
+            .map_err(|_| InternodeMessageError)?;
+        let outcome = match status[0] {
+            0x00 => Ok(read_u32(&mut cursor)?),
+            0x01 => Err(read_string(&mut cursor)?),
+            _ => return Err(InternodeMessageError),
+        };
+
+        Ok(MaintenanceResult { id, outcome })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_request_round_trip() {
+        for table in [Some("flights".to_string()), None] {
+            let request = MaintenanceRequest {
+                id: 7,
+                operation: MaintenanceOperation::Compact,
+                keyspace: "sky".to_string(),
+                table,
+                tag: None,
+            };
+            let parsed = MaintenanceRequest::from_bytes(&request.as_bytes()).unwrap();
+            assert_eq!(parsed, request);
+        }
+
+        let request = MaintenanceRequest {
+            id: 8,
+            operation: MaintenanceOperation::Snapshot,
+            keyspace: "sky".to_string(),
+            table: None,
+            tag: Some("nightly".to_string()),
+        };
+        let parsed = MaintenanceRequest::from_bytes(&request.as_bytes()).unwrap();
+        assert_eq!(parsed, request);
+    }
+
+    #[test]
+    fn test_maintenance_result_round_trip() {
+        for outcome in [Ok(2), Err("Keyspace error".to_string())] {
+            let result = MaintenanceResult { id: 3, outcome };
+            let parsed = MaintenanceResult::from_bytes(&result.as_bytes()).unwrap();
+            assert_eq!(parsed, result);
+        }
+        assert!(MaintenanceResult::from_bytes(&[0, 0, 0, 1, 0x05]).is_err());
+        // An error longer than the result is rejected without allocating for it
+        assert!(
+            MaintenanceResult::from_bytes(&[0, 0, 0, 1, 0x01, 0xff, 0xff, 0xff, 0xff]).is_err()
+        );
+    }
+}
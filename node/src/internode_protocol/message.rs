@@ -1,5 +1,14 @@
-use super::{query::InternodeQuery, response::InternodeResponse, InternodeSerializable};
+use super::{
+    maintenance::{MaintenanceRequest, MaintenanceResult},
+    paxos::{PaxosReply, PaxosRequest},
+    query::InternodeQuery,
+    response::InternodeResponse,
+    startup::ConnectionStartup,
+    streaming::{StreamChunk, StreamChunkAck, StreamRequest, StreamResult},
+    InternodeSerializable,
+};
 use gossip::messages::GossipMessage;
+use native_protocol::{checksum::crc32, compression::Compression};
 use std::{
     io::{Cursor, Read},
     net::Ipv4Addr,
@@ -12,6 +21,15 @@ enum Opcode {
     Query = 0x01,
     Response = 0x02,
     Gossip = 0x03,
+    Maintenance = 0x04,
+    MaintenanceResult = 0x05,
+    Paxos = 0x06,
+    PaxosReply = 0x07,
+    StreamRequest = 0x08,
+    StreamResult = 0x09,
+    StreamChunk = 0x0A,
+    StreamChunkAck = 0x0B,
+    Startup = 0x0C,
 }
 
 /// The header of an internode message.
@@ -20,14 +38,25 @@ enum Opcode {
 ///
 /// * `opcode` - The opcode of the message.
 /// * `ip` - The IP address of the node that sent the message.
+/// * `length` - The length of the content, as sent.
+/// * `compressed` - Whether the content is compressed with the algorithm of the connection.
+/// * `checksum` - The CRC32 of the content, as sent.
 #[derive(Debug, PartialEq)]
 struct InternodeHeader {
     opcode: Opcode,
     ip: Ipv4Addr,
     length: u32,
+    compressed: bool,
+    checksum: u32,
 }
 
-const HEADER_SIZE: usize = 9;
+const HEADER_SIZE: usize = 18;
+/// Bytes of the header covered by the checksum that ends it.
+const CHECKED_HEADER_SIZE: usize = HEADER_SIZE - 4;
+/// Flag of the header of a message whose content is compressed.
+const COMPRESSED_FLAG: u8 = 0x01;
+/// Contents shorter than this are sent uncompressed, as compressing them saves too little.
+const COMPRESSION_THRESHOLD: usize = 256;
 
 impl InternodeSerializable for InternodeHeader {
     /// ```md
@@ -37,25 +66,40 @@ impl InternodeSerializable for InternodeHeader {
     /// +----+----+----+----+
     /// |  content_length   |
     /// +----+----+----+----+
-    /// | op |              |
+    /// | op |flag|
+    /// +----+----+----+----+
+    /// | content_checksum  |
+    /// +----+----+----+----+
+    /// |  header_checksum  |
     /// +----+----+----+----+
     /// ```
-    /// Serializes the header into a byte vector.
+    /// Serializes the header into a byte vector. It ends with the CRC32 of the bytes before it, so
+    /// a corrupted length is detected before it is used to find the end of the message.
     fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
         bytes.extend_from_slice(&self.ip.octets());
         bytes.extend_from_slice(&self.length.to_be_bytes());
         bytes.push(self.opcode as u8);
+        bytes.push(if self.compressed { COMPRESSED_FLAG } else { 0 });
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&crc32(&bytes).to_be_bytes());
 
         bytes
     }
 
-    /// Deserializes the header from a byte slice.
+    /// Deserializes the header from a byte slice, failing if its checksum does not match.
     fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError>
     where
         Self: Sized,
     {
+        let header_checksum = bytes
+            .get(CHECKED_HEADER_SIZE..HEADER_SIZE)
+            .ok_or(InternodeMessageError)?;
+        if crc32(&bytes[..CHECKED_HEADER_SIZE]).to_be_bytes() != header_checksum {
+            return Err(InternodeMessageError);
+        }
+
         let mut cursor = Cursor::new(bytes);
 
         let mut ip_bytes = [0u8; 4];
@@ -81,10 +125,37 @@ impl InternodeSerializable for InternodeHeader {
             0x01 => Opcode::Query,
             0x02 => Opcode::Response,
             0x03 => Opcode::Gossip,
+            0x04 => Opcode::Maintenance,
+            0x05 => Opcode::MaintenanceResult,
+            0x06 => Opcode::Paxos,
+            0x07 => Opcode::PaxosReply,
+            0x08 => Opcode::StreamRequest,
+            0x09 => Opcode::StreamResult,
+            0x0A => Opcode::StreamChunk,
+            0x0B => Opcode::StreamChunkAck,
+            0x0C => Opcode::Startup,
             _ => return Err(InternodeMessageError),
         };
 
-        Ok(InternodeHeader { opcode, ip, length })
+        let mut flags_byte = [0u8; 1];
+        cursor
+            .read_exact(&mut flags_byte)
+            .map_err(|_| InternodeMessageError)?;
+        let compressed = flags_byte[0] & COMPRESSED_FLAG != 0;
+
+        let mut checksum_bytes = [0u8; 4];
+        cursor
+            .read_exact(&mut checksum_bytes)
+            .map_err(|_| InternodeMessageError)?;
+        let checksum = u32::from_be_bytes(checksum_bytes);
+
+        Ok(InternodeHeader {
+            opcode,
+            ip,
+            length,
+            compressed,
+            checksum,
+        })
     }
 }
 
@@ -95,11 +166,29 @@ impl InternodeSerializable for InternodeHeader {
 /// * `Query` - A query message.
 /// * `Response` - A response message.
 /// * `Gossip` - A gossip message.
+/// * `Maintenance` - A request to run a maintenance operation on the storage of a keyspace.
+/// * `MaintenanceResult` - The result of a maintenance request.
+/// * `Paxos` - A phase of a Paxos round of a lightweight transaction.
+/// * `PaxosReply` - The reply of a replica to a phase of a Paxos round.
+/// * `StreamRequest` - A request of a bootstrapping node for the rows of its ranges.
+/// * `StreamResult` - The rows streamed for a stream request.
+/// * `StreamChunk` - Rows of a table streamed to a node.
+/// * `StreamChunkAck` - Whether a node stored a chunk streamed to it.
+/// * `Startup` - The options of the connection it opens, as its first message.
 #[derive(Debug, PartialEq, Clone)]
 pub enum InternodeMessageContent {
     Query(InternodeQuery),
     Response(InternodeResponse),
     Gossip(GossipMessage),
+    Maintenance(MaintenanceRequest),
+    MaintenanceResult(MaintenanceResult),
+    Paxos(PaxosRequest),
+    PaxosReply(PaxosReply),
+    StreamRequest(StreamRequest),
+    StreamResult(StreamResult),
+    StreamChunk(StreamChunk),
+    StreamChunkAck(StreamChunkAck),
+    Startup(ConnectionStartup),
 }
 
 /// A message transmitted between nodes via the internode protocol.
@@ -122,51 +211,86 @@ impl InternodeMessage {
     pub fn new(from: Ipv4Addr, content: InternodeMessageContent) -> Self {
         Self { from, content }
     }
-}
-
-/// An error that occurs when serializing or deserializing an internode message.
-#[derive(Debug)]
-pub struct InternodeMessageError;
 
-impl InternodeSerializable for InternodeMessage {
-    /// ```md
-    /// 0    8    16   24   32
-    /// +----+----+----+----+
-    /// |       header      |
-    /// +----+----+----+----+
-    /// |head|  content...
-    /// +----+----+----+----+
-    /// ```
-    /// Serializes the message into a byte vector.
-    fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+    /// Deserializes every whole message in a byte slice, as a single read of a connection may get
+    /// several messages written back to back. Stops at the first one that can not be deserialized.
+    pub fn all_from_bytes(bytes: &[u8]) -> Vec<Self> {
+        Self::split_from_bytes(bytes, None).0
+    }
 
-        let opcode = match self.content {
-            InternodeMessageContent::Query(_) => Opcode::Query,
-            InternodeMessageContent::Response(_) => Opcode::Response,
-            InternodeMessageContent::Gossip(_) => Opcode::Gossip,
-        };
+    /// Deserializes every whole message in a byte slice as `all_from_bytes` does, decompressing
+    /// the ones compressed with the `compression` of their connection, and also returns how many
+    /// bytes they took. A message cut by the end of the slice is left for the next read to
+    /// complete.
+    ///
+    /// Messages corrupted on the way are dropped, without losing the ones after them: a message
+    /// whose content does not match its checksum (or can not be deserialized) is skipped, as the
+    /// checksum of its header vouches for its length, while bytes that do not start with a valid
+    /// header are skipped one at a time until one does.
+    ///
+    /// A `Startup` is the last message returned, as the ones after it may be compressed with the
+    /// algorithm it negotiates.
+    pub fn split_from_bytes(bytes: &[u8], compression: Option<&Compression>) -> (Vec<Self>, usize) {
+        let mut messages = Vec::new();
+        let mut offset = 0;
+        while let Some(header_bytes) = bytes.get(offset..offset + HEADER_SIZE) {
+            let Ok(header) = InternodeHeader::from_bytes(header_bytes) else {
+                offset += 1;
+                continue;
+            };
+            let end = offset + HEADER_SIZE + header.length as usize;
+            let Some(message_bytes) = bytes.get(offset..end) else {
+                break;
+            };
+            if let Ok(message) = Self::from_compressed_bytes(message_bytes, compression) {
+                messages.push(message);
+            }
+            offset = end;
+            if header.opcode == Opcode::Startup {
+                break;
+            }
+        }
+        (messages, offset)
+    }
 
-        let content_bytes = match &self.content {
-            InternodeMessageContent::Query(internode_query) => internode_query.as_bytes(),
-            InternodeMessageContent::Response(internode_response) => internode_response.as_bytes(),
-            InternodeMessageContent::Gossip(gossip_message) => gossip_message.as_bytes(),
+    /// Serializes the message, compressing its content with `compression` if it is long enough
+    /// for it to pay off. `Startup` messages are never compressed, since they are the ones that
+    /// negotiate the compression. The compressed flag of the header tells whether it is.
+    pub fn to_compressed_bytes(&self, compression: Option<&Compression>) -> Vec<u8> {
+        let (opcode, content_bytes) = self.opcode_and_content();
+        let compressed = match compression {
+            Some(compression)
+                if opcode != Opcode::Startup && content_bytes.len() >= COMPRESSION_THRESHOLD =>
+            {
+                compression
+                    .compress(&content_bytes)
+                    .ok()
+                    .filter(|compressed| compressed.len() < content_bytes.len())
+            }
+            _ => None,
         };
 
+        let content_bytes = compressed.as_ref().unwrap_or(&content_bytes);
         let header = InternodeHeader {
             ip: self.from,
             opcode,
             length: content_bytes.len() as u32,
+            compressed: compressed.is_some(),
+            checksum: crc32(content_bytes),
         };
 
-        bytes.extend_from_slice(&header.as_bytes());
-        bytes.extend_from_slice(&content_bytes);
-
+        let mut bytes = header.as_bytes();
+        bytes.extend_from_slice(content_bytes);
         bytes
     }
 
-    /// Deserializes the message from a byte slice.
-    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+    /// Deserializes a message, decompressing its content with `compression` if the header says it
+    /// is compressed. A compressed message fails to deserialize if no compression was negotiated,
+    /// and any message fails if its header or its content do not match their checksums.
+    pub fn from_compressed_bytes(
+        bytes: &[u8],
+        compression: Option<&Compression>,
+    ) -> Result<Self, InternodeMessageError> {
         let mut cursor = Cursor::new(bytes);
 
         let mut header_bytes = [0u8; HEADER_SIZE];
@@ -180,6 +304,15 @@ impl InternodeSerializable for InternodeMessage {
         cursor
             .read_exact(&mut content_bytes)
             .map_err(|_| InternodeMessageError)?;
+        if crc32(&content_bytes) != header.checksum {
+            return Err(InternodeMessageError);
+        }
+        if header.compressed {
+            content_bytes = compression
+                .ok_or(InternodeMessageError)?
+                .decompress(&content_bytes)
+                .map_err(|_| InternodeMessageError)?;
+        }
 
         let content = match header.opcode {
             Opcode::Query => InternodeMessageContent::Query(
@@ -191,6 +324,33 @@ impl InternodeSerializable for InternodeMessage {
             Opcode::Gossip => InternodeMessageContent::Gossip(
                 GossipMessage::from_bytes(&content_bytes).map_err(|_| InternodeMessageError)?,
             ),
+            Opcode::Maintenance => InternodeMessageContent::Maintenance(
+                MaintenanceRequest::from_bytes(&content_bytes)?,
+            ),
+            Opcode::MaintenanceResult => InternodeMessageContent::MaintenanceResult(
+                MaintenanceResult::from_bytes(&content_bytes)?,
+            ),
+            Opcode::Paxos => {
+                InternodeMessageContent::Paxos(PaxosRequest::from_bytes(&content_bytes)?)
+            }
+            Opcode::PaxosReply => {
+                InternodeMessageContent::PaxosReply(PaxosReply::from_bytes(&content_bytes)?)
+            }
+            Opcode::StreamRequest => {
+                InternodeMessageContent::StreamRequest(StreamRequest::from_bytes(&content_bytes)?)
+            }
+            Opcode::StreamResult => {
+                InternodeMessageContent::StreamResult(StreamResult::from_bytes(&content_bytes)?)
+            }
+            Opcode::StreamChunk => {
+                InternodeMessageContent::StreamChunk(StreamChunk::from_bytes(&content_bytes)?)
+            }
+            Opcode::StreamChunkAck => {
+                InternodeMessageContent::StreamChunkAck(StreamChunkAck::from_bytes(&content_bytes)?)
+            }
+            Opcode::Startup => {
+                InternodeMessageContent::Startup(ConnectionStartup::from_bytes(&content_bytes)?)
+            }
         };
         let message = InternodeMessage {
             from: header.ip,
@@ -199,6 +359,64 @@ impl InternodeSerializable for InternodeMessage {
 
         Ok(message)
     }
+
+    // Returns the opcode of the message and its serialized content
+    fn opcode_and_content(&self) -> (Opcode, Vec<u8>) {
+        match &self.content {
+            InternodeMessageContent::Query(internode_query) => {
+                (Opcode::Query, internode_query.as_bytes())
+            }
+            InternodeMessageContent::Response(internode_response) => {
+                (Opcode::Response, internode_response.as_bytes())
+            }
+            InternodeMessageContent::Gossip(gossip_message) => {
+                (Opcode::Gossip, gossip_message.as_bytes())
+            }
+            InternodeMessageContent::Maintenance(request) => {
+                (Opcode::Maintenance, request.as_bytes())
+            }
+            InternodeMessageContent::MaintenanceResult(result) => {
+                (Opcode::MaintenanceResult, result.as_bytes())
+            }
+            InternodeMessageContent::Paxos(request) => (Opcode::Paxos, request.as_bytes()),
+            InternodeMessageContent::PaxosReply(reply) => (Opcode::PaxosReply, reply.as_bytes()),
+            InternodeMessageContent::StreamRequest(request) => {
+                (Opcode::StreamRequest, request.as_bytes())
+            }
+            InternodeMessageContent::StreamResult(result) => {
+                (Opcode::StreamResult, result.as_bytes())
+            }
+            InternodeMessageContent::StreamChunk(chunk) => (Opcode::StreamChunk, chunk.as_bytes()),
+            InternodeMessageContent::StreamChunkAck(ack) => {
+                (Opcode::StreamChunkAck, ack.as_bytes())
+            }
+            InternodeMessageContent::Startup(startup) => (Opcode::Startup, startup.as_bytes()),
+        }
+    }
+}
+
+/// An error that occurs when serializing or deserializing an internode message.
+#[derive(Debug)]
+pub struct InternodeMessageError;
+
+impl InternodeSerializable for InternodeMessage {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |       header      |
+    /// +----+----+----+----+
+    /// |head|  content...
+    /// +----+----+----+----+
+    /// ```
+    /// Serializes the message into a byte vector, uncompressed.
+    fn as_bytes(&self) -> Vec<u8> {
+        self.to_compressed_bytes(None)
+    }
+
+    /// Deserializes the message from a byte slice, which must not be compressed.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        Self::from_compressed_bytes(bytes, None)
+    }
 }
 
 #[cfg(test)]
@@ -222,6 +440,8 @@ mod tests {
             opcode: Opcode::Query,
             ip: Ipv4Addr::new(127, 0, 0, 1),
             length: 0,
+            compressed: false,
+            checksum: crc32(&[]),
         };
 
         let header_bytes = header.as_bytes();
@@ -230,6 +450,9 @@ mod tests {
         bytes.extend_from_slice(&header.ip.octets());
         bytes.extend_from_slice(&header.length.to_be_bytes());
         bytes.push(header.opcode as u8);
+        bytes.push(0);
+        bytes.extend_from_slice(&header.checksum.to_be_bytes());
+        bytes.extend_from_slice(&crc32(&bytes).to_be_bytes());
 
         assert_eq!(header_bytes, bytes);
     }
@@ -240,6 +463,8 @@ mod tests {
             opcode: Opcode::Query,
             ip: Ipv4Addr::new(127, 0, 0, 1),
             length: 0,
+            compressed: false,
+            checksum: crc32(&[]),
         };
 
         let header_bytes = header.as_bytes();
@@ -249,6 +474,22 @@ mod tests {
         assert_eq!(parsed_header, header);
     }
 
+    #[test]
+    fn test_header_with_a_corrupted_length_is_rejected() {
+        let header = InternodeHeader {
+            opcode: Opcode::Gossip,
+            ip: Ipv4Addr::new(127, 0, 0, 1),
+            length: 12,
+            compressed: false,
+            checksum: 0,
+        };
+
+        let mut header_bytes = header.as_bytes();
+        header_bytes[7] ^= 0x40;
+
+        assert!(InternodeHeader::from_bytes(&header_bytes).is_err());
+    }
+
     #[test]
     fn test_header_from_bytes_error() {
         let header_bytes = vec![0, 0, 0, 0, 0];
@@ -267,6 +508,10 @@ mod tests {
             replication: false,
             keyspace_name: "keyspace".to_string(),
             timestamp: 1,
+            correlation_id: "a1b2c3d4".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
         };
 
         let query_bytes = query.as_bytes();
@@ -284,6 +529,8 @@ mod tests {
             opcode: Opcode::Query,
             ip: Ipv4Addr::new(127, 0, 0, 1),
             length: query_bytes.len() as u32,
+            compressed: false,
+            checksum: crc32(&query_bytes),
         };
 
         bytes.extend_from_slice(&header.as_bytes());
@@ -301,6 +548,10 @@ mod tests {
             replication: false,
             keyspace_name: "keyspace".to_string(),
             timestamp: 1,
+            correlation_id: "a1b2c3d4".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
         };
 
         let message = InternodeMessage {
@@ -325,6 +576,9 @@ mod tests {
                 select_columns: vec!["column1".to_string(), "column2".to_string()],
                 values: vec![vec!["value1".to_string(), "value2".to_string()]],
             }),
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let response_bytes = response.as_bytes();
@@ -342,6 +596,8 @@ mod tests {
             opcode: Opcode::Response,
             ip: Ipv4Addr::new(127, 0, 0, 1),
             length: response_bytes.len() as u32,
+            compressed: false,
+            checksum: crc32(&response_bytes),
         };
 
         bytes.extend_from_slice(&header.as_bytes());
@@ -360,6 +616,9 @@ mod tests {
                 select_columns: vec!["column1".to_string(), "column2".to_string()],
                 values: vec![vec!["value1".to_string(), "value2".to_string()]],
             }),
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let message = InternodeMessage {
@@ -373,4 +632,103 @@ mod tests {
 
         assert_eq!(parsed_message, message);
     }
+
+    #[test]
+    fn test_all_messages_from_bytes() {
+        let from = Ipv4Addr::new(127, 0, 0, 2);
+        let request = InternodeMessage::new(
+            from,
+            InternodeMessageContent::StreamRequest(StreamRequest {
+                id: 1,
+                tokens: vec![42],
+                datacenter: "dc1".to_string(),
+                rack: "rack1".to_string(),
+            }),
+        );
+        let result = InternodeMessage::new(
+            from,
+            InternodeMessageContent::StreamResult(StreamResult {
+                id: 1,
+                outcome: Ok(3),
+            }),
+        );
+
+        let mut bytes = request.as_bytes();
+        bytes.extend(result.as_bytes());
+        // A message cut by the end of the read is not returned
+        bytes.extend(&request.as_bytes()[..HEADER_SIZE + 2]);
+
+        let whole = bytes.len() - (HEADER_SIZE + 2);
+        assert_eq!(
+            InternodeMessage::all_from_bytes(&bytes),
+            vec![request.clone(), result.clone()]
+        );
+        assert_eq!(
+            InternodeMessage::split_from_bytes(&bytes, None),
+            (vec![request, result], whole)
+        );
+    }
+
+    #[test]
+    fn test_corrupted_messages_are_dropped_without_the_ones_after_them() {
+        let from = Ipv4Addr::new(127, 0, 0, 2);
+        let result = |id| {
+            InternodeMessage::new(
+                from,
+                InternodeMessageContent::StreamResult(StreamResult { id, outcome: Ok(3) }),
+            )
+        };
+
+        // A flipped bit in the content of the first message, and one in the header of the second
+        let mut first = result(1).as_bytes();
+        first[HEADER_SIZE] ^= 0x01;
+        let mut second = result(2).as_bytes();
+        second[4] ^= 0x80;
+        assert!(InternodeMessage::from_bytes(&first).is_err());
+        assert!(InternodeMessage::from_bytes(&second).is_err());
+
+        let mut bytes = first;
+        bytes.extend(second);
+        bytes.extend(result(3).as_bytes());
+        bytes.extend(&result(4).as_bytes()[..HEADER_SIZE - 1]);
+
+        assert_eq!(
+            InternodeMessage::split_from_bytes(&bytes, None),
+            (vec![result(3)], bytes.len() - (HEADER_SIZE - 1))
+        );
+    }
+
+    #[test]
+    fn test_compressed_messages_follow_the_startup_of_their_connection() {
+        let from = Ipv4Addr::new(127, 0, 0, 2);
+        let startup = InternodeMessage::new(
+            from,
+            InternodeMessageContent::Startup(ConnectionStartup::new(Some(&Compression::Lz4))),
+        );
+        let rows = vec!["1,EZE,AEP;1700000000".to_string(); 100];
+        let chunk = InternodeMessage::new(
+            from,
+            InternodeMessageContent::StreamChunk(StreamChunk::new(
+                7, 0, "sky", "flights", false, rows,
+            )),
+        );
+
+        let compressed = chunk.to_compressed_bytes(Some(&Compression::Lz4));
+        assert!(compressed.len() < chunk.as_bytes().len());
+        // Without the compression of the connection the content can not be read
+        assert!(InternodeMessage::from_bytes(&compressed).is_err());
+
+        let mut bytes = startup.to_compressed_bytes(Some(&Compression::Lz4));
+        let startup_len = bytes.len();
+        bytes.extend(&compressed);
+        // The messages after the startup wait for the compression it negotiates
+        assert_eq!(
+            InternodeMessage::split_from_bytes(&bytes, None),
+            (vec![startup], startup_len)
+        );
+        assert_eq!(
+            InternodeMessage::split_from_bytes(&bytes[startup_len..], Some(&Compression::Lz4)),
+            (vec![chunk], compressed.len())
+        );
+    }
 }
@@ -3,13 +3,68 @@
 //! This module contains the definitions for the internode protocol messages, queries, and responses.
 //!
 //! The internode protocol is used to communicate between nodes in the cluster. It is a custom
-//! protocol that is used to send queries, responses, and gossip messages between nodes.
+//! protocol that is used to send queries, responses, gossip messages, maintenance requests,
+//! the Paxos rounds of lightweight transactions and the rows streamed to bootstrapping nodes
+//! between nodes.
+//!
+//! The content of a message may be compressed with the algorithm its connection negotiated in
+//! its first message (see `startup`), which its header flags.
+//!
+//! The header of every message carries the length and the CRC32 of its content, and ends with
+//! the CRC32 of the header itself. Messages corrupted on the way are dropped instead of being
+//! mis-parsed, and the ones after them are still read.
+
+use std::io::{Cursor, Read};
 
 use message::InternodeMessageError;
 
+pub mod decoder;
+pub mod maintenance;
 pub mod message;
+pub mod paxos;
 pub mod query;
 pub mod response;
+pub mod startup;
+pub mod statement;
+pub mod streaming;
+
+/// Reads the next `len` bytes of a message. A length past the end of the message is rejected
+/// before allocating for it, as it may come from a corrupted or hostile length prefix.
+pub(crate) fn read_bytes(
+    cursor: &mut Cursor<&[u8]>,
+    len: usize,
+) -> Result<Vec<u8>, InternodeMessageError> {
+    let remaining = (cursor.get_ref().len() as u64).saturating_sub(cursor.position());
+    if len as u64 > remaining {
+        return Err(InternodeMessageError);
+    }
+    let mut bytes = vec![0u8; len];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| InternodeMessageError)?;
+    Ok(bytes)
+}
+
+/// Writes `string` with its length as a 4 byte prefix.
+pub(crate) fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend(&(string.len() as u32).to_be_bytes());
+    bytes.extend(string.as_bytes());
+}
+
+/// Reads a 4 byte big endian number.
+pub(crate) fn read_u32(cursor: &mut Cursor<&[u8]>) -> Result<u32, InternodeMessageError> {
+    let mut bytes = [0u8; 4];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| InternodeMessageError)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Reads a string written by `write_string`.
+pub(crate) fn read_string(cursor: &mut Cursor<&[u8]>) -> Result<String, InternodeMessageError> {
+    let len = read_u32(cursor)? as usize;
+    String::from_utf8(read_bytes(cursor, len)?).map_err(|_| InternodeMessageError)
+}
 
 /// The InternodeSerializable trait is used to serialize and deserialize internode protocol messages.\
 /// This trait is implemented by all internode protocol messages, queries, and responses.\
@@ -0,0 +1,392 @@
+//! Messages of the Paxos rounds that run lightweight transactions (`INSERT ... IF NOT EXISTS` and
+//! `UPDATE ... IF`) on the replicas of a partition, and their replies.
+
+use super::{
+    message::InternodeMessageError, read_string, read_u32, write_string, InternodeSerializable,
+};
+use std::{
+    io::{Cursor, Read},
+    net::Ipv4Addr,
+};
+
+/// Identifies a Paxos round. A replica only takes part in the round with the highest ballot it
+/// has seen, so rounds started later (or by a node with a higher ip, at the same time) win.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Ballot {
+    /// Microseconds since the epoch when the round started.
+    pub micros: i64,
+    /// The node coordinating the round.
+    pub node: Ipv4Addr,
+}
+
+impl Ballot {
+    /// Returns the timestamp, in seconds, of the row written by the round.
+    pub fn timestamp(&self) -> i64 {
+        self.micros / 1_000_000
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.micros.to_be_bytes());
+        bytes.extend(&self.node.octets());
+    }
+
+    fn read(cursor: &mut Cursor<&[u8]>) -> Result<Self, InternodeMessageError> {
+        let mut micros = [0u8; 8];
+        cursor
+            .read_exact(&mut micros)
+            .map_err(|_| InternodeMessageError)?;
+        let mut node = [0u8; 4];
+        cursor
+            .read_exact(&mut node)
+            .map_err(|_| InternodeMessageError)?;
+        Ok(Ballot {
+            micros: i64::from_be_bytes(micros),
+            node: Ipv4Addr::from(node),
+        })
+    }
+}
+
+/// The phases of a Paxos round.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PaxosPhase {
+    /// Asks the replica to promise it takes no part in rounds with lower ballots, and for the row
+    /// the round has to check its condition on.
+    Prepare = 0x00,
+    /// Asks the replica to accept the row written by the round.
+    Propose = 0x01,
+    /// Asks the replica to write the accepted row.
+    Commit = 0x02,
+}
+
+impl PaxosPhase {
+    fn from_byte(byte: u8) -> Result<Self, InternodeMessageError> {
+        match byte {
+            0x00 => Ok(PaxosPhase::Prepare),
+            0x01 => Ok(PaxosPhase::Propose),
+            0x02 => Ok(PaxosPhase::Commit),
+            _ => Err(InternodeMessageError),
+        }
+    }
+}
+
+/// The row written by a Paxos round.
+///
+/// ### Fields
+/// - `row`: A value for every column of the table, separated by commas.
+/// - `ttl`: The `USING TTL` of the statement that writes the row, in seconds. `None` if the row
+///   expires after the `default_time_to_live` of its table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Proposal {
+    pub row: String,
+    pub ttl: Option<u64>,
+}
+
+impl Proposal {
+    // Writes the row, and the TTL as 8 bytes that are 0 without one. A missing proposal is
+    // written as an empty row
+    fn write(proposal: Option<&Self>, bytes: &mut Vec<u8>) {
+        let (row, ttl) = proposal.map_or(("", None), |proposal| (&proposal.row, proposal.ttl));
+        write_string(bytes, row);
+        bytes.extend(&ttl.unwrap_or_default().to_be_bytes());
+    }
+
+    fn read(cursor: &mut Cursor<&[u8]>) -> Result<Option<Self>, InternodeMessageError> {
+        let row = read_string(cursor)?;
+        let mut ttl = [0u8; 8];
+        cursor
+            .read_exact(&mut ttl)
+            .map_err(|_| InternodeMessageError)?;
+        let ttl = Some(u64::from_be_bytes(ttl)).filter(|ttl| *ttl != 0);
+        Ok(Some(Proposal { row, ttl }).filter(|proposal| !proposal.row.is_empty()))
+    }
+}
+
+/// A phase of a Paxos round sent to a replica of a partition.
+///
+/// ### Fields
+/// - `id`: Identifies the request in the node that sent it, which gets it back in the reply.
+/// - `phase`: The phase of the round.
+/// - `ballot`: The ballot of the round.
+/// - `keyspace` and `table`: The table of the row.
+/// - `partition`: The value of the partition key of the row, which decides its replicas.
+/// - `key`: The values of the primary key of the row, separated by commas.
+/// - `proposal`: The row written by the round, with its TTL. `None` in the `Prepare` phase.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PaxosRequest {
+    pub id: u32,
+    pub phase: PaxosPhase,
+    pub ballot: Ballot,
+    pub keyspace: String,
+    pub table: String,
+    pub partition: String,
+    pub key: String,
+    pub proposal: Option<Proposal>,
+}
+
+/// What a replica answered to a phase of a Paxos round.
+///
+/// ### Fields
+/// - `id`: The id of the request.
+/// - `ok`: Whether the replica promised, accepted or committed, depending on the phase.
+/// - `in_progress`: The proposal the replica accepted in an older round that was not committed,
+///   with the ballot of that round. Only sent in the `Prepare` phase.
+/// - `committed`: The ballot of the newest round the replica committed on the partition. Only
+///   sent in the `Prepare` phase.
+/// - `current`: The row of the replica with the key of the round, as a `values;timestamp` line.
+///   Only sent in the `Prepare` phase.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PaxosReply {
+    pub id: u32,
+    pub ok: bool,
+    pub in_progress: Option<(Ballot, Proposal)>,
+    pub committed: Option<Ballot>,
+    pub current: Option<String>,
+}
+
+/// Flags of a reply that tell which of its optional fields it has.
+const IN_PROGRESS_FLAG: u8 = 0x01;
+const COMMITTED_FLAG: u8 = 0x02;
+
+impl InternodeSerializable for PaxosRequest {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// | ph |    ballot    |
+    /// +----+----+----+----+
+    /// |  keyspace, table, |
+    /// +----+----+----+----+
+    /// | partition, key,   |
+    /// +----+----+----+----+
+    /// |     row, ttl      |
+    /// +----+----+----+----+
+    /// ```
+    /// The ballot takes 12 bytes, the TTL 8 and each string is preceded by its length. A request
+    /// without a proposal has an empty row, and a proposal without a TTL has a TTL of 0.
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.push(self.phase as u8);
+        self.ballot.write(&mut bytes);
+        write_string(&mut bytes, &self.keyspace);
+        write_string(&mut bytes, &self.table);
+        write_string(&mut bytes, &self.partition);
+        write_string(&mut bytes, &self.key);
+        Proposal::write(self.proposal.as_ref(), &mut bytes);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let mut phase = [0u8; 1];
+        cursor
+            .read_exact(&mut phase)
+            .map_err(|_| InternodeMessageError)?;
+        let phase = PaxosPhase::from_byte(phase[0])?;
+        let ballot = Ballot::read(&mut cursor)?;
+        let keyspace = read_string(&mut cursor)?;
+        let table = read_string(&mut cursor)?;
+        let partition = read_string(&mut cursor)?;
+        let key = read_string(&mut cursor)?;
+        let proposal = Proposal::read(&mut cursor)?;
+
+        Ok(PaxosRequest {
+            id,
+            phase,
+            ballot,
+            keyspace,
+            table,
+            partition,
+            key,
+            proposal,
+        })
+    }
+}
+
+impl InternodeSerializable for PaxosReply {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// | ok |flag| in_progress? |
+    /// +----+----+----+----+
+    /// |    committed?     |
+    /// +----+----+----+----+
+    /// |      current      |
+    /// +----+----+----+----+
+    /// ```
+    /// The ballot, row and TTL of the proposal in progress, and the ballot of the newest commit,
+    /// are only sent when their flag is set. A reply without a current row has an empty one.
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.push(self.ok as u8);
+
+        let mut flags = 0;
+        if self.in_progress.is_some() {
+            flags |= IN_PROGRESS_FLAG;
+        }
+        if self.committed.is_some() {
+            flags |= COMMITTED_FLAG;
+        }
+        bytes.push(flags);
+
+        if let Some((ballot, proposal)) = &self.in_progress {
+            ballot.write(&mut bytes);
+            Proposal::write(Some(proposal), &mut bytes);
+        }
+        if let Some(committed) = &self.committed {
+            committed.write(&mut bytes);
+        }
+        write_string(&mut bytes, self.current.as_deref().unwrap_or_default());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let mut flags = [0u8; 2];
+        cursor
+            .read_exact(&mut flags)
+            .map_err(|_| InternodeMessageError)?;
+        if flags[1] & !(IN_PROGRESS_FLAG | COMMITTED_FLAG) != 0 {
+            return Err(InternodeMessageError);
+        }
+        let in_progress = if flags[1] & IN_PROGRESS_FLAG != 0 {
+            let ballot = Ballot::read(&mut cursor)?;
+            Some((
+                ballot,
+                Proposal::read(&mut cursor)?.ok_or(InternodeMessageError)?,
+            ))
+        } else {
+            None
+        };
+        let committed = if flags[1] & COMMITTED_FLAG != 0 {
+            Some(Ballot::read(&mut cursor)?)
+        } else {
+            None
+        };
+        let current = Some(read_string(&mut cursor)?).filter(|row| !row.is_empty());
+
+        Ok(PaxosReply {
+            id,
+            ok: flags[0] != 0,
+            in_progress,
+            committed,
+            current,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ballot(micros: i64, last_octet: u8) -> Ballot {
+        Ballot {
+            micros,
+            node: Ipv4Addr::new(127, 0, 0, last_octet),
+        }
+    }
+
+    #[test]
+    fn test_ballots_are_ordered_by_time_then_node() {
+        assert!(ballot(2, 1) > ballot(1, 9));
+        assert!(ballot(1, 2) > ballot(1, 1));
+        assert_eq!(ballot(3_500_000, 1).timestamp(), 3);
+    }
+
+    #[test]
+    fn test_paxos_request_round_trip() {
+        for (phase, proposal) in [
+            (PaxosPhase::Prepare, None),
+            (
+                PaxosPhase::Propose,
+                Some(Proposal {
+                    row: "AR1,EZE,landed".to_string(),
+                    ttl: Some(3600),
+                }),
+            ),
+            (
+                PaxosPhase::Commit,
+                Some(Proposal {
+                    row: "AR1,EZE,landed".to_string(),
+                    ttl: None,
+                }),
+            ),
+        ] {
+            let request = PaxosRequest {
+                id: 4,
+                phase,
+                ballot: ballot(1_700_000_000_000_000, 2),
+                keyspace: "sky".to_string(),
+                table: "flights".to_string(),
+                partition: "AR1".to_string(),
+                key: "AR1".to_string(),
+                proposal,
+            };
+            let parsed = PaxosRequest::from_bytes(&request.as_bytes()).unwrap();
+            assert_eq!(parsed, request);
+        }
+    }
+
+    #[test]
+    fn test_paxos_request_longer_than_its_message_is_rejected() {
+        let request = PaxosRequest {
+            id: 4,
+            phase: PaxosPhase::Propose,
+            ballot: ballot(1_700_000_000_000_000, 2),
+            keyspace: "sky".to_string(),
+            table: "flights".to_string(),
+            partition: "AR1".to_string(),
+            key: "AR1".to_string(),
+            proposal: Some(Proposal {
+                row: "AR1,EZE,landed".to_string(),
+                ttl: None,
+            }),
+        };
+        let mut bytes = request.as_bytes();
+        // The length of the keyspace is the one before its bytes
+        let keyspace = bytes
+            .windows(3)
+            .position(|window| window == b"sky")
+            .unwrap();
+        bytes[keyspace - 4..keyspace].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(PaxosRequest::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_paxos_reply_round_trip() {
+        for (in_progress, committed, current) in [
+            (None, None, None),
+            (None, Some(ballot(4, 1)), None),
+            (
+                Some((
+                    ballot(9, 3),
+                    Proposal {
+                        row: "AR1,EZE,boarding".to_string(),
+                        ttl: Some(60),
+                    },
+                )),
+                Some(ballot(7, 2)),
+                Some("AR1,EZE,landed;1700000000".to_string()),
+            ),
+        ] {
+            let reply = PaxosReply {
+                id: 8,
+                ok: in_progress.is_none(),
+                in_progress,
+                committed,
+                current,
+            };
+            let parsed = PaxosReply::from_bytes(&reply.as_bytes()).unwrap();
+            assert_eq!(parsed, reply);
+        }
+        assert!(PaxosReply::from_bytes(&[0, 0, 0, 1, 1, 0x07]).is_err());
+    }
+}
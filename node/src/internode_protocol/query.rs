@@ -3,24 +3,40 @@
 //! This module contains the definition of the `InternodeQuery` struct, which represents a query
 //! message sent by a coordinator node to other nodes in the cluster. The query message contains
 //! information about the query to be executed, such as the query string, the client ID, and the
-//! keyspace name.
+//! keyspace name. Data statements also carry their structured form, so the receiver does not
+//! have to parse the query string again.
+//!
+//! Queries of a client also carry the time their coordinator keeps waiting for them. It is sent
+//! as a remaining duration rather than as an instant, since the clocks of the nodes may differ,
+//! and a replica that can not start executing the query in time answers with a timeout instead.
+//!
+//! Reads sent to several replicas ask all of them but one for a digest of their rows instead of
+//! the rows themselves (see `InternodeResponse::digest`).
 
 use std::io::{Cursor, Read};
+use std::time::{Duration, Instant};
 
-use super::{message::InternodeMessageError, InternodeSerializable};
-use query_creator::{NeedsKeyspace, NeedsTable, QueryCreator};
+use super::{
+    message::InternodeMessageError, read_bytes, statement::InternodeStatement,
+    InternodeSerializable,
+};
+use query_creator::{errors::CQLError, NeedsKeyspace, NeedsTable, Query, QueryCreator};
 /// A query sent by a coordinator node to other nodes in the cluster.
 ///
 /// ### Fields
-/// - `query_string`: The CQL query string.
+/// - `query_string`: The CQL query string, empty if the query is sent as a structured `statement`.
 /// - `open_query_id`: The `id` of the query to be identified by the open queries handler.
 /// - `client_id`: The client that owns the query in this node.
 /// - `replication`: This query should be executed over the replications stored by the node.
 /// - `keyspace_name`: Keyspace on which the query acts.
 /// - `timestamp`: The timestamp when the coordinator node received the query.
+/// - `correlation_id`: Identifies the client query this message belongs to in the logs.
+/// - `statement`: The already parsed statement, if it is a data statement.
+/// - `time_left`: How long the coordinator waits for the answer since the query was sent.
+/// - `digest`: Whether the node answers a `SELECT` with a digest of its rows instead of the rows.
 #[derive(Debug, PartialEq, Clone)]
 pub struct InternodeQuery {
-    /// The CQL query string.
+    /// The CQL query string, empty if the query is sent as a structured `statement`.
     pub query_string: String,
     /// The `id` of the query to be identified by the open queries handler.
     pub open_query_id: u32,
@@ -33,37 +49,63 @@ pub struct InternodeQuery {
     pub keyspace_name: String,
     /// The timestamp when the coordinator node received the query.
     pub timestamp: i64,
+    /// Identifies the client query this message belongs to in the logs of every node.
+    /// Empty for messages not triggered by a client query (e.g. redistribution).
+    pub correlation_id: String,
+    /// The already parsed statement, so the receiver does not parse `query_string` again.
+    /// `None` for schema changes and for messages sent by nodes that only send CQL strings, in
+    /// which case `query_string` is used.
+    pub statement: Option<InternodeStatement>,
+    /// How long the coordinator waits for the answer since the query was sent, after which the
+    /// client already got a timeout. `None` for queries with no deadline (schema changes, hints)
+    /// and for messages sent by nodes that do not propagate deadlines.
+    pub time_left: Option<Duration>,
+    /// Whether the node answers a `SELECT` with a digest of the rows it read instead of the rows,
+    /// which the coordinator compares with the rows of another replica.
+    pub digest: bool,
+}
+
+impl InternodeQuery {
+    /// Returns the query to execute, taken from the structured statement if there is one or
+    /// parsed from the query string otherwise.
+    pub fn to_query(&self) -> Result<Query, CQLError> {
+        match &self.statement {
+            Some(statement) => Ok(statement.clone().into()),
+            None => QueryCreator::new().handle_query(self.query_string.clone()),
+        }
+    }
+
+    /// Returns the CQL of the query, for the logs: its query string, or the CQL of its statement
+    /// if it is sent as a structured one.
+    pub fn cql(&self) -> String {
+        match &self.statement {
+            Some(statement) => statement.to_cql(),
+            None => self.query_string.clone(),
+        }
+    }
+
+    /// Returns the instant the coordinator stops waiting for the query, for a query received at
+    /// `received`.
+    pub fn deadline(&self, received: Instant) -> Option<Instant> {
+        self.time_left.map(|time_left| received + time_left)
+    }
 }
 
 impl NeedsKeyspace for InternodeQuery {
     fn needs_keyspace(&self) -> bool {
-        // Crear una instancia de QueryCreator con el query_string de InternodeQuery
-        let query_creator = QueryCreator::new();
-
-        // Manejar el resultado de handle_query (Result<Query, Error>)
-        match query_creator.handle_query(self.query_string.clone()) {
-            Ok(query) => query.needs_keyspace(), // Llamar al trait NeedsKeyspace implementado para Query
-            Err(_) => {
-                // En caso de error, se puede asumir que no se necesita keyspace o manejarlo de otro modo
-                false
-            }
-        }
+        // If the query cannot be parsed it is assumed that it does not need a keyspace
+        self.to_query()
+            .map(|query| query.needs_keyspace())
+            .unwrap_or(false)
     }
 }
 
 impl NeedsTable for InternodeQuery {
     fn needs_table(&self) -> bool {
-        // Crear una instancia de QueryCreator con el query_string de InternodeQuery
-        let query_creator = QueryCreator::new();
-
-        // Manejar el resultado de handle_query (Result<Query, Error>)
-        match query_creator.handle_query(self.query_string.clone()) {
-            Ok(query) => query.needs_table(), // Llamar al trait NeedsKeyspace implementado para Query
-            Err(_) => {
-                // En caso de error, se puede asumir que no se necesita keyspace o manejarlo de otro modo
-                false
-            }
-        }
+        // If the query cannot be parsed it is assumed that it does not need a table
+        self.to_query()
+            .map(|query| query.needs_table())
+            .unwrap_or(false)
     }
 }
 
@@ -91,7 +133,29 @@ impl InternodeSerializable for InternodeQuery {
     /// |        ...        |
     /// |    query_string   |
     /// +----+----+----+----+
+    /// |correlation_id_len |
+    /// +----+----+----+----+
+    /// |   correlation_id  |
+    /// |        ...        |
+    /// |   correlation_id  |
+    /// +----+----+----+----+
+    /// |   statement_len   |
+    /// +----+----+----+----+
+    /// |     statement     |
+    /// |        ...        |
+    /// |     statement     |
+    /// +----+----+----+----+
+    /// |     time_left     |
+    /// +----+----+----+----+
+    /// |dig |
+    /// +----+
     /// ```
+    /// A `statement_len` of 0 means there is no structured statement. Queries with one are sent
+    /// with an empty `query_string`, so the statement is not sent twice. Messages that end right
+    /// after the `correlation_id` (sent by nodes that only send CQL strings) are read the same way.
+    /// `time_left` is in milliseconds, and 0 means there is no deadline; messages that end right
+    /// after the statement have none either. Messages that end right after `time_left` ask for the
+    /// rows, not for a digest.
     /// Serializes the `InternodeQuery` struct into a byte vector.
     fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -110,6 +174,26 @@ impl InternodeSerializable for InternodeQuery {
         bytes.extend(&query_string_len.to_be_bytes());
         bytes.extend(self.query_string.as_bytes());
 
+        let correlation_id_len = self.correlation_id.len() as u32;
+        bytes.extend(&correlation_id_len.to_be_bytes());
+        bytes.extend(self.correlation_id.as_bytes());
+
+        let statement_bytes = self
+            .statement
+            .as_ref()
+            .map(|statement| statement.as_bytes())
+            .unwrap_or_default();
+        bytes.extend(&(statement_bytes.len() as u32).to_be_bytes());
+        bytes.extend(statement_bytes);
+
+        // A query with less than a millisecond left still has a deadline
+        let time_left = self.time_left.map_or(0, |time_left| {
+            time_left.as_millis().clamp(1, u32::MAX as u128) as u32
+        });
+        bytes.extend(&time_left.to_be_bytes());
+
+        bytes.push(self.digest as u8);
+
         bytes
     }
 
@@ -170,6 +254,46 @@ impl InternodeSerializable for InternodeQuery {
         let query_string =
             String::from_utf8(query_string_bytes).map_err(|_| InternodeMessageError)?;
 
+        let mut correlation_id_len_bytes = [0u8; 4];
+        cursor
+            .read_exact(&mut correlation_id_len_bytes)
+            .map_err(|_| InternodeMessageError)?;
+        let correlation_id_len = u32::from_be_bytes(correlation_id_len_bytes) as usize;
+
+        let mut correlation_id_bytes = vec![0u8; correlation_id_len];
+        cursor
+            .read_exact(&mut correlation_id_bytes)
+            .map_err(|_| InternodeMessageError)?;
+        let correlation_id =
+            String::from_utf8(correlation_id_bytes).map_err(|_| InternodeMessageError)?;
+
+        let mut statement = None;
+        if (cursor.position() as usize) < bytes.len() {
+            let mut statement_len_bytes = [0u8; 4];
+            cursor
+                .read_exact(&mut statement_len_bytes)
+                .map_err(|_| InternodeMessageError)?;
+            let statement_len = u32::from_be_bytes(statement_len_bytes) as usize;
+
+            if statement_len > 0 {
+                let statement_bytes = read_bytes(&mut cursor, statement_len)?;
+                statement = Some(InternodeStatement::from_bytes(&statement_bytes)?);
+            }
+        }
+
+        let mut time_left = None;
+        if (cursor.position() as usize) < bytes.len() {
+            let mut time_left_bytes = [0u8; 4];
+            cursor
+                .read_exact(&mut time_left_bytes)
+                .map_err(|_| InternodeMessageError)?;
+            let millis = u32::from_be_bytes(time_left_bytes);
+            time_left = (millis > 0).then(|| Duration::from_millis(millis as u64));
+        }
+
+        let mut digest_byte = [0u8; 1];
+        let digest = cursor.read_exact(&mut digest_byte).is_ok() && digest_byte[0] != 0;
+
         Ok(InternodeQuery {
             query_string,
             open_query_id,
@@ -177,6 +301,10 @@ impl InternodeSerializable for InternodeQuery {
             replication,
             keyspace_name,
             timestamp,
+            correlation_id,
+            statement,
+            time_left,
+            digest,
         })
     }
 }
@@ -194,6 +322,10 @@ mod tests {
             replication: false,
             keyspace_name: "keyspace".to_string(),
             timestamp: 1,
+            correlation_id: "a1b2c3d4".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
         };
 
         let query_bytes = query.as_bytes();
@@ -214,6 +346,14 @@ mod tests {
         bytes.extend(&query_string_len.to_be_bytes());
         bytes.extend(query.query_string.as_bytes());
 
+        let correlation_id_len = query.correlation_id.len() as u32;
+        bytes.extend(&correlation_id_len.to_be_bytes());
+        bytes.extend(query.correlation_id.as_bytes());
+
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes.push(0);
+
         assert_eq!(query_bytes, bytes);
     }
 
@@ -226,6 +366,10 @@ mod tests {
             replication: false,
             keyspace_name: "keyspace".to_string(),
             timestamp: 1,
+            correlation_id: "a1b2c3d4".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
         };
 
         let query_bytes = query.as_bytes();
@@ -234,4 +378,84 @@ mod tests {
 
         assert_eq!(parsed_query, query);
     }
+
+    #[test]
+    fn test_query_with_statement_from_bytes() {
+        let query_string = "SELECT * FROM something WHERE id = 1".to_string();
+        let statement = InternodeStatement::from_query(
+            &QueryCreator::new().handle_query(query_string).unwrap(),
+        );
+        let query = InternodeQuery {
+            query_string: String::new(),
+            open_query_id: 1,
+            client_id: 1,
+            replication: true,
+            keyspace_name: "keyspace".to_string(),
+            timestamp: 1,
+            correlation_id: "a1b2c3d4".to_string(),
+            statement,
+            time_left: Some(Duration::from_millis(1500)),
+            digest: true,
+        };
+
+        let parsed_query = InternodeQuery::from_bytes(&query.as_bytes()).unwrap();
+
+        assert!(parsed_query.statement.is_some());
+        assert_eq!(parsed_query, query);
+        // The query is only sent once, as its statement
+        assert!(!String::from_utf8_lossy(&query.as_bytes()).contains("SELECT"));
+        assert_eq!(parsed_query.cql(), parsed_query.statement.unwrap().to_cql());
+    }
+
+    #[test]
+    fn test_query_without_statement_section_from_bytes() {
+        let query = InternodeQuery {
+            query_string: "SELECT * FROM something".to_string(),
+            open_query_id: 1,
+            client_id: 1,
+            replication: false,
+            keyspace_name: "keyspace".to_string(),
+            timestamp: 1,
+            correlation_id: "".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
+        };
+
+        // Nodes that only send CQL strings end the message right after the correlation id
+        let bytes = query.as_bytes();
+        let parsed_query = InternodeQuery::from_bytes(&bytes[..bytes.len() - 9]).unwrap();
+
+        assert_eq!(parsed_query, query);
+        assert!(matches!(parsed_query.to_query(), Ok(Query::Select(_))));
+    }
+
+    #[test]
+    fn test_query_time_left() {
+        let mut query = InternodeQuery {
+            query_string: "INSERT INTO flights (id) VALUES (1)".to_string(),
+            open_query_id: 1,
+            client_id: 1,
+            replication: false,
+            keyspace_name: "keyspace".to_string(),
+            timestamp: 1,
+            correlation_id: "".to_string(),
+            statement: None,
+            time_left: Some(Duration::from_micros(200)),
+            digest: false,
+        };
+
+        // Less than a millisecond left is not confused with no deadline
+        let parsed_query = InternodeQuery::from_bytes(&query.as_bytes()).unwrap();
+        assert_eq!(parsed_query.time_left, Some(Duration::from_millis(1)));
+
+        let received = Instant::now();
+        query.time_left = Some(Duration::from_secs(2));
+        assert_eq!(
+            query.deadline(received),
+            Some(received + Duration::from_secs(2))
+        );
+        query.time_left = None;
+        assert_eq!(query.deadline(received), None);
+    }
 }
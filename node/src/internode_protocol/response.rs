@@ -1,14 +1,84 @@
 //! TODO: Add documentation
 
 use super::{message::InternodeMessageError, InternodeSerializable};
-use std::io::{Cursor, Read};
+use crate::errors::NodeError;
+use aws_lc_rs::digest::{digest, SHA256};
+use query_creator::errors::CQLError;
+use std::{
+    fmt,
+    io::{Cursor, Read},
+};
 
 /// The status of a response sent by a node in response of a coordinator query.
-/// It can be either `Ok` or `Error`.
-#[derive(Debug, PartialEq, Clone)]
+///
+/// Besides `Ok` and a generic `Error`, a node can tell the coordinator why the query failed so
+/// it can react accordingly (for example retrying on another replica).
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum InternodeResponseStatus {
     Ok = 0x00,
+    /// The query failed for a reason not covered by the other statuses.
     Error = 0x01,
+    /// The node does not own the partition the query is about.
+    NotOwner = 0x02,
+    /// The node does not know the keyspace or table of the query, or knows a different version.
+    SchemaMismatch = 0x03,
+    /// The node is too busy to execute the query.
+    Overloaded = 0x04,
+    /// The node could not execute the query in time.
+    Timeout = 0x05,
+    /// The node failed to read or write its storage.
+    StorageError = 0x06,
+}
+
+impl InternodeResponseStatus {
+    fn from_byte(byte: u8) -> Result<Self, InternodeMessageError> {
+        match byte {
+            0x00 => Ok(InternodeResponseStatus::Ok),
+            0x01 => Ok(InternodeResponseStatus::Error),
+            0x02 => Ok(InternodeResponseStatus::NotOwner),
+            0x03 => Ok(InternodeResponseStatus::SchemaMismatch),
+            0x04 => Ok(InternodeResponseStatus::Overloaded),
+            0x05 => Ok(InternodeResponseStatus::Timeout),
+            0x06 => Ok(InternodeResponseStatus::StorageError),
+            _ => Err(InternodeMessageError),
+        }
+    }
+}
+
+impl fmt::Display for InternodeResponseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InternodeResponseStatus::Ok => "OK",
+            InternodeResponseStatus::Error => "ERROR",
+            InternodeResponseStatus::NotOwner => "NOT_OWNER",
+            InternodeResponseStatus::SchemaMismatch => "SCHEMA_MISMATCH",
+            InternodeResponseStatus::Overloaded => "OVERLOADED",
+            InternodeResponseStatus::Timeout => "TIMEOUT",
+            InternodeResponseStatus::StorageError => "STORAGE_ERROR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl From<&NodeError> for InternodeResponseStatus {
+    /// The status a node answers with when executing a query failed with `error`.
+    fn from(error: &NodeError) -> Self {
+        match error {
+            NodeError::StorageEngineError(_) | NodeError::IoError(_) => {
+                InternodeResponseStatus::StorageError
+            }
+            NodeError::KeyspaceError
+            | NodeError::SchemaError(_)
+            | NodeError::CQLError(CQLError::InvalidTable)
+            | NodeError::CQLError(CQLError::NoActualKeyspaceError)
+            | NodeError::CQLError(CQLError::TableAlreadyExist) => {
+                InternodeResponseStatus::SchemaMismatch
+            }
+            NodeError::NotOwner => InternodeResponseStatus::NotOwner,
+            NodeError::DeadlineExceeded => InternodeResponseStatus::Timeout,
+            _ => InternodeResponseStatus::Error,
+        }
+    }
 }
 
 /// The content of a response sent by a node in response of a coordinator query.
@@ -24,6 +94,21 @@ pub struct InternodeResponseContent {
     pub values: Vec<Vec<String>>,
 }
 
+impl InternodeResponseContent {
+    /// Returns the digest of the content, equal for two replicas only if they answered with the same
+    /// rows, in the same order and with the same stamps.
+    ///
+    /// # Notes
+    /// - It is the first 16 bytes of the SHA-256 of the serialized content, so nodes of different
+    ///   builds agree on it.
+    pub fn digest(&self) -> u128 {
+        let hash = digest(&SHA256, &self.as_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hash.as_ref()[..16]);
+        u128::from_be_bytes(bytes)
+    }
+}
+
 impl InternodeSerializable for InternodeResponseContent {
     /// ```md
     /// 0    8    16   24   32
@@ -206,20 +291,44 @@ impl InternodeSerializable for InternodeResponseContent {
     }
 }
 
+/// The versions of the schema and the ring a node had when it executed a query, so the
+/// coordinator can tell whether the node is lagging behind it.
+///
+/// ### Fields
+/// - `schema_version`: The timestamp of the latest schema change known by the node.
+/// - `ring_digest`: The digest of the tokens of the ring known by the node (see
+///   `Partitioner::ring_digest`). It only tells whether two rings differ, not which one is newer.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GenerationStamp {
+    pub schema_version: i64,
+    pub ring_digest: u64,
+}
+
 /// A response sent by a node in response of a coordinator query.
 ///
 /// ### Fields
 /// - `open_query_id`: The `id` of the query to be identified by the open queries handler.
 /// - `status`: If the query was successful.
 /// - `content`: The response content, if any (for example a `SELECT`). It can be `None`.
+/// - `detail`: A description of what went wrong, if the query failed. It can be `None`.
+/// - `generation`: The versions of the schema and the ring the query was executed with. It can be
+///   `None`.
+/// - `digest`: The digest of the content, sent instead of it when the coordinator asked for one. It
+///   can be `None`.
 #[derive(Debug, PartialEq, Clone)]
 pub struct InternodeResponse {
     /// The `id` of the query to be identified by the open queries handler.
     pub open_query_id: u32,
-    /// If the query was successful.
+    /// If the query was successful, or why it failed.
     pub status: InternodeResponseStatus,
     /// The response content, if any (for example a `SELECT`).
     pub content: Option<InternodeResponseContent>,
+    /// A description of what went wrong, if the query failed.
+    pub detail: Option<String>,
+    /// The versions of the schema and the ring the query was executed with.
+    pub generation: Option<GenerationStamp>,
+    /// The digest of the content, sent instead of it when the coordinator asked for one.
+    pub digest: Option<u128>,
 }
 
 impl InternodeResponse {
@@ -233,8 +342,39 @@ impl InternodeResponse {
             open_query_id,
             status,
             content,
+            detail: None,
+            generation: None,
+            digest: None,
         }
     }
+
+    /// Creates the response of a node that failed to execute a query with `error`.
+    pub fn from_error(open_query_id: u32, error: &NodeError) -> Self {
+        Self {
+            open_query_id,
+            status: InternodeResponseStatus::from(error),
+            content: None,
+            detail: Some(error.to_string()),
+            generation: None,
+            digest: None,
+        }
+    }
+
+    /// Returns the response stamped with the versions of the schema and the ring of the node
+    /// that executed the query.
+    pub fn with_generation(mut self, generation: GenerationStamp) -> Self {
+        self.generation = Some(generation);
+        self
+    }
+
+    /// Returns the response with the digest of its content instead of the content, as sent to a
+    /// coordinator that asked for a digest. Responses without content are left as they are.
+    pub fn into_digest(mut self) -> Self {
+        if let Some(content) = self.content.take() {
+            self.digest = Some(content.digest());
+        }
+        self
+    }
 }
 
 impl InternodeSerializable for InternodeResponse {
@@ -249,7 +389,27 @@ impl InternodeSerializable for InternodeResponse {
     /// |        ...        |
     /// |      content      |
     /// +----+----+----+----+
+    /// |detail_len|detail  |
+    /// +----+----+----+----+
+    /// |      detail       |
+    /// +----+----+----+----+
+    /// |  schema_version   |
+    /// |                   |
+    /// +----+----+----+----+
+    /// |    ring_digest    |
+    /// |                   |
+    /// +----+----+----+----+
+    /// |      digest       |
+    /// |                   |
+    /// |                   |
+    /// |                   |
+    /// +----+----+----+----+
     /// ```
+    /// The detail is only written when there is one, or when it is followed by a generation stamp
+    /// (with a length of 0 if there is no detail), so responses without them keep the layout of
+    /// the nodes that do not know about them. The same goes for the generation stamp, written with
+    /// zeros before a digest if the response has none.
+    ///
     /// Serializes the `InternodeResponse` into a `Vec<u8>`.
     fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -258,11 +418,7 @@ impl InternodeSerializable for InternodeResponse {
         bytes.extend(&self.open_query_id.to_be_bytes());
 
         // Serializa el estado
-        let status_byte = match self.status {
-            InternodeResponseStatus::Ok => 0x00,
-            InternodeResponseStatus::Error => 0x01,
-        };
-        bytes.push(status_byte);
+        bytes.push(self.status as u8);
 
         // Serializa el contenido
         if let Some(content) = &self.content {
@@ -273,6 +429,26 @@ impl InternodeSerializable for InternodeResponse {
             bytes.extend(0u16.to_be_bytes()); // Longitud del contenido = 0
         }
 
+        // Serializa el detalle, solo si lo hay o si le sigue la generación
+        if self.detail.is_some() || self.generation.is_some() || self.digest.is_some() {
+            let detail = self.detail.as_deref().unwrap_or_default();
+            bytes.extend((detail.len() as u16).to_be_bytes());
+            bytes.extend(detail.as_bytes());
+        }
+
+        if self.generation.is_some() || self.digest.is_some() {
+            let generation = self.generation.unwrap_or(GenerationStamp {
+                schema_version: 0,
+                ring_digest: 0,
+            });
+            bytes.extend(generation.schema_version.to_be_bytes());
+            bytes.extend(generation.ring_digest.to_be_bytes());
+        }
+
+        if let Some(digest) = self.digest {
+            bytes.extend(digest.to_be_bytes());
+        }
+
         bytes
     }
 
@@ -291,11 +467,7 @@ impl InternodeSerializable for InternodeResponse {
         cursor
             .read_exact(&mut status_byte)
             .map_err(|_| InternodeMessageError)?;
-        let status = match status_byte[0] {
-            0x00 => InternodeResponseStatus::Ok,
-            0x01 => InternodeResponseStatus::Error,
-            _ => return Err(InternodeMessageError),
-        };
+        let status = InternodeResponseStatus::from_byte(status_byte[0])?;
 
         // Deserializa el contenido
         let mut content_len_bytes = [0u8; 2];
@@ -317,10 +489,49 @@ impl InternodeSerializable for InternodeResponse {
             )
         };
 
+        // Deserializa el detalle, si lo hay
+        let mut detail_len_bytes = [0u8; 2];
+        let detail = if cursor.read_exact(&mut detail_len_bytes).is_ok() {
+            let mut detail_bytes = vec![0u8; u16::from_be_bytes(detail_len_bytes) as usize];
+            cursor
+                .read_exact(&mut detail_bytes)
+                .map_err(|_| InternodeMessageError)?;
+            let detail = String::from_utf8(detail_bytes).map_err(|_| InternodeMessageError)?;
+            // An empty detail is only written before a generation stamp
+            Some(detail).filter(|detail| !detail.is_empty())
+        } else {
+            None
+        };
+
+        // Deserializa la generación, si la hay
+        let mut schema_version_bytes = [0u8; 8];
+        let generation = if cursor.read_exact(&mut schema_version_bytes).is_ok() {
+            let mut ring_digest_bytes = [0u8; 8];
+            cursor
+                .read_exact(&mut ring_digest_bytes)
+                .map_err(|_| InternodeMessageError)?;
+            Some(GenerationStamp {
+                schema_version: i64::from_be_bytes(schema_version_bytes),
+                ring_digest: u64::from_be_bytes(ring_digest_bytes),
+            })
+        } else {
+            None
+        };
+
+        // Deserializa el digest, si lo hay
+        let mut digest_bytes = [0u8; 16];
+        let digest = cursor
+            .read_exact(&mut digest_bytes)
+            .is_ok()
+            .then(|| u128::from_be_bytes(digest_bytes));
+
         Ok(InternodeResponse {
             open_query_id,
             status,
             content,
+            detail,
+            generation,
+            digest,
         })
     }
 }
@@ -340,6 +551,9 @@ mod tests {
                 select_columns: vec!["column1".to_string(), "column2".to_string()],
                 values: vec![vec!["value1".to_string(), "value2".to_string()]],
             }),
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let response_bytes = response.as_bytes();
@@ -348,11 +562,7 @@ mod tests {
 
         bytes.extend(response.open_query_id.to_be_bytes());
 
-        let status_byte = match response.status {
-            InternodeResponseStatus::Ok => 0x00,
-            InternodeResponseStatus::Error => 0x01,
-        };
-        bytes.push(status_byte);
+        bytes.push(response.status as u8);
 
         let content_bytes = if let Some(content) = response.content {
             Some(content.as_bytes())
@@ -380,6 +590,9 @@ mod tests {
                 select_columns: vec!["column1".to_string(), "column2".to_string()],
                 values: vec![vec!["value1".to_string(), "value2".to_string()]],
             }),
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let response_bytes = response.as_bytes();
@@ -465,6 +678,9 @@ mod tests {
             open_query_id: 1,
             status: InternodeResponseStatus::Ok,
             content: None,
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let response_bytes = response.as_bytes();
@@ -473,11 +689,7 @@ mod tests {
 
         bytes.extend(response.open_query_id.to_be_bytes());
 
-        let status_byte = match response.status {
-            InternodeResponseStatus::Ok => 0x00,
-            InternodeResponseStatus::Error => 0x01,
-        };
-        bytes.push(status_byte);
+        bytes.push(response.status as u8);
 
         // No content
         bytes.extend(0u16.to_be_bytes()); // Content length = 0
@@ -491,6 +703,27 @@ mod tests {
             open_query_id: 1,
             status: InternodeResponseStatus::Ok,
             content: None,
+            detail: None,
+            generation: None,
+            digest: None,
+        };
+
+        let response_bytes = response.as_bytes();
+
+        let parsed_response = InternodeResponse::from_bytes(&response_bytes).unwrap();
+
+        assert_eq!(parsed_response, response);
+    }
+
+    #[test]
+    fn test_response_with_detail_from_bytes() {
+        let response = InternodeResponse {
+            open_query_id: 7,
+            status: InternodeResponseStatus::SchemaMismatch,
+            content: None,
+            detail: Some("Keyspace error".to_string()),
+            generation: None,
+            digest: None,
         };
 
         let response_bytes = response.as_bytes();
@@ -499,4 +732,98 @@ mod tests {
 
         assert_eq!(parsed_response, response);
     }
+
+    #[test]
+    fn test_response_with_generation_from_bytes() {
+        let generation = GenerationStamp {
+            schema_version: 1733000000000,
+            ring_digest: 42,
+        };
+        let response = InternodeResponse::new(7, InternodeResponseStatus::Ok, None)
+            .with_generation(generation);
+
+        let response_bytes = response.as_bytes();
+        // The stamp follows an empty detail
+        assert_eq!(response_bytes.len(), 4 + 1 + 2 + 2 + 16);
+        assert_eq!(
+            InternodeResponse::from_bytes(&response_bytes).unwrap(),
+            response
+        );
+
+        let response =
+            InternodeResponse::from_error(7, &NodeError::KeyspaceError).with_generation(generation);
+        assert_eq!(
+            InternodeResponse::from_bytes(&response.as_bytes()).unwrap(),
+            response
+        );
+    }
+
+    #[test]
+    fn test_response_with_unknown_status_from_bytes() {
+        let mut response_bytes =
+            InternodeResponse::new(1, InternodeResponseStatus::Ok, None).as_bytes();
+        response_bytes[4] = 0x7F;
+
+        assert!(InternodeResponse::from_bytes(&response_bytes).is_err());
+    }
+
+    #[test]
+    fn test_status_from_node_error() {
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::KeyspaceError),
+            InternodeResponseStatus::SchemaMismatch
+        );
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::CQLError(CQLError::InvalidTable)),
+            InternodeResponseStatus::SchemaMismatch
+        );
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::IoError(std::io::Error::other("disk"))),
+            InternodeResponseStatus::StorageError
+        );
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::NotOwner),
+            InternodeResponseStatus::NotOwner
+        );
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::DeadlineExceeded),
+            InternodeResponseStatus::Timeout
+        );
+        assert_eq!(
+            InternodeResponseStatus::from(&NodeError::OtherError),
+            InternodeResponseStatus::Error
+        );
+
+        let response = InternodeResponse::from_error(3, &NodeError::KeyspaceError);
+        assert_eq!(response.status, InternodeResponseStatus::SchemaMismatch);
+        assert_eq!(response.detail, Some("Keyspace error".to_string()));
+    }
+
+    #[test]
+    fn test_digest_responses_from_bytes() {
+        let content = |value: &str| InternodeResponseContent {
+            columns: vec!["id".to_string()],
+            select_columns: vec!["id".to_string()],
+            values: vec![vec![value.to_string(), "1733000000000".to_string()]],
+        };
+        let generation = GenerationStamp {
+            schema_version: 1733000000000,
+            ring_digest: 42,
+        };
+        let response = InternodeResponse::new(7, InternodeResponseStatus::Ok, Some(content("1")))
+            .with_generation(generation)
+            .into_digest();
+
+        assert_eq!(response.content, None);
+        assert_eq!(response.digest, Some(content("1").digest()));
+        assert_ne!(content("1").digest(), content("2").digest());
+        assert_eq!(
+            InternodeResponse::from_bytes(&response.as_bytes()).unwrap(),
+            response
+        );
+
+        // Responses without content have nothing to digest
+        let response = InternodeResponse::from_error(7, &NodeError::KeyspaceError).into_digest();
+        assert_eq!(response.digest, None);
+    }
 }
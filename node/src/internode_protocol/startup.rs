@@ -0,0 +1,93 @@
+//! The options a node opens a connection to another node with.
+//!
+//! Connections between nodes go one way: the node that opens one only writes to it. Its first
+//! message, when the node compresses its messages, is a `ConnectionStartup` with the name of the
+//! algorithm, as the `STARTUP` of the native protocol does. The node reading the connection then
+//! decompresses the messages whose header has the compressed flag with it, and closes the
+//! connection if it does not support the algorithm.
+
+use super::{message::InternodeMessageError, read_string, write_string, InternodeSerializable};
+use native_protocol::compression::Compression;
+use std::io::Cursor;
+
+/// Options of the connection a node opens to another node, sent as its first message.
+///
+/// ### Fields
+/// - `compression`: The name of the algorithm the bodies of the messages of the connection are
+///   compressed with, or `None` if they are not.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConnectionStartup {
+    pub compression: Option<String>,
+}
+
+impl ConnectionStartup {
+    /// Creates the startup of a connection whose messages are compressed with `compression`.
+    pub fn new(compression: Option<&Compression>) -> Self {
+        ConnectionStartup {
+            compression: compression.map(|compression| compression.name().to_string()),
+        }
+    }
+
+    /// Returns the algorithm the messages of the connection are compressed with.
+    ///
+    /// # Errors
+    /// - `InternodeMessageError` if the algorithm is not supported by this node.
+    pub fn compression(&self) -> Result<Option<Compression>, InternodeMessageError> {
+        self.compression
+            .as_deref()
+            .map(Compression::from_name)
+            .transpose()
+            .map_err(|_| InternodeMessageError)
+    }
+}
+
+impl InternodeSerializable for ConnectionStartup {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |  compression_len  |
+    /// +----+----+----+----+
+    /// |    compression    |
+    /// +----+----+----+----+
+    /// ```
+    /// A connection without compression has an empty name.
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, self.compression.as_deref().unwrap_or(""));
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+        let compression = read_string(&mut cursor)?;
+        Ok(ConnectionStartup {
+            compression: (!compression.is_empty()).then_some(compression),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_startup_round_trip() {
+        for startup in [
+            ConnectionStartup::new(Some(&Compression::Lz4)),
+            ConnectionStartup::new(None),
+        ] {
+            let parsed = ConnectionStartup::from_bytes(&startup.as_bytes()).unwrap();
+            assert_eq!(parsed, startup);
+        }
+        assert_eq!(
+            ConnectionStartup::new(Some(&Compression::Lz4))
+                .compression()
+                .unwrap(),
+            Some(Compression::Lz4)
+        );
+        let unsupported = ConnectionStartup {
+            compression: Some("zstd".to_string()),
+        };
+        assert!(unsupported.compression().is_err());
+    }
+}
@@ -0,0 +1,611 @@
+//! Structured representation of the statements a coordinator node sends to the replicas.
+//!
+//! Instead of sending the CQL string of an `INSERT`, `UPDATE`, `DELETE` or `SELECT`, which every
+//! replica has to tokenize and parse again, the coordinator sends the already parsed statement:
+//! the table it acts on, the columns and values it writes and the conditions it filters by. The
+//! replica rebuilds the `Query` from it without going through the parser, so every replica runs
+//! exactly what the coordinator parsed.
+
+use std::io::{Cursor, Read};
+
+use super::{
+    message::InternodeMessageError, read_bytes, read_string, read_u32, write_string,
+    InternodeSerializable,
+};
+use query_creator::{
+    clauses::{
+        batch_cql::Batch, condition::Condition, delete_cql::Delete, if_cql::If, insert_cql::Insert,
+        into_cql::Into, order_by_cql::OrderBy, select_cql::Select, set_cql::Set,
+        update_cql::Update, where_cql::Where,
+    },
+    logical_operator::LogicalOperator,
+    operator::Operator,
+    GetUsedKeyspace, Query,
+};
+
+/// Deepest condition tree accepted when deserializing, so a malformed message cannot make the
+/// node recurse without limit.
+const MAX_CONDITION_DEPTH: usize = 64;
+
+/// A data statement sent by a coordinator node to the replicas, already parsed.
+///
+/// ### Variants
+/// - `Insert`: Writes a row.
+/// - `Update`: Writes some columns of the rows matching a condition.
+/// - `Delete`: Deletes the rows (or some of their columns) matching a condition.
+/// - `Select`: Reads the rows matching a condition.
+/// - `Batch`: The statements of a batch that a node has to apply, in order, each one with
+///   whether the node applies it as a replica (`true`) or as the owner of the partition.
+#[derive(Debug, PartialEq, Clone)]
+pub enum InternodeStatement {
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    Select(Select),
+    Batch(Vec<(bool, InternodeStatement)>),
+}
+
+impl InternodeStatement {
+    /// Returns the structured statement for `query`, or `None` if the query is not a data
+    /// statement (schema changes are still sent as CQL strings). Batches are split by the
+    /// coordinator, which builds the `Batch` statement of each node.
+    pub fn from_query(query: &Query) -> Option<Self> {
+        match query {
+            Query::Insert(insert) => Some(InternodeStatement::Insert(insert.clone())),
+            Query::Update(update) => Some(InternodeStatement::Update(update.clone())),
+            Query::Delete(delete) => Some(InternodeStatement::Delete(delete.clone())),
+            Query::Select(select) => Some(InternodeStatement::Select(select.clone())),
+            _ => None,
+        }
+    }
+
+    /// Returns the CQL string of the statement, for nodes that only understand CQL strings and
+    /// for the logs.
+    pub fn to_cql(&self) -> String {
+        match self {
+            InternodeStatement::Insert(insert) => insert.serialize(),
+            InternodeStatement::Update(update) => update.serialize(),
+            InternodeStatement::Delete(delete) => delete.serialize(),
+            InternodeStatement::Select(select) => select.serialize(),
+            InternodeStatement::Batch(statements) => {
+                let statements: Vec<String> = statements
+                    .iter()
+                    .map(|(_, statement)| format!("{}; ", statement.to_cql()))
+                    .collect();
+                format!("BEGIN UNLOGGED BATCH {}APPLY BATCH", statements.concat())
+            }
+        }
+    }
+}
+
+impl From<InternodeStatement> for Query {
+    fn from(statement: InternodeStatement) -> Self {
+        match statement {
+            InternodeStatement::Insert(insert) => Query::Insert(insert),
+            InternodeStatement::Update(update) => Query::Update(update),
+            InternodeStatement::Delete(delete) => Query::Delete(delete),
+            InternodeStatement::Select(select) => Query::Select(select),
+            InternodeStatement::Batch(statements) => Query::Batch(Batch {
+                logged: false,
+                statements: statements
+                    .into_iter()
+                    .map(|(_, statement)| statement.into())
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl InternodeSerializable for InternodeStatement {
+    /// Every statement starts with its kind and the table it acts on:
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |kind|  keyspace_len
+    /// +----+----+----+----+
+    /// |    |   keyspace    |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// |     table_len     |
+    /// +----+----+----+----+
+    /// |       table       |
+    /// |        ...        |
+    /// +----+----+----+----+
+    /// ```
+    /// followed by the fields of the statement, where every string is prefixed by its length
+    /// (4 bytes), every list by its number of elements (4 bytes) and every optional field by a
+    /// byte telling whether it is present:
+    /// - `Insert` (kind 1): columns, values, the `IF NOT EXISTS` flag and the `USING TTL` seconds
+    ///   (optional, 8 bytes).
+    /// - `Update` (kind 2): the `SET` pairs (column and value), the `WHERE` condition, the `IF`
+    ///   condition, the `USING TTL` seconds (optional, 8 bytes) and the counter increments (column
+    ///   and increment, written as a string).
+    /// - `Delete` (kind 3): the deleted columns (optional), the `WHERE` condition, the `IF`
+    ///   condition and the `IF EXISTS` flag.
+    /// - `Select` (kind 4): columns, the `WHERE` condition, the `ORDER BY` columns and order
+    ///   (optional), the limit (optional, 8 bytes), the limit of each partition (optional, 8
+    ///   bytes), the `ALLOW FILTERING` flag and the `JSON` flag.
+    /// - `Batch` (kind 5, with the keyspace of its statements and an empty table): the number of
+    ///   statements and, for each one, the replication flag and the statement (prefixed by its
+    ///   length). Batches cannot be nested.
+    ///
+    /// A condition starts with a byte telling its kind: `0` for a simple condition (field,
+    /// operator and value) and `1` for a logical operation (optional left condition, logical
+    /// operator and right condition).
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        match self {
+            InternodeStatement::Insert(insert) => {
+                bytes.push(1);
+                write_string(&mut bytes, &insert.into_clause.keyspace_used_name);
+                write_string(&mut bytes, &insert.into_clause.table_name);
+                write_strings(&mut bytes, &insert.into_clause.columns);
+                write_strings(&mut bytes, &insert.values);
+                bytes.push(insert.if_not_exists as u8);
+                write_optional_u64(&mut bytes, insert.ttl);
+            }
+            InternodeStatement::Update(update) => {
+                bytes.push(2);
+                write_string(&mut bytes, &update.keyspace_used_name);
+                write_string(&mut bytes, &update.table_name);
+                let pairs = update.set_clause.get_pairs();
+                bytes.extend(&(pairs.len() as u32).to_be_bytes());
+                for (column, value) in pairs {
+                    write_string(&mut bytes, column);
+                    write_string(&mut bytes, value);
+                }
+                write_optional_condition(
+                    &mut bytes,
+                    update.where_clause.as_ref().map(|w| &w.condition),
+                );
+                write_optional_condition(
+                    &mut bytes,
+                    update.if_clause.as_ref().map(|i| &i.condition),
+                );
+                write_optional_u64(&mut bytes, update.ttl);
+                let increments = update.set_clause.get_increments();
+                bytes.extend(&(increments.len() as u32).to_be_bytes());
+                for (column, increment) in increments {
+                    write_string(&mut bytes, column);
+                    write_string(&mut bytes, &increment.to_string());
+                }
+            }
+            InternodeStatement::Delete(delete) => {
+                bytes.push(3);
+                write_string(&mut bytes, &delete.keyspace_used_name);
+                write_string(&mut bytes, &delete.table_name);
+                match &delete.columns {
+                    Some(columns) => {
+                        bytes.push(1);
+                        write_strings(&mut bytes, columns);
+                    }
+                    None => bytes.push(0),
+                }
+                write_optional_condition(
+                    &mut bytes,
+                    delete.where_clause.as_ref().map(|w| &w.condition),
+                );
+                write_optional_condition(
+                    &mut bytes,
+                    delete.if_clause.as_ref().map(|i| &i.condition),
+                );
+                bytes.push(delete.if_exist as u8);
+            }
+            InternodeStatement::Select(select) => {
+                bytes.push(4);
+                write_string(&mut bytes, &select.keyspace_used_name);
+                write_string(&mut bytes, &select.table_name);
+                write_strings(&mut bytes, &select.columns);
+                write_optional_condition(
+                    &mut bytes,
+                    select.where_clause.as_ref().map(|w| &w.condition),
+                );
+                match &select.orderby_clause {
+                    Some(order_by) => {
+                        bytes.push(1);
+                        write_strings(&mut bytes, &order_by.columns);
+                        write_string(&mut bytes, &order_by.order);
+                    }
+                    None => bytes.push(0),
+                }
+                write_optional_u64(&mut bytes, select.limit.map(|limit| limit as u64));
+                write_optional_u64(
+                    &mut bytes,
+                    select.per_partition_limit.map(|limit| limit as u64),
+                );
+                bytes.push(select.allow_filtering as u8);
+                bytes.push(select.json as u8);
+            }
+            InternodeStatement::Batch(statements) => {
+                bytes.push(5);
+                let keyspace = statements
+                    .first()
+                    .map(|(_, statement)| Query::from(statement.clone()))
+                    .and_then(|query| query.get_used_keyspace())
+                    .unwrap_or_default();
+                write_string(&mut bytes, &keyspace);
+                write_string(&mut bytes, "");
+                bytes.extend(&(statements.len() as u32).to_be_bytes());
+                for (replication, statement) in statements {
+                    bytes.push(*replication as u8);
+                    let statement_bytes = statement.as_bytes();
+                    bytes.extend(&(statement_bytes.len() as u32).to_be_bytes());
+                    bytes.extend(statement_bytes);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Deserializes a byte vector into an `InternodeStatement`.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError>
+    where
+        Self: Sized,
+    {
+        let mut cursor = Cursor::new(bytes);
+
+        let kind = read_u8(&mut cursor)?;
+        let keyspace_used_name = read_string(&mut cursor)?;
+        let table_name = read_string(&mut cursor)?;
+
+        let statement = match kind {
+            1 => {
+                let columns = read_strings(&mut cursor)?;
+                let values = read_strings(&mut cursor)?;
+                let if_not_exists = read_u8(&mut cursor)? != 0;
+                let ttl = read_optional_u64(&mut cursor)?;
+                InternodeStatement::Insert(Insert {
+                    values,
+                    into_clause: Into {
+                        table_name,
+                        keyspace_used_name,
+                        columns,
+                    },
+                    if_not_exists,
+                    ttl,
+                })
+            }
+            2 => {
+                let pairs_len = read_u32(&mut cursor)? as usize;
+                let mut pairs = Vec::new();
+                for _ in 0..pairs_len {
+                    pairs.push((read_string(&mut cursor)?, read_string(&mut cursor)?));
+                }
+                let where_clause =
+                    read_optional_condition(&mut cursor)?.map(|condition| Where { condition });
+                let if_clause =
+                    read_optional_condition(&mut cursor)?.map(|condition| If { condition });
+                let ttl = read_optional_u64(&mut cursor)?;
+                let increments_len = read_u32(&mut cursor)? as usize;
+                let mut increments = Vec::new();
+                for _ in 0..increments_len {
+                    let column = read_string(&mut cursor)?;
+                    let increment = read_string(&mut cursor)?
+                        .parse()
+                        .map_err(|_| InternodeMessageError)?;
+                    increments.push((column, increment));
+                }
+                InternodeStatement::Update(Update {
+                    table_name,
+                    keyspace_used_name,
+                    set_clause: Set(pairs, increments),
+                    where_clause,
+                    if_clause,
+                    ttl,
+                })
+            }
+            3 => {
+                let columns = match read_u8(&mut cursor)? {
+                    0 => None,
+                    _ => Some(read_strings(&mut cursor)?),
+                };
+                let where_clause =
+                    read_optional_condition(&mut cursor)?.map(|condition| Where { condition });
+                let if_clause =
+                    read_optional_condition(&mut cursor)?.map(|condition| If { condition });
+                let if_exist = read_u8(&mut cursor)? != 0;
+                InternodeStatement::Delete(Delete {
+                    table_name,
+                    keyspace_used_name,
+                    columns,
+                    where_clause,
+                    if_clause,
+                    if_exist,
+                })
+            }
+            4 => {
+                let columns = read_strings(&mut cursor)?;
+                let where_clause =
+                    read_optional_condition(&mut cursor)?.map(|condition| Where { condition });
+                let orderby_clause = match read_u8(&mut cursor)? {
+                    0 => None,
+                    _ => Some(OrderBy {
+                        columns: read_strings(&mut cursor)?,
+                        order: read_string(&mut cursor)?,
+                    }),
+                };
+                let limit = read_optional_u64(&mut cursor)?.map(|limit| limit as usize);
+                let per_partition_limit =
+                    read_optional_u64(&mut cursor)?.map(|limit| limit as usize);
+                let allow_filtering = read_u8(&mut cursor)? != 0;
+                let json = read_u8(&mut cursor)? != 0;
+                InternodeStatement::Select(Select {
+                    table_name,
+                    keyspace_used_name,
+                    columns,
+                    where_clause,
+                    orderby_clause,
+                    per_partition_limit,
+                    limit,
+                    allow_filtering,
+                    json,
+                })
+            }
+            5 => {
+                let len = read_u32(&mut cursor)? as usize;
+                let mut statements = Vec::new();
+                for _ in 0..len {
+                    let replication = read_u8(&mut cursor)? != 0;
+                    let statement_len = read_u32(&mut cursor)? as usize;
+                    let statement_bytes = read_bytes(&mut cursor, statement_len)?;
+                    match InternodeStatement::from_bytes(&statement_bytes)? {
+                        InternodeStatement::Batch(_) => return Err(InternodeMessageError),
+                        statement => statements.push((replication, statement)),
+                    }
+                }
+                InternodeStatement::Batch(statements)
+            }
+            _ => return Err(InternodeMessageError),
+        };
+
+        Ok(statement)
+    }
+}
+
+fn write_strings(bytes: &mut Vec<u8>, values: &[String]) {
+    bytes.extend(&(values.len() as u32).to_be_bytes());
+    for value in values {
+        write_string(bytes, value);
+    }
+}
+
+fn write_optional_u64(bytes: &mut Vec<u8>, value: Option<u64>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            bytes.extend(&value.to_be_bytes());
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn write_optional_condition(bytes: &mut Vec<u8>, condition: Option<&Condition>) {
+    match condition {
+        Some(condition) => {
+            bytes.push(1);
+            write_condition(bytes, condition);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn write_condition(bytes: &mut Vec<u8>, condition: &Condition) {
+    match condition {
+        Condition::Simple {
+            field,
+            operator,
+            value,
+        } => {
+            bytes.push(0);
+            write_string(bytes, field);
+            bytes.push(match operator {
+                Operator::Equal => 0,
+                Operator::Greater => 1,
+                Operator::Lesser => 2,
+                Operator::GreaterOrEqual => 3,
+                Operator::LesserOrEqual => 4,
+            });
+            write_string(bytes, value);
+        }
+        Condition::Complex {
+            left,
+            operator,
+            right,
+        } => {
+            bytes.push(1);
+            write_optional_condition(bytes, left.as_deref());
+            bytes.push(match operator {
+                LogicalOperator::And => 0,
+                LogicalOperator::Or => 1,
+                LogicalOperator::Not => 2,
+            });
+            write_condition(bytes, right);
+        }
+    }
+}
+
+fn read_u8(cursor: &mut Cursor<&[u8]>) -> Result<u8, InternodeMessageError> {
+    let mut byte = [0u8; 1];
+    cursor
+        .read_exact(&mut byte)
+        .map_err(|_| InternodeMessageError)?;
+    Ok(byte[0])
+}
+
+fn read_optional_u64(cursor: &mut Cursor<&[u8]>) -> Result<Option<u64>, InternodeMessageError> {
+    if read_u8(cursor)? == 0 {
+        return Ok(None);
+    }
+    let mut bytes = [0u8; 8];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|_| InternodeMessageError)?;
+    Ok(Some(u64::from_be_bytes(bytes)))
+}
+
+fn read_strings(cursor: &mut Cursor<&[u8]>) -> Result<Vec<String>, InternodeMessageError> {
+    let len = read_u32(cursor)? as usize;
+    let mut values = Vec::new();
+    for _ in 0..len {
+        values.push(read_string(cursor)?);
+    }
+    Ok(values)
+}
+
+fn read_optional_condition(
+    cursor: &mut Cursor<&[u8]>,
+) -> Result<Option<Condition>, InternodeMessageError> {
+    read_optional_condition_with_depth(cursor, 0)
+}
+
+fn read_optional_condition_with_depth(
+    cursor: &mut Cursor<&[u8]>,
+    depth: usize,
+) -> Result<Option<Condition>, InternodeMessageError> {
+    match read_u8(cursor)? {
+        0 => Ok(None),
+        _ => Ok(Some(read_condition(cursor, depth)?)),
+    }
+}
+
+fn read_condition(
+    cursor: &mut Cursor<&[u8]>,
+    depth: usize,
+) -> Result<Condition, InternodeMessageError> {
+    if depth > MAX_CONDITION_DEPTH {
+        return Err(InternodeMessageError);
+    }
+
+    match read_u8(cursor)? {
+        0 => {
+            let field = read_string(cursor)?;
+            let operator = match read_u8(cursor)? {
+                0 => Operator::Equal,
+                1 => Operator::Greater,
+                2 => Operator::Lesser,
+                3 => Operator::GreaterOrEqual,
+                4 => Operator::LesserOrEqual,
+                _ => return Err(InternodeMessageError),
+            };
+            let value = read_string(cursor)?;
+            Ok(Condition::Simple {
+                field,
+                operator,
+                value,
+            })
+        }
+        1 => {
+            let left = read_optional_condition_with_depth(cursor, depth + 1)?.map(Box::new);
+            let operator = match read_u8(cursor)? {
+                0 => LogicalOperator::And,
+                1 => LogicalOperator::Or,
+                2 => LogicalOperator::Not,
+                _ => return Err(InternodeMessageError),
+            };
+            let right = Box::new(read_condition(cursor, depth + 1)?);
+            Ok(Condition::Complex {
+                left,
+                operator,
+                right,
+            })
+        }
+        _ => Err(InternodeMessageError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::QueryCreator;
+
+    fn statement(query: &str) -> InternodeStatement {
+        let query = QueryCreator::new().handle_query(query.to_string()).unwrap();
+        InternodeStatement::from_query(&query).unwrap()
+    }
+
+    #[test]
+    fn test_statements_round_trip() {
+        let statements = vec![
+            statement("INSERT INTO airline.flights (id, origin, destination) VALUES (1, 'EZE', 'MAD')"),
+            statement("UPDATE airline.flights SET status = 'delayed' WHERE id = 1 AND origin = 'EZE' IF status = 'on time'"),
+            statement("INSERT INTO airline.positions (id, lat) VALUES (1, 10) USING TTL 60"),
+            statement("UPDATE airline.positions USING TTL 60 SET lat = 11 WHERE id = 1"),
+            statement("UPDATE airline.departures SET count = count + 1, late = late - 2 WHERE airport = 'EZE'"),
+            statement("DELETE status FROM airline.flights WHERE id = 1"),
+            statement("DELETE FROM airline.flights WHERE id = 1 IF EXISTS"),
+            statement("SELECT id, origin FROM airline.flights WHERE id = 1 AND origin > 'A' ORDER BY origin DESC"),
+            statement("SELECT id, origin FROM airline.flights WHERE id = 1 PER PARTITION LIMIT 2 LIMIT 5"),
+            statement("SELECT * FROM airline.flights WHERE status = 'delayed' ALLOW FILTERING"),
+            statement("SELECT JSON id, origin FROM airline.flights WHERE id = 1"),
+        ];
+
+        for statement in statements {
+            let parsed = InternodeStatement::from_bytes(&statement.as_bytes()).unwrap();
+            assert_eq!(parsed, statement);
+        }
+    }
+
+    #[test]
+    fn test_batch_round_trip() {
+        let batch = InternodeStatement::Batch(vec![
+            (
+                false,
+                statement("INSERT INTO airline.flights (id, origin) VALUES (1, 'EZE')"),
+            ),
+            (
+                true,
+                statement("UPDATE airline.flights SET status = 'delayed' WHERE id = 1"),
+            ),
+        ]);
+
+        let bytes = batch.as_bytes();
+        assert_eq!(InternodeStatement::from_bytes(&bytes).unwrap(), batch);
+        assert!(matches!(Query::from(batch), Query::Batch(b) if b.statements.len() == 2));
+
+        // A batch inside a batch is rejected
+        let nested = InternodeStatement::Batch(vec![(false, InternodeStatement::Batch(vec![]))]);
+        assert!(InternodeStatement::from_bytes(&nested.as_bytes()).is_err());
+
+        // As is a statement longer than the batch: its length follows the kind, keyspace and
+        // table of the batch, the number of statements and whether the first one is replicated
+        let mut bytes = bytes;
+        let statement_len = 1 + (4 + "airline".len()) + 4 + 4 + 1;
+        bytes[statement_len..statement_len + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(InternodeStatement::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_statement_matches_the_parsed_cql() {
+        let statement = statement("UPDATE airline.flights SET status = 'delayed' WHERE id = 1");
+        let parsed = QueryCreator::new()
+            .handle_query(statement.to_cql())
+            .unwrap();
+
+        assert_eq!(InternodeStatement::from_query(&parsed), Some(statement));
+    }
+
+    #[test]
+    fn test_schema_changes_are_not_structured() {
+        let query = QueryCreator::new()
+            .handle_query("DROP TABLE flights".to_string())
+            .unwrap();
+
+        assert!(InternodeStatement::from_query(&query).is_none());
+    }
+
+    #[test]
+    fn test_invalid_statement_from_bytes() {
+        assert!(InternodeStatement::from_bytes(&[]).is_err());
+        assert!(InternodeStatement::from_bytes(&[9, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+
+        let bytes = statement("SELECT * FROM airline.flights WHERE id = 1").as_bytes();
+        assert!(InternodeStatement::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // A length past the end of the statement is rejected without allocating for it
+        let mut bytes = bytes;
+        bytes[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(InternodeStatement::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+//! Requests of the nodes that bootstrap for the rows of the ranges they take in the ring, the
+//! results of the nodes that stream those rows to them, and the chunks the rows are streamed in.
+//!
+//! Rows move between nodes (on a bootstrap, a redistribution or a decommission) as chunks of the
+//! lines of their data files, instead of one internode `INSERT` per row. Each chunk carries a
+//! checksum of its rows and is acknowledged by the node that stores it, so the node streaming it
+//! sends again only the chunks that were lost or corrupted, resuming the stream where it stopped.
+
+use super::{
+    message::InternodeMessageError, read_string, read_u32, write_string, InternodeSerializable,
+};
+use native_protocol::checksum::crc32;
+use std::io::{Cursor, Read};
+
+/// Rows streamed in a chunk, at most.
+pub const STREAM_CHUNK_ROWS: usize = 200;
+
+/// Asks a node for the rows it stores that a bootstrapping node must keep, as the owner or a
+/// replica of their partitions, once it is in the ring at its tokens.
+///
+/// ### Fields
+/// - `id`: Identifies the request in the bootstrapping node, which gets it back in the result.
+/// - `tokens`: The tokens the bootstrapping node takes in the ring.
+/// - `datacenter`: The datacenter of the bootstrapping node.
+/// - `rack`: The rack of the bootstrapping node.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamRequest {
+    pub id: u32,
+    pub tokens: Vec<u64>,
+    pub datacenter: String,
+    pub rack: String,
+}
+
+/// What a node streamed for a stream request. It is sent after every row streamed, through the
+/// same queue, so it reaches the bootstrapping node after them.
+///
+/// ### Fields
+/// - `id`: The id of the request.
+/// - `outcome`: The amount of rows streamed, or why the node failed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamResult {
+    pub id: u32,
+    pub outcome: Result<u32, String>,
+}
+
+/// Rows of a table streamed to a node, as the lines of the data files of the node streaming them.
+///
+/// ### Fields
+/// - `session`: Identifies the stream in the node that sends it.
+/// - `seq`: The position of the chunk in its session.
+/// - `keyspace`: The keyspace of the table.
+/// - `table`: The table the rows belong to.
+/// - `replication`: Whether the receiving node keeps the rows as a replica instead of as their owner.
+/// - `rows`: The rows, as `values;stamp` lines of a data file.
+/// - `checksum`: The CRC32 of the rows, checked by the receiving node.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamChunk {
+    pub session: u32,
+    pub seq: u32,
+    pub keyspace: String,
+    pub table: String,
+    pub replication: bool,
+    pub rows: Vec<String>,
+    pub checksum: u32,
+}
+
+/// The answer of a node to a chunk streamed to it.
+///
+/// ### Fields
+/// - `session`: The session of the chunk.
+/// - `seq`: The position of the chunk in its session.
+/// - `stored`: Whether the rows were stored, or the chunk has to be sent again because it was
+///   corrupted or its table is not known yet.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamChunkAck {
+    pub session: u32,
+    pub seq: u32,
+    pub stored: bool,
+}
+
+impl StreamChunk {
+    /// Creates the chunk `seq` of `session` with the `rows` of a table, and their checksum.
+    pub fn new(
+        session: u32,
+        seq: u32,
+        keyspace: &str,
+        table: &str,
+        replication: bool,
+        rows: Vec<String>,
+    ) -> Self {
+        let checksum = Self::checksum_of(&rows);
+        StreamChunk {
+            session,
+            seq,
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            replication,
+            rows,
+            checksum,
+        }
+    }
+
+    /// Checks the rows against the checksum they were sent with.
+    pub fn is_intact(&self) -> bool {
+        Self::checksum_of(&self.rows) == self.checksum
+    }
+
+    fn checksum_of(rows: &[String]) -> u32 {
+        crc32(rows.join("\n").as_bytes())
+    }
+}
+
+impl InternodeSerializable for StreamRequest {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// |    tokens_len     |
+    /// +----+----+----+----+
+    /// |  tokens (8 each)  |
+    /// +----+----+----+----+
+    /// |  datacenter_len   |
+    /// +----+----+----+----+
+    /// |    datacenter     |
+    /// +----+----+----+----+
+    /// |     rack_len      |
+    /// +----+----+----+----+
+    /// |       rack        |
+    /// +----+----+----+----+
+    /// ```
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.extend(&(self.tokens.len() as u32).to_be_bytes());
+        for token in &self.tokens {
+            bytes.extend(&token.to_be_bytes());
+        }
+        write_string(&mut bytes, &self.datacenter);
+        write_string(&mut bytes, &self.rack);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let tokens_len = read_u32(&mut cursor)?;
+        let mut tokens = Vec::new();
+        for _ in 0..tokens_len {
+            let mut token = [0u8; 8];
+            cursor
+                .read_exact(&mut token)
+                .map_err(|_| InternodeMessageError)?;
+            tokens.push(u64::from_be_bytes(token));
+        }
+        let datacenter = read_string(&mut cursor)?;
+        let rack = read_string(&mut cursor)?;
+
+        Ok(StreamRequest {
+            id,
+            tokens,
+            datacenter,
+            rack,
+        })
+    }
+}
+
+impl InternodeSerializable for StreamResult {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |        id         |
+    /// +----+----+----+----+
+    /// | ok |    rows or     |
+    /// +----+----+----+----+
+    /// |reason_len + reason|
+    /// +----+----+----+----+
+    /// ```
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.id.to_be_bytes());
+        match &self.outcome {
+            Ok(rows) => {
+                bytes.push(0x00);
+                bytes.extend(&rows.to_be_bytes());
+            }
+            Err(reason) => {
+                bytes.push(0x01);
+                write_string(&mut bytes, reason);
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let id = read_u32(&mut cursor)?;
+        let mut status = [0u8; 1];
+        cursor
+            .read_exact(&mut status)
+            .map_err(|_| InternodeMessageError)?;
+        let outcome = match status[0] {
+            0x00 => Ok(read_u32(&mut cursor)?),
+            0x01 => Err(read_string(&mut cursor)?),
+            _ => return Err(InternodeMessageError),
+        };
+
+        Ok(StreamResult { id, outcome })
+    }
+}
+
+impl InternodeSerializable for StreamChunk {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |      session      |
+    /// +----+----+----+----+
+    /// |        seq        |
+    /// +----+----+----+----+
+    /// |   keyspace_len    |
+    /// +----+----+----+----+
+    /// |     keyspace      |
+    /// +----+----+----+----+
+    /// |     table_len     |
+    /// +----+----+----+----+
+    /// |       table       |
+    /// +----+----+----+----+
+    /// |repl|   rows_len   |
+    /// +----+----+----+----+
+    /// | rows (len + row)  |
+    /// +----+----+----+----+
+    /// |     checksum      |
+    /// +----+----+----+----+
+    /// ```
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.session.to_be_bytes());
+        bytes.extend(&self.seq.to_be_bytes());
+        write_string(&mut bytes, &self.keyspace);
+        write_string(&mut bytes, &self.table);
+        bytes.push(self.replication as u8);
+        bytes.extend(&(self.rows.len() as u32).to_be_bytes());
+        for row in &self.rows {
+            write_string(&mut bytes, row);
+        }
+        bytes.extend(&self.checksum.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let session = read_u32(&mut cursor)?;
+        let seq = read_u32(&mut cursor)?;
+        let keyspace = read_string(&mut cursor)?;
+        let table = read_string(&mut cursor)?;
+        let mut replication = [0u8; 1];
+        cursor
+            .read_exact(&mut replication)
+            .map_err(|_| InternodeMessageError)?;
+        let rows_len = read_u32(&mut cursor)?;
+        let mut rows = Vec::new();
+        for _ in 0..rows_len {
+            rows.push(read_string(&mut cursor)?);
+        }
+        let checksum = read_u32(&mut cursor)?;
+
+        Ok(StreamChunk {
+            session,
+            seq,
+            keyspace,
+            table,
+            replication: replication[0] != 0,
+            rows,
+            checksum,
+        })
+    }
+}
+
+impl InternodeSerializable for StreamChunkAck {
+    /// ```md
+    /// 0    8    16   24   32
+    /// +----+----+----+----+
+    /// |      session      |
+    /// +----+----+----+----+
+    /// |        seq        |
+    /// +----+----+----+----+
+    /// |stor|
+    /// +----+
+    /// ```
+    fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(&self.session.to_be_bytes());
+        bytes.extend(&self.seq.to_be_bytes());
+        bytes.push(self.stored as u8);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, InternodeMessageError> {
+        let mut cursor = Cursor::new(bytes);
+
+        let session = read_u32(&mut cursor)?;
+        let seq = read_u32(&mut cursor)?;
+        let mut stored = [0u8; 1];
+        cursor
+            .read_exact(&mut stored)
+            .map_err(|_| InternodeMessageError)?;
+
+        Ok(StreamChunkAck {
+            session,
+            seq,
+            stored: stored[0] != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_request_round_trip() {
+        let request = StreamRequest {
+            id: 4,
+            tokens: vec![17, 4294967295],
+            datacenter: "dc1".to_string(),
+            rack: "rack2".to_string(),
+        };
+        let parsed = StreamRequest::from_bytes(&request.as_bytes()).unwrap();
+        assert_eq!(parsed, request);
+        assert!(StreamRequest::from_bytes(&[0, 0, 0, 4, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_stream_result_round_trip() {
+        for outcome in [Ok(120), Err("Storage Engine Error".to_string())] {
+            let result = StreamResult { id: 4, outcome };
+            let parsed = StreamResult::from_bytes(&result.as_bytes()).unwrap();
+            assert_eq!(parsed, result);
+        }
+    }
+
+    #[test]
+    fn test_stream_chunk_round_trip() {
+        let chunk = StreamChunk::new(
+            7,
+            2,
+            "sky",
+            "flights",
+            true,
+            vec![
+                "1,EZE;1734379315000".to_string(),
+                "2,AEP;1734379316000".to_string(),
+            ],
+        );
+        let parsed = StreamChunk::from_bytes(&chunk.as_bytes()).unwrap();
+        assert_eq!(parsed, chunk);
+        assert!(parsed.is_intact());
+
+        let ack = StreamChunkAck {
+            session: 7,
+            seq: 2,
+            stored: false,
+        };
+        assert_eq!(StreamChunkAck::from_bytes(&ack.as_bytes()).unwrap(), ack);
+    }
+
+    #[test]
+    fn test_stream_chunk_longer_than_its_message_is_rejected() {
+        let chunk = StreamChunk::new(7, 2, "sky", "flights", true, vec!["1,EZE;1".to_string()]);
+        let mut bytes = chunk.as_bytes();
+        // The length of the row is the one before its bytes
+        let row = bytes
+            .windows(7)
+            .position(|window| window == b"1,EZE;1")
+            .unwrap();
+        bytes[row - 4..row].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(StreamChunk::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_stream_chunk_is_not_intact() {
+        let mut chunk =
+            StreamChunk::new(7, 0, "sky", "flights", false, vec!["1,EZE;1".to_string()]);
+        chunk.rows[0] = "1,EZF;1".to_string();
+        assert!(!chunk.is_intact());
+    }
+}
@@ -1,35 +1,70 @@
 // Exportar todos los elementos del módulo query_execution
 
+use crate::connection_manager::ConnectionManager;
+use crate::gossip_transport::InternodeGossipTransport;
+use crate::internode_protocol::maintenance::MaintenanceResult;
 use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::internode_protocol::paxos::PaxosReply;
 use crate::internode_protocol::query::InternodeQuery;
 use crate::internode_protocol::response::{InternodeResponse, InternodeResponseStatus};
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::internode_protocol::streaming::{StreamChunk, StreamChunkAck};
+use crate::metrics::SharedReadRepairMetrics;
 use crate::open_query_handler::OpenQueryHandler;
-use crate::utils::{check_keyspace, check_table, connect_and_send_message};
-use crate::{storage_engine, Node, NodeError, Query, QueryExecution, INTERNODE_PORT};
+use crate::query_execution::select::scans_table;
+use crate::storage_engine::commitlog::{CommitLog, CommitLogEntry, Mutation};
+use crate::storage_engine::counter::{merge_counter_cells, sum_counter_cells};
+use crate::storage_engine::row_stamp::{split_row, RowStamp};
+use crate::storage_engine::select::RowLimits;
+use crate::storage_engine::StorageEngine;
+use crate::utils::{check_keyspace, check_table, connect_and_send_message, get_replicas};
+use crate::worker_pool::WorkerPool;
+use crate::{Node, NodeError, Query, QueryExecution};
 use chrono::Utc;
 use gossip::messages::GossipMessage;
 use gossip::structures::application_state::TableSchema;
-use logger::{Color, Logger};
+use logger::{Color, Component, Logger};
 use native_protocol::frame::Frame;
-use native_protocol::messages::error;
+use native_protocol::messages::result::result_;
 use partitioner::Partitioner;
 use query_creator::clauses::keyspace::{
     alter_keyspace_cql::AlterKeyspace, create_keyspace_cql::CreateKeyspace,
     drop_keyspace_cql::DropKeyspace,
 };
+use query_creator::clauses::select_cql::COUNT_RESULT_COLUMN;
 use query_creator::clauses::table::{
     alter_table_cql::AlterTable, create_table_cql::CreateTable, drop_table_cql::DropTable,
 };
-use query_creator::clauses::types::column::Column;
+use query_creator::clauses::types::{column::Column, datatype::DataType};
 use query_creator::clauses::use_cql::Use;
 use query_creator::clauses::{
-    delete_cql::Delete, insert_cql::Insert, select_cql::Select, update_cql::Update,
+    delete_cql::Delete, insert_cql::Insert, into_cql, select_cql::Select, update_cql::Update,
 };
-use query_creator::{CreateClientResponse, NeedsKeyspace, NeedsTable, QueryCreator};
+use query_creator::operator::Operator;
+use query_creator::{CreateClientResponse, NeedsKeyspace, NeedsTable};
 use std::collections::HashMap;
-use std::net::{Ipv4Addr, TcpStream};
-use std::path::PathBuf;
+use std::net::Ipv4Addr;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// The latest version of a row, to write to a node that answered a read with an older one.
+///
+/// ### Fields
+/// - `replica`: The node that answered with the older version.
+/// - `row`: The values of the latest version, followed by its stamp.
+/// - `replication`: Whether the node holds the row as a replica of the range of another node.
+#[derive(Debug, Clone, PartialEq)]
+struct RepairWrite {
+    replica: Ipv4Addr,
+    row: Vec<String>,
+    replication: bool,
+}
+
+/// Rows a read whose answers were spilled to disk keeps to repair, over all of its batches. The
+/// repairs found past them are dropped and counted, and left to a later read or to `REPAIR`.
+const MAX_READ_REPAIR_ROWS: usize = 10_000;
 
 /// Struct that represents the handler for internode communication protocol.
 pub struct InternodeProtocolHandler;
@@ -58,10 +93,18 @@ impl InternodeProtocolHandler {
     ///       - `InternodeMessageContent::Query`: Represents a query to be executed on this node.
     ///       - `InternodeMessageContent::Response`: Represents a response to a previously issued query.
     ///       - `InternodeMessageContent::Gossip`: Represents a gossip protocol message for cluster state sharing.
+    ///       - `InternodeMessageContent::Maintenance`: Asks this node to flush, compact or snapshot the tables of a keyspace.
+    ///       - `InternodeMessageContent::MaintenanceResult`: What another node did for a maintenance request of this node.
+    ///       - `InternodeMessageContent::Paxos`: A phase of the Paxos round of a lightweight transaction.
+    ///       - `InternodeMessageContent::PaxosReply`: What a replica answered to a phase of a round of this node.
+    ///       - `InternodeMessageContent::StreamRequest`: A bootstrapping node asks for the rows of its ranges.
+    ///       - `InternodeMessageContent::StreamResult`: What another node streamed for the bootstrap of this node.
+    ///       - `InternodeMessageContent::StreamChunk`: Rows another node streams to this node.
+    ///       - `InternodeMessageContent::StreamChunkAck`: Whether a node stored a chunk of rows this node streamed.
     ///     - `from`: The identifier of the node that sent the message.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A thread-safe map of active connections to other nodes in the cluster.
-    ///   - Keys are node addresses (as strings), and values are thread-safe `TcpStream` objects for communication.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes in the cluster, by their internode address, which the
+    ///     responses and forwarded messages are sent through.
     ///
     /// # Returns
     /// - `Result<(), NodeError>`
@@ -80,7 +123,27 @@ impl InternodeProtocolHandler {
     /// 3. **Gossip Handling**:
     ///    - If the message content is `InternodeMessageContent::Gossip`, calls `handle_gossip_command`.
     ///    - Updates the node's internal state based on the gossip protocol message.
-    /// 4. **Error Handling**:
+    /// 4. **Maintenance Handling**:
+    ///    - If the message content is `InternodeMessageContent::Maintenance`, runs the flush, compaction or
+    ///      snapshot in a new thread and sends its outcome back to the sender as a `MaintenanceResult`.
+    ///    - If the message content is `InternodeMessageContent::MaintenanceResult`, hands it to the admin
+    ///      command waiting for it, if it still is.
+    /// 5. **Paxos Handling**:
+    ///    - If the message content is `InternodeMessageContent::Paxos`, takes part in the phase of the round as a
+    ///      replica and sends its answer back as a `PaxosReply`, which is not ok if the phase failed.
+    ///    - If the message content is `InternodeMessageContent::PaxosReply`, hands it to the lightweight
+    ///      transaction waiting for it, if it still is.
+    /// 6. **Bootstrap Handling**:
+    ///    - If the message content is `InternodeMessageContent::StreamRequest`, streams the rows the sender
+    ///      must keep in a new thread, followed by a `StreamResult`.
+    ///    - If the message content is `InternodeMessageContent::StreamResult`, hands it to the bootstrap
+    ///      waiting for it, if it still is.
+    /// 7. **Streaming Handling**:
+    ///    - If the message content is `InternodeMessageContent::StreamChunk`, stores its rows if the chunk is
+    ///      intact and its table is known, and answers with a `StreamChunkAck` saying whether it did.
+    ///    - If the message content is `InternodeMessageContent::StreamChunkAck`, stops waiting for the chunk
+    ///      if it was stored, or keeps it to be sent again otherwise.
+    /// 8. **Error Handling**:
     ///    - Any errors encountered during the handling of commands are returned as `NodeError`.
     ///
     /// # Message Types
@@ -90,6 +153,14 @@ impl InternodeProtocolHandler {
     ///   - Represents the result of a previously executed query or command.
     /// - `InternodeMessageContent::Gossip`:
     ///   - Represents messages exchanged between nodes to share cluster state and maintain consistency.
+    /// - `InternodeMessageContent::Maintenance` and `InternodeMessageContent::MaintenanceResult`:
+    ///   - Represent the flushes and compactions asked with the `FLUSH` and `COMPACT` admin commands.
+    /// - `InternodeMessageContent::Paxos` and `InternodeMessageContent::PaxosReply`:
+    ///   - Represent the Paxos rounds of `INSERT ... IF NOT EXISTS` and `UPDATE ... IF`.
+    /// - `InternodeMessageContent::StreamRequest` and `InternodeMessageContent::StreamResult`:
+    ///   - Represent the streaming of the rows of a node that joins the ring.
+    /// - `InternodeMessageContent::StreamChunk` and `InternodeMessageContent::StreamChunkAck`:
+    ///   - Represent the rows moved by a redistribution, a decommission or a bootstrap.
     ///
     /// # Errors
     /// - Returns `NodeError` in the following cases:
@@ -106,11 +177,22 @@ impl InternodeProtocolHandler {
         &self,
         node: &Arc<Mutex<Node>>,
         message: InternodeMessage,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
     ) -> Result<(), NodeError> {
-        let log = { node.lock()?.get_logger() };
-        match message.clone().content {
+        let log = {
+            node.lock()?
+                .get_logger()
+                .with_component(Component::Internode)
+        };
+        // The content is moved out of the message, as the answers it carries may hold many rows
+        let from = message.from;
+        match message.content {
             InternodeMessageContent::Query(query) => {
+                let log = if query.correlation_id.is_empty() {
+                    log
+                } else {
+                    log.with_correlation_id(&query.correlation_id)
+                };
                 let (open_query_id_str, color) = if query.open_query_id == 0 {
                     ("REDISTRIBUTION".to_string(), Color::Cyan)
                 } else {
@@ -119,16 +201,18 @@ impl InternodeProtocolHandler {
                 log.info(
                     &format!(
                         "INTERNODE ({}): I RECEIVED {:?} from {:?}",
-                        open_query_id_str, query.query_string, message.from
+                        open_query_id_str,
+                        query.cql(),
+                        message.from
                     ),
                     color,
                     true,
                 )?;
-                self.handle_query_command(node, query, connections, message.clone().from)?;
+                self.handle_query_command(node, query, connections, from, log)?;
                 Ok(())
             }
             InternodeMessageContent::Response(response) => {
-                self.handle_response_command(node, &response, message.from, connections)?;
+                self.handle_response_command(node, response, from, connections)?;
 
                 Ok(())
             }
@@ -136,6 +220,154 @@ impl InternodeProtocolHandler {
                 self.handle_gossip_command(node, &message, connections)?;
                 Ok(())
             }
+            InternodeMessageContent::Maintenance(request) => {
+                log.info(
+                    &format!(
+                        "INTERNODE: {:?} OF {:?} ASKED BY {:?}",
+                        request.operation, request.keyspace, message.from
+                    ),
+                    Color::Cyan,
+                    true,
+                )?;
+                // Compactions rewrite whole data files, so the connection keeps serving queries
+                let node = node.clone();
+                thread::spawn(move || {
+                    let outcome = Node::run_maintenance(&node, &request).map_err(|e| e.to_string());
+                    let result = MaintenanceResult {
+                        id: request.id,
+                        outcome,
+                    };
+                    let (self_ip, port) = node
+                        .lock()
+                        .map(|node_guard| (node_guard.ip, node_guard.config.internode_port))?;
+                    connect_and_send_message(
+                        message.from,
+                        port,
+                        connections,
+                        InternodeMessage::new(
+                            self_ip,
+                            InternodeMessageContent::MaintenanceResult(result),
+                        ),
+                    )
+                });
+                Ok(())
+            }
+            InternodeMessageContent::MaintenanceResult(result) => {
+                if let Some(sender) = node.lock()?.pending_maintenance.get(&result.id) {
+                    // The coordinator may have stopped waiting, and then the result is dropped
+                    let _ = sender.send((message.from, result.outcome));
+                }
+                Ok(())
+            }
+            InternodeMessageContent::Paxos(request) => {
+                let reply = match Node::answer_paxos(node, &request) {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        log.error(
+                            &format!(
+                                "PAXOS: {:?} OF {:?} FAILED: {}",
+                                request.phase, request.key, e
+                            ),
+                            true,
+                        )?;
+                        PaxosReply {
+                            id: request.id,
+                            ok: false,
+                            in_progress: None,
+                            committed: None,
+                            current: None,
+                        }
+                    }
+                };
+                let (self_ip, port) = {
+                    let node_guard = node.lock()?;
+                    (node_guard.get_ip(), node_guard.config.internode_port)
+                };
+                connect_and_send_message(
+                    message.from,
+                    port,
+                    connections,
+                    InternodeMessage::new(self_ip, InternodeMessageContent::PaxosReply(reply)),
+                )
+            }
+            InternodeMessageContent::PaxosReply(reply) => {
+                if let Some(sender) = node.lock()?.pending_paxos.get(&reply.id) {
+                    // The phase may have already reached a quorum or timed out
+                    let _ = sender.send(reply);
+                }
+                Ok(())
+            }
+            InternodeMessageContent::StreamRequest(request) => {
+                log.info(
+                    &format!(
+                        "INTERNODE: {:?} ASKED FOR THE ROWS OF ITS RANGES",
+                        message.from
+                    ),
+                    Color::Cyan,
+                    true,
+                )?;
+                // Reading every data file takes long, so the connection keeps serving queries
+                let node = node.clone();
+                thread::spawn(move || {
+                    Node::stream_to_bootstrapping_node(&node, connections, message.from, &request)
+                });
+                Ok(())
+            }
+            InternodeMessageContent::StreamResult(result) => {
+                if let Some((id, sender)) = &node.lock()?.pending_bootstrap {
+                    // Results of a bootstrap that already gave up are dropped
+                    if *id == result.id {
+                        let _ = sender.send((message.from, result.outcome));
+                    }
+                }
+                Ok(())
+            }
+            InternodeMessageContent::StreamChunk(chunk) => {
+                let stored = Self::store_stream_chunk(node, &chunk);
+                let color = if stored.is_ok() {
+                    Color::Cyan
+                } else {
+                    Color::Yellow
+                };
+                log.info(
+                    &format!(
+                        "INTERNODE (REDISTRIBUTION): I RECEIVED CHUNK {}/{} OF {} ROWS OF {}.{} from {:?}: {:?}",
+                        chunk.session,
+                        chunk.seq,
+                        chunk.rows.len(),
+                        chunk.keyspace,
+                        chunk.table,
+                        message.from,
+                        stored
+                    ),
+                    color,
+                    true,
+                )?;
+                let ack = StreamChunkAck {
+                    session: chunk.session,
+                    seq: chunk.seq,
+                    stored: stored.is_ok(),
+                };
+                let (self_ip, port) = {
+                    let node_guard = node.lock()?;
+                    (node_guard.get_ip(), node_guard.config.internode_port)
+                };
+                connect_and_send_message(
+                    message.from,
+                    port,
+                    connections,
+                    InternodeMessage::new(self_ip, InternodeMessageContent::StreamChunkAck(ack)),
+                )
+            }
+            // The options of a connection are set by the node reading it, before any other message
+            InternodeMessageContent::Startup(_) => Ok(()),
+            InternodeMessageContent::StreamChunkAck(ack) => {
+                // Answers to chunks that expired, or were already acknowledged, are dropped
+                node.lock()?
+                    .stream_sessions
+                    .acknowledge(message.from, &ack)?;
+                Ok(())
+            }
         }
     }
 
@@ -163,12 +395,12 @@ impl InternodeProtocolHandler {
     ///   - The IP address of the current node processing the query.
     /// - `from: Ipv4Addr`
     ///   - The IP address of the node that sent the response.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A thread-safe map of connections to other nodes in the cluster.
-    ///   - Keys are node addresses as strings, and values are `TcpStream` objects for internode communication.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes in the cluster, by their internode address, which the repair
+    ///     writes are sent through.
     /// - `partitioner: Partitioner`
     ///   - The partitioner used to distribute and retrieve data within the cluster.
-    /// - `storage_path: PathBuf`
+    /// - `storage_engine: StorageEngine`
     ///   - The file system path for accessing local storage.
     ///
     /// # Returns
@@ -184,20 +416,48 @@ impl InternodeProtocolHandler {
     ///    - Determines if the query has been completed (i.e., all required responses have been received).
     /// 2. **Read Repair**:
     ///    - If the query is complete:
-    ///      - Collects the responses from all involved nodes using `get_acumulated_responses`.
+    ///      - Takes the responses from all involved nodes out of the open query with `take_acumulated_responses`,
+    ///        so the rows of the replicas are moved into the merge instead of copied.
     ///      - Performs a read repair operation to ensure consistency across nodes:
     ///        - Identifies the most up-to-date row based on the responses.
-    ///        - Updates inconsistent nodes to align with the most recent data.
+    ///        - Finds the inconsistent nodes, which are updated with the most recent data once the client got
+    ///          its answer (see step 7).
+    ///    - `SELECT COUNT(*)` queries skip the read repair: their counts are merged with `merge_counts`. Counts
+    ///      that scan the table (with `ALLOW FILTERING`) get the rows of every node instead, which are read
+    ///      repaired and counted once merged, as each row is held by several nodes.
+    ///    - If some answers went over the merge memory limit and were spilled to disk, they are read repaired
+    ///      in batches of disjoint primary keys from `merge_batches`, so the repair holds one batch at a time.
+    ///      The reads of `REPAIR` write the repairs of each batch as soon as it is merged; other reads keep up
+    ///      to `MAX_READ_REPAIR_ROWS` of them and count the rest as dropped.
+    ///    - Debug and test builds check that the merged rows have no primary key twice (as they would if a row
+    ///      kept by both the old and the new owner of a range were merged as two rows), logging an `ERROR`
+    ///      with each repeated key.
+    ///    - `SELECT` rows are sorted in the clustering order (or its reverse, as asked for in `ORDER BY`) and then
+    ///      cut to the `PER PARTITION LIMIT` and the `LIMIT` of the query, as each replica only limits its own rows.
     /// 3. **Filter and Join Columns**:
     ///    - Filters and organizes the rows based on the query's select columns and metadata from the response.
     /// 4. **Create Client Response**:
     ///    - Constructs the response frame for the client using the query's metadata and the final row set.
+    ///    - Queries that did not change anything (e.g. `DROP TABLE IF EXISTS` of a missing table) get a `Void` result.
     /// 5. **Send Response**:
     ///    - Sends the response frame to the client and ensures the connection is flushed.
+    ///    - If the client asked for the timings of the query, the time spent in the read repair and in the rest
+    ///      of the merge is recorded in them first.
+    /// 6. **Digest Mismatch**:
+    ///    - A read that asked some replicas for a digest of their rows is not closed if those digests disagree with
+    ///      the rows of the other replicas: those replicas are asked for their rows with `send_full_reads`, and the
+    ///      client is answered once they arrive, merged and read repaired as above.
+    /// 7. **Repair Inconsistent Nodes**:
+    ///    - After the response is sent, `start_read_repair` writes the most recent rows to the inconsistent nodes
+    ///      in the background, as often as the `read_repair_chance` of the table says, counting them in the
+    ///      read repair metrics of `query_handler`.
+    ///    - The reads of `REPAIR` (see `OpenQuery::forces_repair`) always write them, and before the response
+    ///      is sent, so the table is repaired once the command returns.
     ///
     /// # Errors
     /// - Returns `NodeError` in the following cases:
-    ///   - Issues during read repair or row consistency checks.
+    ///   - Issues during read repair or row consistency checks. Failed repair writes are only logged and counted.
+    ///   - Failures reading the answers spilled to disk.
     ///   - Errors in constructing or sending the client response frame.
     ///   - Connection write or flush failures.
     ///
@@ -208,105 +468,361 @@ impl InternodeProtocolHandler {
 
     pub fn add_ok_response_to_open_query_and_send_response_if_closed(
         query_handler: &mut OpenQueryHandler,
-        response: &InternodeResponse,
+        response: InternodeResponse,
         open_query_id: i32,
         keyspace_name: String,
         table: Option<TableSchema>,
         columns: Vec<Column>,
         self_ip: Ipv4Addr,
+        internode_port: u16,
         from: Ipv4Addr,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         partitioner: Partitioner,
-        storage_path: PathBuf,
+        storage_engine: StorageEngine,
         logger: Logger,
     ) -> Result<(), NodeError> {
-        if let Some(open_query) =
-            query_handler.add_ok_response_and_get_if_closed(open_query_id, response.clone(), from)
+        if let Some(mut open_query) =
+            query_handler.add_ok_response_and_get_if_closed(open_query_id, response, from)
         {
-            let contents_of_different_nodes = open_query.get_acumulated_responses();
+            let merge_started = Instant::now();
+            let mut repair = Duration::ZERO;
+            // The response that closed the query may be a digest, without the columns of the rows
+            let header = open_query
+                .get_acumulated_responses()
+                .iter()
+                .find_map(|(_, response)| response.content.as_ref())
+                .map(|content| (content.select_columns.clone(), content.columns.clone()));
             //here we have to determinated the more new row
             // and do READ REPAIR
 
+            // Counts that scan the table get rows, counted once they are merged
+            let scans = matches!(
+                (open_query.get_query(), &table),
+                (Query::Select(select), Some(table)) if scans_table(&select, table)
+            );
+            let is_count =
+                matches!(open_query.get_query(), Query::Select(select) if select.is_count());
+
+            let forced = open_query.forces_repair();
             let mut rows = vec![];
-            if let Some(table) = table {
-                rows = Self::read_repair(
-                    contents_of_different_nodes,
-                    columns.clone(),
-                    self_ip,
-                    keyspace_name.clone(),
-                    table.clone(),
-                    connections,
-                    partitioner,
-                    storage_path,
-                )?;
+            let mut repairs = vec![];
+            // Rows repaired and failed as the spilled batches of a forced read were merged, and the
+            // repairs of other reads past `MAX_READ_REPAIR_ROWS`
+            let (mut written, mut dropped) = ((0, 0), 0);
+            let repaired_table = table.clone();
+            if is_count && !scans {
+                // Replicas only answer with their count, there are no rows to repair
+                rows = Self::merge_counts(open_query.get_acumulated_responses());
+            } else if let Some(table) = table {
+                let repair_started = Instant::now();
+                if open_query.has_spilled_responses() {
+                    // Every version of a row is in the same batch, so each one is repaired on its own
+                    for batch in open_query.merge_batches()? {
+                        let (batch_rows, batch_repairs) =
+                            Self::read_repair(batch?, &columns, &partitioner)?;
+                        rows.extend(batch_rows);
+                        if forced {
+                            let (repaired, failed) = Self::write_repairs(
+                                batch_repairs,
+                                &keyspace_name,
+                                &table,
+                                self_ip,
+                                internode_port,
+                                &connections,
+                                storage_engine.clone(),
+                                &logger,
+                            );
+                            written = (written.0 + repaired, written.1 + failed);
+                        } else {
+                            let room = MAX_READ_REPAIR_ROWS.saturating_sub(repairs.len());
+                            dropped += batch_repairs.len().saturating_sub(room) as u64;
+                            repairs.extend(batch_repairs.into_iter().take(room));
+                        }
+                    }
+                } else {
+                    (rows, repairs) = Self::read_repair(
+                        open_query.take_acumulated_responses(),
+                        &columns,
+                        &partitioner,
+                    )?;
+                }
+                repair = repair_started.elapsed();
+
+                if cfg!(debug_assertions) {
+                    for key in Self::duplicate_primary_keys(&rows, &columns) {
+                        logger.error(
+                            &format!("INVARIANT: the primary key {} was returned twice", key),
+                            true,
+                        )?;
+                    }
+                }
 
-                rows = if let Some(content) = &response.content {
-                    Self::filter_and_join_columns(
-                        rows,
-                        content.select_columns.clone(),
-                        content.columns.clone(),
-                    )
+                // Clients get the values of the counters, not their shards
+                if columns
+                    .iter()
+                    .any(|column| column.data_type == DataType::Counter)
+                {
+                    rows = rows
+                        .iter()
+                        .map(|row| {
+                            let values: Vec<&str> = row.split(',').collect();
+                            sum_counter_cells(&values, &columns).join(",")
+                        })
+                        .collect();
+                }
+
+                // Rows are merged from different replicas, so they have to be sorted again, and
+                // limited again as each replica limits only the rows it read
+                if let Query::Select(select) = open_query.get_query() {
+                    let reversed = select
+                        .reverses_clustering_order(&table.get_clustering_key_columns())
+                        .map_err(NodeError::CQLError)?;
+                    Self::sort_by_clustering_order(&mut rows, &columns, &table, reversed);
+                    rows = RowLimits::of(&select, &columns).apply(rows);
+                }
+
+                rows = if is_count {
+                    vec![COUNT_RESULT_COLUMN.to_string(), rows.len().to_string()]
+                } else if let Some((select_columns, columns)) = header {
+                    Self::filter_and_join_columns(rows, select_columns, columns)
                 } else {
                     vec![]
                 };
             };
 
             let connection = open_query.get_connection();
-            let frame =
-                open_query
-                    .get_query()
-                    .create_client_response(columns, keyspace_name, rows)?;
+            let frame = if open_query.is_no_op() {
+                Frame::Result(result_::Result::Void)
+            } else {
+                open_query.get_query().create_client_response(
+                    columns,
+                    keyspace_name.clone(),
+                    rows,
+                )?
+            };
 
-            logger.info(
+            if let Some(timings) = open_query.get_timings() {
+                let mut timings = timings.lock()?;
+                timings.repair = repair;
+                timings.merge = merge_started.elapsed().saturating_sub(repair);
+            }
+
+            let read_repairs = query_handler.read_repairs();
+            let workers = query_handler.read_repair_workers();
+            let repair_logger = logger.clone();
+            let mut read_repair = match repaired_table {
+                Some(table) if !repairs.is_empty() || written != (0, 0) => Some(move || {
+                    Self::start_read_repair(
+                        repairs,
+                        written,
+                        dropped,
+                        forced,
+                        keyspace_name,
+                        table,
+                        self_ip,
+                        internode_port,
+                        connections,
+                        storage_engine,
+                        repair_logger,
+                        read_repairs,
+                        workers,
+                    )
+                }),
+                _ => None,
+            };
+            // REPAIR gets its answer once the outdated replicas are repaired
+            if forced {
+                if let Some(read_repair) = read_repair.take() {
+                    read_repair()?;
+                }
+            }
+
+            logger.with_component(Component::Native).info(
                 &format!("NATIVE: I sent FRAME RESPONSE to client",),
                 Color::Yellow,
                 true,
             )?;
 
-            connection.send(frame).map_err(|_| NodeError::OtherError)?;
-            Ok(())
+            let sent = connection.send(frame).map_err(|_| NodeError::OtherError);
+
+            // The client has its answer, the outdated replicas are repaired in the background
+            if let Some(read_repair) = read_repair {
+                read_repair()?;
+            }
+            sent
         } else {
-            Ok(())
+            Self::send_full_reads(
+                query_handler,
+                open_query_id,
+                self_ip,
+                internode_port,
+                connections,
+                &logger,
+            )
         }
     }
 
+    // Asks the replicas of a read that answered with a digest for their rows, once their digests disagreed
+    // with the rows of the other replicas (see `OpenQueryHandler::take_full_reads`). A replica that can not
+    // be reached counts as an error response of the read.
+    fn send_full_reads(
+        query_handler: &mut OpenQueryHandler,
+        open_query_id: i32,
+        self_ip: Ipv4Addr,
+        internode_port: u16,
+        connections: ConnectionManager,
+        logger: &Logger,
+    ) -> Result<(), NodeError> {
+        for (target, query) in query_handler.take_full_reads(open_query_id) {
+            logger.with_component(Component::Internode).info(
+                &format!(
+                    "INTERNODE (Query: {}): THE DIGESTS DISAGREE, I ASKED {:?} FOR ITS ROWS",
+                    open_query_id, target
+                ),
+                Color::Yellow,
+                true,
+            )?;
+            let sent = connect_and_send_message(
+                target,
+                internode_port,
+                connections.clone(),
+                InternodeMessage::new(self_ip, InternodeMessageContent::Query(query)),
+            );
+            if sent.is_err() {
+                Self::add_error_response_to_open_query_and_send_response_if_closed(
+                    query_handler,
+                    open_query_id,
+                    Some(target),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sorts the rows of a `SELECT` in the clustering order of the table, or in its reverse.
+    ///
+    /// # Purpose
+    /// Every replica answers with its rows already sorted, as they are stored in the clustering order
+    /// of the table, but read repair merges the answers of all of them without keeping that order.
+    /// This sorts the merged rows again, so clients get them in the order defined by
+    /// `WITH CLUSTERING ORDER BY`, or in its reverse if the query asks for it in `ORDER BY`.
+    ///
+    /// # Parameters
+    /// - `rows: &mut [String]`
+    ///   - The rows to sort, with their values separated by commas in the order of `columns`.
+    /// - `columns: &[Column]`
+    ///   - The columns of the table.
+    /// - `table: &TableSchema`
+    ///   - The table, whose clustering columns define the order.
+    /// - `reversed: bool`
+    ///   - Whether the rows go in the reverse of the clustering order.
+    ///
+    /// # Notes
+    /// - Values that can not be compared with the type of their column are compared as strings.
+    fn sort_by_clustering_order(
+        rows: &mut [String],
+        columns: &[Column],
+        table: &TableSchema,
+        reversed: bool,
+    ) {
+        let clustering_columns: Vec<(usize, &Column)> = table
+            .get_clustering_column_in_order()
+            .iter()
+            .filter_map(|name| columns.iter().enumerate().find(|(_, c)| c.name == *name))
+            .collect();
+
+        rows.sort_by(|a, b| {
+            let a_values: Vec<&str> = a.split(',').collect();
+            let b_values: Vec<&str> = b.split(',').collect();
+
+            let ordering = clustering_columns
+                .iter()
+                .map(|(index, column)| {
+                    let a_value = a_values.get(*index).copied().unwrap_or("");
+                    let b_value = b_values.get(*index).copied().unwrap_or("");
+                    let ordering = if a_value == b_value {
+                        std::cmp::Ordering::Equal
+                    } else {
+                        match column
+                            .data_type
+                            .compare(a_value, b_value, &Operator::Lesser)
+                        {
+                            Ok(true) => std::cmp::Ordering::Less,
+                            Ok(false) => std::cmp::Ordering::Greater,
+                            Err(_) => a_value.cmp(b_value),
+                        }
+                    };
+
+                    if column.get_clustering_order().to_uppercase() == "DESC" {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| *ordering != std::cmp::Ordering::Equal)
+                .unwrap_or(std::cmp::Ordering::Equal);
+
+            if reversed {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    /// Merges the answers of the replicas to a `SELECT COUNT(*)` into the rows of the client response.
+    ///
+    /// # Purpose
+    /// Replicas answer a count with a single value instead of the rows they read. Every replica that
+    /// answers holds a copy of the same partition, so adding the counts up would count each row once per
+    /// replica. The rows of the partition are counted once by taking the greatest count instead, which is
+    /// the one of the most up to date replica.
+    ///
+    /// # Parameters
+    /// - `contents_of_different_nodes: &[(Ipv4Addr, InternodeResponse)]`
+    ///   - The answers of the replicas, each one with a single value holding its count.
+    ///
+    /// # Returns
+    /// - `Vec<String>`
+    ///   - The header with the count column followed by the merged count, or `0` if no replica answered
+    ///     with a valid count.
+    fn merge_counts(contents_of_different_nodes: &[(Ipv4Addr, InternodeResponse)]) -> Vec<String> {
+        let count = contents_of_different_nodes
+            .iter()
+            .filter_map(|(_, response)| response.content.as_ref())
+            .filter_map(|content| content.values.first()?.first()?.parse::<i64>().ok())
+            .max()
+            .unwrap_or(0);
+
+        vec![COUNT_RESULT_COLUMN.to_string(), count.to_string()]
+    }
+
     /// Performs a read repair operation to ensure data consistency across nodes in a distributed database system.
     ///
     /// # Purpose
     /// Read repair is a fundamental mechanism in distributed databases to ensure eventual consistency.
     /// When data is read from multiple nodes, inconsistencies may arise due to network delays, partial failures,
-    /// or outdated replicas. This function identifies the most recent version of data for each key, updates
-    /// outdated nodes with the correct version, and returns the latest consistent data to the caller.
+    /// or outdated replicas. This function identifies the most recent version of data for each key, returns the
+    /// latest consistent data to the caller, and the writes that bring the outdated nodes up to date.
     ///
     /// # Parameters
     /// - `contents_of_different_nodes: Vec<(Ipv4Addr, InternodeResponse)>`
     ///   - A collection of responses from different nodes. Each response includes:
     ///     - The IP address of the responding node.
     ///     - An `InternodeResponse` containing query results and metadata.
-    /// - `columns: Vec<Column>`
-    ///   - A vector of column metadata that defines the structure of the table. This includes information about
+    /// - `columns: &[Column]`
+    ///   - The column metadata that defines the structure of the table. This includes information about
     ///     primary keys and clustering columns used to identify and order rows.
-    /// - `self_ip: Ipv4Addr`
-    ///   - The IP address of the current node performing the read repair.
-    /// - `keyspace_name: String`
-    ///   - The name of the keyspace associated with the table being queried.
-    /// - `table: TableSchema`
-    ///   - The schema of the table being queried. This includes details about columns, keys, and clustering order.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A thread-safe map of active connections to other nodes in the cluster.
-    ///     - Keys are node addresses as strings.
-    ///     - Values are thread-safe `TcpStream` objects for internode communication.
-    /// - `partitioner: Partitioner`
+    /// - `partitioner: &Partitioner`
     ///   - The partitioner responsible for determining the placement of data in the cluster based on primary keys.
-    /// - `storage_path: PathBuf`
-    ///   - The file system path for accessing local storage.
     ///
     /// # Returns
-    /// - `Result<Vec<String>, NodeError>`
+    /// - `Result<(Vec<String>, Vec<RepairWrite>), NodeError>`
     ///   - On success:
-    ///     - Returns a `Vec<String>` containing the rows of the latest consistent data, formatted as strings.
+    ///     - The rows of the latest consistent data, formatted as strings.
+    ///     - The latest version of every row some node answered with an older version of, to write to that node.
     ///   - On failure:
-    ///     - Returns `Err(NodeError)` if an error occurs during the repair process or node communication.
+    ///     - Returns `Err(NodeError)` if the owner of an outdated row can not be found.
     ///
     /// # Behavior
     /// 1. **Key Index Extraction**:
@@ -314,13 +830,11 @@ impl InternodeProtocolHandler {
     /// 2. **Identify Latest Versions**:
     ///    - Compares responses from all nodes to determine the most recent version of each row based on timestamps.
     ///    - Uses the `find_latest_versions` helper function to construct a mapping of keys to their latest values.
-    /// 3. **Repair Outdated Nodes**:
-    ///    - Updates nodes with outdated data by sending the latest version of inconsistent rows:
-    ///      - If the outdated node is not the current node (`self_ip`), sends an update query to the affected node.
-    ///      - If the outdated node is the current node, applies the update locally using the storage engine.
-    ///    - Uses the `repair_nodes` helper function for this step.
+    /// 3. **Find Outdated Nodes**:
+    ///    - Finds the nodes that answered with an older version of a row, using the `outdated_replicas` helper function.
+    ///    - Nothing is written here: `start_read_repair` writes the repairs once the client got its answer.
     /// 4. **Return Consistent Data**:
-    ///    - Returns the rows corresponding to the latest consistent data after performing repairs.
+    ///    - Returns the rows corresponding to the latest consistent data, with the repairs to write.
     ///
     /// # Key Internal Logic
     /// - **Primary and Clustering Keys**:
@@ -329,60 +843,238 @@ impl InternodeProtocolHandler {
     /// - **Timestamp Comparison**:
     ///   - Timestamps are used to identify the most recent version of a row.
     ///   - Rows with older timestamps are considered outdated and are repaired.
-    /// - **Node Communication**:
-    ///   - Uses internode communication to propagate updates to other nodes as part of the repair process.
     ///
     /// # Notes
     /// - This function is not public but is a cornerstone of maintaining consistency in the system.
     /// - It assumes that `contents_of_different_nodes` contains well-formed responses from participating nodes.
-    /// - Read repair can introduce additional network traffic and disk I/O, but it ensures that the database converges
-    ///   towards a consistent state.
-    ///
-    /// # Errors
-    /// - Returns `NodeError` in the following scenarios:
-    ///   - Failures in node communication while sending updates.
-    ///   - Issues in constructing or applying updates on local or remote nodes.
-    ///   - Errors in parsing or processing timestamps or values.
+    /// - Clients always get the latest version of every row, whether the outdated nodes are repaired or not.
     ///
     /// # Importance
     /// Read repair is critical for ensuring that distributed databases provide accurate and consistent results to users.
     /// While it is an internal mechanism, its role in reconciling inconsistencies makes it essential for achieving
     /// the system's eventual consistency guarantees.
-
     fn read_repair(
         contents_of_different_nodes: Vec<(Ipv4Addr, InternodeResponse)>,
-        columns: Vec<Column>,
-        self_ip: Ipv4Addr,
-        keyspace_name: String,
-        table: TableSchema,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-        partitioner: Partitioner,
-        storage_path: PathBuf,
-    ) -> Result<Vec<String>, NodeError> {
-        let primary_key_indices = Self::get_key_indices(&columns, true);
-        let clustering_column_indices = Self::get_key_indices(&columns, false);
+        columns: &[Column],
+        partitioner: &Partitioner,
+    ) -> Result<(Vec<String>, Vec<RepairWrite>), NodeError> {
+        let primary_key_indices = Self::get_key_indices(columns, true);
+        let clustering_column_indices = Self::get_key_indices(columns, false);
 
         let latest_versions = Self::find_latest_versions(
             &contents_of_different_nodes,
             &primary_key_indices,
             &clustering_column_indices,
+            columns,
         );
 
-        let updated_rows = Self::repair_nodes(
-            contents_of_different_nodes,
-            &columns,
+        let repairs = Self::outdated_replicas(
+            &contents_of_different_nodes,
             &primary_key_indices,
             &clustering_column_indices,
-            latest_versions,
-            &self_ip,
-            &keyspace_name,
-            table,
-            &connections,
-            &partitioner,
-            storage_path,
+            &latest_versions,
+            partitioner,
         )?;
 
-        Ok(updated_rows)
+        let rows = latest_versions
+            .into_values()
+            .map(|(_, _, value)| value.join(","))
+            .collect();
+
+        Ok((rows, repairs))
+    }
+
+    /// Writes the repairs found by `read_repair` to the outdated nodes in the background, after the client
+    /// got its answer, as often as the `read_repair_chance` of the table says.
+    ///
+    /// # Parameters
+    /// - `repairs: Vec<RepairWrite>`
+    ///   - The latest versions of the rows to write, each with the node to write it to.
+    /// - `written: (u64, u64)`
+    ///   - The rows of the read already repaired and failed, as its spilled batches were merged.
+    /// - `dropped: u64`
+    ///   - The repairs of the read dropped past `MAX_READ_REPAIR_ROWS`.
+    /// - `forced: bool`
+    ///   - Whether the read is one of `REPAIR`, whose rows are always written, before this returns.
+    /// - `keyspace_name: String` and `table: TableSchema`
+    ///   - The table the rows belong to, whose `read_repair_chance` decides whether they are written.
+    /// - `self_ip`, `internode_port`, `connections`, `storage_engine` and `logger`
+    ///   - What `write_repairs` needs to write the rows, to other nodes or to this one.
+    /// - `read_repairs: SharedReadRepairMetrics`
+    ///   - The read repair counters of the node, updated with the read and later with the rows written.
+    /// - `workers: Arc<WorkerPool>`
+    ///   - The workers that write the rows in the background.
+    ///
+    /// # Errors
+    /// - `NodeError::LockError` if the counters can not be locked.
+    ///
+    /// # Notes
+    /// - The repairs are best effort: the rows that can not be written are only logged and counted as failed,
+    ///   and are repaired by a later read or by `REPAIR`.
+    /// - If every worker is busy the rows are not written, and are counted as dropped instead, so reads that
+    ///   keep finding outdated replicas can not start a thread each.
+    fn start_read_repair(
+        repairs: Vec<RepairWrite>,
+        written: (u64, u64),
+        dropped: u64,
+        forced: bool,
+        keyspace_name: String,
+        table: TableSchema,
+        self_ip: Ipv4Addr,
+        internode_port: u16,
+        connections: ConnectionManager,
+        storage_engine: StorageEngine,
+        logger: Logger,
+        read_repairs: SharedReadRepairMetrics,
+        workers: Arc<WorkerPool>,
+    ) -> Result<(), NodeError> {
+        let table_name = table.get_name();
+        let repairs_now = forced || Self::rolls(table.get_options().read_repair_chance);
+        {
+            let mut read_repairs = read_repairs.lock()?;
+            read_repairs.record_mismatch(&keyspace_name, &table_name, repairs_now);
+            if repairs_now && dropped > 0 {
+                read_repairs.record_dropped(&keyspace_name, &table_name, dropped);
+            }
+        }
+        if !repairs_now {
+            logger.info(
+                &format!(
+                    "READ REPAIR: I SKIPPED {} outdated rows of {}.{}",
+                    repairs.len(),
+                    keyspace_name,
+                    table_name
+                ),
+                Color::Magenta,
+                true,
+            )?;
+            return Ok(());
+        }
+
+        let rows = repairs.len() as u64;
+        let repair_read_repairs = read_repairs.clone();
+        let repair_logger = logger.clone();
+        let (repair_keyspace, repair_table) = (keyspace_name.clone(), table_name.clone());
+        let repair = move || {
+            let (repaired, failed) = Self::write_repairs(
+                repairs,
+                &repair_keyspace,
+                &table,
+                self_ip,
+                internode_port,
+                &connections,
+                storage_engine,
+                &repair_logger,
+            );
+            if let Ok(mut read_repairs) = repair_read_repairs.lock() {
+                read_repairs.record_rows(
+                    &repair_keyspace,
+                    &repair_table,
+                    written.0 + repaired,
+                    written.1 + failed,
+                );
+            }
+        };
+        if forced {
+            repair();
+        } else if let Err(e) = workers.execute(repair) {
+            read_repairs
+                .lock()?
+                .record_dropped(&keyspace_name, &table_name, rows);
+            logger.warn(
+                &format!(
+                    "READ REPAIR: I DROPPED {} outdated rows of {}.{}: {}",
+                    rows, keyspace_name, table_name, e
+                ),
+                true,
+            )?;
+        }
+        Ok(())
+    }
+
+    // Returns true with probability `chance`, between 0 and 1
+    fn rolls(chance: f64) -> bool {
+        chance >= 1.0 || (Uuid::new_v4().as_u128() as u64 as f64 / u64::MAX as f64) < chance
+    }
+
+    // Writes each repair to its node, the ones of this node straight to its storage, and returns
+    // how many rows were written and how many failed
+    fn write_repairs(
+        repairs: Vec<RepairWrite>,
+        keyspace_name: &String,
+        table: &TableSchema,
+        self_ip: Ipv4Addr,
+        internode_port: u16,
+        connections: &ConnectionManager,
+        storage_engine: StorageEngine,
+        logger: &Logger,
+    ) -> (u64, u64) {
+        let table_name = &table.get_name();
+        let columns = table.get_columns();
+        let (mut repaired, mut failed) = (0, 0);
+
+        for repair in repairs {
+            let written = if repair.replica != self_ip {
+                let insert =
+                    Self::generate_insert(keyspace_name, table_name, &columns, &repair.row);
+                logger
+                    .info(
+                        &format!(
+                            "READ REPAIR: I SENT {:?} to {:?}",
+                            insert.to_cql(),
+                            repair.replica
+                        ),
+                        Color::Magenta,
+                        true,
+                    )
+                    .ok();
+                Self::send_update_to_node(
+                    repair.replica,
+                    internode_port,
+                    connections,
+                    insert,
+                    &self_ip,
+                    keyspace_name,
+                    repair.replication,
+                    logger.correlation_id().unwrap_or_default(),
+                )
+            } else {
+                logger
+                    .info(
+                        &format!("READ REPAIR: I REPAIRED {:?} locally", repair.row),
+                        Color::Magenta,
+                        true,
+                    )
+                    .ok();
+                Self::update_this_node(
+                    keyspace_name,
+                    repair.replication,
+                    table_name,
+                    &repair.row,
+                    table.get_clustering_column_in_order(),
+                    &columns,
+                    storage_engine.clone(),
+                )
+            };
+
+            match written {
+                Ok(()) => repaired += 1,
+                Err(e) => {
+                    failed += 1;
+                    logger
+                        .error(
+                            &format!(
+                                "READ REPAIR: I could not repair a row of {}.{} on {}: {}",
+                                keyspace_name, table_name, repair.replica, e
+                            ),
+                            true,
+                        )
+                        .ok();
+                }
+            }
+        }
+
+        (repaired, failed)
     }
 
     fn get_key_indices(columns: &[Column], is_partition_key: bool) -> Vec<usize> {
@@ -401,12 +1093,19 @@ impl InternodeProtocolHandler {
             .collect()
     }
 
+    // Returns the latest version of each row, by its primary key, with the node that sent it. The
+    // counters of the latest versions hold the shards of every version, as each replica may hold
+    // shards the others did not receive yet.
     fn find_latest_versions(
         contents_of_different_nodes: &[(Ipv4Addr, InternodeResponse)],
         primary_key_indices: &[usize],
         clustering_column_indices: &[usize],
+        columns: &[Column],
     ) -> HashMap<String, (Ipv4Addr, i64, Vec<String>)> {
         let mut latest_versions: HashMap<String, (Ipv4Addr, i64, Vec<String>)> = HashMap::new();
+        let has_counters = columns
+            .iter()
+            .any(|column| column.data_type == DataType::Counter);
 
         for (node_ip, response) in contents_of_different_nodes {
             if let Some(content) = &response.content {
@@ -415,10 +1114,16 @@ impl InternodeProtocolHandler {
                         Self::build_key(value, primary_key_indices, clustering_column_indices);
                     let current_timestamp = Self::get_timestamp(value);
 
-                    if let Some((_, latest_timestamp, _)) = latest_versions.get(&key) {
+                    if let Some((_, latest_timestamp, latest_value)) = latest_versions.get_mut(&key)
+                    {
                         if *latest_timestamp < current_timestamp {
-                            latest_versions
-                                .insert(key, (*node_ip, current_timestamp, value.clone()));
+                            let mut value = value.clone();
+                            if has_counters {
+                                merge_counter_cells(&mut value, latest_value, columns);
+                            }
+                            latest_versions.insert(key, (*node_ip, current_timestamp, value));
+                        } else if has_counters {
+                            merge_counter_cells(latest_value, value, columns);
                         }
                     } else {
                         latest_versions.insert(key, (*node_ip, current_timestamp, value.clone()));
@@ -447,27 +1152,49 @@ impl InternodeProtocolHandler {
         key_components.join("|")
     }
 
+    // Returns the primary keys (partition key and clustering columns) that appear in more than one of
+    // the merged `rows` of a `SELECT`, which must never be returned to a client.
+    fn duplicate_primary_keys(rows: &[String], columns: &[Column]) -> Vec<String> {
+        let primary_key_indices = Self::get_key_indices(columns, true);
+        let clustering_column_indices = Self::get_key_indices(columns, false);
+
+        let mut seen = HashMap::new();
+        for row in rows {
+            let values: Vec<String> = row.split(',').map(str::to_string).collect();
+            let key = Self::build_key(&values, &primary_key_indices, &clustering_column_indices);
+            *seen.entry(key).or_insert(0) += 1;
+        }
+        let mut duplicates: Vec<String> = seen
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(key, _)| key)
+            .collect();
+        duplicates.sort();
+        duplicates
+    }
+
     fn get_timestamp(value: &[String]) -> i64 {
-        let timestamp_index = value.len() - 1;
-        value[timestamp_index].parse::<i64>().unwrap_or(0)
+        Self::get_stamp(value).timestamp
     }
 
-    fn repair_nodes(
-        contents_of_different_nodes: Vec<(Ipv4Addr, InternodeResponse)>,
-        columns: &[Column],
+    // Returns the stamp sent after the values of a row, with its timestamp and expiration.
+    fn get_stamp(value: &[String]) -> RowStamp {
+        value
+            .last()
+            .and_then(|stamp| stamp.parse().ok())
+            .unwrap_or(RowStamp::from(0))
+    }
+
+    // Returns the latest version of every row a node answered with an older version of, to write to it
+    fn outdated_replicas(
+        contents_of_different_nodes: &[(Ipv4Addr, InternodeResponse)],
         primary_key_indices: &[usize],
         clustering_column_indices: &[usize],
-        latest_versions: HashMap<String, (Ipv4Addr, i64, Vec<String>)>,
-        self_ip: &Ipv4Addr,
-        keyspace_name: &String,
-        table: TableSchema,
-        connections: &Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        latest_versions: &HashMap<String, (Ipv4Addr, i64, Vec<String>)>,
         partitioner: &Partitioner,
-        storage_path: PathBuf,
-    ) -> Result<Vec<String>, NodeError> {
-        let mut updated_rows: Vec<String> = Vec::new();
-        let table_name = &table.get_name();
-        for (node_ip, response) in &contents_of_different_nodes {
+    ) -> Result<Vec<RepairWrite>, NodeError> {
+        let mut repairs = Vec::new();
+        for (node_ip, response) in contents_of_different_nodes {
             if let Some(content) = &response.content {
                 for value in &content.values {
                     let key =
@@ -479,61 +1206,23 @@ impl InternodeProtocolHandler {
                         let current_timestamp = Self::get_timestamp(value);
 
                         if node_ip != latest_ip && current_timestamp < *latest_timestamp {
-                            let insert_query = Self::generate_insert_query(
-                                keyspace_name,
-                                table_name,
-                                columns,
-                                latest_value,
-                            );
-
-                            let replication = Self::get_is_replication(
-                                latest_value,
-                                primary_key_indices,
-                                partitioner,
-                                node_ip,
-                            )?;
-
-                            if node_ip != self_ip {
-                                Self::send_update_to_node(
-                                    *node_ip,
-                                    connections,
-                                    insert_query,
-                                    self_ip,
-                                    keyspace_name,
-                                    replication,
-                                )?;
-                            } else {
-                                let latest_values = latest_value
-                                    .iter()
-                                    .map(|v| v.as_str())
-                                    .take(latest_value.len() - 1)
-                                    .collect();
-
-                                Self::update_this_node(
-                                    self_ip,
-                                    keyspace_name,
-                                    replication,
-                                    table_name,
-                                    latest_values,
-                                    table.get_clustering_column_in_order(),
-                                    columns,
-                                    storage_path.clone(),
-                                )?;
-                                // Opcional: manejar lógica para actualizar el propio nodo si es necesario
-                            }
+                            repairs.push(RepairWrite {
+                                replica: *node_ip,
+                                row: latest_value.clone(),
+                                replication: Self::get_is_replication(
+                                    latest_value,
+                                    primary_key_indices,
+                                    partitioner,
+                                    node_ip,
+                                )?,
+                            });
                         }
                     }
                 }
             }
         }
 
-        updated_rows.extend(
-            latest_versions
-                .into_iter()
-                .map(|(_, (_, _, value))| value.join(",")),
-        );
-
-        Ok(updated_rows)
+        Ok(repairs)
     }
 
     fn get_is_replication(
@@ -556,71 +1245,81 @@ impl InternodeProtocolHandler {
         Ok(is_replication)
     }
 
-    fn generate_insert_query(
-        keyspace_name: &String,
-        table_name: &String,
+    // Returns the insert of the latest version of a row, sent already parsed to the replica that
+    // answered with an older one
+    fn generate_insert(
+        keyspace_name: &str,
+        table_name: &str,
         columns: &[Column],
         latest_value: &[String],
-    ) -> String {
-        let mut insert_query = format!("INSERT INTO {}.{} (", keyspace_name, table_name);
-
-        insert_query.push_str(
-            &columns
-                .iter()
-                .map(|col| col.name.clone())
-                .collect::<Vec<String>>()
-                .join(","),
-        );
-        insert_query.push_str(") VALUES (");
-
-        insert_query.push_str(
-            &latest_value
+    ) -> InternodeStatement {
+        InternodeStatement::Insert(Insert {
+            values: latest_value
                 .iter()
                 .take(latest_value.len().saturating_sub(1))
-                .map(|val| format!("'{}'", val))
-                .collect::<Vec<String>>()
-                .join(","),
-        );
-        insert_query.push_str(");");
-
-        insert_query
+                .cloned()
+                .collect(),
+            into_clause: into_cql::Into {
+                table_name: table_name.to_string(),
+                keyspace_used_name: keyspace_name.to_string(),
+                columns: columns.iter().map(|col| col.name.clone()).collect(),
+            },
+            if_not_exists: false,
+            // The repaired row expires when the latest one does
+            ttl: Self::get_stamp(latest_value).remaining_ttl(Utc::now().timestamp()),
+        })
     }
 
     fn send_update_to_node(
         node_ip: Ipv4Addr,
-        connections: &Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-        query: String,
+        port: u16,
+        connections: &ConnectionManager,
+        statement: InternodeStatement,
         self_ip: &Ipv4Addr,
         keyspace_name: &String,
         replication: bool,
+        correlation_id: &str,
     ) -> Result<(), NodeError> {
         let message = InternodeMessage::new(
             *self_ip,
             InternodeMessageContent::Query(InternodeQuery {
-                query_string: query,
+                query_string: String::new(),
                 open_query_id: 0,
                 client_id: 0,
                 replication: replication,
                 keyspace_name: keyspace_name.clone(),
                 timestamp: Utc::now().timestamp(),
+                correlation_id: correlation_id.to_string(),
+                statement: Some(statement),
+                time_left: None,
+                digest: false,
             }),
         );
 
-        connect_and_send_message(node_ip, INTERNODE_PORT, connections.clone(), message)?;
+        connect_and_send_message(node_ip, port, connections.clone(), message)?;
         Ok(())
     }
 
     fn update_this_node(
-        self_ip: &Ipv4Addr,
         keyspace_name: &String,
         replication: bool,
         table_name: &String,
-        values: Vec<&str>,
+        latest_value: &[String],
         clustering_columns_in_order: Vec<String>,
         columns: &[Column],
-        path: PathBuf,
+        storage_engine: StorageEngine,
     ) -> Result<(), NodeError> {
-        storage_engine::StorageEngine::new(path, self_ip.to_string()).insert(
+        let values = latest_value
+            .iter()
+            .map(|v| v.as_str())
+            .take(latest_value.len() - 1)
+            .collect();
+        // The repaired row expires when the latest one does
+        let stamp = RowStamp {
+            timestamp: Utc::now().timestamp(),
+            expires_at: Self::get_stamp(latest_value).expires_at,
+        };
+        storage_engine.insert(
             &keyspace_name,
             &table_name,
             values,
@@ -628,7 +1327,7 @@ impl InternodeProtocolHandler {
             clustering_columns_in_order,
             replication,
             false,
-            Utc::now().timestamp(),
+            stamp,
         )?;
         Ok(())
     }
@@ -682,6 +1381,8 @@ impl InternodeProtocolHandler {
     ///   - This handler is used to mark the query with an error response and check if all responses have been received.
     /// - `open_query_id: i32`
     ///   - The unique identifier of the open query being processed.
+    /// - `from: Option<Ipv4Addr>`
+    ///   - The replica that failed the query, recorded as failed in the open query, if it is known.
     ///
     /// # Returns
     /// - `Result<(), NodeError>`
@@ -720,12 +1421,14 @@ impl InternodeProtocolHandler {
     pub fn add_error_response_to_open_query_and_send_response_if_closed(
         query_handler: &mut OpenQueryHandler,
         open_query_id: i32,
+        from: Option<Ipv4Addr>,
     ) -> Result<(), NodeError> {
-        if let Some(open_query) = query_handler.add_error_response_and_get_if_closed(open_query_id)
+        if let Some(open_query) =
+            query_handler.add_error_response_and_get_if_closed(open_query_id, from)
         {
             let connection = open_query.get_connection();
 
-            let error_frame = Frame::Error(error::Error::ServerError(".".to_string()));
+            let error_frame = open_query.failure_error();
 
             connection
                 .send(error_frame)
@@ -741,17 +1444,17 @@ impl InternodeProtocolHandler {
         &self,
         node: &Arc<Mutex<Node>>,
         query: InternodeQuery,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         node_ip: Ipv4Addr,
+        logger: Logger,
     ) -> Result<(), NodeError> {
+        let deadline = query.deadline(Instant::now());
         if query.needs_keyspace() {
-            let q = QueryCreator::new().handle_query(query.query_string.clone())?;
-            check_keyspace(node, &q, query.client_id as i32, 6)?;
+            check_keyspace(node, &query.to_query()?, query.client_id as i32, 6)?;
         }
 
         if query.needs_table() {
-            let q = QueryCreator::new().handle_query(query.query_string.clone())?;
-            check_table(node, &q, query.client_id as i32, 6)?;
+            check_table(node, &query.to_query()?, query.client_id as i32, 6)?;
         }
 
         if query.keyspace_name != "None" {
@@ -765,15 +1468,37 @@ impl InternodeProtocolHandler {
             }
         }
 
-        let self_ip;
-        let logger;
-        {
-            let guard_node = node.lock()?;
-            self_ip = guard_node.get_ip();
-            logger = guard_node.get_logger();
-        };
+        let self_ip = { node.lock()?.get_ip() };
         let query_split: Vec<&str> = query.query_string.split_whitespace().collect();
-        let result: Result<Option<((i32, i32), InternodeResponse)>, NodeError> =
+        let result: Result<Option<((i32, i32), InternodeResponse)>, NodeError> = if deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            // The client already got a timeout (for example, the node waited for the schema of
+            // the query), so the query is not executed
+            logger.warn(
+                    &format!(
+                        "INTERNODE (Query: {:?}): THE COORDINATOR STOPPED WAITING FOR {:?}, NOT EXECUTED",
+                        query.open_query_id, query.cql()
+                    ),
+                    true,
+                )?;
+            Ok(Some((
+                (0, 0),
+                InternodeResponse::from_error(query.open_query_id, &NodeError::DeadlineExceeded),
+            )))
+        } else if let Some(statement) = query.statement.clone() {
+            // Data statements sent already parsed are executed without parsing the query string
+            Self::handle_statement_command(
+                node,
+                statement,
+                connections.clone(),
+                query.replication,
+                query.open_query_id as i32,
+                query.client_id as i32,
+                query.timestamp,
+                &logger,
+            )
+        } else {
             match query_split[0] {
                 "CREATE" => match query_split[1] {
                     "TABLE" => Self::handle_create_table_command(
@@ -783,6 +1508,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     "KEYSPACE" => Self::handle_create_keyspace_command(
                         node,
@@ -791,6 +1517,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     _ => Err(NodeError::InternodeProtocolError),
                 },
@@ -802,6 +1529,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     "KEYSPACE" => Self::handle_drop_keyspace_command(
                         node,
@@ -810,6 +1538,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     _ => Err(NodeError::InternodeProtocolError),
                 },
@@ -821,6 +1550,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     "KEYSPACE" => Self::handle_alter_keyspace_command(
                         node,
@@ -829,6 +1559,7 @@ impl InternodeProtocolHandler {
                         true,
                         query.open_query_id as i32,
                         query.client_id as i32,
+                        &logger,
                     ),
                     _ => Err(NodeError::InternodeProtocolError),
                 },
@@ -841,6 +1572,7 @@ impl InternodeProtocolHandler {
                     query.open_query_id as i32,
                     query.client_id as i32,
                     query.timestamp,
+                    &logger,
                 ),
                 "UPDATE" => Self::handle_update_command(
                     node,
@@ -851,6 +1583,7 @@ impl InternodeProtocolHandler {
                     query.open_query_id as i32,
                     query.client_id as i32,
                     query.timestamp,
+                    &logger,
                 ),
                 "DELETE" => Self::handle_delete_command(
                     node,
@@ -861,6 +1594,7 @@ impl InternodeProtocolHandler {
                     query.open_query_id as i32,
                     query.client_id as i32,
                     query.timestamp,
+                    &logger,
                 ),
                 "SELECT" => Self::handle_select_command(
                     node,
@@ -870,6 +1604,7 @@ impl InternodeProtocolHandler {
                     query.replication,
                     query.open_query_id as i32,
                     query.client_id as i32,
+                    &logger,
                 ),
                 "USE" => Self::handle_use_command(
                     node,
@@ -878,20 +1613,33 @@ impl InternodeProtocolHandler {
                     true,
                     query.open_query_id as i32,
                     query.client_id as i32,
+                    &logger,
                 ),
                 _ => Err(NodeError::InternodeProtocolError),
-            };
+            }
+        };
 
         let response: Option<((i32, i32), InternodeResponse)> = result?;
 
         if let Some(responses) = response {
-            let (_, value): ((i32, i32), InternodeResponse) = responses.clone();
+            let (_, mut value): ((i32, i32), InternodeResponse) = responses.clone();
+            // The coordinator reads the rows from another replica, and only compares them with these
+            if query.digest {
+                value = value.into_digest();
+            }
+            let (value, port) = {
+                let node_guard = node.lock()?;
+                (
+                    value.with_generation(node_guard.generation_stamp()),
+                    node_guard.config.internode_port,
+                )
+            };
 
             if query.open_query_id != 0 {
                 logger.info(
                     &format!(
-                        "INTERNODE (Query: {:?}): I SENT OK to coordinator node: {:?}",
-                        query.open_query_id, node_ip
+                        "INTERNODE (Query: {:?}): I SENT {} to coordinator node: {:?}",
+                        query.open_query_id, value.status, node_ip
                     ),
                     Color::Green,
                     true,
@@ -899,7 +1647,7 @@ impl InternodeProtocolHandler {
 
                 connect_and_send_message(
                     node_ip,
-                    INTERNODE_PORT,
+                    port,
                     connections,
                     InternodeMessage {
                         from: self_ip,
@@ -912,29 +1660,103 @@ impl InternodeProtocolHandler {
         Ok(())
     }
 
+    // Stores the rows of a chunk streamed to the node, with the stamps they were stored with. The
+    // chunk is rejected if it was corrupted on its way or its table is not known yet, and then it
+    // is sent again later.
+    fn store_stream_chunk(node: &Arc<Mutex<Node>>, chunk: &StreamChunk) -> Result<(), NodeError> {
+        if !chunk.is_intact() {
+            return Err(NodeError::InternodeProtocolError);
+        }
+        let (storage_engine, commit_log, table) = {
+            let node_guard = node.lock()?;
+            let table = node_guard
+                .get_keyspace(&chunk.keyspace)?
+                .ok_or(NodeError::KeyspaceError)?
+                .get_table(&chunk.table)
+                .map_err(|_| NodeError::KeyspaceError)?;
+            (
+                node_guard.storage_engine.clone(),
+                node_guard.commit_log.clone(),
+                table,
+            )
+        };
+
+        let now = RowStamp::now();
+        for row in &chunk.rows {
+            let (values, stamp) = split_row(row)?;
+            // Rows that expired on their way are not stored
+            if stamp.is_expired(now) {
+                continue;
+            }
+            let values: Vec<&str> = values.split(',').collect();
+            let _logged = CommitLog::log(
+                &commit_log,
+                &CommitLogEntry {
+                    keyspace: chunk.keyspace.clone(),
+                    table: chunk.table.clone(),
+                    is_replication: chunk.replication,
+                    timestamp: stamp.timestamp,
+                    mutation: Mutation::insert(
+                        &table,
+                        values.iter().map(|value| value.to_string()).collect(),
+                        false,
+                        stamp.ttl(),
+                    ),
+                },
+            )?;
+            storage_engine.insert(
+                &chunk.keyspace,
+                &chunk.table,
+                values,
+                table.get_columns(),
+                table.get_clustering_column_in_order(),
+                chunk.replication,
+                false,
+                stamp,
+            )?;
+        }
+        Ok(())
+    }
+
     // Handles a response command from another node.
     fn handle_response_command(
         &self,
         node: &Arc<Mutex<Node>>,
-        response: &InternodeResponse,
+        response: InternodeResponse,
         from: Ipv4Addr,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
     ) -> Result<(), NodeError> {
         let self_ip;
+        let internode_port;
         let partitioner;
-        let storage_path;
+        let storage_engine;
         let logger;
         {
-            let guard_node = node.lock()?;
+            let mut guard_node = node.lock()?;
             self_ip = guard_node.get_ip();
+            internode_port = guard_node.config.internode_port;
             partitioner = guard_node.get_partitioner();
-            storage_path = guard_node.storage_path.clone();
-            logger = guard_node.get_logger();
+            storage_engine = guard_node.storage_engine.clone();
+            logger = guard_node.get_logger().with_component(Component::Internode);
+            if let Some(generation) = response.generation {
+                guard_node.check_replica_generation(
+                    from,
+                    generation,
+                    connections.clone(),
+                    &logger,
+                )?;
+            }
         }
         let mut guard_node = node.lock()?;
 
         let query_handler = guard_node.get_open_handle_query();
 
+        // Tags the logs with the id of the client query this response belongs to
+        let logger = match query_handler.get_query_mut(&(response.open_query_id as i32)) {
+            Some(open_query) => logger.with_correlation_id(&open_query.get_correlation_id()),
+            None => logger,
+        };
+
         let keyspace = query_handler.get_keyspace_of_query(response.open_query_id as i32)?;
 
         let keyspace_name = if let Some(value) = keyspace {
@@ -954,92 +1776,134 @@ impl InternodeProtocolHandler {
                     true,
                 )?;
 
+                let open_query_id = response.open_query_id as i32;
                 self.process_ok_response(
                     query_handler,
                     response,
-                    response.open_query_id as i32,
+                    open_query_id,
                     keyspace_name,
                     self_ip,
+                    internode_port,
                     from,
                     connections,
                     partitioner,
-                    storage_path.clone(),
+                    storage_engine.clone(),
                     logger,
                 )?;
             }
-            InternodeResponseStatus::Error => {
-                logger.info(
-                    &format!(
-                        "INTERNODE (Query: {}): I RECEIVED OK RESPONSE {:?} from {:?}",
-                        response.open_query_id, response.status, from
-                    ),
-                    Color::Red,
-                    true,
-                )?;
-                self.process_error_response(query_handler, response.open_query_id as i32)?;
+            status => {
+                // A replica that does not own the partition anymore is replaced by the current one
+                let retried = status == InternodeResponseStatus::NotOwner
+                    && Self::retry_on_current_replica(
+                        query_handler,
+                        response.open_query_id as i32,
+                        self_ip,
+                        internode_port,
+                        from,
+                        connections,
+                        &partitioner,
+                        &logger,
+                    )?;
+
+                if !retried {
+                    logger.info(
+                        &format!(
+                            "INTERNODE (Query: {}): I RECEIVED {} RESPONSE from {:?}: {}",
+                            response.open_query_id,
+                            status,
+                            from,
+                            response.detail.as_deref().unwrap_or("no detail")
+                        ),
+                        Color::Red,
+                        true,
+                    )?;
+                    self.process_error_response(
+                        query_handler,
+                        response.open_query_id as i32,
+                        from,
+                    )?;
+                }
             }
         }
 
         Ok(())
     }
 
+    // Sends again a query rejected with `NotOwner` by `from`, to the node that should get it according
+    // to the latest ring: the owner of the partition, or one of its successors for a replication query.
+    // Returns whether the query was sent again; if not, the response must be handled as an error.
+    fn retry_on_current_replica(
+        query_handler: &mut OpenQueryHandler,
+        open_query_id: i32,
+        self_ip: Ipv4Addr,
+        internode_port: u16,
+        from: Ipv4Addr,
+        connections: ConnectionManager,
+        partitioner: &Partitioner,
+        logger: &Logger,
+    ) -> Result<bool, NodeError> {
+        let (query, partition_value, sent_to) =
+            match query_handler.get_query_to_retry(open_query_id, from) {
+                Some(value) => value,
+                None => return Ok(false),
+            };
+
+        let owner = partitioner.get_ip(partition_value)?;
+        let replicas = if query.replication {
+            let keyspace = query_handler
+                .get_keyspace_of_query(open_query_id)?
+                .ok_or(NodeError::KeyspaceError)?;
+            get_replicas(partitioner, owner, &keyspace)?
+        } else {
+            vec![owner]
+        };
+
+        let target = match replicas.into_iter().find(|ip| !sent_to.contains(ip)) {
+            Some(ip) => ip,
+            None => return Ok(false),
+        };
+
+        logger.info(
+            &format!(
+                "INTERNODE (Query: {}): {:?} IS NOT A REPLICA ANYMORE, I SENT THE QUERY AGAIN to {:?}",
+                open_query_id, from, target
+            ),
+            Color::Yellow,
+            true,
+        )?;
+
+        query_handler.record_retry(open_query_id, from, target, query.clone());
+        let sent = connect_and_send_message(
+            target,
+            internode_port,
+            connections,
+            InternodeMessage::new(self_ip, InternodeMessageContent::Query(query)),
+        );
+
+        Ok(sent.is_ok())
+    }
+
     // Handles a gossip command from another node.
     // This function is responsible for processing the gossip message and responding accordingly.
     fn handle_gossip_command(
         &self,
         node: &Arc<Mutex<Node>>,
         gossip_message: &GossipMessage,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
     ) -> Result<(), NodeError> {
         let mut guard_node = node.lock()?;
 
-        match &gossip_message.payload {
-            gossip::messages::Payload::Syn(syn) => {
-                let ack = guard_node.gossiper.handle_syn(syn);
-
-                let msg =
-                    GossipMessage::new(guard_node.get_ip(), gossip::messages::Payload::Ack(ack));
-
-                let result = connect_and_send_message(
-                    gossip_message.from,
-                    INTERNODE_PORT,
-                    connections,
-                    InternodeMessage::new(
-                        guard_node.get_ip(),
-                        InternodeMessageContent::Gossip(msg),
-                    ),
-                );
-
-                if result.is_err() {
-                    guard_node.gossiper.kill(gossip_message.from).ok();
-                }
-            }
-            gossip::messages::Payload::Ack(ack) => {
-                let ack2 = guard_node.gossiper.handle_ack(ack);
-
-                let msg =
-                    GossipMessage::new(guard_node.get_ip(), gossip::messages::Payload::Ack2(ack2));
-
-                let result = connect_and_send_message(
-                    gossip_message.from,
-                    INTERNODE_PORT,
-                    connections,
-                    InternodeMessage::new(
-                        guard_node.get_ip(),
-                        InternodeMessageContent::Gossip(msg),
-                    ),
-                );
-
-                if result.is_err() {
-                    //println!("Node is dead: {:?}", gossip_message.from);
-                    guard_node.gossiper.kill(gossip_message.from).ok();
-                }
-            }
-
-            gossip::messages::Payload::Ack2(ack2) => {
-                guard_node.gossiper.handle_ack2(ack2);
-            }
-        };
+        let ip = guard_node.get_ip();
+        let transport = InternodeGossipTransport::new(
+            ip,
+            guard_node.config.internode_port,
+            connections,
+            guard_node.blocked_peers.clone(),
+        );
+        guard_node
+            .gossiper
+            .handle_message(ip, gossip_message, &transport)
+            .ok();
 
         Ok(())
     }
@@ -1048,14 +1912,15 @@ impl InternodeProtocolHandler {
     fn process_ok_response(
         &self,
         query_handler: &mut OpenQueryHandler,
-        response: &InternodeResponse,
+        response: InternodeResponse,
         open_query_id: i32,
         keyspace_name: String,
         self_ip: Ipv4Addr,
+        internode_port: u16,
         from: Ipv4Addr,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         partitioner: Partitioner,
-        storage_path: PathBuf,
+        storage_engine: StorageEngine,
         logger: Logger,
     ) -> Result<(), NodeError> {
         // Obtener la consulta abierta
@@ -1084,10 +1949,11 @@ impl InternodeProtocolHandler {
             table,
             columns,
             self_ip,
+            internode_port,
             from,
             connections,
             partitioner,
-            storage_path,
+            storage_engine,
             logger,
         )?;
 
@@ -1099,251 +1965,580 @@ impl InternodeProtocolHandler {
         &self,
         query_handler: &mut OpenQueryHandler,
         open_query_id: i32,
+        from: Ipv4Addr,
     ) -> Result<(), NodeError> {
         Self::add_error_response_to_open_query_and_send_response_if_closed(
             query_handler,
             open_query_id,
+            Some(from),
         )?;
 
         Ok(())
     }
 
+    // Handles a data statement sent already parsed by the coordinator.
+    fn handle_statement_command(
+        node: &Arc<Mutex<Node>>,
+        statement: InternodeStatement,
+        connections: ConnectionManager,
+        replication: bool,
+        open_query_id: i32,
+        client_id: i32,
+        timestamp: i64,
+        logger: &Logger,
+    ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
+        // Reads are not timestamped
+        let timestamp = match statement {
+            InternodeStatement::Select(_) => None,
+            _ => Some(timestamp),
+        };
+        let mut execution =
+            QueryExecution::new(node.clone(), connections)?.with_logger(logger.clone());
+
+        // The part of a batch sent to this node is answered with a single response
+        if let InternodeStatement::Batch(statements) = statement {
+            let response = match execution.execute_batch_part(
+                statements,
+                open_query_id,
+                client_id,
+                timestamp.unwrap_or_default(),
+            ) {
+                Ok(_) => {
+                    InternodeResponse::new(open_query_id as u32, InternodeResponseStatus::Ok, None)
+                }
+                Err(e) => InternodeResponse::from_error(open_query_id as u32, &e),
+            };
+            return Ok(Some(((0, 0), response)));
+        }
+
+        execution.execute(
+            statement.into(),
+            true,
+            replication,
+            open_query_id,
+            client_id,
+            timestamp,
+        )
+    }
+
     // Handles an `INSERT` command.
     fn handle_insert_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         replication: bool,
         open_query_id: i32,
         client_id: i32,
         timestamp: i64,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = Insert::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::Insert(query),
-            internode,
-            replication,
-            open_query_id,
-            client_id,
-            Some(timestamp),
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::Insert(query),
+                internode,
+                replication,
+                open_query_id,
+                client_id,
+                Some(timestamp),
+            )
     }
 
     // Handles a `CREATE_TABLE` command.
     fn handle_create_table_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = CreateTable::deserialize(structure).map_err(NodeError::CQLError)?;
 
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::CreateTable(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::CreateTable(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles a `DROP_TABLE` command.
     fn handle_drop_table_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = DropTable::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::DropTable(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::DropTable(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles an `ALTER_TABLE` command.
     fn handle_alter_table_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = AlterTable::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::AlterTable(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::AlterTable(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles a `CREATE_KEYSPACE` command.
     fn handle_create_keyspace_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
-        let storage_path = { node.lock()?.storage_path.clone() };
         let query = CreateKeyspace::deserialize(structure).map_err(NodeError::CQLError)?;
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::CreateKeyspace(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::CreateKeyspace(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles a `DROP_KEYSPACE` command.
     fn handle_drop_keyspace_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = DropKeyspace::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::DropKeyspace(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::DropKeyspace(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles an `ALTER_KEYSPACE` command.
     fn handle_alter_keyspace_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = AlterKeyspace::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::AlterKeyspace(query),
-            internode,
-            false,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::AlterKeyspace(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles an `UPDATE` command.
     fn handle_update_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         replication: bool,
         open_query_id: i32,
         client_id: i32,
         timestamp: i64,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = Update::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::Update(query),
-            internode,
-            replication,
-            open_query_id,
-            client_id,
-            Some(timestamp),
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::Update(query),
+                internode,
+                replication,
+                open_query_id,
+                client_id,
+                Some(timestamp),
+            )
     }
 
     // Handles a `DELETE` command.
     fn handle_delete_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         replication: bool,
         open_query_id: i32,
         client_id: i32,
         timestamp: i64,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = Delete::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::Delete(query),
-            internode,
-            replication,
-            open_query_id,
-            client_id,
-            Some(timestamp),
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::Delete(query),
+                internode,
+                replication,
+                open_query_id,
+                client_id,
+                Some(timestamp),
+            )
     }
 
     // Handles a `SELECT` command.
     fn handle_select_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         replication: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = Select::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::Select(query),
-            internode,
-            replication,
-            open_query_id,
-            client_id,
-            None,
-        )
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::Select(query),
+                internode,
+                replication,
+                open_query_id,
+                client_id,
+                None,
+            )
     }
 
     // Handles an `INSERT` command.
     fn handle_use_command(
         node: &Arc<Mutex<Node>>,
         structure: &str,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         internode: bool,
         open_query_id: i32,
         client_id: i32,
+        logger: &Logger,
     ) -> Result<Option<((i32, i32), InternodeResponse)>, NodeError> {
         let query = Use::deserialize(structure).map_err(NodeError::CQLError)?;
-        let storage_path = { node.lock()?.storage_path.clone() };
-        QueryExecution::new(node.clone(), connections, storage_path)?.execute(
-            Query::Use(query),
-            internode,
+        QueryExecution::new(node.clone(), connections)?
+            .with_logger(logger.clone())
+            .execute(
+                Query::Use(query),
+                internode,
+                false,
+                open_query_id,
+                client_id,
+                None,
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_INTERNODE_PORT;
+    use crate::internode_protocol::response::InternodeResponseContent;
+    use crate::merge_spill::{MergeBuffer, MergeMemoryLimit};
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    fn flights() -> TableSchema {
+        TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number))",
+            )
+            .unwrap(),
+        )
+    }
+
+    fn answer(from: Ipv4Addr, rows: &[&str]) -> (Ipv4Addr, InternodeResponse) {
+        let content = InternodeResponseContent {
+            columns: vec![],
+            select_columns: vec![],
+            values: rows
+                .iter()
+                .map(|row| row.split(',').map(str::to_string).collect())
+                .collect(),
+        };
+        (
+            from,
+            InternodeResponse::new(0, InternodeResponseStatus::Ok, Some(content)),
+        )
+    }
+
+    // The answers of the old owner of a range, which still keeps its rows after streaming them,
+    // and of its new owner, which got them with the same timestamps, plus rows only one has.
+    fn answers_during_redistribution() -> Vec<(Ipv4Addr, InternodeResponse)> {
+        vec![
+            answer(
+                Ipv4Addr::new(127, 0, 0, 1),
+                &["EZE,1,landed,10", "EZE,2,delayed,10", "AEP,7,boarding,12"],
+            ),
+            answer(
+                Ipv4Addr::new(127, 0, 0, 2),
+                &["EZE,1,landed,10", "EZE,2,delayed,10", "EZE,3,on time,11"],
+            ),
+        ]
+    }
+
+    fn read_repair(
+        answers: Vec<(Ipv4Addr, InternodeResponse)>,
+        table: &TableSchema,
+    ) -> (Vec<String>, Vec<RepairWrite>) {
+        let mut partitioner = Partitioner::new();
+        for ip in 1..=3 {
+            partitioner.add_node(Ipv4Addr::new(127, 0, 0, ip)).unwrap();
+        }
+        InternodeProtocolHandler::read_repair(answers, &table.get_columns(), &partitioner).unwrap()
+    }
+
+    #[test]
+    fn test_rows_of_the_old_and_new_owner_are_returned_once() {
+        let table = flights();
+
+        let (mut rows, repairs) = read_repair(answers_during_redistribution(), &table);
+        assert!(repairs.is_empty());
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![
+                "AEP,7,boarding,12",
+                "EZE,1,landed,10",
+                "EZE,2,delayed,10",
+                "EZE,3,on time,11"
+            ]
+        );
+        assert!(
+            InternodeProtocolHandler::duplicate_primary_keys(&rows, &table.get_columns())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_spilled_rows_of_the_old_and_new_owner_are_returned_once() {
+        let storage_path = PathBuf::from(format!("/tmp/dedup_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&storage_path).unwrap();
+        let table = flights();
+        let limit = MergeMemoryLimit {
+            max_buffered_bytes: 20,
+            spill_dir: storage_path.join("merge_spill"),
+        };
+        let mut buffer = MergeBuffer::new(Some(limit), &table.get_columns());
+
+        let mut answers = answers_during_redistribution();
+        for (from, response) in &mut answers {
+            buffer.add(*from, response.content.as_mut().unwrap());
+        }
+        assert!(buffer.has_spilled());
+
+        let mut rows = vec![];
+        for batch in buffer.merge_batches(answers).unwrap() {
+            rows.extend(read_repair(batch.unwrap(), &table).0);
+        }
+        rows.sort();
+        assert_eq!(rows.len(), 4);
+        assert!(
+            InternodeProtocolHandler::duplicate_primary_keys(&rows, &table.get_columns())
+                .is_empty()
+        );
+        std::fs::remove_dir_all(storage_path).unwrap();
+    }
+
+    #[test]
+    fn test_outdated_replicas_get_the_latest_rows() {
+        let table = flights();
+        let answers = vec![
+            answer(
+                Ipv4Addr::new(127, 0, 0, 1),
+                &["EZE,1,delayed,10", "EZE,2,landed,10"],
+            ),
+            answer(
+                Ipv4Addr::new(127, 0, 0, 2),
+                &["EZE,1,landed,12", "EZE,2,landed,10"],
+            ),
+        ];
+
+        let (mut rows, repairs) = read_repair(answers, &table);
+        rows.sort();
+        assert_eq!(rows, vec!["EZE,1,landed,12", "EZE,2,landed,10"]);
+        assert_eq!(repairs.len(), 1);
+        assert_eq!(repairs[0].replica, Ipv4Addr::new(127, 0, 0, 1));
+        assert_eq!(repairs[0].row, vec!["EZE", "1", "landed", "12"]);
+    }
+
+    #[test]
+    fn test_read_repairs_are_skipped_by_their_chance() {
+        assert!(InternodeProtocolHandler::rolls(1.0));
+        assert!(!InternodeProtocolHandler::rolls(0.0));
+
+        let storage_path = PathBuf::from(format!("/tmp/read_repair_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&storage_path).unwrap();
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number)) WITH read_repair_chance = 0",
+            )
+            .unwrap(),
+        );
+        let repairs = vec![RepairWrite {
+            replica: Ipv4Addr::new(127, 0, 0, 1),
+            row: vec!["EZE".into(), "1".into(), "landed".into(), "12".into()],
+            replication: false,
+        }];
+        let read_repairs = OpenQueryHandler::new().read_repairs();
+
+        InternodeProtocolHandler::start_read_repair(
+            repairs,
+            (0, 0),
+            0,
             false,
-            open_query_id,
-            client_id,
-            None,
+            "sky".to_string(),
+            table,
+            Ipv4Addr::new(127, 0, 0, 3),
+            DEFAULT_INTERNODE_PORT,
+            ConnectionManager::new(),
+            StorageEngine::new(storage_path.clone(), "127.0.0.3".to_string()),
+            Logger::new(&storage_path, "127.0.0.3").unwrap(),
+            read_repairs.clone(),
+            Arc::new(WorkerPool::new("test", 1)),
         )
+        .unwrap();
+        assert_eq!(
+            read_repairs.lock().unwrap().report(),
+            vec![
+                "sky.flights read_repair mismatches=1 skipped=1 repaired_rows=0 failed_rows=0 \
+                 dropped_rows=0"
+            ]
+        );
+        std::fs::remove_dir_all(storage_path).unwrap();
+    }
+
+    // Repairs a row of a node no one listens on, with no workers to write it in the background
+    fn start_unreachable_read_repair(forced: bool, read_repair_chance: &str) -> Vec<String> {
+        let storage_path = PathBuf::from(format!("/tmp/read_repair_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&storage_path).unwrap();
+        let table = TableSchema::new(
+            CreateTable::deserialize(&format!(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number)) WITH read_repair_chance = {}",
+                read_repair_chance
+            ))
+            .unwrap(),
+        );
+        let repairs = vec![RepairWrite {
+            replica: Ipv4Addr::new(127, 0, 0, 1),
+            row: vec!["EZE".into(), "1".into(), "landed".into(), "12".into()],
+            replication: false,
+        }];
+        let read_repairs = OpenQueryHandler::new().read_repairs();
+
+        InternodeProtocolHandler::start_read_repair(
+            repairs,
+            (2, 0),
+            0,
+            forced,
+            "sky".to_string(),
+            table,
+            Ipv4Addr::new(127, 0, 0, 3),
+            1,
+            ConnectionManager::new(),
+            StorageEngine::new(storage_path.clone(), "127.0.0.3".to_string()),
+            Logger::new(&storage_path, "127.0.0.3").unwrap(),
+            read_repairs.clone(),
+            Arc::new(WorkerPool::new("test", 0)),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(storage_path).unwrap();
+        let report = read_repairs.lock().unwrap().report();
+        report
+    }
+
+    #[test]
+    fn test_forced_read_repairs_are_written_before_returning() {
+        assert_eq!(
+            start_unreachable_read_repair(true, "0"),
+            vec![
+                "sky.flights read_repair mismatches=1 skipped=0 repaired_rows=2 failed_rows=1 \
+                 dropped_rows=0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_repairs_are_dropped_when_every_worker_is_busy() {
+        assert_eq!(
+            start_unreachable_read_repair(false, "1"),
+            vec![
+                "sky.flights read_repair mismatches=1 skipped=0 repaired_rows=0 failed_rows=0 \
+                 dropped_rows=1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_primary_keys_are_found() {
+        let columns = flights().get_columns();
+        let rows = vec![
+            "EZE,1,landed,10".to_string(),
+            "EZE,1,delayed,9".to_string(),
+            "EZE,2,landed,10".to_string(),
+            "AEP,1,landed,10".to_string(),
+        ];
+        assert_eq!(
+            InternodeProtocolHandler::duplicate_primary_keys(&rows, &columns),
+            vec!["EZE|1"]
+        );
     }
 }
@@ -0,0 +1,342 @@
+//! Mutual TLS of the internode connections.
+//!
+//! A node configured with `internode_cert`, `internode_key` and `internode_ca` presents its own
+//! certificate to the nodes it connects to and asks the ones that connect to it for theirs, and
+//! both sides only accept certificates signed by the CA of the cluster. As Cassandra does without
+//! endpoint verification, the name of a certificate is not checked against the IP of the node,
+//! so a node certificate is not tied to an address.
+//!
+//! Outgoing connections are opened by the `ConnectionManager`, which only knows the message
+//! it sends; the TLS settings of a node are registered here by its IP, the `from` of its messages.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::aws_lc_rs;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    ServerConfig, ServerConnection, SignatureScheme, StreamOwned,
+};
+
+use crate::config::InternodeTlsFiles;
+use crate::errors::NodeError;
+
+/// Name the nodes connect to each other with, which is not checked (see `AnyNameVerifier`).
+const INTERNODE_SERVER_NAME: &str = "rustic-airlines-node";
+
+/// TLS settings of the nodes of this process that encrypt their internode connections, by IP.
+static INTERNODE_TLS: Mutex<BTreeMap<Ipv4Addr, Arc<InternodeTls>>> = Mutex::new(BTreeMap::new());
+
+/// TLS configurations a node connects to the other nodes with, and accepts their connections.
+pub struct InternodeTls {
+    client: Arc<ClientConfig>,
+    server: Arc<ServerConfig>,
+}
+
+impl fmt::Debug for InternodeTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InternodeTls").finish_non_exhaustive()
+    }
+}
+
+impl InternodeTls {
+    /// Loads the certificate and key of a node, and the CA of the cluster.
+    ///
+    /// # Errors
+    /// - `NodeError::ConfigError` if a file can not be read or parsed, or the key does not match
+    ///   the certificate.
+    pub fn load(files: &InternodeTlsFiles) -> Result<Self, NodeError> {
+        let invalid = |path: &Path| {
+            NodeError::ConfigError(format!("invalid internode TLS file {}", path.display()))
+        };
+
+        let certs = CertificateDer::pem_file_iter(&files.cert)
+            .map_err(|_| invalid(&files.cert))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid(&files.cert))?;
+        let key = PrivateKeyDer::from_pem_file(&files.key).map_err(|_| invalid(&files.key))?;
+        let mut roots = RootCertStore::empty();
+        for ca in CertificateDer::pem_file_iter(&files.ca).map_err(|_| invalid(&files.ca))? {
+            roots
+                .add(ca.map_err(|_| invalid(&files.ca))?)
+                .map_err(|_| invalid(&files.ca))?;
+        }
+        let roots = Arc::new(roots);
+        let provider = Arc::new(aws_lc_rs::default_provider());
+
+        let client_verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::clone(&roots), Arc::clone(&provider))
+                .build()
+                .map_err(|_| invalid(&files.ca))?;
+        let server = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(|_| invalid(&files.cert))?
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(certs.clone(), key.clone_key())
+            .map_err(|_| invalid(&files.key))?;
+
+        let server_verifier =
+            WebPkiServerVerifier::builder_with_provider(roots, Arc::clone(&provider))
+                .build()
+                .map_err(|_| invalid(&files.ca))?;
+        let client = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|_| invalid(&files.cert))?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyNameVerifier(server_verifier)))
+            .with_client_auth_cert(certs, key)
+            .map_err(|_| invalid(&files.key))?;
+
+        Ok(InternodeTls {
+            client: Arc::new(client),
+            server: Arc::new(server),
+        })
+    }
+
+    /// Wraps a connection opened to another node. The handshake runs on the first write.
+    pub fn connect(&self, stream: TcpStream) -> Result<InternodeStream, NodeError> {
+        let name =
+            ServerName::try_from(INTERNODE_SERVER_NAME).map_err(|_| NodeError::OtherError)?;
+        let conn = ClientConnection::new(Arc::clone(&self.client), name)
+            .map_err(|_| NodeError::OtherError)?;
+        Ok(InternodeStream::Tls(Box::new(StreamOwned::new(
+            conn, stream,
+        ))))
+    }
+
+    /// Wraps a connection another node opened. The handshake runs on the first read.
+    pub fn accept(
+        &self,
+        stream: TcpStream,
+    ) -> Result<StreamOwned<ServerConnection, TcpStream>, NodeError> {
+        let conn =
+            ServerConnection::new(Arc::clone(&self.server)).map_err(|_| NodeError::OtherError)?;
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+/// Registers the TLS settings the node at `ip` opens its internode connections with.
+pub fn register(ip: Ipv4Addr, tls: Arc<InternodeTls>) -> Result<(), NodeError> {
+    INTERNODE_TLS.lock()?.insert(ip, tls);
+    Ok(())
+}
+
+/// Returns the TLS settings of the node at `ip`, if it encrypts its internode connections.
+pub fn registered(ip: &Ipv4Addr) -> Result<Option<Arc<InternodeTls>>, NodeError> {
+    Ok(INTERNODE_TLS.lock()?.get(ip).cloned())
+}
+
+/// A connection a node opened to another node, to send it internode messages.
+#[derive(Debug)]
+pub enum InternodeStream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl InternodeStream {
+    /// Returns whether the other node still has the connection open, without blocking. Nodes
+    /// never answer through the connections others opened to them, so a read that would not block
+    /// (other than the bytes of the TLS handshake) means the other node closed it.
+    pub fn is_open(&self) -> bool {
+        let stream = match self {
+            InternodeStream::Plain(stream) => stream,
+            InternodeStream::Tls(stream) => &stream.sock,
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let open = match stream.peek(&mut [0u8; 1]) {
+            Ok(read) => read > 0,
+            Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+        };
+        stream.set_nonblocking(false).is_ok() && open
+    }
+}
+
+impl Write for InternodeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            InternodeStream::Plain(stream) => stream.write(buf),
+            InternodeStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            InternodeStream::Plain(stream) => stream.flush(),
+            InternodeStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+// Checks that the certificate of a node is signed by the CA of the cluster, whatever its name.
+#[derive(Debug)]
+struct AnyNameVerifier(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for AnyNameVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+        KeyUsagePurpose,
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    // Writes a new CA and the certificates and keys of `nodes` signed by it to a new folder, as
+    // `make internode` does, and returns the folder with the files of each node
+    fn cluster_files(nodes: &[&str]) -> (PathBuf, Vec<InternodeTlsFiles>) {
+        let folder = PathBuf::from(format!("/tmp/internode_tls_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "rustic-airlines-ca");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let ca_path = folder.join("ca.crt");
+        fs::write(&ca_path, ca.pem()).unwrap();
+
+        let files = nodes
+            .iter()
+            .map(|node| {
+                let key = KeyPair::generate().unwrap();
+                let mut params = CertificateParams::new(vec![node.to_string()]).unwrap();
+                params.distinguished_name.push(DnType::CommonName, *node);
+                params.extended_key_usages = vec![
+                    ExtendedKeyUsagePurpose::ServerAuth,
+                    ExtendedKeyUsagePurpose::ClientAuth,
+                ];
+                let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+
+                let files = InternodeTlsFiles {
+                    cert: folder.join(format!("{}.crt", node)),
+                    key: folder.join(format!("{}.key", node)),
+                    ca: ca_path.clone(),
+                };
+                fs::write(&files.cert, cert.pem()).unwrap();
+                fs::write(&files.key, key.serialize_pem()).unwrap();
+                files
+            })
+            .collect();
+        (folder, files)
+    }
+
+    // Sends `message` from a node with the `from` files to one with the `to` files, returning
+    // what the second one read
+    fn send(from: &InternodeTlsFiles, to: &InternodeTlsFiles, message: &[u8]) -> Vec<u8> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = InternodeTls::load(to).unwrap();
+        let reader = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = server.accept(stream).unwrap();
+            let mut read = Vec::new();
+            stream.read_to_end(&mut read).ok();
+            read
+        });
+
+        let client = InternodeTls::load(from).unwrap();
+        let mut stream = client.connect(TcpStream::connect(addr).unwrap()).unwrap();
+        stream.write_all(message).ok();
+        stream.flush().ok();
+        if let InternodeStream::Tls(mut tls) = stream {
+            tls.conn.send_close_notify();
+            tls.flush().ok();
+        }
+        reader.join().unwrap()
+    }
+
+    #[test]
+    fn test_nodes_signed_by_the_ca_talk_over_tls() {
+        let (folder, files) = cluster_files(&["node", "node2"]);
+        assert_eq!(send(&files[0], &files[1], b"gossip"), b"gossip");
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_node_not_signed_by_the_ca_is_rejected() {
+        let (folder, files) = cluster_files(&["node"]);
+        let rogue = InternodeTlsFiles {
+            cert: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../certs/cert.crt"),
+            key: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../certs/cert.key"),
+            ca: files[0].ca.clone(),
+        };
+        assert!(send(&rogue, &files[0], b"gossip").is_empty());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_connection_closed_by_the_other_node_is_not_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream =
+            InternodeStream::Plain(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        let (accepted, _) = listener.accept().unwrap();
+        assert!(stream.is_open());
+
+        drop(accepted);
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!stream.is_open());
+    }
+}
@@ -1,72 +1,290 @@
 // Local modules firstsrc/lib
+mod admin;
+pub mod authorization;
+mod client_sessions;
+pub mod config;
+mod connection_manager;
+mod dry_run;
+mod embedded;
 mod errors;
+mod events;
+mod gossip_transport;
+mod hints;
 mod internode_protocol;
 mod internode_protocol_handler;
+mod internode_tls;
+mod merge_spill;
+mod metrics;
 mod open_query_handler;
+mod outbound;
+mod paxos;
+mod peer_connection;
+mod prepared_statements;
 mod query_execution;
+mod replication_check;
+mod result_pages;
+mod roles;
+mod schema_script;
+mod shutdown;
 pub mod storage_engine;
+mod stream_sessions;
+mod system_tables;
 mod utils;
+mod worker_pool;
 
 // Standard libraries
-use std::collections::HashMap;
-use std::io::{BufReader, Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
-use std::path::{Path, PathBuf};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{mpsc, Arc, Mutex};
-use std::time::Instant;
-use std::{env, thread, vec};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{thread, vec};
 
 // External libraries
+use admin::AdminCommand;
+use authorization::{AllowAll, AuthorizationRequest, Authorizer};
 use chrono::Utc;
+pub use client_sessions::ClientConnectionLimits;
+use client_sessions::{ClientSession, CloseReason};
+use config::NodeConfig;
+use driver::drain::DrainReport;
+use driver::events::NodeEvent;
+use driver::maintenance::{MaintenanceStatus, NodeProgress, MAINTENANCE_TIMEOUT};
+use driver::ring::TokenRange;
 use driver::server::{handle_client_request, Request};
+pub use embedded::{EmbeddedNode, EmbeddedNodeBuilder, EmbeddedStorage};
 use errors::NodeError;
+use events::EventLog;
 use gossip::structures::application_state::{KeyspaceSchema, NodeStatus, Schema, TableSchema};
+use gossip::transport::Transport;
 use gossip::Gossiper;
+use gossip_transport::InternodeGossipTransport;
+use hints::HintStore;
+use internode_protocol::decoder::InternodeDecoder;
+use internode_protocol::maintenance::{MaintenanceOperation, MaintenanceRequest};
 use internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use internode_protocol::paxos::{PaxosPhase, PaxosReply, PaxosRequest};
 use internode_protocol::response::{
-    InternodeResponse, InternodeResponseContent, InternodeResponseStatus,
+    GenerationStamp, InternodeResponse, InternodeResponseContent, InternodeResponseStatus,
 };
-use internode_protocol::InternodeSerializable;
+use internode_protocol::streaming::{StreamRequest, StreamResult};
 use internode_protocol_handler::InternodeProtocolHandler;
 // use keyspace::Keyspace;
-use logger::{Color, Logger};
-use native_protocol::frame::Frame;
+pub use connection_manager::ConnectionManager;
+use internode_tls::InternodeTls;
+use logger::{Color, Component, Logger};
+pub use merge_spill::MergeMemoryLimit;
+use metrics::{
+    HotPartitionMetrics, LatencyMetrics, Operation, ReplicaLagMetrics, SharedTimings, TableMetrics,
+};
+use native_protocol::frame::{Frame, FrameOptions};
+use native_protocol::framing::{self, FrameBuffer};
 use native_protocol::messages::auth::{AuthSuccess, Authenticate};
 use native_protocol::messages::error;
+use native_protocol::messages::result::metadata::Metadata;
+use native_protocol::messages::result::prepared::Prepared;
+use native_protocol::messages::result::result_;
+use native_protocol::messages::result::rows::ColumnType;
+use native_protocol::messages::supported::Supported;
 use native_protocol::Serializable;
-use open_query_handler::OpenQueryHandler;
+pub use open_query_handler::RequestTimeouts;
+use open_query_handler::{
+    ConsistencyLevel, LocalReplicas, OpenQueryHandler, DEFAULT_SPECULATIVE_RETRY_DELAY,
+};
+pub use outbound::BandwidthLimits;
+use outbound::{OutboundQueues, Traffic};
 use partitioner::Partitioner;
-use query_creator::clauses::keyspace::create_keyspace_cql::CreateKeyspace;
+use paxos::PaxosState;
+use prepared_statements::{bind_markers, PreparedStatements};
+use query_creator::clauses::keyspace::alter_keyspace_cql::AlterKeyspace;
+use query_creator::clauses::keyspace::create_keyspace_cql::{
+    CreateKeyspace, NETWORK_TOPOLOGY_STRATEGY,
+};
 use query_creator::clauses::table::create_table_cql::CreateTable;
 use query_creator::clauses::types::column::Column;
 use query_creator::errors::CQLError;
 use query_creator::{GetTableName, GetUsedKeyspace, NeedsKeyspace, NeedsTable, Query};
 use query_creator::{NeededResponses, QueryCreator};
 use query_execution::QueryExecution;
+use replication_check::replication_shortfall;
+pub use replication_check::ReplicationCheck;
+use result_pages::ResultPages;
+use roles::{Credentials, Role, RolesCache, StoredRole, AUTH_KEYSPACE, DEFAULT_SUPERUSER};
 use rustls::pki_types::pem::PemObject;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use schema_script::SchemaBootstrap;
+use shutdown::ShutdownSignal;
+use storage_engine::commitlog::{CommitLog, CommitLogEntry, Mutation};
+use storage_engine::compaction::CompactionSettings;
+use storage_engine::lsm::StorageBackend;
 use storage_engine::StorageEngine;
-use utils::{check_keyspace, check_table, connect_and_send_message};
-
-const CLIENT_NODE_PORT: u16 = 0x4645; // Hexadecimal of "FE" (FERRUM) = 17989
-const INTERNODE_PORT: u16 = 0x554D; // Hexadecimal of "UM" (FERRUM) = 21837
+use stream_sessions::StreamSessions;
+use system_tables::{NodeTopology, SystemState};
+use utils::{check_keyspace, check_table, connect_and_send_message, get_replicas};
+use uuid::Uuid;
+pub use worker_pool::ConcurrencyLimits;
+use worker_pool::WorkerPool;
+
+/// Time between two scans of the tables to estimate their droppable data.
+const TABLE_STATS_INTERVAL: Duration = Duration::from_secs(60);
+/// Interval between saves of the gossip states of the endpoints, which change on every round.
+const GOSSIP_STATE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// Time between two checks of the deadlines of the open queries.
+const QUERY_TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Time between two checks of the internode connections that broke.
+const BROKEN_CONNECTIONS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Time between two checks of the open reads whose replicas are slow.
+const SPECULATIVE_RETRY_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+/// Time the node waits for each statement of an imported schema script.
+const SCHEMA_STATEMENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time the node waits for the rows of each range of tokens of a table it repairs, read from all of
+/// its replicas.
+const REPAIR_RANGE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time a decommissioned node keeps gossiping that it left the ring before it stops.
+const LEFT_ANNOUNCEMENT_TIME: Duration = Duration::from_secs(5);
+/// Time a node shutting down keeps gossiping that it is shut down before it stops, as Cassandra's
+/// `shutdown_announce_in_ms`.
+const SHUTDOWN_ANNOUNCEMENT_TIME: Duration = Duration::from_secs(2);
+/// Time a client connection waits for its next frame before checking whether the node is shutting
+/// down.
+const CLIENT_SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Time an internode connection waits for the next message of the other node before checking
+/// whether this node is shutting down.
+const INTERNODE_SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// Time a bootstrapping node waits for the rows of its ranges before it asks for them again.
+const BOOTSTRAP_STREAM_TIMEOUT: Duration = Duration::from_secs(300);
+/// Time between two checks of whether the chunks streamed by a decommission were acknowledged.
+const DECOMMISSION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Bytes read at once from an internode connection. Longer messages take several reads.
+const INTERNODE_READ_BUFFER_SIZE: usize = 64 * 1024;
+/// Bytes read at once from a client connection. Longer frames take several reads.
+const CLIENT_READ_BUFFER_SIZE: usize = 16 * 1024;
+/// Time a client connection refused as overloaded is given to send its first frame and read the
+/// answer.
+const REFUSED_CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Refused client connections answered at the same time, each by a thread of its own. Past them,
+/// refused connections are closed without an answer.
+const MAX_REFUSING_CLIENTS: usize = 32;
+
+/// Where the results of the other nodes for a flush or compaction coordinated by the node are sent,
+/// with the node that sent each one.
+type MaintenanceSender = Sender<(Ipv4Addr, Result<u32, String>)>;
+/// Where the nodes streaming the rows of the ranges of a bootstrapping node send what they streamed,
+/// with the node that sent each result.
+type StreamSender = Sender<(Ipv4Addr, Result<u32, String>)>;
 
 /// Represents a node within the distributed network.
 /// The node can manage keyspaces, tables, and handle connections between nodes and clients.
-///
 pub struct Node {
     ip: Ipv4Addr,
     partitioner: Partitioner,
     open_query_handler: OpenQueryHandler,
     clients_keyspace: HashMap<i32, Option<String>>,
+    /// Clients the `REPAIR` admin command reads tables as, whose reads write every repair they find.
+    repairing_clients: HashSet<i32>,
     last_client_id: i32,
     gossiper: Gossiper,
-    storage_path: PathBuf,
+    /// The storage of the tables of the node, cloned for every query and task that reads or writes them.
+    storage_engine: StorageEngine,
     logger: Logger,
     /// Represents the latest known schema of the cluster.
     schema: Schema,
+    /// Peers whose internode messages are dropped, used to simulate network partitions.
+    blocked_peers: HashSet<Ipv4Addr>,
+    /// Dead node this node is replacing, until its token is known through gossip.
+    replacing: Option<Ipv4Addr>,
+    /// Last heartbeat generation seen for each peer, used to detect restarted nodes.
+    peer_generations: HashMap<Ipv4Addr, u128>,
+    /// High level events of the node, polled by clients through the admin port.
+    events: EventLog,
+    /// Latencies of the reads and writes coordinated by the node, checked against their SLOs.
+    metrics: LatencyMetrics,
+    table_metrics: TableMetrics,
+    /// Responses of the replicas executed with an older schema or a different ring than the ones of the node.
+    replica_lag: ReplicaLagMetrics,
+    /// Writes coordinated by the node to the partitions of each table, to find the hottest ones.
+    hot_partitions: HotPartitionMetrics,
+    /// Write-ahead log of the mutations applied by the node, shared with the queries being executed.
+    commit_log: Arc<Mutex<CommitLog>>,
+    /// Mutations of the commit log found on startup, replayed once the schema of their table is known.
+    pending_replay: Vec<CommitLogEntry>,
+    /// Queries prepared by the clients of the node, executed later by their id.
+    prepared_statements: PreparedStatements,
+    /// Writes that could not reach their replicas, replayed once the replicas are back.
+    hints: HintStore,
+    /// Whether the node was drained, so it takes no more client queries.
+    draining: bool,
+    /// Client queries being executed by the node, which a drain waits for.
+    client_queries: usize,
+    /// Tells the threads of the node to stop once it is shut down.
+    shutdown: ShutdownSignal,
+    /// The threads started by `start`, joined by `shutdown`.
+    threads: Vec<JoinHandle<()>>,
+    /// Decides whether the clients of the node may run their statements.
+    authorizer: Arc<dyn Authorizer>,
+    /// User each authenticated client logged in as.
+    clients_user: HashMap<i32, String>,
+    /// Roles of the users logged in to the node, read from the `system_auth` keyspace.
+    roles: RolesCache,
+    /// Flushes and compactions the node is running in the ring, by id, waiting for the results of
+    /// the other nodes.
+    pending_maintenance: HashMap<u32, MaintenanceSender>,
+    last_maintenance_id: u32,
+    /// Promises and accepted rows of the Paxos rounds of lightweight transactions, as a replica.
+    paxos: PaxosState,
+    /// Phases of Paxos rounds coordinated by the node, by id, waiting for the replies of the replicas.
+    pending_paxos: HashMap<u32, Sender<PaxosReply>>,
+    /// Chunks of rows streamed by the node, waiting for the nodes taking them to acknowledge them.
+    stream_sessions: StreamSessions,
+    /// Whether the node is streaming the rows of its ranges from the ring, before it joins it.
+    bootstrapping: bool,
+    /// The id of the stream requests of the running bootstrap of the node, with where the results
+    /// of the nodes streaming the rows are sent.
+    pending_bootstrap: Option<(u32, StreamSender)>,
+    last_paxos_id: u32,
+    /// Queues of the rows streamed and the hints replayed to each peer, paced to their bandwidth limits.
+    outbound: OutboundQueues,
+    /// How long the connections of the clients may stay idle, and open, before the node closes them.
+    client_limits: ClientConnectionLimits,
+    /// How many connections and client queries the node handles at once before refusing more.
+    concurrency: ConcurrencyLimits,
+    /// Whether the node is the first seed of the cluster, which runs the initial schema.
+    is_first_seed: bool,
+    /// Schema script run by the node if it forms the cluster, with where it was read from.
+    initial_schema: Option<(String, String)>,
+    /// Record of the initial schema run by the node, shown in `system.schema_bootstrap`.
+    schema_bootstrap: Option<SchemaBootstrap>,
+    /// How often the background compaction of the node runs, and which tables it compacts.
+    compaction: CompactionSettings,
+    /// What the node does with the keyspaces created or altered with more replicas than live nodes.
+    replication_check: ReplicationCheck,
+    /// Keyspaces with more replicas than the live nodes of the ring, warned about once until they recover.
+    under_replicated: HashSet<String>,
+    /// Ports, gossip interval and certificates of the node.
+    config: NodeConfig,
+}
+
+/// The client a `REPAIR` runs its reads as, which is forgotten by the node once the repair ends,
+/// whether it fails or not.
+struct RepairClient<'a> {
+    node: &'a Arc<Mutex<Node>>,
+    id: i32,
+}
+
+impl Drop for RepairClient<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut node_guard) = self.node.lock() {
+            node_guard.clients_keyspace.remove(&self.id);
+            node_guard.repairing_clients.remove(&self.id);
+        }
+    }
 }
 
 impl Node {
@@ -85,6 +303,9 @@ impl Node {
     ///     partitioner and gossip protocol for cluster membership and state sharing.
     /// - `storage_path: PathBuf`
     ///   - The file system path where the node's storage engine will manage data and metadata.
+    /// - `config: NodeConfig`
+    ///   - The ports the node listens on (and connects to the other nodes on), the time between its gossip rounds
+    ///     and where its TLS certificates are. `NodeConfig::default()` keeps the ports of a single cluster.
     ///
     /// # Returns
     /// - `Result<Node, NodeError>`
@@ -99,27 +320,37 @@ impl Node {
     ///    - Iterates over the `seeds_nodes` list to add additional nodes to the partitioner, excluding the current node.
     /// 2. **Storage Engine Setup**:
     ///    - Initializes a `StorageEngine` with the provided `storage_path` and node's IP address.
-    ///    - Resets storage folders to ensure a clean state for the node.
+    ///    - Keeps the data files the node wrote before it stopped, or resets them to the snapshot to restore.
+    ///    - Reads the mutations of the commit log, to replay them once the schema of their tables is learnt
+    ///      through gossip, and starts a new segment of the log.
     /// 3. **Node Components**:
     ///    - Creates and configures the following components for the node:
     ///      - `OpenQueryHandler`: Manages queries currently being processed by the node.
     ///      - `clients_keyspace`: Tracks keyspaces for clients connected to the node.
+    ///      - `repairing_clients`: Starts empty, as no `REPAIR` is running.
     ///      - `last_client_id`: Initializes the client ID counter to zero.
-    ///      - `gossiper`: Initializes the gossip protocol with the node's endpoint state and seed nodes.
+    ///      - `gossiper`: Initializes the gossip protocol with the node's endpoint state and seed nodes, and
+    ///        the states of the nodes it knew before it restarted, with a generation greater than its previous one.
+    ///        It gossips `empty_data` when the node starts without data files nor mutations to replay, so the
+    ///        other nodes stream its ranges to it again.
     ///      - `schema`: Manages the database schema (e.g., keyspaces and tables).
     ///
     /// # Notes
     /// - **Seed Nodes**:
     ///   - Seed nodes are critical for the initial discovery of other nodes in the cluster.
     ///   - The current node (`ip`) is excluded from being added as its own seed.
-    /// - **Storage Engine Reset**:
-    ///   - Resetting storage folders ensures no residual data interferes with the node's operation.
-    ///   - This operation should be used with caution in production environments to avoid unintended data loss.
+    /// - **Storage Engine**:
+    ///   - The data files are kept across restarts, so the commit log only holds the mutations that may not be
+    ///     in them yet: its segments are retired once their mutations are (see `CommitLog`).
+    ///   - With `restore_snapshot` in the config, the storage folders are reset and the keyspaces of that
+    ///     snapshot are brought back. Only the mutations of those keyspaces logged after the snapshot was
+    ///     taken are replayed over them, so the node comes back with the snapshot plus the later mutations
+    ///     still in the commit log.
     ///
     /// # Errors
     /// - Returns `NodeError` in the following scenarios:
     ///   - Failure to initialize or add nodes to the partitioner.
-    ///   - Issues resetting storage folders during storage engine initialization.
+    ///   - Issues resetting storage folders or restoring the snapshot during storage engine initialization.
     ///   - General failures in setting up the node's components.
     ///
     /// # Importance
@@ -131,34 +362,496 @@ impl Node {
         ip: Ipv4Addr,
         seeds_nodes: Vec<Ipv4Addr>,
         storage_path: PathBuf,
+        config: NodeConfig,
     ) -> Result<Node, NodeError> {
         let mut partitioner = Partitioner::new();
         partitioner.add_node(ip)?;
 
         let storage_engine = StorageEngine::new(storage_path.clone(), ip.to_string());
-        storage_engine.reset_folders()?;
+        let mut replay_from = HashMap::new();
+        if let Some(tag) = &config.restore_snapshot {
+            storage_engine.reset_folders()?;
+            replay_from = storage_engine.restore_snapshot(tag)?.replay_from;
+        } else {
+            storage_engine.create_folders()?;
+        }
+        let schema_bootstrap = SchemaBootstrap::read(&storage_engine.schema_bootstrap_path());
+        let commit_log_path = storage_engine.commit_log_path();
+        let pending_replay = CommitLog::read_entries_from(&commit_log_path, &replay_from)?;
+        let mut commit_log = CommitLog::open(commit_log_path)?;
+        if pending_replay.is_empty() {
+            commit_log.finish_replay();
+        }
+        // Gossiped so the other nodes stream the ranges of this node to it again if it restarted
+        // without its data, and only then
+        let empty_data = storage_engine.is_empty() && pending_replay.is_empty();
+        let hints = HintStore::new(storage_engine.hints_path());
+        let gossip_state_path = storage_engine.gossip_state_path();
+        let spill_dir = storage_engine.merge_spill_path();
+        merge_spill::remove_leftover_runs(&spill_dir)?;
+        let mut open_query_handler = OpenQueryHandler::new();
+        open_query_handler.set_merge_limit(Some(MergeMemoryLimit::new(spill_dir)));
 
         for seed_ip in seeds_nodes.clone() {
             if seed_ip != ip {
                 partitioner.add_node(seed_ip)?;
             }
         }
+        let is_first_seed = seeds_nodes.first() == Some(&ip);
+        let mut gossiper = Gossiper::new()
+            .with_endpoint_state(ip)
+            .with_state_file(gossip_state_path)
+            .with_seeds(seeds_nodes)
+            .with_config(config.gossip.clone());
+        gossiper
+            .set_empty_data(ip, empty_data)
+            .map_err(|_| NodeError::GossipError)?;
+        if let Some(files) = &config.internode_tls {
+            internode_tls::register(ip, Arc::new(InternodeTls::load(files)?))?;
+        }
+        if let Some(compression) = config.internode_compression {
+            utils::register_internode_compression(ip, compression)?;
+        }
 
         Ok(Node {
             ip,
             partitioner,
-            open_query_handler: OpenQueryHandler::new(),
+            open_query_handler,
             clients_keyspace: HashMap::new(),
+            repairing_clients: HashSet::new(),
             last_client_id: 0,
-            storage_path: storage_path.clone(),
-            gossiper: Gossiper::new()
-                .with_endpoint_state(ip)
-                .with_seeds(seeds_nodes),
-            logger: Logger::new(&storage_path, &ip.to_string())?,
+            storage_engine,
+            gossiper,
+            logger: Logger::new(&storage_path, &ip.to_string())?
+                .with_format(config.log_format)
+                .with_min_level(config.log_level),
             schema: Schema::new(),
+            blocked_peers: HashSet::new(),
+            replacing: None,
+            peer_generations: HashMap::new(),
+            events: EventLog::new(),
+            metrics: LatencyMetrics::new(),
+            table_metrics: TableMetrics::new(),
+            replica_lag: ReplicaLagMetrics::new(),
+            hot_partitions: HotPartitionMetrics::new(),
+            commit_log: Arc::new(Mutex::new(commit_log)),
+            pending_replay,
+            prepared_statements: PreparedStatements::new(),
+            hints,
+            draining: false,
+            client_queries: 0,
+            shutdown: ShutdownSignal::new(),
+            threads: Vec::new(),
+            authorizer: Arc::new(AllowAll),
+            clients_user: HashMap::new(),
+            roles: RolesCache::default(),
+            pending_maintenance: HashMap::new(),
+            last_maintenance_id: 0,
+            paxos: PaxosState::new(),
+            pending_paxos: HashMap::new(),
+            stream_sessions: StreamSessions::new(),
+            bootstrapping: false,
+            pending_bootstrap: None,
+            last_paxos_id: 0,
+            outbound: OutboundQueues::new(BandwidthLimits::default(), config.internode_port),
+            client_limits: ClientConnectionLimits::default(),
+            concurrency: ConcurrencyLimits::default(),
+            is_first_seed,
+            initial_schema: None,
+            schema_bootstrap,
+            compaction: CompactionSettings::default(),
+            replication_check: ReplicationCheck::default(),
+            under_replicated: HashSet::new(),
+            config,
         })
     }
 
+    /// Sets how long this node waits for the replicas of the reads and writes it coordinates.
+    ///
+    /// # Purpose
+    /// Reads and writes have very different latency tolerances, so each one gets its own timeout
+    /// (`read_request_timeout` and `write_request_timeout` in Cassandra). A query that is not resolved
+    /// before its timeout is answered with a `ReadTimeout` or `WriteTimeout` error.
+    ///
+    /// # Parameters
+    /// - `timeouts: RequestTimeouts`
+    ///   - The timeouts of the reads and the writes. Nodes use `RequestTimeouts::default()` otherwise.
+    pub fn with_request_timeouts(mut self, timeouts: RequestTimeouts) -> Node {
+        self.open_query_handler.set_timeouts(timeouts);
+        self
+    }
+
+    /// Sets how many bytes of rows the reads this node coordinates buffer before spilling to disk.
+    ///
+    /// # Purpose
+    /// The coordinator of a `SELECT` keeps the rows of every replica until it can merge them with read repair,
+    /// so a scan over a big table holds one copy of it per replica. Once the rows of a query go over the limit,
+    /// the answers that follow are sorted by primary key and written to temporary files in `spill_dir`, and all
+    /// of them are merged in key order, a batch of about `max_buffered_bytes` of rows at a time.
+    ///
+    /// # Parameters
+    /// - `limit: Option<MergeMemoryLimit>`
+    ///   - The limit, or `None` to merge every answer in memory. Nodes use `MergeMemoryLimit::new` with a folder
+    ///     of their storage path otherwise, which allows 64 MiB of rows per query.
+    ///
+    /// # Notes
+    /// - The native protocol of the nodes has no paging, so the merged rows are still sent to the client in a
+    ///   single frame: the limit bounds what the merge holds, not the size of the result.
+    pub fn with_merge_memory_limit(mut self, limit: Option<MergeMemoryLimit>) -> Node {
+        self.open_query_handler.set_merge_limit(limit);
+        self
+    }
+
+    /// Sets how long this node keeps the writes it could not send to a replica.
+    ///
+    /// # Purpose
+    /// A write that can not reach one of its replicas is stored as a hint and sent again once gossip reports
+    /// the replica back to `Normal` (Cassandra's hinted handoff). Hints older than `ttl` are dropped instead of
+    /// replayed (`max_hint_window` in Cassandra), so a replica that was down for longer must be repaired.
+    ///
+    /// # Parameters
+    /// - `ttl: Duration`
+    ///   - The time a hint is kept since its write was received. Nodes use `DEFAULT_HINT_TTL` (3 hours) otherwise.
+    pub fn with_hint_ttl(mut self, ttl: Duration) -> Node {
+        self.hints = self.hints.with_ttl(ttl);
+        self
+    }
+
+    /// Sets the schema script this node runs if it forms the cluster.
+    ///
+    /// # Purpose
+    /// A new cluster has no keyspaces, so its clients had to create them before loading any data. The first
+    /// seed of the cluster runs `script` once it joins the ring with no other node in it, as if it was imported
+    /// with the admin `IMPORT SCHEMA` command, and records it in the `system.schema_bootstrap` virtual table.
+    ///
+    /// # Parameters
+    /// - `source: &str`
+    ///   - Where the script was read from, shown in the record.
+    /// - `script: &str`
+    ///   - The CQL script, with `CREATE KEYSPACE`, `CREATE TABLE` and `USE` statements.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - Returns `Err(NodeError::ScriptError)` if a statement of the script is not valid, so the node does not
+    ///     start with a script it can not run.
+    ///
+    /// # Notes
+    /// - The other nodes ignore the script, and so does the first seed when it restarts into a cluster that is
+    ///   already formed: the schema reaches them through gossip.
+    /// - The run is recorded in a file next to the commit log, so the script is never run again by the node, even
+    ///   if it forms a new cluster after a restart with its data.
+    pub fn with_initial_schema(mut self, source: &str, script: &str) -> Result<Node, NodeError> {
+        schema_script::import_statements(script)?;
+        self.initial_schema = Some((source.to_string(), script.to_string()));
+        Ok(self)
+    }
+
+    /// Sets how often this node compacts the data files of its tables, and which ones.
+    ///
+    /// # Purpose
+    /// Overwritten and deleted rows, and the rows past their TTL, stay in the files of a table until it is
+    /// compacted. Every `interval` the node compacts, in the background, the tables that need it: with the LSM
+    /// backend the ones with at least `min_sstables` SSTables, whose live rows are merged back into their data
+    /// file, and with the CSV backend the ones with expired rows, which are rewritten without them.
+    ///
+    /// # Parameters
+    /// - `settings: CompactionSettings`
+    ///   - The interval and the SSTables that trigger a compaction. Nodes use `CompactionSettings::default()`
+    ///     otherwise, which compacts every minute the tables with 4 SSTables.
+    ///
+    /// # Notes
+    /// - The `COMPACT` admin command still compacts every SSTable of a keyspace right away.
+    pub fn with_compaction(mut self, settings: CompactionSettings) -> Node {
+        self.compaction = settings;
+        self
+    }
+
+    /// Sets what this node does with the keyspaces its clients create or alter with more replicas than live nodes.
+    ///
+    /// # Purpose
+    /// A keyspace whose replication factor (or the one of any of its datacenters) is above the live nodes of the
+    /// ring gets fewer replicas than it asks for, so its consistency levels such as `ALL` can never be met. The
+    /// coordinator of a `CREATE KEYSPACE` or `ALTER KEYSPACE` checks the replication against the ring first.
+    ///
+    /// # Parameters
+    /// - `check: ReplicationCheck`
+    ///   - `ReplicationCheck::Warn` (the default) runs the statement and logs a warning, and
+    ///     `ReplicationCheck::Reject` fails it with an `Invalid` error.
+    ///
+    /// # Notes
+    /// - Every node still warns, whatever the check, when the nodes that die leave a keyspace with more replicas
+    ///   than live nodes, and logs when the keyspace recovers.
+    pub fn with_replication_check(mut self, check: ReplicationCheck) -> Node {
+        self.replication_check = check;
+        self
+    }
+
+    /// Selects how this node stores the rows of its tables.
+    ///
+    /// # Purpose
+    /// The CSV backend keeps each table sorted in a single file, which is rewritten on every write, so writes
+    /// get slower as tables grow. The LSM backend (Cassandra's own storage) writes to an in-memory memtable
+    /// instead, flushes it to an immutable, sorted SSTable once it is full, and merges them on every read.
+    ///
+    /// # Parameters
+    /// - `backend: StorageBackend`
+    ///   - The backend of the node. Nodes use `StorageBackend::Csv` otherwise.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - On success:
+    ///     - Returns the node storing its tables with the given backend.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the backend can not be registered.
+    ///
+    /// # Notes
+    /// - The rows of the memtables are only on disk once they are flushed, so the segments of the commit log are
+    ///   kept until every memtable is flushed (by the `FLUSH` admin command, a drain or a shutdown) instead of
+    ///   once their mutations are applied.
+    pub fn with_storage_backend(self, backend: StorageBackend) -> Result<Node, NodeError> {
+        self.storage_engine.clone().set_backend(backend)?;
+        self.commit_log
+            .lock()?
+            .retire_when_full(backend == StorageBackend::Csv);
+        Ok(self)
+    }
+
+    /// Makes this node cache in memory the rows read from its most recently read partitions.
+    ///
+    /// # Purpose
+    /// Some partitions are read again and again (such as the flights of an airport the GUI keeps looking up),
+    /// and every read of them goes to disk. With a row cache the rows a read matched in a partition are kept,
+    /// so the next reads of the partition with the same `WHERE` clause are answered from memory.
+    ///
+    /// # Parameters
+    /// - `partitions: usize`
+    ///   - How many partitions the cache holds. Once it is full, the least recently read partition is dropped.
+    ///     Nodes do not cache rows otherwise, nor with 0 partitions.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - On success:
+    ///     - Returns the node caching the rows of up to `partitions` partitions.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the cache can not be registered.
+    ///
+    /// # Notes
+    /// - Only reads that give every column of the partition key with `=` are cached.
+    /// - Every write drops the cached rows of the partition it writes, or of the whole table if it does not
+    ///   give a single partition. The hits and misses of the cache are shown by the `METRICS` admin command.
+    pub fn with_row_cache(self, partitions: usize) -> Result<Node, NodeError> {
+        self.storage_engine.clone().set_row_cache(partitions)?;
+        Ok(self)
+    }
+
+    /// Sets how many tokens (virtual nodes) every node of the cluster takes in the ring.
+    ///
+    /// # Purpose
+    /// With a single token per node the ranges of the ring can be very uneven, and a node joining or
+    /// leaving moves a whole range from or to a single neighbour. With many tokens per node (Cassandra's
+    /// `num_tokens`, 256 by default there) each node owns many small ranges spread over the ring.
+    ///
+    /// # Parameters
+    /// - `num_tokens: usize`
+    ///   - The tokens of each node. It must be the same in every node of the cluster, so they all build the same ring.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - On success:
+    ///     - Returns the node with its ring rebuilt with the given tokens per node.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the tokens of two nodes collide.
+    ///
+    /// # Notes
+    /// - Nodes with more than one token can not be moved, nor replace dead nodes, so this must be called before
+    ///   `with_replace_address`, which fails in that case.
+    pub fn with_num_tokens(mut self, num_tokens: usize) -> Result<Node, NodeError> {
+        let mut partitioner = Partitioner::new().with_vnodes(num_tokens);
+        for ip in self.partitioner.get_nodes() {
+            partitioner.add_node(ip)?;
+            partitioner.set_location(
+                ip,
+                self.partitioner.get_datacenter(&ip),
+                self.partitioner.get_rack(&ip),
+            );
+        }
+        self.partitioner = partitioner;
+        Ok(self)
+    }
+
+    /// Places this node in a datacenter and a rack.
+    ///
+    /// # Purpose
+    /// Keyspaces with `NetworkTopologyStrategy` keep a given amount of copies in each datacenter, spread over
+    /// its racks, so the cluster survives losing a whole rack or datacenter. The location of the node is
+    /// gossiped, so every node places the replicas of these keyspaces the same way.
+    ///
+    /// # Parameters
+    /// - `datacenter: &str`
+    ///   - The datacenter of the node. Nodes are in `datacenter1` otherwise, as in Cassandra.
+    /// - `rack: &str`
+    ///   - The rack of the node within its datacenter. Nodes are in `rack1` otherwise.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - On success:
+    ///     - Returns the node at the given location.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the node has no gossip state.
+    ///
+    /// # Notes
+    /// - The datacenters named in the keyspaces must match the ones given to the nodes: datacenters without
+    ///   nodes keep no copies.
+    pub fn with_location(mut self, datacenter: &str, rack: &str) -> Result<Node, NodeError> {
+        self.gossiper
+            .set_location(self.ip, datacenter, rack)
+            .map_err(|_| NodeError::GossipError)?;
+        self.partitioner.set_location(self.ip, datacenter, rack);
+        Ok(self)
+    }
+
+    /// Sets the authorizer that decides whether the clients of this node may run their statements.
+    ///
+    /// # Purpose
+    /// Every statement of a client is checked before it is executed, with the user the client logged in as, the
+    /// fingerprint of the statement, the keyspace and table it acts on and the permission it needs. Plugging an
+    /// `Authorizer` lets an external policy (for example, a policy service of the organization) decide who can
+    /// read, write or change the schema, without changing how queries are handled.
+    ///
+    /// # Parameters
+    /// - `authorizer: Arc<dyn Authorizer>`
+    ///   - The authorizer to use, such as a `TableAuthorizer` loaded from a rules file. Nodes allow every
+    ///     statement otherwise (`AllowAll`).
+    ///
+    /// # Returns
+    /// - `Node`
+    ///   - The node using the given authorizer.
+    ///
+    /// # Notes
+    /// - Only the statements of clients are authorized: the internode messages and the schema scripts run by
+    ///   the node itself are trusted.
+    /// - Each statement of a batch is authorized on its own, and the whole batch is denied if any of them is.
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn Authorizer>) -> Node {
+        self.authorizer = authorizer;
+        self
+    }
+
+    /// Caps the bandwidth this node uses towards each peer to stream rows and replay hints.
+    ///
+    /// # Purpose
+    /// A rebalance streams whole tables between nodes, and a replica back after a long outage gets every hint
+    /// kept for it. Without limits that traffic can saturate the network card shared with the client queries,
+    /// whose latency spikes until it is done (`stream_throughput_outbound` and `hinted_handoff_throttle` in
+    /// Cassandra).
+    ///
+    /// # Parameters
+    /// - `limits: BandwidthLimits`
+    ///   - The bytes per second each kind of traffic may use towards each peer. Nodes leave both unlimited
+    ///     otherwise.
+    ///
+    /// # Notes
+    /// - Each peer has its own queue for each kind of traffic, drained by a token bucket, so a slow peer does
+    ///   not hold back the others and the streamed rows do not wait behind the hints.
+    /// - The queries of the clients, their replies and gossip are never throttled.
+    pub fn with_bandwidth_limits(mut self, limits: BandwidthLimits) -> Node {
+        self.outbound = OutboundQueues::new(limits, self.config.internode_port);
+        self
+    }
+
+    /// Sets how long the connections of the clients of this node may stay idle, and open, before the node
+    /// closes them.
+    ///
+    /// # Purpose
+    /// Clients that never close their connections (a GUI that leaks them, or one that crashed without the
+    /// connection being reset) would otherwise keep a thread and a session of the node forever.
+    ///
+    /// # Parameters
+    /// - `limits: ClientConnectionLimits`
+    ///   - The time a connection may go without a request, and the time it may stay open since it was
+    ///     accepted. Nodes leave connections open for as long as their clients want otherwise.
+    ///
+    /// # Notes
+    /// - Connections are only closed between two requests, never while one of their queries is running.
+    /// - Before closing a connection the node sends it a `ServerError` frame with the reason, and logs it.
+    /// - The session of a client (its keyspace and user) is forgotten when its connection is closed, by
+    ///   either side.
+    pub fn with_client_connection_limits(mut self, limits: ClientConnectionLimits) -> Node {
+        self.client_limits = limits;
+        self
+    }
+
+    /// Sets how many connections and client queries this node handles at once.
+    ///
+    /// # Purpose
+    /// Each connection takes a worker of a pool for as long as it is open, and each query some memory and
+    /// the time of the node, so without limits a flood of clients (thousands of GUIs) exhausts the threads
+    /// and the memory of the node, and every query slows down with it.
+    ///
+    /// # Parameters
+    /// - `limits: ConcurrencyLimits`
+    ///   - The client and internode connections handled at once, and the client queries executed at once.
+    ///     Nodes use `ConcurrencyLimits::default()` otherwise.
+    ///
+    /// # Notes
+    /// - A client connection beyond the limit gets an `Overloaded` error and is closed right away, and so
+    ///   does a query beyond the limit, without closing its connection. Drivers send them to another node.
+    /// - An internode connection beyond the limit is closed, and its messages are lost as if the node was
+    ///   down, so the limit should be above the size of the cluster.
+    pub fn with_concurrency_limits(mut self, limits: ConcurrencyLimits) -> Node {
+        self.concurrency = limits;
+        self
+    }
+
+    /// Makes this node replace a dead node of the cluster instead of joining as a new ring member.
+    ///
+    /// # Purpose
+    /// Adding a brand new node shifts the ranges of its neighbours and moves data all over the ring.
+    /// A replacement instead seizes the token of the dead node, so it only takes over the ranges the
+    /// dead node owned, and the surviving replicas stream that data to it.
+    ///
+    /// # Parameters
+    /// - `dead_ip: Ipv4Addr`
+    ///   - The IP address of the dead node being replaced.
+    ///
+    /// # Returns
+    /// - `Result<Node, NodeError>`
+    ///   - On success:
+    ///     - Returns the node placed at the token of the dead node.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the token can not be computed or is taken by another node.
+    ///
+    /// # Behavior
+    /// 1. **Token Seizing**:
+    ///    - Places the node at the default token of the dead node (the hash of its IP address) and gossips it.
+    ///    - If the dead node had been moved, the token it gossiped is adopted as soon as it is learnt.
+    /// 2. **Data Streaming**:
+    ///    - Once the rest of the cluster detects the dead node, they put the replacement in its place
+    ///      and redistribute the data of its ranges to it.
+    ///
+    /// # Notes
+    /// - The dead node must really be dead: while it is alive for the rest of the cluster, its token
+    ///   is still taken and the replacement is kept out of their rings.
+    pub fn with_replace_address(mut self, dead_ip: Ipv4Addr) -> Result<Node, NodeError> {
+        let token = Partitioner::default_token(&dead_ip)?;
+
+        if self.partitioner.contains_node(&dead_ip) {
+            self.partitioner.remove_node(dead_ip)?;
+        }
+        self.partitioner.move_node(self.ip, token)?;
+        self.gossiper
+            .set_token(self.ip, token)
+            .map_err(|_| NodeError::GossipError)?;
+        self.replacing = Some(dead_ip);
+
+        self.logger.info(
+            &format!("REPLACING NODE {:?} AT TOKEN {}", dead_ip, token),
+            Color::Blue,
+            true,
+        )?;
+
+        Ok(self)
+    }
+
     /// Starts the gossip protocol for the node, enabling cluster membership and state sharing.
     ///
     /// # Purpose
@@ -171,15 +864,14 @@ impl Node {
     /// - `node: Arc<Mutex<Node>>`
     ///   - A thread-safe reference to the `Node` that will participate in the gossip protocol.
     ///   - The `Node` contains information about its state, schema, and connections to the cluster.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A thread-safe map of active connections to other nodes in the cluster.
-    ///     - Keys are node addresses as strings.
-    ///     - Values are thread-safe `TcpStream` objects for internode communication.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes in the cluster, by their internode address, which the gossip
+    ///     messages are sent through. Clones share the same connections.
     ///
     /// # Returns
-    /// - `Result<(), NodeError>`
+    /// - `Result<JoinHandle<()>, NodeError>`
     ///   - On success:
-    ///     - Returns `Ok(())` indicating that the gossip protocol started successfully.
+    ///     - Returns the gossip thread, which stops once the node is shut down.
     ///   - On failure:
     ///     - Returns `Err(NodeError)` if the initialization of the gossip thread fails.
     ///
@@ -201,14 +893,21 @@ impl Node {
     ///
     /// 4. **Partitioner Updates**:
     ///    - Adjusts the partitioner when nodes join or leave the cluster.
+    ///    - Moves a node in the ring when the token it gossips changes (see the `MOVE` admin command).
     ///    - Redistributes data across the cluster when changes in membership occur.
     ///
     /// 5. **Fault Tolerance**:
-    ///    - Detects dead nodes and removes them from the partitioner to avoid stale data.
+    ///    - Detects dead nodes and removes them from the partitioner to avoid stale data. A peer is
+    ///      marked dead when the failure detector of the gossiper suspects it from the arrival of its
+    ///      heartbeats, not when a single gossip message to it fails.
     ///    - Adds new nodes to the partitioner and redistributes data to maintain consistency.
+    ///    - Detects nodes that restarted with empty storage (a new heartbeat generation gossiping `empty_data`)
+    ///      and redistributes data so their ranges are streamed to them again.
     ///
     /// # Thread Execution
-    /// - The gossip protocol runs indefinitely in a loop with a sleep interval of 1200ms between iterations.
+    /// - The gossip protocol runs in a loop until the node is shut down, after the startup delay of the gossip
+    ///   config of the node.
+    ///   Iterations are separated by its round interval plus a random part of its jitter.
     /// - Within each iteration:
     ///   - The node sends and receives gossip messages.
     ///   - Updates its internal state, schema, and partitioner as needed.
@@ -237,63 +936,85 @@ impl Node {
 
     pub fn start_gossip(
         node: Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-    ) -> Result<(), NodeError> {
-        let _ = thread::spawn(move || {
+        connections: ConnectionManager,
+    ) -> Result<JoinHandle<()>, NodeError> {
+        let shutdown = node.lock()?.shutdown.clone();
+        let gossip = move || -> Result<(), NodeError> {
+            let startup_delay = match node.lock() {
+                Ok(node_guard) => node_guard.gossiper.config().startup_delay,
+                Err(_) => return Err(NodeError::LockError),
+            };
+            if shutdown.sleep(startup_delay) {
+                return Ok(());
+            }
+
             let initial_gossip = Instant::now();
+            let mut state_saved: Option<Instant> = None;
             let mut log;
             loop {
                 {
                     {
                         let mut node_guard = match node.lock() {
                             Ok(guard) => guard,
-                            Err(_) => return NodeError::LockError,
+                            Err(_) => return Err(NodeError::LockError),
                         };
 
                         let ip = node_guard.ip;
-                        log = node_guard.get_logger();
-                        if initial_gossip.elapsed().as_millis() > 3000 {
-                            node_guard
-                                .gossiper
-                                .change_status(ip, NodeStatus::Normal)
-                                .ok();
-                        }
-                        let _ = node_guard.gossiper.heartbeat(ip);
-                    }
-
-                    let ips: Vec<Ipv4Addr>;
-                    let syn;
-                    {
-                        let node_guard = match node.lock() {
-                            Ok(guard) => guard,
-                            Err(_) => return NodeError::LockError,
-                        };
-                        ips = node_guard
+                        log = node_guard.get_logger().with_component(Component::Gossip);
+                        // Only a bootstrapping node becomes Normal, once it has the rows of its
+                        // ranges: a leaving node must keep announcing its status until it is gone.
+                        let is_starting = node_guard
                             .gossiper
-                            .pick_ips(node_guard.get_ip())
-                            .iter()
-                            .map(|x| **x)
-                            .collect();
-                        syn = node_guard.gossiper.create_syn(node_guard.ip);
+                            .get_status(ip)
+                            .is_ok_and(|status| status.is_starting());
+                        if is_starting
+                            && !node_guard.bootstrapping
+                            && initial_gossip.elapsed().as_millis() > 3000
+                        {
+                            node_guard.bootstrapping = true;
+                            let node = Arc::clone(&node);
+                            let connections = connections.clone();
+                            thread::spawn(move || {
+                                if let Err(e) = Node::bootstrap(&node, connections) {
+                                    if let Ok(mut node_guard) = node.lock() {
+                                        let _ = node_guard.get_logger().error(
+                                            &format!("BOOTSTRAP FAILED, RETRYING: {}", e),
+                                            true,
+                                        );
+                                        node_guard.bootstrapping = false;
+                                    }
+                                }
+                            });
+                        }
                     }
 
                     let mut node_guard = match node.lock() {
                         Ok(guard) => guard,
-                        Err(_) => return NodeError::LockError,
+                        Err(_) => return Err(NodeError::LockError),
                     };
 
-                    for ip in ips {
-                        let connections_clone = Arc::clone(&connections);
-                        let msg = InternodeMessage::new(
-                            ip.clone(),
-                            InternodeMessageContent::Gossip(syn.clone()),
-                        );
+                    let ip = node_guard.ip;
+                    let transport = InternodeGossipTransport::new(
+                        ip,
+                        node_guard.config.internode_port,
+                        connections.clone(),
+                        node_guard.blocked_peers.clone(),
+                    );
+                    let _ = node_guard.gossiper.gossip_round(ip, &transport);
 
-                        if connect_and_send_message(ip, INTERNODE_PORT, connections_clone, msg)
-                            .is_err()
-                        {
-                            node_guard.gossiper.kill(ip).ok();
-                        }
+                    // Kept so the node remembers the cluster and its generation once it restarts.
+                    // The file is written once the lock of the node is released.
+                    let state_to_save = if state_saved
+                        .is_none_or(|saved| saved.elapsed() >= GOSSIP_STATE_SAVE_INTERVAL)
+                    {
+                        state_saved = Some(Instant::now());
+                        node_guard.gossiper.state_to_save()
+                    } else {
+                        None
+                    };
+                    drop(node_guard);
+                    if let Some(Err(e)) = state_to_save.map(|state| state.write()) {
+                        let _ = log.warn(&format!("GOSSIP: {}", e), true);
                     }
                 }
 
@@ -301,7 +1022,7 @@ impl Node {
                 {
                     let mut node_guard = match node.lock() {
                         Ok(guard) => guard,
-                        Err(_) => return NodeError::LockError,
+                        Err(_) => return Err(NodeError::LockError),
                     };
 
                     let ip = node_guard.ip;
@@ -313,55 +1034,75 @@ impl Node {
                         {
                             endpoint_state.application_state.set_schema(schema);
                         } else {
-                            return NodeError::GossipError;
+                            return Err(NodeError::GossipError);
                         }
                     }
 
                     // Updates the latest schema from the gossiper
-                    if let Err(e) = node_guard.set_latest_schema_from_gossiper() {
-                        return e;
-                    };
+                    node_guard.set_latest_schema_from_gossiper()?;
                 }
 
                 // After each gossip round, update the partitioner
                 {
                     // Bloqueo del mutex solo para extraer lo necesario
-                    let (storage_path, self_ip, keyspaces, logger) = {
+                    let (keyspaces, logger) = {
                         let node_guard = match node.lock() {
                             Ok(guard) => guard,
-                            Err(_) => return NodeError::LockError,
+                            Err(_) => return Err(NodeError::LockError),
                         };
 
                         (
-                            node_guard.storage_path.clone(), // Clonar el path de almacenamiento
-                            node_guard.get_ip().to_string(), // Clonar el IP
                             node_guard.schema.keyspaces.clone(),
                             node_guard.get_logger(), // Clonar los keyspaces desde el guard     // Referencia mutable al particionador
                         )
                     };
                     let mut node_guard = match node.lock() {
                         Ok(guard) => guard,
-                        Err(_) => return NodeError::LockError,
+                        Err(_) => return Err(NodeError::LockError),
                     };
+
+                    // A replacement takes the token gossiped by the dead node, in case it had been moved
+                    if let Some(dead_ip) = node_guard.replacing {
+                        if let Ok(Some(token)) = node_guard.gossiper.get_token(dead_ip) {
+                            let ip = node_guard.ip;
+                            node_guard.gossiper.set_token(ip, token).ok();
+                            node_guard.replacing = None;
+                        }
+                    }
+
                     let endpoints_states = &node_guard.gossiper.endpoints_state.clone();
+                    let mut peer_generations = std::mem::take(&mut node_guard.peer_generations);
                     let partitioner = &mut node_guard.partitioner;
                     let mut needs_to_redistribute = false;
+                    let mut ring_events = Vec::new();
+
+                    // Dead and decommissioned nodes go first so their tokens are free for the
+                    // nodes replacing them
+                    let mut endpoints_states: Vec<_> = endpoints_states.iter().collect();
+                    endpoints_states.sort_by_key(|(_, state)| {
+                        let status = &state.application_state.status;
+                        status.is_alive() && !status.is_left()
+                    });
 
                     for (ip, state) in endpoints_states {
+                        let generation = state.heartbeat_state.generation;
+                        let previous_generation = peer_generations.insert(*ip, generation);
+
                         let is_in_partitioner: bool;
                         let result = partitioner.node_already_in_partitioner(ip);
                         if let Ok(is_in) = result {
                             is_in_partitioner = is_in;
                         } else {
-                            return NodeError::PartitionerError(
+                            return Err(NodeError::PartitionerError(
                                 partitioner::errors::PartitionerError::HashError,
-                            );
+                            ));
                         }
 
                         if state.application_state.status.is_dead() {
                             if is_in_partitioner {
                                 needs_to_redistribute = true;
                                 partitioner.remove_node(*ip).ok();
+                                ring_events.push(NodeEvent::NodeDied(*ip));
                                 let _ = log.info(
                                     &format!(
                                         "NODE {:?} IS DEAD .. New Ring: {:?}",
@@ -371,65 +1112,671 @@ impl Node {
                                     true,
                                 );
                             }
+                        } else if state.application_state.status.is_removed() {
+                            // Removed nodes are not tracked anymore, so the removal is seen once.
+                            // Its ranges are streamed from the replicas left, as for a dead node
+                            peer_generations.remove(ip);
+                            if is_in_partitioner {
+                                needs_to_redistribute = true;
+                                partitioner.remove_node(*ip).ok();
+                            }
+                            if is_in_partitioner || previous_generation.is_some() {
+                                ring_events.push(NodeEvent::NodeRemoved(*ip));
+                                let _ = log.info(
+                                    &format!(
+                                        "NODE {:?} WAS REMOVED .. New Ring: {:?}",
+                                        ip, partitioner
+                                    ),
+                                    Color::Red,
+                                    true,
+                                );
+                            }
+                        } else if state.application_state.status.is_left() {
+                            // The node already streamed its data to the nodes taking its ranges
+                            if is_in_partitioner {
+                                partitioner.remove_node(*ip).ok();
+                                ring_events.push(NodeEvent::NodeLeft(*ip));
+                                let _ = log.info(
+                                    &format!("NODE {:?} LEFT .. New Ring: {:?}", ip, partitioner),
+                                    Color::Yellow,
+                                    true,
+                                );
+                            }
                         } else {
+                            // The replicas of NetworkTopologyStrategy keyspaces depend on where
+                            // the nodes are
+                            let app_state = &state.application_state;
+                            if partitioner.get_datacenter(ip) != app_state.datacenter
+                                || partitioner.get_rack(ip) != app_state.rack
+                            {
+                                partitioner.set_location(
+                                    *ip,
+                                    &app_state.datacenter,
+                                    &app_state.rack,
+                                );
+                                needs_to_redistribute |= is_in_partitioner;
+                            }
+
+                            // Nodes that were never moved sit at the hashes of their ip
+                            let mut tokens = match state.application_state.token {
+                                Some(token) => vec![token],
+                                None => match partitioner.default_tokens(ip) {
+                                    Ok(tokens) => tokens,
+                                    Err(e) => return Err(NodeError::PartitionerError(e)),
+                                },
+                            };
+                            tokens.sort_unstable();
+
+                            // Bootstrapping nodes join once they have the rows of their ranges
+                            if !is_in_partitioner && state.application_state.status.is_starting() {
+                                continue;
+                            }
+
                             if !is_in_partitioner {
                                 //println!("se acaba de unir un nodo, redistribuyo");
+                                // The tokens may still belong to a node not yet seen as dead
+                                if partitioner.add_node_with_tokens(*ip, &tokens).is_err() {
+                                    continue;
+                                }
                                 needs_to_redistribute = true;
-                                partitioner.add_node(*ip).ok();
+                                ring_events.push(NodeEvent::NodeJoined(*ip));
                                 let _ = log.info(
                                     &format!("NEW NODE {:?} .. New Ring: {:?}", ip, partitioner),
                                     Color::Green,
                                     true,
                                 );
+                            } else if partitioner.get_tokens(ip) != tokens
+                                && partitioner.set_tokens(*ip, &tokens).is_ok()
+                            {
+                                needs_to_redistribute = true;
+                                ring_events.push(NodeEvent::NodeMoved(*ip));
+                                let _ = log.info(
+                                    &format!(
+                                        "NODE {:?} MOVED TO TOKEN {} .. New Ring: {:?}",
+                                        ip, tokens[0], partitioner
+                                    ),
+                                    Color::Blue,
+                                    true,
+                                );
+                            } else if previous_generation
+                                .is_some_and(|previous| previous != 0 && previous != generation)
+                                && state.application_state.empty_data
+                            {
+                                // A new generation of a node that gossips it started without its
+                                // data means it restarted with empty storage, so its ranges are
+                                // streamed to it again. Nodes that kept their data do not need it
+                                needs_to_redistribute = true;
+                                ring_events.push(NodeEvent::NodeRestarted(*ip));
+                                let _ = log.info(
+                                    &format!(
+                                        "NODE {:?} RESTARTED WITH EMPTY DATA .. Restreaming its ranges",
+                                        ip
+                                    ),
+                                    Color::Yellow,
+                                    true,
+                                );
                             }
                         }
                     }
 
                     if needs_to_redistribute {
+                        let partitioner = partitioner.clone();
+                        for event in ring_events {
+                            node_guard.events.record(event);
+                        }
+                        node_guard.events.record(NodeEvent::RedistributionStarted);
                         let _ = logger.info("START REDISTRIBUTION...", Color::Cyan, true);
 
                         // Clonar las variables necesarias para el nuevo hilo
-                        let storage_path = storage_path.clone();
-                        let self_ip = self_ip.clone();
-                        let partitioner = partitioner.clone();
+                        let storage_engine = node_guard.storage_engine.clone();
                         let logger = logger.clone();
                         let connections = connections.clone();
                         let keyspaces: Vec<KeyspaceSchema> = keyspaces.values().cloned().collect();
 
-                        let redistribution_result =
-                            storage_engine::StorageEngine::new(storage_path, self_ip)
-                                .redistribute_data(
-                                    keyspaces,
-                                    &partitioner,
-                                    logger.clone(),
-                                    connections,
-                                );
+                        // Chunks are queued behind the others streamed to the same node, which may be throttled
+                        let (sessions, outbound) = (
+                            node_guard.stream_sessions.clone(),
+                            node_guard.outbound.clone(),
+                        );
+                        let stream = |target_ip: Ipv4Addr, message: InternodeMessage| {
+                            let _ = Node::send_streamed(
+                                &sessions,
+                                &outbound,
+                                target_ip,
+                                message,
+                                connections.clone(),
+                            );
+                        };
+                        let redistribution_result = storage_engine.redistribute_data(
+                            keyspaces,
+                            &partitioner,
+                            logger.clone(),
+                            &node_guard.commit_log,
+                            &stream,
+                        );
 
                         match redistribution_result {
                             Ok(_) => {
+                                node_guard.events.record(NodeEvent::RedistributionFinished);
                                 let _ =
                                     logger
                                         .clone()
                                         .info("END REDISTRIBUTION...", Color::Cyan, true);
                             }
                             Err(e) => {
+                                node_guard.events.record(NodeEvent::RedistributionFailed);
                                 let _ = logger
                                     .clone()
                                     .error(&format!("REDISTRIBUTION FAILED! {:?}", e), true);
                             }
                         }
+                    } else {
+                        // Nodes that leave the ring without changing its ranges, like the ones
+                        // that were already dead when removed
+                        for event in ring_events {
+                            node_guard.events.record(event);
+                        }
                     }
+                    node_guard.peer_generations = peer_generations;
+                    node_guard.check_keyspaces_replication(&log);
                 }
-                let gossip_logger = log.clone();
-                let _ = gossip_logger
-                    .clone()
+
+                // Replicas back to normal get the writes they missed
+                if let Err(e) = Node::replay_hints(&node, connections.clone()) {
+                    let _ = log.error(&format!("Failed to replay hints: {}", e), true);
+                }
+                // And the chunks of rows streamed to them that were lost
+                if let Err(e) = Node::resume_streams(&node, connections.clone()) {
+                    let _ = log.error(&format!("Failed to resume streams: {}", e), true);
+                }
+
+                let gossip_logger = log.clone();
+                let _ = gossip_logger
+                    .clone()
                     .info("GOSSIP: New Gossip Round", Color::White, true);
-                thread::sleep(std::time::Duration::from_millis(1000));
+                let round_delay = match node.lock() {
+                    Ok(node_guard) => node_guard.gossiper.config().next_round_delay(),
+                    Err(_) => return Err(NodeError::LockError),
+                };
+                if shutdown.sleep(round_delay) {
+                    return Ok(());
+                }
             }
-        });
+        };
+        Ok(thread::spawn(move || {
+            if let Err(e) = gossip() {
+                eprintln!("Gossip stopped: {:?}", e);
+            }
+        }))
+    }
+
+    /// Sends the hints kept for the replicas that gossip reports back to `Normal`.
+    ///
+    /// # Purpose
+    /// Writes that could not reach a replica are kept as hints (see `HintStore`). Once the replica is back,
+    /// they are sent to it as they were, with the timestamp of the original write, so the replica catches up
+    /// without waiting for a repair.
+    ///
+    /// # Behavior
+    /// - Expired hints are dropped instead of sent.
+    /// - The hints are queued in the outbound queue of hints of their replica, which sends them at its
+    ///   bandwidth limit without holding back the gossip rounds.
+    /// - If a hint can not be sent, it is kept for a later gossip round.
+    fn replay_hints(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+    ) -> Result<(), NodeError> {
+        let (self_ip, hints, targets, logger, outbound) = {
+            let node_guard = node.lock()?;
+            let targets: Vec<Ipv4Addr> = node_guard
+                .hints
+                .targets()
+                .into_iter()
+                .filter(|ip| {
+                    node_guard
+                        .gossiper
+                        .get_status(*ip)
+                        .is_ok_and(|status| status.is_normal())
+                })
+                .collect();
+            (
+                node_guard.ip,
+                node_guard.hints.clone(),
+                targets,
+                node_guard.get_logger(),
+                node_guard.outbound.clone(),
+            )
+        };
+
+        for target in targets {
+            let pending = hints.take(target)?;
+            for (i, hint) in pending.iter().enumerate() {
+                let message =
+                    InternodeMessage::new(self_ip, InternodeMessageContent::Query(hint.clone()));
+                let (stored, hint) = (hints.clone(), hint.clone());
+                let queued = outbound.send(
+                    target,
+                    Traffic::Hints,
+                    message,
+                    connections.clone(),
+                    Some(Box::new(move || {
+                        let _ = stored.store(target, &hint);
+                    })),
+                );
+                // The hints not queued are stored again, as `take` removed them
+                if let Err(e) = queued {
+                    for hint in &pending[i..] {
+                        hints.store(target, hint)?;
+                    }
+                    return Err(e);
+                }
+            }
+
+            if !pending.is_empty() {
+                logger.info(
+                    &format!("HINTS: replaying {} writes to {:?}", pending.len(), target),
+                    Color::Cyan,
+                    true,
+                )?;
+            }
+        }
         Ok(())
     }
 
+    /// Sends again the chunks of rows the node streamed that were not acknowledged in time, to the nodes
+    /// gossip reports as `Normal`.
+    ///
+    /// # Purpose
+    /// Chunks that were lost with a connection or a restart of the node taking them are kept (see
+    /// `StreamSessions`), so the stream resumes from them instead of running again from its start.
+    ///
+    /// # Behavior
+    /// - Chunks kept longer than `STREAM_CHUNK_TTL` are dropped instead of sent.
+    /// - The chunks are queued in the streaming queue of their node, paced like the rest of the stream.
+    fn resume_streams(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+    ) -> Result<(), NodeError> {
+        let (self_ip, sessions, outbound, logger, due, dropped) = {
+            let node_guard = node.lock()?;
+            let (due, dropped) = node_guard.stream_sessions.due(|ip| {
+                node_guard
+                    .gossiper
+                    .get_status(ip)
+                    .is_ok_and(|status| status.is_normal())
+            })?;
+            (
+                node_guard.ip,
+                node_guard.stream_sessions.clone(),
+                node_guard.outbound.clone(),
+                node_guard.get_logger(),
+                due,
+                dropped,
+            )
+        };
+
+        if dropped > 0 {
+            logger.warn(
+                &format!("STREAMING: {} unacknowledged chunks expired", dropped),
+                true,
+            )?;
+        }
+        if !due.is_empty() {
+            logger.info(
+                &format!("STREAMING: resending {} unacknowledged chunks", due.len()),
+                Color::Cyan,
+                true,
+            )?;
+        }
+        for (target, chunk) in due {
+            let message =
+                InternodeMessage::new(self_ip, InternodeMessageContent::StreamChunk(chunk));
+            Node::send_streamed(&sessions, &outbound, target, message, connections.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Queues a message in the streaming queue of `target`, keeping the chunk of rows it carries, if any,
+    /// until `target` acknowledges it.
+    ///
+    /// # Parameters
+    /// - `sessions: &StreamSessions`
+    ///   - The chunks of the node waiting for their acknowledgment.
+    /// - `outbound: &OutboundQueues`
+    ///   - The outbound queues of the node, which pace the streams to each node.
+    /// - `target: Ipv4Addr`
+    ///   - The node the message is streamed to.
+    /// - `message: InternodeMessage`
+    ///   - The message, usually a `StreamChunk`.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes.
+    ///
+    /// # Returns
+    /// - `Result<(), NodeError>`
+    ///   - Returns `Err(NodeError)` if the chunk can not be kept or the message can not be queued.
+    pub(crate) fn send_streamed(
+        sessions: &StreamSessions,
+        outbound: &OutboundQueues,
+        target: Ipv4Addr,
+        message: InternodeMessage,
+        connections: ConnectionManager,
+    ) -> Result<(), NodeError> {
+        // A chunk that can not be sent is kept anyway, and sent again later
+        if let InternodeMessageContent::StreamChunk(chunk) = &message.content {
+            sessions.record(target, chunk)?;
+        }
+        outbound.send(target, Traffic::Streaming, message, connections, None)
+    }
+
+    /// Starts the background thread that answers the open queries that timed out.
+    ///
+    /// # Purpose
+    /// Every `QUERY_TIMEOUT_CHECK_INTERVAL` the open queries whose deadline passed are closed and their
+    /// clients get a `ReadTimeout` or `WriteTimeout` error, instead of waiting forever for replicas that are
+    /// down or overloaded.
+    ///
+    /// # Behavior
+    /// - The timeout is recorded in the latency metrics of the keyspace and operation of the query, and logged.
+    /// - A write that timed out is stored as a hint for every replica that did not answer it, as they may have
+    ///   missed it, and replayed once they are back to `Normal`.
+    /// - Responses that arrive after the timeout are ignored, as the query is not open anymore.
+    fn start_query_timeouts(node: Arc<Mutex<Node>>, shutdown: ShutdownSignal) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if shutdown.sleep(QUERY_TIMEOUT_CHECK_INTERVAL) {
+                return;
+            }
+
+            let Ok(mut node_guard) = node.lock() else {
+                return;
+            };
+            let logger = node_guard.get_logger().with_component(Component::Native);
+            let timed_out = node_guard
+                .open_query_handler
+                .take_timed_out_queries(Instant::now());
+
+            for (query, keyspace) in timed_out {
+                let query_logger = logger.with_correlation_id(&query.get_correlation_id());
+                if let Some((keyspace, operation)) = keyspace.zip(query.get_operation()) {
+                    node_guard
+                        .metrics
+                        .record_timeout(&keyspace.get_name(), operation);
+                }
+                let mut outcomes: Vec<_> = query.replica_outcomes().iter().collect();
+                outcomes.sort_by_key(|(ip, _)| **ip);
+                query_logger
+                    .warn(
+                        &format!("NATIVE: query timed out, replicas: {:?}", outcomes),
+                        true,
+                    )
+                    .ok();
+                if query.get_operation() != Some(Operation::Read) {
+                    for (replica, missed) in query.missed_replicas() {
+                        if replica == node_guard.ip {
+                            continue;
+                        }
+                        let stored = match node_guard.hints.store(replica, &missed) {
+                            Ok(_) => {
+                                format!("NATIVE: stored a hint for {} after the timeout", replica)
+                            }
+                            Err(e) => {
+                                format!("NATIVE: failed to store a hint for {}: {}", replica, e)
+                            }
+                        };
+                        query_logger.warn(&stored, true).ok();
+                    }
+                }
+                // The client may have disconnected already
+                query.get_connection().send(query.timeout_error()).ok();
+            }
+        })
+    }
+
+    /// Starts the background thread that forgets the internode connections that broke.
+    ///
+    /// # Purpose
+    /// A connection breaks when its peer goes down, restarts or stops reading it. Every
+    /// `BROKEN_CONNECTIONS_CHECK_INTERVAL` the broken ones are dropped with their writer threads, so
+    /// the connections of the node do not pile up as peers come and go, and the next message to
+    /// each of those peers opens a new one.
+    fn start_connection_health_checks(
+        connections: ConnectionManager,
+        log: Logger,
+        shutdown: ShutdownSignal,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if shutdown.sleep(BROKEN_CONNECTIONS_CHECK_INTERVAL) {
+                return;
+            }
+
+            match connections.check_health() {
+                Ok(0) => {}
+                Ok(forgotten) => {
+                    log.info(
+                        &format!("INTERNODE: {} BROKEN CONNECTIONS WERE DROPPED", forgotten),
+                        Color::Yellow,
+                        true,
+                    )
+                    .ok();
+                }
+                Err(_) => return,
+            }
+        })
+    }
+
+    /// Starts the background thread that sends the reads whose replicas are slow to another replica.
+    ///
+    /// # Purpose
+    /// Reads are sent to the replicas their consistency level needs, so a single overloaded replica would
+    /// hold them until it answers. Every `SPECULATIVE_RETRY_CHECK_INTERVAL` the reads that waited longer than
+    /// the p99 read latency of their keyspace (`DEFAULT_SPECULATIVE_RETRY_DELAY` until it has enough samples),
+    /// or that a replica answered with an error, are sent to one of their other replicas, and the first
+    /// responses that complete the consistency level answer the client.
+    ///
+    /// # Behavior
+    /// - A replica that can not be reached counts as an error response of the read.
+    fn start_speculative_retries(
+        node: Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        shutdown: ShutdownSignal,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if shutdown.sleep(SPECULATIVE_RETRY_CHECK_INTERVAL) {
+                return;
+            }
+
+            // The retries are taken under the lock of the node and sent without it, as a replica
+            // that can not be reached may take up to the connection timeout
+            let (retries, self_ip, internode_port, logger) = {
+                let Ok(mut node_guard) = node.lock() else {
+                    return;
+                };
+                let node_ref = &mut *node_guard;
+                let metrics = &node_ref.metrics;
+                let retries = node_ref.open_query_handler.take_speculative_retries(
+                    Instant::now(),
+                    |keyspace| {
+                        metrics
+                            .p99(keyspace, Operation::Read)
+                            .unwrap_or(DEFAULT_SPECULATIVE_RETRY_DELAY)
+                    },
+                );
+                (
+                    retries,
+                    node_ref.ip,
+                    node_ref.config.internode_port,
+                    node_ref.get_logger().with_component(Component::Internode),
+                )
+            };
+
+            for (open_query_id, target, query) in retries {
+                logger
+                    .with_correlation_id(&query.correlation_id)
+                    .info(
+                        &format!(
+                            "INTERNODE (Query: {:?}): REPLICAS ARE SLOW, I SENT {:?} to {:?}",
+                            open_query_id,
+                            query.cql(),
+                            target
+                        ),
+                        Color::Yellow,
+                        true,
+                    )
+                    .ok();
+                let message = InternodeMessage::new(self_ip, InternodeMessageContent::Query(query));
+                let sent =
+                    connect_and_send_message(target, internode_port, connections.clone(), message);
+                if sent.is_err() {
+                    let Ok(mut node_guard) = node.lock() else {
+                        return;
+                    };
+                    InternodeProtocolHandler::add_error_response_to_open_query_and_send_response_if_closed(
+                        &mut node_guard.open_query_handler,
+                        open_query_id,
+                        Some(target),
+                    )
+                    .ok();
+                }
+            }
+        })
+    }
+
+    /// Starts the background thread that estimates the droppable data of every table of the schema.
+    ///
+    /// # Purpose
+    /// Deleted cells and rows past the `default_time_to_live` of their table stay in its data files until
+    /// a compaction rewrites them. Every `TABLE_STATS_INTERVAL` this thread scans the tables stored by the
+    /// node (owned rows and replicas) and keeps the estimate in the table metrics, so operators can see which
+    /// tables need a compaction through the `METRICS` admin command or the `system.table_stats` virtual table.
+    ///
+    /// # Behavior
+    /// - The tables are scanned without holding the lock of the node, which is only taken to read the
+    ///   schema and to store the results.
+    /// - A warning is logged when a table starts needing a compaction, once until it stops needing it.
+    /// - Tables that can not be scanned are skipped and logged, the others are still refreshed.
+    fn start_table_stats(node: Arc<Mutex<Node>>, shutdown: ShutdownSignal) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            if shutdown.sleep(TABLE_STATS_INTERVAL) {
+                return;
+            }
+
+            let (storage, tables, logger) = {
+                let Ok(node_guard) = node.lock() else {
+                    return;
+                };
+                let tables: Vec<(String, TableSchema)> = node_guard
+                    .schema
+                    .keyspaces
+                    .iter()
+                    .flat_map(|(name, keyspace)| {
+                        keyspace
+                            .get_tables()
+                            .into_iter()
+                            .map(move |table| (name.clone(), table))
+                    })
+                    .collect();
+                (
+                    node_guard.storage_engine.clone(),
+                    tables,
+                    node_guard.get_logger().with_component(Component::Storage),
+                )
+            };
+
+            let now = Self::current_timestamp();
+            let mut stats = std::collections::BTreeMap::new();
+            for (keyspace, table) in tables {
+                match storage.table_stats(&keyspace, &table, now) {
+                    Ok(table_stats) => {
+                        stats.insert((keyspace, table.get_name()), table_stats);
+                    }
+                    Err(e) => {
+                        let message = format!(
+                            "TABLE STATS: could not scan {}.{}: {}",
+                            keyspace,
+                            table.get_name(),
+                            e
+                        );
+                        logger.error(&message, true).ok();
+                    }
+                }
+            }
+
+            let Ok(mut node_guard) = node.lock() else {
+                return;
+            };
+            for (keyspace, table) in node_guard.table_metrics.refresh(stats) {
+                let message = format!("TABLE STATS: {}.{} needs a compaction", keyspace, table);
+                logger.warn(&message, true).ok();
+            }
+        })
+    }
+
+    /// Starts the background thread that compacts the tables of the node.
+    ///
+    /// # Purpose
+    /// Every `interval` of the compaction settings of the node this thread compacts the tables of the schema
+    /// that need it (see `with_compaction`), dropping the shadowed versions of their rows, their tombstones and
+    /// their expired rows.
+    ///
+    /// # Behavior
+    /// - The tables are compacted without holding the lock of the node, which is only taken to read the schema.
+    /// - Every compacted table is logged with what was merged or dropped.
+    /// - Tables that can not be compacted are skipped and logged, the others are still compacted.
+    fn start_compaction(node: Arc<Mutex<Node>>, shutdown: ShutdownSignal) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let Ok(settings) = node.lock().map(|node_guard| node_guard.compaction) else {
+                return;
+            };
+            while !shutdown.sleep(settings.interval) {
+                let (storage, tables, logger) = {
+                    let Ok(node_guard) = node.lock() else {
+                        return;
+                    };
+                    let tables: Vec<(String, TableSchema)> = node_guard
+                        .schema
+                        .keyspaces
+                        .iter()
+                        .flat_map(|(name, keyspace)| {
+                            keyspace
+                                .get_tables()
+                                .into_iter()
+                                .map(move |table| (name.clone(), table))
+                        })
+                        .collect();
+                    (
+                        node_guard.storage_engine.clone(),
+                        tables,
+                        node_guard.get_logger().with_component(Component::Storage),
+                    )
+                };
+
+                for (keyspace, table) in tables {
+                    match storage.compact_table(&keyspace, &table, settings.min_sstables) {
+                        Ok(stats) if stats.is_empty() => {}
+                        Ok(stats) => {
+                            let message = format!(
+                                "COMPACTION: {}.{} merged {} SSTables and dropped {} expired rows",
+                                keyspace,
+                                table.get_name(),
+                                stats.sstables,
+                                stats.expired_rows
+                            );
+                            logger.info(&message, Color::Cyan, true).ok();
+                        }
+                        Err(e) => {
+                            let message = format!(
+                                "COMPACTION: could not compact {}.{}: {}",
+                                keyspace,
+                                table.get_name(),
+                                e
+                            );
+                            logger.error(&message, true).ok();
+                        }
+                    }
+                }
+            }
+        })
+    }
+
     /// Adds a new open query in the node, initializing its tracking and determining the required responses.
     ///
     /// # Purpose
@@ -449,6 +1796,8 @@ impl Node {
     ///   - The schema of the table associated with the query, if applicable.
     /// - `keyspace: Option<KeyspaceSchema>`
     ///   - The schema of the keyspace associated with the query, if applicable.
+    /// - `correlation_id: &str`
+    ///   - The identifier generated when the query arrived, used to tag its log lines.
     ///
     /// # Returns
     /// - `Result<i32, NodeError>`
@@ -471,6 +1820,9 @@ impl Node {
     /// 4. **Open Query Initialization**:
     ///    - Registers the query with the specified parameters, including the number of required responses,
     ///      client connection, query details, and associated schema, using `self.open_query_handler.new_open_query`.
+    /// 5. **Local Replicas**:
+    ///    - For `LOCAL_QUORUM` queries on keyspaces with `NetworkTopologyStrategy`, records the nodes of the
+    ///      datacenter of this node and its replication factor there, as only their responses count.
     ///
     /// # Notes
     /// - **Replication Factor**:
@@ -495,6 +1847,7 @@ impl Node {
         tx_reply: Sender<Frame>,
         table: Option<TableSchema>,
         keyspace: Option<KeyspaceSchema>,
+        correlation_id: &str,
     ) -> Result<i32, NodeError> {
         let all_nodes = self.get_how_many_nodes_i_know();
 
@@ -518,27 +1871,84 @@ impl Node {
             }
         };
 
-        Ok(self.open_query_handler.new_open_query(
+        let local_replicas = match &keyspace {
+            Some(keyspace)
+                if ConsistencyLevel::from_str(consistency_level)
+                    == ConsistencyLevel::LocalQuorum
+                    && keyspace.get_replication_class() == NETWORK_TOPOLOGY_STRATEGY =>
+            {
+                let datacenter = self.partitioner.get_datacenter(&self.ip);
+                let nodes: HashSet<Ipv4Addr> = self
+                    .partitioner
+                    .get_nodes()
+                    .into_iter()
+                    .filter(|ip| self.partitioner.get_datacenter(ip) == datacenter)
+                    .collect();
+                let factor = keyspace
+                    .get_datacenters()
+                    .get(datacenter)
+                    .copied()
+                    .unwrap_or(0) as usize;
+                Some(LocalReplicas {
+                    replicas: factor.min(nodes.len()),
+                    nodes,
+                })
+            }
+            _ => None,
+        };
+
+        let open_query_id = self.open_query_handler.new_open_query(
             needed_responses as i32,
             tx_reply,
             query,
             consistency_level,
             table,
             keyspace,
-        ))
+            correlation_id,
+        );
+        if let Some(local_replicas) = local_replicas {
+            self.open_query_handler
+                .set_local_replicas(open_query_id, local_replicas);
+        }
+        Ok(open_query_id)
     }
 
     fn get_ip(&self) -> Ipv4Addr {
         self.ip
     }
 
-    pub fn get_logger(&self) -> Logger {
-        self.logger.clone()
+    /// Returns the location and tokens of the node at `ip` in the ring of this node, for the
+    /// `system.local` and `system.peers` virtual tables.
+    fn topology_of(&self, ip: Ipv4Addr, schema_version: i64) -> NodeTopology {
+        NodeTopology {
+            ip,
+            datacenter: self.partitioner.get_datacenter(&ip).to_string(),
+            rack: self.partitioner.get_rack(&ip).to_string(),
+            tokens: self.partitioner.get_tokens(&ip),
+            schema_version,
+        }
     }
-    fn get_ip_string(&self) -> String {
-        self.ip.to_string()
+
+    /// Returns the other nodes known by the gossiper that did not leave the cluster nor were
+    /// removed from it, by IP, with the version of the schema they gossip.
+    fn peers_topology(&self) -> Vec<NodeTopology> {
+        let mut peers: Vec<NodeTopology> = self
+            .gossiper
+            .endpoints_state
+            .iter()
+            .filter(|(ip, state)| {
+                let status = &state.application_state.status;
+                **ip != self.ip && !status.is_left() && !status.is_removed()
+            })
+            .map(|(ip, state)| self.topology_of(*ip, state.application_state.schema.timestamp))
+            .collect();
+        peers.sort_by_key(|peer| peer.ip);
+        peers
     }
 
+    pub fn get_logger(&self) -> Logger {
+        self.logger.clone()
+    }
     fn get_how_many_nodes_i_know(&self) -> usize {
         self.partitioner.get_nodes().len() - 1
     }
@@ -547,6 +1957,72 @@ impl Node {
         self.partitioner.clone()
     }
 
+    /// Returns the versions of the schema and the ring this node executes its queries with, which it
+    /// stamps on the responses it sends to the coordinators.
+    fn generation_stamp(&self) -> GenerationStamp {
+        GenerationStamp {
+            schema_version: self.schema.timestamp,
+            ring_digest: self.partitioner.ring_digest(),
+        }
+    }
+
+    /// Checks the versions of the schema and the ring a replica answered a query with against the ones of
+    /// this node, as its coordinator.
+    ///
+    /// # Purpose
+    /// After a burst of schema changes, or while the ring changes, a replica that did not learn them yet
+    /// executes the queries with an old table, or for partitions it does not own anymore, without failing.
+    /// The coordinator notices it from the stamp of the response and pushes its own state to the replica,
+    /// instead of waiting for gossip to reach it.
+    ///
+    /// # Behavior
+    /// - A response is stale if its schema is older than the one of this node, or its ring differs (the
+    ///   digest of the ring does not tell which one is newer, and gossip settles it either way).
+    /// - Stale responses are logged and counted by replica, in the `METRICS` admin command.
+    /// - A gossip `Syn` is sent to the replica, at most once per second, so the gossip exchange that
+    ///   follows sends it every state it misses.
+    fn check_replica_generation(
+        &mut self,
+        replica: Ipv4Addr,
+        generation: GenerationStamp,
+        connections: ConnectionManager,
+        logger: &Logger,
+    ) -> Result<(), NodeError> {
+        let current = self.generation_stamp();
+        let stale_schema = generation.schema_version < current.schema_version;
+        let stale_ring = generation.ring_digest != current.ring_digest;
+        if !stale_schema && !stale_ring {
+            return Ok(());
+        }
+
+        logger.warn(
+            &format!(
+                "REPLICA LAG: {} answered with schema {} (mine {}) and ring {:x} (mine {:x})",
+                replica,
+                generation.schema_version,
+                current.schema_version,
+                generation.ring_digest,
+                current.ring_digest
+            ),
+            true,
+        )?;
+
+        if self
+            .replica_lag
+            .record(replica, stale_schema, stale_ring, Instant::now())
+        {
+            let transport = InternodeGossipTransport::new(
+                self.ip,
+                self.config.internode_port,
+                connections,
+                self.blocked_peers.clone(),
+            );
+            // The next gossip round retries it if the replica can not be reached
+            let _ = transport.send(replica, self.gossiper.create_syn(self.ip));
+        }
+        Ok(())
+    }
+
     fn get_open_handle_query(&mut self) -> &mut OpenQueryHandler {
         &mut self.open_query_handler
     }
@@ -558,13 +2034,13 @@ impl Node {
     }
 
     fn update_schema_in_storage(&self, old_schema: Schema) -> Result<(), NodeError> {
-        let storage = StorageEngine::new(self.storage_path.clone(), self.ip.to_string());
+        let storage = self.storage_engine.clone();
 
         // Process new or updated keyspaces
         for (keyspace_name, keyspace) in self.schema.keyspaces.clone() {
             if !old_schema.keyspaces.contains_key(&keyspace_name) {
                 // Create a new keyspace
-                storage.create_keyspace(&keyspace_name)?;
+                storage.create_keyspace(&keyspace_name, true)?;
             }
 
             let old_tables = old_schema
@@ -598,7 +2074,8 @@ impl Node {
         Ok(())
     }
 
-    // Updates tables in an existing keyspace by creating new tables if they don't exist.
+    // Updates tables in an existing keyspace by creating new tables if they don't exist, and
+    // changing the columns of the data files of the ones altered.
     fn update_keyspace_tables(
         &self,
         storage: &StorageEngine,
@@ -607,16 +2084,23 @@ impl Node {
         new_tables: Vec<TableSchema>,
     ) -> Result<(), NodeError> {
         for table in new_tables {
-            if old_tables
+            let old_table = old_tables
                 .iter()
-                .find(|old_table| old_table.get_name() == table.get_name())
-                .is_none()
-            {
+                .find(|old_table| old_table.get_name() == table.get_name());
+            if let Some(old_table) = old_table {
+                // Every node changes its own data files once it learns the altered schema
+                storage.alter_table_columns(
+                    keyspace_name,
+                    &table.get_name(),
+                    &old_table.get_columns(),
+                    &table.get_columns(),
+                )?;
+            } else {
                 // Create a new table
                 let cols = table.get_columns();
                 let col_names: Vec<&str> = cols.iter().map(|c| c.name.as_str()).collect();
 
-                storage.create_table(keyspace_name, &table.get_name(), col_names)?
+                storage.create_table(keyspace_name, &table.get_name(), col_names, true)?
             }
         }
         Ok(())
@@ -652,8 +2136,75 @@ impl Node {
             None => return Err(NodeError::LockError),
         };
 
+        if self.schema.timestamp != old_schema.timestamp {
+            self.events.record(NodeEvent::SchemaChanged);
+        }
+
         self.update_schema_in_storage(old_schema)?;
         //println!("Schema updated: {:?}", self.schema);
+        self.replay_commit_log()?;
+        Ok(())
+    }
+
+    /// Applies the mutations read from the commit log on startup whose table is in the schema.
+    ///
+    /// # Purpose
+    /// Recovers the mutations the node acknowledged before it stopped. The schema is not stored by the node but
+    /// learnt through gossip, so every mutation waits until its table is known, and they are replayed in the
+    /// order they were logged.
+    ///
+    /// # Behavior
+    /// - A mutation is only replayed after every earlier mutation of its table, so a table whose schema is
+    ///   still unknown holds back the ones after it.
+    /// - Mutations that fail to apply are logged and dropped.
+    /// - Once every mutation was replayed, the segments they were read from can be retired.
+    fn replay_commit_log(&mut self) -> Result<(), NodeError> {
+        if self.pending_replay.is_empty() {
+            return Ok(());
+        }
+
+        let storage = self.storage_engine.clone();
+        let mut waiting_tables = HashSet::new();
+        let mut replayed = 0;
+        for entry in std::mem::take(&mut self.pending_replay) {
+            let key = (entry.keyspace.clone(), entry.table.clone());
+            let table = self
+                .schema
+                .keyspaces
+                .get(&entry.keyspace)
+                .and_then(|keyspace| keyspace.get_table(&entry.table).ok());
+
+            match table {
+                Some(table) if !waiting_tables.contains(&key) => {
+                    if let Err(e) = storage.apply(&entry, &table) {
+                        self.logger.with_component(Component::Storage).warn(
+                            &format!("COMMIT LOG: could not replay {:?}: {}", entry, e),
+                            true,
+                        )?;
+                    }
+                    replayed += 1;
+                }
+                _ => {
+                    waiting_tables.insert(key);
+                    self.pending_replay.push(entry);
+                }
+            }
+        }
+
+        if self.pending_replay.is_empty() {
+            self.commit_log.lock()?.finish_replay();
+        }
+        if replayed > 0 {
+            self.logger.with_component(Component::Storage).info(
+                &format!(
+                    "COMMIT LOG: replayed {} mutations, {} waiting for their tables",
+                    replayed,
+                    self.pending_replay.len()
+                ),
+                Color::Cyan,
+                true,
+            )?;
+        }
         Ok(())
     }
 
@@ -669,6 +2220,18 @@ impl Node {
         Ok(())
     }
 
+    fn alter_keyspace(&mut self, alter_keyspace: AlterKeyspace) -> Result<(), NodeError> {
+        self.gossiper
+            .alter_keyspace(self.ip, alter_keyspace)
+            .map_err(|_| NodeError::KeyspaceError)?;
+
+        // We manually update the latest schema right after modification so
+        // we don't have to wait for the next gossip round.
+        self.set_latest_schema_from_gossiper()?;
+
+        Ok(())
+    }
+
     fn remove_keyspace(&mut self, keyspace_name: String) -> Result<(), NodeError> {
         self.gossiper
             .remove_keyspace(self.ip, &keyspace_name)
@@ -765,33 +2328,18 @@ impl Node {
 
     fn update_table(
         &mut self,
-        _keyspace_name: &str,
-        _new_table: CreateTable,
+        keyspace_name: &str,
+        new_table: CreateTable,
     ) -> Result<(), NodeError> {
-        // // Encuentra el índice del Keyspace en el Vec
-        // if let Some(index) = self
-        //     .keyspaces
-        //     .iter()
-        //     .position(|k| k.get_name() == keyspace_name)
-        // {
-        //     // Obtenemos una referencia mutable al Keyspace en el índice encontrado
-        //     let keyspace = &mut self.keyspaces[index];
-
-        //     // Encuentra la posición de la tabla a actualizar en el keyspace
-        //     let table_index = keyspace
-        //         .tables
-        //         .iter()
-        //         .position(|table| table.get_name() == new_table.get_name())
-        //         .ok_or(NodeError::CQLError(CQLError::InvalidTable))?;
-
-        //     // Reemplaza la tabla existente con la nueva en el Keyspace
-        //     keyspace.tables[table_index] = Table::new(new_table);
-        //     Ok(())
-        // } else {
-        //     // Retorna un error si el Keyspace no se encuentra
-        //     Err(NodeError::KeyspaceError)
-        // }
-        unimplemented!()
+        self.gossiper
+            .alter_table(self.ip, keyspace_name, new_table)
+            .map_err(|_| NodeError::GossipError)?;
+
+        // We manually update the latest schema right after modification so
+        // we don't have to wait for the next gossip round.
+        self.set_latest_schema_from_gossiper()?;
+
+        Ok(())
     }
 
     fn table_already_exist(
@@ -813,6 +2361,117 @@ impl Node {
         Ok(false)
     }
 
+    // Checks the permissions of the role of a client and asks the authorizer whether it may run
+    // a query, using its current keyspace for the statements that do not name one. The clients
+    // opened by the node itself to run schema scripts have no user and are trusted.
+    fn authorize(&self, client_id: i32, query: &Query) -> Result<(), NodeError> {
+        let Some(user) = self.clients_user.get(&client_id) else {
+            return Ok(());
+        };
+        let keyspace = self.clients_keyspace.get(&client_id).cloned().flatten();
+        let role = self.role_of(user);
+
+        for request in AuthorizationRequest::of(user, query, keyspace) {
+            role.authorize(&request).map_err(NodeError::Unauthorized)?;
+            self.authorizer
+                .authorize(&request)
+                .map_err(NodeError::Unauthorized)?;
+        }
+        Ok(())
+    }
+
+    // The role of a user logged in to the node, as it was last read. Until the `system_auth`
+    // keyspace exists, the default superuser is the only user.
+    fn role_of(&self, user: &str) -> Role {
+        match self.roles.get(user) {
+            Some(role) => role.clone(),
+            None if user == DEFAULT_SUPERUSER
+                && !roles::auth_tables_exist(&self.schema.keyspaces) =>
+            {
+                Role::default_superuser()
+            }
+            None => Role::without_permissions(user),
+        }
+    }
+
+    // Whether every node of the ring gossiped the tables of the `system_auth` keyspace.
+    fn auth_tables_agreed(&self) -> bool {
+        self.gossiper
+            .endpoints_state
+            .values()
+            .filter(|state| state.application_state.status.is_normal())
+            .all(|state| roles::auth_tables_exist(&state.application_state.schema.keyspaces))
+    }
+
+    // Checks the replication of a keyspace created or altered by a client against the live nodes
+    // of the ring, warning about it or rejecting the statement as the replication check of the
+    // node says. Creating a keyspace that exists with `IF NOT EXISTS` changes nothing, so it is
+    // not checked, and neither is the `system_auth` keyspace created by the node, whose replicas
+    // are fewer than asked for in small rings.
+    fn check_replication(&self, query: &Query, logger: &Logger) -> Result<(), NodeError> {
+        let (name, replication_factor, datacenters) = match query {
+            Query::CreateKeyspace(create) => {
+                if create.get_name() == AUTH_KEYSPACE
+                    || create.if_not_exists_clause
+                        && self.schema.keyspaces.contains_key(&create.get_name())
+                {
+                    return Ok(());
+                }
+                (
+                    create.get_name(),
+                    create.get_replication_factor(),
+                    create.get_datacenters(),
+                )
+            }
+            Query::AlterKeyspace(alter) => (
+                alter.get_name(),
+                alter.get_replication_factor(),
+                alter.get_datacenters(),
+            ),
+            _ => return Ok(()),
+        };
+        let Some(shortfall) =
+            replication_shortfall(replication_factor, &datacenters, &self.partitioner)
+        else {
+            return Ok(());
+        };
+
+        let message = format!("keyspace {}: {}", name, shortfall);
+        match self.replication_check {
+            ReplicationCheck::Warn => {
+                logger.warn(&format!("UNDER-REPLICATED: {}", message), true)?;
+                Ok(())
+            }
+            ReplicationCheck::Reject => Err(NodeError::Invalid(message)),
+        }
+    }
+
+    // Checks the replication of every keyspace of the schema against the live nodes of the ring
+    // after a gossip round. A keyspace left with more replicas than live nodes is warned about
+    // once, until enough nodes are back for it.
+    fn check_keyspaces_replication(&mut self, logger: &Logger) {
+        let mut under_replicated = HashSet::new();
+        for (name, keyspace) in &self.schema.keyspaces {
+            let Some(shortfall) = replication_shortfall(
+                keyspace.inner.get_replication_factor(),
+                &keyspace.inner.get_datacenters(),
+                &self.partitioner,
+            ) else {
+                if self.under_replicated.contains(name) {
+                    let message = format!("REPLICATION RECOVERED: keyspace {}", name);
+                    let _ = logger.info(&message, Color::Green, true);
+                }
+                continue;
+            };
+            if !self.under_replicated.contains(name) {
+                let message = format!("UNDER-REPLICATED: keyspace {}: {}", name, shortfall);
+                let _ = logger.warn(&message, true);
+            }
+            under_replicated.insert(name.clone());
+        }
+        self.under_replicated = under_replicated;
+    }
+
     fn get_client_keyspace(&self, client_id: i32) -> Result<Option<KeyspaceSchema>, NodeError> {
         let keyspace_name = self
             .clients_keyspace
@@ -831,6 +2490,30 @@ impl Node {
         Ok(self.schema.keyspaces.get(keyspace_name).cloned())
     }
 
+    // Binds the values of an EXECUTE to its prepared query, as the types of the columns of the
+    // keyspace the query names or the one the client uses
+    fn bind_prepared(
+        &self,
+        client_id: i32,
+        id: &[u8],
+        values: &[String],
+    ) -> Result<String, error::Error> {
+        let client_keyspace = self.clients_keyspace.get(&client_id).cloned().flatten();
+        self.prepared_statements
+            .bind(id, values, |keyspace, table, column| {
+                let keyspace = keyspace.map(str::to_string).or(client_keyspace.clone())?;
+                self.schema
+                    .keyspaces
+                    .get(&keyspace)?
+                    .get_table(table)
+                    .ok()?
+                    .get_columns()
+                    .into_iter()
+                    .find(|table_column| table_column.name == column)
+                    .map(|table_column| table_column.data_type)
+            })
+    }
+
     /// Starts the node's core functionalities, including internode connections, gossip, and client connections.
     ///
     /// # Purpose
@@ -842,16 +2525,15 @@ impl Node {
     /// - `node: Arc<Mutex<Node>>`
     ///   - A thread-safe reference to the `Node` instance being started.
     ///   - Contains the node's state, schema, partitioner, and other critical components.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A thread-safe map of active TCP connections to other nodes and clients.
-    ///     - Keys are addresses (as strings), and values are `TcpStream` objects for communication.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, by their internode address, shared by every thread of the node.
     ///
     /// # Returns
     /// - `Result<(), NodeError>`
     ///   - On success:
-    ///     - Returns `Ok(())`, indicating that all threads started successfully and are running.
+    ///     - Returns `Ok(())` once the node was shut down (see `shutdown`) and all its threads stopped.
     ///   - On failure:
-    ///     - Returns `Err(NodeError)` if any thread fails to start or encounters an unrecoverable error.
+    ///     - Returns `Err(NodeError)` if the node can not be locked to register its threads.
     ///
     /// # Behavior
     /// 1. **Retrieve Node IP**:
@@ -869,14 +2551,15 @@ impl Node {
     ///    - Creates a thread to handle incoming client connections and requests.
     ///    - Uses the `handle_client_connections` function to manage client queries and responses.
     ///
-    /// 5. **Thread Joining**:
-    ///    - Waits for the threads handling internode connections and client connections to complete using `join`.
-    ///    - Propagates errors if any thread encounters a failure or panic.
+    /// 5. **Thread for Admin Connections**:
+    ///    - Creates a thread listening on the admin port for operator commands (`KILL`, `PARTITION <ip>`, `HEAL`, `LOGS [n]`, `EVENTS [since]`, `MOVE <token>`).
+    ///    - Uses the `handle_admin_connections` function; these commands are used to inspect the node, rebalance the ring and inject faults for testing.
+    ///
+    /// 6. **Waiting**:
+    ///    - Registers every thread started in the node, so `shutdown` can join them, and waits until it did.
     ///
     /// # Error Handling
     /// - Errors are logged for each thread individually using `unwrap_or_else` to ensure independent thread robustness.
-    /// - If any thread panics or fails during execution:
-    ///   - The error is captured and returned as a `NodeError`.
     ///
     /// # Notes
     /// - **Thread-Safe Design**:
@@ -886,122 +2569,1725 @@ impl Node {
     ///     participate in cluster operations or serve clients.
     ///
     /// # Errors
-    /// - Returns `NodeError::InternodeError` if the internode connections thread fails.
-    /// - Returns `NodeError::ClientError` if the client connections thread fails.
-    /// - Errors during gossip are logged but do not cause the `start` function to fail.
+    /// - Errors of the threads are logged but do not cause the `start` function to fail, which keeps running
+    ///   until the node is shut down or decommissioned.
+    /// - Returns `Err(NodeError::Killed)` as soon as the node is killed by the `KILL` admin command, without
+    ///   waiting for its threads.
     ///
     /// # Importance
     /// This function encapsulates the primary operational lifecycle of a node in the system. By starting the gossip protocol,
     /// managing internode connections, and handling client queries, it ensures the node's integration into the cluster
     /// and its ability to serve requests efficiently.
 
-    pub fn start(
-        node: Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-    ) -> Result<(), NodeError> {
+    pub fn start(node: Arc<Mutex<Node>>, connections: ConnectionManager) -> Result<(), NodeError> {
         let self_ip;
         let log;
+        let shutdown;
         {
             let node_guard = node.lock()?;
             self_ip = node_guard.get_ip();
             log = node_guard.get_logger().clone();
+            shutdown = node_guard.shutdown.clone();
         }
 
+        let mut threads = vec![
+            Self::start_table_stats(Arc::clone(&node), shutdown.clone()),
+            Self::start_compaction(Arc::clone(&node), shutdown.clone()),
+            Self::start_query_timeouts(Arc::clone(&node), shutdown.clone()),
+            Self::start_speculative_retries(
+                Arc::clone(&node),
+                connections.clone(),
+                shutdown.clone(),
+            ),
+            Self::start_connection_health_checks(
+                connections.clone(),
+                log.with_component(Component::Internode),
+                shutdown.clone(),
+            ),
+        ];
+
         let log_gossip = log.clone();
         // Creates a thread to handle gossip
-        let gossip_connections = Arc::clone(&connections);
+        let gossip_connections = connections.clone();
         let node_gossip = Arc::clone(&node);
-        Self::start_gossip(node_gossip, gossip_connections).unwrap_or_else(|err| {
-            let message = format!("ERROR in GOSSIP: {:?}", err);
-            log_gossip.clone().error(&message, true).ok(); // Or handle the error as needed
-        });
-
-        //thread::sleep(Duration::from_secs(2));
-        // Creates a thread to handle client connections
+        match Self::start_gossip(node_gossip, gossip_connections) {
+            Ok(gossip) => threads.push(gossip),
+            Err(err) => {
+                let message = format!("ERROR in GOSSIP: {:?}", err);
+                log_gossip.clone().error(&message, true).ok(); // Or handle the error as needed
+            }
+        }
+
+        // Creates a thread to handle client connections
         let client_connections_node = Arc::clone(&node);
-        let client_connections = Arc::clone(&connections);
+        let client_connections = connections.clone();
         let self_ip_client = self_ip;
 
-        let log_client = log.clone();
-        let handle_client_thread = thread::spawn(move || {
-            Self::handle_client_connections(
-                client_connections_node,
-                client_connections,
-                self_ip_client,
+        let log_client = log.clone();
+        threads.push(thread::spawn(move || {
+            Self::handle_client_connections(
+                client_connections_node,
+                client_connections,
+                self_ip_client,
+            )
+            .unwrap_or_else(|e| {
+                let message = format!("ERROR in CLIENT CONNECTIONS: {:?}", e);
+                log_client.clone().error(&message, true).ok();
+            });
+        }));
+
+        // Creates a thread to handle node connections
+        let node_connections_node = Arc::clone(&node);
+        let node_connections = connections.clone();
+        let self_ip_node = self_ip.clone();
+        let log_internode = log.clone();
+        threads.push(thread::spawn(move || {
+            Self::handle_node_connections(node_connections_node, node_connections, self_ip_node)
+                .unwrap_or_else(|err| {
+                    let message = format!("ERROR in INTERNODE CONNECTIONS: {:?}", err);
+                    log_internode.error(&message, true).ok(); // Or handle the error as needed
+                });
+        }));
+
+        // Creates a thread to handle admin connections
+        let admin_connections_node = Arc::clone(&node);
+        let admin_connections = connections.clone();
+        let log_admin = log.clone();
+        threads.push(thread::spawn(move || {
+            Self::handle_admin_connections(admin_connections_node, admin_connections, self_ip)
+                .unwrap_or_else(|e| {
+                    let message = format!("ERROR in ADMIN CONNECTIONS: {:?}", e);
+                    log_admin.error(&message, true).ok();
+                });
+        }));
+
+        node.lock()?.threads.extend(threads);
+        shutdown.wait_stopped();
+        if shutdown.is_killed() {
+            return Err(NodeError::Killed);
+        }
+        Ok(())
+    }
+
+    fn handle_node_connections(
+        node: Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        self_ip: std::net::Ipv4Addr,
+    ) -> Result<(), NodeError> {
+        let socket = SocketAddrV4::new(self_ip, node.lock()?.config.internode_port);
+        let listener = TcpListener::bind(socket)?;
+        let workers = WorkerPool::new("internode", node.lock()?.concurrency.internode_connections);
+        let shutdown = node.lock()?.shutdown.clone();
+        for stream in listener.incoming() {
+            // The node wakes its listeners with a connection of its own once it shuts down
+            if shutdown.is_stopping() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let node_clone = Arc::clone(&node);
+                    let connections_clone = connections.clone();
+                    let tls = internode_tls::registered(&self_ip)?;
+                    // The other nodes keep their connections open, so they are read with a
+                    // timeout to notice when this node shuts down
+                    stream.set_read_timeout(Some(INTERNODE_SHUTDOWN_CHECK_INTERVAL))?;
+
+                    // The stream is dropped with the refused job, so the peer opens a new
+                    // connection for its next message
+                    let handled = workers.execute(move || {
+                        // The nodes with internode TLS only read from the nodes they trust
+                        let result = match tls {
+                            Some(tls) => tls.accept(stream).and_then(|stream| {
+                                Node::handle_incoming_internode_messages(
+                                    node_clone,
+                                    stream,
+                                    connections_clone,
+                                )
+                            }),
+                            None => Node::handle_incoming_internode_messages(
+                                node_clone,
+                                stream,
+                                connections_clone,
+                            ),
+                        };
+                        if let Err(e) = result {
+                            eprintln!("{:?}", e);
+                        }
+                    });
+                    if let Err(e) = handled {
+                        eprintln!("Refused internode connection: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error accepting internode connection: {:?}", e);
+                }
+            }
+        }
+
+        workers.join();
+        Ok(())
+    }
+
+    fn handle_admin_connections(
+        node: Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        self_ip: std::net::Ipv4Addr,
+    ) -> Result<(), NodeError> {
+        let socket = SocketAddrV4::new(self_ip, node.lock()?.config.admin_port);
+        let listener = TcpListener::bind(socket)?;
+        let shutdown = node.lock()?.shutdown.clone();
+        for stream in listener.incoming() {
+            if shutdown.is_stopping() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let node_clone = Arc::clone(&node);
+                    let connections_clone = connections.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = Node::handle_incoming_admin_messages(
+                            node_clone,
+                            connections_clone,
+                            stream,
+                        ) {
+                            eprintln!("{:?}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Error accepting admin connection: {:?}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Receives one command per line from an admin client and answers its output lines
+    // followed by `OK`, or `ERROR <reason>`
+    fn handle_incoming_admin_messages(
+        node: Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        mut stream: TcpStream,
+    ) -> Result<(), NodeError> {
+        let reader = BufReader::new(stream.try_clone()?);
+        let log = node.lock()?.get_logger();
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut left_the_ring = false;
+            let response = match AdminCommand::from_str(&line) {
+                Ok(command) => {
+                    log.warn(&format!("ADMIN: I RECEIVED {:?}", command), true)?;
+                    let is_decommission = matches!(command, AdminCommand::Decommission(_));
+                    match Node::execute_admin_command(&node, connections.clone(), command) {
+                        Ok(output) => {
+                            left_the_ring = is_decommission;
+                            output.into_iter().chain(["OK".to_string()]).collect()
+                        }
+                        Err(e) => vec![format!("ERROR {}", e)],
+                    }
+                }
+                Err(_) => vec![format!("ERROR unknown command: {}", line.trim())],
+            };
+
+            for response_line in response {
+                stream.write_all(format!("{}\n", response_line).as_bytes())?;
+            }
+            stream.flush()?;
+
+            // The rest of the ring hears the node left from its gossip before it stops
+            if left_the_ring {
+                thread::sleep(LEFT_ANNOUNCEMENT_TIME);
+                log.warn("DECOMMISSIONED: STOPPING", true)?;
+                Node::stop(&node, Ok(DrainReport::default()))?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute_admin_command(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        command: AdminCommand,
+    ) -> Result<Vec<String>, NodeError> {
+        match command {
+            // `start` returns at once, failing, without waiting for the threads of the node
+            AdminCommand::Kill => node.lock()?.shutdown.kill(),
+            AdminCommand::Partition(peer) => {
+                node.lock()?.blocked_peers.insert(peer);
+            }
+            AdminCommand::Heal => {
+                node.lock()?.blocked_peers.clear();
+            }
+            AdminCommand::RecentLogs(amount) => {
+                let logger = node.lock()?.get_logger();
+                return Ok(logger.recent_logs(amount)?);
+            }
+            AdminCommand::Events(since) => {
+                let events = node.lock()?.events.since(since);
+                return Ok(events.iter().map(|record| record.to_string()).collect());
+            }
+            AdminCommand::Slo(keyspace, operation, slo) => {
+                node.lock()?.metrics.set_slo(&keyspace, operation, slo);
+            }
+            AdminCommand::Metrics => {
+                let node_guard = node.lock()?;
+                let mut report = node_guard.metrics.report();
+                report.extend(node_guard.table_metrics.report());
+                report.extend(node_guard.replica_lag.report());
+                report.extend(node_guard.hot_partitions.report());
+                report.extend(
+                    node_guard
+                        .open_query_handler
+                        .read_repairs()
+                        .lock()?
+                        .report(),
+                );
+                report.extend(node_guard.storage_engine.clone().row_cache_report());
+                return Ok(report);
+            }
+            AdminCommand::Sample(keyspace, table, percent) => {
+                let (storage_engine, table) = {
+                    let node_guard = node.lock()?;
+                    let table = node_guard
+                        .schema
+                        .keyspaces
+                        .get(&keyspace)
+                        .ok_or(NodeError::KeyspaceError)?
+                        .get_table(&table)?;
+                    (node_guard.storage_engine.clone(), table)
+                };
+                // A new seed on every sample, so repeated samples read different partitions
+                let seed = Uuid::new_v4().as_u128() as u64;
+                let sample =
+                    storage_engine.sample_partitions(&keyspace, &table, percent / 100.0, seed)?;
+                return Ok(vec![sample.to_string()]);
+            }
+            AdminCommand::Splits(n) => {
+                let splits = node.lock()?.partitioner.token_splits(n)?;
+                return Ok(splits
+                    .into_iter()
+                    .map(|(tokens, owner)| TokenRange::new(tokens, owner).to_string())
+                    .collect());
+            }
+            AdminCommand::Move(token) => {
+                let mut node_guard = node.lock()?;
+                let ip = node_guard.ip;
+
+                // Fail early if another node owns the token, the ring itself is updated by the
+                // gossip thread of every node, which also moves the affected data
+                node_guard.partitioner.clone().move_node(ip, token)?;
+                node_guard
+                    .gossiper
+                    .set_token(ip, token)
+                    .map_err(|_| NodeError::GossipError)?;
+                node_guard.replacing = None;
+            }
+            AdminCommand::ExportSchema(keyspace) => {
+                let node_guard = node.lock()?;
+                return schema_script::export(&node_guard.schema, keyspace.as_deref());
+            }
+            AdminCommand::ExportTable(export) => {
+                let (storage_engine, table) = {
+                    let node_guard = node.lock()?;
+                    let table = node_guard
+                        .schema
+                        .keyspaces
+                        .get(&export.keyspace)
+                        .ok_or(NodeError::KeyspaceError)?
+                        .get_table(&export.table)?;
+                    (node_guard.storage_engine.clone(), table)
+                };
+                let report = storage_engine.export_table(
+                    &export.keyspace,
+                    &table,
+                    export.format,
+                    export.tokens,
+                    &export.path,
+                )?;
+                return Ok(vec![report.to_string()]);
+            }
+            AdminCommand::ImportSchema(path) => {
+                let script = std::fs::read_to_string(path)?;
+                return Node::import_schema(node, connections, &script);
+            }
+            AdminCommand::Drain(timeout) => {
+                return Ok(vec![
+                    Node::drain(node, timeout, NodeStatus::Shutdown)?.to_string()
+                ]);
+            }
+            AdminCommand::Decommission(timeout) => {
+                return Ok(vec![
+                    Node::decommission(node, connections, timeout)?.to_string()
+                ]);
+            }
+            AdminCommand::RemoveNode(removed) => {
+                // The ring is updated by the gossip thread of every node once it learns the
+                // removal, which also streams the ranges of the removed node
+                let mut node_guard = node.lock()?;
+                node_guard
+                    .gossiper
+                    .remove_endpoint(removed)
+                    .map_err(|_| NodeError::GossipError)?;
+                node_guard.blocked_peers.remove(&removed);
+            }
+            AdminCommand::Maintenance(operation, keyspace, table) => {
+                return Node::run_maintenance_in_ring(
+                    node,
+                    connections,
+                    operation,
+                    keyspace,
+                    table,
+                    None,
+                );
+            }
+            AdminCommand::Snapshot(keyspace, tag) => {
+                return Node::run_maintenance_in_ring(
+                    node,
+                    connections,
+                    MaintenanceOperation::Snapshot,
+                    keyspace,
+                    None,
+                    Some(tag),
+                );
+            }
+            AdminCommand::Status => return Ok(node.lock()?.ring_status()),
+            AdminCommand::DescribeRing(keyspace) => {
+                return node.lock()?.describe_ring(&keyspace);
+            }
+            AdminCommand::Repair(keyspace, table) => {
+                return Node::repair(node, connections, &keyspace, table);
+            }
+        }
+        Ok(vec![])
+    }
+
+    // Counts a new client query as being executed, unless the node is draining, still streaming
+    // the rows of its ranges or already executing as many queries as it may. Returns why the
+    // query is refused otherwise.
+    fn start_client_query(&mut self) -> Result<(), String> {
+        if self.draining {
+            return Err("The node is draining".to_string());
+        }
+        if self.bootstrapping {
+            return Err("The node is bootstrapping".to_string());
+        }
+        if self.client_queries >= self.concurrency.concurrent_requests {
+            return Err(format!(
+                "The node is already executing {} queries",
+                self.client_queries
+            ));
+        }
+        self.client_queries += 1;
+        Ok(())
+    }
+
+    /// Drains the node, so it can be stopped without failing the queries of its clients.
+    ///
+    /// # Purpose
+    /// Restarting the nodes of a cluster one at a time (a rolling restart) must not fail the queries they are
+    /// coordinating. Draining a node first (Cassandra's `nodetool drain`) lets those queries finish, writes
+    /// what the node keeps in memory to disk and tells the rest of the cluster it is going away.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node to drain.
+    /// - `timeout: Duration`
+    ///   - The longest time to wait for the client queries being executed by the node.
+    /// - `status: NodeStatus`
+    ///   - What the node is announced as once drained: `Shutdown` if it is stopped to come back, so it keeps its
+    ///     ranges of the ring, or `Leaving` if it is being decommissioned.
+    ///
+    /// # Returns
+    /// - `Result<DrainReport, NodeError>`
+    ///   - On success:
+    ///     - Returns the queries that finished, the ones still open after `timeout` and the memtables flushed.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the memtables can not be written or the status can not be gossiped.
+    ///
+    /// # Behavior
+    /// 1. **Client Queries**:
+    ///    - New client connections are dropped and new queries are answered with an `Overloaded` error, so
+    ///      drivers send them to another node.
+    ///    - Waits for the queries already being executed to finish, up to `timeout`.
+    /// 2. **Storage**:
+    ///    - Writes the memtables of the LSM backend to SSTables. The commit log is already synced on every write.
+    /// 3. **Gossip**:
+    ///    - Announces the node as `status`, and records a `Drained` event. The other nodes take a `Shutdown` node
+    ///      as down, hinting its writes, but never as dead, so they do not redistribute its ranges.
+    ///
+    /// # Notes
+    /// - The node keeps answering the queries of the other nodes, as it still holds its ranges of the ring
+    ///   until it stops.
+    /// - A drain can not be undone: the node must be restarted to take client queries again.
+    fn drain(
+        node: &Arc<Mutex<Node>>,
+        timeout: Duration,
+        status: NodeStatus,
+    ) -> Result<DrainReport, NodeError> {
+        let (open_queries, log) = {
+            let mut node_guard = node.lock()?;
+            node_guard.draining = true;
+            (node_guard.client_queries, node_guard.get_logger())
+        };
+        log.warn(
+            &format!("DRAINING: WAITING FOR {} CLIENT QUERIES", open_queries),
+            true,
+        )?;
+
+        let deadline = Instant::now() + timeout;
+        let mut abandoned = open_queries;
+        while abandoned > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+            abandoned = node.lock()?.client_queries;
+        }
+
+        let mut node_guard = node.lock()?;
+        let flushed = node_guard.flush_memtables()?;
+        let ip = node_guard.ip;
+        node_guard
+            .gossiper
+            .change_status(ip, status)
+            .map_err(|_| NodeError::GossipError)?;
+        node_guard.events.record(NodeEvent::Drained);
+
+        let report = DrainReport {
+            finished_queries: open_queries.saturating_sub(abandoned),
+            abandoned_queries: abandoned,
+            flushed_memtables: flushed,
+        };
+        log.warn(&format!("DRAINED: {:?}", report), true)?;
+        Ok(report)
+    }
+
+    /// Shuts the node down cleanly, so its process can exit without failing queries nor losing writes.
+    ///
+    /// # Purpose
+    /// Stopping a node by killing its process fails the queries it is coordinating, loses the writes it keeps in
+    /// memory until its commit log is replayed, and leaves the rest of the cluster sending it queries until the
+    /// failure detector suspects it. Shutting it down first (as Cassandra does on `SIGTERM`) avoids all three.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node to shut down.
+    /// - `timeout: Duration`
+    ///   - The longest time to wait for the client queries being executed by the node.
+    ///
+    /// # Returns
+    /// - `Result<DrainReport, NodeError>`
+    ///   - On success:
+    ///     - Returns the queries that finished, the ones still open after `timeout` and the memtables flushed,
+    ///       once every thread started by `start` stopped.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the node can not be drained. Its threads are stopped anyway.
+    ///
+    /// # Behavior
+    /// 1. **Drain**:
+    ///    - Drains the node (see `drain`): it takes no new clients nor queries, waits for the open ones, flushes
+    ///      its memtables and gossips that it is `Shutdown`, keeping its ranges of the ring.
+    /// 2. **Announcement**:
+    ///    - Keeps gossiping for `SHUTDOWN_ANNOUNCEMENT_TIME`, so the rest of the ring hears it is shut down.
+    /// 3. **Stop**:
+    ///    - Tells the threads of the node to stop, wakes its listeners with a connection of its own, and joins
+    ///      them all, with the workers of their pools. The clients still connected get their connections closed
+    ///      between two requests, and the other nodes between two messages.
+    /// 4. **Flush**:
+    ///    - Flushes the writes the node got as a replica while it was shutting down.
+    ///
+    /// # Notes
+    /// - The threads writing the internode connections of this node are not joined: they end with the process,
+    ///   as their peers keep them open.
+    /// - `start` returns once the node was shut down.
+    pub fn shutdown(node: &Arc<Mutex<Node>>, timeout: Duration) -> Result<DrainReport, NodeError> {
+        let drained = Node::drain(node, timeout, NodeStatus::Shutdown);
+        if drained.is_ok() {
+            thread::sleep(SHUTDOWN_ANNOUNCEMENT_TIME);
+        }
+        Node::stop(node, drained)
+    }
+
+    // Stops the threads of a drained node, flushes the writes it got meanwhile and announces that
+    // it stopped, which `start` returns on
+    fn stop(
+        node: &Arc<Mutex<Node>>,
+        drained: Result<DrainReport, NodeError>,
+    ) -> Result<DrainReport, NodeError> {
+        let (shutdown, threads, listeners, log) = {
+            let mut node_guard = node.lock()?;
+            let ip = node_guard.ip;
+            let config = &node_guard.config;
+            let listeners = [config.client_port, config.internode_port, config.admin_port]
+                .map(|port| SocketAddrV4::new(ip, port));
+            (
+                node_guard.shutdown.clone(),
+                std::mem::take(&mut node_guard.threads),
+                listeners,
+                node_guard.get_logger(),
+            )
+        };
+        log.warn(
+            &format!("SHUTTING DOWN: STOPPING {} THREADS", threads.len()),
+            true,
+        )?;
+
+        shutdown.stop();
+        for listener in listeners {
+            // A listener that stopped already refuses the connection
+            TcpStream::connect(listener).ok();
+        }
+        let panicked = threads
+            .into_iter()
+            .map(JoinHandle::join)
+            .filter(Result::is_err)
+            .count();
+
+        let report = drained.and_then(|mut report| {
+            report.flushed_memtables += node.lock()?.flush_memtables()?;
+            Ok(report)
+        });
+
+        if panicked > 0 {
+            log.error(&format!("SHUT DOWN: {} THREADS PANICKED", panicked), true)
+                .ok();
+        }
+        match &report {
+            Ok(report) => log.warn(&format!("SHUT DOWN: {:?}", report), true).ok(),
+            Err(e) => log.error(&format!("SHUT DOWN: {}", e), true).ok(),
+        };
+        // Logged before, as the process may exit as soon as `start` returns
+        shutdown.finish();
+        report
+    }
+
+    /// Bootstraps the node: streams the rows of its ranges from the nodes of the ring before it joins it.
+    ///
+    /// # Purpose
+    /// A node that joins the ring starts empty. Until the rows of its ranges reach it, it would answer the
+    /// reads of its partitions with nothing. Bootstrapping keeps the node out of the ring of the other nodes
+    /// (which do not take `Bootstrap` nodes in) while it asks them for its rows, and announces it as `Normal`
+    /// only once it has them.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node to bootstrap.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, which the stream requests are sent through.
+    ///
+    /// # Returns
+    /// - `Result<usize, NodeError>`
+    ///   - On success:
+    ///     - Returns the amount of rows streamed to the node, after announcing it as `Normal`.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::InternodeError)` if a node could not be asked for its rows, could not
+    ///       read them or did not stream them before `BOOTSTRAP_STREAM_TIMEOUT`. The node stays `Bootstrap`,
+    ///       and the gossip loop bootstraps it again.
+    ///
+    /// # Behavior
+    /// 1. **Request**:
+    ///    - Registers the bootstrap under a new id, and sends a `StreamRequest` with the tokens, datacenter and
+    ///      rack of the node to every `Normal` node of the ring.
+    /// 2. **Streaming**:
+    ///    - Each node streams the rows the bootstrapping node owns or replicates in the ring with it, in
+    ///      checksummed chunks (see `StreamChunk`), and then a `StreamResult` with the amount of rows, through
+    ///      the same queue.
+    /// 3. **Join**:
+    ///    - Once every node answered, announces the node as `Normal`. The other nodes then take it into their
+    ///      ring, and their redistribution sends it the writes it missed while it was streaming.
+    ///
+    /// # Notes
+    /// - The first nodes of a cluster have no `Normal` node to stream from, and become `Normal` right away.
+    /// - The node refuses client queries while it streams, so drivers send them to other nodes.
+    fn bootstrap(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+    ) -> Result<usize, NodeError> {
+        let (self_ip, peers, request, receiver, log) = {
+            let mut node_guard = node.lock()?;
+            let self_ip = node_guard.ip;
+            let peers: Vec<Ipv4Addr> = node_guard
+                .gossiper
+                .endpoints_state
+                .iter()
+                .filter(|(ip, state)| **ip != self_ip && state.application_state.status.is_normal())
+                .map(|(ip, _)| *ip)
+                .collect();
+            // Placed in the ring like the other nodes will place it
+            let tokens = match node_guard.gossiper.get_token(self_ip) {
+                Ok(Some(token)) => vec![token],
+                _ => node_guard.partitioner.default_tokens(&self_ip)?,
+            };
+            let (datacenter, rack) = node_guard
+                .gossiper
+                .get_location(self_ip)
+                .map_err(|_| NodeError::GossipError)?;
+
+            let id = node_guard.get_open_handle_query().reserve_id() as u32;
+            let (sender, receiver) = mpsc::channel();
+            node_guard.pending_bootstrap = Some((id, sender));
+            let request = StreamRequest {
+                id,
+                tokens,
+                datacenter,
+                rack,
+            };
+            (self_ip, peers, request, receiver, node_guard.get_logger())
+        };
+
+        if !peers.is_empty() {
+            log.info(
+                &format!("BOOTSTRAP: ASKING {:?} FOR MY RANGES", peers),
+                Color::Cyan,
+                true,
+            )?;
+        }
+        let port = node.lock()?.config.internode_port;
+        let mut waiting = 0;
+        for peer in &peers {
+            let message = InternodeMessage::new(
+                self_ip,
+                InternodeMessageContent::StreamRequest(request.clone()),
+            );
+            connect_and_send_message(*peer, port, connections.clone(), message)?;
+            waiting += 1;
+        }
+
+        let deadline = Instant::now() + BOOTSTRAP_STREAM_TIMEOUT;
+        let mut streamed = 0;
+        let mut outcome = Ok(());
+        while waiting > 0 {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                outcome = Err(NodeError::InternodeError);
+                break;
+            };
+            match receiver.recv_timeout(left) {
+                Ok((from, Ok(rows))) => {
+                    log.info(
+                        &format!("BOOTSTRAP: {} STREAMED {} ROWS", from, rows),
+                        Color::Cyan,
+                        true,
+                    )?;
+                    streamed += rows as usize;
+                    waiting -= 1;
+                }
+                Ok((from, Err(e))) => {
+                    log.error(&format!("BOOTSTRAP: {} FAILED: {}", from, e), true)?;
+                    outcome = Err(NodeError::InternodeError);
+                    break;
+                }
+                Err(_) => {
+                    outcome = Err(NodeError::InternodeError);
+                    break;
+                }
+            }
+        }
+
+        let mut node_guard = node.lock()?;
+        node_guard.pending_bootstrap = None;
+        outcome?;
+        node_guard
+            .gossiper
+            .change_status(self_ip, NodeStatus::Normal)
+            .map_err(|_| NodeError::GossipError)?;
+        node_guard.bootstrapping = false;
+        // The schema is run once, by the seed that forms the cluster, and not again on its restarts
+        let initial_schema = if peers.is_empty()
+            && node_guard.is_first_seed
+            && node_guard.schema_bootstrap.is_none()
+        {
+            node_guard.initial_schema.take()
+        } else {
+            None
+        };
+        drop(node_guard);
+        log.info(
+            &format!("BOOTSTRAP: {} ROWS STREAMED, JOINING THE RING", streamed),
+            Color::Cyan,
+            true,
+        )?;
+
+        if let Some((source, script)) = initial_schema {
+            if let Err(e) = Node::run_initial_schema(node, connections, &source, &script) {
+                log.error(&format!("INITIAL SCHEMA {} FAILED: {}", source, e), true)?;
+            }
+        }
+        Ok(streamed)
+    }
+
+    // Runs the initial schema of the node, which just formed the cluster, and records it next to
+    // the commit log
+    fn run_initial_schema(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        source: &str,
+        script: &str,
+    ) -> Result<(), NodeError> {
+        let statements = Node::import_schema(node, connections, script)?;
+
+        let bootstrap = SchemaBootstrap {
+            source: source.to_string(),
+            statements: statements.len(),
+            ran_at: Utc::now().timestamp_millis(),
+        };
+        let mut node_guard = node.lock()?;
+        bootstrap.write(&node_guard.storage_engine.schema_bootstrap_path())?;
+        node_guard.schema_bootstrap = Some(bootstrap);
+        node_guard.get_logger().info(
+            &format!(
+                "INITIAL SCHEMA {}: {} STATEMENTS RUN",
+                source,
+                statements.len()
+            ),
+            Color::Cyan,
+            true,
+        )?;
+        Ok(())
+    }
+
+    /// Streams to a bootstrapping node the rows of this node it must keep once it joins the ring, and then
+    /// what it streamed, for a `StreamRequest` of that node (see `bootstrap`).
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node streaming the rows.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, which the rows are streamed through.
+    /// - `joining_ip: Ipv4Addr`
+    ///   - The bootstrapping node.
+    /// - `request: &StreamRequest`
+    ///   - Where the bootstrapping node is placed in the ring.
+    ///
+    /// # Returns
+    /// - `Result<(), NodeError>`
+    ///   - Returns `Err(NodeError)` if the result can not be queued. A failure to stream the rows is sent to
+    ///     the bootstrapping node instead.
+    ///
+    /// # Notes
+    /// - The chunks of rows and the result go through the streaming queue of the bootstrapping node, so they
+    ///   are paced by its bandwidth limit and the result reaches it after every chunk.
+    pub(crate) fn stream_to_bootstrapping_node(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        joining_ip: Ipv4Addr,
+        request: &StreamRequest,
+    ) -> Result<(), NodeError> {
+        let (self_ip, storage_engine, keyspaces, mut partitioner, sessions, outbound, log) = {
+            let node_guard = node.lock()?;
+            (
+                node_guard.ip,
+                node_guard.storage_engine.clone(),
+                node_guard
+                    .schema
+                    .keyspaces
+                    .values()
+                    .cloned()
+                    .collect::<Vec<KeyspaceSchema>>(),
+                node_guard.partitioner.clone(),
+                node_guard.stream_sessions.clone(),
+                node_guard.outbound.clone(),
+                node_guard.get_logger(),
+            )
+        };
+
+        let stream = |target_ip: Ipv4Addr, message: InternodeMessage| {
+            let _ = Node::send_streamed(
+                &sessions,
+                &outbound,
+                target_ip,
+                message,
+                connections.clone(),
+            );
+        };
+        let outcome = if partitioner.contains_node(&joining_ip)
+            || partitioner
+                .add_node_with_tokens(joining_ip, &request.tokens)
+                .is_ok()
+        {
+            partitioner.set_location(joining_ip, &request.datacenter, &request.rack);
+            storage_engine
+                .stream_ranges_to(keyspaces, &partitioner, joining_ip, log.clone(), &stream)
+                .map(|rows| rows as u32)
+                .map_err(|e| e.to_string())
+        } else {
+            Err("the tokens of the node are taken".to_string())
+        };
+        log.info(
+            &format!("BOOTSTRAP OF {}: {:?}", joining_ip, outcome),
+            Color::Cyan,
+            true,
+        )?;
+
+        let result = StreamResult {
+            id: request.id,
+            outcome,
+        };
+        outbound.send(
+            joining_ip,
+            Traffic::Streaming,
+            InternodeMessage::new(self_ip, InternodeMessageContent::StreamResult(result)),
+            connections,
+            None,
+        )
+    }
+
+    /// Decommissions the node: hands its data over to the rest of the ring and takes it out of the ring.
+    ///
+    /// # Purpose
+    /// A node that just stops is seen as dead, and the other nodes redistribute their data to make up for
+    /// the copies it held. Decommissioning the node (Cassandra's `nodetool decommission`) streams its rows
+    /// to the nodes that take its ranges before it goes away, so the ring shrinks without losing copies.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node to decommission.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, which the rows are streamed through.
+    /// - `timeout: Duration`
+    ///   - The longest time to wait for the client queries of the node and then for the rows streamed to be
+    ///     acknowledged.
+    ///
+    /// # Returns
+    /// - `Result<usize, NodeError>`
+    ///   - On success:
+    ///     - Returns the amount of rows streamed to the other nodes, counting each copy.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::DecommissionError)` if the node is the last one of the ring, or some rows
+    ///       were not acknowledged in time. The node is then left `Leaving`, with its data, and can be
+    ///       decommissioned again.
+    ///
+    /// # Behavior
+    /// 1. **Drain**:
+    ///    - Drains the node (see `drain`), which stops its client queries and announces it as `Leaving`.
+    /// 2. **Streaming**:
+    ///    - Sends every row the node owns or replicates, in chunks of rows (see `StreamChunk`), to its owner and
+    ///      replicas in the ring without the node. The chunks go through the streaming queues, paced like a
+    ///      redistribution.
+    /// 3. **Acknowledgment**:
+    ///    - Waits until every chunk was acknowledged, up to `timeout`. The chunks that were lost or rejected are
+    ///      sent again meanwhile (see `resume_streams`).
+    /// 4. **Gossip**:
+    ///    - Announces the node as `Left` and records a `Decommissioned` event. The other nodes take it out of
+    ///      their ring without redistributing anything, as its data is already where it belongs.
+    ///
+    /// # Notes
+    /// - The data files of the node are not changed, so nothing is lost if the streaming fails.
+    /// - The ring keeps the node until it has left, so writes it takes as a replica while it streams are not
+    ///   handed over. They are repaired by read repair or by the hints of the coordinators.
+    /// - The admin command stops the node a few gossip rounds after answering, which makes `start` return.
+    fn decommission(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        timeout: Duration,
+    ) -> Result<usize, NodeError> {
+        let deadline = Instant::now() + timeout;
+        Node::drain(node, timeout, NodeStatus::Leaving)?;
+
+        let (ip, storage_engine, keyspaces, mut partitioner, sessions, outbound, log) = {
+            let node_guard = node.lock()?;
+            (
+                node_guard.ip,
+                node_guard.storage_engine.clone(),
+                node_guard
+                    .schema
+                    .keyspaces
+                    .values()
+                    .cloned()
+                    .collect::<Vec<KeyspaceSchema>>(),
+                node_guard.partitioner.clone(),
+                node_guard.stream_sessions.clone(),
+                node_guard.outbound.clone(),
+                node_guard.get_logger(),
+            )
+        };
+        partitioner.remove_node(ip)?;
+        if partitioner.get_nodes().is_empty() {
+            return Err(NodeError::DecommissionError(
+                "the node is the last one of the ring".to_string(),
+            ));
+        }
+
+        log.warn(
+            &format!(
+                "DECOMMISSIONING: STREAMING TO {:?}",
+                partitioner.get_nodes()
+            ),
+            true,
+        )?;
+
+        // The chunks of the decommission are told apart from the ones of other streams by their session
+        let session = Cell::new(None);
+        let stream = |target_ip: Ipv4Addr, message: InternodeMessage| {
+            if let InternodeMessageContent::StreamChunk(chunk) = &message.content {
+                session.set(Some(chunk.session));
+            }
+            let _ = Node::send_streamed(
+                &sessions,
+                &outbound,
+                target_ip,
+                message,
+                connections.clone(),
+            );
+        };
+        let streamed =
+            storage_engine.stream_data_away(keyspaces, &partitioner, log.clone(), &stream)?;
+
+        // Lost chunks are sent again by the gossip rounds while this waits
+        let mut unacknowledged = 0;
+        if let Some(session) = session.get() {
+            loop {
+                unacknowledged = sessions.len_of(session)?;
+                if unacknowledged == 0 || Instant::now() >= deadline {
+                    break;
+                }
+                thread::sleep(DECOMMISSION_POLL_INTERVAL);
+            }
+        }
+        if unacknowledged > 0 {
+            return Err(NodeError::DecommissionError(format!(
+                "{} chunks of rows were not acknowledged",
+                unacknowledged
+            )));
+        }
+
+        let mut node_guard = node.lock()?;
+        node_guard
+            .gossiper
+            .change_status(ip, NodeStatus::Left)
+            .map_err(|_| NodeError::GossipError)?;
+        node_guard.events.record(NodeEvent::Decommissioned);
+        log.warn(&format!("DECOMMISSIONED: {} ROWS STREAMED", streamed), true)?;
+        Ok(streamed)
+    }
+
+    /// Flushes, compacts or takes a snapshot of the tables of a keyspace in every node of the ring.
+    ///
+    /// # Purpose
+    /// Gives operators control over the memtables and SSTables of the LSM backend, and backups of the data
+    /// of the cluster, like Cassandra's `nodetool flush`, `nodetool compact` and `nodetool snapshot`, without
+    /// having to reach each node of the cluster.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node that got the admin command, which coordinates the operation.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, which are asked to run the operation too.
+    /// - `operation: MaintenanceOperation`
+    ///   - Whether the memtables are flushed, the SSTables compacted or a snapshot taken.
+    /// - `keyspace: String`
+    ///   - The keyspace whose tables the operation runs on.
+    /// - `table: Option<String>`
+    ///   - The only table of the keyspace to run the operation on, or `None` for all of them.
+    /// - `tag: Option<String>`
+    ///   - The name of the snapshot taken by a `Snapshot`.
+    ///
+    /// # Returns
+    /// - `Result<Vec<String>, NodeError>`
+    ///   - On success:
+    ///     - Returns one `NodeProgress` line per node of the ring, with the amount of memtables flushed,
+    ///       SSTables compacted or files snapshotted by the node, why it failed, or that it did not answer.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::KeyspaceError)` if the keyspace does not exist.
+    ///
+    /// # Behavior
+    /// 1. **Request**:
+    ///    - Registers the request under a new id, so the results of the other nodes reach it.
+    ///    - Sends a `Maintenance` internode message to every other node of the ring.
+    /// 2. **Local Run**:
+    ///    - Runs the operation on the storage of this node, for its owned and replicated rows.
+    /// 3. **Progress**:
+    ///    - Waits up to `MAINTENANCE_TIMEOUT` for the results of the other nodes. The nodes that could not be
+    ///      reached or did not answer in time are reported as unreachable.
+    ///
+    /// # Notes
+    /// - Flushes and compactions do nothing in the nodes using the CSV backend, which report `0`.
+    /// - The node keeps executing queries while it waits, as it does not hold its lock.
+    fn run_maintenance_in_ring(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        operation: MaintenanceOperation,
+        keyspace: String,
+        table: Option<String>,
+        tag: Option<String>,
+    ) -> Result<Vec<String>, NodeError> {
+        let (self_ip, nodes, request, receiver) = {
+            let mut node_guard = node.lock()?;
+            if !node_guard.schema.keyspaces.contains_key(&keyspace) {
+                return Err(NodeError::KeyspaceError);
+            }
+            node_guard.last_maintenance_id = node_guard.last_maintenance_id.wrapping_add(1);
+            let id = node_guard.last_maintenance_id;
+            let (sender, receiver) = mpsc::channel();
+            node_guard.pending_maintenance.insert(id, sender);
+            let request = MaintenanceRequest {
+                id,
+                operation,
+                keyspace,
+                table,
+                tag,
+            };
+            (
+                node_guard.ip,
+                node_guard.partitioner.get_nodes(),
+                request,
+                receiver,
+            )
+        };
+
+        let port = node.lock()?.config.internode_port;
+        let mut progress: HashMap<Ipv4Addr, MaintenanceStatus> = HashMap::new();
+        let mut waiting = 0;
+        for peer in nodes.iter().filter(|ip| **ip != self_ip) {
+            let message = InternodeMessage::new(
+                self_ip,
+                InternodeMessageContent::Maintenance(request.clone()),
+            );
+            if connect_and_send_message(*peer, port, connections.clone(), message).is_ok() {
+                waiting += 1;
+            }
+        }
+
+        let outcome = Node::run_maintenance(node, &request).map_err(|e| e.to_string());
+        progress.insert(self_ip, MaintenanceStatus::from(outcome));
+
+        let deadline = Instant::now() + MAINTENANCE_TIMEOUT;
+        while waiting > 0 {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            match receiver.recv_timeout(left) {
+                Ok((from, outcome)) => {
+                    progress.insert(from, MaintenanceStatus::from(outcome));
+                    waiting -= 1;
+                }
+                Err(_) => break,
+            }
+        }
+        node.lock()?.pending_maintenance.remove(&request.id);
+
+        Ok(nodes
+            .into_iter()
+            .map(|ip| NodeProgress {
+                node: ip,
+                status: progress
+                    .remove(&ip)
+                    .unwrap_or(MaintenanceStatus::Unreachable),
+            })
+            .map(|progress| progress.to_string())
+            .collect())
+    }
+
+    /// Returns one line per node of the cluster known by gossip, as Cassandra's `nodetool status`:
+    /// whether it is up (`U`) or down (`D`), its state (`N`ormal, `J`oining, `L`eaving or `R`emoving),
+    /// its address, datacenter and rack, and how many tokens of the ring it owns.
+    ///
+    /// # Notes
+    /// - The nodes that left the ring or were removed from it are not listed.
+    /// - Whether a node is up is what the failure detector of this node thinks, so nodes may disagree
+    ///   for a few seconds after one goes down.
+    fn ring_status(&self) -> Vec<String> {
+        let mut nodes: Vec<_> = self
+            .gossiper
+            .endpoints_state
+            .iter()
+            .filter(|(_, state)| {
+                let status = &state.application_state.status;
+                !status.is_left() && !status.is_removed()
+            })
+            .collect();
+        nodes.sort_by_key(|(ip, _)| **ip);
+
+        nodes
+            .into_iter()
+            .map(|(ip, state)| {
+                let up = *ip == self.ip || self.gossiper.is_alive(*ip);
+                let state = match state.application_state.status {
+                    NodeStatus::Bootstrap => 'J',
+                    NodeStatus::Leaving => 'L',
+                    NodeStatus::Removing => 'R',
+                    _ => 'N',
+                };
+                format!(
+                    "{}{} {} {} {} {}",
+                    if up { 'U' } else { 'D' },
+                    state,
+                    ip,
+                    self.partitioner.get_datacenter(ip),
+                    self.partitioner.get_rack(ip),
+                    self.partitioner.get_tokens(ip).len()
+                )
+            })
+            .collect()
+    }
+
+    /// Returns one line per range of tokens of the ring, as Cassandra's `nodetool describering`: the
+    /// range, as `TokenRange` prints it, followed by the replicas that store it in `keyspace`.
+    ///
+    /// # Errors
+    /// - `NodeError::KeyspaceError` if the keyspace does not exist.
+    /// - `NodeError::PartitionerError` if the ring has no nodes.
+    fn describe_ring(&self, keyspace: &str) -> Result<Vec<String>, NodeError> {
+        let keyspace = self
+            .schema
+            .keyspaces
+            .get(keyspace)
+            .ok_or(NodeError::KeyspaceError)?;
+
+        let mut lines = Vec::new();
+        for (tokens, owner) in self.partitioner.token_ranges()? {
+            let replicas = get_replicas(&self.partitioner, owner, keyspace)?;
+            let mut line = TokenRange::new(tokens, owner).to_string();
+            for replica in replicas {
+                line.push_str(&format!(" {}", replica));
+            }
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+
+    /// Repairs the tables of a keyspace, or one of them, by reading all of their rows from every
+    /// replica.
+    ///
+    /// # Purpose
+    /// Replicas that missed writes (because they were down, or dropped by a partition) keep stale rows
+    /// until something reads them at a consistency level that reaches the others. Repairing a table
+    /// reads every row at `ALL`, so the read repair of the node fixes them all at once instead.
+    ///
+    /// # Returns
+    /// - `Result<Vec<String>, NodeError>`
+    ///   - On success:
+    ///     - Returns one line per table, with how many rows were compared across its replicas.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::KeyspaceError)` if the keyspace does not exist, or an error of the
+    ///       table if it does not exist.
+    ///     - Returns `Err(NodeError::RepairError)` if the rows of a range of tokens of a table can not be
+    ///       read from all of its replicas within `REPAIR_RANGE_TIMEOUT`, as when one of them is down.
+    ///
+    /// # Notes
+    /// - Tables are read a range of tokens of the ring at a time, so the rows of a whole table are never
+    ///   merged in a single result.
+    /// - Read repair updates the replicas that return an older version of a row. A row missing from a
+    ///   replica altogether is not written back to it; hints and the streaming of a bootstrap or a
+    ///   decommission are what restore those.
+    /// - The reads of a repair write every repair they find before they are answered, whatever the
+    ///   `read_repair_chance` of the table, so the table is repaired once `REPAIR` returns.
+    fn repair(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        keyspace: &str,
+        table: Option<String>,
+    ) -> Result<Vec<String>, NodeError> {
+        let (tables, ranges, client, log) = {
+            let mut node_guard = node.lock()?;
+            let keyspace_schema = node_guard
+                .schema
+                .keyspaces
+                .get(keyspace)
+                .ok_or(NodeError::KeyspaceError)?;
+            let tables = match table {
+                Some(table) => vec![keyspace_schema.get_table(&table)?],
+                None => keyspace_schema.get_tables(),
+            };
+            let ranges = node_guard.partitioner.token_ranges()?;
+            let client_id = node_guard.generate_client_id();
+            node_guard.repairing_clients.insert(client_id);
+            let client = RepairClient {
+                node,
+                id: client_id,
+            };
+            (tables, ranges, client, node_guard.get_logger())
+        };
+
+        let mut report = Vec::new();
+        for table in tables {
+            let name = format!("{}.{}", keyspace, table.get_name());
+            let token = format!("token({})", table.get_partition_keys()?.join(","));
+            let query_log = log.with_correlation_id(&Self::new_correlation_id());
+            query_log.info(&format!("ADMIN: REPAIRING {}", name), Color::Yellow, true)?;
+
+            let mut compared = 0;
+            for (tokens, _) in &ranges {
+                let statement = format!(
+                    "SELECT * FROM {} WHERE {} >= {} AND {} < {}",
+                    name, token, tokens.start, token, tokens.end
+                );
+                let (tx_reply, rx_reply) = mpsc::channel();
+                Node::handle_query_execution(
+                    &statement,
+                    "all",
+                    node,
+                    connections.clone(),
+                    tx_reply,
+                    client.id,
+                    query_log.clone(),
+                )
+                .map_err(|e| NodeError::RepairError(format!("{}: {}", name, e)))?;
+
+                match rx_reply.recv_timeout(REPAIR_RANGE_TIMEOUT) {
+                    Ok(Frame::Result(result_::Result::Rows(rows))) => {
+                        compared += rows.rows_content.len()
+                    }
+                    Ok(Frame::Error(e)) => {
+                        return Err(NodeError::RepairError(format!("{}: {:?}", name, e)))
+                    }
+                    Ok(_) => {}
+                    Err(_) => return Err(NodeError::RepairError(format!("{}: timed out", name))),
+                }
+            }
+            report.push(format!("{}: {} rows compared", name, compared));
+        }
+
+        Ok(report)
+    }
+
+    // Writes every memtable to a new SSTable and retires the segments of the commit log whose mutations
+    // are now in the data files. Returns how many memtables were written.
+    fn flush_memtables(&self) -> Result<usize, NodeError> {
+        let first_kept = self.commit_log.lock()?.flush_point()?;
+        let flushed = self.storage_engine.clone().flush_memtables()?;
+        self.commit_log.lock()?.retire_before(first_kept)?;
+        Ok(flushed)
+    }
+
+    /// Runs a flush, a compaction or a snapshot on the storage of this node, asked by the node coordinating
+    /// it in the ring (or by itself).
+    ///
+    /// # Returns
+    /// - `Result<u32, NodeError>`
+    ///   - On success:
+    ///     - Returns the amount of memtables flushed, SSTables compacted or files in the snapshot.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the keyspace or table does not exist, the snapshot has no tag or
+    ///       already exists, or the storage can not be written.
+    fn run_maintenance(
+        node: &Arc<Mutex<Node>>,
+        request: &MaintenanceRequest,
+    ) -> Result<u32, NodeError> {
+        let (storage_engine, tables, commit_log) = {
+            let node_guard = node.lock()?;
+            let keyspace = node_guard
+                .schema
+                .keyspaces
+                .get(&request.keyspace)
+                .ok_or(NodeError::KeyspaceError)?;
+            let tables = match &request.table {
+                Some(table) => vec![keyspace.get_table(table)?],
+                None => keyspace.get_tables(),
+            };
+            (
+                node_guard.storage_engine.clone(),
+                tables,
+                Arc::clone(&node_guard.commit_log),
+            )
+        };
+
+        if request.operation == MaintenanceOperation::Snapshot {
+            let tag = request.tag.as_deref().ok_or(NodeError::OtherError)?;
+            // The mutations of the segments before this one are in the files the snapshot is taken from
+            let replay_from = commit_log.lock()?.flush_point()?;
+            return Ok(storage_engine.snapshot(&request.keyspace, tag, replay_from)? as u32);
+        }
+
+        let first_kept = commit_log.lock()?.flush_point()?;
+        let mut amount = 0;
+        for table in tables {
+            amount += match request.operation {
+                MaintenanceOperation::Flush => {
+                    storage_engine.flush_lsm_table(&request.keyspace, &table.get_name())?
+                }
+                MaintenanceOperation::Compact => storage_engine.compact_lsm_table(
+                    &request.keyspace,
+                    &table.get_name(),
+                    &table.get_columns(),
+                )?,
+                MaintenanceOperation::Snapshot => 0,
+            };
+        }
+        // The segments are only retired once no memtable, of any keyspace, holds their mutations
+        if !storage_engine.has_unflushed_rows()? {
+            commit_log.lock()?.retire_before(first_kept)?;
+        }
+        Ok(amount as u32)
+    }
+
+    /// Takes part, as a replica of its partition, in a phase of the Paxos round of a lightweight transaction.
+    ///
+    /// # Returns
+    /// - `Result<PaxosReply, NodeError>`
+    ///   - On success:
+    ///     - Returns whether the replica promised, accepted or committed. A promise also carries the row
+    ///       stored by the replica with the key of the round, and the row accepted in a round not committed.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError)` if the keyspace or table does not exist, or the storage can not be
+    ///       read or written.
+    ///
+    /// # Notes
+    /// - The committed row is written through the commit log, with the timestamp of the ballot of its round,
+    ///   so committing it again is harmless.
+    /// - The committed row expires after the `USING TTL` of the statement of its round, carried in the
+    ///   proposal, or the `default_time_to_live` of its table.
+    fn answer_paxos(
+        node: &Arc<Mutex<Node>>,
+        request: &PaxosRequest,
+    ) -> Result<PaxosReply, NodeError> {
+        let key = (
+            request.keyspace.clone(),
+            request.table.clone(),
+            request.partition.clone(),
+        );
+        let (table, is_replication, storage_engine, commit_log, mut reply) = {
+            let mut node_guard = node.lock()?;
+            let table = node_guard
+                .schema
+                .keyspaces
+                .get(&request.keyspace)
+                .ok_or(NodeError::KeyspaceError)?
+                .get_table(&request.table)?;
+            let is_replication =
+                node_guard.partitioner.get_ip(request.partition.clone())? != node_guard.ip;
+
+            let mut reply = PaxosReply {
+                id: request.id,
+                ok: true,
+                in_progress: None,
+                committed: None,
+                current: None,
+            };
+            match (request.phase, &request.proposal) {
+                (PaxosPhase::Prepare, _) => {
+                    let promise = node_guard.paxos.prepare(key, request.ballot);
+                    reply.ok = promise.promised;
+                    reply.in_progress = promise.in_progress;
+                    reply.committed = promise.committed;
+                }
+                (PaxosPhase::Propose, Some(proposal)) => {
+                    reply.ok = node_guard
+                        .paxos
+                        .propose(key, request.ballot, proposal.clone());
+                }
+                (PaxosPhase::Commit, Some(_)) => node_guard.paxos.commit(key, request.ballot),
+                (_, None) => return Err(NodeError::InternodeProtocolError),
+            }
+            (
+                table,
+                is_replication,
+                node_guard.storage_engine.clone(),
+                Arc::clone(&node_guard.commit_log),
+                reply,
+            )
+        };
+
+        match (request.phase, &request.proposal) {
+            (PaxosPhase::Prepare, _) if reply.ok => {
+                reply.current = storage_engine.read_row(
+                    &request.keyspace,
+                    &table,
+                    is_replication,
+                    &request.key,
+                )?;
+            }
+            (PaxosPhase::Commit, Some(proposal)) => {
+                let entry = CommitLogEntry {
+                    keyspace: request.keyspace.clone(),
+                    table: request.table.clone(),
+                    is_replication,
+                    timestamp: request.ballot.timestamp(),
+                    mutation: Mutation::insert(
+                        &table,
+                        proposal
+                            .row
+                            .split(',')
+                            .map(|value| value.to_string())
+                            .collect(),
+                        false,
+                        proposal.ttl,
+                    ),
+                };
+                let _logged = CommitLog::log(&commit_log, &entry)?;
+                storage_engine.apply(&entry, &table)?;
+            }
+            _ => {}
+        }
+        Ok(reply)
+    }
+
+    /// Runs the schema statements of a CQL script, in order, as if a client had sent them.
+    ///
+    /// # Purpose
+    /// Moves the schema exported with `EXPORT SCHEMA` from another cluster to this one, without its data.
+    ///
+    /// # Parameters
+    /// - `node: &Arc<Mutex<Node>>`
+    ///   - The node that coordinates the statements.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes, which also have to run the statements.
+    /// - `script: &str`
+    ///   - The CQL script, with `CREATE KEYSPACE`, `CREATE TABLE` and `USE` statements.
+    ///
+    /// # Returns
+    /// - `Result<Vec<String>, NodeError>`
+    ///   - On success:
+    ///     - Returns the statements run, one per line.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::ScriptError)` with the statement that failed, or could not be run. The
+    ///       statements before it are not undone.
+    ///
+    /// # Behavior
+    /// - Every `CREATE` is run with `IF NOT EXISTS`, so the keyspaces and tables that already exist are left as
+    ///   they are and a script can be imported again.
+    /// - The whole script is validated before running its first statement.
+    fn import_schema(
+        node: &Arc<Mutex<Node>>,
+        connections: ConnectionManager,
+        script: &str,
+    ) -> Result<Vec<String>, NodeError> {
+        let statements = schema_script::import_statements(script)?;
+        let (client_id, log) = {
+            let mut node_guard = node.lock()?;
+            (node_guard.generate_client_id(), node_guard.get_logger())
+        };
+
+        for statement in &statements {
+            let query_log = log.with_correlation_id(&Self::new_correlation_id());
+            query_log.info(
+                &format!("ADMIN: IMPORTING {}", statement),
+                Color::Yellow,
+                true,
+            )?;
+
+            let (tx_reply, rx_reply) = mpsc::channel();
+            Node::handle_query_execution(
+                statement,
+                "all",
+                node,
+                connections.clone(),
+                tx_reply,
+                client_id,
+                query_log,
             )
-            .unwrap_or_else(|e| {
-                let message = format!("ERROR in CLIENT CONNECTIONS: {:?}", e);
-                log_client.clone().error(&message, true).ok();
-            });
-        });
+            .map_err(|e| NodeError::ScriptError(format!("{}: {}", statement, e)))?;
 
-        // Creates a thread to handle node connections
-        let node_connections_node = Arc::clone(&node);
-        let node_connections = Arc::clone(&connections);
-        let self_ip_node = self_ip.clone();
-        let log_internode = log.clone();
-        let handle_node_thread = thread::spawn(move || {
-            Self::handle_node_connections(node_connections_node, node_connections, self_ip_node)
-                .unwrap_or_else(|err| {
-                    let message = format!("ERROR in INTERNODE CONNECTIONS: {:?}", err);
-                    log_internode.error(&message, true).ok(); // Or handle the error as needed
-                });
+            match rx_reply.recv_timeout(SCHEMA_STATEMENT_TIMEOUT) {
+                Ok(Frame::Error(e)) => {
+                    return Err(NodeError::ScriptError(format!("{}: {:?}", statement, e)))
+                }
+                Ok(_) => {}
+                Err(_) => return Err(NodeError::ScriptError(format!("{}: timed out", statement))),
+            }
+        }
+
+        Ok(statements)
+    }
+
+    // Runs a statement of the node itself, as a client without a user, and returns its result.
+    fn execute_internal(
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        statement: &str,
+        consistency_level: &str,
+        logger: &Logger,
+    ) -> Result<Frame, NodeError> {
+        let client_id = node.lock()?.generate_client_id();
+        let (tx_reply, rx_reply) = mpsc::channel();
+        let reply = Node::handle_query_execution(
+            statement,
+            consistency_level,
+            node,
+            connections.clone(),
+            tx_reply,
+            client_id,
+            logger.clone(),
+        )
+        .and_then(|_| {
+            rx_reply
+                .recv_timeout(SCHEMA_STATEMENT_TIMEOUT)
+                .map_err(|_| NodeError::AuthError("timed out".to_string()))
         });
+        node.lock()?.clients_keyspace.remove(&client_id);
+
+        match reply? {
+            Frame::Error(e) => Err(NodeError::AuthError(format!("{:?}", e))),
+            frame => Ok(frame),
+        }
+    }
+
+    // Reads a user from the `system_auth` tables, which is `None` if it does not exist.
+    fn load_role(
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        user: &str,
+        logger: &Logger,
+    ) -> Result<Option<StoredRole>, NodeError> {
+        let [role_select, permissions_select] = roles::role_selects(user);
+        let role = Self::execute_internal(node, connections, &role_select, "one", logger)?;
+        let permissions =
+            Self::execute_internal(node, connections, &permissions_select, "one", logger)?;
+
+        match (role, permissions) {
+            (
+                Frame::Result(result_::Result::Rows(role_rows)),
+                Frame::Result(result_::Result::Rows(permission_rows)),
+            ) => Ok(StoredRole::from_rows(&role_rows, &permission_rows)),
+            _ => Err(NodeError::AuthError(format!(
+                "user {} was not read as rows",
+                user
+            ))),
+        }
+    }
 
-        handle_node_thread
-            .join()
-            .map_err(|_| NodeError::InternodeError)?;
-        handle_client_thread
-            .join()
-            .map_err(|_| NodeError::ClientError)?;
+    // Checks the credentials a client logs in with, sent in the token of its `AUTH_RESPONSE`.
+    // Returns the user they belong to, or `None` if they are wrong.
+    fn authenticate(
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        token: &str,
+        logger: &Logger,
+    ) -> Result<Option<String>, NodeError> {
+        let credentials = Credentials::from_token(token);
+        if !roles::auth_tables_exist(&node.lock()?.schema.keyspaces) {
+            return Ok(credentials.are_default().then_some(credentials.user));
+        }
+        if !roles::is_valid_user_name(&credentials.user) {
+            return Ok(None);
+        }
+
+        match Self::load_role(node, connections, &credentials.user, logger)? {
+            Some(stored) if roles::verify_password(&credentials.password, &stored.salted_hash) => {
+                node.lock()?.roles.insert(stored.role, Instant::now());
+                Ok(Some(credentials.user))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Reads again the role of the user of a client if the one the node has is older than
+    // `ROLES_VALIDITY`. If it can not be read, the one the node has is used.
+    fn refresh_role(
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        client_id: i32,
+        logger: &Logger,
+    ) -> Result<(), NodeError> {
+        let user = {
+            let node_guard = node.lock()?;
+            match node_guard.clients_user.get(&client_id) {
+                Some(user)
+                    if roles::auth_tables_exist(&node_guard.schema.keyspaces)
+                        && node_guard.roles.is_stale(user, Instant::now()) =>
+                {
+                    user.clone()
+                }
+                _ => return Ok(()),
+            }
+        };
 
+        match Self::load_role(node, connections, &user, logger) {
+            Ok(stored) => {
+                // A user dropped while its clients are connected may not run anything else
+                let role = stored.map_or_else(|| Role::without_permissions(&user), |s| s.role);
+                node.lock()?.roles.insert(role, Instant::now());
+            }
+            Err(e) => logger.warn(
+                &format!("AUTH: COULD NOT READ THE ROLE OF USER {}: {}", user, e),
+                true,
+            )?,
+        }
         Ok(())
     }
 
-    fn handle_node_connections(
-        node: Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-        self_ip: std::net::Ipv4Addr,
+    // Creates the `system_auth` keyspace and its tables with the default superuser, if they do
+    // not exist yet.
+    fn create_auth_keyspace(
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        logger: &Logger,
     ) -> Result<(), NodeError> {
-        let socket = SocketAddrV4::new(self_ip, INTERNODE_PORT);
-        let listener = TcpListener::bind(socket)?;
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let node_clone = Arc::clone(&node);
-                    let stream = Arc::new(Mutex::new(stream)); // Encapsulates the stream in Arc<Mutex<TcpStream>>
-                    let connections_clone = Arc::clone(&connections);
+        if roles::auth_tables_exist(&node.lock()?.schema.keyspaces) {
+            return Ok(());
+        }
+        logger.info(
+            &format!("AUTH: CREATING THE {} KEYSPACE", AUTH_KEYSPACE),
+            Color::Yellow,
+            true,
+        )?;
+        for statement in roles::schema_statements() {
+            Self::execute_internal(node, connections, &statement, "all", logger)?;
+        }
+        // The tables reach the other nodes by gossip, and the replicas that do not know them yet
+        // reject their rows
+        let deadline = Instant::now() + SCHEMA_STATEMENT_TIMEOUT;
+        while !node.lock()?.auth_tables_agreed() {
+            if Instant::now() >= deadline {
+                return Err(NodeError::AuthError(format!(
+                    "the {} tables did not reach every node",
+                    AUTH_KEYSPACE
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let superuser = roles::default_superuser_statement();
+        Self::execute_internal(node, connections, &superuser, "one", logger)?;
+        Ok(())
+    }
 
-                    thread::spawn(move || {
-                        if let Err(e) = Node::handle_incoming_internode_messages(
-                            node_clone,
-                            stream,
-                            connections_clone,
-                        ) {
-                            eprintln!("{:?}", e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    eprintln!("Error accepting internode connection: {:?}", e);
+    // Runs a `CREATE USER`, `ALTER USER`, `DROP USER`, `GRANT` or `REVOKE` of a client as
+    // statements on the `system_auth` tables, creating them the first time.
+    fn execute_role_statement(
+        query: &Query,
+        node: &Arc<Mutex<Node>>,
+        connections: &ConnectionManager,
+        client_id: i32,
+        logger: &Logger,
+    ) -> Result<(), NodeError> {
+        let user = roles::target_user(query).ok_or(NodeError::OtherError)?;
+        if !roles::is_valid_user_name(&user) {
+            return Err(NodeError::Invalid(format!("Invalid user name {}", user)));
+        }
+        {
+            let node_guard = node.lock()?;
+            let requester = node_guard.clients_user.get(&client_id);
+            if roles::changes_superuser(query)
+                && requester.is_some_and(|requester| !node_guard.role_of(requester).is_superuser)
+            {
+                return Err(NodeError::Unauthorized(
+                    "Only superusers may make or unmake superusers".to_string(),
+                ));
+            }
+            if let Query::Grant(grant) = query {
+                if let Some(keyspace) = &grant.keyspace {
+                    node_guard.get_keyspace(keyspace)?.ok_or_else(|| {
+                        NodeError::Invalid(format!("Unknown keyspace {}", keyspace))
+                    })?;
                 }
             }
         }
 
+        Self::create_auth_keyspace(node, connections, logger)?;
+        let stored = Self::load_role(node, connections, &user, logger)?;
+        for statement in roles::statements_of(query, stored.as_ref())? {
+            Self::execute_internal(node, connections, &statement, "one", logger)?;
+        }
+        node.lock()?.roles.invalidate(&user);
         Ok(())
     }
 
     fn handle_client_connections(
         node: Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         self_ip: std::net::Ipv4Addr,
     ) -> Result<(), NodeError> {
-        // Cargar configuración TLS
-        let project_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
-        let path_certs = Path::new(&project_dir)
-            .parent()
-            .unwrap_or_else(|| Path::new("."))
-            .join("certs");
+        let (path_certs, client_port) = {
+            let node_guard = node.lock()?;
+            (
+                node_guard.config.certs_path.clone(),
+                node_guard.config.client_port,
+            )
+        };
 
         // Cargar configuración TLS
         let certs = CertificateDer::pem_file_iter(path_certs.join("cert.crt"))
@@ -1017,30 +4303,57 @@ impl Node {
             }
         }
 
-        let config = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, private_key)
-            .unwrap();
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_no_client_auth()
+                .with_single_cert(certs, private_key)
+                .unwrap(),
+        );
 
-        let socket = SocketAddrV4::new(self_ip, CLIENT_NODE_PORT); // Specific port for clients
+        let socket = SocketAddrV4::new(self_ip, client_port); // Specific port for clients
         let listener = TcpListener::bind(socket)?;
+        let workers = WorkerPool::new("client", node.lock()?.concurrency.client_connections);
+        let shutdown = node.lock()?.shutdown.clone();
+        let refusing = Arc::new(AtomicUsize::new(0));
 
         for stream in listener.incoming() {
+            if shutdown.is_stopping() {
+                break;
+            }
             match stream {
-                Ok(mut stream) => {
-                    // Crear una conexión TLS para el stream TCP
-                    let mut conn = ServerConnection::new(Arc::new(config.clone()))
-                        .expect("No se pudo crear la conexión TLS");
-
-                    conn.complete_io(&mut stream).unwrap();
-                    let connections_clone = Arc::clone(&connections);
-
-                    let stream = StreamOwned::new(conn, stream);
+                Ok(stream) => {
+                    // A drained node takes no new clients
+                    if node.lock()?.draining {
+                        continue;
+                    }
 
+                    // Kept to tell the client why, if no worker takes its connection
+                    let refused = stream.try_clone()?;
+                    let connections_clone = connections.clone();
                     let node_clone = Arc::clone(&node);
-                    thread::spawn(move || {
-                        Node::handle_incoming_client_messages(node_clone, stream, connections_clone)
+                    let config_clone = Arc::clone(&config);
+                    let handled = workers.execute(move || {
+                        let result =
+                            Node::accept_tls_client(config_clone, stream).and_then(|stream| {
+                                Node::handle_incoming_client_messages(
+                                    node_clone,
+                                    stream,
+                                    connections_clone,
+                                )
+                            });
+                        if let Err(e) = result {
+                            eprintln!("{:?}", e);
+                        }
                     });
+
+                    if let Err(NodeError::Overloaded(reason)) = handled {
+                        Node::refuse_client_connection(
+                            Arc::clone(&config),
+                            refused,
+                            reason,
+                            &refusing,
+                        );
+                    }
                 }
                 Err(e) => {
                     eprintln!("Error accepting client connection: {:?}", e);
@@ -1048,6 +4361,62 @@ impl Node {
             }
         }
 
+        workers.join();
+        Ok(())
+    }
+
+    // Completes the TLS handshake of a client connection
+    fn accept_tls_client(
+        config: Arc<ServerConfig>,
+        mut stream: TcpStream,
+    ) -> Result<StreamOwned<ServerConnection, TcpStream>, NodeError> {
+        let mut conn = ServerConnection::new(config).map_err(|_| NodeError::OtherError)?;
+        conn.complete_io(&mut stream)?;
+        Ok(StreamOwned::new(conn, stream))
+    }
+
+    // Refuses a client connection no worker could take, answering it in a short-lived thread so a
+    // client that is slow to answer does not hold the thread accepting the connections. Once
+    // `MAX_REFUSING_CLIENTS` are being answered, the connection is closed without an answer.
+    fn refuse_client_connection(
+        config: Arc<ServerConfig>,
+        stream: TcpStream,
+        reason: String,
+        refusing: &Arc<AtomicUsize>,
+    ) {
+        if refusing.fetch_add(1, Ordering::SeqCst) >= MAX_REFUSING_CLIENTS {
+            refusing.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+
+        let refusing = Arc::clone(refusing);
+        thread::spawn(move || {
+            if let Err(e) = Node::answer_refused_client(config, stream, reason) {
+                eprintln!("Error refusing client connection: {:?}", e);
+            }
+            refusing.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    // Answers the first frame of a refused client connection with an `Overloaded` error and
+    // closes it, so its driver connects to another node
+    fn answer_refused_client(
+        config: Arc<ServerConfig>,
+        stream: TcpStream,
+        reason: String,
+    ) -> Result<(), NodeError> {
+        stream.set_read_timeout(Some(REFUSED_CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(REFUSED_CLIENT_TIMEOUT))?;
+        let mut stream = Node::accept_tls_client(config, stream)?;
+
+        // The first frame is read before answering it, or closing the connection with it unread
+        // would reset it before the client reads the answer
+        framing::read_frame(&mut stream)?;
+        let frame = Frame::Error(error::Error::Overloaded(reason));
+        stream.write_all(&frame.to_bytes()?)?;
+        stream.conn.send_close_notify();
+        stream.flush()?;
         Ok(())
     }
 
@@ -1055,109 +4424,359 @@ impl Node {
     fn handle_incoming_client_messages(
         node: Arc<Mutex<Node>>,
         mut stream: StreamOwned<ServerConnection, TcpStream>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
     ) -> Result<(), NodeError> {
         // Clone the stream under Mutex protection and create the reader
 
         let client_id;
         let log;
+        let limits;
+        let shutdown;
 
         {
             let mut guard_node = node.lock()?;
             client_id = guard_node.generate_client_id();
-            log = guard_node.get_logger();
+            log = guard_node.get_logger().with_component(Component::Native);
+            limits = guard_node.client_limits;
+            shutdown = guard_node.shutdown.clone();
         };
+        // Forgets the session of the client however the connection ends
+        let _session = ClientSession::new(Arc::clone(&node), client_id);
+        let opened = Instant::now();
+        let mut last_request = opened;
 
         let mut is_authenticated = false;
+        // Compression and checksum negotiated in the STARTUP of the connection, for the frames
+        // that follow it
+        let mut frame_options = FrameOptions::default();
+        let mut frames = FrameBuffer::new();
+        let mut buffer = vec![0; CLIENT_READ_BUFFER_SIZE];
+        // Rows of the results of the connection not sent yet, for the clients that page them
+        let mut result_pages = ResultPages::new();
 
         loop {
-            // Clean the buffer
-
-            let mut buffer = [0; 2048];
-
-            // Execute initial inserts if necessary
-
-            // Try to read a line
-            let bytes_read = stream.read(&mut buffer);
+            // Close the connection if it was idle, or open, for too long, or the node is shutting
+            // down, which is checked at least every `CLIENT_SHUTDOWN_CHECK_INTERVAL`
+            let time_left = if shutdown.is_stopping() {
+                Err(CloseReason::ShuttingDown)
+            } else {
+                limits.time_left(opened, last_request, Instant::now())
+            };
+            match time_left {
+                Ok(time_left) => stream.sock.set_read_timeout(Some(
+                    time_left.map_or(CLIENT_SHUTDOWN_CHECK_INTERVAL, |time_left| {
+                        time_left.min(CLIENT_SHUTDOWN_CHECK_INTERVAL)
+                    }),
+                ))?,
+                Err(reason) => {
+                    log.info(
+                        &format!(
+                            "NATIVE: CLOSING THE CONNECTION OF CLIENT {}: {}",
+                            client_id, reason
+                        ),
+                        Color::Yellow,
+                        true,
+                    )?;
+                    let frame = Frame::Error(error::Error::ServerError(format!(
+                        "Connection closed by the node: {}",
+                        reason
+                    )));
+                    stream.write_all(&frame.to_bytes_with_options(&frame_options, None)?)?;
+                    stream.conn.send_close_notify();
+                    stream.flush()?;
+                    break;
+                }
+            }
 
-            match bytes_read {
-                Ok(0) => {
-                    // Connection closed
+            // Frames already read whole are answered before reading again, and the bytes of a
+            // frame that did not fully arrive are kept across reads (and their timeouts)
+            let request_bytes = match frames.next_frame() {
+                Ok(Some(request_bytes)) => request_bytes,
+                Ok(None) => {
+                    match stream.read(&mut buffer) {
+                        Ok(0) => {
+                            // Connection closed
+                            break;
+                        }
+                        Ok(read) => frames.extend(&buffer[..read]),
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                            ) =>
+                        {
+                            // The read timed out, the limits are checked again
+                        }
+                        Err(_) => {
+                            // Another type of error
+                            return Err(NodeError::OtherError);
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    // The frames after a corrupted length can not be found, so the connection
+                    // is closed
+                    let frame =
+                        Frame::Error(error::Error::ProtocolError(format!("Invalid frame: {}", e)));
+                    stream.write_all(&frame.to_bytes_with_options(&frame_options, None)?)?;
+                    stream.conn.send_close_notify();
+                    stream.flush()?;
                     break;
                 }
-                Ok(_) => {
-                    let request = handle_client_request(&buffer).unwrap();
+            };
+
+            last_request = Instant::now();
+            let request = match handle_client_request(&request_bytes, &frame_options) {
+                Ok(request) => request,
+                Err(e) => {
+                    let frame = Frame::Error(error::Error::ProtocolError(format!(
+                        "Invalid frame: {:?}",
+                        e
+                    )));
+                    stream.write_all(&frame.to_bytes_with_options(&frame_options, None)?)?;
+                    stream.flush()?;
+                    continue;
+                }
+            };
 
-                    match request {
-                        Request::Startup => {
+            let statement = match request {
+                Request::Startup(startup) => {
+                    let response = match startup.negotiate() {
+                        Ok(negotiated) => {
                             let auth = Frame::Authenticate(Authenticate::default()).to_bytes()?;
-                            stream.write(auth.as_slice())?;
-                            stream.flush()?;
+                            // The answer to the STARTUP is not compressed nor
+                            // checksummed yet
+                            frame_options = negotiated;
+                            auth
                         }
-                        Request::AuthResponse(password) => {
-                            let response = if password == "admin" {
-                                is_authenticated = true;
-                                Frame::AuthSuccess(AuthSuccess::default()).to_bytes()?
-                            } else {
-                                Frame::Authenticate(Authenticate::default()).to_bytes()?
-                            };
+                        Err(e) => Frame::Error(e).to_bytes()?,
+                    };
+                    stream.write_all(response.as_slice())?;
+                    stream.flush()?;
+                    None
+                }
+                Request::AuthResponse(token) => {
+                    let user = Node::authenticate(&node, &connections, &token, &log)
+                        .unwrap_or_else(|e| {
+                            let _ = log.warn(
+                                &format!("AUTH: COULD NOT CHECK THE CREDENTIALS: {}", e),
+                                true,
+                            );
+                            None
+                        });
+                    let response = match user {
+                        Some(user) => {
+                            is_authenticated = true;
+                            node.lock()?.clients_user.insert(client_id, user);
+                            Frame::AuthSuccess(AuthSuccess::default())
+                        }
+                        None => Frame::Authenticate(Authenticate::default()),
+                    };
+                    let response = response.to_bytes_with_options(&frame_options, None)?;
 
-                            stream.write(response.as_slice())?;
+                    stream.write_all(response.as_slice())?;
+                    stream.flush()?;
+                    None
+                }
+                // Answered before the authentication too, as drivers also send it to
+                // check their idle connections
+                Request::Options => {
+                    let supported = Frame::Supported(Supported::default())
+                        .to_bytes_with_options(&frame_options, None)?;
+                    stream.write_all(&supported)?;
+                    stream.flush()?;
+                    None
+                }
+                _ if !is_authenticated => {
+                    let auth = Frame::Authenticate(Authenticate::default())
+                        .to_bytes_with_options(&frame_options, None)?;
+                    stream.write_all(auth.as_slice())?;
+                    stream.flush()?;
+                    None
+                }
+                Request::Prepare(prepare) => {
+                    let query = prepare.get_query();
+                    let id = node.lock()?.prepared_statements.prepare(query);
+                    let markers = bind_markers(query).len();
+                    let bind_metadata = Metadata::new(
+                        markers as u32,
+                        (0..markers)
+                            .map(|i| (format!("?{}", i), ColumnType::Varchar))
+                            .collect(),
+                    );
+                    let prepared = Frame::Result(result_::Result::Prepared(Prepared::new(
+                        id,
+                        bind_metadata,
+                        Metadata::new(0, vec![]),
+                    )));
+                    stream.write_all(&prepared.to_bytes_with_options(&frame_options, None)?)?;
+                    stream.flush()?;
+                    None
+                }
+                Request::Execute(execute) => {
+                    let bound = node.lock()?.bind_prepared(
+                        client_id,
+                        execute.get_id(),
+                        execute.get_values(),
+                    );
+                    match bound {
+                        Ok(query) => Some((
+                            query,
+                            execute.get_consistency().to_string(),
+                            execute.wants_timings(),
+                            None,
+                        )),
+                        Err(e) => {
+                            stream.write_all(
+                                &Frame::Error(e).to_bytes_with_options(&frame_options, None)?,
+                            )?;
                             stream.flush()?;
+                            None
                         }
-                        Request::Query(query) => {
-                            if !is_authenticated {
-                                let auth =
-                                    Frame::Authenticate(Authenticate::default()).to_bytes()?;
-                                stream.write(auth.as_slice())?;
-                                stream.flush()?;
-                                continue;
-                            }
-                            // Handle the query
-                            let query_str = query.get_query();
-                            let query_consistency_level: &str = &query.get_consistency();
-                            log.info(
-                                &format!(
-                                    "NATIVE: I RECEIVED {} whit CL: {} from CLIENT",
-                                    query_str.replace("\n", ""),
-                                    query_consistency_level,
-                                ),
-                                Color::Yellow,
-                                true,
-                            )?;
+                    }
+                }
+                // The next page of a result is sent from the rows kept for it, without running
+                // the query again
+                Request::Query(query) if query.params().paging_state().is_some() => {
+                    let params = query.params();
+                    let frame = params
+                        .paging_state()
+                        .and_then(|state| result_pages.next_page(state, params.page_size()))
+                        .unwrap_or_else(|| {
+                            Frame::Error(error::Error::Invalid(
+                                "The paging state is not one of a result of this connection"
+                                    .to_string(),
+                            ))
+                        });
+                    stream.write_all(&frame.to_bytes_with_options(&frame_options, None)?)?;
+                    stream.flush()?;
+                    None
+                }
+                Request::Query(query) => Some((
+                    query.get_query().to_string(),
+                    query.get_consistency().to_string(),
+                    query.wants_timings(),
+                    query.params().page_size(),
+                )),
+                // The statements of a batch are run as a single `BEGIN BATCH` query
+                Request::Batch(batch) => Some((
+                    batch.to_cql(),
+                    batch.get_consistency().to_string(),
+                    batch.wants_timings(),
+                    None,
+                )),
+            };
 
-                            let (tx_reply, rx_reply) = mpsc::channel();
+            // A drained or busy node answers new queries as overloaded, so drivers retry them on
+            // another node
+            let started = match statement {
+                Some(_) => node.lock()?.start_client_query(),
+                None => Ok(()),
+            };
+            let statement = match statement {
+                Some(statement) => match started {
+                    Ok(()) => Some(statement),
+                    Err(reason) => {
+                        let frame = Frame::Error(error::Error::Overloaded(reason));
+                        stream.write_all(&frame.to_bytes_with_options(&frame_options, None)?)?;
+                        stream.flush()?;
+                        None
+                    }
+                },
+                None => None,
+            };
 
-                            let result = Node::handle_query_execution(
-                                query_str,
-                                query_consistency_level,
-                                &node,
-                                connections.clone(),
-                                tx_reply,
-                                client_id,
-                            );
+            // Handle the query, either sent as is or bound to a prepared one
+            if let Some((query_str, query_consistency_level, wants_timings, page_size)) = statement
+            {
+                let query_str = query_str.as_str();
+                let query_consistency_level = query_consistency_level.as_str();
+                let query_log = log.with_correlation_id(&Self::new_correlation_id());
+                query_log.info(
+                    &format!(
+                        "NATIVE: I RECEIVED {} whit CL: {} from CLIENT",
+                        roles::loggable(query_str).replace("\n", ""),
+                        query_consistency_level,
+                    ),
+                    Color::Yellow,
+                    true,
+                )?;
 
-                            if let Err(e) = result {
-                                let frame = Frame::Error(error::Error::ServerError(e.to_string()));
+                let (tx_reply, rx_reply) = mpsc::channel();
+                let started = Instant::now();
 
-                                let frame_bytes_result = &frame.to_bytes();
-                                let mut frame_bytes = &vec![];
-                                if let Ok(value) = frame_bytes_result {
-                                    frame_bytes = value;
-                                }
-                                stream.write(&frame_bytes)?;
-                                stream.flush()?;
-                            } else {
-                                // await resolution of the query
-                                let reply = rx_reply.recv().map_err(|_| NodeError::OtherError)?;
-                                stream.write(&reply.to_bytes()?)?;
+                let result = Node::handle_query_execution(
+                    query_str,
+                    query_consistency_level,
+                    &node,
+                    connections.clone(),
+                    tx_reply,
+                    client_id,
+                    query_log.clone(),
+                );
+
+                match result {
+                    Err(e) => {
+                        node.lock()?.client_queries -= 1;
+                        let frame = match e {
+                            NodeError::Unauthorized(reason) => {
+                                Frame::Error(error::Error::Unauthorized(reason))
                             }
+                            NodeError::Invalid(reason) => {
+                                Frame::Error(error::Error::Invalid(reason))
+                            }
+                            NodeError::WriteTimeout(reason, details) => {
+                                Frame::Error(error::Error::WriteTimeout(reason, details))
+                            }
+                            NodeError::Unavailable(reason) => {
+                                Frame::Error(error::Error::UnavailableException(
+                                    reason,
+                                    error::UnavailableException,
+                                ))
+                            }
+                            e => Frame::Error(error::Error::ServerError(e.to_string())),
+                        };
+
+                        let frame_bytes_result = &frame.to_bytes_with_options(&frame_options, None);
+                        let mut frame_bytes = &vec![];
+                        if let Ok(value) = frame_bytes_result {
+                            frame_bytes = value;
                         }
-                    };
-                }
-                Err(_) => {
-                    // Another type of error
-                    return Err(NodeError::OtherError);
+                        stream.write_all(frame_bytes)?;
+                        stream.flush()?;
+                    }
+                    Ok((tracked, timings)) => {
+                        // await resolution of the query
+                        let reply = rx_reply.recv();
+                        node.lock()?.client_queries -= 1;
+                        let reply = reply.map_err(|_| NodeError::OtherError)?;
+                        let reply = result_pages.first_page(reply, page_size);
+
+                        // Only results carry the timings, errors are sent as they are
+                        let payload = match &reply {
+                            Frame::Result(_) if wants_timings => {
+                                let mut timings = timings.lock()?;
+                                timings.replica_wait =
+                                    started.elapsed().saturating_sub(timings.total());
+                                Some(timings.to_payload())
+                            }
+                            _ => None,
+                        };
+                        stream.write_all(
+                            &reply.to_bytes_with_options(&frame_options, payload.as_ref())?,
+                        )?;
+
+                        if let Some((keyspace, operation)) = tracked {
+                            Node::record_latency(
+                                &node,
+                                &keyspace,
+                                operation,
+                                started.elapsed(),
+                                &query_log,
+                            )?;
+                        }
+                    }
                 }
             }
         }
@@ -1167,59 +4786,67 @@ impl Node {
 
     fn handle_incoming_internode_messages(
         node: Arc<Mutex<Node>>,
-        stream: Arc<Mutex<TcpStream>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        mut stream: impl Read,
+        connections: ConnectionManager,
     ) -> Result<(), NodeError> {
-        // Clone the stream under Mutex protection and create the reader
-        let mut reader = {
-            let stream_guard = stream.lock()?;
-            BufReader::new(stream_guard.try_clone().map_err(NodeError::IoError)?)
-        };
+        // Messages written close together arrive in the same read, and long ones (or the ones
+        // split into TLS records) in several reads
+        let mut decoder = InternodeDecoder::new();
+        let mut buffer = vec![0u8; INTERNODE_READ_BUFFER_SIZE];
 
         let internode_protocol_handler = InternodeProtocolHandler::new();
+        let shutdown = node.lock()?.shutdown.clone();
 
         loop {
-            // Clean the buffer
-            let mut buffer = [0u8; 850000];
-
-            // Execute initial inserts if necessary
-
-            // Self::execute_querys(&node, connections.clone())?;
-
-            // Try to read a line
-            let bytes_read = reader.read(&mut buffer);
-            let result = InternodeMessage::from_bytes(&buffer);
-
-            let message;
-
-            match result {
-                Ok(value) => {
-                    message = value;
-                }
-                Err(_) => {
-                    //println!("error al procesar mensaje internodo");
-                    // println!("Error al crear los bytes: {:?}", e);
-
-                    continue;
-                }
-            }
-
-            match bytes_read {
+            match stream.read(&mut buffer) {
                 Ok(0) => {
-                    // Connection closed
+                    // Connection closed, maybe in the middle of a message
+                    if decoder.pending_len() > 0 {
+                        let log = node.lock()?.get_logger();
+                        log.with_component(Component::Internode).warn(
+                            &format!(
+                                "INTERNODE: CONNECTION CLOSED WITH {} BYTES OF A MESSAGE",
+                                decoder.pending_len()
+                            ),
+                            true,
+                        )?;
+                    }
                     break;
                 }
-                Ok(_) => {
-                    // Process the command with the protocol, passing the buffer and the necessary parameters
-                    let result = internode_protocol_handler.handle_command(
-                        &node,
-                        message.clone(),
-                        connections.clone(),
-                    );
+                Ok(read) => {
+                    // A connection compressed with an unknown algorithm is closed
+                    let messages = decoder
+                        .decode(&buffer[..read])
+                        .map_err(|_| NodeError::InternodeProtocolError)?;
+
+                    for message in messages {
+                        // Messages coming from a partitioned peer are silently dropped
+                        if node.lock()?.blocked_peers.contains(&message.from) {
+                            continue;
+                        }
 
-                    // If there's an error handling the command, exit the loop
-                    if let Err(e) = result {
-                        eprintln!("{:?} when other node sent me {:?}", e, message);
+                        // Process the command with the protocol, passing the buffer and the necessary parameters
+                        let result = internode_protocol_handler.handle_command(
+                            &node,
+                            message.clone(),
+                            connections.clone(),
+                        );
+
+                        // If there's an error handling the command, exit the loop
+                        if let Err(e) = result {
+                            eprintln!("{:?} when other node sent me {:?}", e, message);
+                            return Ok(());
+                        }
+                    }
+                }
+                // No message arrived before the timeout of the connection
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if shutdown.is_stopping() {
                         break;
                     }
                 }
@@ -1237,17 +4864,104 @@ impl Node {
         Utc::now().timestamp()
     }
 
+    // Generates the id used to follow a client query through the logs of every node
+    fn new_correlation_id() -> String {
+        Uuid::new_v4().simple().to_string()[..8].to_string()
+    }
+
+    // Records the latency of a query of a client, logging a warning if it makes the p99 of
+    // the keyspace go above its SLO
+    fn record_latency(
+        node: &Arc<Mutex<Node>>,
+        keyspace: &str,
+        operation: Operation,
+        latency: Duration,
+        logger: &Logger,
+    ) -> Result<(), NodeError> {
+        let breach = node.lock()?.metrics.record(keyspace, operation, latency);
+        if let Some(breach) = breach {
+            logger.warn(&format!("SLO BREACHED: {}", breach), true)?;
+        }
+        Ok(())
+    }
+
+    // Starts the execution of a query of a client, whose reply is sent through `tx_reply`.
+    // Returns the keyspace and operation of the queries that read or write rows, so the
+    // latency of their reply can be recorded, and the timings of the query, which are complete
+    // once the reply is sent but for the time waited for the replicas.
     fn handle_query_execution(
         query_str: &str,
         consistency_level: &str,
         node: &Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        connections: ConnectionManager,
         tx_reply: Sender<Frame>,
         client_id: i32,
-    ) -> Result<(), NodeError> {
+        logger: Logger,
+    ) -> Result<(Option<(String, Operation)>, SharedTimings), NodeError> {
+        let timings = SharedTimings::default();
+        let started = Instant::now();
+        let dry_run = dry_run::strip_dry_run(query_str);
         let query = QueryCreator::new()
-            .handle_query(query_str.to_string())
+            .handle_query(dry_run.unwrap_or(query_str).to_string())
             .map_err(NodeError::CQLError)?;
+        timings.lock()?.parse = started.elapsed();
+        let route_started = Instant::now();
+
+        Self::refresh_role(node, &connections, client_id, &logger)?;
+        {
+            let node_guard = node.lock()?;
+            node_guard.authorize(client_id, &query)?;
+            node_guard.check_replication(&query, &logger)?;
+        }
+
+        // A dry run is answered by this node from its schema, without applying the statement
+        if dry_run.is_some() {
+            let changes = {
+                let node_guard = node.lock()?;
+                let client_keyspace = node_guard
+                    .get_client_keyspace(client_id)?
+                    .map(|keyspace| keyspace.get_name());
+                dry_run::plan(
+                    &query,
+                    &node_guard.schema.keyspaces,
+                    client_keyspace.as_deref(),
+                )?
+            };
+            tx_reply
+                .send(dry_run::to_frame(changes))
+                .map_err(|_| NodeError::OtherError)?;
+            timings.lock()?.route = route_started.elapsed();
+            return Ok((None, timings));
+        }
+
+        // The users are rows of the `system_auth` tables, written by this node as one more client
+        if roles::is_role_statement(&query) {
+            Self::execute_role_statement(&query, node, &connections, client_id, &logger)?;
+            tx_reply
+                .send(Frame::Result(result_::Result::Void))
+                .map_err(|_| NodeError::OtherError)?;
+            timings.lock()?.route = route_started.elapsed();
+            return Ok((None, timings));
+        }
+
+        // Virtual tables are built by this node from its own state, without asking the replicas
+        if let Some(select) = system_tables::as_system_select(&query) {
+            let frame = {
+                let node_guard = node.lock()?;
+                let state = SystemState {
+                    table_metrics: &node_guard.table_metrics,
+                    hot_partitions: &node_guard.hot_partitions,
+                    schema_bootstrap: node_guard.schema_bootstrap.as_ref(),
+                    cluster_name: &node_guard.config.cluster_name,
+                    local: node_guard.topology_of(node_guard.ip, node_guard.schema.timestamp),
+                    peers: node_guard.peers_topology(),
+                };
+                system_tables::select(select, &state)?
+            };
+            tx_reply.send(frame).map_err(|_| NodeError::OtherError)?;
+            timings.lock()?.route = route_started.elapsed();
+            return Ok((None, timings));
+        }
 
         if query.needs_keyspace() {
             //println!("esta query: {:?} necesita un keyspace", query_str);
@@ -1259,10 +4973,29 @@ impl Node {
             check_table(node, &query, client_id, 6)?;
         }
 
+        // Conditional writes run a Paxos round on the replicas instead of being an open query
+        if query_execution::lwt::is_lightweight_transaction(&query) {
+            let keyspace = {
+                let guard_node = node.lock()?;
+                match query.get_used_keyspace() {
+                    Some(keyspace_name) => guard_node.get_keyspace(&keyspace_name)?,
+                    None => guard_node.get_client_keyspace(client_id)?,
+                }
+                .ok_or(NodeError::CQLError(CQLError::NoActualKeyspaceError))?
+            };
+            let frame = QueryExecution::new(node.clone(), connections)?
+                .with_logger(logger)
+                .execute_lightweight_transaction(&query, &keyspace)?;
+            tx_reply.send(frame).map_err(|_| NodeError::OtherError)?;
+            timings.lock()?.route = route_started.elapsed();
+            let tracked = Operation::of(&query).map(|operation| (keyspace.get_name(), operation));
+            return Ok((tracked, timings));
+        }
+
         let open_query_id;
         let self_ip: Ipv4Addr;
-        let storage_path;
-        let logger;
+        let storage_engine;
+        let tracked;
         {
             let mut guard_node = node.lock()?;
             let keyspace;
@@ -1280,6 +5013,11 @@ impl Node {
                     .and_then(|k| guard_node.get_table(table_name, k).ok())
             });
 
+            tracked = keyspace
+                .as_ref()
+                .zip(Operation::of(&query))
+                .map(|(keyspace, operation)| (keyspace.get_name(), operation));
+
             // Agregar la consulta abierta
             open_query_id = guard_node.add_open_query(
                 query.clone(),
@@ -1287,15 +5025,24 @@ impl Node {
                 tx_reply,
                 table,
                 keyspace,
+                logger.correlation_id().unwrap_or_default(),
             )?;
+            guard_node
+                .get_open_handle_query()
+                .set_timings(open_query_id, timings.clone());
+            if guard_node.repairing_clients.contains(&client_id) {
+                guard_node
+                    .get_open_handle_query()
+                    .force_repair(open_query_id);
+            }
             self_ip = guard_node.get_ip();
-            storage_path = guard_node.storage_path.clone();
-            logger = guard_node.get_logger();
+            storage_engine = guard_node.storage_engine.clone();
         }
         let timestamp = Self::current_timestamp();
 
-        let response =
-            QueryExecution::new(node.clone(), connections.clone(), storage_path.clone())?.execute(
+        let response = QueryExecution::new(node.clone(), connections.clone())?
+            .with_logger(logger.clone())
+            .execute(
                 query.clone(),
                 false,
                 false,
@@ -1303,6 +5050,7 @@ impl Node {
                 client_id,
                 Some(timestamp),
             )?;
+        timings.lock()?.route = route_started.elapsed();
 
         if let Some(((finished_responses, failed_nodes), content)) = response {
             let mut guard_node = node.lock()?;
@@ -1334,6 +5082,7 @@ impl Node {
             };
 
             let partitioner = guard_node.get_partitioner();
+            let internode_port = guard_node.config.internode_port;
             let query_handler = guard_node.get_open_handle_query();
 
             for _ in 0..finished_responses {
@@ -1349,7 +5098,7 @@ impl Node {
                 InternodeProtocolHandler::add_ok_response_to_open_query_and_send_response_if_closed(
                     query_handler,
                     // TODO: convertir el content al content de la response
-                    &InternodeResponse::new(open_query_id as u32, InternodeResponseStatus::Ok, Some(InternodeResponseContent{
+                    InternodeResponse::new(open_query_id as u32, InternodeResponseStatus::Ok, Some(InternodeResponseContent{
                         columns: complete_columns,
                         select_columns:  select_columns,
                         values: values,
@@ -1359,10 +5108,11 @@ impl Node {
                     table.clone(),
                     columns.clone(),
                     self_ip,
+                    internode_port,
                     self_ip,
                     connections.clone(),
                     partitioner.clone(),
-                    storage_path.clone(),
+                    storage_engine.clone(),
                     logger.clone(),
                 )?;
             }
@@ -1370,11 +5120,11 @@ impl Node {
                 InternodeProtocolHandler::add_error_response_to_open_query_and_send_response_if_closed(
                     query_handler,
                     open_query_id,
-
+                    None,
                 )?;
             }
         }
 
-        Ok(())
+        Ok((tracked, timings))
     }
 }
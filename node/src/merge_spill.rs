@@ -0,0 +1,432 @@
+//! Bounded memory for the merge of the answers of the replicas to big reads.
+//!
+//! The coordinator of a `SELECT` keeps the rows every replica answered with until it can merge them
+//! with read repair, so a scan over a big table holds one copy of it per replica. Once the rows
+//! buffered by an open query go over `max_buffered_bytes`, the answers that follow are sorted by
+//! primary key and spilled to a run file under the storage path. The answers are then merged in key
+//! order, a batch of keys at a time, so the read repair never holds much more than
+//! `max_buffered_bytes` of them at once.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use query_creator::clauses::types::column::Column;
+
+use crate::internode_protocol::response::{
+    InternodeResponse, InternodeResponseContent, InternodeResponseStatus,
+};
+
+/// Rows an open query buffers by default before spilling the answers that follow.
+pub const DEFAULT_MAX_MERGE_BUFFER_BYTES: usize = 64 * 1024 * 1024;
+
+/// Numbers the run files of the process, so the runs of different queries never clash.
+static NEXT_RUN: AtomicU64 = AtomicU64::new(0);
+
+/// How many bytes of rows an open query buffers before spilling the answers that follow to
+/// `spill_dir`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeMemoryLimit {
+    pub max_buffered_bytes: usize,
+    pub spill_dir: PathBuf,
+}
+
+impl MergeMemoryLimit {
+    /// The default limit, spilling to `spill_dir`.
+    pub fn new(spill_dir: PathBuf) -> Self {
+        Self {
+            max_buffered_bytes: DEFAULT_MAX_MERGE_BUFFER_BYTES,
+            spill_dir,
+        }
+    }
+}
+
+/// Removes the runs left in `spill_dir` by a node that stopped in the middle of a merge.
+pub fn remove_leftover_runs(spill_dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(spill_dir) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The rows of one answer, spilled to a file sorted by key. The file is removed when the run is
+/// dropped, however the query ends.
+#[derive(Debug)]
+struct SpilledRun {
+    from: Ipv4Addr,
+    path: PathBuf,
+}
+
+impl Drop for SpilledRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The accounting of the rows buffered by an open query, and the runs it spilled.
+#[derive(Debug, Default)]
+pub struct MergeBuffer {
+    limit: Option<MergeMemoryLimit>,
+    /// Positions of the partition key and clustering columns, which identify a row.
+    key_indices: Vec<usize>,
+    buffered_bytes: usize,
+    runs: Vec<SpilledRun>,
+}
+
+impl MergeBuffer {
+    /// A buffer for the answers of a read of a table with `columns`. Without a `limit`, every
+    /// answer is kept in memory.
+    pub fn new(limit: Option<MergeMemoryLimit>, columns: &[Column]) -> Self {
+        let indices = |is_key: fn(&Column) -> bool| {
+            columns
+                .iter()
+                .enumerate()
+                .filter(move |(_, column)| is_key(column))
+                .map(|(index, _)| index)
+        };
+        let key_indices = indices(|column| column.is_partition_key)
+            .chain(indices(|column| column.is_clustering_column))
+            .collect();
+        Self {
+            limit,
+            key_indices,
+            buffered_bytes: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Whether some answers were spilled, so they must be merged with `merge_batches`.
+    pub fn has_spilled(&self) -> bool {
+        !self.runs.is_empty()
+    }
+
+    /// Accounts the rows of an answer from `from`, spilling them (and leaving `content` without
+    /// values) if they do not fit under the limit. If the run can not be written, the rows are
+    /// kept in memory.
+    pub fn add(&mut self, from: Ipv4Addr, content: &mut InternodeResponseContent) {
+        let bytes = rows_bytes(&content.values);
+        if let Some(limit) = &self.limit {
+            if !content.values.is_empty()
+                && self.buffered_bytes + bytes > limit.max_buffered_bytes
+                && self.spill(from, content).is_ok()
+            {
+                return;
+            }
+        }
+        self.buffered_bytes += bytes;
+    }
+
+    fn spill(&mut self, from: Ipv4Addr, content: &mut InternodeResponseContent) -> io::Result<()> {
+        let Some(limit) = &self.limit else {
+            return Ok(());
+        };
+        fs::create_dir_all(&limit.spill_dir)?;
+        let path = limit.spill_dir.join(format!(
+            "merge_{}.run",
+            NEXT_RUN.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut rows = std::mem::take(&mut content.values);
+        rows.sort_by(|a, b| key(a, &self.key_indices).cmp(&key(b, &self.key_indices)));
+        let written = write_run(&path, &rows);
+        if written.is_err() {
+            let _ = fs::remove_file(&path);
+            content.values = rows;
+            return written;
+        }
+        self.runs.push(SpilledRun { from, path });
+        Ok(())
+    }
+
+    /// Returns the rows of the answers (the ones in memory, from `responses`, and the spilled
+    /// ones) in batches of answers whose rows have disjoint keys, sorted by key. Every version of
+    /// a row, from every replica, is in the same batch, so each batch can be read repaired on its
+    /// own. Batches are cut once they hold `max_buffered_bytes` of rows.
+    pub fn merge_batches(
+        &self,
+        responses: Vec<(Ipv4Addr, InternodeResponse)>,
+    ) -> io::Result<MergeBatches> {
+        let mut sources = Vec::new();
+        for (from, response) in responses {
+            if let Some(content) = response.content {
+                let mut rows = content.values;
+                rows.sort_by(|a, b| key(a, &self.key_indices).cmp(&key(b, &self.key_indices)));
+                sources.push(RunSource::memory(from, rows));
+            }
+        }
+        for run in &self.runs {
+            sources.push(RunSource::file(run.from, File::open(&run.path)?)?);
+        }
+
+        Ok(MergeBatches {
+            sources,
+            key_indices: self.key_indices.clone(),
+            max_batch_bytes: self
+                .limit
+                .as_ref()
+                .map_or(usize::MAX, |limit| limit.max_buffered_bytes),
+        })
+    }
+}
+
+/// Iterator over the batches of `MergeBuffer::merge_batches`.
+pub struct MergeBatches {
+    sources: Vec<RunSource>,
+    key_indices: Vec<usize>,
+    max_batch_bytes: usize,
+}
+
+impl Iterator for MergeBatches {
+    type Item = io::Result<Vec<(Ipv4Addr, InternodeResponse)>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch: Vec<Vec<Vec<String>>> = vec![Vec::new(); self.sources.len()];
+        let mut batch_bytes = 0;
+        let mut empty = true;
+
+        while batch_bytes < self.max_batch_bytes {
+            // The smallest key among the next rows of every source
+            let Some(smallest) = self
+                .sources
+                .iter()
+                .filter_map(|source| source.head.as_ref())
+                .map(|row| key(row, &self.key_indices))
+                .min()
+                .map(|key| key.into_iter().map(str::to_string).collect::<Vec<_>>())
+            else {
+                break;
+            };
+
+            for (source, rows) in self.sources.iter_mut().zip(batch.iter_mut()) {
+                while source
+                    .head
+                    .as_ref()
+                    .is_some_and(|row| key(row, &self.key_indices) == smallest)
+                {
+                    let advanced = match source.advance() {
+                        Ok(row) => row,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    if let Some(row) = advanced {
+                        batch_bytes += row_bytes(&row);
+                        rows.push(row);
+                        empty = false;
+                    }
+                }
+            }
+        }
+
+        if empty {
+            return None;
+        }
+        Some(Ok(self
+            .sources
+            .iter()
+            .zip(batch)
+            .filter(|(_, rows)| !rows.is_empty())
+            .map(|(source, rows)| {
+                let content = InternodeResponseContent {
+                    columns: vec![],
+                    select_columns: vec![],
+                    values: rows,
+                };
+                (
+                    source.from,
+                    InternodeResponse::new(0, InternodeResponseStatus::Ok, Some(content)),
+                )
+            })
+            .collect()))
+    }
+}
+
+/// The rows of one answer, read in key order, with the next one at hand.
+struct RunSource {
+    from: Ipv4Addr,
+    head: Option<Vec<String>>,
+    rows: RunRows,
+}
+
+enum RunRows {
+    Memory(std::vec::IntoIter<Vec<String>>),
+    File(BufReader<File>),
+}
+
+impl RunSource {
+    fn memory(from: Ipv4Addr, rows: Vec<Vec<String>>) -> Self {
+        let mut rows = rows.into_iter();
+        Self {
+            from,
+            head: rows.next(),
+            rows: RunRows::Memory(rows),
+        }
+    }
+
+    fn file(from: Ipv4Addr, file: File) -> io::Result<Self> {
+        let mut reader = BufReader::new(file);
+        Ok(Self {
+            from,
+            head: read_row(&mut reader)?,
+            rows: RunRows::File(reader),
+        })
+    }
+
+    /// Returns the next row, reading the one after it.
+    fn advance(&mut self) -> io::Result<Option<Vec<String>>> {
+        let next = match &mut self.rows {
+            RunRows::Memory(rows) => rows.next(),
+            RunRows::File(reader) => read_row(reader)?,
+        };
+        Ok(std::mem::replace(&mut self.head, next))
+    }
+}
+
+/// The values that identify a row, in the order rows are sorted by in the runs.
+fn key<'a>(row: &'a [String], key_indices: &[usize]) -> Vec<&'a str> {
+    key_indices
+        .iter()
+        .map(|&index| row.get(index).map_or("", String::as_str))
+        .collect()
+}
+
+fn row_bytes(row: &[String]) -> usize {
+    row.iter().map(String::len).sum()
+}
+
+fn rows_bytes(rows: &[Vec<String>]) -> usize {
+    rows.iter().map(|row| row_bytes(row)).sum()
+}
+
+/// Writes the rows of a run: each one as its amount of values, followed by each value as its
+/// length and its bytes, all lengths as 4 bytes big endian integers.
+fn write_run(path: &Path, rows: &[Vec<String>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for row in rows {
+        writer.write_all(&(row.len() as u32).to_be_bytes())?;
+        for value in row {
+            writer.write_all(&(value.len() as u32).to_be_bytes())?;
+            writer.write_all(value.as_bytes())?;
+        }
+    }
+    writer.flush()
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<Option<u32>> {
+    let mut bytes = [0u8; 4];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(u32::from_be_bytes(bytes))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the next row of a run, or `None` at its end.
+fn read_row(reader: &mut impl Read) -> io::Result<Option<Vec<String>>> {
+    let Some(values) = read_u32(reader)? else {
+        return Ok(None);
+    };
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "truncated merge run");
+    (0..values)
+        .map(|_| {
+            let length = read_u32(reader)?.ok_or_else(truncated)?;
+            let mut value = vec![0u8; length as usize];
+            reader.read_exact(&mut value)?;
+            String::from_utf8(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect::<io::Result<Vec<String>>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::types::column::Column;
+    use query_creator::clauses::types::datatype::DataType;
+    use uuid::Uuid;
+
+    fn row(id: &str, timestamp: &str) -> Vec<String> {
+        vec![id.to_string(), "EZE".to_string(), timestamp.to_string()]
+    }
+
+    fn content(rows: Vec<Vec<String>>) -> InternodeResponseContent {
+        InternodeResponseContent {
+            columns: vec![],
+            select_columns: vec![],
+            values: rows,
+        }
+    }
+
+    #[test]
+    fn test_answers_over_the_limit_are_spilled_and_merged_by_key() {
+        let spill_dir = PathBuf::from(format!("/tmp/merge_spill_test_{}", Uuid::new_v4()));
+        let mut id = Column::new("id", DataType::String, true, false);
+        id.is_partition_key = true;
+        let columns = vec![id, Column::new("airport", DataType::String, false, true)];
+        let limit = MergeMemoryLimit {
+            max_buffered_bytes: 25,
+            spill_dir: spill_dir.clone(),
+        };
+        let mut buffer = MergeBuffer::new(Some(limit), &columns);
+        let ips: Vec<Ipv4Addr> = (1..=3).map(|i| Ipv4Addr::new(127, 0, 0, i)).collect();
+
+        // The first answer fits, the other two are spilled
+        let mut responses = vec![];
+        for (ip, rows) in ips.iter().zip([
+            vec![row("AR2", "10"), row("AR1", "10")],
+            vec![row("AR3", "11"), row("AR1", "12"), row("AR2", "12")],
+            vec![row("AR2", "9"), row("AR4", "9")],
+        ]) {
+            let mut content = content(rows);
+            buffer.add(*ip, &mut content);
+            responses.push((
+                *ip,
+                InternodeResponse::new(0, InternodeResponseStatus::Ok, Some(content)),
+            ));
+        }
+        assert!(buffer.has_spilled());
+        assert_eq!(buffer.buffered_bytes, 16);
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 2);
+
+        let batches: Vec<_> = buffer
+            .merge_batches(responses)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        // Every version of a row is in the same batch, and batches follow the order of the keys
+        let keys: Vec<Vec<(Ipv4Addr, String)>> = batches
+            .iter()
+            .map(|batch| {
+                let mut keys: Vec<_> = batch
+                    .iter()
+                    .flat_map(|(ip, response)| {
+                        let values = &response.content.as_ref().unwrap().values;
+                        values.iter().map(move |row| (*ip, row[0].clone()))
+                    })
+                    .collect();
+                keys.sort();
+                keys
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec![
+                    (ips[0], "AR1".to_string()),
+                    (ips[0], "AR2".to_string()),
+                    (ips[1], "AR1".to_string()),
+                    (ips[1], "AR2".to_string()),
+                    (ips[2], "AR2".to_string()),
+                ],
+                vec![(ips[1], "AR3".to_string()), (ips[2], "AR4".to_string())],
+            ]
+        );
+
+        // The runs are removed with the query
+        drop(buffer);
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 0);
+        fs::remove_dir_all(spill_dir).unwrap();
+    }
+}
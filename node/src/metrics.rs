@@ -0,0 +1,728 @@
+//! Latency metrics of the queries coordinated by a node, with per keyspace SLOs (service level
+//! objectives) on the p99 latency of reads and writes.
+//!
+//! Operators set the SLOs through the `SLO` admin command and read the metrics through the
+//! `METRICS` one. The node logs a warning every time the p99 of a keyspace goes above its SLO.
+//!
+//! The metrics also hold the latest estimate of the droppable data of every table stored by the
+//! node, refreshed in the background, so operators know which tables need a compaction.
+//!
+//! Coordinators also count, by replica, the responses executed with an older schema or a
+//! different ring than theirs, which tell which replicas lag behind after schema or ring changes.
+//!
+//! The writes a coordinator sends are also counted by partition, in a heavy-hitters sketch per
+//! table, so the partitions that take most of the writes of a table (and overload its replicas)
+//! can be found.
+//!
+//! Read repairs are counted by table too: the reads that found replicas out of date, the ones
+//! that skipped repairing them by their table's `read_repair_chance`, and the rows written back
+//! to the replicas in the background.
+//!
+//! Clients can also ask for the timings of a single query, which the coordinator records while
+//! resolving it and sends back in the custom payload of the result.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use driver::timings::QueryTimings;
+use query_creator::Query;
+
+use crate::errors::NodeError;
+use crate::storage_engine::table_stats::TableStats;
+
+/// Number of latest latencies over which the p99 is computed.
+const ROLLING_WINDOW: usize = 200;
+/// Latencies needed in the window before its p99 is compared against the SLO.
+const MIN_SAMPLES: usize = 20;
+/// Minimum time between two pushes of the schema and the ring to the same lagging replica.
+const LAG_PUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Partitions counted by the heavy-hitters sketch of each table.
+const HOT_PARTITION_COUNTERS: usize = 64;
+/// Hottest partitions of each table shown in the metrics and `system.hot_partitions`.
+pub const HOT_PARTITIONS_SHOWN: usize = 10;
+
+/// Timings of a query, filled by the thread that answers the client and the one that merges the
+/// answers of the replicas.
+pub type SharedTimings = Arc<Mutex<QueryTimings>>;
+
+/// Read repair counters of every table, shared by the threads that merge the answers of the
+/// replicas and the ones that write the repairs in the background.
+pub type SharedReadRepairMetrics = Arc<Mutex<ReadRepairMetrics>>;
+
+/// Kind of data operation whose latency is tracked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+impl Operation {
+    /// Returns the operation done by `query`, or `None` if it does not read or write rows.
+    pub fn of(query: &Query) -> Option<Operation> {
+        match query {
+            Query::Select(_) => Some(Operation::Read),
+            Query::Insert(_) | Query::Update(_) | Query::Delete(_) | Query::Batch(_) => {
+                Some(Operation::Write)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = NodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "READ" => Ok(Operation::Read),
+            "WRITE" => Ok(Operation::Write),
+            _ => Err(NodeError::OtherError),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Read => write!(f, "read"),
+            Operation::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// The p99 latency of an operation in a keyspace went above its SLO.
+#[derive(Debug, PartialEq)]
+pub struct SloBreach {
+    pub keyspace: String,
+    pub operation: Operation,
+    pub p99: Duration,
+    pub slo: Duration,
+}
+
+impl fmt::Display for SloBreach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p99 {} latency of keyspace {} is {} ms, above its SLO of {} ms",
+            self.operation,
+            self.keyspace,
+            self.p99.as_millis(),
+            self.slo.as_millis()
+        )
+    }
+}
+
+/// Latest latencies of an operation in a keyspace.
+#[derive(Debug, Default)]
+struct LatencyWindow {
+    latencies: VecDeque<Duration>,
+    slo: Option<Duration>,
+    /// Whether the p99 was above the SLO after the last latency recorded.
+    breached: bool,
+    /// Times the p99 went above the SLO.
+    breaches: u64,
+    /// Operations that were not answered by the replicas before their timeout.
+    timeouts: u64,
+}
+
+impl LatencyWindow {
+    fn record(&mut self, latency: Duration) {
+        if self.latencies.len() >= ROLLING_WINDOW {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+
+    /// Returns the latency below which 99 percent of the latencies in the window fall.
+    fn p99(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut sorted: Vec<Duration> = self.latencies.iter().copied().collect();
+        sorted.sort();
+
+        let rank = (0.99 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    /// Updates the breached state with the current p99, returning it if it just went above
+    /// the SLO.
+    fn check_slo(&mut self) -> Option<(Duration, Duration)> {
+        let slo = self.slo?;
+        if self.latencies.len() < MIN_SAMPLES {
+            return None;
+        }
+
+        let p99 = self.p99();
+        let was_breached = self.breached;
+        self.breached = p99 > slo;
+        if self.breached && !was_breached {
+            self.breaches += 1;
+            return Some((p99, slo));
+        }
+        None
+    }
+}
+
+/// Rolling latencies of the reads and writes coordinated by a node, by keyspace.
+#[derive(Debug, Default)]
+pub struct LatencyMetrics {
+    windows: BTreeMap<(String, Operation), LatencyWindow>,
+}
+
+impl LatencyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the SLO on the p99 latency of `operation` in `keyspace`.
+    pub fn set_slo(&mut self, keyspace: &str, operation: Operation, slo: Duration) {
+        let window = self
+            .windows
+            .entry((keyspace.to_string(), operation))
+            .or_default();
+        window.slo = Some(slo);
+        window.breached = false;
+    }
+
+    /// Records the latency of an `operation` in `keyspace`.
+    ///
+    /// Returns the breach if this latency made the p99 go above the SLO. While it stays above,
+    /// later latencies return `None`, so every breach is reported once.
+    pub fn record(
+        &mut self,
+        keyspace: &str,
+        operation: Operation,
+        latency: Duration,
+    ) -> Option<SloBreach> {
+        let window = self
+            .windows
+            .entry((keyspace.to_string(), operation))
+            .or_default();
+        window.record(latency);
+
+        window.check_slo().map(|(p99, slo)| SloBreach {
+            keyspace: keyspace.to_string(),
+            operation,
+            p99,
+            slo,
+        })
+    }
+
+    /// Returns the p99 latency of `operation` in `keyspace`, once it has enough samples to be
+    /// meaningful.
+    pub fn p99(&self, keyspace: &str, operation: Operation) -> Option<Duration> {
+        self.windows
+            .get(&(keyspace.to_string(), operation))
+            .filter(|window| window.latencies.len() >= MIN_SAMPLES)
+            .map(LatencyWindow::p99)
+    }
+
+    /// Records that an `operation` in `keyspace` timed out waiting for the replicas.
+    pub fn record_timeout(&mut self, keyspace: &str, operation: Operation) {
+        self.windows
+            .entry((keyspace.to_string(), operation))
+            .or_default()
+            .timeouts += 1;
+    }
+
+    /// Returns one line per keyspace and operation with its samples, p99, SLO, breaches and
+    /// timeouts.
+    pub fn report(&self) -> Vec<String> {
+        self.windows
+            .iter()
+            .map(|((keyspace, operation), window)| {
+                format!(
+                    "{} {} samples={} p99_ms={} slo_ms={} breaches={} timeouts={}",
+                    keyspace,
+                    operation,
+                    window.latencies.len(),
+                    window.p99().as_millis(),
+                    window
+                        .slo
+                        .map_or_else(|| "-".to_string(), |slo| slo.as_millis().to_string()),
+                    window.breaches,
+                    window.timeouts
+                )
+            })
+            .collect()
+    }
+}
+
+/// Latest droppable data statistics of the tables stored by a node, by keyspace and table.
+#[derive(Debug, Default)]
+pub struct TableMetrics {
+    tables: BTreeMap<(String, String), TableStats>,
+}
+
+impl TableMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the statistics with the ones of a new scan of every table.
+    ///
+    /// Returns the tables that need a compaction and did not in the previous scan, so every
+    /// table is reported once until it is compacted.
+    pub fn refresh(
+        &mut self,
+        tables: BTreeMap<(String, String), TableStats>,
+    ) -> Vec<(String, String)> {
+        let newly_needing_compaction = tables
+            .iter()
+            .filter(|(key, stats)| {
+                stats.needs_compaction()
+                    && !self
+                        .tables
+                        .get(*key)
+                        .is_some_and(|previous| previous.needs_compaction())
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        self.tables = tables;
+        newly_needing_compaction
+    }
+
+    /// Returns the statistics of every table, sorted by keyspace and table.
+    pub fn tables(&self) -> impl Iterator<Item = (&(String, String), &TableStats)> {
+        self.tables.iter()
+    }
+
+    /// Returns one line per table with its rows, droppable data and whether it needs a compaction.
+    pub fn report(&self) -> Vec<String> {
+        self.tables
+            .iter()
+            .map(|((keyspace, table), stats)| {
+                format!(
+                    "{}.{} rows={} expired_rows={} tombstone_cells={} droppable_ratio={:.2} needs_compaction={}",
+                    keyspace,
+                    table,
+                    stats.rows,
+                    stats.expired_rows,
+                    stats.tombstone_cells,
+                    stats.droppable_ratio(),
+                    stats.needs_compaction()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Stale responses of a replica, and the pushes of the schema and the ring sent to it.
+#[derive(Debug, Default)]
+struct ReplicaLag {
+    stale_schema: u64,
+    stale_ring: u64,
+    pushes: u64,
+    last_push: Option<Instant>,
+}
+
+/// Responses received by a coordinator that were executed with an older schema, or a different
+/// ring, than the ones of the coordinator, by replica.
+#[derive(Debug, Default)]
+pub struct ReplicaLagMetrics {
+    replicas: BTreeMap<Ipv4Addr, ReplicaLag>,
+}
+
+impl ReplicaLagMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a response of `replica` executed with an older schema (`stale_schema`) or a
+    /// different ring (`stale_ring`) than the ones of the coordinator.
+    ///
+    /// Returns whether the coordinator should push its schema and ring to the replica: a response
+    /// was stale and none was pushed to it in the last `LAG_PUSH_INTERVAL`, so a burst of stale
+    /// responses pushes them once.
+    pub fn record(
+        &mut self,
+        replica: Ipv4Addr,
+        stale_schema: bool,
+        stale_ring: bool,
+        now: Instant,
+    ) -> bool {
+        if !stale_schema && !stale_ring {
+            return false;
+        }
+        let lag = self.replicas.entry(replica).or_default();
+        lag.stale_schema += stale_schema as u64;
+        lag.stale_ring += stale_ring as u64;
+
+        let push = lag
+            .last_push
+            .is_none_or(|last_push| now.duration_since(last_push) >= LAG_PUSH_INTERVAL);
+        if push {
+            lag.pushes += 1;
+            lag.last_push = Some(now);
+        }
+        push
+    }
+
+    /// Returns one line per lagging replica with its stale responses and the pushes sent to it.
+    pub fn report(&self) -> Vec<String> {
+        self.replicas
+            .iter()
+            .map(|(replica, lag)| {
+                format!(
+                    "replica {} stale_schema={} stale_ring={} pushes={}",
+                    replica, lag.stale_schema, lag.stale_ring, lag.pushes
+                )
+            })
+            .collect()
+    }
+}
+
+/// A partition among the hottest of its table, with the writes counted for it.
+///
+/// ### Fields
+/// - `partition`: The values of the partition key of the partition, joined.
+/// - `writes`: The writes counted for the partition, which may be over its real writes by up to
+///   `error`.
+/// - `error`: The writes counted for the partition it replaced in the sketch, which it may not have had.
+/// - `share`: The part of the writes of the table counted for the partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotPartition {
+    pub partition: String,
+    pub writes: u64,
+    pub error: u64,
+    pub share: f64,
+}
+
+/// Space-Saving sketch of the writes of the partitions of a table: it counts up to
+/// `HOT_PARTITION_COUNTERS` partitions, and a new partition replaces the least written one, taking
+/// its count as its error. Every partition with more than `1 / HOT_PARTITION_COUNTERS` of the
+/// writes is kept.
+#[derive(Debug, Default)]
+struct PartitionSketch {
+    counters: HashMap<String, (u64, u64)>,
+    writes: u64,
+}
+
+impl PartitionSketch {
+    fn record(&mut self, partition: &str) {
+        self.writes += 1;
+        if let Some((count, _)) = self.counters.get_mut(partition) {
+            *count += 1;
+            return;
+        }
+        let mut error = 0;
+        if self.counters.len() >= HOT_PARTITION_COUNTERS {
+            let coldest = self
+                .counters
+                .iter()
+                .min_by_key(|(partition, (count, _))| (*count, *partition))
+                .map(|(partition, (count, _))| (partition.clone(), *count));
+            if let Some((coldest, count)) = coldest {
+                self.counters.remove(&coldest);
+                error = count;
+            }
+        }
+        self.counters
+            .insert(partition.to_string(), (error + 1, error));
+    }
+
+    fn hottest(&self, n: usize) -> Vec<HotPartition> {
+        let mut hottest: Vec<HotPartition> = self
+            .counters
+            .iter()
+            .map(|(partition, (writes, error))| HotPartition {
+                partition: partition.clone(),
+                writes: *writes,
+                error: *error,
+                share: *writes as f64 / self.writes as f64,
+            })
+            .collect();
+        hottest.sort_by(|a, b| b.writes.cmp(&a.writes).then(a.partition.cmp(&b.partition)));
+        hottest.truncate(n);
+        hottest
+    }
+}
+
+/// Writes sent by a coordinator to the partitions of each table, kept in a heavy-hitters sketch
+/// per table so the hottest partitions are known without counting every partition.
+#[derive(Debug, Default)]
+pub struct HotPartitionMetrics {
+    tables: BTreeMap<(String, String), PartitionSketch>,
+}
+
+impl HotPartitionMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a write to `partition`, the joined values of the partition key of a row of
+    /// `keyspace.table`.
+    pub fn record(&mut self, keyspace: &str, table: &str, partition: &str) {
+        self.tables
+            .entry((keyspace.to_string(), table.to_string()))
+            .or_default()
+            .record(partition);
+    }
+
+    /// Returns the `HOT_PARTITIONS_SHOWN` hottest partitions of every table written, sorted by
+    /// keyspace and table, the hottest first.
+    pub fn tables(&self) -> impl Iterator<Item = (&(String, String), Vec<HotPartition>)> {
+        self.tables
+            .iter()
+            .map(|(key, sketch)| (key, sketch.hottest(HOT_PARTITIONS_SHOWN)))
+    }
+
+    /// Returns one line per hot partition of every table with its writes and share of the table.
+    pub fn report(&self) -> Vec<String> {
+        self.tables()
+            .flat_map(|((keyspace, table), hottest)| {
+                hottest.into_iter().map(move |hot| {
+                    format!(
+                        "{}.{} partition {} writes={} error={} share={:.2}",
+                        keyspace, table, hot.partition, hot.writes, hot.error, hot.share
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Read repairs of a table.
+///
+/// ### Fields
+/// - `mismatches`: Reads that found replicas with out of date rows.
+/// - `skipped`: Reads among the `mismatches` that did not repair the replicas, by the
+///   `read_repair_chance` of the table.
+/// - `repaired_rows`: Rows written to the replicas that were out of date.
+/// - `failed_rows`: Rows that could not be written to their replica.
+/// - `dropped_rows`: Rows that were not written as every read repair worker was busy, or as the
+///   read found more than `MAX_READ_REPAIR_ROWS` of them.
+#[derive(Debug, Default)]
+struct ReadRepairCounts {
+    mismatches: u64,
+    skipped: u64,
+    repaired_rows: u64,
+    failed_rows: u64,
+    dropped_rows: u64,
+}
+
+/// Read repairs of the reads coordinated by a node, by keyspace and table.
+#[derive(Debug, Default)]
+pub struct ReadRepairMetrics {
+    tables: BTreeMap<(String, String), ReadRepairCounts>,
+}
+
+impl ReadRepairMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a read of `keyspace.table` that found replicas out of date, and whether it
+    /// repairs them.
+    pub fn record_mismatch(&mut self, keyspace: &str, table: &str, repairs: bool) {
+        let counts = self.counts_mut(keyspace, table);
+        counts.mismatches += 1;
+        counts.skipped += !repairs as u64;
+    }
+
+    /// Records the rows of `keyspace.table` a read repair wrote to the replicas, and the ones it
+    /// failed to.
+    pub fn record_rows(&mut self, keyspace: &str, table: &str, repaired: u64, failed: u64) {
+        let counts = self.counts_mut(keyspace, table);
+        counts.repaired_rows += repaired;
+        counts.failed_rows += failed;
+    }
+
+    /// Records the rows of `keyspace.table` a read repair did not write, without trying to.
+    pub fn record_dropped(&mut self, keyspace: &str, table: &str, dropped: u64) {
+        self.counts_mut(keyspace, table).dropped_rows += dropped;
+    }
+
+    fn counts_mut(&mut self, keyspace: &str, table: &str) -> &mut ReadRepairCounts {
+        self.tables
+            .entry((keyspace.to_string(), table.to_string()))
+            .or_default()
+    }
+
+    /// Returns one line per table read with out of date replicas, with its read repairs.
+    pub fn report(&self) -> Vec<String> {
+        self.tables
+            .iter()
+            .map(|((keyspace, table), counts)| {
+                format!(
+                    "{}.{} read_repair mismatches={} skipped={} repaired_rows={} failed_rows={} \
+                     dropped_rows={}",
+                    keyspace,
+                    table,
+                    counts.mismatches,
+                    counts.skipped,
+                    counts.repaired_rows,
+                    counts.failed_rows,
+                    counts.dropped_rows
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_many(metrics: &mut LatencyMetrics, millis: u64, times: usize) -> Vec<SloBreach> {
+        (0..times)
+            .filter_map(|_| metrics.record("sky", Operation::Read, Duration::from_millis(millis)))
+            .collect()
+    }
+
+    #[test]
+    fn breaches_are_reported_once_until_the_p99_recovers() {
+        let mut metrics = LatencyMetrics::new();
+        metrics.set_slo("sky", Operation::Read, Duration::from_millis(50));
+
+        assert!(record_many(&mut metrics, 10, MIN_SAMPLES).is_empty());
+
+        // With 21 latencies, the p99 is the slowest one
+        let breaches = record_many(&mut metrics, 80, 5);
+        assert_eq!(breaches.len(), 1);
+        assert_eq!(breaches[0].p99, Duration::from_millis(80));
+        assert_eq!(breaches[0].slo, Duration::from_millis(50));
+
+        // The slow reads leave the window and the p99 goes back below the SLO
+        assert!(record_many(&mut metrics, 10, ROLLING_WINDOW).is_empty());
+        assert_eq!(record_many(&mut metrics, 80, 5).len(), 1);
+
+        assert_eq!(
+            metrics.report(),
+            vec!["sky read samples=200 p99_ms=80 slo_ms=50 breaches=2 timeouts=0"]
+        );
+    }
+
+    #[test]
+    fn latencies_are_tracked_by_keyspace_and_operation() {
+        let mut metrics = LatencyMetrics::new();
+        metrics.set_slo("sky", Operation::Write, Duration::from_millis(50));
+
+        assert!(record_many(&mut metrics, 80, MIN_SAMPLES * 2).is_empty());
+        assert!(metrics
+            .record("other", Operation::Write, Duration::from_millis(80))
+            .is_none());
+        metrics.record_timeout("sky", Operation::Write);
+
+        assert_eq!(
+            metrics.report(),
+            vec![
+                "other write samples=1 p99_ms=80 slo_ms=- breaches=0 timeouts=0",
+                "sky read samples=40 p99_ms=80 slo_ms=- breaches=0 timeouts=0",
+                "sky write samples=0 p99_ms=0 slo_ms=50 breaches=0 timeouts=1",
+            ]
+        );
+        assert_eq!(
+            metrics.p99("sky", Operation::Read),
+            Some(Duration::from_millis(80))
+        );
+        // Too few samples to tell the p99 apart from noise
+        assert_eq!(metrics.p99("other", Operation::Write), None);
+    }
+
+    #[test]
+    fn tables_needing_compaction_are_reported_once() {
+        let key = ("sky".to_string(), "flights".to_string());
+        let stats = |droppable_cells| TableStats {
+            rows: 10,
+            cells: 10,
+            droppable_cells,
+            ..Default::default()
+        };
+        let mut metrics = TableMetrics::new();
+
+        assert!(metrics
+            .refresh(BTreeMap::from([(key.clone(), stats(1))]))
+            .is_empty());
+        assert_eq!(
+            metrics.refresh(BTreeMap::from([(key.clone(), stats(5))])),
+            vec![key.clone()]
+        );
+        assert!(metrics
+            .refresh(BTreeMap::from([(key.clone(), stats(6))]))
+            .is_empty());
+        assert_eq!(
+            metrics.report(),
+            vec!["sky.flights rows=10 expired_rows=0 tombstone_cells=0 droppable_ratio=0.60 needs_compaction=true"]
+        );
+    }
+
+    #[test]
+    fn lagging_replicas_are_pushed_once_per_interval() {
+        let replica = Ipv4Addr::new(127, 0, 0, 2);
+        let start = Instant::now();
+        let mut metrics = ReplicaLagMetrics::new();
+
+        assert!(!metrics.record(replica, false, false, start));
+        assert!(metrics.report().is_empty());
+
+        assert!(metrics.record(replica, true, false, start));
+        assert!(!metrics.record(replica, true, true, start + LAG_PUSH_INTERVAL / 2));
+        assert!(metrics.record(replica, false, true, start + LAG_PUSH_INTERVAL));
+        assert_eq!(
+            metrics.report(),
+            vec!["replica 127.0.0.2 stale_schema=2 stale_ring=2 pushes=2"]
+        );
+    }
+
+    #[test]
+    fn hot_partitions_outlive_the_cold_ones() {
+        let mut metrics = HotPartitionMetrics::new();
+        for i in 0..HOT_PARTITION_COUNTERS * 4 {
+            metrics.record("sky", "flights", "EZE");
+            metrics.record("sky", "flights", &format!("cold{}", i));
+        }
+        metrics.record("sky", "airports", "AR");
+
+        let tables: Vec<_> = metrics.tables().collect();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].1.len(), 1);
+        let flights = &tables[1].1;
+        assert_eq!(flights.len(), HOT_PARTITIONS_SHOWN);
+        assert_eq!(flights[0].partition, "EZE");
+        assert_eq!(flights[0].writes, HOT_PARTITION_COUNTERS as u64 * 4);
+        assert_eq!(flights[0].error, 0);
+        assert_eq!(flights[0].share, 0.5);
+        assert!(flights[1].writes > flights[1].error);
+
+        assert_eq!(
+            metrics.report()[..2],
+            [
+                "sky.airports partition AR writes=1 error=0 share=1.00",
+                "sky.flights partition EZE writes=256 error=0 share=0.50",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_repairs_are_counted_by_table() {
+        let mut metrics = ReadRepairMetrics::new();
+        metrics.record_mismatch("sky", "flights", true);
+        metrics.record_mismatch("sky", "flights", false);
+        metrics.record_rows("sky", "flights", 3, 1);
+        metrics.record_dropped("sky", "flights", 2);
+        metrics.record_mismatch("sky", "airports", false);
+
+        assert_eq!(
+            metrics.report(),
+            vec![
+                "sky.airports read_repair mismatches=1 skipped=1 repaired_rows=0 failed_rows=0 \
+                 dropped_rows=0",
+                "sky.flights read_repair mismatches=2 skipped=1 repaired_rows=3 failed_rows=1 \
+                 dropped_rows=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn operation_from_str() {
+        assert_eq!(Operation::from_str("read").unwrap(), Operation::Read);
+        assert_eq!(Operation::from_str("WRITE").unwrap(), Operation::Write);
+        assert!(Operation::from_str("scan").is_err());
+    }
+}
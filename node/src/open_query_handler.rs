@@ -1,14 +1,75 @@
 use crate::errors::NodeError;
+use crate::internode_protocol::query::InternodeQuery;
 use crate::internode_protocol::response::InternodeResponse;
+use crate::merge_spill::{MergeBatches, MergeBuffer, MergeMemoryLimit};
+use crate::metrics::{Operation, ReadRepairMetrics, SharedReadRepairMetrics, SharedTimings};
+use crate::query_execution::select::scans_table;
+use crate::worker_pool::{WorkerPool, READ_REPAIR_WORKERS};
 use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
 use native_protocol::frame::Frame;
+use native_protocol::messages::error::{
+    self, ReadTimeout, UnavailableException, WriteTimeout, WriteType,
+};
+use native_protocol::messages::query::Consistency;
+use query_creator::clauses::types::datatype::DataType;
 use query_creator::Query;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::net::Ipv4Addr;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, PartialEq)]
+/// Maximum number of times a query is sent again to another node after a `NotOwner` response.
+const MAX_NOT_OWNER_RETRIES: u32 = 3;
+
+/// Time the coordinator waits for the replicas of a read by default, as in Cassandra.
+pub const DEFAULT_READ_REQUEST_TIMEOUT: Duration = Duration::from_millis(5000);
+/// Time the coordinator waits for the replicas of a write by default, as in Cassandra.
+pub const DEFAULT_WRITE_REQUEST_TIMEOUT: Duration = Duration::from_millis(2000);
+/// Time the coordinator waits for the replicas of a read before it sends it to another replica, until
+/// the keyspace has enough reads to use their p99 latency instead.
+pub const DEFAULT_SPECULATIVE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// How long the coordinator waits for the replicas of a query before answering the client with a
+/// timeout error.
+///
+/// # Fields
+/// - `read: Duration`
+///   - The deadline of the `SELECT` queries (`read_request_timeout`).
+/// - `write: Duration`
+///   - The deadline of the `INSERT`, `UPDATE` and `DELETE` queries (`write_request_timeout`).
+///
+/// # Notes
+/// - Reads and writes have separate timeouts because their latency tolerances are very different: bulk
+///   inserts of flights should fail fast so they can be retried, while the reads of the GUI can wait longer.
+/// - Schema changes have no deadline, they are answered once every node applied them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestTimeouts {
+    pub read: Duration,
+    pub write: Duration,
+}
+
+impl Default for RequestTimeouts {
+    fn default() -> Self {
+        Self {
+            read: DEFAULT_READ_REQUEST_TIMEOUT,
+            write: DEFAULT_WRITE_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl RequestTimeouts {
+    /// Returns the timeout of an operation.
+    pub fn of(&self, operation: Operation) -> Duration {
+        match operation {
+            Operation::Read => self.read,
+            Operation::Write => self.write,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 
 /// Represents the consistency levels available for queries in a distributed database.
 ///
@@ -35,6 +96,10 @@ use std::sync::mpsc::Sender;
 ///   - The operation is considered successful if a majority (quorum) of replicas respond.
 ///   - Balances consistency and availability, commonly used for both reads and writes in distributed databases.
 ///   - Ensures that a read after a write will see the most recent value as long as the write was acknowledged by a quorum.
+/// - `LocalQuorum`
+///   - The operation is considered successful if a majority of the replicas in the datacenter of the coordinator respond.
+///   - Avoids waiting for the replicas of other datacenters, whose latency is usually much higher.
+///   - On keyspaces with `SimpleStrategy`, which do not place replicas by datacenter, it works as `Quorum`.
 /// - `All`
 ///   - The operation is considered successful only if all replicas respond.
 ///   - Provides the highest level of consistency but sacrifices availability and increases latency.
@@ -51,6 +116,7 @@ pub enum ConsistencyLevel {
     Two,
     Three,
     Quorum,
+    LocalQuorum,
     All,
 }
 
@@ -60,7 +126,7 @@ impl ConsistencyLevel {
     /// # Arguments
     /// - `s: &str`
     ///   - The string representation of the consistency level.
-    ///     Valid values are `"any"`, `"one"`, `"two"`, `"three"`, `"quorum"`, and `"all"`.
+    ///     Valid values are `"any"`, `"one"`, `"two"`, `"three"`, `"quorum"`, `"local_quorum"` and `"all"`.
     ///
     /// # Returns
     /// - A `ConsistencyLevel` corresponding to the input string.
@@ -75,11 +141,38 @@ impl ConsistencyLevel {
             "two" => ConsistencyLevel::Two,
             "three" => ConsistencyLevel::Three,
             "quorum" => ConsistencyLevel::Quorum,
+            "local_quorum" => ConsistencyLevel::LocalQuorum,
             "all" => ConsistencyLevel::All,
             _ => ConsistencyLevel::All,
         }
     }
 
+    /// Returns the name of the consistency level, as clients send it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConsistencyLevel::Any => "ANY",
+            ConsistencyLevel::One => "ONE",
+            ConsistencyLevel::Two => "TWO",
+            ConsistencyLevel::Three => "THREE",
+            ConsistencyLevel::Quorum => "QUORUM",
+            ConsistencyLevel::LocalQuorum => "LOCAL_QUORUM",
+            ConsistencyLevel::All => "ALL",
+        }
+    }
+
+    /// Returns the consistency level as the native protocol sends it to clients.
+    pub fn to_consistency(self) -> Consistency {
+        match self {
+            ConsistencyLevel::Any => Consistency::Any,
+            ConsistencyLevel::One => Consistency::One,
+            ConsistencyLevel::Two => Consistency::Two,
+            ConsistencyLevel::Three => Consistency::Three,
+            ConsistencyLevel::Quorum => Consistency::Quorum,
+            ConsistencyLevel::LocalQuorum => Consistency::LocalQuorum,
+            ConsistencyLevel::All => Consistency::All,
+        }
+    }
+
     /// Checks if a query is ready based on the number of responses received and the required responses.
     ///
     /// # Arguments
@@ -96,7 +189,8 @@ impl ConsistencyLevel {
     /// - The required number of responses varies depending on the `ConsistencyLevel`:
     ///   - `Any`, `One`: Requires at least one response.
     ///   - `Two`, `Three`: Requires two and three responses, respectively.
-    ///   - `Quorum`: Requires more than half of the required responses.
+    ///   - `Quorum`, `LocalQuorum`: Requires more than half of the required responses (of the local datacenter,
+    ///     for `LocalQuorum`).
     ///   - `All`: Requires all responses.
     pub fn is_query_ready(&self, responses_received: usize, responses_needed: usize) -> bool {
         match self {
@@ -104,7 +198,9 @@ impl ConsistencyLevel {
             ConsistencyLevel::One => responses_received >= 1,
             ConsistencyLevel::Two => responses_received >= 2,
             ConsistencyLevel::Three => responses_received >= 3,
-            ConsistencyLevel::Quorum => responses_received >= (responses_needed / 2 + 1),
+            ConsistencyLevel::Quorum | ConsistencyLevel::LocalQuorum => {
+                responses_received >= (responses_needed / 2 + 1)
+            }
             ConsistencyLevel::All => responses_received >= responses_needed,
         }
     }
@@ -122,7 +218,8 @@ impl ConsistencyLevel {
     /// - The required number of responses varies depending on the `ConsistencyLevel`:
     ///   - `Any`, `One`: Requires one response.
     ///   - `Two`, `Three`: Requires two and three responses, respectively.
-    ///   - `Quorum`: Requires more than half of the required responses.
+    ///   - `Quorum`, `LocalQuorum`: Requires more than half of the required responses (of the local datacenter,
+    ///     for `LocalQuorum`).
     ///   - `All`: Requires all responses.
     pub fn required_oks(&self, responses_needed: usize) -> usize {
         match self {
@@ -130,12 +227,38 @@ impl ConsistencyLevel {
             ConsistencyLevel::One => 1,
             ConsistencyLevel::Two => 2,
             ConsistencyLevel::Three => 3,
-            ConsistencyLevel::Quorum => responses_needed / 2 + 1,
+            ConsistencyLevel::Quorum | ConsistencyLevel::LocalQuorum => responses_needed / 2 + 1,
             ConsistencyLevel::All => responses_needed,
         }
     }
 }
 
+/// The nodes of the datacenter of a coordinator, and how many replicas of a query they hold.
+///
+/// # Fields
+/// - `nodes: HashSet<Ipv4Addr>`
+///   - The nodes of the datacenter, whose responses count for a `LocalQuorum` query.
+/// - `replicas: usize`
+///   - The replication factor of the keyspace of the query in the datacenter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalReplicas {
+    pub nodes: HashSet<Ipv4Addr>,
+    pub replicas: usize,
+}
+
+/// What a replica of an open query did with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplicaOutcome {
+    /// The query was sent to the replica, which did not answer yet.
+    Pending,
+    /// The replica answered that it executed the query.
+    Acked,
+    /// The replica answered with an error, or the query could not be sent to it.
+    Failed,
+    /// The query could not be sent to the replica, and was stored as a hint for it.
+    Hinted,
+}
+
 /// Represents an open query being processed in the distributed database system.
 ///
 /// # Purpose
@@ -169,6 +292,58 @@ impl ConsistencyLevel {
 /// - `table: Option<TableSchema>`
 ///   - An optional schema of the table associated with the query.
 ///   - Used to validate and process the query's structure and data.
+/// - `correlation_id: String`
+///   - The identifier generated when the client query arrived.
+///   - Used to tag the log lines of the query on every node it goes through.
+/// - `sent_queries: HashMap<Ipv4Addr, InternodeQuery>`
+///   - The query sent to each replica, kept to send it again to another node if a replica answers
+///     that it does not own the partition anymore.
+/// - `replicas: HashMap<Ipv4Addr, ReplicaOutcome>`
+///   - What each replica the query was sent to did with it, so a write that times out tells which
+///     replicas acknowledged it and which ones missed it.
+/// - `partition_value: Option<String>`
+///   - The value hashed to pick the replicas of the query, used to pick them again with the latest ring.
+/// - `retries: u32`
+///   - How many times the query was sent again after a `NotOwner` response.
+/// - `no_op: bool`
+///   - Whether the query did not change anything (e.g. `CREATE TABLE IF NOT EXISTS` on an existing table),
+///     so the client gets a `Void` result instead of a schema change.
+/// - `deadline: Option<Instant>`
+///   - When the query times out if it is still open, according to the `RequestTimeouts` of its operation.
+///     Queries that do not read or write rows have no deadline.
+/// - `merge_buffer: MergeBuffer`
+///   - The bytes of rows held by the answers of a `SELECT`, and the answers spilled to disk once they go
+///     over the `MergeMemoryLimit` of the handler.
+/// - `local_replicas: Option<LocalReplicas>`
+///   - The replicas of the datacenter of the coordinator, for `LocalQuorum` queries on keyspaces with
+///     `NetworkTopologyStrategy`. Only the responses of those nodes count for the consistency level.
+/// - `hint_acks: i32`
+///   - The hints stored for replicas that could not be reached, which acknowledge `Any` writes.
+/// - `speculative_targets: Vec<(Ipv4Addr, InternodeQuery)>`
+///   - The replicas a read was not sent to yet, with the query each would get, in the order they are tried
+///     when the replicas that got it are slow.
+/// - `last_sent: Instant`
+///   - When the query was last sent to a replica, from which the delay of the next speculative retry counts.
+/// - `speculations: usize`
+///   - The speculative retries sent for the query.
+/// - `overloaded: Option<String>`
+///   - Why the query could not be sent to a replica whose outbound queue was full, if that happened.
+///     A query that fails after it answers the client with `Unavailable` instead of a server error.
+/// - `digests: HashMap<Ipv4Addr, u128>`
+///   - The digests of the rows of the replicas a read asked for one instead of the rows.
+/// - `row_digests: HashMap<Ipv4Addr, u128>`
+///   - The digests of the rows the other replicas of a read that asked for digests answered with, compared
+///     with the ones in `digests`.
+/// - `full_reads: Option<Vec<(Ipv4Addr, InternodeQuery)>>`
+///   - Once the digests of a read disagreed, the queries that ask the replicas that sent them for their rows,
+///     until they are sent. `None` while the digests were not found to disagree.
+/// - `forces_repair: bool`
+///   - Whether the query is a read of the `REPAIR` admin command, which writes every repair it finds before
+///     answering, whatever the `read_repair_chance` of the table.
+/// - `batch_partitions: Vec<Vec<Ipv4Addr>>`
+///   - The replicas of each partition written by a batch. A batch is only ready once every one of its
+///     partitions got the OK responses its consistency level requires from its own replicas. Empty for
+///     the other queries.
 ///
 /// # Usage
 /// - `OpenQuery` is created when a new query is initiated by a client.
@@ -201,6 +376,26 @@ pub struct OpenQuery {
     query: Query,
     consistency_level: ConsistencyLevel,
     table: Option<TableSchema>,
+    correlation_id: String,
+    sent_queries: HashMap<Ipv4Addr, InternodeQuery>,
+    replicas: HashMap<Ipv4Addr, ReplicaOutcome>,
+    partition_value: Option<String>,
+    retries: u32,
+    no_op: bool,
+    deadline: Option<Instant>,
+    timings: Option<SharedTimings>,
+    merge_buffer: MergeBuffer,
+    local_replicas: Option<LocalReplicas>,
+    hint_acks: i32,
+    speculative_targets: Vec<(Ipv4Addr, InternodeQuery)>,
+    last_sent: Instant,
+    speculations: usize,
+    overloaded: Option<String>,
+    digests: HashMap<Ipv4Addr, u128>,
+    row_digests: HashMap<Ipv4Addr, u128>,
+    full_reads: Option<Vec<(Ipv4Addr, InternodeQuery)>>,
+    forces_repair: bool,
+    batch_partitions: Vec<Vec<Ipv4Addr>>,
 }
 
 impl OpenQuery {
@@ -210,6 +405,8 @@ impl OpenQuery {
         query: Query,
         consistencty: &str,
         table: Option<TableSchema>,
+        correlation_id: &str,
+        deadline: Option<Instant>,
     ) -> Self {
         Self {
             needed_responses,
@@ -220,6 +417,26 @@ impl OpenQuery {
             query,
             consistency_level: ConsistencyLevel::from_str(consistencty),
             table,
+            correlation_id: correlation_id.to_string(),
+            sent_queries: HashMap::new(),
+            replicas: HashMap::new(),
+            partition_value: None,
+            retries: 0,
+            no_op: false,
+            deadline,
+            timings: None,
+            merge_buffer: MergeBuffer::default(),
+            local_replicas: None,
+            hint_acks: 0,
+            speculative_targets: vec![],
+            last_sent: Instant::now(),
+            speculations: 0,
+            overloaded: None,
+            digests: HashMap::new(),
+            row_digests: HashMap::new(),
+            full_reads: None,
+            forces_repair: false,
+            batch_partitions: vec![],
         }
     }
 
@@ -227,34 +444,166 @@ impl OpenQuery {
     //
     // # Parameters
     // - `response`: The response to be added.
-    fn add_ok_response(&mut self, response: InternodeResponse, from: Ipv4Addr) {
-        self.acumulated_ok_responses.push((from, response));
+    //
+    // A digest is kept apart from the rows, and ignored once the replicas were asked for their rows.
+    fn add_ok_response(&mut self, mut response: InternodeResponse, from: Ipv4Addr) {
+        if let Some(digest) = response.digest {
+            if self.full_reads.is_some() {
+                return;
+            }
+            self.digests.insert(from, digest);
+        } else {
+            if let Some(content) = &mut response.content {
+                // The digest is taken before the rows may be spilled to disk
+                if self.asks_for_digests() {
+                    self.row_digests.insert(from, content.digest());
+                }
+                self.merge_buffer.add(from, content);
+            }
+            self.acumulated_ok_responses.push((from, response));
+        }
+        self.replicas.insert(from, ReplicaOutcome::Acked);
         self.ok_responses += 1;
     }
 
-    // Adds a response to the query and increments the count of actual responses.
+    // Returns whether the query asked a replica for a digest instead of its rows
+    fn asks_for_digests(&self) -> bool {
+        !self.digests.is_empty() || self.sent_queries.values().any(|query| query.digest)
+    }
+
+    // Returns whether the digests the replicas answered with disagree with the rows of the others, or no
+    // replica answered with rows, so the rows must be read from all of them. Digests are only compared
+    // once, before the replicas are asked for their rows.
+    fn digests_disagree(&self) -> bool {
+        if self.digests.is_empty() || self.full_reads.is_some() {
+            return false;
+        }
+        let mut digests = self.row_digests.values().chain(self.digests.values());
+        let first = digests.next();
+        self.row_digests.is_empty() || digests.any(|digest| Some(digest) != first)
+    }
+
+    // Asks the replicas that were asked for a digest for their rows instead, kept in `full_reads` until
+    // they are sent. Their digests no longer count as responses.
+    fn start_full_reads(&mut self) {
+        let mut reads = vec![];
+        for (ip, query) in self
+            .sent_queries
+            .iter_mut()
+            .filter(|(_, query)| query.digest)
+        {
+            query.digest = false;
+            if self.digests.remove(ip).is_some() {
+                self.ok_responses -= 1;
+            }
+            self.replicas.insert(*ip, ReplicaOutcome::Pending);
+            reads.push((*ip, query.clone()));
+        }
+        reads.sort_by_key(|(ip, _)| *ip);
+        self.digests.clear();
+        self.last_sent = Instant::now();
+        self.full_reads = Some(reads);
+    }
+
+    // Adds an error response to the query, from the replica that sent it if it is known.
     //
     // # Parameters
-    // - `response`: The response to be added.
-    fn add_error_response(&mut self) {
+    // - `from`: The replica that answered with the error.
+    fn add_error_response(&mut self, from: Option<Ipv4Addr>) {
+        if let Some(from) = from {
+            self.replicas.insert(from, ReplicaOutcome::Failed);
+        }
         self.error_responses += 1;
     }
 
+    // Records the query sent to a replica, which has not answered it yet
+    fn sent_to(&mut self, to: Ipv4Addr, query: InternodeQuery) {
+        self.sent_queries.insert(to, query);
+        self.replicas.entry(to).or_insert(ReplicaOutcome::Pending);
+    }
+
     // Checks if the query has received all needed responses.
     //
     // # Returns
     /// `true` if the query is closed (i.e., all responses have been received), `false` otherwise.
     fn is_close(&self) -> bool {
-        self.consistency_level
-            .is_query_ready(self.ok_responses as usize, self.needed_responses as usize)
+        if !self.batch_partitions.is_empty() {
+            return self.is_ready()
+                || self.batch_partitions.iter().any(|replicas| {
+                    let (counted, _, failed) = self.partition_outcomes(replicas);
+                    counted - failed < self.consistency_level.required_oks(counted)
+                });
+        }
+        let (_, needed_responses) = self.counted_responses();
+        self.is_ready()
             || !self.can_still_achieve_required_ok(
                 self.needed_responses,
                 self.error_responses,
-                self.consistency_level
-                    .required_oks(self.needed_responses as usize) as i32,
+                self.consistency_level.required_oks(needed_responses) as i32,
             )
     }
 
+    // Returns whether the query got the OK responses its consistency level requires
+    fn is_ready(&self) -> bool {
+        if !self.batch_partitions.is_empty() {
+            return self.batch_partitions.iter().all(|replicas| {
+                let (counted, acked, _) = self.partition_outcomes(replicas);
+                acked >= self.consistency_level.required_oks(counted)
+            });
+        }
+        let (ok_responses, needed_responses) = self.counted_responses();
+        self.consistency_level
+            .is_query_ready(ok_responses, needed_responses)
+    }
+
+    // Returns the OK responses that count for the consistency level, and the replicas that can send them:
+    // only the ones of the local datacenter for a `LocalQuorum` query with `local_replicas`.
+    //
+    // The errors of the replicas do not tell where they come from, so a `LocalQuorum` query whose local
+    // replicas failed may wait until its deadline instead of failing right away.
+    fn counted_responses(&self) -> (usize, usize) {
+        match &self.local_replicas {
+            Some(local) if self.consistency_level == ConsistencyLevel::LocalQuorum => {
+                let ok_responses = self
+                    .acumulated_ok_responses
+                    .iter()
+                    .map(|(from, _)| from)
+                    .chain(self.digests.keys())
+                    .filter(|from| local.nodes.contains(from))
+                    .count();
+                (ok_responses, local.replicas)
+            }
+            _ => (self.ok_responses as usize, self.needed_responses as usize),
+        }
+    }
+
+    // Returns how many replicas of a partition of a batch count for the consistency level, and how many of
+    // them acknowledged the batch and failed. Hints only acknowledge `Any` batches.
+    fn partition_outcomes(&self, replicas: &[Ipv4Addr]) -> (usize, usize, usize) {
+        let counted: Vec<&Ipv4Addr> = replicas
+            .iter()
+            .filter(|ip| match &self.local_replicas {
+                Some(local) if self.consistency_level == ConsistencyLevel::LocalQuorum => {
+                    local.nodes.contains(ip)
+                }
+                _ => true,
+            })
+            .collect();
+        let outcomes = counted.iter().map(|ip| self.replicas.get(ip));
+        let acked = outcomes
+            .clone()
+            .filter(|outcome| match outcome {
+                Some(ReplicaOutcome::Acked) => true,
+                Some(ReplicaOutcome::Hinted) => self.consistency_level == ConsistencyLevel::Any,
+                _ => false,
+            })
+            .count();
+        let failed = outcomes
+            .filter(|outcome| matches!(outcome, Some(ReplicaOutcome::Failed)))
+            .count();
+        (counted.len(), acked, failed)
+    }
+
     fn can_still_achieve_required_ok(
         &self,
         total_responses: i32,
@@ -314,22 +663,148 @@ impl OpenQuery {
         self.table.clone()
     }
 
-    /// Returns a clone of the accumulated successful responses from nodes.
+    /// Returns the accumulated successful responses from nodes.
     ///
     /// # Purpose
     /// Provides access to the list of responses received from nodes that successfully processed the query.
     ///
     /// # Returns
-    /// - `Vec<(Ipv4Addr, InternodeResponse)>`:
-    ///   - A vector of tuples where each tuple contains:
+    /// - `&[(Ipv4Addr, InternodeResponse)]`:
+    ///   - A slice of tuples where each tuple contains:
     ///     - `Ipv4Addr`: The IP address of the responding node.
     ///     - `InternodeResponse`: The response received from the node.
     ///
     /// # Notes
-    /// - The vector is cloned to ensure the original data remains intact.
     /// - Useful for tasks like read repair or aggregating results for the client.
-    pub fn get_acumulated_responses(&self) -> Vec<(Ipv4Addr, InternodeResponse)> {
-        self.acumulated_ok_responses.clone()
+    pub fn get_acumulated_responses(&self) -> &[(Ipv4Addr, InternodeResponse)] {
+        &self.acumulated_ok_responses
+    }
+
+    /// Takes the accumulated successful responses out of the query, so their rows are merged without
+    /// copying them. The query has no responses left after it.
+    pub fn take_acumulated_responses(&mut self) -> Vec<(Ipv4Addr, InternodeResponse)> {
+        std::mem::take(&mut self.acumulated_ok_responses)
+    }
+
+    /// Returns whether some answers went over the merge memory limit and were spilled to disk, in which
+    /// case they have to be merged with `merge_batches` instead of `get_acumulated_responses`.
+    pub fn has_spilled_responses(&self) -> bool {
+        self.merge_buffer.has_spilled()
+    }
+
+    /// Returns the answers of the replicas, the ones in memory and the spilled ones, as batches of answers
+    /// whose rows have disjoint primary keys, in key order, each holding about the merge memory limit of rows.
+    ///
+    /// # Errors
+    /// - Returns `NodeError::IoError` if a spilled answer can not be read.
+    pub fn merge_batches(&mut self) -> Result<MergeBatches, NodeError> {
+        let responses = std::mem::take(&mut self.acumulated_ok_responses);
+        Ok(self.merge_buffer.merge_batches(responses)?)
+    }
+
+    /// Returns whether the query did not change anything, in which case the client gets a `Void` result.
+    pub fn is_no_op(&self) -> bool {
+        self.no_op
+    }
+
+    /// Returns where the coordinator records the time spent on the query, if the client asked
+    /// for it.
+    pub fn get_timings(&self) -> Option<SharedTimings> {
+        self.timings.clone()
+    }
+
+    /// Returns the correlation ID assigned to the query when it arrived from the client.
+    ///
+    /// # Notes
+    /// - Used to tag the log lines written while the query is being resolved.
+    pub fn get_correlation_id(&self) -> String {
+        self.correlation_id.clone()
+    }
+
+    /// Returns whether the query is a read of `REPAIR`, whose repairs are written before it is answered.
+    pub fn forces_repair(&self) -> bool {
+        self.forces_repair
+    }
+
+    /// Returns the operation of the query, if it reads or writes rows.
+    pub fn get_operation(&self) -> Option<Operation> {
+        Operation::of(&self.query)
+    }
+
+    /// Returns the error sent to the client when the query times out: a `ReadTimeout` for reads and a
+    /// `WriteTimeout` for everything else.
+    ///
+    /// # Notes
+    /// - The message tells how many replicas answered, out of the ones the consistency level required.
+    /// - A `WriteTimeout` also carries those numbers, with the consistency level and the kind of write, so
+    ///   drivers can tell whether to retry it.
+    pub fn timeout_error(&self) -> Frame {
+        let (ok_responses, needed_responses) = self.counted_responses();
+        let required = self.consistency_level.required_oks(needed_responses);
+        let message = format!(
+            "Operation timed out - received only {} responses of the {} required",
+            ok_responses, required
+        );
+        match self.get_operation() {
+            Some(Operation::Read) => Frame::Error(error::Error::ReadTimeout(message, ReadTimeout)),
+            _ => Frame::Error(error::Error::WriteTimeout(
+                message,
+                WriteTimeout {
+                    consistency: self.consistency_level.to_consistency(),
+                    received: ok_responses as i32,
+                    block_for: required as i32,
+                    write_type: self.write_type(),
+                },
+            )),
+        }
+    }
+
+    // Returns the kind of write of the query, as told to clients when it times out
+    fn write_type(&self) -> WriteType {
+        let has_counters = self.table.as_ref().is_some_and(|table| {
+            table
+                .get_columns()
+                .iter()
+                .any(|column| column.data_type == DataType::Counter)
+        });
+        match &self.query {
+            // There is no batch log, so no batch is told to be written to it
+            Query::Batch(_) => WriteType::UnloggedBatch,
+            Query::Update(_) if has_counters => WriteType::Counter,
+            _ => WriteType::Simple,
+        }
+    }
+
+    /// Returns what each replica the query was sent to did with it.
+    pub fn replica_outcomes(&self) -> &HashMap<Ipv4Addr, ReplicaOutcome> {
+        &self.replicas
+    }
+
+    /// Returns the replicas that did not answer the query yet, with the query sent to each, sorted by address.
+    ///
+    /// # Notes
+    /// - Used when a write times out, to store a hint for each of them as they may have missed it.
+    pub fn missed_replicas(&self) -> Vec<(Ipv4Addr, InternodeQuery)> {
+        let mut missed: Vec<(Ipv4Addr, InternodeQuery)> = self
+            .replicas
+            .iter()
+            .filter(|(_, outcome)| **outcome == ReplicaOutcome::Pending)
+            .filter_map(|(ip, _)| Some((*ip, self.sent_queries.get(ip)?.clone())))
+            .collect();
+        missed.sort_by_key(|(ip, _)| *ip);
+        missed
+    }
+
+    /// Returns the error sent to the client when too many replicas failed to achieve the consistency level:
+    /// `Unavailable` if a replica was overloaded when the query was sent to it, a server error otherwise.
+    pub fn failure_error(&self) -> Frame {
+        match &self.overloaded {
+            Some(reason) => Frame::Error(error::Error::UnavailableException(
+                reason.clone(),
+                UnavailableException,
+            )),
+            None => Frame::Error(error::Error::ServerError(".".to_string())),
+        }
     }
 }
 
@@ -361,6 +836,14 @@ impl fmt::Display for OpenQuery {
 /// - `next_id: i32`
 ///   - A counter for generating unique IDs for new queries.
 ///   - Increments with each new query added to ensure unique identification.
+/// - `timeouts: RequestTimeouts`
+///   - The deadlines given to the queries opened.
+/// - `merge_limit: Option<MergeMemoryLimit>`
+///   - How many bytes of rows the `SELECT` queries opened buffer before spilling answers to disk, if limited.
+/// - `read_repairs: SharedReadRepairMetrics`
+///   - The read repairs of the `SELECT` queries closed, also updated by the repairs written in the background.
+/// - `read_repair_workers: Arc<WorkerPool>`
+///   - The workers that write the read repairs in the background, `READ_REPAIR_WORKERS` at most.
 ///
 /// # Usage
 /// - The `OpenQueryHandler` is used to add, retrieve, and manage queries during their execution lifecycle.
@@ -388,6 +871,10 @@ pub struct OpenQueryHandler {
     queries: HashMap<i32, OpenQuery>,
     keyspaces_queries: HashMap<i32, Option<KeyspaceSchema>>,
     next_id: i32,
+    timeouts: RequestTimeouts,
+    merge_limit: Option<MergeMemoryLimit>,
+    read_repairs: SharedReadRepairMetrics,
+    read_repair_workers: Arc<WorkerPool>,
 }
 
 impl OpenQueryHandler {
@@ -402,6 +889,7 @@ impl OpenQueryHandler {
     ///   - An empty `queries` map to store active queries.
     ///   - An empty `keyspaces_queries` map to associate queries with keyspace schemas.
     ///   - The `next_id` field initialized to `1`, ensuring unique identification for newly added queries.
+    ///   - The default `RequestTimeouts`.
     ///
     /// # Behavior
     /// - The `queries` and `keyspaces_queries` fields are initialized as empty hash maps.
@@ -416,9 +904,59 @@ impl OpenQueryHandler {
             queries: HashMap::new(),
             keyspaces_queries: HashMap::new(),
             next_id: 1,
+            timeouts: RequestTimeouts::default(),
+            merge_limit: None,
+            read_repairs: Arc::new(Mutex::new(ReadRepairMetrics::new())),
+            read_repair_workers: Arc::new(WorkerPool::new("read-repair", READ_REPAIR_WORKERS)),
+        }
+    }
+
+    /// Sets the timeouts of the queries opened from now on. The queries already open keep their deadlines.
+    pub fn set_timeouts(&mut self, timeouts: RequestTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Sets the memory limit of the merges of the `SELECT` queries opened from now on, or removes it with
+    /// `None`. The queries already open keep their limit.
+    pub fn set_merge_limit(&mut self, limit: Option<MergeMemoryLimit>) {
+        self.merge_limit = limit;
+    }
+
+    /// Returns the read repair counters of the queries closed, shared with the repairs still being written.
+    pub fn read_repairs(&self) -> SharedReadRepairMetrics {
+        self.read_repairs.clone()
+    }
+
+    /// Returns the workers that write the read repairs in the background.
+    pub fn read_repair_workers(&self) -> Arc<WorkerPool> {
+        self.read_repair_workers.clone()
+    }
+
+    /// Makes the read with the specified ID write every repair it finds before it is answered, as `REPAIR`
+    /// does, whatever the `read_repair_chance` of its table.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn force_repair(&mut self, open_query_id: i32) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.forces_repair = true;
         }
     }
 
+    /// Takes an ID no open query will use, for the internode queries the node sends on its own.
+    ///
+    /// # Purpose
+    /// The responses to those queries come back with this ID, so they can be told apart from the
+    /// responses of the client queries.
+    ///
+    /// # Returns
+    /// - `i32`: The reserved ID.
+    pub fn reserve_id(&mut self) -> i32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
     /// Creates and registers a new open query with a unique ID.
     ///
     /// # Purpose
@@ -442,6 +980,8 @@ impl OpenQueryHandler {
     /// - `keyspace: Option<KeyspaceSchema>`
     ///   - An optional keyspace schema associated with the query.
     ///   - Used to validate the query's context within the keyspace.
+    /// - `correlation_id: &str`
+    ///   - The identifier used to tag the log lines of the query.
     ///
     /// # Returns
     /// - `i32`: The unique ID assigned to the new query.
@@ -453,6 +993,8 @@ impl OpenQueryHandler {
     /// 2. **Query Initialization**:
     ///    - Creates a new `OpenQuery` using the provided arguments.
     ///    - Populates the query with details like the number of needed responses, client connection, query, and schema.
+    ///    - Sets its deadline from the read or write timeout, depending on the query.
+    ///    - Gives `SELECT` queries over a table a merge buffer limited by the merge memory limit.
     /// 3. **Query Registration**:
     ///    - Adds the new `OpenQuery` to the `queries` map, associating it with the generated ID.
     ///    - If a keyspace is provided, associates it with the query in the `keyspaces_queries` map.
@@ -480,10 +1022,32 @@ impl OpenQueryHandler {
         consistency_level: &str,
         table: Option<TableSchema>,
         keyspace: Option<KeyspaceSchema>,
+        correlation_id: &str,
     ) -> i32 {
         let new_id = self.next_id;
         self.next_id += 1;
-        let query = OpenQuery::new(needed_responses, tx_reply, query, consistency_level, table);
+        let deadline =
+            Operation::of(&query).map(|operation| Instant::now() + self.timeouts.of(operation));
+        // Counts are merged from a single value per replica, so they never spill, unless they scan
+        // the table and get its rows
+        let merge_buffer = match (&query, &table) {
+            (Query::Select(select), Some(table))
+                if !select.is_count() || scans_table(select, table) =>
+            {
+                MergeBuffer::new(self.merge_limit.clone(), &table.get_columns())
+            }
+            _ => MergeBuffer::default(),
+        };
+        let mut query = OpenQuery::new(
+            needed_responses,
+            tx_reply,
+            query,
+            consistency_level,
+            table,
+            correlation_id,
+            deadline,
+        );
+        query.merge_buffer = merge_buffer;
         self.queries.insert(new_id, query);
         self.keyspaces_queries.insert(new_id, keyspace);
         new_id
@@ -661,6 +1225,382 @@ impl OpenQueryHandler {
         self.keyspaces_queries.insert(open_query_id, Some(keyspace));
     }
 
+    /// Marks the open query with the specified ID as not having changed anything, such as a
+    /// `CREATE KEYSPACE IF NOT EXISTS` of a keyspace that already exists or a `DROP TABLE IF EXISTS`
+    /// of a table that does not.
+    ///
+    /// # Notes
+    /// - The client gets a `Void` result instead of a schema change for these queries.
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn mark_as_no_op(&mut self, open_query_id: i32) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.no_op = true;
+        }
+    }
+
+    /// Makes the open query with the specified ID record the time spent merging the answers of its
+    /// replicas and repairing them in `timings`, shared with the thread that answers the client.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn set_timings(&mut self, open_query_id: i32, timings: SharedTimings) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.timings = Some(timings);
+        }
+    }
+
+    /// Makes the batch with the specified ID wait, for each partition it writes, for the OK responses its
+    /// consistency level requires from the replicas of that partition.
+    ///
+    /// # Parameters
+    /// - `open_query_id: i32`
+    ///   - The unique ID of the `OpenQuery` of the batch.
+    /// - `partitions: Vec<Vec<Ipv4Addr>>`
+    ///   - The owner and replicas of each partition written by the batch.
+    ///
+    /// # Notes
+    /// - Each node answers once for its part of the batch, which acknowledges every partition it is a replica of.
+    /// - Must be called before any response is added to the query.
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn set_batch_partitions(&mut self, open_query_id: i32, partitions: Vec<Vec<Ipv4Addr>>) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            let nodes: HashSet<&Ipv4Addr> = partitions.iter().flatten().collect();
+            open_query.needed_responses = nodes.len() as i32;
+            open_query.batch_partitions = partitions;
+        }
+    }
+
+    /// Makes the open query with the specified ID wait for an answer from each of the `nodes` it was sent
+    /// to, whatever its consistency level.
+    ///
+    /// # Notes
+    /// - Used by the `SELECT`s that scan a table, which get a different part of it from each node.
+    /// - Must be called before any response is added to the query.
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn set_scanned_nodes(&mut self, open_query_id: i32, nodes: i32) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.needed_responses = nodes;
+            open_query.consistency_level = ConsistencyLevel::All;
+        }
+    }
+
+    /// Records the query sent to a replica as part of the open query with the specified ID.
+    ///
+    /// # Purpose
+    /// Keeps what was sent to each replica so the coordinator can send it again to another node if the
+    /// replica answers `NotOwner` (for example because the ring changed while the query was in flight).
+    ///
+    /// # Parameters
+    /// - `open_query_id: i32`
+    ///   - The unique ID of the `OpenQuery` the query belongs to.
+    /// - `to: Ipv4Addr`
+    ///   - The IP address of the replica the query was sent to.
+    /// - `query: InternodeQuery`
+    ///   - The query sent to the replica.
+    /// - `partition_value: Option<&str>`
+    ///   - The value hashed to pick the replicas of the query. `None` for the parts of a batch, which hold the
+    ///     statements of several partitions and are not sent again.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID (e.g. in the nodes that are not the coordinator).
+    pub fn record_sent_query(
+        &mut self,
+        open_query_id: i32,
+        to: Ipv4Addr,
+        query: InternodeQuery,
+        partition_value: Option<&str>,
+    ) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.sent_to(to, query);
+            open_query.partition_value = partition_value.map(str::to_string);
+        }
+    }
+
+    /// Returns what is needed to send again the query that a replica rejected with `NotOwner`.
+    ///
+    /// # Parameters
+    /// - `open_query_id: i32`
+    ///   - The unique ID of the `OpenQuery` the rejected query belongs to.
+    /// - `from: Ipv4Addr`
+    ///   - The IP address of the replica that rejected the query.
+    ///
+    /// # Returns
+    /// - `Option<(InternodeQuery, String, Vec<Ipv4Addr>)>`:
+    ///   - The rejected query with the time left until its deadline, the value hashed to pick its replicas
+    ///     and the nodes that already received the query, which must not be picked again.
+    ///   - `None` if the query was not recorded or was already retried `MAX_NOT_OWNER_RETRIES` times,
+    ///     in which case the response must be handled as an error.
+    pub fn get_query_to_retry(
+        &mut self,
+        open_query_id: i32,
+        from: Ipv4Addr,
+    ) -> Option<(InternodeQuery, String, Vec<Ipv4Addr>)> {
+        let open_query = self.get_query_mut(&open_query_id)?;
+        if open_query.retries >= MAX_NOT_OWNER_RETRIES {
+            return None;
+        }
+        let mut query = open_query.sent_queries.get(&from)?.clone();
+        let partition_value = open_query.partition_value.clone()?;
+        let sent_to = open_query.sent_queries.keys().copied().collect();
+        // The query is sent again with the time left now
+        query.time_left = self.time_left(open_query_id);
+        Some((query, partition_value, sent_to))
+    }
+
+    /// Returns how long the coordinator still waits for the replicas of the open query with `open_query_id`,
+    /// sent along with its internode queries.
+    ///
+    /// # Returns
+    /// - `Option<Duration>`:
+    ///   - The time left until the deadline of the query, which is zero once it passed.
+    ///   - `None` if the query has no deadline or is not open.
+    pub fn time_left(&self, open_query_id: i32) -> Option<Duration> {
+        let deadline = self.queries.get(&open_query_id)?.deadline?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Makes only the responses of `local_replicas` count for the open query with the specified ID, if it
+    /// is a `LocalQuorum` query.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn set_local_replicas(&mut self, open_query_id: i32, local_replicas: LocalReplicas) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.local_replicas = Some(local_replicas);
+        }
+    }
+
+    /// Records that a hint was stored for `replica`, a replica of the open query with the specified ID that
+    /// could not be reached.
+    ///
+    /// # Returns
+    /// - `true` if the hint acknowledges the write, which only happens for `Any` queries. The replica is then
+    ///   not counted as failed.
+    /// - `false` otherwise, or if there is no open query with the given ID.
+    pub fn record_hint(&mut self, open_query_id: i32, replica: Ipv4Addr) -> bool {
+        let Some(open_query) = self.get_query_mut(&open_query_id) else {
+            return false;
+        };
+        open_query.replicas.insert(replica, ReplicaOutcome::Hinted);
+        if open_query.consistency_level != ConsistencyLevel::Any {
+            return false;
+        }
+        open_query.hint_acks += 1;
+        true
+    }
+
+    /// Records that the open query with the specified ID could not be sent to `replica`.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn record_unreachable(&mut self, open_query_id: i32, replica: Ipv4Addr) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.replicas.insert(replica, ReplicaOutcome::Failed);
+        }
+    }
+
+    /// Records that a replica of the open query with the specified ID could not be sent the query because it
+    /// is overloaded, for the given reason.
+    ///
+    /// # Notes
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn record_overloaded(&mut self, open_query_id: i32, reason: String) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.overloaded = Some(reason);
+        }
+    }
+
+    /// Returns the hints that acknowledged the open query with the specified ID since the last call, which
+    /// the coordinator adds as OK responses.
+    pub fn take_hint_acks(&mut self, open_query_id: i32) -> i32 {
+        self.get_query_mut(&open_query_id)
+            .map_or(0, |open_query| std::mem::take(&mut open_query.hint_acks))
+    }
+
+    /// Checks that enough replicas of the open query with the specified ID are alive to achieve its
+    /// consistency level, before the query is sent to them.
+    ///
+    /// # Parameters
+    /// - `open_query_id: i32`
+    ///   - The unique ID of the `OpenQuery` to check.
+    /// - `alive: &[Ipv4Addr]`
+    ///   - The replicas of the query that gossip does not consider dead.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the consistency level can be achieved, or there is no open query with the given ID.
+    /// - `Err(NodeError::Unavailable)` if fewer replicas than required are alive. The query is closed, as
+    ///   Cassandra does not send a query it knows will fail.
+    /// - `Err(NodeError::Invalid)` if the query is a read with consistency level `Any`, which only writes support.
+    ///
+    /// # Behavior
+    /// - `Any` writes need no live replica, since the coordinator stores a hint for the ones it can not reach.
+    /// - `LocalQuorum` queries with `local_replicas` only count the live replicas of the local datacenter.
+    /// - Every other level needs its `required_oks` of the replicas of the query.
+    pub fn check_available(
+        &mut self,
+        open_query_id: i32,
+        alive: &[Ipv4Addr],
+    ) -> Result<(), NodeError> {
+        let Some(open_query) = self.queries.get(&open_query_id) else {
+            return Ok(());
+        };
+        let level = open_query.consistency_level;
+        let is_read = open_query.get_operation() == Some(Operation::Read);
+        let (required, alive) = match (&open_query.local_replicas, level) {
+            (_, ConsistencyLevel::Any) if is_read => {
+                self.close(open_query_id);
+                return Err(NodeError::Invalid(
+                    "ANY ConsistencyLevel is only supported for writes".to_string(),
+                ));
+            }
+            (_, ConsistencyLevel::Any) => (0, alive.len()),
+            (Some(local), ConsistencyLevel::LocalQuorum) => (
+                level.required_oks(local.replicas),
+                alive.iter().filter(|ip| local.nodes.contains(ip)).count(),
+            ),
+            _ => (
+                level.required_oks(open_query.needed_responses as usize),
+                alive.len(),
+            ),
+        };
+        if alive >= required {
+            return Ok(());
+        }
+        self.close(open_query_id);
+        Err(NodeError::Unavailable(format!(
+            "Cannot achieve consistency level {}: {} replicas required, {} alive",
+            level.name(),
+            required,
+            alive
+        )))
+    }
+
+    /// Returns how many of the `replicas` of the open query with the specified ID it is sent to right away.
+    ///
+    /// # Behavior
+    /// - Reads with consistency level `One`, `Two`, `Three` or `Quorum` are sent to the replicas their level
+    ///   requires, the others are kept with `set_speculative_targets` in case those are slow.
+    /// - Every other query is sent to all its replicas, as writes must reach all of them and `All` and
+    ///   `LocalQuorum` reads need specific ones.
+    pub fn initial_targets(&self, open_query_id: i32, replicas: usize) -> usize {
+        let Some(open_query) = self.queries.get(&open_query_id) else {
+            return replicas;
+        };
+        let speculates = open_query.get_operation() == Some(Operation::Read)
+            && matches!(
+                open_query.consistency_level,
+                ConsistencyLevel::One
+                    | ConsistencyLevel::Two
+                    | ConsistencyLevel::Three
+                    | ConsistencyLevel::Quorum
+            );
+        if !speculates {
+            return replicas;
+        }
+        open_query
+            .consistency_level
+            .required_oks(open_query.needed_responses as usize)
+            .clamp(1, replicas.max(1))
+    }
+
+    /// Keeps the replicas the read with the specified ID was not sent to, with the query each would get,
+    /// to send it to them if the ones that got it are slow.
+    ///
+    /// # Notes
+    /// - The delay of the first speculative retry counts from this call.
+    /// - Does nothing if there is no open query with the given ID.
+    pub fn set_speculative_targets(
+        &mut self,
+        open_query_id: i32,
+        targets: Vec<(Ipv4Addr, InternodeQuery)>,
+    ) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.speculative_targets = targets;
+            open_query.last_sent = Instant::now();
+        }
+    }
+
+    /// Takes the next replica of each open read whose replicas are slow, to send it the same query.
+    ///
+    /// # Parameters
+    /// - `now: Instant`
+    ///   - The time the delays are compared against.
+    /// - `delay: impl Fn(&str) -> Duration`
+    ///   - The time a read in the given keyspace waits for its replicas before it is sent to another one,
+    ///     usually the p99 latency of the reads of the keyspace.
+    ///
+    /// # Returns
+    /// - `Vec<(i32, Ipv4Addr, InternodeQuery)>`:
+    ///   - The ID of each read, the replica it must be sent to and the query, with the time left until its
+    ///     deadline. The replica is recorded as one the query was sent to.
+    ///
+    /// # Behavior
+    /// - A read is due once `delay` passed since it was last sent, or as soon as a replica answered it with
+    ///   an error, so a failed replica is replaced right away. Each due read takes one replica at a time.
+    /// - Replicas the query was already sent to, for example after a `NotOwner` answer, are skipped.
+    /// - The first response that completes the consistency level closes the read, and the ones of the slow
+    ///   replicas are then ignored.
+    pub fn take_speculative_retries(
+        &mut self,
+        now: Instant,
+        delay: impl Fn(&str) -> Duration,
+    ) -> Vec<(i32, Ipv4Addr, InternodeQuery)> {
+        let mut retries = vec![];
+        let ids: Vec<i32> = self.queries.keys().copied().collect();
+        for id in ids {
+            let Some(open_query) = self.queries.get_mut(&id) else {
+                continue;
+            };
+            open_query
+                .speculative_targets
+                .retain(|(ip, _)| !open_query.sent_queries.contains_key(ip));
+            let Some((_, query)) = open_query.speculative_targets.first() else {
+                continue;
+            };
+            let slow = now.duration_since(open_query.last_sent) >= delay(&query.keyspace_name);
+            let failed = open_query.error_responses as usize > open_query.speculations;
+            if !slow && !failed {
+                continue;
+            }
+
+            let (to, query) = open_query.speculative_targets.remove(0);
+            open_query.sent_to(to, query.clone());
+            open_query.last_sent = now;
+            open_query.speculations += 1;
+            let mut query = query;
+            query.time_left = self.time_left(id);
+            retries.push((id, to, query));
+        }
+        retries
+    }
+
+    // Removes the open query with the specified ID without answering it
+    fn close(&mut self, open_query_id: i32) {
+        self.queries.remove(&open_query_id);
+        self.keyspaces_queries.remove(&open_query_id);
+    }
+
+    /// Records that a query rejected with `NotOwner` by `from` was sent again to `to`.
+    ///
+    /// # Notes
+    /// - The response of `to` replaces the one expected from the node that rejected the query, so the
+    ///   number of needed responses does not change.
+    /// - The node that rejected the query is kept among the nodes that received it so it is not picked again,
+    ///   but not among its replicas.
+    pub fn record_retry(
+        &mut self,
+        open_query_id: i32,
+        from: Ipv4Addr,
+        to: Ipv4Addr,
+        query: InternodeQuery,
+    ) {
+        if let Some(open_query) = self.get_query_mut(&open_query_id) {
+            open_query.replicas.remove(&from);
+            open_query.sent_to(to, query);
+            open_query.retries += 1;
+        }
+    }
+
     /// Adds a successful response to the `OpenQuery` with the specified ID and checks if it is closed.
     ///
     /// # Purpose
@@ -689,6 +1629,8 @@ impl OpenQueryHandler {
     ///    - Invokes `add_ok_response` on the query to update its state with the new successful response.
     /// 3. **Closure Check**:
     ///    - Calls `is_close` to determine if the query has received enough successful responses to meet its consistency level.
+    ///    - A read whose replicas answered with digests that disagree with the rows of the others is kept open
+    ///      instead, and those replicas are asked for their rows (see `take_full_reads`).
     ///    - If closed, removes the query from the handler and returns it.
     ///    - If not closed, the query remains in the handler, and `None` is returned.
     ///
@@ -717,6 +1659,11 @@ impl OpenQueryHandler {
         match self.get_query_mut(&open_query_id) {
             Some(query) => {
                 query.add_ok_response(response, from);
+                // The rows are read from the replicas that sent a digest before answering the client
+                if query.is_ready() && query.digests_disagree() {
+                    query.start_full_reads();
+                    return None;
+                }
                 if query.is_close() {
                     // println!(
                     //     "con {:?} / {:?} OKS la query se cerro",
@@ -732,6 +1679,33 @@ impl OpenQueryHandler {
         }
     }
 
+    /// Takes the queries that ask the replicas of the read with the specified ID for their rows, once the
+    /// digests they answered with disagreed with the rows of the other replicas.
+    ///
+    /// # Returns
+    /// - `Vec<(Ipv4Addr, InternodeQuery)>`:
+    ///   - Each replica with the query to send it, with the time left until the deadline of the read.
+    ///   - Empty if the digests of the read agreed, the queries were already taken or the read is not open.
+    ///
+    /// # Notes
+    /// - The replicas are recorded as ones the query was sent to, so the read waits for their rows.
+    pub fn take_full_reads(&mut self, open_query_id: i32) -> Vec<(Ipv4Addr, InternodeQuery)> {
+        let time_left = self.time_left(open_query_id);
+        let Some(reads) = self
+            .get_query_mut(&open_query_id)
+            .and_then(|query| query.full_reads.as_mut())
+        else {
+            return vec![];
+        };
+        std::mem::take(reads)
+            .into_iter()
+            .map(|(ip, mut query)| {
+                query.time_left = time_left;
+                (ip, query)
+            })
+            .collect()
+    }
+
     /// Adds an error response to the `OpenQuery` with the specified ID and checks if it is closed.
     ///
     /// # Purpose
@@ -741,6 +1715,8 @@ impl OpenQueryHandler {
     /// # Parameters
     /// - `open_query_id: i32`
     ///   - The unique ID of the `OpenQuery` to which the error response is to be added.
+    /// - `from: Option<Ipv4Addr>`
+    ///   - The replica that answered with the error, recorded as failed, if it is known.
     ///
     /// # Returns
     /// - `Option<OpenQuery>`:
@@ -752,7 +1728,8 @@ impl OpenQueryHandler {
     ///    - Attempts to retrieve the `OpenQuery` associated with the provided `open_query_id`.
     ///    - If the query does not exist, returns `None`.
     /// 2. **Error Response Addition**:
-    ///    - Calls `add_error_response` on the retrieved query to increment its error response count.
+    ///    - Calls `add_error_response` on the retrieved query to increment its error response count and record
+    ///      the replica as failed.
     /// 3. **Closure Check**:
     ///    - Evaluates whether the query has gathered enough responses (successful or errors) to meet its closure condition.
     ///    - If the query is closed, it is removed from the `queries` map and returned.
@@ -777,10 +1754,11 @@ impl OpenQueryHandler {
     pub fn add_error_response_and_get_if_closed(
         &mut self,
         open_query_id: i32,
+        from: Option<Ipv4Addr>,
     ) -> Option<OpenQuery> {
         match self.get_query_mut(&open_query_id) {
             Some(query) => {
-                query.add_error_response();
+                query.add_error_response(from);
 
                 if query.is_close() {
                     // println!(
@@ -795,6 +1773,40 @@ impl OpenQueryHandler {
             None => None,
         }
     }
+
+    /// Removes and returns the open queries whose deadline passed, with the keyspace they run in.
+    ///
+    /// # Purpose
+    /// A replica that is down or overloaded may never answer, which would leave its queries open and their
+    /// clients waiting forever. The coordinator calls this periodically and answers the returned queries
+    /// with their `timeout_error`.
+    ///
+    /// # Parameters
+    /// - `now: Instant`
+    ///   - The time the deadlines are compared against.
+    ///
+    /// # Notes
+    /// - Responses that arrive after a query timed out are ignored, since the query is not open anymore.
+    pub fn take_timed_out_queries(
+        &mut self,
+        now: Instant,
+    ) -> Vec<(OpenQuery, Option<KeyspaceSchema>)> {
+        let timed_out: Vec<i32> = self
+            .queries
+            .iter()
+            .filter(|(_, query)| query.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(id, _)| *id)
+            .collect();
+
+        timed_out
+            .into_iter()
+            .filter_map(|id| {
+                let query = self.queries.remove(&id)?;
+                let keyspace = self.keyspaces_queries.get(&id).cloned().flatten();
+                Some((query, keyspace))
+            })
+            .collect()
+    }
 }
 
 impl fmt::Display for OpenQueryHandler {
@@ -810,3 +1822,543 @@ impl fmt::Display for OpenQueryHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internode_protocol::response::{InternodeResponseContent, InternodeResponseStatus};
+    use query_creator::QueryCreator;
+    use std::sync::mpsc;
+
+    fn open_query(handler: &mut OpenQueryHandler) -> i32 {
+        let (tx, _rx) = mpsc::channel();
+        let query = QueryCreator::new()
+            .handle_query("USE airline".to_string())
+            .unwrap();
+        handler.new_open_query(2, tx, query, "all", None, None, "")
+    }
+
+    fn internode_query(replication: bool) -> InternodeQuery {
+        InternodeQuery {
+            query_string: "SELECT * FROM flights WHERE id = 1".to_string(),
+            open_query_id: 1,
+            client_id: 0,
+            replication,
+            keyspace_name: "airline".to_string(),
+            timestamp: 0,
+            correlation_id: "".to_string(),
+            statement: None,
+            time_left: None,
+            digest: false,
+        }
+    }
+
+    #[test]
+    fn test_query_rejected_by_a_replica_can_be_retried() {
+        let mut handler = OpenQueryHandler::new();
+        let id = open_query(&mut handler);
+        let owner = Ipv4Addr::new(127, 0, 0, 2);
+        let replica = Ipv4Addr::new(127, 0, 0, 3);
+
+        handler.record_sent_query(id, owner, internode_query(false), Some("1"));
+        handler.record_sent_query(id, replica, internode_query(true), Some("1"));
+
+        let (query, partition_value, sent_to) = handler.get_query_to_retry(id, replica).unwrap();
+        assert!(query.replication);
+        assert_eq!(partition_value, "1");
+        assert_eq!(sent_to.len(), 2);
+        assert!(sent_to.contains(&owner) && sent_to.contains(&replica));
+
+        // A node the query was never sent to cannot reject it
+        assert!(handler
+            .get_query_to_retry(id, Ipv4Addr::new(127, 0, 0, 9))
+            .is_none());
+    }
+
+    #[test]
+    fn test_retries_are_limited() {
+        let mut handler = OpenQueryHandler::new();
+        let id = open_query(&mut handler);
+        let mut from = Ipv4Addr::new(127, 0, 0, 2);
+        handler.record_sent_query(id, from, internode_query(false), Some("1"));
+
+        for i in 0..MAX_NOT_OWNER_RETRIES {
+            let (query, _, sent_to) = handler.get_query_to_retry(id, from).unwrap();
+            assert_eq!(sent_to.len(), i as usize + 1);
+            let to = Ipv4Addr::new(127, 0, 0, 3 + i as u8);
+            handler.record_retry(id, from, to, query);
+            from = to;
+        }
+
+        assert!(handler.get_query_to_retry(id, from).is_none());
+    }
+
+    #[test]
+    fn test_queries_are_not_recorded_without_open_query() {
+        let mut handler = OpenQueryHandler::new();
+        let ip = Ipv4Addr::new(127, 0, 0, 2);
+        handler.record_sent_query(7, ip, internode_query(false), Some("1"));
+
+        assert!(handler.get_query_to_retry(7, ip).is_none());
+    }
+
+    #[test]
+    fn test_reads_and_writes_time_out_with_their_own_deadlines() {
+        let mut handler = OpenQueryHandler::new();
+        handler.set_timeouts(RequestTimeouts {
+            read: Duration::from_millis(500),
+            write: Duration::from_millis(100),
+        });
+        let mut open = |query: &str| {
+            let (tx, _rx) = mpsc::channel();
+            let query = QueryCreator::new().handle_query(query.to_string()).unwrap();
+            handler.new_open_query(2, tx, query, "quorum", None, None, "")
+        };
+        let read = open("SELECT * FROM flights WHERE id = 1");
+        let write = open("INSERT INTO flights (id) VALUES (1)");
+        let schema_change = open("USE airline");
+        let now = Instant::now();
+
+        // The replicas are told how long the coordinator still waits
+        let read_time_left = handler.time_left(read).unwrap();
+        assert!(read_time_left > Duration::from_millis(100));
+        assert!(read_time_left <= Duration::from_millis(500));
+        assert_eq!(handler.time_left(schema_change), None);
+
+        assert!(handler.take_timed_out_queries(now).is_empty());
+
+        let timed_out = handler.take_timed_out_queries(now + Duration::from_millis(200));
+        assert_eq!(timed_out.len(), 1);
+        assert!(matches!(
+            timed_out[0].0.timeout_error(),
+            Frame::Error(error::Error::WriteTimeout(..))
+        ));
+        assert!(handler.get_query_mut(&write).is_none());
+
+        let timed_out = handler.take_timed_out_queries(now + Duration::from_secs(60));
+        assert_eq!(timed_out.len(), 1);
+        assert!(matches!(
+            timed_out[0].0.timeout_error(),
+            Frame::Error(error::Error::ReadTimeout(..))
+        ));
+        assert!(handler.get_query_mut(&read).is_none());
+        assert!(handler.get_query_mut(&schema_change).is_some());
+
+        // Late responses of a query that timed out are ignored
+        assert!(handler
+            .add_error_response_and_get_if_closed(read, None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_mark_as_no_op() {
+        let mut handler = OpenQueryHandler::new();
+        let id = open_query(&mut handler);
+        assert!(!handler.get_query_mut(&id).unwrap().is_no_op());
+
+        handler.mark_as_no_op(id);
+        assert!(handler.get_query_mut(&id).unwrap().is_no_op());
+    }
+
+    fn open_query_with(
+        handler: &mut OpenQueryHandler,
+        query: &str,
+        level: &str,
+        needed: i32,
+    ) -> i32 {
+        let (tx, _rx) = mpsc::channel();
+        let query = QueryCreator::new().handle_query(query.to_string()).unwrap();
+        handler.new_open_query(needed, tx, query, level, None, None, "")
+    }
+
+    fn ok_response(id: i32) -> InternodeResponse {
+        InternodeResponse {
+            open_query_id: id as u32,
+            status: InternodeResponseStatus::Ok,
+            content: None,
+            detail: None,
+            generation: None,
+            digest: None,
+        }
+    }
+
+    #[test]
+    fn test_queries_without_enough_live_replicas_are_unavailable() {
+        let mut handler = OpenQueryHandler::new();
+        let replicas = [Ipv4Addr::new(127, 0, 0, 2), Ipv4Addr::new(127, 0, 0, 3)];
+        let write = "INSERT INTO flights (id) VALUES (1)";
+
+        let quorum = open_query_with(&mut handler, write, "quorum", 3);
+        assert!(handler.check_available(quorum, &replicas).is_ok());
+        assert!(matches!(
+            handler.check_available(quorum, &replicas[..1]),
+            Err(NodeError::Unavailable(_))
+        ));
+        // The query is closed, so the client is only answered once
+        assert!(handler.get_query_mut(&quorum).is_none());
+
+        let three = open_query_with(&mut handler, write, "three", 3);
+        assert!(handler.check_available(three, &replicas).is_err());
+
+        // Hints acknowledge `ANY` writes, even without live replicas, but `ANY` reads are invalid
+        let any = open_query_with(&mut handler, write, "any", 3);
+        assert!(handler.check_available(any, &[]).is_ok());
+        let read = open_query_with(&mut handler, "SELECT * FROM flights WHERE id = 1", "any", 3);
+        assert!(matches!(
+            handler.check_available(read, &replicas),
+            Err(NodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_scans_wait_for_every_node() {
+        let mut handler = OpenQueryHandler::new();
+        let nodes = [
+            Ipv4Addr::new(127, 0, 0, 1),
+            Ipv4Addr::new(127, 0, 0, 2),
+            Ipv4Addr::new(127, 0, 0, 3),
+        ];
+        let scan = "SELECT * FROM flights WHERE status = 'delayed' ALLOW FILTERING";
+
+        let id = open_query_with(&mut handler, scan, "one", 1);
+        handler.set_scanned_nodes(id, nodes.len() as i32);
+        assert!(matches!(
+            handler.check_available(id, &nodes[..2]),
+            Err(NodeError::Unavailable(_))
+        ));
+
+        let id = open_query_with(&mut handler, scan, "one", 1);
+        handler.set_scanned_nodes(id, nodes.len() as i32);
+        assert!(handler.check_available(id, &nodes).is_ok());
+        for ip in &nodes[..2] {
+            assert!(handler
+                .add_ok_response_and_get_if_closed(id, ok_response(id), *ip)
+                .is_none());
+        }
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), nodes[2])
+            .is_some());
+    }
+
+    #[test]
+    fn test_batches_need_a_quorum_of_each_partition() {
+        let mut handler = OpenQueryHandler::new();
+        let batch = "BEGIN BATCH INSERT INTO flights (id) VALUES (1); \
+                     INSERT INTO flights (id) VALUES (2); APPLY BATCH";
+        let nodes: Vec<Ipv4Addr> = (1..=4).map(|i| Ipv4Addr::new(127, 0, 0, i)).collect();
+        let partitions = vec![nodes[..3].to_vec(), nodes[1..].to_vec()];
+
+        let id = open_query_with(&mut handler, batch, "quorum", 3);
+        handler.set_batch_partitions(id, partitions.clone());
+        // The first partition gets a quorum, but the second one only one of its replicas
+        for ip in &nodes[..2] {
+            assert!(handler
+                .add_ok_response_and_get_if_closed(id, ok_response(id), *ip)
+                .is_none());
+        }
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), nodes[2])
+            .is_some());
+
+        let id = open_query_with(&mut handler, batch, "quorum", 3);
+        handler.set_batch_partitions(id, partitions);
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), nodes[0])
+            .is_none());
+        assert!(handler
+            .add_error_response_and_get_if_closed(id, Some(nodes[2]))
+            .is_none());
+        let failed = handler
+            .add_error_response_and_get_if_closed(id, Some(nodes[3]))
+            .unwrap();
+        assert!(!failed.is_ready());
+    }
+
+    #[test]
+    fn test_batch_timeouts_tell_which_nodes_missed_their_part() {
+        let mut handler = OpenQueryHandler::new();
+        let batch = "BEGIN BATCH INSERT INTO flights (id) VALUES (1); \
+                     INSERT INTO flights (id) VALUES (2); APPLY BATCH";
+        let nodes: Vec<Ipv4Addr> = (1..=4).map(|i| Ipv4Addr::new(127, 0, 0, i)).collect();
+        let id = open_query_with(&mut handler, batch, "quorum", 3);
+        handler.set_batch_partitions(id, vec![nodes[..3].to_vec(), nodes[1..].to_vec()]);
+        for node in &nodes {
+            handler.record_sent_query(id, *node, internode_query(false), None);
+        }
+        for node in &nodes[..2] {
+            assert!(handler
+                .add_ok_response_and_get_if_closed(id, ok_response(id), *node)
+                .is_none());
+        }
+        // The parts of a batch are not sent again to another node
+        assert!(handler.get_query_to_retry(id, nodes[2]).is_none());
+
+        let (timed_out, _) = handler
+            .take_timed_out_queries(Instant::now() + Duration::from_secs(60))
+            .remove(0);
+        let missed: Vec<Ipv4Addr> = timed_out
+            .missed_replicas()
+            .into_iter()
+            .map(|(ip, _)| ip)
+            .collect();
+        assert_eq!(missed, nodes[2..].to_vec());
+        let Frame::Error(error::Error::WriteTimeout(_, details)) = timed_out.timeout_error() else {
+            panic!("a batch must time out with a WriteTimeout");
+        };
+        assert_eq!(details.received, 2);
+        assert_eq!(details.write_type, WriteType::UnloggedBatch);
+    }
+
+    #[test]
+    fn test_hints_acknowledge_only_any_writes() {
+        let mut handler = OpenQueryHandler::new();
+        let write = "INSERT INTO flights (id) VALUES (1)";
+        let any = open_query_with(&mut handler, write, "any", 2);
+        let one = open_query_with(&mut handler, write, "one", 2);
+
+        let replica = Ipv4Addr::new(127, 0, 0, 2);
+        assert!(handler.record_hint(any, replica));
+        assert!(!handler.record_hint(one, replica));
+        assert_eq!(handler.take_hint_acks(any), 1);
+        assert_eq!(handler.take_hint_acks(any), 0);
+        assert_eq!(handler.take_hint_acks(one), 0);
+    }
+
+    #[test]
+    fn test_write_timeouts_tell_which_replicas_acked() {
+        let mut handler = OpenQueryHandler::new();
+        let write = open_query_with(
+            &mut handler,
+            "INSERT INTO flights (id) VALUES (1)",
+            "quorum",
+            3,
+        );
+        let replicas = [
+            Ipv4Addr::new(127, 0, 0, 1),
+            Ipv4Addr::new(127, 0, 0, 2),
+            Ipv4Addr::new(127, 0, 0, 3),
+        ];
+        for replica in replicas {
+            handler.record_sent_query(write, replica, internode_query(false), Some("1"));
+        }
+        assert!(handler
+            .add_ok_response_and_get_if_closed(write, ok_response(write), replicas[0])
+            .is_none());
+        handler.record_hint(write, replicas[1]);
+
+        let (timed_out, _) = handler
+            .take_timed_out_queries(Instant::now() + Duration::from_secs(60))
+            .remove(0);
+        assert_eq!(
+            timed_out.replica_outcomes().get(&replicas[0]),
+            Some(&ReplicaOutcome::Acked)
+        );
+        assert_eq!(
+            timed_out.replica_outcomes().get(&replicas[1]),
+            Some(&ReplicaOutcome::Hinted)
+        );
+        let missed: Vec<Ipv4Addr> = timed_out
+            .missed_replicas()
+            .into_iter()
+            .map(|(ip, _)| ip)
+            .collect();
+        assert_eq!(missed, vec![replicas[2]]);
+
+        let Frame::Error(error::Error::WriteTimeout(_, details)) = timed_out.timeout_error() else {
+            panic!("a write must time out with a WriteTimeout");
+        };
+        assert_eq!(
+            details,
+            WriteTimeout {
+                consistency: Consistency::Quorum,
+                received: 1,
+                block_for: 2,
+                write_type: WriteType::Simple,
+            }
+        );
+    }
+
+    #[test]
+    fn test_queries_failed_by_an_overloaded_replica_are_unavailable() {
+        let mut handler = OpenQueryHandler::new();
+        let write = "INSERT INTO flights (id) VALUES (1)";
+        let failed = open_query_with(&mut handler, write, "one", 1);
+        let overloaded = open_query_with(&mut handler, write, "one", 1);
+        handler.record_overloaded(overloaded, "Node 127.0.0.2 is overloaded".to_string());
+
+        let failed = handler
+            .add_error_response_and_get_if_closed(failed, None)
+            .unwrap();
+        assert!(matches!(
+            failed.failure_error(),
+            Frame::Error(error::Error::ServerError(_))
+        ));
+        let overloaded = handler
+            .add_error_response_and_get_if_closed(overloaded, None)
+            .unwrap();
+        assert!(matches!(
+            overloaded.failure_error(),
+            Frame::Error(error::Error::UnavailableException(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_local_quorum_only_counts_the_local_datacenter() {
+        let mut handler = OpenQueryHandler::new();
+        let local = [Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(127, 0, 0, 2)];
+        let remote = Ipv4Addr::new(127, 0, 1, 1);
+        let write = "INSERT INTO flights (id) VALUES (1)";
+        let id = open_query_with(&mut handler, write, "local_quorum", 4);
+        handler.set_local_replicas(
+            id,
+            LocalReplicas {
+                nodes: local.into_iter().collect(),
+                replicas: 2,
+            },
+        );
+
+        assert!(handler.check_available(id, &[local[0], local[1]]).is_ok());
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), remote)
+            .is_none());
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), local[0])
+            .is_none());
+        assert!(handler
+            .add_ok_response_and_get_if_closed(id, ok_response(id), local[1])
+            .is_some());
+
+        // Live replicas of other datacenters do not make up for the local ones
+        let id = open_query_with(&mut handler, write, "local_quorum", 4);
+        handler.set_local_replicas(
+            id,
+            LocalReplicas {
+                nodes: local.into_iter().collect(),
+                replicas: 2,
+            },
+        );
+        assert!(matches!(
+            handler.check_available(id, &[local[0], remote]),
+            Err(NodeError::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_reads_ask_for_the_rows_when_digests_disagree() {
+        let rows = |value: &str| InternodeResponseContent {
+            columns: vec!["id".to_string()],
+            select_columns: vec!["id".to_string()],
+            values: vec![vec![value.to_string(), "1".to_string()]],
+        };
+        let data = |id: i32, value: &str| {
+            InternodeResponse::new(id as u32, InternodeResponseStatus::Ok, Some(rows(value)))
+        };
+        let (owner, replica) = (Ipv4Addr::new(127, 0, 0, 2), Ipv4Addr::new(127, 0, 0, 3));
+        let mut digest_query = internode_query(true);
+        digest_query.digest = true;
+
+        let mut handler = OpenQueryHandler::new();
+        let agreed = open_query_with(
+            &mut handler,
+            "SELECT * FROM flights WHERE id = 1",
+            "quorum",
+            3,
+        );
+        let disagreed = open_query_with(
+            &mut handler,
+            "SELECT * FROM flights WHERE id = 1",
+            "quorum",
+            3,
+        );
+        for id in [agreed, disagreed] {
+            handler.record_sent_query(id, owner, internode_query(false), Some("1"));
+            handler.record_sent_query(id, replica, digest_query.clone(), Some("1"));
+            assert!(handler
+                .add_ok_response_and_get_if_closed(id, data(id, "1"), owner)
+                .is_none());
+        }
+
+        // Matching digests close the read with the rows of the owner alone
+        let closed = handler
+            .add_ok_response_and_get_if_closed(agreed, data(agreed, "1").into_digest(), replica)
+            .unwrap();
+        assert_eq!(closed.get_acumulated_responses().len(), 1);
+        assert!(handler.take_full_reads(agreed).is_empty());
+
+        // A digest that disagrees keeps the read open until the replica sends its rows
+        assert!(handler
+            .add_ok_response_and_get_if_closed(
+                disagreed,
+                data(disagreed, "2").into_digest(),
+                replica,
+            )
+            .is_none());
+        let reads = handler.take_full_reads(disagreed);
+        assert_eq!(reads.len(), 1);
+        assert_eq!(reads[0].0, replica);
+        assert!(!reads[0].1.digest);
+        assert!(handler.take_full_reads(disagreed).is_empty());
+
+        let closed = handler
+            .add_ok_response_and_get_if_closed(disagreed, data(disagreed, "2"), replica)
+            .unwrap();
+        let responses = closed.get_acumulated_responses();
+        assert_eq!(responses.len(), 2);
+        assert!(responses
+            .iter()
+            .all(|(_, response)| response.content.is_some()));
+    }
+
+    #[test]
+    fn test_slow_reads_are_sent_to_another_replica() {
+        let mut handler = OpenQueryHandler::new();
+        let read = open_query_with(&mut handler, "SELECT * FROM flights WHERE id = 1", "one", 3);
+        let write = open_query_with(
+            &mut handler,
+            "INSERT INTO flights (id) VALUES (1)",
+            "one",
+            3,
+        );
+        let all = open_query_with(&mut handler, "SELECT * FROM flights WHERE id = 1", "all", 3);
+        assert_eq!(handler.initial_targets(read, 3), 1);
+        assert_eq!(handler.initial_targets(write, 3), 3);
+        assert_eq!(handler.initial_targets(all, 3), 3);
+
+        let slow = Ipv4Addr::new(127, 0, 0, 2);
+        let (second, third) = (Ipv4Addr::new(127, 0, 0, 3), Ipv4Addr::new(127, 0, 0, 4));
+        handler.record_sent_query(read, slow, internode_query(false), Some("1"));
+        handler.set_speculative_targets(
+            read,
+            vec![
+                (second, internode_query(true)),
+                (third, internode_query(true)),
+            ],
+        );
+        let delay = |_: &str| Duration::from_millis(50);
+        let now = Instant::now();
+        assert!(handler.take_speculative_retries(now, delay).is_empty());
+
+        let retries = handler.take_speculative_retries(now + Duration::from_millis(60), delay);
+        assert_eq!(retries.len(), 1);
+        assert_eq!((retries[0].0, retries[0].1), (read, second));
+        assert!(retries[0].2.time_left.is_some());
+
+        // A replica that fails is replaced without waiting for the delay
+        assert!(handler
+            .add_error_response_and_get_if_closed(read, None)
+            .is_none());
+        assert!(handler
+            .add_error_response_and_get_if_closed(read, None)
+            .is_none());
+        let retries = handler.take_speculative_retries(now + Duration::from_millis(61), delay);
+        assert_eq!(retries[0].1, third);
+
+        // The first response closes the read
+        assert!(handler
+            .add_ok_response_and_get_if_closed(read, ok_response(read), third)
+            .is_some());
+        assert!(handler
+            .take_speculative_retries(now + Duration::from_secs(1), delay)
+            .is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-peer outbound queues for the background internode traffic of a node: the rows streamed
+//! to other nodes when the ring changes, and the hints replayed to replicas that are back.
+//!
+//! Each peer gets its own queue for each kind of traffic, drained by a thread that paces the
+//! messages with a token bucket, so a rebalance does not saturate the network shared with the
+//! client traffic (Cassandra's `stream_throughput_outbound` and `hinted_handoff_throttle`). The
+//! queries of the clients and their replies are never queued here.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::connection_manager::ConnectionManager;
+use crate::internode_protocol::message::InternodeMessage;
+use crate::internode_protocol::InternodeSerializable;
+use crate::utils::connect_and_send_message;
+use crate::NodeError;
+
+/// The bandwidth, in bytes per second, each kind of background traffic may use towards each peer.
+/// `None` leaves it unlimited, which is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BandwidthLimits {
+    /// The rows streamed to other nodes when the ring changes.
+    pub streaming: Option<u64>,
+    /// The hints replayed to the replicas that are back.
+    pub hints: Option<u64>,
+}
+
+/// The kinds of background traffic that have their own queue to each peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Traffic {
+    Streaming,
+    Hints,
+}
+
+impl BandwidthLimits {
+    fn of(&self, traffic: Traffic) -> Option<u64> {
+        match traffic {
+            Traffic::Streaming => self.streaming,
+            Traffic::Hints => self.hints,
+        }
+    }
+}
+
+/// Paces the bytes sent to a peer at a rate, allowing bursts of up to a second of it.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate: rate.max(1) as f64,
+            tokens: rate.max(1) as f64,
+            last: now,
+        }
+    }
+
+    /// Takes the tokens of a message of `bytes` and returns how long to wait before sending it.
+    /// Messages larger than the bucket are sent too, once the tokens they owe are refilled.
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Time the thread of a queue waits before sending its message again to an overloaded peer.
+const OVERLOADED_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Run by the thread of a queue when its message can not be sent.
+pub type OnFailure = Box<dyn FnOnce() + Send>;
+
+type Queued = (InternodeMessage, Option<OnFailure>);
+type Queues = HashMap<(Ipv4Addr, Traffic), Sender<Queued>>;
+
+/// The outbound queues of a node, created the first time a peer gets each kind of traffic, which
+/// send to the internode `port` of the peers.
+#[derive(Clone)]
+pub struct OutboundQueues {
+    limits: BandwidthLimits,
+    port: u16,
+    queues: Arc<Mutex<Queues>>,
+}
+
+impl OutboundQueues {
+    pub fn new(limits: BandwidthLimits, port: u16) -> Self {
+        Self {
+            limits,
+            port,
+            queues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Queues a message to a peer, behind the other messages of the same kind of traffic to it.
+    ///
+    /// Messages are sent through the shared `connections`, in order. If one can not be sent,
+    /// `on_failure` is run and the queue goes on with the next one.
+    pub fn send(
+        &self,
+        peer: Ipv4Addr,
+        traffic: Traffic,
+        message: InternodeMessage,
+        connections: ConnectionManager,
+        on_failure: Option<OnFailure>,
+    ) -> Result<(), NodeError> {
+        let mut queues = self.queues.lock()?;
+        let queue = queues.entry((peer, traffic)).or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<Queued>();
+            let port = self.port;
+            let mut bucket = self
+                .limits
+                .of(traffic)
+                .map(|rate| TokenBucket::new(rate, Instant::now()));
+            thread::spawn(move || {
+                for (message, on_failure) in receiver {
+                    let bytes = message.as_bytes().len();
+                    if let Some(bucket) = bucket.as_mut() {
+                        thread::sleep(bucket.reserve(bytes, Instant::now()));
+                    }
+                    let sent = send_waiting_for_room(peer, port, &connections, message);
+                    if let (Err(_), Some(on_failure)) = (sent, on_failure) {
+                        on_failure();
+                    }
+                }
+            });
+            sender
+        });
+        queue
+            .send((message, on_failure))
+            .map_err(|_| NodeError::InternodeError)
+    }
+}
+
+// Sends a message through the connection to a peer, waiting while the peer is overloaded: the
+// background traffic is not in a hurry, and dropping it would only make it be sent again.
+fn send_waiting_for_room(
+    peer: Ipv4Addr,
+    port: u16,
+    connections: &ConnectionManager,
+    message: InternodeMessage,
+) -> Result<(), NodeError> {
+    loop {
+        match connect_and_send_message(peer, port, connections.clone(), message.clone()) {
+            Err(NodeError::Unavailable(_)) => thread::sleep(OVERLOADED_RETRY_DELAY),
+            sent => return sent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_paces_bytes_at_its_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000, start);
+
+        // A second of bytes goes out at once, the rest waits for the bucket to refill
+        assert_eq!(bucket.reserve(600, start), Duration::ZERO);
+        assert_eq!(bucket.reserve(400, start), Duration::ZERO);
+        assert_eq!(bucket.reserve(500, start), Duration::from_millis(500));
+
+        // Tokens refill at the rate, up to a second of them
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.reserve(1000, later), Duration::ZERO);
+        assert_eq!(bucket.reserve(2000, later), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,156 @@
+//! The part of the Paxos rounds of lightweight transactions played by the replicas of a
+//! partition: promising to take no part in older rounds, and accepting the rows proposed by the
+//! round they promised.
+//!
+//! The state of each partition is kept in memory only, so a replica that restarts takes part in
+//! any round again. Unlike Cassandra, which keeps it in its `system.paxos` table, a round that
+//! was accepted but not committed before a restart is then left to the next round of a quorum
+//! that still has it.
+
+use std::collections::HashMap;
+
+use crate::internode_protocol::paxos::{Ballot, Proposal};
+
+/// A partition of a table, as its keyspace, table and the value of its partition key.
+pub type PaxosKey = (String, String, String);
+
+#[derive(Debug, Default)]
+struct PaxosSlot {
+    promised: Option<Ballot>,
+    accepted: Option<(Ballot, Proposal)>,
+    committed: Option<Ballot>,
+}
+
+/// What a replica answers when asked to promise a ballot.
+///
+/// - `promised`: Whether it promised it.
+/// - `in_progress`: The proposal it accepted in a round that it did not commit yet, with its ballot.
+/// - `committed`: The ballot of the newest round it committed.
+#[derive(Debug, PartialEq)]
+pub struct Promise {
+    pub promised: bool,
+    pub in_progress: Option<(Ballot, Proposal)>,
+    pub committed: Option<Ballot>,
+}
+
+/// The Paxos state of the partitions a replica was asked about.
+#[derive(Debug, Default)]
+pub struct PaxosState {
+    slots: HashMap<PaxosKey, PaxosSlot>,
+}
+
+impl PaxosState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Promises to take no part in rounds older than `ballot` on the partition, unless the
+    /// replica already promised a round as new as it.
+    pub fn prepare(&mut self, key: PaxosKey, ballot: Ballot) -> Promise {
+        let slot = self.slots.entry(key).or_default();
+        let promised = slot.promised.is_none_or(|promised| promised < ballot);
+        if promised {
+            slot.promised = Some(ballot);
+        }
+        Promise {
+            promised,
+            in_progress: slot.accepted.clone(),
+            committed: slot.committed,
+        }
+    }
+
+    /// Accepts the row proposed by the round of `ballot`, unless the replica promised a newer
+    /// round. Returns whether it accepted it.
+    pub fn propose(&mut self, key: PaxosKey, ballot: Ballot, proposal: Proposal) -> bool {
+        let slot = self.slots.entry(key).or_default();
+        if slot.promised.is_some_and(|promised| promised > ballot) {
+            return false;
+        }
+        slot.promised = Some(ballot);
+        slot.accepted = Some((ballot, proposal));
+        true
+    }
+
+    /// Records that the round of `ballot` was committed, and forgets the row accepted in it or in
+    /// an older round.
+    pub fn commit(&mut self, key: PaxosKey, ballot: Ballot) {
+        let slot = self.slots.entry(key).or_default();
+        if slot
+            .accepted
+            .as_ref()
+            .is_some_and(|(accepted, _)| *accepted <= ballot)
+        {
+            slot.accepted = None;
+        }
+        slot.committed = slot.committed.max(Some(ballot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn key() -> PaxosKey {
+        ("sky".to_string(), "flights".to_string(), "AR1".to_string())
+    }
+
+    fn ballot(micros: i64) -> Ballot {
+        Ballot {
+            micros,
+            node: Ipv4Addr::new(127, 0, 0, 1),
+        }
+    }
+
+    fn proposal(row: &str) -> Proposal {
+        Proposal {
+            row: row.to_string(),
+            ttl: None,
+        }
+    }
+
+    fn promise(
+        promised: bool,
+        in_progress: Option<(i64, &str)>,
+        committed: Option<i64>,
+    ) -> Promise {
+        Promise {
+            promised,
+            in_progress: in_progress.map(|(micros, row)| (ballot(micros), proposal(row))),
+            committed: committed.map(ballot),
+        }
+    }
+
+    #[test]
+    fn test_replica_only_takes_part_in_the_newest_round() {
+        let mut state = PaxosState::new();
+        assert_eq!(state.prepare(key(), ballot(2)), promise(true, None, None));
+        assert_eq!(state.prepare(key(), ballot(1)), promise(false, None, None));
+        assert!(!state.propose(key(), ballot(1), proposal("AR1,late")));
+
+        assert!(state.propose(key(), ballot(2), proposal("AR1,boarding")));
+        // A newer round learns about the row accepted and not committed
+        assert_eq!(
+            state.prepare(key(), ballot(3)),
+            promise(true, Some((2, "AR1,boarding")), None)
+        );
+
+        state.commit(key(), ballot(3));
+        assert_eq!(
+            state.prepare(key(), ballot(4)),
+            promise(true, None, Some(3))
+        );
+    }
+
+    #[test]
+    fn test_commit_of_an_older_round_keeps_the_accepted_row() {
+        let mut state = PaxosState::new();
+        assert!(state.propose(key(), ballot(5), proposal("AR1,landed")));
+        state.commit(key(), ballot(4));
+        assert_eq!(
+            state.prepare(key(), ballot(6)),
+            promise(true, Some((5, "AR1,landed")), Some(4))
+        );
+    }
+}
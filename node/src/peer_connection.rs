@@ -0,0 +1,145 @@
+//! Outbound internode connections, each written by a thread of its own.
+//!
+//! The threads that send a message to a peer (client queries, replica answers, gossip) only queue
+//! its bytes in the connection, so a slow peer never blocks them while they hold the node or the
+//! connections. The queue of each connection is bounded: once it is full the peer is reported as
+//! overloaded, as Cassandra drops the messages of an outbound queue that falls too far behind,
+//! and the coordinators answer their clients with `Unavailable` instead of piling up more
+//! messages.
+
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::NodeError;
+use crate::internode_tls::InternodeStream;
+
+/// Messages that can wait in the queue of a connection before its peer is overloaded.
+pub const OUTBOUND_QUEUE_CAPACITY: usize = 1024;
+/// Time a write to a peer can take before its connection is considered broken.
+pub const INTERNODE_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time a connection can go without messages before its writer checks that the peer did not
+/// close it.
+pub const CONNECTION_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A connection a node opened to another node, shared by the threads that send it messages.
+///
+/// ### Fields
+/// - `peer`: The node the connection goes to.
+/// - `queue`: The bytes of the messages waiting to be written, in order.
+/// - `closed`: Set by the writer once the connection broke, so the next message opens a new one.
+#[derive(Debug, Clone)]
+pub struct PeerConnection {
+    peer: Ipv4Addr,
+    queue: SyncSender<Vec<u8>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl PeerConnection {
+    /// Starts the thread that writes the messages queued in the connection to `stream`, with
+    /// `first` (the messages that negotiate the connection, if any) written before them.
+    ///
+    /// The thread stops when the connection breaks, dropping the messages still queued, or when
+    /// every clone of the connection was dropped. While there are no messages to write, it checks
+    /// every `CONNECTION_HEALTH_CHECK_INTERVAL` that the peer did not close the connection, so a
+    /// peer that restarted gets a new one before the next message instead of losing it.
+    pub fn start(peer: Ipv4Addr, mut stream: InternodeStream, first: Vec<u8>) -> Self {
+        let (queue, messages) = mpsc::sync_channel::<Vec<u8>>(OUTBOUND_QUEUE_CAPACITY);
+        let closed = Arc::new(AtomicBool::new(false));
+        let writer_closed = Arc::clone(&closed);
+
+        thread::spawn(move || {
+            let mut bytes = first;
+            loop {
+                if !bytes.is_empty() {
+                    if let Err(e) = stream.write_all(&bytes).and_then(|_| stream.flush()) {
+                        eprintln!("Error al escribir en el stream de {:?}: {:?}", peer, e);
+                        break;
+                    }
+                }
+                bytes = match messages.recv_timeout(CONNECTION_HEALTH_CHECK_INTERVAL) {
+                    Ok(bytes) => bytes,
+                    Err(RecvTimeoutError::Timeout) if stream.is_open() => Vec::new(),
+                    Err(_) => break,
+                };
+            }
+            writer_closed.store(true, Ordering::SeqCst);
+        });
+
+        Self {
+            peer,
+            queue,
+            closed,
+        }
+    }
+
+    /// Queues the bytes of a message, without waiting for the peer.
+    ///
+    /// # Errors
+    /// - `NodeError::Unavailable` if the queue of the connection is full.
+    /// - `NodeError::InternodeError` if the connection broke.
+    pub fn send(&self, bytes: Vec<u8>) -> Result<(), NodeError> {
+        self.queue.try_send(bytes).map_err(|e| match e {
+            TrySendError::Full(_) => NodeError::Unavailable(format!(
+                "Node {} is overloaded: {} messages are waiting to be sent to it",
+                self.peer, OUTBOUND_QUEUE_CAPACITY
+            )),
+            TrySendError::Disconnected(_) => NodeError::InternodeError,
+        })
+    }
+
+    /// Returns whether the connection broke, in which case messages can not be sent through it.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    fn connection() -> (PeerConnection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        let connection = PeerConnection::start(
+            Ipv4Addr::LOCALHOST,
+            InternodeStream::Plain(stream),
+            b"startup;".to_vec(),
+        );
+        (connection, accepted)
+    }
+
+    #[test]
+    fn test_messages_are_written_after_the_first_one_in_order() {
+        let (connection, mut accepted) = connection();
+        connection.send(b"one;".to_vec()).unwrap();
+        connection.send(b"two".to_vec()).unwrap();
+        drop(connection);
+
+        let mut read = String::new();
+        accepted.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "startup;one;two");
+    }
+
+    #[test]
+    fn test_peer_that_does_not_read_is_overloaded() {
+        // The peer never reads, so the writer blocks once the socket buffers are full and the
+        // queue fills up behind it
+        let (connection, _accepted) = connection();
+        let message = vec![0u8; 64 * 1024];
+        let sent = (0..OUTBOUND_QUEUE_CAPACITY * 2)
+            .map(|_| connection.send(message.clone()))
+            .find(|sent| sent.is_err());
+
+        assert!(matches!(sent, Some(Err(NodeError::Unavailable(_)))));
+        assert!(!connection.is_closed());
+    }
+}
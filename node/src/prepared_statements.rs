@@ -0,0 +1,373 @@
+//! Cache of the queries prepared by the clients of a node, executed later by their id with the
+//! values of their `?` bind markers.
+//!
+//! Values are bound as the type of the column of their marker: numbers and booleans must be
+//! valid values of their type, and text, timestamps and UUIDs are quoted, so a value is always
+//! a single literal of the query and can not change what it runs.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use native_protocol::messages::error::Error;
+use query_creator::clauses::types::datatype::DataType;
+
+/// Number of prepared queries kept by default. The oldest ones are dropped first, and a client
+/// executing one of them is told to prepare it again.
+const PREPARED_STATEMENTS_CAPACITY: usize = 1000;
+
+/// The queries prepared in a node, by id. The id of a query is a hash of its text, so preparing
+/// the same query again (from any client) returns the same id.
+pub struct PreparedStatements {
+    statements: HashMap<Vec<u8>, String>,
+    /// Ids in the order they were prepared, to drop the oldest one when the cache is full.
+    order: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl PreparedStatements {
+    pub fn new() -> Self {
+        PreparedStatements {
+            statements: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: PREPARED_STATEMENTS_CAPACITY,
+        }
+    }
+
+    /// Stores `query` and returns its id, dropping the oldest query if the cache is full.
+    pub fn prepare(&mut self, query: &str) -> Vec<u8> {
+        let mut hasher = DefaultHasher::new();
+        query.hash(&mut hasher);
+        let id = hasher.finish().to_be_bytes().to_vec();
+
+        if !self.statements.contains_key(&id) {
+            if self.order.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.statements.remove(&oldest);
+                }
+            }
+            self.order.push_back(id.clone());
+            self.statements.insert(id.clone(), query.to_string());
+        }
+        id
+    }
+
+    /// Returns the query prepared with `id`, with each of its bind markers replaced by the value
+    /// in the same position of `values`, as a literal of the type of the column of the marker.
+    ///
+    /// # Parameters
+    /// - `column_type`: Returns the type of a column, given its keyspace (if the query names
+    ///   it), table and name. Values of unknown columns are bound as text, and the ones of
+    ///   markers that are not compared to or written in a column (as `LIMIT ?`) as integers.
+    ///
+    /// # Errors
+    /// - `Error::Unprepared` if there is no query with `id`.
+    /// - `Error::ProtocolError` if the amount of values is not the amount of markers.
+    /// - `Error::Invalid` if a value is not a valid value of its type, or is text with a quote.
+    pub fn bind(
+        &self,
+        id: &[u8],
+        values: &[String],
+        column_type: impl Fn(Option<&str>, &str, &str) -> Option<DataType>,
+    ) -> Result<String, Error> {
+        let query = self
+            .statements
+            .get(id)
+            .ok_or_else(|| Error::Unprepared(format!("Unknown prepared id {:02x?}", id)))?;
+
+        let markers = bind_markers(query);
+        if markers.len() != values.len() {
+            return Err(Error::ProtocolError(format!(
+                "Expected {} values for the prepared query, got {}",
+                markers.len(),
+                values.len()
+            )));
+        }
+
+        let columns = marker_columns(query);
+        let mut bound = String::with_capacity(query.len());
+        let mut last = 0;
+        for ((marker, column), value) in markers.into_iter().zip(columns).zip(values) {
+            let data_type = match column {
+                Some(column) => {
+                    column_type(column.keyspace.as_deref(), &column.table, &column.name)
+                        .unwrap_or(DataType::String)
+                }
+                None => DataType::Int,
+            };
+            bound.push_str(&query[last..marker]);
+            bound.push_str(&literal(value, data_type)?);
+            last = marker + 1;
+        }
+        bound.push_str(&query[last..]);
+        Ok(bound)
+    }
+}
+
+// Returns `value` as a CQL literal of `data_type`
+fn literal(value: &str, data_type: DataType) -> Result<String, Error> {
+    if !data_type.is_valid_value(value) {
+        return Err(Error::Invalid(format!(
+            "{} is not a valid {} value",
+            value,
+            data_type.to_string()
+        )));
+    }
+    match data_type {
+        DataType::String | DataType::Timestamp | DataType::Uuid => {
+            // The parser has no escape for quotes inside a string
+            if value.contains('\'') {
+                return Err(Error::Invalid(format!(
+                    "{} can not be bound, text values can not hold quotes",
+                    value
+                )));
+            }
+            Ok(format!("'{}'", value))
+        }
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// The column a bind marker is compared to or written in.
+#[derive(Debug, PartialEq)]
+struct MarkerColumn {
+    keyspace: Option<String>,
+    table: String,
+    name: String,
+}
+
+// A word (a name or a number) or a symbol of a query, with its position
+type Token<'a> = (usize, &'a str);
+
+// Splits a query in words and symbols, leaving out quoted strings
+fn tokens(query: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut in_quotes = false;
+    let mut word_start = None;
+    for (i, c) in query.char_indices() {
+        let is_word = !in_quotes && (c.is_alphanumeric() || c == '_' || c == '.');
+        match (word_start, is_word) {
+            (None, true) => word_start = Some(i),
+            (Some(start), false) => {
+                tokens.push((start, &query[start..i]));
+                word_start = None;
+            }
+            _ => {}
+        }
+        if c == '\'' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && !is_word && !c.is_whitespace() {
+            tokens.push((i, &query[i..i + c.len_utf8()]));
+        }
+    }
+    if let Some(start) = word_start {
+        tokens.push((start, &query[start..]));
+    }
+    tokens
+}
+
+// Returns the column of each bind marker of a query, in order: the one it is compared to (as in
+// `WHERE id = ?`), assigned to (`SET status = ?` or `SET count = count + ?`) or inserted in (the
+// one in its position in `INSERT INTO flights (id, status) VALUES (?, ?)`), of the table of the
+// statement it is in. Markers of anything else (as `LIMIT ?` or `USING TTL ?`) have none.
+fn marker_columns(query: &str) -> Vec<Option<MarkerColumn>> {
+    let tokens = tokens(query);
+    let is_symbol = |token: &str, symbols: &str| token.len() == 1 && symbols.contains(token);
+
+    let mut columns = vec![];
+    let mut table = None;
+    for (i, (_, token)) in tokens.iter().enumerate() {
+        if ["INTO", "UPDATE", "FROM"]
+            .iter()
+            .any(|keyword| token.eq_ignore_ascii_case(keyword))
+        {
+            table = tokens
+                .get(i + 1)
+                .map(|(_, table)| match table.split_once('.') {
+                    Some((keyspace, table)) => (Some(keyspace.to_string()), table.to_string()),
+                    None => (None, table.to_string()),
+                });
+        }
+        if *token != "?" {
+            continue;
+        }
+
+        let mut before = i;
+        while before > 0 && is_symbol(tokens[before - 1].1, "=<>!+-") {
+            before -= 1;
+        }
+        let name = if before < i {
+            // Compared or assigned: the name before the operator
+            before
+                .checked_sub(1)
+                .map(|name| tokens[name].1)
+                .filter(|name| !is_symbol(name, "(),?"))
+                .map(str::to_string)
+        } else if i > 0 && is_symbol(tokens[i - 1].1, "(,") {
+            inserted_column(&tokens, i)
+        } else {
+            None
+        };
+
+        columns.push(
+            name.zip(table.clone())
+                .map(|(name, (keyspace, table))| MarkerColumn {
+                    keyspace,
+                    table,
+                    name,
+                }),
+        );
+    }
+    columns
+}
+
+// Returns the column of the marker in position `marker` of the values of an `INSERT`, which is the
+// one in the same position of the columns of the statement
+fn inserted_column(tokens: &[Token<'_>], marker: usize) -> Option<String> {
+    let opening = tokens[..marker]
+        .iter()
+        .rposition(|(_, token)| *token == "(")?;
+    let position = tokens[opening..marker]
+        .iter()
+        .filter(|(_, token)| *token == ",")
+        .count();
+    if !tokens
+        .get(opening.checked_sub(1)?)?
+        .1
+        .eq_ignore_ascii_case("VALUES")
+    {
+        return None;
+    }
+
+    let closing = opening.checked_sub(2)?;
+    if tokens[closing].1 != ")" {
+        return None;
+    }
+    let columns_opening = tokens[..closing]
+        .iter()
+        .rposition(|(_, token)| *token == "(")?;
+    tokens[columns_opening + 1..closing]
+        .iter()
+        .map(|(_, token)| *token)
+        .filter(|token| *token != ",")
+        .nth(position)
+        .map(str::to_string)
+}
+
+/// Returns the positions of the `?` bind markers of `query`, ignoring the ones inside quoted
+/// strings.
+pub fn bind_markers(query: &str) -> Vec<usize> {
+    let mut in_quotes = false;
+    query
+        .char_indices()
+        .filter(|(_, c)| {
+            if *c == '\'' {
+                in_quotes = !in_quotes;
+            }
+            *c == '?' && !in_quotes
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The types of the columns of `sky.flights`
+    fn flights(keyspace: Option<&str>, table: &str, column: &str) -> Option<DataType> {
+        if keyspace.is_some_and(|keyspace| keyspace != "sky") || table != "flights" {
+            return None;
+        }
+        match column {
+            "id" | "delay" => Some(DataType::Int),
+            "status" | "note" => Some(DataType::String),
+            "landed" => Some(DataType::Boolean),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_prepare_and_bind() {
+        let mut statements = PreparedStatements::new();
+        let query = "UPDATE flights SET status = ? WHERE id = ? AND note = 'why?'";
+
+        let id = statements.prepare(query);
+        assert_eq!(statements.prepare(query), id);
+        assert_eq!(bind_markers(query).len(), 2);
+
+        let bound = statements
+            .bind(&id, &["delayed".to_string(), "42".to_string()], flights)
+            .unwrap();
+        assert_eq!(
+            bound,
+            "UPDATE flights SET status = 'delayed' WHERE id = 42 AND note = 'why?'"
+        );
+
+        assert!(matches!(
+            statements.bind(&id, &["42".to_string()], flights),
+            Err(Error::ProtocolError(_))
+        ));
+        assert!(matches!(
+            statements.bind(&[0, 1], &[], flights),
+            Err(Error::Unprepared(_))
+        ));
+    }
+
+    #[test]
+    fn test_values_are_bound_as_the_type_of_their_column() {
+        let mut statements = PreparedStatements::new();
+        let insert = statements
+            .prepare("INSERT INTO sky.flights (id, status, landed) VALUES (?, ?, ?) USING TTL ?");
+        let bound = statements.bind(
+            &insert,
+            &[
+                "7".to_string(),
+                "on time".to_string(),
+                "true".to_string(),
+                "60".to_string(),
+            ],
+            flights,
+        );
+        assert_eq!(
+            bound.unwrap(),
+            "INSERT INTO sky.flights (id, status, landed) VALUES (7, 'on time', true) USING TTL 60"
+        );
+
+        let update = statements.prepare("UPDATE flights SET delay = delay + ? WHERE id >= ?");
+        let values = ["15".to_string(), "3".to_string()];
+        assert_eq!(
+            statements.bind(&update, &values, flights).unwrap(),
+            "UPDATE flights SET delay = delay + 15 WHERE id >= 3"
+        );
+
+        // Values can not be more than a single literal of the query
+        let select = statements.prepare("SELECT * FROM flights WHERE id = ? AND status = ?");
+        for values in [
+            ["1 OR true".to_string(), "landed".to_string()],
+            ["1".to_string(), "landed' OR status = 'delayed".to_string()],
+        ] {
+            assert!(matches!(
+                statements.bind(&select, &values, flights),
+                Err(Error::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_oldest_statement_is_dropped() {
+        let mut statements = PreparedStatements::new();
+        statements.capacity = 2;
+
+        let first = statements.prepare("SELECT * FROM flights");
+        statements.prepare("SELECT * FROM airports");
+        statements.prepare("SELECT * FROM passengers");
+
+        assert!(matches!(
+            statements.bind(&first, &[], flights),
+            Err(Error::Unprepared(_))
+        ));
+        assert_eq!(statements.statements.len(), 2);
+    }
+}
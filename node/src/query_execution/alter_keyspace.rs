@@ -9,31 +9,19 @@ use super::QueryExecution;
 impl QueryExecution {
     pub(crate) fn execute_alter_keyspace(
         &mut self,
-        _alter_keyspace: AlterKeyspace,
+        alter_keyspace: AlterKeyspace,
     ) -> Result<(), NodeError> {
-        todo!()
-        // // Look for the keyspace in the list of keyspaces
-        // let mut node = self.node_that_execute.lock()?;
+        // Locks the node to ensure safe concurrent access
+        let mut node = self
+            .node_that_execute
+            .lock()
+            .map_err(|_| NodeError::LockError)?;
 
-        // let mut keyspace = node
-        //     .keyspaces
-        //     .iter()
-        //     .find(|k| k.get_name() == alter_keyspace.get_name())
-        //     .ok_or(NodeError::KeyspaceError)?
-        //     .clone();
+        // Updates the replication of the keyspace in the gossiped schema, so the queries opened
+        // from now on use the new replication factor
+        node.alter_keyspace(alter_keyspace)?;
 
-        // // Validate if the replication class and factor are the same to avoid unnecessary operations
-        // if keyspace.get_replication_class() == alter_keyspace.get_replication_class()
-        //     && keyspace.get_replication_factor() == alter_keyspace.get_replication_factor()
-        // {
-        //     return Ok(()); // No changes, nothing to execute
-        // }
-
-        // // Update the replication class and factor in the keyspace
-        // keyspace.update_replication_class(alter_keyspace.get_replication_class());
-        // keyspace.update_replication_factor(alter_keyspace.get_replication_factor());
-        // node.update_keyspace(client_id, keyspace);
-
-        // Ok(())
+        self.execution_finished_itself = true;
+        Ok(())
     }
 }
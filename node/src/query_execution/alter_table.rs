@@ -1,5 +1,6 @@
 // Ordered imports
 use crate::NodeError;
+use gossip::structures::application_state::TableSchema;
 use query_creator::clauses::table::alter_table_cql::AlterTable;
 use query_creator::clauses::types::alter_table_op::AlterTableOperation;
 use query_creator::errors::CQLError;
@@ -31,42 +32,49 @@ impl QueryExecution {
             .get_table(table_name.clone(), client_keyspace.clone())?
             .inner;
 
-        // Apply each alteration operation
+        // Apply each alteration operation to the schema. The data files of every node, this one
+        // included, are changed when it learns the new schema
         for operation in alter_table.get_operations() {
             match operation {
                 AlterTableOperation::AddColumn(column) => {
                     table.add_column(column.clone())?;
-                    self.storage_engine.add_column_to_table(
-                        &client_keyspace.get_name(),
-                        &table_name,
-                        &column.name,
-                    )?;
                 }
                 AlterTableOperation::DropColumn(column_name) => {
                     table.remove_column(&column_name)?;
-                    self.storage_engine.remove_column_from_table(
-                        &client_keyspace.get_name(),
-                        &table_name,
-                        &column_name,
-                    )?;
                 }
                 AlterTableOperation::ModifyColumn(_column_name, _new_data_type, _allows_null) => {
                     return Err(NodeError::CQLError(CQLError::InvalidSyntax));
                 }
                 AlterTableOperation::RenameColumn(old_name, new_name) => {
+                    // As in Cassandra, only the columns of the primary key can be renamed, so a
+                    // renamed column is never taken for one dropped and another one added
+                    let is_key = table.get_columns().iter().any(|column| {
+                        column.name == old_name
+                            && (column.is_partition_key || column.is_clustering_column)
+                    });
+                    if !is_key {
+                        return Err(NodeError::CQLError(CQLError::InvalidColumn));
+                    }
                     table.rename_column(&old_name, &new_name)?;
-                    self.storage_engine.rename_column_from_table(
-                        &client_keyspace.get_name(),
-                        &table_name,
-                        &old_name,
-                        &new_name,
-                    )?;
+                }
+                AlterTableOperation::SetOptions(options) => {
+                    // Options only change the schema, the stored rows stay as they are
+                    for option in options {
+                        table.options.apply(option);
+                    }
                 }
             }
         }
 
+        // A table can not get counters besides other columns, nor lose them to other columns
+        if !table.has_valid_counters() {
+            return Err(NodeError::CQLError(CQLError::InvalidColumn));
+        }
+
         // Save the updated table structure to the node
-        node.update_table(&client_keyspace.get_name(), table)?;
+        node.update_table(&client_keyspace.get_name(), table.clone())?;
+        node.get_open_handle_query()
+            .update_table_in_keyspace(&client_keyspace.get_name(), TableSchema::new(table))?;
 
         self.execution_finished_itself = true;
         Ok(())
@@ -0,0 +1,282 @@
+// Ordered imports
+use super::QueryExecution;
+use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::internode_protocol::query::InternodeQuery;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::utils::{connect_and_send_message, get_replicas};
+use crate::{Node, NodeError};
+use gossip::structures::application_state::TableSchema;
+use logger::{Color, Component};
+use query_creator::clauses::batch_cql::Batch;
+use query_creator::errors::CQLError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::Ipv4Addr;
+
+/// Executes a batch of `INSERT`, `UPDATE` and `DELETE` statements. This function is public only
+/// for internal use within the library (defined as `pub(crate)`).
+impl QueryExecution {
+    /// Groups the statements of the batch by the nodes that have to apply them (the owner of the
+    /// partition of each statement and its replicas) and sends every node its part in a single
+    /// message. The part of this node is applied here.
+    ///
+    /// Each node answers once for its part of the batch, and the open query waits, for every
+    /// partition of the batch, for the OK responses its consistency level requires from the
+    /// replicas of that partition, as a single write does. The parts are not sent again if a node
+    /// answers that it does not own a partition anymore, the response counts as an error instead.
+    ///
+    /// The batch is rejected as `Unavailable`, without applying any of it, if the replicas alive of
+    /// any of its partitions are not enough for its consistency level.
+    ///
+    /// The nodes keep no batch log: a `LOGGED` batch is applied like an `UNLOGGED` one, so a
+    /// coordinator that fails midway may leave some of its parts unapplied.
+    pub(crate) fn execute_batch(
+        &mut self,
+        batch: Batch,
+        open_query_id: i32,
+        client_id: i32,
+        timestamp: i64,
+    ) -> Result<(), NodeError> {
+        let mut failed_nodes = 0;
+        let local_part;
+        let (keyspace, statements) = {
+            let mut node = self.node_that_execute.lock()?;
+            let keyspace = node
+                .get_open_handle_query()
+                .get_keyspace_of_query(open_query_id)?
+                .ok_or(NodeError::CQLError(CQLError::NoActualKeyspaceError))?;
+            let mut statements = Vec::new();
+            for query in batch.statements {
+                let statement = InternodeStatement::from_query(&query)
+                    .ok_or(NodeError::CQLError(CQLError::InvalidSyntax))?;
+                let table = node.get_table(Self::table_of(&statement), keyspace.clone())?;
+                let partition = Self::partition_value(&statement, &table)?;
+                // Checked for every partition before any counter shard is written or any part
+                // is sent, so a batch that cannot be applied whole is not applied at all
+                let owner = node.get_partitioner().get_ip(partition.clone())?;
+                self.check_availability(&mut node, owner, open_query_id)?;
+                statements.push((statement, table, partition));
+            }
+            (keyspace, statements)
+        };
+
+        // Counters are incremented through the shards of this node, and never inserted. The
+        // shards are written to the counter shard log without holding the lock of the node.
+        let statements = statements
+            .into_iter()
+            .map(|(statement, table, partition)| {
+                let statement = match statement {
+                    InternodeStatement::Update(update) => InternodeStatement::Update(
+                        self.increments_as_shards(update, &table, &keyspace.get_name())?,
+                    ),
+                    InternodeStatement::Insert(insert) => {
+                        Self::check_not_counter_table(&table.get_columns())?;
+                        InternodeStatement::Insert(insert)
+                    }
+                    statement => statement,
+                };
+                Ok((statement, table, partition))
+            })
+            .collect::<Result<Vec<_>, NodeError>>()?;
+
+        {
+            let mut node = self.node_that_execute.lock()?;
+            let partitioner = node.get_partitioner();
+
+            // The statements each node has to apply, in the order of the batch
+            let mut parts: BTreeMap<Ipv4Addr, Vec<(bool, InternodeStatement)>> = BTreeMap::new();
+            // The owner and replicas of each partition, whose responses count for it
+            let mut partitions: BTreeSet<Vec<Ipv4Addr>> = BTreeSet::new();
+            for (statement, table, partition) in statements {
+                node.hot_partitions
+                    .record(&keyspace.get_name(), &table.get_name(), &partition);
+                let owner = partitioner.get_ip(partition)?;
+                let replicas = get_replicas(&partitioner, owner, &keyspace)?;
+
+                parts
+                    .entry(owner)
+                    .or_default()
+                    .push((false, statement.clone()));
+                for replica in &replicas {
+                    parts
+                        .entry(*replica)
+                        .or_default()
+                        .push((true, statement.clone()));
+                }
+                partitions.insert(std::iter::once(owner).chain(replicas).collect());
+            }
+
+            node.get_open_handle_query()
+                .set_batch_partitions(open_query_id, partitions.into_iter().collect());
+
+            let self_ip = node.get_ip();
+            local_part = parts.get(&self_ip).cloned();
+            for (ip, statements) in parts {
+                let statement = InternodeStatement::Batch(statements);
+                let query = InternodeQuery {
+                    query_string: String::new(),
+                    open_query_id: open_query_id as u32,
+                    client_id: client_id as u32,
+                    replication: false,
+                    keyspace_name: keyspace.get_name(),
+                    timestamp,
+                    correlation_id: self.logger.correlation_id().unwrap_or_default().to_string(),
+                    statement: Some(statement),
+                    time_left: node.get_open_handle_query().time_left(open_query_id),
+                    digest: false,
+                };
+                // Kept so a batch that times out tells which nodes missed their part
+                node.get_open_handle_query().record_sent_query(
+                    open_query_id,
+                    ip,
+                    query.clone(),
+                    None,
+                );
+                if ip != self_ip {
+                    failed_nodes += self.send_batch_to_node(&mut node, ip, query)?;
+                }
+            }
+        }
+
+        if let Some(statements) = local_part {
+            self.execute_batch_part(statements, open_query_id, client_id, timestamp)?;
+            self.execution_finished_itself = true;
+        }
+
+        // Applying the local part resets the count of the statements it runs
+        self.how_many_nodes_failed = failed_nodes;
+        Ok(())
+    }
+
+    /// Applies, in order, the statements of a batch that were sent to this node. Each statement
+    /// is applied as a replica or as the owner of its partition, as the coordinator decided.
+    pub(crate) fn execute_batch_part(
+        &mut self,
+        statements: Vec<(bool, InternodeStatement)>,
+        open_query_id: i32,
+        client_id: i32,
+        timestamp: i64,
+    ) -> Result<(), NodeError> {
+        for (replication, statement) in statements {
+            match statement {
+                InternodeStatement::Insert(insert) => {
+                    let table = {
+                        let mut node = self.node_that_execute.lock()?;
+                        let keyspace = node
+                            .get_open_handle_query()
+                            .get_keyspace_of_query(open_query_id)?
+                            .ok_or(NodeError::CQLError(CQLError::NoActualKeyspaceError))?;
+                        node.get_table(insert.into_clause.table_name.clone(), keyspace)?
+                    };
+                    self.execute_insert(
+                        insert,
+                        table,
+                        true,
+                        replication,
+                        open_query_id,
+                        client_id,
+                        timestamp,
+                    )?
+                }
+                InternodeStatement::Update(update) => self.execute_update(
+                    update,
+                    true,
+                    replication,
+                    open_query_id,
+                    client_id,
+                    timestamp,
+                )?,
+                InternodeStatement::Delete(delete) => self.execute_delete(
+                    delete,
+                    true,
+                    replication,
+                    open_query_id,
+                    client_id,
+                    timestamp,
+                )?,
+                _ => return Err(NodeError::InternodeProtocolError),
+            }
+        }
+        Ok(())
+    }
+
+    fn table_of(statement: &InternodeStatement) -> String {
+        match statement {
+            InternodeStatement::Insert(insert) => insert.into_clause.table_name.clone(),
+            InternodeStatement::Update(update) => update.table_name.clone(),
+            InternodeStatement::Delete(delete) => delete.table_name.clone(),
+            InternodeStatement::Select(select) => select.table_name.clone(),
+            InternodeStatement::Batch(_) => String::new(),
+        }
+    }
+
+    // Returns the value hashed to find the owner of the partition a statement writes, computed
+    // as the execution of the statement does.
+    fn partition_value(
+        statement: &InternodeStatement,
+        table: &TableSchema,
+    ) -> Result<String, NodeError> {
+        let where_clause = match statement {
+            InternodeStatement::Insert(insert) => {
+                return table
+                    .get_columns()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, column)| column.is_partition_key)
+                    .map(|(index, _)| {
+                        insert.values.get(index).cloned().ok_or(NodeError::CQLError(
+                            CQLError::MissingPartitionOrClusteringColumns,
+                        ))
+                    })
+                    .collect::<Result<Vec<String>, NodeError>>()
+                    .map(|values| values.join(""));
+            }
+            InternodeStatement::Update(update) => update.where_clause.clone(),
+            InternodeStatement::Delete(delete) => delete.where_clause.clone(),
+            _ => return Err(NodeError::InternodeProtocolError),
+        };
+
+        Ok(where_clause
+            .ok_or(NodeError::CQLError(CQLError::NoWhereCondition))?
+            .get_value_partitioner_key_condition(table.get_partition_keys()?)?
+            .join(""))
+    }
+
+    // Sends a node its part of the batch. Returns 1 if it could not be sent, in which case it is
+    // kept as a hint for the node, unless the hint acknowledges an `ANY` batch.
+    fn send_batch_to_node(
+        &self,
+        local_node: &mut Node,
+        target_ip: Ipv4Addr,
+        query: InternodeQuery,
+    ) -> Result<i32, NodeError> {
+        self.logger.with_component(Component::Internode).info(
+            &format!(
+                "INTERNODE (Query: {:?}): I SENT BATCH {:?} to {:?}",
+                query.open_query_id,
+                query.cql(),
+                target_ip
+            ),
+            Color::Green,
+            true,
+        )?;
+
+        let result = connect_and_send_message(
+            target_ip,
+            local_node.config.internode_port,
+            self.connections.clone(),
+            InternodeMessage::new(
+                local_node.get_ip(),
+                InternodeMessageContent::Query(query.clone()),
+            ),
+        );
+
+        let open_query_id = query.open_query_id as i32;
+        if !Self::was_sent(local_node, open_query_id, target_ip, result)
+            && !self.store_hint(local_node, target_ip, &query, &self.logger)?
+        {
+            return Ok(1);
+        }
+
+        Ok(0)
+    }
+}
@@ -10,6 +10,7 @@ impl QueryExecution {
     pub(crate) fn execute_create_keyspace(
         &mut self,
         create_keyspace: CreateKeyspace,
+        open_query_id: i32,
     ) -> Result<(), NodeError> {
         // Locks the node to ensure safe concurrent access
 
@@ -18,11 +19,14 @@ impl QueryExecution {
             .lock()
             .map_err(|_| NodeError::LockError)?;
 
-        // Adds the keyspace to the node
-        if let Err(e) = node.add_keyspace(create_keyspace.clone()) {
-            if !create_keyspace.if_not_exists_clause {
-                return Err(e);
-            }
+        // With IF NOT EXISTS an existing keyspace is left as it is and the client gets no schema change
+        if create_keyspace.if_not_exists_clause
+            && node.get_keyspace(&create_keyspace.get_name())?.is_some()
+        {
+            node.get_open_handle_query().mark_as_no_op(open_query_id);
+        } else {
+            // Adds the keyspace to the node
+            node.add_keyspace(create_keyspace.clone())?;
         }
 
         self.execution_finished_itself = true;
@@ -14,6 +14,11 @@ impl QueryExecution {
         create_table: CreateTable,
         open_query_id: i32,
     ) -> Result<(), NodeError> {
+        // Counters can not be part of the primary key nor be mixed with other columns
+        if !create_table.has_valid_counters() {
+            return Err(NodeError::CQLError(CQLError::InvalidColumn));
+        }
+
         // Add the table to the node
         let mut node = self
             .node_that_execute
@@ -25,16 +30,21 @@ impl QueryExecution {
             .get_keyspace_of_query(open_query_id)?
             .ok_or(NodeError::CQLError(CQLError::NoActualKeyspaceError))?;
 
-        if let Err(e) = node.add_table(create_table.clone(), &client_keyspace.get_name()) {
-            if !create_table.get_if_not_exists_clause() {
-                return Err(e);
-            }
-        }
+        let table_exists = node
+            .get_keyspace(&client_keyspace.get_name())?
+            .is_some_and(|keyspace| keyspace.get_table(&create_table.get_name()).is_ok());
 
-        node.get_open_handle_query().update_table_in_keyspace(
-            &client_keyspace.get_name(),
-            TableSchema::new(create_table.clone()),
-        )?;
+        // With IF NOT EXISTS an existing table is left as it is and the client gets no schema change
+        if table_exists && create_table.get_if_not_exists_clause() {
+            node.get_open_handle_query().mark_as_no_op(open_query_id);
+        } else {
+            node.add_table(create_table.clone(), &client_keyspace.get_name())?;
+
+            node.get_open_handle_query().update_table_in_keyspace(
+                &client_keyspace.get_name(),
+                TableSchema::new(create_table.clone()),
+            )?;
+        }
 
         self.execution_finished_itself = true;
 
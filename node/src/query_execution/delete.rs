@@ -1,5 +1,7 @@
 // Ordered imports
 use super::QueryExecution;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::storage_engine::commitlog::Mutation;
 use crate::CQLError;
 use crate::NodeError;
 use query_creator::clauses::delete_cql::Delete;
@@ -67,15 +69,26 @@ impl QueryExecution {
                 .get_value_partitioner_key_condition(partition_keys)?
                 .join("");
             let node_to_delete = node.partitioner.get_ip(value_to_hash.clone())?;
+            // Reject the query if it was sent with an outdated view of the ring
+            if internode {
+                self.check_ownership(&mut node, &value_to_hash, replication, open_query_id)?;
+            } else {
+                self.check_availability(&mut node, node_to_delete, open_query_id)?;
+                node.hot_partitions.record(
+                    &client_keyspace.get_name(),
+                    &table_name,
+                    &value_to_hash,
+                );
+            }
             let self_ip = node.get_ip().clone();
-            let logger = node.get_logger();
+            let logger = self.logger.clone();
             // Forward the DELETE operation if the responsible node is different and not an internode operation
             if !internode && node_to_delete != self_ip {
-                let serialized_delete = delete_query.serialize();
                 failed_nodes = self.send_to_single_node(
-                    node.get_ip(),
+                    &mut node,
                     node_to_delete,
-                    &serialized_delete,
+                    InternodeStatement::Delete(delete_query.clone()),
+                    &value_to_hash,
                     open_query_id,
                     client_id,
                     &client_keyspace.get_name(),
@@ -87,11 +100,11 @@ impl QueryExecution {
 
             // Send DELETE to replication nodes if required
             if !internode {
-                let serialized_delete = delete_query.serialize();
                 (internode_failed_nodes, replication) = self.send_to_replication_nodes(
                     node,
                     node_to_delete,
-                    &serialized_delete,
+                    InternodeStatement::Delete(delete_query.clone()),
+                    &value_to_hash,
                     open_query_id,
                     client_id,
                     &client_keyspace.get_name(),
@@ -119,6 +132,13 @@ impl QueryExecution {
             self.execution_replicate_itself = true;
         }
 
+        let _logged = self.log_mutation(
+            &client_keyspace.get_name(),
+            &table.get_name(),
+            replication,
+            timestamp,
+            Mutation::Delete(delete_query.clone()),
+        )?;
         self.storage_engine.delete(
             delete_query,
             table,
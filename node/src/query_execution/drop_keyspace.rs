@@ -10,6 +10,7 @@ impl QueryExecution {
     pub(crate) fn execute_drop_keyspace(
         &mut self,
         drop_keyspace: DropKeyspace,
+        open_query_id: i32,
     ) -> Result<(), NodeError> {
         // Get the name of the keyspace to delete
         let keyspace_name = drop_keyspace.get_name().clone();
@@ -20,7 +21,15 @@ impl QueryExecution {
             .lock()
             .map_err(|_| NodeError::LockError)?;
 
-        node.remove_keyspace(keyspace_name.clone())?;
+        if node.get_keyspace(&keyspace_name)?.is_none() {
+            // With IF EXISTS a missing keyspace is not an error and the client gets no schema change
+            if !drop_keyspace.get_if_exists_clause() {
+                return Err(NodeError::KeyspaceError);
+            }
+            node.get_open_handle_query().mark_as_no_op(open_query_id);
+        } else {
+            node.remove_keyspace(keyspace_name.clone())?;
+        }
 
         self.execution_finished_itself = true;
         Ok(())
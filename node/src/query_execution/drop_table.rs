@@ -2,6 +2,7 @@
 use super::QueryExecution;
 use crate::NodeError;
 use query_creator::clauses::table::drop_table_cql::DropTable;
+use query_creator::errors::CQLError;
 
 /// Executes the deletion of a table. This function is public only for internal use
 /// within the library (defined as `pub(crate)`).
@@ -19,8 +20,25 @@ impl QueryExecution {
         // Get the name of the table to delete
         let table_name = drop_table.get_table_name();
 
-        // Lock the node and remove the table from the internal list
-        node.remove_table(table_name.clone(), open_query_id)?;
+        let keyspace_name = node
+            .get_open_handle_query()
+            .get_keyspace_of_query(open_query_id)?
+            .ok_or(NodeError::KeyspaceError)?
+            .get_name();
+        let table_exists = node
+            .get_keyspace(&keyspace_name)?
+            .is_some_and(|keyspace| keyspace.get_table(&table_name).is_ok());
+
+        if !table_exists {
+            // With IF EXISTS a missing table is not an error and the client gets no schema change
+            if !drop_table.get_if_exists_clause() {
+                return Err(NodeError::CQLError(CQLError::InvalidTable));
+            }
+            node.get_open_handle_query().mark_as_no_op(open_query_id);
+        } else {
+            // Lock the node and remove the table from the internal list
+            node.remove_table(table_name.clone(), open_query_id)?;
+        }
 
         self.execution_finished_itself = true;
 
@@ -1,5 +1,8 @@
 // Ordered imports
 // use crate::table::Table;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::storage_engine::commitlog::Mutation;
+use crate::storage_engine::row_stamp::RowStamp;
 use crate::NodeError;
 use gossip::structures::application_state::TableSchema;
 use query_creator::clauses::insert_cql::Insert;
@@ -39,6 +42,9 @@ impl QueryExecution {
 
         // Retrieve columns and the partition keys
         let columns = table_to_insert.get_columns();
+        if !internode {
+            Self::check_not_counter_table(&columns)?;
+        }
 
         let mut keys_index: Vec<usize> = columns
             .iter()
@@ -94,17 +100,29 @@ impl QueryExecution {
 
         // Deterclient_keyspacemine the node responsible for the insert
         let node_to_insert = node.get_partitioner().get_ip(value_to_hash.clone())?;
+        // Reject the query if it was sent with an outdated view of the ring
+        if internode {
+            self.check_ownership(&mut node, &value_to_hash, replication, open_query_id)?;
+        } else {
+            self.check_availability(&mut node, node_to_insert, open_query_id)?;
+            // Only the coordinator counts the write, not the replicas it sends it to
+            node.hot_partitions.record(
+                &client_keyspace.get_name(),
+                &table_to_insert.get_name(),
+                &value_to_hash,
+            );
+        }
         let self_ip = node.get_ip().clone();
         let keyspace_name = client_keyspace.get_name();
-        let logger = node.get_logger();
+        let logger = self.logger.clone();
         // If not internode and the target IP differs, forward the insert
         if !internode {
             if node_to_insert != self_ip {
-                let serialized_insert = new_insert.serialize();
                 failed_nodes = self.send_to_single_node(
-                    node.get_ip(),
+                    &mut node,
                     node_to_insert,
-                    &serialized_insert,
+                    InternodeStatement::Insert(new_insert.clone()),
+                    &value_to_hash,
                     open_query_id,
                     client_id,
                     &client_keyspace.get_name(),
@@ -117,11 +135,11 @@ impl QueryExecution {
             }
 
             // Send the insert to replication nodes
-            let serialized_insert = new_insert.serialize();
             (internode_failed_nodes, replication) = self.send_to_replication_nodes(
                 node,
                 node_to_insert,
-                &serialized_insert,
+                InternodeStatement::Insert(new_insert.clone()),
+                &value_to_hash,
                 open_query_id,
                 client_id,
                 &client_keyspace.get_name(),
@@ -144,6 +162,18 @@ impl QueryExecution {
         // If this node is responsible for the insert, execute it here
         keys_index.extend(&clustering_columns_index);
 
+        let _logged = self.log_mutation(
+            &keyspace_name,
+            &insert_query.into_clause.table_name,
+            replication,
+            timestap,
+            Mutation::insert(
+                &table_to_insert,
+                values.clone(),
+                insert_query.if_not_exists,
+                insert_query.ttl,
+            ),
+        )?;
         self.storage_engine.insert(
             &keyspace_name,
             &insert_query.into_clause.table_name,
@@ -152,11 +182,33 @@ impl QueryExecution {
             table_to_insert.get_clustering_column_in_order(),
             replication,
             insert_query.if_not_exists,
-            timestap,
+            RowStamp::new(
+                timestap,
+                table_to_insert.get_options().ttl_for(insert_query.ttl),
+            ),
         )?;
         Ok(())
     }
 
+    /// Returns the row written by an `INSERT ... IF NOT EXISTS`, with a value for every column of
+    /// the table. A lightweight transaction only writes it if the table has no row with its
+    /// primary key.
+    pub(crate) fn conditional_insert_row(
+        &self,
+        insert_query: &Insert,
+        table: &TableSchema,
+    ) -> Result<Vec<String>, NodeError> {
+        let columns = table.get_columns();
+        Self::check_not_counter_table(&columns)?;
+        let values = self.complete_row(
+            columns.clone(),
+            insert_query.into_clause.columns.clone(),
+            insert_query.values.clone(),
+        )?;
+        self.validate_values(columns, &values)?;
+        Ok(values)
+    }
+
     fn complete_row(
         &self,
         columns: Vec<Column>,
@@ -0,0 +1,445 @@
+// Ordered imports
+use super::QueryExecution;
+use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::internode_protocol::paxos::{Ballot, PaxosPhase, PaxosReply, PaxosRequest, Proposal};
+use crate::storage_engine::row_stamp::split_row;
+use crate::utils::{connect_and_send_message, get_replicas};
+use crate::{Node, NodeError};
+use chrono::Utc;
+use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
+use logger::Color;
+use native_protocol::frame::Frame;
+use native_protocol::messages::error::{WriteTimeout, WriteType};
+use native_protocol::messages::query::Consistency;
+use native_protocol::messages::result::result_;
+use native_protocol::messages::result::rows::{ColumnType, ColumnValue, Rows};
+use query_creator::clauses::update_cql::Update;
+use query_creator::errors::CQLError;
+use query_creator::{GetTableName, Query};
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Name of the only column of the result of a lightweight transaction, which tells whether its
+/// condition held and its row was written.
+pub const APPLIED_COLUMN: &str = "[applied]";
+
+/// Paxos rounds a lightweight transaction starts before giving up, when newer rounds of other
+/// coordinators take their place.
+const LWT_ATTEMPTS: u64 = 3;
+
+/// Time the coordinator waits for the replicas in each phase of a Paxos round.
+const PAXOS_PHASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns whether a query is a lightweight transaction (`INSERT ... IF NOT EXISTS` or
+/// `UPDATE ... IF`), which is run with a Paxos round on the replicas of its partition instead of
+/// as a plain write.
+pub fn is_lightweight_transaction(query: &Query) -> bool {
+    match query {
+        Query::Insert(insert) => insert.if_not_exists,
+        Query::Update(update) => update.if_clause.is_some(),
+        _ => false,
+    }
+}
+
+// What a lightweight transaction writes, once its condition is checked on the current row
+enum ConditionalWrite {
+    Insert(Vec<String>),
+    Update(Update),
+}
+
+// The row of a lightweight transaction and the replicas that store it
+struct PaxosTarget {
+    keyspace: String,
+    table: TableSchema,
+    partition: String,
+    key: String,
+    replicas: Vec<Ipv4Addr>,
+    self_ip: Ipv4Addr,
+}
+
+impl PaxosTarget {
+    fn quorum(&self) -> usize {
+        self.replicas.len() / 2 + 1
+    }
+
+    // The details of a `WriteTimeout` of the transaction, once `received` replicas took part in it
+    fn timeout(&self, received: usize) -> WriteTimeout {
+        WriteTimeout {
+            consistency: Consistency::Serial,
+            received: received as i32,
+            block_for: self.quorum() as i32,
+            write_type: WriteType::Cas,
+        }
+    }
+
+    fn request(
+        &self,
+        id: u32,
+        phase: PaxosPhase,
+        ballot: Ballot,
+        proposal: Option<Proposal>,
+    ) -> PaxosRequest {
+        PaxosRequest {
+            id,
+            phase,
+            ballot,
+            keyspace: self.keyspace.clone(),
+            table: self.table.get_name(),
+            partition: self.partition.clone(),
+            key: self.key.clone(),
+            proposal,
+        }
+    }
+}
+
+impl QueryExecution {
+    /// Executes a lightweight transaction, writing its row only if its condition holds on the
+    /// row the replicas of its partition agree is the current one.
+    ///
+    /// # Purpose
+    /// Plain writes are applied by each replica on its own, so two clients that check a row and then write
+    /// it can both succeed. Lightweight transactions run a Paxos round among the replicas of the partition
+    /// instead, so exactly one of the concurrent conditional writes of a row is applied.
+    ///
+    /// # Parameters
+    /// - `query: &Query`
+    ///   - An `INSERT ... IF NOT EXISTS` or an `UPDATE ... IF`, whose `WHERE` clause names the whole primary key.
+    /// - `keyspace: &KeyspaceSchema`
+    ///   - The keyspace of the table of the query.
+    ///
+    /// # Returns
+    /// - `Result<Frame, NodeError>`
+    ///   - On success:
+    ///     - Returns a `Rows` result with a single row and a boolean `[applied]` column, which tells whether
+    ///       the condition held and the row was written.
+    ///   - On failure:
+    ///     - Returns `Err(NodeError::WriteTimeout)` if a quorum of the replicas did not take part in any of the
+    ///       rounds, or did not acknowledge the write.
+    ///
+    /// # Behavior
+    /// 1. **Prepare**:
+    ///    - Asks the replicas to promise a new ballot. Each one answers with its row with the primary key of
+    ///      the query, and the row it accepted in a round that was not committed, if any.
+    ///    - A round that was not committed is finished first, and then a new round is started.
+    /// 2. **Condition**:
+    ///    - Checks the condition on the newest of the rows of the replicas that promised: an insert needs
+    ///      the row not to exist, and an update needs it to exist and to match its `IF` clause.
+    /// 3. **Propose and Commit**:
+    ///    - Asks the replicas to accept the written row and, once a quorum did, to write it.
+    ///
+    /// # Notes
+    /// - Every phase needs a quorum of the replicas of the partition, whatever the consistency level of the query.
+    /// - A round that loses to a newer one is started again, up to `LWT_ATTEMPTS` times.
+    pub(crate) fn execute_lightweight_transaction(
+        &mut self,
+        query: &Query,
+        keyspace: &KeyspaceSchema,
+    ) -> Result<Frame, NodeError> {
+        let (target, write) = self.paxos_target(query, keyspace)?;
+        let ttl = match query {
+            Query::Insert(insert) => insert.ttl,
+            Query::Update(update) => update.ttl,
+            _ => None,
+        };
+        let mut last_ballot: Option<Ballot> = None;
+
+        for attempt in 0..LWT_ATTEMPTS {
+            if attempt > 0 {
+                // Coordinators that lost to each other wait different times before trying again
+                let jitter = Uuid::new_v4().as_u128() as u64 % 20;
+                thread::sleep(Duration::from_millis(attempt * 20 + jitter));
+            }
+            let ballot = Self::new_ballot(target.self_ip, last_ballot);
+            last_ballot = Some(ballot);
+
+            let promises: Vec<PaxosReply> = self
+                .run_paxos_phase(&target, PaxosPhase::Prepare, ballot, None)?
+                .into_iter()
+                .filter(|reply| reply.ok)
+                .collect();
+            if promises.len() < target.quorum() {
+                continue;
+            }
+
+            // The round of another coordinator was accepted and not committed, it is finished
+            // first. Rows accepted before the newest commit were already replaced by it
+            let committed = promises.iter().filter_map(|reply| reply.committed).max();
+            if let Some((_, proposal)) = promises
+                .iter()
+                .filter_map(|reply| reply.in_progress.clone())
+                .filter(|(accepted, _)| committed.is_none_or(|committed| *accepted > committed))
+                .max_by_key(|(accepted, _)| *accepted)
+            {
+                self.logger.info(
+                    &format!("PAXOS: FINISHING A ROUND IN PROGRESS ON {:?}", target.key),
+                    Color::Cyan,
+                    true,
+                )?;
+                self.propose_and_commit(&target, ballot, proposal)?;
+                continue;
+            }
+
+            let current = promises
+                .iter()
+                .filter_map(|reply| reply.current.as_deref())
+                .filter_map(|line| split_row(line).ok())
+                .max_by_key(|(_, stamp)| stamp.timestamp)
+                .map(|(values, _)| {
+                    values
+                        .split(',')
+                        .map(|value| value.to_string())
+                        .collect::<Vec<String>>()
+                });
+
+            let row = match (&write, current) {
+                (ConditionalWrite::Insert(row), None) => Some(row.clone()),
+                (ConditionalWrite::Insert(_), Some(_)) => None,
+                (ConditionalWrite::Update(update), Some(current)) => {
+                    Self::conditional_update_row(update, &target.table, &current)?
+                }
+                (ConditionalWrite::Update(_), None) => None,
+            };
+            let Some(row) = row else {
+                return Ok(Self::applied_frame(false));
+            };
+
+            let proposal = Proposal {
+                row: row.join(","),
+                ttl,
+            };
+            if self.propose_and_commit(&target, ballot, proposal)? {
+                return Ok(Self::applied_frame(true));
+            }
+        }
+
+        Err(NodeError::WriteTimeout(
+            format!(
+                "Lightweight transaction on {:?} lost {} Paxos rounds",
+                target.key, LWT_ATTEMPTS
+            ),
+            target.timeout(0),
+        ))
+    }
+
+    // Finds the row of a lightweight transaction and the replicas of its partition
+    fn paxos_target(
+        &self,
+        query: &Query,
+        keyspace: &KeyspaceSchema,
+    ) -> Result<(PaxosTarget, ConditionalWrite), NodeError> {
+        let mut node = self.node_that_execute.lock()?;
+        let table_name = query
+            .get_table_name()
+            .ok_or(NodeError::CQLError(CQLError::InvalidTable))?;
+        let table = node.get_table(table_name, keyspace.clone())?;
+        let columns = table.get_columns();
+
+        let (key_values, write) = match query {
+            Query::Insert(insert) => {
+                let row = self.conditional_insert_row(insert, &table)?;
+                (row.clone(), ConditionalWrite::Insert(row))
+            }
+            Query::Update(update) => {
+                let where_clause = update
+                    .where_clause
+                    .as_ref()
+                    .ok_or(NodeError::CQLError(CQLError::NoWhereCondition))?;
+                let mut values = vec![String::new(); columns.len()];
+                for (index, column) in columns.iter().enumerate() {
+                    if column.is_partition_key || column.is_clustering_column {
+                        values[index] = where_clause
+                            .get_value_for_clustering_column(&column.name)
+                            .ok_or(NodeError::CQLError(
+                                CQLError::MissingPartitionOrClusteringColumns,
+                            ))?;
+                    }
+                }
+                (values, ConditionalWrite::Update(update.clone()))
+            }
+            _ => return Err(NodeError::CQLError(CQLError::InvalidSyntax)),
+        };
+
+        let value_of = |partition_key: bool| -> Vec<String> {
+            columns
+                .iter()
+                .zip(&key_values)
+                .filter(|(column, _)| {
+                    column.is_partition_key || (!partition_key && column.is_clustering_column)
+                })
+                .map(|(_, value)| value.clone())
+                .collect()
+        };
+        let partition = value_of(true).join("");
+        let key = value_of(false).join(",");
+
+        node.hot_partitions
+            .record(&keyspace.get_name(), &table.get_name(), &partition);
+        let partitioner = node.get_partitioner();
+        let owner = partitioner.get_ip(partition.clone())?;
+        let mut replicas = vec![owner];
+        replicas.extend(get_replicas(&partitioner, owner, keyspace)?);
+
+        let target = PaxosTarget {
+            keyspace: keyspace.get_name(),
+            table,
+            partition,
+            key,
+            replicas,
+            self_ip: node.get_ip(),
+        };
+        Ok((target, write))
+    }
+
+    // Asks the replicas to accept a row and, if a quorum did, to write it. Returns whether a
+    // quorum accepted it.
+    fn propose_and_commit(
+        &self,
+        target: &PaxosTarget,
+        ballot: Ballot,
+        proposal: Proposal,
+    ) -> Result<bool, NodeError> {
+        let accepted = self
+            .run_paxos_phase(target, PaxosPhase::Propose, ballot, Some(proposal.clone()))?
+            .iter()
+            .filter(|reply| reply.ok)
+            .count();
+        if accepted < target.quorum() {
+            return Ok(false);
+        }
+
+        let committed = self
+            .run_paxos_phase(target, PaxosPhase::Commit, ballot, Some(proposal))?
+            .iter()
+            .filter(|reply| reply.ok)
+            .count();
+        if committed < target.quorum() {
+            return Err(NodeError::WriteTimeout(
+                format!(
+                    "Only {} replicas wrote the row of the lightweight transaction on {:?}",
+                    committed, target.key
+                ),
+                target.timeout(committed),
+            ));
+        }
+        Ok(true)
+    }
+
+    // Sends a phase of a Paxos round to the replicas of the partition, answering the one of this
+    // node here, and returns their replies once a quorum said ok, all of them answered or the
+    // phase timed out
+    fn run_paxos_phase(
+        &self,
+        target: &PaxosTarget,
+        phase: PaxosPhase,
+        ballot: Ballot,
+        proposal: Option<Proposal>,
+    ) -> Result<Vec<PaxosReply>, NodeError> {
+        let (sender, receiver) = mpsc::channel();
+        let (id, port) = {
+            let mut node = self.node_that_execute.lock()?;
+            node.last_paxos_id = node.last_paxos_id.wrapping_add(1);
+            let id = node.last_paxos_id;
+            node.pending_paxos.insert(id, sender);
+            (id, node.config.internode_port)
+        };
+        let request = target.request(id, phase, ballot, proposal);
+
+        let mut replies = Vec::new();
+        let mut waiting = 0;
+        for replica in &target.replicas {
+            if *replica == target.self_ip {
+                replies.push(Node::answer_paxos(&self.node_that_execute, &request)?);
+                continue;
+            }
+            let message = InternodeMessage::new(
+                target.self_ip,
+                InternodeMessageContent::Paxos(request.clone()),
+            );
+            if connect_and_send_message(*replica, port, self.connections.clone(), message).is_ok() {
+                waiting += 1;
+            }
+        }
+
+        let deadline = Instant::now() + PAXOS_PHASE_TIMEOUT;
+        while waiting > 0 && replies.iter().filter(|reply| reply.ok).count() < target.quorum() {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            match receiver.recv_timeout(left) {
+                Ok(reply) => {
+                    replies.push(reply);
+                    waiting -= 1;
+                }
+                Err(_) => break,
+            }
+        }
+        self.node_that_execute.lock()?.pending_paxos.remove(&id);
+        Ok(replies)
+    }
+
+    // Returns a ballot newer than the last one of this coordinator, even within the same
+    // microsecond
+    fn new_ballot(self_ip: Ipv4Addr, last: Option<Ballot>) -> Ballot {
+        let micros = Utc::now().timestamp_micros();
+        let micros = match last {
+            Some(last) if last.micros >= micros => last.micros + 1,
+            _ => micros,
+        };
+        Ballot {
+            micros,
+            node: self_ip,
+        }
+    }
+
+    fn applied_frame(applied: bool) -> Frame {
+        let record = BTreeMap::from([(APPLIED_COLUMN.to_string(), ColumnValue::Boolean(applied))]);
+        Frame::Result(result_::Result::Rows(Rows::new(
+            vec![(APPLIED_COLUMN.to_string(), ColumnType::Boolean)],
+            vec![record],
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::QueryCreator;
+
+    fn query(cql: &str) -> Query {
+        QueryCreator::new().handle_query(cql.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_lightweight_transactions_are_the_conditional_writes() {
+        assert!(is_lightweight_transaction(&query(
+            "INSERT INTO flights (id, status) VALUES ('AR1', 'boarding') IF NOT EXISTS"
+        )));
+        assert!(is_lightweight_transaction(&query(
+            "UPDATE flights SET status = 'landed' WHERE id = 'AR1' IF status = 'boarding'"
+        )));
+        assert!(!is_lightweight_transaction(&query(
+            "INSERT INTO flights (id, status) VALUES ('AR1', 'boarding')"
+        )));
+        assert!(!is_lightweight_transaction(&query(
+            "UPDATE flights SET status = 'landed' WHERE id = 'AR1'"
+        )));
+    }
+
+    #[test]
+    fn test_ballots_of_a_coordinator_always_grow() {
+        let ip = Ipv4Addr::new(127, 0, 0, 1);
+        let future = Ballot {
+            micros: Utc::now().timestamp_micros() + 60_000_000,
+            node: ip,
+        };
+        assert_eq!(
+            QueryExecution::new_ballot(ip, Some(future)).micros,
+            future.micros + 1
+        );
+        assert!(QueryExecution::new_ballot(ip, None) < future);
+    }
+}
@@ -1,43 +1,59 @@
+use crate::connection_manager::ConnectionManager;
 use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
 use crate::internode_protocol::query::InternodeQuery;
 use crate::internode_protocol::response::{
     InternodeResponse, InternodeResponseContent, InternodeResponseStatus,
 };
-use crate::utils::connect_and_send_message;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::utils::{connect_and_send_message, get_replicas};
+use crate::Node;
 use crate::NodeError;
-use crate::{Node, INTERNODE_PORT};
-use logger::{Color, Logger};
-use query_creator::clauses::types::column::Column;
+use logger::{Color, Component, Logger};
+use partitioner::Partitioner;
+use query_creator::clauses::types::{column::Column, datatype::DataType};
 
 pub mod alter_keyspace;
 pub mod alter_table;
+pub mod batch;
 pub mod create_keyspace;
 pub mod create_table;
 pub mod delete;
 pub mod drop_keyspace;
 pub mod drop_table;
 pub mod insert;
+pub mod lwt;
 pub mod select;
 pub mod update;
 pub mod use_cql;
+use super::storage_engine::commitlog::{CommitLog, CommitLogEntry, LoggedMutation, Mutation};
+use super::storage_engine::counter::CounterShards;
 use super::storage_engine::StorageEngine;
 use query_creator::errors::CQLError;
 use query_creator::Query;
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, TcpStream};
-use std::path::PathBuf;
+use std::net::Ipv4Addr;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 // Si `node` es el módulo raíz
 
+/// Time a schema change waits for the live nodes to agree on the schema before it is answered,
+/// above the few seconds five nodes take to agree and under the time the driver waits for the
+/// answer to a schema change (`driver::SCHEMA_CHANGE_TIMEOUT`).
+const SCHEMA_AGREEMENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time between two checks of whether the live nodes agree on the schema.
+const SCHEMA_AGREEMENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Struct for executing various database queries across nodes with support
 /// for distributed communication and replication.
 pub struct QueryExecution {
     node_that_execute: Arc<Mutex<Node>>,
-    connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+    connections: ConnectionManager,
     execution_finished_itself: bool,
     execution_replicate_itself: bool,
     how_many_nodes_failed: i32,
     storage_engine: StorageEngine,
+    commit_log: Arc<Mutex<CommitLog>>,
+    logger: Logger,
 }
 
 impl QueryExecution {
@@ -52,13 +68,9 @@ impl QueryExecution {
     /// - `node_that_execute: Arc<Mutex<Node>>`
     ///   - A shared, thread-safe reference to the node responsible for executing queries.
     ///   - The node is locked during initialization to retrieve its IP address and other details.
-    /// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-    ///   - A shared, thread-safe map of active connections to other nodes in the cluster.
-    ///   - The key is a string representing the node address, and the value is a thread-safe `TcpStream`
-    ///     for communication with the corresponding node.
-    /// - `storage_path: PathBuf`
-    ///   - A file system path to the storage directory used by the `StorageEngine`.
-    ///   - This defines where local data for the node is stored.
+    /// - `connections: ConnectionManager`
+    ///   - The connections to the other nodes in the cluster, by their internode address, which the
+    ///     queries are sent to the replicas through.
     ///
     /// # Returns
     /// - `Result<QueryExecution, NodeError>`
@@ -70,33 +82,36 @@ impl QueryExecution {
     /// # Behavior
     /// 1. **Node Access**:
     ///    - Locks the `node_that_execute` mutex to safely access the node's details.
-    ///    - Retrieves the IP address of the node using `get_ip_string()`.
     /// 2. **Storage Engine Initialization**:
-    ///    - Creates a new instance of `StorageEngine` using the provided `storage_path` and the node's IP address.
+    ///    - Takes a clone of the `StorageEngine` of the node, which shares its memtables.
     /// 3. **QueryExecution Initialization**:
     ///    - Sets default values for execution-related flags:
     ///      - `execution_finished_itself`: `false` (indicates whether the execution is complete).
     ///      - `execution_replicate_itself`: `false` (indicates whether replication is complete).
     ///      - `how_many_nodes_failed`: `0` (initializes the failure counter for nodes).
     ///    - Assigns the `node_that_execute`, `connections`, and `storage_engine` to the `QueryExecution` object.
+    ///    - Uses the logger of the node until another one is set with `with_logger`.
     ///
     /// # Errors
     /// - Returns `NodeError` in the following cases:
     ///   - Failure to lock the `node_that_execute` mutex.
-    ///   - Failure to initialize the `StorageEngine` (e.g., invalid `storage_path` or node IP issues).
     ///
     /// # Notes
     /// - This function is designed to be thread-safe, utilizing `Arc` and `Mutex` for shared resources.
-    /// - Ensure that the `storage_path` is valid and accessible to avoid initialization errors.
 
     pub fn new(
         node_that_execute: Arc<Mutex<Node>>,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
-        storage_path: PathBuf,
+        connections: ConnectionManager,
     ) -> Result<QueryExecution, NodeError> {
-        let ip = { node_that_execute.lock()?.get_ip_string() };
+        let (storage_engine, logger, commit_log) = {
+            let node = node_that_execute.lock()?;
+            (
+                node.storage_engine.clone(),
+                node.get_logger(),
+                Arc::clone(&node.commit_log),
+            )
+        };
 
-        let storage_engine = StorageEngine::new(storage_path, ip);
         Ok(QueryExecution {
             node_that_execute,
             connections,
@@ -104,9 +119,59 @@ impl QueryExecution {
             execution_replicate_itself: false,
             how_many_nodes_failed: 0,
             storage_engine: storage_engine,
+            commit_log,
+            logger,
         })
     }
 
+    /// Appends a mutation to the commit log of the node, which must be done before applying it to the
+    /// storage so it can be replayed if the node crashes.
+    ///
+    /// # Parameters
+    /// - `keyspace: &str`, `table: &str`
+    ///   - The table of the mutation.
+    /// - `is_replication: bool`
+    ///   - Whether the mutation is applied to the replicas of the node instead of its own data.
+    /// - `timestamp: i64`
+    ///   - The timestamp of the mutation.
+    /// - `mutation: Mutation`
+    ///   - The mutation, as it is applied to the storage.
+    ///
+    /// # Returns
+    /// - `Result<LoggedMutation, NodeError>`
+    ///   - On success, returns the logged mutation, which must be kept until the mutation is applied.
+    ///   - On failure, returns `Err(NodeError)` if the log can not be written, and the mutation must not be applied.
+    fn log_mutation(
+        &self,
+        keyspace: &str,
+        table: &str,
+        is_replication: bool,
+        timestamp: i64,
+        mutation: Mutation,
+    ) -> Result<LoggedMutation, NodeError> {
+        Ok(CommitLog::log(
+            &self.commit_log,
+            &CommitLogEntry {
+                keyspace: keyspace.to_string(),
+                table: table.to_string(),
+                is_replication,
+                timestamp,
+                mutation,
+            },
+        )?)
+    }
+
+    /// Sets the logger used while executing the query.
+    ///
+    /// # Purpose
+    /// Lets the caller pass a logger tagged with the correlation ID of the client query, so every
+    /// log line written during the execution (and the ID sent to other nodes) can be traced back
+    /// to that query. By default the logger of the node is used.
+    pub fn with_logger(mut self, logger: Logger) -> Self {
+        self.logger = logger;
+        self
+    }
+
     /// Executes a query against the database, with support for various query types
     /// (e.g., SELECT, INSERT, UPDATE, DELETE, etc.) and internode communication.
     ///
@@ -120,6 +185,7 @@ impl QueryExecution {
     ///     - `Query::CreateTable`, `Query::DropTable`, `Query::AlterTable` for table management.
     ///     - `Query::CreateKeyspace`, `Query::DropKeyspace`, `Query::AlterKeyspace` for keyspace management.
     ///     - `Query::Use` for switching keyspaces.
+    ///     - `Query::Batch` for batches of INSERT, UPDATE and DELETE queries.
     /// - `internode: bool`
     ///   - If `true`, enables internode communication for the query, involving other nodes in the cluster.
     /// - `replication: bool`
@@ -161,13 +227,19 @@ impl QueryExecution {
     /// - **Table and Keyspace Management**:
     ///   - Handles `CREATE`, `DROP`, and `ALTER` operations for tables and keyspaces.
     ///   - Operations are forwarded to specific handlers like `execute_create_table`.
+    ///   - The client is answered once the live nodes agree on the schema, or after
+    ///     `SCHEMA_AGREEMENT_TIMEOUT` with a warning (see `wait_for_schema_agreement`).
     /// - **USE Queries**:
     ///   - Switches the keyspace context for subsequent queries.
+    /// - **BATCH Queries**:
+    ///   - Groups the statements by the nodes that have to apply them and sends every node its
+    ///     part in a single message (see `execute_batch`).
     ///
     /// # Internode Communication
     /// - If `internode` is enabled, the function constructs an `InternodeResponse` object:
     ///   - `Ok`: Indicates the query succeeded.
-    ///   - `Error`: Captures failures, logs the error, and updates the response status.
+    ///   - Any other status: Captures failures, logs the error and answers with the status that
+    ///     matches it (for example `SchemaMismatch` or `StorageError`) and the error as detail.
     /// - Non-internode queries return execution status and failure counts directly.
     ///
     /// # Error Handling
@@ -189,6 +261,9 @@ impl QueryExecution {
             open_query_id: open_query_id as u32,
             status: InternodeResponseStatus::Ok,
             content: None,
+            detail: None,
+            generation: None,
+            digest: None,
         };
 
         let query_result = {
@@ -315,14 +390,33 @@ impl QueryExecution {
                     self.execute_alter_table(alter_table, open_query_id)
                 }
                 Query::CreateKeyspace(create_keyspace) => {
-                    self.execute_create_keyspace(create_keyspace)
+                    self.execute_create_keyspace(create_keyspace, open_query_id)
+                }
+                Query::DropKeyspace(drop_keyspace) => {
+                    self.execute_drop_keyspace(drop_keyspace, open_query_id)
                 }
-                Query::DropKeyspace(drop_keyspace) => self.execute_drop_keyspace(drop_keyspace),
                 Query::AlterKeyspace(alter_keyspace) => self.execute_alter_keyspace(alter_keyspace),
                 Query::Use(_) => {
                     return Err(NodeError::OtherError);
                     //self.execute_use(use_cql, internode, open_query_id, client_id)
                 }
+                // The coordinator rewrites them into statements on the tables of the users
+                Query::CreateUser(_)
+                | Query::AlterUser(_)
+                | Query::DropUser(_)
+                | Query::Grant(_)
+                | Query::Revoke(_) => Err(NodeError::InternodeProtocolError),
+                // Other nodes get their part of a batch as an `InternodeStatement::Batch`
+                Query::Batch(_) if internode => Err(NodeError::InternodeProtocolError),
+                Query::Batch(batch) => {
+                    let timestamp_n;
+                    if let Some(t) = timestap {
+                        timestamp_n = t;
+                    } else {
+                        return Err(NodeError::InternodeProtocolError);
+                    }
+                    self.execute_batch(batch, open_query_id, client_id, timestamp_n)
+                }
             }
         };
 
@@ -331,16 +425,9 @@ impl QueryExecution {
                 match query_result {
                     Ok(_) => response,
 
-                    Err(_) => {
-                        eprintln!(
-                            "el error en este nodo es {:?} de la query {:?}",
-                            query_result, query
-                        );
-                        InternodeResponse {
-                            open_query_id: open_query_id as u32,
-                            status: InternodeResponseStatus::Error,
-                            content: None,
-                        }
+                    Err(e) => {
+                        eprintln!("el error en este nodo es {:?} de la query {:?}", e, query);
+                        InternodeResponse::from_error(open_query_id as u32, &e)
                     }
                 }
             };
@@ -348,6 +435,18 @@ impl QueryExecution {
         } else {
             match query_result {
                 Ok(_) => {
+                    if matches!(
+                        query,
+                        Query::CreateTable(_)
+                            | Query::DropTable(_)
+                            | Query::AlterTable(_)
+                            | Query::CreateKeyspace(_)
+                            | Query::DropKeyspace(_)
+                            | Query::AlterKeyspace(_)
+                    ) {
+                        self.wait_for_schema_agreement()?;
+                    }
+
                     let how_many_internode_query_has_finish = match (
                         self.execution_finished_itself,
                         self.execution_replicate_itself,
@@ -357,9 +456,16 @@ impl QueryExecution {
                         (false, false) => 0,
                     };
 
+                    // The hints stored for unreachable replicas acknowledge `ANY` writes
+                    let hint_acks = self
+                        .node_that_execute
+                        .lock()?
+                        .get_open_handle_query()
+                        .take_hint_acks(open_query_id);
+
                     return Ok(Some((
                         (
-                            how_many_internode_query_has_finish,
+                            how_many_internode_query_has_finish + hint_acks,
                             self.how_many_nodes_failed,
                         ),
                         response,
@@ -370,6 +476,47 @@ impl QueryExecution {
         }
     }
 
+    /// Waits until the live nodes gossip the same version of the schema as this node, so a client
+    /// answered after a schema change can use it through any node.
+    ///
+    /// # Notes
+    /// - The node is locked only while the agreement is checked, as gossip needs it to spread
+    ///   the change.
+    /// - The change is already made, so once `SCHEMA_AGREEMENT_TIMEOUT` passes the client is
+    ///   answered anyway, and the nodes that disagree are logged as a warning.
+    fn wait_for_schema_agreement(&self) -> Result<(), NodeError> {
+        let deadline = Instant::now() + SCHEMA_AGREEMENT_TIMEOUT;
+        loop {
+            let agreement = {
+                let node = self.node_that_execute.lock()?;
+                node.gossiper
+                    .schema_agreement(node.get_ip())
+                    .map_err(|_| NodeError::GossipError)?
+            };
+            if agreement.is_reached() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                let disagreeing: Vec<String> = agreement
+                    .disagreeing
+                    .iter()
+                    .map(|(ip, version)| format!("{} ({:x})", ip, version))
+                    .collect();
+                self.logger.warn(
+                    &format!(
+                        "SCHEMA: NO AGREEMENT ON VERSION {:x} AFTER {:?}, DISAGREEING NODES: {}",
+                        agreement.version,
+                        SCHEMA_AGREEMENT_TIMEOUT,
+                        disagreeing.join(", ")
+                    ),
+                    true,
+                )?;
+                return Ok(());
+            }
+            thread::sleep(SCHEMA_AGREEMENT_POLL_INTERVAL);
+        }
+    }
+
     // Función auxiliar para enviar un mensaje a todos los nodos en el partitioner
     fn _send_to_other_nodes(
         &self,
@@ -390,6 +537,10 @@ impl QueryExecution {
                 replication: false,
                 keyspace_name: keyspace_name.to_string(),
                 timestamp: timestap,
+                correlation_id: self.logger.correlation_id().unwrap_or_default().to_string(),
+                statement: None,
+                time_left: None,
+                digest: false,
             }),
         );
 
@@ -399,7 +550,7 @@ impl QueryExecution {
             if ip != current_ip {
                 let result = connect_and_send_message(
                     ip,
-                    INTERNODE_PORT,
+                    local_node.config.internode_port,
                     self.connections.clone(),
                     message.clone(),
                 );
@@ -414,56 +565,161 @@ impl QueryExecution {
     // Función auxiliar para enviar un mensaje a un nodo específico en el partitioner
     fn send_to_single_node(
         &self,
-        self_ip: Ipv4Addr,
+        local_node: &mut Node,
         target_ip: Ipv4Addr,
-        serialized_message: &str,
+        statement: InternodeStatement,
+        partition_value: &str,
         open_query_id: i32,
         client_id: i32,
         keyspace_name: &str,
         timestap: i64,
         logger: Logger,
     ) -> Result<i32, NodeError> {
+        let query = InternodeQuery {
+            query_string: String::new(),
+            open_query_id: open_query_id as u32,
+            client_id: client_id as u32,
+            replication: false,
+            keyspace_name: keyspace_name.to_string(),
+            timestamp: timestap,
+            correlation_id: logger.correlation_id().unwrap_or_default().to_string(),
+            statement: Some(statement),
+            time_left: local_node.get_open_handle_query().time_left(open_query_id),
+            digest: false,
+        };
         let message = InternodeMessage::new(
-            self_ip,
-            InternodeMessageContent::Query(InternodeQuery {
-                query_string: serialized_message.to_string(),
-                open_query_id: open_query_id as u32,
-                client_id: client_id as u32,
-                replication: false,
-                keyspace_name: keyspace_name.to_string(),
-                timestamp: timestap,
-            }),
+            local_node.get_ip(),
+            InternodeMessageContent::Query(query.clone()),
         );
 
-        logger.info(
+        logger.with_component(Component::Internode).info(
             &format!(
                 "INTERNODE (Query: {:?}): I SENT {:?} to {:?}",
-                open_query_id, serialized_message, target_ip
+                open_query_id,
+                query.cql(),
+                target_ip
             ),
             Color::Green,
             true,
         )?;
 
+        // Kept to send the query to another node if the target does not own the partition anymore
+        local_node.get_open_handle_query().record_sent_query(
+            open_query_id,
+            target_ip,
+            query.clone(),
+            Some(partition_value),
+        );
+
         let result = connect_and_send_message(
             target_ip,
-            INTERNODE_PORT,
+            local_node.config.internode_port,
             self.connections.clone(),
             message.clone(),
         );
 
-        if result.is_err() {
+        if !Self::was_sent(local_node, open_query_id, target_ip, result)
+            && !self.store_hint(local_node, target_ip, &query, &logger)?
+        {
             return Ok(1);
         }
 
         Ok(0)
     }
 
+    // Returns whether a query could be sent to `target_ip`. A replica it could not be sent to is
+    // recorded as failed in the open query, and one that is overloaded also makes its client get
+    // `Unavailable` if the query fails.
+    fn was_sent(
+        local_node: &mut Node,
+        open_query_id: i32,
+        target_ip: Ipv4Addr,
+        result: Result<(), NodeError>,
+    ) -> bool {
+        if let Err(e) = &result {
+            let handler = local_node.get_open_handle_query();
+            handler.record_unreachable(open_query_id, target_ip);
+            if let NodeError::Unavailable(reason) = e {
+                handler.record_overloaded(open_query_id, reason.clone());
+            }
+        }
+        result.is_ok()
+    }
+
+    // Keeps a write that could not be sent to `target_ip` as a hint, replayed once gossip reports
+    // the node back to `Normal`. Reads are not hinted. Returns whether the hint acknowledges the
+    // write, as it does for `ANY` queries, in which case the replica does not count as failed.
+    fn store_hint(
+        &self,
+        local_node: &mut Node,
+        target_ip: Ipv4Addr,
+        query: &InternodeQuery,
+        logger: &Logger,
+    ) -> Result<bool, NodeError> {
+        if matches!(query.statement, Some(InternodeStatement::Select(_))) {
+            return Ok(false);
+        }
+
+        match local_node.hints.store(target_ip, query) {
+            Ok(_) => logger.with_component(Component::Internode).warn(
+                &format!(
+                    "INTERNODE (Query: {:?}): {:?} IS UNREACHABLE, STORED A HINT FOR IT",
+                    query.open_query_id, target_ip
+                ),
+                true,
+            )?,
+            Err(e) => {
+                logger.error(
+                    &format!("Failed to store a hint for {:?}: {}", target_ip, e),
+                    true,
+                )?;
+                return Ok(false);
+            }
+        }
+        Ok(local_node
+            .get_open_handle_query()
+            .record_hint(query.open_query_id as i32, target_ip))
+    }
+
+    // Rejects a query of a client with `Unavailable` before it is sent, if fewer replicas of the
+    // partition owned by `owner` than its consistency level requires are alive. Replicas gossip
+    // does not know about yet count as alive, and so does this node.
+    fn check_availability(
+        &self,
+        local_node: &mut Node,
+        owner: Ipv4Addr,
+        open_query_id: i32,
+    ) -> Result<(), NodeError> {
+        let self_ip = local_node.get_ip();
+        let keyspace = local_node
+            .get_open_handle_query()
+            .get_keyspace_of_query(open_query_id)?
+            .ok_or(NodeError::KeyspaceError)?;
+        let mut replicas = get_replicas(&local_node.get_partitioner(), owner, &keyspace)?;
+        replicas.push(owner);
+
+        let alive: Vec<Ipv4Addr> = replicas
+            .into_iter()
+            .filter(|ip| {
+                *ip == self_ip
+                    || local_node
+                        .gossiper
+                        .get_status(*ip)
+                        .map_or(true, |status| status.is_alive())
+            })
+            .collect();
+        local_node
+            .get_open_handle_query()
+            .check_available(open_query_id, &alive)
+    }
+
     // Función auxiliar para enviar un mensaje a todos los nodos en el partitioner con replicación
     fn send_to_replication_nodes(
         &self,
         mut local_node: MutexGuard<'_, Node>,
         node_to_get_succesor: Ipv4Addr,
-        serialized_message: &str,
+        statement: InternodeStatement,
+        partition_value: &str,
         open_query_id: i32,
         client_id: i32,
         keyspace_name: &str,
@@ -475,38 +731,62 @@ impl QueryExecution {
         // Bloquea el nodo para obtener el partitioner y la IP
         let current_ip = local_node.get_ip();
 
-        let message = InternodeMessage::new(
-            current_ip,
-            InternodeMessageContent::Query(InternodeQuery {
-                query_string: serialized_message.to_string(),
-                open_query_id: open_query_id as u32,
-                client_id: client_id as u32,
-                replication: true,
-                keyspace_name: keyspace_name.to_string(),
-                timestamp: timestap,
-            }),
-        );
+        let query = InternodeQuery {
+            query_string: String::new(),
+            open_query_id: open_query_id as u32,
+            client_id: client_id as u32,
+            replication: true,
+            keyspace_name: keyspace_name.to_string(),
+            timestamp: timestap,
+            correlation_id: logger.correlation_id().unwrap_or_default().to_string(),
+            statement: Some(statement),
+            time_left: local_node.get_open_handle_query().time_left(open_query_id),
+            digest: false,
+        };
+        let message =
+            InternodeMessage::new(current_ip, InternodeMessageContent::Query(query.clone()));
 
-        let replication_factor = local_node
+        let keyspace = local_node
             .get_open_handle_query()
             .get_keyspace_of_query(open_query_id)?
-            .ok_or(NodeError::KeyspaceError)?
-            .get_replication_factor();
+            .ok_or(NodeError::KeyspaceError)?;
 
-        let n_succesors = local_node
-            .get_partitioner()
-            .get_n_successors(node_to_get_succesor, (replication_factor - 1) as usize)?;
+        let n_succesors = get_replicas(
+            &local_node.get_partitioner(),
+            node_to_get_succesor,
+            &keyspace,
+        )?;
 
         let mut failed_nodes = 0;
         let mut the_node_has_to_replicate = false;
 
         // Recorre los nodos del partitioner y envía el mensaje a cada nodo excepto el actual
+        // This node executes its part of the query itself, so it is never picked when the query is
+        // sent again after a `NotOwner` response
+        if node_to_get_succesor == current_ip || n_succesors.contains(&current_ip) {
+            local_node.get_open_handle_query().record_sent_query(
+                open_query_id,
+                current_ip,
+                query.clone(),
+                Some(partition_value),
+            );
+        }
+
         for ip in n_succesors {
             if ip != current_ip {
-                logger.info(
+                local_node.get_open_handle_query().record_sent_query(
+                    open_query_id,
+                    ip,
+                    query.clone(),
+                    Some(partition_value),
+                );
+
+                logger.with_component(Component::Internode).info(
                     &format!(
                         "INTERNODE (Query: {:?}): I SENT as REPLICATION {:?} to {:?}",
-                        open_query_id, serialized_message, ip
+                        open_query_id,
+                        query.cql(),
+                        ip
                     ),
                     Color::Green,
                     true,
@@ -514,11 +794,13 @@ impl QueryExecution {
 
                 let result = connect_and_send_message(
                     ip,
-                    INTERNODE_PORT,
+                    local_node.config.internode_port,
                     self.connections.clone(),
                     message.clone(),
                 );
-                if result.is_err() {
+                if !Self::was_sent(&mut local_node, open_query_id, ip, result)
+                    && !self.store_hint(&mut local_node, ip, &query, &logger)?
+                {
                     failed_nodes += 1;
                 }
             } else {
@@ -528,6 +810,69 @@ impl QueryExecution {
         Ok((failed_nodes, the_node_has_to_replicate))
     }
 
+    // Checks that this node still owns the partition of a query received from another node, or
+    // replicates it if `replication` is set. The coordinator picked the replicas with its own view
+    // of the ring, which may be outdated after a node joined, moved or left: answering `NotOwner`
+    // lets it send the query again to the right node. The partitions this node takes from the
+    // nodes that are leaving the ring are accepted too, as they are streamed to it before they leave.
+    fn check_ownership(
+        &self,
+        local_node: &mut Node,
+        partition: &str,
+        replication: bool,
+        open_query_id: i32,
+    ) -> Result<(), NodeError> {
+        // Queries sent while redistributing data do not belong to a client query and are always accepted
+        if open_query_id == 0 {
+            return Ok(());
+        }
+
+        let self_ip = local_node.get_ip();
+        let keyspace = if replication {
+            Some(
+                local_node
+                    .get_open_handle_query()
+                    .get_keyspace_of_query(open_query_id)?
+                    .ok_or(NodeError::KeyspaceError)?,
+            )
+        } else {
+            None
+        };
+        let owns_partition = |partitioner: &Partitioner| -> Result<bool, NodeError> {
+            let owner = partitioner.get_ip(partition)?;
+            match &keyspace {
+                Some(keyspace) => {
+                    Ok(get_replicas(partitioner, owner, keyspace)?.contains(&self_ip))
+                }
+                None => Ok(owner == self_ip),
+            }
+        };
+
+        let mut partitioner = local_node.get_partitioner();
+        if owns_partition(&partitioner)? {
+            return Ok(());
+        }
+
+        let leaving: Vec<Ipv4Addr> = local_node
+            .gossiper
+            .endpoints_state
+            .iter()
+            .filter(|(_, state)| state.application_state.status.is_leaving())
+            .map(|(ip, _)| *ip)
+            .collect();
+        if leaving.is_empty() {
+            return Err(NodeError::NotOwner);
+        }
+        for ip in leaving {
+            partitioner.remove_node(ip).ok();
+        }
+        if !partitioner.get_nodes().is_empty() && owns_partition(&partitioner)? {
+            Ok(())
+        } else {
+            Err(NodeError::NotOwner)
+        }
+    }
+
     fn validate_values(&self, columns: Vec<Column>, values: &[String]) -> Result<(), CQLError> {
         if values.len() != columns.len() {
             return Err(CQLError::InvalidSyntax);
@@ -537,10 +882,26 @@ impl QueryExecution {
             if value == "" {
                 continue;
             }
-            if !column.data_type.is_valid_value(value) {
+            // Counters are only written by other nodes, with their shards
+            let is_valid = match column.data_type {
+                DataType::Counter => value.parse::<CounterShards>().is_ok(),
+                data_type => data_type.is_valid_value(value),
+            };
+            if !is_valid {
                 return Err(CQLError::InvalidSyntax);
             }
         }
         Ok(())
     }
+
+    // Rejects an `INSERT` of a client into a table with counters, which can only be incremented
+    fn check_not_counter_table(columns: &[Column]) -> Result<(), CQLError> {
+        if columns
+            .iter()
+            .any(|column| column.data_type == DataType::Counter)
+        {
+            return Err(CQLError::InvalidColumn);
+        }
+        Ok(())
+    }
 }
@@ -1,8 +1,40 @@
 // Ordered imports
 use super::QueryExecution;
-use crate::NodeError;
-use query_creator::clauses::select_cql::Select;
+use crate::internode_protocol::message::{InternodeMessage, InternodeMessageContent};
+use crate::internode_protocol::query::InternodeQuery;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::storage_engine::row_cache::PartitionKey;
+use crate::utils::{connect_and_send_message, get_replicas};
+use crate::{Node, NodeError};
+use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
+use logger::{Color, Component};
+use query_creator::clauses::select_cql::{Select, COUNT_RESULT_COLUMN};
 use query_creator::errors::CQLError;
+use std::net::Ipv4Addr;
+
+/// Returns whether a `SELECT` reads every partition of its table instead of a single one, which only
+/// the queries with `ALLOW FILTERING` or conditions on the token of the partitions, whose `WHERE`
+/// clause does not give the whole partition key with `=`, do.
+pub(crate) fn scans_table(select_query: &Select, table: &TableSchema) -> bool {
+    let restricts_token = select_query
+        .where_clause
+        .as_ref()
+        .is_some_and(|where_clause| where_clause.restricts_token());
+    (select_query.allow_filtering || restricts_token)
+        && select_query
+            .where_clause
+            .as_ref()
+            .and_then(|where_clause| {
+                PartitionKey::of_where(
+                    &select_query.keyspace_used_name,
+                    &table.get_name(),
+                    false,
+                    where_clause,
+                    &table.get_columns(),
+                )
+            })
+            .is_none()
+}
 
 impl QueryExecution {
     /// Executes the retrieval of row/rows. This function is public only for internal use
@@ -17,9 +49,9 @@ impl QueryExecution {
     ) -> Result<Vec<String>, NodeError> {
         let table;
         let mut do_in_this_node = true;
+        let mut scan = false;
 
         let mut failed_nodes = 0;
-        let mut internode_failed_nodes = 0;
         let client_keyspace;
         {
             // Get the table name and reference the node
@@ -37,28 +69,13 @@ impl QueryExecution {
             // Get the table and replication factor
             table = node.get_table(table_name.clone(), client_keyspace.clone())?;
 
-            // Validate the primary key and where clause
-            let partition_keys = table.get_partition_keys()?;
-            let clustering_columns = table.get_clustering_columns()?;
-            let where_clause = select_query
-                .clone()
-                .where_clause
-                .ok_or(NodeError::CQLError(CQLError::NoWhereCondition))?;
-
-            where_clause.validate_cql_conditions(
-                &partition_keys,
-                &clustering_columns,
-                true,
-                false,
-            )?;
-
-            select_query.validate_order_by_cql_conditions(&clustering_columns)?;
-
             // Ensure that the columns specified in the query exist in the table
             let complet_columns: Vec<String> =
                 table.get_columns().iter().map(|c| c.name.clone()).collect();
 
-            if select_query.columns[0] == String::from("*") {
+            if select_query.is_count() {
+                // A count does not read any column, replicas only answer with the number of rows
+            } else if select_query.columns[0] == String::from("*") {
                 select_query.columns = complet_columns;
             } else {
                 for col in select_query.clone().columns {
@@ -68,52 +85,91 @@ impl QueryExecution {
                 }
             }
 
-            // Determine the target node based on partition key hashing
-            let value_to_hash = where_clause
-                .get_value_partitioner_key_condition(partition_keys)?
-                .join("");
-            let node_to_query = node.partitioner.get_ip(value_to_hash.clone())?;
-            let self_ip = node.get_ip().clone();
-            let logger = node.get_logger();
-            // Forward the SELECT if this is not an internode operation and the target node differs
-            if !internode && node_to_query != self_ip {
-                let serialized_query = select_query.serialize();
-                failed_nodes = self.send_to_single_node(
-                    node.get_ip(),
-                    node_to_query,
-                    &serialized_query,
-                    open_query_id,
-                    client_id,
-                    &client_keyspace.get_name(),
-                    0,
-                    node.get_logger(),
-                )?;
-                do_in_this_node = false;
-            }
+            if scans_table(&select_query, &table) {
+                // Rows of different partitions are not sorted between them
+                if select_query.orderby_clause.is_some() {
+                    return Err(NodeError::CQLError(CQLError::InvalidCondition));
+                }
+                if let Some(where_clause) = &select_query.where_clause {
+                    where_clause.validate_token_conditions(
+                        &table.get_partition_keys()?,
+                        select_query.allow_filtering,
+                    )?;
+                }
+                // Every node scans the rows it holds, this node included
+                if !internode {
+                    failed_nodes = self.send_scan(
+                        &mut node,
+                        &select_query,
+                        open_query_id,
+                        client_id,
+                        &client_keyspace,
+                    )?;
+                    self.execution_finished_itself = true;
+                }
+                scan = true;
+            } else {
+                // Validate the primary key and where clause
+                let partition_keys = table.get_partition_keys()?;
+                let clustering_columns = table.get_clustering_columns()?;
+                let where_clause = select_query
+                    .clone()
+                    .where_clause
+                    .ok_or(NodeError::CQLError(CQLError::NoWhereCondition))?;
 
-            // Send the SELECT to replication nodes if needed
-            if !internode {
-                let serialized_select = select_query.serialize();
-                (internode_failed_nodes, replication) = self.send_to_replication_nodes(
-                    node,
-                    node_to_query,
-                    &serialized_select,
-                    open_query_id,
-                    client_id,
-                    &client_keyspace.get_name(),
-                    0,
-                    logger.clone(),
-                )?;
-            }
+                // With `ALLOW FILTERING`, the rows of the partition may be filtered by any column
+                if !select_query.allow_filtering {
+                    where_clause.validate_cql_conditions(
+                        &partition_keys,
+                        &clustering_columns,
+                        true,
+                        false,
+                    )?;
+                }
+
+                select_query
+                    .validate_order_by_cql_conditions(&table.get_clustering_key_columns())?;
 
-            // Set execution finished if the node itself is the target and no other replication is needed
-            if !internode && node_to_query == self_ip {
-                self.execution_finished_itself = true;
+                // Determine the target node based on partition key hashing
+                let value_to_hash = where_clause
+                    .get_value_partitioner_key_condition(partition_keys)?
+                    .join("");
+                let node_to_query = node.partitioner.get_ip(value_to_hash.clone())?;
+                // Reject the query if it was sent with an outdated view of the ring
+                if internode {
+                    self.check_ownership(&mut node, &value_to_hash, replication, open_query_id)?;
+                } else {
+                    self.check_availability(&mut node, node_to_query, open_query_id)?;
+                }
+                // Send the SELECT to the replicas its consistency level needs, this node included
+                if !internode {
+                    let reads_itself;
+                    (failed_nodes, reads_itself) = self.send_select(
+                        &mut node,
+                        node_to_query,
+                        &select_query,
+                        &value_to_hash,
+                        open_query_id,
+                        client_id,
+                        &client_keyspace,
+                    )?;
+                    match reads_itself {
+                        Some(false) => self.execution_finished_itself = true,
+                        Some(true) => {
+                            do_in_this_node = false;
+                            replication = true;
+                        }
+                        None => do_in_this_node = false,
+                    }
+                }
             }
         }
 
-        failed_nodes += internode_failed_nodes;
         self.how_many_nodes_failed = failed_nodes;
+        if scan {
+            return self.scan_table(select_query, table, &client_keyspace.get_name());
+        }
+
         // Return if no local execution or replication is needed
         if !do_in_this_node && !replication {
             return Ok(vec![]);
@@ -123,6 +179,20 @@ impl QueryExecution {
         if replication {
             self.execution_replicate_itself = true;
         }
+        if select_query.is_count() {
+            let count = self.storage_engine.count(
+                &select_query,
+                &table,
+                replication,
+                &client_keyspace.get_name(),
+            )?;
+            return Ok(vec![
+                COUNT_RESULT_COLUMN.to_string(),
+                COUNT_RESULT_COLUMN.to_string(),
+                count.to_string(),
+            ]);
+        }
+
         let results = self.storage_engine.select(
             select_query,
             table,
@@ -131,4 +201,186 @@ impl QueryExecution {
         )?;
         Ok(results)
     }
+
+    // Reads the rows of a table held by this node that match a SELECT that scans it: the ones of the
+    // partitions it owns and the ones it holds as a replica of other nodes. Counts get their rows
+    // too, as the coordinator counts them once the rows held by several nodes are merged.
+    fn scan_table(
+        &self,
+        select_query: Select,
+        table: TableSchema,
+        keyspace: &str,
+    ) -> Result<Vec<String>, NodeError> {
+        let mut results =
+            self.storage_engine
+                .select(select_query.clone(), table.clone(), false, keyspace)?;
+        let replicated = self
+            .storage_engine
+            .select(select_query, table, true, keyspace)?;
+        results.extend(replicated.into_iter().skip(2));
+        Ok(results)
+    }
+
+    // Sends a SELECT of a client that scans its table to every other node of the ring, and makes its
+    // open query wait for all of them, as each one holds a different part of the table. The scan is
+    // rejected with `Unavailable` if gossip considers any of them down. Returns how many nodes could
+    // not be reached.
+    fn send_scan(
+        &self,
+        node: &mut Node,
+        select_query: &Select,
+        open_query_id: i32,
+        client_id: i32,
+        keyspace: &KeyspaceSchema,
+    ) -> Result<i32, NodeError> {
+        let self_ip = node.get_ip();
+        let nodes = node.get_partitioner().get_nodes();
+        let alive: Vec<Ipv4Addr> = nodes
+            .iter()
+            .copied()
+            .filter(|ip| {
+                *ip == self_ip
+                    || node
+                        .gossiper
+                        .get_status(*ip)
+                        .map_or(true, |status| status.is_alive())
+            })
+            .collect();
+        node.get_open_handle_query()
+            .set_scanned_nodes(open_query_id, nodes.len() as i32);
+        node.get_open_handle_query()
+            .check_available(open_query_id, &alive)?;
+
+        let time_left = node.get_open_handle_query().time_left(open_query_id);
+        let mut failed_nodes = 0;
+        for ip in nodes.into_iter().filter(|ip| *ip != self_ip) {
+            let statement = InternodeStatement::Select(select_query.clone());
+            let query = InternodeQuery {
+                query_string: String::new(),
+                open_query_id: open_query_id as u32,
+                client_id: client_id as u32,
+                replication: false,
+                keyspace_name: keyspace.get_name(),
+                timestamp: 0,
+                correlation_id: self.logger.correlation_id().unwrap_or_default().to_string(),
+                statement: Some(statement),
+                time_left,
+                digest: false,
+            };
+            self.logger.with_component(Component::Internode).info(
+                &format!(
+                    "INTERNODE (Query: {:?}): I SENT {:?} to {:?}",
+                    open_query_id,
+                    query.cql(),
+                    ip
+                ),
+                Color::Green,
+                true,
+            )?;
+            let message = InternodeMessage::new(self_ip, InternodeMessageContent::Query(query));
+            let result = connect_and_send_message(
+                ip,
+                node.config.internode_port,
+                self.connections.clone(),
+                message,
+            );
+            if !Self::was_sent(node, open_query_id, ip, result) {
+                failed_nodes += 1;
+            }
+        }
+        Ok(failed_nodes)
+    }
+
+    // Sends a SELECT of a client to the replicas of its partition that its consistency level needs,
+    // this node and the replicas gossip considers alive first, and keeps the others to send it to
+    // them speculatively if those are slow. Only the first replica reads the rows, the others only
+    // answer with a digest of theirs, and the rows are read from them too if the digests disagree.
+    // Counts are not digested, as replicas answer them with a single value. Returns how many
+    // replicas could not be reached and, if this node reads its own copy, whether it does as a
+    // replica instead of as the owner.
+    fn send_select(
+        &self,
+        node: &mut Node,
+        owner: Ipv4Addr,
+        select_query: &Select,
+        partition_value: &str,
+        open_query_id: i32,
+        client_id: i32,
+        keyspace: &KeyspaceSchema,
+    ) -> Result<(i32, Option<bool>), NodeError> {
+        let self_ip = node.get_ip();
+        let mut replicas = get_replicas(&node.get_partitioner(), owner, keyspace)?;
+        replicas.insert(0, owner);
+        replicas.sort_by_key(|ip| {
+            let alive = node
+                .gossiper
+                .get_status(*ip)
+                .map_or(true, |status| status.is_alive());
+            (*ip != self_ip, !alive)
+        });
+
+        let targets = node
+            .get_open_handle_query()
+            .initial_targets(open_query_id, replicas.len());
+        let time_left = node.get_open_handle_query().time_left(open_query_id);
+        let digests = targets > 1 && !select_query.is_count();
+        let mut failed_nodes = 0;
+        let mut reads_itself = None;
+        let mut speculative_targets = vec![];
+        for (i, ip) in replicas.into_iter().enumerate() {
+            let statement = InternodeStatement::Select(select_query.clone());
+            let query = InternodeQuery {
+                query_string: String::new(),
+                open_query_id: open_query_id as u32,
+                client_id: client_id as u32,
+                replication: ip != owner,
+                keyspace_name: keyspace.get_name(),
+                timestamp: 0,
+                correlation_id: self.logger.correlation_id().unwrap_or_default().to_string(),
+                statement: Some(statement),
+                time_left,
+                digest: digests && i > 0 && i < targets,
+            };
+            if i >= targets {
+                speculative_targets.push((ip, query));
+                continue;
+            }
+
+            node.get_open_handle_query().record_sent_query(
+                open_query_id,
+                ip,
+                query.clone(),
+                Some(partition_value),
+            );
+            if ip == self_ip {
+                reads_itself = Some(query.replication);
+                continue;
+            }
+
+            self.logger.with_component(Component::Internode).info(
+                &format!(
+                    "INTERNODE (Query: {:?}): I SENT {:?} to {:?}",
+                    open_query_id,
+                    query.cql(),
+                    ip
+                ),
+                Color::Green,
+                true,
+            )?;
+            let message = InternodeMessage::new(self_ip, InternodeMessageContent::Query(query));
+            let result = connect_and_send_message(
+                ip,
+                node.config.internode_port,
+                self.connections.clone(),
+                message,
+            );
+            if !Self::was_sent(node, open_query_id, ip, result) {
+                failed_nodes += 1;
+            }
+        }
+
+        node.get_open_handle_query()
+            .set_speculative_targets(open_query_id, speculative_targets);
+        Ok((failed_nodes, reads_itself))
+    }
 }
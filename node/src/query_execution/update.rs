@@ -1,17 +1,26 @@
 // Ordered imports
 use super::QueryExecution;
+use crate::internode_protocol::statement::InternodeStatement;
+use crate::storage_engine::commitlog::Mutation;
+use crate::storage_engine::counter::{counter_cell, CounterShards};
 use crate::NodeError;
+use gossip::structures::application_state::TableSchema;
 use query_creator::clauses::set_cql::Set;
 use query_creator::clauses::types::column::Column;
+use query_creator::clauses::types::datatype::DataType;
 use query_creator::clauses::update_cql::Update;
 use query_creator::errors::CQLError;
+use std::collections::HashMap;
 
 impl QueryExecution {
     /// Executes the update of row (or insert if not exist). This function is public only for internal use
     /// within the library (defined as `pub(crate)`).
+    ///
+    /// Counter increments are added to the shards of the counters this node owns, which are sent to
+    /// the replicas instead of the increments, so they can be applied more than once.
     pub(crate) fn execute_update(
         &mut self,
-        update_query: Update,
+        mut update_query: Update,
         internode: bool,
         mut replication: bool,
         open_query_id: i32,
@@ -66,15 +75,37 @@ impl QueryExecution {
                 .join("");
 
             let node_to_update = node.partitioner.get_ip(value_to_hash.clone())?;
+
+            // Reject the query if it was sent with an outdated view of the ring
+
+            if internode {
+                self.check_ownership(&mut node, &value_to_hash, replication, open_query_id)?;
+            } else {
+                self.check_availability(&mut node, node_to_update, open_query_id)?;
+                node.hot_partitions.record(
+                    &client_keyspace.get_name(),
+                    &table_name,
+                    &value_to_hash,
+                );
+                // The shards are written to the counter shard log without holding the lock of
+                // the node
+                drop(node);
+                update_query =
+                    self.increments_as_shards(update_query, &table, &client_keyspace.get_name())?;
+                node = self
+                    .node_that_execute
+                    .lock()
+                    .map_err(|_| NodeError::LockError)?;
+            }
             let self_ip = node.get_ip().clone();
-            let logger = node.get_logger();
+            let logger = self.logger.clone();
             // If not an internode operation and the target node differs, forward the update
             if !internode && node_to_update != self_ip {
-                let serialized_update = update_query.serialize();
                 failed_nodes = self.send_to_single_node(
-                    node.get_ip(),
+                    &mut node,
                     node_to_update,
-                    &serialized_update,
+                    InternodeStatement::Update(update_query.clone()),
+                    &value_to_hash,
                     open_query_id,
                     client_id,
                     &client_keyspace.get_name(),
@@ -86,11 +117,11 @@ impl QueryExecution {
 
             // Send update to replication nodes if needed
             if !internode {
-                let serialized_update = update_query.serialize();
                 (internode_failed_nodes, replication) = self.send_to_replication_nodes(
                     node,
                     node_to_update,
-                    &serialized_update,
+                    InternodeStatement::Update(update_query.clone()),
+                    &value_to_hash,
                     open_query_id,
                     client_id,
                     &client_keyspace.get_name(),
@@ -121,6 +152,13 @@ impl QueryExecution {
         // Validate the update types
         Self::validate_update_types(update_query.clone().set_clause, table.get_columns())?;
 
+        let _logged = self.log_mutation(
+            &client_keyspace.get_name(),
+            &table.get_name(),
+            replication,
+            timestamp,
+            Mutation::Update(update_query.clone()),
+        )?;
         self.storage_engine.update(
             update_query,
             table,
@@ -131,7 +169,47 @@ impl QueryExecution {
         Ok(())
     }
 
-    /// Validates the types of the `SET` clause against the columns of the table
+    /// Applies an `UPDATE ... IF` to `current`, the values of the row with the primary key of its
+    /// `WHERE` clause, and returns the updated row, or `None` if the `IF` condition does not hold.
+    pub(crate) fn conditional_update_row(
+        update_query: &Update,
+        table: &TableSchema,
+        current: &[String],
+    ) -> Result<Option<Vec<String>>, NodeError> {
+        let columns = table.get_columns();
+        Self::validate_update_types(update_query.set_clause.clone(), columns.clone())?;
+        // Counters can not be incremented conditionally
+        if !update_query.set_clause.get_increments().is_empty() {
+            return Err(NodeError::CQLError(CQLError::InvalidColumn));
+        }
+
+        let register: HashMap<String, String> = columns
+            .iter()
+            .map(|column| column.name.clone())
+            .zip(current.iter().cloned())
+            .collect();
+        if let Some(if_clause) = &update_query.if_clause {
+            if !if_clause
+                .condition
+                .execute(&register, columns)
+                .unwrap_or(false)
+            {
+                return Ok(None);
+            }
+        }
+
+        let mut row = current.to_vec();
+        for (column, value) in update_query.set_clause.get_pairs() {
+            let index = table
+                .get_column_index(column)
+                .ok_or(NodeError::CQLError(CQLError::InvalidColumn))?;
+            row[index] = value.clone();
+        }
+        Ok(Some(row))
+    }
+
+    /// Validates the types of the `SET` clause against the columns of the table. Values of counter
+    /// columns must be their shards, as sent by the coordinator of the update.
     pub(crate) fn validate_update_types(
         set_clause: Set,
         columns: Vec<Column>,
@@ -142,7 +220,11 @@ impl QueryExecution {
                     if column.is_partition_key || column.is_clustering_column {
                         return Err(NodeError::CQLError(CQLError::InvalidCondition));
                     }
-                    if !column.data_type.is_valid_value(value) {
+                    let is_valid = match column.data_type {
+                        DataType::Counter => value.parse::<CounterShards>().is_ok(),
+                        data_type => data_type.is_valid_value(value),
+                    };
+                    if !is_valid {
                         return Err(NodeError::CQLError(CQLError::InvalidCondition));
                     }
                 }
@@ -150,4 +232,66 @@ impl QueryExecution {
         }
         Ok(())
     }
+
+    /// Replaces the counter increments of an `UPDATE` of a client by the shards of the counters
+    /// owned by this node, once the increments are added to them.
+    ///
+    /// Counters can only be changed by increments, which need the whole primary key of the row and
+    /// can not be conditional (`IF`) or expire (`USING TTL`). Every column but the counters of the
+    /// table can only be set by statements of other nodes.
+    pub(crate) fn increments_as_shards(
+        &self,
+        update_query: Update,
+        table: &TableSchema,
+        keyspace: &str,
+    ) -> Result<Update, NodeError> {
+        let columns = table.get_columns();
+        let is_counter = |name: &str| {
+            columns
+                .iter()
+                .any(|column| column.name == name && column.data_type == DataType::Counter)
+        };
+        let increments = update_query.set_clause.get_increments();
+        let assigns_counters = update_query
+            .set_clause
+            .get_pairs()
+            .iter()
+            .any(|(name, _)| is_counter(name));
+        if increments.is_empty() && !assigns_counters {
+            return Ok(update_query);
+        }
+        if assigns_counters
+            || increments.iter().any(|(name, _)| !is_counter(name))
+            || update_query.if_clause.is_some()
+            || update_query.ttl.is_some()
+        {
+            return Err(NodeError::CQLError(CQLError::InvalidColumn));
+        }
+
+        let where_clause = update_query
+            .where_clause
+            .as_ref()
+            .ok_or(NodeError::CQLError(CQLError::NoWhereCondition))?;
+        let primary_key = columns
+            .iter()
+            .filter(|column| column.is_partition_key || column.is_clustering_column)
+            .map(|column| where_clause.get_value_for_clustering_column(&column.name))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(NodeError::CQLError(
+                CQLError::MissingPartitionOrClusteringColumns,
+            ))?;
+
+        let mut pairs = update_query.set_clause.get_pairs().clone();
+        for (column, increment) in increments {
+            let cell = counter_cell(keyspace, &table.get_name(), column, &primary_key, &columns);
+            let shard = self
+                .storage_engine
+                .increment_counter_shard(&cell, *increment)?;
+            pairs.push((column.clone(), shard.to_string()));
+        }
+        Ok(Update {
+            set_clause: Set(pairs, vec![]),
+            ..update_query
+        })
+    }
 }
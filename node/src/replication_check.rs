@@ -0,0 +1,156 @@
+//! Checks of the replication of the keyspaces against the nodes of the ring.
+//!
+//! A keyspace whose replication factor is above the nodes of the ring (or of one of its
+//! datacenters) gets fewer replicas than it asks for: its queries still run, but consistency
+//! levels such as `ALL` can never be met and the data is less durable than expected. The
+//! coordinator of a `CREATE KEYSPACE` or `ALTER KEYSPACE` warns about it, or rejects the
+//! statement, and every node warns when the nodes that die leave a keyspace in that state.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use partitioner::Partitioner;
+
+/// What the coordinator of a `CREATE KEYSPACE` or `ALTER KEYSPACE` does when the replication of
+/// the keyspace asks for more replicas than the live nodes of the ring.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReplicationCheck {
+    /// The statement runs, and the coordinator logs a warning.
+    #[default]
+    Warn,
+    /// The statement is rejected with an `Invalid` error.
+    Reject,
+}
+
+impl FromStr for ReplicationCheck {
+    type Err = String;
+
+    /// Parses `warn` or `reject`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(ReplicationCheck::Warn),
+            "reject" => Ok(ReplicationCheck::Reject),
+            _ => Err(format!("Unknown replication check {}", s)),
+        }
+    }
+}
+
+/// A replication factor that is above the live nodes that can hold its replicas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicationShortfall {
+    /// The datacenter whose replication factor is too high, with `NetworkTopologyStrategy`.
+    pub datacenter: Option<String>,
+    pub replication_factor: u32,
+    pub live_nodes: usize,
+}
+
+impl fmt::Display for ReplicationShortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.datacenter {
+            Some(datacenter) => write!(
+                f,
+                "replication factor {} of datacenter {} is above its {} live nodes",
+                self.replication_factor, datacenter, self.live_nodes
+            ),
+            None => write!(
+                f,
+                "replication factor {} is above the {} live nodes of the ring",
+                self.replication_factor, self.live_nodes
+            ),
+        }
+    }
+}
+
+/// Returns the first replication factor of a keyspace that is above the live nodes that can
+/// hold its replicas, if there is one.
+///
+/// # Arguments
+/// - `replication_factor`: The replication factor of a `SimpleStrategy` keyspace.
+/// - `datacenters`: The replication factor of each datacenter of a `NetworkTopologyStrategy`
+///   keyspace, which is empty with `SimpleStrategy`.
+/// - `partitioner`: The ring, which only holds the live nodes (dead nodes are taken out of it).
+pub fn replication_shortfall(
+    replication_factor: u32,
+    datacenters: &BTreeMap<String, u32>,
+    partitioner: &Partitioner,
+) -> Option<ReplicationShortfall> {
+    let nodes = partitioner.get_nodes();
+    if datacenters.is_empty() {
+        return (replication_factor as usize > nodes.len()).then_some(ReplicationShortfall {
+            datacenter: None,
+            replication_factor,
+            live_nodes: nodes.len(),
+        });
+    }
+
+    datacenters.iter().find_map(|(datacenter, &factor)| {
+        let live_nodes = nodes
+            .iter()
+            .filter(|ip| partitioner.get_datacenter(ip) == datacenter)
+            .count();
+        (factor as usize > live_nodes).then(|| ReplicationShortfall {
+            datacenter: Some(datacenter.clone()),
+            replication_factor: factor,
+            live_nodes,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn ring(nodes: &[(u8, &str)]) -> Partitioner {
+        let mut partitioner = Partitioner::new();
+        for (last, datacenter) in nodes {
+            let ip = Ipv4Addr::new(127, 0, 0, *last);
+            partitioner.add_node(ip).unwrap();
+            partitioner.set_location(ip, datacenter, "rack1");
+        }
+        partitioner
+    }
+
+    #[test]
+    fn test_simple_strategy_shortfall() {
+        let partitioner = ring(&[(1, "dc1"), (2, "dc1")]);
+
+        assert_eq!(
+            replication_shortfall(2, &BTreeMap::new(), &partitioner),
+            None
+        );
+        let shortfall = replication_shortfall(3, &BTreeMap::new(), &partitioner).unwrap();
+        assert_eq!(
+            shortfall.to_string(),
+            "replication factor 3 is above the 2 live nodes of the ring"
+        );
+    }
+
+    #[test]
+    fn test_network_topology_shortfall_by_datacenter() {
+        let partitioner = ring(&[(1, "dc1"), (2, "dc1"), (3, "dc2")]);
+        let datacenters =
+            |dc1, dc2| BTreeMap::from([("dc1".to_string(), dc1), ("dc2".to_string(), dc2)]);
+
+        assert_eq!(
+            replication_shortfall(3, &datacenters(2, 1), &partitioner),
+            None
+        );
+        assert_eq!(
+            replication_shortfall(4, &datacenters(2, 2), &partitioner),
+            Some(ReplicationShortfall {
+                datacenter: Some("dc2".to_string()),
+                replication_factor: 2,
+                live_nodes: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_replication_check_from_str() {
+        assert_eq!("WARN".parse(), Ok(ReplicationCheck::Warn));
+        assert_eq!("reject".parse(), Ok(ReplicationCheck::Reject));
+        assert!("ignore".parse::<ReplicationCheck>().is_err());
+    }
+}
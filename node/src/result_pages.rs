@@ -0,0 +1,154 @@
+//! Paging of the results sent to the clients.
+//!
+//! A client that asks for its results in pages (with the page size of its `QUERY`) gets the rows
+//! merged by the coordinator a page at a time, so a big result is never sent in a single frame.
+//! The rows of the pages not sent yet are kept by the connection of the client, under the paging
+//! state returned with the previous page, until the client asks for them by sending the query
+//! again with that paging state.
+
+use std::collections::BTreeMap;
+
+use native_protocol::frame::Frame;
+use native_protocol::messages::result::{result_, rows::Rows};
+
+/// Results a connection keeps pages of at the same time. Once it keeps more, the pages of the
+/// oldest one are dropped.
+pub const MAX_PAGED_RESULTS: usize = 16;
+
+/// The rows of the results of a client connection that were not sent yet, by their paging state.
+///
+/// ### Fields
+/// - `pending`: The rows left of each result, by the id in its paging state.
+/// - `next_id`: The id of the paging state of the next page kept.
+#[derive(Debug, Default)]
+pub struct ResultPages {
+    pending: BTreeMap<u64, Rows>,
+    next_id: u64,
+}
+
+impl ResultPages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first page of the answer to a query, keeping the rows after it if the client
+    /// asked for pages of `page_size` rows and the answer has more.
+    pub fn first_page(&mut self, frame: Frame, page_size: Option<i32>) -> Frame {
+        match (frame, page_size) {
+            (Frame::Result(result_::Result::Rows(rows)), Some(page_size)) => {
+                Frame::Result(result_::Result::Rows(self.cut_page(rows, page_size)))
+            }
+            (frame, _) => frame,
+        }
+    }
+
+    /// Returns the page of a result that starts at `paging_state`, as returned with the previous
+    /// page, or `None` if the connection does not keep it (it was already sent, dropped, or the
+    /// paging state is not one of this connection).
+    pub fn next_page(&mut self, paging_state: &[u8], page_size: Option<i32>) -> Option<Frame> {
+        let id = u64::from_be_bytes(paging_state.try_into().ok()?);
+        let rows = self.pending.remove(&id)?;
+        let page = match page_size {
+            Some(page_size) => self.cut_page(rows, page_size),
+            None => rows,
+        };
+        Some(Frame::Result(result_::Result::Rows(page)))
+    }
+
+    // Cuts the first `page_size` rows of `rows`, keeping the rest under a new paging state
+    fn cut_page(&mut self, mut rows: Rows, page_size: i32) -> Rows {
+        let page_size = page_size.max(1) as usize;
+        if rows.rows_content.len() <= page_size {
+            rows.metadata.flags.has_more_pages = false;
+            rows.metadata.paging_state = None;
+            return rows;
+        }
+
+        let rest = rows.rows_content.split_off(page_size);
+        let mut metadata = rows.metadata.clone();
+        metadata.flags.has_more_pages = false;
+        metadata.paging_state = None;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(
+            id,
+            Rows {
+                metadata,
+                rows_count: rest.len() as i32,
+                rows_content: rest,
+            },
+        );
+        while self.pending.len() > MAX_PAGED_RESULTS {
+            self.pending.pop_first();
+        }
+
+        rows.rows_count = page_size as i32;
+        rows.metadata.flags.has_more_pages = true;
+        rows.metadata.paging_state = Some(id.to_be_bytes().to_vec());
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_protocol::messages::result::rows::{ColumnType, ColumnValue};
+    use std::collections::BTreeMap;
+
+    fn rows(ids: std::ops::Range<i32>) -> Frame {
+        let rows = ids
+            .map(|id| BTreeMap::from([("id".to_string(), ColumnValue::Int(id))]))
+            .collect();
+        Frame::Result(result_::Result::Rows(Rows::new(
+            vec![("id".to_string(), ColumnType::Int)],
+            rows,
+        )))
+    }
+
+    fn page(frame: Frame) -> Rows {
+        match frame {
+            Frame::Result(result_::Result::Rows(rows)) => rows,
+            _ => panic!("not rows"),
+        }
+    }
+
+    #[test]
+    fn test_results_are_sent_a_page_at_a_time() {
+        let mut pages = ResultPages::new();
+
+        let first = page(pages.first_page(rows(0..5), Some(2)));
+        assert_eq!(first.rows_count, 2);
+        assert!(first.metadata.flags.has_more_pages);
+        let paging_state = first.metadata.paging_state.unwrap();
+
+        let second = page(pages.next_page(&paging_state, Some(2)).unwrap());
+        assert_eq!(second.rows_content[0].get("id"), Some(&ColumnValue::Int(2)));
+        // A page is only sent once
+        assert!(pages.next_page(&paging_state, Some(2)).is_none());
+
+        let last = page(
+            pages
+                .next_page(&second.metadata.paging_state.unwrap(), Some(2))
+                .unwrap(),
+        );
+        assert_eq!(last.rows_count, 1);
+        assert!(!last.metadata.flags.has_more_pages);
+        assert_eq!(last.metadata.paging_state, None);
+        assert!(pages.pending.is_empty());
+    }
+
+    #[test]
+    fn test_results_are_not_paged_unless_asked_for() {
+        let mut pages = ResultPages::new();
+        assert_eq!(page(pages.first_page(rows(0..5), None)).rows_count, 5);
+        assert_eq!(page(pages.first_page(rows(0..5), Some(5))).rows_count, 5);
+        assert!(pages.pending.is_empty());
+
+        for _ in 0..MAX_PAGED_RESULTS + 1 {
+            pages.first_page(rows(0..5), Some(1));
+        }
+        assert_eq!(pages.pending.len(), MAX_PAGED_RESULTS);
+        assert!(pages.next_page(&0u64.to_be_bytes(), None).is_none());
+        assert!(pages.next_page(&[1, 2], None).is_none());
+    }
+}
@@ -0,0 +1,659 @@
+//! Users of the cluster and the permissions granted to them, stored in the `system_auth` keyspace.
+//!
+//! Clients log in with SASL `PLAIN` credentials (`\0<user>\0<password>`). A token with no user
+//! is taken as the password of the default superuser, `admin`, which is what older drivers send.
+//! Until the first `CREATE USER`, `ALTER USER`, `DROP USER`, `GRANT` or `REVOKE` creates the
+//! `system_auth` keyspace, `admin` with password `admin` is the only user.
+//!
+//! Every user has a row in `system_auth.roles`, with the salted hash of its password, and a row in
+//! `system_auth.role_permissions` for each resource it was granted permissions on. The resources
+//! are `data` (every keyspace) and `data_<keyspace>`. Superusers may run any statement, and the
+//! other users only the ones they were granted. Users are not scoped to a keyspace, so changing
+//! them or their permissions needs `AUTHORIZE` on `ALL KEYSPACES`. Each node caches the roles of its clients for
+//! `ROLES_VALIDITY`, so the changes to a user reach every node after that time.
+//!
+//! The values of the rows are sent to the replicas without quotes, so they are all single words:
+//! the hashes are in hexadecimal and the permissions of a resource are joined by `_`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+use aws_lc_rs::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use gossip::structures::application_state::KeyspaceSchema;
+use native_protocol::messages::result::rows::{ColumnValue, Rows};
+use query_creator::{clauses::user::grant_cql::PERMISSIONS, Query};
+
+use crate::{
+    authorization::{fingerprint, AuthorizationRequest, Permission},
+    errors::NodeError,
+};
+
+/// Keyspace with the users of the cluster and their permissions.
+pub const AUTH_KEYSPACE: &str = "system_auth";
+/// Superuser created with the `system_auth` keyspace, whose password is `admin`.
+pub const DEFAULT_SUPERUSER: &str = "admin";
+const DEFAULT_PASSWORD: &str = "admin";
+/// Replicas of the `system_auth` keyspace. Rings with fewer nodes keep it in all of them.
+pub const AUTH_REPLICATION_FACTOR: u32 = 3;
+/// Time a node uses the role of a client before it reads it again.
+pub const ROLES_VALIDITY: Duration = Duration::from_secs(2);
+
+const ROLES_TABLE: &str = "roles";
+const PERMISSIONS_TABLE: &str = "role_permissions";
+/// Resource of the permissions granted on every keyspace.
+const ALL_KEYSPACES: &str = "data";
+const PBKDF2_ITERATIONS: NonZeroU32 = NonZeroU32::new(10_000).unwrap();
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+
+/// The statements that create the `system_auth` keyspace and its tables, which can be run again.
+/// The keyspace has `AUTH_REPLICATION_FACTOR` replicas, so clients can still log in with a node
+/// down.
+pub fn schema_statements() -> Vec<String> {
+    vec![
+        format!(
+            "CREATE KEYSPACE IF NOT EXISTS {} WITH replication = \
+             {{'class': 'SimpleStrategy', 'replication_factor': {}}}",
+            AUTH_KEYSPACE, AUTH_REPLICATION_FACTOR
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} (role TEXT, salted_hash TEXT, is_superuser BOOLEAN, \
+             PRIMARY KEY (role))",
+            AUTH_KEYSPACE, ROLES_TABLE
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} (role TEXT, resource TEXT, permissions TEXT, \
+             PRIMARY KEY (role, resource))",
+            AUTH_KEYSPACE, PERMISSIONS_TABLE
+        ),
+    ]
+}
+
+/// Whether the `system_auth` keyspace and its tables exist, in the schema of the node.
+pub fn auth_tables_exist(keyspaces: &HashMap<String, KeyspaceSchema>) -> bool {
+    keyspaces.get(AUTH_KEYSPACE).is_some_and(|keyspace| {
+        keyspace.get_table(ROLES_TABLE).is_ok() && keyspace.get_table(PERMISSIONS_TABLE).is_ok()
+    })
+}
+
+/// The statement that adds the default superuser, once the `system_auth` tables exist.
+pub fn default_superuser_statement() -> String {
+    insert_role(DEFAULT_SUPERUSER, &hash_password(DEFAULT_PASSWORD), true)
+}
+
+/// The statements that read the role of `user`, whose results are taken by `StoredRole::from_rows`.
+pub fn role_selects(user: &str) -> [String; 2] {
+    [
+        format!(
+            "SELECT role, salted_hash, is_superuser FROM {}.{} WHERE role = '{}'",
+            AUTH_KEYSPACE, ROLES_TABLE, user
+        ),
+        format!(
+            "SELECT role, resource, permissions FROM {}.{} WHERE role = '{}'",
+            AUTH_KEYSPACE, PERMISSIONS_TABLE, user
+        ),
+    ]
+}
+
+/// Whether `name` can be the name of a user: letters, digits and underscores, as the names of
+/// the keyspaces. The names are written in the statements on the `system_auth` tables.
+pub fn is_valid_user_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `query` creates, alters or drops a user, or changes its permissions.
+pub fn is_role_statement(query: &Query) -> bool {
+    matches!(
+        query,
+        Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_)
+    )
+}
+
+/// Returns `query` as it can be logged, replacing the literals of the statements that hold a
+/// password.
+pub fn loggable(query: &str) -> String {
+    let upper = query.trim_start().to_uppercase();
+    if upper.starts_with("CREATE USER") || upper.starts_with("ALTER USER") {
+        fingerprint(query)
+    } else {
+        query.to_string()
+    }
+}
+
+/// The user and password a client logs in with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Reads the token of an `AUTH_RESPONSE`: SASL `PLAIN` credentials, or the password of the
+    /// default superuser.
+    pub fn from_token(token: &str) -> Credentials {
+        let mut parts = token.split('\0');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(_authorization_id), Some(user), Some(password)) => Credentials {
+                user: user.to_string(),
+                password: password.to_string(),
+            },
+            _ => Credentials {
+                user: DEFAULT_SUPERUSER.to_string(),
+                password: token.to_string(),
+            },
+        }
+    }
+
+    /// Whether these are the credentials of the default superuser before it is stored, which
+    /// are the only valid ones until the `system_auth` keyspace exists.
+    pub fn are_default(&self) -> bool {
+        self.user == DEFAULT_SUPERUSER && self.password == DEFAULT_PASSWORD
+    }
+}
+
+/// Hashes `password` with PBKDF2 and a random salt, as the salt followed by the hash in
+/// hexadecimal.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; SALT_LENGTH];
+    // The system random generator only fails if the OS has no source of randomness
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("no source of randomness");
+    let mut hash = [0u8; HASH_LENGTH];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+    format!("{}{}", to_hex(&salt), to_hex(&hash))
+}
+
+/// Whether `password` is the one hashed in `salted_hash` by `hash_password`.
+pub fn verify_password(password: &str, salted_hash: &str) -> bool {
+    let Some(bytes) = from_hex(salted_hash).filter(|bytes| bytes.len() > SALT_LENGTH) else {
+        return false;
+    };
+    let (salt, hash) = bytes.split_at(SALT_LENGTH);
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        salt,
+        password.as_bytes(),
+        hash,
+    )
+    .is_ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A user, with the permissions granted to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub is_superuser: bool,
+    /// Permissions granted on each resource (`data` or `data_<keyspace>`).
+    pub grants: BTreeMap<String, Vec<Permission>>,
+}
+
+impl Role {
+    /// The default superuser, before the `system_auth` keyspace exists.
+    pub fn default_superuser() -> Role {
+        Role {
+            name: DEFAULT_SUPERUSER.to_string(),
+            is_superuser: true,
+            grants: BTreeMap::new(),
+        }
+    }
+
+    /// A user that may not run any statement, such as one that was dropped.
+    pub fn without_permissions(name: &str) -> Role {
+        Role {
+            name: name.to_string(),
+            is_superuser: false,
+            grants: BTreeMap::new(),
+        }
+    }
+
+    /// Returns `Ok(())` if this user may run the statement of `request`, or the reason it may
+    /// not. Only superusers may change the `system_auth` keyspace or read from it.
+    pub fn authorize(&self, request: &AuthorizationRequest) -> Result<(), String> {
+        if self.is_superuser {
+            return Ok(());
+        }
+        if request.keyspace.as_deref() == Some(AUTH_KEYSPACE) {
+            return Err(format!(
+                "Only superusers may access the {} keyspace",
+                AUTH_KEYSPACE
+            ));
+        }
+
+        let granted = |resource: &str| {
+            self.grants
+                .get(resource)
+                .is_some_and(|permissions| permissions.contains(&request.permission))
+        };
+        let on_keyspace = request
+            .keyspace
+            .as_deref()
+            .is_some_and(|keyspace| granted(&keyspace_resource(keyspace)));
+        if granted(ALL_KEYSPACES) || on_keyspace {
+            return Ok(());
+        }
+        Err(format!(
+            "User {} has no {} permission on {}",
+            self.name,
+            request.permission,
+            match &request.keyspace {
+                Some(keyspace) => format!("keyspace {}", keyspace),
+                None => "all keyspaces".to_string(),
+            }
+        ))
+    }
+}
+
+fn keyspace_resource(keyspace: &str) -> String {
+    format!("{}_{}", ALL_KEYSPACES, keyspace)
+}
+
+fn resource_of(keyspace: &Option<String>) -> String {
+    keyspace
+        .as_deref()
+        .map_or(ALL_KEYSPACES.to_string(), keyspace_resource)
+}
+
+/// A user as it is stored in the `system_auth` tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredRole {
+    pub role: Role,
+    pub salted_hash: String,
+}
+
+impl StoredRole {
+    /// Builds the role read by the statements of `role_selects`, which is `None` if the user
+    /// does not exist.
+    pub fn from_rows(role_rows: &Rows, permission_rows: &Rows) -> Option<StoredRole> {
+        let row = role_rows.rows_content.first()?;
+        let mut grants = BTreeMap::new();
+        for row in &permission_rows.rows_content {
+            let (Some(resource), Some(permissions)) =
+                (text(row.get("resource")), text(row.get("permissions")))
+            else {
+                continue;
+            };
+            let permissions = permissions
+                .split('_')
+                .filter_map(|permission| permission.parse().ok())
+                .collect();
+            grants.insert(resource, permissions);
+        }
+
+        Some(StoredRole {
+            role: Role {
+                name: text(row.get("role"))?,
+                is_superuser: matches!(row.get("is_superuser"), Some(ColumnValue::Boolean(true))),
+                grants,
+            },
+            salted_hash: text(row.get("salted_hash"))?,
+        })
+    }
+}
+
+fn text(value: Option<&ColumnValue>) -> Option<String> {
+    match value? {
+        ColumnValue::Varchar(text) | ColumnValue::Ascii(text) => Some(text.clone()),
+        _ => None,
+    }
+}
+
+fn insert_role(user: &str, salted_hash: &str, is_superuser: bool) -> String {
+    format!(
+        "INSERT INTO {}.{} (role, salted_hash, is_superuser) VALUES ('{}', '{}', {})",
+        AUTH_KEYSPACE, ROLES_TABLE, user, salted_hash, is_superuser
+    )
+}
+
+// Writes the permissions of `user` on `resource`, removing its row if it has none left.
+fn write_permissions(user: &str, resource: &str, permissions: &[Permission]) -> String {
+    if permissions.is_empty() {
+        return format!(
+            "DELETE FROM {}.{} WHERE role = '{}' AND resource = '{}'",
+            AUTH_KEYSPACE, PERMISSIONS_TABLE, user, resource
+        );
+    }
+    let permissions: Vec<String> = permissions.iter().map(Permission::to_string).collect();
+    format!(
+        "INSERT INTO {}.{} (role, resource, permissions) VALUES ('{}', '{}', '{}')",
+        AUTH_KEYSPACE,
+        PERMISSIONS_TABLE,
+        user,
+        resource,
+        permissions.join("_")
+    )
+}
+
+// The permissions named by a `GRANT` or `REVOKE`, where `None` is `ALL PERMISSIONS`.
+fn named_permissions(permission: &Option<String>) -> Result<Vec<Permission>, NodeError> {
+    let names = match permission {
+        Some(permission) => vec![permission.as_str()],
+        None => PERMISSIONS.to_vec(),
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            name.parse()
+                .map_err(|_| NodeError::Invalid(format!("Unknown permission {}", name)))
+        })
+        .collect()
+}
+
+/// Returns the statements on the `system_auth` tables that apply a role statement.
+///
+/// # Parameters
+/// - `query: &Query`
+///   - A `CREATE USER`, `ALTER USER`, `DROP USER`, `GRANT` or `REVOKE`.
+/// - `existing: Option<&StoredRole>`
+///   - The user the statement acts on, as it is stored, if it exists.
+///
+/// # Returns
+/// - `Ok(Vec<String>)` with the statements to run, which are none if the statement changes
+///   nothing (such as a `CREATE USER IF NOT EXISTS` of an existing user).
+/// - `Err(NodeError::Invalid)` if the user exists and is created, or does not exist and is
+///   changed.
+pub fn statements_of(
+    query: &Query,
+    existing: Option<&StoredRole>,
+) -> Result<Vec<String>, NodeError> {
+    let missing = |user: &str| NodeError::Invalid(format!("User {} does not exist", user));
+
+    let statements = match (query, existing) {
+        (Query::CreateUser(create), Some(_)) if create.if_not_exists_clause => vec![],
+        (Query::CreateUser(create), Some(_)) => {
+            return Err(NodeError::Invalid(format!(
+                "User {} already exists",
+                create.get_name()
+            )))
+        }
+        (Query::CreateUser(create), None) => vec![insert_role(
+            &create.get_name(),
+            &hash_password(&create.password),
+            create.superuser,
+        )],
+        (Query::AlterUser(alter), Some(stored)) => {
+            let salted_hash = match &alter.password {
+                Some(password) => hash_password(password),
+                None => stored.salted_hash.clone(),
+            };
+            let is_superuser = alter.superuser.unwrap_or(stored.role.is_superuser);
+            vec![insert_role(&alter.get_name(), &salted_hash, is_superuser)]
+        }
+        (Query::DropUser(drop), None) if drop.get_if_exists_clause() => vec![],
+        (Query::DropUser(drop), Some(_)) => {
+            let user = drop.get_name();
+            vec![
+                format!(
+                    "DELETE FROM {}.{} WHERE role = '{}'",
+                    AUTH_KEYSPACE, ROLES_TABLE, user
+                ),
+                format!(
+                    "DELETE FROM {}.{} WHERE role = '{}'",
+                    AUTH_KEYSPACE, PERMISSIONS_TABLE, user
+                ),
+            ]
+        }
+        (Query::Grant(grant), Some(stored)) => {
+            let resource = resource_of(&grant.keyspace);
+            let mut permissions = stored
+                .role
+                .grants
+                .get(&resource)
+                .cloned()
+                .unwrap_or_default();
+            for permission in named_permissions(&grant.permission)? {
+                if !permissions.contains(&permission) {
+                    permissions.push(permission);
+                }
+            }
+            vec![write_permissions(&grant.user, &resource, &permissions)]
+        }
+        (Query::Revoke(revoke), Some(stored)) => {
+            let resource = resource_of(&revoke.keyspace);
+            let revoked = named_permissions(&revoke.permission)?;
+            let mut permissions = stored
+                .role
+                .grants
+                .get(&resource)
+                .cloned()
+                .unwrap_or_default();
+            permissions.retain(|permission| !revoked.contains(permission));
+            vec![write_permissions(&revoke.user, &resource, &permissions)]
+        }
+        (Query::AlterUser(alter), None) => return Err(missing(&alter.get_name())),
+        (Query::DropUser(drop), None) => return Err(missing(&drop.get_name())),
+        (Query::Grant(grant), None) => return Err(missing(&grant.user)),
+        (Query::Revoke(revoke), None) => return Err(missing(&revoke.user)),
+        _ => return Err(NodeError::OtherError),
+    };
+    Ok(statements)
+}
+
+/// Returns the user a role statement acts on.
+pub fn target_user(query: &Query) -> Option<String> {
+    match query {
+        Query::CreateUser(create) => Some(create.get_name()),
+        Query::AlterUser(alter) => Some(alter.get_name()),
+        Query::DropUser(drop) => Some(drop.get_name()),
+        Query::Grant(grant) => Some(grant.user.clone()),
+        Query::Revoke(revoke) => Some(revoke.user.clone()),
+        _ => None,
+    }
+}
+
+/// Whether a role statement makes a user a superuser or takes that from it, which only
+/// superusers may do.
+pub fn changes_superuser(query: &Query) -> bool {
+    match query {
+        Query::CreateUser(create) => create.superuser,
+        Query::AlterUser(alter) => alter.superuser.is_some(),
+        _ => false,
+    }
+}
+
+/// The roles of the users logged in to a node, read from the `system_auth` tables.
+#[derive(Debug, Default)]
+pub struct RolesCache {
+    roles: HashMap<String, (Role, Instant)>,
+}
+
+impl RolesCache {
+    /// Returns the role of `user`, however old it is.
+    pub fn get(&self, user: &str) -> Option<&Role> {
+        self.roles.get(user).map(|(role, _)| role)
+    }
+
+    /// Whether the role of `user` has to be read again, since it is missing or older than
+    /// `ROLES_VALIDITY`.
+    pub fn is_stale(&self, user: &str, now: Instant) -> bool {
+        self.roles
+            .get(user)
+            .is_none_or(|(_, read_at)| now.duration_since(*read_at) >= ROLES_VALIDITY)
+    }
+
+    pub fn insert(&mut self, role: Role, now: Instant) {
+        self.roles.insert(role.name.clone(), (role, now));
+    }
+
+    /// Forgets the role of `user`, so it is read again before its next statement.
+    pub fn invalidate(&mut self, user: &str) {
+        self.roles.remove(user);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::QueryCreator;
+
+    fn query(cql: &str) -> Query {
+        QueryCreator::new().handle_query(cql.to_string()).unwrap()
+    }
+
+    fn request(cql: &str, keyspace: &str) -> AuthorizationRequest {
+        AuthorizationRequest::of("pilot", &query(cql), Some(keyspace.to_string())).remove(0)
+    }
+
+    #[test]
+    fn test_credentials_from_token() {
+        assert_eq!(
+            Credentials::from_token("\0pilot\0take off"),
+            Credentials {
+                user: "pilot".to_string(),
+                password: "take off".to_string()
+            }
+        );
+        assert!(Credentials::from_token("admin").are_default());
+        assert!(!Credentials::from_token("\0pilot\0admin").are_default());
+        assert!(is_valid_user_name("pilot_2"));
+        assert!(!is_valid_user_name("pilot' OR role = 'admin"));
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let salted_hash = hash_password("take off");
+
+        assert!(verify_password("take off", &salted_hash));
+        assert!(!verify_password("landing", &salted_hash));
+        assert_ne!(hash_password("take off"), salted_hash);
+        assert!(!verify_password("take off", "not a hash"));
+    }
+
+    #[test]
+    fn test_role_authorize() {
+        let mut role = Role::without_permissions("pilot");
+        role.grants.insert(
+            keyspace_resource("sky"),
+            vec![Permission::Select, Permission::Modify],
+        );
+
+        assert!(role
+            .authorize(&request(
+                "SELECT * FROM flights WHERE number = 'AR1'",
+                "sky"
+            ))
+            .is_ok());
+        assert!(role
+            .authorize(&request("DROP TABLE flights", "sky"))
+            .is_err());
+        assert!(role
+            .authorize(&request(
+                "SELECT * FROM airports WHERE iata = 'EZE'",
+                "ground"
+            ))
+            .is_err());
+
+        role.grants
+            .insert(ALL_KEYSPACES.to_string(), vec![Permission::Drop]);
+        assert!(role
+            .authorize(&request("DROP TABLE airports", "ground"))
+            .is_ok());
+        // Only superusers may read the users
+        role.grants
+            .insert(keyspace_resource(AUTH_KEYSPACE), vec![Permission::Select]);
+        assert!(role
+            .authorize(&request(
+                "SELECT * FROM roles WHERE role = 'admin'",
+                AUTH_KEYSPACE
+            ))
+            .is_err());
+        assert!(Role::default_superuser()
+            .authorize(&request(
+                "SELECT * FROM roles WHERE role = 'admin'",
+                AUTH_KEYSPACE
+            ))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_role_statements_need_authorize_on_every_keyspace() {
+        let mut role = Role::without_permissions("pilot");
+        role.grants
+            .insert(keyspace_resource("sky"), vec![Permission::Authorize]);
+
+        for cql in [
+            "ALTER USER admin WITH PASSWORD 'x'",
+            "DROP USER admin",
+            "CREATE USER tower WITH PASSWORD 'x'",
+            "GRANT SELECT ON KEYSPACE sky TO tower",
+        ] {
+            assert!(role.authorize(&request(cql, "sky")).is_err(), "{}", cql);
+        }
+
+        role.grants
+            .insert(ALL_KEYSPACES.to_string(), vec![Permission::Authorize]);
+        assert!(role.authorize(&request("DROP USER tower", "sky")).is_ok());
+        assert!(Role::default_superuser()
+            .authorize(&request("DROP USER tower", "sky"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_statements_of_grant_and_revoke() {
+        let mut stored = StoredRole {
+            role: Role::without_permissions("pilot"),
+            salted_hash: hash_password("take off"),
+        };
+        stored
+            .role
+            .grants
+            .insert(keyspace_resource("sky"), vec![Permission::Select]);
+
+        assert_eq!(
+            statements_of(
+                &query("GRANT MODIFY ON KEYSPACE sky TO pilot"),
+                Some(&stored)
+            )
+            .unwrap(),
+            vec![
+                "INSERT INTO system_auth.role_permissions (role, resource, permissions) \
+                 VALUES ('pilot', 'data_sky', 'SELECT_MODIFY')"
+            ]
+        );
+        assert_eq!(
+            statements_of(&query("REVOKE ALL ON KEYSPACE sky FROM pilot"), Some(&stored)).unwrap(),
+            vec!["DELETE FROM system_auth.role_permissions WHERE role = 'pilot' AND resource = 'data_sky'"]
+        );
+        assert!(statements_of(&query("GRANT SELECT ON ALL KEYSPACES TO tower"), None).is_err());
+        assert!(
+            statements_of(&query("CREATE USER pilot WITH PASSWORD 'x'"), Some(&stored)).is_err()
+        );
+        assert!(statements_of(
+            &query("CREATE USER IF NOT EXISTS pilot WITH PASSWORD 'x'"),
+            Some(&stored)
+        )
+        .unwrap()
+        .is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+//! CQL scripts with the schema of the cluster, to move keyspaces and tables between clusters
+//! without their data.
+//!
+//! A script is a list of statements ended by `;`, which can span many lines. Lines starting with
+//! `--` are comments. Only `CREATE KEYSPACE`, `CREATE TABLE` and `USE` statements are accepted.
+
+use std::{fs, io, path::Path};
+
+use gossip::structures::application_state::Schema;
+use query_creator::{Query, QueryCreator};
+
+use crate::errors::NodeError;
+
+/// Returns the script that creates the given keyspace, or every keyspace of the schema sorted by
+/// name, with their tables. Each statement takes a single line.
+///
+/// Fails with `KeyspaceError` if the keyspace does not exist.
+pub fn export(schema: &Schema, keyspace: Option<&str>) -> Result<Vec<String>, NodeError> {
+    let mut keyspaces: Vec<_> = match keyspace {
+        Some(name) => vec![schema.keyspaces.get(name).ok_or(NodeError::KeyspaceError)?],
+        None => schema.keyspaces.values().collect(),
+    };
+    keyspaces.sort_by_key(|keyspace| keyspace.get_name());
+
+    Ok(keyspaces
+        .iter()
+        .flat_map(|keyspace| keyspace.describe())
+        .collect())
+}
+
+/// Splits a script into its statements, without comments nor the `;` that ends them. A `;`
+/// between quotes, as in a table comment, does not end a statement.
+pub fn split_statements(script: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for line in script.lines() {
+        if !in_quotes && line.trim_start().starts_with("--") {
+            continue;
+        }
+        for c in line.chars() {
+            match c {
+                ';' if !in_quotes => {
+                    statements.push(current.trim().to_string());
+                    current.clear();
+                }
+                '\'' => {
+                    in_quotes = !in_quotes;
+                    current.push(c);
+                }
+                _ => current.push(c),
+            }
+        }
+        current.push(' ');
+    }
+    statements.push(current.trim().to_string());
+
+    statements.retain(|statement| !statement.is_empty());
+    statements
+}
+
+/// Returns the statements of a script to import it, in order, with `IF NOT EXISTS` added to every
+/// `CREATE` so keyspaces and tables that already exist are left as they are.
+///
+/// Fails with `ScriptError` if a statement is not valid CQL or does not create schema, before any
+/// statement is run.
+pub fn import_statements(script: &str) -> Result<Vec<String>, NodeError> {
+    split_statements(script)
+        .into_iter()
+        .map(|statement| {
+            let query = QueryCreator::new()
+                .handle_query(statement.clone())
+                .map_err(|e| NodeError::ScriptError(format!("{}: {}", statement, e)))?;
+            match query {
+                Query::CreateKeyspace(mut create_keyspace) => {
+                    create_keyspace.if_not_exists_clause = true;
+                    Ok(create_keyspace.serialize())
+                }
+                Query::CreateTable(mut create_table) => {
+                    create_table.if_not_exists_clause = true;
+                    Ok(create_table.serialize())
+                }
+                Query::Use(_) => Ok(statement),
+                _ => Err(NodeError::ScriptError(format!(
+                    "{}: not a schema statement",
+                    statement
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Record of the initial schema script run by the first seed of the cluster when it formed it,
+/// shown in the `system.schema_bootstrap` virtual table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaBootstrap {
+    /// Where the script was read from.
+    pub source: String,
+    /// Amount of statements run.
+    pub statements: usize,
+    /// When the script was run, in milliseconds since the epoch.
+    pub ran_at: i64,
+}
+
+impl SchemaBootstrap {
+    /// Writes the record to `path`, as `<ran_at>,<statements>,<source>`. It is written under a
+    /// temporary name and synced first, so a crash never leaves half a record behind.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        fs::write(
+            &temp_path,
+            format!("{},{},{}", self.ran_at, self.statements, self.source),
+        )?;
+        fs::File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, path)
+    }
+
+    /// Reads the record written to `path`, if there is one.
+    pub fn read(path: &Path) -> Option<Self> {
+        let record = fs::read_to_string(path).ok()?;
+        let mut fields = record.splitn(3, ',');
+        Some(Self {
+            ran_at: fields.next()?.parse().ok()?,
+            statements: fields.next()?.parse().ok()?,
+            source: fields.next()?.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
+    use query_creator::clauses::keyspace::create_keyspace_cql::CreateKeyspace;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+
+    use super::*;
+
+    fn schema() -> Schema {
+        let keyspace = |name: &str, tables: Vec<&str>| {
+            KeyspaceSchema::new(
+                CreateKeyspace::deserialize(&format!(
+                    "CREATE KEYSPACE {} WITH replication = {{'class': 'SimpleStrategy', 'replication_factor': 1}}",
+                    name
+                ))
+                .unwrap(),
+                tables
+                    .into_iter()
+                    .map(|table| TableSchema::new(CreateTable::deserialize(table).unwrap()))
+                    .collect(),
+            )
+        };
+        let mut schema = Schema::new();
+        schema.keyspaces = HashMap::from([
+            (
+                "sky".to_string(),
+                keyspace(
+                    "sky",
+                    vec!["CREATE TABLE sky.flights (id INT PRIMARY KEY, origin TEXT) WITH comment = 'a; b'"],
+                ),
+            ),
+            ("ground".to_string(), keyspace("ground", vec![])),
+        ]);
+        schema
+    }
+
+    #[test]
+    fn test_export_and_import_schema() {
+        let script = export(&schema(), None).unwrap();
+        assert_eq!(script.len(), 3);
+        assert!(script[0].starts_with("CREATE KEYSPACE ground "));
+        assert!(script[1].starts_with("CREATE KEYSPACE sky "));
+        assert!(script[2].starts_with("CREATE TABLE sky.flights "));
+
+        assert_eq!(export(&schema(), Some("sky")).unwrap().len(), 2);
+        assert!(export(&schema(), Some("water")).is_err());
+
+        let script = format!("-- Flights schema\n{}", script.join("\n"));
+
+        let statements = import_statements(&script).unwrap();
+        assert_eq!(statements.len(), 3);
+        assert!(statements[1].starts_with("CREATE KEYSPACE IF NOT EXISTS sky "));
+        assert!(statements[2].starts_with("CREATE TABLE IF NOT EXISTS sky.flights "));
+    }
+
+    #[test]
+    fn test_schema_bootstrap_is_kept_in_its_file() {
+        let path = std::env::temp_dir().join(format!("schema_bootstrap_{}", uuid::Uuid::new_v4()));
+        assert_eq!(SchemaBootstrap::read(&path), None);
+
+        let bootstrap = SchemaBootstrap {
+            source: "/etc/schema,v2.cql".to_string(),
+            statements: 3,
+            ran_at: 1_700_000_000_000,
+        };
+        bootstrap.write(&path).unwrap();
+        assert_eq!(SchemaBootstrap::read(&path), Some(bootstrap));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import_only_accepts_schema_statements() {
+        assert_eq!(
+            split_statements("USE sky;\nCREATE TABLE flights (\n  id INT PRIMARY KEY\n);\n\n"),
+            vec!["USE sky", "CREATE TABLE flights (   id INT PRIMARY KEY )"]
+        );
+        assert_eq!(
+            split_statements("CREATE TABLE t (id INT PRIMARY KEY) WITH comment = 'a; b';").len(),
+            1
+        );
+        assert!(import_statements("USE sky; CREATE TABLE flights (id INT PRIMARY KEY);").is_ok());
+        assert!(import_statements("INSERT INTO sky.flights (id) VALUES (1);").is_err());
+        assert!(import_statements("CREATE NOTHING;").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! The signal that stops the threads of a node once it is shut down.
+//!
+//! The threads started by `Node::start` (the listeners, gossip and the background work) wait on
+//! the signal instead of sleeping, so they stop as soon as the node is shut down instead of after
+//! their next round, and `Node::shutdown` can join them all.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far a node got in shutting down.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum ShutdownState {
+    #[default]
+    Running,
+    /// The threads of the node were told to stop.
+    Stopping,
+    /// Every thread of the node stopped.
+    Stopped,
+    /// The node was killed: its threads were told to stop, but nobody waits for them.
+    Killed,
+}
+
+/// Tells the threads of a node to stop, and who waits for the node to stop once they did. Clones
+/// share the same signal.
+///
+/// ### Fields
+/// - `state`: How far the node got in shutting down, and the condition its changes are announced on.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownSignal {
+    state: Arc<(Mutex<ShutdownState>, Condvar)>,
+}
+
+impl ShutdownSignal {
+    /// Creates the signal of a running node.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Moves the signal to `state`, waking every thread waiting on it
+    fn set(&self, state: ShutdownState) {
+        let (current, changed) = &*self.state;
+        if let Ok(mut current) = current.lock() {
+            *current = state;
+        }
+        changed.notify_all();
+    }
+
+    /// Tells every thread waiting on the signal to stop.
+    pub fn stop(&self) {
+        self.set(ShutdownState::Stopping);
+    }
+
+    /// Announces that every thread of the node stopped, which `wait_stopped` waits for.
+    pub fn finish(&self) {
+        self.set(ShutdownState::Stopped);
+    }
+
+    /// Kills the node: tells its threads to stop and wakes `wait_stopped` without waiting for them.
+    pub fn kill(&self) {
+        self.set(ShutdownState::Killed);
+    }
+
+    /// Returns whether the node was killed instead of shut down.
+    pub fn is_killed(&self) -> bool {
+        self.state
+            .0
+            .lock()
+            .is_ok_and(|state| *state == ShutdownState::Killed)
+    }
+
+    /// Returns whether the threads were told to stop. A poisoned signal counts as stopping.
+    pub fn is_stopping(&self) -> bool {
+        self.state
+            .0
+            .lock()
+            .map_or(true, |state| *state != ShutdownState::Running)
+    }
+
+    /// Waits for `duration`, or until the threads are told to stop, and returns whether they were.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (state, changed) = &*self.state;
+        let Ok(mut state) = state.lock() else {
+            return true;
+        };
+        while *state == ShutdownState::Running {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            state = match changed.wait_timeout(state, left) {
+                Ok((state, _)) => state,
+                Err(_) => return true,
+            };
+        }
+        true
+    }
+
+    /// Waits until every thread of the node stopped, or the node was killed.
+    pub fn wait_stopped(&self) {
+        let (state, changed) = &*self.state;
+        let Ok(mut state) = state.lock() else {
+            return;
+        };
+        while *state != ShutdownState::Stopped && *state != ShutdownState::Killed {
+            state = match changed.wait(state) {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_stopping_wakes_the_sleeping_threads() {
+        let signal = ShutdownSignal::new();
+        assert!(!signal.sleep(Duration::from_millis(10)));
+
+        let sleeper = {
+            let signal = signal.clone();
+            thread::spawn(move || {
+                let started = Instant::now();
+                (signal.sleep(Duration::from_secs(30)), started.elapsed())
+            })
+        };
+        signal.stop();
+        let (stopped, slept) = sleeper.join().unwrap();
+        assert!(stopped);
+        assert!(slept < Duration::from_secs(30));
+        assert!(signal.is_stopping());
+
+        let waiter = {
+            let signal = signal.clone();
+            thread::spawn(move || signal.wait_stopped())
+        };
+        signal.finish();
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn test_killing_wakes_the_waiters_without_stopping_the_threads() {
+        let signal = ShutdownSignal::new();
+        let waiter = {
+            let signal = signal.clone();
+            thread::spawn(move || signal.wait_stopped())
+        };
+        signal.kill();
+        waiter.join().unwrap();
+        assert!(signal.is_stopping());
+        assert!(signal.is_killed());
+    }
+}
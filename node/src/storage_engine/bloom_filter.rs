@@ -0,0 +1,298 @@
+//! Bloom filters of the partition keys of the data files of the tables, so the reads of a single
+//! partition skip the files that do not hold it without reading them.
+//!
+//! Each data file (the CSV file of a table and, with the LSM backend, each of its SSTables) may have
+//! a filter next to it, `<file>.bloom`, written with the size and modification time of the file it
+//! was built from. The SSTables get theirs when they are written, as they never change, and the CSV
+//! files when their table is compacted. A filter that does not match its file anymore, because the
+//! file was written since, is ignored until the next compaction builds it again.
+//!
+//! A filter never misses a partition of its file, but says that it may hold about 1% of the ones
+//! it does not hold.
+//!
+//! Filters are written as two lines: `<file size>,<file modification time>,<hashes>`, in
+//! nanoseconds since the epoch, and the bits of the filter as hexadecimal words.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, Metadata},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use query_creator::clauses::types::{column::Column, datatype::DataType};
+
+use super::{
+    errors::StorageEngineError, row_cache::canonical, row_stamp::split_row, StorageEngine,
+};
+
+/// Bits of a filter for each partition it holds, which with `HASHES` gives about 1% of false positives.
+const BITS_PER_KEY: usize = 10;
+
+/// Bits set for each partition of a filter.
+const HASHES: u32 = 7;
+
+/// The partitions of a data file, as a bloom filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Returns an empty filter sized for `partitions` partitions.
+    pub fn new(partitions: usize) -> Self {
+        Self {
+            bits: vec![0; (partitions.max(1) * BITS_PER_KEY).div_ceil(64)],
+            hashes: HASHES,
+        }
+    }
+
+    pub fn insert(&mut self, partition: &str) {
+        for bit in self.bit_indices(partition) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns whether the partition may have been inserted. It was not if it returns `false`.
+    pub fn may_contain(&self, partition: &str) -> bool {
+        self.bit_indices(partition)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Returns the bits of a partition, derived from the two halves of its hash
+    fn bit_indices(&self, partition: &str) -> impl Iterator<Item = usize> {
+        let hash = fnv1a(partition.as_bytes());
+        let (first, second) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let len = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    /// Writes the filter of a data file next to it, built from the file as it was when `metadata`
+    /// was taken. It is written under a temporary name, so a crash never leaves half a filter behind.
+    pub(super) fn write(
+        &self,
+        data_file: &Path,
+        metadata: &Metadata,
+    ) -> Result<(), StorageEngineError> {
+        let (size, modified) = file_stamp(metadata).ok_or(StorageEngineError::IoError)?;
+        let path = filter_path(data_file);
+        let temp_path = path.with_extension("bloom.tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writeln!(writer, "{},{},{}", size, modified, self.hashes)?;
+        for word in &self.bits {
+            write!(writer, "{:016x}", word)?;
+        }
+        writeln!(writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp_path, &path).map_err(|_| StorageEngineError::FileWriteFailed)
+    }
+
+    /// Reads the filter of a data file, if it has one built from the file as it is described by
+    /// `metadata`.
+    pub(super) fn read(data_file: &Path, metadata: &Metadata) -> Option<Self> {
+        let file = File::open(filter_path(data_file)).ok()?;
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next()?.ok()?;
+        let mut fields = header.split(',');
+        let stamp = (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?);
+        if Some(stamp) != file_stamp(metadata) {
+            return None;
+        }
+        let hashes = fields.next()?.parse().ok()?;
+
+        let words = lines.next()?.ok()?;
+        let bits = (0..words.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(words.get(i..i + 16)?, 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        if bits.is_empty() {
+            return None;
+        }
+        Some(Self { bits, hashes })
+    }
+}
+
+// FNV-1a, which unlike the hasher of the standard library hashes the same on every build, as the
+// filters are kept on disk
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Returns the size and modification time of a file, which change whenever it is written.
+pub(super) fn file_stamp(metadata: &Metadata) -> Option<(u64, u128)> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos()))
+}
+
+/// Returns the path of the filter of a data file.
+pub(super) fn filter_path(data_file: &Path) -> PathBuf {
+    data_file.with_extension("bloom")
+}
+
+/// Returns whether a data file may hold a partition, which it does not if its filter says so. Files
+/// without a filter, or written since it was built, may hold any partition.
+pub(super) fn may_hold_partition(data_file: &Path, partition: &str) -> bool {
+    let Ok(metadata) = fs::metadata(data_file) else {
+        return true;
+    };
+    BloomFilter::read(data_file, &metadata).is_none_or(|filter| filter.may_contain(partition))
+}
+
+/// Removes the filter of a data file that was removed, if it has one.
+pub(super) fn remove_filter(data_file: &Path) {
+    let _ = fs::remove_file(filter_path(data_file));
+}
+
+/// Where the values of the partition key of a table are, in its rows or in the primary keys of
+/// its rows, with their types, so a partition is written the same whatever way its values were
+/// written.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PartitionColumns(Vec<(usize, DataType)>);
+
+impl PartitionColumns {
+    /// Returns where the partition key is in the rows of a table.
+    pub(super) fn of_rows(columns: &[Column]) -> Self {
+        Self(
+            columns
+                .iter()
+                .enumerate()
+                .filter(|(_, column)| column.is_partition_key)
+                .map(|(i, column)| (i, column.data_type))
+                .collect(),
+        )
+    }
+
+    /// Returns where the partition key is in the primary keys of the rows of a table.
+    pub(super) fn of_primary_keys(columns: &[Column]) -> Self {
+        Self(
+            columns
+                .iter()
+                .filter(|column| column.is_partition_key || column.is_clustering_column)
+                .enumerate()
+                .filter(|(_, column)| column.is_partition_key)
+                .map(|(i, column)| (i, column.data_type))
+                .collect(),
+        )
+    }
+
+    /// Returns the partition of a row or a primary key, given as its values separated by commas.
+    pub(super) fn partition(&self, values: &str) -> String {
+        let values: Vec<&str> = values.split(',').collect();
+        self.0
+            .iter()
+            .map(|(i, data_type)| canonical(values.get(*i).copied().unwrap_or_default(), data_type))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+impl StorageEngine {
+    /// Builds the filter of a CSV data file of a table again if it was written since its filter was
+    /// built, or has none. Returns whether it was built.
+    pub(super) fn refresh_bloom_filter(
+        &self,
+        data_file: &Path,
+        columns: &[Column],
+    ) -> Result<bool, StorageEngineError> {
+        let Ok(file) = File::open(data_file) else {
+            return Ok(false);
+        };
+        // The metadata of the file being read, even if it is replaced while it is read
+        let metadata = file.metadata()?;
+        if BloomFilter::read(data_file, &metadata).is_some() {
+            return Ok(false);
+        }
+
+        let partition_columns = PartitionColumns::of_rows(columns);
+        let mut partitions = HashSet::new();
+        for line in BufReader::new(file).lines().skip(1) {
+            let line = line?;
+            partitions.insert(partition_columns.partition(split_row(&line)?.0));
+        }
+
+        let mut filter = BloomFilter::new(partitions.len());
+        for partition in &partitions {
+            filter.insert(partition);
+        }
+        filter.write(data_file, &metadata)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_bloom_filter_holds_its_partitions() {
+        let mut filter = BloomFilter::new(1000);
+        for number in 0..1000 {
+            filter.insert(&format!("EZE,{}", number));
+        }
+        assert!((0..1000).all(|number| filter.may_contain(&format!("EZE,{}", number))));
+
+        let false_positives = (0..10000)
+            .filter(|number| filter.may_contain(&format!("AEP,{}", number)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_bloom_filter_is_ignored_once_its_file_is_written() {
+        let folder = PathBuf::from(format!("/tmp/bloom_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let data_file = folder.join("flights.csv");
+        fs::write(&data_file, "airport,number\nEZE,1;1\n").unwrap();
+
+        let mut filter = BloomFilter::new(1);
+        filter.insert("EZE");
+        filter
+            .write(&data_file, &fs::metadata(&data_file).unwrap())
+            .unwrap();
+        assert_eq!(
+            BloomFilter::read(&data_file, &fs::metadata(&data_file).unwrap()),
+            Some(filter)
+        );
+        assert!(may_hold_partition(&data_file, "EZE"));
+        assert!(!may_hold_partition(&data_file, "AEP"));
+
+        thread::sleep(Duration::from_millis(10));
+        fs::write(&data_file, "airport,number\nEZE,1;1\nAEP,2;2\n").unwrap();
+        assert!(may_hold_partition(&data_file, "AEP"));
+
+        remove_filter(&data_file);
+        assert!(!filter_path(&data_file).exists());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_partition_columns_write_partitions_the_same() {
+        let mut airport = Column::new("airport", DataType::String, true, false);
+        airport.is_partition_key = true;
+        let mut day = Column::new("day", DataType::Int, true, false);
+        day.is_partition_key = true;
+        let mut number = Column::new("number", DataType::Int, true, false);
+        number.is_clustering_column = true;
+        let status = Column::new("status", DataType::String, false, true);
+        let columns = [status, airport, number, day];
+
+        assert_eq!(
+            PartitionColumns::of_rows(&columns).partition("landed,EZE,7,03"),
+            "EZE,3"
+        );
+        assert_eq!(
+            PartitionColumns::of_primary_keys(&columns).partition("EZE,7,3"),
+            "EZE,3"
+        );
+    }
+}
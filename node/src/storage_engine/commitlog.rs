@@ -0,0 +1,637 @@
+//! Write-ahead commit log of the mutations applied by a node.
+//!
+//! Every `INSERT`, `UPDATE` and `DELETE` is appended to the log, and synced to disk, before it is
+//! applied to the files of its table, so a node that crashes can recover the mutations it
+//! acknowledged. The log is split in segments of about `SEGMENT_SIZE` bytes, named
+//! `segment_<id>.log` with increasing ids; a new one is started when the current one is full and
+//! on every start of the node.
+//!
+//! The data files of the node are kept when it restarts, so a segment is only needed until its
+//! mutations are in them. With the CSV backend a mutation is written to its data file when it is
+//! applied, and a segment is retired as soon as the next one is started and its mutations were
+//! applied. With the LSM backend, the segments are retired when every memtable was flushed. The
+//! segments of the previous run are kept until their mutations were replayed. Replaying a segment
+//! whose mutations were already in the data files is harmless, as they are applied in order and
+//! counter increments are never counted twice.
+//!
+//! Each entry takes a line: `timestamp;replication;keyspace;table;kind;payload`, where `kind` is
+//! `I`, `U` or `D`. The payload of an insert is its `if_not_exists` flag, the values of the row,
+//! its `USING TTL` (empty if it has none) and the names of the columns of the values, and the one
+//! of updates and deletes is their CQL statement. The values of an insert are matched to the
+//! columns of its table by name when it is replayed, so a table altered since it was logged gets
+//! them in the right columns; entries logged without the names are replayed by position.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use gossip::structures::application_state::TableSchema;
+use query_creator::clauses::{delete_cql::Delete, update_cql::Update};
+
+use super::{errors::StorageEngineError, row_stamp::RowStamp, StorageEngine};
+
+/// Size above which the current segment is closed and a new one started.
+pub const SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// A mutation of a table, as it is applied to its files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// A row to insert, with a value for every column of the table, in the order of `columns`
+    /// (the columns of the table when it was written), and the TTL it was written with (`None`
+    /// for the default TTL of the table).
+    Insert {
+        columns: Vec<String>,
+        values: Vec<String>,
+        if_not_exists: bool,
+        ttl: Option<u64>,
+    },
+    Update(Update),
+    Delete(Delete),
+}
+
+impl Mutation {
+    /// A row to insert in `table`, with a value for each of its columns, in order.
+    pub fn insert(
+        table: &TableSchema,
+        values: Vec<String>,
+        if_not_exists: bool,
+        ttl: Option<u64>,
+    ) -> Mutation {
+        Mutation::Insert {
+            columns: table
+                .get_columns()
+                .into_iter()
+                .map(|column| column.name)
+                .collect(),
+            values,
+            if_not_exists,
+            ttl,
+        }
+    }
+}
+
+/// A mutation recorded in the commit log, with everything needed to apply it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitLogEntry {
+    pub keyspace: String,
+    pub table: String,
+    pub is_replication: bool,
+    pub timestamp: i64,
+    pub mutation: Mutation,
+}
+
+impl CommitLogEntry {
+    fn to_line(&self) -> String {
+        let (kind, payload) = match &self.mutation {
+            Mutation::Insert {
+                columns,
+                values,
+                if_not_exists,
+                ttl,
+            } => {
+                let ttl = ttl.map(|ttl| ttl.to_string()).unwrap_or_default();
+                let payload = format!(
+                    "{};{};{};{}",
+                    *if_not_exists as u8,
+                    values.join(","),
+                    ttl,
+                    columns.join(",")
+                );
+                ("I", payload)
+            }
+            Mutation::Update(update) => ("U", update.serialize()),
+            Mutation::Delete(delete) => ("D", delete.serialize()),
+        };
+        format!(
+            "{};{};{};{};{};{}",
+            self.timestamp,
+            self.is_replication as u8,
+            self.keyspace,
+            self.table,
+            kind,
+            payload.replace('\n', " ")
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, ';');
+        let timestamp = fields.next()?.parse().ok()?;
+        let is_replication = fields.next()? == "1";
+        let keyspace = fields.next()?.to_string();
+        let table = fields.next()?.to_string();
+        let kind = fields.next()?;
+        let payload = fields.next()?;
+
+        let mutation = match kind {
+            "I" => {
+                let mut payload = payload.splitn(4, ';');
+                let if_not_exists = payload.next()?;
+                let values = payload.next()?;
+                let ttl = match payload.next() {
+                    Some("") | None => None,
+                    Some(ttl) => Some(ttl.parse().ok()?),
+                };
+                // Entries logged before the names of the columns were have none
+                let columns = match payload.next() {
+                    Some(columns) => columns.split(',').map(|name| name.to_string()).collect(),
+                    None => vec![],
+                };
+                Mutation::Insert {
+                    columns,
+                    values: values.split(',').map(|value| value.to_string()).collect(),
+                    if_not_exists: if_not_exists == "1",
+                    ttl,
+                }
+            }
+            "U" => Mutation::Update(Update::deserialize(payload).ok()?),
+            "D" => Mutation::Delete(Delete::deserialize(payload).ok()?),
+            _ => return None,
+        };
+
+        Some(Self {
+            keyspace,
+            table,
+            is_replication,
+            timestamp,
+            mutation,
+        })
+    }
+}
+
+/// The commit log of a node, which appends entries to its latest segment.
+pub struct CommitLog {
+    folder: PathBuf,
+    segment_size: u64,
+    segment_id: u64,
+    segment: File,
+    segment_len: u64,
+    // Entries logged but not applied yet, by their segment
+    applying: BTreeMap<u64, usize>,
+    // Whether the segments of the previous run are not replayed yet
+    replaying: bool,
+    retire_when_full: bool,
+}
+
+/// A mutation logged in the commit log while it is applied. Its segment is not retired until it
+/// is dropped, once the mutation was applied (or failed to).
+pub struct LoggedMutation {
+    log: Arc<Mutex<CommitLog>>,
+    segment_id: u64,
+}
+
+impl Drop for LoggedMutation {
+    fn drop(&mut self) {
+        if let Ok(mut log) = self.log.lock() {
+            if let Some(applying) = log.applying.get_mut(&self.segment_id) {
+                *applying -= 1;
+                if *applying == 0 {
+                    log.applying.remove(&self.segment_id);
+                }
+            }
+        }
+    }
+}
+
+impl CommitLog {
+    /// Opens the commit log in `folder`, creating it if needed, and starts a new segment after
+    /// the existing ones, which are kept until `finish_replay` is called.
+    pub fn open(folder: PathBuf) -> Result<Self, StorageEngineError> {
+        fs::create_dir_all(&folder).map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+        let previous = Self::segment_ids(&folder)?;
+        let segment_id = previous.last().map_or(0, |id| id + 1);
+        let segment = Self::create_segment(&folder, segment_id)?;
+
+        Ok(Self {
+            folder,
+            segment_size: SEGMENT_SIZE,
+            segment_id,
+            segment,
+            segment_len: 0,
+            applying: BTreeMap::new(),
+            replaying: !previous.is_empty(),
+            retire_when_full: true,
+        })
+    }
+
+    /// Sets the size above which the current segment is closed and a new one started.
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Sets whether a segment is retired once the next one is started and its mutations were
+    /// applied, which is only safe if an applied mutation is in the data files. Otherwise the
+    /// segments are only retired by `retire_before` once the memtables are flushed.
+    pub fn retire_when_full(&mut self, retire: bool) {
+        self.retire_when_full = retire;
+    }
+
+    /// Lets the segments of the previous run be retired, once their mutations were replayed.
+    pub fn finish_replay(&mut self) {
+        self.replaying = false;
+    }
+
+    /// Appends an entry to `log`, and syncs it to disk, before its mutation is applied. The
+    /// mutation must be applied before the returned `LoggedMutation` is dropped.
+    pub fn log(
+        log: &Arc<Mutex<CommitLog>>,
+        entry: &CommitLogEntry,
+    ) -> Result<LoggedMutation, StorageEngineError> {
+        let mut guard = log.lock().map_err(|_| StorageEngineError::IoError)?;
+        guard.append(entry)?;
+        let segment_id = guard.segment_id;
+        *guard.applying.entry(segment_id).or_default() += 1;
+        Ok(LoggedMutation {
+            log: Arc::clone(log),
+            segment_id,
+        })
+    }
+
+    /// Starts a new segment before the memtables are flushed, and returns the first segment that
+    /// must be kept after the flush: the mutations of the ones before it were all applied, so the
+    /// flush writes them to the data files.
+    pub fn flush_point(&mut self) -> Result<u64, StorageEngineError> {
+        if self.segment_len > 0 {
+            self.start_segment()?;
+        }
+        Ok(self.first_applying())
+    }
+
+    /// Removes the segments before `first_kept`, returned by `flush_point` before the memtables
+    /// were flushed, and returns how many were removed.
+    pub fn retire_before(&mut self, first_kept: u64) -> Result<usize, StorageEngineError> {
+        if self.replaying {
+            return Ok(0);
+        }
+        let mut retired = 0;
+        for id in Self::segment_ids(&self.folder)? {
+            if id >= first_kept {
+                break;
+            }
+            fs::remove_file(Self::segment_path(&self.folder, id))
+                .map_err(|_| StorageEngineError::FileDeletionFailed)?;
+            retired += 1;
+        }
+        Ok(retired)
+    }
+
+    // Appends an entry to the log and syncs it to disk, starting a new segment if the current
+    // one is full.
+    fn append(&mut self, entry: &CommitLogEntry) -> Result<(), StorageEngineError> {
+        if self.segment_len >= self.segment_size {
+            self.start_segment()?;
+            if self.retire_when_full {
+                self.retire_before(self.first_applying())?;
+            }
+        }
+
+        let line = format!("{}\n", entry.to_line());
+        self.segment
+            .write_all(line.as_bytes())
+            .map_err(|_| StorageEngineError::FileWriteFailed)?;
+        self.segment
+            .sync_data()
+            .map_err(|_| StorageEngineError::FileWriteFailed)?;
+        self.segment_len += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Returns the entries of every segment in `folder`, in the order they were appended. Lines
+    /// that can not be read, such as the last one of a segment being written when the node
+    /// crashed, are skipped.
+    pub fn read_entries(folder: &Path) -> Result<Vec<CommitLogEntry>, StorageEngineError> {
+        Self::read_entries_from(folder, &HashMap::new())
+    }
+
+    /// Returns the entries in `folder`, like `read_entries`, but the ones of a keyspace in
+    /// `replay_from` only from the segment it gives on. It leaves out the mutations a restored
+    /// snapshot of the keyspace already holds (see `StorageEngine::restore_snapshot`).
+    pub fn read_entries_from(
+        folder: &Path,
+        replay_from: &HashMap<String, u64>,
+    ) -> Result<Vec<CommitLogEntry>, StorageEngineError> {
+        if !folder.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut entries = Vec::new();
+        for id in Self::segment_ids(folder)? {
+            let segment = File::open(Self::segment_path(folder, id))?;
+            for line in BufReader::new(segment).lines() {
+                let Some(entry) = CommitLogEntry::from_line(&line?) else {
+                    continue;
+                };
+                if replay_from
+                    .get(&entry.keyspace)
+                    .is_none_or(|&first| id >= first)
+                {
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn start_segment(&mut self) -> Result<(), StorageEngineError> {
+        self.segment_id += 1;
+        self.segment = Self::create_segment(&self.folder, self.segment_id)?;
+        self.segment_len = 0;
+        Ok(())
+    }
+
+    // Returns the first segment with mutations being applied, or the current one
+    fn first_applying(&self) -> u64 {
+        self.applying
+            .keys()
+            .next()
+            .map_or(self.segment_id, |&id| id.min(self.segment_id))
+    }
+
+    fn segment_path(folder: &Path, id: u64) -> PathBuf {
+        folder.join(format!("segment_{}.log", id))
+    }
+
+    fn create_segment(folder: &Path, id: u64) -> Result<File, StorageEngineError> {
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(Self::segment_path(folder, id))
+            .map_err(|_| StorageEngineError::FileWriteFailed)
+    }
+
+    // Returns the ids of the segments in `folder`, sorted.
+    fn segment_ids(folder: &Path) -> Result<Vec<u64>, StorageEngineError> {
+        let mut ids: Vec<u64> = fs::read_dir(folder)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("segment_")?
+                    .strip_suffix(".log")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+}
+
+impl StorageEngine {
+    /// Returns the folder of the commit log of the node. It is not inside the folder of the
+    /// keyspaces, so it is kept when they are reset on startup.
+    pub fn commit_log_path(&self) -> PathBuf {
+        self.root
+            .join(format!("commitlog_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Applies a mutation read from the commit log to the files of its table. The rows it writes
+    /// expire as when the mutation was first applied, counting from its timestamp.
+    ///
+    /// The values of an insert go to the columns of `table` with their names: the ones of the
+    /// columns dropped since it was logged are left out, and the columns added since are empty.
+    ///
+    /// # Arguments
+    /// - `entry`: The mutation to apply.
+    /// - `table`: The schema of the table of the mutation.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the mutation is applied.
+    /// - `Err(StorageEngineError)` if the storage operation fails.
+    pub fn apply(
+        &self,
+        entry: &CommitLogEntry,
+        table: &TableSchema,
+    ) -> Result<(), StorageEngineError> {
+        match &entry.mutation {
+            Mutation::Insert {
+                columns,
+                values,
+                if_not_exists,
+                ttl,
+            } => self.insert(
+                &entry.keyspace,
+                &entry.table,
+                values_by_name(columns, values, table),
+                table.get_columns(),
+                table.get_clustering_column_in_order(),
+                entry.is_replication,
+                *if_not_exists,
+                RowStamp::new(entry.timestamp, table.get_options().ttl_for(*ttl)),
+            ),
+            Mutation::Update(update) => self.update(
+                update.clone(),
+                table.clone(),
+                entry.is_replication,
+                &entry.keyspace,
+                entry.timestamp,
+            ),
+            Mutation::Delete(delete) => self.delete(
+                delete.clone(),
+                table.clone(),
+                &entry.keyspace,
+                entry.is_replication,
+                entry.timestamp,
+            ),
+        }
+    }
+}
+
+// Returns the values of an insert logged for `columns` in the order of the columns of `table`.
+fn values_by_name<'a>(
+    columns: &[String],
+    values: &'a [String],
+    table: &TableSchema,
+) -> Vec<&'a str> {
+    if columns.is_empty() {
+        return values.iter().map(|value| value.as_str()).collect();
+    }
+    table
+        .get_columns()
+        .iter()
+        .map(|column| {
+            columns
+                .iter()
+                .position(|name| *name == column.name)
+                .and_then(|i| values.get(i))
+                .map_or("", |value| value.as_str())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use uuid::Uuid;
+
+    fn insert(id: &str, status: &str, timestamp: i64) -> CommitLogEntry {
+        CommitLogEntry {
+            keyspace: "sky".to_string(),
+            table: "flights".to_string(),
+            is_replication: false,
+            timestamp,
+            mutation: Mutation::Insert {
+                columns: vec!["id".to_string(), "status".to_string()],
+                values: vec![id.to_string(), status.to_string()],
+                if_not_exists: false,
+                ttl: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_commit_log_rotates_and_replays() {
+        let root = PathBuf::from(format!("/tmp/commitlog_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let folder = storage.commit_log_path();
+
+        let delete = CommitLogEntry {
+            mutation: Mutation::Delete(
+                Delete::deserialize("DELETE FROM sky.flights WHERE id = 2").unwrap(),
+            ),
+            ..insert("2", "", 30)
+        };
+        let expiring = CommitLogEntry {
+            mutation: Mutation::Insert {
+                columns: vec!["id".to_string(), "status".to_string()],
+                values: vec!["3".to_string(), "landed".to_string()],
+                if_not_exists: false,
+                ttl: Some(60),
+            },
+            ..insert("3", "landed", 40)
+        };
+        let entries = vec![
+            insert("1", "boarding", 10),
+            insert("2", "delayed", 20),
+            delete,
+            expiring,
+        ];
+
+        let mut log = CommitLog::open(folder.clone())
+            .unwrap()
+            .with_segment_size(1);
+        log.retire_when_full(false);
+        for entry in &entries[..2] {
+            log.append(entry).unwrap();
+        }
+        // A node that restarts appends to a new segment
+        let mut log = CommitLog::open(folder.clone()).unwrap();
+        log.append(&entries[2]).unwrap();
+        log.append(&entries[3]).unwrap();
+        // The last line of a segment may be torn by a crash
+        fs::write(CommitLog::segment_path(&folder, 9), "40;0;sky;fli").unwrap();
+
+        assert_eq!(CommitLog::segment_ids(&folder).unwrap(), vec![0, 1, 2, 9]);
+        assert_eq!(CommitLog::read_entries(&folder).unwrap(), entries);
+        // A restored snapshot only gets the mutations logged after it replayed
+        let replay_from = HashMap::from([("sky".to_string(), 2)]);
+        assert_eq!(
+            CommitLog::read_entries_from(&folder, &replay_from).unwrap(),
+            entries[2..]
+        );
+        let replay_from = HashMap::from([("moon".to_string(), 2)]);
+        assert_eq!(
+            CommitLog::read_entries_from(&folder, &replay_from).unwrap(),
+            entries
+        );
+
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (id INT, status TEXT, PRIMARY KEY (id))",
+            )
+            .unwrap(),
+        );
+        storage.reset_folders().unwrap();
+        for entry in CommitLog::read_entries(&folder).unwrap() {
+            storage.apply(&entry, &table).unwrap();
+        }
+        let data = fs::read_to_string(
+            storage
+                .get_folder_path("sky", false)
+                .unwrap()
+                .join("flights.csv"),
+        )
+        .unwrap();
+        assert_eq!(data, "id,status\n1,boarding;10\n3,landed;40;100\n");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_segments_are_retired_once_applied_and_flushed() {
+        let folder = PathBuf::from(format!("/tmp/commitlog_test_{}", Uuid::new_v4()));
+        let log = Arc::new(Mutex::new(
+            CommitLog::open(folder.clone())
+                .unwrap()
+                .with_segment_size(1),
+        ));
+        let segments = || CommitLog::segment_ids(&folder).unwrap();
+
+        // A segment with a mutation being applied is kept
+        let applying = CommitLog::log(&log, &insert("1", "boarding", 10)).unwrap();
+        drop(CommitLog::log(&log, &insert("2", "delayed", 20)).unwrap());
+        assert_eq!(segments(), vec![0, 1]);
+        drop(applying);
+        drop(CommitLog::log(&log, &insert("3", "landed", 30)).unwrap());
+        assert_eq!(segments(), vec![2]);
+
+        // With the LSM backend they wait for the memtables to be flushed
+        log.lock().unwrap().retire_when_full(false);
+        drop(CommitLog::log(&log, &insert("4", "landed", 40)).unwrap());
+        assert_eq!(segments(), vec![2, 3]);
+        let first_kept = log.lock().unwrap().flush_point().unwrap();
+        drop(CommitLog::log(&log, &insert("5", "landed", 50)).unwrap());
+        assert_eq!(log.lock().unwrap().retire_before(first_kept).unwrap(), 2);
+        assert_eq!(segments(), vec![4]);
+
+        // The segments of the previous run wait for their replay
+        drop(log);
+        CommitLog::log(
+            &Arc::new(Mutex::new(CommitLog::open(folder.clone()).unwrap())),
+            &insert("6", "boarding", 60),
+        )
+        .unwrap();
+        let mut log = CommitLog::open(folder.clone()).unwrap();
+        let first_kept = log.flush_point().unwrap();
+        assert_eq!(log.retire_before(first_kept).unwrap(), 0);
+        log.finish_replay();
+        assert_eq!(log.retire_before(first_kept).unwrap(), 2);
+        assert_eq!(segments(), vec![6]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_inserts_are_replayed_by_column_name() {
+        let root = PathBuf::from(format!("/tmp/commitlog_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        storage.reset_folders().unwrap();
+
+        // Logged before `status` was dropped and `gate` added
+        let entry = CommitLogEntry::from_line(&insert("1", "boarding", 10).to_line()).unwrap();
+        let logged_before_names = CommitLogEntry::from_line("20;0;sky;flights;I;0;2,A4").unwrap();
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (id INT, gate TEXT, PRIMARY KEY (id))",
+            )
+            .unwrap(),
+        );
+        storage.apply(&entry, &table).unwrap();
+        storage.apply(&logged_before_names, &table).unwrap();
+
+        let data = fs::read_to_string(
+            storage
+                .get_folder_path("sky", false)
+                .unwrap()
+                .join("flights.csv"),
+        )
+        .unwrap();
+        assert_eq!(data, "id,gate\n1,;10\n2,A4;20\n");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,349 @@
+//! Background compaction of the data files of the tables.
+//!
+//! With the LSM backend every flush leaves one more SSTable next to the data file of a table, and
+//! the overwritten rows, tombstones and expired rows stay in them until the table is compacted.
+//! A table is compacted once it has `min_sstables` SSTables: its live rows are written back to its
+//! data file and its SSTables are removed.
+//!
+//! The CSV backend drops the expired rows of a table when it rewrites it on a write, so only the
+//! tables that are not written anymore keep them. Those are rewritten without their expired rows,
+//! holding the write lock of the table, so no write is lost in between.
+//!
+//! Either way, the data files written since the last compaction get their bloom filter built again.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use gossip::structures::application_state::TableSchema;
+
+use super::{
+    errors::StorageEngineError,
+    row_stamp::{split_row, RowStamp},
+    StorageEngine,
+};
+
+/// Time between two compactions of the tables of a node by default.
+pub const DEFAULT_COMPACTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// SSTables a table needs before it is compacted by default. It is the default `min_threshold` of
+/// the compaction strategies of Cassandra.
+pub const DEFAULT_MIN_SSTABLES: usize = 4;
+
+/// How often the tables of a node are compacted, and which of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionSettings {
+    /// Time between two compactions of the tables of the node.
+    pub interval: Duration,
+    /// SSTables a data file (owned rows or replicas) of a table needs to be compacted, with the
+    /// LSM backend.
+    pub min_sstables: usize,
+}
+
+impl Default for CompactionSettings {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_COMPACTION_INTERVAL,
+            min_sstables: DEFAULT_MIN_SSTABLES,
+        }
+    }
+}
+
+/// What a compaction of a table did, summing the owned rows and the replicas.
+///
+/// ### Fields
+/// - `sstables`: SSTables merged into the data files, with the LSM backend.
+/// - `expired_rows`: Expired rows dropped from the data files, with the CSV backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompactionStats {
+    pub sstables: usize,
+    pub expired_rows: u64,
+}
+
+impl CompactionStats {
+    /// Whether the compaction rewrote any file of the table.
+    pub fn is_empty(&self) -> bool {
+        self.sstables == 0 && self.expired_rows == 0
+    }
+}
+
+impl StorageEngine {
+    /// Compacts the data files of a table (owned rows and replicas) that need it.
+    ///
+    /// # Arguments
+    /// - `keyspace`: The keyspace of the table.
+    /// - `table`: The schema of the table.
+    /// - `min_sstables`: The SSTables a data file needs to be compacted, with the LSM backend.
+    ///
+    /// # Returns
+    /// - `Ok(CompactionStats)` with what was merged or dropped, which is empty if no file needed it.
+    /// - `Err(StorageEngineError)` if the files of the table can not be read or replaced.
+    ///
+    /// # Notes
+    /// - With the CSV backend, the writes of a file wait for its compaction to finish.
+    /// - The bloom filters of the data files written since the last compaction are built again.
+    pub fn compact_table(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        min_sstables: usize,
+    ) -> Result<CompactionStats, StorageEngineError> {
+        let table_name = table.get_name();
+        let columns = table.get_columns();
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table_name, &column_names)?;
+
+        let mut stats = CompactionStats::default();
+        for is_replication in [false, true] {
+            let data_path = self
+                .get_folder_path(keyspace, is_replication)?
+                .join(format!("{}.csv", table_name));
+            match self.lsm_store() {
+                Some(store) => {
+                    stats.sstables +=
+                        self.lsm_compact_if_needed(&store, &data_path, &columns, min_sstables)?
+                }
+                None => stats.expired_rows += self.purge_expired_rows(&data_path, table)?,
+            }
+            self.refresh_bloom_filter(&data_path, &columns)?;
+        }
+        Ok(stats)
+    }
+
+    // Rewrites a data file of the CSV backend, and its index, without its expired rows. Returns how
+    // many rows were dropped.
+    fn purge_expired_rows(
+        &self,
+        data_path: &Path,
+        table: &TableSchema,
+    ) -> Result<u64, StorageEngineError> {
+        let _lock = self.lock_table(data_path)?;
+        let now = RowStamp::now();
+
+        let mut lines = BufReader::new(File::open(data_path)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let mut live_rows = Vec::new();
+        let mut expired_rows = 0;
+        for line in lines {
+            let line = line?;
+            if split_row(&line)?.1.is_expired(now) {
+                expired_rows += 1;
+            } else {
+                live_rows.push(line);
+            }
+        }
+        if expired_rows == 0 {
+            return Ok(0);
+        }
+
+        let clustering_indices = Self::get_clustering_indices(
+            &table.get_columns(),
+            &table.get_clustering_column_in_order(),
+        )?;
+        let index_path = data_path.with_file_name(format!("{}_index.csv", table.get_name()));
+        let temp_path = data_path.with_extension("compacting");
+        let temp_index_path = index_path.with_extension("compacting");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writeln!(writer, "{}", header)?;
+        let mut current_byte_offset = header.len() as u64 + 1;
+        let mut index_map = BTreeMap::new();
+        for line in &live_rows {
+            writeln!(writer, "{}", line)?;
+            let (values, _) = split_row(line)?;
+            let row: Vec<&str> = values.split(',').collect();
+            Self::update_index_map(
+                &row,
+                &clustering_indices,
+                &mut index_map,
+                current_byte_offset,
+                line.len() as u64,
+            );
+            current_byte_offset += line.len() as u64 + 1;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let mut index_writer = BufWriter::new(File::create(&temp_index_path)?);
+        writeln!(index_writer, "clustering_column,start_byte,end_byte")?;
+        for (key, (start_byte, end_byte)) in index_map {
+            writeln!(index_writer, "{},{},{}", key, start_byte, end_byte)?;
+        }
+        index_writer.flush()?;
+        drop(index_writer);
+
+        fs::rename(&temp_path, data_path).map_err(|_| StorageEngineError::FileReplacementFailed)?;
+        fs::rename(&temp_index_path, &index_path)
+            .map_err(|_| StorageEngineError::FileReplacementFailed)?;
+        Ok(expired_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_engine::{bloom_filter::may_hold_partition, lsm::StorageBackend};
+    use query_creator::clauses::{select_cql::Select, table::create_table_cql::CreateTable};
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    fn flights_table() -> TableSchema {
+        TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number))",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_compact_table_drops_expired_csv_rows() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = flights_table();
+        let now = RowStamp::now();
+
+        storage.compact_table("sky", &table, 1).unwrap();
+        let folder = storage.get_folder_path("sky", false).unwrap();
+        fs::write(
+            folder.join("flights.csv"),
+            format!(
+                "airport,number,status\nEZE,1,landed;1;2\nEZE,2,boarding;{}\nEZE,3,delayed;1;2\n",
+                now
+            ),
+        )
+        .unwrap();
+
+        let stats = storage.compact_table("sky", &table, 1).unwrap();
+        assert_eq!(
+            stats,
+            CompactionStats {
+                sstables: 0,
+                expired_rows: 2
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("flights.csv")).unwrap(),
+            format!("airport,number,status\nEZE,2,boarding;{}\n", now)
+        );
+        let row_length = format!("EZE,2,boarding;{}", now).len();
+        assert_eq!(
+            fs::read_to_string(folder.join("flights_index.csv")).unwrap(),
+            format!(
+                "clustering_column,start_byte,end_byte\n2,22,{}\n",
+                22 + row_length
+            )
+        );
+
+        // Nothing is rewritten once the expired rows are gone
+        assert!(storage.compact_table("sky", &table, 1).unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_compact_table_builds_bloom_filters() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = flights_table();
+        let columns = table.get_columns();
+        let insert = |airport: &str, timestamp| {
+            storage
+                .insert(
+                    "sky",
+                    "flights",
+                    vec![airport, "1", "landed"],
+                    columns.clone(),
+                    table.get_clustering_column_in_order(),
+                    false,
+                    false,
+                    timestamp,
+                )
+                .unwrap()
+        };
+        let select = |airport: &str| {
+            let query = format!(
+                "SELECT airport, number, status FROM sky.flights WHERE airport = '{}'",
+                airport
+            );
+            storage
+                .select(
+                    Select::deserialize(&query).unwrap(),
+                    table.clone(),
+                    false,
+                    "sky",
+                )
+                .unwrap()
+        };
+
+        insert("EZE", 1);
+        storage.compact_table("sky", &table, 1).unwrap();
+        let data_path = storage
+            .get_folder_path("sky", false)
+            .unwrap()
+            .join("flights.csv");
+        assert!(may_hold_partition(&data_path, "EZE"));
+        assert!(!may_hold_partition(&data_path, "AEP"));
+        assert_eq!(select("EZE").len(), 3);
+        assert_eq!(select("AEP").len(), 2);
+        assert_eq!(
+            storage.read_row("sky", &table, false, "AEP,1").unwrap(),
+            None
+        );
+
+        // The filter is not used once the file is written again, until the next compaction
+        insert("AEP", 2);
+        assert_eq!(select("AEP").len(), 3);
+        assert_eq!(
+            storage.read_row("sky", &table, false, "AEP,1").unwrap(),
+            Some("AEP,1,landed;2".to_string())
+        );
+        storage.compact_table("sky", &table, 1).unwrap();
+        assert!(may_hold_partition(&data_path, "AEP"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_compact_table_waits_for_min_sstables() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        storage
+            .set_backend(StorageBackend::Lsm { memtable_rows: 1 })
+            .unwrap();
+        let table = flights_table();
+        let columns = table.get_columns();
+        for (number, status, timestamp) in [("1", "landed", 1), ("1", "delayed", 2)] {
+            storage
+                .insert(
+                    "sky",
+                    "flights",
+                    vec!["EZE", number, status],
+                    columns.clone(),
+                    table.get_clustering_column_in_order(),
+                    false,
+                    false,
+                    timestamp,
+                )
+                .unwrap();
+        }
+
+        assert!(storage.compact_table("sky", &table, 3).unwrap().is_empty());
+        assert_eq!(storage.compact_table("sky", &table, 2).unwrap().sstables, 2);
+
+        // The overwritten version of the row was dropped
+        let folder = storage.get_folder_path("sky", false).unwrap();
+        assert_eq!(
+            fs::read_to_string(folder.join("flights.csv")).unwrap(),
+            "airport,number,status\nEZE,1,delayed;2\n"
+        );
+
+        storage.set_backend(StorageBackend::Csv).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,390 @@
+//! Counter columns, stored as shards so their increments can be replicated and replayed without
+//! being counted twice.
+//!
+//! A counter is never written as a number. Each node that coordinates increments of a counter
+//! owns a shard of it, with the sum of the increments it coordinated and a clock that it advances
+//! with each one. The coordinator adds the increment to its shard and sends replicas the whole
+//! shard, which they keep if its clock is ahead of the one they hold. A shard received twice, or
+//! after a newer one, changes nothing, so writes can be retried, hinted and replayed from the
+//! commit log freely. The value of a counter is the sum of its shards, taken when it is read.
+//!
+//! Counter cells are written as their shards separated by `|`, each one as
+//! `<owner>:<clock>:<count>`. The shards a node owns are also kept in its counter shard log, as
+//! the node needs them to coordinate increments of counters it does not hold a replica of. The
+//! log gets a line per increment, so once it holds `SHARD_LOG_COMPACTION_RATIO` times more lines
+//! than counters it is rewritten with the last shard of each counter only.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use query_creator::clauses::types::{column::Column, datatype::DataType};
+
+use super::{errors::StorageEngineError, row_cache::canonical, StorageEngine};
+
+/// Shards owned by each node, by the path of its counter shard log. They are loaded from the log
+/// the first time the node increments a counter.
+static OWN_SHARDS: Mutex<BTreeMap<PathBuf, OwnShards>> = Mutex::new(BTreeMap::new());
+
+/// Lines of a counter shard log, for each counter it holds, past which it is compacted.
+const SHARD_LOG_COMPACTION_RATIO: usize = 4;
+
+/// Lines a counter shard log holds before it is compacted, however few counters it holds.
+const MIN_SHARD_LOG_LINES: usize = 1024;
+
+/// The shards a node owns.
+///
+/// ### Fields
+/// - `shards`: The clock and count of each counter the node coordinated increments of.
+/// - `lines`: The lines of the counter shard log, one per increment since it was last compacted.
+#[derive(Debug, Default)]
+struct OwnShards {
+    shards: HashMap<String, (u64, i64)>,
+    lines: usize,
+}
+
+/// The shards of a counter cell, by their owner, each one with its clock and count.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CounterShards(BTreeMap<String, (u64, i64)>);
+
+impl CounterShards {
+    /// Returns a cell with a single shard.
+    pub fn single(owner: &str, clock: u64, count: i64) -> Self {
+        Self(BTreeMap::from([(owner.to_string(), (clock, count))]))
+    }
+
+    /// Merges the shards of another cell, keeping for each owner the shard with the latest clock.
+    pub fn merge(&mut self, other: &CounterShards) {
+        for (owner, (clock, count)) in &other.0 {
+            let shard = self.0.entry(owner.clone()).or_insert((*clock, *count));
+            if *clock > shard.0 {
+                *shard = (*clock, *count);
+            }
+        }
+    }
+
+    /// Returns the value of the counter, the sum of the counts of its shards.
+    pub fn total(&self) -> i64 {
+        self.0
+            .values()
+            .fold(0, |total, (_, count)| total.wrapping_add(*count))
+    }
+}
+
+impl FromStr for CounterShards {
+    type Err = StorageEngineError;
+
+    fn from_str(cell: &str) -> Result<Self, Self::Err> {
+        let mut shards = CounterShards::default();
+        for shard in cell.split('|').filter(|shard| !shard.is_empty()) {
+            let mut fields = shard.split(':');
+            let (Some(owner), Some(clock), Some(count), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(StorageEngineError::InvalidQuery);
+            };
+            let clock = clock
+                .parse()
+                .map_err(|_| StorageEngineError::InvalidQuery)?;
+            let count = count
+                .parse()
+                .map_err(|_| StorageEngineError::InvalidQuery)?;
+            shards.merge(&CounterShards::single(owner, clock, count));
+        }
+        Ok(shards)
+    }
+}
+
+impl fmt::Display for CounterShards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shards: Vec<String> = self
+            .0
+            .iter()
+            .map(|(owner, (clock, count))| format!("{}:{}:{}", owner, clock, count))
+            .collect();
+        write!(f, "{}", shards.join("|"))
+    }
+}
+
+/// Merges the counter cells of a version of a row into another version of it, given as their values
+/// in the order of the columns of the table. Cells that are not valid shards are left as they are.
+pub fn merge_counter_cells(into: &mut [String], other: &[String], columns: &[Column]) {
+    for (i, column) in columns.iter().enumerate() {
+        if column.data_type != DataType::Counter {
+            continue;
+        }
+        let (Some(cell), Some(other_cell)) = (into.get(i), other.get(i)) else {
+            continue;
+        };
+        if let (Ok(mut shards), Ok(other_shards)) = (
+            cell.parse::<CounterShards>(),
+            other_cell.parse::<CounterShards>(),
+        ) {
+            shards.merge(&other_shards);
+            into[i] = shards.to_string();
+        }
+    }
+}
+
+/// Returns a counter cell with the shards of another one merged into it.
+pub(super) fn merge_counter_cell(cell: &str, other: &str) -> Result<String, StorageEngineError> {
+    let mut shards = cell.parse::<CounterShards>()?;
+    shards.merge(&other.parse()?);
+    Ok(shards.to_string())
+}
+
+/// Returns the values of a row with the shards of its counter cells replaced by the values of the
+/// counters. Empty cells, of counters never incremented, are left empty.
+pub fn sum_counter_cells(values: &[&str], columns: &[Column]) -> Vec<String> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match columns.get(i) {
+            Some(column) if column.data_type == DataType::Counter && !value.is_empty() => value
+                .parse::<CounterShards>()
+                .map(|shards| shards.total().to_string())
+                .unwrap_or_else(|_| value.to_string()),
+            _ => value.to_string(),
+        })
+        .collect()
+}
+
+/// Returns the cell of the counter shard log of a counter: the column of a row of a table, given
+/// by the values of its primary key in the order of the `columns` of the table.
+pub fn counter_cell(
+    keyspace: &str,
+    table: &str,
+    column: &str,
+    primary_key: &[String],
+    columns: &[Column],
+) -> String {
+    // The same row is written the same whatever way its key was written in the query
+    let primary_key: Vec<String> = columns
+        .iter()
+        .filter(|column| column.is_partition_key || column.is_clustering_column)
+        .zip(primary_key)
+        .map(|(column, value)| canonical(value, &column.data_type))
+        .collect();
+    format!(
+        "{},{},{},{}",
+        keyspace,
+        table,
+        column,
+        primary_key.join(",")
+    )
+}
+
+impl StorageEngine {
+    /// Returns the log of the counter shards owned by the node. Like the commit log, it is kept
+    /// when the keyspaces are reset on startup.
+    pub fn counter_shards_path(&self) -> PathBuf {
+        self.root
+            .join(format!("counter_shards_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Adds an increment to the shard the node owns of a counter, advancing its clock, and returns
+    /// the shard to send to the replicas of the counter. The shard is written to the counter shard
+    /// log before it is returned.
+    ///
+    /// # Arguments
+    /// - `cell`: The counter, as returned by [`counter_cell`].
+    /// - `delta`: The increment, negative to decrement the counter.
+    ///
+    /// # Returns
+    /// - `Ok(CounterShards)` with the shard of the node.
+    /// - `Err(StorageEngineError)` if the log can not be read or written.
+    pub fn increment_counter_shard(
+        &self,
+        cell: &str,
+        delta: i64,
+    ) -> Result<CounterShards, StorageEngineError> {
+        let path = self.counter_shards_path();
+        let mut registry = OWN_SHARDS.lock().map_err(|_| StorageEngineError::IoError)?;
+        if !registry.contains_key(&path) {
+            registry.insert(path.clone(), read_counter_shards(&path)?);
+        }
+        let own = registry.get_mut(&path).ok_or(StorageEngineError::IoError)?;
+
+        let (clock, count) = own.shards.get(cell).copied().unwrap_or_default();
+        let (clock, count) = (clock + 1, count.wrapping_add(delta));
+
+        let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(log, "{},{},{}", clock, count, cell)?;
+        log.sync_data()?;
+        own.shards.insert(cell.to_string(), (clock, count));
+        own.lines += 1;
+
+        if own.lines > MIN_SHARD_LOG_LINES.max(own.shards.len() * SHARD_LOG_COMPACTION_RATIO) {
+            compact_counter_shards(&path, &own.shards)?;
+            own.lines = own.shards.len();
+        }
+
+        Ok(CounterShards::single(&self.ip, clock, count))
+    }
+}
+
+// Reads the shards written to a counter shard log, the last one written of each counter
+fn read_counter_shards(path: &Path) -> Result<OwnShards, StorageEngineError> {
+    let mut own = OwnShards::default();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) if !path.exists() => return Ok(own),
+        Err(e) => return Err(e.into()),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        own.lines += 1;
+        let mut fields = line.splitn(3, ',');
+        // A line cut by a crash while it was written is skipped
+        if let (Some(Ok(clock)), Some(Ok(count)), Some(cell)) = (
+            fields.next().map(str::parse::<u64>),
+            fields.next().map(str::parse::<i64>),
+            fields.next(),
+        ) {
+            own.shards.insert(cell.to_string(), (clock, count));
+        }
+    }
+    Ok(own)
+}
+
+// Rewrites a counter shard log with the last shard of each counter only. The log is written under
+// a temporary name and synced before it replaces the old one, so a crash leaves either of them.
+fn compact_counter_shards(
+    path: &Path,
+    shards: &HashMap<String, (u64, i64)>,
+) -> Result<(), StorageEngineError> {
+    let temp_path = path.with_extension("tmp");
+    let mut log = BufWriter::new(File::create(&temp_path)?);
+    for (cell, (clock, count)) in shards {
+        writeln!(log, "{},{},{}", clock, count, cell)?;
+    }
+    log.into_inner()
+        .map_err(|_| StorageEngineError::FileWriteFailed)?
+        .sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    // Forgets the shards a node owns, so they are read from its log again
+    fn forget_counter_shards(path: &PathBuf) {
+        OWN_SHARDS.lock().unwrap().remove(path);
+    }
+
+    #[test]
+    fn test_counter_shards_merge_by_clock() {
+        let mut shards: CounterShards = "127.0.0.1:3:5|127.0.0.2:1:-1".parse().unwrap();
+        assert_eq!(shards.total(), 4);
+
+        // A replayed shard and an older one change nothing
+        shards.merge(&"127.0.0.1:3:5|127.0.0.2:0:7".parse().unwrap());
+        assert_eq!(shards.to_string(), "127.0.0.1:3:5|127.0.0.2:1:-1");
+
+        shards.merge(&CounterShards::single("127.0.0.2", 2, 2));
+        shards.merge(&CounterShards::single("127.0.0.3", 1, 10));
+        assert_eq!(shards.total(), 17);
+        assert_eq!("".parse::<CounterShards>().unwrap().total(), 0);
+        assert!("127.0.0.1:x:5".parse::<CounterShards>().is_err());
+    }
+
+    #[test]
+    fn test_counter_cells_are_merged_and_summed() {
+        let mut airport = Column::new("airport", DataType::String, true, false);
+        airport.is_partition_key = true;
+        let departures = Column::new("departures", DataType::Counter, false, true);
+        let columns = [airport, departures];
+
+        let mut row = vec!["EZE".to_string(), "127.0.0.1:2:3".to_string()];
+        merge_counter_cells(
+            &mut row,
+            &["EZE".to_string(), "127.0.0.1:1:1|127.0.0.2:1:4".to_string()],
+            &columns,
+        );
+        assert_eq!(row[1], "127.0.0.1:2:3|127.0.0.2:1:4");
+        assert_eq!(sum_counter_cells(&["EZE", &row[1]], &columns), ["EZE", "7"]);
+        assert_eq!(sum_counter_cells(&["EZE", ""], &columns), ["EZE", ""]);
+    }
+
+    #[test]
+    fn test_own_shards_are_kept_in_the_log() {
+        let root = PathBuf::from(format!("/tmp/counter_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let engine = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let mut airport = Column::new("airport", DataType::String, true, false);
+        airport.is_partition_key = true;
+        let mut day = Column::new("day", DataType::Int, true, false);
+        day.is_clustering_column = true;
+        let columns = [airport, day];
+        let cell = counter_cell(
+            "sky",
+            "departures",
+            "count",
+            &["EZE".into(), "07".into()],
+            &columns,
+        );
+        assert_eq!(
+            cell,
+            counter_cell(
+                "sky",
+                "departures",
+                "count",
+                &["EZE".into(), "7".into()],
+                &columns
+            )
+        );
+
+        engine.increment_counter_shard(&cell, 2).unwrap();
+        assert_eq!(
+            engine.increment_counter_shard(&cell, -5).unwrap(),
+            CounterShards::single("127.0.0.1", 2, -3)
+        );
+
+        // The shards are read from the log again, as they are once the node restarts
+        forget_counter_shards(&engine.counter_shards_path());
+        assert_eq!(
+            engine.increment_counter_shard(&cell, 1).unwrap(),
+            CounterShards::single("127.0.0.1", 3, -2)
+        );
+
+        forget_counter_shards(&engine.counter_shards_path());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_counter_shard_log_is_compacted() {
+        let root = PathBuf::from(format!("/tmp/counter_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let engine = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let path = engine.counter_shards_path();
+
+        for increment in 0..=MIN_SHARD_LOG_LINES {
+            let cell = format!("sky,departures,count,{}", increment % 2);
+            engine.increment_counter_shard(&cell, 1).unwrap();
+        }
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 2);
+
+        // The compacted log holds the same shards
+        forget_counter_shards(&path);
+        assert_eq!(
+            engine
+                .increment_counter_shard("sky,departures,count,0", 1)
+                .unwrap(),
+            CounterShards::single("127.0.0.1", 514, 514)
+        );
+
+        forget_counter_shards(&path);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,27 +1,32 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fs::{self, File},
     io::{BufRead, BufReader, BufWriter, Write},
-    net::{Ipv4Addr, TcpStream},
+    net::Ipv4Addr,
     sync::{Arc, Mutex},
     // thread::{self},
     // time::Duration,
 };
 
 use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
-use logger::{Color, Logger};
+use logger::{Color, Component, Logger};
 use partitioner::Partitioner;
 
 use crate::{
     internode_protocol::{
         message::{InternodeMessage, InternodeMessageContent},
-        query::InternodeQuery,
+        streaming::{StreamChunk, STREAM_CHUNK_ROWS},
     },
-    utils::connect_and_send_message,
-    INTERNODE_PORT,
+    stream_sessions::StreamSessions,
+    utils::get_replicas,
 };
 
-use super::{errors::StorageEngineError, StorageEngine};
+use super::{
+    commitlog::{CommitLog, CommitLogEntry, Mutation},
+    errors::StorageEngineError,
+    row_stamp::RowStamp,
+    StorageEngine,
+};
 
 impl StorageEngine {
     /// Redistributes data across nodes for the specified keyspaces.
@@ -36,7 +41,9 @@ impl StorageEngine {
     /// * `keyspaces` - A vector of keyspace schemas to process and redistribute.
     /// * `partitioner` - The partitioner responsible for determining the ownership of data.
     /// * `logger` - The logger instance for recording progress and errors.
-    /// * `connections` - A shared map of connections to other nodes in the cluster.
+    /// * `commit_log` - The commit log of the node, where the rows it starts keeping as a replica
+    ///   of its own partitions are logged, as the rows streamed to it are.
+    /// * `stream` - Sends each chunk of the rows that move, as a `StreamChunk`, to a node.
     ///
     /// # Returns
     ///
@@ -54,12 +61,27 @@ impl StorageEngine {
         keyspaces: Vec<KeyspaceSchema>,
         partitioner: &Partitioner,
         logger: Logger,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        commit_log: &Arc<Mutex<CommitLog>>,
+        stream: &dyn Fn(Ipv4Addr, InternodeMessage),
     ) -> Result<(), StorageEngineError> {
+        let self_ip: Ipv4Addr = self
+            .ip
+            .parse()
+            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+        let mut chunks = ChunkStream::new(self_ip, stream, logger);
+
         for keyspace in keyspaces {
             let tables = keyspace.clone().get_tables();
 
             for table in tables {
+                chunks.start_table(&keyspace.get_name(), &table.get_name());
+                // The rows of the memtables and SSTables are redistributed from the data files
+                self.compact_lsm_table(
+                    &keyspace.get_name(),
+                    &table.get_name(),
+                    &table.get_columns(),
+                )?;
+
                 // Rutas de archivos
                 let base_folder_path = self.get_keyspace_path(&keyspace.clone().get_name());
                 let normal_file_path = base_folder_path.join(format!("{}.csv", table.get_name()));
@@ -72,12 +94,12 @@ impl StorageEngine {
                     self.process_file(
                         &normal_file_path,
                         &partitioner,
-                        logger.clone(),
                         keyspace.clone(),
                         table.clone(),
                         false,
-                        self.ip.clone(),
-                        connections.clone(),
+                        self_ip,
+                        commit_log,
+                        &mut chunks,
                     )?;
                 }
 
@@ -86,37 +108,176 @@ impl StorageEngine {
                     self.process_file(
                         &replication_file_path,
                         &partitioner,
-                        logger.clone(),
                         keyspace.clone(),
                         table.clone(),
                         true,
-                        self.ip.clone(),
-                        connections.clone(),
+                        self_ip,
+                        commit_log,
+                        &mut chunks,
                     )?;
                 }
+                chunks.flush();
+                self.invalidate_cached_table(&keyspace.get_name(), Some(&table.get_name()));
             }
         }
 
         Ok(())
     }
 
+    /// Streams every row stored by this node to the nodes that keep it in `partitioner`, which
+    /// must no longer hold this node.
+    ///
+    /// This is how a decommissioned node hands its data over before it leaves the ring: the rows
+    /// it owns and the ones it replicates go to their owner and replicas in the ring without it.
+    /// Unlike `redistribute_data`, the data files are left untouched, so the node still has its
+    /// rows if the others never get them.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyspaces` - The keyspace schemas whose tables are streamed.
+    /// * `partitioner` - The ring the node is leaving, already without the node.
+    /// * `logger` - The logger instance for recording the rows sent.
+    /// * `stream` - Sends each chunk of rows, as a `StreamChunk`, to a node.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(count)` with the amount of rows streamed, counting each copy.
+    /// * `Err(StorageEngineError)` if the data files can not be read or the ring is empty.
+    pub fn stream_data_away(
+        &self,
+        keyspaces: Vec<KeyspaceSchema>,
+        partitioner: &Partitioner,
+        logger: Logger,
+        stream: &dyn Fn(Ipv4Addr, InternodeMessage),
+    ) -> Result<usize, StorageEngineError> {
+        self.stream_rows(keyspaces, partitioner, logger, stream, None)
+    }
+
+    /// Streams to a bootstrapping node the rows stored by this node that it must keep, as the
+    /// owner or a replica of their partitions, in `partitioner`, which already holds it.
+    ///
+    /// The data files are left untouched: the rows this node stops keeping are removed by the
+    /// redistribution run once the bootstrapping node joins the ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyspaces` - The keyspace schemas whose tables are streamed.
+    /// * `partitioner` - The ring with the bootstrapping node at its tokens.
+    /// * `joining_ip` - The bootstrapping node.
+    /// * `logger` - The logger instance for recording the rows sent.
+    /// * `stream` - Sends each chunk of rows, as a `StreamChunk`, to the bootstrapping node.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(count)` with the amount of rows streamed.
+    /// * `Err(StorageEngineError)` if the data files can not be read or the ring is empty.
+    pub fn stream_ranges_to(
+        &self,
+        keyspaces: Vec<KeyspaceSchema>,
+        partitioner: &Partitioner,
+        joining_ip: Ipv4Addr,
+        logger: Logger,
+        stream: &dyn Fn(Ipv4Addr, InternodeMessage),
+    ) -> Result<usize, StorageEngineError> {
+        self.stream_rows(keyspaces, partitioner, logger, stream, Some(joining_ip))
+    }
+
+    // Streams each stored row to its owner and replicas in `partitioner`, or only to `only_to`
+    // if it is one of them, and returns the amount of rows streamed
+    fn stream_rows(
+        &self,
+        keyspaces: Vec<KeyspaceSchema>,
+        partitioner: &Partitioner,
+        logger: Logger,
+        stream: &dyn Fn(Ipv4Addr, InternodeMessage),
+        only_to: Option<Ipv4Addr>,
+    ) -> Result<usize, StorageEngineError> {
+        let self_ip: Ipv4Addr = self
+            .ip
+            .parse()
+            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+        let now = RowStamp::now();
+        let mut streamed = 0;
+        let mut chunks = ChunkStream::new(self_ip, stream, logger);
+
+        for keyspace in keyspaces {
+            for table in keyspace.clone().get_tables() {
+                chunks.start_table(&keyspace.get_name(), &table.get_name());
+                self.compact_lsm_table(
+                    &keyspace.get_name(),
+                    &table.get_name(),
+                    &table.get_columns(),
+                )?;
+
+                let partition_key_indices: Vec<usize> = table
+                    .get_columns()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, col)| col.is_partition_key)
+                    .map(|(idx, _)| idx)
+                    .collect();
+
+                let base_folder_path = self.get_keyspace_path(&keyspace.get_name());
+                let file_name = format!("{}.csv", table.get_name());
+                let file_paths = [
+                    base_folder_path.join(&file_name),
+                    base_folder_path.join("replication").join(&file_name),
+                ];
+
+                for file_path in file_paths.iter().filter(|path| path.exists()) {
+                    let file = File::open(file_path).map_err(|_| StorageEngineError::IoError)?;
+
+                    // The first line is the header of the file
+                    for line in BufReader::new(file).lines().skip(1) {
+                        let line = line.map_err(|_| StorageEngineError::IoError)?;
+                        let Some((data, timestamp)) = line.split_once(";") else {
+                            continue;
+                        };
+                        let row: Vec<&str> = data.split(',').collect();
+                        let stamp: RowStamp = timestamp
+                            .parse()
+                            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+                        if stamp.is_expired(now) {
+                            continue;
+                        }
+
+                        let partition_key: String = partition_key_indices
+                            .iter()
+                            .map(|index| row[*index])
+                            .collect();
+                        let owner = partitioner
+                            .get_ip(partition_key)
+                            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+                        let replicas = get_replicas(partitioner, owner, &keyspace)
+                            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+
+                        let targets = std::iter::once((owner, false))
+                            .chain(replicas.into_iter().map(|replica| (replica, true)))
+                            .filter(|(target_ip, _)| only_to.is_none_or(|ip| ip == *target_ip));
+                        for (target_ip, is_replication) in targets {
+                            chunks.push(target_ip, is_replication, &line);
+                            streamed += 1;
+                        }
+                    }
+                }
+                chunks.flush();
+            }
+        }
+
+        Ok(streamed)
+    }
+
     fn process_file(
         &self,
         file_path: &std::path::Path,
         partitioner: &Partitioner,
-        logger: Logger,
         keyspace: KeyspaceSchema,
         table: TableSchema,
         is_replication: bool,
-        self_ip: String,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+        self_ip: Ipv4Addr,
+        commit_log: &Arc<Mutex<CommitLog>>,
+        chunks: &mut ChunkStream,
     ) -> Result<(), StorageEngineError> {
-        let self_ip: Ipv4Addr = self_ip
-            .parse()
-            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
-
-        let columns: Vec<String> = table.get_columns().iter().map(|c| c.name.clone()).collect();
-
         let temp_file_path = file_path.with_extension("tmp");
 
         // Crear el archivo de índice con el formato `{nombre_archivo}_index.csv`
@@ -126,8 +287,10 @@ impl StorageEngine {
 
         let mut temp_file =
             BufWriter::new(File::create(&temp_file_path).map_err(|_| StorageEngineError::IoError)?);
+        // The index is replaced like the data file, so it is never seen half written
+        let temp_index_path = file_path.with_extension("index.tmp");
         let mut index_file = BufWriter::new(
-            File::create(&index_file_path).map_err(|_| StorageEngineError::IoError)?,
+            File::create(&temp_index_path).map_err(|_| StorageEngineError::IoError)?,
         );
 
         // Escribir el encabezado en el archivo de índice
@@ -169,6 +332,7 @@ impl StorageEngine {
             })
             .collect();
 
+        let now = RowStamp::now();
         for (i, line) in reader.lines().enumerate() {
             let line = line.map_err(|_| StorageEngineError::IoError)?;
             let line_length = line.len() as u64;
@@ -183,6 +347,14 @@ impl StorageEngine {
             // Procesar línea de datos
             if let Some((data, timestamp)) = line.split_once(";") {
                 let row: Vec<&str> = data.split(',').collect();
+                let stamp: RowStamp = timestamp
+                    .parse()
+                    .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+
+                // Expired rows are dropped instead of being moved
+                if stamp.is_expired(now) {
+                    continue;
+                }
 
                 // Construir la clave de partición
                 let mut partition_key = String::new();
@@ -211,49 +383,15 @@ impl StorageEngine {
                         }
                         current_byte_offset += line_length + 1;
                     } else {
-                        let timest: i64 = timestamp
-                            .parse()
-                            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
-
-                        self.insert(
-                            &keyspace.get_name(),
-                            &table.get_name(),
-                            row.clone(),
-                            table.get_columns(),
-                            table.get_clustering_column_in_order(),
-                            true,
-                            false,
-                            timest,
-                        )?;
+                        self.keep_as_replica(&keyspace, &table, &row, stamp, commit_log)?;
                     }
                 } else {
                     // Reubicar la fila al nodo correspondiente
-                    let insert_string = Self::create_cql_insert(
-                        &keyspace.get_name(),
-                        &table.get_name(),
-                        columns.clone(),
-                        row.clone(),
-                    )?;
-
-                    let timestamp_n: i64 = timestamp
-                        .parse()
-                        .map_err(|_| StorageEngineError::UnsupportedOperation)?;
-
-                    Self::create_and_send_internode_message(
-                        self_ip,
-                        current_node,
-                        &keyspace.get_name(),
-                        &insert_string,
-                        timestamp_n,
-                        false,
-                        connections.clone(),
-                        logger.clone(),
-                    );
+                    chunks.push(current_node, false, &line);
                 }
 
                 // Manejo de réplicas
-                let successors = partitioner
-                    .get_n_successors(current_node, keyspace.get_replication_factor() as usize - 1)
+                let successors = get_replicas(partitioner, current_node, &keyspace)
                     .map_err(|_| StorageEngineError::UnsupportedOperation)?;
 
                 for rep_ip in successors {
@@ -271,41 +409,10 @@ impl StorageEngine {
                             }
                             current_byte_offset += line_length + 1;
                         } else {
-                            let timest: i64 = timestamp
-                                .parse()
-                                .map_err(|_| StorageEngineError::UnsupportedOperation)?;
-                            self.insert(
-                                &keyspace.get_name(),
-                                &table.get_name(),
-                                row.clone(),
-                                table.get_columns(),
-                                table.get_clustering_column_in_order(),
-                                true,
-                                false,
-                                timest,
-                            )?;
+                            self.keep_as_replica(&keyspace, &table, &row, stamp, commit_log)?;
                         }
                     } else {
-                        let insert_string = Self::create_cql_insert(
-                            &keyspace.get_name(),
-                            &table.get_name(),
-                            columns.clone(),
-                            row.clone(),
-                        )?;
-                        let timestamp_n: i64 = timestamp
-                            .parse()
-                            .map_err(|_| StorageEngineError::UnsupportedOperation)?;
-
-                        Self::create_and_send_internode_message(
-                            self_ip,
-                            rep_ip,
-                            &keyspace.get_name(),
-                            &insert_string,
-                            timestamp_n,
-                            true,
-                            connections.clone(),
-                            logger.clone(),
-                        );
+                        chunks.push(rep_ip, true, &line);
                     }
                 }
             }
@@ -325,85 +432,148 @@ impl StorageEngine {
             writeln!(index_file, "{},{},{}", key, start_byte, end_byte)
                 .map_err(|_| StorageEngineError::IoError)?;
         }
+        index_file
+            .flush()
+            .map_err(|_| StorageEngineError::IoError)?;
+        drop(index_file);
 
         fs::rename(&temp_file_path, file_path).map_err(|_| StorageEngineError::IoError)?;
+        fs::rename(&temp_index_path, &index_file_path).map_err(|_| StorageEngineError::IoError)?;
 
         Ok(())
     }
 
-    fn create_and_send_internode_message(
+    // Writes a row the node keeps as a replica through the commit log, as it may only be in a
+    // memtable once the file it was read from is rewritten without it
+    fn keep_as_replica(
+        &self,
+        keyspace: &KeyspaceSchema,
+        table: &TableSchema,
+        row: &[&str],
+        stamp: RowStamp,
+        commit_log: &Arc<Mutex<CommitLog>>,
+    ) -> Result<(), StorageEngineError> {
+        let _logged = CommitLog::log(
+            commit_log,
+            &CommitLogEntry {
+                keyspace: keyspace.get_name(),
+                table: table.get_name(),
+                is_replication: true,
+                timestamp: stamp.timestamp,
+                mutation: Mutation::insert(
+                    table,
+                    row.iter().map(|value| value.to_string()).collect(),
+                    false,
+                    stamp.ttl(),
+                ),
+            },
+        )?;
+        self.insert(
+            &keyspace.get_name(),
+            &table.get_name(),
+            row.to_vec(),
+            table.get_columns(),
+            table.get_clustering_column_in_order(),
+            true,
+            false,
+            stamp,
+        )
+    }
+}
+
+// Rows of a table waiting to be streamed to each node, and whether as a replica, sent as a chunk
+// once `STREAM_CHUNK_ROWS` of them gather or the table ends. Every chunk of a redistribution,
+// decommission or bootstrap belongs to one session.
+struct ChunkStream<'a> {
+    self_ip: Ipv4Addr,
+    session: u32,
+    next_seq: u32,
+    keyspace: String,
+    table: String,
+    pending: BTreeMap<(Ipv4Addr, bool), Vec<String>>,
+    stream: &'a dyn Fn(Ipv4Addr, InternodeMessage),
+    logger: Logger,
+}
+
+impl<'a> ChunkStream<'a> {
+    fn new(
         self_ip: Ipv4Addr,
-        target_ip: Ipv4Addr,
-        keyspace_name: &str,
-        serialized_message: &str,
-        timestamp: i64,
-        is_replication: bool,
-        connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>, // Ajusta el tipo si es necesario
+        stream: &'a dyn Fn(Ipv4Addr, InternodeMessage),
         logger: Logger,
-    ) {
-        // Crear el mensaje de internodo
-        let message = InternodeMessage::new(
+    ) -> Self {
+        ChunkStream {
             self_ip,
-            InternodeMessageContent::Query(InternodeQuery {
-                query_string: serialized_message.to_string(),
-                open_query_id: 0,
-                client_id: 0,
-                replication: is_replication,
-                keyspace_name: keyspace_name.to_string(),
-                timestamp,
-            }),
+            session: StreamSessions::new_session(),
+            next_seq: 0,
+            keyspace: String::new(),
+            table: String::new(),
+            pending: BTreeMap::new(),
+            stream,
+            logger,
+        }
+    }
+
+    // Sets the table of the rows pushed next, once the rows of the previous one were flushed
+    fn start_table(&mut self, keyspace: &str, table: &str) {
+        self.keyspace = keyspace.to_string();
+        self.table = table.to_string();
+    }
+
+    // Adds a line of a data file to the rows streamed to `target`
+    fn push(&mut self, target: Ipv4Addr, is_replication: bool, line: &str) {
+        let rows = self.pending.entry((target, is_replication)).or_default();
+        rows.push(line.to_string());
+        if rows.len() >= STREAM_CHUNK_ROWS {
+            let rows = std::mem::take(rows);
+            self.send(target, is_replication, rows);
+        }
+    }
+
+    // Sends the rows of the table that did not fill a chunk
+    fn flush(&mut self) {
+        for ((target, is_replication), rows) in std::mem::take(&mut self.pending) {
+            if !rows.is_empty() {
+                self.send(target, is_replication, rows);
+            }
+        }
+    }
+
+    fn send(&mut self, target: Ipv4Addr, is_replication: bool, rows: Vec<String>) {
+        let chunk = StreamChunk::new(
+            self.session,
+            self.next_seq,
+            &self.keyspace,
+            &self.table,
+            is_replication,
+            rows,
         );
-        // Enviar el mensaje al nodo objetivo
+        self.next_seq += 1;
+
         let rep = if is_replication {
             "AS REPLICATION "
         } else {
             ""
         };
-
-        logger
+        self.logger
+            .with_component(Component::Internode)
             .info(
                 &format!(
-                    "INTERNODE (REDISTRIBUTION): I SENT {:?}{:?} to {:?}",
+                    "INTERNODE (REDISTRIBUTION): I SENT CHUNK {}/{} OF {} ROWS OF {}.{} {}to {:?}",
+                    chunk.session,
+                    chunk.seq,
+                    chunk.rows.len(),
+                    chunk.keyspace,
+                    chunk.table,
                     rep,
-                    serialized_message.to_string(),
-                    target_ip
+                    target
                 ),
                 Color::Cyan,
                 true,
             )
             .ok();
-        //thread::sleep(Duration::from_millis(300));
-        let result = connect_and_send_message(target_ip, INTERNODE_PORT, connections, message);
-        // Manejar errores o resultados
-        _ = result;
-    }
-
-    fn create_cql_insert(
-        keyspace: &str,
-        table: &str,
-        columns: Vec<String>,
-        values: Vec<&str>,
-    ) -> Result<String, StorageEngineError> {
-        if columns.len() != values.len() {
-            return Err(StorageEngineError::UnsupportedOperation);
-        }
-
-        // Generar la lista de columnas separadas por comas
-        let columns_string = columns.join(", ");
-
-        // Escapar valores si es necesario, rodeándolos con comillas simples
-        let values_string = values
-            .iter()
-            .map(|value| format!("'{}'", value.replace("'", "''"))) // Escapar comillas simples en los valores
-            .collect::<Vec<String>>()
-            .join(",");
-
-        // Construir la sentencia CQL
-        let cql = format!(
-            "INSERT INTO {}.{} ({}) VALUES ({})",
-            keyspace, table, columns_string, values_string
+        (self.stream)(
+            target,
+            InternodeMessage::new(self.self_ip, InternodeMessageContent::StreamChunk(chunk)),
         );
-
-        Ok(cql)
     }
 }
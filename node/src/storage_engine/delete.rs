@@ -7,7 +7,9 @@ use std::{
 use gossip::structures::application_state::TableSchema;
 use query_creator::clauses::delete_cql::Delete;
 
-use super::{errors::StorageEngineError, StorageEngine};
+use super::{
+    errors::StorageEngineError, row_cache::PartitionKey, row_stamp::RowStamp, StorageEngine,
+};
 
 impl StorageEngine {
     /// Deletes rows or specific column values from a table within the specified keyspace.
@@ -73,23 +75,47 @@ impl StorageEngine {
         timestamp: i64,
     ) -> Result<(), StorageEngineError> {
         let table_name = table.get_name();
-        let base_folder_path = self.get_keyspace_path(keyspace);
+        let folder_path = self.get_folder_path(keyspace, is_replication)?;
 
-        // Construcción de la ruta de la carpeta según si es replicación o no
-        let folder_path = if is_replication {
-            base_folder_path.join("replication")
-        } else {
-            base_folder_path
-        };
-
-        // Crear la carpeta si no existe
-        if !folder_path.exists() {
-            fs::create_dir_all(&folder_path)
-                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
-        }
+        // The table may not have been created yet in this node if its schema is still being gossiped
+        let columns = table.get_columns();
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table_name, &column_names)?;
 
         // Rutas para los archivos de datos y de índices
         let file_path = folder_path.join(format!("{}.csv", table_name));
+        if let Some(store) = self.lsm_store() {
+            let partition = delete_query
+                .where_clause
+                .as_ref()
+                .and_then(|where_clause| {
+                    PartitionKey::of_where(
+                        keyspace,
+                        &table_name,
+                        is_replication,
+                        where_clause,
+                        &columns,
+                    )
+                })
+                .map(|key| key.partition());
+            self.lsm_delete(
+                &store,
+                &file_path,
+                &delete_query,
+                &table,
+                partition.as_deref(),
+                timestamp,
+            )?;
+            self.invalidate_cached_where(
+                keyspace,
+                &table_name,
+                is_replication,
+                delete_query.where_clause.as_ref(),
+                &columns,
+            );
+            return Ok(());
+        }
+        let _lock = self.lock_table(&file_path)?;
         let temp_file_path = folder_path.join(format!(
             "{}.tmp",
             SystemTime::now()
@@ -182,8 +208,13 @@ impl StorageEngine {
 
             // Si la línea no debe ser eliminada, escribirla en el archivo temporal
             if write_line {
+                // The row keeps the expiration of its last write
                 let time_to_write = if changed_line {
-                    &timestamp.to_string()
+                    &RowStamp {
+                        timestamp,
+                        expires_at: time_of_row.parse::<RowStamp>()?.expires_at,
+                    }
+                    .to_string()
                 } else {
                     time_of_row
                 };
@@ -222,11 +253,18 @@ impl StorageEngine {
         fs::rename(&temp_index_file_path, &index_file_path)
             .map_err(|_| StorageEngineError::FileReplacementFailed)?;
 
+        self.invalidate_cached_where(
+            keyspace,
+            &table_name,
+            is_replication,
+            delete_query.where_clause.as_ref(),
+            &columns,
+        );
         Ok(())
     }
 
     /// Verifica si una línea cumple las condiciones para ser eliminada
-    fn should_delete_line(
+    pub(super) fn should_delete_line(
         &self,
         table: &TableSchema,
         delete_query: &Delete,
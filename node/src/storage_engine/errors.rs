@@ -86,6 +86,18 @@ pub enum StorageEngineError {
     /// This error is returned when an operation is attempted that is not supported
     /// by the storage engine.
     UnsupportedOperation,
+
+    /// Error when creating a keyspace, table or snapshot that already exists.
+    ///
+    /// This error is returned when the creation was not asked to be skipped if the keyspace
+    /// or table already exists (`IF NOT EXISTS`), or a snapshot is taken again with its tag.
+    AlreadyExists,
+
+    /// Error when a snapshot does not match its manifest.
+    ///
+    /// This error occurs when a file of a snapshot is missing, or its size or checksum
+    /// is not the one the manifest of the snapshot lists.
+    SnapshotCorrupted,
 }
 
 impl std::fmt::Display for StorageEngineError {
@@ -120,6 +132,12 @@ impl std::fmt::Display for StorageEngineError {
                 write!(f, "Clustering key values are incomplete or mismatched.")
             }
             StorageEngineError::UnsupportedOperation => write!(f, "This operation is unsupported."),
+            StorageEngineError::AlreadyExists => {
+                write!(f, "The keyspace, table or snapshot already exists.")
+            }
+            StorageEngineError::SnapshotCorrupted => {
+                write!(f, "The snapshot does not match its manifest.")
+            }
         }
     }
 }
@@ -0,0 +1,428 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::Path,
+    sync::Arc,
+};
+
+use driver::export::{ExportFormat, ExportReport};
+use gossip::structures::application_state::TableSchema;
+use parquet::{
+    data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, FloatType, Int32Type, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use partitioner::Partitioner;
+use query_creator::clauses::types::{column::Column, datatype::DataType};
+
+use super::{counter::sum_counter_cells, errors::StorageEngineError, StorageEngine};
+
+/// Rows written to each row group of a Parquet file.
+const PARQUET_ROW_GROUP_ROWS: usize = 10_000;
+
+impl StorageEngine {
+    /// Writes the rows of a table stored by the node, as the owner or a replica of their
+    /// partitions, to a CSV or Parquet file, for analytics pipelines that read the table without
+    /// going through the native protocol.
+    ///
+    /// # Arguments
+    /// - `keyspace`: The keyspace of the table.
+    /// - `table`: The schema of the table, which gives the names and types of the columns.
+    /// - `format`: The format of the file.
+    /// - `tokens`: The range of the ring whose partitions are written, or `None` for all of them.
+    /// - `path`: The file to write, replaced if it exists.
+    ///
+    /// # Returns
+    /// - `Ok(ExportReport)` with the rows written and the size of the file.
+    /// - `Err(StorageEngineError)` if the rows can not be read or the file can not be written.
+    pub fn export_table(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        format: ExportFormat,
+        tokens: Option<Range<u64>>,
+        path: &Path,
+    ) -> Result<ExportReport, StorageEngineError> {
+        let columns = table.get_columns();
+        let partition_key: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_partition_key)
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut writer = match format {
+            ExportFormat::Csv => RowWriter::csv(path, &columns)?,
+            ExportFormat::Parquet => RowWriter::parquet(path, &table.get_name(), &columns)?,
+        };
+        for is_replication in [false, true] {
+            self.for_each_stored_row(keyspace, table, is_replication, |line| {
+                let (values, _) = line.split_once(';').ok_or(StorageEngineError::IoError)?;
+                let values: Vec<&str> = values.split(',').collect();
+
+                if let Some(tokens) = &tokens {
+                    // Partitions are placed in the ring by the values of their key, joined
+                    let key: String = partition_key
+                        .iter()
+                        .map(|&i| values.get(i).copied().unwrap_or_default())
+                        .collect();
+                    let token = Partitioner::token_of(key)
+                        .map_err(|_| StorageEngineError::UnsupportedOperation)?;
+                    if !tokens.contains(&token) {
+                        return Ok(());
+                    }
+                }
+                // Counters are written with their values, not their shards
+                let values = sum_counter_cells(&values, &columns);
+                writer.write_row(&values.iter().map(String::as_str).collect::<Vec<&str>>())
+            })?;
+        }
+        let rows = writer.finish()?;
+
+        Ok(ExportReport {
+            rows,
+            bytes: fs::metadata(path)?.len(),
+        })
+    }
+}
+
+// Writes the rows of a table to a file, one at a time
+enum RowWriter {
+    Csv {
+        file: BufWriter<File>,
+        rows: u64,
+    },
+    Parquet {
+        file: SerializedFileWriter<File>,
+        columns: Vec<ParquetColumn>,
+        buffered: usize,
+        rows: u64,
+    },
+}
+
+impl RowWriter {
+    fn csv(path: &Path, columns: &[Column]) -> Result<Self, StorageEngineError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let header: Vec<String> = columns.iter().map(|c| csv_field(&c.name)).collect();
+        writeln!(file, "{}", header.join(","))?;
+        Ok(RowWriter::Csv { file, rows: 0 })
+    }
+
+    fn parquet(path: &Path, table: &str, columns: &[Column]) -> Result<Self, StorageEngineError> {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|c| match parquet_type(&c.data_type) {
+                (physical, Some(logical)) => {
+                    format!("OPTIONAL {} {} ({});", physical, c.name, logical)
+                }
+                (physical, None) => format!("OPTIONAL {} {};", physical, c.name),
+            })
+            .collect();
+        let schema = parse_message_type(&format!("message {} {{ {} }}", table, fields.join(" ")))
+            .map_err(parquet_error)?;
+        let file = SerializedFileWriter::new(
+            File::create(path)?,
+            Arc::new(schema),
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .map_err(parquet_error)?;
+
+        Ok(RowWriter::Parquet {
+            file,
+            columns: columns
+                .iter()
+                .map(|c| ParquetColumn::new(&c.data_type))
+                .collect(),
+            buffered: 0,
+            rows: 0,
+        })
+    }
+
+    fn write_row(&mut self, values: &[&str]) -> Result<(), StorageEngineError> {
+        match self {
+            RowWriter::Csv { file, rows } => {
+                let fields: Vec<String> = values.iter().map(|value| csv_field(value)).collect();
+                writeln!(file, "{}", fields.join(","))?;
+                *rows += 1;
+            }
+            RowWriter::Parquet {
+                file,
+                columns,
+                buffered,
+                rows,
+            } => {
+                for (i, column) in columns.iter_mut().enumerate() {
+                    column.push(values.get(i).copied().unwrap_or_default());
+                }
+                *buffered += 1;
+                *rows += 1;
+                if *buffered == PARQUET_ROW_GROUP_ROWS {
+                    write_row_group(file, columns)?;
+                    *buffered = 0;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Writes what is left of the file and returns the rows written
+    fn finish(self) -> Result<u64, StorageEngineError> {
+        match self {
+            RowWriter::Csv { mut file, rows } => {
+                file.flush()?;
+                Ok(rows)
+            }
+            RowWriter::Parquet {
+                mut file,
+                mut columns,
+                buffered,
+                rows,
+            } => {
+                if buffered > 0 {
+                    write_row_group(&mut file, &mut columns)?;
+                }
+                file.close().map_err(parquet_error)?;
+                Ok(rows)
+            }
+        }
+    }
+}
+
+// The values of a column of a row group, with the definition level of each row: 0 for the
+// empty (null) values and the ones that do not parse as the type of the column, 1 otherwise
+enum ParquetColumn {
+    Int(Vec<i32>, Vec<i16>),
+    Long(Vec<i64>, Vec<i16>),
+    Boolean(Vec<bool>, Vec<i16>),
+    Float(Vec<f32>, Vec<i16>),
+    Double(Vec<f64>, Vec<i16>),
+    Text(Vec<ByteArray>, Vec<i16>),
+}
+
+impl ParquetColumn {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int => ParquetColumn::Int(vec![], vec![]),
+            DataType::Counter => ParquetColumn::Long(vec![], vec![]),
+            DataType::Boolean => ParquetColumn::Boolean(vec![], vec![]),
+            DataType::Float => ParquetColumn::Float(vec![], vec![]),
+            DataType::Double => ParquetColumn::Double(vec![], vec![]),
+            DataType::String | DataType::Timestamp | DataType::Uuid => {
+                ParquetColumn::Text(vec![], vec![])
+            }
+        }
+    }
+
+    fn push(&mut self, value: &str) {
+        fn push_parsed<T: std::str::FromStr>(
+            values: &mut Vec<T>,
+            levels: &mut Vec<i16>,
+            value: &str,
+        ) {
+            match value.parse() {
+                Ok(parsed) if !value.is_empty() => {
+                    values.push(parsed);
+                    levels.push(1);
+                }
+                _ => levels.push(0),
+            }
+        }
+
+        match self {
+            ParquetColumn::Int(values, levels) => push_parsed(values, levels, value),
+            ParquetColumn::Long(values, levels) => push_parsed(values, levels, value),
+            ParquetColumn::Boolean(values, levels) => push_parsed(values, levels, value),
+            ParquetColumn::Float(values, levels) => push_parsed(values, levels, value),
+            ParquetColumn::Double(values, levels) => push_parsed(values, levels, value),
+            ParquetColumn::Text(values, levels) => {
+                if value.is_empty() {
+                    levels.push(0);
+                } else {
+                    values.push(ByteArray::from(value));
+                    levels.push(1);
+                }
+            }
+        }
+    }
+}
+
+// Writes the buffered values of every column as a row group, and empties them
+fn write_row_group(
+    file: &mut SerializedFileWriter<File>,
+    columns: &mut [ParquetColumn],
+) -> Result<(), StorageEngineError> {
+    let mut row_group = file.next_row_group().map_err(parquet_error)?;
+    for column in columns.iter_mut() {
+        let mut writer = row_group
+            .next_column()
+            .map_err(parquet_error)?
+            .ok_or(StorageEngineError::FileWriteFailed)?;
+        match column {
+            ParquetColumn::Int(values, levels) => {
+                writer
+                    .typed::<Int32Type>()
+                    .write_batch(values, Some(levels), None)
+            }
+            ParquetColumn::Long(values, levels) => {
+                writer
+                    .typed::<Int64Type>()
+                    .write_batch(values, Some(levels), None)
+            }
+            ParquetColumn::Boolean(values, levels) => {
+                writer
+                    .typed::<BoolType>()
+                    .write_batch(values, Some(levels), None)
+            }
+            ParquetColumn::Float(values, levels) => {
+                writer
+                    .typed::<FloatType>()
+                    .write_batch(values, Some(levels), None)
+            }
+            ParquetColumn::Double(values, levels) => {
+                writer
+                    .typed::<DoubleType>()
+                    .write_batch(values, Some(levels), None)
+            }
+            ParquetColumn::Text(values, levels) => {
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(values, Some(levels), None)
+            }
+        }
+        .map_err(parquet_error)?;
+        writer.close().map_err(parquet_error)?;
+
+        match column {
+            ParquetColumn::Int(values, levels) => clear(values, levels),
+            ParquetColumn::Long(values, levels) => clear(values, levels),
+            ParquetColumn::Boolean(values, levels) => clear(values, levels),
+            ParquetColumn::Float(values, levels) => clear(values, levels),
+            ParquetColumn::Double(values, levels) => clear(values, levels),
+            ParquetColumn::Text(values, levels) => clear(values, levels),
+        }
+    }
+    row_group.close().map_err(parquet_error)?;
+    Ok(())
+}
+
+fn clear<T>(values: &mut Vec<T>, levels: &mut Vec<i16>) {
+    values.clear();
+    levels.clear();
+}
+
+// Parquet physical type of the values of a column, and the logical type annotating it, text for
+// the types Parquet does not have
+fn parquet_type(data_type: &DataType) -> (&'static str, Option<&'static str>) {
+    match data_type {
+        DataType::Int => ("INT32", None),
+        DataType::Counter => ("INT64", None),
+        DataType::Boolean => ("BOOLEAN", None),
+        DataType::Float => ("FLOAT", None),
+        DataType::Double => ("DOUBLE", None),
+        DataType::String | DataType::Timestamp | DataType::Uuid => ("BYTE_ARRAY", Some("UTF8")),
+    }
+}
+
+// Quotes the values that would break the line of a row
+fn csv_field(value: &str) -> String {
+    if value.contains(['"', ',', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn parquet_error(_: ParquetError) -> StorageEngineError {
+    StorageEngineError::FileWriteFailed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use std::{fs::OpenOptions, path::PathBuf};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_export_table_as_csv_and_parquet() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number))",
+            )
+            .unwrap(),
+        );
+        // Creates the files of the table
+        storage.sample_partitions("sky", &table, 1.0, 0).unwrap();
+
+        for (is_replication, lines) in [
+            (false, ["EZE,1,landed;10", "AEP,2,;10"]),
+            (true, ["COR,3,boarding;10", "MDZ,4,delayed;10"]),
+        ] {
+            let data_path = storage
+                .get_folder_path("sky", is_replication)
+                .unwrap()
+                .join("flights.csv");
+            let mut file = OpenOptions::new().append(true).open(&data_path).unwrap();
+            for line in lines {
+                writeln!(file, "{}", line).unwrap();
+            }
+        }
+
+        let csv_path = root.join("flights_export.csv");
+        let report = storage
+            .export_table("sky", &table, ExportFormat::Csv, None, &csv_path)
+            .unwrap();
+        assert_eq!(report.rows, 4);
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "airport,number,status",
+                "EZE,1,landed",
+                "AEP,2,",
+                "COR,3,boarding",
+                "MDZ,4,delayed"
+            ]
+        );
+        assert_eq!(report.bytes, csv.len() as u64);
+
+        // Only the partitions whose token is in the range
+        let token = Partitioner::token_of("EZE").unwrap();
+        let report = storage
+            .export_table(
+                "sky",
+                &table,
+                ExportFormat::Csv,
+                Some(token..token + 1),
+                &csv_path,
+            )
+            .unwrap();
+        assert_eq!(report.rows, 1);
+        assert_eq!(
+            fs::read_to_string(&csv_path).unwrap(),
+            "airport,number,status\nEZE,1,landed\n"
+        );
+
+        let parquet_path = root.join("flights_export.parquet");
+        let report = storage
+            .export_table("sky", &table, ExportFormat::Parquet, None, &parquet_path)
+            .unwrap();
+        assert_eq!(report.rows, 4);
+        let parquet = fs::read(&parquet_path).unwrap();
+        assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_csv_field_quotes_what_breaks_the_line() {
+        assert_eq!(csv_field("landed"), "landed");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+}
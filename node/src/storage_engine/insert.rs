@@ -5,7 +5,11 @@ use std::{
 
 use query_creator::{clauses::types::column::Column, operator::Operator};
 
-use super::{errors::StorageEngineError, StorageEngine};
+use super::{
+    errors::StorageEngineError,
+    row_stamp::{split_row, RowStamp},
+    StorageEngine,
+};
 
 impl StorageEngine {
     /// Inserts a new row into a table within the specified keyspace.
@@ -25,7 +29,8 @@ impl StorageEngine {
     /// - `clustering_columns_in_order`: A vector of strings indicating the clustering columns and their order.
     /// - `is_replication`: A boolean indicating whether the insertion is part of a replication process.
     /// - `if_not_exist`: A boolean indicating whether the row should only be inserted if it does not already exist.
-    /// - `timestamp`: A 64-bit integer representing the timestamp of the operation.
+    /// - `stamp`: The timestamp of the operation and, if the row expires, its expiration (a bare
+    ///   `i64` timestamp writes a row that never expires).
     ///
     /// # Returns
     /// - `Ok(())`: If the row is successfully inserted.
@@ -41,6 +46,8 @@ impl StorageEngine {
     /// - If the table file exists:
     ///   - The header is validated, and rows are written in clustering order.
     /// - If `if_not_exist` is `true`, rows with matching clustering keys will not be overwritten.
+    /// - The expired rows of the file are dropped while it is rewritten, so they do not count as
+    ///   existing rows for `if_not_exist`.
     /// - For clustering keys:
     ///   - The function ensures that rows are inserted in the correct order based on the `clustering_columns_in_order`.
     ///   - Clustering order can be `ASC` (ascending) or `DESC` (descending), defined per column.
@@ -54,7 +61,7 @@ impl StorageEngine {
     /// # Edge Cases
     /// - **Empty `values` or `columns`:** The function will return an error if the values or columns are missing.
     /// - **Invalid clustering order:** If a clustering column's order is unspecified or inconsistent, an error may occur.
-    /// - **Concurrent writes:** Simultaneous calls to `insert` on the same table may cause unexpected behavior and are not supported.
+    /// - **Concurrent writes:** Simultaneous writes to the same table wait for each other, as each one rewrites its file.
     ///
     /// # Limitations
     /// - The function currently supports only `.csv` file formats.
@@ -69,20 +76,26 @@ impl StorageEngine {
         clustering_columns_in_order: Vec<String>,
         is_replication: bool,
         if_not_exist: bool,
-        timestamp: i64,
+        stamp: impl Into<RowStamp>,
     ) -> Result<(), StorageEngineError> {
-        let folder_path =
-            self.get_keyspace_path(keyspace)
-                .join(if is_replication { "replication" } else { "" });
+        let stamp = stamp.into();
+        let folder_path = self.get_folder_path(keyspace, is_replication)?;
 
-        if !folder_path.exists() {
-            fs::create_dir_all(&folder_path)
-                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
-        }
+        // The table may not have been created yet in this node if its schema is still being gossiped
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, table, &column_names)?;
 
         let file_path = folder_path.join(format!("{}.csv", table));
-        let temp_file_path = folder_path.join(format!("temp_{}.csv", timestamp));
+        if let Some(store) = self.lsm_store() {
+            self.lsm_insert(&store, &file_path, &values, &columns, if_not_exist, stamp)?;
+            self.invalidate_cached_row(keyspace, table, is_replication, &values, &columns);
+            return Ok(());
+        }
+        let _lock = self.lock_table(&file_path)?;
+        let temp_file_path = folder_path.join(format!("{}_{}.tmp", table, stamp.timestamp));
         let index_file_path = folder_path.join(format!("{}_index.csv", table));
+        // The index is replaced like the data file, so it is never seen half written
+        let temp_index_path = temp_file_path.with_extension("index.tmp");
 
         let clustering_indices =
             Self::get_clustering_indices(&columns, &clustering_columns_in_order)?;
@@ -96,7 +109,7 @@ impl StorageEngine {
         let mut temp_file =
             File::create(&temp_file_path).map_err(|_| StorageEngineError::IoError)?;
         let mut temp_index = BufWriter::new(
-            File::create(&index_file_path).map_err(|_| StorageEngineError::IoError)?,
+            File::create(&temp_index_path).map_err(|_| StorageEngineError::IoError)?,
         );
 
         writeln!(temp_index, "clustering_column,start_byte,end_byte")
@@ -111,10 +124,14 @@ impl StorageEngine {
                 writeln!(temp_file, "{}", header_line).map_err(|_| StorageEngineError::IoError)?;
                 current_byte_offset += header_line.len() as u64 + 1; // Contamos el '\n'
             }
+            let now = RowStamp::now();
             for (_, line) in lines.enumerate() {
                 let line = line.map_err(|_| StorageEngineError::IoError)?;
                 let line_length = line.len() as u64;
 
+                if split_row(&line)?.1.is_expired(now) {
+                    continue;
+                }
                 let (line_content, row_timestamp) = Self::split_line(&line)?;
                 let row: Vec<&str> = line_content.split(',').collect();
 
@@ -123,8 +140,10 @@ impl StorageEngine {
                 let clustering_cmp =
                     Self::compare_clustering(&row, &values, &clustering_indices, &columns)?;
 
-                if clustering_cmp == std::cmp::Ordering::Equal {
-                    if is_same_partition && if_not_exist {
+                // Rows of other partitions with the same clustering key are kept, the row goes
+                // after them
+                if clustering_cmp == std::cmp::Ordering::Equal && is_same_partition {
+                    if if_not_exist {
                         writeln!(temp_file, "{};{}", line_content, row_timestamp)
                             .map_err(|_| StorageEngineError::IoError)?;
                         current_byte_offset += line_length + 1;
@@ -140,7 +159,7 @@ impl StorageEngine {
                     Self::write_inserted_row(
                         &mut temp_file,
                         &values,
-                        timestamp,
+                        stamp,
                         &mut inserted,
                         &mut current_byte_offset,
                         &mut index_map,
@@ -151,7 +170,7 @@ impl StorageEngine {
                     Self::write_inserted_row(
                         &mut temp_file,
                         &values,
-                        timestamp,
+                        stamp,
                         &mut inserted,
                         &mut current_byte_offset,
                         &mut index_map,
@@ -177,7 +196,7 @@ impl StorageEngine {
             Self::write_inserted_row(
                 &mut temp_file,
                 &values,
-                timestamp,
+                stamp,
                 &mut inserted,
                 &mut current_byte_offset,
                 &mut index_map,
@@ -189,21 +208,27 @@ impl StorageEngine {
             writeln!(temp_index, "{},{},{}", key, start_byte, end_byte)
                 .map_err(|_| StorageEngineError::IoError)?;
         }
+        temp_index
+            .flush()
+            .map_err(|_| StorageEngineError::IoError)?;
+        drop(temp_index);
 
         fs::rename(&temp_file_path, &file_path).map_err(|_| StorageEngineError::IoError)?;
+        fs::rename(&temp_index_path, &index_file_path).map_err(|_| StorageEngineError::IoError)?;
+        self.invalidate_cached_row(keyspace, table, is_replication, &values, &columns);
         Ok(())
     }
 
     fn write_inserted_row(
         file: &mut File,
         values: &[&str],
-        timestamp: i64,
+        stamp: RowStamp,
         inserted: &mut bool,
         current_byte_offset: &mut u64,
         index_map: &mut std::collections::BTreeMap<String, (u64, u64)>,
         clustering_indices: &[(usize, String)],
     ) -> Result<(), StorageEngineError> {
-        let line = format!("{};{}", values.join(","), timestamp);
+        let line = format!("{};{}", values.join(","), stamp);
         let line_length = line.len() as u64;
 
         writeln!(file, "{}", line).map_err(|_| StorageEngineError::IoError)?;
@@ -219,7 +244,7 @@ impl StorageEngine {
         Ok(())
     }
 
-    fn update_index_map(
+    pub(super) fn update_index_map(
         row: &[&str],
         clustering_indices: &[(usize, String)],
         index_map: &mut std::collections::BTreeMap<String, (u64, u64)>,
@@ -235,7 +260,7 @@ impl StorageEngine {
         }
     }
 
-    fn get_clustering_indices(
+    pub(super) fn get_clustering_indices(
         columns: &[Column],
         clustering_columns: &[String],
     ) -> Result<Vec<(usize, String)>, StorageEngineError> {
@@ -276,7 +301,7 @@ impl StorageEngine {
             .all(|&index| row.get(index) == values.get(index))
     }
 
-    fn compare_clustering(
+    pub(super) fn compare_clustering(
         row: &[&str],
         values: &[&str],
         clustering_indices: &[(usize, String)],
@@ -477,4 +502,93 @@ mod tests {
             fs::remove_dir_all(&root).unwrap();
         }
     }
+
+    #[test]
+    fn test_insert_keeps_rows_of_other_partitions_with_the_same_clustering_key() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let keyspace = "test_keyspace";
+        fs::create_dir_all(storage.get_keyspace_path(keyspace)).unwrap();
+
+        let mut airport = Column::new("airport", DataType::String, true, false);
+        airport.is_partition_key = true;
+        let mut number = Column::new("number", DataType::Int, true, false);
+        number.is_clustering_column = true;
+        let status = Column::new("status", DataType::String, false, true);
+        let columns = vec![airport, number, status];
+
+        for (values, timestamp) in [
+            (vec!["EZE", "1", "delayed"], 10),
+            (vec!["AEP", "1", "on time"], 11),
+            (vec!["EZE", "1", "landed"], 12),
+        ] {
+            storage
+                .insert(
+                    keyspace,
+                    "flights",
+                    values,
+                    columns.clone(),
+                    vec!["number".to_string()],
+                    false,
+                    false,
+                    timestamp,
+                )
+                .unwrap();
+        }
+
+        let file = File::open(storage.get_keyspace_path(keyspace).join("flights.csv")).unwrap();
+        let mut rows: Vec<String> = BufReader::new(file)
+            .lines()
+            .skip(1)
+            .map(|line| line.unwrap().split(';').next().unwrap().to_string())
+            .collect();
+        rows.sort();
+        assert_eq!(rows, ["AEP,1,on time", "EZE,1,landed"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_insert_drops_expired_rows() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let columns = vec![
+            Column::new("flight", DataType::String, true, false),
+            Column::new("lat", DataType::Int, false, true),
+        ];
+        let insert = |values: Vec<&str>, if_not_exist, stamp: RowStamp| {
+            storage
+                .insert(
+                    "sky",
+                    "positions",
+                    values,
+                    columns.clone(),
+                    vec![],
+                    false,
+                    if_not_exist,
+                    stamp,
+                )
+                .unwrap()
+        };
+        let data_path = root
+            .join("keyspaces_of_127_0_0_1")
+            .join("sky")
+            .join("positions.csv");
+
+        insert(vec!["AR1", "10"], false, RowStamp::new(1, Some(60)));
+        assert_eq!(
+            fs::read_to_string(&data_path).unwrap(),
+            "flight,lat\nAR1,10;1;61\n"
+        );
+
+        // The expired row does not count as existing, and it is dropped when the file is rewritten
+        let now = RowStamp::now();
+        insert(vec!["AR1", "11"], true, RowStamp::new(now, Some(0)));
+        assert_eq!(
+            fs::read_to_string(&data_path).unwrap(),
+            format!("flight,lat\nAR1,11;{}\n", now)
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
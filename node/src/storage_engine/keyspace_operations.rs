@@ -1,4 +1,4 @@
-use super::{errors::StorageEngineError, StorageEngine};
+use super::{errors::StorageEngineError, StorageEngine, CREATION_LOCK};
 
 impl StorageEngine {
     /// Creates a keyspace in the storage location.
@@ -8,30 +8,33 @@ impl StorageEngine {
     ///
     /// # Arguments
     /// - `name`: The name of the keyspace to create.
+    /// - `if_not_exists`: Whether an existing keyspace is left as it is instead of being an error,
+    ///   as with `CREATE KEYSPACE IF NOT EXISTS`.
     ///
     /// # Returns
-    /// - `Ok(())` if the keyspace and its subdirectory are successfully created.
+    /// - `Ok(())` if the keyspace and its subdirectory are successfully created (or already existed
+    ///   and `if_not_exists` is set).
+    /// - `Err(StorageEngineError::AlreadyExists)` if the keyspace already exists and `if_not_exists` is not set.
     /// - `Err(StorageEngineError::DirectoryCreationFailed)` if there is an issue creating the directories.
     ///
     /// # Errors
     /// This function will return an error if the directory or any subdirectory cannot be created.
 
-    pub fn create_keyspace(&self, name: &str) -> Result<(), StorageEngineError> {
-        // Generate the folder name where the keyspace will be stored
-        let ip_str = self.ip.replace(".", "_");
-        let keyspace_folder = format!("keyspaces_of_{}", ip_str);
-        let keyspace_path = self.root.join(&keyspace_folder).join(name);
-
-        // Create the keyspace folder if it doesn't exist
-        if let Err(_) = std::fs::create_dir_all(&keyspace_path) {
-            return Err(StorageEngineError::DirectoryCreationFailed);
+    pub fn create_keyspace(
+        &self,
+        name: &str,
+        if_not_exists: bool,
+    ) -> Result<(), StorageEngineError> {
+        let _guard = CREATION_LOCK
+            .lock()
+            .map_err(|_| StorageEngineError::IoError)?;
+
+        if self.get_keyspace_path(name).exists() && !if_not_exists {
+            return Err(StorageEngineError::AlreadyExists);
         }
 
-        // Create the replication folder inside the keyspace folder
-        let replication_path = keyspace_path.join("replication");
-        if let Err(_) = std::fs::create_dir_all(&replication_path) {
-            return Err(StorageEngineError::DirectoryCreationFailed);
-        }
+        // Creates the keyspace folder and the replication folder inside it if they don't exist
+        self.get_folder_path(name, true)?;
 
         Ok(())
     }
@@ -61,6 +64,8 @@ impl StorageEngine {
         if let Err(_) = std::fs::remove_dir_all(&keyspace_path) {
             return Err(StorageEngineError::FileDeletionFailed);
         }
+        self.lsm_drop_keyspace(name)?;
+        self.invalidate_cached_table(name, None);
 
         Ok(())
     }
@@ -89,7 +94,7 @@ mod tests {
         }
 
         // Call the function
-        let result = storage.create_keyspace(keyspace_name);
+        let result = storage.create_keyspace(keyspace_name, false);
         assert!(result.is_ok(), "Failed to create keyspace");
 
         // Check that the keyspace directory and replication folder were created
@@ -103,6 +108,26 @@ mod tests {
         fs::remove_dir_all(&keyspace_path).unwrap();
     }
 
+    #[test]
+    fn test_create_existing_keyspace() {
+        let root = PathBuf::from("/tmp/storage_test_existing_keyspace");
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let keyspace_name = "test_keyspace";
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+
+        assert!(storage.create_keyspace(keyspace_name, false).is_ok());
+        assert!(matches!(
+            storage.create_keyspace(keyspace_name, false),
+            Err(StorageEngineError::AlreadyExists)
+        ));
+        assert!(storage.create_keyspace(keyspace_name, true).is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_drop_keyspace() {
         let root = PathBuf::from("/tmp/storage_test");
@@ -0,0 +1,1248 @@
+//! LSM-style storage backend: writes go to an in-memory memtable, which is flushed to an
+//! immutable SSTable once it holds enough rows, and reads merge all of them.
+//!
+//! The rows of a table are looked up, from oldest to newest, in:
+//! 1. Its CSV data file (the *base*), which is what the CSV backend writes. The data replayed
+//!    from the commit log before the backend is selected, and the rows kept by a redistribution,
+//!    end up there.
+//! 2. Its SSTables, `<table>-<generation>.sst`, next to the data file. Each line is
+//!    `<primary key>\t<values>;<stamp>`, sorted by primary key, and a deleted row is written
+//!    as a tombstone, `<primary key>\t;<timestamp>` (rows are never empty, as they hold their
+//!    primary key). Each SSTable has a bloom filter, `<table>-<generation>.bloom`, and a
+//!    partition index, `<table>-<generation>.index`, which tells where the rows of each of its
+//!    partitions are, so the reads of a partition skip the SSTables without it and only read the
+//!    part of the others that holds it.
+//! 3. Its memtable.
+//!
+//! As with the CSV backend, the last write of a row replaces the previous ones, whatever their
+//! timestamps. Writes are not lost if the node crashes before a flush, as they are in the commit
+//! log.
+//!
+//! Expired rows are skipped by the reads, but they are kept in the memtable and SSTables until a
+//! compaction, as dropping them before would bring back the older versions they replaced.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use gossip::structures::application_state::TableSchema;
+use query_creator::clauses::{
+    delete_cql::Delete, select_cql::Select, types::column::Column, update_cql::Update,
+};
+
+use super::{
+    bloom_filter::{file_stamp, may_hold_partition, remove_filter, BloomFilter, PartitionColumns},
+    errors::StorageEngineError,
+    row_stamp::{split_row, RowStamp},
+    StorageEngine,
+};
+
+/// Rows a memtable holds before it is flushed by default.
+pub const DEFAULT_MEMTABLE_ROWS: usize = 1000;
+
+/// How a node stores the rows of its tables.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StorageBackend {
+    /// Each table is a CSV file, sorted by clustering order and rewritten on every write.
+    #[default]
+    Csv,
+    /// Each table is a memtable plus SSTables, flushed once the memtable holds `memtable_rows`.
+    Lsm { memtable_rows: usize },
+}
+
+impl FromStr for StorageBackend {
+    type Err = StorageEngineError;
+
+    /// Parses `csv` or `lsm` (which flushes every `DEFAULT_MEMTABLE_ROWS`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(StorageBackend::Csv),
+            "lsm" => Ok(StorageBackend::Lsm {
+                memtable_rows: DEFAULT_MEMTABLE_ROWS,
+            }),
+            _ => Err(StorageEngineError::UnsupportedOperation),
+        }
+    }
+}
+
+/// A row of a memtable or an SSTable.
+#[derive(Debug, Clone, PartialEq)]
+enum LsmEntry {
+    Row { values: String, stamp: RowStamp },
+    Tombstone { timestamp: i64 },
+}
+
+impl LsmEntry {
+    fn to_line(&self, key: &str) -> String {
+        match self {
+            LsmEntry::Row { values, stamp } => format!("{}\t{};{}", key, values, stamp),
+            LsmEntry::Tombstone { timestamp } => format!("{}\t;{}", key, timestamp),
+        }
+    }
+
+    fn from_line(line: &str) -> Result<(String, Self), StorageEngineError> {
+        let (key, entry) = line.split_once('\t').ok_or(StorageEngineError::IoError)?;
+        let (values, stamp) = split_row(entry)?;
+
+        let entry = if values.is_empty() {
+            LsmEntry::Tombstone {
+                timestamp: stamp.timestamp,
+            }
+        } else {
+            LsmEntry::Row {
+                values: values.to_string(),
+                stamp,
+            }
+        };
+        Ok((key.to_string(), entry))
+    }
+}
+
+type Memtable = BTreeMap<String, LsmEntry>;
+
+/// The memtable of a table, with where its partition key is in the primary keys of its rows, to
+/// write the bloom filters of its SSTables.
+#[derive(Debug)]
+struct TableMemtable {
+    rows: Memtable,
+    partition_columns: PartitionColumns,
+}
+
+/// The rows of a table, by primary key, as `(values, stamp)`.
+type Rows = BTreeMap<String, (String, RowStamp)>;
+
+/// The memtables of the tables of a node, by the path of their data file.
+#[derive(Debug)]
+pub struct LsmStore {
+    memtables: Mutex<HashMap<PathBuf, TableMemtable>>,
+    memtable_rows: usize,
+}
+
+impl LsmStore {
+    fn new(memtable_rows: usize) -> Self {
+        LsmStore {
+            memtables: Mutex::new(HashMap::new()),
+            memtable_rows: memtable_rows.max(1),
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<PathBuf, TableMemtable>>, StorageEngineError> {
+        self.memtables
+            .lock()
+            .map_err(|_| StorageEngineError::IoError)
+    }
+
+    /// Writes `entry` to the memtable of the table, flushing it if it is full.
+    fn put(
+        &self,
+        data_path: &Path,
+        key: String,
+        entry: LsmEntry,
+        partition_columns: &PartitionColumns,
+    ) -> Result<(), StorageEngineError> {
+        let mut memtables = self.lock()?;
+        let memtable = memtables
+            .entry(data_path.to_path_buf())
+            .or_insert_with(|| TableMemtable {
+                rows: Memtable::new(),
+                partition_columns: partition_columns.clone(),
+            });
+        memtable.rows.insert(key, entry);
+
+        if memtable.rows.len() >= self.memtable_rows {
+            Self::write_sstable(data_path, memtable)?;
+            memtable.rows.clear();
+        }
+        Ok(())
+    }
+
+    /// Returns the live rows of the table, the ones neither deleted nor expired.
+    fn rows(&self, data_path: &Path, key_indices: &[usize]) -> Result<Rows, StorageEngineError> {
+        let now = RowStamp::now();
+        let mut rows = self.stored_rows(data_path, key_indices)?;
+        rows.retain(|_, (_, stamp)| !stamp.is_expired(now));
+        Ok(rows)
+    }
+
+    /// Returns the live rows of the table that may be of a partition, skipping the data file and
+    /// SSTables whose bloom filters say they do not hold it. Rows of other partitions may be
+    /// returned too.
+    fn partition_rows(
+        &self,
+        data_path: &Path,
+        key_indices: &[usize],
+        partition: &str,
+    ) -> Result<Rows, StorageEngineError> {
+        let now = RowStamp::now();
+        let memtables = self.lock()?;
+        let mut rows = Self::merge(
+            data_path,
+            memtables.get(data_path),
+            key_indices,
+            Some(partition),
+        )?;
+        rows.retain(|_, (_, stamp)| !stamp.is_expired(now));
+        Ok(rows)
+    }
+
+    /// Returns the rows of the table that were not deleted, including the expired ones.
+    fn stored_rows(
+        &self,
+        data_path: &Path,
+        key_indices: &[usize],
+    ) -> Result<Rows, StorageEngineError> {
+        let memtables = self.lock()?;
+        Self::merge(data_path, memtables.get(data_path), key_indices, None)
+    }
+
+    /// Writes the live rows of the table to its data file, dropping the expired ones, and removes
+    /// its SSTables and the contents of its memtable. Returns how many SSTables were removed.
+    fn compact(
+        &self,
+        data_path: &Path,
+        key_indices: &[usize],
+    ) -> Result<usize, StorageEngineError> {
+        let mut memtables = self.lock()?;
+        let now = RowStamp::now();
+        let mut rows = Self::merge(data_path, memtables.get(data_path), key_indices, None)?;
+        rows.retain(|_, (_, stamp)| !stamp.is_expired(now));
+
+        let header = match File::open(data_path) {
+            Ok(file) => BufReader::new(file).lines().next().transpose()?,
+            Err(_) => None,
+        };
+        let temp_path = data_path.with_extension("compacting");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        if let Some(header) = header {
+            writeln!(writer, "{}", header)?;
+        }
+        for (values, stamp) in rows.values() {
+            writeln!(writer, "{};{}", values, stamp)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp_path, data_path).map_err(|_| StorageEngineError::FileReplacementFailed)?;
+
+        let sstables = Self::sstables(data_path)?;
+        for sstable in &sstables {
+            fs::remove_file(sstable).map_err(|_| StorageEngineError::FileDeletionFailed)?;
+            remove_filter(sstable);
+            let _ = fs::remove_file(index_path(sstable));
+        }
+        memtables.remove(data_path);
+        Ok(sstables.len())
+    }
+
+    /// Writes the memtable of the table as a new SSTable, and returns whether it had any row.
+    fn flush(&self, data_path: &Path) -> Result<bool, StorageEngineError> {
+        let mut memtables = self.lock()?;
+        match memtables.get_mut(data_path) {
+            Some(memtable) if !memtable.rows.is_empty() => {
+                Self::write_sstable(data_path, memtable)?;
+                memtable.rows.clear();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Writes every memtable that is not empty as a new SSTable of its table, and returns how
+    /// many were written.
+    fn flush_all(&self) -> Result<usize, StorageEngineError> {
+        let mut memtables = self.lock()?;
+        let mut flushed = 0;
+        for (data_path, memtable) in memtables.iter_mut() {
+            if memtable.rows.is_empty() {
+                continue;
+            }
+            Self::write_sstable(data_path, memtable)?;
+            memtable.rows.clear();
+            flushed += 1;
+        }
+        Ok(flushed)
+    }
+
+    /// Removes the SSTables and the memtable of a dropped table.
+    fn drop_table(&self, data_path: &Path) -> Result<(), StorageEngineError> {
+        let mut memtables = self.lock()?;
+        for sstable in Self::sstables(data_path)? {
+            fs::remove_file(&sstable).map_err(|_| StorageEngineError::FileDeletionFailed)?;
+            remove_filter(&sstable);
+            let _ = fs::remove_file(index_path(&sstable));
+        }
+        memtables.remove(data_path);
+        Ok(())
+    }
+
+    /// Removes the memtables of the tables of a dropped keyspace, whose folder was removed.
+    fn drop_keyspace(&self, keyspace_path: &Path) -> Result<(), StorageEngineError> {
+        self.lock()?
+            .retain(|data_path, _| !data_path.starts_with(keyspace_path));
+        Ok(())
+    }
+
+    // Reads the base, then the SSTables and then the memtable, each one replacing the rows of the
+    // previous ones, and drops the deleted rows. If a partition is given, the base and SSTables
+    // that do not hold it are skipped, and only the part of the SSTables that holds it is read.
+    fn merge(
+        data_path: &Path,
+        memtable: Option<&TableMemtable>,
+        key_indices: &[usize],
+        partition: Option<&str>,
+    ) -> Result<Rows, StorageEngineError> {
+        let mut entries = Memtable::new();
+        let may_hold = |file: &Path| partition.is_none_or(|p| may_hold_partition(file, p));
+
+        if let Some(file) = File::open(data_path).ok().filter(|_| may_hold(data_path)) {
+            for line in BufReader::new(file).lines().skip(1) {
+                let line = line?;
+                let (values, stamp) = split_row(&line)?;
+                entries.insert(
+                    primary_key(values, key_indices),
+                    LsmEntry::Row {
+                        values: values.to_string(),
+                        stamp,
+                    },
+                );
+            }
+        }
+
+        for sstable in Self::sstables(data_path)? {
+            if !may_hold(&sstable) {
+                continue;
+            }
+            let mut file = File::open(&sstable)?;
+            let range = match partition {
+                Some(partition) => match partition_range(&sstable, &file.metadata()?, partition) {
+                    Some(Some(range)) => Some(range),
+                    // The index lists every partition of the SSTable, so it does not hold this one
+                    Some(None) => continue,
+                    None => None,
+                },
+                None => None,
+            };
+            let reader: Box<dyn Read> = match range {
+                Some((start, end)) => {
+                    file.seek(SeekFrom::Start(start))?;
+                    Box::new(file.take(end - start))
+                }
+                None => Box::new(file),
+            };
+            for line in BufReader::new(reader).lines() {
+                let (key, entry) = LsmEntry::from_line(&line?)?;
+                entries.insert(key, entry);
+            }
+        }
+
+        if let Some(memtable) = memtable {
+            entries.extend(memtable.rows.clone());
+        }
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|(key, entry)| match entry {
+                LsmEntry::Row { values, stamp } => Some((key, (values, stamp))),
+                LsmEntry::Tombstone { .. } => None,
+            })
+            .collect())
+    }
+
+    // Writes the memtable as the newest SSTable of the table, with its bloom filter and partition
+    // index. It is written under a temporary name, so a crash never leaves half an SSTable behind.
+    fn write_sstable(data_path: &Path, memtable: &TableMemtable) -> Result<(), StorageEngineError> {
+        let generation = Self::sstables(data_path)?
+            .last()
+            .and_then(|path| Self::generation(data_path, path))
+            .map_or(1, |generation| generation + 1);
+        let sstable_path = Self::sstable_path(data_path, generation);
+        let temp_path = sstable_path.with_extension("tmp");
+
+        // Deleted rows are kept in the filter and index too, so the reads of their partition see
+        // the tombstones
+        let mut filter = BloomFilter::new(memtable.rows.len());
+        let mut ranges: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        let mut offset = 0;
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        for (key, entry) in &memtable.rows {
+            let line = entry.to_line(key);
+            writeln!(writer, "{}", line)?;
+            let end = offset + line.len() as u64 + 1;
+
+            let partition = memtable.partition_columns.partition(key);
+            filter.insert(&partition);
+            ranges
+                .entry(partition)
+                .and_modify(|(_, last)| *last = end)
+                .or_insert((offset, end));
+            offset = end;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temp_path, &sstable_path).map_err(|_| StorageEngineError::FileWriteFailed)?;
+
+        let metadata = fs::metadata(&sstable_path)?;
+        filter.write(&sstable_path, &metadata)?;
+        write_partition_index(&sstable_path, &metadata, &ranges)
+    }
+
+    // Returns the SSTables of the table, oldest first.
+    fn sstables(data_path: &Path) -> Result<Vec<PathBuf>, StorageEngineError> {
+        let folder = data_path.parent().ok_or(StorageEngineError::IoError)?;
+        let Ok(entries) = fs::read_dir(folder) else {
+            return Ok(vec![]);
+        };
+
+        let mut sstables: Vec<(u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                Some((Self::generation(data_path, &path)?, path))
+            })
+            .collect();
+        sstables.sort();
+        Ok(sstables.into_iter().map(|(_, path)| path).collect())
+    }
+
+    fn sstable_path(data_path: &Path, generation: u64) -> PathBuf {
+        let table = data_path.file_stem().unwrap_or_default().to_string_lossy();
+        data_path.with_file_name(format!("{}-{}.sst", table, generation))
+    }
+
+    // Returns the generation of `path` if it is an SSTable of the table.
+    fn generation(data_path: &Path, path: &Path) -> Option<u64> {
+        let table = data_path.file_stem()?.to_str()?;
+        path.file_name()?
+            .to_str()?
+            .strip_prefix(table)?
+            .strip_prefix('-')?
+            .strip_suffix(".sst")?
+            .parse()
+            .ok()
+    }
+}
+
+// Returns the path of the partition index of an SSTable.
+fn index_path(sstable: &Path) -> PathBuf {
+    sstable.with_extension("index")
+}
+
+// Writes the partition index of an SSTable: a header with the stamp of the SSTable, as its bloom
+// filter has, and then a `<start>,<end>,<partition>` line per partition, with the byte range of the
+// SSTable that holds its rows. The rows of a partition are not always together, as they are sorted
+// by primary key, so the range may hold rows of other partitions too.
+fn write_partition_index(
+    sstable: &Path,
+    metadata: &fs::Metadata,
+    ranges: &BTreeMap<String, (u64, u64)>,
+) -> Result<(), StorageEngineError> {
+    let (size, modified) = file_stamp(metadata).ok_or(StorageEngineError::IoError)?;
+    let path = index_path(sstable);
+    let temp_path = path.with_extension("index.tmp");
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    writeln!(writer, "{},{}", size, modified)?;
+    for (partition, (start, end)) in ranges {
+        writeln!(writer, "{},{},{}", start, end, partition)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, &path).map_err(|_| StorageEngineError::FileWriteFailed)
+}
+
+// Returns the byte range of an SSTable, described by `metadata`, that holds the rows of a
+// partition, or `Some(None)` if it holds none. Returns `None` if the SSTable has no index built
+// from it as it is, as the ones written before there were indexes.
+fn partition_range(
+    sstable: &Path,
+    metadata: &fs::Metadata,
+    partition: &str,
+) -> Option<Option<(u64, u64)>> {
+    let file = File::open(index_path(sstable)).ok()?;
+    let mut lines = BufReader::new(file).lines();
+    let header = lines.next()?.ok()?;
+    let (size, modified) = header.split_once(',')?;
+    if Some((size.parse().ok()?, modified.parse().ok()?)) != file_stamp(metadata) {
+        return None;
+    }
+
+    for line in lines {
+        let line = line.ok()?;
+        let mut fields = line.splitn(3, ',');
+        let (start, end) = (fields.next()?, fields.next()?);
+        if fields.next()? == partition {
+            return Some(Some((start.parse().ok()?, end.parse().ok()?)));
+        }
+    }
+    Some(None)
+}
+
+// Returns the values of the primary key of a row, which identify it in the memtable.
+fn primary_key(values: &str, key_indices: &[usize]) -> String {
+    let values: Vec<&str> = values.split(',').collect();
+    key_indices
+        .iter()
+        .map(|&i| values.get(i).copied().unwrap_or_default())
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+fn primary_key_indices(columns: &[Column]) -> Vec<usize> {
+    columns
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.is_partition_key || c.is_clustering_column)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+impl StorageEngine {
+    /// Selects how the node of this storage engine stores its tables from now on. Every clone of
+    /// the storage engine uses it too.
+    ///
+    /// It must be selected before the node stores any row, as the rows in the memtables and
+    /// SSTables are not written back to the data files when going back to the CSV backend.
+    pub fn set_backend(&self, backend: StorageBackend) -> Result<(), StorageEngineError> {
+        *self.lsm.write().map_err(|_| StorageEngineError::IoError)? = match backend {
+            StorageBackend::Csv => None,
+            StorageBackend::Lsm { memtable_rows } => Some(Arc::new(LsmStore::new(memtable_rows))),
+        };
+        self.clear_row_cache();
+        Ok(())
+    }
+
+    /// Returns the LSM store of the node, if it uses that backend.
+    pub(crate) fn lsm_store(&self) -> Option<Arc<LsmStore>> {
+        self.lsm.read().ok()?.clone()
+    }
+
+    /// Writes the live rows of the memtables and SSTables of a table (owned and replicas) back
+    /// to its data files. Does nothing with the CSV backend.
+    ///
+    /// Features that rewrite the data files (redistribution, `ALTER TABLE`) call it first, so
+    /// they see every row of the table. Returns how many SSTables were compacted.
+    pub fn compact_lsm_table(
+        &self,
+        keyspace: &str,
+        table: &str,
+        columns: &[Column],
+    ) -> Result<usize, StorageEngineError> {
+        let Some(store) = self.lsm_store() else {
+            return Ok(0);
+        };
+        let key_indices = primary_key_indices(columns);
+        let mut compacted = 0;
+        for is_replication in [false, true] {
+            let data_path = self
+                .get_folder_path(keyspace, is_replication)?
+                .join(format!("{}.csv", table));
+            compacted += store.compact(&data_path, &key_indices)?;
+        }
+        Ok(compacted)
+    }
+
+    /// Writes the memtables of a table (owned and replicas) to new SSTables, and returns how many
+    /// were written. Does nothing with the CSV backend.
+    pub fn flush_lsm_table(
+        &self,
+        keyspace: &str,
+        table: &str,
+    ) -> Result<usize, StorageEngineError> {
+        let Some(store) = self.lsm_store() else {
+            return Ok(0);
+        };
+        let mut flushed = 0;
+        for is_replication in [false, true] {
+            let data_path = self
+                .get_folder_path(keyspace, is_replication)?
+                .join(format!("{}.csv", table));
+            if store.flush(&data_path)? {
+                flushed += 1;
+            }
+        }
+        Ok(flushed)
+    }
+
+    /// Writes the memtable of every table to a new SSTable, so every row the node stores is on
+    /// disk, and returns how many memtables were written. Does nothing with the CSV backend.
+    pub fn flush_memtables(&self) -> Result<usize, StorageEngineError> {
+        match self.lsm_store() {
+            Some(store) => store.flush_all(),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns whether a memtable holds rows, which are not on disk. Always `false` with the CSV
+    /// backend.
+    pub fn has_unflushed_rows(&self) -> Result<bool, StorageEngineError> {
+        match self.lsm_store() {
+            Some(store) => Ok(store
+                .lock()?
+                .values()
+                .any(|memtable| !memtable.rows.is_empty())),
+            None => Ok(false),
+        }
+    }
+
+    /// Calls `on_row` with every live row of a table, as a `values;stamp` line, from its data
+    /// file or, with the LSM backend, merged from the data file, SSTables and memtable. Expired
+    /// rows are skipped.
+    pub(crate) fn for_each_stored_row<F>(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        is_replication: bool,
+        on_row: F,
+    ) -> Result<(), StorageEngineError>
+    where
+        F: FnMut(&str) -> Result<(), StorageEngineError>,
+    {
+        self.scan_stored_rows(keyspace, table, is_replication, false, on_row)
+    }
+
+    /// Like [`for_each_stored_row`](Self::for_each_stored_row), but also calls `on_row` with the
+    /// expired rows that were not dropped by a compaction yet if `include_expired` is set.
+    pub(super) fn scan_stored_rows<F>(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        is_replication: bool,
+        include_expired: bool,
+        mut on_row: F,
+    ) -> Result<(), StorageEngineError>
+    where
+        F: FnMut(&str) -> Result<(), StorageEngineError>,
+    {
+        let table_name = table.get_name();
+        let columns = table.get_columns();
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table_name, &column_names)?;
+
+        let data_path = self
+            .get_folder_path(keyspace, is_replication)?
+            .join(format!("{}.csv", table_name));
+
+        let now = RowStamp::now();
+        if let Some(store) = self.lsm_store() {
+            for (values, stamp) in store
+                .stored_rows(&data_path, &primary_key_indices(&columns))?
+                .values()
+            {
+                if include_expired || !stamp.is_expired(now) {
+                    on_row(&format!("{};{}", values, stamp))?;
+                }
+            }
+            return Ok(());
+        }
+
+        for line in BufReader::new(File::open(&data_path)?).lines().skip(1) {
+            let line = line?;
+            if include_expired || !split_row(&line)?.1.is_expired(now) {
+                on_row(&line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the stored row of a table with the given primary key (its values separated by
+    /// commas), as a `values;stamp` line, if there is one that did not expire. The data files whose
+    /// bloom filters do not hold the partition of the row are not read.
+    pub(crate) fn read_row(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        is_replication: bool,
+        key: &str,
+    ) -> Result<Option<String>, StorageEngineError> {
+        let columns = table.get_columns();
+        let key_indices = primary_key_indices(&columns);
+        let partition = PartitionColumns::of_primary_keys(&columns).partition(key);
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table.get_name(), &column_names)?;
+        let data_path = self
+            .get_folder_path(keyspace, is_replication)?
+            .join(format!("{}.csv", table.get_name()));
+
+        if let Some(store) = self.lsm_store() {
+            return Ok(store
+                .partition_rows(&data_path, &key_indices, &partition)?
+                .get(key)
+                .map(|(values, stamp)| format!("{};{}", values, stamp)));
+        }
+        if !may_hold_partition(&data_path, &partition) {
+            return Ok(None);
+        }
+
+        let mut found = None;
+        self.for_each_stored_row(keyspace, table, is_replication, |line| {
+            let values = line.split_once(';').map_or(line, |(values, _)| values);
+            if primary_key(values, &key_indices) == key {
+                found = Some(line.to_string());
+            }
+            Ok(())
+        })?;
+        Ok(found)
+    }
+
+    /// Inserts a row through the LSM backend, like [`insert`](Self::insert) does with the CSV one.
+    pub(super) fn lsm_insert(
+        &self,
+        store: &LsmStore,
+        data_path: &Path,
+        values: &[&str],
+        columns: &[Column],
+        if_not_exist: bool,
+        stamp: RowStamp,
+    ) -> Result<(), StorageEngineError> {
+        let key_indices = primary_key_indices(columns);
+        let row = values.join(",");
+        let key = primary_key(&row, &key_indices);
+
+        if if_not_exist
+            && store
+                .partition_rows(
+                    data_path,
+                    &key_indices,
+                    &PartitionColumns::of_rows(columns).partition(&row),
+                )?
+                .contains_key(&key)
+        {
+            return Ok(());
+        }
+        store.put(
+            data_path,
+            key,
+            LsmEntry::Row { values: row, stamp },
+            &PartitionColumns::of_primary_keys(columns),
+        )
+    }
+
+    /// Applies an `UPDATE` through the LSM backend, like [`update`](Self::update) does with the
+    /// CSV one: the rows that match its `WHERE` (and `IF`) clauses are written again with the new
+    /// values. Returns whether any row matched. If it updates a single partition, the data file
+    /// and SSTables that do not hold it are skipped.
+    pub(super) fn lsm_update(
+        &self,
+        store: &LsmStore,
+        data_path: &Path,
+        update_query: &Update,
+        table: &TableSchema,
+        partition: Option<&str>,
+        stamp: RowStamp,
+    ) -> Result<bool, StorageEngineError> {
+        let columns_schema = table.get_columns();
+        let key_indices = primary_key_indices(&columns_schema);
+        let partition_columns = PartitionColumns::of_primary_keys(&columns_schema);
+        let Some(where_clause) = &update_query.where_clause else {
+            return Ok(false);
+        };
+        let mut found_match = false;
+        let stored_rows = match partition {
+            Some(partition) => store.partition_rows(data_path, &key_indices, partition)?,
+            None => store.rows(data_path, &key_indices)?,
+        };
+
+        for (key, (values, _)) in stored_rows {
+            let mut columns: Vec<String> =
+                values.split(',').map(|s| s.trim().to_string()).collect();
+            let column_value_map = self.create_column_value_map(table, &columns, false);
+
+            let matches = |condition: &query_creator::clauses::condition::Condition| {
+                condition
+                    .execute(&column_value_map, columns_schema.clone())
+                    .unwrap_or(false)
+            };
+            if !matches(&where_clause.condition)
+                || update_query
+                    .if_clause
+                    .as_ref()
+                    .is_some_and(|if_clause| !matches(&if_clause.condition))
+            {
+                continue;
+            }
+
+            for (column, new_value) in update_query.set_clause.get_pairs() {
+                if table
+                    .is_primary_key(column)
+                    .map_err(|_| StorageEngineError::ColumnNotFound)?
+                {
+                    return Err(StorageEngineError::PrimaryKeyModificationNotAllowed);
+                }
+                Self::set_column(table, &mut columns, column, new_value)?;
+            }
+            found_match = true;
+
+            store.put(
+                data_path,
+                key,
+                LsmEntry::Row {
+                    values: columns.join(","),
+                    stamp,
+                },
+                &partition_columns,
+            )?;
+        }
+        Ok(found_match)
+    }
+
+    /// Applies a `DELETE` through the LSM backend, like [`delete`](Self::delete) does with the
+    /// CSV one: the rows that match are replaced by tombstones, or written again without the
+    /// deleted columns. If it deletes from a single partition, the data file and SSTables that do
+    /// not hold it are skipped.
+    pub(super) fn lsm_delete(
+        &self,
+        store: &LsmStore,
+        data_path: &Path,
+        delete_query: &Delete,
+        table: &TableSchema,
+        partition: Option<&str>,
+        timestamp: i64,
+    ) -> Result<(), StorageEngineError> {
+        let key_indices = primary_key_indices(&table.get_columns());
+        let partition_columns = PartitionColumns::of_primary_keys(&table.get_columns());
+        let stored_rows = match partition {
+            Some(partition) => store.partition_rows(data_path, &key_indices, partition)?,
+            None => store.rows(data_path, &key_indices)?,
+        };
+
+        for (key, (values, stamp)) in stored_rows {
+            if !self.should_delete_line(table, delete_query, &values)? {
+                continue;
+            }
+
+            let entry = match &delete_query.columns {
+                Some(columns_to_delete) => {
+                    let mut columns: Vec<String> =
+                        values.split(',').map(|s| s.trim().to_string()).collect();
+                    for column_name in columns_to_delete {
+                        if let Some(index) = table.get_column_index(column_name) {
+                            columns[index] = String::new();
+                        }
+                    }
+                    // The row keeps the expiration of its last write
+                    LsmEntry::Row {
+                        values: columns.join(","),
+                        stamp: RowStamp {
+                            timestamp,
+                            expires_at: stamp.expires_at,
+                        },
+                    }
+                }
+                None => LsmEntry::Tombstone { timestamp },
+            };
+            store.put(data_path, key, entry, &partition_columns)?;
+        }
+        Ok(())
+    }
+
+    /// Calls `on_row` with every row of the table that matches the `WHERE` clause of the query,
+    /// in clustering order, like `for_each_matching_row` does with the CSV backend. If the query
+    /// reads a single partition, the data file and SSTables that do not hold it are skipped.
+    pub(super) fn lsm_for_each_matching_row<F: FnMut(String)>(
+        &self,
+        store: &LsmStore,
+        data_path: &Path,
+        select_query: &Select,
+        table: &TableSchema,
+        partition: Option<&str>,
+        mut on_row: F,
+    ) -> Result<(), StorageEngineError> {
+        let columns = table.get_columns();
+        let clustering_indices =
+            Self::get_clustering_indices(&columns, &table.get_clustering_column_in_order())?;
+
+        let key_indices = primary_key_indices(&columns);
+        let stored_rows = match partition {
+            Some(partition) => store.partition_rows(data_path, &key_indices, partition)?,
+            None => store.rows(data_path, &key_indices)?,
+        };
+        let mut rows: Vec<(String, RowStamp)> = Vec::new();
+        for (values, stamp) in stored_rows.into_values() {
+            if self.line_matches_where_clause(&values, table, select_query)? {
+                rows.push((values, stamp));
+            }
+        }
+
+        // Rows are merged by primary key, so they are sorted again as the CSV backend keeps them
+        if !clustering_indices.is_empty() {
+            rows.sort_by(|(a, _), (b, _)| {
+                let a: Vec<&str> = a.split(',').collect();
+                let b: Vec<&str> = b.split(',').collect();
+                Self::compare_clustering(&a, &b, &clustering_indices, &columns)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+
+        for (values, stamp) in rows {
+            on_row(format!("{};{}", values, stamp));
+        }
+        Ok(())
+    }
+
+    /// Compacts a data file of a table through the LSM backend if it has at least `min_sstables`
+    /// SSTables, and returns how many were compacted.
+    pub(super) fn lsm_compact_if_needed(
+        &self,
+        store: &LsmStore,
+        data_path: &Path,
+        columns: &[Column],
+        min_sstables: usize,
+    ) -> Result<usize, StorageEngineError> {
+        if LsmStore::sstables(data_path)?.len() < min_sstables.max(1) {
+            return Ok(0);
+        }
+        store.compact(data_path, &primary_key_indices(columns))
+    }
+
+    /// Removes the SSTables and memtables of a dropped table. Does nothing with the CSV backend.
+    pub(super) fn lsm_drop_table(
+        &self,
+        keyspace: &str,
+        table: &str,
+    ) -> Result<(), StorageEngineError> {
+        let Some(store) = self.lsm_store() else {
+            return Ok(());
+        };
+        let keyspace_path = self.get_keyspace_path(keyspace);
+        store.drop_table(&keyspace_path.join(format!("{}.csv", table)))?;
+        store.drop_table(
+            &keyspace_path
+                .join("replication")
+                .join(format!("{}.csv", table)),
+        )
+    }
+
+    /// Removes the memtables of the tables of a dropped keyspace. Does nothing with the CSV
+    /// backend.
+    pub(super) fn lsm_drop_keyspace(&self, keyspace: &str) -> Result<(), StorageEngineError> {
+        match self.lsm_store() {
+            Some(store) => store.drop_keyspace(&self.get_keyspace_path(keyspace)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use query_creator::clauses::types::datatype::DataType;
+    use uuid::Uuid;
+
+    // The partition key of the tables of the tests of the store, their first column
+    fn first_column_partition() -> PartitionColumns {
+        let mut id = Column::new("id", DataType::String, true, false);
+        id.is_partition_key = true;
+        PartitionColumns::of_primary_keys(&[id])
+    }
+
+    #[test]
+    fn test_lsm_store_flushes_and_merges() {
+        let folder = PathBuf::from(format!("/tmp/lsm_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let data_path = folder.join("flights.csv");
+        fs::write(&data_path, "id,status\nAR1,landed;1\nAR2,landed;1\n").unwrap();
+
+        let store = LsmStore::new(2);
+        let partition = first_column_partition();
+        let row = |values: &str, timestamp: i64| LsmEntry::Row {
+            values: values.to_string(),
+            stamp: timestamp.into(),
+        };
+        store
+            .put(
+                &data_path,
+                "AR3".to_string(),
+                row("AR3,boarding", 2),
+                &partition,
+            )
+            .unwrap();
+        assert!(LsmStore::sstables(&data_path).unwrap().is_empty());
+        store
+            .put(
+                &data_path,
+                "AR1".to_string(),
+                row("AR1,delayed", 3),
+                &partition,
+            )
+            .unwrap();
+        // The memtable was full, so it was flushed
+        assert_eq!(
+            LsmStore::sstables(&data_path).unwrap(),
+            vec![folder.join("flights-1.sst")]
+        );
+
+        store
+            .put(
+                &data_path,
+                "AR2".to_string(),
+                LsmEntry::Tombstone { timestamp: 4 },
+                &partition,
+            )
+            .unwrap();
+
+        let rows = store.rows(&data_path, &[0]).unwrap();
+        let expected: Rows = [
+            ("AR1".to_string(), ("AR1,delayed".to_string(), 3.into())),
+            ("AR3".to_string(), ("AR3,boarding".to_string(), 2.into())),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(rows, expected);
+
+        // The tombstone is flushed too
+        assert_eq!(store.flush_all().unwrap(), 1);
+        assert_eq!(store.flush_all().unwrap(), 0);
+        assert_eq!(LsmStore::sstables(&data_path).unwrap().len(), 2);
+        assert_eq!(store.rows(&data_path, &[0]).unwrap(), expected);
+
+        // Each SSTable has the filter of its partitions, tombstones included
+        let first_sstable = folder.join("flights-1.sst");
+        assert!(may_hold_partition(&first_sstable, "AR3"));
+        assert!(!may_hold_partition(&first_sstable, "AR2"));
+        let rows = store.partition_rows(&data_path, &[0], "AR2").unwrap();
+        assert!(!rows.contains_key("AR2"));
+
+        assert_eq!(store.compact(&data_path, &[0]).unwrap(), 2);
+        assert!(LsmStore::sstables(&data_path).unwrap().is_empty());
+        assert!(!folder.join("flights-1.bloom").exists());
+        assert_eq!(
+            fs::read_to_string(&data_path).unwrap(),
+            "id,status\nAR1,delayed;3\nAR3,boarding;2\n"
+        );
+        assert_eq!(store.rows(&data_path, &[0]).unwrap(), expected);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_lsm_store_reads_partitions_through_the_index_of_the_sstables() {
+        let folder = PathBuf::from(format!("/tmp/lsm_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let data_path = folder.join("flights.csv");
+
+        let mut id = Column::new("id", DataType::String, true, false);
+        id.is_partition_key = true;
+        let seat = Column::new("seat", DataType::Int, true, true);
+        let partition = PartitionColumns::of_primary_keys(&[id, seat]);
+
+        let store = LsmStore::new(4);
+        for (key, values) in [
+            ("AR1,1", "AR1,1,anna"),
+            ("AR1,2", "AR1,2,bob"),
+            ("AR2,1", "AR2,1,carl"),
+            ("AR3,1", "AR3,1,dana"),
+        ] {
+            let entry = LsmEntry::Row {
+                values: values.to_string(),
+                stamp: 1.into(),
+            };
+            store
+                .put(&data_path, key.to_string(), entry, &partition)
+                .unwrap();
+        }
+        let sstable = folder.join("flights-1.sst");
+        let metadata = fs::metadata(&sstable).unwrap();
+
+        // Each partition points to the lines of its rows, and the ones missing to none
+        let contents = fs::read_to_string(&sstable).unwrap();
+        let start = contents.find("AR2,1\t").unwrap() as u64;
+        let end = start + "AR2,1\tAR2,1,carl;1\n".len() as u64;
+        assert_eq!(
+            partition_range(&sstable, &metadata, "AR2"),
+            Some(Some((start, end)))
+        );
+        assert_eq!(
+            partition_range(&sstable, &metadata, "AR1"),
+            Some(Some((0, start)))
+        );
+        assert_eq!(partition_range(&sstable, &metadata, "AR9"), Some(None));
+
+        let rows = store.partition_rows(&data_path, &[0, 1], "AR2").unwrap();
+        let expected: Rows = [("AR2,1".to_string(), ("AR2,1,carl".to_string(), 1.into()))]
+            .into_iter()
+            .collect();
+        assert_eq!(rows, expected);
+        assert_eq!(store.rows(&data_path, &[0, 1]).unwrap().len(), 4);
+
+        // An index is ignored once its SSTable is written
+        fs::write(&sstable, &contents[..start as usize]).unwrap();
+        assert_eq!(
+            partition_range(&sstable, &fs::metadata(&sstable).unwrap(), "AR2"),
+            None
+        );
+
+        assert_eq!(store.compact(&data_path, &[0, 1]).unwrap(), 1);
+        assert!(!folder.join("flights-1.index").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_lsm_store_skips_and_purges_expired_rows() {
+        let folder = PathBuf::from(format!("/tmp/lsm_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let data_path = folder.join("positions.csv");
+        fs::write(&data_path, "flight,lat\nAR1,10;1;2\n").unwrap();
+
+        let store = LsmStore::new(10);
+        let partition = first_column_partition();
+        let row = |values: &str, stamp: RowStamp| LsmEntry::Row {
+            values: values.to_string(),
+            stamp,
+        };
+        let live = RowStamp::new(RowStamp::now(), Some(3600));
+        store
+            .put(
+                &data_path,
+                "AR2".to_string(),
+                row("AR2,20", live),
+                &partition,
+            )
+            .unwrap();
+        // An expired write still hides the older version of its row
+        store
+            .put(
+                &data_path,
+                "AR3".to_string(),
+                row("AR3,30", RowStamp::new(1, Some(1))),
+                &partition,
+            )
+            .unwrap();
+        store.flush_all().unwrap();
+        assert_eq!(
+            LsmEntry::from_line(
+                fs::read_to_string(LsmStore::sstable_path(&data_path, 1))
+                    .unwrap()
+                    .lines()
+                    .nth(1)
+                    .unwrap()
+            )
+            .unwrap(),
+            ("AR3".to_string(), row("AR3,30", RowStamp::new(1, Some(1))))
+        );
+
+        let live_rows: Rows = [("AR2".to_string(), ("AR2,20".to_string(), live))]
+            .into_iter()
+            .collect();
+        assert_eq!(store.rows(&data_path, &[0]).unwrap(), live_rows);
+        assert_eq!(store.stored_rows(&data_path, &[0]).unwrap().len(), 3);
+
+        // Compactions drop the expired rows for good
+        assert_eq!(store.compact(&data_path, &[0]).unwrap(), 1);
+        assert_eq!(
+            fs::read_to_string(&data_path).unwrap(),
+            format!("flight,lat\nAR2,20;{}\n", live)
+        );
+        assert_eq!(store.stored_rows(&data_path, &[0]).unwrap(), live_rows);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_lsm_backend_insert_delete_and_select() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        storage
+            .set_backend(StorageBackend::Lsm { memtable_rows: 2 })
+            .unwrap();
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number)) WITH CLUSTERING ORDER BY (number DESC)",
+            )
+            .unwrap(),
+        );
+        let columns = table.get_columns();
+        let insert = |values: Vec<&str>, timestamp| {
+            storage
+                .insert(
+                    "sky",
+                    "flights",
+                    values,
+                    columns.clone(),
+                    table.get_clustering_column_in_order(),
+                    false,
+                    false,
+                    timestamp,
+                )
+                .unwrap()
+        };
+        insert(vec!["EZE", "1", "landed"], 1);
+        insert(vec!["EZE", "3", "boarding"], 2);
+        insert(vec!["EZE", "2", "delayed"], 3);
+        insert(vec!["EZE", "1", "cancelled"], 4);
+
+        let delete =
+            Delete::deserialize("DELETE FROM sky.flights WHERE airport = 'EZE' AND number = 3")
+                .unwrap();
+        storage
+            .delete(delete, table.clone(), "sky", false, 5)
+            .unwrap();
+
+        let select = Select::deserialize(
+            "SELECT airport, number, status FROM sky.flights WHERE airport = 'EZE'",
+        )
+        .unwrap();
+        let rows = storage.select(select, table.clone(), false, "sky").unwrap();
+        assert_eq!(
+            rows[2..],
+            [
+                "EZE,2,delayed;3".to_string(),
+                "EZE,1,cancelled;4".to_string()
+            ]
+        );
+
+        // The clones of the storage engine of the node share its memtables
+        let rows = storage
+            .clone()
+            .select(
+                Select::deserialize("SELECT status FROM sky.flights WHERE airport = 'EZE'")
+                    .unwrap(),
+                table.clone(),
+                false,
+                "sky",
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 4);
+
+        assert_eq!(
+            storage.read_row("sky", &table, false, "EZE,2").unwrap(),
+            Some("EZE,2,delayed;3".to_string())
+        );
+        assert_eq!(
+            storage.read_row("sky", &table, false, "EZE,3").unwrap(),
+            None
+        );
+
+        // Only the memtable of the owned rows has the tombstone left
+        assert_eq!(storage.flush_lsm_table("sky", "flights").unwrap(), 1);
+        assert_eq!(storage.flush_lsm_table("sky", "flights").unwrap(), 0);
+
+        // Redistributions see the rows once they are written to the data file
+        assert_eq!(
+            storage
+                .compact_lsm_table("sky", "flights", &columns)
+                .unwrap(),
+            3
+        );
+        let data = fs::read_to_string(
+            storage
+                .get_folder_path("sky", false)
+                .unwrap()
+                .join("flights.csv"),
+        )
+        .unwrap();
+        assert_eq!(
+            data,
+            "airport,number,status\nEZE,1,cancelled;4\nEZE,2,delayed;3\n"
+        );
+
+        storage.set_backend(StorageBackend::Csv).unwrap();
+        assert!(storage.lsm_store().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,19 +1,49 @@
-use std::fs::{self};
-use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 
+pub mod bloom_filter;
+pub mod commitlog;
+pub mod compaction;
+pub mod counter;
 pub mod data_redistribution;
 pub mod delete;
 pub mod errors;
+pub mod export;
 pub mod insert;
 pub mod keyspace_operations;
+pub mod lsm;
+pub mod row_cache;
+pub mod row_stamp;
+pub mod sampling;
 pub mod select;
+pub mod snapshot;
+pub mod table_lock;
 pub mod table_operations;
+pub mod table_stats;
 pub mod update;
 use errors::StorageEngineError;
 
+/// Serializes the creation of keyspaces and tables, which can be triggered at the same time by a
+/// query and by the schema received through gossip. Without it, a table could be seen by one
+/// thread while another one is still writing its header.
+static CREATION_LOCK: Mutex<()> = Mutex::new(());
+
+/// The storage of the tables of a node. It is cloned for every query and task of the node, and
+/// its clones share the LSM store of the node, if it uses that backend.
+///
+/// ### Fields
+/// - `root`: The folder where the node keeps its keyspaces, commit log and hints.
+/// - `ip`: The address of the node, which names its folders.
+/// - `lsm`: The memtables of the node, set by `set_backend`, so no write can skip them.
+/// - `table_locks`: The data files being rewritten by the CSV backend.
+#[derive(Clone)]
 pub struct StorageEngine {
     root: PathBuf,
     ip: String,
+    lsm: Arc<RwLock<Option<Arc<lsm::LsmStore>>>>,
+    table_locks: Arc<table_lock::TableLocks>,
 }
 
 impl StorageEngine {
@@ -25,7 +55,36 @@ impl StorageEngine {
     ///
 
     pub fn new(root: PathBuf, ip: String) -> Self {
-        Self { root, ip }
+        Self {
+            root,
+            ip,
+            lsm: Arc::new(RwLock::new(None)),
+            table_locks: Arc::new(table_lock::TableLocks::default()),
+        }
+    }
+
+    /// Creates the keyspace directory associated with the storage engine if it does not exist,
+    /// keeping the data files it holds.
+    ///
+    /// # Returns
+    /// - `Ok(())` on success.
+    /// - `Err(StorageEngineError)` if the directory can not be created.
+    pub fn create_folders(&self) -> Result<(), StorageEngineError> {
+        fs::create_dir_all(self.get_keyspaces_path())
+            .map_err(|_| StorageEngineError::DirectoryCreationFailed)
+    }
+
+    /// Returns whether the keyspace directory holds no files, so the node has none of the data it
+    /// kept before it stopped (a missing directory counts as empty).
+    pub fn is_empty(&self) -> bool {
+        fn holds_files(path: &Path) -> bool {
+            fs::read_dir(path).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| !entry.path().is_dir() || holds_files(&entry.path()))
+            })
+        }
+        !holds_files(&self.get_keyspaces_path())
     }
 
     /// Resets the keyspace directories associated with the storage engine.
@@ -40,9 +99,7 @@ impl StorageEngine {
     /// - Deleted directories cannot be recovered.
 
     pub fn reset_folders(&self) -> Result<(), StorageEngineError> {
-        let ip_str = self.ip.replace(".", "_");
-        let keyspace_folder = format!("keyspaces_of_{}", ip_str);
-        let keyspace_path = self.root.join(&keyspace_folder);
+        let keyspace_path = self.get_keyspaces_path();
 
         // Check if the folder exists and delete it if it does
         if keyspace_path.exists() {
@@ -53,14 +110,115 @@ impl StorageEngine {
         // Create the folder
         fs::create_dir_all(&keyspace_path)
             .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+        self.clear_row_cache();
 
         Ok(())
     }
 
-    fn get_keyspace_path(&self, keyspace: &str) -> PathBuf {
+    /// Returns the folder of the hints kept by the node for the replicas it could not reach. Like
+    /// the commit log, it is kept when the keyspaces are reset on startup.
+    pub fn hints_path(&self) -> PathBuf {
+        self.root
+            .join(format!("hints_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Returns the file where the gossiper of the node saves the states of the nodes it knows. Like
+    /// the commit log, it is kept when the keyspaces are reset on startup.
+    pub fn gossip_state_path(&self) -> PathBuf {
+        self.root
+            .join(format!("gossip_state_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Returns the file where the node records the initial schema script it ran, so it is run only
+    /// once. Like the commit log, it is kept when the keyspaces are reset on startup.
+    pub fn schema_bootstrap_path(&self) -> PathBuf {
+        self.root
+            .join(format!("schema_bootstrap_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Returns the folder where the coordinator spills the answers of the reads that go over the merge
+    /// memory limit.
+    pub fn merge_spill_path(&self) -> PathBuf {
+        self.root
+            .join(format!("merge_spill_of_{}", self.ip.replace(".", "_")))
+    }
+
+    // Returns the folder where the keyspaces of the node are stored.
+    fn get_keyspaces_path(&self) -> PathBuf {
         let ip_str = self.ip.replace(".", "_");
-        let keyspace_folder = format!("keyspaces_of_{}", ip_str);
-        self.root.join(&keyspace_folder).join(keyspace)
+        self.root.join(format!("keyspaces_of_{}", ip_str))
+    }
+
+    fn get_keyspace_path(&self, keyspace: &str) -> PathBuf {
+        self.get_keyspaces_path().join(keyspace)
+    }
+
+    /// Returns the folder where the tables of `keyspace` are stored (or their replicas, if
+    /// `is_replication` is set), creating the folders of the keyspace if they do not exist yet.
+    fn get_folder_path(
+        &self,
+        keyspace: &str,
+        is_replication: bool,
+    ) -> Result<PathBuf, StorageEngineError> {
+        let keyspace_path = self.get_keyspace_path(keyspace);
+        let replication_path = keyspace_path.join("replication");
+
+        // `create_dir_all` does not fail if another thread creates the folder at the same time
+        fs::create_dir_all(&replication_path)
+            .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+
+        Ok(if is_replication {
+            replication_path
+        } else {
+            keyspace_path
+        })
+    }
+
+    /// Creates the data and index files of a table, both for the owned data and the replicas,
+    /// leaving untouched the ones that already exist.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if the table did not exist, `Ok(false)` if its data file already existed.
+    fn ensure_table_files(
+        &self,
+        keyspace: &str,
+        table: &str,
+        columns: &[&str],
+    ) -> Result<bool, StorageEngineError> {
+        let _guard = CREATION_LOCK
+            .lock()
+            .map_err(|_| StorageEngineError::IoError)?;
+
+        let keyspace_path = self.get_folder_path(keyspace, false)?;
+        let replication_path = self.get_folder_path(keyspace, true)?;
+        let header = columns.join(",");
+
+        let created =
+            Self::create_file_if_missing(&keyspace_path.join(format!("{}.csv", table)), &header)?;
+        Self::create_file_if_missing(&replication_path.join(format!("{}.csv", table)), &header)?;
+        Self::create_file_if_missing(
+            &keyspace_path.join(format!("{}_index.csv", table)),
+            "clustering_column,start_byte,end_byte",
+        )?;
+        Self::create_file_if_missing(
+            &replication_path.join(format!("{}_index.csv", table)),
+            "clustering_column,first_byte,last_byte",
+        )?;
+
+        Ok(created)
+    }
+
+    // Creates `path` with `header` as its first line, unless it already exists.
+    // Returns whether the file was created.
+    fn create_file_if_missing(path: &Path, header: &str) -> Result<bool, StorageEngineError> {
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+            Err(_) => return Err(StorageEngineError::FileWriteFailed),
+        };
+
+        writeln!(file, "{}", header).map_err(|_| StorageEngineError::FileWriteFailed)?;
+        Ok(true)
     }
 }
 
@@ -104,4 +262,23 @@ mod tests {
         // Limpiar después de la prueba
         fs::remove_dir_all(&keyspace_path).unwrap();
     }
+
+    #[test]
+    fn test_is_empty() {
+        let root = PathBuf::from(format!("/tmp/storage_empty_test_{}", uuid::Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        assert!(storage.is_empty());
+
+        // Empty keyspace folders hold no data
+        storage.create_folders().unwrap();
+        storage.get_folder_path("sky", true).unwrap();
+        assert!(storage.is_empty());
+
+        storage
+            .ensure_table_files("sky", "flights", &["id", "origin"])
+            .unwrap();
+        assert!(!storage.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,514 @@
+//! Cache of the rows read by the `SELECT`s of the partitions of a node, so the partitions read
+//! again and again (such as the flights the GUI looks up) are not read from disk every time.
+//!
+//! Entries are kept by partition: the keyspace, the table, whether the rows are the replicas of
+//! the node, and the values of the partition key. Each one holds the rows that every `WHERE`
+//! clause on the partition matched, with their stamps, before `ORDER BY` and `LIMIT` are applied.
+//! A write to a partition drops its entry, and writes that may touch any partition of a table,
+//! such as an `ALTER TABLE` or a redistribution, drop the entries of the whole table or node. Once
+//! the cache holds its capacity of partitions, the least recently read one is dropped.
+//!
+//! Expired rows are skipped when they are read from the cache, as they are when read from disk.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use query_creator::{
+    clauses::{
+        condition::Condition,
+        types::{column::Column, datatype::DataType},
+        where_cql::Where,
+    },
+    logical_operator::LogicalOperator,
+    operator::Operator,
+};
+
+use super::{errors::StorageEngineError, StorageEngine};
+
+/// Row caches of the nodes that use one, by the folder of their keyspaces. Every `StorageEngine`
+/// of a node looks its cache up here, so every write drops the entries it makes stale.
+static ROW_CACHES: Mutex<BTreeMap<PathBuf, Arc<RowCache>>> = Mutex::new(BTreeMap::new());
+
+/// A partition of a table: its keyspace, its table, whether it holds replicas and the values of
+/// its partition key, in the order of the columns of the table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PartitionKey {
+    keyspace: String,
+    table: String,
+    is_replication: bool,
+    values: Vec<String>,
+}
+
+impl PartitionKey {
+    /// Returns the partition a `WHERE` clause reads, if it gives every column of the partition key
+    /// with `=` and only joins conditions with `AND`.
+    pub fn of_where(
+        keyspace: &str,
+        table: &str,
+        is_replication: bool,
+        where_clause: &Where,
+        columns: &[Column],
+    ) -> Option<Self> {
+        let mut equalities = HashMap::new();
+        collect_equalities(&where_clause.condition, &mut equalities)?;
+
+        let values = columns
+            .iter()
+            .filter(|column| column.is_partition_key)
+            .map(|column| {
+                let value = equalities.get(column.name.as_str())?;
+                Some(canonical(value, &column.data_type))
+            })
+            .collect::<Option<Vec<String>>>()?;
+        Some(Self::new(keyspace, table, is_replication, values))
+    }
+
+    /// Returns the partition of a row, given as its values in the order of the columns of the table.
+    pub fn of_row(
+        keyspace: &str,
+        table: &str,
+        is_replication: bool,
+        values: &[&str],
+        columns: &[Column],
+    ) -> Self {
+        let values = columns
+            .iter()
+            .zip(values)
+            .filter(|(column, _)| column.is_partition_key)
+            .map(|(column, value)| canonical(value, &column.data_type))
+            .collect();
+        Self::new(keyspace, table, is_replication, values)
+    }
+
+    /// Returns the values of the partition key separated by commas, as the bloom filters of the
+    /// data files hold them.
+    pub(super) fn partition(&self) -> String {
+        self.values.join(",")
+    }
+
+    fn new(keyspace: &str, table: &str, is_replication: bool, values: Vec<String>) -> Self {
+        Self {
+            keyspace: keyspace.to_string(),
+            table: table.to_string(),
+            is_replication,
+            values,
+        }
+    }
+}
+
+// Collects the `column = value` conditions of a condition that only uses `AND`, failing if it
+// uses `OR` or `NOT`, or gives a column two values.
+fn collect_equalities<'a>(
+    condition: &'a Condition,
+    equalities: &mut HashMap<&'a str, &'a str>,
+) -> Option<()> {
+    match condition {
+        Condition::Simple {
+            field,
+            operator: Operator::Equal,
+            value,
+        } => match equalities.insert(field, value) {
+            Some(previous) if previous != value => None,
+            _ => Some(()),
+        },
+        Condition::Simple { .. } => Some(()),
+        Condition::Complex {
+            left: Some(left),
+            operator: LogicalOperator::And,
+            right,
+        } => {
+            collect_equalities(left, equalities)?;
+            collect_equalities(right, equalities)
+        }
+        Condition::Complex { .. } => None,
+    }
+}
+
+// Writes a value of a partition key the same way whatever way it was written in the query, as
+// `07` and `7` are the same `INT`.
+pub(super) fn canonical(value: &str, data_type: &DataType) -> String {
+    let value = value.trim();
+    let parsed = match data_type {
+        DataType::Int => value.parse::<i32>().map(|v| v.to_string()).ok(),
+        DataType::Float => value.parse::<f32>().map(|v| v.to_string()).ok(),
+        DataType::Double => value.parse::<f64>().map(|v| v.to_string()).ok(),
+        DataType::Boolean => value.parse::<bool>().map(|v| v.to_string()).ok(),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| value.to_string())
+}
+
+// The rows each `WHERE` clause on a partition matched, by the clause
+#[derive(Debug, Default)]
+struct CachedPartition {
+    reads: HashMap<String, Vec<String>>,
+    last_read: u64,
+}
+
+#[derive(Debug, Default)]
+struct RowCacheState {
+    partitions: HashMap<PartitionKey, CachedPartition>,
+    // The partitions by the time they were last read, to drop the least recently read one
+    by_last_read: BTreeMap<u64, PartitionKey>,
+    clock: u64,
+    // Incremented by every write, so a read that went to disk while a write was being applied does
+    // not keep what it read
+    version: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl RowCacheState {
+    fn touch(&mut self, key: &PartitionKey) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(partition) = self.partitions.get_mut(key) {
+            self.by_last_read.remove(&partition.last_read);
+            partition.last_read = clock;
+            self.by_last_read.insert(clock, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &PartitionKey) {
+        if let Some(partition) = self.partitions.remove(key) {
+            self.by_last_read.remove(&partition.last_read);
+        }
+    }
+
+    fn retain(&mut self, keep: impl Fn(&PartitionKey) -> bool) {
+        self.version += 1;
+        self.partitions.retain(|key, _| keep(key));
+        self.by_last_read.retain(|_, key| keep(key));
+    }
+}
+
+/// The partitions read by a node, with the rows their reads matched, up to `capacity` partitions.
+#[derive(Debug)]
+pub struct RowCache {
+    capacity: usize,
+    state: Mutex<RowCacheState>,
+}
+
+impl RowCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(RowCacheState::default()),
+        }
+    }
+
+    /// Returns the rows a read with `where_clause` matched in the partition, if they are cached,
+    /// making it the most recently read partition.
+    pub fn get(&self, key: &PartitionKey, where_clause: &str) -> Option<Vec<String>> {
+        let mut state = self.state.lock().ok()?;
+        let rows = state
+            .partitions
+            .get(key)
+            .and_then(|partition| partition.reads.get(where_clause))
+            .cloned();
+        match rows {
+            Some(_) => {
+                state.hits += 1;
+                state.touch(key);
+            }
+            None => state.misses += 1,
+        }
+        rows
+    }
+
+    /// Returns the version of the cache, to be given to `put` with the rows read from disk after it.
+    pub fn version(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.version)
+    }
+
+    /// Keeps the rows a read with `where_clause` matched in the partition, unless a write was
+    /// applied since `version` was taken, dropping the least recently read partition if the cache
+    /// is full.
+    pub fn put(&self, key: PartitionKey, where_clause: &str, rows: Vec<String>, version: u64) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.version != version || self.capacity == 0 {
+            return;
+        }
+        if !state.partitions.contains_key(&key) && state.partitions.len() >= self.capacity {
+            if let Some((_, oldest)) = state.by_last_read.pop_first() {
+                state.partitions.remove(&oldest);
+            }
+        }
+        state
+            .partitions
+            .entry(key.clone())
+            .or_default()
+            .reads
+            .insert(where_clause.to_string(), rows);
+        state.touch(&key);
+    }
+
+    /// Drops the rows of a partition, which was just written.
+    pub fn invalidate(&self, key: &PartitionKey) {
+        if let Ok(mut state) = self.state.lock() {
+            state.version += 1;
+            state.remove(key);
+        }
+    }
+
+    /// Drops the rows of every partition of a table, or of every table of the keyspace if `table`
+    /// is `None`.
+    pub fn invalidate_table(&self, keyspace: &str, table: Option<&str>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.retain(|key| {
+                key.keyspace != keyspace || table.is_some_and(|table| key.table != table)
+            });
+        }
+    }
+
+    /// Drops every cached row.
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.retain(|_| false);
+        }
+    }
+
+    /// Returns a line with the partitions cached, out of the capacity, and the hits and misses of
+    /// the reads.
+    pub fn report(&self) -> String {
+        let (partitions, hits, misses) = self.state.lock().map_or((0, 0, 0), |state| {
+            (state.partitions.len(), state.hits, state.misses)
+        });
+        format!(
+            "row_cache partitions={} capacity={} hits={} misses={}",
+            partitions, self.capacity, hits, misses
+        )
+    }
+}
+
+impl StorageEngine {
+    /// Makes the node of this storage engine cache the rows read from up to `capacity` partitions,
+    /// or stop caching them if it is 0. Every storage engine created later for the same node uses
+    /// the same cache.
+    pub fn set_row_cache(&self, capacity: usize) -> Result<(), StorageEngineError> {
+        let mut caches = ROW_CACHES.lock().map_err(|_| StorageEngineError::IoError)?;
+        if capacity == 0 {
+            caches.remove(&self.get_keyspaces_path());
+        } else {
+            caches.insert(self.get_keyspaces_path(), Arc::new(RowCache::new(capacity)));
+        }
+        Ok(())
+    }
+
+    /// Returns the row cache of the node, if it caches rows.
+    pub(crate) fn row_cache(&self) -> Option<Arc<RowCache>> {
+        ROW_CACHES
+            .lock()
+            .ok()?
+            .get(&self.get_keyspaces_path())
+            .cloned()
+    }
+
+    /// Returns the line of the row cache of the node for the `METRICS` admin command, if it caches rows.
+    pub fn row_cache_report(&self) -> Option<String> {
+        self.row_cache().map(|cache| cache.report())
+    }
+
+    /// Drops the cached rows of the partition written by a statement with `where_clause`, or of the
+    /// whole table if the clause does not give a single partition.
+    pub(super) fn invalidate_cached_where(
+        &self,
+        keyspace: &str,
+        table: &str,
+        is_replication: bool,
+        where_clause: Option<&Where>,
+        columns: &[Column],
+    ) {
+        let Some(cache) = self.row_cache() else {
+            return;
+        };
+        match where_clause.and_then(|where_clause| {
+            PartitionKey::of_where(keyspace, table, is_replication, where_clause, columns)
+        }) {
+            Some(key) => cache.invalidate(&key),
+            None => cache.invalidate_table(keyspace, Some(table)),
+        }
+    }
+
+    /// Drops the cached rows of the partition of a row just written.
+    pub(super) fn invalidate_cached_row(
+        &self,
+        keyspace: &str,
+        table: &str,
+        is_replication: bool,
+        values: &[&str],
+        columns: &[Column],
+    ) {
+        if let Some(cache) = self.row_cache() {
+            cache.invalidate(&PartitionKey::of_row(
+                keyspace,
+                table,
+                is_replication,
+                values,
+                columns,
+            ));
+        }
+    }
+
+    /// Drops the cached rows of a table, or of a whole keyspace if `table` is `None`.
+    pub(super) fn invalidate_cached_table(&self, keyspace: &str, table: Option<&str>) {
+        if let Some(cache) = self.row_cache() {
+            cache.invalidate_table(keyspace, table);
+        }
+    }
+
+    /// Drops every cached row of the node, after a write that may touch any of its partitions.
+    pub(super) fn clear_row_cache(&self) {
+        if let Some(cache) = self.row_cache() {
+            cache.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gossip::structures::application_state::TableSchema;
+    use query_creator::clauses::{
+        select_cql::Select, table::create_table_cql::CreateTable, update_cql::Update,
+    };
+    use std::fs;
+    use uuid::Uuid;
+
+    fn key(airport: &str) -> PartitionKey {
+        PartitionKey::new("sky", "flights", false, vec![airport.to_string()])
+    }
+
+    #[test]
+    fn test_row_cache_drops_the_least_recently_read_partition() {
+        let cache = RowCache::new(2);
+        for airport in ["EZE", "AEP"] {
+            cache.put(key(airport), "w", vec![format!("{},1;1", airport)], 0);
+        }
+        assert!(cache.get(&key("EZE"), "w").is_some());
+
+        cache.put(key("COR"), "w", vec![], 0);
+        assert!(cache.get(&key("AEP"), "w").is_none());
+        assert!(cache.get(&key("EZE"), "w").is_some());
+        assert!(cache.get(&key("COR"), "w").is_some());
+        assert_eq!(
+            cache.report(),
+            "row_cache partitions=2 capacity=2 hits=3 misses=1"
+        );
+
+        // A read that went to disk before a write does not keep what it read
+        let version = cache.version();
+        cache.invalidate(&key("EZE"));
+        cache.put(key("EZE"), "w", vec!["EZE,1;1".to_string()], version);
+        assert!(cache.get(&key("EZE"), "w").is_none());
+
+        cache.invalidate_table("sky", Some("flights"));
+        assert!(cache.get(&key("COR"), "w").is_none());
+    }
+
+    #[test]
+    fn test_partition_key_of_where() {
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY ((airport, number)))",
+            )
+            .unwrap(),
+        );
+        let columns = table.get_columns();
+        let of_select = |query: &str| {
+            let select = Select::deserialize(query).unwrap();
+            PartitionKey::of_where(
+                "sky",
+                "flights",
+                false,
+                select.where_clause.as_ref().unwrap(),
+                &columns,
+            )
+        };
+
+        let row = PartitionKey::of_row("sky", "flights", false, &["EZE", "7", "landed"], &columns);
+        assert_eq!(
+            of_select("SELECT status FROM sky.flights WHERE number = 07 AND airport = 'EZE'"),
+            Some(row)
+        );
+        assert_eq!(
+            of_select("SELECT status FROM sky.flights WHERE airport = 'EZE'"),
+            None
+        );
+        assert_eq!(
+            of_select("SELECT status FROM sky.flights WHERE airport = 'EZE' OR number = 7"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cached_reads_see_the_writes() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.2".to_string());
+        storage.set_row_cache(10).unwrap();
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, \
+                PRIMARY KEY (airport, number))",
+            )
+            .unwrap(),
+        );
+        let columns = table.get_columns();
+        let insert = |values: Vec<&str>, timestamp| {
+            storage
+                .insert(
+                    "sky",
+                    "flights",
+                    values,
+                    columns.clone(),
+                    table.get_clustering_column_in_order(),
+                    false,
+                    false,
+                    timestamp,
+                )
+                .unwrap()
+        };
+        let select = || {
+            storage
+                .select(
+                    Select::deserialize(
+                        "SELECT airport, number, status FROM sky.flights WHERE airport = 'EZE'",
+                    )
+                    .unwrap(),
+                    table.clone(),
+                    false,
+                    "sky",
+                )
+                .unwrap()
+        };
+
+        insert(vec!["EZE", "1", "landed"], 1);
+        assert_eq!(select().len(), 3);
+        insert(vec!["EZE", "2", "boarding"], 2);
+        assert_eq!(select().len(), 4);
+        assert_eq!(select().len(), 4);
+
+        let update = Update::deserialize(
+            "UPDATE sky.flights SET status = 'delayed' WHERE airport = 'EZE' AND number = 2",
+        )
+        .unwrap();
+        storage
+            .update(update, table.clone(), false, "sky", 3)
+            .unwrap();
+        assert!(select().contains(&"EZE,2,delayed;3".to_string()));
+        assert_eq!(
+            storage.row_cache_report().unwrap(),
+            "row_cache partitions=1 capacity=10 hits=1 misses=3"
+        );
+
+        storage.set_row_cache(0).unwrap();
+        assert!(storage.row_cache_report().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
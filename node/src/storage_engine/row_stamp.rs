@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr};
+
+use chrono::Utc;
+
+use super::errors::StorageEngineError;
+
+/// What a node stores after the values of every row: `<timestamp>` or, if the row expires,
+/// `<timestamp>;<expires_at>`, both in seconds.
+///
+/// The expiration is decided when the row is written, from the `USING TTL` of the write or the
+/// `default_time_to_live` of the table, so changing the default later does not affect the rows
+/// already written. Expired rows are skipped by the reads and dropped by the compactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowStamp {
+    pub timestamp: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl RowStamp {
+    /// Returns the stamp of a row written at `timestamp` with a TTL of `ttl` seconds, as given by
+    /// `TableOptions::ttl_for`. A TTL of 0 means the row never expires.
+    pub fn new(timestamp: i64, ttl: Option<u64>) -> Self {
+        Self {
+            timestamp,
+            expires_at: ttl
+                .filter(|&ttl| ttl > 0)
+                .map(|ttl| timestamp.saturating_add(ttl as i64)),
+        }
+    }
+
+    /// Whether the row expired at `now`.
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns the TTL the row was written with, to write it somewhere else with the same
+    /// timestamp and expiration.
+    pub fn ttl(&self) -> Option<u64> {
+        self.expires_at
+            .map(|expires_at| expires_at.saturating_sub(self.timestamp).max(1) as u64)
+    }
+
+    /// Returns the seconds the row has left at `now`, to write it somewhere else with the same
+    /// expiration. It is at least 1, as a TTL of 0 would make it never expire.
+    pub fn remaining_ttl(&self, now: i64) -> Option<u64> {
+        self.expires_at
+            .map(|expires_at| expires_at.saturating_sub(now).max(1) as u64)
+    }
+
+    /// The current time, in seconds, as the timestamps of the rows.
+    pub fn now() -> i64 {
+        Utc::now().timestamp()
+    }
+}
+
+impl From<i64> for RowStamp {
+    /// The stamp of a row that never expires.
+    fn from(timestamp: i64) -> Self {
+        Self {
+            timestamp,
+            expires_at: None,
+        }
+    }
+}
+
+impl fmt::Display for RowStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.expires_at {
+            Some(expires_at) => write!(f, "{};{}", self.timestamp, expires_at),
+            None => write!(f, "{}", self.timestamp),
+        }
+    }
+}
+
+impl FromStr for RowStamp {
+    type Err = StorageEngineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<i64>()
+                .map_err(|_| StorageEngineError::IoError)
+        };
+        match s.split_once(';') {
+            Some((timestamp, expires_at)) => Ok(Self {
+                timestamp: parse(timestamp)?,
+                expires_at: Some(parse(expires_at)?),
+            }),
+            None => Ok(Self::from(parse(s)?)),
+        }
+    }
+}
+
+/// Splits a stored row, `<values>;<stamp>`, into its values and its stamp.
+pub fn split_row(line: &str) -> Result<(&str, RowStamp), StorageEngineError> {
+    let (values, stamp) = line.split_once(';').ok_or(StorageEngineError::IoError)?;
+    Ok((values, stamp.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_stamp_expiration() {
+        let stamp = RowStamp::new(100, Some(60));
+        assert_eq!(stamp.to_string(), "100;160");
+        assert!(!stamp.is_expired(159));
+        assert!(stamp.is_expired(160));
+        assert_eq!(stamp.ttl(), Some(60));
+        assert_eq!(stamp.remaining_ttl(130), Some(30));
+        assert_eq!(stamp.remaining_ttl(200), Some(1));
+
+        // Without a TTL, or with a TTL of 0, rows never expire
+        for stamp in [RowStamp::new(100, None), RowStamp::new(100, Some(0))] {
+            assert_eq!(stamp.to_string(), "100");
+            assert!(!stamp.is_expired(i64::MAX));
+            assert_eq!(stamp.ttl(), None);
+            assert_eq!(stamp.remaining_ttl(200), None);
+        }
+
+        assert_eq!(
+            split_row("AR1,EZE;100;160").unwrap(),
+            ("AR1,EZE", RowStamp::new(100, Some(60)))
+        );
+        assert_eq!(split_row("AR1,EZE;100").unwrap(), ("AR1,EZE", 100.into()));
+        assert!(split_row("AR1,EZE").is_err());
+        assert!(split_row("AR1,EZE;soon").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use driver::sampling::TableSample;
+use gossip::structures::application_state::TableSchema;
+
+use super::{errors::StorageEngineError, StorageEngine};
+
+impl StorageEngine {
+    /// Reads a random subset of the partitions of a table owned by the node, for approximate
+    /// statistics of the table. Replicas are left out, so the samples of every node of the ring
+    /// add up to a sample of the whole table.
+    ///
+    /// Each partition is picked with probability `fraction`, from a hash of its key and `seed`,
+    /// so all the rows of a partition are either read or skipped together.
+    ///
+    /// # Arguments
+    /// - `keyspace`: The keyspace of the table.
+    /// - `table`: The schema of the table.
+    /// - `fraction`: The fraction of the partitions to read, between 0 and 1.
+    /// - `seed`: Picks the partitions to read. Samples taken with the same seed read the same
+    ///   partitions.
+    ///
+    /// # Returns
+    /// - `Ok(TableSample)` with the partitions, rows and bytes read.
+    /// - `Err(StorageEngineError)` if the files of the table can not be created or read.
+    pub fn sample_partitions(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        fraction: f64,
+        seed: u64,
+    ) -> Result<TableSample, StorageEngineError> {
+        let columns = table.get_columns();
+        let partition_key: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_partition_key)
+            .map(|(i, _)| i)
+            .collect();
+        // Picked partitions hash below the threshold
+        let threshold = (fraction.clamp(0.0, 1.0) * u64::MAX as f64) as u64;
+
+        let mut sample = TableSample {
+            fraction,
+            ..TableSample::default()
+        };
+        let mut partitions = HashSet::new();
+
+        self.for_each_stored_row(keyspace, table, false, |line| {
+            let (values, _) = line.split_once(';').ok_or(StorageEngineError::IoError)?;
+            let values: Vec<&str> = values.split(',').collect();
+            let key: Vec<&str> = partition_key
+                .iter()
+                .map(|&i| values.get(i).copied().unwrap_or_default())
+                .collect();
+
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            if fraction < 1.0 && hasher.finish() >= threshold {
+                return Ok(());
+            }
+
+            if partitions.insert(key.join(",")) {
+                sample.partitions += 1;
+            }
+            sample.rows += 1;
+            sample.bytes += line.len() as u64 + 1;
+            Ok(())
+        })?;
+
+        Ok(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use std::{fs, io::Write, path::PathBuf};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_sample_partitions_reads_whole_partitions() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (airport TEXT, number TEXT, status TEXT, \
+                PRIMARY KEY (airport, number))",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            storage.sample_partitions("sky", &table, 0.5, 7).unwrap(),
+            TableSample {
+                fraction: 0.5,
+                ..TableSample::default()
+            }
+        );
+
+        let data_path = storage
+            .get_folder_path("sky", false)
+            .unwrap()
+            .join("flights.csv");
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&data_path)
+            .unwrap();
+        // 100 airports with two flights each
+        for airport in 0..100 {
+            writeln!(file, "A{},AR{},landed;10", airport, airport).unwrap();
+            writeln!(file, "A{},AR{},boarding;10", airport, airport + 100).unwrap();
+        }
+
+        let all = storage.sample_partitions("sky", &table, 1.0, 7).unwrap();
+        assert_eq!((all.partitions, all.rows), (100, 200));
+        assert_eq!(all.estimated_rows(), 200);
+
+        let none = storage.sample_partitions("sky", &table, 0.0, 7).unwrap();
+        assert_eq!((none.partitions, none.rows), (0, 0));
+
+        let half = storage.sample_partitions("sky", &table, 0.5, 7).unwrap();
+        assert!(half.partitions > 20 && half.partitions < 80);
+        assert_eq!(half.rows, 2 * half.partitions);
+        assert_eq!(half.mean_partition_rows(), 2.0);
+        // The same seed reads the same partitions
+        assert_eq!(
+            storage.sample_partitions("sky", &table, 0.5, 7).unwrap(),
+            half
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
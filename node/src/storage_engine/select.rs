@@ -1,12 +1,72 @@
 use std::{
-    fs::{self, OpenOptions},
+    collections::HashMap,
+    fs::OpenOptions,
     io::{BufRead, BufReader, Seek},
 };
 
 use gossip::structures::application_state::TableSchema;
-use query_creator::clauses::select_cql::Select;
+use partitioner::Partitioner;
+use query_creator::clauses::{
+    condition::{token_columns, Condition},
+    select_cql::Select,
+    types::column::Column,
+};
+
+use super::{
+    bloom_filter::{may_hold_partition, PartitionColumns},
+    errors::StorageEngineError,
+    row_cache::PartitionKey,
+    row_stamp::{split_row, RowStamp},
+    StorageEngine,
+};
 
-use super::{errors::StorageEngineError, StorageEngine};
+/// The `PER PARTITION LIMIT` and the `LIMIT` of a `SELECT`, applied to its rows in the order they
+/// are returned.
+pub struct RowLimits {
+    partition_columns: PartitionColumns,
+    per_partition_limit: Option<usize>,
+    limit: Option<usize>,
+    rows_by_partition: HashMap<String, usize>,
+    rows: usize,
+}
+
+impl RowLimits {
+    /// Returns the limits of a query on a table with the given columns.
+    pub fn of(select_query: &Select, columns: &[Column]) -> Self {
+        Self {
+            partition_columns: PartitionColumns::of_rows(columns),
+            per_partition_limit: select_query.per_partition_limit,
+            limit: select_query.limit,
+            rows_by_partition: HashMap::new(),
+            rows: 0,
+        }
+    }
+
+    /// Returns whether the next row is returned, counting it if it is. Rows are given as their
+    /// values separated by commas, in the order of the columns of the table, with or without their
+    /// stamp.
+    pub fn admits(&mut self, row: &str) -> bool {
+        if self.limit.is_some_and(|limit| self.rows >= limit) {
+            return false;
+        }
+        if let Some(per_partition_limit) = self.per_partition_limit {
+            let values = row.split(';').next().unwrap_or_default();
+            let partition = self.partition_columns.partition(values);
+            let rows = self.rows_by_partition.entry(partition).or_default();
+            if *rows >= per_partition_limit {
+                return false;
+            }
+            *rows += 1;
+        }
+        self.rows += 1;
+        true
+    }
+
+    /// Returns the rows that are returned, in their order.
+    pub fn apply(&mut self, rows: Vec<String>) -> Vec<String> {
+        rows.into_iter().filter(|row| self.admits(row)).collect()
+    }
+}
 
 impl StorageEngine {
     /// Executes a `SELECT` query on a table stored as CSV files, returning rows that match the given conditions.
@@ -61,12 +121,13 @@ impl StorageEngine {
     ///    - Evaluates each row against the `WHERE` clause conditions using the `line_matches_where_clause` helper function.
     ///    - Adds rows matching the conditions to the result vector.
     ///
-    /// 6. **Apply `LIMIT`**:
-    ///    - Truncates the results to include only the specified number of rows if a `LIMIT` clause is present.
+    /// 6. **Apply `ORDER BY`**:
+    ///    - Rows are stored in the clustering order of the table, so they are already sorted.
+    ///    - Reverses them if the `ORDER BY` clause asks for the reverse of the clustering order.
     ///
-    /// 7. **Apply `ORDER BY`**:
-    ///    - Sorts the results based on a single column and order (ascending or descending) if specified in the `ORDER BY` clause.
-    ///    - Uses the `sort_results_single_column` helper function for sorting.
+    /// 7. **Apply `PER PARTITION LIMIT` and `LIMIT`**:
+    ///    - Keeps at most the specified number of rows of each partition, and then of the whole result, in the order
+    ///      of the rows, if the clauses are present.
     ///
     /// 8. **Return Results**:
     ///    - Returns the vector of rows as `Ok(Vec<String>)`.
@@ -78,17 +139,11 @@ impl StorageEngine {
     ///   Validates if a row matches the `WHERE` clause conditions. Converts the row into a key-value map of column names to values
     ///   and evaluates the conditions in the query.
     ///
-    /// - `sort_results_single_column`:
-    ///   Sorts the result rows based on a single column specified in the `ORDER BY` clause. Supports ascending (`ASC`) and descending (`DESC`) orders.
-    ///
     /// # Errors
     ///
     /// - **`StorageEngineError::DirectoryCreationFailed`**:
     ///   If the directory for the keyspace or replication files cannot be created.
     ///
-    /// - **`StorageEngineError::MissingWhereClause`**:
-    ///   If the `WHERE` clause is missing and is required for clustering column evaluation.
-    ///
     /// - **`StorageEngineError::IoError`**:
     ///   For general input/output issues during file reading or seeking.
     ///
@@ -105,24 +160,153 @@ impl StorageEngine {
         is_replication: bool,
         keyspace: &str,
     ) -> Result<Vec<String>, StorageEngineError> {
+        let mut results = Vec::new();
+        let complete_columns: Vec<String> =
+            table.get_columns().iter().map(|c| c.name.clone()).collect();
+        results.push(complete_columns.join(","));
+        results.push(select_query.columns.join(","));
+
+        self.for_each_matching_row(&select_query, &table, is_replication, keyspace, |row| {
+            results.push(row)
+        })?;
+
+        // Las filas se guardan en el orden de clustering de la tabla, un `ORDER BY` solo puede
+        // invertirlo
+        let reversed = select_query
+            .reverses_clustering_order(&table.get_clustering_key_columns())
+            .map_err(|_| StorageEngineError::InvalidQuery)?;
+        if reversed {
+            results[2..].reverse();
+        }
+
+        let rows = results.split_off(2);
+        results.extend(RowLimits::of(&select_query, &table.get_columns()).apply(rows));
+
+        Ok(results)
+    }
+
+    /// Counts the rows of a table that match the `WHERE` clause of a `SELECT COUNT(*)`, reading
+    /// them like [`select`](Self::select) does but without keeping them.
+    ///
+    /// Only the rows the `PER PARTITION LIMIT` and the `LIMIT` of the query return are counted.
+    pub fn count(
+        &self,
+        select_query: &Select,
+        table: &TableSchema,
+        is_replication: bool,
+        keyspace: &str,
+    ) -> Result<usize, StorageEngineError> {
+        let mut limits = RowLimits::of(select_query, &table.get_columns());
+        let mut count = 0;
+        self.for_each_matching_row(select_query, table, is_replication, keyspace, |row| {
+            if limits.admits(&row) {
+                count += 1
+            }
+        })?;
+
+        Ok(count)
+    }
+
+    /// Calls `on_row` with every row of the table (with its timestamp) that matches the `WHERE`
+    /// clause of the query. When the table has clustering columns, the index file is used to read
+    /// only the rows with the value of the first one given in the query.
+    fn for_each_matching_row<F: FnMut(String)>(
+        &self,
+        select_query: &Select,
+        table: &TableSchema,
+        is_replication: bool,
+        keyspace: &str,
+        mut on_row: F,
+    ) -> Result<(), StorageEngineError> {
         let table_name = table.get_name();
-        let base_folder_path = self.get_keyspace_path(keyspace);
 
-        // Construcción de la ruta de la carpeta según si es replicación o no
-        let folder_path = if is_replication {
-            base_folder_path.join("replication")
-        } else {
-            base_folder_path
+        // The table may not have been created yet in this node if its schema is still being gossiped
+        let columns = table.get_columns();
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table_name, &column_names)?;
+
+        // Reads of a single partition go through the row cache of the node, if it has one, and
+        // skip the data files that do not hold the partition
+        let partition = select_query.where_clause.as_ref().and_then(|where_clause| {
+            PartitionKey::of_where(
+                keyspace,
+                &table_name,
+                is_replication,
+                where_clause,
+                &columns,
+            )
+        });
+        let cached = self.row_cache().and_then(|cache| {
+            let where_clause = select_query.where_clause.as_ref()?.serialize();
+            Some((cache, partition.clone()?, where_clause))
+        });
+        let Some((cache, key, where_clause)) = cached else {
+            return self.read_matching_rows(
+                select_query,
+                table,
+                is_replication,
+                keyspace,
+                partition.map(|key| key.partition()).as_deref(),
+                on_row,
+            );
         };
 
-        // Crear la carpeta si no existe
-        if !folder_path.exists() {
-            fs::create_dir_all(&folder_path)
-                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+        if let Some(rows) = cache.get(&key, &where_clause) {
+            let now = RowStamp::now();
+            for row in rows {
+                if !split_row(&row)?.1.is_expired(now) {
+                    on_row(row);
+                }
+            }
+            return Ok(());
         }
 
+        let version = cache.version();
+        let mut rows = Vec::new();
+        self.read_matching_rows(
+            select_query,
+            table,
+            is_replication,
+            keyspace,
+            Some(&key.partition()),
+            |row| rows.push(row),
+        )?;
+        cache.put(key, &where_clause, rows.clone(), version);
+        rows.into_iter().for_each(on_row);
+        Ok(())
+    }
+
+    /// Calls `on_row` with every row of the table read from disk that matches the `WHERE` clause
+    /// of the query, as `for_each_matching_row` does without the row cache. The data files whose
+    /// bloom filters do not hold `partition`, the one the query reads if it reads a single one,
+    /// are not read.
+    fn read_matching_rows<F: FnMut(String)>(
+        &self,
+        select_query: &Select,
+        table: &TableSchema,
+        is_replication: bool,
+        keyspace: &str,
+        partition: Option<&str>,
+        mut on_row: F,
+    ) -> Result<(), StorageEngineError> {
+        let table_name = table.get_name();
+        let folder_path = self.get_folder_path(keyspace, is_replication)?;
+
         // Rutas para los archivos de datos e índices
         let file_path = folder_path.join(format!("{}.csv", table_name));
+        if let Some(store) = self.lsm_store() {
+            return self.lsm_for_each_matching_row(
+                &store,
+                &file_path,
+                select_query,
+                table,
+                partition,
+                on_row,
+            );
+        }
+        if partition.is_some_and(|partition| !may_hold_partition(&file_path, partition)) {
+            return Ok(());
+        }
         let index_file_path = folder_path.join(format!("{}_index.csv", table_name));
 
         let file = OpenOptions::new().read(true).open(&file_path)?;
@@ -134,13 +318,12 @@ impl StorageEngine {
         let mut start_byte = 0;
         let mut end_byte = u64::MAX;
 
-        // Obtener la primera columna de clustering y sus valores
+        // Obtener la primera columna de clustering y sus valores. Las consultas que recorren toda la
+        // tabla pueden no tener `WHERE`
         if let Some(first_clustering_column) = table.get_clustering_column_in_order().get(0) {
-            let clustering_value = select_query
-                .clone()
-                .where_clause
-                .ok_or(StorageEngineError::MissingWhereClause)?
-                .get_value_for_clustering_column(&first_clustering_column);
+            let clustering_value = select_query.where_clause.as_ref().and_then(|where_clause| {
+                where_clause.get_value_for_clustering_column(first_clustering_column)
+            });
 
             if let Some(clustering_column_value) = clustering_value {
                 for (i, line) in index_reader.lines().enumerate() {
@@ -168,14 +351,9 @@ impl StorageEngine {
             reader.read_line(&mut buffer)?; // Leer y descartar el header
         }
 
-        let mut results = Vec::new();
-        let complete_columns: Vec<String> =
-            table.get_columns().iter().map(|c| c.name.clone()).collect();
-        results.push(complete_columns.join(","));
-        results.push(select_query.columns.join(","));
-
         // Leer las líneas del rango especificado
         let mut current_byte_offset = start_byte;
+        let now = RowStamp::now();
 
         while current_byte_offset < end_byte {
             let mut buffer = String::new();
@@ -184,74 +362,19 @@ impl StorageEngine {
                 break; // Fin del archivo
             }
             current_byte_offset += bytes_read as u64;
-            let (line, _) = buffer
-                .trim_end()
-                .split_once(";")
-                .ok_or(StorageEngineError::IoError)?;
-            if self.line_matches_where_clause(&line, &table, &select_query)? {
-                results.push(buffer.trim_end().to_string());
+            let (line, stamp) = split_row(buffer.trim_end())?;
+            if stamp.is_expired(now) {
+                continue;
             }
-        }
-
-        // Aplicar `LIMIT` si está presente
-        if let Some(limit) = select_query.limit {
-            if limit < results.len() - 2 {
-                results = results[..limit + 2].to_vec();
+            if self.line_matches_where_clause(&line, table, select_query)? {
+                on_row(buffer.trim_end().to_string());
             }
         }
 
-        // Ordenar los resultados si hay cláusula `ORDER BY`
-        if let Some(order_by) = select_query.orderby_clause {
-            self.sort_results_single_column(&mut results, &order_by.columns[0], &order_by.order)?
-        }
-
-        Ok(results)
-    }
-
-    fn sort_results_single_column(
-        &self,
-        results: &mut Vec<String>,
-        order_by_column: &str,
-        order: &str, // Either "ASC" or "DESC"
-    ) -> Result<(), StorageEngineError> {
-        if results.len() <= 3 {
-            // No sorting needed if only headers or very few rows
-            return Ok(());
-        }
-
-        // Separate the two headers
-        let header1 = results[0].clone();
-        let header2 = results[1].clone();
-        let rows = &mut results[2..];
-
-        // Get the index of the column specified in order_by_column
-        let header_columns: Vec<&str> = header1.split(',').collect();
-        let col_index = header_columns
-            .iter()
-            .position(|&col| col == order_by_column);
-
-        if let Some(col_index) = col_index {
-            // Define sort closure based on order
-            rows.sort_by(|a, b| {
-                let a_val = a.split(',').nth(col_index).unwrap_or("");
-                let b_val = b.split(',').nth(col_index).unwrap_or("");
-                let cmp = a_val.cmp(b_val);
-
-                match order {
-                    "ASC" => cmp,
-                    "DESC" => cmp.reverse(),
-                    _ => std::cmp::Ordering::Equal, // Ignore invalid order specifiers
-                }
-            });
-        }
-
-        // Restore headers
-        results[0] = header1;
-        results[1] = header2;
         Ok(())
     }
 
-    fn line_matches_where_clause(
+    pub(super) fn line_matches_where_clause(
         &self,
         line: &str,
         table: &TableSchema,
@@ -260,11 +383,12 @@ impl StorageEngine {
         // Convert the line into a map of column to value
 
         let values: Vec<String> = line.split(',').map(|s| s.trim().to_string()).collect();
-        let column_value_map = self.create_column_value_map(table, &values, false);
+        let mut column_value_map = self.create_column_value_map(table, &values, false);
 
         let columns = table.get_columns();
         // Check the WHERE clause condition in the SELECT query
         if let Some(where_clause) = &select_query.where_clause {
+            insert_tokens(&mut column_value_map, &where_clause.condition)?;
             Ok(where_clause
                 .condition
                 .execute(&column_value_map, columns)
@@ -275,6 +399,27 @@ impl StorageEngine {
     }
 }
 
+// Adds to the values of a row the tokens its condition compares, the positions in the ring of its
+// partition. Partitions are placed by the values of their key joined, as the coordinators do.
+fn insert_tokens(
+    column_value_map: &mut HashMap<String, String>,
+    condition: &Condition,
+) -> Result<(), StorageEngineError> {
+    for field in condition.token_fields() {
+        let Some(columns) = token_columns(field) else {
+            continue;
+        };
+        let key: String = columns
+            .iter()
+            .map(|column| column_value_map.get(*column).cloned().unwrap_or_default())
+            .collect();
+        let token =
+            Partitioner::token_of(key).map_err(|_| StorageEngineError::UnsupportedOperation)?;
+        column_value_map.insert(field.to_string(), token.to_string());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -604,4 +749,185 @@ mod tests {
             fs::remove_dir_all(&root).unwrap();
         }
     }
+
+    #[test]
+    fn test_count_with_where_and_limit() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+
+        let keyspace = "test_keyspace";
+        let table_name = "test_table";
+        let columns = vec![
+            Column::new("id", DataType::Int, true, false),
+            Column::new("name", DataType::String, false, false),
+        ];
+        let clustering_columns_in_order = vec!["name".to_string()];
+
+        let folder_path = storage.get_keyspace_path(keyspace);
+        fs::create_dir_all(folder_path.clone()).unwrap();
+        let mut file = File::create(folder_path.join(format!("{}.csv", table_name))).unwrap();
+        writeln!(file, "id,name").unwrap();
+
+        for values in [vec!["1", "John"], vec!["1", "Jaz"], vec!["2", "Jol"]] {
+            storage
+                .insert(
+                    keyspace,
+                    table_name,
+                    values,
+                    columns.clone(),
+                    clustering_columns_in_order.clone(),
+                    false,
+                    false,
+                    1234567890,
+                )
+                .unwrap();
+        }
+
+        let create_table = CreateTable::new_from_tokens(vec![
+            "CREATE".to_string(),
+            "TABLE".to_string(),
+            "test_keyspace.test_table".to_string(),
+            "id INT, name TEXT, PRIMARY KEY (id, name)".to_string(),
+        ])
+        .unwrap();
+        let table = TableSchema::new(create_table);
+
+        let count_query = |query: &str| Select::deserialize(query).unwrap();
+        let query = "SELECT COUNT(*) FROM test_keyspace.test_table WHERE id = 1";
+
+        let count = storage.count(&count_query(query), &table, false, keyspace);
+        assert_eq!(count.unwrap(), 2);
+
+        let count = storage.count(
+            &count_query(&format!("{} LIMIT 1", query)),
+            &table,
+            false,
+            keyspace,
+        );
+        assert_eq!(count.unwrap(), 1);
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_row_limits_count_the_rows_of_each_partition() {
+        let mut flight = Column::new("flight", DataType::String, true, false);
+        flight.is_partition_key = true;
+        let mut time = Column::new("time", DataType::Int, true, false);
+        time.is_clustering_column = true;
+        let columns = [flight, time];
+        let rows: Vec<String> = [
+            "AR1,1;5", "AR2,1;5", "AR1,2;5", "AR1,3;5", "AR2,2;5", "AR3,1",
+        ]
+        .iter()
+        .map(|row| row.to_string())
+        .collect();
+
+        let limits = |query: &str| RowLimits::of(&Select::deserialize(query).unwrap(), &columns);
+        assert_eq!(
+            limits("SELECT * FROM positions PER PARTITION LIMIT 1").apply(rows.clone()),
+            ["AR1,1;5", "AR2,1;5", "AR3,1"]
+        );
+        assert_eq!(
+            limits("SELECT * FROM positions PER PARTITION LIMIT 2 LIMIT 4").apply(rows.clone()),
+            ["AR1,1;5", "AR2,1;5", "AR1,2;5", "AR2,2;5"]
+        );
+        assert_eq!(limits("SELECT * FROM positions").apply(rows.clone()), rows);
+    }
+
+    #[test]
+    fn test_select_honors_clustering_order() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let keyspace = "test_keyspace";
+
+        let create_table = CreateTable::deserialize(
+            "CREATE TABLE test_keyspace.positions (flight TEXT, time INT, lat INT, \
+            PRIMARY KEY (flight, time)) WITH CLUSTERING ORDER BY (time DESC)",
+        )
+        .unwrap();
+        let table = TableSchema::new(create_table);
+
+        fs::create_dir_all(storage.get_keyspace_path(keyspace)).unwrap();
+        for values in [
+            vec!["AR1", "9", "10"],
+            vec!["AR1", "20", "11"],
+            vec!["AR1", "100", "12"],
+        ] {
+            storage
+                .insert(
+                    keyspace,
+                    "positions",
+                    values,
+                    table.get_columns(),
+                    table.get_clustering_column_in_order(),
+                    false,
+                    false,
+                    1234567890,
+                )
+                .unwrap();
+        }
+
+        let select = |query: &str| {
+            let rows = storage
+                .select(
+                    Select::deserialize(query).unwrap(),
+                    table.clone(),
+                    false,
+                    keyspace,
+                )
+                .unwrap();
+            rows[2..]
+                .iter()
+                .map(|row| row.split(',').nth(1).unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+
+        let query = "SELECT time FROM test_keyspace.positions WHERE flight = 'AR1'";
+        assert_eq!(select(query), ["100", "20", "9"]);
+        assert_eq!(
+            select(&format!("{} ORDER BY time DESC", query)),
+            ["100", "20", "9"]
+        );
+        assert_eq!(
+            select(&format!("{} ORDER BY time ASC LIMIT 2", query)),
+            ["9", "20"]
+        );
+        assert_eq!(
+            select(&format!(
+                "{} ORDER BY time ASC PER PARTITION LIMIT 2",
+                query
+            )),
+            ["9", "20"]
+        );
+        // Scans read the whole table, filtering by any column
+        assert_eq!(
+            select("SELECT time FROM test_keyspace.positions ALLOW FILTERING"),
+            ["100", "20", "9"]
+        );
+        assert_eq!(
+            select("SELECT time FROM test_keyspace.positions WHERE lat > 10 ALLOW FILTERING"),
+            ["100", "20"]
+        );
+        assert_eq!(
+            select("SELECT time FROM test_keyspace.positions WHERE lat <= 11 ALLOW FILTERING"),
+            ["20", "9"]
+        );
+        // Token ranges read the partitions placed in them
+        let token = Partitioner::token_of("AR1").unwrap();
+        let tokens = |low: u64, high: u64| {
+            select(&format!(
+                "SELECT time FROM test_keyspace.positions WHERE token(flight) >= {} AND token(flight) < {}",
+                low, high
+            ))
+        };
+        assert_eq!(tokens(token, token + 1), ["100", "20", "9"]);
+        assert!(tokens(token + 1, token + 100).is_empty());
+
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
 }
@@ -0,0 +1,476 @@
+//! Snapshots of the keyspaces of a node, to back up their data and restore it when the node starts.
+//!
+//! The snapshot `<tag>` of a keyspace is kept in `snapshots_of_<ip>/<tag>/<keyspace>`, with the
+//! same layout as the folder of the keyspace (its owned tables and its `replication` folder), and a
+//! `manifest.csv` listing every file of the snapshot with its size and CRC32. The manifest is
+//! written last, so a snapshot without one was never finished.
+//!
+//! SSTables, bloom filters and partition indexes are hard-linked, as they are only ever replaced by
+//! renaming a new file over them, so the snapshot takes no space until they are compacted away. The
+//! data and index files are copied. They are also replaced by renaming a new file over them, never
+//! written in place, so each copy holds a whole version of its file.
+//!
+//! The manifest also records the first segment of the commit log whose mutations may not be in the
+//! snapshot. A restore brings back the snapshot and then replays, for its keyspaces, only the
+//! mutations logged from that segment on, the ones made after the snapshot was taken that are still
+//! in the commit log. Snapshots whose manifest has no segment get the whole commit log replayed.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use native_protocol::checksum::crc32_update;
+
+use super::{errors::StorageEngineError, StorageEngine};
+
+const MANIFEST_FILE: &str = "manifest.csv";
+const MANIFEST_HEADER: &str = "file,size,crc32";
+const REPLAY_FROM_FIELD: &str = "replay_from";
+
+/// The keyspaces brought back by `restore_snapshot`.
+///
+/// ### Fields
+/// - `files`: The amount of files restored.
+/// - `replay_from`: The first segment of the commit log to replay for each restored keyspace whose
+///   manifest records one. The mutations of the keyspaces without one are all replayed.
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoredSnapshot {
+    pub files: usize,
+    pub replay_from: HashMap<String, u64>,
+}
+
+/// A file of a snapshot, as listed by its manifest.
+///
+/// ### Fields
+/// - `file`: The path of the file, relative to the folder of the keyspace.
+/// - `size`: The size of the file in bytes.
+/// - `crc32`: The CRC32 of the contents of the file.
+#[derive(Debug, PartialEq, Clone)]
+struct ManifestEntry {
+    file: PathBuf,
+    size: u64,
+    crc32: u32,
+}
+
+impl StorageEngine {
+    /// Returns the folder of the snapshots of the node. Like the commit log, it is kept when the
+    /// keyspaces are reset on startup.
+    pub fn snapshots_path(&self) -> PathBuf {
+        self.root
+            .join(format!("snapshots_of_{}", self.ip.replace(".", "_")))
+    }
+
+    /// Takes a snapshot of the tables of a keyspace stored by the node, owned and replicas, as
+    /// Cassandra's `nodetool snapshot`.
+    ///
+    /// # Arguments
+    /// - `keyspace`: The keyspace to take the snapshot of.
+    /// - `tag`: The name of the snapshot, made of letters, digits, `_` and `-`.
+    /// - `replay_from`: The first segment of the commit log whose mutations may not be in the files
+    ///   of the keyspace, as returned by `CommitLog::flush_point` right before the snapshot. It is
+    ///   recorded in the manifest, so a restore only replays the mutations logged after it.
+    ///
+    /// # Returns
+    /// - `Ok(usize)` with the amount of files in the snapshot.
+    /// - `Err(StorageEngineError::InvalidQuery)` if the tag is not a valid name.
+    /// - `Err(StorageEngineError::FileNotFound)` if the node does not store the keyspace.
+    /// - `Err(StorageEngineError::AlreadyExists)` if the keyspace already has a snapshot with the tag.
+    /// - `Err(StorageEngineError)` if the files can not be linked, copied or read.
+    ///
+    /// # Notes
+    /// - The memtables are flushed first, so the rows only kept in memory are in the snapshot.
+    /// - The keyspace keeps taking writes while the snapshot is taken, which may or may not be in it.
+    pub fn snapshot(
+        &self,
+        keyspace: &str,
+        tag: &str,
+        replay_from: u64,
+    ) -> Result<usize, StorageEngineError> {
+        if !is_valid_tag(tag) {
+            return Err(StorageEngineError::InvalidQuery);
+        }
+        let keyspace_path = self.get_keyspace_path(keyspace);
+        if !keyspace_path.is_dir() {
+            return Err(StorageEngineError::FileNotFound);
+        }
+        let snapshot_path = self.snapshots_path().join(tag).join(keyspace);
+        if snapshot_path.exists() {
+            return Err(StorageEngineError::AlreadyExists);
+        }
+        self.flush_memtables()?;
+
+        // The SSTables are linked before the data files are copied: a compaction in between moves
+        // the rows of the SSTables it removes to the data files, so they are still in the snapshot
+        let (immutable, mutable): (Vec<_>, Vec<_>) = stored_files(&keyspace_path)?
+            .into_iter()
+            .partition(|file| is_immutable(file));
+        let mut manifest = Vec::new();
+        for file in immutable {
+            let target = snapshot_path.join(&file);
+            fs::create_dir_all(target.parent().ok_or(StorageEngineError::IoError)?)
+                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+            match fs::hard_link(keyspace_path.join(&file), &target) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                // Other file systems than the one of the keyspace can not hold links to its files
+                Err(_) => copy_file(&keyspace_path.join(&file), &target)?,
+            }
+            manifest.push(ManifestEntry::of(&snapshot_path, file)?);
+        }
+        for file in mutable {
+            let target = snapshot_path.join(&file);
+            fs::create_dir_all(target.parent().ok_or(StorageEngineError::IoError)?)
+                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+            copy_file(&keyspace_path.join(&file), &target)?;
+            manifest.push(ManifestEntry::of(&snapshot_path, file)?);
+        }
+
+        write_manifest(&snapshot_path, replay_from, &manifest)?;
+        Ok(manifest.len())
+    }
+
+    /// Brings back the data of every keyspace in the snapshot `tag` of the node, replacing the
+    /// files of their tables. It is run on startup, after the keyspaces are reset and before the
+    /// commit log is replayed.
+    ///
+    /// # Returns
+    /// - `Ok(RestoredSnapshot)` with the amount of files restored and the segment of the commit log
+    ///   to replay each keyspace from.
+    /// - `Err(StorageEngineError::FileNotFound)` if the node has no snapshot with the tag, or one of
+    ///   its keyspaces has no manifest.
+    /// - `Err(StorageEngineError::SnapshotCorrupted)` if a file of the snapshot is missing or does
+    ///   not match its manifest.
+    ///
+    /// # Notes
+    /// - Every file is checked against the manifest before anything is restored, so a corrupted
+    ///   snapshot leaves the keyspaces as they were.
+    /// - The tables are seen once the schema of their keyspace is known by the node, as their files
+    ///   are kept when it creates them.
+    /// - The mutations of the keyspaces logged before the snapshot must not be replayed, as they
+    ///   would bring back rows deleted or overwritten before it was taken
+    ///   (see `CommitLog::read_entries_from`).
+    pub fn restore_snapshot(&self, tag: &str) -> Result<RestoredSnapshot, StorageEngineError> {
+        if !is_valid_tag(tag) {
+            return Err(StorageEngineError::InvalidQuery);
+        }
+        let tag_path = self.snapshots_path().join(tag);
+        let entries = fs::read_dir(&tag_path).map_err(|_| StorageEngineError::FileNotFound)?;
+
+        let mut keyspaces = Vec::new();
+        for entry in entries {
+            let snapshot_path = entry?.path();
+            if !snapshot_path.is_dir() {
+                continue;
+            }
+            let (replay_from, manifest) = read_manifest(&snapshot_path)?;
+            for entry in &manifest {
+                entry.verify(&snapshot_path)?;
+            }
+            keyspaces.push((snapshot_path, replay_from, manifest));
+        }
+
+        let mut restored = RestoredSnapshot::default();
+        for (snapshot_path, replay_from, manifest) in keyspaces {
+            let keyspace = snapshot_path
+                .file_name()
+                .and_then(|keyspace| keyspace.to_str())
+                .ok_or(StorageEngineError::IoError)?;
+            let keyspace_path = self.get_keyspaces_path().join(keyspace);
+            for entry in manifest {
+                let target = keyspace_path.join(&entry.file);
+                fs::create_dir_all(target.parent().ok_or(StorageEngineError::IoError)?)
+                    .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+                copy_file(&snapshot_path.join(&entry.file), &target)?;
+                restored.files += 1;
+            }
+            if let Some(replay_from) = replay_from {
+                restored
+                    .replay_from
+                    .insert(keyspace.to_string(), replay_from);
+            }
+        }
+        self.clear_row_cache();
+
+        Ok(restored)
+    }
+}
+
+impl ManifestEntry {
+    // Describes `file` as it is in the snapshot at `snapshot_path`.
+    fn of(snapshot_path: &Path, file: PathBuf) -> Result<Self, StorageEngineError> {
+        let (size, crc32) = size_and_crc32(&snapshot_path.join(&file))?;
+        Ok(ManifestEntry { file, size, crc32 })
+    }
+
+    // Checks that the file is in the snapshot at `snapshot_path` as the manifest says.
+    fn verify(&self, snapshot_path: &Path) -> Result<(), StorageEngineError> {
+        let (size, crc32) = size_and_crc32(&snapshot_path.join(&self.file))
+            .map_err(|_| StorageEngineError::SnapshotCorrupted)?;
+        if size != self.size || crc32 != self.crc32 {
+            return Err(StorageEngineError::SnapshotCorrupted);
+        }
+        Ok(())
+    }
+}
+
+// Returns the size and CRC32 of a file, read a buffer at a time, as data files may not fit in memory.
+fn size_and_crc32(path: &Path) -> Result<(u64, u32), StorageEngineError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (mut size, mut crc32) = (0, 0);
+    loop {
+        let read = reader.fill_buf()?.len();
+        if read == 0 {
+            return Ok((size, crc32));
+        }
+        crc32 = crc32_update(crc32, reader.buffer());
+        size += read as u64;
+        reader.consume(read);
+    }
+}
+
+// Tags name folders, so they can not hold separators nor be `.` or `..`.
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Whether a file of a keyspace is only ever replaced, never written in place.
+fn is_immutable(file: &Path) -> bool {
+    matches!(
+        file.extension().and_then(|extension| extension.to_str()),
+        Some("sst" | "bloom" | "index")
+    )
+}
+
+// Returns the files of the keyspace at `keyspace_path`, relative to it, leaving out the temporary
+// files of the writes in progress.
+fn stored_files(keyspace_path: &Path) -> Result<Vec<PathBuf>, StorageEngineError> {
+    let mut files = Vec::new();
+    let mut folders = vec![PathBuf::new()];
+    while let Some(folder) = folders.pop() {
+        for entry in fs::read_dir(keyspace_path.join(&folder))? {
+            let entry = entry?;
+            let file = folder.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                folders.push(file);
+            } else if file.extension().is_none_or(|extension| extension != "tmp") {
+                files.push(file);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Copies `from` to `to`, keeping its modification time, which the bloom filter of a data file is
+// checked against. A file at `to` is replaced rather than written, as it may be linked to a snapshot.
+fn copy_file(from: &Path, to: &Path) -> Result<(), StorageEngineError> {
+    match fs::remove_file(to) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(StorageEngineError::FileDeletionFailed)
+        }
+        _ => {}
+    }
+    fs::copy(from, to).map_err(|_| StorageEngineError::FileWriteFailed)?;
+    let modified = fs::metadata(from)?.modified()?;
+    File::options()
+        .write(true)
+        .open(to)?
+        .set_modified(modified)?;
+    Ok(())
+}
+
+fn write_manifest(
+    snapshot_path: &Path,
+    replay_from: u64,
+    manifest: &[ManifestEntry],
+) -> Result<(), StorageEngineError> {
+    fs::create_dir_all(snapshot_path).map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+    let path = snapshot_path.join(MANIFEST_FILE);
+    let temp_path = path.with_extension("tmp");
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    writeln!(writer, "{},{}", REPLAY_FROM_FIELD, replay_from)?;
+    writeln!(writer, "{}", MANIFEST_HEADER)?;
+    for entry in manifest {
+        writeln!(
+            writer,
+            "{},{},{}",
+            entry.file.display(),
+            entry.size,
+            entry.crc32
+        )?;
+    }
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&temp_path, &path).map_err(|_| StorageEngineError::FileWriteFailed)
+}
+
+// Reads the manifest of a snapshot, with the segment of the commit log it was taken at, which the
+// manifests written before it was recorded do not have.
+fn read_manifest(
+    snapshot_path: &Path,
+) -> Result<(Option<u64>, Vec<ManifestEntry>), StorageEngineError> {
+    let file = File::open(snapshot_path.join(MANIFEST_FILE))
+        .map_err(|_| StorageEngineError::FileNotFound)?;
+    let mut lines = BufReader::new(file).lines();
+    let mut first = lines.next().transpose()?;
+    let replay_from = match first
+        .as_deref()
+        .and_then(|line| line.strip_prefix(REPLAY_FROM_FIELD))
+        .and_then(|line| line.strip_prefix(','))
+    {
+        Some(segment) => {
+            let segment = segment
+                .parse()
+                .map_err(|_| StorageEngineError::SnapshotCorrupted)?;
+            first = lines.next().transpose()?;
+            Some(segment)
+        }
+        None => None,
+    };
+    if first.as_deref() != Some(MANIFEST_HEADER) {
+        return Err(StorageEngineError::SnapshotCorrupted);
+    }
+
+    let entries = lines
+        .map(|line| {
+            let line = line?;
+            let mut fields = line.rsplitn(3, ',');
+            let (Some(crc32), Some(size), Some(file)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(StorageEngineError::SnapshotCorrupted);
+            };
+            let file = PathBuf::from(file);
+            // A manifest can only bring back files inside the folder of its keyspace
+            if file.is_absolute() || file.components().any(|c| c.as_os_str() == "..") {
+                return Err(StorageEngineError::SnapshotCorrupted);
+            }
+            Ok(ManifestEntry {
+                file,
+                size: size
+                    .parse()
+                    .map_err(|_| StorageEngineError::SnapshotCorrupted)?,
+                crc32: crc32
+                    .parse()
+                    .map_err(|_| StorageEngineError::SnapshotCorrupted)?,
+            })
+        })
+        .collect::<Result<Vec<ManifestEntry>, StorageEngineError>>()?;
+    Ok((replay_from, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn storage_with_flights(root: &Path) -> StorageEngine {
+        let storage = StorageEngine::new(root.to_path_buf(), "127.0.0.1".to_string());
+        storage.reset_folders().unwrap();
+        storage.create_keyspace("sky", false).unwrap();
+        storage
+            .create_table("sky", "flights", vec!["flight", "lat"], false)
+            .unwrap();
+        let keyspace_path = storage.get_keyspace_path("sky");
+        fs::write(keyspace_path.join("flights.csv"), "flight,lat\nAR1,10;1\n").unwrap();
+        fs::write(keyspace_path.join("flights-1.sst"), "AR2\tAR2,20;2\n").unwrap();
+        fs::write(keyspace_path.join("flights-2.sst.tmp"), "AR3").unwrap();
+        storage
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let root = PathBuf::from(format!("/tmp/snapshot_test_{}", Uuid::new_v4()));
+        let storage = storage_with_flights(&root);
+
+        // The data file, its index and the SSTable, of the owned rows and the replicas
+        assert_eq!(storage.snapshot("sky", "before_landing", 3).unwrap(), 5);
+        let snapshot_path = storage.snapshots_path().join("before_landing").join("sky");
+        assert!(snapshot_path.join(MANIFEST_FILE).exists());
+        assert!(!snapshot_path.join("flights-2.sst.tmp").exists());
+        assert!(matches!(
+            storage.snapshot("sky", "before_landing", 3),
+            Err(StorageEngineError::AlreadyExists)
+        ));
+
+        // The snapshot outlives the reset of the keyspaces on startup
+        storage.reset_folders().unwrap();
+        let restored = storage.restore_snapshot("before_landing").unwrap();
+        assert_eq!(restored.files, 5);
+        assert_eq!(
+            restored.replay_from,
+            HashMap::from([("sky".to_string(), 3)])
+        );
+        let keyspace_path = storage.get_keyspace_path("sky");
+        assert_eq!(
+            fs::read_to_string(keyspace_path.join("flights.csv")).unwrap(),
+            "flight,lat\nAR1,10;1\n"
+        );
+        assert_eq!(
+            fs::read_to_string(keyspace_path.join("flights-1.sst")).unwrap(),
+            "AR2\tAR2,20;2\n"
+        );
+        assert!(keyspace_path.join("replication/flights.csv").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_without_commit_log_segment_replays_the_whole_log() {
+        let root = PathBuf::from(format!("/tmp/snapshot_test_{}", Uuid::new_v4()));
+        let storage = storage_with_flights(&root);
+        storage.snapshot("sky", "old", 7).unwrap();
+
+        // Manifests written before the segment was recorded start with their header
+        let manifest_path = storage
+            .snapshots_path()
+            .join("old")
+            .join("sky")
+            .join(MANIFEST_FILE);
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        let (first, rest) = manifest.split_once('\n').unwrap();
+        assert_eq!(first, "replay_from,7");
+        fs::write(&manifest_path, rest).unwrap();
+
+        storage.reset_folders().unwrap();
+        let restored = storage.restore_snapshot("old").unwrap();
+        assert_eq!(restored.files, 5);
+        assert!(restored.replay_from.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_snapshot_is_not_restored() {
+        let root = PathBuf::from(format!("/tmp/snapshot_test_{}", Uuid::new_v4()));
+        let storage = storage_with_flights(&root);
+        storage.snapshot("sky", "nightly", 1).unwrap();
+
+        let snapshot_path = storage.snapshots_path().join("nightly").join("sky");
+        fs::write(snapshot_path.join("flights.csv"), "flight,lat\nAR1,99;1\n").unwrap();
+        storage.reset_folders().unwrap();
+        assert!(matches!(
+            storage.restore_snapshot("nightly"),
+            Err(StorageEngineError::SnapshotCorrupted)
+        ));
+        assert!(!storage.get_keyspace_path("sky").exists());
+
+        assert!(matches!(
+            storage.restore_snapshot("weekly"),
+            Err(StorageEngineError::FileNotFound)
+        ));
+        assert!(matches!(
+            storage.snapshot("sky", "../nightly", 1),
+            Err(StorageEngineError::InvalidQuery)
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
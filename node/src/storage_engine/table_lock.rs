@@ -0,0 +1,99 @@
+//! Write locks of the data files of the CSV backend, which rewrites a whole data file, and its
+//! index, on every write. Two writers of the same file would each replace it with their own
+//! version, so the one renamed first would be lost.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use super::{errors::StorageEngineError, StorageEngine};
+
+/// The data files being written, shared by the clones of the storage engine of a node.
+///
+/// ### Fields
+/// - `writing`: The paths of the data files locked by a writer.
+/// - `released`: Notified every time a data file is unlocked.
+#[derive(Debug, Default)]
+pub(crate) struct TableLocks {
+    writing: Mutex<HashSet<PathBuf>>,
+    released: Condvar,
+}
+
+/// The write lock of a data file, held until it is dropped.
+#[derive(Debug)]
+pub(crate) struct TableWriteLock {
+    locks: Arc<TableLocks>,
+    data_path: PathBuf,
+}
+
+impl StorageEngine {
+    /// Locks a data file (and its index) for writing, waiting for its current writer to finish.
+    ///
+    /// # Notes
+    /// - The lock is not reentrant: a writer that writes the same file again, as an `UPDATE` that
+    ///   inserts a counter, must drop its lock first.
+    pub(crate) fn lock_table(
+        &self,
+        data_path: &Path,
+    ) -> Result<TableWriteLock, StorageEngineError> {
+        let mut writing = self
+            .table_locks
+            .writing
+            .lock()
+            .map_err(|_| StorageEngineError::IoError)?;
+        while writing.contains(data_path) {
+            writing = self
+                .table_locks
+                .released
+                .wait(writing)
+                .map_err(|_| StorageEngineError::IoError)?;
+        }
+        writing.insert(data_path.to_path_buf());
+        Ok(TableWriteLock {
+            locks: Arc::clone(&self.table_locks),
+            data_path: data_path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for TableWriteLock {
+    fn drop(&mut self) {
+        if let Ok(mut writing) = self.locks.writing.lock() {
+            writing.remove(&self.data_path);
+        }
+        self.locks.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn test_table_is_written_by_one_writer_at_a_time() {
+        let storage = StorageEngine::new(PathBuf::from("/tmp"), "127.0.0.1".to_string());
+        let data_path = PathBuf::from("/tmp/sky/flights.csv");
+        let lock = storage.lock_table(&data_path).unwrap();
+        // Other tables are not locked
+        drop(
+            storage
+                .lock_table(Path::new("/tmp/sky/airports.csv"))
+                .unwrap(),
+        );
+
+        let (sender, receiver) = mpsc::channel();
+        let clone = storage.clone();
+        let writer = thread::spawn(move || {
+            let _lock = clone.lock_table(&data_path).unwrap();
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(lock);
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        writer.join().unwrap();
+    }
+}
@@ -1,5 +1,8 @@
-use super::{errors::StorageEngineError, StorageEngine};
-use std::fs::{self, OpenOptions};
+use super::{
+    bloom_filter::remove_filter, errors::StorageEngineError, row_stamp::split_row, StorageEngine,
+};
+use query_creator::clauses::types::column::Column;
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
 
 impl StorageEngine {
@@ -10,86 +13,39 @@ impl StorageEngine {
     /// * `keyspace`: The name of the keyspace where the table will be stored.
     /// * `table`: The name of the table to create.
     /// * `columns`: A vector of strings representing the names of the table columns.
+    /// * `if_not_exists`: Whether an existing table is left as it is instead of being an error,
+    ///   as with `CREATE TABLE IF NOT EXISTS`.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if the table is created successfully, or an error if it fails.
+    /// * `Ok(())` if the table is created successfully (or already existed and `if_not_exists` is set),
+    ///   or an error if it fails.
     ///
     /// # Errors
     ///
     /// This function can return the following errors:
     ///
+    /// * `StorageEngineError::AlreadyExists` if the table already exists and `if_not_exists` is not set.
     /// * `StorageEngineError::DirectoryCreationFailed` if the directory for the table cannot be created.
     /// * `StorageEngineError::FileWriteFailed` if writing to the table or replication files fails.
-    /// * `StorageEngineError::IoError` if an I/O error occurs while renaming files.
+    ///
+    /// # Notes
+    ///
+    /// * The keyspace folders are created if they do not exist yet.
+    /// * The files of an existing table are never truncated, so creating a table that is being written
+    ///   to at the same time (e.g. after receiving the schema through gossip) does not lose its rows.
     pub fn create_table(
         &self,
         keyspace: &str,
         table: &str,
         columns: Vec<&str>,
+        if_not_exists: bool,
     ) -> Result<(), StorageEngineError> {
-        // Generate the folder name where the keyspace will be stored
-        let keyspace_path = self.get_keyspace_path(keyspace);
-        let replication_path = keyspace_path.join("replication");
+        let created = self.ensure_table_files(keyspace, table, &columns)?;
 
-        let primary_file_path = keyspace_path.join(format!("{}.csv", table));
-        let replication_file_path = replication_path.join(format!("{}.csv", table));
-
-        // Create the keyspace and replication folders if they don't exist
-        std::fs::create_dir_all(&keyspace_path)
-            .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
-        std::fs::create_dir_all(&replication_path)
-            .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
-
-        // Create the file in the primary folder and write the columns as the header
-        let mut primary_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&primary_file_path)
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        let header: Vec<String> = columns.iter().map(|col| col.to_string()).collect();
-        writeln!(primary_file, "{}", header.join(","))
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        // Create the same file in the replication folder and write the columns as the header
-        let mut replication_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&replication_file_path)
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        writeln!(replication_file, "{}", header.join(","))
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        // Create the index file in the primary folder
-        let index_file_path = keyspace_path.join(format!("{}_index.csv", table));
-        let mut index_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&index_file_path)
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        writeln!(index_file, "clustering_column,start_byte,end_byte")
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        // Create the index file in the replication folder
-        let replication_index_file_path = replication_path.join(format!("{}_index.csv", table));
-        let mut replication_index_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&replication_index_file_path)
-            .map_err(|_| StorageEngineError::FileWriteFailed)?;
-
-        writeln!(
-            replication_index_file,
-            "clustering_column,first_byte,last_byte"
-        )
-        .map_err(|_| StorageEngineError::FileWriteFailed)?;
+        if !created && !if_not_exists {
+            return Err(StorageEngineError::AlreadyExists);
+        }
 
         Ok(())
     }
@@ -120,6 +76,8 @@ impl StorageEngine {
         let primary_index_path = keyspace_path.join(format!("{}_index.csv", table));
         let replication_index_path = replication_path.join(format!("{}_index.csv", table));
 
+        self.lsm_drop_table(keyspace, table)?;
+
         // Remove the primary and replication files
         if let Err(_) = std::fs::remove_file(&primary_file_path) {
             return Err(StorageEngineError::FileDeletionFailed);
@@ -137,6 +95,9 @@ impl StorageEngine {
         if let Err(_) = std::fs::remove_file(&replication_index_path) {
             return Err(StorageEngineError::FileDeletionFailed);
         }
+        remove_filter(&primary_file_path);
+        remove_filter(&replication_file_path);
+        self.invalidate_cached_table(keyspace, Some(table));
 
         Ok(())
     }
@@ -172,6 +133,7 @@ impl StorageEngine {
 
         Self::add_column_to_file(file_path.to_str().unwrap(), column)?;
         Self::add_column_to_file(replica_path.to_str().unwrap(), column)?;
+        self.invalidate_cached_table(keyspace, Some(table));
 
         Ok(())
     }
@@ -208,6 +170,7 @@ impl StorageEngine {
 
         Self::remove_column_from_file(file_path.to_str().unwrap(), column)?;
         Self::remove_column_from_file(replica_path.to_str().unwrap(), column)?;
+        self.invalidate_cached_table(keyspace, Some(table));
 
         Ok(())
     }
@@ -245,7 +208,69 @@ impl StorageEngine {
 
         Self::rename_column_in_file(file_path.to_str().unwrap(), column, new_column)?;
         Self::rename_column_in_file(replica_path.to_str().unwrap(), column, new_column)?;
+        self.invalidate_cached_table(keyspace, Some(table));
+
+        Ok(())
+    }
+
+    /// Changes the columns of the data files of a table from the ones of its schema before an
+    /// `ALTER TABLE` to the ones after it.
+    ///
+    /// # Parameters
+    ///
+    /// * `keyspace`: The name of the keyspace that contains the table.
+    /// * `table`: The name of the altered table.
+    /// * `old_columns`: The columns of the table before it was altered.
+    /// * `new_columns`: The columns of the table after it was altered.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the data files have the new columns, or an error if they can not be rewritten.
+    ///
+    /// # Notes
+    ///
+    /// The columns of the primary key can not be added nor dropped, so the ones that are gone were
+    /// renamed, in order, to the new ones. The other columns that are gone are dropped with their
+    /// values, and the new ones are added at the end, empty in every row.
+    pub fn alter_table_columns(
+        &self,
+        keyspace: &str,
+        table: &str,
+        old_columns: &[Column],
+        new_columns: &[Column],
+    ) -> Result<(), StorageEngineError> {
+        let is_key = |column: &&Column| column.is_partition_key || column.is_clustering_column;
+        let missing_from = |columns: &[Column], column: &&Column| {
+            !columns.iter().any(|other| other.name == column.name)
+        };
+        let gone: Vec<&Column> = old_columns
+            .iter()
+            .filter(|column| missing_from(new_columns, column))
+            .collect();
+        let added: Vec<&Column> = new_columns
+            .iter()
+            .filter(|column| missing_from(old_columns, column))
+            .collect();
+        if gone.is_empty() && added.is_empty() {
+            return Ok(());
+        }
 
+        // Columns are changed in the data files, so the rows of an LSM backend must be there
+        self.compact_lsm_table(keyspace, table, old_columns)?;
+
+        let renamed = gone
+            .iter()
+            .filter(|column| is_key(column))
+            .zip(added.iter().filter(|column| is_key(column)));
+        for (old_column, new_column) in renamed {
+            self.rename_column_from_table(keyspace, table, &old_column.name, &new_column.name)?;
+        }
+        for column in gone.iter().filter(|column| !is_key(column)) {
+            self.remove_column_from_table(keyspace, table, &column.name)?;
+        }
+        for column in added.iter().filter(|column| !is_key(column)) {
+            self.add_column_to_table(keyspace, table, &column.name)?;
+        }
         Ok(())
     }
 
@@ -254,24 +279,22 @@ impl StorageEngine {
         column_name: &str,
     ) -> Result<(), StorageEngineError> {
         let temp_path = format!("{}.temp", file_path);
-        let mut temp_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&temp_path)?;
+        let mut temp_file = File::create(&temp_path)?;
 
         let file = OpenOptions::new().read(true).open(file_path)?;
         let reader = BufReader::new(file);
         let mut first_line = true;
 
         for line in reader.lines() {
-            let mut line = line?;
+            let line = line?;
             if first_line {
-                line.push_str(&format!(",{}", column_name));
+                writeln!(temp_file, "{},{}", line, column_name)?;
                 first_line = false;
             } else {
-                line.push_str(","); // Append an empty cell for the new column in each row
+                // Append an empty cell for the new column in each row, before its stamp
+                let (values, stamp) = split_row(&line)?;
+                writeln!(temp_file, "{},;{}", values, stamp)?;
             }
-            writeln!(temp_file, "{}", line)?;
         }
 
         fs::rename(temp_path, file_path).map_err(|_| StorageEngineError::IoError)
@@ -282,10 +305,7 @@ impl StorageEngine {
         column_name: &str,
     ) -> Result<(), StorageEngineError> {
         let temp_path = format!("{}.temp", file_path);
-        let mut temp_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&temp_path)?;
+        let mut temp_file = File::create(&temp_path)?;
 
         let file = OpenOptions::new().read(true).open(file_path)?;
         let reader = BufReader::new(file);
@@ -293,11 +313,20 @@ impl StorageEngine {
 
         for line in reader.lines() {
             let line = line?;
-            let cells: Vec<&str> = line.split(',').collect();
+            // The header has no stamp, every row has one after its values
+            let (values, stamp) = match col_index {
+                None => (line.as_str(), None),
+                Some(_) => {
+                    let (values, stamp) = split_row(&line)?;
+                    (values, Some(stamp))
+                }
+            };
+            let cells: Vec<&str> = values.split(',').collect();
 
             if col_index.is_none() {
                 col_index = cells.iter().position(|&col| col == column_name);
                 if col_index.is_none() {
+                    let _ = fs::remove_file(&temp_path);
                     return Err(StorageEngineError::UnsupportedOperation);
                 }
             }
@@ -309,7 +338,10 @@ impl StorageEngine {
                 .map(|(_, &cell)| cell)
                 .collect();
 
-            writeln!(temp_file, "{}", filtered_line.join(","))?;
+            match stamp {
+                Some(stamp) => writeln!(temp_file, "{};{}", filtered_line.join(","), stamp)?,
+                None => writeln!(temp_file, "{}", filtered_line.join(","))?,
+            }
         }
 
         fs::rename(temp_path, file_path).map_err(|_| StorageEngineError::IoError)
@@ -321,10 +353,7 @@ impl StorageEngine {
         new_name: &str,
     ) -> Result<(), StorageEngineError> {
         let temp_path = format!("{}.temp", file_path);
-        let mut temp_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&temp_path)?;
+        let mut temp_file = File::create(&temp_path)?;
 
         let file = OpenOptions::new().read(true).open(file_path)?;
         let reader = BufReader::new(file);
@@ -332,7 +361,12 @@ impl StorageEngine {
         for (i, line) in reader.lines().enumerate() {
             let mut line = line?;
             if i == 0 {
-                line = line.replace(old_name, new_name); // Rename in the header
+                // Rename in the header, only the column with that exact name
+                line = line
+                    .split(',')
+                    .map(|column| if column == old_name { new_name } else { column })
+                    .collect::<Vec<&str>>()
+                    .join(",");
             }
             writeln!(temp_file, "{}", line)?;
         }
@@ -343,8 +377,10 @@ impl StorageEngine {
 
 #[cfg(test)]
 mod tests {
-    use super::StorageEngine;
-    use std::fs::File;
+    use super::{Column, StorageEngine, StorageEngineError};
+    use gossip::structures::application_state::TableSchema;
+    use query_creator::clauses::{table::create_table_cql::CreateTable, types::datatype::DataType};
+    use std::fs::{self, File};
     use std::io::{BufRead, BufReader};
     use std::path::PathBuf;
     use uuid::Uuid;
@@ -359,7 +395,7 @@ mod tests {
         let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
 
         // Ejecutar create_table
-        let result = storage.create_table(keyspace, table_name, columns);
+        let result = storage.create_table(keyspace, table_name, columns, false);
         assert!(result.is_ok(), "Failed to create table");
 
         let keyspace_path = root.join(format!("keyspaces_of_127_0_0_1")).join(keyspace);
@@ -391,7 +427,7 @@ mod tests {
         let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
 
         // Crear la tabla primero
-        let result = storage.create_table(keyspace, table_name, columns);
+        let result = storage.create_table(keyspace, table_name, columns, false);
         assert!(result.is_ok(), "Failed to create table");
 
         // Ejecutar drop_table
@@ -428,7 +464,7 @@ mod tests {
         let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
 
         // Crear la tabla primero
-        let result = storage.create_table(keyspace, table_name, columns);
+        let result = storage.create_table(keyspace, table_name, columns, false);
         assert!(result.is_ok(), "Failed to create table");
 
         // Agregar una columna a la tabla
@@ -459,7 +495,7 @@ mod tests {
         let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
 
         // Crear la tabla primero
-        let result = storage.create_table(keyspace, table_name, columns);
+        let result = storage.create_table(keyspace, table_name, columns, false);
         assert!(result.is_ok(), "Failed to create table");
 
         // Eliminar una columna de la tabla
@@ -490,7 +526,7 @@ mod tests {
         let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
 
         // Crear la tabla primero
-        let result = storage.create_table(keyspace, table_name, columns);
+        let result = storage.create_table(keyspace, table_name, columns, false);
         assert!(result.is_ok(), "Failed to create table");
 
         // Renombrar una columna de la tabla
@@ -510,4 +546,108 @@ mod tests {
         // Verificar que la columna "age" ha sido renombrada a "years"
         assert!(header.contains("years"), "Column not renamed");
     }
+
+    #[test]
+    fn test_alter_table_columns_keeps_the_rows() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = CreateTable::deserialize(
+            "CREATE TABLE sky.flights (airport TEXT, number INT, status TEXT, gate TEXT, \
+            PRIMARY KEY (airport, number))",
+        )
+        .unwrap();
+        let columns = table.get_columns();
+        storage
+            .insert(
+                "sky",
+                "flights",
+                vec!["EZE", "1", "landed", "A2"],
+                columns.clone(),
+                TableSchema::new(table.clone()).get_clustering_column_in_order(),
+                false,
+                false,
+                1,
+            )
+            .unwrap();
+
+        let mut altered = table;
+        altered.rename_column("number", "flight").unwrap();
+        altered.remove_column("gate").unwrap();
+        altered
+            .add_column(Column::new("delay", DataType::Int, false, true))
+            .unwrap();
+        storage
+            .alter_table_columns("sky", "flights", &columns, &altered.get_columns())
+            .unwrap();
+
+        let data = fs::read_to_string(
+            root.join("keyspaces_of_127_0_0_1")
+                .join("sky")
+                .join("flights.csv"),
+        )
+        .unwrap();
+        assert_eq!(data, "airport,flight,status,delay\nEZE,1,landed,;1\n");
+    }
+
+    #[test]
+    fn test_create_existing_table() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let keyspace = "test_keyspace";
+        let table_name = "test_table";
+
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        storage
+            .create_table(keyspace, table_name, vec!["id", "name"], false)
+            .unwrap();
+
+        let file_path = root
+            .join("keyspaces_of_127_0_0_1")
+            .join(keyspace)
+            .join(format!("{}.csv", table_name));
+        std::fs::write(&file_path, "id,name\n1,Juan;1\n").unwrap();
+
+        // Sin IF NOT EXISTS la tabla existente es un error
+        assert!(matches!(
+            storage.create_table(keyspace, table_name, vec!["id", "name"], false),
+            Err(StorageEngineError::AlreadyExists)
+        ));
+
+        // Con IF NOT EXISTS la tabla queda como estaba
+        assert!(storage
+            .create_table(keyspace, table_name, vec!["id", "name"], true)
+            .is_ok());
+        assert_eq!(
+            std::fs::read_to_string(&file_path).unwrap(),
+            "id,name\n1,Juan;1\n"
+        );
+    }
+
+    #[test]
+    fn test_create_table_concurrently() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let root = root.clone();
+                std::thread::spawn(move || {
+                    StorageEngine::new(root, "127.0.0.1".to_string()).create_table(
+                        "test_keyspace",
+                        "test_table",
+                        vec!["id", "name"],
+                        true,
+                    )
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap().is_ok());
+        }
+
+        let file_path = root
+            .join("keyspaces_of_127_0_0_1")
+            .join("test_keyspace")
+            .join("test_table.csv");
+        assert_eq!(std::fs::read_to_string(file_path).unwrap(), "id,name\n");
+    }
 }
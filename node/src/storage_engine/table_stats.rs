@@ -0,0 +1,166 @@
+use gossip::structures::application_state::TableSchema;
+
+use super::{errors::StorageEngineError, row_stamp::split_row, StorageEngine};
+
+/// Fraction of droppable cells above which a table should be compacted. It is the default
+/// `tombstone_threshold` of the compaction strategies of Cassandra.
+pub const COMPACTION_THRESHOLD: f64 = 0.2;
+
+/// Estimate of the data of a table that a compaction could drop, taken from a scan of its
+/// data files (both the owned rows and the replicas).
+///
+/// ### Fields
+/// - `rows`: Rows stored in the data files.
+/// - `expired_rows`: Rows whose TTL ran out, which the reads skip until a compaction drops them.
+/// - `tombstone_cells`: Cells of the rows that did not expire which were left empty, by deleting
+///   their column or writing a null. They play the role of the cell tombstones of Cassandra.
+/// - `cells`: Cells of every row that are not part of its primary key.
+/// - `droppable_cells`: The cells of the expired rows plus the tombstone cells.
+/// - `bytes`: Size of the rows in the data files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableStats {
+    pub rows: u64,
+    pub expired_rows: u64,
+    pub tombstone_cells: u64,
+    pub cells: u64,
+    pub droppable_cells: u64,
+    pub bytes: u64,
+}
+
+impl TableStats {
+    /// Returns the fraction of the cells of the table that could be dropped, between 0 and 1.
+    pub fn droppable_ratio(&self) -> f64 {
+        if self.cells == 0 {
+            return 0.0;
+        }
+        self.droppable_cells as f64 / self.cells as f64
+    }
+
+    /// Whether enough of the table could be dropped to be worth compacting it.
+    pub fn needs_compaction(&self) -> bool {
+        self.droppable_ratio() > COMPACTION_THRESHOLD
+    }
+}
+
+impl StorageEngine {
+    /// Scans the stored rows of a table and returns the estimate of the data that could be dropped.
+    ///
+    /// # Arguments
+    /// - `keyspace`: The keyspace of the table.
+    /// - `table`: The schema of the table.
+    /// - `now`: The current time, in seconds, as the timestamps of the rows, which decides which
+    ///   rows expired.
+    ///
+    /// # Returns
+    /// - `Ok(TableStats)` with the sum of the owned rows and the replicas.
+    /// - `Err(StorageEngineError)` if the files of the table can not be created or read.
+    pub fn table_stats(
+        &self,
+        keyspace: &str,
+        table: &TableSchema,
+        now: i64,
+    ) -> Result<TableStats, StorageEngineError> {
+        let columns = table.get_columns();
+        let regular_columns: Vec<usize> = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !(c.is_primary_key || c.is_partition_key || c.is_clustering_column))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut stats = TableStats::default();
+        for is_replication in [false, true] {
+            self.scan_stored_rows(keyspace, table, is_replication, true, |line| {
+                let (values, stamp) = split_row(line)?;
+                let values: Vec<&str> = values.split(',').collect();
+
+                stats.rows += 1;
+                stats.bytes += line.len() as u64 + 1;
+                stats.cells += regular_columns.len() as u64;
+
+                if stamp.is_expired(now) {
+                    stats.expired_rows += 1;
+                    stats.droppable_cells += regular_columns.len() as u64;
+                    return Ok(());
+                }
+
+                let empty_cells = regular_columns
+                    .iter()
+                    .filter(|&&i| values.get(i).is_none_or(|value| value.trim().is_empty()))
+                    .count() as u64;
+                stats.tombstone_cells += empty_cells;
+                stats.droppable_cells += empty_cells;
+                Ok(())
+            })?;
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query_creator::clauses::table::create_table_cql::CreateTable;
+    use std::{fs, io::Write, path::PathBuf};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_table_stats_counts_expired_rows_and_tombstones() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let table = TableSchema::new(
+            CreateTable::deserialize(
+                "CREATE TABLE sky.flights (id INT, origin TEXT, status TEXT, PRIMARY KEY (id)) \
+                WITH default_time_to_live = 60",
+            )
+            .unwrap(),
+        );
+
+        let empty = storage.table_stats("sky", &table, 1000).unwrap();
+        assert_eq!(empty, TableStats::default());
+        assert!(!empty.needs_compaction());
+
+        let data_path = storage
+            .get_folder_path("sky", false)
+            .unwrap()
+            .join("flights.csv");
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&data_path)
+            .unwrap();
+        // An expired row, a row with a deleted column and two complete rows
+        writeln!(file, "1,EZE,landed;900;960").unwrap();
+        writeln!(file, "2,AEP,;990;1050").unwrap();
+        writeln!(file, "3,COR,boarding;990;1050").unwrap();
+        writeln!(file, "4,MDZ,delayed;995").unwrap();
+        let replica_path = storage
+            .get_folder_path("sky", true)
+            .unwrap()
+            .join("flights.csv");
+        let mut replica = fs::OpenOptions::new()
+            .append(true)
+            .open(&replica_path)
+            .unwrap();
+        writeln!(replica, "5,ROS,landed;995;1055").unwrap();
+
+        let stats = storage.table_stats("sky", &table, 1000).unwrap();
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.expired_rows, 1);
+        assert_eq!(stats.tombstone_cells, 1);
+        assert_eq!(stats.cells, 10);
+        assert_eq!(stats.droppable_cells, 3);
+        assert!((stats.droppable_ratio() - 0.3).abs() < 1e-9);
+        assert!(stats.needs_compaction());
+
+        // Rows expire when their own TTL runs out, and the rows written without one never do
+        let stats = storage.table_stats("sky", &table, 900).unwrap();
+        assert_eq!(stats.expired_rows, 0);
+        assert_eq!(stats.droppable_cells, 1);
+        assert!(!stats.needs_compaction());
+        let stats = storage.table_stats("sky", &table, 2000).unwrap();
+        assert_eq!(stats.expired_rows, 4);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
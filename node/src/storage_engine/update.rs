@@ -6,9 +6,12 @@ use std::{
 };
 
 use gossip::structures::application_state::TableSchema;
-use query_creator::clauses::update_cql::Update;
+use query_creator::clauses::{types::datatype::DataType, update_cql::Update};
 
-use super::{errors::StorageEngineError, StorageEngine};
+use super::{
+    counter::merge_counter_cell, errors::StorageEngineError, row_cache::PartitionKey,
+    row_stamp::RowStamp, StorageEngine,
+};
 
 impl StorageEngine {
     /// Performs an update on rows in a table by applying an `UPDATE` query to the records
@@ -31,6 +34,12 @@ impl StorageEngine {
     ///
     /// * `timestamp` - An `i64` value representing the timestamp associated with the update.
     ///   This value will be included in the updated rows to track when the modification occurred.
+    ///   The updated rows expire after the `USING TTL` of the query or, without one, the
+    ///   `default_time_to_live` of the table. Expired rows are not updated.
+    ///
+    /// Values of counter columns are shards of the counters, which are merged with the shards the
+    /// rows hold instead of replacing them. If no row matches, the row is written with them, as
+    /// counters are created by their first increment.
     ///
     /// # Returns
     ///
@@ -84,23 +93,51 @@ impl StorageEngine {
         timestamp: i64,
     ) -> Result<(), StorageEngineError> {
         let table_name = table.get_name();
-        let base_folder_path = self.get_keyspace_path(keyspace);
-
-        // Construcción de la ruta de la carpeta según si es replicación o no
-        let folder_path = if is_replication {
-            base_folder_path.join("replication")
-        } else {
-            base_folder_path
-        };
+        let folder_path = self.get_folder_path(keyspace, is_replication)?;
 
-        // Crear la carpeta si no existe
-        if !folder_path.exists() {
-            fs::create_dir_all(&folder_path)
-                .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
-        }
+        // The table may not have been created yet in this node if its schema is still being gossiped
+        let columns = table.get_columns();
+        let column_names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        self.ensure_table_files(keyspace, &table_name, &column_names)?;
 
         // Rutas para el archivo original y el archivo temporal
         let file_path = folder_path.join(format!("{}.csv", table_name));
+        let stamp = RowStamp::new(timestamp, table.get_options().ttl_for(update_query.ttl));
+        if let Some(store) = self.lsm_store() {
+            let partition = update_query
+                .where_clause
+                .as_ref()
+                .and_then(|where_clause| {
+                    PartitionKey::of_where(
+                        keyspace,
+                        &table_name,
+                        is_replication,
+                        where_clause,
+                        &columns,
+                    )
+                })
+                .map(|key| key.partition());
+            let found_match = self.lsm_update(
+                &store,
+                &file_path,
+                &update_query,
+                &table,
+                partition.as_deref(),
+                stamp,
+            )?;
+            if !found_match && Self::sets_counters(&table, &update_query) {
+                self.add_new_row_in_update(&table, &update_query, keyspace, is_replication, stamp)?;
+            }
+            self.invalidate_cached_where(
+                keyspace,
+                &table_name,
+                is_replication,
+                update_query.where_clause.as_ref(),
+                &columns,
+            );
+            return Ok(());
+        }
+        let lock = self.lock_table(&file_path)?;
         let index_file_path = folder_path.join(format!("{}_index.csv", table.get_name()));
         let temp_file_path = folder_path.join(format!(
             "{}.tmp",
@@ -109,8 +146,10 @@ impl StorageEngine {
                 .map_err(|_| StorageEngineError::TempFileCreationFailed)?
                 .as_nanos()
         ));
+        // The index is replaced like the data file, so it is never seen half written
+        let temp_index_path = temp_file_path.with_extension("index.tmp");
         let mut temp_index = BufWriter::new(
-            File::create(&index_file_path).map_err(|_| StorageEngineError::IoError)?,
+            File::create(&temp_index_path).map_err(|_| StorageEngineError::IoError)?,
         );
 
         writeln!(temp_index, "clustering_column,start_byte,end_byte")
@@ -158,12 +197,12 @@ impl StorageEngine {
             .map_err(|_| StorageEngineError::FileWriteFailed)?;
         current_byte_offset += header_line.len() as u64; // Contar el tamaño del encabezado
 
-        let mut _found_match = false;
+        let mut found_match = false;
 
         // Iterar sobre las líneas del archivo original y aplicar la actualización
         for line in reader.lines() {
             let line = line?;
-            _found_match |= self.update_or_write_line(
+            found_match |= self.update_or_write_line(
                 &table,
                 &update_query,
                 &line,
@@ -171,7 +210,7 @@ impl StorageEngine {
                 &mut index_map,
                 clustering_key_index,
                 &mut current_byte_offset,
-                timestamp,
+                stamp,
             )?;
         }
 
@@ -184,13 +223,26 @@ impl StorageEngine {
             writeln!(temp_index, "{},{},{}", key, start_byte, end_byte)
                 .map_err(|_| StorageEngineError::IoError)?;
         }
-
+        temp_index
+            .flush()
+            .map_err(|_| StorageEngineError::IoError)?;
         std::mem::drop(temp_index);
-        // Si no se encontró ninguna fila que coincida, agregar una nueva
-        /*if !found_match {
-            self.add_new_row_in_update(&table, &update_query, keyspace, is_replication, timestamp)?;
-        }*/
+        fs::rename(&temp_index_path, &index_file_path)
+            .map_err(|_| StorageEngineError::DirectoryCreationFailed)?;
+        // The counter is inserted by a write of its own
+        drop(lock);
+        // Counters are created by their first increment
+        if !found_match && Self::sets_counters(&table, &update_query) {
+            self.add_new_row_in_update(&table, &update_query, keyspace, is_replication, stamp)?;
+        }
 
+        self.invalidate_cached_where(
+            keyspace,
+            &table_name,
+            is_replication,
+            update_query.where_clause.as_ref(),
+            &columns,
+        );
         Ok(())
     }
 
@@ -222,11 +274,12 @@ impl StorageEngine {
         index_map: &mut std::collections::BTreeMap<String, (u64, u64)>,
         clustering_key_index: Option<usize>,
         current_byte_offset: &mut u64,
-        timestamp: i64,
+        stamp: RowStamp,
     ) -> Result<bool, StorageEngineError> {
         // Dividir la línea en contenido y timestamp
         let (line_content, time_of_row) =
             line.split_once(";").ok_or(StorageEngineError::IoError)?;
+        let expired = time_of_row.parse::<RowStamp>()?.is_expired(RowStamp::now());
         let mut columns: Vec<String> = line_content
             .split(',')
             .map(|s| s.trim().to_string())
@@ -239,7 +292,7 @@ impl StorageEngine {
         let mut line_length;
 
         // Evaluar la cláusula WHERE
-        if let Some(where_clause) = &update_query.where_clause {
+        if let Some(where_clause) = update_query.where_clause.as_ref().filter(|_| !expired) {
             if where_clause
                 .condition
                 .execute(&column_value_map, columns_schema.clone())
@@ -275,14 +328,11 @@ impl StorageEngine {
                     {
                         return Err(StorageEngineError::PrimaryKeyModificationNotAllowed);
                     }
-                    let index = table
-                        .get_column_index(&column)
-                        .ok_or(StorageEngineError::ColumnNotFound)?;
-                    columns[index] = new_value.clone();
+                    Self::set_column(table, &mut columns, column, new_value)?;
                 }
 
                 // Crear línea actualizada con el nuevo timestamp
-                let updated_line = format!("{};{}", columns.join(","), timestamp);
+                let updated_line = format!("{};{}", columns.join(","), stamp);
                 line_length = updated_line.len() as u64 + 1; // Contar '\n'
                 writeln!(temp_file, "{}", updated_line)?;
 
@@ -319,6 +369,39 @@ impl StorageEngine {
         Ok(replaced)
     }
 
+    /// Writes the value of a column of a row given by an `UPDATE`, merging it with the shards the
+    /// row holds if the column is a counter.
+    pub(super) fn set_column(
+        table: &TableSchema,
+        row: &mut [String],
+        column: &str,
+        value: &str,
+    ) -> Result<(), StorageEngineError> {
+        let index = table
+            .get_column_index(column)
+            .ok_or(StorageEngineError::ColumnNotFound)?;
+        let is_counter = table
+            .get_columns()
+            .get(index)
+            .is_some_and(|column| column.data_type == DataType::Counter);
+        row[index] = if is_counter {
+            merge_counter_cell(&row[index], value)?
+        } else {
+            value.to_string()
+        };
+        Ok(())
+    }
+
+    // Returns whether an `UPDATE` writes a counter column, which creates the row if it does not exist
+    fn sets_counters(table: &TableSchema, update_query: &Update) -> bool {
+        let columns = table.get_columns();
+        update_query.set_clause.get_pairs().iter().any(|(name, _)| {
+            columns
+                .iter()
+                .any(|column| column.name == *name && column.data_type == DataType::Counter)
+        })
+    }
+
     fn update_index_map_update(
         row: &[String],
         clustering_key_index: Option<usize>,
@@ -335,13 +418,13 @@ impl StorageEngine {
         }
     }
 
-    fn _add_new_row_in_update(
+    fn add_new_row_in_update(
         &self,
         table: &TableSchema,
         update_query: &Update,
         keyspace: &str,
         is_replication: bool,
-        timestamp: i64,
+        stamp: RowStamp,
     ) -> Result<(), StorageEngineError> {
         let mut new_row: Vec<String> = vec!["".to_string(); table.get_columns().len()];
 
@@ -398,11 +481,7 @@ impl StorageEngine {
             {
                 return Err(StorageEngineError::PrimaryKeyModificationNotAllowed);
             }
-            let index = table
-                .get_column_index(&column)
-                .ok_or(StorageEngineError::ColumnNotFound)?;
-
-            new_row[index] = new_value.clone();
+            Self::set_column(table, &mut new_row, column, new_value)?;
         }
 
         let values: Vec<&str> = new_row.iter().map(|v| v.as_str()).collect();
@@ -415,7 +494,7 @@ impl StorageEngine {
             table.get_clustering_column_in_order(),
             is_replication,
             true,
-            timestamp,
+            stamp,
         )?;
 
         Ok(())
@@ -429,6 +508,7 @@ mod tests {
     use query_creator::clauses::table::create_table_cql::CreateTable;
     use query_creator::clauses::types::column::Column;
     use query_creator::clauses::types::datatype::DataType;
+    use query_creator::QueryCreator;
     use std::fs::{self, File};
     use std::io::{BufRead, BufReader};
     use std::path::PathBuf;
@@ -610,6 +690,47 @@ mod tests {
             fs::remove_dir_all(&root).unwrap();
         }
     }
+    #[test]
+    fn test_update_counter_merges_shards_and_creates_the_row() {
+        let root = PathBuf::from(format!("/tmp/storage_test_{}", Uuid::new_v4()));
+        let storage = StorageEngine::new(root.clone(), "127.0.0.1".to_string());
+        let create_table = CreateTable::new_from_tokens(QueryCreator::tokens_from_query(
+            "CREATE TABLE sky.departures (airport TEXT, count COUNTER, PRIMARY KEY (airport))",
+        ))
+        .unwrap();
+        let table = TableSchema::new(create_table);
+        let update = |shard: &str, timestamp| {
+            let query = format!(
+                "UPDATE sky.departures SET count = '{}' WHERE airport = 'EZE'",
+                shard
+            );
+            let tokens = QueryCreator::tokens_from_query(&query);
+            storage
+                .update(
+                    Update::new_from_tokens(tokens).unwrap(),
+                    table.clone(),
+                    false,
+                    "sky",
+                    timestamp,
+                )
+                .unwrap();
+        };
+
+        // The first shard creates the row, and an older or replayed one changes nothing
+        update("127.0.0.1:2:5", 1);
+        update("127.0.0.2:1:-1", 2);
+        update("127.0.0.1:1:3", 3);
+        update("127.0.0.2:1:-1", 4);
+
+        let content =
+            fs::read_to_string(storage.get_keyspace_path("sky").join("departures.csv")).unwrap();
+        assert_eq!(
+            content,
+            "airport,count\nEZE,127.0.0.1:2:5|127.0.0.2:1:-1;4\n"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_update_where_condition_not_met() {
         // Usamos un directorio único para esta prueba
@@ -0,0 +1,179 @@
+//! The chunks of rows a node streamed to the others that were not acknowledged yet.
+//!
+//! Every chunk (see `StreamChunk`) is kept from the moment it is queued until its node answers
+//! that it stored it. The chunks with no answer, or that the node rejected because they arrived
+//! corrupted or their table was not known there yet, are sent again every `STREAM_RETRY_INTERVAL`
+//! while their node is `Normal`, so a stream cut by a dead connection or a restarted node resumes
+//! from its first lost chunk instead of starting over. Chunks are dropped
+//! once `STREAM_CHUNK_TTL` passed since they were first sent, as their node is then expected to be
+//! repaired by other means.
+
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::errors::NodeError;
+use crate::internode_protocol::streaming::{StreamChunk, StreamChunkAck};
+
+/// Time a chunk waits for its answer before it is sent again.
+pub const STREAM_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+/// Time a chunk is kept since it was first sent (3 hours, as hints).
+pub const STREAM_CHUNK_TTL: Duration = Duration::from_secs(3 * 60 * 60);
+
+// The node a chunk was streamed to, its session and its position in the session
+type ChunkKey = (Ipv4Addr, u32, u32);
+
+// A chunk waiting for its answer
+#[derive(Debug, Clone)]
+struct PendingChunk {
+    chunk: StreamChunk,
+    first_sent: Instant,
+    last_sent: Instant,
+}
+
+/// The chunks streamed by a node that were not acknowledged yet, by node, session and position.
+#[derive(Debug, Clone, Default)]
+pub struct StreamSessions {
+    pending: Arc<Mutex<BTreeMap<ChunkKey, PendingChunk>>>,
+}
+
+impl StreamSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of a new session, which the nodes the chunks go to never saw before, even
+    /// if this node restarted.
+    pub fn new_session() -> u32 {
+        let bytes = Uuid::new_v4().into_bytes();
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    /// Keeps `chunk`, just sent to `target`, until it is acknowledged. A chunk sent again keeps the
+    /// time it was first sent.
+    pub fn record(&self, target: Ipv4Addr, chunk: &StreamChunk) -> Result<(), NodeError> {
+        let now = Instant::now();
+        self.pending
+            .lock()?
+            .entry((target, chunk.session, chunk.seq))
+            .and_modify(|pending| pending.last_sent = now)
+            .or_insert_with(|| PendingChunk {
+                chunk: chunk.clone(),
+                first_sent: now,
+                last_sent: now,
+            });
+        Ok(())
+    }
+
+    /// Handles the answer of `from` to a chunk, which is dropped if `from` stored it and kept to
+    /// be sent again otherwise.
+    ///
+    /// # Returns
+    /// Whether the chunk was waiting for the answer.
+    pub fn acknowledge(&self, from: Ipv4Addr, ack: &StreamChunkAck) -> Result<bool, NodeError> {
+        let mut pending = self.pending.lock()?;
+        let key = (from, ack.session, ack.seq);
+        if ack.stored {
+            return Ok(pending.remove(&key).is_some());
+        }
+        Ok(pending.contains_key(&key))
+    }
+
+    /// Returns the chunks of the nodes `is_live` accepts that waited `STREAM_RETRY_INTERVAL` for
+    /// their answer, marking them as sent again, and drops the ones that expired.
+    ///
+    /// # Returns
+    /// The chunks to send again with their node, and the amount of chunks dropped.
+    pub fn due(
+        &self,
+        is_live: impl Fn(Ipv4Addr) -> bool,
+    ) -> Result<(Vec<(Ipv4Addr, StreamChunk)>, usize), NodeError> {
+        let now = Instant::now();
+        let mut pending = self.pending.lock()?;
+        let before = pending.len();
+        pending.retain(|_, chunk| now.duration_since(chunk.first_sent) < STREAM_CHUNK_TTL);
+        let dropped = before - pending.len();
+
+        let due = pending
+            .iter_mut()
+            .filter(|((target, _, _), chunk)| {
+                now.duration_since(chunk.last_sent) >= STREAM_RETRY_INTERVAL && is_live(*target)
+            })
+            .map(|((target, _, _), chunk)| {
+                chunk.last_sent = now;
+                (*target, chunk.chunk.clone())
+            })
+            .collect();
+        Ok((due, dropped))
+    }
+
+    /// Returns the amount of chunks of `session` waiting for their answer.
+    pub fn len_of(&self, session: u32) -> Result<usize, NodeError> {
+        Ok(self
+            .pending
+            .lock()?
+            .keys()
+            .filter(|(_, chunk_session, _)| *chunk_session == session)
+            .count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(seq: u32) -> StreamChunk {
+        StreamChunk::new(
+            1,
+            seq,
+            "sky",
+            "flights",
+            false,
+            vec![format!("{},EZE;1", seq)],
+        )
+    }
+
+    #[test]
+    fn test_acknowledged_chunks_are_dropped_and_rejected_ones_kept() {
+        let sessions = StreamSessions::new();
+        let target = Ipv4Addr::new(127, 0, 0, 2);
+        sessions.record(target, &chunk(0)).unwrap();
+        sessions.record(target, &chunk(1)).unwrap();
+
+        let stored = StreamChunkAck {
+            session: 1,
+            seq: 0,
+            stored: true,
+        };
+        assert!(sessions.acknowledge(target, &stored).unwrap());
+        let rejected = StreamChunkAck {
+            session: 1,
+            seq: 1,
+            stored: false,
+        };
+        assert!(sessions.acknowledge(target, &rejected).unwrap());
+        // Answers of other nodes do not acknowledge the chunk
+        let other = Ipv4Addr::new(127, 0, 0, 3);
+        assert!(!sessions
+            .acknowledge(other, &StreamChunkAck { seq: 1, ..stored })
+            .unwrap());
+        assert_eq!(sessions.len_of(1).unwrap(), 1);
+        assert_eq!(sessions.len_of(2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unanswered_chunks_are_not_due_before_the_retry_interval() {
+        let sessions = StreamSessions::new();
+        sessions
+            .record(Ipv4Addr::new(127, 0, 0, 2), &chunk(0))
+            .unwrap();
+
+        let (due, dropped) = sessions.due(|_| true).unwrap();
+        assert!(due.is_empty());
+        assert_eq!(dropped, 0);
+        assert_ne!(StreamSessions::new_session(), StreamSessions::new_session());
+    }
+}
@@ -0,0 +1,427 @@
+//! Virtual tables of the `system` keyspace, which are not stored but built on every read from
+//! the state of the node that receives the query. As in Cassandra, they are local to that node.
+//!
+//! - `system.table_stats`: the latest estimate of the droppable data of every table stored by
+//!   the node, refreshed in the background.
+//! - `system.hot_partitions`: the partitions that took most of the writes coordinated by the
+//!   node, by table.
+//! - `system.schema_bootstrap`: the initial schema script run by the node when it formed the
+//!   cluster, if it did.
+//! - `system.local`: the node itself, with the name of its cluster, its location, its tokens and
+//!   the version of its schema.
+//! - `system.peers`: the other nodes of the cluster known by the gossiper of the node, so that
+//!   clients can discover the topology of the cluster from any of them.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use native_protocol::frame::Frame;
+use query_creator::clauses::select_cql::Select;
+use query_creator::clauses::types::{column::Column, datatype::DataType};
+use query_creator::errors::CQLError;
+use query_creator::{CreateClientResponse, Query};
+
+use crate::errors::NodeError;
+use crate::metrics::{HotPartitionMetrics, TableMetrics};
+use crate::schema_script::SchemaBootstrap;
+
+/// Keyspace of the virtual tables.
+pub const SYSTEM_KEYSPACE: &str = "system";
+/// Virtual table with the droppable data statistics of every table.
+pub const TABLE_STATS: &str = "table_stats";
+/// Virtual table with the hottest partitions of every table written through the node.
+pub const HOT_PARTITIONS: &str = "hot_partitions";
+/// Virtual table with the initial schema script run by the node.
+pub const SCHEMA_BOOTSTRAP: &str = "schema_bootstrap";
+/// Virtual table with the node that answers the query.
+pub const LOCAL: &str = "local";
+/// Virtual table with the other nodes of the cluster.
+pub const PEERS: &str = "peers";
+
+/// A node of the cluster, as known by the node the virtual tables are built from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeTopology {
+    pub ip: Ipv4Addr,
+    pub datacenter: String,
+    pub rack: String,
+    pub tokens: Vec<u64>,
+    /// Timestamp of the latest schema change known by the node.
+    pub schema_version: i64,
+}
+
+/// The state of the node the virtual tables are built from.
+pub struct SystemState<'a> {
+    pub table_metrics: &'a TableMetrics,
+    pub hot_partitions: &'a HotPartitionMetrics,
+    pub schema_bootstrap: Option<&'a SchemaBootstrap>,
+    pub cluster_name: &'a str,
+    pub local: NodeTopology,
+    pub peers: Vec<NodeTopology>,
+}
+
+/// Returns the `SELECT` of `query` if it reads a virtual table, which has to be answered by the
+/// node itself instead of the replicas.
+pub fn as_system_select(query: &Query) -> Option<&Select> {
+    match query {
+        Query::Select(select) if select.keyspace_used_name == SYSTEM_KEYSPACE => Some(select),
+        _ => None,
+    }
+}
+
+fn table_stats_columns() -> Vec<Column> {
+    let mut keyspace_name = Column::new("keyspace_name", DataType::String, true, false);
+    keyspace_name.is_partition_key = true;
+    let mut table_name = Column::new("table_name", DataType::String, true, false);
+    table_name.is_clustering_column = true;
+
+    vec![
+        keyspace_name,
+        table_name,
+        Column::new("rows", DataType::Int, false, false),
+        Column::new("expired_rows", DataType::Int, false, false),
+        Column::new("tombstone_cells", DataType::Int, false, false),
+        Column::new("droppable_ratio", DataType::Double, false, false),
+        Column::new("needs_compaction", DataType::Boolean, false, false),
+    ]
+}
+
+fn table_stats_rows(table_metrics: &TableMetrics) -> Vec<Vec<String>> {
+    table_metrics
+        .tables()
+        .map(|((keyspace, table), stats)| {
+            vec![
+                keyspace.clone(),
+                table.clone(),
+                to_int(stats.rows),
+                to_int(stats.expired_rows),
+                to_int(stats.tombstone_cells),
+                stats.droppable_ratio().to_string(),
+                stats.needs_compaction().to_string(),
+            ]
+        })
+        .collect()
+}
+
+fn hot_partitions_columns() -> Vec<Column> {
+    let mut keyspace_name = Column::new("keyspace_name", DataType::String, true, false);
+    keyspace_name.is_partition_key = true;
+    let mut table_name = Column::new("table_name", DataType::String, true, false);
+    table_name.is_clustering_column = true;
+    let mut partition_key = Column::new("partition_key", DataType::String, true, false);
+    partition_key.is_clustering_column = true;
+
+    vec![
+        keyspace_name,
+        table_name,
+        partition_key,
+        Column::new("writes", DataType::Int, false, false),
+        Column::new("error", DataType::Int, false, false),
+        Column::new("share", DataType::Double, false, false),
+    ]
+}
+
+fn hot_partitions_rows(hot_partitions: &HotPartitionMetrics) -> Vec<Vec<String>> {
+    hot_partitions
+        .tables()
+        .flat_map(|((keyspace, table), hottest)| {
+            hottest.into_iter().map(move |hot| {
+                vec![
+                    keyspace.clone(),
+                    table.clone(),
+                    hot.partition,
+                    to_int(hot.writes),
+                    to_int(hot.error),
+                    hot.share.to_string(),
+                ]
+            })
+        })
+        .collect()
+}
+
+fn schema_bootstrap_columns() -> Vec<Column> {
+    let mut source = Column::new("source", DataType::String, true, false);
+    source.is_partition_key = true;
+
+    vec![
+        source,
+        Column::new("statements", DataType::Int, false, false),
+        Column::new("ran_at", DataType::Timestamp, false, false),
+    ]
+}
+
+fn schema_bootstrap_rows(schema_bootstrap: Option<&SchemaBootstrap>) -> Vec<Vec<String>> {
+    schema_bootstrap
+        .map(|bootstrap| {
+            vec![
+                bootstrap.source.clone(),
+                to_int(bootstrap.statements as u64),
+                bootstrap.ran_at.to_string(),
+            ]
+        })
+        .into_iter()
+        .collect()
+}
+
+fn local_columns() -> Vec<Column> {
+    let mut key = Column::new("key", DataType::String, true, false);
+    key.is_partition_key = true;
+
+    vec![
+        key,
+        Column::new("cluster_name", DataType::String, false, false),
+        Column::new("broadcast_address", DataType::String, false, false),
+        Column::new("listen_address", DataType::String, false, false),
+        Column::new("rpc_address", DataType::String, false, false),
+        Column::new("data_center", DataType::String, false, false),
+        Column::new("rack", DataType::String, false, false),
+        Column::new("release_version", DataType::String, false, false),
+        Column::new("schema_version", DataType::Timestamp, false, false),
+        Column::new("tokens", DataType::String, false, false),
+    ]
+}
+
+fn local_rows(cluster_name: &str, local: &NodeTopology) -> Vec<Vec<String>> {
+    let ip = local.ip.to_string();
+    vec![vec![
+        LOCAL.to_string(),
+        cluster_name.to_string(),
+        ip.clone(),
+        ip.clone(),
+        ip,
+        local.datacenter.clone(),
+        local.rack.clone(),
+        env!("CARGO_PKG_VERSION").to_string(),
+        local.schema_version.to_string(),
+        tokens_value(&local.tokens),
+    ]]
+}
+
+fn peers_columns() -> Vec<Column> {
+    let mut peer = Column::new("peer", DataType::String, true, false);
+    peer.is_partition_key = true;
+
+    vec![
+        peer,
+        Column::new("rpc_address", DataType::String, false, false),
+        Column::new("data_center", DataType::String, false, false),
+        Column::new("rack", DataType::String, false, false),
+        Column::new("schema_version", DataType::Timestamp, false, false),
+        Column::new("tokens", DataType::String, false, false),
+    ]
+}
+
+fn peers_rows(peers: &[NodeTopology]) -> Vec<Vec<String>> {
+    peers
+        .iter()
+        .map(|peer| {
+            vec![
+                peer.ip.to_string(),
+                peer.ip.to_string(),
+                peer.datacenter.clone(),
+                peer.rack.clone(),
+                peer.schema_version.to_string(),
+                tokens_value(&peer.tokens),
+            ]
+        })
+        .collect()
+}
+
+// The tokens are separated by spaces, as the values of a row are separated by commas
+fn tokens_value(tokens: &[u64]) -> String {
+    tokens
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn to_int(value: u64) -> String {
+    value.min(i32::MAX as u64).to_string()
+}
+
+/// Answers a `SELECT` on a virtual table with its rows that match the `WHERE` clause, if any.
+///
+/// Fails with `InvalidTable` if the table does not exist, or `InvalidColumn` if the query selects
+/// a column the table does not have.
+pub fn select(select: &Select, state: &SystemState) -> Result<Frame, NodeError> {
+    let (columns, table_rows) = match select.table_name.as_str() {
+        TABLE_STATS => (table_stats_columns(), table_stats_rows(state.table_metrics)),
+        HOT_PARTITIONS => (
+            hot_partitions_columns(),
+            hot_partitions_rows(state.hot_partitions),
+        ),
+        SCHEMA_BOOTSTRAP => (
+            schema_bootstrap_columns(),
+            schema_bootstrap_rows(state.schema_bootstrap),
+        ),
+        LOCAL => (
+            local_columns(),
+            local_rows(state.cluster_name, &state.local),
+        ),
+        PEERS => (peers_columns(), peers_rows(&state.peers)),
+        _ => return Err(NodeError::CQLError(CQLError::InvalidTable)),
+    };
+    if select.is_count() {
+        return Err(NodeError::CQLError(CQLError::InvalidTable));
+    }
+
+    let column_names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+    let selected = if select.columns.first().is_some_and(|c| c == "*") {
+        column_names.clone()
+    } else {
+        select.columns.clone()
+    };
+    if selected.iter().any(|c| !column_names.contains(c)) {
+        return Err(NodeError::CQLError(CQLError::InvalidColumn));
+    }
+
+    let mut rows = vec![selected.join(",")];
+    for values in table_rows {
+        let register: HashMap<String, String> = column_names.iter().cloned().zip(values).collect();
+
+        if let Some(where_clause) = &select.where_clause {
+            if !where_clause
+                .condition
+                .execute(&register, columns.clone())
+                .map_err(NodeError::CQLError)?
+            {
+                continue;
+            }
+        }
+        let row: Vec<&str> = selected.iter().map(|c| register[c].as_str()).collect();
+        rows.push(row.join(","));
+    }
+
+    if let Some(limit) = select.limit {
+        rows.truncate(limit + 1);
+    }
+
+    Ok(Query::Select(select.clone()).create_client_response(
+        columns,
+        SYSTEM_KEYSPACE.to_string(),
+        rows,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use native_protocol::messages::result::result_;
+
+    use super::*;
+    use crate::storage_engine::table_stats::TableStats;
+
+    fn metrics() -> TableMetrics {
+        let stats = |rows, droppable_cells| TableStats {
+            rows,
+            cells: rows,
+            droppable_cells,
+            ..Default::default()
+        };
+        let mut metrics = TableMetrics::new();
+        metrics.refresh(BTreeMap::from([
+            (("sky".to_string(), "flights".to_string()), stats(10, 5)),
+            (("sky".to_string(), "airports".to_string()), stats(4, 0)),
+            (("ground".to_string(), "gates".to_string()), stats(2, 0)),
+        ]));
+        metrics
+    }
+
+    fn rows_of(query: &str) -> Result<usize, NodeError> {
+        let table_metrics = metrics();
+        let schema_bootstrap = SchemaBootstrap {
+            source: "schema.cql".to_string(),
+            statements: 4,
+            ran_at: 1734379315000,
+        };
+        let mut hot_partitions = HotPartitionMetrics::new();
+        for partition in ["EZE", "EZE", "AEP", "COR"] {
+            hot_partitions.record("sky", "flights", partition);
+        }
+        let node = |ip: [u8; 4], datacenter: &str, tokens: Vec<u64>| NodeTopology {
+            ip: Ipv4Addr::from(ip),
+            datacenter: datacenter.to_string(),
+            rack: "rack1".to_string(),
+            tokens,
+            schema_version: 1734379315000,
+        };
+        let state = SystemState {
+            table_metrics: &table_metrics,
+            hot_partitions: &hot_partitions,
+            schema_bootstrap: Some(&schema_bootstrap),
+            cluster_name: "Test Cluster",
+            local: node([127, 0, 0, 1], "dc1", vec![10, 2000]),
+            peers: vec![
+                node([127, 0, 0, 2], "dc1", vec![300, 4000]),
+                node([127, 0, 0, 3], "dc2", vec![50000]),
+            ],
+        };
+        let Query::Select(select) = query_creator::QueryCreator::new()
+            .handle_query(query.to_string())
+            .map_err(NodeError::CQLError)?
+        else {
+            panic!("not a select");
+        };
+        match super::select(&select, &state)? {
+            Frame::Result(result_::Result::Rows(rows)) => Ok(rows.rows_content.len()),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_table_stats_virtual_table() {
+        assert_eq!(rows_of("SELECT * FROM system.table_stats").unwrap(), 3);
+        assert_eq!(
+            rows_of("SELECT table_name, needs_compaction FROM system.table_stats WHERE keyspace_name = 'sky'")
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            rows_of("SELECT * FROM system.table_stats LIMIT 1").unwrap(),
+            1
+        );
+
+        assert!(rows_of("SELECT * FROM system.columns").is_err());
+        assert!(rows_of("SELECT size FROM system.table_stats").is_err());
+    }
+
+    #[test]
+    fn test_hot_partitions_virtual_table() {
+        assert_eq!(rows_of("SELECT * FROM system.hot_partitions").unwrap(), 3);
+        assert_eq!(
+            rows_of(
+                "SELECT partition_key, writes FROM system.hot_partitions WHERE keyspace_name = 'sky' AND table_name = 'flights' LIMIT 1"
+            )
+            .unwrap(),
+            1
+        );
+        assert!(rows_of("SELECT rows FROM system.hot_partitions").is_err());
+    }
+
+    #[test]
+    fn test_schema_bootstrap_virtual_table() {
+        assert_eq!(rows_of("SELECT * FROM system.schema_bootstrap").unwrap(), 1);
+        assert_eq!(
+            rows_of("SELECT statements FROM system.schema_bootstrap WHERE source = 'other.cql'")
+                .unwrap(),
+            0
+        );
+        assert!(rows_of("SELECT rows FROM system.schema_bootstrap").is_err());
+    }
+
+    #[test]
+    fn test_local_and_peers_virtual_tables() {
+        assert_eq!(rows_of("SELECT * FROM system.local").unwrap(), 1);
+        assert_eq!(
+            rows_of("SELECT cluster_name, tokens FROM system.local WHERE key = 'local'").unwrap(),
+            1
+        );
+        assert_eq!(rows_of("SELECT * FROM system.peers").unwrap(), 2);
+        assert_eq!(
+            rows_of("SELECT peer, tokens FROM system.peers WHERE peer = '127.0.0.3'").unwrap(),
+            1
+        );
+        assert!(rows_of("SELECT peer FROM system.local").is_err());
+    }
+}
@@ -1,131 +1,51 @@
 use gossip::structures::application_state::{KeyspaceSchema, TableSchema};
+use partitioner::{errors::PartitionerError, Partitioner};
+use query_creator::clauses::keyspace::create_keyspace_cql::NETWORK_TOPOLOGY_STRATEGY;
 use query_creator::errors::CQLError;
 use query_creator::{GetTableName, GetUsedKeyspace, Query};
 
+use crate::connection_manager::ConnectionManager;
 use crate::errors::NodeError;
 use crate::internode_protocol::message::InternodeMessage;
-use crate::internode_protocol::InternodeSerializable;
 use crate::Node;
-use std::collections::HashMap;
-use std::io::Write;
-use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
+use native_protocol::compression::Compression;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-/// Attempts to connect to a peer and send a message over the `TcpStream`.
-///
-/// # Purpose
-/// This function manages communication with a peer node in a distributed system.
-/// It reuses existing connections when available, attempts to reconnect if a connection is broken,
-/// and ensures thread-safe access to the shared connections map while sending the message.
-///
-/// # Parameters
-/// - `peer_id: Ipv4Addr`
-///   - The IPv4 address of the peer to connect to.
-/// - `port: u16`
-///   - The port number on which the peer is listening for incoming connections.
-/// - `connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>`
-///   - A thread-safe map of active TCP connections to other nodes.
-///     - Keys are peer addresses (in `String` format).
-///     - Values are `Arc<Mutex<TcpStream>>`, allowing thread-safe access and sharing of streams.
-/// - `message: InternodeMessage`
-///   - The message to send to the peer, serialized using the `InternodeSerializable` trait.
-///
-/// # Returns
-/// - `Result<(), NodeError>`:
-///   - Returns `Ok(())` on successful connection and message transmission.
-///   - Returns `Err(NodeError)` if an error occurs during connection or message handling.
-///
-/// # Behavior
-/// 1. **Existing Connection Handling**:
-///    - Checks if a connection to the peer already exists in the `connections` map.
-///    - If an existing connection is found:
-///      - Acquires a lock on the `TcpStream` and attempts to send the message.
-///      - Ensures the stream is flushed after writing.
-///      - Returns `Err(NodeError::IoError)` if any errors occur during this process.
-/// 2. **New Connection Handling**:
-///    - If no existing connection is found, attempts to establish a new `TcpStream` connection to the peer.
-///    - Adds the new connection to the `connections` map for future reuse.
-///    - Sends the message through the newly established connection and ensures the stream is flushed.
-/// 3. **Thread Safety**:
-///    - Uses `Mutex` locks to ensure safe access to the shared `connections` map and individual streams.
+/// Compression of the internode messages of the nodes of this process that compress them, by IP.
+static INTERNODE_COMPRESSION: Mutex<BTreeMap<Ipv4Addr, Compression>> = Mutex::new(BTreeMap::new());
+
+/// Registers the compression the node at `ip` negotiates on the internode connections it opens.
+pub fn register_internode_compression(
+    ip: Ipv4Addr,
+    compression: Compression,
+) -> Result<(), NodeError> {
+    INTERNODE_COMPRESSION.lock()?.insert(ip, compression);
+    Ok(())
+}
+
+// Returns the compression of the internode messages of the node at `ip`, if it compresses them
+pub(crate) fn internode_compression(ip: &Ipv4Addr) -> Result<Option<Compression>, NodeError> {
+    Ok(INTERNODE_COMPRESSION.lock()?.get(ip).copied())
+}
+
+/// Sends a message to the internode `port` of a peer, through the connection the node keeps to it
+/// in `connections` (see `ConnectionManager::send`).
 ///
 /// # Errors
-/// - Returns `NodeError::LockError` if the `Mutex` lock on the `connections` map or a `TcpStream` fails.
-/// - Returns `NodeError::IoError` for I/O errors during connection, writing, or flushing operations.
-///
-/// # Notes
-/// - **Efficient Reuse**:
-///   - This function optimizes network usage by reusing existing connections where possible.
-/// - **Logging**:
-///   - Logs errors to `stderr` for debugging purposes but does not expose them in the return type.
-/// - **Thread-Safe Design**:
-///   - The function ensures thread safety for shared resources, making it suitable for concurrent environments.
-///
-/// # Importance
-/// This function is critical for maintaining efficient and reliable communication between nodes in a distributed system.
-/// By managing connections dynamically and reusing streams, it minimizes overhead and improves resilience to network issues.
-
+/// - Returns `NodeError::IoError` if the connection can not be opened.
+/// - Returns `NodeError::Unavailable` if the peer is overloaded: the queue of its connection is full.
+/// - Returns `NodeError::InternodeError` if the connection broke while the message was queued.
 pub fn connect_and_send_message(
     peer_id: Ipv4Addr,
     port: u16,
-    connections: Arc<Mutex<HashMap<String, Arc<Mutex<TcpStream>>>>>,
+    connections: ConnectionManager,
     message: InternodeMessage,
 ) -> Result<(), NodeError> {
-    let peer_socket = SocketAddrV4::new(peer_id, port);
-    let peer_addr = peer_socket.to_string();
-
-    // Intentar reutilizar una conexión existente
-    if let Some(existing_stream) = {
-        let connections_guard = connections.lock().map_err(|_| NodeError::LockError)?;
-        connections_guard.get(&peer_addr).cloned()
-    } {
-        let mut stream_guard = existing_stream.lock().map_err(|_| NodeError::LockError)?;
-        if stream_guard.write_all(&message.as_bytes()).is_err() {
-            return Err(NodeError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Error al escribir en el stream",
-            )));
-        }
-        if stream_guard.flush().is_err() {
-            return Err(NodeError::IoError(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Error al hacer flush en el stream",
-            )));
-        }
-        return Ok(());
-    }
-
-    // Si no hay conexión, intentar conectar una vez
-    let stream = TcpStream::connect((peer_id, port))
-        .map_err(|e| {
-            eprintln!("Error al intentar conectar con {:?}: {:?}", peer_addr, e);
-            NodeError::IoError(e)
-        })
-        .map_err(|e| e)?;
-
-    let stream = Arc::new(Mutex::new(stream));
-
-    // Añadir la nueva conexión al HashMap
-    {
-        let mut connections_guard = connections.lock().map_err(|_| NodeError::LockError)?;
-        connections_guard.insert(peer_addr.clone(), Arc::clone(&stream));
-    }
-
-    // Intentar enviar el mensaje a través de la nueva conexión
-    {
-        let mut stream_guard = stream.lock().map_err(|_| NodeError::LockError)?;
-        stream_guard.write_all(&message.as_bytes()).map_err(|e| {
-            eprintln!("Error al escribir en el stream: {:?}", e);
-            NodeError::IoError(e)
-        })?;
-        stream_guard.flush().map_err(|e| {
-            eprintln!("Error al hacer flush en el stream: {:?}", e);
-            NodeError::IoError(e)
-        })?;
-    }
-    Ok(())
+    connections.send(peer_id, port, message)
 }
 
 /// Checks if a keyspace exists for the given query and client ID.
@@ -190,6 +110,34 @@ pub fn check_keyspace(
     Err(NodeError::CQLError(CQLError::InvalidSyntax))
 }
 
+/// Returns the nodes that keep a copy of the data owned by `owner` in a keyspace, besides
+/// `owner` itself.
+///
+/// Keyspaces with `SimpleStrategy` take the `replication_factor - 1` nodes that follow `owner` in
+/// the ring, while keyspaces with `NetworkTopologyStrategy` take the copies of each datacenter
+/// from its own nodes, spread over its racks (see `Partitioner::get_successors_by_datacenter`).
+///
+/// # Errors
+///
+/// Returns a `PartitionerError` if the partitioner has no nodes.
+pub fn get_replicas(
+    partitioner: &Partitioner,
+    owner: Ipv4Addr,
+    keyspace: &KeyspaceSchema,
+) -> Result<Vec<Ipv4Addr>, PartitionerError> {
+    if keyspace.get_replication_class() == NETWORK_TOPOLOGY_STRATEGY {
+        let factors = keyspace
+            .get_datacenters()
+            .into_iter()
+            .map(|(datacenter, factor)| (datacenter, factor as usize))
+            .collect();
+        return partitioner.get_successors_by_datacenter(owner, &factors);
+    }
+
+    let replication_factor = keyspace.get_replication_factor() as usize;
+    partitioner.get_n_successors(owner, replication_factor.saturating_sub(1))
+}
+
 /// Checks if a table exists in the keyspace for the given query and client ID.
 ///
 /// This function attempts to retrieve a table associated with a query. It first ensures
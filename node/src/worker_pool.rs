@@ -0,0 +1,220 @@
+//! Fixed pools of threads the node handles its connections with, and the limits of the work it
+//! takes at once.
+//!
+//! Each connection a node accepts is handled by a worker of a pool for as long as it is open,
+//! instead of a thread of its own: once every worker is busy, new connections are refused right
+//! away, so a flood of clients (thousands of GUIs) can not exhaust the threads and the memory of
+//! the node. The queries of the clients are limited too, as a few connections can send many of
+//! them at once.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::NodeError;
+
+/// Client connections a node handles at once by default.
+pub const DEFAULT_MAX_CLIENT_CONNECTIONS: usize = 1024;
+/// Internode connections a node handles at once by default, one for each node of the cluster
+/// that sends it messages.
+pub const DEFAULT_MAX_INTERNODE_CONNECTIONS: usize = 256;
+/// Client queries a node executes at once by default, as Cassandra's
+/// `native_transport_max_concurrent_requests_in_flight`.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 512;
+
+/// Read repairs a node writes in the background at once. The repairs of a read that finds every
+/// worker busy are dropped, and left to a later read or to `REPAIR`.
+pub const READ_REPAIR_WORKERS: usize = 4;
+
+/// How much work a node takes at once. Work beyond these limits is refused as overloaded, so
+/// drivers send it to another node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConcurrencyLimits {
+    /// Client connections handled at once, each by a worker of the client pool.
+    pub client_connections: usize,
+    /// Internode connections handled at once, each by a worker of the internode pool.
+    pub internode_connections: usize,
+    /// Client queries executed at once, over all the connections.
+    pub concurrent_requests: usize,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self {
+            client_connections: DEFAULT_MAX_CLIENT_CONNECTIONS,
+            internode_connections: DEFAULT_MAX_INTERNODE_CONNECTIONS,
+            concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Up to a fixed amount of threads that run jobs, one at a time each. Workers are started as they
+/// are needed, up to the size of the pool, and wait for the next job once they finish one.
+///
+/// ### Fields
+/// - `name`: What the pool handles, which names its threads and the errors of the jobs it refuses.
+/// - `size`: The most workers the pool has.
+/// - `busy`: The workers running a job, or about to.
+/// - `started`: The workers started so far.
+/// - `jobs`: The jobs taken, waiting for the worker that runs them.
+/// - `receiver`: Where the workers take the jobs from, one worker at a time.
+/// - `workers`: The threads of the workers started, joined by `join`.
+pub struct WorkerPool {
+    name: String,
+    size: usize,
+    busy: Arc<AtomicUsize>,
+    started: AtomicUsize,
+    jobs: Sender<Job>,
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl WorkerPool {
+    /// Creates a pool of up to `size` workers, without starting any of them yet.
+    pub fn new(name: &str, size: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        Self {
+            name: name.to_string(),
+            size,
+            busy: Arc::new(AtomicUsize::new(0)),
+            started: AtomicUsize::new(0),
+            jobs,
+            receiver: Arc::new(Mutex::new(receiver)),
+            workers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs a job in a free worker of the pool, starting a new one if every worker started is
+    /// busy.
+    ///
+    /// # Errors
+    /// - `NodeError::Overloaded` if all the workers of the pool are busy, in which case the job
+    ///   is dropped without running.
+    /// - `NodeError::ThreadError` if a worker can not be started.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) -> Result<(), NodeError> {
+        let busy = self
+            .busy
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |busy| {
+                (busy < self.size).then_some(busy + 1)
+            })
+            .map_err(|_| {
+                NodeError::Overloaded(format!(
+                    "The {} workers of the node are busy ({} of them)",
+                    self.name, self.size
+                ))
+            })?
+            + 1;
+
+        let needs_worker =
+            self.started
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |started| {
+                    (started < busy).then_some(started + 1)
+                });
+        if let Ok(started) = needs_worker {
+            if let Err(e) = self.start_worker(started) {
+                self.started.fetch_sub(1, Ordering::SeqCst);
+                self.busy.fetch_sub(1, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+
+        self.jobs.send(Box::new(job)).map_err(|_| {
+            self.busy.fetch_sub(1, Ordering::SeqCst);
+            NodeError::ThreadError
+        })
+    }
+
+    /// Takes no more jobs, and waits for the workers to finish the ones they took.
+    pub fn join(self) {
+        drop(self.jobs);
+        let workers = match self.workers.into_inner() {
+            Ok(workers) => workers,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for worker in workers {
+            worker.join().ok();
+        }
+    }
+
+    // Starts the thread of a worker, which runs the jobs of the pool until the pool is dropped
+    fn start_worker(&self, number: usize) -> Result<(), NodeError> {
+        let receiver = Arc::clone(&self.receiver);
+        let busy = Arc::clone(&self.busy);
+        let worker = thread::Builder::new()
+            .name(format!("{}-{}", self.name, number))
+            .spawn(move || loop {
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                let Ok(job) = job else {
+                    return;
+                };
+                // A job that panics only ends its connection, not the worker
+                panic::catch_unwind(AssertUnwindSafe(job)).ok();
+                busy.fetch_sub(1, Ordering::SeqCst);
+            })
+            .map_err(|_| NodeError::ThreadError)?;
+        self.workers.lock()?.push(worker);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_jobs_beyond_the_workers_are_refused() {
+        let pool = WorkerPool::new("test", 2);
+        let (release, released) = channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+
+        for _ in 0..2 {
+            let released = Arc::clone(&released);
+            pool.execute(move || {
+                released.lock().unwrap().recv().ok();
+            })
+            .unwrap();
+        }
+        assert!(matches!(pool.execute(|| {}), Err(NodeError::Overloaded(_))));
+
+        // A worker that finishes its job takes the next one
+        release.send(()).unwrap();
+        let (done, finished) = channel();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let done = done.clone();
+            if pool.execute(move || done.send(()).unwrap()).is_ok() {
+                break;
+            }
+            assert!(Instant::now() < deadline, "the worker did not finish");
+            thread::sleep(Duration::from_millis(1));
+        }
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_join_waits_for_the_jobs_taken() {
+        let pool = WorkerPool::new("test", 2);
+        let (done, finished) = channel();
+        for _ in 0..2 {
+            let done = done.clone();
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(50));
+                done.send(()).unwrap();
+            })
+            .unwrap();
+        }
+        pool.join();
+        assert_eq!(finished.try_iter().count(), 2);
+    }
+}
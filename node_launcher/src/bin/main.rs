@@ -1,16 +1,38 @@
-use std::collections::HashMap;
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, BufRead};
 use std::net::Ipv4Addr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
 // Import the Node struct from the "node" library
-use node::Node; // Assumes that Node is defined in the crate "node"
+use node::authorization::TableAuthorizer;
+use node::config::NodeConfig;
+use node::storage_engine::compaction::CompactionSettings;
+use node::storage_engine::lsm::StorageBackend;
+use node::{
+    BandwidthLimits, ClientConnectionLimits, ConcurrencyLimits, ConnectionManager, Node,
+    ReplicationCheck, RequestTimeouts,
+}; // Assumes that Node is defined in the crate "node"
+
+/// Schema script run by the first seed when it forms the cluster, if no other is given.
+const DEFAULT_INITIAL_SCHEMA: &str = "schema.cql";
+
+/// Configuration of the node read if no other is given.
+const DEFAULT_CONFIG: &str = "node.yaml";
+
+/// Time a node shutting down on `SIGTERM` waits for the queries of its clients.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between two checks of whether the process got `SIGTERM`.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set once the process gets `SIGTERM`.
+static TERMINATE: AtomicBool = AtomicBool::new(false);
 
 /// Main entry point to start a node in the distributed system.
 ///
@@ -20,37 +42,288 @@ use node::Node; // Assumes that Node is defined in the crate "node"
 ///
 /// Optionally, a custom path for the node's storage can be provided as a third argument.
 ///
+/// A node can also be started with `--replace <dead_ip>` to take the place (and the token) of a
+/// dead node instead of joining the ring as a new member.
+///
+/// The time the node waits for the replicas of the queries it coordinates can be set, in
+/// milliseconds, with `--read-timeout <ms>` and `--write-timeout <ms>`.
+///
+/// Writes that can not reach a replica are kept as hints for 3 hours, or for the seconds given with
+/// `--hint-ttl <s>`, and sent to the replica once it is back.
+///
+/// The rows streamed to other nodes when the ring changes, and the hints replayed to replicas that
+/// are back, may use any bandwidth towards each node unless capped, in KiB per second, with
+/// `--stream-throughput <KiB/s>` and `--hint-throughput <KiB/s>`.
+///
+/// Client connections stay open for as long as their clients want, unless the node is started with
+/// `--client-idle-timeout <s>`, which closes the ones that send no request for that long, or
+/// `--client-max-lifetime <s>`, which closes them once they were open for that long.
+///
+/// A node handles up to 1024 client connections, 256 internode connections and 512 client queries
+/// at once, or the amounts given with `--max-client-connections <n>`,
+/// `--max-internode-connections <n>` and `--max-concurrent-requests <n>`. The ones beyond them are
+/// refused as overloaded.
+///
+/// Tables are stored as CSV files unless the node is started with `--storage lsm`, which keeps
+/// them in memtables flushed to SSTables instead.
+///
+/// Tables are compacted in the background every minute, or every `--compaction-interval <s>`
+/// seconds. With the LSM backend only the tables with 4 SSTables, or with the amount given with
+/// `--compaction-min-sstables <n>`, are compacted.
+///
+/// Rows are read from disk on every read unless the node is started with `--row-cache <partitions>`,
+/// which keeps in memory the rows read from that many of the most recently read partitions.
+///
+/// Keyspaces created or altered with a replication factor above the live nodes of the ring are
+/// logged as under-replicated, or rejected with an `Invalid` error if the node coordinating the
+/// statement is started with `--replication-check reject`.
+///
+/// Every node takes a single token of the ring unless started with `--num-tokens <n>`, which
+/// splits it into `n` virtual nodes. All the nodes of the cluster must be started with the same amount.
+///
+/// Nodes are placed in `datacenter1` and `rack1` unless started with `--dc <name>` and
+/// `--rack <name>`, which `NetworkTopologyStrategy` keyspaces use to spread their replicas.
+///
+/// The first seed runs the CQL script given with `--initial-schema <file>`, or the `schema.cql` file
+/// of the current directory if there is one, when it forms the cluster, so its keyspaces and tables
+/// exist before any client connects.
+///
+/// The ports, the gossip interval, the TLS certificates and the storage folder of the node are read
+/// from the file given with `--config <file>`, or from the `node.yaml` file of the current directory
+/// if there is one. A storage path given as argument takes precedence over the one of the file.
+///
+/// The node shuts down cleanly once the process gets `SIGTERM` (see `Node::shutdown`): it waits up to
+/// 30 seconds for the queries of its clients, flushes its storage and tells the rest of the cluster it is
+/// leaving before the program exits.
+///
 /// # Usage
 ///
 /// ```sh
-/// cargo run -- <node_ip> [custom_path]
+/// cargo run -- <node_ip> [custom_path] [--replace <dead_ip>] [--read-timeout <ms>] [--write-timeout <ms>] [--num-tokens <n>] [--hint-ttl <s>] [--stream-throughput <KiB/s>] [--hint-throughput <KiB/s>] [--client-idle-timeout <s>] [--client-max-lifetime <s>] [--max-client-connections <n>] [--max-internode-connections <n>] [--max-concurrent-requests <n>] [--storage <csv|lsm>] [--compaction-interval <s>] [--compaction-min-sstables <n>] [--row-cache <partitions>] [--replication-check <warn|reject>] [--dc <name>] [--rack <name>] [--initial-schema <file>] [--config <file>]
 /// ```
 ///
 /// # Example Execution
 ///
 /// ```sh
 /// cargo run -- 192.168.1.2 /path/to/node/storage
+/// cargo run -- 192.168.1.6 --replace 192.168.1.3
+/// cargo run -- 192.168.1.7 --read-timeout 5000 --write-timeout 2000
+/// cargo run -- 192.168.1.8 --num-tokens 256
+/// cargo run -- 192.168.1.9 --hint-ttl 600
+/// cargo run -- 192.168.1.12 --stream-throughput 8192 --hint-throughput 1024
+/// cargo run -- 192.168.1.13 --client-idle-timeout 300 --client-max-lifetime 86400
+/// cargo run -- 192.168.1.18 --max-client-connections 4096 --max-concurrent-requests 1024
+/// cargo run -- 192.168.1.10 --storage lsm
+/// cargo run -- 192.168.1.14 --storage lsm --compaction-interval 300 --compaction-min-sstables 8
+/// cargo run -- 192.168.1.15 --replication-check reject
+/// cargo run -- 192.168.1.17 --row-cache 10000
+/// cargo run -- 192.168.1.11 --dc us_east --rack rack2
+/// cargo run -- 192.168.1.1 --initial-schema ../flight-sim/schema.cql
+/// cargo run -- 192.168.1.16 --config cluster_b.yaml
 /// ```
 ///
 /// # Errors
 ///
 /// The program returns an error if:
 /// - The number of arguments is incorrect.
-/// - The provided IP address is invalid.
+/// - The provided IP address (or the one to replace) is invalid.
+/// - A timeout is not a number of milliseconds, the amount of tokens or SSTables is not a number,
+///   the amount of connections or client queries handled at once is not a positive number,
+///   the hint TTL, a client connection limit or the compaction interval is not a number of
+///   seconds, a throughput is not a number of KiB per second, the storage backend is not `csv`
+///   nor `lsm`, or the replication check is not `warn` nor `reject`.
+/// - `--dc` or `--rack` is not followed by a name.
+/// - The authorization rules file cannot be read or has an invalid rule.
+/// - The initial schema file cannot be read or has a statement that does not create schema.
+/// - The configuration file cannot be read or has an invalid setting.
 /// - The seed_nodes.txt file does not exist or cannot be read.
 /// - The custom path (if provided) cannot be created.
 ///
 /// # Return Values
 ///
-/// - `Ok(())` - The node started successfully.
+/// - `Ok(())` - The node ran until it was shut down.
 /// - `Err(String)` - There was an error starting the node.
 fn main() -> Result<(), String> {
     // Collect command-line arguments
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // Take out the address of the node to replace, if any
+    let replace_ip = match args.iter().position(|arg| arg == "--replace") {
+        Some(i) => {
+            let dead_ip = args
+                .get(i + 1)
+                .ok_or("Missing IP address after --replace".to_string())?;
+            let dead_ip = Ipv4Addr::from_str(dead_ip)
+                .map_err(|_| "Invalid IP address to replace".to_string())?;
+            args.drain(i..i + 2);
+            Some(dead_ip)
+        }
+        None => None,
+    };
+
+    // Take out the request timeouts, if any
+    let mut timeouts = RequestTimeouts::default();
+    if let Some(read) = take_timeout_arg(&mut args, "--read-timeout")? {
+        timeouts.read = read;
+    }
+    if let Some(write) = take_timeout_arg(&mut args, "--write-timeout")? {
+        timeouts.write = write;
+    }
+
+    // Take out the amount of tokens of each node, if given
+    let num_tokens = match args.iter().position(|arg| arg == "--num-tokens") {
+        Some(i) => {
+            let num_tokens = args
+                .get(i + 1)
+                .ok_or("Missing amount after --num-tokens".to_string())?
+                .parse::<usize>()
+                .map_err(|_| "Invalid amount of tokens".to_string())?;
+            args.drain(i..i + 2);
+            Some(num_tokens)
+        }
+        None => None,
+    };
+
+    // Take out the time hints are kept, if given
+    let hint_ttl = match args.iter().position(|arg| arg == "--hint-ttl") {
+        Some(i) => {
+            let secs = args
+                .get(i + 1)
+                .ok_or("Missing seconds after --hint-ttl".to_string())?
+                .parse::<u64>()
+                .map_err(|_| "Invalid seconds after --hint-ttl".to_string())?;
+            args.drain(i..i + 2);
+            Some(Duration::from_secs(secs))
+        }
+        None => None,
+    };
+
+    // Take out the bandwidth limits of the streamed rows and replayed hints, if given
+    let bandwidth_limits = BandwidthLimits {
+        streaming: take_throughput_arg(&mut args, "--stream-throughput")?,
+        hints: take_throughput_arg(&mut args, "--hint-throughput")?,
+    };
+
+    // Take out the limits of the client connections, if given
+    let client_limits = ClientConnectionLimits {
+        idle_timeout: take_seconds_arg(&mut args, "--client-idle-timeout")?,
+        max_lifetime: take_seconds_arg(&mut args, "--client-max-lifetime")?,
+    };
+
+    // Take out the connections and client queries handled at once, if given
+    let defaults = ConcurrencyLimits::default();
+    let concurrency = ConcurrencyLimits {
+        client_connections: take_count_arg(&mut args, "--max-client-connections")?
+            .unwrap_or(defaults.client_connections),
+        internode_connections: take_count_arg(&mut args, "--max-internode-connections")?
+            .unwrap_or(defaults.internode_connections),
+        concurrent_requests: take_count_arg(&mut args, "--max-concurrent-requests")?
+            .unwrap_or(defaults.concurrent_requests),
+    };
+
+    // Take out the storage backend, if given
+    let storage_backend = match args.iter().position(|arg| arg == "--storage") {
+        Some(i) => {
+            let backend = args
+                .get(i + 1)
+                .ok_or("Missing backend after --storage".to_string())?
+                .parse::<StorageBackend>()
+                .map_err(|_| "Invalid backend after --storage (csv or lsm)".to_string())?;
+            args.drain(i..i + 2);
+            Some(backend)
+        }
+        None => None,
+    };
+
+    // Take out the compaction settings, if given
+    let mut compaction = CompactionSettings::default();
+    if let Some(interval) = take_seconds_arg(&mut args, "--compaction-interval")? {
+        compaction.interval = interval;
+    }
+    if let Some(i) = args
+        .iter()
+        .position(|arg| arg == "--compaction-min-sstables")
+    {
+        compaction.min_sstables = args
+            .get(i + 1)
+            .ok_or("Missing amount after --compaction-min-sstables".to_string())?
+            .parse::<usize>()
+            .map_err(|_| "Invalid amount after --compaction-min-sstables".to_string())?;
+        args.drain(i..i + 2);
+    }
+
+    // Take out the partitions of the row cache, if given
+    let row_cache = match args.iter().position(|arg| arg == "--row-cache") {
+        Some(i) => {
+            let partitions = args
+                .get(i + 1)
+                .ok_or("Missing amount after --row-cache".to_string())?
+                .parse::<usize>()
+                .map_err(|_| "Invalid amount after --row-cache".to_string())?;
+            args.drain(i..i + 2);
+            Some(partitions)
+        }
+        None => None,
+    };
+
+    // Take out what is done with the keyspaces with more replicas than live nodes, if given
+    let replication_check = match args.iter().position(|arg| arg == "--replication-check") {
+        Some(i) => {
+            let check = args
+                .get(i + 1)
+                .ok_or("Missing check after --replication-check".to_string())?
+                .parse::<ReplicationCheck>()
+                .map_err(|_| {
+                    "Invalid check after --replication-check (warn or reject)".to_string()
+                })?;
+            args.drain(i..i + 2);
+            check
+        }
+        None => ReplicationCheck::default(),
+    };
+
+    // Take out the rules that authorize the statements of the clients, if given
+    let authorizer = match args.iter().position(|arg| arg == "--authorization") {
+        Some(i) => {
+            let path = args
+                .get(i + 1)
+                .ok_or("Missing rules file after --authorization".to_string())?;
+            let rules = fs::read_to_string(path)
+                .map_err(|_| format!("Failed to read the authorization rules at {}", path))?;
+            let authorizer = rules
+                .parse::<TableAuthorizer>()
+                .map_err(|e| e.to_string())?;
+            args.drain(i..i + 2);
+            Some(authorizer)
+        }
+        None => None,
+    };
+
+    // Take out the datacenter and rack of the node, if given
+    let datacenter = take_name_arg(&mut args, "--dc")?;
+    let rack = take_name_arg(&mut args, "--rack")?;
+
+    // Take out the schema run when the node forms the cluster, or look for the default one
+    let initial_schema = match take_name_arg(&mut args, "--initial-schema")? {
+        Some(path) => Some(path),
+        None if Path::new(DEFAULT_INITIAL_SCHEMA).exists() => {
+            Some(DEFAULT_INITIAL_SCHEMA.to_string())
+        }
+        None => None,
+    };
+
+    // Take out the configuration of the node, or look for the default one
+    let config = match take_name_arg(&mut args, "--config")? {
+        Some(path) => NodeConfig::load(Path::new(&path)).map_err(|e| e.to_string())?,
+        None if Path::new(DEFAULT_CONFIG).exists() => {
+            NodeConfig::load(Path::new(DEFAULT_CONFIG)).map_err(|e| e.to_string())?
+        }
+        None => NodeConfig::default(),
+    };
 
     // Ensure at least one argument (node IP) is provided
     if args.len() < 2 || args.len() > 3 {
-        return Err("Usage: program <node_ip> [custom_path]".to_string());
+        return Err("Usage: program <node_ip> [custom_path] [--replace <dead_ip>] [--read-timeout <ms>] [--write-timeout <ms>] [--num-tokens <n>] [--hint-ttl <s>] [--stream-throughput <KiB/s>] [--hint-throughput <KiB/s>] [--client-idle-timeout <s>] [--client-max-lifetime <s>] [--max-client-connections <n>] [--max-internode-connections <n>] [--max-concurrent-requests <n>] [--storage <csv|lsm>] [--compaction-interval <s>] [--compaction-min-sstables <n>] [--row-cache <partitions>] [--replication-check <warn|reject>] [--dc <name>] [--rack <name>] [--authorization <rules_file>] [--initial-schema <file>] [--config <file>]".to_string());
     }
 
     // Pause for a brief moment before continuing, allowing other nodes to initialize
@@ -60,8 +333,11 @@ fn main() -> Result<(), String> {
     let node_ip = Ipv4Addr::from_str(&args[1]).map_err(|_| "Invalid IP address".to_string())?;
 
     // Determine the path for node storage
-    let path_buf = if args.len() == 3 {
-        let custom_path = PathBuf::from(&args[2]);
+    let custom_path = match args.get(2) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config.storage_path.clone(),
+    };
+    let path_buf = if let Some(custom_path) = custom_path {
         if !custom_path.exists() {
             fs::create_dir_all(&custom_path)
                 .map_err(|_| format!("Failed to create directory at {}", custom_path.display()))?;
@@ -74,19 +350,217 @@ fn main() -> Result<(), String> {
     let seed_ips = read_seed_ips("seed_nodes.txt")?;
 
     // Create the node with the specified IP and the list of seed IPs
-    let node = Arc::new(Mutex::new(
-        Node::new(node_ip, seed_ips, path_buf).map_err(|e| e.to_string())?,
-    ));
+    let mut node = Node::new(node_ip, seed_ips, path_buf, config)
+        .map_err(|e| e.to_string())?
+        .with_request_timeouts(timeouts)
+        .with_bandwidth_limits(bandwidth_limits)
+        .with_client_connection_limits(client_limits)
+        .with_concurrency_limits(concurrency)
+        .with_compaction(compaction)
+        .with_replication_check(replication_check);
+    if let Some(num_tokens) = num_tokens {
+        node = node
+            .with_num_tokens(num_tokens)
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(hint_ttl) = hint_ttl {
+        node = node.with_hint_ttl(hint_ttl);
+    }
+    if let Some(storage_backend) = storage_backend {
+        node = node
+            .with_storage_backend(storage_backend)
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(partitions) = row_cache {
+        node = node.with_row_cache(partitions).map_err(|e| e.to_string())?;
+    }
+    if let Some(authorizer) = authorizer {
+        node = node.with_authorizer(Arc::new(authorizer));
+    }
+    if datacenter.is_some() || rack.is_some() {
+        node = node
+            .with_location(
+                datacenter.as_deref().unwrap_or("datacenter1"),
+                rack.as_deref().unwrap_or("rack1"),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(path) = initial_schema {
+        let script = fs::read_to_string(&path)
+            .map_err(|_| format!("Failed to read the initial schema at {}", path))?;
+        node = node
+            .with_initial_schema(&path, &script)
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(dead_ip) = replace_ip {
+        node = node
+            .with_replace_address(dead_ip)
+            .map_err(|e| e.to_string())?;
+    }
+    let node = Arc::new(Mutex::new(node));
 
     // Initialize the connections map
-    let connections = Arc::new(Mutex::new(HashMap::new()));
+    let connections = ConnectionManager::new();
+
+    // Shut the node down cleanly once the process is asked to terminate
+    let stopper = shut_down_on_sigterm(Arc::clone(&node))?;
 
-    // Start the node with the specified IP and connection map
-    Node::start(Arc::clone(&node), Arc::clone(&connections)).map_err(|e| e.to_string())?;
+    // Start the node with the specified IP and connection map, until it is shut down
+    Node::start(Arc::clone(&node), connections).map_err(|e| e.to_string())?;
+    // A decommissioned node stops without being asked to terminate, and then nobody stops the thread
+    if TERMINATE.load(Ordering::SeqCst) {
+        stopper
+            .join()
+            .map_err(|_| "The node could not be shut down".to_string())?;
+    }
 
     Ok(())
 }
 
+/// Removes the flag with the given name and its value from the arguments, if present.
+///
+/// # Returns
+///
+/// - `Ok(Some(Duration))` - The timeout given after the flag, in milliseconds.
+/// - `Ok(None)` - The flag is not present.
+/// - `Err(String)` - The value after the flag is missing or is not a number.
+fn take_timeout_arg(args: &mut Vec<String>, flag: &str) -> Result<Option<Duration>, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let millis: u64 = args
+        .get(i + 1)
+        .ok_or(format!("Missing milliseconds after {}", flag))?
+        .parse()
+        .map_err(|_| format!("Invalid milliseconds after {}", flag))?;
+    args.drain(i..i + 2);
+    Ok(Some(Duration::from_millis(millis)))
+}
+
+/// Removes the flag with the given name and the seconds after it from the arguments, if present.
+///
+/// # Returns
+///
+/// - `Ok(Some(Duration))` - The time given after the flag, in seconds.
+/// - `Ok(None)` - The flag is not present.
+/// - `Err(String)` - The value after the flag is missing or is not a number.
+fn take_seconds_arg(args: &mut Vec<String>, flag: &str) -> Result<Option<Duration>, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let secs: u64 = args
+        .get(i + 1)
+        .ok_or(format!("Missing seconds after {}", flag))?
+        .parse()
+        .map_err(|_| format!("Invalid seconds after {}", flag))?;
+    args.drain(i..i + 2);
+    Ok(Some(Duration::from_secs(secs)))
+}
+
+/// Removes the flag with the given name and the amount after it from the arguments, if present.
+///
+/// # Returns
+///
+/// - `Ok(Some(usize))` - The amount given after the flag.
+/// - `Ok(None)` - The flag is not present.
+/// - `Err(String)` - The value after the flag is missing or is not a positive number.
+fn take_count_arg(args: &mut Vec<String>, flag: &str) -> Result<Option<usize>, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let count: usize = args
+        .get(i + 1)
+        .ok_or(format!("Missing amount after {}", flag))?
+        .parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or(format!("Invalid amount after {}", flag))?;
+    args.drain(i..i + 2);
+    Ok(Some(count))
+}
+
+/// Removes the flag with the given name and its throughput, in KiB per second, from the arguments,
+/// if present.
+///
+/// # Returns
+///
+/// - `Ok(Some(u64))` - The throughput given after the flag, in bytes per second.
+/// - `Ok(None)` - The flag is not present.
+/// - `Err(String)` - The value after the flag is missing, is not a number or is too big.
+fn take_throughput_arg(args: &mut Vec<String>, flag: &str) -> Result<Option<u64>, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let bytes = args
+        .get(i + 1)
+        .ok_or(format!("Missing KiB per second after {}", flag))?
+        .parse()
+        .ok()
+        .and_then(|kibibytes: u64| kibibytes.checked_mul(1024))
+        .ok_or(format!("Invalid KiB per second after {}", flag))?;
+    args.drain(i..i + 2);
+    Ok(Some(bytes))
+}
+
+/// Removes the flag with the given name and the name after it from the arguments, if present.
+///
+/// # Returns
+///
+/// - `Ok(Some(String))` - The name given after the flag.
+/// - `Ok(None)` - The flag is not present.
+/// - `Err(String)` - The name after the flag is missing.
+fn take_name_arg(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let name = args
+        .get(i + 1)
+        .ok_or(format!("Missing name after {}", flag))?
+        .clone();
+    args.drain(i..i + 2);
+    Ok(Some(name))
+}
+
+/// Handler of `SIGTERM`, which only sets `TERMINATE`, as little else is safe in a signal handler.
+extern "C" fn request_termination(_signal: libc::c_int) {
+    TERMINATE.store(true, Ordering::SeqCst);
+}
+
+/// Installs the handler of `SIGTERM`, and starts the thread that shuts the node down once the process
+/// gets it.
+///
+/// # Returns
+///
+/// - `Ok(JoinHandle)` - The thread that shuts the node down, which ends once it did.
+/// - `Err(String)` - The handler could not be installed.
+fn shut_down_on_sigterm(node: Arc<Mutex<Node>>) -> Result<JoinHandle<()>, String> {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    let previous = unsafe {
+        libc::signal(
+            libc::SIGTERM,
+            request_termination as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+    if previous == libc::SIG_ERR {
+        return Err("Failed to install the handler of SIGTERM".to_string());
+    }
+
+    Ok(thread::spawn(move || {
+        while !TERMINATE.load(Ordering::SeqCst) {
+            thread::sleep(SIGNAL_CHECK_INTERVAL);
+        }
+        println!("SIGTERM received, shutting the node down");
+        match Node::shutdown(&node, SHUTDOWN_TIMEOUT) {
+            Ok(report) if report.is_clean() => {}
+            Ok(report) => eprintln!(
+                "{} client queries were still open when the node shut down",
+                report.abandoned_queries
+            ),
+            Err(e) => eprintln!("The node could not be shut down cleanly: {}", e),
+        }
+    }))
+}
+
 /// Reads seed IP addresses from a file and returns them as a vector of `Ipv4Addr`.
 ///
 /// This function expects a file named `seed_nodes.txt` in the current directory,
@@ -8,6 +8,8 @@ use std::fmt::{self, Display};
 /// - `NodeNotFound`: the IP address could not be found in the partitioner.
 /// - `HashError`: an error occurred while hashing a value.
 /// - `EmptyPartitioner`: attempted to retrieve an IP but the partitioner has no nodes.
+/// - `TokenAlreadyTaken`: another node is already placed at the requested token.
+/// - `MultipleTokens`: the node has more than one token, so it can not be moved to a single one.
 ///
 /// These errors allow for more detailed handling and logging of unexpected issues.
 #[derive(Debug, PartialEq)]
@@ -16,6 +18,8 @@ pub enum PartitionerError {
     NodeNotFound,
     HashError,
     EmptyPartitioner,
+    TokenAlreadyTaken,
+    MultipleTokens,
 }
 
 impl Display for PartitionerError {
@@ -36,6 +40,14 @@ impl Display for PartitionerError {
                 f,
                 "[EmptyPartitioner]: The partitioner has no nodes available"
             ),
+            PartitionerError::TokenAlreadyTaken => write!(
+                f,
+                "[TokenAlreadyTaken]: Another node already owns the token"
+            ),
+            PartitionerError::MultipleTokens => write!(
+                f,
+                "[MultipleTokens]: The node has more than one token and can not be moved"
+            ),
         }
     }
 }
@@ -1,14 +1,38 @@
 use errors::PartitionerError;
 use murmur3::murmur3_32;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::io::Cursor;
 use std::net::Ipv4Addr;
+use std::ops::Range;
 pub mod errors;
 
+/// Number of tokens in the ring, as tokens are 32 bit hashes.
+pub const RING_SIZE: u64 = 1 << 32;
+
+/// Tokens each node takes by default: a single one, the hash of its IP address.
+pub const DEFAULT_VNODES: usize = 1;
+
+/// Datacenter of the nodes whose location is not set, as in Cassandra.
+pub const DEFAULT_DATACENTER: &str = "datacenter1";
+
+/// Rack of the nodes whose location is not set, as in Cassandra.
+pub const DEFAULT_RACK: &str = "rack1";
+
+/// Maps the tokens of the ring to the nodes that own them.
+///
+/// A node can own several tokens (virtual nodes, or vnodes), spread over the ring. With many
+/// tokens per node, as the 256 of Cassandra, each node owns many small ranges instead of a single
+/// big one, so the data is evenly distributed even with few nodes and a node joining or leaving
+/// the ring only moves small slices from (or to) every other node.
+///
+/// Nodes can also be given a datacenter and a rack, to place the replicas of
+/// `NetworkTopologyStrategy` keyspaces (see `get_successors_by_datacenter`).
 #[derive(Clone)]
 pub struct Partitioner {
     nodes: BTreeMap<u64, Ipv4Addr>,
+    vnodes: usize,
+    locations: HashMap<Ipv4Addr, (String, String)>,
 }
 
 impl Default for Partitioner {
@@ -25,9 +49,45 @@ impl Partitioner {
     pub fn new() -> Self {
         Partitioner {
             nodes: BTreeMap::new(),
+            vnodes: DEFAULT_VNODES,
+            locations: HashMap::new(),
         }
     }
 
+    /// Sets the amount of tokens taken by the nodes added with `add_node`. An amount of 0 is taken as 1.
+    ///
+    /// Every node of the cluster must use the same amount, so they all build the same ring.
+    pub fn with_vnodes(mut self, vnodes: usize) -> Self {
+        self.vnodes = vnodes.max(1);
+        self
+    }
+
+    /// Returns the amount of tokens taken by the nodes added with `add_node`.
+    pub fn vnodes(&self) -> usize {
+        self.vnodes
+    }
+
+    /// Sets the datacenter and rack of a node. The location is kept even if the node is not (or
+    /// no longer) in the ring.
+    pub fn set_location(&mut self, ip: Ipv4Addr, datacenter: &str, rack: &str) {
+        self.locations
+            .insert(ip, (datacenter.to_string(), rack.to_string()));
+    }
+
+    /// Returns the datacenter of a node, `DEFAULT_DATACENTER` if its location is not set.
+    pub fn get_datacenter(&self, ip: &Ipv4Addr) -> &str {
+        self.locations
+            .get(ip)
+            .map_or(DEFAULT_DATACENTER, |(datacenter, _rack)| datacenter)
+    }
+
+    /// Returns the rack of a node, `DEFAULT_RACK` if its location is not set.
+    pub fn get_rack(&self, ip: &Ipv4Addr) -> &str {
+        self.locations
+            .get(ip)
+            .map_or(DEFAULT_RACK, |(_datacenter, rack)| rack)
+    }
+
     /// Hashes a value using the `murmur3_32` algorithm and returns the hash as a `u64`.
     ///
     /// # Parameters
@@ -42,7 +102,15 @@ impl Partitioner {
             .map_err(|_| PartitionerError::HashError)
     }
 
-    /// Adds a new node to the partitioner using its IP address.
+    /// Returns the token of a value, the position in the ring of the partitions with that key.
+    ///
+    /// # Errors
+    /// - `PartitionerError::HashError` - If there is an issue hashing the value.
+    pub fn token_of<T: AsRef<[u8]>>(value: T) -> Result<u64, PartitionerError> {
+        Self::hash_value(value)
+    }
+
+    /// Adds a new node to the partitioner at its default tokens, see `default_tokens`.
     ///
     /// # Parameters
     /// - `ip`: The IP address of the node to add.
@@ -53,17 +121,175 @@ impl Partitioner {
     ///
     /// # Errors
     /// - `PartitionerError::HashError` - If there is an issue hashing the IP address.
-    /// - `PartitionerError::NodeAlreadyExists` - If the node's hash already exists in the partitioner.
+    /// - `PartitionerError::NodeAlreadyExists` - If the node is already in the partitioner.
+    /// - `PartitionerError::TokenAlreadyTaken` - If another node already owns one of the tokens.
     pub fn add_node(&mut self, ip: Ipv4Addr) -> Result<(), PartitionerError> {
-        let hash = Self::hash_value(ip.to_string())?;
-        if self.nodes.contains_key(&hash) {
+        let tokens = self.default_tokens(&ip)?;
+        self.add_node_with_tokens(ip, &tokens)
+    }
+
+    /// Adds a new node to the partitioner at the given token instead of the hash of its IP address.
+    ///
+    /// # Parameters
+    /// - `ip`: The IP address of the node to add.
+    /// - `token`: The position of the node in the ring.
+    ///
+    /// # Returns
+    /// * `Result<(), PartitionerError>` - Returns `Ok(())` if the node is successfully added.
+    ///
+    /// # Errors
+    /// - `PartitionerError::NodeAlreadyExists` - If the node is already in the partitioner.
+    /// - `PartitionerError::TokenAlreadyTaken` - If another node already owns the token.
+    pub fn add_node_with_token(
+        &mut self,
+        ip: Ipv4Addr,
+        token: u64,
+    ) -> Result<(), PartitionerError> {
+        self.add_node_with_tokens(ip, &[token])
+    }
+
+    /// Adds a new node to the partitioner at the given tokens, one for each of its virtual nodes.
+    ///
+    /// # Parameters
+    /// - `ip`: The IP address of the node to add.
+    /// - `tokens`: The positions of the node in the ring. Repeated tokens are taken once.
+    ///
+    /// # Returns
+    /// * `Result<(), PartitionerError>` - Returns `Ok(())` if the node is successfully added. The
+    ///   partitioner is left untouched otherwise.
+    ///
+    /// # Errors
+    /// - `PartitionerError::NodeAlreadyExists` - If the node is already in the partitioner.
+    /// - `PartitionerError::TokenAlreadyTaken` - If another node already owns one of the tokens.
+    pub fn add_node_with_tokens(
+        &mut self,
+        ip: Ipv4Addr,
+        tokens: &[u64],
+    ) -> Result<(), PartitionerError> {
+        if self.contains_node(&ip) {
             return Err(PartitionerError::NodeAlreadyExists);
         }
-        self.nodes.insert(hash, ip);
+        if tokens.iter().any(|token| self.nodes.contains_key(token)) {
+            return Err(PartitionerError::TokenAlreadyTaken);
+        }
+        for token in tokens {
+            self.nodes.insert(*token, ip);
+        }
 
         Ok(())
     }
 
+    /// Replaces every token of a node already in the partitioner with the given ones.
+    ///
+    /// # Parameters
+    /// - `ip`: The IP address of the node.
+    /// - `tokens`: The new positions of the node in the ring.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u64>, PartitionerError>` - Returns the previous tokens of the node, sorted.
+    ///   The partitioner is left untouched on failure.
+    ///
+    /// # Errors
+    /// - `PartitionerError::NodeNotFound` - If the node is not in the partitioner.
+    /// - `PartitionerError::TokenAlreadyTaken` - If another node already owns one of the tokens.
+    pub fn set_tokens(
+        &mut self,
+        ip: Ipv4Addr,
+        tokens: &[u64],
+    ) -> Result<Vec<u64>, PartitionerError> {
+        let old_tokens = self.get_tokens(&ip);
+        if old_tokens.is_empty() {
+            return Err(PartitionerError::NodeNotFound);
+        }
+        if tokens
+            .iter()
+            .any(|token| self.nodes.get(token).is_some_and(|owner| *owner != ip))
+        {
+            return Err(PartitionerError::TokenAlreadyTaken);
+        }
+
+        for token in &old_tokens {
+            self.nodes.remove(token);
+        }
+        for token in tokens {
+            self.nodes.insert(*token, ip);
+        }
+
+        Ok(old_tokens)
+    }
+
+    /// Moves a node already in the partitioner to a new token.
+    ///
+    /// As in Cassandra, only nodes with a single token can be moved: the ranges of nodes with
+    /// virtual nodes are already small and evenly spread.
+    ///
+    /// # Parameters
+    /// - `ip`: The IP address of the node to move.
+    /// - `token`: The new position of the node in the ring.
+    ///
+    /// # Returns
+    /// * `Result<u64, PartitionerError>` - Returns the previous token of the node.
+    ///
+    /// # Errors
+    /// - `PartitionerError::NodeNotFound` - If the node is not in the partitioner.
+    /// - `PartitionerError::MultipleTokens` - If the node has more than one token.
+    /// - `PartitionerError::TokenAlreadyTaken` - If another node already owns the token.
+    pub fn move_node(&mut self, ip: Ipv4Addr, token: u64) -> Result<u64, PartitionerError> {
+        match self.get_tokens(&ip)[..] {
+            [] => Err(PartitionerError::NodeNotFound),
+            [old_token] => {
+                self.set_tokens(ip, &[token])?;
+                Ok(old_token)
+            }
+            _ => Err(PartitionerError::MultipleTokens),
+        }
+    }
+
+    /// Returns the lowest token of the node with the given IP address, if it is in the partitioner.
+    /// Nodes with virtual nodes have more tokens, see `get_tokens`.
+    pub fn get_token(&self, ip: &Ipv4Addr) -> Option<u64> {
+        self.nodes
+            .iter()
+            .find(|(_token, addr)| *addr == ip)
+            .map(|(token, _addr)| *token)
+    }
+
+    /// Returns every token of the node with the given IP address, sorted. It is empty if the node
+    /// is not in the partitioner.
+    pub fn get_tokens(&self, ip: &Ipv4Addr) -> Vec<u64> {
+        self.nodes
+            .iter()
+            .filter(|(_token, addr)| *addr == ip)
+            .map(|(token, _addr)| *token)
+            .collect()
+    }
+
+    /// Returns the token a node takes when no token is assigned to it: the hash of its IP address.
+    ///
+    /// # Errors
+    /// - `PartitionerError::HashError` - If there is an issue hashing the IP address.
+    pub fn default_token(ip: &Ipv4Addr) -> Result<u64, PartitionerError> {
+        Self::hash_value(ip.to_string())
+    }
+
+    /// Returns the tokens a node takes when no token is assigned to it, one for each virtual node:
+    /// its default token followed by the hashes of its IP address with the number of each other
+    /// virtual node appended (`ip#1`, `ip#2`, ...). Every node computes the same tokens for an IP.
+    ///
+    /// # Errors
+    /// - `PartitionerError::HashError` - If there is an issue hashing the IP address.
+    pub fn default_tokens(&self, ip: &Ipv4Addr) -> Result<Vec<u64>, PartitionerError> {
+        let mut tokens = vec![Self::default_token(ip)?];
+        for vnode in 1..self.vnodes {
+            let token = Self::hash_value(format!("{}#{}", ip, vnode))?;
+            // Hashes of the same node may collide, the token is taken once
+            if !tokens.contains(&token) {
+                tokens.push(token);
+            }
+        }
+        Ok(tokens)
+    }
+
     /// Removes a node from the partitioner based on its IP address.
     ///
     /// # Parameters
@@ -77,21 +303,19 @@ impl Partitioner {
     /// - `PartitionerError::HashError` - If there is an issue hashing the IP address.
     /// - `PartitionerError::NodeNotFound` - If the node is not found in the partitioner.
     pub fn remove_node(&mut self, ip: Ipv4Addr) -> Result<Ipv4Addr, PartitionerError> {
-        let hash = Self::hash_value(ip.to_string())?;
+        let tokens = self.get_tokens(&ip);
+        if tokens.is_empty() {
+            return Err(PartitionerError::NodeNotFound);
+        }
+        for token in tokens {
+            self.nodes.remove(&token);
+        }
 
-        self.nodes
-            .remove(&hash)
-            .ok_or(PartitionerError::NodeNotFound)
+        Ok(ip)
     }
 
     pub fn node_already_in_partitioner(&mut self, ip: &Ipv4Addr) -> Result<bool, PartitionerError> {
-        let hash = Self::hash_value(ip.to_string())?;
-
-        if self.nodes.contains_key(&hash) {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
+        Ok(self.get_token(ip).is_some())
     }
     /// Retrieves the IP address of the node responsible for a given value.
     ///
@@ -106,12 +330,16 @@ impl Partitioner {
     /// - `PartitionerError::HashError` - If there is an issue hashing the value.
     /// - `PartitionerError::EmptyPartitioner` - If the partitioner contains no nodes.
     pub fn get_ip<T: AsRef<[u8]>>(&self, value: T) -> Result<Ipv4Addr, PartitionerError> {
-        let hash = Self::hash_value(value)?;
-        if self.nodes.is_empty() {
-            return Err(PartitionerError::EmptyPartitioner);
-        }
+        self.get_ip_of_token(Self::hash_value(value)?)
+    }
 
-        match self.nodes.range(hash..).next() {
+    /// Retrieves the IP address of the node that owns the given token: the one with the closest
+    /// token at or after it, wrapping around the end of the ring.
+    ///
+    /// # Errors
+    /// - `PartitionerError::EmptyPartitioner` - If the partitioner contains no nodes.
+    pub fn get_ip_of_token(&self, token: u64) -> Result<Ipv4Addr, PartitionerError> {
+        match self.nodes.range(token..).next() {
             Some((_key, addr)) => Ok(*addr),
             None => self
                 .nodes
@@ -125,9 +353,29 @@ impl Partitioner {
     /// Returns a list of all nodes' IP addresses within the partitioner.
     ///
     /// # Returns
-    /// * `Vec<Ipv4Addr>` - A vector of IP addresses of all nodes.
+    /// * `Vec<Ipv4Addr>` - A vector of IP addresses of all nodes, each once, sorted by their lowest token.
     pub fn get_nodes(&self) -> Vec<Ipv4Addr> {
-        self.nodes.values().cloned().collect()
+        let mut nodes = Vec::new();
+        for ip in self.nodes.values() {
+            if !nodes.contains(ip) {
+                nodes.push(*ip);
+            }
+        }
+        nodes
+    }
+
+    /// Returns a digest of the tokens of the ring and the nodes that own them, so two nodes can
+    /// tell whether they send the partitions to the same replicas by comparing their digests.
+    ///
+    /// The locations of the nodes are not part of it. Two different rings may get the same
+    /// digest, although it is very unlikely.
+    pub fn ring_digest(&self) -> u64 {
+        let ring: String = self
+            .nodes
+            .iter()
+            .map(|(token, ip)| format!("{}={};", token, ip))
+            .collect();
+        Self::hash_value(ring).unwrap_or_default()
     }
 
     /// Checks if a node with the given IP address exists in the partitioner.
@@ -138,13 +386,16 @@ impl Partitioner {
     /// # Returns
     /// * `bool` - Returns `true` if the node exists, `false` otherwise.
     pub fn contains_node(&self, ip: &Ipv4Addr) -> bool {
-        let hash = Self::hash_value(ip.to_string()).unwrap_or_default();
-        self.nodes.contains_key(&hash)
+        self.get_token(ip).is_some()
     }
 
     /// Retrieves the IP addresses of the next `n` successor nodes in the partitioner,
     /// starting from a given IP address and skipping the starting IP address.
     ///
+    /// The ring is walked from the lowest token of the node, and nodes with virtual nodes are taken
+    /// once, at their first token found, so the successors are always `n` different nodes (or every
+    /// other node if there are not that many).
+    ///
     /// # Parameters
     /// - `ip`: The starting IP address.
     /// - `n`: The number of successors to retrieve.
@@ -164,40 +415,190 @@ impl Partitioner {
             return Err(PartitionerError::EmptyPartitioner);
         }
 
-        let hash = Self::hash_value(ip.to_string())?;
+        let hash = match self.get_token(&ip) {
+            Some(token) => token,
+            None => Self::hash_value(ip.to_string())?,
+        };
         let mut successors = Vec::new();
 
-        for (_key, addr) in self.nodes.range(hash..) {
+        // Walks the whole ring once, starting at the token of the node
+        for (_key, addr) in self.nodes.range(hash..).chain(self.nodes.range(..hash)) {
             if successors.len() == n {
                 break;
             }
-            if *addr != ip {
+            if *addr != ip && !successors.contains(addr) {
                 successors.push(*addr);
             }
         }
+        Ok(successors)
+    }
+
+    /// Retrieves the replicas of the data owned by a node for a `NetworkTopologyStrategy`
+    /// keyspace, which keeps `factors[dc]` copies in each datacenter `dc`, skipping the node.
+    ///
+    /// As in Cassandra, the ring is walked from the lowest token of the node and each datacenter
+    /// takes the first nodes found in it, preferring racks it has not taken a node from yet, so
+    /// a rack going down takes as few copies as possible with it. Nodes skipped for being in an
+    /// already taken rack are only taken once every rack of their datacenter was, or when there
+    /// are no more nodes in the datacenter.
+    ///
+    /// The node always keeps a copy, as it owns the data, which counts for its datacenter.
+    ///
+    /// # Parameters
+    /// - `ip`: The node that owns the data.
+    /// - `factors`: The amount of copies of each datacenter. Datacenters left out keep no copies.
+    ///
+    /// # Returns
+    /// * `Result<Vec<Ipv4Addr>, PartitionerError>` - Returns the replicas, fewer than asked if a
+    ///   datacenter does not have enough nodes.
+    ///
+    /// # Errors
+    /// - `PartitionerError::EmptyPartitioner` - If there are no nodes in the partitioner.
+    /// - `PartitionerError::HashError` - If there is an issue hashing the IP address.
+    pub fn get_successors_by_datacenter(
+        &self,
+        ip: Ipv4Addr,
+        factors: &BTreeMap<String, usize>,
+    ) -> Result<Vec<Ipv4Addr>, PartitionerError> {
+        // Every other node of the ring, in the order the ring is walked from the node
+        let successors = self.get_n_successors(ip, usize::MAX)?;
+
+        let mut racks_by_datacenter: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for node in self.get_nodes() {
+            racks_by_datacenter
+                .entry(self.get_datacenter(&node))
+                .or_default()
+                .insert(self.get_rack(&node));
+        }
 
-        if successors.len() < n {
-            for (_key, addr) in self.nodes.iter() {
-                if successors.len() == n {
-                    break;
-                }
-                if *addr != ip && !successors.contains(addr) {
-                    successors.push(*addr);
-                }
+        let mut taken: HashMap<&str, usize> = HashMap::new();
+        let mut seen_racks: HashMap<&str, HashSet<&str>> = HashMap::new();
+        let mut skipped: Vec<Ipv4Addr> = Vec::new();
+
+        *taken.entry(self.get_datacenter(&ip)).or_default() += 1;
+        seen_racks
+            .entry(self.get_datacenter(&ip))
+            .or_default()
+            .insert(self.get_rack(&ip));
+
+        let mut replicas = Vec::new();
+        for node in successors {
+            let datacenter = self.get_datacenter(&node);
+            let factor = factors.get(datacenter).copied().unwrap_or(0);
+            let count = taken.entry(datacenter).or_default();
+            if *count >= factor {
+                continue;
+            }
+
+            let seen = seen_racks.entry(datacenter).or_default();
+            let all_racks_seen = racks_by_datacenter
+                .get(datacenter)
+                .is_some_and(|racks| racks.len() <= seen.len());
+            if seen.insert(self.get_rack(&node)) || all_racks_seen {
+                *count += 1;
+                replicas.push(node);
+            } else {
+                skipped.push(node);
             }
         }
-        Ok(successors)
+
+        // Datacenters with fewer racks than copies take the nodes skipped before
+        for node in skipped {
+            let datacenter = self.get_datacenter(&node);
+            let factor = factors.get(datacenter).copied().unwrap_or(0);
+            let count = taken.entry(datacenter).or_default();
+            if *count < factor {
+                *count += 1;
+                replicas.push(node);
+            }
+        }
+
+        Ok(replicas)
+    }
+
+    /// Returns the ranges of tokens owned by each node, sorted by token.
+    ///
+    /// A node owns the tokens after the one of the previous node in the ring, up to its own
+    /// token. The range that wraps around the end of the ring is returned as two ranges, one at
+    /// each end, both owned by the first node.
+    ///
+    /// # Errors
+    /// - `PartitionerError::EmptyPartitioner` - If there are no nodes in the partitioner.
+    pub fn token_ranges(&self) -> Result<Vec<(Range<u64>, Ipv4Addr)>, PartitionerError> {
+        let (first_token, first_ip) = self
+            .nodes
+            .iter()
+            .next()
+            .ok_or(PartitionerError::EmptyPartitioner)?;
+
+        let mut ranges = vec![(0..first_token + 1, *first_ip)];
+        let mut start = first_token + 1;
+        for (token, ip) in self.nodes.iter().skip(1) {
+            ranges.push((start..token + 1, *ip));
+            start = token + 1;
+        }
+        if start < RING_SIZE {
+            ranges.push((start..RING_SIZE, *first_ip));
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns the ranges of tokens owned by the node with the given IP address, sorted by token.
+    /// A node with virtual nodes owns one range for each of its tokens (and one more if it owns
+    /// the range that wraps around the end of the ring).
+    ///
+    /// # Errors
+    /// - `PartitionerError::EmptyPartitioner` - If there are no nodes in the partitioner.
+    pub fn get_token_ranges(&self, ip: &Ipv4Addr) -> Result<Vec<Range<u64>>, PartitionerError> {
+        Ok(self
+            .token_ranges()?
+            .into_iter()
+            .filter(|(_range, owner)| owner == ip)
+            .map(|(range, _owner)| range)
+            .collect())
+    }
+
+    /// Splits the ring into about `n` ranges of tokens of roughly the same size, so a full scan
+    /// of a table can be done in parallel, one range at a time.
+    ///
+    /// Ranges never cross node boundaries, so each one has a single owner: the ranges of the
+    /// nodes are cut in pieces of at most `RING_SIZE / n` tokens. This can give a few more than
+    /// `n` ranges, one more at most for each node. An `n` of 0 is taken as 1.
+    ///
+    /// # Errors
+    /// - `PartitionerError::EmptyPartitioner` - If there are no nodes in the partitioner.
+    pub fn token_splits(&self, n: usize) -> Result<Vec<(Range<u64>, Ipv4Addr)>, PartitionerError> {
+        let max_size = RING_SIZE.div_ceil(n.max(1) as u64);
+
+        let mut splits = Vec::new();
+        for (range, ip) in self.token_ranges()? {
+            let size = range.end - range.start;
+            let pieces = size.div_ceil(max_size);
+            for i in 0..pieces {
+                let start = range.start + size * i / pieces;
+                let end = range.start + size * (i + 1) / pieces;
+                splits.push((start..end, ip));
+            }
+        }
+
+        Ok(splits)
     }
 }
 
 impl fmt::Debug for Partitioner {
     /// Custom `Debug` implementation to display partitioner's nodes in a `->` format.
+    /// Nodes with virtual nodes are displayed once, at their lowest token.
     ///
     /// # Examples
     /// * For a partitioner with nodes "192.168.0.1" and "192.168.0.2", the debug output
     ///   will display as `"192.168.0.1 -> 192.168.0.2"`.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let addresses: Vec<String> = self.nodes.values().map(|addr| addr.to_string()).collect();
+        let addresses: Vec<String> = self
+            .get_nodes()
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
         if !addresses.is_empty() {
             write!(f, "{}", addresses.join(" -> "))
         } else {
@@ -211,6 +612,25 @@ mod tests {
     use super::*;
     use std::net::Ipv4Addr;
 
+    #[test]
+    fn test_ring_digest_changes_with_the_tokens() {
+        let mut partitioner = Partitioner::new();
+        partitioner.add_node(Ipv4Addr::new(192, 168, 0, 1)).unwrap();
+        let mut other = partitioner.clone();
+        assert_eq!(partitioner.ring_digest(), other.ring_digest());
+
+        // The location of a node does not change how the partitions are routed
+        other.set_location(Ipv4Addr::new(192, 168, 0, 1), "dc2", "rack2");
+        assert_eq!(partitioner.ring_digest(), other.ring_digest());
+
+        other.add_node(Ipv4Addr::new(192, 168, 0, 2)).unwrap();
+        assert_ne!(partitioner.ring_digest(), other.ring_digest());
+        other.move_node(Ipv4Addr::new(192, 168, 0, 2), 7).unwrap();
+        let moved = other.ring_digest();
+        other.move_node(Ipv4Addr::new(192, 168, 0, 2), 8).unwrap();
+        assert_ne!(moved, other.ring_digest());
+    }
+
     #[test]
     fn test_add_and_get_nodes() {
         let mut partitioner = Partitioner::new();
@@ -252,6 +672,254 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_successors_by_datacenter() {
+        let ip = |last| Ipv4Addr::new(10, 0, 0, last);
+        let mut partitioner = Partitioner::new();
+        for (last, token, datacenter, rack) in [
+            (1, 10, "dc1", "r1"),
+            (2, 20, "dc1", "r1"),
+            (3, 30, "dc2", "r1"),
+            (4, 40, "dc1", "r2"),
+            (5, 50, "dc2", "r2"),
+            (6, 60, "dc2", "r1"),
+        ] {
+            partitioner.add_node_with_token(ip(last), token).unwrap();
+            partitioner.set_location(ip(last), datacenter, rack);
+        }
+        let factors =
+            |dc1, dc2| BTreeMap::from([("dc1".to_string(), dc1), ("dc2".to_string(), dc2)]);
+
+        // 10.0.0.2 is skipped for being in the rack of the owner
+        assert_eq!(
+            partitioner
+                .get_successors_by_datacenter(ip(1), &factors(2, 2))
+                .unwrap(),
+            vec![ip(3), ip(4), ip(5)]
+        );
+        // Once every rack of dc1 was taken, its nodes are taken in ring order
+        assert_eq!(
+            partitioner
+                .get_successors_by_datacenter(ip(4), &factors(3, 0))
+                .unwrap(),
+            vec![ip(1), ip(2)]
+        );
+        // The nodes skipped for their rack are taken last
+        assert_eq!(
+            partitioner
+                .get_successors_by_datacenter(ip(1), &factors(3, 0))
+                .unwrap(),
+            vec![ip(4), ip(2)]
+        );
+        // Datacenters without enough nodes keep fewer copies
+        assert_eq!(
+            partitioner
+                .get_successors_by_datacenter(ip(1), &factors(5, 0))
+                .unwrap(),
+            vec![ip(4), ip(2)]
+        );
+        assert_eq!(partitioner.get_datacenter(&ip(9)), DEFAULT_DATACENTER);
+        assert_eq!(partitioner.get_rack(&ip(9)), DEFAULT_RACK);
+    }
+
+    #[test]
+    fn test_move_node() {
+        let mut partitioner = Partitioner::new();
+        let ip1 = Ipv4Addr::new(192, 168, 0, 1);
+        let ip2 = Ipv4Addr::new(192, 168, 0, 2);
+        partitioner.add_node_with_token(ip1, 100).unwrap();
+        partitioner.add_node_with_token(ip2, 200).unwrap();
+
+        assert_eq!(partitioner.get_nodes(), vec![ip1, ip2]);
+
+        assert_eq!(partitioner.move_node(ip1, 300).unwrap(), 100);
+        assert_eq!(partitioner.get_token(&ip1), Some(300));
+        assert_eq!(partitioner.get_nodes(), vec![ip2, ip1]);
+        assert!(partitioner.contains_node(&ip1));
+
+        assert_eq!(
+            partitioner.move_node(ip1, 200),
+            Err(PartitionerError::TokenAlreadyTaken)
+        );
+        assert_eq!(
+            partitioner.move_node(Ipv4Addr::new(192, 168, 0, 3), 400),
+            Err(PartitionerError::NodeNotFound)
+        );
+
+        partitioner.remove_node(ip1).unwrap();
+        assert_eq!(partitioner.get_nodes(), vec![ip2]);
+    }
+
+    #[test]
+    fn test_token_ranges() {
+        let mut partitioner = Partitioner::new();
+        let ip1 = Ipv4Addr::new(192, 168, 0, 1);
+        let ip2 = Ipv4Addr::new(192, 168, 0, 2);
+        partitioner.add_node_with_token(ip1, 100).unwrap();
+        partitioner.add_node_with_token(ip2, 200).unwrap();
+
+        assert_eq!(
+            partitioner.token_ranges().unwrap(),
+            vec![(0..101, ip1), (101..201, ip2), (201..RING_SIZE, ip1)]
+        );
+        // Every token of a range is owned by its node
+        for (range, ip) in partitioner.token_ranges().unwrap() {
+            let owner = partitioner
+                .nodes
+                .range(range.end - 1..)
+                .next()
+                .map(|(_, ip)| *ip);
+            assert_eq!(owner.unwrap_or(ip1), ip);
+        }
+
+        assert_eq!(
+            Partitioner::new().token_ranges(),
+            Err(PartitionerError::EmptyPartitioner)
+        );
+    }
+
+    #[test]
+    fn test_token_splits() {
+        let mut partitioner = Partitioner::new();
+        let ip1 = Ipv4Addr::new(192, 168, 0, 1);
+        let ip2 = Ipv4Addr::new(192, 168, 0, 2);
+        partitioner
+            .add_node_with_token(ip1, RING_SIZE / 4 - 1)
+            .unwrap();
+        partitioner
+            .add_node_with_token(ip2, RING_SIZE / 2 + 10)
+            .unwrap();
+
+        let splits = partitioner.token_splits(4).unwrap();
+
+        // The range of ip2 is cut at RING_SIZE / 2 + 11, so it takes two ranges
+        assert_eq!(splits.len(), 5);
+        assert_eq!(splits[0], (0..RING_SIZE / 4, ip1));
+        assert!(splits[1..3].iter().all(|(_, ip)| *ip == ip2));
+        assert!(splits[3..].iter().all(|(_, ip)| *ip == ip1));
+
+        // The splits cover the whole ring, in order and without gaps
+        assert_eq!(splits.last().unwrap().0.end, RING_SIZE);
+        for pair in splits.windows(2) {
+            assert_eq!(pair[0].0.end, pair[1].0.start);
+        }
+        assert!(splits
+            .iter()
+            .all(|(range, _)| range.end - range.start <= RING_SIZE / 4));
+
+        assert_eq!(partitioner.token_splits(0).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_vnodes() {
+        let mut partitioner = Partitioner::new().with_vnodes(16);
+        let ips: Vec<Ipv4Addr> = (1..=3).map(|i| Ipv4Addr::new(192, 168, 0, i)).collect();
+        for ip in &ips {
+            partitioner.add_node(*ip).unwrap();
+        }
+
+        assert_eq!(partitioner.get_nodes().len(), 3);
+        for ip in &ips {
+            let tokens = partitioner.get_tokens(ip);
+            assert_eq!(tokens, {
+                let mut default_tokens = partitioner.default_tokens(ip).unwrap();
+                default_tokens.sort();
+                default_tokens
+            });
+            assert_eq!(tokens.len(), 16);
+            assert_eq!(partitioner.get_token(ip), tokens.first().copied());
+            assert!(partitioner.get_token_ranges(ip).unwrap().len() >= 16);
+        }
+        assert_eq!(
+            partitioner.add_node(ips[0]),
+            Err(PartitionerError::NodeAlreadyExists)
+        );
+        assert_eq!(
+            partitioner.move_node(ips[0], 7),
+            Err(PartitionerError::MultipleTokens)
+        );
+
+        // Successors are different nodes, even if their tokens are next to each other
+        let successors = partitioner.get_n_successors(ips[0], 5).unwrap();
+        assert_eq!(successors.len(), 2);
+        assert!(!successors.contains(&ips[0]));
+        assert_ne!(successors[0], successors[1]);
+
+        // Each node owns about a third of the ring
+        let owned = |partitioner: &Partitioner, ip: &Ipv4Addr| -> u64 {
+            partitioner
+                .get_token_ranges(ip)
+                .unwrap()
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum()
+        };
+        for ip in &ips {
+            let share = owned(&partitioner, ip) as f64 / RING_SIZE as f64;
+            assert!(share > 0.15 && share < 0.5, "{} owns {}", ip, share);
+        }
+
+        // Every key is owned by the node with the closest token, as without vnodes
+        let owners: Vec<Ipv4Addr> = (0..100)
+            .map(|key| partitioner.get_ip(key.to_string()).unwrap())
+            .collect();
+        partitioner.remove_node(ips[2]).unwrap();
+        assert!(partitioner.get_tokens(&ips[2]).is_empty());
+        assert_eq!(
+            owned(&partitioner, &ips[0]) + owned(&partitioner, &ips[1]),
+            RING_SIZE
+        );
+        // Only the keys of the removed node change owner, now spread over the rest
+        for (key, owner) in owners.iter().enumerate() {
+            let new_owner = partitioner.get_ip(key.to_string()).unwrap();
+            if *owner != ips[2] {
+                assert_eq!(new_owner, *owner);
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_node_with_tokens_and_set_tokens() {
+        let mut partitioner = Partitioner::new();
+        let ip1 = Ipv4Addr::new(192, 168, 0, 1);
+        let ip2 = Ipv4Addr::new(192, 168, 0, 2);
+        partitioner.add_node_with_tokens(ip1, &[300, 100]).unwrap();
+
+        assert_eq!(
+            partitioner.add_node_with_tokens(ip2, &[200, 300]),
+            Err(PartitionerError::TokenAlreadyTaken)
+        );
+        // Nothing of the failed node was added
+        assert!(!partitioner.contains_node(&ip2));
+        partitioner.add_node_with_tokens(ip2, &[200, 400]).unwrap();
+
+        assert_eq!(partitioner.get_tokens(&ip1), vec![100, 300]);
+        assert_eq!(partitioner.get_ip_of_token(150).unwrap(), ip2);
+        assert_eq!(partitioner.get_ip_of_token(250).unwrap(), ip1);
+        assert_eq!(partitioner.get_ip_of_token(500).unwrap(), ip1);
+        assert_eq!(
+            partitioner.token_ranges().unwrap(),
+            vec![
+                (0..101, ip1),
+                (101..201, ip2),
+                (201..301, ip1),
+                (301..401, ip2),
+                (401..RING_SIZE, ip1)
+            ]
+        );
+
+        assert_eq!(
+            partitioner.set_tokens(ip1, &[100, 200]),
+            Err(PartitionerError::TokenAlreadyTaken)
+        );
+        assert_eq!(
+            partitioner.set_tokens(ip1, &[300, 500]).unwrap(),
+            vec![100, 300]
+        );
+        assert_eq!(partitioner.get_tokens(&ip1), vec![300, 500]);
+        assert_eq!(partitioner.get_nodes(), vec![ip2, ip1]);
+    }
+
     #[test]
     fn test_debug_trait() {
         let mut partitioner = Partitioner::new();
@@ -0,0 +1,205 @@
+use crate::errors::CQLError;
+use crate::{GetUsedKeyspace, Query, QueryCreator};
+
+/// Struct that represents a `BEGIN BATCH ... APPLY BATCH` statement.
+/// A batch groups several `INSERT`, `UPDATE` and `DELETE` statements so they are sent to the
+/// cluster together and the coordinator can send every node its part in a single message.
+///
+/// # Fields
+///
+/// * `logged` - `false` for `BEGIN UNLOGGED BATCH`, `true` otherwise. The nodes keep no batch log,
+///   so it does not change how the batch is applied.
+/// * `statements` - The statements of the batch, in the order they were written.
+///
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub logged: bool,
+    pub statements: Vec<Query>,
+}
+
+impl Batch {
+    /// Creates and returns a new `Batch` instance from the query string.
+    ///
+    /// The statements of a batch are separated by `;`, which the tokenizer drops, so the batch is
+    /// split on the `;` outside quotes before parsing each statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A query in the format
+    ///   `BEGIN [LOGGED | UNLOGGED] BATCH statement; statement; ... APPLY BATCH`.
+    ///
+    /// # Returns
+    /// * `Ok(Batch)` - A successfully parsed `Batch` struct.
+    /// * `Err(CQLError::InvalidSyntax)` - If the query is improperly formatted, the batch is empty,
+    ///   a statement is not an `INSERT`, `UPDATE` or `DELETE`, or the statements use different keyspaces.
+    pub fn new_from_query(query: &str) -> Result<Self, CQLError> {
+        let body = query
+            .trim()
+            .trim_end_matches(';')
+            .trim_end()
+            .strip_suffix("APPLY BATCH")
+            .and_then(|q| q.strip_prefix("BEGIN"))
+            .ok_or(CQLError::InvalidSyntax)?
+            .trim_start();
+
+        let (logged, body) = if let Some(rest) = body.strip_prefix("UNLOGGED ") {
+            (false, rest.trim_start())
+        } else if let Some(rest) = body.strip_prefix("LOGGED ") {
+            (true, rest.trim_start())
+        } else {
+            (true, body)
+        };
+
+        let body = body
+            .strip_prefix("BATCH")
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            .ok_or(CQLError::InvalidSyntax)?;
+
+        let statements = Self::split_statements(body)
+            .into_iter()
+            .map(
+                |statement| match QueryCreator::new().handle_query(statement)? {
+                    query @ (Query::Insert(_) | Query::Update(_) | Query::Delete(_)) => Ok(query),
+                    _ => Err(CQLError::InvalidSyntax),
+                },
+            )
+            .collect::<Result<Vec<Query>, CQLError>>()?;
+
+        if statements.is_empty() {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let batch = Self { logged, statements };
+        let mut keyspaces = batch.statements.iter().map(|s| s.get_used_keyspace());
+        let first = keyspaces.next().flatten();
+        if keyspaces.any(|keyspace| keyspace != first) {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        Ok(batch)
+    }
+
+    // Splits the body of the batch on the `;` that are not inside a quoted value.
+    fn split_statements(body: &str) -> Vec<String> {
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+
+        for char in body.chars() {
+            match char {
+                '\'' => {
+                    in_quotes = !in_quotes;
+                    current.push(char);
+                }
+                ';' if !in_quotes => statements.push(std::mem::take(&mut current)),
+                _ => current.push(char),
+            }
+        }
+        statements.push(current);
+
+        statements
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// Returns the keyspace used by the statements of the batch, if they name one.
+    pub fn get_used_keyspace(&self) -> Option<String> {
+        self.statements.first().and_then(|s| s.get_used_keyspace())
+    }
+
+    /// Serializes the `Batch` struct into a query string representation.
+    ///
+    /// # Returns
+    /// A `String` in the format `BEGIN [UNLOGGED] BATCH statement; ... APPLY BATCH`
+    pub fn serialize(&self) -> String {
+        let statements: Vec<String> = self
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Query::Insert(insert) => Some(insert.serialize()),
+                Query::Update(update) => Some(update.serialize()),
+                Query::Delete(delete) => Some(delete.serialize()),
+                _ => None,
+            })
+            .map(|statement| format!("{}; ", statement))
+            .collect();
+
+        format!(
+            "BEGIN {}BATCH {}APPLY BATCH",
+            if self.logged { "" } else { "UNLOGGED " },
+            statements.concat()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_batch() {
+        let batch = Batch::new_from_query(
+            "BEGIN BATCH INSERT INTO sky.flights (number, status) VALUES ('AR1', 'on time'); \
+             UPDATE sky.flight_info SET fuel = 10 WHERE number = 'a;b'; APPLY BATCH;",
+        )
+        .unwrap();
+
+        assert!(batch.logged);
+        assert_eq!(batch.statements.len(), 2);
+        assert!(matches!(batch.statements[0], Query::Insert(_)));
+        match &batch.statements[1] {
+            Query::Update(update) => assert_eq!(update.table_name, "flight_info"),
+            other => panic!("expected an update, got {:?}", other),
+        }
+        assert_eq!(batch.get_used_keyspace(), Some("sky".to_string()));
+    }
+
+    #[test]
+    fn test_new_unlogged_batch() {
+        let batch = Batch::new_from_query(
+            "BEGIN UNLOGGED BATCH DELETE FROM flights WHERE number = 'AR1' APPLY BATCH",
+        )
+        .unwrap();
+
+        assert!(!batch.logged);
+        assert_eq!(batch.statements.len(), 1);
+        assert!(matches!(batch.statements[0], Query::Delete(_)));
+    }
+
+    #[test]
+    fn test_serialize_batch() {
+        let batch = Batch::new_from_query(
+            "BEGIN UNLOGGED BATCH INSERT INTO flights (number) VALUES (1); APPLY BATCH",
+        )
+        .unwrap();
+
+        let serialized = batch.serialize();
+        assert_eq!(
+            serialized,
+            "BEGIN UNLOGGED BATCH INSERT INTO flights (number) VALUES (1); APPLY BATCH"
+        );
+        assert_eq!(
+            Batch::new_from_query(&serialized).unwrap().statements.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_new_batch_invalid() {
+        // No statements
+        assert!(Batch::new_from_query("BEGIN BATCH APPLY BATCH").is_err());
+        // Missing APPLY BATCH
+        assert!(Batch::new_from_query("BEGIN BATCH INSERT INTO t (a) VALUES (1);").is_err());
+        // Only data statements are allowed
+        assert!(
+            Batch::new_from_query("BEGIN BATCH SELECT * FROM t WHERE a = 1; APPLY BATCH").is_err()
+        );
+        // Every statement must use the same keyspace
+        assert!(Batch::new_from_query(
+            "BEGIN BATCH INSERT INTO a.t (x) VALUES (1); INSERT INTO b.t (x) VALUES (1); APPLY BATCH"
+        )
+        .is_err());
+    }
+}
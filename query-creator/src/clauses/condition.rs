@@ -3,6 +3,26 @@ use std::collections::HashMap;
 
 use super::types::column::Column;
 
+/// Name of the function that gives the token of a partition, its position in the ring, which
+/// conditions compare as in `token(airport) > 100`.
+pub const TOKEN_FUNCTION: &str = "token";
+
+/// Returns the field of a condition on the token of the partitions with the given key columns, as
+/// in `token(airport,day)`.
+pub fn token_field(columns: &[&str]) -> String {
+    format!("{}({})", TOKEN_FUNCTION, columns.join(","))
+}
+
+/// Returns the columns whose token a condition field compares, or `None` if it compares a column.
+pub fn token_columns(field: &str) -> Option<Vec<&str>> {
+    let columns = field
+        .get(..TOKEN_FUNCTION.len())
+        .filter(|function| function.eq_ignore_ascii_case(TOKEN_FUNCTION))
+        .and_then(|_| field[TOKEN_FUNCTION.len()..].strip_prefix('('))?
+        .strip_suffix(')')?;
+    Some(columns.split(',').map(str::trim).collect())
+}
+
 /// Represents a condition in a `WHERE` clause of a CQL query.
 ///
 /// # Variants
@@ -43,10 +63,28 @@ impl Condition {
     ///   - If the tokens represent a valid simple condition.
     /// - `Err(CQLError::InvalidSyntax)`:
     ///   - If the tokens are invalid or improperly formatted.
+    ///
+    /// # Notes
+    /// - `token(columns)`, split by the tokenizer into `token` and the columns, is taken as a
+    ///   single field, see [`token_field`].
     pub fn new_simple_from_tokens(tokens: &[&str], pos: &mut usize) -> Result<Self, CQLError> {
         if let Some(field) = tokens.get(*pos) {
             *pos += 1;
 
+            let token_field = match tokens.get(*pos) {
+                Some(columns)
+                    if field.eq_ignore_ascii_case(TOKEN_FUNCTION)
+                        && Operator::deserialize(columns).is_err() =>
+                {
+                    *pos += 1;
+                    Some(token_field(
+                        &columns.split(',').map(str::trim).collect::<Vec<&str>>(),
+                    ))
+                }
+                _ => None,
+            };
+            let field = token_field.as_deref().unwrap_or(field);
+
             if let Some(operator) = tokens.get(*pos) {
                 *pos += 1;
 
@@ -65,20 +103,38 @@ impl Condition {
     }
 
     fn new_simple(field: &str, operator: &str, value: &str) -> Result<Self, CQLError> {
-        let op = match operator {
-            "=" => Operator::Equal,
-            ">" => Operator::Greater,
-            "<" => Operator::Lesser,
-            _ => return Err(CQLError::InvalidSyntax),
-        };
-
         Ok(Condition::Simple {
             field: field.to_string(),
-            operator: op,
+            operator: Operator::deserialize(operator)?,
             value: value.to_string(),
         })
     }
 
+    /// Returns the fields of the conditions on the token of a partition, as given by
+    /// [`token_field`], each once.
+    pub fn token_fields(&self) -> Vec<&str> {
+        let mut fields = Vec::new();
+        self.for_each_field(&mut |field| {
+            if token_columns(field).is_some() && !fields.contains(&field) {
+                fields.push(field);
+            }
+        });
+        fields
+    }
+
+    // Calls `f` with the field of each simple condition
+    fn for_each_field<'a>(&'a self, f: &mut impl FnMut(&'a str)) {
+        match self {
+            Condition::Simple { field, .. } => f(field),
+            Condition::Complex { left, right, .. } => {
+                if let Some(left) = left {
+                    left.for_each_field(f);
+                }
+                right.for_each_field(f);
+            }
+        }
+    }
+
     /// Creates a new `Complex` condition.
     ///
     /// # Parameters
@@ -129,6 +185,12 @@ impl Condition {
                 value,
             } => {
                 let y = value;
+                // Tokens are compared as numbers, the register holds the token of the row
+                if let (Some(x), Some(_)) = (register.get(field), token_columns(field)) {
+                    let x = x.parse::<i64>().map_err(|_| CQLError::InvalidCondition)?;
+                    let y = y.parse::<i64>().map_err(|_| CQLError::InvalidCondition)?;
+                    return Ok(operator.compare(&x, &y));
+                }
                 if let Some(x) = register.get(field) {
                     let col = columns
                         .iter()
@@ -312,6 +374,43 @@ mod tests {
         )
     }
 
+    #[test]
+    fn create_token_conditions() {
+        use super::{token_columns, token_field};
+
+        let tokens = vec!["token", "airport, day", "<=", "100"];
+        let mut pos = 0;
+        let condition = Condition::new_simple_from_tokens(&tokens, &mut pos).unwrap();
+        assert_eq!(pos, 4);
+        assert_eq!(
+            condition,
+            Condition::Simple {
+                field: String::from("token(airport,day)"),
+                operator: Operator::LesserOrEqual,
+                value: String::from("100")
+            }
+        );
+        assert_eq!(condition.token_fields(), ["token(airport,day)"]);
+        assert_eq!(
+            token_columns(&token_field(&["airport", "day"])),
+            Some(vec!["airport", "day"])
+        );
+        assert_eq!(token_columns("airport"), None);
+
+        // A column named token is not the function
+        let tokens = vec!["token", ">=", "5"];
+        let mut pos = 0;
+        let condition = Condition::new_simple_from_tokens(&tokens, &mut pos).unwrap();
+        assert!(condition.token_fields().is_empty());
+
+        let register = HashMap::from([(String::from("token(airport,day)"), String::from("100"))]);
+        let columns = vec![Column::new("airport", DataType::String, true, false)];
+        let condition = Condition::new_simple("token(airport,day)", "<=", "100").unwrap();
+        assert_eq!(condition.execute(&register, columns.clone()), Ok(true));
+        let condition = Condition::new_simple("token(airport,day)", ">", "100").unwrap();
+        assert_eq!(condition.execute(&register, columns), Ok(false));
+    }
+
     #[test]
     fn create_complex_with_left() {
         let left = Condition::Simple {
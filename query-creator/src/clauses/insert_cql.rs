@@ -1,6 +1,7 @@
 use super::into_cql::Into;
 use crate::errors::CQLError;
-use crate::utils::{is_insert, is_values};
+use crate::json::decode_object;
+use crate::utils::{is_insert, is_json, is_using, is_values, ttl_from_tokens};
 use crate::QueryCreator;
 
 /// Represents the `INSERT` clause in CQL queries.
@@ -14,6 +15,9 @@ use crate::QueryCreator;
 ///   - An `Into` struct containing the table name and the list of column names.
 /// - `if_not_exists: bool`
 ///   - Indicates whether the `IF NOT EXISTS` clause is included in the query.
+/// - `ttl: Option<u64>`
+///   - The seconds given with `USING TTL`, after which the row expires. `None` leaves the
+///     `default_time_to_live` of the table, and `Some(0)` makes the row never expire.
 ///
 /// # Purpose
 /// This struct encapsulates the functionality for parsing, serializing, and deserializing the `INSERT` clause.
@@ -22,6 +26,7 @@ pub struct Insert {
     pub values: Vec<String>,
     pub into_clause: Into,
     pub if_not_exists: bool,
+    pub ttl: Option<u64>,
 }
 
 impl Insert {
@@ -39,25 +44,37 @@ impl Insert {
     ///
     /// # Notes
     /// - The expected token order is:
-    ///   `"INSERT", "INTO", "table_name", "columns", "VALUES", "values" [IF NOT EXISTS]`.
+    ///   `"INSERT", "INTO", "table_name", "columns", "VALUES", "values" [IF NOT EXISTS] [USING TTL seconds]`.
     /// - Column names and values should be enclosed in parentheses and separated by commas.
+    /// - With `JSON`, the columns and values are given by a JSON object instead, as in
+    ///   `"INSERT", "INTO", "table_name", "JSON", "object" [IF NOT EXISTS] [USING TTL seconds]`.
+    ///   Its `null` members are left out, as the columns the insert does not write, so the query
+    ///   is the same as the one with the other members in `VALUES`, which is how it is serialized.
     pub fn new_from_tokens(tokens: Vec<String>) -> Result<Self, CQLError> {
-        if tokens.len() < 6 {
+        let json = is_json(&tokens, 3);
+        if tokens.len() < 6 && !json {
             return Err(CQLError::InvalidSyntax);
         }
         let mut into_tokens: Vec<&str> = Vec::new();
         let mut values: Vec<String> = Vec::new();
+        let json_columns;
 
         let mut i = 0;
 
-        if is_insert(&tokens[i]) {
+        if json && is_insert(&tokens[i]) {
+            let (columns, json_values) = columns_and_values_of_json(&tokens[4])?;
+            json_columns = columns.join(",");
+            into_tokens = vec![&tokens[1], &tokens[2], &json_columns];
+            values = json_values;
+            i = 5;
+        } else if is_insert(&tokens[i]) {
             i += 1;
             while !is_values(&tokens[i]) && i < tokens.len() {
                 into_tokens.push(tokens[i].as_str());
                 i += 1;
             }
         }
-        if is_values(&tokens[i]) {
+        if !json && is_values(&tokens[i]) {
             i += 1;
 
             let vals: Vec<String> = tokens[i]
@@ -73,13 +90,21 @@ impl Insert {
         }
 
         let mut if_not_exists = false;
+        let mut ttl = None;
 
-        if i < tokens.len()
-            && tokens[i] == "IF"
-            && tokens[i + 1] == "NOT"
-            && tokens[i + 2] == "EXISTS"
-        {
-            if_not_exists = true;
+        while i < tokens.len() {
+            if tokens[i] == "IF"
+                && tokens.get(i + 1).is_some_and(|token| token == "NOT")
+                && tokens.get(i + 2).is_some_and(|token| token == "EXISTS")
+            {
+                if_not_exists = true;
+                i += 3;
+            } else if is_using(&tokens[i]) {
+                ttl = Some(ttl_from_tokens(&tokens[i + 1..])?);
+                i += 3;
+            } else {
+                break;
+            }
         }
 
         if into_tokens.is_empty() || values.is_empty() {
@@ -92,6 +117,7 @@ impl Insert {
             values,
             into_clause,
             if_not_exists,
+            ttl,
         })
     }
 
@@ -101,17 +127,19 @@ impl Insert {
     /// - `String`:
     ///   - A string representation of the `INSERT` query in the following format:
     ///     ```sql
-    ///     INSERT INTO [keyspace.]table_name (columns) VALUES (values) [IF NOT EXISTS];
+    ///     INSERT INTO [keyspace.]table_name (columns) VALUES (values) [IF NOT EXISTS] [USING TTL seconds];
     ///     `
     pub fn serialize(&self) -> String {
         let columns = self.into_clause.columns.join(", ");
         let values = self.values.join(", ");
 
-        let if_not_exists = if self.if_not_exists {
-            " IF NOT EXISTS"
-        } else {
-            ""
-        };
+        let mut options = String::new();
+        if self.if_not_exists {
+            options.push_str(" IF NOT EXISTS");
+        }
+        if let Some(ttl) = self.ttl {
+            options.push_str(&format!(" USING TTL {}", ttl));
+        }
 
         let table_name_str = if !self.into_clause.keyspace_used_name.is_empty() {
             format!(
@@ -124,7 +152,7 @@ impl Insert {
 
         format!(
             "INSERT INTO {} ({}) VALUES ({}){}",
-            table_name_str, columns, values, if_not_exists
+            table_name_str, columns, values, options
         )
     }
 
@@ -145,6 +173,30 @@ impl Insert {
     }
 }
 
+// Returns the columns and values written by the JSON object of an `INSERT JSON`, leaving out its
+// `null` members. Names and values that the `VALUES` clause could not hold are rejected, as the
+// insert is serialized with it.
+fn columns_and_values_of_json(object: &str) -> Result<(Vec<String>, Vec<String>), CQLError> {
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+    for (column, value) in decode_object(object)? {
+        let Some(value) = value else {
+            continue;
+        };
+        if column.is_empty()
+            || !column
+                .chars()
+                .all(|char| char.is_alphanumeric() || char == '_')
+            || value.contains([',', '\'', '(', ')'])
+        {
+            return Err(CQLError::InvalidSyntax);
+        }
+        columns.push(column);
+        values.push(value);
+    }
+    Ok((columns, values))
+}
+
 #[cfg(test)]
 mod test {
     use crate::{clauses::into_cql, errors::CQLError, Insert};
@@ -159,6 +211,7 @@ mod test {
                 columns: vec![String::from("name"), String::from("age")],
             },
             if_not_exists: false,
+            ttl: None,
         };
 
         let serialized = insert.serialize();
@@ -178,6 +231,7 @@ mod test {
                 columns: vec![String::from("name"), String::from("age")],
             },
             if_not_exists: true,
+            ttl: None,
         };
 
         let serialized = insert.serialize();
@@ -202,6 +256,7 @@ mod test {
                     columns: vec![String::from("name"), String::from("age")],
                 },
                 if_not_exists: false,
+                ttl: None,
             }
         );
     }
@@ -221,10 +276,58 @@ mod test {
                     columns: vec![String::from("name"), String::from("age")],
                 },
                 if_not_exists: true,
+                ttl: None,
             }
         );
     }
 
+    #[test]
+    fn insert_using_ttl_round_trip() {
+        let s = "INSERT INTO positions (flight, lat) VALUES (AR1, 10) IF NOT EXISTS USING TTL 60";
+        let insert = Insert::deserialize(s).unwrap();
+        assert!(insert.if_not_exists);
+        assert_eq!(insert.ttl, Some(60));
+        assert_eq!(insert.serialize(), s);
+
+        let without_condition =
+            Insert::deserialize("INSERT INTO positions (flight) VALUES (AR1) USING TTL 0").unwrap();
+        assert_eq!(without_condition.ttl, Some(0));
+        assert!(!without_condition.if_not_exists);
+
+        assert_eq!(
+            Insert::deserialize("INSERT INTO positions (flight) VALUES (AR1) USING TTL soon"),
+            Err(CQLError::InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn deserialize_insert_json() {
+        let insert = Insert::deserialize(
+            r#"INSERT INTO sky.flights JSON '{"number": 7, "origin": "Buenos Aires", "gate": null}' IF NOT EXISTS USING TTL 60"#,
+        )
+        .unwrap();
+        let expected = Insert::deserialize(
+            "INSERT INTO sky.flights (number, origin) VALUES (7, 'Buenos Aires') IF NOT EXISTS USING TTL 60",
+        )
+        .unwrap();
+        assert_eq!(insert, expected);
+        assert_eq!(Insert::deserialize(&insert.serialize()).unwrap(), insert);
+
+        for s in [
+            r#"INSERT INTO flights JSON '{"gate": null}'"#,
+            r#"INSERT INTO flights JSON '{"origin": "Buenos Aires, AR"}'"#,
+            r#"INSERT INTO flights JSON '{"origin": }'"#,
+            r#"INSERT INTO flights JSON '{"number": 1} IF EXISTS'"#,
+        ] {
+            assert_eq!(
+                Insert::deserialize(s),
+                Err(CQLError::InvalidSyntax),
+                "{}",
+                s
+            );
+        }
+    }
+
     #[test]
     fn deserialize_invalid_syntax_missing_values() {
         let s = "INSERT INTO table (name, age)";
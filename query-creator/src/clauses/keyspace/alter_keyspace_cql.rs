@@ -1,11 +1,16 @@
+use std::collections::BTreeMap;
+
 use crate::errors::CQLError;
 use crate::QueryCreator;
 
+use super::create_keyspace_cql::{parse_replication, serialize_replication};
+
 #[derive(Debug, Clone)]
 pub struct AlterKeyspace {
     name: String,
     replication_class: String,
     replication_factor: u32,
+    datacenters: BTreeMap<String, u32>,
 }
 
 impl AlterKeyspace {
@@ -24,8 +29,9 @@ impl AlterKeyspace {
     /// # Validation
     /// - The query must begin with `ALTER KEYSPACE`.
     /// - The query must include `WITH REPLICATION = { ... }`.
-    /// - The replication class must be `SimpleStrategy`.
-    /// - The replication factor must be a valid unsigned integer.
+    /// - The replication class must be `SimpleStrategy`, with a `replication_factor`, or
+    ///   `NetworkTopologyStrategy`, with the replication factor of each datacenter.
+    /// - The replication factors must be valid unsigned integers.
     pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
         if query.len() < 10
             || query[0].to_uppercase() != "ALTER"
@@ -48,35 +54,18 @@ impl AlterKeyspace {
             return Err(CQLError::InvalidSyntax);
         }
 
-        let mut replication_class = String::new();
-        let mut replication_factor = 0;
-
-        // Iterar sobre los tokens dentro de las llaves, empezando en el índice 7 y terminando en len - 1
-        let mut i = 7;
-        while i < query.len() - 1 {
-            let key = query[i].trim();
-            let value = query[i + 1].trim();
-
-            match key {
-                "class" => replication_class = value.to_string(),
-                "replication_factor" => {
-                    replication_factor =
-                        value.parse::<u32>().map_err(|_| CQLError::InvalidSyntax)?;
-                }
-                _ => return Err(CQLError::InvalidSyntax),
-            }
-            i += 2; // Saltar al siguiente par clave-valor
-        }
-
-        // Validar la clase de replicación
-        if replication_class != "SimpleStrategy" {
-            return Err(CQLError::InvalidSyntax);
-        }
+        // Los tokens dentro de las llaves, empezando en el índice 7 y terminando en len - 1
+        let options: Vec<&str> = query[7..query.len() - 1]
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let (replication_class, replication_factor, datacenters) = parse_replication(&options)?;
 
         Ok(Self {
             name: keyspace_name,
             replication_class,
             replication_factor,
+            datacenters,
         })
     }
 
@@ -98,6 +87,16 @@ impl AlterKeyspace {
         self.replication_factor
     }
 
+    /// Retrieves the replication factor of each datacenter, which is empty unless the keyspace uses
+    /// `NetworkTopologyStrategy`.
+    ///
+    /// # Returns
+    /// - `BTreeMap<String, u32>`:
+    ///   - The replication factor of each datacenter, by name.
+    pub fn get_datacenters(&self) -> BTreeMap<String, u32> {
+        self.datacenters.clone()
+    }
+
     /// Retrieves the name of the keyspace.
     ///
     /// # Returns
@@ -120,8 +119,13 @@ impl AlterKeyspace {
 
     pub fn serialize(&self) -> String {
         format!(
-            "ALTER KEYSPACE {} WITH REPLICATION = {{'class': '{}', 'replication_factor': {}}};",
-            self.name, self.replication_class, self.replication_factor
+            "ALTER KEYSPACE {} WITH REPLICATION = {};",
+            self.name,
+            serialize_replication(
+                &self.replication_class,
+                self.replication_factor,
+                &self.datacenters
+            )
         )
     }
 
@@ -214,4 +218,22 @@ mod tests {
         let result = AlterKeyspace::new_from_tokens(query);
         assert!(matches!(result, Err(CQLError::InvalidSyntax)));
     }
+
+    #[test]
+    fn test_alter_keyspace_network_topology_strategy() {
+        let alter_keyspace = AlterKeyspace::deserialize(
+            "ALTER KEYSPACE sky WITH REPLICATION = {'class': 'NetworkTopologyStrategy', 'dc1': 2}",
+        )
+        .unwrap();
+
+        assert_eq!(alter_keyspace.get_replication_factor(), 2);
+        assert_eq!(
+            alter_keyspace.get_datacenters(),
+            BTreeMap::from([("dc1".to_string(), 2)])
+        );
+        assert_eq!(
+            alter_keyspace.serialize(),
+            "ALTER KEYSPACE sky WITH REPLICATION = {'class': 'NetworkTopologyStrategy', 'dc1': 2};"
+        );
+    }
 }
@@ -1,5 +1,15 @@
+use std::collections::BTreeMap;
+
 use crate::{errors::CQLError, QueryCreator};
 
+/// Replication class that places the same amount of replicas in every datacenter, walking the
+/// ring.
+pub const SIMPLE_STRATEGY: &str = "SimpleStrategy";
+
+/// Replication class that places a given amount of replicas in each datacenter, on different
+/// racks when possible.
+pub const NETWORK_TOPOLOGY_STRATEGY: &str = "NetworkTopologyStrategy";
+
 #[derive(Debug, Clone, Default)]
 /// Represents a `CREATE KEYSPACE` operation in CQL.
 ///
@@ -11,7 +21,11 @@ use crate::{errors::CQLError, QueryCreator};
 /// - `replication_class: String`
 ///   - The replication strategy class for the keyspace (e.g., `SimpleStrategy`).
 /// - `replication_factor: u32`
-///   - The replication factor for the keyspace.
+///   - The replication factor for the keyspace. With `NetworkTopologyStrategy`, the sum of the
+///     replication factors of its datacenters.
+/// - `datacenters: BTreeMap<String, u32>`
+///   - The replication factor of each datacenter, with `NetworkTopologyStrategy`. It is empty
+///     with `SimpleStrategy`.
 ///
 /// # Purpose
 /// This struct models the `CREATE KEYSPACE` operation in CQL, enabling parsing, validation, and serialization of such operations.
@@ -20,6 +34,7 @@ pub struct CreateKeyspace {
     pub if_not_exists_clause: bool,
     pub replication_class: String, // TODO: enum?
     pub replication_factor: u32,
+    pub datacenters: BTreeMap<String, u32>,
 }
 
 impl CreateKeyspace {
@@ -39,8 +54,9 @@ impl CreateKeyspace {
     /// - The query must begin with `CREATE KEYSPACE`.
     /// - The query may optionally include `IF NOT EXISTS`.
     /// - The query must include `WITH REPLICATION = { ... }`.
-    /// - The replication class must be `SimpleStrategy`.
-    /// - The replication factor must be a valid unsigned integer.
+    /// - The replication class must be `SimpleStrategy`, with a `replication_factor`, or
+    ///   `NetworkTopologyStrategy`, with the replication factor of each datacenter.
+    /// - The replication factors must be valid unsigned integers.
     pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
         if query.len() < 10
             || query[0].to_uppercase() != "CREATE"
@@ -72,37 +88,21 @@ impl CreateKeyspace {
             return Err(CQLError::InvalidSyntax);
         }
 
-        let mut replication_class = String::new();
-        let mut replication_factor = 0;
-
-        let mut replication_index = index + 4; // Start after "WITH REPLICATION ="
-        while replication_index < query.len() {
-            match query[replication_index].as_str() {
-                "{" => replication_index += 1, // Skip the start of block '{'
-                "class" => {
-                    replication_class = query[replication_index + 1].to_string();
-                    replication_index += 2;
-                }
-                "replication_factor" => {
-                    replication_factor = query[replication_index + 1]
-                        .parse::<u32>()
-                        .map_err(|_| CQLError::InvalidSyntax)?;
-                    replication_index += 2;
-                }
-                "}" => break, // End when finding '}'
-                _ => replication_index += 1,
-            }
-        }
-
-        if replication_class != "SimpleStrategy" {
-            return Err(CQLError::InvalidSyntax);
-        }
+        // The options of the replication go in pairs between the braces
+        let options = query[index + 4..]
+            .iter()
+            .skip_while(|token| *token == "{")
+            .take_while(|token| *token != "}")
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        let (replication_class, replication_factor, datacenters) = parse_replication(&options)?;
 
         Ok(Self {
             name: keyspace_name,
             if_not_exists_clause,
             replication_class,
             replication_factor,
+            datacenters,
         })
     }
 
@@ -133,6 +133,16 @@ impl CreateKeyspace {
         self.replication_factor
     }
 
+    /// Retrieves the replication factor of each datacenter, which is empty unless the keyspace uses
+    /// `NetworkTopologyStrategy`.
+    ///
+    /// # Returns
+    /// - `BTreeMap<String, u32>`:
+    ///   - The replication factor of each datacenter, by name.
+    pub fn get_datacenters(&self) -> BTreeMap<String, u32> {
+        self.datacenters.clone()
+    }
+
     /// Updates the replication class of the keyspace.
     ///
     /// # Parameters
@@ -151,6 +161,15 @@ impl CreateKeyspace {
         self.replication_factor = replication_factor;
     }
 
+    /// Updates the replication factor of each datacenter of the keyspace.
+    ///
+    /// # Parameters
+    /// - `datacenters: BTreeMap<String, u32>`:
+    ///   - The new replication factor of each datacenter.
+    pub fn update_datacenters(&mut self, datacenters: BTreeMap<String, u32>) {
+        self.datacenters = datacenters;
+    }
+
     /// Serializes the `CreateKeyspace` structure to a CQL query string.
     ///
     /// # Returns
@@ -159,18 +178,23 @@ impl CreateKeyspace {
     ///     ```sql
     ///     CREATE KEYSPACE [IF NOT EXISTS] <keyspace_name> WITH replication = {'class': '<replication_class>', 'replication_factor': <replication_factor>};
     ///     ```
+    ///     With `NetworkTopologyStrategy`, the replication factor of each datacenter takes the place
+    ///     of `'replication_factor'`.
     ///
     pub fn serialize(&self) -> String {
         format!(
-            "CREATE KEYSPACE {}{} WITH replication = {{'class': '{}', 'replication_factor': {}}};",
+            "CREATE KEYSPACE {}{} WITH replication = {};",
             if self.if_not_exists_clause {
                 "IF NOT EXISTS "
             } else {
                 ""
             },
             self.name,
-            self.replication_class,
-            self.replication_factor
+            serialize_replication(
+                &self.replication_class,
+                self.replication_factor,
+                &self.datacenters
+            )
         )
     }
 
@@ -192,6 +216,81 @@ impl CreateKeyspace {
     }
 }
 
+/// Parses the options of a replication map (`class`, then `replication_factor` or the replication
+/// factor of each datacenter), given as the tokens between its braces: a key followed by its value.
+///
+/// # Returns
+/// - `Ok((class, replication_factor, datacenters))`:
+///   - With `NetworkTopologyStrategy`, `replication_factor` is the sum of the replication factors
+///     of the datacenters.
+/// - `Err(CQLError::InvalidSyntax)`:
+///   - If the class is unknown, a replication factor is not a valid unsigned integer, or the
+///     options do not match the class.
+pub(crate) fn parse_replication(
+    options: &[&str],
+) -> Result<(String, u32, BTreeMap<String, u32>), CQLError> {
+    let pairs = options.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(CQLError::InvalidSyntax);
+    }
+
+    let mut replication_class = String::new();
+    let mut replication_factor = None;
+    let mut datacenters = BTreeMap::new();
+    for pair in pairs {
+        let (key, value) = (pair[0].trim(), pair[1].trim());
+        match key {
+            "class" => replication_class = value.to_string(),
+            "replication_factor" => {
+                replication_factor =
+                    Some(value.parse::<u32>().map_err(|_| CQLError::InvalidSyntax)?);
+            }
+            datacenter => {
+                let factor = value.parse::<u32>().map_err(|_| CQLError::InvalidSyntax)?;
+                datacenters.insert(datacenter.to_string(), factor);
+            }
+        }
+    }
+
+    match replication_class.as_str() {
+        SIMPLE_STRATEGY if datacenters.is_empty() => Ok((
+            replication_class,
+            replication_factor.unwrap_or(0),
+            datacenters,
+        )),
+        NETWORK_TOPOLOGY_STRATEGY if replication_factor.is_none() && !datacenters.is_empty() => {
+            let replication_factor = datacenters.values().sum();
+            Ok((replication_class, replication_factor, datacenters))
+        }
+        _ => Err(CQLError::InvalidSyntax),
+    }
+}
+
+/// Serializes a replication map, as `{'class': '<class>', 'replication_factor': <factor>}` or,
+/// with `NetworkTopologyStrategy`, `{'class': '<class>', '<datacenter>': <factor>, ...}`.
+pub(crate) fn serialize_replication(
+    replication_class: &str,
+    replication_factor: u32,
+    datacenters: &BTreeMap<String, u32>,
+) -> String {
+    if replication_class != NETWORK_TOPOLOGY_STRATEGY {
+        return format!(
+            "{{'class': '{}', 'replication_factor': {}}}",
+            replication_class, replication_factor
+        );
+    }
+
+    let factors: Vec<String> = datacenters
+        .iter()
+        .map(|(datacenter, factor)| format!("'{}': {}", datacenter, factor))
+        .collect();
+    format!(
+        "{{'class': '{}', {}}}",
+        replication_class,
+        factors.join(", ")
+    )
+}
+
 impl PartialEq for CreateKeyspace {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
@@ -300,4 +399,38 @@ mod tests {
         assert_eq!(create_keyspace.replication_factor, 3);
         assert_eq!(create_keyspace.if_not_exists_clause, true)
     }
+
+    #[test]
+    fn test_create_keyspace_network_topology_strategy() {
+        let create_keyspace = CreateKeyspace::deserialize(
+            "CREATE KEYSPACE sky WITH replication = {'class': 'NetworkTopologyStrategy', \
+            'us_east': 3, 'eu_west': 2}",
+        )
+        .unwrap();
+
+        assert_eq!(create_keyspace.replication_class, NETWORK_TOPOLOGY_STRATEGY);
+        assert_eq!(
+            create_keyspace.get_datacenters(),
+            BTreeMap::from([("eu_west".to_string(), 2), ("us_east".to_string(), 3)])
+        );
+        assert_eq!(create_keyspace.get_replication_factor(), 5);
+        assert_eq!(
+            create_keyspace.serialize(),
+            "CREATE KEYSPACE sky WITH replication = {'class': 'NetworkTopologyStrategy', \
+            'eu_west': 2, 'us_east': 3};"
+        );
+        let parsed = CreateKeyspace::deserialize(&create_keyspace.serialize()).unwrap();
+        assert_eq!(parsed.get_datacenters(), create_keyspace.get_datacenters());
+
+        // Each class takes its own options
+        assert!(CreateKeyspace::deserialize(
+            "CREATE KEYSPACE sky WITH replication = {'class': 'NetworkTopologyStrategy', \
+            'replication_factor': 3}"
+        )
+        .is_err());
+        assert!(CreateKeyspace::deserialize(
+            "CREATE KEYSPACE sky WITH replication = {'class': 'SimpleStrategy', 'us_east': 3}"
+        )
+        .is_err());
+    }
 }
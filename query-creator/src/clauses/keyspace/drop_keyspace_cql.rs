@@ -7,11 +7,14 @@ use crate::errors::CQLError;
 /// # Fields
 /// - `name: String`
 ///   - The name of the keyspace to be dropped.
+/// - `if_exists: bool`
+///   - Whether the query includes `IF EXISTS`, so dropping a keyspace that does not exist is not an error.
 ///
 /// # Purpose
 /// This struct models the `DROP KEYSPACE` operation in CQL, allowing for parsing,
 pub struct DropKeyspace {
     name: String,
+    if_exists: bool,
 }
 
 impl DropKeyspace {
@@ -28,21 +31,30 @@ impl DropKeyspace {
     ///   - If the query is invalid or improperly formatted.
     ///
     /// # Validation
-    /// - The query must contain exactly 3 tokens.
+    /// - The query must contain exactly 3 tokens, or 5 if it includes `IF EXISTS`.
     /// - The query must begin with `DROP KEYSPACE`.
     ///
     pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
-        if query.len() != 3
+        if query.len() < 3
             || query[0].to_uppercase() != "DROP"
             || query[1].to_uppercase() != "KEYSPACE"
         {
             return Err(CQLError::InvalidSyntax);
         }
 
-        let name = &query[2];
+        let if_exists = query.len() == 5
+            && query[2].to_uppercase() == "IF"
+            && query[3].to_uppercase() == "EXISTS";
+
+        if query.len() != 3 && !if_exists {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let name = &query[query.len() - 1];
 
         Ok(Self {
             name: name.to_string(),
+            if_exists,
         })
     }
 
@@ -55,17 +67,27 @@ impl DropKeyspace {
         self.name.clone()
     }
 
+    /// Checks if the `IF EXISTS` clause is present.
+    ///
+    /// # Returns
+    /// - `bool`:
+    ///   - Whether the clause is included.
+    pub fn get_if_exists_clause(&self) -> bool {
+        self.if_exists
+    }
+
     /// Serializes the `DropKeyspace` structure to a CQL query string.
     ///
     /// # Returns
     /// - `String`:
     ///   - A string representing the `DROP KEYSPACE` CQL query in the following format:
     ///     ```sql
-    ///     DROP KEYSPACE <keyspace_name>;
+    ///     DROP KEYSPACE [IF EXISTS] <keyspace_name>;
     ///     ```
     ///
     pub fn serialize(&self) -> String {
-        format!("DROP KEYSPACE {}", self.name)
+        let if_exists_str = if self.if_exists { "IF EXISTS " } else { "" };
+        format!("DROP KEYSPACE {}{}", if_exists_str, self.name)
     }
 
     /// Deserializes a CQL query string into a `DropKeyspace` structure.
@@ -140,6 +162,7 @@ mod tests {
     fn test_serialize() {
         let drop_keyspace = DropKeyspace {
             name: "example_keyspace".to_string(),
+            if_exists: false,
         };
         let serialized = drop_keyspace.serialize();
 
@@ -154,6 +177,21 @@ mod tests {
         assert_eq!(drop_keyspace.get_name(), "example_keyspace".to_string());
     }
 
+    #[test]
+    fn test_if_exists() {
+        let query = "DROP KEYSPACE IF EXISTS example_keyspace";
+        let drop_keyspace = DropKeyspace::deserialize(query).unwrap();
+
+        assert!(drop_keyspace.get_if_exists_clause());
+        assert_eq!(drop_keyspace.get_name(), "example_keyspace".to_string());
+        assert_eq!(drop_keyspace.serialize(), query);
+
+        assert!(matches!(
+            DropKeyspace::deserialize("DROP KEYSPACE IF NOT example_keyspace"),
+            Err(CQLError::InvalidSyntax)
+        ));
+    }
+
     #[test]
     fn test_deserialize_invalid_syntax() {
         // Caso: Query incompleta
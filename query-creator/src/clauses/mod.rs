@@ -1,3 +1,4 @@
+pub mod batch_cql;
 pub mod condition;
 pub mod delete_cql;
 pub mod if_cql;
@@ -23,8 +24,17 @@ pub mod keyspace {
     pub mod drop_keyspace_cql;
 }
 
+pub mod user {
+    pub mod alter_user_cql;
+    pub mod create_user_cql;
+    pub mod drop_user_cql;
+    pub mod grant_cql;
+    pub mod revoke_cql;
+}
+
 pub mod types {
     pub mod alter_table_op;
     pub mod column;
     pub mod datatype;
+    pub mod table_options;
 }
@@ -1,8 +1,11 @@
-use super::{order_by_cql::OrderBy, where_cql::Where};
+use super::{order_by_cql::OrderBy, types::column::Column, where_cql::Where};
 use crate::QueryCreator;
 use crate::{
     errors::CQLError,
-    utils::{is_by, is_from, is_limit, is_order, is_select, is_where},
+    utils::{
+        is_allow_filtering, is_by, is_from, is_json, is_limit, is_order, is_per_partition_limit,
+        is_select, is_where,
+    },
 };
 
 /// Struct that represents the `SELECT` SQL clause.
@@ -14,6 +17,10 @@ use crate::{
 /// * `columns` - The columns to select from the table.
 /// * `where_clause` - The `WHERE` clause to filter the result set.
 /// * `orderby_clause` - The `ORDER BY` clause to sort the result set.
+/// * `per_partition_limit` - The number of rows returned at most of each partition.
+/// * `limit` - The number of rows returned at most.
+/// * `allow_filtering` - Whether the query may read every partition of the table, filtering their rows.
+/// * `json` - Whether each row is returned as a JSON object, in a single `[json]` column.
 ///
 #[derive(Debug, PartialEq, Clone)]
 pub struct Select {
@@ -22,14 +29,47 @@ pub struct Select {
     pub columns: Vec<String>,
     pub where_clause: Option<Where>,
     pub orderby_clause: Option<OrderBy>,
+    pub per_partition_limit: Option<usize>,
     pub limit: Option<usize>,
+    pub allow_filtering: bool,
+    pub json: bool,
 }
 
-fn parse_columns<'a>(tokens: &'a [String], i: &mut usize) -> Result<Vec<&'a String>, CQLError> {
+/// Column of a `SELECT COUNT(*)`, which returns the number of matching rows instead of the rows.
+pub const COUNT_COLUMN: &str = "COUNT(*)";
+
+/// Name of the column of the result of a `SELECT COUNT(*)`.
+pub const COUNT_RESULT_COLUMN: &str = "count";
+
+/// Name of the column of the result of a `SELECT JSON`, which holds each row as a JSON object.
+pub const JSON_RESULT_COLUMN: &str = "[json]";
+
+/// Turns the tokens of `COUNT(*)` (or `COUNT(1)`), split by the tokenizer into `COUNT` and `*`,
+/// into the single `COUNT(*)` column.
+fn parse_count(columns: Vec<&String>) -> Vec<String> {
+    match columns.as_slice() {
+        [function, argument]
+            if function.eq_ignore_ascii_case("count") && matches!(argument.trim(), "*" | "1") =>
+        {
+            vec![COUNT_COLUMN.to_string()]
+        }
+        _ => columns.iter().map(|c| c.to_string()).collect(),
+    }
+}
+
+fn parse_columns<'a>(
+    tokens: &'a [String],
+    i: &mut usize,
+    json: &mut bool,
+) -> Result<Vec<&'a String>, CQLError> {
     let mut columns = Vec::new();
     if is_select(&tokens[*i]) {
         if *i < tokens.len() {
             *i += 1;
+            if is_json(tokens, *i) {
+                *json = true;
+                *i += 1;
+            }
             while !is_from(&tokens[*i]) && *i < tokens.len() {
                 columns.push(&tokens[*i]);
                 *i += 1;
@@ -52,40 +92,74 @@ fn parse_table_name(tokens: &[String], i: &mut usize) -> Result<String, CQLError
     }
 }
 
-type Tokens<'a> = Vec<&'a str>;
-type ParsedResult<'a> = Result<(Tokens<'a>, Tokens<'a>, Option<usize>), CQLError>;
+// The clauses that follow the table of a `SELECT`
+struct Clauses<'a> {
+    where_tokens: Vec<&'a str>,
+    orderby_tokens: Vec<&'a str>,
+    per_partition_limit: Option<usize>,
+    limit: Option<usize>,
+    allow_filtering: bool,
+}
+
+// Whether the token at `i` starts a clause that follows the `WHERE` and `ORDER BY` of the query
+fn ends_condition(tokens: &[String], i: usize) -> bool {
+    is_limit(&tokens[i]) || is_per_partition_limit(tokens, i) || is_allow_filtering(tokens, i)
+}
 
-fn parse_where_orderby_limit<'a>(tokens: &'a [String], i: &mut usize) -> ParsedResult<'a> {
-    let mut where_tokens = Vec::new();
-    let mut orderby_tokens = Vec::new();
-    let mut limit = None;
+fn parse_where_orderby_limit<'a>(
+    tokens: &'a [String],
+    i: &mut usize,
+) -> Result<Clauses<'a>, CQLError> {
+    let mut clauses = Clauses {
+        where_tokens: Vec::new(),
+        orderby_tokens: Vec::new(),
+        per_partition_limit: None,
+        limit: None,
+        allow_filtering: false,
+    };
 
     if *i < tokens.len() {
         if is_where(&tokens[*i]) {
-            while *i < tokens.len() && !is_order(&tokens[*i]) && !is_limit(&tokens[*i]) {
-                where_tokens.push(tokens[*i].as_str());
+            while *i < tokens.len() && !is_order(&tokens[*i]) && !ends_condition(tokens, *i) {
+                clauses.where_tokens.push(tokens[*i].as_str());
                 *i += 1;
             }
         }
         if *i < tokens.len() && is_order(&tokens[*i]) {
-            orderby_tokens.push(tokens[*i].as_str());
+            clauses.orderby_tokens.push(tokens[*i].as_str());
             *i += 1;
             if *i < tokens.len() && is_by(&tokens[*i]) {
-                while *i < tokens.len() && !is_limit(&tokens[*i]) {
-                    orderby_tokens.push(tokens[*i].as_str());
+                while *i < tokens.len() && !ends_condition(tokens, *i) {
+                    clauses.orderby_tokens.push(tokens[*i].as_str());
                     *i += 1;
                 }
             }
         }
+        if is_per_partition_limit(tokens, *i) {
+            *i += 3;
+            clauses.per_partition_limit = Some(parse_limit(tokens, i)?);
+        }
         if *i < tokens.len() && is_limit(&tokens[*i]) {
             *i += 1;
-            if *i < tokens.len() {
-                limit = tokens[*i].parse::<usize>().ok(); // Attempt to parse LIMIT value
-                *i += 1;
-            }
+            clauses.limit = Some(parse_limit(tokens, i)?);
+        }
+        if is_allow_filtering(tokens, *i) {
+            *i += 2;
+            clauses.allow_filtering = true;
         }
     }
-    Ok((where_tokens, orderby_tokens, limit))
+    Ok(clauses)
+}
+
+// Parses the number of rows of a `LIMIT`, which must be positive
+fn parse_limit(tokens: &[String], i: &mut usize) -> Result<usize, CQLError> {
+    let limit = tokens
+        .get(*i)
+        .and_then(|token| token.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+        .ok_or(CQLError::InvalidSyntax)?;
+    *i += 1;
+    Ok(limit)
 }
 
 impl Select {
@@ -103,8 +177,8 @@ impl Select {
     ///
     /// # Notes
     /// - The expected token order is:
-    ///   `"SELECT", "columns", "FROM", "table_name", "[WHERE condition]", "[ORDER BY columns order]", "[LIMIT number]"`.
-    /// - The `columns` should be comma-separated.
+    ///   `"SELECT", "[JSON]", "columns", "FROM", "table_name", "[WHERE condition]", "[ORDER BY columns order]", "[PER PARTITION LIMIT number]", "[LIMIT number]", "[ALLOW FILTERING]"`.
+    /// - The `columns` should be comma-separated, or `COUNT(*)` to count the matching rows.
     pub fn new_from_tokens(tokens: Vec<String>) -> Result<Self, CQLError> {
         if tokens.len() < 4 {
             return Err(CQLError::InvalidSyntax);
@@ -112,7 +186,8 @@ impl Select {
 
         let mut i = 0;
 
-        let columns = parse_columns(&tokens, &mut i)?;
+        let mut json = false;
+        let columns = parse_count(parse_columns(&tokens, &mut i, &mut json)?);
         let full_table_name = parse_table_name(&tokens, &mut i)?;
 
         let (keyspace_used_name, table_name) = if full_table_name.contains('.') {
@@ -126,17 +201,21 @@ impl Select {
             return Err(CQLError::InvalidSyntax);
         }
 
-        let (where_tokens, orderby_tokens, limit) = parse_where_orderby_limit(&tokens, &mut i)?;
+        let clauses = parse_where_orderby_limit(&tokens, &mut i)?;
 
-        let where_clause = if !where_tokens.is_empty() {
-            Some(Where::new_from_tokens(where_tokens)?)
+        let where_clause = if !clauses.where_tokens.is_empty() {
+            Some(Where::new_from_tokens(clauses.where_tokens)?)
         } else {
             None
         };
 
-        let order_by_tokens = orderby_tokens.iter().map(|s| s.to_string()).collect();
+        let order_by_tokens = clauses
+            .orderby_tokens
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
 
-        let orderby_clause = if !orderby_tokens.is_empty() {
+        let orderby_clause = if !clauses.orderby_tokens.is_empty() {
             Some(OrderBy::new_from_tokens(order_by_tokens)?)
         } else {
             None
@@ -145,10 +224,13 @@ impl Select {
         Ok(Self {
             table_name,
             keyspace_used_name,
-            columns: columns.iter().map(|c| c.to_string()).collect(),
+            columns,
             where_clause,
             orderby_clause,
-            limit,
+            per_partition_limit: clauses.per_partition_limit,
+            limit: clauses.limit,
+            allow_filtering: clauses.allow_filtering,
+            json,
         })
     }
 
@@ -158,7 +240,7 @@ impl Select {
     /// - `String`:
     ///   - A string representation of the `SELECT` query in the following format:
     ///     ```sql
-    ///     SELECT columns FROM [keyspace.]table_name [WHERE condition] [ORDER BY columns order] [LIMIT number];
+    ///     SELECT [JSON] columns FROM [keyspace.]table_name [WHERE condition] [ORDER BY columns order] [PER PARTITION LIMIT number] [LIMIT number] [ALLOW FILTERING];
     ///    
     pub fn serialize(&self) -> String {
         let table_name_str = if !self.keyspace_used_name.is_empty() {
@@ -166,7 +248,13 @@ impl Select {
         } else {
             self.table_name.clone()
         };
-        let mut result = format!("SELECT {} FROM {}", self.columns.join(","), table_name_str);
+        let json = if self.json { "JSON " } else { "" };
+        let mut result = format!(
+            "SELECT {}{} FROM {}",
+            json,
+            self.columns.join(","),
+            table_name_str
+        );
 
         // Agrega el `WHERE` si existe
         if let Some(where_clause) = &self.where_clause {
@@ -175,13 +263,21 @@ impl Select {
 
         // Agrega el `ORDER BY` si existe
         if let Some(orderby_clause) = &self.orderby_clause {
-            result.push_str(&format!(" ORDER BY {}", orderby_clause.serialize()));
+            result.push_str(&format!(" {}", orderby_clause.serialize()));
+        }
+
+        if let Some(per_partition_limit) = &self.per_partition_limit {
+            result.push_str(&format!(" PER PARTITION LIMIT {}", per_partition_limit));
         }
 
         // Agrega el `LIMIT` si existe
         if let Some(limit) = &self.limit {
             result.push_str(&format!(" LIMIT {}", limit));
         }
+
+        if self.allow_filtering {
+            result.push_str(" ALLOW FILTERING");
+        }
         result
     }
 
@@ -201,37 +297,91 @@ impl Select {
         Self::new_from_tokens(tokens)
     }
 
+    /// Whether the query is a `SELECT COUNT(*)`, which returns the number of matching rows.
+    pub fn is_count(&self) -> bool {
+        self.columns.len() == 1 && self.columns[0] == COUNT_COLUMN
+    }
+
     /// Validates the `ORDER BY` clause in the `Select` query.
     ///
     /// # Parameters
-    /// - `clustering_columns: &[String]`:
-    ///   - A slice of strings representing the clustering columns of the table.
+    /// - `clustering_columns: &[Column]`:
+    ///   - The clustering columns of the table, in the order of the primary key.
     ///
     /// # Returns
     /// - `Ok(())`:
     ///   - If the `ORDER BY` clause is valid.
+    /// - `Err(CQLError::InvalidColumn)`:
+    ///   - If the `ORDER BY` clause does not use the first clustering columns, in their order.
     /// - `Err(CQLError::InvalidCondition)`:
-    ///   - If the `ORDER BY` clause is invalid (e.g., it uses non-clustering columns).
+    ///   - If the order asked for neither matches nor reverses the clustering order of the table.
     pub fn validate_order_by_cql_conditions(
         &mut self,
-        clustering_columns: &[String],
+        clustering_columns: &[Column],
     ) -> Result<(), CQLError> {
-        if let Some(mut order_by) = self.orderby_clause.clone() {
-            if order_by.columns.len() != 1 {
-                return Err(CQLError::InvalidCondition);
-            }
+        self.reverses_clustering_order(clustering_columns)
+            .map(|_| ())
+    }
 
-            if order_by.order.is_empty() {
-                order_by.order = "ASC".to_string();
-            }
+    /// Checks whether the rows must be returned in the reverse of the clustering order of the
+    /// table, which is the order they are stored in.
+    ///
+    /// The `ORDER BY` clause can only sort by the first clustering columns, in the order of the
+    /// primary key, and its order (`ASC` or `DESC`) applies to all of them. So it either matches
+    /// the order every column was defined with in `WITH CLUSTERING ORDER BY`, or reverses all of
+    /// them.
+    ///
+    /// # Parameters
+    /// - `clustering_columns: &[Column]`:
+    ///   - The clustering columns of the table, in the order of the primary key.
+    ///
+    /// # Returns
+    /// - `Ok(false)`:
+    ///   - If there is no `ORDER BY` clause or it matches the clustering order.
+    /// - `Ok(true)`:
+    ///   - If the `ORDER BY` clause reverses the clustering order.
+    /// - `Err(CQLError)`:
+    ///   - If the `ORDER BY` clause is invalid, as in `validate_order_by_cql_conditions`.
+    pub fn reverses_clustering_order(
+        &self,
+        clustering_columns: &[Column],
+    ) -> Result<bool, CQLError> {
+        let order_by = match &self.orderby_clause {
+            Some(order_by) => order_by,
+            None => return Ok(false),
+        };
+
+        if order_by.columns.is_empty() || order_by.columns.len() > clustering_columns.len() {
+            return Err(CQLError::InvalidColumn);
+        }
+
+        let order = if order_by.order.is_empty() {
+            "ASC".to_string()
+        } else {
+            order_by.order.to_uppercase()
+        };
 
-            if !clustering_columns.contains(&order_by.columns[0]) {
+        let mut matches = true;
+        let mut reverses = true;
+        for (column_name, column) in order_by.columns.iter().zip(clustering_columns) {
+            if *column_name != column.name {
                 return Err(CQLError::InvalidColumn);
             }
 
-            Ok(())
-        } else {
-            Ok(())
+            let clustering_order = column.get_clustering_order().to_uppercase();
+            let defined_desc = clustering_order == "DESC";
+            let asked_desc = order == "DESC";
+            if defined_desc == asked_desc {
+                reverses = false;
+            } else {
+                matches = false;
+            }
+        }
+
+        match (matches, reverses) {
+            (true, _) => Ok(false),
+            (false, true) => Ok(true),
+            (false, false) => Err(CQLError::InvalidCondition),
         }
     }
 }
@@ -414,4 +564,148 @@ mod tests {
         );
         assert_eq!(select.limit.unwrap(), 10)
     }
+
+    #[test]
+    fn new_with_per_partition_limit() {
+        let select = Select::deserialize(
+            "SELECT number FROM sky.flights WHERE airport = 'EZE' ORDER BY number DESC PER PARTITION LIMIT 2 LIMIT 5",
+        )
+        .unwrap();
+        assert_eq!(select.orderby_clause.as_ref().unwrap().order, "DESC");
+        assert_eq!(select.per_partition_limit, Some(2));
+        assert_eq!(select.limit, Some(5));
+        assert_eq!(Select::deserialize(&select.serialize()).unwrap(), select);
+
+        let select =
+            Select::deserialize("SELECT number FROM flights per partition limit 1").unwrap();
+        assert_eq!((select.per_partition_limit, select.limit), (Some(1), None));
+
+        assert_eq!(
+            Select::deserialize("SELECT number FROM flights LIMIT 0"),
+            Err(CQLError::InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn new_with_allow_filtering() {
+        let select = Select::deserialize(
+            "SELECT number FROM sky.flights WHERE status = 'delayed' LIMIT 5 ALLOW FILTERING",
+        )
+        .unwrap();
+        assert!(select.allow_filtering);
+        assert_eq!(
+            select.where_clause.as_ref().unwrap().condition,
+            Condition::Simple {
+                field: String::from("status"),
+                operator: Operator::Equal,
+                value: String::from("delayed"),
+            }
+        );
+        assert_eq!(select.limit, Some(5));
+        assert_eq!(Select::deserialize(&select.serialize()).unwrap(), select);
+
+        let select = Select::deserialize("SELECT * FROM flights allow filtering").unwrap();
+        assert!(select.allow_filtering && select.where_clause.is_none());
+        assert!(
+            !Select::deserialize("SELECT * FROM flights")
+                .unwrap()
+                .allow_filtering
+        );
+    }
+
+    #[test]
+    fn new_with_token_range() {
+        let select = Select::deserialize(
+            "SELECT * FROM sky.flights WHERE token(airport) > -1 AND token(airport) <= 1000",
+        )
+        .unwrap();
+        let where_clause = select.where_clause.as_ref().unwrap();
+        assert!(where_clause.restricts_token());
+        assert_eq!(where_clause.condition.token_fields(), ["token(airport)"]);
+        assert_eq!(Select::deserialize(&select.serialize()).unwrap(), select);
+    }
+
+    #[test]
+    fn new_with_json() {
+        let select =
+            Select::deserialize("SELECT JSON number, status FROM sky.flights WHERE number = 1")
+                .unwrap();
+        assert!(select.json);
+        assert_eq!(select.columns, ["number", "status"]);
+        assert_eq!(Select::deserialize(&select.serialize()).unwrap(), select);
+
+        let select = Select::deserialize("SELECT json COUNT(*) FROM flights").unwrap();
+        assert!(select.json && select.is_count());
+
+        // A column named json is not the keyword
+        let select = Select::deserialize("SELECT json FROM flights").unwrap();
+        assert!(!select.json);
+        assert_eq!(select.columns, ["json"]);
+    }
+
+    #[test]
+    fn new_with_count() {
+        for query in [
+            "SELECT COUNT(*) FROM table WHERE id = 1",
+            "SELECT count(1) FROM table WHERE id = 1",
+            "SELECT count ( * ) FROM table WHERE id = 1",
+        ] {
+            let select = Select::deserialize(query).unwrap();
+            assert!(select.is_count());
+            assert_eq!(select.columns, ["COUNT(*)"]);
+
+            let serialized = Select::deserialize(&select.serialize()).unwrap();
+            assert_eq!(serialized, select);
+        }
+
+        let select = Select::deserialize("SELECT count FROM table").unwrap();
+        assert!(!select.is_count());
+        assert_eq!(select.columns, ["count"]);
+    }
+
+    #[test]
+    fn order_by_matches_or_reverses_the_clustering_order() {
+        use crate::clauses::types::{column::Column, datatype::DataType};
+
+        let clustering_column = |name: &str, order: &str| {
+            let mut column = Column::new(name, DataType::Int, false, true);
+            column.is_clustering_column = true;
+            column.clustering_order = order.to_string();
+            column
+        };
+        let clustering_columns = vec![
+            clustering_column("time", "DESC"),
+            clustering_column("seq", "DESC"),
+        ];
+        let reverses = |query: &str| {
+            Select::deserialize(query)
+                .unwrap()
+                .reverses_clustering_order(&clustering_columns)
+        };
+
+        assert_eq!(reverses("SELECT * FROM t WHERE id = 1"), Ok(false));
+        assert_eq!(
+            reverses("SELECT * FROM t WHERE id = 1 ORDER BY time DESC"),
+            Ok(false)
+        );
+        assert_eq!(
+            reverses("SELECT * FROM t WHERE id = 1 ORDER BY time, seq ASC"),
+            Ok(true)
+        );
+        assert_eq!(
+            reverses("SELECT * FROM t WHERE id = 1 ORDER BY seq DESC"),
+            Err(CQLError::InvalidColumn)
+        );
+
+        let mixed = vec![
+            clustering_column("time", "DESC"),
+            clustering_column("seq", "ASC"),
+        ];
+        let mut select =
+            Select::deserialize("SELECT * FROM t WHERE id = 1 ORDER BY time, seq ASC").unwrap();
+        assert_eq!(
+            select.validate_order_by_cql_conditions(&mixed),
+            Err(CQLError::InvalidCondition)
+        );
+    }
 }
@@ -7,8 +7,10 @@ use crate::{errors::CQLError, utils::is_set};
 /// # Fields
 ///
 /// * A vector of tuples containing the column name and the new value.
+/// * A vector of tuples containing the name of a counter column and the increment added to it, as
+///   given by `column = column + increment` or `column = column - decrement`.
 #[derive(PartialEq, Debug, Clone)]
-pub struct Set(pub Vec<(String, String)>);
+pub struct Set(pub Vec<(String, String)>, pub Vec<(String, i64)>);
 
 impl Set {
     /// Retrieves a reference to the internal vector of column-value pairs.
@@ -19,6 +21,15 @@ impl Set {
         &self.0
     }
 
+    /// Retrieves a reference to the internal vector of counter increments.
+    ///
+    /// # Returns
+    /// - A reference to the vector of `(String, i64)` pairs representing the counter columns and
+    ///   the increments added to them, negative for decrements.
+    pub fn get_increments(&self) -> &Vec<(String, i64)> {
+        &self.1
+    }
+
     /// Creates and returns a new `Set` instance from a vector of tokens.
     ///
     /// # Parameters
@@ -32,9 +43,11 @@ impl Set {
     ///   - If the tokens are invalid or improperly formatted.
     ///
     /// # Notes
-    /// - The tokens must be in the format: `"SET column = value"`.
+    /// - The tokens must be in the format: `"SET column = value"`, or
+    ///   `"SET column = column + increment"` (or `-`) for counter columns.
     pub fn new_from_tokens(tokens: Vec<&str>) -> Result<Self, CQLError> {
         let mut set = Vec::new();
+        let mut increments = Vec::new();
         let mut i = 0;
 
         if !is_set(tokens[i]) || !tokens.contains(&"=") {
@@ -44,12 +57,32 @@ impl Set {
 
         while i < tokens.len() {
             if tokens[i] == "=" && i + 1 < tokens.len() {
-                set.push((tokens[i - 1].to_string(), tokens[i + 1].to_string()));
+                let column = tokens[i - 1];
+                match Self::increment_from_tokens(column, &tokens[i + 1..])? {
+                    Some(increment) => increments.push((column.to_string(), increment)),
+                    None => set.push((column.to_string(), tokens[i + 1].to_string())),
+                }
             }
             i += 1;
         }
 
-        Ok(Self(set))
+        Ok(Self(set, increments))
+    }
+
+    // Returns the increment of `column = column + increment` given the tokens after `=`, or `None`
+    // if they assign a value. The tokenizer keeps a `-` next to the number it is written with.
+    fn increment_from_tokens(column: &str, tokens: &[&str]) -> Result<Option<i64>, CQLError> {
+        if tokens.len() < 2 || tokens[0] != column {
+            return Ok(None);
+        }
+        let (sign, amount) = match (tokens[1], tokens.get(2)) {
+            ("+", Some(amount)) => (1, *amount),
+            ("-", Some(amount)) => (-1, *amount),
+            (amount, _) if amount.starts_with('-') && amount.len() > 1 => (1, amount),
+            _ => return Ok(None),
+        };
+        let amount = amount.parse::<i64>().map_err(|_| CQLError::InvalidSyntax)?;
+        Ok(Some(sign * amount))
     }
 
     /// Serializes the `Set` clause into a CQL string.
@@ -57,7 +90,8 @@ impl Set {
     /// # Returns
     /// - `String`:
     ///   - The serialized string representation of the `SET` clause.
-    ///   - Format: `column1 = value1, column2 = value2`.
+    ///   - Format: `column1 = value1, column2 = value2`, followed by the increments as
+    ///     `counter = counter + increment` (or `-`).
     ///   - If a value is not numeric, it will be wrapped in single quotes.
     pub fn serialize(&self) -> String {
         self.0
@@ -70,6 +104,10 @@ impl Set {
                 };
                 format!("{} = {}", col, formatted_value)
             })
+            .chain(self.1.iter().map(|(col, increment)| {
+                let sign = if *increment < 0 { "-" } else { "+" };
+                format!("{} = {} {} {}", col, col, sign, increment.unsigned_abs())
+            }))
             .collect::<Vec<String>>()
             .join(", ")
     }
@@ -84,7 +122,10 @@ mod tests {
     fn test_new_from_tokens_single_pair() {
         let tokens = vec!["SET", "age", "=", "18"];
         let set_clause = Set::new_from_tokens(tokens).unwrap();
-        assert_eq!(set_clause, Set(vec![("age".to_string(), "18".to_string())]));
+        assert_eq!(
+            set_clause,
+            Set(vec![("age".to_string(), "18".to_string())], vec![])
+        );
     }
 
     #[test]
@@ -93,10 +134,13 @@ mod tests {
         let set_clause = Set::new_from_tokens(tokens).unwrap();
         assert_eq!(
             set_clause,
-            Set(vec![
-                ("age".to_string(), "18".to_string()),
-                ("name".to_string(), "John".to_string())
-            ])
+            Set(
+                vec![
+                    ("age".to_string(), "18".to_string()),
+                    ("name".to_string(), "John".to_string())
+                ],
+                vec![]
+            )
         );
     }
 
@@ -116,31 +160,37 @@ mod tests {
 
     #[test]
     fn test_serialize_with_numbers() {
-        let set_clause = Set(vec![("age".to_string(), "18".to_string())]);
+        let set_clause = Set(vec![("age".to_string(), "18".to_string())], vec![]);
         assert_eq!(set_clause.serialize(), "age = 18");
     }
 
     #[test]
     fn test_serialize_with_strings() {
-        let set_clause = Set(vec![("name".to_string(), "John".to_string())]);
+        let set_clause = Set(vec![("name".to_string(), "John".to_string())], vec![]);
         assert_eq!(set_clause.serialize(), "name = 'John'");
     }
 
     #[test]
     fn test_serialize_mixed_types() {
-        let set_clause = Set(vec![
-            ("age".to_string(), "18".to_string()),
-            ("name".to_string(), "John".to_string()),
-        ]);
+        let set_clause = Set(
+            vec![
+                ("age".to_string(), "18".to_string()),
+                ("name".to_string(), "John".to_string()),
+            ],
+            vec![],
+        );
         assert_eq!(set_clause.serialize(), "age = 18, name = 'John'");
     }
 
     #[test]
     fn test_get_pairs() {
-        let set_clause = Set(vec![
-            ("age".to_string(), "18".to_string()),
-            ("name".to_string(), "John".to_string()),
-        ]);
+        let set_clause = Set(
+            vec![
+                ("age".to_string(), "18".to_string()),
+                ("name".to_string(), "John".to_string()),
+            ],
+            vec![],
+        );
         let pairs = set_clause.get_pairs();
         assert_eq!(
             pairs,
@@ -150,4 +200,43 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_new_from_tokens_counter_increments() {
+        let tokens = vec![
+            "SET",
+            "departures",
+            "=",
+            "departures",
+            "+",
+            "1",
+            "late",
+            "=",
+            "late",
+            "-",
+            "2",
+            "delayed",
+            "=",
+            "delayed",
+            "-3",
+        ];
+        let set_clause = Set::new_from_tokens(tokens).unwrap();
+        assert_eq!(
+            set_clause.get_increments(),
+            &vec![
+                ("departures".to_string(), 1),
+                ("late".to_string(), -2),
+                ("delayed".to_string(), -3)
+            ]
+        );
+        assert!(set_clause.get_pairs().is_empty());
+        assert_eq!(
+            set_clause.serialize(),
+            "departures = departures + 1, late = late - 2, delayed = delayed - 3"
+        );
+
+        let tokens = vec!["SET", "departures", "=", "departures", "+", "one"];
+        let result = Set::new_from_tokens(tokens);
+        assert!(matches!(result, Err(CQLError::InvalidSyntax)));
+    }
 }
@@ -1,6 +1,7 @@
 use crate::clauses::types::alter_table_op::AlterTableOperation;
 use crate::clauses::types::column::Column;
 use crate::clauses::types::datatype::DataType;
+use crate::clauses::types::table_options::TableOption;
 use crate::errors::CQLError;
 use crate::QueryCreator;
 use std::cmp::PartialEq;
@@ -82,7 +83,8 @@ impl AlterTable {
     ///
     /// # Validation
     /// - The query must begin with `ALTER TABLE`.
-    /// - Operations supported include `ADD`, `DROP`, `MODIFY`, and `RENAME`.
+    /// - Operations supported include `ADD`, `DROP`, `MODIFY`, `RENAME` and `WITH`, which sets
+    ///   options of the table (`WITH <option> = <value> AND ...`) and must be the last one.
     pub fn new_from_tokens(query: Vec<String>) -> Result<AlterTable, CQLError> {
         if query.len() < 4
             || query[0].to_uppercase() != "ALTER"
@@ -177,6 +179,25 @@ impl AlterTable {
                     ));
                     i += 4;
                 }
+                "WITH" => {
+                    let mut options = Vec::new();
+                    let mut j = i + 1;
+                    loop {
+                        if j + 2 >= operations.len() || operations[j + 1] != "=" {
+                            return Err(CQLError::InvalidSyntax);
+                        }
+                        options.push(TableOption::new(&operations[j], &operations[j + 2])?);
+                        j += 3;
+
+                        match operations.get(j) {
+                            Some(token) if token.to_uppercase() == "AND" => j += 1,
+                            Some(_) => return Err(CQLError::InvalidSyntax),
+                            None => break,
+                        }
+                    }
+                    ops.push(AlterTableOperation::SetOptions(options));
+                    i = j;
+                }
                 _ => return Err(CQLError::InvalidSyntax),
             }
             i += 1;
@@ -213,6 +234,10 @@ impl AlterTable {
                 AlterTableOperation::RenameColumn(old_name, new_name) => {
                     format!("RENAME {} TO {}", old_name, new_name)
                 }
+                AlterTableOperation::SetOptions(options) => {
+                    let options_str: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+                    format!("WITH {}", options_str.join(" AND "))
+                }
             })
             .collect();
 
@@ -375,4 +400,22 @@ mod tests {
             )]
         );
     }
+
+    #[test]
+    fn test_alter_table_with_options() {
+        let serialized =
+            "ALTER TABLE sky.flights WITH default_time_to_live = 3600 AND comment = 'live positions'";
+        let alter_table = AlterTable::deserialize(serialized).unwrap();
+        assert_eq!(
+            alter_table.get_operations(),
+            vec![AlterTableOperation::SetOptions(vec![
+                TableOption::DefaultTimeToLive(3600),
+                TableOption::Comment("live positions".to_string()),
+            ])]
+        );
+        assert_eq!(alter_table.serialize(), serialized);
+
+        assert!(AlterTable::deserialize("ALTER TABLE flights WITH comment").is_err());
+        assert!(AlterTable::deserialize("ALTER TABLE flights WITH speed = 3").is_err());
+    }
 }
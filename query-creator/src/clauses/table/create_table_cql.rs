@@ -1,5 +1,6 @@
 use crate::clauses::types::column::Column;
 use crate::clauses::types::datatype::DataType;
+use crate::clauses::types::table_options::{TableOption, TableOptions};
 use crate::errors::CQLError;
 use crate::QueryCreator;
 use std::cmp::PartialEq;
@@ -20,6 +21,8 @@ use std::str::FromStr;
 ///   - A list of columns for the table, including their definitions.
 /// - `clustering_columns_in_order: Vec<String>`
 ///   - The clustering columns of the table, in the specified order.
+/// - `options: TableOptions`
+///   - The options given in the `WITH` clause, like `default_time_to_live` and `comment`.
 ///
 /// # Purpose
 /// This struct models the `CREATE TABLE` operation in CQL, providing methods for parsing,
@@ -30,6 +33,7 @@ pub struct CreateTable {
     pub if_not_exists_clause: bool,
     pub columns: Vec<Column>,
     pub clustering_columns_in_order: Vec<String>,
+    pub options: TableOptions,
 }
 
 impl CreateTable {
@@ -165,6 +169,28 @@ impl CreateTable {
         self.clustering_columns_in_order.clone()
     }
 
+    /// Retrieves the options of the table.
+    ///
+    /// # Returns
+    /// - `&TableOptions` with the options given in the `WITH` clause, or their defaults.
+    pub fn get_options(&self) -> &TableOptions {
+        &self.options
+    }
+
+    /// Checks that the counter columns of the table, if any, are valid: counters can not be part of
+    /// the primary key, and every other column of a table with counters must be a counter.
+    ///
+    /// # Returns
+    /// - `bool` indicating whether the counters are valid.
+    pub fn has_valid_counters(&self) -> bool {
+        let is_key = |column: &&Column| column.is_partition_key || column.is_clustering_column;
+        let (key, regular): (Vec<&Column>, Vec<&Column>) = self.columns.iter().partition(is_key);
+        let is_counter = |column: &&Column| column.data_type == DataType::Counter;
+
+        !key.iter().any(is_counter)
+            && (regular.iter().all(is_counter) || !regular.iter().any(is_counter))
+    }
+
     /// Constructs a `CreateTable` instance from a vector of tokens.
     ///
     /// # Parameters
@@ -287,26 +313,57 @@ impl CreateTable {
             }
         }
 
-        // Procesar WITH CLUSTERING ORDER BY si existe
+        // Procesar las opciones del WITH, separadas por AND
         index += 1;
-        if index + 4 < tokens.len()
-            && tokens[index] == "WITH"
-            && tokens[index + 1] == "CLUSTERING"
-            && tokens[index + 2] == "ORDER"
-            && tokens[index + 3] == "BY"
-        {
-            let clustering_order_def = &tokens[index + 4];
-            let order_parts: Vec<&str> = clustering_order_def.split(',').collect();
-
-            for order_part in order_parts {
-                let parts: Vec<&str> = order_part.split_whitespace().collect();
-                if parts.len() == 2 {
-                    let col_name = parts[0].trim().to_string();
-                    let order = parts[1].trim().to_uppercase();
-
-                    if order == "ASC" || order == "DESC" {
-                        clustering_orders.insert(col_name, order);
+        let mut options = TableOptions::default();
+        if index < tokens.len() {
+            if tokens[index].to_uppercase() != "WITH" {
+                return Err(CQLError::InvalidSyntax);
+            }
+            index += 1;
+
+            loop {
+                if index >= tokens.len() {
+                    return Err(CQLError::InvalidSyntax);
+                }
+
+                if tokens[index].to_uppercase() == "CLUSTERING" {
+                    // WITH CLUSTERING ORDER BY (col ASC, ...)
+                    if index + 3 >= tokens.len()
+                        || tokens[index + 1].to_uppercase() != "ORDER"
+                        || tokens[index + 2].to_uppercase() != "BY"
+                    {
+                        return Err(CQLError::InvalidSyntax);
                     }
+
+                    let clustering_order_def = &tokens[index + 3];
+                    let order_parts: Vec<&str> = clustering_order_def.split(',').collect();
+
+                    for order_part in order_parts {
+                        let parts: Vec<&str> = order_part.split_whitespace().collect();
+                        if parts.len() == 2 {
+                            let col_name = parts[0].trim().to_string();
+                            let order = parts[1].trim().to_uppercase();
+
+                            if order == "ASC" || order == "DESC" {
+                                clustering_orders.insert(col_name, order);
+                            }
+                        }
+                    }
+                    index += 4;
+                } else {
+                    // WITH <opcion> = <valor>
+                    if index + 2 >= tokens.len() || tokens[index + 1] != "=" {
+                        return Err(CQLError::InvalidSyntax);
+                    }
+                    options.apply(TableOption::new(&tokens[index], &tokens[index + 2])?);
+                    index += 3;
+                }
+
+                match tokens.get(index) {
+                    Some(token) if token.to_uppercase() == "AND" => index += 1,
+                    Some(_) => return Err(CQLError::InvalidSyntax),
+                    None => break,
                 }
             }
         }
@@ -329,6 +386,7 @@ impl CreateTable {
             if_not_exists_clause,
             columns,
             clustering_columns_in_order: clustering_key_cols,
+            options,
         })
     }
 
@@ -414,11 +472,19 @@ impl CreateTable {
             columns_str.join(", ")
         );
 
-        // Añadir la cláusula WITH CLUSTERING ORDER BY si hay órdenes de clustering
+        // Añadir la cláusula WITH con el orden de clustering y las opciones de la tabla
+        let mut with_clauses: Vec<String> = Vec::new();
         if !ordered_clustering_orders.is_empty() {
-            query.push_str(" WITH CLUSTERING ORDER BY (");
-            query.push_str(&ordered_clustering_orders.join(", "));
-            query.push(')');
+            with_clauses.push(format!(
+                "CLUSTERING ORDER BY ({})",
+                ordered_clustering_orders.join(", ")
+            ));
+        }
+        with_clauses.extend(self.options.non_default().iter().map(|o| o.to_string()));
+
+        if !with_clauses.is_empty() {
+            query.push_str(" WITH ");
+            query.push_str(&with_clauses.join(" AND "));
         }
 
         query
@@ -523,6 +589,7 @@ mod tests {
                 },
             ],
             clustering_columns_in_order: vec!["iata".to_string()],
+            options: Default::default(),
         };
 
         assert_eq!(result.unwrap(), expected_table);
@@ -570,6 +637,7 @@ mod tests {
                 },
             ],
             clustering_columns_in_order: vec!["iata".to_string()],
+            options: Default::default(),
         };
 
         assert_eq!(result.unwrap(), expected_table);
@@ -636,6 +704,7 @@ mod tests {
                 },
             ],
             clustering_columns_in_order: vec!["iata".to_string(), "name".to_string()],
+            options: Default::default(),
         };
 
         assert_eq!(result.unwrap(), expected_table);
@@ -661,4 +730,45 @@ mod tests {
             vec!["iata".to_string(), "name".to_string()]
         );
     }
+
+    #[test]
+    fn test_create_table_with_options() {
+        let query =
+            "CREATE TABLE sky.positions (flight TEXT, time INT, PRIMARY KEY (flight, time)) \
+            WITH CLUSTERING ORDER BY (time DESC) AND default_time_to_live = 3600 \
+            AND comment = 'live positions' AND read_repair_chance = 0.1";
+
+        let table = CreateTable::deserialize(query).unwrap();
+
+        assert_eq!(table.get_options().default_time_to_live, 3600);
+        assert_eq!(table.get_options().comment, "live positions");
+        assert_eq!(table.get_options().read_repair_chance, 0.1);
+        assert_eq!(table.get_columns()[1].clustering_order, "DESC");
+
+        let serialized = table.serialize();
+        assert!(serialized.ends_with(
+            "WITH CLUSTERING ORDER BY (time DESC) AND default_time_to_live = 3600 AND comment = 'live positions' AND read_repair_chance = 0.1"
+        ));
+        let deserialized = CreateTable::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized.get_options(), table.get_options());
+
+        let invalid =
+            "CREATE TABLE positions (flight TEXT PRIMARY KEY) WITH default_time_to_live = x";
+        assert!(CreateTable::deserialize(invalid).is_err());
+    }
+
+    #[test]
+    fn test_has_valid_counters() {
+        let table = |query: &str| {
+            CreateTable::new_from_tokens(QueryCreator::tokens_from_query(query)).unwrap()
+        };
+
+        assert!(table("CREATE TABLE departures (airport TEXT, count COUNTER, late COUNTER, PRIMARY KEY (airport))").has_valid_counters());
+        assert!(
+            table("CREATE TABLE flights (airport TEXT, status TEXT, PRIMARY KEY (airport))")
+                .has_valid_counters()
+        );
+        assert!(!table("CREATE TABLE departures (airport TEXT, count COUNTER, status TEXT, PRIMARY KEY (airport))").has_valid_counters());
+        assert!(!table("CREATE TABLE departures (airport TEXT, count COUNTER, late COUNTER, PRIMARY KEY (airport, count))").has_valid_counters());
+    }
 }
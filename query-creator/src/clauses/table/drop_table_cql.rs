@@ -7,6 +7,8 @@ use crate::errors::CQLError;
 ///   - The name of the table being dropped.
 /// - `keyspace_used_name: String`
 ///   - The keyspace containing the table, if specified.
+/// - `if_exists: bool`
+///   - Whether the query includes `IF EXISTS`, so dropping a table that does not exist is not an error.
 ///
 /// # Purpose
 /// This struct models the `DROP TABLE` operation in CQL, providing methods for parsing,
@@ -15,6 +17,7 @@ use crate::errors::CQLError;
 pub struct DropTable {
     table_name: String,
     keyspace_used_name: String,
+    if_exists: bool,
 }
 
 impl DropTable {
@@ -31,17 +34,25 @@ impl DropTable {
     ///   - If the query is invalid or improperly formatted.
     ///
     /// # Validation
-    /// - The query must contain exactly 3 tokens.
+    /// - The query must contain exactly 3 tokens, or 5 if it includes `IF EXISTS`.
     /// - The query must begin with `DROP TABLE`.
     pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
-        if query.len() != 3
+        if query.len() < 3
             || query[0].to_uppercase() != "DROP"
             || query[1].to_uppercase() != "TABLE"
         {
             return Err(CQLError::InvalidSyntax);
         }
 
-        let full_table_name = query[2].to_string();
+        let if_exists = query.len() == 5
+            && query[2].to_uppercase() == "IF"
+            && query[3].to_uppercase() == "EXISTS";
+
+        if query.len() != 3 && !if_exists {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let full_table_name = query[query.len() - 1].to_string();
         let (keyspace_used_name, table_name) = if full_table_name.contains('.') {
             let parts: Vec<&str> = full_table_name.split('.').collect();
             (parts[0].to_string(), parts[1].to_string())
@@ -52,6 +63,7 @@ impl DropTable {
         Ok(Self {
             table_name,
             keyspace_used_name,
+            if_exists,
         })
     }
 
@@ -63,21 +75,30 @@ impl DropTable {
         self.table_name.clone()
     }
 
+    /// Checks if the `IF EXISTS` clause is present.
+    ///
+    /// # Returns
+    /// - `bool` indicating whether the clause is included.
+    pub fn get_if_exists_clause(&self) -> bool {
+        self.if_exists
+    }
+
     /// Serializes the `DropTable` instance into a CQL query string.
     ///
     /// # Returns
     /// - `String` representing the `DROP TABLE` query in the following format:
     ///     ```sql
-    ///     DROP TABLE [<keyspace_name>.]<table_name>;
+    ///     DROP TABLE [IF EXISTS] [<keyspace_name>.]<table_name>;
     ///    
     pub fn serialize(&self) -> String {
+        let if_exists_str = if self.if_exists { "IF EXISTS " } else { "" };
         let table_name_str = if !self.keyspace_used_name.is_empty() {
             format!("{}.{}", self.keyspace_used_name, self.table_name)
         } else {
             self.table_name.clone()
         };
 
-        format!("DROP TABLE {}", table_name_str)
+        format!("DROP TABLE {}{}", if_exists_str, table_name_str)
     }
 
     /// Deserializes a CQL query string into a `DropTable` instance.
@@ -154,6 +175,7 @@ mod tests {
         let drop_table = DropTable {
             table_name: "test_table".to_string(),
             keyspace_used_name: "test_keyspace".to_string(),
+            if_exists: false,
         };
         let serialized = drop_table.serialize();
         assert_eq!(serialized, "DROP TABLE test_keyspace.test_table");
@@ -180,19 +202,41 @@ mod tests {
         assert_eq!(drop_table, Err(CQLError::InvalidSyntax));
     }
 
+    #[test]
+    fn test_if_exists() {
+        let serialized = "DROP TABLE IF EXISTS test_keyspace.test_table";
+        let drop_table = DropTable::deserialize(serialized).unwrap();
+
+        assert!(drop_table.get_if_exists_clause());
+        assert_eq!(drop_table.get_table_name(), "test_table");
+        assert_eq!(drop_table.get_used_keyspace(), "test_keyspace");
+        assert_eq!(drop_table.serialize(), serialized);
+
+        assert!(!DropTable::deserialize("DROP TABLE test_table")
+            .unwrap()
+            .get_if_exists_clause());
+        assert_eq!(
+            DropTable::deserialize("DROP TABLE IF test_table"),
+            Err(CQLError::InvalidSyntax)
+        );
+    }
+
     #[test]
     fn test_partial_eq() {
         let drop_table1 = DropTable {
             table_name: "test_table".to_string(),
             keyspace_used_name: String::new(),
+            if_exists: false,
         };
         let drop_table2 = DropTable {
             table_name: "test_table".to_string(),
             keyspace_used_name: String::new(),
+            if_exists: false,
         };
         let drop_table3 = DropTable {
             table_name: "another_table".to_string(),
             keyspace_used_name: String::new(),
+            if_exists: false,
         };
 
         assert_eq!(drop_table1, drop_table2);
@@ -1,5 +1,6 @@
 use super::column::Column;
 use super::datatype::DataType;
+use super::table_options::TableOption;
 
 #[derive(Debug, Clone)]
 pub enum AlterTableOperation {
@@ -7,6 +8,7 @@ pub enum AlterTableOperation {
     DropColumn(String),
     ModifyColumn(String, DataType, bool), // column name, new data type, allows null
     RenameColumn(String, String),         // old column name, new column name
+    SetOptions(Vec<TableOption>),         // options of the WITH clause
 }
 
 // Implementación de `PartialEq` para permitir comparación de `AlterTableOperation`
@@ -27,6 +29,10 @@ impl PartialEq for AlterTableOperation {
                 AlterTableOperation::RenameColumn(old1, new1),
                 AlterTableOperation::RenameColumn(old2, new2),
             ) => old1 == old2 && new1 == new2,
+            (
+                AlterTableOperation::SetOptions(options1),
+                AlterTableOperation::SetOptions(options2),
+            ) => options1 == options2,
             _ => false,
         }
     }
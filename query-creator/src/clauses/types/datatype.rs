@@ -25,6 +25,9 @@ pub enum DataType {
 
     /// Represents a UUID (CQL `UUID`).
     Uuid = 0x06,
+
+    /// Represents a counter (CQL `COUNTER`), a 64-bit integer only changed by increments.
+    Counter = 0x07,
 }
 
 impl std::str::FromStr for DataType {
@@ -49,6 +52,7 @@ impl std::str::FromStr for DataType {
             "DOUBLE" => Ok(DataType::Double),
             "TIMESTAMP" => Ok(DataType::Timestamp),
             "UUID" => Ok(DataType::Uuid),
+            "COUNTER" => Ok(DataType::Counter),
             _ => Err(CQLError::InvalidSyntax),
         }
     }
@@ -69,10 +73,11 @@ impl DataType {
             DataType::Double => "DOUBLE",
             DataType::Timestamp => "TIMESTAMP",
             DataType::Uuid => "UUID",
+            DataType::Counter => "COUNTER",
         }
     }
 
-    /// Compares two values (as strings) of the current `DataType` with a specified operator (e.g., `=`, `>`, `<`).
+    /// Compares two values (as strings) of the current `DataType` with a specified operator (e.g., `=`, `>`, `<=`).
     ///
     /// # Arguments
     ///
@@ -89,11 +94,7 @@ impl DataType {
             DataType::Int => {
                 let x = x.parse::<i32>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<i32>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
             DataType::String => {
                 let x = x
@@ -102,56 +103,32 @@ impl DataType {
                 let y = y
                     .parse::<String>()
                     .map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
             DataType::Boolean => {
                 let x = x.parse::<bool>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<bool>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x & !y),
-                    Operator::Lesser => Ok(!x & y),
-                }
+                Ok(operator.compare(&x, &y))
             }
             DataType::Float => {
                 let x = x.parse::<f32>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<f32>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
             DataType::Double => {
                 let x = x.parse::<f64>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<f64>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
-            DataType::Timestamp => {
+            DataType::Timestamp | DataType::Counter => {
                 let x = x.parse::<i64>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<i64>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
             DataType::Uuid => {
                 let x = x.parse::<Uuid>().map_err(|_| CQLError::InvalidCondition)?;
                 let y = y.parse::<Uuid>().map_err(|_| CQLError::InvalidCondition)?;
-                match operator {
-                    Operator::Equal => Ok(x == y),
-                    Operator::Greater => Ok(x > y),
-                    Operator::Lesser => Ok(x < y),
-                }
+                Ok(operator.compare(&x, &y))
             }
         }
     }
@@ -176,6 +153,7 @@ impl DataType {
             DataType::Double => value.parse::<f64>().is_ok(),
             DataType::Timestamp => self.is_valid_timestamp(value),
             DataType::Uuid => value.parse::<Uuid>().is_ok(),
+            DataType::Counter => value.parse::<i64>().is_ok(),
         }
     }
 
@@ -0,0 +1,170 @@
+use crate::errors::CQLError;
+use std::fmt;
+
+/// Name of the option with the default TTL of the rows of a table.
+pub const DEFAULT_TIME_TO_LIVE: &str = "default_time_to_live";
+
+/// Name of the option with the comment of a table.
+pub const COMMENT: &str = "comment";
+
+/// Name of the option with the chance of a read of a table to repair its replicas.
+pub const READ_REPAIR_CHANCE: &str = "read_repair_chance";
+
+/// Chance of a read to repair its replicas when the table does not set one: every read does.
+pub const DEFAULT_READ_REPAIR_CHANCE: f64 = 1.0;
+
+/// A single option of a table, as given in `WITH <option> = <value>` by `CREATE TABLE` and
+/// `ALTER TABLE`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableOption {
+    /// Seconds the rows written without an explicit TTL live for. `0` means they never expire.
+    DefaultTimeToLive(u64),
+    /// Free text describing the table.
+    Comment(String),
+    /// Chance, between 0 and 1, of a read that finds replicas out of date to write them the
+    /// latest rows. Reads always return the latest rows, whether they repair or not.
+    ReadRepairChance(f64),
+}
+
+impl TableOption {
+    /// Builds the option `name` (case insensitive) with the given value.
+    ///
+    /// # Returns
+    /// - `Ok(TableOption)` if the option exists and the value is valid for it.
+    /// - `Err(CQLError::InvalidSyntax)` otherwise.
+    pub fn new(name: &str, value: &str) -> Result<Self, CQLError> {
+        match name.to_lowercase().as_str() {
+            DEFAULT_TIME_TO_LIVE => value
+                .parse::<u64>()
+                .map(TableOption::DefaultTimeToLive)
+                .map_err(|_| CQLError::InvalidSyntax),
+            COMMENT if !value.contains('\'') => Ok(TableOption::Comment(value.to_string())),
+            READ_REPAIR_CHANCE => value
+                .parse::<f64>()
+                .ok()
+                .filter(|chance| (0.0..=1.0).contains(chance))
+                .map(TableOption::ReadRepairChance)
+                .ok_or(CQLError::InvalidSyntax),
+            _ => Err(CQLError::InvalidSyntax),
+        }
+    }
+}
+
+/// Writes the option as CQL, e.g. `comment = 'flights of the day'`.
+impl fmt::Display for TableOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableOption::DefaultTimeToLive(seconds) => {
+                write!(f, "{} = {}", DEFAULT_TIME_TO_LIVE, seconds)
+            }
+            TableOption::Comment(comment) => write!(f, "{} = '{}'", COMMENT, comment),
+            TableOption::ReadRepairChance(chance) => {
+                write!(f, "{} = {}", READ_REPAIR_CHANCE, chance)
+            }
+        }
+    }
+}
+
+/// The options of a table. Every option not given when creating the table keeps its default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableOptions {
+    pub default_time_to_live: u64,
+    pub comment: String,
+    pub read_repair_chance: f64,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            default_time_to_live: 0,
+            comment: String::new(),
+            read_repair_chance: DEFAULT_READ_REPAIR_CHANCE,
+        }
+    }
+}
+
+impl TableOptions {
+    /// Sets the given option, keeping the others.
+    pub fn apply(&mut self, option: TableOption) {
+        match option {
+            TableOption::DefaultTimeToLive(seconds) => self.default_time_to_live = seconds,
+            TableOption::Comment(comment) => self.comment = comment,
+            TableOption::ReadRepairChance(chance) => self.read_repair_chance = chance,
+        }
+    }
+
+    /// Returns the TTL, in seconds, a write to the table gets: the one given in the write if it
+    /// has one, or the default of the table otherwise. `None` means the row never expires.
+    pub fn ttl_for(&self, ttl: Option<u64>) -> Option<u64> {
+        match ttl {
+            Some(ttl) => Some(ttl),
+            None if self.default_time_to_live > 0 => Some(self.default_time_to_live),
+            None => None,
+        }
+    }
+
+    /// Returns the options that differ from their defaults, in the order they are written in CQL.
+    pub fn non_default(&self) -> Vec<TableOption> {
+        let mut options = Vec::new();
+        if self.default_time_to_live > 0 {
+            options.push(TableOption::DefaultTimeToLive(self.default_time_to_live));
+        }
+        if !self.comment.is_empty() {
+            options.push(TableOption::Comment(self.comment.clone()));
+        }
+        if self.read_repair_chance != DEFAULT_READ_REPAIR_CHANCE {
+            options.push(TableOption::ReadRepairChance(self.read_repair_chance));
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_option_new() {
+        assert_eq!(
+            TableOption::new("DEFAULT_TIME_TO_LIVE", "60").unwrap(),
+            TableOption::DefaultTimeToLive(60)
+        );
+        assert_eq!(
+            TableOption::new("comment", "flights of the day").unwrap(),
+            TableOption::Comment("flights of the day".to_string())
+        );
+        assert_eq!(
+            TableOption::new("read_repair_chance", "0.1").unwrap(),
+            TableOption::ReadRepairChance(0.1)
+        );
+        assert!(TableOption::new("default_time_to_live", "-1").is_err());
+        assert!(TableOption::new("read_repair_chance", "1.5").is_err());
+        assert!(TableOption::new("read_repair_chance", "NaN").is_err());
+        assert!(TableOption::new("gc_grace_seconds", "10").is_err());
+    }
+
+    #[test]
+    fn test_table_options_apply_and_ttl() {
+        let mut options = TableOptions::default();
+        assert_eq!(options.ttl_for(None), None);
+        assert!(options.non_default().is_empty());
+
+        options.apply(TableOption::DefaultTimeToLive(3600));
+        assert_eq!(options.ttl_for(None), Some(3600));
+        assert_eq!(options.ttl_for(Some(10)), Some(10));
+        assert_eq!(
+            options.non_default(),
+            vec![TableOption::DefaultTimeToLive(3600)]
+        );
+        assert_eq!(
+            options.non_default()[0].to_string(),
+            "default_time_to_live = 3600"
+        );
+
+        options.apply(TableOption::ReadRepairChance(0.25));
+        assert_eq!(
+            options.non_default()[1].to_string(),
+            "read_repair_chance = 0.25"
+        );
+    }
+}
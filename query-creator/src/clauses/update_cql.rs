@@ -2,7 +2,7 @@ use super::if_cql::If;
 use super::set_cql::Set;
 use super::where_cql::Where;
 use crate::errors::CQLError;
-use crate::utils::{is_set, is_update, is_where};
+use crate::utils::{is_set, is_update, is_using, is_where, ttl_from_tokens};
 use crate::QueryCreator;
 
 /// Struct representing the `UPDATE` SQL clause.
@@ -16,6 +16,8 @@ use crate::QueryCreator;
 /// * `set_clause` - The `SET` clause specifying the columns and values to update.
 /// * `where_clause` - Optional `WHERE` clause for filtering records to update.
 /// * `if_clause` - Optional `IF` clause specifying conditions for the update.
+/// * `ttl` - The seconds given with `USING TTL`, after which the updated row expires. `None` leaves
+///   the `default_time_to_live` of the table, and `Some(0)` makes the row never expire.
 #[derive(PartialEq, Debug, Clone)]
 pub struct Update {
     pub table_name: String,
@@ -23,6 +25,7 @@ pub struct Update {
     pub set_clause: Set,
     pub where_clause: Option<Where>,
     pub if_clause: Option<If>,
+    pub ttl: Option<u64>,
 }
 
 impl Update {
@@ -32,15 +35,24 @@ impl Update {
     ///
     /// * `tokens` - A vector of `String` tokens representing the `UPDATE` clause.
     ///
-    /// The tokens must include the table name, `SET` clause, and optionally `WHERE` and `IF` clauses.
+    /// The tokens must include the table name, `SET` clause, and optionally `USING TTL`, `WHERE` and
+    /// `IF` clauses.
     ///
     /// # Returns
     /// * `Ok(Update)` - A successfully parsed `Update` struct.
     /// * `Err(CQLError::InvalidSyntax)` - If the tokens are invalid or improperly formatted.
-    pub fn new_from_tokens(tokens: Vec<String>) -> Result<Self, CQLError> {
+    pub fn new_from_tokens(mut tokens: Vec<String>) -> Result<Self, CQLError> {
         if tokens.len() < 6 {
             return Err(CQLError::InvalidSyntax);
         }
+
+        // `UPDATE table USING TTL seconds SET ...`
+        let mut ttl = None;
+        if is_using(&tokens[2]) {
+            ttl = Some(ttl_from_tokens(&tokens[3..])?);
+            tokens.drain(2..5);
+        }
+
         let mut where_tokens = Vec::new();
         let mut set_tokens = Vec::new();
         let mut table_name = String::new();
@@ -109,6 +121,7 @@ impl Update {
             where_clause,
             set_clause,
             if_clause,
+            ttl,
         })
     }
 
@@ -123,11 +136,11 @@ impl Update {
             self.table_name.clone()
         };
 
-        let mut result = format!(
-            "UPDATE {} SET {}",
-            table_name_str,
-            self.set_clause.serialize()
-        );
+        let mut result = format!("UPDATE {}", table_name_str);
+        if let Some(ttl) = self.ttl {
+            result.push_str(&format!(" USING TTL {}", ttl));
+        }
+        result.push_str(&format!(" SET {}", self.set_clause.serialize()));
 
         if let Some(where_clause) = &self.where_clause {
             result.push_str(&format!(" WHERE {}", where_clause.serialize()));
@@ -200,9 +213,10 @@ mod tests {
             Update {
                 table_name: String::from("table"),
                 keyspace_used_name: String::new(),
-                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))]),
+                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))], vec![]),
                 where_clause: None,
                 if_clause: None,
+                ttl: None,
             }
         );
     }
@@ -223,9 +237,10 @@ mod tests {
             Update {
                 table_name: String::from("table"),
                 keyspace_used_name: String::from("keyspace"),
-                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))]),
+                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))], vec![]),
                 where_clause: None,
                 if_clause: None,
+                ttl: None,
             }
         );
     }
@@ -250,7 +265,7 @@ mod tests {
             Update {
                 table_name: String::from("table"),
                 keyspace_used_name: String::new(),
-                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))]),
+                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))], vec![]),
                 where_clause: Some(Where {
                     condition: Condition::Simple {
                         field: String::from("edad"),
@@ -259,6 +274,7 @@ mod tests {
                     },
                 }),
                 if_clause: None,
+                ttl: None,
             }
         );
     }
@@ -287,7 +303,7 @@ mod tests {
             Update {
                 table_name: String::from("table"),
                 keyspace_used_name: String::new(),
-                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))]),
+                set_clause: Set(vec![(String::from("nombre"), String::from("Alen"))], vec![]),
                 where_clause: Some(Where {
                     condition: Condition::Simple {
                         field: String::from("edad"),
@@ -302,7 +318,37 @@ mod tests {
                         value: String::from("john"),
                     },
                 }),
+                ttl: None,
             }
         );
     }
+
+    #[test]
+    fn update_using_ttl_round_trip() {
+        let s = "UPDATE sky.positions USING TTL 60 SET lat = 10 WHERE flight = AR1";
+        let update = Update::deserialize(s).unwrap();
+        assert_eq!(update.ttl, Some(60));
+        assert_eq!(update.table_name, "positions");
+        assert_eq!(
+            update.set_clause,
+            Set(vec![(String::from("lat"), String::from("10"))], vec![])
+        );
+        assert_eq!(update.serialize(), s);
+
+        assert_eq!(
+            Update::deserialize("UPDATE positions USING TTL SET lat = 10"),
+            Err(CQLError::InvalidSyntax)
+        );
+    }
+
+    #[test]
+    fn update_counter_round_trip() {
+        let s = "UPDATE sky.departures SET count = count + 1 WHERE airport = EZE";
+        let update = Update::deserialize(s).unwrap();
+        assert_eq!(
+            update.set_clause,
+            Set(vec![], vec![(String::from("count"), 1)])
+        );
+        assert_eq!(update.serialize(), s);
+    }
 }
@@ -0,0 +1,146 @@
+use crate::errors::CQLError;
+use crate::QueryCreator;
+
+#[derive(Debug, Clone, PartialEq)]
+
+/// Represents an `ALTER USER` operation in CQL.
+///
+/// # Fields
+/// - `name: String`
+///   - The name of the user to alter.
+/// - `password: Option<String>`
+///   - The new password of the user, if it changes.
+/// - `superuser: Option<bool>`
+///   - Whether the user becomes a superuser (`SUPERUSER`) or stops being one (`NOSUPERUSER`), if it changes.
+///
+/// # Purpose
+/// This struct models the `ALTER USER` operation in CQL, which changes at least one of them:
+/// ```sql
+/// ALTER USER <name> [WITH PASSWORD '<password>'] [SUPERUSER | NOSUPERUSER];
+/// ```
+pub struct AlterUser {
+    pub name: String,
+    pub password: Option<String>,
+    pub superuser: Option<bool>,
+}
+
+impl AlterUser {
+    /// Creates a new `AlterUser` instance from a vector of query tokens.
+    ///
+    /// # Parameters
+    /// - `query: Vec<String>`:
+    ///   - A vector of strings representing the tokens of a CQL `ALTER USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(AlterUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid, improperly formatted or changes nothing.
+    pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
+        if query.len() < 4
+            || query[0].to_uppercase() != "ALTER"
+            || query[1].to_uppercase() != "USER"
+        {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let name = query[2].clone();
+        let mut index = 3;
+        let mut password = None;
+        if query[index].to_uppercase() == "WITH" {
+            if query.len() < 6 || query[index + 1].to_uppercase() != "PASSWORD" {
+                return Err(CQLError::InvalidSyntax);
+            }
+            password = Some(query[index + 2].clone());
+            index += 3;
+        }
+
+        let superuser = match query.get(index).map(|token| token.to_uppercase()) {
+            None => None,
+            Some(token) if token == "SUPERUSER" => Some(true),
+            Some(token) if token == "NOSUPERUSER" => Some(false),
+            Some(_) => return Err(CQLError::InvalidSyntax),
+        };
+        if superuser.is_some() {
+            index += 1;
+        }
+        if query.len() > index {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        Ok(Self {
+            name,
+            password,
+            superuser,
+        })
+    }
+
+    /// Retrieves the name of the user.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - The name of the user.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Serializes the `AlterUser` structure to a CQL query string.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - A string representing the `ALTER USER` CQL query.
+    pub fn serialize(&self) -> String {
+        let mut query = format!("ALTER USER {}", self.name);
+        if let Some(password) = &self.password {
+            query.push_str(&format!(" WITH PASSWORD '{}'", password));
+        }
+        match self.superuser {
+            Some(true) => query.push_str(" SUPERUSER"),
+            Some(false) => query.push_str(" NOSUPERUSER"),
+            None => {}
+        }
+        query
+    }
+
+    /// Deserializes a CQL query string into an `AlterUser` structure.
+    ///
+    /// # Parameters
+    /// - `query: &str`:
+    ///   - A string representing a CQL `ALTER USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(AlterUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn deserialize(query: &str) -> Result<Self, CQLError> {
+        Self::new_from_tokens(QueryCreator::tokens_from_query(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alter_user() {
+        let query = "ALTER USER pilot WITH PASSWORD 'landing' SUPERUSER";
+        let alter_user = AlterUser::deserialize(query).unwrap();
+
+        assert_eq!(alter_user.get_name(), "pilot");
+        assert_eq!(alter_user.password, Some("landing".to_string()));
+        assert_eq!(alter_user.superuser, Some(true));
+        assert_eq!(alter_user.serialize(), query);
+
+        let alter_user = AlterUser::deserialize("ALTER USER pilot NOSUPERUSER").unwrap();
+        assert_eq!(alter_user.password, None);
+        assert_eq!(alter_user.superuser, Some(false));
+    }
+
+    #[test]
+    fn test_alter_user_invalid_syntax() {
+        assert!(AlterUser::deserialize("ALTER USER pilot").is_err());
+        assert!(AlterUser::deserialize("ALTER USER pilot WITH 'landing'").is_err());
+        assert!(AlterUser::deserialize("ALTER USER pilot SUPERUSER NOSUPERUSER").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::errors::CQLError;
+use crate::QueryCreator;
+
+#[derive(Debug, Clone, PartialEq)]
+
+/// Represents a `CREATE USER` operation in CQL.
+///
+/// # Fields
+/// - `name: String`
+///   - The name the user logs in with.
+/// - `password: String`
+///   - The password of the user, as written in the query.
+/// - `superuser: bool`
+///   - Whether the user may run any statement (`SUPERUSER`), instead of only the ones granted to it (`NOSUPERUSER`,
+///     the default).
+/// - `if_not_exists_clause: bool`
+///   - Whether the query includes `IF NOT EXISTS`, so creating a user that exists is not an error.
+///
+/// # Purpose
+/// This struct models the `CREATE USER` operation in CQL:
+/// ```sql
+/// CREATE USER [IF NOT EXISTS] <name> WITH PASSWORD '<password>' [SUPERUSER | NOSUPERUSER];
+/// ```
+pub struct CreateUser {
+    pub name: String,
+    pub password: String,
+    pub superuser: bool,
+    pub if_not_exists_clause: bool,
+}
+
+impl CreateUser {
+    /// Creates a new `CreateUser` instance from a vector of query tokens.
+    ///
+    /// # Parameters
+    /// - `query: Vec<String>`:
+    ///   - A vector of strings representing the tokens of a CQL `CREATE USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(CreateUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
+        if query.len() < 6
+            || query[0].to_uppercase() != "CREATE"
+            || query[1].to_uppercase() != "USER"
+        {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let mut index = 2;
+        let if_not_exists_clause = query[index].to_uppercase() == "IF";
+        if if_not_exists_clause {
+            if query.len() < 9
+                || query[3].to_uppercase() != "NOT"
+                || query[4].to_uppercase() != "EXISTS"
+            {
+                return Err(CQLError::InvalidSyntax);
+            }
+            index += 3;
+        }
+
+        let name = query[index].clone();
+        if query[index + 1].to_uppercase() != "WITH"
+            || query[index + 2].to_uppercase() != "PASSWORD"
+        {
+            return Err(CQLError::InvalidSyntax);
+        }
+        let password = query[index + 3].clone();
+
+        let superuser = match query.get(index + 4).map(|token| token.to_uppercase()) {
+            None => false,
+            Some(token) if token == "SUPERUSER" => true,
+            Some(token) if token == "NOSUPERUSER" => false,
+            Some(_) => return Err(CQLError::InvalidSyntax),
+        };
+        if query.len() > index + 5 {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        Ok(Self {
+            name,
+            password,
+            superuser,
+            if_not_exists_clause,
+        })
+    }
+
+    /// Retrieves the name of the user.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - The name of the user.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Serializes the `CreateUser` structure to a CQL query string.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - A string representing the `CREATE USER` CQL query.
+    pub fn serialize(&self) -> String {
+        let if_not_exists = if self.if_not_exists_clause {
+            "IF NOT EXISTS "
+        } else {
+            ""
+        };
+        let superuser = if self.superuser {
+            "SUPERUSER"
+        } else {
+            "NOSUPERUSER"
+        };
+        format!(
+            "CREATE USER {}{} WITH PASSWORD '{}' {}",
+            if_not_exists, self.name, self.password, superuser
+        )
+    }
+
+    /// Deserializes a CQL query string into a `CreateUser` structure.
+    ///
+    /// # Parameters
+    /// - `query: &str`:
+    ///   - A string representing a CQL `CREATE USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(CreateUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn deserialize(query: &str) -> Result<Self, CQLError> {
+        Self::new_from_tokens(QueryCreator::tokens_from_query(query))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_user() {
+        let create_user =
+            CreateUser::deserialize("CREATE USER pilot WITH PASSWORD 'take off'").unwrap();
+
+        assert_eq!(create_user.get_name(), "pilot");
+        assert_eq!(create_user.password, "take off");
+        assert!(!create_user.superuser);
+        assert_eq!(
+            create_user.serialize(),
+            "CREATE USER pilot WITH PASSWORD 'take off' NOSUPERUSER"
+        );
+    }
+
+    #[test]
+    fn test_create_superuser_if_not_exists() {
+        let query = "CREATE USER IF NOT EXISTS tower WITH PASSWORD 'secret' SUPERUSER";
+        let create_user = CreateUser::deserialize(query).unwrap();
+
+        assert!(create_user.if_not_exists_clause);
+        assert!(create_user.superuser);
+        assert_eq!(create_user.serialize(), query);
+    }
+
+    #[test]
+    fn test_create_user_invalid_syntax() {
+        assert!(CreateUser::deserialize("CREATE USER pilot").is_err());
+        assert!(CreateUser::deserialize("CREATE USER pilot WITH 'secret'").is_err());
+        assert!(CreateUser::deserialize("CREATE USER pilot WITH PASSWORD 'secret' ADMIN").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::errors::CQLError;
+
+#[derive(Debug, Clone, PartialEq)]
+
+/// Represents a `DROP USER` operation in CQL.
+///
+/// # Fields
+/// - `name: String`
+///   - The name of the user to drop, with its permissions.
+/// - `if_exists: bool`
+///   - Whether the query includes `IF EXISTS`, so dropping a user that does not exist is not an error.
+///
+/// # Purpose
+/// This struct models the `DROP USER` operation in CQL:
+/// ```sql
+/// DROP USER [IF EXISTS] <name>;
+/// ```
+pub struct DropUser {
+    name: String,
+    if_exists: bool,
+}
+
+impl DropUser {
+    /// Creates a new `DropUser` instance from a vector of query tokens.
+    ///
+    /// # Parameters
+    /// - `query: Vec<String>`:
+    ///   - A vector of strings representing the tokens of a CQL `DROP USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(DropUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
+        if query.len() < 3 || query[0].to_uppercase() != "DROP" || query[1].to_uppercase() != "USER"
+        {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        let if_exists = query.len() == 5
+            && query[2].to_uppercase() == "IF"
+            && query[3].to_uppercase() == "EXISTS";
+
+        if query.len() != 3 && !if_exists {
+            return Err(CQLError::InvalidSyntax);
+        }
+
+        Ok(Self {
+            name: query[query.len() - 1].to_string(),
+            if_exists,
+        })
+    }
+
+    /// Retrieves the name of the user.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - The name of the user.
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Checks if the `IF EXISTS` clause is present.
+    ///
+    /// # Returns
+    /// - `bool`:
+    ///   - Whether the clause is included.
+    pub fn get_if_exists_clause(&self) -> bool {
+        self.if_exists
+    }
+
+    /// Serializes the `DropUser` structure to a CQL query string.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - A string representing the `DROP USER` CQL query.
+    pub fn serialize(&self) -> String {
+        let if_exists_str = if self.if_exists { "IF EXISTS " } else { "" };
+        format!("DROP USER {}{}", if_exists_str, self.name)
+    }
+
+    /// Deserializes a CQL query string into a `DropUser` structure.
+    ///
+    /// # Parameters
+    /// - `query: &str`:
+    ///   - A string representing a CQL `DROP USER` query.
+    ///
+    /// # Returns
+    /// - `Ok(DropUser)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn deserialize(query: &str) -> Result<Self, CQLError> {
+        let tokens = query.split_whitespace().map(|s| s.to_string()).collect();
+        Self::new_from_tokens(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_user() {
+        let drop_user = DropUser::deserialize("DROP USER IF EXISTS pilot").unwrap();
+
+        assert_eq!(drop_user.get_name(), "pilot");
+        assert!(drop_user.get_if_exists_clause());
+        assert_eq!(drop_user.serialize(), "DROP USER IF EXISTS pilot");
+
+        assert!(DropUser::deserialize("DROP USER").is_err());
+        assert!(DropUser::deserialize("DROP USER IF pilot").is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::errors::CQLError;
+
+/// Permissions a `GRANT` or `REVOKE` can name, besides `ALL PERMISSIONS`.
+pub const PERMISSIONS: [&str; 7] = [
+    "SELECT",
+    "MODIFY",
+    "CREATE",
+    "ALTER",
+    "DROP",
+    "DESCRIBE",
+    "AUTHORIZE",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+
+/// Represents a `GRANT` operation in CQL.
+///
+/// # Fields
+/// - `permission: Option<String>`
+///   - The permission granted, one of `PERMISSIONS`, or `None` for `ALL PERMISSIONS`.
+/// - `keyspace: Option<String>`
+///   - The keyspace the permission is granted on, or `None` for `ALL KEYSPACES`.
+/// - `user: String`
+///   - The user the permission is granted to.
+///
+/// # Purpose
+/// This struct models the `GRANT` operation in CQL:
+/// ```sql
+/// GRANT <permission> [PERMISSION] ON <KEYSPACE <name> | ALL KEYSPACES> TO <user>;
+/// GRANT ALL [PERMISSIONS] ON <KEYSPACE <name> | ALL KEYSPACES> TO <user>;
+/// ```
+pub struct Grant {
+    pub permission: Option<String>,
+    pub keyspace: Option<String>,
+    pub user: String,
+}
+
+impl Grant {
+    /// Creates a new `Grant` instance from a vector of query tokens.
+    ///
+    /// # Parameters
+    /// - `query: Vec<String>`:
+    ///   - A vector of strings representing the tokens of a CQL `GRANT` query.
+    ///
+    /// # Returns
+    /// - `Ok(Grant)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid, improperly formatted or names an unknown permission.
+    pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
+        let (permission, keyspace, user) = parse_permission_statement(&query, "GRANT", "TO")?;
+        Ok(Self {
+            permission,
+            keyspace,
+            user,
+        })
+    }
+
+    /// Serializes the `Grant` structure to a CQL query string.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - A string representing the `GRANT` CQL query.
+    pub fn serialize(&self) -> String {
+        format!(
+            "GRANT {} TO {}",
+            describe_permission_on(&self.permission, &self.keyspace),
+            self.user
+        )
+    }
+
+    /// Deserializes a CQL query string into a `Grant` structure.
+    ///
+    /// # Parameters
+    /// - `query: &str`:
+    ///   - A string representing a CQL `GRANT` query.
+    ///
+    /// # Returns
+    /// - `Ok(Grant)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn deserialize(query: &str) -> Result<Self, CQLError> {
+        let tokens = query.split_whitespace().map(|s| s.to_string()).collect();
+        Self::new_from_tokens(tokens)
+    }
+}
+
+/// Parses `<verb> <permission> ON <resource> <preposition> <user>`, the syntax shared by `GRANT`
+/// and `REVOKE`, into the permission, keyspace and user it names.
+pub(crate) fn parse_permission_statement(
+    query: &[String],
+    verb: &str,
+    preposition: &str,
+) -> Result<(Option<String>, Option<String>, String), CQLError> {
+    let tokens: Vec<String> = query.iter().map(|token| token.to_uppercase()).collect();
+    if tokens.len() < 6 || tokens[0] != verb {
+        return Err(CQLError::InvalidSyntax);
+    }
+
+    let mut index = 1;
+    let permission = match tokens[index].as_str() {
+        "ALL" => None,
+        permission if PERMISSIONS.contains(&permission) => Some(permission.to_string()),
+        _ => return Err(CQLError::InvalidSyntax),
+    };
+    index += 1;
+    if matches!(tokens[index].as_str(), "PERMISSION" | "PERMISSIONS") {
+        index += 1;
+    }
+
+    if tokens.get(index).map(String::as_str) != Some("ON") {
+        return Err(CQLError::InvalidSyntax);
+    }
+    index += 1;
+    let keyspace = match tokens.get(index).map(String::as_str) {
+        Some("ALL") if tokens.get(index + 1).map(String::as_str) == Some("KEYSPACES") => None,
+        Some("KEYSPACE") => Some(query.get(index + 1).ok_or(CQLError::InvalidSyntax)?.clone()),
+        _ => return Err(CQLError::InvalidSyntax),
+    };
+    index += 2;
+
+    if tokens.len() != index + 2 || tokens[index] != preposition {
+        return Err(CQLError::InvalidSyntax);
+    }
+    Ok((permission, keyspace, query[index + 1].clone()))
+}
+
+/// Writes `<permission> ON <resource>` as CQL, e.g. `SELECT ON KEYSPACE sky`.
+pub(crate) fn describe_permission_on(
+    permission: &Option<String>,
+    keyspace: &Option<String>,
+) -> String {
+    let permission = permission.as_deref().unwrap_or("ALL PERMISSIONS");
+    match keyspace {
+        Some(keyspace) => format!("{} ON KEYSPACE {}", permission, keyspace),
+        None => format!("{} ON ALL KEYSPACES", permission),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grant() {
+        let grant = Grant::deserialize("GRANT SELECT ON KEYSPACE sky TO pilot").unwrap();
+
+        assert_eq!(grant.permission, Some("SELECT".to_string()));
+        assert_eq!(grant.keyspace, Some("sky".to_string()));
+        assert_eq!(grant.user, "pilot");
+        assert_eq!(grant.serialize(), "GRANT SELECT ON KEYSPACE sky TO pilot");
+
+        let grant = Grant::deserialize("GRANT ALL PERMISSIONS ON ALL KEYSPACES TO tower").unwrap();
+        assert_eq!(grant.permission, None);
+        assert_eq!(grant.keyspace, None);
+        assert_eq!(
+            grant.serialize(),
+            "GRANT ALL PERMISSIONS ON ALL KEYSPACES TO tower"
+        );
+    }
+
+    #[test]
+    fn test_grant_invalid_syntax() {
+        assert!(Grant::deserialize("GRANT WRITE ON KEYSPACE sky TO pilot").is_err());
+        assert!(Grant::deserialize("GRANT SELECT ON TABLE sky TO pilot").is_err());
+        assert!(Grant::deserialize("GRANT SELECT ON KEYSPACE sky FROM pilot").is_err());
+        assert!(Grant::deserialize("GRANT SELECT ON KEYSPACE sky TO").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use super::grant_cql::{describe_permission_on, parse_permission_statement};
+use crate::errors::CQLError;
+
+#[derive(Debug, Clone, PartialEq)]
+
+/// Represents a `REVOKE` operation in CQL, which takes back a permission given by a `GRANT`.
+///
+/// # Fields
+/// - `permission: Option<String>`
+///   - The permission revoked, or `None` for `ALL PERMISSIONS`.
+/// - `keyspace: Option<String>`
+///   - The keyspace the permission is revoked on, or `None` for `ALL KEYSPACES`.
+/// - `user: String`
+///   - The user the permission is revoked from.
+///
+/// # Purpose
+/// This struct models the `REVOKE` operation in CQL:
+/// ```sql
+/// REVOKE <permission> [PERMISSION] ON <KEYSPACE <name> | ALL KEYSPACES> FROM <user>;
+/// REVOKE ALL [PERMISSIONS] ON <KEYSPACE <name> | ALL KEYSPACES> FROM <user>;
+/// ```
+pub struct Revoke {
+    pub permission: Option<String>,
+    pub keyspace: Option<String>,
+    pub user: String,
+}
+
+impl Revoke {
+    /// Creates a new `Revoke` instance from a vector of query tokens.
+    ///
+    /// # Parameters
+    /// - `query: Vec<String>`:
+    ///   - A vector of strings representing the tokens of a CQL `REVOKE` query.
+    ///
+    /// # Returns
+    /// - `Ok(Revoke)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid, improperly formatted or names an unknown permission.
+    pub fn new_from_tokens(query: Vec<String>) -> Result<Self, CQLError> {
+        let (permission, keyspace, user) = parse_permission_statement(&query, "REVOKE", "FROM")?;
+        Ok(Self {
+            permission,
+            keyspace,
+            user,
+        })
+    }
+
+    /// Serializes the `Revoke` structure to a CQL query string.
+    ///
+    /// # Returns
+    /// - `String`:
+    ///   - A string representing the `REVOKE` CQL query.
+    pub fn serialize(&self) -> String {
+        format!(
+            "REVOKE {} FROM {}",
+            describe_permission_on(&self.permission, &self.keyspace),
+            self.user
+        )
+    }
+
+    /// Deserializes a CQL query string into a `Revoke` structure.
+    ///
+    /// # Parameters
+    /// - `query: &str`:
+    ///   - A string representing a CQL `REVOKE` query.
+    ///
+    /// # Returns
+    /// - `Ok(Revoke)`:
+    ///   - If the query is valid and can be successfully parsed.
+    /// - `Err(CQLError::InvalidSyntax)`:
+    ///   - If the query is invalid or improperly formatted.
+    pub fn deserialize(query: &str) -> Result<Self, CQLError> {
+        let tokens = query.split_whitespace().map(|s| s.to_string()).collect();
+        Self::new_from_tokens(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revoke() {
+        let revoke =
+            Revoke::deserialize("REVOKE MODIFY PERMISSION ON KEYSPACE sky FROM pilot").unwrap();
+
+        assert_eq!(revoke.permission, Some("MODIFY".to_string()));
+        assert_eq!(revoke.keyspace, Some("sky".to_string()));
+        assert_eq!(revoke.user, "pilot");
+        assert_eq!(
+            revoke.serialize(),
+            "REVOKE MODIFY ON KEYSPACE sky FROM pilot"
+        );
+
+        assert!(Revoke::deserialize("REVOKE MODIFY ON KEYSPACE sky TO pilot").is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use super::{condition::Condition, recursive_parser::parse_condition};
+use super::{
+    condition::{token_columns, Condition},
+    recursive_parser::parse_condition,
+};
 use crate::{errors::CQLError, logical_operator::LogicalOperator, operator::Operator};
 
 /// Struct representing the `WHERE` SQL clause.
@@ -454,6 +457,54 @@ impl Where {
             }
         }
     }
+
+    /// Whether the clause compares the token of the partitions, as in `WHERE token(id) > 100`.
+    pub fn restricts_token(&self) -> bool {
+        !self.condition.token_fields().is_empty()
+    }
+
+    /// Validates the conditions on the token of the partitions, which read the partitions of a
+    /// range of the ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `partitioner_keys` - The names of the partition key columns, in the order of the key.
+    /// * `allow_filtering` - Whether the query has `ALLOW FILTERING`, which lets the token
+    ///   conditions be combined with conditions on any column.
+    ///
+    /// # Errors
+    ///
+    /// - `CQLError::InvalidColumn` if a token is not the one of the whole partition key, with its
+    ///   columns in order.
+    /// - `CQLError::InvalidCondition` if, without `ALLOW FILTERING`, the clause compares anything
+    ///   other than tokens or joins them by anything other than `AND`.
+    pub fn validate_token_conditions(
+        &self,
+        partitioner_keys: &[String],
+        allow_filtering: bool,
+    ) -> Result<(), CQLError> {
+        for field in self.condition.token_fields() {
+            if token_columns(field).is_none_or(|columns| columns != partitioner_keys) {
+                return Err(CQLError::InvalidColumn);
+            }
+        }
+        if !allow_filtering && !Self::only_compares_tokens(&self.condition) {
+            return Err(CQLError::InvalidCondition);
+        }
+        Ok(())
+    }
+
+    fn only_compares_tokens(condition: &Condition) -> bool {
+        match condition {
+            Condition::Simple { field, .. } => token_columns(field).is_some(),
+            Condition::Complex {
+                left: Some(left),
+                operator: LogicalOperator::And,
+                right,
+            } => Self::only_compares_tokens(left) && Self::only_compares_tokens(right),
+            Condition::Complex { .. } => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -461,6 +512,58 @@ mod tests {
     use super::*;
     use crate::{errors::CQLError, logical_operator::LogicalOperator, operator::Operator};
 
+    #[test]
+    fn test_token_conditions() {
+        let partition_keys = vec!["airport".to_string(), "day".to_string()];
+        // The tokenizer gives the columns of `token(airport, day)` as a single token
+        let where_clause = |tokens: Vec<&str>| Where::new_from_tokens(tokens).unwrap();
+
+        let range = where_clause(vec![
+            "WHERE",
+            "token",
+            "airport, day",
+            ">",
+            "10",
+            "AND",
+            "token",
+            "airport, day",
+            "<=",
+            "20",
+        ]);
+        assert!(range.restricts_token());
+        assert_eq!(
+            range.validate_token_conditions(&partition_keys, false),
+            Ok(())
+        );
+
+        let filtered = where_clause(vec![
+            "WHERE",
+            "token",
+            "airport, day",
+            ">",
+            "10",
+            "AND",
+            "status",
+            "=",
+            "delayed",
+        ]);
+        assert_eq!(
+            filtered.validate_token_conditions(&partition_keys, false),
+            Err(CQLError::InvalidCondition)
+        );
+        assert_eq!(
+            filtered.validate_token_conditions(&partition_keys, true),
+            Ok(())
+        );
+
+        let partial = where_clause(vec!["WHERE", "token", "airport", ">", "10"]);
+        assert_eq!(
+            partial.validate_token_conditions(&partition_keys, false),
+            Err(CQLError::InvalidColumn)
+        );
+        assert!(!where_clause(vec!["WHERE", "airport", "=", "EZE"]).restricts_token());
+    }
+
     #[test]
     fn test_new_from_tokens_simple_condition() {
         let tokens = vec!["WHERE", "age", ">", "18"];
@@ -0,0 +1,235 @@
+//! JSON objects of the rows of `SELECT JSON` and `INSERT JSON` queries.
+//!
+//! Rows are flat objects with one member for each column: numbers and booleans are written as
+//! such, every other type as a string, and empty values as `null`.
+
+use std::{iter::Peekable, str::Chars};
+
+use crate::{clauses::types::datatype::DataType, errors::CQLError};
+
+/// Writes the values of a row as a JSON object, with the names and types of their columns.
+pub fn encode_object(names: &[&str], types: &[DataType], values: &[&str]) -> String {
+    let members: Vec<String> = names
+        .iter()
+        .zip(types)
+        .enumerate()
+        .map(|(i, (name, data_type))| {
+            let value = values.get(i).copied().unwrap_or_default();
+            format!(
+                "{}: {}",
+                encode_string(name),
+                encode_value(value, data_type)
+            )
+        })
+        .collect();
+    format!("{{{}}}", members.join(", "))
+}
+
+fn encode_value(value: &str, data_type: &DataType) -> String {
+    let is_literal = match data_type {
+        _ if value.is_empty() => return "null".to_string(),
+        DataType::Int | DataType::Double | DataType::Float | DataType::Counter => {
+            value.parse::<f64>().is_ok_and(|number| number.is_finite())
+        }
+        DataType::Boolean => value == "true" || value == "false",
+        DataType::String | DataType::Timestamp | DataType::Uuid => false,
+    };
+    if is_literal {
+        value.to_string()
+    } else {
+        encode_string(value)
+    }
+}
+
+fn encode_string(value: &str) -> String {
+    let mut encoded = String::from("\"");
+    for char in value.chars() {
+        match char {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            char if char.is_control() => encoded.push_str(&format!("\\u{:04x}", char as u32)),
+            char => encoded.push(char),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+/// Reads a JSON object of a row, returning its members in the order they were written, each one
+/// with its value as it is written in a `VALUES` clause or `None` if it is `null`.
+///
+/// # Errors
+/// - `CQLError::InvalidSyntax` if the text is not a JSON object, if a member is repeated or if a
+///   value is an object or an array, which no column type holds.
+pub fn decode_object(text: &str) -> Result<Vec<(String, Option<String>)>, CQLError> {
+    let mut chars = text.chars().peekable();
+    let mut members: Vec<(String, Option<String>)> = Vec::new();
+
+    expect(&mut chars, '{')?;
+    if skip_whitespace(&mut chars) == Some('}') {
+        chars.next();
+    } else {
+        loop {
+            skip_whitespace(&mut chars);
+            let name = decode_string(&mut chars)?;
+            if members.iter().any(|(other, _)| *other == name) {
+                return Err(CQLError::InvalidSyntax);
+            }
+            expect(&mut chars, ':')?;
+            let value = decode_value(&mut chars)?;
+            members.push((name, value));
+
+            match skip_whitespace(&mut chars) {
+                Some(',') => chars.next(),
+                Some('}') => {
+                    chars.next();
+                    break;
+                }
+                _ => return Err(CQLError::InvalidSyntax),
+            };
+        }
+    }
+
+    if skip_whitespace(&mut chars).is_some() {
+        return Err(CQLError::InvalidSyntax);
+    }
+    Ok(members)
+}
+
+// Skips the whitespace before the next character, and returns it without taking it
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|char| char.is_whitespace()).is_some() {}
+    chars.peek().copied()
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), CQLError> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(char) if char == expected => Ok(()),
+        _ => Err(CQLError::InvalidSyntax),
+    }
+}
+
+fn decode_value(chars: &mut Peekable<Chars>) -> Result<Option<String>, CQLError> {
+    match skip_whitespace(chars) {
+        Some('"') => decode_string(chars).map(Some),
+        Some(char) if char == '-' || char.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(char) = chars.next_if(|char| {
+                char.is_ascii_digit() || matches!(char, '-' | '+' | '.' | 'e' | 'E')
+            }) {
+                number.push(char);
+            }
+            number
+                .parse::<f64>()
+                .map(|_| Some(number))
+                .map_err(|_| CQLError::InvalidSyntax)
+        }
+        Some(char) if char.is_ascii_alphabetic() => {
+            let mut word = String::new();
+            while let Some(char) = chars.next_if(|char| char.is_ascii_alphabetic()) {
+                word.push(char);
+            }
+            match word.as_str() {
+                "true" | "false" => Ok(Some(word)),
+                "null" => Ok(None),
+                _ => Err(CQLError::InvalidSyntax),
+            }
+        }
+        _ => Err(CQLError::InvalidSyntax),
+    }
+}
+
+fn decode_string(chars: &mut Peekable<Chars>) -> Result<String, CQLError> {
+    expect(chars, '"')?;
+    let mut decoded = String::new();
+    loop {
+        match chars.next().ok_or(CQLError::InvalidSyntax)? {
+            '"' => return Ok(decoded),
+            '\\' => decoded.push(match chars.next().ok_or(CQLError::InvalidSyntax)? {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    u32::from_str_radix(&code, 16)
+                        .ok()
+                        .filter(|_| code.len() == 4)
+                        .and_then(char::from_u32)
+                        .ok_or(CQLError::InvalidSyntax)?
+                }
+                _ => return Err(CQLError::InvalidSyntax),
+            }),
+            char => decoded.push(char),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_are_encoded_by_the_types_of_their_columns() {
+        let object = encode_object(
+            &["number", "origin", "delayed", "speed", "gate"],
+            &[
+                DataType::Int,
+                DataType::String,
+                DataType::Boolean,
+                DataType::Double,
+                DataType::String,
+            ],
+            &["7", "Buenos \"Aires\"", "true", "810.5", ""],
+        );
+        assert_eq!(
+            object,
+            r#"{"number": 7, "origin": "Buenos \"Aires\"", "delayed": true, "speed": 810.5, "gate": null}"#
+        );
+        assert_eq!(
+            decode_object(&object).unwrap(),
+            [
+                ("number".to_string(), Some("7".to_string())),
+                ("origin".to_string(), Some("Buenos \"Aires\"".to_string())),
+                ("delayed".to_string(), Some("true".to_string())),
+                ("speed".to_string(), Some("810.5".to_string())),
+                ("gate".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_objects_are_rejected() {
+        assert_eq!(decode_object(" { } ").unwrap(), []);
+        assert_eq!(
+            decode_object(r#"{"origin": "EZE"}"#).unwrap(),
+            [("origin".to_string(), Some("EZE".to_string()))]
+        );
+        for text in [
+            "",
+            "[1]",
+            r#"{"number": 1,}"#,
+            r#"{"number": 1} 2"#,
+            r#"{"number": 1, "number": 2}"#,
+            r#"{"legs": [1, 2]}"#,
+            r#"{"number": 1-}"#,
+            r#"{"delayed": yes}"#,
+            r#"{"origin": "EZE}"#,
+        ] {
+            assert_eq!(
+                decode_object(text),
+                Err(CQLError::InvalidSyntax),
+                "{}",
+                text
+            );
+        }
+    }
+}
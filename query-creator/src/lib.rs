@@ -1,5 +1,6 @@
-﻿pub mod clauses;
+pub mod clauses;
 pub mod errors;
+mod json;
 pub mod logical_operator;
 pub mod operator;
 mod utils;
@@ -13,8 +14,17 @@ use clauses::table::{
 };
 use clauses::types::column::Column;
 use clauses::types::datatype::DataType;
+use clauses::user::{
+    alter_user_cql::AlterUser, create_user_cql::CreateUser, drop_user_cql::DropUser,
+    grant_cql::Grant, revoke_cql::Revoke,
+};
 use clauses::{
-    delete_cql::Delete, insert_cql::Insert, select_cql::Select, update_cql::Update, use_cql::Use,
+    batch_cql::Batch,
+    delete_cql::Delete,
+    insert_cql::Insert,
+    select_cql::{Select, COUNT_RESULT_COLUMN, JSON_RESULT_COLUMN},
+    update_cql::Update,
+    use_cql::Use,
 };
 use errors::CQLError;
 use native_protocol::frame::Frame;
@@ -192,6 +202,12 @@ pub enum Query {
     DropKeyspace(DropKeyspace),
     AlterKeyspace(AlterKeyspace),
     Use(Use),
+    Batch(Batch),
+    CreateUser(CreateUser),
+    AlterUser(AlterUser),
+    DropUser(DropUser),
+    Grant(Grant),
+    Revoke(Revoke),
 }
 
 /// Implements the `fmt::Display` trait for `Query`. This allows the enum to be printed in a human-readable format.
@@ -209,6 +225,12 @@ impl fmt::Display for Query {
             Query::DropKeyspace(_) => "DropKeyspace",
             Query::AlterKeyspace(_) => "AlterKeyspace",
             Query::Use(_) => "Use",
+            Query::Batch(_) => "Batch",
+            Query::CreateUser(_) => "CreateUser",
+            Query::AlterUser(_) => "AlterUser",
+            Query::DropUser(_) => "DropUser",
+            Query::Grant(_) => "Grant",
+            Query::Revoke(_) => "Revoke",
         };
         write!(f, "{}", query_type)
     }
@@ -225,6 +247,7 @@ impl From<DataType> for ColumnType {
             DataType::Float => ColumnType::Float,
             DataType::Timestamp => ColumnType::Timestamp,
             DataType::Uuid => ColumnType::Uuid,
+            DataType::Counter => ColumnType::Counter,
         }
     }
 }
@@ -310,6 +333,45 @@ impl CreateClientResponse for Query {
         rows: Vec<String>,
     ) -> Result<Frame, CQLError> {
         let query_type = match self {
+            Query::Select(select) if select.json => {
+                let names: Vec<&str> = rows
+                    .first()
+                    .ok_or(CQLError::InvalidSyntax)?
+                    .split(",")
+                    .collect();
+                let types = names
+                    .iter()
+                    .map(|&name| match columns.iter().find(|col| col.name == name) {
+                        Some(column) => Ok(column.data_type),
+                        None if select.is_count() => Ok(DataType::Int),
+                        None => Err(CQLError::Error),
+                    })
+                    .collect::<Result<Vec<_>, CQLError>>()?;
+
+                let col_types = vec![(JSON_RESULT_COLUMN.to_string(), ColumnType::Varchar)];
+                let records = rows[1..]
+                    .iter()
+                    .map(|row| {
+                        let values: Vec<&str> = row.split(",").collect();
+                        BTreeMap::from([(
+                            JSON_RESULT_COLUMN.to_string(),
+                            ColumnValue::Varchar(json::encode_object(&names, &types, &values)),
+                        )])
+                    })
+                    .collect();
+
+                Frame::Result(result_::Result::Rows(Rows::new(col_types, records)))
+            }
+            Query::Select(select) if select.is_count() => {
+                let count = rows.get(1).ok_or(CQLError::Error)?;
+                let col_types = vec![(COUNT_RESULT_COLUMN.to_string(), ColumnType::Bigint)];
+                let record = BTreeMap::from([(
+                    COUNT_RESULT_COLUMN.to_string(),
+                    create_column_value_from_type(&ColumnType::Bigint, count)?,
+                )]);
+
+                Frame::Result(result_::Result::Rows(Rows::new(col_types, vec![record])))
+            }
             Query::Select(_) => {
                 let necessary_columns: Vec<_> = rows
                     .first()
@@ -399,6 +461,12 @@ impl CreateClientResponse for Query {
                 )))
             }
             Query::Use(_) => Frame::Result(result_::Result::SetKeyspace(keyspace)),
+            Query::Batch(_) => Frame::Result(result_::Result::Void),
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => Frame::Result(result_::Result::Void),
         };
 
         Ok(query_type)
@@ -421,6 +489,12 @@ impl NeededResponses for Query {
             Query::DropKeyspace(_) => NeededResponseCount::One,
             Query::AlterKeyspace(_) => NeededResponseCount::One,
             Query::Use(_) => NeededResponseCount::One,
+            Query::Batch(_) => NeededResponseCount::ReplicationFactor,
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => NeededResponseCount::One,
         }
     }
 }
@@ -439,6 +513,12 @@ impl NeedsKeyspace for Query {
             Query::Insert(_) => true,          // `INSERT` no es una consulta que necesite keyspace
             Query::Update(_) => true,          // `UPDATE` no es una consulta que necesite keyspace
             Query::Delete(_) => true,          // `DELETE` no es una consulta que necesite keyspace
+            Query::Batch(_) => true,           // Los statements de un `BATCH` necesitan keyspace
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => false, // Los usuarios no pertenecen a un keyspace
         }
     }
 }
@@ -457,6 +537,12 @@ impl NeedsTable for Query {
             Query::DropKeyspace(_) => false,   // `DROP KEYSPACE` no requiere tabla
             Query::AlterKeyspace(_) => false,  // `ALTER KEYSPACE` no requiere tabla
             Query::Use(_) => false,            // `USE` no requiere tabla
+            Query::Batch(_) => false,          // Cada statement del `BATCH` usa su propia tabla
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => false, // Los usuarios no requieren tabla
         }
     }
 }
@@ -478,6 +564,12 @@ impl GetTableName for Query {
                 Query::DropKeyspace(_) => None,
                 Query::AlterKeyspace(_) => None,
                 Query::Use(_) => None,
+                Query::Batch(_) => None,
+                Query::CreateUser(_)
+                | Query::AlterUser(_)
+                | Query::DropUser(_)
+                | Query::Grant(_)
+                | Query::Revoke(_) => None,
             }
         }
     }
@@ -539,6 +631,12 @@ impl GetUsedKeyspace for Query {
             Query::DropKeyspace(_) => None,
             Query::AlterKeyspace(_) => None,
             Query::Use(_) => None,
+            Query::Batch(batch) => batch.get_used_keyspace(),
+            Query::CreateUser(_)
+            | Query::AlterUser(_)
+            | Query::DropUser(_)
+            | Query::Grant(_)
+            | Query::Revoke(_) => None,
         }
     }
 }
@@ -598,6 +696,10 @@ impl QueryCreator {
                     let create_keyspace = CreateKeyspace::new_from_tokens(tokens)?;
                     Ok(Query::CreateKeyspace(create_keyspace))
                 }
+                "USER" => {
+                    let create_user = CreateUser::new_from_tokens(tokens)?;
+                    Ok(Query::CreateUser(create_user))
+                }
                 _ => Err(CQLError::InvalidSyntax),
             },
             "DROP" => match tokens[1].as_str() {
@@ -609,6 +711,10 @@ impl QueryCreator {
                     let drop_keyspace = DropKeyspace::new_from_tokens(tokens)?;
                     Ok(Query::DropKeyspace(drop_keyspace))
                 }
+                "USER" => {
+                    let drop_user = DropUser::new_from_tokens(tokens)?;
+                    Ok(Query::DropUser(drop_user))
+                }
                 _ => Err(CQLError::InvalidSyntax),
             },
             "ALTER" => match tokens[1].as_str() {
@@ -620,12 +726,28 @@ impl QueryCreator {
                     let alter_keyspace = AlterKeyspace::new_from_tokens(tokens)?;
                     Ok(Query::AlterKeyspace(alter_keyspace))
                 }
+                "USER" => {
+                    let alter_user = AlterUser::new_from_tokens(tokens)?;
+                    Ok(Query::AlterUser(alter_user))
+                }
                 _ => Err(CQLError::InvalidSyntax),
             },
             "USE" => {
                 let use_cql = Use::new_from_tokens(tokens)?;
                 Ok(Query::Use(use_cql))
             }
+            "BEGIN" => {
+                let batch = Batch::new_from_query(&query)?;
+                Ok(Query::Batch(batch))
+            }
+            "GRANT" => {
+                let grant = Grant::new_from_tokens(tokens)?;
+                Ok(Query::Grant(grant))
+            }
+            "REVOKE" => {
+                let revoke = Revoke::new_from_tokens(tokens)?;
+                Ok(Query::Revoke(revoke))
+            }
             _ => Err(CQLError::InvalidSyntax),
         }
     }
@@ -863,6 +985,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_create_batch_query() {
+        let coordinator = QueryCreator::new();
+        let query = "BEGIN BATCH INSERT INTO users (name, age) VALUES ('John', 28); UPDATE users SET age = 29 WHERE name = 'John'; APPLY BATCH;".to_string();
+        let result = coordinator.handle_query(query);
+        assert!(matches!(result, Ok(Query::Batch(_))));
+
+        if let Ok(query) = result {
+            assert!(matches!(
+                query.needed_responses(),
+                NeededResponseCount::ReplicationFactor
+            ));
+            assert!(query.needs_keyspace());
+            assert!(!query.needs_table());
+        }
+    }
+
     #[test]
     fn test_create_table_query_success() {
         let coordinator = QueryCreator::new();
@@ -911,4 +1050,85 @@ mod tests {
             assert!(matches!(query.needed_responses(), NeededResponseCount::One));
         }
     }
+
+    #[test]
+    fn test_user_queries_success() {
+        for (query, expected) in [
+            ("CREATE USER pilot WITH PASSWORD 'take off';", "CreateUser"),
+            ("ALTER USER pilot WITH PASSWORD 'landing';", "AlterUser"),
+            ("DROP USER IF EXISTS pilot;", "DropUser"),
+            ("GRANT SELECT ON KEYSPACE sky TO pilot;", "Grant"),
+            ("REVOKE SELECT ON KEYSPACE sky FROM pilot;", "Revoke"),
+        ] {
+            let query = QueryCreator::new().handle_query(query.to_string()).unwrap();
+            assert_eq!(query.to_string(), expected);
+            assert!(!query.needs_keyspace());
+            assert_eq!(query.get_used_keyspace(), None);
+        }
+    }
+
+    #[test]
+    fn test_count_client_response() {
+        let coordinator = QueryCreator::new();
+        let query = coordinator
+            .handle_query("SELECT COUNT(*) FROM users WHERE id = 1;".to_string())
+            .unwrap();
+
+        let frame = query
+            .create_client_response(
+                vec![],
+                "keyspace".to_string(),
+                vec!["count".to_string(), "42".to_string()],
+            )
+            .unwrap();
+
+        let expected_rows = Rows::new(
+            vec![("count".to_string(), ColumnType::Bigint)],
+            vec![BTreeMap::from([(
+                "count".to_string(),
+                ColumnValue::Bigint(42),
+            )])],
+        );
+        match frame {
+            Frame::Result(result_::Result::Rows(rows)) => assert_eq!(rows, expected_rows),
+            _ => panic!("a count must be answered with rows"),
+        }
+    }
+    #[test]
+    fn test_json_client_response() {
+        let coordinator = QueryCreator::new();
+        let query = coordinator
+            .handle_query("SELECT JSON id, name FROM users WHERE id = 1;".to_string())
+            .unwrap();
+        let columns = vec![
+            Column::new("id", DataType::Int, true, false),
+            Column::new("name", DataType::String, false, true),
+        ];
+
+        let frame = query
+            .create_client_response(
+                columns,
+                "keyspace".to_string(),
+                vec!["id,name".to_string(), "1,Ana".to_string(), "2,".to_string()],
+            )
+            .unwrap();
+
+        let json_row = |object: &str| {
+            BTreeMap::from([(
+                "[json]".to_string(),
+                ColumnValue::Varchar(object.to_string()),
+            )])
+        };
+        let expected_rows = Rows::new(
+            vec![("[json]".to_string(), ColumnType::Varchar)],
+            vec![
+                json_row(r#"{"id": 1, "name": "Ana"}"#),
+                json_row(r#"{"id": 2, "name": null}"#),
+            ],
+        );
+        match frame {
+            Frame::Result(result_::Result::Rows(rows)) => assert_eq!(rows, expected_rows),
+            _ => panic!("a select must be answered with rows"),
+        }
+    }
 }
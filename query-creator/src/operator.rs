@@ -2,6 +2,8 @@
 /// - `Equal`: Equal operator
 /// - `Greater`: Greater than operator
 /// - `Lesser`: Lesser than operator
+/// - `GreaterOrEqual`: Greater than or equal operator
+/// - `LesserOrEqual`: Lesser than or equal operator
 ///
 ///
 ///
@@ -17,6 +19,10 @@ use crate::CQLError;
 ///   - Represents the greater than (`>`) operator.
 /// - `Lesser`
 ///   - Represents the lesser than (`<`) operator.
+/// - `GreaterOrEqual`
+///   - Represents the greater than or equal (`>=`) operator.
+/// - `LesserOrEqual`
+///   - Represents the lesser than or equal (`<=`) operator.
 ///
 /// # Purpose
 /// The `Operator` enum encapsulates comparison operators commonly used in SQL-like query conditions. It provides methods to serialize these operators to their string representations and deserialize them back into enum variants.
@@ -29,6 +35,8 @@ pub enum Operator {
     Equal,
     Greater,
     Lesser,
+    GreaterOrEqual,
+    LesserOrEqual,
 }
 
 impl Operator {
@@ -43,12 +51,16 @@ impl Operator {
     ///     - `"="` for `Operator::Equal`.
     ///     - `">"` for `Operator::Greater`.
     ///     - `"<"` for `Operator::Lesser`.
+    ///     - `">="` for `Operator::GreaterOrEqual`.
+    ///     - `"<="` for `Operator::LesserOrEqual`.
 
     pub fn serialize(&self) -> &str {
         match self {
             Operator::Equal => "=",
             Operator::Greater => ">",
             Operator::Lesser => "<",
+            Operator::GreaterOrEqual => ">=",
+            Operator::LesserOrEqual => "<=",
         }
     }
 
@@ -61,7 +73,7 @@ impl Operator {
     /// # Parameters
     /// - `op_str: &str`:
     ///   - A string slice representing a comparison operator.
-    ///     - Valid inputs: `"="`, `">"`, `"<"`.
+    ///     - Valid inputs: `"="`, `">"`, `"<"`, `">="`, `"<="`.
     ///
     /// # Returns
     /// - `Result<Operator, CQLError>`:
@@ -73,9 +85,22 @@ impl Operator {
             "=" => Ok(Operator::Equal),
             ">" => Ok(Operator::Greater),
             "<" => Ok(Operator::Lesser),
+            ">=" => Ok(Operator::GreaterOrEqual),
+            "<=" => Ok(Operator::LesserOrEqual),
             _ => Err(CQLError::InvalidSyntax),
         }
     }
+
+    /// Returns whether `x` is related to `y` as the operator says, as in `x < y` for `Lesser`.
+    pub fn compare<T: PartialOrd>(&self, x: &T, y: &T) -> bool {
+        match self {
+            Operator::Equal => x == y,
+            Operator::Greater => x > y,
+            Operator::Lesser => x < y,
+            Operator::GreaterOrEqual => x >= y,
+            Operator::LesserOrEqual => x <= y,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +122,8 @@ mod tests {
         assert_eq!(Operator::deserialize("="), Ok(Operator::Equal));
         assert_eq!(Operator::deserialize(">"), Ok(Operator::Greater));
         assert_eq!(Operator::deserialize("<"), Ok(Operator::Lesser));
+        assert_eq!(Operator::deserialize(">="), Ok(Operator::GreaterOrEqual));
+        assert_eq!(Operator::deserialize("<="), Ok(Operator::LesserOrEqual));
     }
 
     #[test]
@@ -109,10 +136,24 @@ mod tests {
         assert_eq!(Operator::deserialize(""), Err(CQLError::InvalidSyntax));
     }
 
+    #[test]
+    fn test_compare() {
+        assert!(Operator::LesserOrEqual.compare(&3, &3));
+        assert!(!Operator::Lesser.compare(&3, &3));
+        assert!(Operator::GreaterOrEqual.compare(&true, &false));
+        assert!(!Operator::Greater.compare(&"AEP", &"EZE"));
+    }
+
     #[test]
     fn test_serialize_and_deserialize_roundtrip() {
         // Test that serialization and deserialization are inverses
-        let operators = vec![Operator::Equal, Operator::Greater, Operator::Lesser];
+        let operators = vec![
+            Operator::Equal,
+            Operator::Greater,
+            Operator::Lesser,
+            Operator::GreaterOrEqual,
+            Operator::LesserOrEqual,
+        ];
 
         for op in operators {
             let serialized = op.serialize();
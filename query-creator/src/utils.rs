@@ -1,3 +1,5 @@
+use crate::errors::CQLError;
+
 /// Returns true if the token is equal to "AND".
 pub fn is_and(token: &str) -> bool {
     token == "AND"
@@ -82,3 +84,47 @@ pub fn is_values(token: &str) -> bool {
 pub fn is_limit(token: &str) -> bool {
     token.eq_ignore_ascii_case("LIMIT")
 }
+
+/// Returns true if the tokens from `i` are "PER PARTITION LIMIT".
+pub fn is_per_partition_limit(tokens: &[String], i: usize) -> bool {
+    matches!(
+        tokens.get(i..i + 3),
+        Some([per, partition, limit])
+            if per.eq_ignore_ascii_case("PER")
+                && partition.eq_ignore_ascii_case("PARTITION")
+                && is_limit(limit)
+    )
+}
+
+/// Returns true if the tokens from `i` are "ALLOW FILTERING".
+pub fn is_allow_filtering(tokens: &[String], i: usize) -> bool {
+    matches!(
+        tokens.get(i..i + 2),
+        Some([allow, filtering])
+            if allow.eq_ignore_ascii_case("ALLOW") && filtering.eq_ignore_ascii_case("FILTERING")
+    )
+}
+
+/// Returns true if the token at `i` is "JSON" and it is not the column of a `SELECT`, which the
+/// token that follows it, "FROM", would be.
+pub fn is_json(tokens: &[String], i: usize) -> bool {
+    tokens
+        .get(i)
+        .is_some_and(|token| token.eq_ignore_ascii_case("JSON"))
+        && tokens.get(i + 1).is_some_and(|token| !is_from(token))
+}
+
+/// Returns true if the token is equal to "USING".
+pub fn is_using(token: &str) -> bool {
+    token == "USING"
+}
+
+/// Parses the seconds of a `USING TTL <seconds>` clause, from the tokens that follow `USING`.
+pub fn ttl_from_tokens(tokens: &[String]) -> Result<u64, CQLError> {
+    match tokens {
+        [ttl, seconds, ..] if ttl.eq_ignore_ascii_case("TTL") => {
+            seconds.parse().map_err(|_| CQLError::InvalidSyntax)
+        }
+        _ => Err(CQLError::InvalidSyntax),
+    }
+}
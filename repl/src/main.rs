@@ -16,6 +16,9 @@ fn main() {
         return;
     }
 
+    // Whether the timings of each query are shown, toggled with `TIMING ON` and `TIMING OFF`
+    let mut show_timings = false;
+
     loop {
         print!("> "); // Prompt symbol
         io::stdout().flush().unwrap(); // Ensure the prompt is displayed immediately
@@ -25,6 +28,31 @@ fn main() {
 
         let trimmed = input.trim(); // Remove trailing newline and whitespace
 
+        match trimmed.to_uppercase().as_str() {
+            "TIMING ON" => {
+                show_timings = true;
+                continue;
+            }
+            "TIMING OFF" => {
+                show_timings = false;
+                continue;
+            }
+            _ => {}
+        }
+
+        if show_timings {
+            match client.execute_with_timings(trimmed, "all") {
+                Ok((result, timings)) => {
+                    println!("{:?}", result);
+                    if let Some(timings) = timings {
+                        println!("Timings: {}", timings);
+                    }
+                }
+                Err(error) => eprintln!("{:?}", error),
+            }
+            continue;
+        }
+
         match client.execute(&trimmed, "all") {
             Ok(result) => println!("{:?}", result),
             Err(error) => eprintln!("{:?}", error),